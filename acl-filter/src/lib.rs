@@ -20,6 +20,7 @@ trace_target!("acl-filter", LevelFilter::INFO, &["pipeline"]);
 mod access;
 mod context;
 mod display;
+mod prefilter;
 
 #[cfg(test)]
 mod tests;
@@ -27,6 +28,7 @@ mod tests;
 pub use access::{
     AclFilterContext, AclFilterContextReader, AclFilterContextReaderFactory, AclFilterContextWriter,
 };
+pub use prefilter::PrefilterRules;
 
 pub struct AclFilter {
     name: String,
@@ -0,0 +1,141 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Rules of the pre-filter, dropping obviously unwanted traffic before it reaches the pipeline.
+//!
+//! The kernel driver may drop packets in an XDP program, before they get to userspace. The
+//! program only knows of IPv4 sources, and drops the packets:
+//!
+//! - from spoofed sources: the martian ranges and the VTEP of the gateway itself, which no packet
+//!   received may legitimately come from;
+//! - encapsulated in VXLAN, from an inner source denied by the ACLs whatever the destination, given
+//!   by VNI of the VPC the packet comes from.
+//!
+//! The pre-filter sees no flow and doesn't route, so a source of a VPC is only denied if the ACL
+//! of every peering of the VPC drops all its traffic, as the ACL filter would: with a rule
+//! denying it to any destination, protocol and port, ahead of any rule allowing traffic from the
+//! VPC, or with a default action denying it, if no rule allows traffic from the VPC or replies to
+//! it.
+
+use config::external::overlay::ValidatedOverlay;
+use config::external::overlay::acl::{AclAction, AclProtoMatch, AclScope, ValidatedAclRule};
+use config::external::overlay::vpc::{ValidatedPeering, ValidatedVpc};
+use config::external::overlay::vpcpeering::ValidatedManifest;
+use config::internal::routing::evpn::VtepConfig;
+use lpm::prefix::{Prefix, PrefixPortsSet, PrefixWithOptionalPorts};
+use net::vxlan::Vni;
+use std::collections::BTreeSet;
+use std::net::{IpAddr, Ipv4Addr};
+
+/// The IPv4 ranges no packet received may come from
+const MARTIANS: [(Ipv4Addr, u8); 4] = [
+    (Ipv4Addr::UNSPECIFIED, 8),
+    (Ipv4Addr::LOCALHOST, 8),
+    (Ipv4Addr::new(224, 0, 0, 0), 4),
+    (Ipv4Addr::new(240, 0, 0, 0), 4),
+];
+
+/// The IPv4 sources the pre-filter drops the packets of
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrefilterRules {
+    spoofed: BTreeSet<Prefix>,
+    denied: BTreeSet<(Vni, Prefix)>,
+}
+
+fn any_port(prefix: &PrefixWithOptionalPorts) -> bool {
+    prefix.ports().is_none_or(|ports| ports.is_max_range())
+}
+
+/// Tell if `rule` matches all the traffic from its sources to the addresses exposed by `remote`
+fn matches_any_destination(rule: &ValidatedAclRule, remote: &ValidatedManifest) -> bool {
+    let pattern = rule.pattern();
+    if pattern.proto() != AclProtoMatch::Any {
+        return false;
+    }
+    let covers = |reachable: Prefix| {
+        pattern
+            .dst()
+            .iter()
+            .any(|dst| any_port(dst) && dst.prefix().covers(&reachable))
+    };
+    if remote.has_default_expose() {
+        return covers(Prefix::root_v4());
+    }
+    remote
+        .all_public_ips()
+        .iter()
+        .all(|reachable| covers(reachable.prefix()))
+}
+
+/// The sources of the local VPC of `peering` its ACL drops all the traffic of
+fn denied_by_peering(peering: &ValidatedPeering) -> PrefixPortsSet {
+    let Some(acl) = peering.acl() else {
+        return PrefixPortsSet::new();
+    };
+    let local = peering.local().name();
+    let mut denied = PrefixPortsSet::new();
+    // rules match in order: past a rule allowing traffic from the VPC, no source is denied for sure
+    for rule in acl.rules().iter().filter(|rule| rule.from() == local) {
+        if rule.action() == AclAction::Allow {
+            return denied;
+        }
+        if matches_any_destination(rule, peering.remote()) {
+            for src in rule.pattern().src().iter().filter(|src| any_port(src)) {
+                denied.insert(PrefixWithOptionalPorts::new(src.prefix(), None));
+            }
+        }
+    }
+    let replies_allowed = acl.rules().iter().any(|rule| {
+        rule.from() != local && rule.action() == AclAction::Allow && rule.scope() == AclScope::Flow
+    });
+    if acl.default_action() == AclAction::Deny && !replies_allowed {
+        return PrefixPortsSet::root_v4();
+    }
+    denied
+}
+
+/// The sources of `vpc` the ACLs of all its peerings drop all the traffic of
+fn denied_sources(vpc: &ValidatedVpc) -> PrefixPortsSet {
+    let mut peerings = vpc.peerings().iter();
+    let Some(first) = peerings.next() else {
+        return PrefixPortsSet::new();
+    };
+    peerings.fold(denied_by_peering(first), |denied, peering| {
+        denied.intersection_prefixes_and_ports(&denied_by_peering(peering))
+    })
+}
+
+impl PrefilterRules {
+    /// Compile the rules of the pre-filter from the ACLs of `overlay` and the VTEP of the gateway.
+    #[must_use]
+    pub fn build(overlay: &ValidatedOverlay, vtep: Option<&VtepConfig>) -> Self {
+        let martians = MARTIANS.iter().map(|(address, len)| {
+            Prefix::try_from((IpAddr::V4(*address), *len)).unwrap_or_else(|_| unreachable!())
+        });
+        let vtep = vtep
+            .map(|vtep| vtep.address.inner())
+            .filter(IpAddr::is_ipv4)
+            .map(Prefix::from);
+        let spoofed = martians.chain(vtep).collect();
+
+        let mut denied = BTreeSet::new();
+        for vpc in overlay.vpc_table().values() {
+            for src in &denied_sources(vpc) {
+                if src.prefix().is_ipv4() {
+                    denied.insert((vpc.vni(), src.prefix()));
+                }
+            }
+        }
+        Self { spoofed, denied }
+    }
+
+    /// The sources of the packets dropped as spoofed
+    pub fn spoofed(&self) -> impl Iterator<Item = &Prefix> {
+        self.spoofed.iter()
+    }
+
+    /// The inner sources of the VXLAN packets dropped as denied, by VNI
+    pub fn denied(&self) -> impl Iterator<Item = &(Vni, Prefix)> {
+        self.denied.iter()
+    }
+}
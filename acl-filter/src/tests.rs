@@ -8,7 +8,7 @@
 //! (if no ACL is configured at all, traffic is allowed). A rule's scope ('flow' or 'packet')
 //! decides whether reply traffic is allowed once the corresponding request is allowed.
 
-use crate::{AclFilter, AclFilterContext, AclFilterContextWriter};
+use crate::{AclFilter, AclFilterContext, AclFilterContextWriter, PrefilterRules};
 
 use config::external::overlay::acl::{
     Acl, AclAction, AclPattern, AclProtoMatch, AclRule, AclScope,
@@ -838,6 +838,97 @@ fn directional_rule_only_applies_to_correct_direction() {
     assert!(is_allowed(&run(&mut filter, reverse)));
 }
 
+// -------------------------------------------------------------------------------------------------
+// Pre-filter rules: only the sources the ACLs drop all the traffic of are denied
+
+fn prefilter(acl: Acl) -> PrefilterRules {
+    let overlay = overlay(
+        &[("vpc1", VNI1), ("vpc2", VNI2)],
+        vec![peering(
+            "vpc1-to-vpc2",
+            ("vpc1", vec![expose(V1_IPS)]),
+            ("vpc2", vec![expose(V2_IPS)]),
+            Some(acl),
+        )],
+    );
+    PrefilterRules::build(&overlay, None)
+}
+
+fn denied_sources(rules: &PrefilterRules) -> Vec<(u32, String)> {
+    rules
+        .denied()
+        .map(|(vni, prefix)| (vni.as_u32(), prefix.to_string()))
+        .collect()
+}
+
+#[test]
+fn prefilter_denies_sources_denied_to_any_destination() {
+    let any = AclProtoMatch::Any;
+    let acl = Acl::new(
+        AclAction::Allow,
+        vec![
+            rule(
+                "deny-half",
+                AclAction::Deny,
+                AclScope::Packet,
+                pattern(&["10.0.0.128/25"], &[], any),
+            ),
+            // only some of the traffic of the source is denied
+            rule(
+                "deny-tcp",
+                AclAction::Deny,
+                AclScope::Packet,
+                pattern(&["10.0.0.0/26"], &[], AclProtoMatch::Tcp),
+            ),
+            rule(
+                "deny-narrow",
+                AclAction::Deny,
+                AclScope::Packet,
+                pattern(&["10.0.0.0/26"], &["20.0.0.0/25"], any),
+            ),
+            rule(
+                "allow",
+                AclAction::Allow,
+                AclScope::Packet,
+                pattern(&[], &[], any),
+            ),
+            // an earlier rule may allow some of the traffic of the source
+            rule(
+                "deny-late",
+                AclAction::Deny,
+                AclScope::Packet,
+                pattern(&["10.0.0.64/26"], &[], any),
+            ),
+        ],
+    );
+    let rules = prefilter(acl);
+    assert_eq!(
+        denied_sources(&rules),
+        vec![(VNI1, "10.0.0.128/25".to_string())]
+    );
+    assert!(rules.spoofed().any(|p| p.to_string() == "127.0.0.0/8"));
+}
+
+#[test]
+fn prefilter_denies_all_sources_by_default_action() {
+    // no rule allows traffic from vpc1, nor replies to it
+    let acl = Acl::new(
+        AclAction::Deny,
+        vec![directional_rule(
+            "allow-from-vpc2",
+            "vpc2",
+            "vpc1",
+            AclAction::Allow,
+            AclScope::Packet,
+            pattern(&[], &[], AclProtoMatch::Any),
+        )],
+    );
+    assert_eq!(
+        denied_sources(&prefilter(acl)),
+        vec![(VNI1, "0.0.0.0/0".to_string())]
+    );
+}
+
 // -------------------------------------------------------------------------------------------------
 // End-to-end flow-scope test
 //
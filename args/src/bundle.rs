@@ -0,0 +1,426 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Supplementary data sections handed from `dataplane-init` to the dataplane.
+//!
+//! Besides the launch configuration and its integrity check, the parent process may hand the
+//! worker named sections of arbitrary data (e.g. a hardware scan report, a pre-computed NUMA
+//! plan or TLS certificates). Each section is a [`FinalizedMemFile`] of its own. A
+//! [`MemFdBundle`] collects them and is sealed into a [`SealedBundle`]: the sections, and a
+//! manifest listing their names, lengths and [`IntegrityCheck`]s, also in a sealed memfd.
+//!
+//! The manifest is passed at [`SealedBundle::STANDARD_MANIFEST_FD`], and the sections at the
+//! following file descriptor numbers, in the order of the manifest. The worker recovers them
+//! with [`InheritedBundle::inherit()`], which checks each section against the manifest, and
+//! then looks the sections up by name.
+//!
+//! [`IntegrityCheck`]: crate::IntegrityCheck
+
+use crate::{FinalizedMemFile, IntegrityCheck, IntegrityCheckBytes, MemFile};
+use bytecheck::CheckBytes;
+use miette::{Context, IntoDiagnostic};
+use nix::fcntl::FcntlArg;
+use std::collections::BTreeMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::os::fd::{BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use tracing::debug;
+
+/// The maximum length of the name of a section
+const MAX_NAME_LEN: usize = 64;
+
+/// Errors building, sealing or inheriting a bundle of sections
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum MemFdBundleError {
+    #[error(
+        "Invalid section name \"{0}\": must have 1 to {MAX_NAME_LEN} ASCII alphanumeric characters, '-', '_' or '.'"
+    )]
+    InvalidName(String),
+    #[error("Section \"{0}\" is attached more than once")]
+    DuplicateSection(String),
+    #[error(
+        "A bundle can't have more than {} sections",
+        SealedBundle::MAX_SECTIONS
+    )]
+    TooManySections,
+    #[error("Invalid bundle manifest: {0}")]
+    InvalidManifest(String),
+    #[error("The manifest lists {listed} sections but {given} were given")]
+    SectionCount { listed: usize, given: usize },
+    #[error("Invalid section \"{0}\": {1}")]
+    InvalidSection(String, String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// The description of a section in the manifest
+#[derive(Debug, PartialEq, Eq, rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, CheckBytes)]
+#[rkyv(attr(derive(Debug, PartialEq, Eq)))]
+struct ManifestEntry {
+    name: String,
+    len: u64,
+    integrity_check: IntegrityCheckBytes,
+}
+
+/// The list of the sections of a bundle, in the order of their file descriptors
+#[derive(Debug, PartialEq, Eq, rkyv::Serialize, rkyv::Deserialize, rkyv::Archive, CheckBytes)]
+#[rkyv(attr(derive(Debug, PartialEq, Eq)))]
+struct Manifest {
+    sections: Vec<ManifestEntry>,
+}
+
+/// Check that a name can be used for a section
+fn check_name(name: &str) -> Result<(), MemFdBundleError> {
+    let valid = |c: char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.');
+    if name.is_empty() || name.len() > MAX_NAME_LEN || !name.chars().all(valid) {
+        return Err(MemFdBundleError::InvalidName(name.to_string()));
+    }
+    Ok(())
+}
+
+/// Seek a sealed memfd back to its start
+fn rewind(file: &mut FinalizedMemFile) -> Result<(), MemFdBundleError> {
+    file.0.0.seek(SeekFrom::Start(0))?;
+    Ok(())
+}
+
+/// The named sections of data the parent process hands to the worker, under construction.
+#[derive(Default)]
+pub struct MemFdBundle {
+    sections: Vec<(String, FinalizedMemFile)>,
+}
+
+impl MemFdBundle {
+    /// Create an empty bundle
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Attach a section with the given `contents`, in a memfd sealed right away.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name is invalid or already used, or if the bundle is full.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the memfd can't be created or written to, as when launching the dataplane.
+    pub fn attach(&mut self, name: &str, contents: &[u8]) -> Result<(), MemFdBundleError> {
+        self.check_attach(name)?;
+        let mut memfd = MemFile::new();
+        memfd
+            .as_mut()
+            .write_all(contents)
+            .into_diagnostic()
+            .wrap_err_with(|| format!("failed to write section {name} to memfd"))
+            .unwrap();
+        self.sections.push((name.to_string(), memfd.finalize()));
+        Ok(())
+    }
+
+    /// Attach a section whose contents are already sealed in a memfd
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the name is invalid or already used, or if the bundle is full.
+    pub fn attach_memfd(
+        &mut self,
+        name: &str,
+        file: FinalizedMemFile,
+    ) -> Result<(), MemFdBundleError> {
+        self.check_attach(name)?;
+        self.sections.push((name.to_string(), file));
+        Ok(())
+    }
+
+    fn check_attach(&self, name: &str) -> Result<(), MemFdBundleError> {
+        check_name(name)?;
+        if self.sections.iter().any(|(attached, _)| attached == name) {
+            return Err(MemFdBundleError::DuplicateSection(name.to_string()));
+        }
+        if self.sections.len() >= SealedBundle::MAX_SECTIONS {
+            return Err(MemFdBundleError::TooManySections);
+        }
+        Ok(())
+    }
+
+    /// Compute the manifest of the sections and seal it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a section can't be read.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the manifest can't be serialized, or its memfd created or written to.
+    pub fn seal(self) -> Result<SealedBundle, MemFdBundleError> {
+        let mut entries = Vec::with_capacity(self.sections.len());
+        let mut sections = Vec::with_capacity(self.sections.len());
+        for (name, mut file) in self.sections {
+            let len = file.as_ref().metadata()?.len();
+            let integrity_check = file.integrity_check().serialize();
+            rewind(&mut file)?;
+            entries.push(ManifestEntry {
+                name,
+                len,
+                integrity_check,
+            });
+            sections.push(file);
+        }
+        let manifest = Manifest { sections: entries };
+        let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&manifest)
+            .into_diagnostic()
+            .wrap_err("failed to serialize bundle manifest")
+            .unwrap();
+        let mut memfd = MemFile::new();
+        memfd
+            .as_mut()
+            .write_all(bytes.as_slice())
+            .into_diagnostic()
+            .wrap_err("failed to write bundle manifest to memfd")
+            .unwrap();
+        Ok(SealedBundle {
+            manifest: memfd.finalize(),
+            sections,
+        })
+    }
+}
+
+/// A bundle ready to be handed to the worker: the sealed memfds of its manifest and sections.
+pub struct SealedBundle {
+    /// The memfd of the manifest
+    pub manifest: FinalizedMemFile,
+    /// The memfds of the sections, in the order of the manifest
+    pub sections: Vec<FinalizedMemFile>,
+}
+
+impl SealedBundle {
+    /// Standard file descriptor number for the manifest memfd.
+    ///
+    /// The parent process must pass the manifest at this file descriptor number, and the
+    /// sections at the following ones, in the order of the manifest.
+    pub const STANDARD_MANIFEST_FD: RawFd = 50;
+
+    /// The maximum number of sections of a bundle
+    pub const MAX_SECTIONS: usize = 64;
+
+    /// Consume the bundle and return its file descriptors, along with the number each must be
+    /// given in the worker process.
+    ///
+    /// # Note
+    ///
+    /// As with [`FinalizedMemFile::to_owned_fd`], only call this when about to hand the file
+    /// descriptors to the worker.
+    #[must_use]
+    pub fn to_owned_fds(self) -> Vec<(RawFd, OwnedFd)> {
+        std::iter::once(self.manifest)
+            .chain(self.sections)
+            .zip(Self::STANDARD_MANIFEST_FD..)
+            .map(|(file, fd)| (fd, file.to_owned_fd()))
+            .collect()
+    }
+}
+
+/// The sections inherited from the parent process, by name
+#[derive(Default)]
+pub struct InheritedBundle {
+    sections: BTreeMap<String, FinalizedMemFile>,
+}
+
+impl InheritedBundle {
+    /// Inherit the bundle passed by the parent process at the standard file descriptor numbers
+    /// (see [`SealedBundle::STANDARD_MANIFEST_FD`]). The bundle is empty if the parent passed
+    /// no manifest.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the manifest or any of the sections are missing or invalid, or if a
+    /// section does not match its description in the manifest.
+    #[allow(unsafe_code)] // the descriptors at the standard numbers are reserved for the bundle
+    pub fn inherit() -> Result<Self, MemFdBundleError> {
+        let is_open = |fd: RawFd| {
+            let fd = unsafe { BorrowedFd::borrow_raw(fd) };
+            nix::fcntl::fcntl(fd, FcntlArg::F_GETFD).is_ok()
+        };
+        if !is_open(SealedBundle::STANDARD_MANIFEST_FD) {
+            debug!("No bundle of sections inherited");
+            return Ok(Self::default());
+        }
+        let take = |fd: RawFd| -> Result<FinalizedMemFile, MemFdBundleError> {
+            if !is_open(fd) {
+                return Err(MemFdBundleError::InvalidManifest(format!(
+                    "file descriptor {fd} is not open"
+                )));
+            }
+            let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+            unsafe { FinalizedMemFile::try_from_fd(fd) }
+                .map_err(|e| MemFdBundleError::InvalidManifest(e.to_string()))
+        };
+        let mut manifest_file = take(SealedBundle::STANDARD_MANIFEST_FD)?;
+        let manifest = Self::read_manifest(&mut manifest_file)?;
+        let sections = (SealedBundle::STANDARD_MANIFEST_FD + 1..)
+            .take(manifest.sections.len())
+            .map(take)
+            .collect::<Result<_, _>>()?;
+        Self::check(manifest, sections)
+    }
+
+    /// Recover a bundle from the memfds of its manifest and of its sections, in the order of
+    /// the manifest. This is the part of [`Self::inherit()`] which does not depend on the file
+    /// descriptor numbers.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the manifest is invalid, or if the sections don't match it.
+    pub fn from_sealed(
+        manifest: FinalizedMemFile,
+        sections: Vec<FinalizedMemFile>,
+    ) -> Result<Self, MemFdBundleError> {
+        let mut manifest_file = manifest;
+        let manifest = Self::read_manifest(&mut manifest_file)?;
+        Self::check(manifest, sections)
+    }
+
+    fn read_manifest(file: &mut FinalizedMemFile) -> Result<Manifest, MemFdBundleError> {
+        rewind(file)?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        // the memfd is page aligned when mapped: align the copy likewise
+        let mut aligned = rkyv::util::AlignedVec::<16>::with_capacity(contents.len());
+        aligned.extend_from_slice(&contents);
+        let manifest = rkyv::from_bytes::<Manifest, rkyv::rancor::Error>(&aligned)
+            .map_err(|e| MemFdBundleError::InvalidManifest(e.to_string()))?;
+        if manifest.sections.len() > SealedBundle::MAX_SECTIONS {
+            return Err(MemFdBundleError::TooManySections);
+        }
+        Ok(manifest)
+    }
+
+    /// Check the sections against the manifest
+    fn check(
+        manifest: Manifest,
+        sections: Vec<FinalizedMemFile>,
+    ) -> Result<Self, MemFdBundleError> {
+        if manifest.sections.len() != sections.len() {
+            return Err(MemFdBundleError::SectionCount {
+                listed: manifest.sections.len(),
+                given: sections.len(),
+            });
+        }
+        let mut bundle = Self::default();
+        for (entry, mut file) in manifest.sections.into_iter().zip(sections) {
+            check_name(&entry.name)?;
+            let invalid = |reason: &str| {
+                MemFdBundleError::InvalidSection(entry.name.clone(), reason.to_string())
+            };
+            if file.as_ref().metadata()?.len() != entry.len {
+                return Err(invalid("length mismatch"));
+            }
+            if file.integrity_check() != IntegrityCheck::deserialize(entry.integrity_check) {
+                return Err(invalid("checksum mismatch"));
+            }
+            rewind(&mut file)?;
+            if bundle.sections.insert(entry.name.clone(), file).is_some() {
+                return Err(MemFdBundleError::DuplicateSection(entry.name));
+            }
+        }
+        debug!("Inherited bundle sections: {:?}", bundle.sections.keys());
+        Ok(bundle)
+    }
+
+    /// The names of the sections
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.sections.keys().map(String::as_str)
+    }
+
+    /// Tell if there is a section with the given name
+    #[must_use]
+    pub fn contains(&self, name: &str) -> bool {
+        self.sections.contains_key(name)
+    }
+
+    /// Take the memfd of the section with the given name, if any
+    pub fn take(&mut self, name: &str) -> Option<FinalizedMemFile> {
+        self.sections.remove(name)
+    }
+
+    /// Read the contents of the section with the given name, if any
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the section can't be read.
+    pub fn read(&mut self, name: &str) -> Result<Option<Vec<u8>>, MemFdBundleError> {
+        let Some(file) = self.sections.get_mut(name) else {
+            return Ok(None);
+        };
+        rewind(file)?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        Ok(Some(contents))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InheritedBundle, MemFdBundle, MemFdBundleError, SealedBundle};
+
+    fn bundle() -> MemFdBundle {
+        let mut bundle = MemFdBundle::new();
+        bundle.attach("hardware-scan", b"{\"nics\": []}").unwrap();
+        bundle.attach("numa.plan", &[1, 2, 3, 4]).unwrap();
+        bundle.attach("empty", &[]).unwrap();
+        bundle
+    }
+
+    #[test]
+    fn bundle_round_trip() {
+        let SealedBundle { manifest, sections } = bundle().seal().unwrap();
+        let mut inherited = InheritedBundle::from_sealed(manifest, sections).unwrap();
+        assert_eq!(
+            inherited.names().collect::<Vec<_>>(),
+            ["empty", "hardware-scan", "numa.plan"]
+        );
+        assert_eq!(inherited.read("numa.plan").unwrap(), Some(vec![1, 2, 3, 4]));
+        assert_eq!(inherited.read("empty").unwrap(), Some(vec![]));
+        assert_eq!(inherited.read("tls").unwrap(), None);
+        assert!(inherited.take("hardware-scan").is_some());
+        assert!(!inherited.contains("hardware-scan"));
+    }
+
+    #[test]
+    fn bundle_rejects_invalid_sections() {
+        let mut invalid = bundle();
+        assert!(matches!(
+            invalid.attach("numa.plan", &[]),
+            Err(MemFdBundleError::DuplicateSection(_))
+        ));
+        for name in ["", "tls certs", "../etc"] {
+            assert!(matches!(
+                invalid.attach(name, &[]),
+                Err(MemFdBundleError::InvalidName(_))
+            ));
+        }
+
+        // sections swapped after sealing
+        let SealedBundle {
+            manifest,
+            mut sections,
+        } = invalid.seal().unwrap();
+        sections.swap(0, 1);
+        assert!(matches!(
+            InheritedBundle::from_sealed(manifest, sections),
+            Err(MemFdBundleError::InvalidSection(..))
+        ));
+
+        // a missing section
+        let SealedBundle {
+            manifest,
+            mut sections,
+        } = bundle().seal().unwrap();
+        sections.pop();
+        assert!(matches!(
+            InheritedBundle::from_sealed(manifest, sections),
+            Err(MemFdBundleError::SectionCount { .. })
+        ));
+    }
+}
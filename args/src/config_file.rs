@@ -0,0 +1,237 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Settings of the dataplane given in a config file rather than as command line flags.
+//!
+//! A config file maps the long names of the flags (with `-` or `_` as separator) to their
+//! values. Flags taking several values take a list, and boolean flags `true` or `false`:
+//!
+//! ```yaml
+//! driver: kernel
+//! interface:
+//!   - eth0=kernel@enp2s0
+//!   - eth1=kernel@enp2s1
+//! metrics_address: 0.0.0.0:9090
+//! bmp-enable: true
+//! ```
+//!
+//! Files whose name ends with `.toml` are read as TOML, others as YAML. The settings are checked
+//! as the flags are, and take precedence over the defaults of the flags, but not over the flags
+//! given on the command line.
+
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, Command};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::Path;
+
+/// Errors reading a config file
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum ConfigFileError {
+    #[error("Failed to read config file {0}: {1}")]
+    Read(String, std::io::Error),
+    #[error("Invalid config file {0}: {1}")]
+    Parse(String, String),
+    #[error("Unknown setting {0} in config file")]
+    UnknownSetting(String),
+    #[error("Setting {0} can't be given in a config file")]
+    Forbidden(String),
+    #[error("Setting {0} of the config file must be {1}")]
+    InvalidValue(String, &'static str),
+}
+
+/// The value of a setting
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Value {
+    Bool(bool),
+    Integer(i64),
+    Text(String),
+    List(Vec<Value>),
+}
+
+impl Value {
+    /// The value as the argument of a flag
+    fn as_arg(&self, name: &str) -> Result<String, ConfigFileError> {
+        match self {
+            Value::Bool(value) => Ok(value.to_string()),
+            Value::Integer(value) => Ok(value.to_string()),
+            Value::Text(value) => Ok(value.clone()),
+            Value::List(_) => Err(ConfigFileError::InvalidValue(
+                name.to_string(),
+                "a single value",
+            )),
+        }
+    }
+}
+
+/// Read the settings of a config file
+fn read(path: &Path) -> Result<BTreeMap<String, Value>, ConfigFileError> {
+    let name = path.display().to_string();
+    let contents =
+        std::fs::read_to_string(path).map_err(|e| ConfigFileError::Read(name.clone(), e))?;
+    if path.extension().is_some_and(|ext| ext == "toml") {
+        toml::from_str(&contents).map_err(|e| ConfigFileError::Parse(name, e.to_string()))
+    } else {
+        serde_yaml_ng::from_str::<Option<_>>(&contents)
+            .map(Option::unwrap_or_default)
+            .map_err(|e| ConfigFileError::Parse(name, e.to_string()))
+    }
+}
+
+/// Turn the settings of the config file at `path` into command line arguments of `command`.
+/// The settings of the flags given on the command line, per `given`, are left out.
+///
+/// # Errors
+///
+/// Returns an error if the file can't be read, or gives unknown settings or settings of the wrong
+/// type. The values of the settings are only checked when parsing the arguments.
+pub(crate) fn file_args(
+    command: &Command,
+    path: &Path,
+    given: &ArgMatches,
+) -> Result<Vec<OsString>, ConfigFileError> {
+    let mut args = vec![];
+    for (name, value) in read(path)? {
+        let long = name.replace('_', "-");
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long.as_str()))
+            .ok_or_else(|| ConfigFileError::UnknownSetting(name.clone()))?;
+        if arg.get_id() == "config_file" {
+            return Err(ConfigFileError::Forbidden(name));
+        }
+        if given.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        let flag = OsString::from(format!("--{long}"));
+        match (arg.get_action(), &value) {
+            (ArgAction::SetTrue, Value::Bool(true)) => args.push(flag),
+            (ArgAction::SetTrue, Value::Bool(false)) => {}
+            (ArgAction::SetTrue, _) => {
+                return Err(ConfigFileError::InvalidValue(name, "true or false"));
+            }
+            (ArgAction::Append, Value::List(values)) => {
+                for value in values {
+                    args.push(flag.clone());
+                    args.push(value.as_arg(&name)?.into());
+                }
+            }
+            (_, value) => {
+                args.push(flag);
+                args.push(value.as_arg(&name)?.into());
+            }
+        }
+    }
+    Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CmdArgs, InterfaceArg};
+    use std::path::PathBuf;
+    use std::str::FromStr;
+
+    /// Write a config file for a test, removed once dropped
+    struct TestFile(PathBuf);
+    impl TestFile {
+        fn new(name: &str, contents: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("args-{}-{name}", std::process::id()));
+            std::fs::write(&path, contents).unwrap();
+            TestFile(path)
+        }
+        fn parse(&self, flags: &[&str]) -> Result<CmdArgs, clap::Error> {
+            let path = self.0.to_str().unwrap();
+            let argv = ["dataplane", "--config-file", path].iter().chain(flags);
+            CmdArgs::try_parse_with_config_file_from(argv)
+        }
+    }
+    impl Drop for TestFile {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    const YAML: &str = "
+driver: kernel
+interface:
+  - eth0=kernel@enp2s0
+  - eth1=kernel@enp2s1
+metrics_address: 0.0.0.0:9191
+num-workers: 4
+bmp-enable: true
+nat-alg-sip: true
+";
+
+    #[test]
+    fn config_file_yaml() {
+        let file = TestFile::new("config.yaml", YAML);
+        let args = file.parse(&[]).unwrap();
+        assert_eq!(args.driver_name(), "kernel");
+        assert_eq!(
+            args.interfaces().collect::<Vec<_>>(),
+            vec![
+                InterfaceArg::from_str("eth0=kernel@enp2s0").unwrap(),
+                InterfaceArg::from_str("eth1=kernel@enp2s1").unwrap(),
+            ]
+        );
+        assert_eq!(args.metrics_address().to_string(), "0.0.0.0:9191");
+        assert_eq!(args.kernel_num_workers(), 4);
+        assert!(args.bmp_enabled());
+        assert!(args.nat_alg_sip());
+        assert!(args.nat_alg_ftp());
+    }
+
+    #[test]
+    fn config_file_flags_take_precedence() {
+        let file = TestFile::new("precedence.yaml", YAML);
+        let args = file
+            .parse(&[
+                "--interface",
+                "eth9=kernel@enp9s0",
+                "--num-workers",
+                "2",
+                "--nat-alg-sip",
+                "false",
+            ])
+            .unwrap();
+        assert_eq!(
+            args.interfaces().collect::<Vec<_>>(),
+            vec![InterfaceArg::from_str("eth9=kernel@enp9s0").unwrap()]
+        );
+        assert_eq!(args.kernel_num_workers(), 2);
+        assert!(!args.nat_alg_sip());
+        assert_eq!(args.driver_name(), "kernel");
+    }
+
+    #[test]
+    fn config_file_toml() {
+        let file = TestFile::new(
+            "config.toml",
+            "driver = \"kernel\"\ninterface = [\"eth0=kernel@enp2s0\"]\nbmp_interval = 5000\n",
+        );
+        let args = file.parse(&[]).unwrap();
+        assert_eq!(args.driver_name(), "kernel");
+        assert_eq!(args.interfaces().count(), 1);
+        assert_eq!(args.bmp_interval().as_millis(), 5000);
+    }
+
+    #[test]
+    fn config_file_invalid() {
+        for (name, contents) in [
+            ("unknown.yaml", "no-such-flag: 1\n"),
+            ("nested.yaml", "config-file: other.yaml\n"),
+            ("flag.yaml", "bmp-enable: yes please\n"),
+            ("list.yaml", "driver: [kernel, dpdk]\n"),
+            ("value.yaml", "num-workers: many\n"),
+            ("syntax.toml", "driver = \n"),
+        ] {
+            let file = TestFile::new(name, contents);
+            assert!(file.parse(&[]).is_err(), "{name}");
+        }
+        let missing = std::env::temp_dir().join("args-no-such-config.yaml");
+        let argv = ["dataplane", "--config-file", missing.to_str().unwrap()];
+        assert!(CmdArgs::try_parse_with_config_file_from(argv).is_err());
+    }
+}
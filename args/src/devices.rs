@@ -0,0 +1,427 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Selection of the PCI devices handed to DPDK by their properties.
+//!
+//! Beyond exact PCI addresses, the NICs of the DPDK driver may be selected with filters like
+//! `match:vendor=mellanox,numa=0,count=2`. The filters are resolved by a [`DeviceSearch`] when
+//! the launch configuration is built: each filter, in order, picks the network devices it
+//! matches by increasing PCI address, skipping those already picked, so that the same host
+//! always yields the same devices.
+
+use bytecheck::CheckBytes;
+use net::pci::PciEbdf;
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Where the kernel lists the PCI devices
+pub(crate) const PCI_DEVICES: &str = "/sys/bus/pci/devices";
+
+/// The PCI class of network controllers
+const NETWORK_CLASS: u32 = 0x02;
+
+/// A PCI vendor, given by name if known, or by its hexadecimal id
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    rkyv::Archive,
+    CheckBytes,
+)]
+#[rkyv(attr(derive(PartialEq, Eq, Debug)))]
+pub struct PciVendor(pub u16);
+
+impl PciVendor {
+    /// The vendors known by name
+    const NAMES: [(&'static str, u16); 6] = [
+        ("amazon", 0x1d0f),
+        ("broadcom", 0x14e4),
+        ("intel", 0x8086),
+        ("mellanox", 0x15b3),
+        ("nvidia", 0x15b3),
+        ("virtio", 0x1af4),
+    ];
+}
+
+impl Display for PciVendor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match Self::NAMES.iter().find(|(_, id)| *id == self.0) {
+            Some((name, _)) => write!(f, "{name}"),
+            None => write!(f, "{:04x}", self.0),
+        }
+    }
+}
+
+impl FromStr for PciVendor {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        if let Some((_, id)) = Self::NAMES.iter().find(|(name, _)| *name == input) {
+            return Ok(PciVendor(*id));
+        }
+        parse_id(input)
+            .map(PciVendor)
+            .map_err(|e| format!("Unknown vendor '{input}': {e}"))
+    }
+}
+
+/// Parse a PCI id, in hexadecimal with an optional `0x` prefix
+fn parse_id(input: &str) -> Result<u16, String> {
+    let digits = input.strip_prefix("0x").unwrap_or(input);
+    u16::from_str_radix(digits, 16).map_err(|e| format!("bad hexadecimal id: {e}"))
+}
+
+/// A filter selecting PCI network devices by their properties, with syntax
+/// `match:KEY=VALUE,...`.
+///
+/// The keys are `vendor` (a name like `mellanox` or a hexadecimal id), `device` (a hexadecimal
+/// id), `driver` (the kernel driver the device is bound to), `numa` (the NUMA node of the
+/// device) and `count` (the number of devices to pick, 1 unless given). Properties left out
+/// match any device.
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    rkyv::Archive,
+    CheckBytes,
+)]
+#[rkyv(attr(derive(PartialEq, Eq, Debug)))]
+pub struct PciDeviceFilter {
+    /// The vendor of the devices
+    pub vendor: Option<PciVendor>,
+    /// The device id of the devices
+    pub device: Option<u16>,
+    /// The kernel driver the devices are bound to
+    pub driver: Option<String>,
+    /// The NUMA node of the devices
+    pub numa: Option<u16>,
+    /// The number of devices to pick
+    pub count: u16,
+}
+
+impl PciDeviceFilter {
+    /// The keys of the criteria
+    const KEYS: [&'static str; 5] = ["vendor", "device", "driver", "numa", "count"];
+
+    /// Tell if `device` matches the filter
+    #[must_use]
+    pub fn matches(&self, device: &PciDeviceInfo) -> bool {
+        self.vendor.is_none_or(|vendor| vendor.0 == device.vendor)
+            && self.device.is_none_or(|id| id == device.device)
+            && self
+                .driver
+                .as_ref()
+                .is_none_or(|driver| device.driver.as_ref() == Some(driver))
+            && self.numa.is_none_or(|numa| device.numa == Some(numa))
+    }
+}
+
+impl Display for PciDeviceFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "match:")?;
+        if let Some(vendor) = self.vendor {
+            write!(f, "vendor={vendor},")?;
+        }
+        if let Some(device) = self.device {
+            write!(f, "device={device:04x},")?;
+        }
+        if let Some(driver) = &self.driver {
+            write!(f, "driver={driver},")?;
+        }
+        if let Some(numa) = self.numa {
+            write!(f, "numa={numa},")?;
+        }
+        write!(f, "count={}", self.count)
+    }
+}
+
+impl FromStr for PciDeviceFilter {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let criteria = input
+            .strip_prefix("match:")
+            .ok_or("Bad syntax: missing match:".to_string())?;
+        let mut filter = PciDeviceFilter {
+            vendor: None,
+            device: None,
+            driver: None,
+            numa: None,
+            count: 1,
+        };
+        let mut keys = BTreeSet::new();
+        for criterion in criteria.split(',') {
+            let (key, value) = criterion
+                .split_once('=')
+                .ok_or(format!("Bad criterion '{criterion}': missing ="))?;
+            if !keys.insert(key) {
+                return Err(format!("Criterion {key} given more than once"));
+            }
+            match key {
+                "vendor" => filter.vendor = Some(value.parse()?),
+                "device" => {
+                    let id = parse_id(value).map_err(|e| format!("Bad device '{value}': {e}"))?;
+                    filter.device = Some(id);
+                }
+                "driver" if value.is_empty() => return Err("Empty driver".to_string()),
+                "driver" => filter.driver = Some(value.to_string()),
+                "numa" => {
+                    let node = value
+                        .parse()
+                        .map_err(|e| format!("Bad NUMA node '{value}': {e}"))?;
+                    filter.numa = Some(node);
+                }
+                "count" => match value.parse::<u16>() {
+                    Ok(0) => return Err("Number of devices must be greater than 0".to_string()),
+                    Ok(count) => filter.count = count,
+                    Err(e) => return Err(format!("Bad number of devices '{value}': {e}")),
+                },
+                _ => {
+                    return Err(format!(
+                        "Unknown criterion '{key}': allowed values are {}",
+                        Self::KEYS.join("|")
+                    ));
+                }
+            }
+        }
+        Ok(filter)
+    }
+}
+
+/// The properties of a PCI network device that filters select on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PciDeviceInfo {
+    /// The PCI address of the device
+    pub address: PciEbdf,
+    /// The vendor id of the device
+    pub vendor: u16,
+    /// The device id of the device
+    pub device: u16,
+    /// The kernel driver the device is bound to, if any
+    pub driver: Option<String>,
+    /// The NUMA node of the device, if known
+    pub numa: Option<u16>,
+}
+
+impl PciDeviceInfo {
+    /// Read the properties of the PCI device at `path` in sysfs, if it is a network device
+    fn read(path: &Path) -> Option<Self> {
+        let read = |attribute: &str| std::fs::read_to_string(path.join(attribute)).ok();
+        let hex = |attribute: &str| {
+            let value = read(attribute)?;
+            let value = value.trim();
+            u32::from_str_radix(value.strip_prefix("0x").unwrap_or(value), 16).ok()
+        };
+        if hex("class")? >> 16 != NETWORK_CLASS {
+            return None;
+        }
+        let name = path.file_name()?.to_string_lossy().into_owned();
+        Some(PciDeviceInfo {
+            address: PciEbdf::try_new(name).ok()?,
+            vendor: u16::try_from(hex("vendor")?).ok()?,
+            device: u16::try_from(hex("device")?).ok()?,
+            driver: std::fs::read_link(path.join("driver"))
+                .ok()
+                .and_then(|link| {
+                    link.file_name()
+                        .map(|name| name.to_string_lossy().into_owned())
+                }),
+            // the kernel reports -1 when the node is not known
+            numa: read("numa_node").and_then(|node| node.trim().parse().ok()),
+        })
+    }
+}
+
+/// The PCI devices selected by a filter
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    rkyv::Archive,
+    CheckBytes,
+)]
+#[rkyv(attr(derive(PartialEq, Eq, Debug)))]
+pub struct DeviceMatch {
+    /// The filter
+    pub filter: PciDeviceFilter,
+    /// The devices it selected, by increasing PCI address
+    pub devices: Vec<PciEbdf>,
+}
+
+impl Display for DeviceMatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let devices: Vec<_> = self.devices.iter().map(ToString::to_string).collect();
+        write!(f, "{} selects {}", self.filter, devices.join(", "))
+    }
+}
+
+/// Errors resolving PCI device filters
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum DeviceSearchError {
+    #[error("Failed to list the PCI devices of this host: {0}")]
+    Scan(#[from] std::io::Error),
+    #[error("Filter {0} matches {1} devices not selected otherwise, but {2} are needed")]
+    NotEnoughDevices(PciDeviceFilter, usize, u16),
+}
+
+/// A search of the PCI network devices of a host, resolving [`PciDeviceFilter`]s
+#[derive(Debug, Clone)]
+pub struct DeviceSearch {
+    devices: Vec<PciDeviceInfo>,
+}
+
+impl DeviceSearch {
+    /// Search among `devices`.
+    #[must_use]
+    pub fn new(mut devices: Vec<PciDeviceInfo>) -> Self {
+        devices.sort_by(|a, b| a.address.cmp(&b.address));
+        Self { devices }
+    }
+
+    /// Search among the PCI network devices of this host, as listed in sysfs.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the PCI devices can't be listed.
+    pub fn scan() -> Result<Self, DeviceSearchError> {
+        let devices = std::fs::read_dir(PCI_DEVICES)?
+            .filter_map(|entry| PciDeviceInfo::read(&entry.ok()?.path()))
+            .collect();
+        Ok(Self::new(devices))
+    }
+
+    /// Resolve `filters`, in order. Each filter picks the devices it matches with the lowest
+    /// PCI addresses, skipping those in `taken` and those picked by the filters before it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a filter does not match enough devices.
+    pub fn resolve(
+        &self,
+        filters: &[PciDeviceFilter],
+        taken: &BTreeSet<PciEbdf>,
+    ) -> Result<Vec<DeviceMatch>, DeviceSearchError> {
+        let mut taken = taken.clone();
+        let mut matches = vec![];
+        for filter in filters {
+            let devices: Vec<_> = self
+                .devices
+                .iter()
+                .filter(|device| !taken.contains(&device.address) && filter.matches(device))
+                .take(usize::from(filter.count))
+                .map(|device| device.address.clone())
+                .collect();
+            if devices.len() < usize::from(filter.count) {
+                return Err(DeviceSearchError::NotEnoughDevices(
+                    filter.clone(),
+                    devices.len(),
+                    filter.count,
+                ));
+            }
+            taken.extend(devices.iter().cloned());
+            matches.push(DeviceMatch {
+                filter: filter.clone(),
+                devices,
+            });
+        }
+        Ok(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeviceSearch, DeviceSearchError, PciDeviceFilter, PciDeviceInfo, PciVendor};
+    use net::pci::PciEbdf;
+    use std::collections::BTreeSet;
+    use std::str::FromStr;
+
+    fn pci(address: &str) -> PciEbdf {
+        PciEbdf::try_new(address.to_string()).unwrap()
+    }
+
+    fn nic(address: &str, vendor: u16, driver: &str, numa: u16) -> PciDeviceInfo {
+        PciDeviceInfo {
+            address: pci(address),
+            vendor,
+            device: 0x1017,
+            driver: Some(driver.to_string()),
+            numa: Some(numa),
+        }
+    }
+
+    #[test]
+    fn device_filter_syntax() {
+        let filter = PciDeviceFilter::from_str("match:vendor=mellanox,numa=0,count=2").unwrap();
+        assert_eq!(filter.vendor, Some(PciVendor(0x15b3)));
+        assert_eq!(filter.numa, Some(0));
+        assert_eq!(filter.count, 2);
+        assert_eq!(filter.to_string(), "match:vendor=mellanox,numa=0,count=2");
+
+        let filter = PciDeviceFilter::from_str("match:vendor=0x1234,device=1017").unwrap();
+        assert_eq!(filter.vendor, Some(PciVendor(0x1234)));
+        assert_eq!(filter.device, Some(0x1017));
+        assert_eq!(filter.count, 1);
+        assert_eq!(
+            PciDeviceFilter::from_str(&filter.to_string()).unwrap(),
+            filter
+        );
+
+        for bad in [
+            "vendor=mellanox",
+            "match:",
+            "match:vendor=acme",
+            "match:numa=x",
+            "match:count=0",
+            "match:driver=",
+            "match:speed=100g",
+            "match:numa=0,numa=1",
+        ] {
+            assert!(PciDeviceFilter::from_str(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn device_search_is_deterministic() {
+        let search = DeviceSearch::new(vec![
+            nic("0000:81:00.1", 0x15b3, "mlx5_core", 1),
+            nic("0000:03:00.0", 0x8086, "ice", 0),
+            nic("0000:02:00.1", 0x15b3, "mlx5_core", 0),
+            nic("0000:02:00.0", 0x15b3, "mlx5_core", 0),
+            nic("0000:81:00.0", 0x15b3, "mlx5_core", 1),
+        ]);
+        let filters = [
+            PciDeviceFilter::from_str("match:vendor=mellanox,count=3").unwrap(),
+            PciDeviceFilter::from_str("match:driver=ice").unwrap(),
+        ];
+        let taken = BTreeSet::from([pci("0000:02:00.1")]);
+        let matches = search.resolve(&filters, &taken).unwrap();
+        assert_eq!(
+            matches[0].devices,
+            [
+                pci("0000:02:00.0"),
+                pci("0000:81:00.0"),
+                pci("0000:81:00.1")
+            ]
+        );
+        assert_eq!(matches[1].devices, [pci("0000:03:00.0")]);
+
+        let filters = [PciDeviceFilter::from_str("match:vendor=mellanox,numa=0,count=2").unwrap()];
+        assert!(matches!(
+            search.resolve(&filters, &taken),
+            Err(DeviceSearchError::NotEnoughDevices(_, 1, 2))
+        ));
+    }
+}
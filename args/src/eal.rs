@@ -0,0 +1,488 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Initialization settings of the DPDK Environment Abstraction Layer (EAL).
+//!
+//! The settings are checked for conflicts when the launch configuration is built from the command
+//! line, and only turned into EAL arguments by [`EalConfig::args`], when the driver initializes
+//! the EAL.
+
+use bytecheck::CheckBytes;
+use net::pci::PciEbdf;
+use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// The IO virtual addresses used by DPDK
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    rkyv::Archive,
+    CheckBytes,
+)]
+#[serde(rename_all = "snake_case")]
+#[rkyv(attr(derive(PartialEq, Eq, Debug)))]
+#[repr(u8)]
+pub enum IovaMode {
+    /// Physical addresses
+    Pa,
+    /// Virtual addresses
+    Va,
+}
+
+impl Display for IovaMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IovaMode::Pa => write!(f, "pa"),
+            IovaMode::Va => write!(f, "va"),
+        }
+    }
+}
+
+impl FromStr for IovaMode {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "pa" => Ok(IovaMode::Pa),
+            "va" => Ok(IovaMode::Va),
+            _ => Err(format!(
+                "Unknown IOVA mode '{input}': allowed values are pa|va"
+            )),
+        }
+    }
+}
+
+/// A list of lcores, with syntax like `0-3,8`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LcoreList(pub Vec<u16>);
+
+impl FromStr for LcoreList {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let lcore = |s: &str| {
+            s.parse::<u16>()
+                .map_err(|e| format!("Bad lcore '{s}': {e}"))
+        };
+        let mut lcores = vec![];
+        for item in input.split(',') {
+            match item.split_once('-') {
+                None => lcores.push(lcore(item)?),
+                Some((first, last)) => {
+                    let (first, last) = (lcore(first)?, lcore(last)?);
+                    if first > last {
+                        return Err(format!("Bad lcore range {item}: {first} > {last}"));
+                    }
+                    lcores.extend(first..=last);
+                }
+            }
+        }
+        Ok(LcoreList(lcores))
+    }
+}
+
+/// The hugepage memory to reserve on each NUMA node, in MB, indexed by node, with syntax like
+/// `1024,0,512`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SocketMemList(pub Vec<u32>);
+
+impl FromStr for SocketMemList {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        input
+            .split(',')
+            .map(|mb| {
+                mb.parse::<u32>()
+                    .map_err(|e| format!("Bad memory size '{mb}': {e}"))
+            })
+            .collect::<Result<_, _>>()
+            .map(SocketMemList)
+    }
+}
+
+/// The device arguments of a PCI device, with syntax `PCI_ADDRESS=DEVARGS`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DevargsArg {
+    pub address: PciEbdf,
+    pub devargs: String,
+}
+
+impl FromStr for DevargsArg {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (address, devargs) = input
+            .split_once('=')
+            .ok_or("Bad syntax: missing =".to_string())?;
+        Ok(DevargsArg {
+            address: PciEbdf::try_new(address.to_string()).map_err(|e| e.to_string())?,
+            devargs: devargs.to_string(),
+        })
+    }
+}
+
+/// A PCI device handed to DPDK
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    rkyv::Archive,
+    CheckBytes,
+)]
+#[rkyv(attr(derive(PartialEq, Eq, Debug)))]
+pub struct EalDevice {
+    /// The PCI address of the device
+    pub address: PciEbdf,
+    /// The arguments of the driver of the device (e.g. `mprq_en=1,rxqs_min_mprq=1`)
+    pub devargs: Option<String>,
+}
+
+/// Errors resulting from conflicting or invalid EAL settings
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum InvalidEalConfig {
+    #[error("The EAL allow and block lists of PCI devices are exclusive")]
+    AllowAndBlock,
+    #[error("PCI device {0} is listed more than once")]
+    DuplicateDevice(PciEbdf),
+    #[error("Lcore {0} is listed more than once")]
+    DuplicateLcore(u16),
+    #[error("The number of memory channels must be positive")]
+    NoMemoryChannels,
+    #[error("Hugepage directory {0} is not an absolute path")]
+    RelativeHugeDir(String),
+    #[error("Device arguments given for PCI device {0}, which is not allowed")]
+    UnknownDevice(PciEbdf),
+    #[error("Invalid device arguments for PCI device {0}: {1}")]
+    InvalidDevargs(PciEbdf, &'static str),
+}
+
+/// Initialization settings of the DPDK EAL.
+///
+/// Settings left empty are left to the defaults of the EAL.
+#[derive(
+    Debug,
+    Clone,
+    Default,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    rkyv::Archive,
+    CheckBytes,
+)]
+#[rkyv(attr(derive(PartialEq, Eq, Debug)))]
+pub struct EalConfig {
+    /// The lcores to run on
+    pub lcores: Vec<u16>,
+    /// The number of memory channels per socket
+    pub memory_channels: Option<u8>,
+    /// The directory where hugetlbfs is mounted
+    pub huge_dir: Option<String>,
+    /// The hugepage memory to reserve on each NUMA node, in MB, indexed by node
+    pub socket_mem: Vec<u32>,
+    /// The IO virtual addresses to use
+    pub iova_mode: Option<IovaMode>,
+    /// The only PCI devices DPDK may use
+    pub allow: Vec<EalDevice>,
+    /// The PCI devices DPDK may not use
+    pub block: Vec<PciEbdf>,
+}
+
+impl EalConfig {
+    /// Give device arguments to an allowed PCI device.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the device is not allowed, already has device arguments, or if the
+    /// device arguments are malformed.
+    pub fn set_devargs(
+        &mut self,
+        address: &PciEbdf,
+        devargs: &str,
+    ) -> Result<(), InvalidEalConfig> {
+        let device = self
+            .allow
+            .iter_mut()
+            .find(|device| device.address == *address)
+            .ok_or_else(|| InvalidEalConfig::UnknownDevice(address.clone()))?;
+        if device.devargs.is_some() {
+            return Err(InvalidEalConfig::InvalidDevargs(
+                address.clone(),
+                "given more than once",
+            ));
+        }
+        if devargs.is_empty() || devargs.starts_with(',') {
+            return Err(InvalidEalConfig::InvalidDevargs(address.clone(), "empty"));
+        }
+        if devargs.contains(char::is_whitespace) {
+            return Err(InvalidEalConfig::InvalidDevargs(
+                address.clone(),
+                "whitespace",
+            ));
+        }
+        device.devargs = Some(devargs.to_string());
+        Ok(())
+    }
+
+    /// Check that the settings do not conflict.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first conflict found.
+    pub fn validate(&self) -> Result<(), InvalidEalConfig> {
+        if !self.allow.is_empty() && !self.block.is_empty() {
+            return Err(InvalidEalConfig::AllowAndBlock);
+        }
+        let mut devices = BTreeSet::new();
+        let addresses = self.allow.iter().map(|device| &device.address);
+        if let Some(address) = addresses
+            .chain(&self.block)
+            .find(|address| !devices.insert(*address))
+        {
+            return Err(InvalidEalConfig::DuplicateDevice(address.clone()));
+        }
+        let mut lcores = BTreeSet::new();
+        if let Some(lcore) = self.lcores.iter().find(|lcore| !lcores.insert(**lcore)) {
+            return Err(InvalidEalConfig::DuplicateLcore(*lcore));
+        }
+        if self.memory_channels == Some(0) {
+            return Err(InvalidEalConfig::NoMemoryChannels);
+        }
+        if let Some(dir) = &self.huge_dir
+            && !dir.starts_with('/')
+        {
+            return Err(InvalidEalConfig::RelativeHugeDir(dir.clone()));
+        }
+        Ok(())
+    }
+
+    /// The arguments to initialize the EAL with
+    #[must_use]
+    pub fn args(&self) -> Vec<String> {
+        let mut args = vec![];
+        if !self.lcores.is_empty() {
+            let lcores: Vec<_> = self.lcores.iter().map(ToString::to_string).collect();
+            args.extend(["-l".to_string(), lcores.join(",")]);
+        }
+        if let Some(channels) = self.memory_channels {
+            args.extend(["-n".to_string(), channels.to_string()]);
+        }
+        if let Some(dir) = &self.huge_dir {
+            args.extend(["--huge-dir".to_string(), dir.clone()]);
+        }
+        if !self.socket_mem.is_empty() {
+            let socket_mem: Vec<_> = self.socket_mem.iter().map(ToString::to_string).collect();
+            args.extend(["--socket-mem".to_string(), socket_mem.join(",")]);
+        }
+        if let Some(mode) = self.iova_mode {
+            args.push(format!("--iova-mode={mode}"));
+        }
+        for device in &self.allow {
+            let allow = match &device.devargs {
+                None => device.address.to_string(),
+                Some(devargs) => format!("{},{devargs}", device.address),
+            };
+            args.extend(["--allow".to_string(), allow]);
+        }
+        for address in &self.block {
+            args.extend(["--block".to_string(), address.to_string()]);
+        }
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{EalConfig, EalDevice, InvalidEalConfig, IovaMode, LcoreList};
+    use crate::{CmdArgs, DriverConfigSection, LaunchConfiguration, Parser};
+    use net::pci::PciEbdf;
+    use std::str::FromStr;
+
+    fn pci(address: &str) -> PciEbdf {
+        PciEbdf::try_new(address.to_string()).unwrap()
+    }
+
+    fn device(address: &str) -> EalDevice {
+        EalDevice {
+            address: pci(address),
+            devargs: None,
+        }
+    }
+
+    #[test]
+    fn eal_lcore_list() {
+        assert_eq!(
+            LcoreList::from_str("0-3,8").unwrap(),
+            LcoreList(vec![0, 1, 2, 3, 8])
+        );
+        assert_eq!(LcoreList::from_str("5").unwrap(), LcoreList(vec![5]));
+        for bad in ["", "3-1", "a", "1,,2", "1-"] {
+            assert!(LcoreList::from_str(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn eal_args() {
+        let mut eal = EalConfig {
+            lcores: vec![0, 1, 4],
+            memory_channels: Some(4),
+            huge_dir: Some("/dev/hugepages".to_string()),
+            socket_mem: vec![1024, 0, 512],
+            iova_mode: Some(IovaMode::Va),
+            allow: vec![device("0000:01:00.0"), device("0000:01:00.1")],
+            block: vec![],
+        };
+        eal.set_devargs(&pci("0000:01:00.1"), "mprq_en=1,rxqs_min_mprq=1")
+            .unwrap();
+        eal.validate().unwrap();
+        assert_eq!(
+            eal.args(),
+            [
+                "-l",
+                "0,1,4",
+                "-n",
+                "4",
+                "--huge-dir",
+                "/dev/hugepages",
+                "--socket-mem",
+                "1024,0,512",
+                "--iova-mode=va",
+                "--allow",
+                "0000:01:00.0",
+                "--allow",
+                "0000:01:00.1,mprq_en=1,rxqs_min_mprq=1",
+            ]
+        );
+        assert!(EalConfig::default().args().is_empty());
+    }
+
+    #[test]
+    fn eal_conflicts() {
+        let eal = EalConfig {
+            allow: vec![device("0000:01:00.0")],
+            block: vec![pci("0000:02:00.0")],
+            ..EalConfig::default()
+        };
+        assert!(matches!(
+            eal.validate(),
+            Err(InvalidEalConfig::AllowAndBlock)
+        ));
+
+        let eal = EalConfig {
+            allow: vec![device("0000:01:00.0"), device("0000:01:00.0")],
+            ..EalConfig::default()
+        };
+        assert!(matches!(
+            eal.validate(),
+            Err(InvalidEalConfig::DuplicateDevice(_))
+        ));
+
+        let eal = EalConfig {
+            lcores: vec![1, 2, 1],
+            ..EalConfig::default()
+        };
+        assert!(matches!(
+            eal.validate(),
+            Err(InvalidEalConfig::DuplicateLcore(1))
+        ));
+
+        let eal = EalConfig {
+            huge_dir: Some("hugepages".to_string()),
+            ..EalConfig::default()
+        };
+        assert!(matches!(
+            eal.validate(),
+            Err(InvalidEalConfig::RelativeHugeDir(_))
+        ));
+
+        let mut eal = EalConfig {
+            allow: vec![device("0000:01:00.0")],
+            ..EalConfig::default()
+        };
+        assert!(matches!(
+            eal.set_devargs(&pci("0000:02:00.0"), "a=1"),
+            Err(InvalidEalConfig::UnknownDevice(_))
+        ));
+        assert!(eal.set_devargs(&pci("0000:01:00.0"), "").is_err());
+        assert!(eal.set_devargs(&pci("0000:01:00.0"), "a=1 b=2").is_err());
+        eal.set_devargs(&pci("0000:01:00.0"), "a=1").unwrap();
+        assert!(eal.set_devargs(&pci("0000:01:00.0"), "b=2").is_err());
+    }
+
+    #[test]
+    fn eal_from_cmd_args() {
+        let args = CmdArgs::try_parse_from([
+            "dataplane",
+            "--driver",
+            "dpdk",
+            "--interface",
+            "eth0=pci@0000:01:00.0",
+            "--eal-lcores",
+            "2-3",
+            "--eal-iova-mode",
+            "pa",
+            "--eal-devargs",
+            "0000:01:00.0=txq_inline_max=128",
+        ])
+        .unwrap();
+        let config = LaunchConfiguration::try_from(&args).unwrap();
+        let DriverConfigSection::Dpdk(dpdk) = config.driver else {
+            unreachable!()
+        };
+        assert_eq!(
+            dpdk.eal.args(),
+            [
+                "-l",
+                "2,3",
+                "--iova-mode=pa",
+                "--allow",
+                "0000:01:00.0,txq_inline_max=128"
+            ]
+        );
+
+        let args = CmdArgs::try_parse_from([
+            "dataplane",
+            "--driver",
+            "dpdk",
+            "--interface",
+            "eth0=pci@0000:01:00.0",
+            "--eal-devargs",
+            "0000:02:00.0=txq_inline_max=128",
+        ])
+        .unwrap();
+        assert!(LaunchConfiguration::try_from(&args).is_err());
+    }
+
+    #[test]
+    fn eal_socket_mem_from_cmd_args() {
+        let args = CmdArgs::try_parse_from([
+            "dataplane",
+            "--driver",
+            "dpdk",
+            "--interface",
+            "eth0=pci@0000:01:00.0",
+            "--eal-pool-size",
+            "256",
+            "--eal-socket-mem",
+            "0,1024",
+        ])
+        .unwrap();
+        let config = LaunchConfiguration::try_from(&args).unwrap();
+        let DriverConfigSection::Dpdk(dpdk) = config.driver else {
+            unreachable!()
+        };
+        // the memory given takes precedence over the memory planned for the pools
+        assert_eq!(dpdk.eal.socket_mem, [0, 1024]);
+
+        assert!(CmdArgs::try_parse_from(["dataplane", "--eal-socket-mem", "1024,x"]).is_err());
+    }
+}
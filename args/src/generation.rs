@@ -0,0 +1,528 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Later generations of the launch configuration.
+//!
+//! The [`LaunchConfiguration`] is inherited by the dataplane once, at launch. Afterwards,
+//! `dataplane-init` may send new generations of it to the unix socket given with
+//! `--generation-socket`. A generation is a datagram carrying its number (a little endian `u64`),
+//! along with the sealed memfds of the configuration and of its [`IntegrityCheck`] as
+//! `SCM_RIGHTS`, followed by the memfd of its [`LaunchSignature`] when the configuration is signed.
+//! Those are checked as the memfds inherited at launch are: when a verifying key is configured,
+//! generations are only accepted with a valid signature by the same key.
+//!
+//! The socket is only accessible to its owner, in a directory which only its owner may write to,
+//! and generations are only accepted from root or from the user the dataplane runs as, as told by
+//! the `SCM_CREDENTIALS` of their messages.
+//!
+//! Only the sections listed in [`RuntimeSection`] can change at runtime. A generation changing
+//! any other section is rejected, as is a generation whose number is not greater than that of the
+//! generation in effect. The configuration launched with is generation 0.
+//!
+//! [`IntegrityCheck`]: crate::IntegrityCheck
+//! [`LaunchSignature`]: crate::LaunchSignature
+
+use crate::{
+    AsFinalizedMemFile, FinalizedMemFile, LaunchConfiguration, LaunchSignatureError,
+    LaunchSigningKey, LaunchVerifyingKey,
+};
+use arc_swap::ArcSwap;
+use nix::sys::socket::sockopt::PassCred;
+use nix::sys::socket::{
+    ControlMessage, ControlMessageOwned, MsgFlags, UnixAddr, UnixCredentials, recvmsg, sendmsg,
+    setsockopt,
+};
+use nix::unistd::geteuid;
+use std::fs::{DirBuilder, Permissions};
+use std::io::{IoSlice, IoSliceMut};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{debug, info};
+
+/// Length of the payload of the messages carrying a generation: its number
+const GENERATION_MSG_LEN: usize = size_of::<u64>();
+
+/// The sections of the launch configuration which can change at runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RuntimeSection {
+    /// The tracing configuration and its rate limit
+    Tracing,
+    /// The address of the metrics endpoint
+    MetricsAddress,
+    /// The path of the CLI socket
+    CliSocket,
+    /// The values of the feature flags
+    FeatureFlags,
+}
+
+/// Errors receiving or applying a generation of the launch configuration
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum GenerationError {
+    #[error("Generation {given} is not newer than generation {current}, in effect")]
+    Stale { current: u64, given: u64 },
+    #[error("Section {0} of the launch configuration can't change at runtime")]
+    Immutable(&'static str),
+    #[error("Invalid generation message: {0}")]
+    InvalidMessage(String),
+    #[error("Invalid launch configuration: {0}")]
+    InvalidConfiguration(String),
+    #[error("Signature verification failed for the generation: {0}")]
+    Signature(LaunchSignatureError),
+    #[error("Generation sent by {0}, which may not change the launch configuration")]
+    UnauthorizedSender(String),
+    #[error("Unsafe generation socket directory {0}: {1}")]
+    UnsafeDirectory(String, &'static str),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+impl GenerationError {
+    fn invalid_configuration(report: &miette::Report) -> Self {
+        let causes: Vec<_> = report.chain().map(ToString::to_string).collect();
+        GenerationError::InvalidConfiguration(causes.join(": "))
+    }
+}
+
+/// A generation of the launch configuration
+#[derive(Debug)]
+pub struct Generation {
+    /// The number of the generation
+    pub number: u64,
+    /// The launch configuration of the generation
+    pub config: LaunchConfiguration,
+}
+
+/// The generation of the launch configuration in effect
+#[derive(Debug)]
+pub struct ConfigGeneration {
+    current: ArcSwap<Generation>,
+}
+
+/// Tell the sections of the launch configuration changed by `next`.
+///
+/// # Errors
+///
+/// Returns [`GenerationError::Immutable`] if a section which can't change at runtime does.
+fn changed_sections(
+    current: &LaunchConfiguration,
+    next: &LaunchConfiguration,
+) -> Result<Vec<RuntimeSection>, GenerationError> {
+    let immutable = [
+        ("general", current.general == next.general),
+        ("config_server", current.config_server == next.config_server),
+        ("driver", current.driver == next.driver),
+        ("cli.cli_views", current.cli.cli_views == next.cli.cli_views),
+        ("routing", current.routing == next.routing),
+        (
+            "metrics.looking_glass_token_file",
+            current.metrics.looking_glass_token_file == next.metrics.looking_glass_token_file,
+        ),
+        ("bmp", current.bmp == next.bmp),
+        ("otlp", current.otlp == next.otlp),
+        ("profiling", current.profiling == next.profiling),
+    ];
+    if let Some((section, _)) = immutable.into_iter().find(|(_, same)| !same) {
+        return Err(GenerationError::Immutable(section));
+    }
+    let runtime = [
+        (RuntimeSection::Tracing, current.tracing == next.tracing),
+        (
+            RuntimeSection::MetricsAddress,
+            current.metrics.address == next.metrics.address,
+        ),
+        (
+            RuntimeSection::CliSocket,
+            current.cli.cli_sock_path == next.cli.cli_sock_path,
+        ),
+        (
+            RuntimeSection::FeatureFlags,
+            current.feature_flags == next.feature_flags,
+        ),
+    ];
+    Ok(runtime
+        .into_iter()
+        .filter(|(_, same)| !same)
+        .map(|(section, _)| section)
+        .collect())
+}
+
+impl ConfigGeneration {
+    /// Start with the launch configuration the dataplane was launched with, as generation 0
+    #[must_use]
+    pub fn new(config: LaunchConfiguration) -> Self {
+        Self {
+            current: ArcSwap::from_pointee(Generation { number: 0, config }),
+        }
+    }
+
+    /// Get the generation in effect
+    #[must_use]
+    pub fn current(&self) -> Arc<Generation> {
+        self.current.load_full()
+    }
+
+    /// Put a generation of the launch configuration in effect.
+    ///
+    /// Returns the sections which differ from the generation previously in effect: it is up to
+    /// the caller to apply them.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`GenerationError::Stale`] if the generation is not newer than the one in effect,
+    /// and [`GenerationError::Immutable`] if it changes a section which can't change at runtime.
+    /// The generation in effect is left unchanged in either case.
+    pub fn apply(
+        &self,
+        number: u64,
+        config: LaunchConfiguration,
+    ) -> Result<Vec<RuntimeSection>, GenerationError> {
+        let current = self.current.load_full();
+        if number <= current.number {
+            return Err(GenerationError::Stale {
+                current: current.number,
+                given: number,
+            });
+        }
+        let changed = changed_sections(&current.config, &config)?;
+        let previous = self
+            .current
+            .compare_and_swap(&current, Arc::new(Generation { number, config }));
+        if !Arc::ptr_eq(&*previous, &current) {
+            // another generation got in effect meanwhile
+            return Err(GenerationError::Stale {
+                current: previous.number,
+                given: number,
+            });
+        }
+        info!("Generation {number} of the launch configuration in effect; changed {changed:?}");
+        Ok(changed)
+    }
+}
+
+/// Send a generation of the launch configuration to the dataplane listening at `path`, signed
+/// with `key` if given.
+///
+/// # Errors
+///
+/// Returns an error if the generation can't be signed or sent.
+///
+/// # Panics
+///
+/// Panics if the memfds of the configuration can't be created or written to, as when launching
+/// the dataplane.
+pub fn send_generation(
+    path: &Path,
+    number: u64,
+    config: LaunchConfiguration,
+    key: Option<&LaunchSigningKey>,
+) -> Result<(), GenerationError> {
+    let mut config_file = config.finalize();
+    let check_file = config_file.integrity_check().finalize();
+    let signature_file = key
+        .map(|key| key.sign(&mut config_file))
+        .transpose()
+        .map_err(GenerationError::Signature)?
+        .map(AsFinalizedMemFile::finalize);
+    let mut owned_fds = vec![config_file.to_owned_fd(), check_file.to_owned_fd()];
+    owned_fds.extend(signature_file.map(|file| file.to_owned_fd()));
+    let fds: Vec<RawFd> = owned_fds.iter().map(AsRawFd::as_raw_fd).collect();
+
+    let sock = UnixDatagram::unbound()?;
+    let addr = UnixAddr::new(path).map_err(std::io::Error::from)?;
+    let payload = number.to_le_bytes();
+    sendmsg(
+        sock.as_raw_fd(),
+        &[IoSlice::new(&payload)],
+        &[ControlMessage::ScmRights(&fds)],
+        MsgFlags::empty(),
+        Some(&addr),
+    )
+    .map_err(std::io::Error::from)?;
+    debug!(
+        "Sent generation {number} of the launch configuration to {}",
+        path.display()
+    );
+    Ok(())
+}
+
+/// The socket the dataplane receives generations of its launch configuration on
+#[derive(Debug)]
+pub struct GenerationListener {
+    sock: UnixDatagram,
+    /// The key the signature of generations is verified with, if any
+    key: Option<LaunchVerifyingKey>,
+}
+
+/// Tell if generations sent by `uid` are accepted: only root and the user the dataplane runs as
+/// may change its launch configuration
+fn is_authorized_sender(uid: u32) -> bool {
+    uid == 0 || uid == geteuid().as_raw()
+}
+
+/// Create the directory of the generation socket if needed, and check that nobody but root or
+/// the user the dataplane runs as may write to it
+fn check_socket_dir(dir: &Path) -> Result<(), GenerationError> {
+    DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+    let unsafe_dir = |reason| GenerationError::UnsafeDirectory(dir.display().to_string(), reason);
+    let metadata = std::fs::symlink_metadata(dir)?;
+    if !metadata.is_dir() {
+        return Err(unsafe_dir("not a directory"));
+    }
+    if !is_authorized_sender(metadata.uid()) {
+        return Err(unsafe_dir("owned by another user"));
+    }
+    if metadata.mode() & 0o022 != 0 {
+        return Err(unsafe_dir("writable by other users"));
+    }
+    Ok(())
+}
+
+impl GenerationListener {
+    /// Listen for generations at `path`, replacing any stale socket there. The socket is only
+    /// accessible to its owner, and the credentials of the senders are received with their
+    /// messages. If a verifying `key` is given, only generations signed with it are accepted:
+    /// it should be the key the launch configuration is verified with (see
+    /// [`LaunchVerifyingKey::from_env()`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the directory of the socket may be written to by other users, or if
+    /// the socket can't be bound.
+    pub fn bind(path: &Path, key: Option<LaunchVerifyingKey>) -> Result<Self, GenerationError> {
+        let dir = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        check_socket_dir(dir)?;
+        let _ = std::fs::remove_file(path);
+        let sock = UnixDatagram::bind(path)?;
+        std::fs::set_permissions(path, Permissions::from_mode(0o600))?;
+        setsockopt(&sock, PassCred, &true).map_err(std::io::Error::from)?;
+        info!(
+            "Listening for launch configuration generations at {}",
+            path.display()
+        );
+        Ok(Self { sock, key })
+    }
+
+    /// Wait for the next generation, returning its number and its launch configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the sender may not change the launch configuration, if the message is
+    /// malformed, or if it does not carry valid memfds of a launch configuration, of its
+    /// integrity check and, if a verifying key is configured, of a valid signature.
+    #[allow(unsafe_code)] // the descriptors received are owned by this process from now on
+    pub fn recv(&self) -> Result<(u64, LaunchConfiguration), GenerationError> {
+        let mut payload = [0u8; GENERATION_MSG_LEN];
+        let mut iov = [IoSliceMut::new(&mut payload)];
+        let mut cmsg = nix::cmsg_space!([RawFd; 3], UnixCredentials);
+        let msg = recvmsg::<UnixAddr>(
+            self.sock.as_raw_fd(),
+            &mut iov,
+            Some(&mut cmsg),
+            MsgFlags::MSG_CMSG_CLOEXEC,
+        )
+        .map_err(std::io::Error::from)?;
+        // take ownership of the descriptors first, so that they get closed whatever happens
+        let mut fds: Vec<OwnedFd> = Vec::new();
+        let mut sender = None;
+        for cmsg in msg
+            .cmsgs()
+            .map_err(|e| GenerationError::InvalidMessage(e.to_string()))?
+        {
+            match cmsg {
+                ControlMessageOwned::ScmRights(received) => fds.extend(
+                    received
+                        .into_iter()
+                        .map(|fd| unsafe { OwnedFd::from_raw_fd(fd) }),
+                ),
+                ControlMessageOwned::ScmCredentials(creds) => sender = Some(creds.uid()),
+                _ => {}
+            }
+        }
+        match sender {
+            Some(uid) if is_authorized_sender(uid) => {}
+            Some(uid) => return Err(GenerationError::UnauthorizedSender(format!("uid {uid}"))),
+            None => return Err(GenerationError::UnauthorizedSender("unknown".to_string())),
+        }
+        if msg
+            .flags
+            .intersects(MsgFlags::MSG_TRUNC | MsgFlags::MSG_CTRUNC)
+        {
+            return Err(GenerationError::InvalidMessage("truncated".to_string()));
+        }
+        if msg.bytes != GENERATION_MSG_LEN {
+            let msg = format!("payload of {} octets", msg.bytes);
+            return Err(GenerationError::InvalidMessage(msg));
+        }
+        let expected = if self.key.is_some() { 3 } else { 2 };
+        if fds.len() != expected {
+            let msg = format!("{} file descriptors, expected {expected}", fds.len());
+            return Err(GenerationError::InvalidMessage(msg));
+        }
+        let number = u64::from_le_bytes(payload);
+
+        let mut files = fds.into_iter().map(|fd| {
+            unsafe { FinalizedMemFile::try_from_fd(fd) }
+                .map_err(|e| GenerationError::InvalidConfiguration(e.to_string()))
+        });
+        let mut next_file = || files.next().unwrap_or_else(|| unreachable!());
+        let mut config_file = next_file()?;
+        let check_file = next_file()?;
+        if let Some(key) = &self.key {
+            key.verify(&mut config_file, next_file()?)
+                .map_err(GenerationError::Signature)?;
+            debug!("Verified the signature of generation {number} with key {key}");
+        }
+        let config = LaunchConfiguration::from_memfds(config_file, check_file)
+            .map_err(|e| GenerationError::invalid_configuration(&e))?;
+        debug!("Received generation {number} of the launch configuration");
+        Ok((number, config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfigGeneration, GenerationError, GenerationListener, RuntimeSection};
+    use crate::{CmdArgs, LaunchConfiguration, LaunchSignatureError, LaunchSigningKey, Parser};
+    use std::fs::Permissions;
+    use std::os::unix::fs::PermissionsExt;
+    use std::path::PathBuf;
+
+    fn config(extra: &[&str]) -> LaunchConfiguration {
+        let args = ["dataplane", "--driver", "kernel", "--interface", "eth0"];
+        let args = CmdArgs::try_parse_from(args.iter().chain(extra)).unwrap();
+        LaunchConfiguration::try_from(args).unwrap()
+    }
+
+    #[test]
+    fn generation_apply() {
+        let generations = ConfigGeneration::new(config(&[]));
+        assert_eq!(generations.current().number, 0);
+
+        let changed = generations.apply(1, config(&[])).unwrap();
+        assert!(changed.is_empty());
+
+        let next = config(&[
+            "--tracing",
+            "default=info",
+            "--metrics-address",
+            "127.0.0.1:9191",
+            "--cli-sock-path",
+            "/tmp/cli.sock",
+            "--feature-flag",
+            "reconcile-offloads=off",
+        ]);
+        let changed = generations.apply(3, next).unwrap();
+        assert_eq!(
+            changed,
+            [
+                RuntimeSection::Tracing,
+                RuntimeSection::MetricsAddress,
+                RuntimeSection::CliSocket,
+                RuntimeSection::FeatureFlags,
+            ]
+        );
+        let current = generations.current();
+        assert_eq!(current.number, 3);
+        assert_eq!(current.config.cli.cli_sock_path, "/tmp/cli.sock");
+    }
+
+    #[test]
+    fn generation_rejected() {
+        let generations = ConfigGeneration::new(config(&[]));
+        generations.apply(2, config(&[])).unwrap();
+        assert!(matches!(
+            generations.apply(2, config(&[])),
+            Err(GenerationError::Stale {
+                current: 2,
+                given: 2
+            })
+        ));
+        assert!(matches!(
+            generations.apply(3, config(&["--interface", "eth1"])),
+            Err(GenerationError::Immutable("driver"))
+        ));
+        assert!(matches!(
+            generations.apply(3, config(&["--cli-views", "/etc/views.yaml"])),
+            Err(GenerationError::Immutable("cli.cli_views"))
+        ));
+        assert_eq!(generations.current().number, 2);
+    }
+
+    fn socket_dir(test: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("generations-{test}-{}", std::process::id()))
+    }
+
+    #[test]
+    fn generation_send_and_receive() {
+        let dir = socket_dir("send");
+        let path = dir.join("generation.sock");
+        let listener = GenerationListener::bind(&path, None).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        let sent = config(&["--tracing", "default=debug"]);
+        let expected = format!("{sent:?}");
+        super::send_generation(&path, 7, sent, None).unwrap();
+        let (number, received) = listener.recv().unwrap();
+        assert_eq!(number, 7);
+        assert_eq!(format!("{received:?}"), expected);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn generation_signed() {
+        const SEED: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+        let signing = LaunchSigningKey::from_hex(SEED).unwrap();
+        let other = LaunchSigningKey::from_hex(&"11".repeat(32)).unwrap();
+        let dir = socket_dir("signed");
+        let path = dir.join("generation.sock");
+        let listener = GenerationListener::bind(&path, Some(signing.verifying_key())).unwrap();
+
+        super::send_generation(&path, 1, config(&[]), Some(&signing)).unwrap();
+        assert_eq!(listener.recv().unwrap().0, 1);
+
+        // unsigned, or signed with another key
+        super::send_generation(&path, 2, config(&[]), None).unwrap();
+        assert!(matches!(
+            listener.recv(),
+            Err(GenerationError::InvalidMessage(_))
+        ));
+        super::send_generation(&path, 3, config(&[]), Some(&other)).unwrap();
+        assert!(matches!(
+            listener.recv(),
+            Err(GenerationError::Signature(
+                LaunchSignatureError::InvalidSignature
+            ))
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn generation_socket_dir() {
+        let dir = socket_dir("unsafe");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::set_permissions(&dir, Permissions::from_mode(0o777)).unwrap();
+        assert!(matches!(
+            GenerationListener::bind(&dir.join("generation.sock"), None),
+            Err(GenerationError::UnsafeDirectory(_, _))
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn generation_senders() {
+        assert!(super::is_authorized_sender(0));
+        assert!(super::is_authorized_sender(nix::unistd::geteuid().as_raw()));
+        let other = if nix::unistd::geteuid().is_root() {
+            1000
+        } else {
+            0xfffe
+        };
+        assert!(!super::is_authorized_sender(other));
+    }
+}
@@ -0,0 +1,231 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Sizing of the hugepage memory the DPDK driver reserves on each NUMA node.
+//!
+//! The buffers of a NIC are best allocated from the NUMA node the NIC is attached to, where the
+//! NIC writes the packets it receives. Given the size of the buffer pool of each NIC, a
+//! [`SocketMemPlan`] reserves on every NUMA node the pools of the NICs local to it, and nothing on
+//! the nodes without NICs. The EAL gets the plan as its `--socket-mem` argument.
+//!
+//! The plan is checked against the free hugepages of each node, so that a node lacking hugepages
+//! for its NICs is reported at startup, rather than as the EAL failing to reserve its memory or
+//! as buffers silently allocated from a remote node.
+
+use bytecheck::CheckBytes;
+use net::pci::PciEbdf;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::Path;
+
+use crate::devices::PCI_DEVICES;
+
+/// Where the NUMA nodes of the host are listed in sysfs
+const NUMA_NODES: &str = "/sys/devices/system/node";
+
+/// The NUMA node of the PCI device at `address`, as reported in sysfs, if known
+#[must_use]
+pub fn numa_node_of(address: &PciEbdf) -> Option<u16> {
+    let path = Path::new(PCI_DEVICES).join(address.to_string());
+    let node = std::fs::read_to_string(path.join("numa_node")).ok()?;
+    // the kernel reports -1 when the node is not known
+    node.trim().parse().ok()
+}
+
+/// The free hugepage memory of the NUMA node at `path` in sysfs, in MB, over all page sizes
+fn free_hugepages_mb(path: &Path) -> u64 {
+    let Ok(sizes) = std::fs::read_dir(path.join("hugepages")) else {
+        return 0;
+    };
+    sizes
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let name = path.file_name()?.to_string_lossy().into_owned();
+            let size_kb: u64 = name
+                .strip_prefix("hugepages-")?
+                .strip_suffix("kB")?
+                .parse()
+                .ok()?;
+            let free: u64 = std::fs::read_to_string(path.join("free_hugepages"))
+                .ok()?
+                .trim()
+                .parse()
+                .ok()?;
+            Some(free * size_kb / 1024)
+        })
+        .sum()
+}
+
+/// The free hugepage memory of each NUMA node of this host, in MB.
+///
+/// # Errors
+///
+/// Returns an error if the NUMA nodes can't be listed.
+pub fn free_hugepages() -> std::io::Result<BTreeMap<u16, u64>> {
+    let mut free = BTreeMap::new();
+    for entry in std::fs::read_dir(NUMA_NODES)? {
+        let path = entry?.path();
+        let node = path
+            .file_name()
+            .and_then(|name| name.to_str()?.strip_prefix("node")?.parse().ok());
+        if let Some(node) = node {
+            free.insert(node, free_hugepages_mb(&path));
+        }
+    }
+    Ok(free)
+}
+
+/// A NUMA node with too few free hugepages for the pools of the NICs local to it
+#[derive(
+    Debug,
+    Clone,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    rkyv::Archive,
+    CheckBytes,
+)]
+#[rkyv(attr(derive(PartialEq, Eq, Debug)))]
+pub struct HugepageShortage {
+    /// The NUMA node
+    pub node: u16,
+    /// The hugepage memory the pools of the NICs of the node need, in MB
+    pub needed_mb: u32,
+    /// The free hugepage memory of the node, in MB
+    pub free_mb: u64,
+    /// The NICs local to the node
+    pub nics: Vec<PciEbdf>,
+}
+
+impl Display for HugepageShortage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let nics: Vec<_> = self.nics.iter().map(ToString::to_string).collect();
+        write!(
+            f,
+            "NUMA node {} has {} MB of free hugepages, {} MB are needed for the pools of {}",
+            self.node,
+            self.free_mb,
+            self.needed_mb,
+            nics.join(", ")
+        )
+    }
+}
+
+/// The hugepage memory to reserve on each NUMA node for the buffer pools of the NICs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SocketMemPlan {
+    /// The NICs local to each NUMA node
+    nics: BTreeMap<u16, Vec<PciEbdf>>,
+    /// The size of the buffer pool of each NIC, in MB
+    pool_mb: u32,
+}
+
+impl SocketMemPlan {
+    /// Plan the memory of the pools of `pool_mb` MB of `nics`, given with their NUMA node. The
+    /// pools of the NICs whose node is not known are reserved on node 0.
+    #[must_use]
+    pub fn new(nics: impl IntoIterator<Item = (PciEbdf, Option<u16>)>, pool_mb: u32) -> Self {
+        let mut by_node: BTreeMap<u16, Vec<PciEbdf>> = BTreeMap::new();
+        for (nic, node) in nics {
+            by_node.entry(node.unwrap_or(0)).or_default().push(nic);
+        }
+        Self {
+            nics: by_node,
+            pool_mb,
+        }
+    }
+
+    /// The memory to reserve on a NUMA node, in MB
+    fn needed_mb(&self, node: u16) -> u32 {
+        let nics = self.nics.get(&node).map_or(0, Vec::len);
+        u32::try_from(nics)
+            .unwrap_or(u32::MAX)
+            .saturating_mul(self.pool_mb)
+    }
+
+    /// The memory to reserve on each NUMA node, in MB, indexed by node, up to the last node
+    /// with NICs. Empty if there are no NICs.
+    #[must_use]
+    pub fn socket_mem(&self) -> Vec<u32> {
+        let Some(last) = self.nics.keys().next_back() else {
+            return vec![];
+        };
+        (0..=*last).map(|node| self.needed_mb(node)).collect()
+    }
+
+    /// The NUMA nodes whose free hugepages, from `free_mb`, are too few for the pools of their
+    /// NICs. A node missing from `free_mb` has no free hugepages.
+    #[must_use]
+    pub fn shortages(&self, free_mb: &BTreeMap<u16, u64>) -> Vec<HugepageShortage> {
+        self.nics
+            .iter()
+            .filter_map(|(node, nics)| {
+                let needed_mb = self.needed_mb(*node);
+                let free_mb = free_mb.get(node).copied().unwrap_or(0);
+                (free_mb < u64::from(needed_mb)).then(|| HugepageShortage {
+                    node: *node,
+                    needed_mb,
+                    free_mb,
+                    nics: nics.clone(),
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SocketMemPlan;
+    use net::pci::PciEbdf;
+    use std::collections::BTreeMap;
+
+    fn pci(address: &str) -> PciEbdf {
+        PciEbdf::try_new(address.to_string()).unwrap()
+    }
+
+    #[test]
+    fn socket_mem_follows_nic_numa_nodes() {
+        let plan = SocketMemPlan::new(
+            [
+                (pci("0000:01:00.0"), Some(0)),
+                (pci("0000:81:00.0"), Some(2)),
+                (pci("0000:81:00.1"), Some(2)),
+                (pci("0000:02:00.0"), None),
+            ],
+            512,
+        );
+        assert_eq!(plan.socket_mem(), [1024, 0, 1024]);
+        assert!(SocketMemPlan::new([], 512).socket_mem().is_empty());
+    }
+
+    #[test]
+    fn socket_mem_shortages() {
+        let plan = SocketMemPlan::new(
+            [
+                (pci("0000:01:00.0"), Some(0)),
+                (pci("0000:81:00.0"), Some(1)),
+                (pci("0000:81:00.1"), Some(1)),
+            ],
+            512,
+        );
+        assert!(
+            plan.shortages(&BTreeMap::from([(0, 512), (1, 2048)]))
+                .is_empty()
+        );
+
+        let shortages = plan.shortages(&BTreeMap::from([(0, 4096), (1, 1000)]));
+        assert_eq!(shortages.len(), 1);
+        assert_eq!(shortages[0].node, 1);
+        assert_eq!(shortages[0].needed_mb, 1024);
+        assert_eq!(
+            shortages[0].to_string(),
+            "NUMA node 1 has 1000 MB of free hugepages, 1024 MB are needed for the pools of 0000:81:00.0, 0000:81:00.1"
+        );
+
+        let shortages = plan.shortages(&BTreeMap::from([(1, 2048)]));
+        assert_eq!(shortages.len(), 1);
+        assert_eq!((shortages[0].node, shortages[0].free_mb), (0, 0));
+    }
+}
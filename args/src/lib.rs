@@ -18,13 +18,19 @@
 //!    - Serializes the configuration using `rkyv` for zero-copy deserialization
 //!    - Writes serialized data to a [`MemFile`] and finalizes it into a [`FinalizedMemFile`]
 //!    - Computes an [`IntegrityCheck`] (SHA-384 hash) of the configuration
+//!    - May sign the configuration with a [`LaunchSigningKey`] (Ed25519); `dataplane-init`
+//!      does not sign it yet, so verification is only enabled where a verifying key is configured
 //!    - Passes both file descriptors to the child process at known FD numbers
+//!    - Optionally hands supplementary named sections over in a [`MemFdBundle`]
 //!
 //! 2. **Child Process (dataplane)**:
 //!    - Inherits the configuration via [`LaunchConfiguration::inherit()`]
 //!    - Validates the integrity check matches the configuration
+//!    - Verifies the signature of the configuration, and of its later generations, if a
+//!      [`LaunchVerifyingKey`] is configured
 //!    - Memory-maps the sealed memfd for zero-copy access
 //!    - Accesses the configuration through the rkyv archive format
+//!    - Looks up any supplementary sections by name in the [`InheritedBundle`]
 //!
 //! # Key Types
 //!
@@ -33,6 +39,10 @@
 //! - [`MemFile`]: Mutable memfd wrapper for building configuration
 //! - [`FinalizedMemFile`]: Immutable, sealed memfd for safe inter-process sharing
 //! - [`IntegrityCheck`]: SHA-384 hash for validating configuration integrity
+//! - [`LaunchSignature`]: Optional Ed25519 signature, to detect tampering and not only corruption
+//! - [`MemFdBundle`]: Named, individually sealed supplementary sections, with a manifest
+//! - [`generation::ConfigGeneration`]: The configuration in effect, which later generations
+//!   received at runtime replace
 //!
 //! # `FinalizedMemFile` Integrity
 //!
@@ -50,15 +60,38 @@
 #![deny(unsafe_code, clippy::pedantic)]
 #![allow(clippy::unwrap_used, clippy::expect_used, clippy::panic)]
 
+pub mod bundle;
+mod config_file;
+mod devices;
+mod eal;
+pub mod generation;
+mod hugepages;
+pub mod signature;
+
+pub use bundle::{InheritedBundle, MemFdBundle, MemFdBundleError, SealedBundle};
+pub use config_file::ConfigFileError;
+pub use devices::{
+    DeviceMatch, DeviceSearch, DeviceSearchError, PciDeviceFilter, PciDeviceInfo, PciVendor,
+};
+pub use eal::{
+    DevargsArg, EalConfig, EalDevice, InvalidEalConfig, IovaMode, LcoreList, SocketMemList,
+};
+pub use hugepages::{HugepageShortage, SocketMemPlan, free_hugepages, numa_node_of};
+pub use signature::{LaunchSignature, LaunchSignatureError, LaunchSigningKey, LaunchVerifyingKey};
+
 pub use clap::Parser;
+use clap::{CommandFactory, FromArgMatches};
 use miette::{Context, IntoDiagnostic};
 use net::interface::IllegalInterfaceName;
 use net::interface::InterfaceName;
+use net::vxlan::Vni;
 use sha2::Digest;
 use std::borrow::Borrow;
+use std::ffi::OsString;
 use std::io::{Read, Seek, SeekFrom, Write};
-use std::net::SocketAddr;
+use std::net::{Ipv4Addr, SocketAddr};
 use std::os::fd::{AsFd, AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::path::Path;
 use std::str::FromStr;
 
 use std::time::Duration;
@@ -80,6 +113,33 @@ pub enum PortArg {
 pub struct InterfaceArg {
     pub interface: InterfaceName,
     pub port: Option<PortArg>,
+    pub queues: PortQueues,
+}
+
+/// The queues of the port of an interface, and the lcores serving them.
+///
+/// They are given after the port of an interface, with syntax `;rxq=N;txq=N;cores=LIST`, where
+/// the lcores of the list are separated by `:` (e.g. `2-5:8`). Settings left out are left to the
+/// driver.
+#[derive(
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    Clone,
+    serde::Serialize,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    rkyv::Archive,
+)]
+#[rkyv(attr(derive(PartialEq, Eq, Debug)))]
+pub struct PortQueues {
+    /// The number of receive queues
+    pub rx: Option<u16>,
+    /// The number of transmit queues
+    pub tx: Option<u16>,
+    /// The lcores serving the queues
+    pub cores: Vec<u16>,
 }
 
 #[derive(
@@ -99,6 +159,184 @@ pub struct TracingRateLimit {
     pub replenish_per_second: u32,
 }
 
+/// The bounds of the number of packets the drivers receive at once, with syntax `MIN:MAX`.
+///
+/// The drivers adapt the size of their batches between the bounds: batches grow under sustained
+/// load, for throughput, and shrink back when the queues are shallow, for latency.
+#[derive(
+    Debug,
+    PartialEq,
+    Eq,
+    Clone,
+    Copy,
+    serde::Serialize,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    rkyv::Archive,
+    CheckBytes,
+)]
+#[rkyv(attr(derive(PartialEq, Eq, Debug)))]
+pub struct BatchSize {
+    pub min: u16,
+    pub max: u16,
+}
+
+impl BatchSize {
+    /// The largest batch the drivers may receive
+    pub const LIMIT: u16 = 1024;
+}
+
+/// The value of a feature flag, with syntax `NAME=on|off`.
+///
+/// The names of the flags are only checked by the dataplane, which knows its flags.
+#[derive(
+    Debug,
+    PartialEq,
+    Eq,
+    Clone,
+    serde::Serialize,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    rkyv::Archive,
+    CheckBytes,
+)]
+#[rkyv(attr(derive(PartialEq, Eq, Debug)))]
+pub struct FeatureFlagArg {
+    pub name: String,
+    pub enabled: bool,
+}
+
+/// A header sent along with the OTLP exports, e.g. to authenticate to the collector, with
+/// syntax `NAME=VALUE`.
+#[derive(
+    Debug,
+    PartialEq,
+    Eq,
+    Clone,
+    serde::Serialize,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    rkyv::Archive,
+    CheckBytes,
+)]
+#[rkyv(attr(derive(PartialEq, Eq, Debug)))]
+pub struct OtlpHeader {
+    pub name: String,
+    pub value: String,
+}
+
+/// The ratio of the traces sampled for OTLP export, in [0..1].
+///
+/// Kept in parts per million so that the launch configuration can be compared exactly.
+#[derive(
+    Debug,
+    PartialEq,
+    Eq,
+    Clone,
+    Copy,
+    serde::Serialize,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    rkyv::Archive,
+    CheckBytes,
+)]
+#[rkyv(attr(derive(PartialEq, Eq, Debug)))]
+pub struct SamplingRatio {
+    per_million: u32,
+}
+
+impl SamplingRatio {
+    const MILLION: u32 = 1_000_000;
+
+    /// Sample all traces
+    pub const ALWAYS: SamplingRatio = SamplingRatio {
+        per_million: Self::MILLION,
+    };
+
+    /// The ratio, as a fraction
+    #[must_use]
+    pub fn as_f64(&self) -> f64 {
+        f64::from(self.per_million) / f64::from(Self::MILLION)
+    }
+}
+
+impl std::fmt::Display for SamplingRatio {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_f64())
+    }
+}
+
+/// A range of kernel route table ids, with syntax `FIRST-LAST`.
+///
+/// The range may not include the tables reserved by linux (0 and 253 to 255).
+#[derive(
+    Debug,
+    PartialEq,
+    Eq,
+    Clone,
+    Copy,
+    serde::Serialize,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    rkyv::Archive,
+    CheckBytes,
+)]
+#[rkyv(attr(derive(PartialEq, Eq, Debug)))]
+pub struct RouteTableRange {
+    pub first: u32,
+    pub last: u32,
+}
+
+impl RouteTableRange {
+    /// The range route table ids are allocated from, unless specified
+    pub const DEFAULT: RouteTableRange = RouteTableRange {
+        first: 10_000,
+        last: 65_535,
+    };
+}
+
+/// What the pipeline does at ingress with the packets for a class of destinations which are not
+/// meant to be routed: link-local and multicast destinations.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DestinationAction {
+    /// Process the packets as the other packets
+    Forward,
+    /// Hand the packets over to the kernel
+    Punt,
+    Drop,
+}
+
+impl std::fmt::Display for DestinationAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DestinationAction::Forward => write!(f, "forward"),
+            DestinationAction::Punt => write!(f, "punt"),
+            DestinationAction::Drop => write!(f, "drop"),
+        }
+    }
+}
+
+/// A path between endpoints of two VPCs verified with OAM probes, with syntax
+/// `SRC_VNI:SRC_IP=DST_VNI:DST_IP`: probes go from the IPv4 address `SRC_IP` of the VPC of
+/// `SRC_VNI` to the IPv4 address `DST_IP` of the VPC of `DST_VNI`, and back.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct OamProbeArg {
+    pub src_vni: Vni,
+    pub src: Ipv4Addr,
+    pub dst_vni: Vni,
+    pub dst: Ipv4Addr,
+}
+
+impl std::fmt::Display for OamProbeArg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}:{}={}:{}",
+            self.src_vni, self.src, self.dst_vni, self.dst
+        )
+    }
+}
+
 impl FromStr for PortArg {
     type Err = String;
     fn from_str(input: &str) -> Result<Self, Self::Err> {
@@ -124,9 +362,47 @@ impl FromStr for PortArg {
     }
 }
 
+impl FromStr for PortQueues {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let count = |key: &str, value: &str| match value.parse::<u16>() {
+            Ok(0) => Err(format!("Number of queues {key} must be greater than 0")),
+            Ok(count) => Ok(count),
+            Err(e) => Err(format!("Bad number of queues {key}: {e}")),
+        };
+        let mut queues = PortQueues::default();
+        for option in input.split(';') {
+            let (key, value) = option
+                .split_once('=')
+                .ok_or(format!("Bad syntax: missing = in '{option}'"))?;
+            let given = match key {
+                "rxq" => queues.rx.replace(count(key, value)?).is_some(),
+                "txq" => queues.tx.replace(count(key, value)?).is_some(),
+                "cores" => {
+                    let cores = LcoreList::from_str(&value.replace(':', ","))?.0;
+                    !std::mem::replace(&mut queues.cores, cores).is_empty()
+                }
+                _ => {
+                    return Err(format!(
+                        "Unknown option '{key}': allowed options are rxq|txq|cores"
+                    ));
+                }
+            };
+            if given {
+                return Err(format!("Option '{key}' given more than once"));
+            }
+        }
+        Ok(queues)
+    }
+}
+
 impl FromStr for InterfaceArg {
     type Err = String;
     fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (input, queues) = match input.split_once(';') {
+            Some((input, options)) => (input, PortQueues::from_str(options)?),
+            None => (input, PortQueues::default()),
+        };
         if let Some((first, second)) = input.split_once('=') {
             let interface =
                 InterfaceName::try_from(first).map_err(|e| format!("Bad interface name: {e}"))?;
@@ -135,6 +411,7 @@ impl FromStr for InterfaceArg {
             Ok(InterfaceArg {
                 interface,
                 port: Some(port),
+                queues,
             })
         } else {
             let interface =
@@ -142,11 +419,17 @@ impl FromStr for InterfaceArg {
             Ok(InterfaceArg {
                 interface,
                 port: None,
+                queues,
             })
         }
     }
 }
 
+/// Parse the name of a kernel interface
+fn parse_interface_name(input: &str) -> Result<InterfaceName, String> {
+    InterfaceName::try_from(input).map_err(|e| format!("Bad interface name: {e}"))
+}
+
 impl FromStr for TracingRateLimit {
     type Err = String;
     fn from_str(input: &str) -> Result<Self, Self::Err> {
@@ -175,7 +458,182 @@ impl FromStr for TracingRateLimit {
     }
 }
 
-use tracing::instrument;
+impl FromStr for BatchSize {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (min, max) = input
+            .split_once(':')
+            .ok_or("Bad syntax: missing :".to_string())?;
+        let min = min
+            .parse::<u16>()
+            .map_err(|e| format!("Bad minimum batch size: {e}"))?;
+        let max = max
+            .parse::<u16>()
+            .map_err(|e| format!("Bad maximum batch size: {e}"))?;
+        if min == 0 {
+            return Err("Minimum batch size must be greater than 0".to_string());
+        }
+        if min > max {
+            return Err(format!(
+                "Minimum batch size {min} is greater than the maximum {max}"
+            ));
+        }
+        if max > Self::LIMIT {
+            return Err(format!(
+                "Maximum batch size {max} is greater than {}",
+                Self::LIMIT
+            ));
+        }
+        Ok(Self { min, max })
+    }
+}
+
+impl FromStr for FeatureFlagArg {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (name, value) = input
+            .split_once('=')
+            .ok_or("Bad syntax: missing =".to_string())?;
+        if name.is_empty() {
+            return Err("Missing feature flag name".to_string());
+        }
+        let enabled = match value {
+            "on" => true,
+            "off" => false,
+            _ => return Err(format!("Bad value '{value}': allowed values are on|off")),
+        };
+        Ok(Self {
+            name: name.to_string(),
+            enabled,
+        })
+    }
+}
+
+impl FromStr for OtlpHeader {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (name, value) = input
+            .split_once('=')
+            .ok_or("Bad syntax: missing =".to_string())?;
+        let legal = |c: char| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c);
+        if name.is_empty() || !name.chars().all(legal) {
+            return Err(format!("Bad header name '{name}'"));
+        }
+        if value.chars().any(char::is_control) {
+            return Err(format!("Bad value for header '{name}'"));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            value: value.to_string(),
+        })
+    }
+}
+
+impl FromStr for SamplingRatio {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let ratio = input
+            .parse::<f64>()
+            .map_err(|e| format!("Bad sampling ratio: {e}"))?;
+        if !(0.0..=1.0).contains(&ratio) {
+            return Err(format!("Sampling ratio {ratio} is not in [0..1]"));
+        }
+        // in range, per the check above
+        #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+        let per_million = (ratio * f64::from(Self::MILLION)).round() as u32;
+        Ok(Self { per_million })
+    }
+}
+
+impl FromStr for RouteTableRange {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let (first, last) = input
+            .split_once('-')
+            .ok_or("Bad syntax: missing -".to_string())?;
+
+        let first = first
+            .parse::<u32>()
+            .map_err(|e| format!("Bad first route table id: {e}"))?;
+        let last = last
+            .parse::<u32>()
+            .map_err(|e| format!("Bad last route table id: {e}"))?;
+
+        if first == 0 {
+            return Err("Route table 0 is reserved".to_string());
+        }
+        if first > last {
+            return Err(format!("Empty route table range {first}-{last}"));
+        }
+        if first <= 255 && last >= 253 {
+            return Err("Route tables 253 to 255 are reserved".to_string());
+        }
+        Ok(Self { first, last })
+    }
+}
+
+impl FromStr for DestinationAction {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "forward" => Ok(DestinationAction::Forward),
+            "punt" => Ok(DestinationAction::Punt),
+            "drop" => Ok(DestinationAction::Drop),
+            _ => Err(format!(
+                "Bad action '{input}': allowed values are forward|punt|drop"
+            )),
+        }
+    }
+}
+
+impl FromStr for OamProbeArg {
+    type Err = String;
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let endpoint = |endpoint: &str| -> Result<(Vni, Ipv4Addr), String> {
+            let (vni, ip) = endpoint
+                .split_once(':')
+                .ok_or(format!("Bad endpoint '{endpoint}': expected VNI:IP"))?;
+            let vni = vni
+                .parse::<u32>()
+                .map_err(|e| format!("Bad VNI '{vni}': {e}"))
+                .and_then(|vni| Vni::new_checked(vni).map_err(|e| format!("Bad VNI: {e}")))?;
+            let ip = ip
+                .parse()
+                .map_err(|e| format!("Bad IPv4 address '{ip}': {e}"))?;
+            Ok((vni, ip))
+        };
+        let (src, dst) = input
+            .split_once('=')
+            .ok_or("Bad syntax: missing =".to_string())?;
+        let (src_vni, src) = endpoint(src)?;
+        let (dst_vni, dst) = endpoint(dst)?;
+        if src_vni == dst_vni {
+            return Err(format!("The endpoints of '{input}' are in the same VPC"));
+        }
+        Ok(Self {
+            src_vni,
+            src,
+            dst_vni,
+            dst,
+        })
+    }
+}
+
+/// Parse the size of the rings of the AF_XDP sockets, which must be a power of 2 in
+/// [64..16384].
+fn parse_xdp_ring_size(input: &str) -> Result<u32, String> {
+    let size = input
+        .parse::<u32>()
+        .map_err(|e| format!("Bad ring size: {e}"))?;
+    if !size.is_power_of_two() || !(64..=16384).contains(&size) {
+        return Err(format!(
+            "Ring size {size} is not a power of 2 in [64..16384]"
+        ));
+    }
+    Ok(size)
+}
+
+use tracing::{debug, instrument};
 
 use bytecheck::CheckBytes;
 use nix::fcntl::{FcntlArg, FdFlag};
@@ -198,6 +656,27 @@ pub const DEFAULT_DP_UX_PATH_CLI: &str = "/var/run/dataplane/cli.sock";
 /// configuration reloads.
 pub const DEFAULT_FRR_AGENT_PATH: &str = "/var/run/frr/frr-agent.sock";
 
+/// Default grace period of FRR restarts, in seconds.
+///
+/// When FRR goes away, the dataplane keeps forwarding with the routes it had learned for this
+/// long, giving FRR time to come back and push them again.
+pub const DEFAULT_FRR_GRACE_PERIOD: u64 = 60;
+
+/// Default path to the embedded store persisting the state learned at runtime.
+///
+/// This state, e.g. the route table ids allocated to VPCs, is kept across restarts.
+pub const DEFAULT_STATE_STORE_PATH: &str = "/var/lib/dataplane/state.redb";
+
+/// Default directory where the reports of the crashes of the dataplane are written.
+///
+/// The reports are kept across restarts, for fleet tooling to harvest them.
+pub const DEFAULT_CRASH_DIR: &str = "/var/lib/dataplane/crash";
+
+/// Default directory where packet captures started from the cli are written.
+///
+/// Captures are only written, or streamed to sockets, in this directory.
+pub const DEFAULT_CAPTURE_DIR: &str = "/var/lib/dataplane/captures";
+
 /// A type wrapper around [`std::fs::File`] which is reserved to describe linux [memfd] files.
 ///
 /// Memory file descriptors are anonymous, file-like objects that exist only in memory
@@ -232,21 +711,66 @@ pub struct MemFile(std::fs::File);
 /// receive and read the file from child processes.
 pub struct FinalizedMemFile(MemFile);
 
+/// Errors creating, sealing or receiving a memfd.
+///
+/// These are returned by the `try_*` variants of the methods of [`MemFile`] and
+/// [`FinalizedMemFile`], for use outside of early process initialization (e.g. in tests and tools).
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum MemFdError {
+    #[error("failed to create memfd: {0}")]
+    Create(nix::Error),
+    #[error("failed to set memfd to read only mode: {0}")]
+    ReadOnly(nix::Error),
+    #[error("failed to add seals {0:?} to memfd: {1}")]
+    Seal(SealFlag, nix::Error),
+    #[error("failed to seek to start of memfd: {0}")]
+    Seek(std::io::Error),
+    #[error("failed to read memfd link in /proc: {0}")]
+    Readlink(nix::Error),
+    #[error("file descriptor readlink returned invalid unicode")]
+    InvalidUnicode,
+    #[error("supplied file descriptor is not a memfd: {0}")]
+    NotMemFd(String),
+    #[error("failed to stat memfd: {0}")]
+    Stat(nix::Error),
+    #[error(
+        "finalized memfd not in read only mode: given mode is {given:o}, expected {expected:o}"
+    )]
+    NotReadOnly { given: u32, expected: u32 },
+    #[error("failed to get seals on memfd: {0}")]
+    GetSeals(nix::Error),
+    #[error("seal bits on memfd are set but are unknown to the system")]
+    UnknownSeals,
+    #[error("missing seal bits on finalized memfd: bits set {given:?}, bits expected {expected:?}")]
+    MissingSeals { given: SealFlag, expected: SealFlag },
+    #[error("unable to mark memfd as close-on-exec: {0}")]
+    CloseOnExec(nix::Error),
+}
+
 impl MemFile {
     /// Create a new, blank [`MemFile`].
     ///
     /// # Panics
     ///
     /// Panics if the operating system is unable to allocate an in-memory file descriptor.
+    /// See [`Self::try_new`] for a non-panicking variant.
     #[must_use]
     pub fn new() -> MemFile {
+        Self::try_new().into_diagnostic().unwrap()
+    }
+
+    /// Create a new, blank [`MemFile`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the operating system is unable to allocate an in-memory file
+    /// descriptor.
+    pub fn try_new() -> Result<MemFile, MemFdError> {
         let id: id::Id<MemFile> = id::Id::new();
         let descriptor =
             nix::sys::memfd::memfd_create(id.to_string().as_bytes(), MFdFlags::MFD_ALLOW_SEALING)
-                .into_diagnostic()
-                .wrap_err("failed to create memfd")
-                .unwrap();
-        MemFile(std::fs::File::from(descriptor))
+                .map_err(MemFdError::Create)?;
+        Ok(MemFile(std::fs::File::from(descriptor)))
     }
 
     /// Finalize and seal this [`MemFile`]
@@ -269,37 +793,39 @@ impl MemFile {
     ///
     /// 1. The file can not be modified to exclude write operations (basically chmod 400)
     /// 2. if the file can not be sealed against extension, truncation, mutation, and any attempt to remove the seals.
+    ///
+    /// See [`Self::try_finalize`] for a non-panicking variant.
     #[must_use]
     pub fn finalize(self) -> FinalizedMemFile {
+        self.try_finalize().into_diagnostic().unwrap()
+    }
+
+    /// Finalize and seal this [`MemFile`], as [`Self::finalize`] does.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file can not be made read only, sealed, or `seek`ed to its start.
+    pub fn try_finalize(self) -> Result<FinalizedMemFile, MemFdError> {
         let mut this = self;
         // mark the file as read only
         nix::sys::stat::fchmod(&this, nix::sys::stat::Mode::S_IRUSR)
-            .into_diagnostic()
-            .wrap_err("failed to set dataplane configuration memfd to readonly mode")
-            .unwrap();
+            .map_err(MemFdError::ReadOnly)?;
         this.seal(
             SealFlag::F_SEAL_WRITE
                 | SealFlag::F_SEAL_GROW
                 | SealFlag::F_SEAL_SHRINK
                 | SealFlag::F_SEAL_SEAL,
-        );
-        this.0
-            .seek(SeekFrom::Start(0))
-            .into_diagnostic()
-            .wrap_err("unable to seek finalized file to start")
-            .unwrap();
-        FinalizedMemFile(this)
+        )?;
+        this.0.seek(SeekFrom::Start(0)).map_err(MemFdError::Seek)?;
+        Ok(FinalizedMemFile(this))
     }
 
     /// Seal the file with the provided seal flags.
     #[tracing::instrument(level = "info")]
-    fn seal(&mut self, seals: SealFlag) {
-        nix::fcntl::fcntl(self, FcntlArg::F_ADD_SEALS(seals))
-            .into_diagnostic()
-            .wrap_err(format!(
-                "failed to add seals to mfd; attempted to add seals {seals:?}"
-            ))
-            .unwrap();
+    fn seal(&mut self, seals: SealFlag) -> Result<(), MemFdError> {
+        nix::fcntl::fcntl(&*self, FcntlArg::F_ADD_SEALS(seals))
+            .map_err(|e| MemFdError::Seal(seals, e))?;
+        Ok(())
     }
 }
 
@@ -373,11 +899,20 @@ impl From<MemFile> for FinalizedMemFile {
 pub struct GeneralConfigSection {
     /// Name to give to this dataplane/gateway
     name: Option<String>,
+    /// Unix socket where later generations of the configuration are received
+    /// (None => the configuration can't be changed at runtime)
+    generation_socket: Option<String>,
+    /// Embedded store persisting the state learned at runtime
+    state_store: String,
+    /// Directory where crash reports are written
+    crash_dir: String,
+    /// Directory where packet captures are written
+    capture_dir: String,
 }
 
 /// Configuration for the packet processing driver used by the dataplane.
 ///
-/// The dataplane supports two packet processing backends:
+/// The dataplane supports three packet processing backends:
 ///
 /// - **DPDK (Data Plane Development Kit)**: High-performance userspace driver for
 ///   specialized network hardware. Provides kernel-bypass networking with direct access
@@ -386,10 +921,15 @@ pub struct GeneralConfigSection {
 /// - **Kernel**: Standard Linux kernel networking stack. Uses traditional network
 ///   interfaces and kernel packet processing.
 ///
+/// - **AF_XDP**: Kernel interfaces whose frames are redirected by an XDP program to
+///   AF_XDP sockets, bypassing the kernel stack. Zero-copy where the NIC driver supports it.
+///
 /// # Choosing a Driver
 ///
 /// - Use **DPDK** for maximum performance on supported hardware, typically in production
 ///   environments with dedicated NICs.
+/// - Use **AF_XDP** for better performance than the kernel driver where DPDK isn't
+///   available.
 /// - Use **Kernel** for development, testing, or environments without DPDK-compatible
 ///   hardware.
 #[derive(
@@ -403,6 +943,8 @@ pub enum DriverConfigSection {
     Dpdk(DpdkDriverConfigSection),
     /// Linux kernel driver configuration
     Kernel(KernelDriverConfigSection),
+    /// AF_XDP driver configuration
+    AfXdp(AfXdpDriverConfigSection),
 }
 
 /// Configuration for the DPDK (Data Plane Development Kit) driver.
@@ -424,8 +966,14 @@ pub enum DriverConfigSection {
 pub struct DpdkDriverConfigSection {
     /// Network devices to use with DPDK (identified by PCI address)
     pub interfaces: Vec<InterfaceArg>,
-    /// DPDK EAL (Environment Abstraction Layer) initialization arguments
-    pub eal_args: Vec<String>,
+    /// DPDK EAL (Environment Abstraction Layer) initialization settings
+    pub eal: EalConfig,
+    /// The devices selected by filters, also in the EAL allow list
+    pub matched: Vec<DeviceMatch>,
+    /// The NUMA nodes lacking free hugepages for the buffer pools of their NICs
+    pub hugepage_shortages: Vec<HugepageShortage>,
+    /// Bounds of the bursts of packets received from the queues
+    pub batch_size: BatchSize,
 }
 
 /// Configuration for the Linux kernel networking driver.
@@ -447,6 +995,47 @@ pub struct DpdkDriverConfigSection {
 pub struct KernelDriverConfigSection {
     /// Kernel network interfaces to manage
     pub interfaces: Vec<InterfaceArg>,
+    /// Time after which a worker making no progress on a batch is reported stalled
+    pub stall_timeout: Duration,
+    /// Directory where to store the stacks of stalled workers, if they are to be captured
+    pub stall_profile_dir: Option<String>,
+    /// Bounds of the batches of packets received from the interfaces
+    pub batch_size: BatchSize,
+    /// Whether to drop unwanted frames in an XDP program, before they reach the workers
+    pub prefilter: bool,
+    /// Whether to receive frames through rings shared with the kernel, which give their RX hash
+    pub rx_ring: bool,
+}
+
+/// Configuration for the AF_XDP driver.
+///
+/// Workers are those of the kernel driver, but the frames of the interfaces are exchanged with
+/// the NICs through the UMEM rings of AF_XDP sockets: each worker serves the queue of its index
+/// of every interface.
+#[derive(
+    Debug,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    rkyv::Archive,
+    CheckBytes,
+)]
+#[rkyv(attr(derive(PartialEq, Eq, Debug)))]
+pub struct AfXdpDriverConfigSection {
+    /// Kernel network interfaces to manage
+    pub interfaces: Vec<InterfaceArg>,
+    /// Number of descriptors of each ring of the AF_XDP sockets
+    pub ring_size: u32,
+    /// Whether to require the NIC to copy frames, rather than to use zero-copy where supported
+    pub force_copy: bool,
+    /// Time after which a worker making no progress on a batch is reported stalled
+    pub stall_timeout: Duration,
+    /// Directory where to store the stacks of stalled workers, if they are to be captured
+    pub stall_profile_dir: Option<String>,
+    /// Bounds of the batches of packets received from the interfaces
+    pub batch_size: BatchSize,
 }
 
 /// Configuration for the dataplane's command-line interface (CLI).
@@ -467,13 +1056,15 @@ pub struct KernelDriverConfigSection {
 pub struct CliConfigSection {
     /// Unix socket path for CLI connections
     pub cli_sock_path: String,
+    /// File with the views of the dataplane that CLI sessions may be restricted to
+    pub cli_views: Option<String>,
 }
 
 /// Configuration for metrics collection and export.
 ///
 /// Defines the HTTP endpoint where Prometheus-compatible metrics are exposed.
 /// Metrics include packet counters, latency statistics, and other operational
-/// telemetry.
+/// telemetry. The same endpoint may serve a read-only looking glass API.
 #[derive(
     Debug,
     PartialEq,
@@ -488,9 +1079,15 @@ pub struct CliConfigSection {
 pub struct MetricsConfigSection {
     /// Socket address (IP and port) where metrics HTTP endpoint listens
     pub address: SocketAddr,
+    /// File holding the bearer token required by the looking glass API
+    /// (None => looking glass disabled)
+    pub looking_glass_token_file: Option<String>,
 }
 
-/// Configuration for the tracing / logging service used by the dataplane.
+/// Configuration of the export of traces and metrics to an OpenTelemetry collector.
+///
+/// The spans of the dataplane are exported as traces, and the metrics served on the
+/// Prometheus endpoint are exported as well, both with the OTLP/HTTP protocol.
 #[derive(
     Debug,
     PartialEq,
@@ -502,12 +1099,33 @@ pub struct MetricsConfigSection {
     CheckBytes,
 )]
 #[rkyv(attr(derive(PartialEq, Eq, Debug)))]
-pub struct TracingConfigSection {
-    /// Display options for trace output
-    pub show: TracingShowSection,
-    /// Tracing configuration string (e.g., "default=info,nat=debug")
-    pub config: Option<String>, // TODO: stronger typing on this config?
-    /// Rate limit for lower-severity tracing output; throttling is always on,
+pub struct OtlpConfigSection {
+    /// Base URL of the collector, to which `/v1/traces` and `/v1/metrics` are appended
+    pub endpoint: String,
+    /// Headers sent along with the exports
+    pub headers: Vec<OtlpHeader>,
+    /// Ratio of the traces which are sampled
+    pub sampling_ratio: SamplingRatio,
+}
+
+/// Configuration for the tracing / logging service used by the dataplane.
+#[derive(
+    Debug,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+    rkyv::Archive,
+    CheckBytes,
+)]
+#[rkyv(attr(derive(PartialEq, Eq, Debug)))]
+pub struct TracingConfigSection {
+    /// Display options for trace output
+    pub show: TracingShowSection,
+    /// Tracing configuration string (e.g., "default=info,nat=debug")
+    pub config: Option<String>, // TODO: stronger typing on this config?
+    /// Rate limit for lower-severity tracing output; throttling is always on,
     /// so an unset CLI flag falls back to the default (50:5)
     pub rate_limit: Option<TracingRateLimit>,
 }
@@ -572,6 +1190,8 @@ pub struct RoutingConfigSection {
     pub control_plane_socket: String,
     /// Unix socket path for FRR agent communication
     pub frr_agent_socket: String,
+    /// Time the routes learned from FRR are kept once FRR goes away
+    pub grace_period: Duration,
 }
 
 /// Configuration for the dynamic configuration server.
@@ -591,6 +1211,22 @@ pub struct RoutingConfigSection {
 #[rkyv(attr(derive(PartialEq, Eq, Debug)))]
 pub struct ConfigServerSection {
     pub config_dir: Option<String>,
+    /// The range route table ids are allocated to VPCs from
+    pub route_table_range: RouteTableRange,
+    /// Address of the gRPC endpoints streaming the events of the dataplane, rolling back its
+    /// configuration and reporting its health, if served
+    pub grpc_address: Option<SocketAddr>,
+    /// Address of the same gRPC endpoints, restricted to the methods which do not change the
+    /// dataplane, if served
+    pub grpc_observer_address: Option<SocketAddr>,
+    /// File holding the bearer token the gRPC methods changing the dataplane require, if any
+    pub grpc_token_file: Option<String>,
+    /// Directory where the config applied last is kept, if any
+    pub config_cache_dir: Option<String>,
+    /// Whether the config kept is applied at startup
+    pub config_replay: bool,
+    /// Kernel interfaces the dataplane must never observe, update or remove
+    pub unmanaged_interfaces: Vec<InterfaceName>,
 }
 
 /// BMP server configuration (optional; disabled when absent)
@@ -610,6 +1246,10 @@ pub struct BmpConfigSection {
     pub address: SocketAddr,
     /// Periodic housekeeping/flush interval in milliseconds
     pub interval: Duration,
+    /// Max number of recent route monitoring messages kept for dumps, if any are kept
+    pub store_messages: Option<u32>,
+    /// Max size in bytes of the recent route monitoring messages kept for dumps, if any are kept
+    pub store_bytes: Option<u64>,
 }
 
 /// Complete dataplane launch configuration.
@@ -655,10 +1295,14 @@ pub struct LaunchConfiguration {
     pub tracing: TracingConfigSection,
     /// Metrics collection configuration
     pub metrics: MetricsConfigSection,
+    /// Optional export of traces and metrics over OTLP (None => no export)
+    pub otlp: Option<OtlpConfigSection>,
     /// Optional BMP server configuration (None => BMP disabled)
     pub bmp: Option<BmpConfigSection>,
     /// Profiling configuration
     pub profiling: ProfilingConfigSection,
+    /// Values of feature flags, overriding their defaults
+    pub feature_flags: Vec<FeatureFlagArg>,
 }
 
 #[derive(
@@ -706,51 +1350,108 @@ impl LaunchConfiguration {
     /// file descriptor number.
     pub const STANDARD_CONFIG_FD: RawFd = 40;
 
+    /// Standard file descriptor number for the signature memfd.
+    ///
+    /// The parent process must pass the signature of the configuration at this file descriptor
+    /// number when signing it (see [`signature`]).
+    pub const STANDARD_SIGNATURE_FD: RawFd = 31;
+
     /// Inherit the launch configuration from the parent process.
     ///
     /// This method is called by the dataplane worker process to receive its configuration
     /// from the init process. It expects two sealed memory file descriptors at the standard
     /// FD numbers ([`STANDARD_INTEGRITY_CHECK_FD`](Self::STANDARD_INTEGRITY_CHECK_FD) and
-    /// [`STANDARD_CONFIG_FD`](Self::STANDARD_CONFIG_FD)).
+    /// [`STANDARD_CONFIG_FD`](Self::STANDARD_CONFIG_FD)), and a third one at
+    /// [`STANDARD_SIGNATURE_FD`](Self::STANDARD_SIGNATURE_FD) if a verifying key is configured
+    /// (see [`LaunchVerifyingKey::from_env()`]).
     ///
     /// # Process
     ///
     /// 1. Receives integrity check and configuration file descriptors
-    /// 2. Validates the SHA-384 hash matches the configuration
-    /// 3. Memory-maps the configuration for zero-copy access
-    /// 4. Validates the archived data structure (alignment, bounds, enum variants)
-    /// 5. Deserializes the configuration
+    /// 2. Verifies the Ed25519 signature of the configuration, if a verifying key is configured
+    /// 3. Validates the SHA-384 hash matches the configuration
+    /// 4. Memory-maps the configuration for zero-copy access
+    /// 5. Validates the archived data structure (alignment, bounds, enum variants)
+    /// 6. Deserializes the configuration
     ///
     /// # Panics
     ///
     /// This method is designed for early process initialization and will panic if:
     ///
     /// - File descriptors are missing or invalid
+    /// - The verifying key is invalid, or the signature does not match the configuration
     /// - Integrity check validation fails (hash mismatch)
     /// - Memory mapping fails
     /// - Archived data is misaligned or has invalid size
     /// - Deserialization fails (corrupt or invalid data)
     ///
     /// These panics are intentional as the dataplane cannot start without valid configuration.
+    /// See [`Self::try_inherit`] for a non-panicking variant.
     #[must_use]
-    #[allow(unsafe_code)] // no-escape from unsafety in this function as it involves constraints the compiler can't see
     pub fn inherit() -> LaunchConfiguration {
-        let integrity_check_fd = unsafe { OwnedFd::from_raw_fd(Self::STANDARD_INTEGRITY_CHECK_FD) };
-        let launch_configuration_fd = unsafe { OwnedFd::from_raw_fd(Self::STANDARD_CONFIG_FD) };
-        let integrity_check_file = unsafe { FinalizedMemFile::from_fd(integrity_check_fd) };
-        let mut launch_configuration_file =
-            unsafe { FinalizedMemFile::from_fd(launch_configuration_fd) };
+        Self::try_inherit().into_diagnostic().unwrap()
+    }
+
+    /// Inherit the launch configuration from the parent process, as [`Self::inherit`] does.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error in the cases [`Self::inherit`] panics in.
+    #[allow(unsafe_code)] // no-escape from unsafety in this function as it involves constraints the compiler can't see
+    pub fn try_inherit() -> Result<LaunchConfiguration, InheritError> {
+        let take = |fd: RawFd| {
+            let owned = unsafe { OwnedFd::from_raw_fd(fd) };
+            unsafe { FinalizedMemFile::try_from_fd(owned) }
+                .map_err(|error| InheritError::MemFd { fd, error })
+        };
+        let integrity_check_file = take(Self::STANDARD_INTEGRITY_CHECK_FD)?;
+        let mut launch_configuration_file = take(Self::STANDARD_CONFIG_FD)?;
+        match LaunchVerifyingKey::from_env().map_err(InheritError::VerifyingKey)? {
+            Some(key) => {
+                let signature_file = take(Self::STANDARD_SIGNATURE_FD)?;
+                key.verify(&mut launch_configuration_file, signature_file)
+                    .map_err(InheritError::Signature)?;
+                debug!("Verified the signature of the launch configuration with key {key}");
+            }
+            None => {
+                debug!("No verifying key configured: checking launch configuration integrity only");
+            }
+        }
+        Self::from_memfds(launch_configuration_file, integrity_check_file).map_err(|report| {
+            let causes: Vec<_> = report.chain().map(ToString::to_string).collect();
+            InheritError::InvalidConfiguration(causes.join(": "))
+        })
+    }
+
+    /// Recover a launch configuration from its sealed memfd and the memfd of its integrity check.
+    ///
+    /// This is the part of [`Self::inherit()`] which does not depend on the file descriptor
+    /// numbers, and which is also used to receive later generations of the configuration (see
+    /// [`generation`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if
+    ///
+    /// - the integrity check does not match the configuration
+    /// - the configuration can not be memory mapped
+    /// - the archived data is misaligned or has an invalid size
+    /// - the archived data can not be validated or deserialized
+    #[allow(unsafe_code)] // the memfd is sealed against modification while mapped
+    pub fn from_memfds(
+        config_file: FinalizedMemFile,
+        check_file: FinalizedMemFile,
+    ) -> Result<LaunchConfiguration, miette::Report> {
+        let mut launch_configuration_file = config_file;
         launch_configuration_file
-            .validate(integrity_check_file)
-            .wrap_err("checksum validation failed for launch configuration")
-            .unwrap();
+            .validate(check_file)
+            .wrap_err("checksum validation failed for launch configuration")?;
 
         let mut mmap_options = memmap2::MmapOptions::new();
         let mmap_options = mmap_options.no_reserve_swap();
         let launch_config_memmap = unsafe { mmap_options.map(launch_configuration_file.as_ref()) }
             .into_diagnostic()
-            .wrap_err("failed to memory map launch configuration")
-            .unwrap();
+            .wrap_err("failed to memory map launch configuration")?;
 
         // VERY IMPORTANT: we must check for unaligned pointer here or risk undefined behavior.
 
@@ -760,14 +1461,14 @@ impl LaunchConfiguration {
             .as_ptr()
             .cast::<ArchivedLaunchConfiguration>()
             .is_aligned();
-        assert!(
+        miette::ensure!(
             is_aligned,
-            "invalid alignment for ArchivedLaunchConfiguration found in inherited memfd"
+            "invalid alignment for ArchivedLaunchConfiguration found in memfd"
         );
 
-        assert!(
+        miette::ensure!(
             launch_config_memmap.as_ref().len() >= size_of::<ArchivedLaunchConfiguration>(),
-            "invalid size for inherited memfd"
+            "invalid size for launch configuration memfd"
         );
 
         // we slightly abuse the access method here just to get byte level validation.
@@ -777,14 +1478,59 @@ impl LaunchConfiguration {
             launch_config_memmap.as_ref(),
         )
         .into_diagnostic()
-        .wrap_err("failed to validate ArchivedLaunchConfiguration")
-        .unwrap();
+        .wrap_err("failed to validate ArchivedLaunchConfiguration")?;
 
         // here we actually deserialize the data
         rkyv::from_bytes::<LaunchConfiguration, rkyv::rancor::Error>(launch_config_memmap.as_ref())
             .into_diagnostic()
             .wrap_err("failed to deserialize launch configuration")
-            .unwrap()
+    }
+}
+
+impl LaunchConfiguration {
+    /// Go through the handoff of this configuration to a dataplane process, without starting
+    /// one: serialize the configuration into a sealed memfd and compute its integrity check,
+    /// then validate, check and deserialize the memfd as [`Self::inherit()`] would.
+    ///
+    /// Returns the configuration recovered from the memfd, and its integrity check.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the configuration can't be recovered from the memfd.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the memfd can't be created or written to, as when launching the dataplane.
+    pub fn dry_run(self) -> Result<(LaunchConfiguration, IntegrityCheck), miette::Report> {
+        let mut config_file = self.finalize();
+        let check_file = config_file.integrity_check().finalize();
+        config_file
+            .validate(check_file)
+            .wrap_err("checksum validation failed for launch configuration")?;
+
+        let mut contents = Vec::new();
+        config_file
+            .0
+            .0
+            .seek(SeekFrom::Start(0))
+            .into_diagnostic()
+            .wrap_err("failed to seek to start of memfd")?;
+        config_file
+            .as_ref()
+            .read_to_end(&mut contents)
+            .into_diagnostic()
+            .wrap_err("failed to read launch configuration from memfd")?;
+        // the memfd is page aligned when mapped by the dataplane: align the copy likewise
+        let mut aligned = rkyv::util::AlignedVec::<16>::with_capacity(contents.len());
+        aligned.extend_from_slice(&contents);
+
+        rkyv::access::<ArchivedLaunchConfiguration, rkyv::rancor::Failure>(&aligned)
+            .into_diagnostic()
+            .wrap_err("failed to validate ArchivedLaunchConfiguration")?;
+        let config = rkyv::from_bytes::<LaunchConfiguration, rkyv::rancor::Error>(&aligned)
+            .into_diagnostic()
+            .wrap_err("failed to deserialize launch configuration")?;
+        Ok((config, config_file.integrity_check()))
     }
 }
 
@@ -856,12 +1602,7 @@ impl FinalizedMemFile {
     ///
     /// # Safety
     ///
-    /// This function _attempts_ to check that it has been given a real [`FinalizedMemFile`] (typically from another
-    /// process).
-    /// This check is on a best effort basis, and is not infallible.
-    /// Additional checks (e.g., checksums or cryptographic signatures) should be used to ensure that this file contains
-    /// the expected bits.
-    /// That said, these checks are not and can't really be infallible.
+    /// See [`Self::try_from_fd`].
     ///
     /// # Panics
     ///
@@ -873,62 +1614,68 @@ impl FinalizedMemFile {
     /// 6. panics if the provided memfd is not sealed (against writes, truncation, extension, and seal modifications)
     /// 7. panics if the provided memfd can not be `seek`ed to the start of the file (very unlikely)
     /// 8. panics if the provided memfd can not be marked as close-on-exec (very unlikely)
+    #[allow(unsafe_code)] // same contract as try_from_fd
+    pub unsafe fn from_fd(fd: OwnedFd) -> FinalizedMemFile {
+        unsafe { Self::try_from_fd(fd) }.into_diagnostic().unwrap()
+    }
+
+    /// Construct a [`FinalizedMemFile`] from a file descriptor, failing if it does not look like one
+    ///
+    /// # Safety
+    ///
+    /// This function _attempts_ to check that it has been given a real [`FinalizedMemFile`] (typically from another
+    /// process).
+    /// This check is on a best effort basis, and is not infallible.
+    /// Additional checks (e.g., checksums or cryptographic signatures) should be used to ensure that this file contains
+    /// the expected bits.
+    /// That said, these checks are not and can't really be infallible.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error in the cases [`Self::from_fd`] panics in.
     #[instrument(level = "debug", skip(fd))]
     #[allow(unsafe_code)] // external contract documented and checked as well as I can for now
-    pub unsafe fn from_fd(fd: OwnedFd) -> FinalizedMemFile {
+    pub unsafe fn try_from_fd(fd: OwnedFd) -> Result<FinalizedMemFile, MemFdError> {
         // TODO: is procfs actually mounted at /proc?  Are we reading the correct file.  Annoying to fix this properly.
         let os_str =
             nix::fcntl::readlink(format!("/proc/self/fd/{fd}", fd = fd.as_raw_fd()).as_str())
-                .into_diagnostic()
-                .wrap_err("failed to read memfd link in /proc")
-                .unwrap();
+                .map_err(MemFdError::Readlink)?;
         let readlink_result = os_str
             .into_string()
-            .map_err(|_| std::io::Error::other("file descriptor readlink returned invalid unicode"))
-            .into_diagnostic()
-            .unwrap();
-        assert!(
-            readlink_result.starts_with("/memfd:"),
-            "supplied file descriptor is not a memfd: {readlink_result}"
-        );
-        let stat = nix::sys::stat::fstat(fd.as_fd())
-            .into_diagnostic()
-            .wrap_err("failed to stat memfd")
-            .unwrap();
+            .map_err(|_| MemFdError::InvalidUnicode)?;
+        if !readlink_result.starts_with("/memfd:") {
+            return Err(MemFdError::NotMemFd(readlink_result));
+        }
+        let stat = nix::sys::stat::fstat(fd.as_fd()).map_err(MemFdError::Stat)?;
         const EXPECTED_PERMISSIONS: u32 = nix::libc::S_IFREG | nix::libc::S_IRUSR; // regular file | owner read-only
-        assert!(
-            stat.st_mode == EXPECTED_PERMISSIONS,
-            "finalized memfd not in read only mode: given mode is {:o}, expected {EXPECTED_PERMISSIONS:o}",
-            stat.st_mode
-        );
+        if stat.st_mode != EXPECTED_PERMISSIONS {
+            return Err(MemFdError::NotReadOnly {
+                given: stat.st_mode,
+                expected: EXPECTED_PERMISSIONS,
+            });
+        }
 
-        let Some(seals) = SealFlag::from_bits(
-            nix::fcntl::fcntl(fd.as_fd(), FcntlArg::F_GET_SEALS)
-                .into_diagnostic()
-                .wrap_err("failed to get seals on file descriptor")
-                .unwrap(),
-        ) else {
-            panic!("seal bits on memfd are set but are unknown to the system");
+        let seals =
+            nix::fcntl::fcntl(fd.as_fd(), FcntlArg::F_GET_SEALS).map_err(MemFdError::GetSeals)?;
+        let Some(seals) = SealFlag::from_bits(seals) else {
+            return Err(MemFdError::UnknownSeals);
         };
         let expected_bits: SealFlag = SealFlag::F_SEAL_GROW
             | SealFlag::F_SEAL_SHRINK
             | SealFlag::F_SEAL_WRITE
             | SealFlag::F_SEAL_SEAL;
-        assert!(
-            seals.contains(expected_bits),
-            "missing seal bits on finalized memfd: bits set {seals:?}, bits expected: {expected_bits:?}"
-        );
+        if !seals.contains(expected_bits) {
+            return Err(MemFdError::MissingSeals {
+                given: seals,
+                expected: expected_bits,
+            });
+        }
         let mut file = std::fs::File::from(fd);
-        file.seek(SeekFrom::Start(0))
-            .into_diagnostic()
-            .wrap_err("failed to seek to start of memfd")
-            .unwrap();
+        file.seek(SeekFrom::Start(0)).map_err(MemFdError::Seek)?;
         // mark file close on exec so we are less likely to accidentally leak it
         nix::fcntl::fcntl(file.as_fd(), FcntlArg::F_SETFD(FdFlag::FD_CLOEXEC))
-            .into_diagnostic()
-            .wrap_err("unable to mark memfd as close-on-exec")
-            .unwrap();
-        FinalizedMemFile(MemFile(file))
+            .map_err(MemFdError::CloseOnExec)?;
+        Ok(FinalizedMemFile(MemFile(file)))
     }
 
     /// Validate this file using an [`IntegrityCheck`] serialized into the provided `check_file`
@@ -967,6 +1714,19 @@ impl FinalizedMemFile {
     }
 }
 
+/// Errors inheriting the launch configuration from the parent process.
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum InheritError {
+    #[error("invalid memfd at file descriptor {fd}: {error}")]
+    MemFd { fd: RawFd, error: MemFdError },
+    #[error("failed to load the launch configuration verifying key: {0}")]
+    VerifyingKey(LaunchSignatureError),
+    #[error("signature verification failed for launch configuration: {0}")]
+    Signature(LaunchSignatureError),
+    #[error("invalid launch configuration: {0}")]
+    InvalidConfiguration(String),
+}
+
 /// Errors that can occur during integrity check validation.
 #[derive(Debug, thiserror::Error, miette::Diagnostic)]
 pub enum IntegrityCheckError {
@@ -1065,6 +1825,15 @@ impl IntegrityCheck {
     }
 }
 
+impl std::fmt::Display for IntegrityCheck {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.sha384
+            .0
+            .iter()
+            .try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
 impl AsFinalizedMemFile for IntegrityCheck {
     #[tracing::instrument(level = "info")]
     fn finalize(self) -> FinalizedMemFile {
@@ -1105,14 +1874,61 @@ pub enum InvalidCmdArguments {
     /// (e.g., `eth0`, `ens3`)
     #[error(transparent)]
     InvalidInterfaceName(#[from] IllegalInterfaceName),
-    #[error("\"{0}\" is not a valid driver.  Must be dpdk or kernel")]
+    #[error("\"{0}\" is not a valid driver.  Must be dpdk, kernel or af_xdp")]
     InvalidDriver(String),
-    #[error("Must specify driver as dpdk or kernel")]
+    #[error("Must specify driver as dpdk, kernel or af_xdp")]
     NoDriverSpecified,
     #[error("No network interfaces specified")]
     NoInterfacesSpecified,
     #[error(transparent)]
     UnsupportedByDriver(#[from] UnsupportedByDriver),
+    #[error("Port {1} of interface {0} does not exist on this host")]
+    NoSuchPort(InterfaceName, String),
+    #[error("Interface {0} is given {1} queues, but the driver has only {2} workers")]
+    TooManyQueues(InterfaceName, u16, usize),
+    #[error(transparent)]
+    InvalidEal(#[from] InvalidEalConfig),
+    #[error(transparent)]
+    DeviceSearch(#[from] DeviceSearchError),
+}
+
+/// Describe the driver the port of an interface is bound to.
+///
+/// # Errors
+///
+/// Returns [`InvalidCmdArguments::NoSuchPort`] if the port does not exist on this host.
+fn port_binding(nic: &InterfaceArg) -> Result<String, InvalidCmdArguments> {
+    let driver_of = |device: &Path| {
+        std::fs::read_link(device.join("driver"))
+            .ok()
+            .and_then(|link| {
+                link.file_name()
+                    .map(|name| name.to_string_lossy().into_owned())
+            })
+    };
+    let (port, driver) = match &nic.port {
+        None => return Ok(format!("{}: no port", nic.interface)),
+        Some(PortArg::PCI(address)) => {
+            let device = Path::new("/sys/bus/pci/devices").join(address.to_string());
+            (
+                format!("pci@{address}"),
+                device.exists().then(|| driver_of(&device)),
+            )
+        }
+        Some(PortArg::KERNEL(name)) => {
+            let device = Path::new("/sys/class/net").join(name.to_string());
+            let driver = driver_of(&device.join("device")).unwrap_or("virtual".to_string());
+            (
+                format!("kernel@{name}"),
+                device.exists().then_some(Some(driver)),
+            )
+        }
+    };
+    let Some(driver) = driver else {
+        return Err(InvalidCmdArguments::NoSuchPort(nic.interface.clone(), port));
+    };
+    let driver = driver.unwrap_or("no driver".to_string());
+    Ok(format!("{}: {port}, bound to {driver}", nic.interface))
 }
 
 /// Errors resulting from invalid command lines (driver to interface spec mismatch)
@@ -1132,22 +1948,43 @@ impl TryFrom<CmdArgs> for LaunchConfiguration {
     type Error = InvalidCmdArguments;
 
     fn try_from(value: CmdArgs) -> Result<Self, InvalidCmdArguments> {
+        Self::try_from(&value)
+    }
+}
+
+impl TryFrom<&CmdArgs> for LaunchConfiguration {
+    type Error = InvalidCmdArguments;
+
+    fn try_from(value: &CmdArgs) -> Result<Self, InvalidCmdArguments> {
         Ok(LaunchConfiguration {
             general: GeneralConfigSection {
                 name: value.get_name().cloned(),
+                generation_socket: value
+                    .generation_socket()
+                    .map(std::string::ToString::to_string),
+                state_store: value.state_store(),
+                crash_dir: value.crash_dir(),
+                capture_dir: value.capture_dir(),
             },
             config_server: Some(ConfigServerSection {
                 config_dir: value.config_dir().cloned(),
+                route_table_range: value.route_table_range(),
+                grpc_address: value.grpc_address(),
+                grpc_observer_address: value.grpc_observer_address(),
+                grpc_token_file: value.grpc_token_file().map(str::to_string),
+                config_cache_dir: value.config_cache_dir().map(str::to_string),
+                config_replay: value.config_replay(),
+                unmanaged_interfaces: value.unmanaged_interfaces().cloned().collect(),
             }),
             driver: match &value.driver {
                 Some(driver) if driver == "dpdk" => {
-                    // TODO: adjust command line to specify lcore usage more flexibly in next PR
-                    let eal_args = value
+                    let allow = value
                         .interfaces()
                         .map(|nic| match nic.port {
-                            Some(PortArg::PCI(pci_address)) => {
-                                Ok(["--allow".to_string(), format!("{pci_address}")])
-                            }
+                            Some(PortArg::PCI(address)) => Ok(EalDevice {
+                                address,
+                                devargs: None,
+                            }),
                             Some(PortArg::KERNEL(interface_name)) => {
                                 Err(InvalidCmdArguments::UnsupportedByDriver(
                                     UnsupportedByDriver::Dpdk(interface_name.clone()),
@@ -1155,18 +1992,76 @@ impl TryFrom<CmdArgs> for LaunchConfiguration {
                             }
                             None => Err(InvalidCmdArguments::NoInterfacesSpecified),
                         })
-                        .collect::<Result<Vec<_>, _>>()?
-                        .into_iter()
-                        .flatten()
-                        .collect();
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let matched = value.eal_device_matches()?;
+                    let allow =
+                        allow
+                            .into_iter()
+                            .chain(matched.iter().flat_map(|m| &m.devices).map(|address| {
+                                EalDevice {
+                                    address: address.clone(),
+                                    devargs: None,
+                                }
+                            }))
+                            .collect();
+                    let mut eal = EalConfig {
+                        lcores: value.eal_lcores(),
+                        memory_channels: value.eal_memory_channels(),
+                        huge_dir: value.eal_huge_dir().map(std::string::ToString::to_string),
+                        socket_mem: value.eal_socket_mem(),
+                        iova_mode: value.eal_iova_mode(),
+                        allow,
+                        block: vec![],
+                    };
+                    for devargs in value.eal_devargs() {
+                        eal.set_devargs(&devargs.address, &devargs.devargs)?;
+                    }
+                    let mut hugepage_shortages = vec![];
+                    if let Some(pool_mb) = value.eal_pool_size() {
+                        let nics = eal
+                            .allow
+                            .iter()
+                            .map(|device| (device.address.clone(), numa_node_of(&device.address)));
+                        let plan = SocketMemPlan::new(nics, pool_mb);
+                        if eal.socket_mem.is_empty() {
+                            eal.socket_mem = plan.socket_mem();
+                        }
+                        // with no NUMA node listed, every node is reported lacking hugepages
+                        hugepage_shortages = plan.shortages(&free_hugepages().unwrap_or_default());
+                    }
+                    eal.validate()?;
                     DriverConfigSection::Dpdk(DpdkDriverConfigSection {
                         interfaces: value.interfaces().collect(),
-                        eal_args,
+                        eal,
+                        matched,
+                        hugepage_shortages,
+                        batch_size: value.rx_batch_size(),
                     })
                 }
                 Some(driver) if driver == "kernel" => {
+                    value.check_worker_queues()?;
                     DriverConfigSection::Kernel(KernelDriverConfigSection {
                         interfaces: value.interfaces().collect(),
+                        stall_timeout: value.worker_stall_timeout(),
+                        stall_profile_dir: value
+                            .worker_stall_profile_dir()
+                            .map(std::string::ToString::to_string),
+                        batch_size: value.rx_batch_size(),
+                        prefilter: value.kernel_prefilter(),
+                        rx_ring: value.kernel_rx_ring(),
+                    })
+                }
+                Some(driver) if driver == "af_xdp" => {
+                    value.check_worker_queues()?;
+                    DriverConfigSection::AfXdp(AfXdpDriverConfigSection {
+                        interfaces: value.interfaces().collect(),
+                        ring_size: value.xdp_ring_size(),
+                        force_copy: value.xdp_force_copy(),
+                        stall_timeout: value.worker_stall_timeout(),
+                        stall_profile_dir: value
+                            .worker_stall_profile_dir()
+                            .map(std::string::ToString::to_string),
+                        batch_size: value.rx_batch_size(),
                     })
                 }
                 Some(other) => Err(InvalidCmdArguments::InvalidDriver(other.clone()))?,
@@ -1174,10 +2069,12 @@ impl TryFrom<CmdArgs> for LaunchConfiguration {
             },
             cli: CliConfigSection {
                 cli_sock_path: value.cli_sock_path(),
+                cli_views: value.cli_views().map(std::string::ToString::to_string),
             },
             routing: RoutingConfigSection {
                 control_plane_socket: value.cpi_sock_path(),
                 frr_agent_socket: value.frr_agent_path(),
+                grace_period: value.frr_grace_period(),
             },
             tracing: TracingConfigSection {
                 show: TracingShowSection {
@@ -1197,11 +2094,21 @@ impl TryFrom<CmdArgs> for LaunchConfiguration {
             },
             metrics: MetricsConfigSection {
                 address: value.metrics_address(),
+                looking_glass_token_file: value
+                    .looking_glass_token_file()
+                    .map(std::string::ToString::to_string),
             },
+            otlp: value.otlp_endpoint().map(|endpoint| OtlpConfigSection {
+                endpoint: endpoint.to_string(),
+                headers: value.otlp_headers().cloned().collect(),
+                sampling_ratio: value.otlp_sampling_ratio(),
+            }),
             bmp: if value.bmp_enabled() {
                 Some(BmpConfigSection {
                     address: value.bmp_address(),
                     interval: value.bmp_interval(),
+                    store_messages: value.bmp_store_messages(),
+                    store_bytes: value.bmp_store_bytes(),
                 })
             } else {
                 None
@@ -1210,6 +2117,7 @@ impl TryFrom<CmdArgs> for LaunchConfiguration {
                 pyroscope_url: value.pyroscope_url().map(std::string::ToString::to_string),
                 frequency: ProfilingConfigSection::DEFAULT_FREQUENCY,
             },
+            feature_flags: value.feature_flags().cloned().collect(),
         })
     }
 }
@@ -1220,7 +2128,14 @@ impl TryFrom<CmdArgs> for LaunchConfiguration {
 #[command(about = "A dataplane for hedgehog's fabric gateway", long_about = None)]
 #[allow(clippy::struct_excessive_bools)]
 pub struct CmdArgs {
-    #[arg(long, value_name = "packet driver to use: kernel or dpdk")]
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "YAML (or TOML, if named *.toml) file giving flags by their long name. Flags on the command line take precedence over the file, which takes precedence over the defaults"
+    )]
+    config_file: Option<String>,
+
+    #[arg(long, value_name = "packet driver to use: kernel, af_xdp or dpdk")]
     driver: Option<String>,
     #[arg(
         long,
@@ -1229,23 +2144,164 @@ pub struct CmdArgs {
         value_delimiter=',',
         help = "Interface name mapping, with syntax INTERFACE=DISCRIMINANT@{PCI,IFNAME}. Two discriminants are possible: pci and kernel.
 Pci should be followed by a PCI address. Kernel should be followed by a valid kernel interface name.
+The port may be followed by the number of its receive and transmit queues and the lcores serving them, with syntax ;rxq=N;txq=N;cores=LIST
+where the lcores of the list are separated by colons.
 Examples:
    --interface eth0=pci@0000:02:01.0
    --interface eth1=kernel@enp2s1
+   --interface 'eth0=pci@0000:02:01.0;rxq=4;txq=4;cores=2-5'
 Note: multiple interfaces can be specified separated by commas and no spaces"
     )]
     interface: Vec<InterfaceArg>,
 
+    /// Lcores of the DPDK driver.
+    #[arg(
+        long,
+        value_name = "LIST",
+        value_parser = LcoreList::from_str,
+        help = "Lcores the DPDK driver runs on, as a list of lcores and ranges of lcores (e.g. 0-3,8)"
+    )]
+    eal_lcores: Option<LcoreList>,
+
+    /// Memory channels of the DPDK driver.
+    #[arg(
+        long,
+        value_name = "N",
+        value_parser = clap::value_parser!(u8).range(1..=32),
+        help = "Number of memory channels per socket for the DPDK driver in [1..32]"
+    )]
+    eal_memory_channels: Option<u8>,
+
+    /// Hugepage directory of the DPDK driver.
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Directory where the hugetlbfs used by the DPDK driver is mounted"
+    )]
+    eal_huge_dir: Option<String>,
+
+    /// Size of the buffer pool of each NIC of the DPDK driver.
+    #[arg(
+        long,
+        value_name = "MB",
+        value_parser = clap::value_parser!(u32).range(1..=65536),
+        help = "Size in MB of the buffer pool of each device of the DPDK driver, in [1..65536]. The hugepage memory of the pools is reserved on the NUMA node of each device, and a node lacking free hugepages for its devices is reported at startup"
+    )]
+    eal_pool_size: Option<u32>,
+
+    /// Hugepage memory of the DPDK driver on each NUMA node.
+    #[arg(
+        long,
+        value_name = "LIST",
+        value_parser = SocketMemList::from_str,
+        help = "Hugepage memory in MB the DPDK driver reserves on each NUMA node, indexed by node (e.g. 1024,0,512). Takes precedence over the memory planned from --eal-pool-size, which is then only checked against the free hugepages"
+    )]
+    eal_socket_mem: Option<SocketMemList>,
+
+    /// IOVA mode of the DPDK driver.
+    #[arg(
+        long,
+        value_name = "pa|va",
+        value_parser = IovaMode::from_str,
+        help = "IO virtual addresses used by the DPDK driver: physical (pa) or virtual (va)"
+    )]
+    eal_iova_mode: Option<IovaMode>,
+
+    /// Device arguments of the DPDK driver.
+    #[arg(
+        long,
+        value_name = "PCI=DEVARGS",
+        value_parser = DevargsArg::from_str,
+        help = "Arguments of the DPDK driver of the device of an interface, with syntax PCI_ADDRESS=DEVARGS.
+Example:
+   --eal-devargs 0000:02:01.0=mprq_en=1,rxqs_min_mprq=1
+Note: can be given once per device"
+    )]
+    eal_devargs: Vec<DevargsArg>,
+
+    /// Filters selecting devices of the DPDK driver.
+    #[arg(
+        long,
+        value_name = "FILTER",
+        value_parser = PciDeviceFilter::from_str,
+        help = "Hand the DPDK driver the PCI network devices matching a filter, with syntax match:KEY=VALUE,... where the keys are vendor (a name or a hexadecimal id), device (a hexadecimal id), driver (the kernel driver), numa (the NUMA node) and count (the number of devices, 1 unless given).
+The devices matching are picked by increasing PCI address, skipping those of the interfaces and those picked by the filters given before.
+Example:
+   --eal-device-match match:vendor=mellanox,numa=0,count=2
+Note: can be given more than once"
+    )]
+    eal_device_match: Vec<PciDeviceFilter>,
+
     /// Number of worker threads for the kernel driver.
     #[arg(
         long,
         value_name = "N",
         default_value_t = 1,
         value_parser = clap::value_parser!(u16).range(1..=64),
-        help = "Number of worker threads for the kernel driver in [1..64]"
+        help = "Number of worker threads for the kernel and AF_XDP drivers in [1..64]"
     )]
     num_workers: u16,
 
+    /// Size of the rings of the AF_XDP sockets.
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 2048,
+        value_parser = parse_xdp_ring_size,
+        help = "Number of descriptors of each ring of the AF_XDP sockets, a power of 2 in [64..16384]"
+    )]
+    xdp_ring_size: u32,
+
+    /// Whether the AF_XDP driver requires NICs to copy frames.
+    #[arg(
+        long,
+        help = "Make the AF_XDP driver copy frames from and to the NICs, even if their drivers support zero-copy"
+    )]
+    xdp_force_copy: bool,
+
+    /// Bounds of the batches of packets received by the drivers.
+    #[arg(
+        long,
+        value_name = "MIN:MAX",
+        default_value = "16:128",
+        value_parser = BatchSize::from_str,
+        help = "Bounds of the number of packets the drivers receive at once, with syntax MIN:MAX, in [1..1024].
+Batches grow towards MAX under sustained load, and shrink back towards MIN when the queues are shallow"
+    )]
+    rx_batch_size: BatchSize,
+
+    /// Time after which a busy worker making no progress is reported stalled.
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 5,
+        value_parser = clap::value_parser!(u64).range(1..=3600),
+        help = "Report the kernel driver workers stuck on a batch for this many seconds"
+    )]
+    worker_stall_timeout: u64,
+
+    /// Directory where to store the stacks of stalled workers.
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Capture the stacks of stalled kernel driver workers with pprof, and store them in this directory"
+    )]
+    worker_stall_profile_dir: Option<String>,
+
+    /// Whether the kernel driver drops unwanted frames in an XDP program.
+    #[arg(
+        long,
+        help = "Drop the frames from spoofed sources, or sources denied by the ACLs, in an XDP program on the interfaces of the kernel driver, before they reach the workers"
+    )]
+    kernel_prefilter: bool,
+
+    /// Whether the kernel driver receives frames through rings shared with the kernel.
+    #[arg(
+        long,
+        help = "Receive the frames of the interfaces of the kernel driver through TPACKET_V3 rings, which give the RX hash of the frames to the pipeline, at the cost of up to 1 ms of latency under low load. The receive queues of rings are not watched for microbursts"
+    )]
+    kernel_rx_ring: bool,
+
     #[arg(
         long,
         value_name = "CPI Unix socket path",
@@ -1262,6 +2318,13 @@ Note: multiple interfaces can be specified separated by commas and no spaces"
     )]
     cli_sock_path: String,
 
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "YAML file with the CLI administrators and the views of the dataplane that other CLI sessions are restricted to, by token or user id. Without it, only root may use the CLI"
+    )]
+    cli_views: Option<String>,
+
     #[arg(
         long,
         value_name = "FRR Agent Unix socket path",
@@ -1270,6 +2333,16 @@ Note: multiple interfaces can be specified separated by commas and no spaces"
     )]
     frr_agent_path: String,
 
+    /// Time the routes learned from FRR are kept once FRR goes away.
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = DEFAULT_FRR_GRACE_PERIOD,
+        value_parser = clap::value_parser!(u64).range(1..=3600),
+        help = "Keep forwarding with the routes learned from FRR for this many seconds when FRR or the dataplane restarts, or FRR goes away"
+    )]
+    frr_grace_period: u64,
+
     /// Prometheus metrics server bind address
     #[arg(
         long,
@@ -1279,6 +2352,40 @@ Note: multiple interfaces can be specified separated by commas and no spaces"
     )]
     metrics_address: SocketAddr,
 
+    /// Bearer token file for the looking glass API
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Serve the read-only looking glass API alongside the metrics, to clients presenting the bearer token in this file"
+    )]
+    looking_glass_token_file: Option<String>,
+
+    /// OpenTelemetry collector for traces and metrics
+    #[arg(
+        long,
+        value_name = "URL",
+        help = "Export traces and metrics to the OpenTelemetry collector at this URL, with OTLP/HTTP (e.g. http://127.0.0.1:4318)"
+    )]
+    otlp_endpoint: Option<url::Url>,
+
+    #[arg(
+        long,
+        value_name = "NAME=VALUE",
+        value_parser = OtlpHeader::from_str,
+        requires = "otlp_endpoint",
+        help = "Header to send along with the OTLP exports, e.g. to authenticate. May be repeated"
+    )]
+    otlp_header: Vec<OtlpHeader>,
+
+    #[arg(
+        long,
+        value_name = "RATIO",
+        value_parser = SamplingRatio::from_str,
+        default_value_t = SamplingRatio::ALWAYS,
+        help = "Ratio of the traces exported with OTLP, in [0..1]"
+    )]
+    otlp_sampling_ratio: SamplingRatio,
+
     /// Pyroscope server address for profiling uploads
     #[arg(
         long,
@@ -1330,54 +2437,280 @@ Throttling is always on: if omitted, the default rate limit of 50:5 is applied.
     #[arg(
         long,
         help = "Run in k8s-less mode using this directory to watch for configurations.
-You can copy json/yaml config files in this directory to reconfigure dataplane. You can modify existing
-files or just 'touch' them to trigger a new reconfiguration. Every change will increase the generation id by one.
+You can copy json/yaml config files in this directory to reconfigure dataplane. The configuration is made of all the
+documents of all the files of the directory, merged in the order of the file names, so that it can be split across files.
+You can add, modify, move or delete files or just 'touch' them to trigger a new reconfiguration. Every change will increase the generation id by one.
 NOTE: dataplane tracks file 'save' events. If you modify an existing file, depending on the editor used, this will
 trigger more than one reconfiguration (e.g. gedit). If this is undesired, use nano or vi(m), or edit your file
 elsewhere and copy it in the configuration directory. This mode is meant mostly for debugging or early testing."
     )]
     config_dir: Option<String>,
-    /// Enable BMP server
-    #[arg(long, default_value_t = false, help = "Enable BMP server")]
-    bmp_enable: bool,
 
     #[arg(
         long,
-        value_name = "IP:PORT",
-        default_value_t = SocketAddr::from(([0, 0, 0, 0], 5000)),
-        help = "Bind address for the BMP server"
+        value_name = "FIRST-LAST",
+        value_parser=RouteTableRange::from_str,
+        help = "Range of kernel route table ids allocated to the VRFs of VPCs which do not specify one (default: 10000-65535).
+User-specified route table ids must lie outside of this range."
     )]
-    bmp_address: SocketAddr,
+    route_table_range: Option<RouteTableRange>,
 
     #[arg(
         long,
-        value_name = "MILLISECONDS",
-        default_value_t = 10_000,
-        help = "BMP periodic interval for housekeeping/flush (ms)"
+        value_name = "ADDRESS:PORT",
+        help = "Serve the gRPC endpoints of the dataplane on this address: the stream of its events (configs applied, interface and BGP session changes, NAT pool exhaustion...), the rollback of its configuration, its status and counters, and the grpc.health.v1 health service reporting its readiness"
     )]
-    bmp_interval: u64,
-}
+    grpc_address: Option<SocketAddr>,
 
-impl CmdArgs {
-    /// Get the configured driver name.
-    ///
-    /// Returns `"dpdk"` if no driver was explicitly specified (the default),
-    /// otherwise returns the specified driver name (`"dpdk"` or `"kernel"`).
-    #[must_use]
-    pub fn driver_name(&self) -> &str {
-        match &self.driver {
-            None => "dpdk",
-            Some(name) => name,
-        }
-    }
+    #[arg(
+        long,
+        value_name = "ADDRESS:PORT",
+        help = "Serve the read-only gRPC endpoints of the dataplane on this address, for monitoring systems: the stream of its events, its status and counters, and its health. The methods changing the dataplane, like the rollback of its configuration, are denied there"
+    )]
+    grpc_observer_address: Option<SocketAddr>,
 
-    /// Check if the `--show-tracing-tags` flag was set.
-    ///
-    /// When true, the application should display available tracing tags and exit.
-    ///
-    /// # Returns
-    ///
-    /// `true` if `--show-tracing-tags` was passed, `false` otherwise.
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "File holding the bearer token the clients of the gRPC endpoints must present, in the authorization metadata, to call the methods changing the dataplane, like the rollback of its configuration. Those methods are denied without it"
+    )]
+    grpc_token_file: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Directory where the config applied last is kept, as a sealed and checksummed file, to be applied again when the dataplane restarts, before connecting to k8s"
+    )]
+    config_cache_dir: Option<String>,
+
+    #[arg(
+        long,
+        value_name = "BOOL",
+        default_value_t = true,
+        action = clap::ArgAction::Set,
+        help = "Apply the config kept in --config-cache-dir at startup. Disable for deployments where only the controller may configure the dataplane"
+    )]
+    config_replay: bool,
+
+    #[arg(
+        long,
+        value_name = "NAME",
+        value_parser = parse_interface_name,
+        value_delimiter = ',',
+        help = "Kernel interface the dataplane must never touch, like the management NIC or a port shared with the IPMI: it is not observed, and never updated nor removed, even if a config requires it. May be repeated
+Note: multiple interfaces can be specified separated by commas and no spaces"
+    )]
+    unmanaged_interface: Vec<InterfaceName>,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        default_value_t = DEFAULT_STATE_STORE_PATH.to_string(),
+        help = "Embedded store persisting the state learned at runtime, like the route table ids allocated to VPCs, across restarts"
+    )]
+    state_store: String,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        default_value_t = DEFAULT_CRASH_DIR.to_string(),
+        help = "Directory where a report is written when the dataplane crashes, kept across restarts"
+    )]
+    crash_dir: String,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        default_value_t = DEFAULT_CAPTURE_DIR.to_string(),
+        help = "Directory where the packet captures started from the CLI are written, or streamed to sockets. Captures are never written elsewhere"
+    )]
+    capture_dir: String,
+
+    #[arg(
+        long,
+        value_name = "BOOL",
+        default_value_t = true,
+        action = clap::ArgAction::Set,
+        help = "Enable the FTP application layer gateway of masquerading (rewrites PORT/EPRT commands and PASV/EPSV replies)"
+    )]
+    nat_alg_ftp: bool,
+
+    #[arg(
+        long,
+        value_name = "BOOL",
+        default_value_t = false,
+        action = clap::ArgAction::Set,
+        help = "Enable the SIP application layer gateway of masquerading (rewrites the addresses of SIP signalling over UDP)"
+    )]
+    nat_alg_sip: bool,
+
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 0,
+        help = "Lease the binding of masquerading of each inside address (external address and port block) for this many seconds, renewed while in use, and persist it in --state-store so that hosts keep their external address across restarts. 0 disables the leases"
+    )]
+    nat_lease_duration: u64,
+
+    #[arg(
+        long,
+        value_name = "forward|punt|drop",
+        default_value_t = DestinationAction::Punt,
+        value_parser = DestinationAction::from_str,
+        help = "Action of the pipeline on the packets for link-local destinations (169.254.0.0/16, fe80::/10), which must not be routed"
+    )]
+    link_local_policy: DestinationAction,
+
+    #[arg(
+        long,
+        value_name = "forward|punt|drop",
+        default_value_t = DestinationAction::Punt,
+        value_parser = DestinationAction::from_str,
+        help = "Action of the pipeline on the packets for multicast destinations (224.0.0.0/4, ff00::/8), which it does not route"
+    )]
+    multicast_policy: DestinationAction,
+
+    #[arg(
+        long,
+        value_name = "SRC_VNI:SRC_IP=DST_VNI:DST_IP",
+        value_parser = OamProbeArg::from_str,
+        help = "Verify the path between endpoints of two VPCs with probes originated by the gateway, from SRC_IP in the VPC of SRC_VNI to DST_IP in the VPC of DST_VNI and back. May be repeated"
+    )]
+    oam_probe: Vec<OamProbeArg>,
+
+    /// Period of the OAM probes.
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 5,
+        value_parser = clap::value_parser!(u64).range(1..=3600),
+        help = "Send a probe on each of the paths given with --oam-probe every this many seconds. A path is down if its last probe did not return within this period"
+    )]
+    oam_interval: u64,
+
+    #[arg(
+        long,
+        value_name = "NAME=on|off",
+        value_parser = FeatureFlagArg::from_str,
+        value_delimiter = ',',
+        help = "Turn a feature flag on or off, overriding its default. Flags which can change at runtime can be overridden from the cli.
+Example:
+   --feature-flag reconcile-offloads=off
+Note: multiple flags can be specified separated by commas and no spaces"
+    )]
+    feature_flag: Vec<FeatureFlagArg>,
+
+    #[arg(
+        long,
+        default_value_t = false,
+        help = "Check the interfaces and build the launch configuration, print it as YAML and exit without starting the dataplane"
+    )]
+    validate: bool,
+
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Unix socket to receive later generations of the launch configuration on, to change its tracing, metrics address, cli socket and feature flags at runtime. Its directory must only be writable by the user of the dataplane, and generations are only accepted from root or that user"
+    )]
+    generation_socket: Option<String>,
+
+    /// Enable BMP server
+    #[arg(long, default_value_t = false, help = "Enable BMP server")]
+    bmp_enable: bool,
+
+    #[arg(
+        long,
+        value_name = "IP:PORT",
+        default_value_t = SocketAddr::from(([0, 0, 0, 0], 5000)),
+        help = "Bind address for the BMP server"
+    )]
+    bmp_address: SocketAddr,
+
+    #[arg(
+        long,
+        value_name = "MILLISECONDS",
+        default_value_t = 10_000,
+        help = "BMP periodic interval for housekeeping/flush (ms)"
+    )]
+    bmp_interval: u64,
+
+    #[arg(
+        long,
+        value_name = "COUNT",
+        help = "Keep up to this many recent BMP route monitoring messages, for the cli to dump them as MRT or JSON. None are kept unless this or --bmp-store-bytes is set"
+    )]
+    bmp_store_messages: Option<u32>,
+
+    #[arg(
+        long,
+        value_name = "BYTES",
+        help = "Keep up to this many bytes of recent BMP route monitoring messages, for the cli to dump them as MRT or JSON. None are kept unless this or --bmp-store-messages is set"
+    )]
+    bmp_store_bytes: Option<u64>,
+}
+
+impl CmdArgs {
+    /// Parse the command line, completed with the settings of the config file it gives with
+    /// `--config-file`, if any (see [`Self::try_parse_with_config_file_from`]).
+    ///
+    /// Exits with a usage error, as [`Parser::parse`] does, if either is invalid.
+    #[must_use]
+    pub fn parse_with_config_file() -> Self {
+        Self::try_parse_with_config_file_from(std::env::args_os()).unwrap_or_else(|e| e.exit())
+    }
+
+    /// Parse a command line, completed with the settings of the config file it gives with
+    /// `--config-file`, if any.
+    ///
+    /// A setting is taken, in order of precedence, from:
+    ///
+    /// 1. the flags of the command line
+    /// 2. the config file
+    /// 3. the default of the flag
+    ///
+    /// Flags taking several values (e.g. `--interface`) are not merged: those of the command line
+    /// replace those of the config file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the command line or the config file is invalid, or if the config file
+    /// can't be read.
+    pub fn try_parse_with_config_file_from<I, T>(argv: I) -> Result<Self, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let argv: Vec<OsString> = argv.into_iter().map(Into::into).collect();
+        let command = Self::command();
+        let matches = command.clone().try_get_matches_from(&argv)?;
+        let Some(path) = matches.get_one::<String>("config_file") else {
+            return Self::from_arg_matches(&matches);
+        };
+        let file_args = config_file::file_args(&command, Path::new(path), &matches)
+            .map_err(|e| Self::command().error(clap::error::ErrorKind::InvalidValue, e))?;
+        let (program, flags) = argv.split_at(1.min(argv.len()));
+        Self::try_parse_from(program.iter().chain(&file_args).chain(flags))
+    }
+
+    /// Get the configured driver name.
+    ///
+    /// Returns `"dpdk"` if no driver was explicitly specified (the default),
+    /// otherwise returns the specified driver name (`"dpdk"`, `"kernel"` or `"af_xdp"`).
+    #[must_use]
+    pub fn driver_name(&self) -> &str {
+        match &self.driver {
+            None => "dpdk",
+            Some(name) => name,
+        }
+    }
+
+    /// Check if the `--show-tracing-tags` flag was set.
+    ///
+    /// When true, the application should display available tracing tags and exit.
+    ///
+    /// # Returns
+    ///
+    /// `true` if `--show-tracing-tags` was passed, `false` otherwise.
     #[must_use]
     pub fn show_tracing_tags(&self) -> bool {
         self.show_tracing_tags
@@ -1443,6 +2776,158 @@ impl CmdArgs {
         self.num_workers.into()
     }
 
+    /// Get the number of descriptors of each ring of the AF_XDP sockets.
+    ///
+    /// This value comes from the `--xdp-ring-size` argument (default: 2048).
+    #[must_use]
+    pub fn xdp_ring_size(&self) -> u32 {
+        self.xdp_ring_size
+    }
+
+    /// Check if the `--xdp-force-copy` flag was set.
+    ///
+    /// When true, the AF_XDP driver binds its sockets in copy mode, even on NICs supporting
+    /// zero-copy.
+    #[must_use]
+    pub fn xdp_force_copy(&self) -> bool {
+        self.xdp_force_copy
+    }
+
+    /// Check that no interface is given more queues than there are workers: each worker of the
+    /// kernel and AF_XDP drivers serves a queue of every interface.
+    fn check_worker_queues(&self) -> Result<(), InvalidCmdArguments> {
+        let workers = self.kernel_num_workers();
+        for nic in self.interfaces() {
+            let queues = nic.queues.rx.max(nic.queues.tx).unwrap_or(1);
+            if usize::from(queues) > workers {
+                return Err(InvalidCmdArguments::TooManyQueues(
+                    nic.interface,
+                    queues,
+                    workers,
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Get the time after which a worker stuck on a batch is reported stalled.
+    ///
+    /// This value comes from the `--worker-stall-timeout` argument (default: 5 seconds).
+    #[must_use]
+    pub fn worker_stall_timeout(&self) -> Duration {
+        Duration::from_secs(self.worker_stall_timeout)
+    }
+
+    /// Get the bounds of the batches of packets received by the drivers.
+    ///
+    /// This value comes from the `--rx-batch-size` argument (default: 16:128).
+    #[must_use]
+    pub fn rx_batch_size(&self) -> BatchSize {
+        self.rx_batch_size
+    }
+
+    /// Get the directory where to store the stacks of stalled workers.
+    ///
+    /// Stacks are not captured unless a directory is given.
+    #[must_use]
+    pub fn worker_stall_profile_dir(&self) -> Option<&str> {
+        self.worker_stall_profile_dir.as_deref()
+    }
+
+    /// Check if the `--kernel-prefilter` flag was set.
+    ///
+    /// When true, the kernel driver attaches an XDP program to its interfaces, dropping the frames
+    /// from spoofed sources and from sources denied by the ACLs.
+    #[must_use]
+    pub fn kernel_prefilter(&self) -> bool {
+        self.kernel_prefilter
+    }
+
+    /// Check if the `--kernel-rx-ring` flag was set.
+    ///
+    /// When true, the kernel driver receives the frames of its interfaces through `TPACKET_V3`
+    /// rings, which tell the RX hash of the frames, rather than with `recv`.
+    #[must_use]
+    pub fn kernel_rx_ring(&self) -> bool {
+        self.kernel_rx_ring
+    }
+
+    /// Get the lcores of the DPDK driver, from the `--eal-lcores` argument.
+    ///
+    /// Empty if not given, leaving the choice to the EAL.
+    #[must_use]
+    pub fn eal_lcores(&self) -> Vec<u16> {
+        self.eal_lcores
+            .as_ref()
+            .map(|lcores| lcores.0.clone())
+            .unwrap_or_default()
+    }
+
+    /// Get the number of memory channels of the DPDK driver, from the `--eal-memory-channels`
+    /// argument.
+    #[must_use]
+    pub fn eal_memory_channels(&self) -> Option<u8> {
+        self.eal_memory_channels
+    }
+
+    /// Get the hugepage directory of the DPDK driver, from the `--eal-huge-dir` argument.
+    #[must_use]
+    pub fn eal_huge_dir(&self) -> Option<&str> {
+        self.eal_huge_dir.as_deref()
+    }
+
+    /// Get the size in MB of the buffer pool of each device of the DPDK driver, from the
+    /// `--eal-pool-size` argument.
+    ///
+    /// If not given, the EAL reserves hugepage memory with its defaults.
+    #[must_use]
+    pub fn eal_pool_size(&self) -> Option<u32> {
+        self.eal_pool_size
+    }
+
+    /// Get the hugepage memory in MB of the DPDK driver on each NUMA node, from the
+    /// `--eal-socket-mem` argument.
+    ///
+    /// Empty if not given, leaving it to the pools planned from `--eal-pool-size`, if any.
+    #[must_use]
+    pub fn eal_socket_mem(&self) -> Vec<u32> {
+        self.eal_socket_mem
+            .as_ref()
+            .map(|mb| mb.0.clone())
+            .unwrap_or_default()
+    }
+
+    /// Get the IOVA mode of the DPDK driver, from the `--eal-iova-mode` argument.
+    #[must_use]
+    pub fn eal_iova_mode(&self) -> Option<IovaMode> {
+        self.eal_iova_mode
+    }
+
+    /// Get the device arguments of the DPDK driver, from the `--eal-devargs` arguments.
+    pub fn eal_devargs(&self) -> impl Iterator<Item = &DevargsArg> {
+        self.eal_devargs.iter()
+    }
+
+    /// Resolve the filters of the `--eal-device-match` arguments against the PCI devices of
+    /// this host, skipping the devices of the interfaces.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the devices can't be listed, or a filter does not match enough.
+    pub fn eal_device_matches(&self) -> Result<Vec<DeviceMatch>, DeviceSearchError> {
+        if self.eal_device_match.is_empty() {
+            return Ok(vec![]);
+        }
+        let taken = self
+            .interfaces()
+            .filter_map(|nic| match nic.port {
+                Some(PortArg::PCI(address)) => Some(address),
+                _ => None,
+            })
+            .collect();
+        DeviceSearch::scan()?.resolve(&self.eal_device_match, &taken)
+    }
+
     /// Get the list of kernel network interfaces to use.
     ///
     /// Returns the interfaces specified via `--interface` arguments.
@@ -1453,7 +2938,7 @@ impl CmdArgs {
     ///
     /// # Note
     ///
-    /// This is only used with the kernel driver.
+    /// This is only used with the kernel and AF_XDP drivers.
     #[must_use]
     pub fn kernel_interfaces(&self) -> Vec<String> {
         self.interface
@@ -1483,6 +2968,15 @@ impl CmdArgs {
         self.cli_sock_path.clone()
     }
 
+    /// Get the file with the views of the dataplane offered to CLI sessions.
+    ///
+    /// CLI sessions presenting a token or sent by a user listed in a view only see the VPCs
+    /// of that view. Without such a file, CLI sessions are never restricted.
+    #[must_use]
+    pub fn cli_views(&self) -> Option<&str> {
+        self.cli_views.as_deref()
+    }
+
     /// Get the FRR agent socket path.
     ///
     /// Returns the path to connect to the FRR agent that controls FRR configuration reloads.
@@ -1491,6 +2985,16 @@ impl CmdArgs {
         self.frr_agent_path.clone()
     }
 
+    /// Get the grace period of FRR and dataplane restarts.
+    ///
+    /// This value comes from the `--frr-grace-period` argument (default: 60 seconds). Routes
+    /// which FRR doesn't push again within it, once it restarts or is requested a refresh, are
+    /// removed.
+    #[must_use]
+    pub fn frr_grace_period(&self) -> Duration {
+        Duration::from_secs(self.frr_grace_period)
+    }
+
     /// Get the Prometheus metrics HTTP endpoint address.
     ///
     /// Returns the socket address (IP and port) where the dataplane exposes
@@ -1500,6 +3004,33 @@ impl CmdArgs {
         self.metrics_address
     }
 
+    /// Get the file holding the bearer token of the looking glass API.
+    ///
+    /// The looking glass is disabled unless a token file is given.
+    #[must_use]
+    pub fn looking_glass_token_file(&self) -> Option<&str> {
+        self.looking_glass_token_file.as_deref()
+    }
+
+    /// Get the URL of the OpenTelemetry collector to export traces and metrics to.
+    ///
+    /// Nothing is exported unless a collector is given.
+    #[must_use]
+    pub fn otlp_endpoint(&self) -> Option<&url::Url> {
+        self.otlp_endpoint.as_ref()
+    }
+
+    /// Get the headers to send along with the OTLP exports.
+    pub fn otlp_headers(&self) -> impl Iterator<Item = &OtlpHeader> {
+        self.otlp_header.iter()
+    }
+
+    /// Get the ratio of the traces exported with OTLP.
+    #[must_use]
+    pub fn otlp_sampling_ratio(&self) -> SamplingRatio {
+        self.otlp_sampling_ratio
+    }
+
     #[must_use]
     pub fn pyroscope_url(&self) -> Option<&url::Url> {
         self.pyroscope_url.as_ref()
@@ -1522,6 +3053,16 @@ impl CmdArgs {
     pub fn bmp_interval(&self) -> Duration {
         Duration::from_millis(self.bmp_interval)
     }
+    /// Get the max number of recent BMP route monitoring messages kept, if set.
+    #[must_use]
+    pub fn bmp_store_messages(&self) -> Option<u32> {
+        self.bmp_store_messages
+    }
+    /// Get the max size in bytes of the recent BMP route monitoring messages kept, if set.
+    #[must_use]
+    pub fn bmp_store_bytes(&self) -> Option<u64> {
+        self.bmp_store_bytes
+    }
 
     /// Get the configuration directory.
     /// Setting the configuration directory enables k8s-less mode, where configurations are retrieved from files
@@ -1530,14 +3071,189 @@ impl CmdArgs {
     pub fn config_dir(&self) -> Option<&String> {
         self.config_dir.as_ref()
     }
+
+    /// Get the range of route table ids allocated to VPCs.
+    #[must_use]
+    pub fn route_table_range(&self) -> RouteTableRange {
+        self.route_table_range.unwrap_or(RouteTableRange::DEFAULT)
+    }
+
+    /// Get the address of the gRPC endpoints streaming the events of the dataplane, rolling back
+    /// its configuration and reporting its health.
+    ///
+    /// The endpoints are not served unless an address is given.
+    #[must_use]
+    pub fn grpc_address(&self) -> Option<SocketAddr> {
+        self.grpc_address
+    }
+
+    /// Get the address of the read-only gRPC endpoints of the dataplane, granted to monitoring
+    /// systems.
+    ///
+    /// The endpoints are not served unless an address is given.
+    #[must_use]
+    pub fn grpc_observer_address(&self) -> Option<SocketAddr> {
+        self.grpc_observer_address
+    }
+
+    /// Get the file holding the bearer token required to change the dataplane over gRPC.
+    ///
+    /// The methods changing the dataplane are denied unless a token is given.
+    #[must_use]
+    pub fn grpc_token_file(&self) -> Option<&str> {
+        self.grpc_token_file.as_deref()
+    }
+
+    /// Get the directory where the config applied last is kept, if any.
+    #[must_use]
+    pub fn config_cache_dir(&self) -> Option<&str> {
+        self.config_cache_dir.as_deref()
+    }
+
+    /// Check if the config kept in the config cache is applied at startup.
+    #[must_use]
+    pub fn config_replay(&self) -> bool {
+        self.config_replay
+    }
+
+    /// Get the kernel interfaces the dataplane must never touch.
+    pub fn unmanaged_interfaces(&self) -> impl Iterator<Item = &InterfaceName> {
+        self.unmanaged_interface.iter()
+    }
+
+    /// Get the path of the embedded store persisting the state learned at runtime.
+    #[must_use]
+    pub fn state_store(&self) -> String {
+        self.state_store.clone()
+    }
+
+    /// Get the directory where crash reports are written.
+    #[must_use]
+    pub fn crash_dir(&self) -> String {
+        self.crash_dir.clone()
+    }
+
+    /// Get the directory where packet captures are written.
+    #[must_use]
+    pub fn capture_dir(&self) -> String {
+        self.capture_dir.clone()
+    }
+
+    /// Check if the FTP application layer gateway of masquerading is enabled.
+    #[must_use]
+    pub fn nat_alg_ftp(&self) -> bool {
+        self.nat_alg_ftp
+    }
+
+    /// Check if the SIP application layer gateway of masquerading is enabled.
+    #[must_use]
+    pub fn nat_alg_sip(&self) -> bool {
+        self.nat_alg_sip
+    }
+
+    /// Get the duration of the leases of the bindings of masquerading, if enabled.
+    ///
+    /// This value comes from the `--nat-lease-duration` argument (default: 0, disabled).
+    #[must_use]
+    pub fn nat_lease_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs(self.nat_lease_duration)).filter(|duration| !duration.is_zero())
+    }
+
+    /// Get the action of the pipeline on the packets for link-local destinations.
+    #[must_use]
+    pub fn link_local_policy(&self) -> DestinationAction {
+        self.link_local_policy
+    }
+
+    /// Get the action of the pipeline on the packets for multicast destinations.
+    #[must_use]
+    pub fn multicast_policy(&self) -> DestinationAction {
+        self.multicast_policy
+    }
+
+    /// Get the paths verified with OAM probes, given with `--oam-probe`.
+    pub fn oam_probes(&self) -> impl Iterator<Item = &OamProbeArg> {
+        self.oam_probe.iter()
+    }
+
+    /// Get the period of the OAM probes.
+    #[must_use]
+    pub fn oam_interval(&self) -> Duration {
+        Duration::from_secs(self.oam_interval)
+    }
+
+    /// Get the values of the feature flags given with `--feature-flag`.
+    pub fn feature_flags(&self) -> impl Iterator<Item = &FeatureFlagArg> {
+        self.feature_flag.iter()
+    }
+
+    /// Get the unix socket to receive later generations of the launch configuration on.
+    ///
+    /// The launch configuration can't be changed at runtime unless one is given.
+    #[must_use]
+    pub fn generation_socket(&self) -> Option<&str> {
+        self.generation_socket.as_deref()
+    }
+
+    /// Check if the launch of the dataplane must only be validated (`--validate`).
+    #[must_use]
+    pub fn validate_only(&self) -> bool {
+        self.validate
+    }
+
+    /// Validate the launch of the dataplane without starting it.
+    ///
+    /// Checks that the ports of the interfaces exist on this host, builds the
+    /// [`LaunchConfiguration`] and goes through its handoff with
+    /// [`LaunchConfiguration::dry_run`].
+    ///
+    /// Returns the launch configuration as YAML, followed by its integrity check and the
+    /// drivers the ports are bound to, as comments.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a port does not exist, the arguments are invalid, or the launch
+    /// configuration does not survive its handoff.
+    pub fn dry_run(self) -> Result<String, miette::Report> {
+        let bindings = self
+            .interfaces()
+            .map(|nic| port_binding(&nic))
+            .collect::<Result<Vec<_>, _>>()?;
+        let config = LaunchConfiguration::try_from(self)?;
+        let (config, check) = config.dry_run()?;
+        let mut out = serde_yaml_ng::to_string(&config)
+            .into_diagnostic()
+            .wrap_err("failed to render launch configuration")?;
+        out += &format!("# integrity check (sha384): {check}\n");
+        for binding in bindings {
+            out += &format!("# {binding}\n");
+        }
+        if let DriverConfigSection::Dpdk(dpdk) = &config.driver {
+            for matched in &dpdk.matched {
+                out += &format!("# {matched}\n");
+            }
+            for shortage in &dpdk.hugepage_shortages {
+                out += &format!("# warning: {shortage}\n");
+            }
+        }
+        Ok(out)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use net::interface::InterfaceName;
 
-    use super::TracingRateLimit;
-    use crate::{InterfaceArg, PortArg};
+    use super::{
+        BatchSize, FeatureFlagArg, OamProbeArg, OtlpHeader, RouteTableRange, SamplingRatio,
+        TracingRateLimit, port_binding,
+    };
+    use crate::{
+        CmdArgs, FinalizedMemFile, INTEGRITY_CHECK_BYTE_LEN, InterfaceArg, InvalidCmdArguments,
+        LaunchConfiguration, MemFdError, MemFile, Parser, PortArg, PortQueues,
+    };
+    use std::net::Ipv4Addr;
+    use std::os::fd::OwnedFd;
     use std::str::FromStr;
 
     #[test]
@@ -1576,6 +3292,96 @@ mod tests {
         // bad discriminant
         assert!(InterfaceArg::from_str("GbEth1.9000=foo@0000:02:01.7").is_err());
     }
+
+    #[test]
+    fn test_parse_interface_queues() {
+        let spec = InterfaceArg::from_str("eth0=pci@0000:01:00.0;rxq=4;txq=2;cores=2-5:8").unwrap();
+        assert_eq!(spec.interface.as_ref(), "eth0");
+        assert_eq!(
+            spec.queues,
+            PortQueues {
+                rx: Some(4),
+                tx: Some(2),
+                cores: vec![2, 3, 4, 5, 8],
+            }
+        );
+
+        let spec = InterfaceArg::from_str("eth1=kernel@enp2s1;cores=3").unwrap();
+        assert_eq!(spec.queues.rx, None);
+        assert_eq!(spec.queues.cores, vec![3]);
+
+        let spec = InterfaceArg::from_str("eth1=kernel@enp2s1").unwrap();
+        assert_eq!(spec.queues, PortQueues::default());
+
+        for bad in [
+            "eth0=pci@0000:01:00.0;rxq=0",
+            "eth0=pci@0000:01:00.0;rxq=4;rxq=2",
+            "eth0=pci@0000:01:00.0;rxq",
+            "eth0=pci@0000:01:00.0;queues=4",
+            "eth0=pci@0000:01:00.0;cores=5-2",
+            "eth0=pci@0000:01:00.0;",
+        ] {
+            assert!(InterfaceArg::from_str(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn interface_queues_within_kernel_workers() {
+        let parse = |interface| {
+            let args = CmdArgs::try_parse_from([
+                "dataplane",
+                "--driver",
+                "kernel",
+                "--num-workers",
+                "2",
+                "--interface",
+                interface,
+            ])
+            .unwrap();
+            LaunchConfiguration::try_from(args)
+        };
+        assert!(parse("eth0=kernel@lo;rxq=2;txq=2").is_ok());
+        assert!(matches!(
+            parse("eth0=kernel@lo;rxq=4"),
+            Err(InvalidCmdArguments::TooManyQueues(_, 4, 2))
+        ));
+    }
+    #[test]
+    fn af_xdp_launch_configuration() {
+        let args = CmdArgs::try_parse_from([
+            "dataplane",
+            "--driver",
+            "af_xdp",
+            "--num-workers",
+            "2",
+            "--interface",
+            "eth0=kernel@lo;rxq=2",
+            "--xdp-ring-size",
+            "1024",
+        ])
+        .unwrap();
+        let config = LaunchConfiguration::try_from(args).unwrap();
+        let DriverConfigSection::AfXdp(af_xdp) = config.driver else {
+            panic!("Unexpected driver {:?}", config.driver);
+        };
+        assert_eq!(af_xdp.ring_size, 1024);
+        assert!(!af_xdp.force_copy);
+        assert_eq!(af_xdp.batch_size, BatchSize { min: 16, max: 128 });
+
+        for bad in ["1000", "32", "32768"] {
+            let args = ["dataplane", "--driver", "af_xdp", "--xdp-ring-size", bad];
+            assert!(CmdArgs::try_parse_from(args).is_err(), "{bad}");
+        }
+    }
+    #[test]
+    fn batch_size_parses() {
+        let batch = BatchSize::from_str("8:256").unwrap();
+        assert_eq!(batch, BatchSize { min: 8, max: 256 });
+        assert!(BatchSize::from_str("32:32").is_ok());
+        for bad in ["32", "0:32", "64:32", "16:2048", "a:32"] {
+            assert!(BatchSize::from_str(bad).is_err(), "{bad}");
+        }
+    }
     #[test]
     fn tracing_rate_limit_parses_valid_values() {
         let rate_limit = TracingRateLimit::from_str("10:20").unwrap();
@@ -1601,4 +3407,178 @@ mod tests {
         let err = TracingRateLimit::from_str("10:0").unwrap_err();
         assert_eq!(err, "Replenish-per-second must be greater than 0");
     }
+    #[test]
+    fn launch_configuration_dry_run() {
+        let args = CmdArgs::try_parse_from([
+            "dataplane",
+            "--driver",
+            "kernel",
+            "--interface",
+            "eth0=kernel@lo",
+            "--validate",
+        ])
+        .unwrap();
+        assert!(args.validate_only());
+        let config = LaunchConfiguration::try_from(args).unwrap();
+        let expected = format!("{config:?}");
+        let (recovered, check) = config.dry_run().unwrap();
+        assert_eq!(format!("{recovered:?}"), expected);
+        assert_eq!(check.to_string().len(), 2 * INTEGRITY_CHECK_BYTE_LEN);
+    }
+
+    #[test]
+    fn port_binding_of_missing_port() {
+        let nic = InterfaceArg::from_str("eth0=kernel@nosuchif0").unwrap();
+        assert!(matches!(
+            port_binding(&nic),
+            Err(InvalidCmdArguments::NoSuchPort(..))
+        ));
+        let nic = InterfaceArg::from_str("eth0").unwrap();
+        assert!(port_binding(&nic).is_ok());
+    }
+
+    #[test]
+    fn feature_flag_parses() {
+        let flag = FeatureFlagArg::from_str("new-fib=on").unwrap();
+        assert_eq!(flag.name, "new-fib");
+        assert!(flag.enabled);
+        assert!(!FeatureFlagArg::from_str("new-fib=off").unwrap().enabled);
+        for bad in ["new-fib", "new-fib=yes", "=on"] {
+            assert!(FeatureFlagArg::from_str(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn oam_probe_parses() {
+        let probe = OamProbeArg::from_str("100:10.0.0.1=200:10.1.0.1").unwrap();
+        assert_eq!(probe.src_vni.as_u32(), 100);
+        assert_eq!(probe.src, Ipv4Addr::new(10, 0, 0, 1));
+        assert_eq!(probe.dst_vni.as_u32(), 200);
+        assert_eq!(probe.dst, Ipv4Addr::new(10, 1, 0, 1));
+        assert_eq!(probe.to_string(), "100:10.0.0.1=200:10.1.0.1");
+        for bad in [
+            "100:10.0.0.1",
+            "100:10.0.0.1=200",
+            "0:10.0.0.1=200:10.1.0.1",
+            "100:10.0.0.1=200:2001:db8::1",
+            "100:10.0.0.1=100:10.0.0.2",
+        ] {
+            assert!(OamProbeArg::from_str(bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn otlp_arguments_parse() {
+        let header = OtlpHeader::from_str("Authorization=Bearer a=b").unwrap();
+        assert_eq!(header.name, "Authorization");
+        assert_eq!(header.value, "Bearer a=b");
+        for bad in ["Authorization", "=token", "bad name=token", "x-token=a\nb"] {
+            assert!(OtlpHeader::from_str(bad).is_err(), "{bad}");
+        }
+
+        assert_eq!(SamplingRatio::from_str("1"), Ok(SamplingRatio::ALWAYS));
+        let ratio = SamplingRatio::from_str("0.25").unwrap();
+        assert!((ratio.as_f64() - 0.25).abs() < f64::EPSILON);
+        for bad in ["-0.1", "1.5", "NaN", "half"] {
+            assert!(SamplingRatio::from_str(bad).is_err(), "{bad}");
+        }
+
+        let args = CmdArgs::try_parse_from([
+            "dataplane",
+            "--driver",
+            "kernel",
+            "--interface",
+            "eth0",
+            "--otlp-endpoint",
+            "http://127.0.0.1:4318",
+            "--otlp-header",
+            "x-tenant=gw1",
+            "--otlp-sampling-ratio",
+            "0.5",
+        ])
+        .unwrap();
+        let config = LaunchConfiguration::try_from(args).unwrap();
+        let otlp = config.otlp.unwrap();
+        assert_eq!(otlp.endpoint, "http://127.0.0.1:4318/");
+        assert_eq!(otlp.headers.len(), 1);
+        assert_eq!(otlp.sampling_ratio.to_string(), "0.5");
+
+        let args = ["dataplane", "--otlp-header", "x-tenant=gw1"];
+        assert!(CmdArgs::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn unmanaged_interfaces_parse() {
+        let args = CmdArgs::try_parse_from([
+            "dataplane",
+            "--driver",
+            "kernel",
+            "--interface",
+            "eth0",
+            "--unmanaged-interface",
+            "mgmt0,ipmi0",
+            "--unmanaged-interface",
+            "eno1",
+        ])
+        .unwrap();
+        let config = LaunchConfiguration::try_from(args).unwrap();
+        let unmanaged: Vec<_> = config
+            .config_server
+            .unwrap()
+            .unmanaged_interfaces
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(unmanaged, ["mgmt0", "ipmi0", "eno1"]);
+
+        let args = [
+            "dataplane",
+            "--unmanaged-interface",
+            "way-too-long-interface",
+        ];
+        assert!(CmdArgs::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn route_table_range_parses() {
+        let range = RouteTableRange::from_str("1000-1999").unwrap();
+        assert_eq!((range.first, range.last), (1000, 1999));
+        assert!(RouteTableRange::from_str("1000").is_err());
+        assert!(RouteTableRange::from_str("2000-1000").is_err());
+        assert!(RouteTableRange::from_str("0-100").is_err());
+        assert!(RouteTableRange::from_str("100-253").is_err());
+        assert!(RouteTableRange::from_str("255-300").is_err());
+        assert!(RouteTableRange::from_str("256-300").is_ok());
+    }
+
+    #[test]
+    fn destination_action_parses() {
+        for action in [
+            DestinationAction::Forward,
+            DestinationAction::Punt,
+            DestinationAction::Drop,
+        ] {
+            assert_eq!(DestinationAction::from_str(&action.to_string()), Ok(action));
+        }
+        assert!(DestinationAction::from_str("reject").is_err());
+    }
+
+    #[test]
+    #[allow(unsafe_code)] // the file descriptors are owned by the test
+    fn try_from_fd_checks_memfd() {
+        let unsealed = MemFile::try_new().unwrap();
+        let fd = OwnedFd::from(std::fs::File::from(unsealed));
+        let r = unsafe { FinalizedMemFile::try_from_fd(fd) };
+        assert!(matches!(r, Err(MemFdError::NotReadOnly { .. })));
+
+        let path = std::env::temp_dir().join(format!("not-a-memfd-{}", std::process::id()));
+        let file = std::fs::File::create(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let r = unsafe { FinalizedMemFile::try_from_fd(OwnedFd::from(file)) };
+        assert!(matches!(r, Err(MemFdError::NotMemFd(_))));
+
+        let sealed = MemFile::try_new().unwrap().try_finalize().unwrap();
+        let r = unsafe { FinalizedMemFile::try_from_fd(sealed.to_owned_fd()) };
+        assert!(r.is_ok());
+    }
 }
@@ -0,0 +1,321 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Optional Ed25519 signature of the launch configuration.
+//!
+//! The [`IntegrityCheck`] handed over with the launch configuration detects corruption, but a
+//! process able to replace both memfds (e.g. another process of the same user) can forge it. The
+//! launcher of the dataplane may sign the serialized configuration with a signing key, and pass
+//! the signature in a sealed memfd at
+//! [`LaunchConfiguration::STANDARD_SIGNATURE_FD`](crate::LaunchConfiguration::STANDARD_SIGNATURE_FD).
+//! When a verifying key is configured, [`LaunchConfiguration::inherit()`] refuses any configuration
+//! whose signature doesn't match, before deserializing it, and so does the listener of later
+//! generations (see [`generation`](crate::generation)) with the same key. Without keys, only the
+//! integrity check is used.
+//!
+//! `dataplane-init` does not sign the configuration yet: verifying keys must not be configured
+//! until it does.
+//!
+//! Keys are 32 bytes, hex encoded, given in an environment variable or in a file whose path is
+//! given in an environment variable:
+//!
+//! - signing key (the secret seed, for the launcher): [`SIGNING_KEY_ENV`] or
+//!   [`SIGNING_KEY_FILE_ENV`]
+//! - verifying key (the public key, for the dataplane): [`VERIFYING_KEY_ENV`] or
+//!   [`VERIFYING_KEY_FILE_ENV`]
+//!
+//! [`IntegrityCheck`]: crate::IntegrityCheck
+//! [`LaunchConfiguration::inherit()`]: crate::LaunchConfiguration::inherit
+
+use crate::{AsFinalizedMemFile, FinalizedMemFile, MemFile};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use miette::{Context, IntoDiagnostic};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Environment variable with the hex encoded signing key
+pub const SIGNING_KEY_ENV: &str = "DATAPLANE_LAUNCH_SIGNING_KEY";
+
+/// Environment variable with the path of a file holding the hex encoded signing key
+pub const SIGNING_KEY_FILE_ENV: &str = "DATAPLANE_LAUNCH_SIGNING_KEY_FILE";
+
+/// Environment variable with the hex encoded verifying key
+pub const VERIFYING_KEY_ENV: &str = "DATAPLANE_LAUNCH_VERIFYING_KEY";
+
+/// Environment variable with the path of a file holding the hex encoded verifying key
+pub const VERIFYING_KEY_FILE_ENV: &str = "DATAPLANE_LAUNCH_VERIFYING_KEY_FILE";
+
+/// Size of an Ed25519 key in bytes
+const KEY_BYTE_LEN: usize = ed25519_dalek::SECRET_KEY_LENGTH;
+
+/// Size of an Ed25519 signature in bytes
+pub const SIGNATURE_BYTE_LEN: usize = ed25519_dalek::SIGNATURE_LENGTH;
+
+/// Errors loading keys, signing or verifying the launch configuration
+#[derive(Debug, thiserror::Error, miette::Diagnostic)]
+pub enum LaunchSignatureError {
+    #[error("Invalid {0} key: expected {KEY_BYTE_LEN} hex encoded bytes")]
+    InvalidKey(&'static str),
+    #[error("Failed to read key file {0}: {1}")]
+    KeyFile(String, std::io::Error),
+    #[error("Wrong signature file length; received {0} bytes, expected {SIGNATURE_BYTE_LEN} bytes")]
+    WrongSignatureLength(u64),
+    #[error("The signature of the launch configuration is invalid")]
+    InvalidSignature,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// Decode a hex encoded key, ignoring surrounding whitespace
+fn decode_key(hex: &str, kind: &'static str) -> Result<[u8; KEY_BYTE_LEN], LaunchSignatureError> {
+    let hex = hex.trim().as_bytes();
+    let invalid = || LaunchSignatureError::InvalidKey(kind);
+    if hex.len() != 2 * KEY_BYTE_LEN || !hex.iter().all(u8::is_ascii_hexdigit) {
+        return Err(invalid());
+    }
+    let mut key = [0; KEY_BYTE_LEN];
+    for (byte, digits) in key.iter_mut().zip(hex.chunks_exact(2)) {
+        let digits = std::str::from_utf8(digits).map_err(|_| invalid())?;
+        *byte = u8::from_str_radix(digits, 16).map_err(|_| invalid())?;
+    }
+    Ok(key)
+}
+
+/// Get a hex encoded key from the value of an environment variable, or from the file whose path
+/// is the value of another. The value takes precedence.
+fn key_from_sources(
+    value: Option<String>,
+    file: Option<&Path>,
+    kind: &'static str,
+) -> Result<Option<[u8; KEY_BYTE_LEN]>, LaunchSignatureError> {
+    if let Some(value) = value {
+        return decode_key(&value, kind).map(Some);
+    }
+    let Some(file) = file else {
+        return Ok(None);
+    };
+    let hex = std::fs::read_to_string(file)
+        .map_err(|e| LaunchSignatureError::KeyFile(file.display().to_string(), e))?;
+    decode_key(&hex, kind).map(Some)
+}
+
+fn key_from_env(
+    env: &str,
+    file_env: &str,
+    kind: &'static str,
+) -> Result<Option<[u8; KEY_BYTE_LEN]>, LaunchSignatureError> {
+    let file = std::env::var_os(file_env);
+    key_from_sources(
+        std::env::var(env).ok(),
+        file.as_deref().map(Path::new),
+        kind,
+    )
+}
+
+/// Read the whole contents of a sealed memfd
+fn contents(file: &mut FinalizedMemFile) -> Result<Vec<u8>, LaunchSignatureError> {
+    file.0.0.seek(SeekFrom::Start(0))?;
+    let mut contents = Vec::new();
+    file.as_ref().read_to_end(&mut contents)?;
+    Ok(contents)
+}
+
+/// The key the launcher of the dataplane signs the launch configuration with
+pub struct LaunchSigningKey(SigningKey);
+
+impl LaunchSigningKey {
+    /// Build a signing key from its hex encoded secret seed
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `hex` is not a hex encoded 32-byte seed.
+    pub fn from_hex(hex: &str) -> Result<Self, LaunchSignatureError> {
+        let seed = decode_key(hex, "signing")?;
+        Ok(Self(SigningKey::from_bytes(&seed)))
+    }
+
+    /// Load the signing key from [`SIGNING_KEY_ENV`] or [`SIGNING_KEY_FILE_ENV`], if any is set
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key file can't be read or if the key is invalid.
+    pub fn from_env() -> Result<Option<Self>, LaunchSignatureError> {
+        let seed = key_from_env(SIGNING_KEY_ENV, SIGNING_KEY_FILE_ENV, "signing")?;
+        Ok(seed.map(|seed| Self(SigningKey::from_bytes(&seed))))
+    }
+
+    /// The verifying key matching this signing key
+    #[must_use]
+    pub fn verifying_key(&self) -> LaunchVerifyingKey {
+        LaunchVerifyingKey(self.0.verifying_key())
+    }
+
+    /// Sign the contents of a sealed memfd (typically the launch configuration)
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the memfd can't be read.
+    pub fn sign(
+        &self,
+        file: &mut FinalizedMemFile,
+    ) -> Result<LaunchSignature, LaunchSignatureError> {
+        let contents = contents(file)?;
+        Ok(LaunchSignature(self.0.sign(&contents)))
+    }
+}
+
+impl std::fmt::Debug for LaunchSigningKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("LaunchSigningKey").finish_non_exhaustive()
+    }
+}
+
+/// The key the dataplane checks the signature of its launch configuration with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LaunchVerifyingKey(VerifyingKey);
+
+impl LaunchVerifyingKey {
+    /// Build a verifying key from its hex encoding
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `hex` is not a hex encoded 32-byte Ed25519 public key.
+    pub fn from_hex(hex: &str) -> Result<Self, LaunchSignatureError> {
+        Self::from_bytes(&decode_key(hex, "verifying")?)
+    }
+
+    fn from_bytes(bytes: &[u8; KEY_BYTE_LEN]) -> Result<Self, LaunchSignatureError> {
+        VerifyingKey::from_bytes(bytes)
+            .map(Self)
+            .map_err(|_| LaunchSignatureError::InvalidKey("verifying"))
+    }
+
+    /// Load the verifying key from [`VERIFYING_KEY_ENV`] or [`VERIFYING_KEY_FILE_ENV`], if any
+    /// is set
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the key file can't be read or if the key is invalid.
+    pub fn from_env() -> Result<Option<Self>, LaunchSignatureError> {
+        key_from_env(VERIFYING_KEY_ENV, VERIFYING_KEY_FILE_ENV, "verifying")?
+            .map(|bytes| Self::from_bytes(&bytes))
+            .transpose()
+    }
+
+    /// Verify the contents of `file` against the signature serialized in `signature_file`
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if either file can't be read, if the signature file has the wrong size,
+    /// or if the signature does not match the contents of `file`.
+    pub fn verify(
+        &self,
+        file: &mut FinalizedMemFile,
+        signature_file: FinalizedMemFile,
+    ) -> Result<(), LaunchSignatureError> {
+        let mut signature_file = signature_file;
+        let given = contents(&mut signature_file)?;
+        let signature = <[u8; SIGNATURE_BYTE_LEN]>::try_from(given.as_slice())
+            .map_err(|_| LaunchSignatureError::WrongSignatureLength(given.len() as u64))?;
+        let signature = Signature::from_bytes(&signature);
+        let contents = contents(file)?;
+        self.0
+            .verify_strict(&contents, &signature)
+            .map_err(|_| LaunchSignatureError::InvalidSignature)
+    }
+}
+
+impl std::fmt::Display for LaunchVerifyingKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0
+            .as_bytes()
+            .iter()
+            .try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+/// The Ed25519 signature of the launch configuration
+#[must_use]
+#[derive(Debug, PartialEq, Eq)]
+pub struct LaunchSignature(Signature);
+
+impl AsFinalizedMemFile for LaunchSignature {
+    #[tracing::instrument(level = "info")]
+    fn finalize(self) -> FinalizedMemFile {
+        let bytes = self.0.to_bytes();
+        let mut memfd = MemFile::new();
+        memfd
+            .as_mut()
+            .write_all(bytes.as_slice())
+            .into_diagnostic()
+            .wrap_err("failed to write launch configuration signature to memfd")
+            .unwrap();
+        memfd.finalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LaunchSignatureError, LaunchSigningKey, LaunchVerifyingKey, key_from_sources};
+    use crate::{AsFinalizedMemFile, FinalizedMemFile, MemFile};
+    use std::io::Write;
+
+    const SEED: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+    // RFC 8032 test vector 1: the public key of SEED
+    const PUBLIC: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+
+    fn memfd(contents: &[u8]) -> FinalizedMemFile {
+        let mut memfd = MemFile::new();
+        memfd.as_mut().write_all(contents).unwrap();
+        memfd.finalize()
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let signing = LaunchSigningKey::from_hex(SEED).unwrap();
+        let verifying = LaunchVerifyingKey::from_hex(PUBLIC).unwrap();
+        assert_eq!(signing.verifying_key(), verifying);
+        assert_eq!(verifying.to_string(), PUBLIC);
+
+        let mut config = memfd(b"launch configuration");
+        let signature = signing.sign(&mut config).unwrap().finalize();
+        verifying.verify(&mut config, signature).unwrap();
+
+        // tampered contents
+        let signature = signing.sign(&mut config).unwrap().finalize();
+        let mut tampered = memfd(b"launch configuratioN");
+        let r = verifying.verify(&mut tampered, signature);
+        assert!(matches!(r, Err(LaunchSignatureError::InvalidSignature)));
+
+        // signature by another key
+        let other = LaunchSigningKey::from_hex(&"11".repeat(32)).unwrap();
+        let signature = other.sign(&mut config).unwrap().finalize();
+        let r = verifying.verify(&mut config, signature);
+        assert!(matches!(r, Err(LaunchSignatureError::InvalidSignature)));
+
+        // truncated signature
+        let r = verifying.verify(&mut config, memfd(&[0; 12]));
+        assert!(matches!(
+            r,
+            Err(LaunchSignatureError::WrongSignatureLength(12))
+        ));
+    }
+
+    #[test]
+    fn test_key_sources() {
+        assert!(key_from_sources(None, None, "signing").unwrap().is_none());
+        assert!(key_from_sources(Some(format!(" {SEED}\n")), None, "signing").is_ok());
+        for invalid in ["", "9d61", &SEED.replace('9', "g"), &format!("{SEED}00")] {
+            let r = key_from_sources(Some(invalid.to_string()), None, "signing");
+            assert!(matches!(r, Err(LaunchSignatureError::InvalidKey(_))));
+        }
+
+        let path = std::env::temp_dir().join(format!("launch-key-{}", std::process::id()));
+        std::fs::write(&path, format!("{PUBLIC}\n")).unwrap();
+        let key = key_from_sources(None, Some(&path), "verifying").unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(key.map(|key| key[0]), Some(0xd7));
+        let r = key_from_sources(None, Some(&path), "verifying");
+        assert!(matches!(r, Err(LaunchSignatureError::KeyFile(_, _))));
+    }
+}
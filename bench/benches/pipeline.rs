@@ -0,0 +1,93 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+#![allow(clippy::unwrap_used, clippy::expect_used)]
+
+use std::hint::black_box;
+
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+
+use dataplane_bench::{BenchSetup, NatMode, PacketSizes, TrafficGenerator, TrafficProfile};
+use dataplane_bench::{VniDistribution, harness::Stage};
+use pipeline::NetworkFunction;
+
+fn profiles() -> Vec<TrafficProfile> {
+    let base = TrafficProfile::default();
+    vec![
+        base.clone(),
+        TrafficProfile {
+            flows: 65_536,
+            ..base.clone()
+        },
+        TrafficProfile {
+            vpcs: 64,
+            vni_distribution: VniDistribution::Skewed { hot_percent: 80 },
+            ..base.clone()
+        },
+        TrafficProfile {
+            packet_sizes: PacketSizes::Imix,
+            ..base.clone()
+        },
+        TrafficProfile {
+            nat: NatMode::Static,
+            ..base.clone()
+        },
+        TrafficProfile {
+            nat: NatMode::Masquerade,
+            ..base
+        },
+    ]
+}
+
+/// Throughput of the whole pipeline
+fn bench_pipeline(c: &mut Criterion) {
+    let mut group = c.benchmark_group("pipeline");
+    for profile in profiles() {
+        let setup = BenchSetup::new(&profile.overlay().unwrap()).unwrap();
+        let _guard = setup.enter();
+        let mut pipeline = setup.pipeline();
+        let mut generator = TrafficGenerator::new(&profile);
+
+        group.throughput(Throughput::Elements(profile.batch_size as u64));
+        group.bench_function(BenchmarkId::from_parameter(&profile), |b| {
+            b.iter_batched(
+                || generator.batch(),
+                |batch| black_box(pipeline.process(batch.into_iter()).count()),
+                BatchSize::SmallInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+/// Cost of each stage, fed with the output of the stages before it
+fn bench_stages(c: &mut Criterion) {
+    let profile = TrafficProfile::default();
+    let setup = BenchSetup::new(&profile.overlay().unwrap()).unwrap();
+    let _guard = setup.enter();
+    eprintln!("{}", setup.measure(&profile, 1024));
+
+    let mut group = c.benchmark_group("stages");
+    group.throughput(Throughput::Elements(profile.batch_size as u64));
+    let mut stages = setup.stages();
+    for index in 0..stages.len() {
+        let (previous, rest) = stages.split_at_mut(index);
+        let stage: &mut Stage = &mut rest[0];
+        let mut generator = TrafficGenerator::new(&profile);
+        group.bench_function(BenchmarkId::new(stage.name(), &profile), |b| {
+            b.iter_batched(
+                || {
+                    previous
+                        .iter_mut()
+                        .fold(generator.batch(), |batch, stage| stage.process(batch))
+                },
+                |batch| black_box(stage.process(batch)),
+                BatchSize::SmallInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benchmarks, bench_pipeline, bench_stages);
+criterion_main!(benchmarks);
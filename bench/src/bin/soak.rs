@@ -0,0 +1,117 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Long-running stability test of the pipeline, meant to be run nightly. The pipeline runs on
+//! worker threads under synthetic traffic and configuration churn, while the resident memory and
+//! open descriptors of the process, and the latency percentiles of the pipeline, are sampled and
+//! printed. Exits with a failure if descriptors leak or memory grows; see
+//! [`dataplane_bench::soak`].
+
+#![deny(clippy::all, clippy::pedantic)]
+
+use clap::Parser;
+use dataplane_bench::soak::{SoakConfig, check, soak};
+use dataplane_bench::{NatMode, PacketSizes, TrafficProfile};
+use std::process::ExitCode;
+use std::time::Duration;
+
+#[derive(Parser)]
+#[command(name = "dataplane-soak")]
+#[command(about = "Run the pipeline for hours under traffic and config churn, and check for leaks")]
+struct Args {
+    /// Duration of the run, in minutes
+    #[arg(long, default_value_t = 240)]
+    minutes: u64,
+
+    /// Time for caches and tables to fill up before resources are tracked, in seconds
+    #[arg(long, default_value_t = 300)]
+    warmup: u64,
+
+    /// Number of worker threads, each with its own pipeline
+    #[arg(long, default_value_t = 2)]
+    workers: usize,
+
+    /// Interval between configuration changes, in milliseconds
+    #[arg(long, default_value_t = 1000)]
+    churn_interval: u64,
+
+    /// Interval between samples of the process, in seconds
+    #[arg(long, default_value_t = 10)]
+    sample_interval: u64,
+
+    /// Number of VPCs of the traffic
+    #[arg(long, default_value_t = 4)]
+    vpcs: u8,
+
+    /// Number of distinct flows of the traffic
+    #[arg(long, default_value_t = 65_536)]
+    flows: usize,
+
+    /// Send IMIX traffic rather than 64-byte packets
+    #[arg(long)]
+    imix: bool,
+
+    /// Allowed growth of resident memory after the warmup, in MiB
+    #[arg(long, default_value_t = 64)]
+    rss_slack: u64,
+
+    /// Allowed growth of the number of open file descriptors after the warmup
+    #[arg(long, default_value_t = 8)]
+    fd_slack: usize,
+
+    /// Seed of the traffic and of the churn, so that runs are reproducible
+    #[arg(long, default_value_t = 0x5eed)]
+    seed: u64,
+}
+
+impl From<&Args> for SoakConfig {
+    fn from(args: &Args) -> Self {
+        SoakConfig {
+            duration: Duration::from_secs(args.minutes * 60),
+            workers: args.workers.max(1),
+            churn_interval: Duration::from_millis(args.churn_interval.max(1)),
+            sample_interval: Duration::from_secs(args.sample_interval.max(1)),
+            warmup: Duration::from_secs(args.warmup),
+            profile: TrafficProfile {
+                vpcs: args.vpcs,
+                flows: args.flows,
+                packet_sizes: if args.imix {
+                    PacketSizes::Imix
+                } else {
+                    PacketSizes::Fixed(64)
+                },
+                nat: NatMode::None,
+                seed: args.seed,
+                ..TrafficProfile::default()
+            },
+            rss_slack_kib: args.rss_slack * 1024,
+            fd_slack: args.fd_slack,
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let config = SoakConfig::from(&args);
+    println!(
+        "Soaking {} for {} minutes with {} workers",
+        config.profile, args.minutes, config.workers
+    );
+    let samples = match soak(&config, |sample| println!("{sample}")) {
+        Ok(samples) => samples,
+        Err(e) => {
+            eprintln!("Soak test failed to run: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    match check(&samples, &config) {
+        Ok(()) => {
+            println!("Soak test passed");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Soak test failed: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,354 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! A pipeline made of the stages that need neither a driver nor a router
+//!
+//! The stages are those of the dataplane pipeline between the two IP forwarding stages, in the
+//! same order, configured from the overlay of a [`TrafficProfile`] the same way management does.
+//! The ACL filter is left out, since it needs the DPDK EAL.
+
+use crate::traffic::{TrafficGenerator, TrafficProfile};
+use concurrency::sync::Arc;
+use config::ConfigError;
+use config::external::GenId;
+use config::external::overlay::ValidatedOverlay;
+use config::external::qos::QosConfig;
+use config::external::session_table::SessionTableConfig;
+use flow_entry::flow_table::{FlowLookup, FlowTable};
+use flow_filter::{FlowFilter, FlowFilterTable, FlowFilterTableWriter};
+use nat::masquerade::{MasqueradeConfig, NatAllocatorWriter};
+use nat::portfw::{PortForwarder, PortFwTableWriter, build_port_forwarding_configuration};
+use nat::static_nat::NatTablesWriter;
+use nat::static_nat::setup::build_nat_configuration;
+use nat::{Masquerade, StaticNat};
+use net::buffer::TestBuffer;
+use net::packet::{Packet, PacketStats};
+use pipeline::sample_nfs::PacketStatsNF;
+use pipeline::{DynPipeline, NetworkFunction};
+use qos::{QosScheduler, QosTable, QosTableWriter};
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+use tokio::runtime::{EnterGuard, Runtime};
+
+/// Errors setting up a benchmark
+#[derive(Debug, thiserror::Error)]
+pub enum BenchError {
+    #[error("Invalid configuration: {0}")]
+    Config(#[from] ConfigError),
+    #[error("Failed to build the runtime: {0}")]
+    Runtime(#[from] std::io::Error),
+    #[error("Failed to set up port forwarding: {0}")]
+    PortForwarding(String),
+}
+
+/// A pipeline stage, which can be driven on its own
+pub struct Stage {
+    name: &'static str,
+    pipeline: DynPipeline<TestBuffer>,
+}
+
+impl Stage {
+    fn new<NF: NetworkFunction<TestBuffer> + 'static>(name: &'static str, nf: NF) -> Self {
+        Self {
+            name,
+            pipeline: DynPipeline::new().add_stage(nf),
+        }
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Process a batch of packets, returning those not dropped by the stage
+    pub fn process(&mut self, packets: Vec<Packet<TestBuffer>>) -> Vec<Packet<TestBuffer>> {
+        self.pipeline.process(packets.into_iter()).collect()
+    }
+}
+
+/// The tables of the stages, configured from an overlay
+pub struct BenchSetup {
+    runtime: Runtime,
+    flow_table: Arc<FlowTable>,
+    flowfilterw: FlowFilterTableWriter,
+    nattablesw: NatTablesWriter,
+    natallocatorw: NatAllocatorWriter,
+    portfw_w: PortFwTableWriter,
+    qosw: QosTableWriter,
+    pkt_stats: Arc<PacketStats>,
+    /// Generation of the configuration of the tables
+    genid: GenId,
+}
+
+impl BenchSetup {
+    /// Build the tables of the stages from an overlay
+    ///
+    /// # Errors
+    ///
+    /// Fails if the tables cannot be built from the overlay.
+    pub fn new(overlay: &ValidatedOverlay) -> Result<Self, BenchError> {
+        // flow tables arm a timer per flow, which requires a tokio context
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()?;
+        let mut setup = Self {
+            runtime,
+            flow_table: Arc::new(FlowTable::default()),
+            flowfilterw: FlowFilterTableWriter::new(),
+            nattablesw: NatTablesWriter::new(),
+            natallocatorw: NatAllocatorWriter::new(),
+            portfw_w: PortFwTableWriter::new(),
+            qosw: QosTableWriter::new(),
+            pkt_stats: Arc::new(PacketStats::new()),
+            genid: 0,
+        };
+        setup.reconfigure(overlay)?;
+        Ok(setup)
+    }
+
+    /// Rebuild the tables of the stages from another overlay, as management does when applying a
+    /// new configuration. Pipelines built before keep running, with the new tables.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the tables cannot be built from the overlay. Tables may then have been partially
+    /// updated.
+    pub fn reconfigure(&mut self, overlay: &ValidatedOverlay) -> Result<(), BenchError> {
+        self.genid += 1;
+        self.flowfilterw
+            .update_flow_filter_table(FlowFilterTable::build_from_overlay(overlay)?);
+        self.nattablesw
+            .update_nat_tables(build_nat_configuration(overlay.vpc_table())?);
+        self.natallocatorw.update_nat_allocator(
+            MasqueradeConfig::new(overlay.vpc_table(), self.genid),
+            &self.flow_table,
+        );
+        self.portfw_w
+            .update_table(&build_port_forwarding_configuration(
+                overlay.vpc_table(),
+                &SessionTableConfig::default(),
+            )?)
+            .map_err(|e| BenchError::PortForwarding(e.to_string()))?;
+        self.qosw
+            .update_qos_table(QosTable::build(&QosConfig::new(), overlay.vpc_table())?);
+        Ok(())
+    }
+
+    /// Enter the runtime context that the stages need to run in
+    #[must_use]
+    pub fn enter(&self) -> EnterGuard<'_> {
+        self.runtime.enter()
+    }
+
+    /// Build the stages, in pipeline order
+    #[must_use]
+    pub fn stages(&self) -> Vec<Stage> {
+        vec![
+            Stage::new(
+                "flow-lookup",
+                FlowLookup::new("flow-lookup", self.flow_table.clone()),
+            ),
+            Stage::new(
+                "flow-filter",
+                FlowFilter::new("flow-filter", self.flowfilterw.get_reader()),
+            ),
+            Stage::new(
+                "static-nat",
+                StaticNat::with_reader("static-NAT", self.nattablesw.get_reader()),
+            ),
+            Stage::new(
+                "port-forwarder",
+                PortForwarder::new(
+                    "port-forwarder",
+                    self.portfw_w.reader(),
+                    self.flow_table.clone(),
+                ),
+            ),
+            Stage::new(
+                "masquerade",
+                Masquerade::new(
+                    "masquerade",
+                    self.flow_table.clone(),
+                    self.natallocatorw.get_reader(),
+                ),
+            ),
+            Stage::new(
+                "qos-scheduler",
+                QosScheduler::new("qos-scheduler", self.qosw.get_reader()),
+            ),
+            Stage::new("pkt-stats", PacketStatsNF::new(self.pkt_stats.clone())),
+        ]
+    }
+
+    /// Build a pipeline with all the stages
+    #[must_use]
+    pub fn pipeline(&self) -> DynPipeline<TestBuffer> {
+        self.stages()
+            .into_iter()
+            .fold(DynPipeline::new(), |pipeline, stage| {
+                pipeline.add_stage(stage.pipeline)
+            })
+    }
+
+    /// Build a factory of pipelines with all the stages, which may be called from any thread, like
+    /// the one the drivers build a pipeline per worker with
+    #[must_use]
+    pub fn pipeline_factory(&self) -> Arc<dyn Send + Sync + Fn() -> DynPipeline<TestBuffer>> {
+        let flow_table = self.flow_table.clone();
+        let flowfilter_factory = self.flowfilterw.get_reader_factory();
+        let nattables_factory = self.nattablesw.get_reader_factory();
+        let natallocator_factory = self.natallocatorw.get_reader_factory();
+        let portfw_factory = self.portfw_w.reader().factory();
+        let qos_factory = self.qosw.get_reader_factory();
+        let pkt_stats = self.pkt_stats.clone();
+        Arc::new(move || {
+            DynPipeline::new()
+                .add_stage(FlowLookup::new("flow-lookup", flow_table.clone()))
+                .add_stage(FlowFilter::new("flow-filter", flowfilter_factory.handle()))
+                .add_stage(StaticNat::with_reader(
+                    "static-NAT",
+                    nattables_factory.handle(),
+                ))
+                .add_stage(PortForwarder::new(
+                    "port-forwarder",
+                    portfw_factory.handle(),
+                    flow_table.clone(),
+                ))
+                .add_stage(Masquerade::new(
+                    "masquerade",
+                    flow_table.clone(),
+                    natallocator_factory.handle(),
+                ))
+                .add_stage(QosScheduler::new("qos-scheduler", qos_factory.handle()))
+                .add_stage(PacketStatsNF::new(pkt_stats.clone()))
+        })
+    }
+
+    /// Drive some batches of traffic through the stages, timing each of them
+    #[must_use]
+    pub fn measure(&self, profile: &TrafficProfile, batches: usize) -> Report {
+        let _guard = self.enter();
+        let mut generator = TrafficGenerator::new(profile);
+        let mut stages = self.stages();
+        let mut report = Report {
+            profile: profile.to_string(),
+            packets: 0,
+            delivered: 0,
+            stages: stages
+                .iter()
+                .map(|stage| StageReport {
+                    name: stage.name(),
+                    packets: 0,
+                    elapsed: Duration::ZERO,
+                })
+                .collect(),
+        };
+        for _ in 0..batches {
+            let mut packets = generator.batch();
+            report.packets += packets.len() as u64;
+            for (stage, stage_report) in stages.iter_mut().zip(report.stages.iter_mut()) {
+                stage_report.packets += packets.len() as u64;
+                let start = Instant::now();
+                packets = stage.process(packets);
+                stage_report.elapsed += start.elapsed();
+            }
+            report.delivered += packets.len() as u64;
+        }
+        report
+    }
+}
+
+/// The cost of a stage in a [`Report`]
+#[derive(Debug, Clone)]
+pub struct StageReport {
+    pub name: &'static str,
+    /// Number of packets the stage processed
+    pub packets: u64,
+    pub elapsed: Duration,
+}
+
+impl StageReport {
+    /// The average processing time of a packet by the stage
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn ns_per_packet(&self) -> f64 {
+        if self.packets == 0 {
+            return 0.0;
+        }
+        self.elapsed.as_nanos() as f64 / self.packets as f64
+    }
+}
+
+/// The throughput of the pipeline for some traffic, and the cost of each stage
+#[derive(Debug, Clone)]
+pub struct Report {
+    pub profile: String,
+    /// Number of packets sent to the pipeline
+    pub packets: u64,
+    /// Number of packets that went through all the stages
+    pub delivered: u64,
+    pub stages: Vec<StageReport>,
+}
+
+impl Report {
+    /// The time spent in the stages
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        self.stages.iter().map(|stage| stage.elapsed).sum()
+    }
+
+    /// The throughput of the pipeline, in millions of packets per second
+    #[must_use]
+    #[allow(clippy::cast_precision_loss)]
+    pub fn mpps(&self) -> f64 {
+        let elapsed = self.elapsed().as_secs_f64();
+        if elapsed == 0.0 {
+            return 0.0;
+        }
+        self.packets as f64 / elapsed / 1e6
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{}: {} packets, {} delivered, {:.3} Mpps",
+            self.profile,
+            self.packets,
+            self.delivered,
+            self.mpps()
+        )?;
+        for stage in &self.stages {
+            writeln!(
+                f,
+                "  {:<16} {:>10.1} ns/packet",
+                stage.name,
+                stage.ns_per_packet()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::traffic::NatMode;
+
+    #[test]
+    fn test_measure() {
+        for nat in [NatMode::None, NatMode::Static, NatMode::Masquerade] {
+            let profile = TrafficProfile {
+                nat,
+                ..Default::default()
+            };
+            let setup = BenchSetup::new(&profile.overlay().unwrap()).unwrap();
+            let report = setup.measure(&profile, 4);
+            assert_eq!(report.packets, 4 * profile.batch_size as u64);
+            // the traffic must exercise the stages rather than being dropped early
+            assert_eq!(report.delivered, report.packets, "{report}");
+            assert_eq!(report.stages.len(), setup.stages().len());
+        }
+    }
+}
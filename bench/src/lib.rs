@@ -0,0 +1,25 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Pipeline throughput benchmarks and soak test
+//!
+//! [`traffic`] generates batches of synthetic packets, with configurable flow counts, packet
+//! sizes and distribution over the VPCs, along with the overlay they are meant for. [`harness`]
+//! drives them through the pipeline stages, configured from that overlay, without any driver.
+//!
+//! The criterion benchmarks (`cargo bench -p dataplane-bench`) report the throughput of the
+//! pipeline in packets per second and the cost of each stage, to detect performance regressions.
+//!
+//! [`soak`] runs the pipeline for hours instead, on worker threads and under configuration churn,
+//! to detect leaks and latency drifts. The `dataplane-soak` binary runs it, and is meant to be run
+//! nightly (`just soak`).
+
+#![deny(clippy::all, clippy::pedantic)]
+
+pub mod harness;
+pub mod soak;
+pub mod traffic;
+
+pub use harness::{BenchError, BenchSetup, Report, Stage, StageReport};
+pub use soak::{SoakConfig, SoakError, SoakFailure};
+pub use traffic::{NatMode, PacketSizes, TrafficGenerator, TrafficProfile, VniDistribution};
@@ -0,0 +1,531 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Soak test: the pipeline under synthetic traffic and configuration churn, for hours
+//!
+//! Workers run the pipeline the way the workers of the kernel driver do: each on its own thread,
+//! with its own pipeline built from the shared tables and its own current-thread tokio runtime,
+//! which runs the flow timers between batches. Meanwhile, the tables are rebuilt from randomized
+//! overlays at a fixed interval, the way management applies new configurations.
+//!
+//! The process is sampled at a fixed interval: resident memory, open file descriptors, and the
+//! percentiles of the time the pipeline took to process a batch since the previous sample. The run
+//! fails if descriptors leak, if resident memory grows beyond some slack, or if it keeps growing
+//! over the whole run, however slowly.
+
+use crate::harness::{BenchError, BenchSetup};
+use crate::traffic::{MAX_VPCS, NatMode, Rng, TrafficGenerator, TrafficProfile};
+use concurrency::sync::atomic::{AtomicBool, Ordering};
+use concurrency::sync::mpsc;
+use concurrency::thread;
+use net::buffer::TestBuffer;
+use pipeline::DynPipeline;
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+/// Number of significant bits of the latencies in a [`Histogram`]
+const SUB_BUCKET_BITS: u32 = 3;
+/// Number of buckets needed for any `u64` latency
+const BUCKETS: usize = ((64 - SUB_BUCKET_BITS as usize) + 1) << SUB_BUCKET_BITS;
+/// Number of batches after which a worker hands its latencies over
+const LATENCY_CHUNK: u64 = 1024;
+/// Number of windows the samples after the warmup are split in, to detect monotonic growth
+const RSS_WINDOWS: usize = 8;
+/// Growth of resident memory, in KiB, below which monotonic growth is considered noise
+const RSS_NOISE_KIB: u64 = 1024;
+
+/// The parameters of a soak run
+#[derive(Clone, Debug)]
+pub struct SoakConfig {
+    pub duration: Duration,
+    /// Number of worker threads, each with its own pipeline
+    pub workers: usize,
+    /// Interval between configuration changes
+    pub churn_interval: Duration,
+    /// Interval between samples of the process
+    pub sample_interval: Duration,
+    /// Time for caches and tables to fill up. Resources are checked against the first sample
+    /// after it.
+    pub warmup: Duration,
+    /// The traffic of the workers. The churn varies the number of VPCs and the NAT mode of its
+    /// overlay.
+    pub profile: TrafficProfile,
+    /// Allowed growth of resident memory after the warmup, in KiB
+    pub rss_slack_kib: u64,
+    /// Allowed growth of the number of open file descriptors after the warmup
+    pub fd_slack: usize,
+}
+
+impl Default for SoakConfig {
+    fn default() -> Self {
+        Self {
+            duration: Duration::from_secs(4 * 3600),
+            workers: 2,
+            churn_interval: Duration::from_secs(1),
+            sample_interval: Duration::from_secs(10),
+            warmup: Duration::from_secs(300),
+            profile: TrafficProfile {
+                flows: 65_536,
+                ..TrafficProfile::default()
+            },
+            rss_slack_kib: 64 * 1024,
+            fd_slack: 8,
+        }
+    }
+}
+
+/// Errors running a soak test
+#[derive(Debug, thiserror::Error)]
+pub enum SoakError {
+    #[error(transparent)]
+    Setup(#[from] BenchError),
+    #[error("Failed to sample the process: {0}")]
+    Sampling(std::io::Error),
+    #[error("Worker {0} failed: {1}")]
+    Worker(usize, std::io::Error),
+    #[error("Worker {0} panicked")]
+    WorkerPanic(usize),
+}
+
+/// The reasons a soak run fails
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum SoakFailure {
+    #[error("The run ended before the warmup did")]
+    TooShort,
+    #[error("The pipeline processed no batch between {0:?} and the previous sample")]
+    Stalled(Duration),
+    #[error("File descriptors leaked: {baseline} open after the warmup, {last} at the end")]
+    FdLeak { baseline: usize, last: usize },
+    #[error("Resident memory grew by {growth} KiB after the warmup, over the allowed {slack} KiB")]
+    RssGrowth { growth: u64, slack: u64 },
+    #[error("Resident memory grew monotonically after the warmup, from {first} KiB to {last} KiB")]
+    MonotonicRss { first: u64, last: u64 },
+}
+
+/// Histogram of latencies, in nanoseconds, with a precision of 1/8th
+#[derive(Clone, Debug)]
+pub struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    max: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: vec![0; BUCKETS],
+            count: 0,
+            max: 0,
+        }
+    }
+}
+
+impl Histogram {
+    #[allow(clippy::cast_possible_truncation)] // below BUCKETS
+    fn bucket(ns: u64) -> usize {
+        if ns < 1 << SUB_BUCKET_BITS {
+            return ns as usize;
+        }
+        let exponent = 63 - ns.leading_zeros();
+        let sub_bucket = (ns >> (exponent - SUB_BUCKET_BITS)) & ((1 << SUB_BUCKET_BITS) - 1);
+        (((exponent - SUB_BUCKET_BITS + 1) << SUB_BUCKET_BITS) as usize) + sub_bucket as usize
+    }
+
+    /// The smallest latency in a bucket
+    fn lower_bound(bucket: usize) -> u64 {
+        let sub_buckets = 1 << SUB_BUCKET_BITS;
+        if bucket < sub_buckets {
+            return bucket as u64;
+        }
+        let shift = bucket / sub_buckets - 1;
+        ((sub_buckets + bucket % sub_buckets) as u64) << shift
+    }
+
+    pub fn record(&mut self, latency: Duration) {
+        let ns = u64::try_from(latency.as_nanos()).unwrap_or(u64::MAX);
+        self.buckets[Self::bucket(ns)] += 1;
+        self.count += 1;
+        self.max = self.max.max(ns);
+    }
+
+    pub fn merge(&mut self, other: &Histogram) {
+        for (bucket, count) in self.buckets.iter_mut().zip(&other.buckets) {
+            *bucket += count;
+        }
+        self.count += other.count;
+        self.max = self.max.max(other.max);
+    }
+
+    /// Number of latencies recorded
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The latency below which a fraction `quantile` of the latencies are, rounded down to the
+    /// precision of the histogram
+    #[must_use]
+    #[allow(
+        clippy::cast_precision_loss,
+        clippy::cast_possible_truncation,
+        clippy::cast_sign_loss
+    )]
+    pub fn percentile(&self, quantile: f64) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        let rank = ((quantile * self.count as f64).ceil() as u64).clamp(1, self.count);
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Duration::from_nanos(Self::lower_bound(bucket).min(self.max));
+            }
+        }
+        Duration::from_nanos(self.max)
+    }
+
+    #[must_use]
+    pub fn max(&self) -> Duration {
+        Duration::from_nanos(self.max)
+    }
+}
+
+/// The resources of the process and the latencies of the pipeline at some point of a run
+#[derive(Clone, Debug)]
+pub struct Sample {
+    /// Time since the start of the run
+    pub elapsed: Duration,
+    /// Resident memory, in KiB
+    pub rss_kib: u64,
+    /// Number of open file descriptors
+    pub fds: usize,
+    /// Number of configuration changes since the start of the run
+    pub configs: u64,
+    /// Latencies of the batches processed since the previous sample
+    pub latencies: Histogram,
+}
+
+impl Sample {
+    /// Sample the resources of the process
+    fn take(elapsed: Duration, configs: u64, latencies: Histogram) -> Result<Self, std::io::Error> {
+        let status = std::fs::read_to_string("/proc/self/status")?;
+        let rss_kib = status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmRSS:"))
+            .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+            .ok_or_else(|| std::io::Error::other("no VmRSS in /proc/self/status"))?;
+        // the count includes the descriptor of the directory being read, which does not matter
+        // as long as all the samples do
+        let fds = std::fs::read_dir("/proc/self/fd")?.count();
+        Ok(Self {
+            elapsed,
+            rss_kib,
+            fds,
+            configs,
+            latencies,
+        })
+    }
+}
+
+impl Display for Sample {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:>8}s: rss={} KiB fds={} configs={} batches={} p50={:?} p99={:?} p99.9={:?} max={:?}",
+            self.elapsed.as_secs(),
+            self.rss_kib,
+            self.fds,
+            self.configs,
+            self.latencies.count(),
+            self.latencies.percentile(0.5),
+            self.latencies.percentile(0.99),
+            self.latencies.percentile(0.999),
+            self.latencies.max(),
+        )
+    }
+}
+
+/// Check the samples of a run for stalls, leaks and memory growth after the warmup
+///
+/// # Errors
+///
+/// Returns the first problem found.
+pub fn check(samples: &[Sample], config: &SoakConfig) -> Result<(), SoakFailure> {
+    let start = samples
+        .iter()
+        .position(|sample| sample.elapsed >= config.warmup)
+        .ok_or(SoakFailure::TooShort)?;
+    let steady = &samples[start..];
+    let (baseline, last) = (&steady[0], &steady[steady.len() - 1]);
+
+    if let Some(stalled) = steady.iter().find(|sample| sample.latencies.count() == 0) {
+        return Err(SoakFailure::Stalled(stalled.elapsed));
+    }
+    if last.fds > baseline.fds + config.fd_slack {
+        return Err(SoakFailure::FdLeak {
+            baseline: baseline.fds,
+            last: last.fds,
+        });
+    }
+    let growth = last.rss_kib.saturating_sub(baseline.rss_kib);
+    if growth > config.rss_slack_kib {
+        return Err(SoakFailure::RssGrowth {
+            growth,
+            slack: config.rss_slack_kib,
+        });
+    }
+    // a leak slower than the slack still shows as the peak of each window being above the one of
+    // the window before
+    if steady.len() >= RSS_WINDOWS {
+        let peaks: Vec<u64> = steady
+            .chunks(steady.len() / RSS_WINDOWS)
+            .map(|window| window.iter().map(|s| s.rss_kib).max().unwrap_or_default())
+            .collect();
+        let (first, last) = (peaks[0], peaks[peaks.len() - 1]);
+        if peaks.windows(2).all(|pair| pair[1] > pair[0]) && last - first > RSS_NOISE_KIB {
+            return Err(SoakFailure::MonotonicRss { first, last });
+        }
+    }
+    Ok(())
+}
+
+/// Pick the overlay of the next configuration, varying the number of VPCs and the NAT mode of
+/// the overlay of the traffic, so that part of the traffic changes verdict along the way
+fn churn_profile(base: &TrafficProfile, rng: &mut Rng) -> TrafficProfile {
+    #[allow(clippy::cast_possible_truncation)] // below 4
+    let extra_vpcs = rng.below(4) as u8;
+    TrafficProfile {
+        vpcs: base.vpcs.saturating_add(extra_vpcs).min(MAX_VPCS),
+        nat: [NatMode::None, NatMode::Static, NatMode::Masquerade][rng.below(3)],
+        ..base.clone()
+    }
+}
+
+/// Run the pipeline of a worker until told to stop, handing over the latencies of its batches
+fn run_worker(
+    id: usize,
+    setup_pipeline: &(dyn Send + Sync + Fn() -> DynPipeline<TestBuffer>),
+    profile: &TrafficProfile,
+    stop: &AtomicBool,
+    latencies: &mpsc::Sender<Histogram>,
+) -> Result<(), std::io::Error> {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .build()?;
+    runtime.block_on(async {
+        let mut pipeline = setup_pipeline();
+        let mut generator = TrafficGenerator::new(&TrafficProfile {
+            seed: profile.seed.wrapping_add(id as u64),
+            ..profile.clone()
+        });
+        let mut histogram = Histogram::default();
+        while !stop.load(Ordering::Relaxed) {
+            let batch = generator.batch();
+            let start = Instant::now();
+            pipeline.process(batch.into_iter()).for_each(drop);
+            histogram.record(start.elapsed());
+            if histogram.count() == LATENCY_CHUNK {
+                // the receiver is only gone once told to stop
+                let _ = latencies.send(std::mem::take(&mut histogram));
+            }
+            // let the flow timers run, as the workers of the kernel driver do between batches
+            tokio::task::yield_now().await;
+        }
+        let _ = latencies.send(histogram);
+    });
+    Ok(())
+}
+
+/// Run a soak test, calling `report` with each sample as it is taken
+///
+/// # Errors
+///
+/// Fails if the pipeline cannot be set up, a worker fails or the process cannot be sampled. The
+/// verdict of the run is left to [`check`].
+pub fn soak(
+    config: &SoakConfig,
+    mut report: impl FnMut(&Sample),
+) -> Result<Vec<Sample>, SoakError> {
+    let mut setup = BenchSetup::new(&config.profile.overlay().map_err(BenchError::from)?)?;
+    let setup_pipeline = setup.pipeline_factory();
+    let mut rng = Rng::new(config.profile.seed);
+    let stop = AtomicBool::new(false);
+    let (latencies_tx, latencies_rx) = mpsc::channel();
+
+    thread::scope(|scope| {
+        let workers: Vec<_> = (0..config.workers)
+            .map(|id| {
+                let setup_pipeline = setup_pipeline.clone();
+                let latencies_tx = latencies_tx.clone();
+                let (profile, stop) = (&config.profile, &stop);
+                thread::Builder::new()
+                    .name(format!("soak-worker-{id}"))
+                    .spawn_scoped(scope, move || {
+                        run_worker(id, setup_pipeline.as_ref(), profile, stop, &latencies_tx)
+                    })
+                    .map_err(|e| SoakError::Worker(id, e))
+            })
+            .collect();
+        drop(latencies_tx);
+
+        let mut run = || -> Result<Vec<Sample>, SoakError> {
+            let start = Instant::now();
+            let mut samples = Vec::new();
+            let mut configs = 0;
+            let mut window = Histogram::default();
+            let mut next_churn = start + config.churn_interval;
+            let mut next_sample = start + config.sample_interval;
+            let end = start + config.duration;
+            loop {
+                let now = Instant::now();
+                if now >= next_sample {
+                    let sample = Sample::take(now - start, configs, std::mem::take(&mut window))
+                        .map_err(SoakError::Sampling)?;
+                    report(&sample);
+                    samples.push(sample);
+                    next_sample += config.sample_interval;
+                }
+                if now >= end {
+                    return Ok(samples);
+                }
+                if now >= next_churn {
+                    let overlay = churn_profile(&config.profile, &mut rng)
+                        .overlay()
+                        .map_err(BenchError::from)?;
+                    setup.reconfigure(&overlay)?;
+                    configs += 1;
+                    next_churn += config.churn_interval;
+                }
+                let next = next_churn.min(next_sample).min(end);
+                match latencies_rx.recv_timeout(next.saturating_duration_since(Instant::now())) {
+                    Ok(latencies) => window.merge(&latencies),
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    // all the workers are gone, which the stall check reports
+                    Err(mpsc::RecvTimeoutError::Disconnected) => {
+                        thread::sleep(next.saturating_duration_since(Instant::now()));
+                    }
+                }
+            }
+        };
+        // without all its workers, the run would not mean much
+        let samples = if workers.iter().all(Result::is_ok) {
+            run()
+        } else {
+            Ok(Vec::new())
+        };
+
+        stop.store(true, Ordering::Relaxed);
+        for (id, worker) in workers.into_iter().enumerate() {
+            worker?
+                .join()
+                .map_err(|_| SoakError::WorkerPanic(id))?
+                .map_err(|e| SoakError::Worker(id, e))?;
+        }
+        samples
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sample(elapsed: u64, rss_kib: u64, fds: usize) -> Sample {
+        let mut latencies = Histogram::default();
+        latencies.record(Duration::from_micros(10));
+        Sample {
+            elapsed: Duration::from_secs(elapsed),
+            rss_kib,
+            fds,
+            configs: elapsed,
+            latencies,
+        }
+    }
+
+    fn config() -> SoakConfig {
+        SoakConfig {
+            warmup: Duration::from_secs(10),
+            ..SoakConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_histogram() {
+        let mut histogram = Histogram::default();
+        assert_eq!(histogram.percentile(0.5), Duration::ZERO);
+        for ns in 1..=1000 {
+            histogram.record(Duration::from_nanos(ns));
+        }
+        assert_eq!(histogram.count(), 1000);
+        let p50 = histogram.percentile(0.5).as_nanos();
+        assert!((448..=500).contains(&p50), "{p50}");
+        let p99 = histogram.percentile(0.99).as_nanos();
+        assert!((896..=990).contains(&p99), "{p99}");
+        assert_eq!(histogram.max(), Duration::from_nanos(1000));
+        assert!(histogram.percentile(1.0) <= histogram.max());
+        for bucket in 0..BUCKETS {
+            assert_eq!(Histogram::bucket(Histogram::lower_bound(bucket)), bucket);
+        }
+    }
+
+    #[test]
+    fn test_check() {
+        let config = config();
+        // growth during the warmup does not matter
+        let mut samples: Vec<_> = (0..10)
+            .map(|s| sample(s, 1000 * (s + 1), 10 + 2 * s as usize))
+            .collect();
+        assert_eq!(check(&samples, &config), Err(SoakFailure::TooShort));
+        samples.extend((10..100).map(|s| sample(s, 100_000 + (s % 3) * 100, 30)));
+        assert_eq!(check(&samples, &config), Ok(()));
+
+        let mut leaky = samples.clone();
+        leaky.push(sample(100, 100_000, 40));
+        assert!(matches!(
+            check(&leaky, &config),
+            Err(SoakFailure::FdLeak { .. })
+        ));
+
+        let mut stalled = samples.clone();
+        stalled[50].latencies = Histogram::default();
+        assert_eq!(
+            check(&stalled, &config),
+            Err(SoakFailure::Stalled(Duration::from_secs(50)))
+        );
+
+        let mut growing = samples.clone();
+        growing.push(sample(100, 200_000, 30));
+        assert!(matches!(
+            check(&growing, &config),
+            Err(SoakFailure::RssGrowth { .. })
+        ));
+
+        // growing slower than the slack, but steadily
+        let creeping: Vec<_> = (0..100).map(|s| sample(s, 100_000 + 100 * s, 30)).collect();
+        assert!(matches!(
+            check(&creeping, &config),
+            Err(SoakFailure::MonotonicRss { .. })
+        ));
+    }
+
+    #[test]
+    fn test_soak() {
+        let config = SoakConfig {
+            duration: Duration::from_millis(500),
+            churn_interval: Duration::from_millis(20),
+            sample_interval: Duration::from_millis(100),
+            warmup: Duration::ZERO,
+            profile: TrafficProfile::default(),
+            ..SoakConfig::default()
+        };
+        let samples = soak(&config, |_| {}).unwrap();
+        assert!(samples.len() >= 4, "{samples:?}");
+        assert!(
+            samples
+                .iter()
+                .all(|sample| sample.fds > 0 && sample.rss_kib > 0)
+        );
+        assert!(samples.last().unwrap().configs > 0);
+        assert!(samples.iter().any(|sample| sample.latencies.count() > 0));
+    }
+}
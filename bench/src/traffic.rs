@@ -0,0 +1,347 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Synthetic traffic: an overlay of peered VPCs, and batches of packets between them
+//!
+//! VPC `i` has VNI `1000 + i` and peers with VPC `i + 1` (the last VPC peers with the first). In
+//! the peering, VPC `i` exposes `10.i.0.0/17` and VPC `i + 1` exposes `10.(i + 1).128.0/17`. Each
+//! flow goes from the first prefix of some VPC to the second prefix of the next VPC, so that all
+//! the generated packets are allowed by the flow filter.
+
+use config::ConfigError;
+use config::external::overlay::vpc::{Vpc, VpcTable};
+use config::external::overlay::vpcpeering::{VpcExpose, VpcManifest, VpcPeering, VpcPeeringTable};
+use config::external::overlay::{Overlay, ValidatedOverlay};
+use lpm::prefix::{Prefix, PrefixWithOptionalPorts};
+use net::buffer::TestBuffer;
+use net::headers::builder::HeaderStack;
+use net::ipv4::addr::UnicastIpv4Addr;
+use net::packet::{Packet, VpcDiscriminant};
+use net::parse::DeParse;
+use net::udp::port::UdpPort;
+use net::vxlan::Vni;
+use std::fmt::Display;
+use std::net::{IpAddr, Ipv4Addr};
+
+/// The VNI of the first VPC
+const FIRST_VNI: u32 = 1000;
+/// The maximum number of VPCs, so that their names, ids and prefixes remain valid
+pub(crate) const MAX_VPCS: u8 = 100;
+/// The length of the Ethernet, IPv4 and UDP headers of the generated packets
+const HEADERS_LEN: u16 = 14 + 20 + 8;
+/// The destination port of the generated packets
+const DST_PORT: u16 = 5001;
+
+/// The sizes of the generated packets (Ethernet frames, without FCS)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum PacketSizes {
+    /// All packets have the same size
+    Fixed(u16),
+    /// Simple IMIX: 7 packets of 64 bytes, 4 of 576 bytes and 1 of 1500 bytes out of 12
+    Imix,
+}
+
+/// How the traffic is spread over the source VPCs
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VniDistribution {
+    /// All VPCs send the same share of the flows
+    Uniform,
+    /// The first VPC sends the given percentage of the flows, the others share the rest
+    Skewed { hot_percent: u8 },
+}
+
+/// The NAT mode used by the source side of the peerings
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NatMode {
+    None,
+    /// Source prefix `10.i.0.0/17` is exposed as `100.i.0.0/17`
+    Static,
+    /// Source prefix `10.i.0.0/17` is masqueraded behind `100.i.0.0/24`
+    Masquerade,
+}
+
+/// The parameters of the synthetic traffic
+#[derive(Clone, Debug)]
+pub struct TrafficProfile {
+    /// Number of VPCs, between 2 and 100
+    pub vpcs: u8,
+    /// Number of distinct flows (5-tuples)
+    pub flows: usize,
+    pub packet_sizes: PacketSizes,
+    pub vni_distribution: VniDistribution,
+    pub nat: NatMode,
+    /// Number of packets per batch
+    pub batch_size: usize,
+    /// Seed of the pseudo-random generator, so that runs are reproducible
+    pub seed: u64,
+}
+
+impl Default for TrafficProfile {
+    fn default() -> Self {
+        Self {
+            vpcs: 4,
+            flows: 1024,
+            packet_sizes: PacketSizes::Fixed(64),
+            vni_distribution: VniDistribution::Uniform,
+            nat: NatMode::None,
+            batch_size: 64,
+            seed: 0x5eed,
+        }
+    }
+}
+
+impl Display for TrafficProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "vpcs={}/flows={}/size=", self.vpcs, self.flows)?;
+        match self.packet_sizes {
+            PacketSizes::Fixed(size) => write!(f, "{size}")?,
+            PacketSizes::Imix => write!(f, "imix")?,
+        }
+        match self.vni_distribution {
+            VniDistribution::Uniform => {}
+            VniDistribution::Skewed { hot_percent } => write!(f, "/hot={hot_percent}%")?,
+        }
+        match self.nat {
+            NatMode::None => Ok(()),
+            NatMode::Static => write!(f, "/nat=static"),
+            NatMode::Masquerade => write!(f, "/nat=masquerade"),
+        }
+    }
+}
+
+/// The prefix `a.b.c.0/len`
+fn prefix(a: u8, b: u8, c: u8, len: u8) -> PrefixWithOptionalPorts {
+    let address = IpAddr::V4(Ipv4Addr::new(a, b, c, 0));
+    Prefix::try_from((address, len))
+        .unwrap_or_else(|_| unreachable!())
+        .into()
+}
+
+fn vpc_name(index: u8) -> String {
+    format!("vpc-{index}")
+}
+
+fn vpc_vni(index: u8) -> Vni {
+    Vni::new_checked(FIRST_VNI + u32::from(index)).unwrap_or_else(|_| unreachable!())
+}
+
+impl TrafficProfile {
+    /// The index of the VPC that VPC `index` sends its traffic to
+    fn peer_of(&self, index: u8) -> u8 {
+        (index + 1) % self.vpcs
+    }
+
+    /// Build the overlay that the traffic is meant for
+    ///
+    /// # Errors
+    ///
+    /// Fails if the number of VPCs is out of range, or if the resulting overlay is invalid.
+    pub fn overlay(&self) -> Result<ValidatedOverlay, ConfigError> {
+        if !(2..=MAX_VPCS).contains(&self.vpcs) {
+            return Err(ConfigError::Invalid(format!(
+                "traffic profile needs between 2 and {MAX_VPCS} VPCs, got {}",
+                self.vpcs
+            )));
+        }
+        let mut vpc_table = VpcTable::new();
+        for index in 0..self.vpcs {
+            let vpc = Vpc::new(
+                &vpc_name(index),
+                &format!("VPC{index:02}"),
+                vpc_vni(index).as_u32(),
+            )?;
+            vpc_table.add(vpc)?;
+        }
+
+        let mut peering_table = VpcPeeringTable::new();
+        for index in 0..self.vpcs {
+            let peer = self.peer_of(index);
+            let source = VpcExpose::empty().ip(prefix(10, index, 0, 17));
+            let source = match self.nat {
+                NatMode::None => source,
+                NatMode::Static => source
+                    .make_static_nat()?
+                    .as_range(prefix(100, index, 0, 17))?,
+                NatMode::Masquerade => source
+                    .make_masquerade(None)?
+                    .as_range(prefix(100, index, 0, 24))?,
+            };
+            let destination = VpcExpose::empty().ip(prefix(10, peer, 128, 17));
+            peering_table.add(VpcPeering::with_default_group(
+                &format!("{}--{}", vpc_name(index), vpc_name(peer)),
+                VpcManifest::with_exposes(&vpc_name(index), vec![source]),
+                VpcManifest::with_exposes(&vpc_name(peer), vec![destination]),
+            ))?;
+        }
+        Overlay::new(vpc_table, peering_table).validate()
+    }
+}
+
+/// A flow of the synthetic traffic
+#[derive(Clone, Debug)]
+struct Flow {
+    vni: Vni,
+    src: UnicastIpv4Addr,
+    dst: Ipv4Addr,
+    src_port: UdpPort,
+}
+
+/// A small xorshift pseudo-random generator, so that traffic does not depend on an external crate
+#[derive(Debug)]
+pub(crate) struct Rng(u64);
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed | 1)
+    }
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    #[allow(clippy::cast_possible_truncation)] // bounded by the modulo
+    pub(crate) fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+/// Generator of batches of UDP packets following a [`TrafficProfile`]
+///
+/// Packets are generated as the ingress stage would hand them to the rest of the pipeline: parsed,
+/// with their source VPC discriminant set.
+#[derive(Debug)]
+pub struct TrafficGenerator {
+    flows: Vec<Flow>,
+    sizes: Vec<u16>,
+    batch_size: usize,
+    rng: Rng,
+}
+
+impl TrafficGenerator {
+    /// Create a generator for some traffic profile
+    #[must_use]
+    pub fn new(profile: &TrafficProfile) -> Self {
+        let mut rng = Rng::new(profile.seed);
+        let vpcs = profile.vpcs.clamp(2, MAX_VPCS);
+        let flows = (0..profile.flows.max(1))
+            .map(|index| {
+                let src_vpc = match profile.vni_distribution {
+                    VniDistribution::Uniform => rng.below(usize::from(vpcs)),
+                    VniDistribution::Skewed { hot_percent } => {
+                        if rng.below(100) < usize::from(hot_percent) {
+                            0
+                        } else {
+                            1 + rng.below(usize::from(vpcs - 1))
+                        }
+                    }
+                };
+                #[allow(clippy::cast_possible_truncation)] // src_vpc < vpcs
+                let src_vpc = src_vpc as u8;
+                let dst_vpc = (src_vpc + 1) % vpcs;
+                #[allow(clippy::cast_possible_truncation)] // masked
+                let (high, low) = (((index >> 8) & 0x7f) as u8, (index & 0xff) as u8);
+                #[allow(clippy::cast_possible_truncation)] // bounded by the modulo
+                let src_port = 1024 + (index % 64_000) as u16;
+                Flow {
+                    vni: vpc_vni(src_vpc),
+                    src: UnicastIpv4Addr::new(Ipv4Addr::new(10, src_vpc, high, low))
+                        .unwrap_or_else(|_| unreachable!()),
+                    dst: Ipv4Addr::new(10, dst_vpc, 128 | high, low),
+                    src_port: UdpPort::new_checked(src_port).unwrap_or_else(|_| unreachable!()),
+                }
+            })
+            .collect();
+        let sizes = match profile.packet_sizes {
+            PacketSizes::Fixed(size) => vec![size],
+            PacketSizes::Imix => [[64; 7].as_slice(), &[576; 4], &[1500]].concat(),
+        };
+        Self {
+            flows,
+            sizes,
+            batch_size: profile.batch_size.max(1),
+            rng,
+        }
+    }
+
+    /// Build a packet of some flow
+    fn packet(flow: &Flow, size: u16) -> Packet<TestBuffer> {
+        let payload = vec![0u8; usize::from(size.saturating_sub(HEADERS_LEN))];
+        let headers = HeaderStack::new()
+            .eth(|_| {})
+            .ipv4(|ipv4| {
+                ipv4.set_source(flow.src);
+                ipv4.set_destination(flow.dst);
+                ipv4.set_ttl(64);
+            })
+            .udp(|udp| {
+                udp.set_source(flow.src_port);
+                udp.set_destination(
+                    UdpPort::new_checked(DST_PORT).unwrap_or_else(|_| unreachable!()),
+                );
+            })
+            .build_headers_with_payload(&payload)
+            .unwrap_or_else(|e| unreachable!("failed to build headers: {e}"));
+        let mut frame = vec![0u8; usize::from(headers.size().get())];
+        headers
+            .deparse(&mut frame)
+            .unwrap_or_else(|e| unreachable!("failed to deparse headers: {e:?}"));
+        frame.extend_from_slice(&payload);
+
+        let mut packet = Packet::new(TestBuffer::from_raw_data(&frame))
+            .unwrap_or_else(|e| unreachable!("failed to parse generated packet: {e:?}"));
+        packet.meta_mut().src_vpcd = Some(VpcDiscriminant::from_vni(flow.vni));
+        packet
+    }
+
+    /// Generate the next batch of packets, picking flows and sizes at random
+    pub fn batch(&mut self) -> Vec<Packet<TestBuffer>> {
+        (0..self.batch_size)
+            .map(|_| {
+                let flow = &self.flows[self.rng.below(self.flows.len())];
+                let size = self.sizes[self.rng.below(self.sizes.len())];
+                Self::packet(flow, size)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_traffic_generator() {
+        let profile = TrafficProfile {
+            packet_sizes: PacketSizes::Imix,
+            vni_distribution: VniDistribution::Skewed { hot_percent: 100 },
+            ..Default::default()
+        };
+        let mut generator = TrafficGenerator::new(&profile);
+        let batch = generator.batch();
+        assert_eq!(batch.len(), profile.batch_size);
+        for packet in &batch {
+            assert!([64, 576, 1500].contains(&packet.total_len()));
+            assert_eq!(
+                packet.meta().src_vpcd,
+                Some(VpcDiscriminant::from_vni(vpc_vni(0)))
+            );
+        }
+    }
+
+    #[test]
+    fn test_traffic_overlay() {
+        for nat in [NatMode::None, NatMode::Static, NatMode::Masquerade] {
+            let profile = TrafficProfile {
+                nat,
+                ..Default::default()
+            };
+            let overlay = profile.overlay().unwrap();
+            assert_eq!(overlay.vpc_table().len(), usize::from(profile.vpcs));
+        }
+        let profile = TrafficProfile {
+            vpcs: 1,
+            ..Default::default()
+        };
+        assert!(profile.overlay().is_err());
+    }
+}
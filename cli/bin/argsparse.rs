@@ -3,7 +3,10 @@
 
 //! Adds main parser for command arguments
 
-use dataplane_cli::cliproto::{RequestArgs, RouteProtocol};
+use crate::export::Redaction;
+use dataplane_cli::cliproto::{
+    CaptureTarget, FlagValue, RequestArgs, RouteProtocol, TransportProtocol,
+};
 use std::collections::HashMap;
 use std::net::IpAddr;
 use std::str::FromStr;
@@ -28,12 +31,20 @@ pub enum ArgsError {
     BadValue(String),
     #[error("Unknown protocol '{0}'")]
     UnknownProtocol(String),
+    #[error("Unknown transport '{0}'")]
+    UnknownTransport(String),
+    #[error("Unknown redaction '{0}'")]
+    UnknownRedaction(String),
+    #[error("Unknown feature flag value '{0}'")]
+    UnknownFlagValue(String),
 }
 
 #[derive(Default, Debug)]
 pub struct CliArgs {
     pub connpath: Option<String>,     /* connection path; this is local */
     pub bind_address: Option<String>, /* address to bind unix sock to */
+    pub file: Option<String>,         /* file to export state to; this is local */
+    pub redact: Option<Redaction>,    /* addresses to redact from exported state */
     pub remote: RequestArgs,          /* args to send to remote */
 }
 
@@ -68,6 +79,31 @@ impl CliArgs {
                 return Err(ArgsError::BadPrefixFormat(prefix.clone()));
             }
         }
+        if let Some(addr) = args_map.remove("src-address") {
+            args.remote.src_address =
+                Some(IpAddr::from_str(&addr).map_err(|_| ArgsError::BadValue(addr))?);
+        }
+        if let Some(addr) = args_map.remove("dst-address") {
+            args.remote.dst_address =
+                Some(IpAddr::from_str(&addr).map_err(|_| ArgsError::BadValue(addr))?);
+        }
+        if let Some(port) = args_map.remove("src-port") {
+            args.remote.src_port =
+                Some(port.parse::<u16>().map_err(|_| ArgsError::BadValue(port))?);
+        }
+        if let Some(port) = args_map.remove("dst-port") {
+            args.remote.dst_port =
+                Some(port.parse::<u16>().map_err(|_| ArgsError::BadValue(port))?);
+        }
+        if let Some(transport) = args_map.remove("transport") {
+            if transport.is_empty() {
+                return Err(ArgsError::MissingValue("transport"));
+            }
+            args.remote.transport = Some(
+                TransportProtocol::from_str(&transport)
+                    .map_err(|_| ArgsError::UnknownTransport(transport))?,
+            );
+        }
         if let Some(path) = args_map.remove("path") {
             if path.is_empty() {
                 return Err(ArgsError::MissingValue("path"));
@@ -80,6 +116,20 @@ impl CliArgs {
             }
             args.bind_address = Some(path.clone());
         }
+        if let Some(file) = args_map.remove("file") {
+            if file.is_empty() {
+                return Err(ArgsError::MissingValue("file"));
+            }
+            args.file = Some(file);
+        }
+        if let Some(redact) = args_map.remove("redact") {
+            if redact.is_empty() {
+                return Err(ArgsError::MissingValue("redact"));
+            }
+            args.redact = Some(
+                Redaction::from_str(&redact).map_err(|_| ArgsError::UnknownRedaction(redact))?,
+            );
+        }
         if let Some(vrfid) = args_map.remove("vrfid") {
             if vrfid.is_empty() {
                 return Err(ArgsError::MissingValue("vrfid"));
@@ -111,6 +161,78 @@ impl CliArgs {
                     .map_err(|_| ArgsError::UnknownProtocol(protocol))?,
             );
         }
+        if let Some(flag) = args_map.remove("name") {
+            if flag.is_empty() {
+                return Err(ArgsError::MissingValue("name"));
+            }
+            args.remote.flag = Some(flag);
+        }
+        if let Some(value) = args_map.remove("value") {
+            if value.is_empty() {
+                return Err(ArgsError::MissingValue("value"));
+            }
+            args.remote.flag_value =
+                Some(FlagValue::from_str(&value).map_err(|_| ArgsError::UnknownFlagValue(value))?);
+        }
+        if let Some(tap) = args_map.remove("tap") {
+            if tap.is_empty() {
+                return Err(ArgsError::MissingValue("tap"));
+            }
+            args.remote.tap = Some(tap);
+        }
+        if let Some(port) = args_map.remove("port") {
+            args.remote.port = Some(port.parse::<u16>().map_err(|_| ArgsError::BadValue(port))?);
+        }
+        if let Some(count) = args_map.remove("count") {
+            args.remote.count = Some(
+                count
+                    .parse::<u64>()
+                    .map_err(|_| ArgsError::BadValue(count))?,
+            );
+        }
+        if let Some(file) = args_map.remove("pcapng") {
+            if file.is_empty() {
+                return Err(ArgsError::MissingValue("pcapng"));
+            }
+            args.remote.capture_to = Some(CaptureTarget::File(file));
+        }
+        if let Some(socket) = args_map.remove("stream") {
+            if socket.is_empty() {
+                return Err(ArgsError::MissingValue("stream"));
+            }
+            args.remote.capture_to = Some(CaptureTarget::Socket(socket));
+        }
+        if let Some(namespace) = args_map.remove("namespace") {
+            if namespace.is_empty() {
+                return Err(ArgsError::MissingValue("namespace"));
+            }
+            args.remote.namespace = Some(namespace);
+        }
+        if let Some(report) = args_map.remove("report") {
+            if report.is_empty() {
+                return Err(ArgsError::MissingValue("report"));
+            }
+            args.remote.report = Some(report);
+        }
+        if let Some(levels) = args_map.remove("levels") {
+            if levels.is_empty() {
+                return Err(ArgsError::MissingValue("levels"));
+            }
+            args.remote.tracing = Some(levels);
+        }
+        if let Some(file) = args_map.remove("mrt") {
+            if file.is_empty() {
+                return Err(ArgsError::MissingValue("mrt"));
+            }
+            args.remote.dump_to = Some(file);
+        }
+        if let Some(genid) = args_map.remove("genid") {
+            args.remote.genid = Some(
+                genid
+                    .parse::<i64>()
+                    .map_err(|_| ArgsError::BadValue(genid))?,
+            );
+        }
         if args_map.is_empty() {
             Ok(args)
         } else {
@@ -3,6 +3,7 @@
 
 //! Cmd line to start cli
 
+use crate::output::OutputFormat;
 use clap::Parser;
 
 pub(crate) const DEFAULT_DATAPLANE_PATH: &str = "/var/run/dataplane/cli.sock";
@@ -34,4 +35,32 @@ pub struct Cmdline {
         help = "Execute the provided command and exit. Multiple commands can be specified"
     )]
     pub command: Vec<String>,
+
+    #[arg(
+        long,
+        value_name = "Token file",
+        help = "File with the token to present to the dataplane, to see the VPCs of its view only"
+    )]
+    pub token_file: Option<String>,
+
+    #[arg(
+        long,
+        short,
+        value_enum,
+        default_value_t = OutputFormat::Table,
+        help = "Format to print the results of commands in"
+    )]
+    pub output: OutputFormat,
+}
+
+impl Cmdline {
+    /// Read the token to present to the dataplane, if any
+    pub fn token(&self) -> Result<Option<String>, String> {
+        let Some(path) = &self.token_file else {
+            return Ok(None);
+        };
+        let token = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read token file {path}: {e}"))?;
+        Ok(Some(token.trim().to_string()))
+    }
 }
@@ -4,7 +4,8 @@
 //! Builds our command tree for dataplane
 
 use crate::cmdtree::{Node, NodeArg};
-use dataplane_cli::cliproto::{CliAction, RouteProtocol};
+use crate::export::Redaction;
+use dataplane_cli::cliproto::{CliAction, FlagValue, RouteProtocol, TransportProtocol};
 use std::convert::AsRef;
 use strum::IntoEnumIterator;
 
@@ -223,9 +224,16 @@ fn cmd_show_dpdk() -> Node {
 }
 fn cmd_show_kernel() -> Node {
     let mut root = Node::new("kernel");
-    root += Node::new("interfaces").desc("Kernel interface status");
+    root += Node::new("interfaces")
+        .desc("Kernel interface status")
+        .action(CliAction::ShowKernelInterfaces);
     root
 }
+fn cmd_show_interfaces() -> Node {
+    Node::new("interfaces")
+        .desc("Show the kernel interfaces, required vs observed")
+        .action(CliAction::ShowKernelInterfaces)
+}
 fn cmd_show_tracing() -> Node {
     let mut root = Node::new("tracing");
     root += Node::new("targets")
@@ -266,6 +274,13 @@ fn cmd_show_masquerading() -> Node {
         .action(CliAction::ShowMasquerading);
     root
 }
+fn cmd_show_nat_alg() -> Node {
+    let mut root = Node::new("nat-alg");
+    root += Node::new("stats")
+        .desc("Show the counters of the NAT application layer gateways")
+        .action(CliAction::ShowNatAlg);
+    root
+}
 fn cmd_show_static_nat() -> Node {
     let mut root = Node::new("static-nat");
     root += Node::new("rules")
@@ -278,6 +293,7 @@ fn cmd_show_nat() -> Node {
     root += cmd_show_static_nat();
     root += cmd_show_port_forwarding_rules();
     root += cmd_show_masquerading();
+    root += cmd_show_nat_alg();
     root
 }
 fn cmd_show_config_summary() -> Node {
@@ -299,6 +315,50 @@ fn cmd_show_packet_stats() -> Node {
     root
 }
 
+fn cmd_show_tables() -> Node {
+    Node::new("tables")
+        .desc("Show the generation of the tables shared with the packet workers")
+        .action(CliAction::ShowTables)
+}
+
+fn cmd_show_state_store() -> Node {
+    Node::new("state-store")
+        .desc("Show the state learned at runtime and persisted across restarts")
+        .action(CliAction::ShowStateStore)
+        .arg("namespace")
+}
+
+fn cmd_show_crash_reports() -> Node {
+    Node::new("crash-reports")
+        .desc("List the reports of the crashes of the dataplane")
+        .action(CliAction::ShowCrashReports)
+}
+
+fn cmd_show_crash_report() -> Node {
+    Node::new("crash-report")
+        .desc("Show a report of a crash of the dataplane")
+        .action(CliAction::ShowCrashReport)
+        .arg("report")
+}
+
+fn cmd_show_microbursts() -> Node {
+    Node::new("microbursts")
+        .desc("Show the microbursts detected on the receive queues")
+        .action(CliAction::ShowMicrobursts)
+}
+
+fn cmd_show_feature_flags() -> Node {
+    Node::new("feature-flags")
+        .desc("Show the feature flags, their scope and their values")
+        .action(CliAction::ShowFeatureFlags)
+}
+
+fn cmd_show_capture() -> Node {
+    Node::new("capture")
+        .desc("Show the packet capture in progress")
+        .action(CliAction::ShowCapture)
+}
+
 fn cmd_show_tech() -> Node {
     Node::new("tech")
         .desc("Dump dataplanes state")
@@ -314,12 +374,20 @@ fn cmd_show() -> Node {
     root += cmd_show_routing();
     root += cmd_show_dpdk();
     root += cmd_show_kernel();
+    root += cmd_show_interfaces();
     root += cmd_show_tracing();
     root += cmd_show_flow_table();
     root += cmd_show_flow_filter();
     root += cmd_show_gateway();
     root += cmd_show_config_summary();
     root += cmd_show_packet_stats();
+    root += cmd_show_tables();
+    root += cmd_show_state_store();
+    root += cmd_show_crash_reports();
+    root += cmd_show_crash_report();
+    root += cmd_show_microbursts();
+    root += cmd_show_feature_flags();
+    root += cmd_show_capture();
     root += cmd_show_tech();
     root
 }
@@ -364,6 +432,25 @@ fn cmd_frrmi() -> Node {
     root
 }
 
+fn cmd_simulate_packet() -> Node {
+    let mut root = Node::new("simulate");
+    let mut packet = Node::new("packet")
+        .desc("Tell the fate of a synthetic packet, without transmitting it")
+        .action(CliAction::SimulatePacket)
+        .arg("src-address")
+        .arg("dst-address")
+        .arg("src-port")
+        .arg("dst-port")
+        .arg("vni")
+        .arg("ifname");
+
+    let mut arg = NodeArg::new("transport");
+    TransportProtocol::iter().for_each(|proto| arg.add_choice(proto.as_ref()));
+    packet = packet.arg_add(arg);
+    root += packet;
+    root
+}
+
 fn cmd_cpi_request_refresh() -> Node {
     let mut root = Node::new("request");
     root += Node::new("refresh")
@@ -377,11 +464,89 @@ fn cmd_cpi() -> Node {
     root
 }
 
+fn cmd_set() -> Node {
+    let mut root = Node::new("set");
+    let mut flag = Node::new("feature-flag")
+        .desc("Override a feature flag at runtime, or remove the override with value default")
+        .action(CliAction::SetFeatureFlag)
+        .arg("name");
+
+    let mut arg = NodeArg::new("value");
+    FlagValue::iter().for_each(|value| arg.add_choice(value.as_ref()));
+    flag = flag.arg_add(arg);
+    root += flag;
+    root += Node::new("tracing")
+        .desc("Set tracing levels at runtime, as levels=tag=level[,tag=level]")
+        .action(CliAction::SetTracing)
+        .arg("levels");
+    root
+}
+
+fn cmd_capture() -> Node {
+    let mut root = Node::new("capture");
+    root += Node::new("start")
+        .desc("Capture the packets matching a filter at the taps of the pipeline, to a new pcapng file or a unix socket of the capture directory")
+        .action(CliAction::StartCapture)
+        .arg("tap")
+        .arg("vni")
+        .arg("prefix")
+        .arg("address")
+        .arg("port")
+        .arg("count")
+        .arg("pcapng")
+        .arg("stream");
+    root += Node::new("stop")
+        .desc("Stop the packet capture in progress")
+        .action(CliAction::StopCapture);
+    root
+}
+
+fn cmd_bmp() -> Node {
+    let mut root = Node::new("bmp");
+    root += Node::new("dump")
+        .desc(
+            "Dump the recent BMP route monitoring messages kept, to an MRT file or as their routes",
+        )
+        .action(CliAction::DumpBmp)
+        .arg("count")
+        .arg("mrt");
+    root
+}
+
+fn cmd_config() -> Node {
+    let mut root = Node::new("config");
+    root += Node::new("rollback")
+        .desc("Roll back to a config applied before, by generation id")
+        .action(CliAction::RollbackConfig)
+        .arg("genid");
+    root
+}
+
+fn cmd_state_export() -> Node {
+    let mut root = Node::new("state");
+    let mut export = Node::new("export")
+        .desc("Export the dataplane state to a compressed archive, for bug reports")
+        .action(CliAction::ExportState)
+        .arg("file");
+
+    let mut arg = NodeArg::new("redact");
+    Redaction::iter().for_each(|redaction| arg.add_choice(redaction.as_ref()));
+    export = export.arg_add(arg);
+    root += export;
+    root
+}
+
 pub fn gw_cmd_tree() -> Node {
     let mut root = Node::new("");
     root += cmd_local();
     root += cmd_show();
     root += cmd_frrmi();
     root += cmd_cpi();
+    root += cmd_simulate_packet();
+    root += cmd_set();
+    root += cmd_capture();
+    root += cmd_bmp();
+    root += cmd_config();
+    root += cmd_state_export();
     root
 }
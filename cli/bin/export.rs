@@ -0,0 +1,311 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Exports the operational state of the dataplane as a compressed archive,
+//! to be attached to bug reports.
+
+use dataplane_cli::cliproto::{CliAction, CliLocalError, CliRequest, CliResponse, RequestArgs};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::net::IpAddr;
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use strum::{AsRefStr, EnumIter, EnumString};
+use thiserror::Error;
+
+/// The files of the archive, and the requests whose output they store
+const SECTIONS: &[(&str, CliAction)] = &[
+    ("config/internal.txt", CliAction::ShowConfigInternal),
+    ("config/history.txt", CliAction::ShowConfigSummary),
+    ("config/vpcs.txt", CliAction::ShowVpc),
+    ("config/vpc-peerings.txt", CliAction::ShowVpcPeerings),
+    ("router/interfaces.txt", CliAction::ShowRouterInterfaces),
+    (
+        "router/interface-addresses.txt",
+        CliAction::ShowRouterInterfaceAddresses,
+    ),
+    ("router/vrfs.txt", CliAction::ShowRouterVrfs),
+    ("router/adjacencies.txt", CliAction::ShowAdjacencies),
+    ("router/fib-ipv4.txt", CliAction::ShowRouterIpv4FibEntries),
+    ("router/fib-ipv6.txt", CliAction::ShowRouterIpv6FibEntries),
+    ("router/fib-groups.txt", CliAction::ShowRouterIpv4FibGroups),
+    ("router/events.txt", CliAction::RouterEventLog),
+    ("router/cpi-stats.txt", CliAction::ShowCpiStats),
+    ("router/frrmi-stats.txt", CliAction::ShowFrrmiStats),
+    ("nf/flow-table.txt", CliAction::ShowFlowTable),
+    ("nf/flow-filter.txt", CliAction::ShowFlowFilter),
+    ("nf/static-nat.txt", CliAction::ShowStaticNat),
+    ("nf/port-forwarding.txt", CliAction::ShowPortForwarding),
+    ("nf/masquerading.txt", CliAction::ShowMasquerading),
+    ("nf/nat-alg.txt", CliAction::ShowNatAlg),
+    ("stats/packets.txt", CliAction::ShowPacketStats),
+    ("stats/tables.txt", CliAction::ShowTables),
+    ("stats/microbursts.txt", CliAction::ShowMicrobursts),
+    ("state/store.txt", CliAction::ShowStateStore),
+    ("state/crash-reports.txt", CliAction::ShowCrashReports),
+    ("config/feature-flags.txt", CliAction::ShowFeatureFlags),
+];
+
+/// The addresses to hide from an exported state
+#[derive(AsRefStr, EnumString, EnumIter, Debug, Clone, Copy, PartialEq, Eq)]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+pub enum Redaction {
+    Ip,
+    Mac,
+    Addresses,
+}
+
+impl Redaction {
+    fn ip(self) -> bool {
+        matches!(self, Redaction::Ip | Redaction::Addresses)
+    }
+    fn mac(self) -> bool {
+        matches!(self, Redaction::Mac | Redaction::Addresses)
+    }
+}
+
+/// Errors which abort a state export
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("Failed to write archive: {0}")]
+    Archive(#[from] std::io::Error),
+    #[error("Failed to query dataplane: {0}")]
+    Request(#[from] CliLocalError),
+}
+
+/// Replaces addresses with aliases. The same address always gets the same alias,
+/// so that the relations between the objects of an exported state are preserved.
+struct Redactor {
+    redaction: Redaction,
+    aliases: HashMap<String, String>,
+    counts: HashMap<&'static str, usize>,
+}
+
+impl Redactor {
+    fn new(redaction: Redaction) -> Self {
+        Self {
+            redaction,
+            aliases: HashMap::new(),
+            counts: HashMap::new(),
+        }
+    }
+
+    fn is_address_char(c: char) -> bool {
+        c.is_ascii_hexdigit() || c == '.' || c == ':'
+    }
+
+    fn is_mac(word: &str) -> bool {
+        let octets: Vec<_> = word.split(':').collect();
+        octets.len() == 6
+            && octets
+                .iter()
+                .all(|o| o.len() == 2 && o.chars().all(|c| c.is_ascii_hexdigit()))
+    }
+
+    /// The alias of a word, if it is an address to redact
+    fn alias(&mut self, word: &str) -> Option<String> {
+        let (kind, key) = if self.redaction.mac() && Self::is_mac(word) {
+            if word.chars().all(|c| c == '0' || c == ':') {
+                return None;
+            }
+            ("mac", word.to_ascii_lowercase())
+        } else if self.redaction.ip() {
+            let address: IpAddr = word.parse().ok()?;
+            if address.is_unspecified() || address.is_loopback() {
+                return None;
+            }
+            let kind = if address.is_ipv4() { "ipv4" } else { "ipv6" };
+            (kind, address.to_string())
+        } else {
+            return None;
+        };
+        if let Some(alias) = self.aliases.get(&key) {
+            return Some(alias.clone());
+        }
+        let count = self.counts.entry(kind).or_default();
+        *count += 1;
+        let alias = format!("{kind}-{count}");
+        self.aliases.insert(key, alias.clone());
+        Some(alias)
+    }
+
+    /// Split the port off an IPv4 address followed by one, e.g. `192.168.1.1:179`. IPv6
+    /// addresses are followed by a port only when in brackets, which are not part of words.
+    fn split_port(word: &str) -> Option<(&str, &str)> {
+        let (address, port) = word.rsplit_once(':')?;
+        let is_port = !port.is_empty() && port.chars().all(|c| c.is_ascii_digit());
+        (is_port && address.contains('.') && !address.contains(':'))
+            .then(|| word.split_at(address.len()))
+    }
+
+    fn redact_word(&mut self, word: &str, out: &mut String) {
+        // a word may end with punctuation, or be glued to a preceding colon
+        let trimmed = word.trim_end_matches(['.', ':']);
+        let suffix = &word[trimmed.len()..];
+        let leading = trimmed.len() - trimmed.trim_start_matches(':').len();
+        for start in [0, leading] {
+            let candidate = &trimmed[start..];
+            let (address, port) = Self::split_port(candidate).unwrap_or((candidate, ""));
+            let alias = self.alias(candidate).map(|alias| (alias, "")).or_else(|| {
+                (!port.is_empty())
+                    .then(|| self.alias(address))
+                    .flatten()
+                    .map(|alias| (alias, port))
+            });
+            if let Some((alias, port)) = alias {
+                out.push_str(&trimmed[..start]);
+                out.push_str(&alias);
+                out.push_str(port);
+                out.push_str(suffix);
+                return;
+            }
+        }
+        out.push_str(word);
+    }
+
+    fn redact(&mut self, text: &str) -> String {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(start) = rest.find(Self::is_address_char) {
+            out.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let end = rest
+                .find(|c| !Self::is_address_char(c))
+                .unwrap_or(rest.len());
+            let (word, tail) = rest.split_at(end);
+            self.redact_word(word, &mut out);
+            rest = tail;
+        }
+        out.push_str(rest);
+        out
+    }
+}
+
+/// Query the dataplane for the contents of a section
+fn query(
+    sock: &UnixDatagram,
+    action: CliAction,
+    token: Option<&str>,
+) -> Result<Result<String, String>, CliLocalError> {
+    CliRequest::new(action, RequestArgs::default())
+        .with_token(token.map(str::to_owned))
+        .send(sock)?;
+    let response = CliResponse::recv_sync(sock)?;
+    Ok(response.result.map_err(|e| e.to_string()))
+}
+
+fn append(
+    archive: &mut tar::Builder<GzEncoder<File>>,
+    path: &Path,
+    data: &str,
+    mtime: u64,
+) -> std::io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    archive.append_data(&mut header, path, data.as_bytes())
+}
+
+/// The path of the archive if the user does not provide one
+#[must_use]
+pub fn default_archive_path() -> PathBuf {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    PathBuf::from(format!("dataplane-state-{now}.tar.gz"))
+}
+
+/// Gather the state of the dataplane and store it in a gzipped tarball at `path`.
+/// Sections the dataplane fails to provide are listed in the manifest of the archive,
+/// and do not abort the export. Requests present `token`, if any, to be scoped to its view.
+pub fn export_state(
+    sock: &UnixDatagram,
+    path: &Path,
+    redaction: Option<Redaction>,
+    token: Option<&str>,
+) -> Result<(), ExportError> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let root = PathBuf::from(format!("dataplane-state-{now}"));
+    let mut redactor = redaction.map(Redactor::new);
+
+    let mut manifest = String::from("Dataplane state export\n");
+    let _ = writeln!(manifest, "time: {now}");
+    let _ = writeln!(
+        manifest,
+        "redaction: {}",
+        redaction.map_or("none", |r| r.as_ref())
+    );
+    let _ = writeln!(manifest, "sections:");
+
+    let mut archive =
+        tar::Builder::new(GzEncoder::new(File::create(path)?, Compression::default()));
+    for (file, action) in SECTIONS {
+        match query(sock, *action, token)? {
+            Ok(data) => {
+                let data = match redactor.as_mut() {
+                    Some(redactor) => redactor.redact(&data),
+                    None => data,
+                };
+                append(&mut archive, &root.join(file), &data, now)?;
+                let _ = writeln!(manifest, "  {file}: ok");
+            }
+            Err(e) => {
+                let _ = writeln!(manifest, "  {file}: failed: {e}");
+            }
+        }
+    }
+    append(&mut archive, &root.join("MANIFEST"), &manifest, now)?;
+    archive.into_inner()?.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redact_addresses() {
+        let mut redactor = Redactor::new(Redaction::Addresses);
+        let text = "route 10.0.0.0/24 via 192.168.1.1 dev eth0 mac 02:00:00:00:00:01\n\
+                    neighbor 192.168.1.1: 02:00:00:00:00:01, fe80::1%eth0, gw:2001:db8::1.\n\
+                    any 0.0.0.0/0 lo 127.0.0.1 null 00:00:00:00:00:00 time 12:34:56 v0.23.1";
+        let redacted = redactor.redact(text);
+        assert_eq!(
+            redacted,
+            "route ipv4-1/24 via ipv4-2 dev eth0 mac mac-1\n\
+             neighbor ipv4-2: mac-1, ipv6-1%eth0, gw:ipv6-2.\n\
+             any 0.0.0.0/0 lo 127.0.0.1 null 00:00:00:00:00:00 time 12:34:56 v0.23.1"
+        );
+    }
+
+    #[test]
+    fn redact_selectively() {
+        let text = "10.0.0.1 02:00:00:00:00:01";
+        assert_eq!(
+            Redactor::new(Redaction::Ip).redact(text),
+            "ipv4-1 02:00:00:00:00:01"
+        );
+        assert_eq!(Redactor::new(Redaction::Mac).redact(text), "10.0.0.1 mac-1");
+    }
+
+    #[test]
+    fn redact_addresses_with_ports() {
+        let mut redactor = Redactor::new(Redaction::Addresses);
+        let text = "neighbor 192.168.1.1:179 [2001:db8::1]:179 up, peer 192.168.1.1 \
+                    listen 0.0.0.0:179 [::]:179 at 12:34:56 local 10.0.0.1:40000.";
+        assert_eq!(
+            redactor.redact(text),
+            "neighbor ipv4-1:179 [ipv6-1]:179 up, peer ipv4-1 \
+             listen 0.0.0.0:179 [::]:179 at 12:34:56 local ipv4-2:40000."
+        );
+    }
+}
@@ -15,8 +15,11 @@ use colored::Colorize;
 use concurrency::sync::Arc;
 use dataplane_cli::cliproto::CliLocalError;
 use dataplane_cli::cliproto::{CliAction, CliRequest, CliResponse};
+use export::{ExportError, default_archive_path, export_state};
+use output::OutputFormat;
 use std::io::stdin;
 use std::os::unix::net::UnixDatagram;
+use std::path::PathBuf;
 use terminal::{TermInput, Terminal};
 
 mod argsparse;
@@ -24,6 +27,8 @@ mod cmdline;
 mod cmdtree;
 mod cmdtree_dp;
 mod completions;
+mod export;
+mod output;
 mod terminal;
 
 #[rustfmt::skip]
@@ -65,14 +70,17 @@ fn process_cli_response(sock: &UnixDatagram) -> Result<String, String> {
 fn execute_remote_action(
     action: CliAction,       // action to perform
     args: &CliArgs,          // action arguments
+    command: &str,           // the command line, as typed
     terminal: &mut Terminal, // this terminal
 ) {
     if !terminal.is_connected() {
         print_err!("Not connnected to dataplane.");
         return;
     }
-    // build request
-    let request = CliRequest::new(action, args.remote.clone());
+    // build request, asking for the data in the format to print
+    let mut remote = args.remote.clone();
+    remote.format = terminal.output.into();
+    let request = CliRequest::new(action, remote).with_token(terminal.token.clone());
 
     // serialize it and send it
     if let Err(e) = request.send(&terminal.sock) {
@@ -84,15 +92,39 @@ fn execute_remote_action(
     }
 
     // receive and deserialize response, synchronously
-    match process_cli_response(&terminal.sock) {
-        Ok(data) => println!("{data}"),
-        Err(e) => print_err!("{e}"),
+    let result = process_cli_response(&terminal.sock);
+    terminal.output.print(command, &result);
+}
+
+fn execute_export_state(args: &CliArgs, terminal: &mut Terminal) {
+    if !terminal.is_connected() {
+        print_err!("Not connnected to dataplane.");
+        return;
+    }
+    let path = args
+        .file
+        .as_ref()
+        .map_or_else(default_archive_path, PathBuf::from);
+    match export_state(
+        &terminal.sock,
+        &path,
+        args.redact,
+        terminal.token.as_deref(),
+    ) {
+        Ok(()) => println!("State exported to {}", path.display()),
+        Err(e) => {
+            print_err!("{e}");
+            if matches!(e, ExportError::Request(CliLocalError::IoError(_))) {
+                terminal.connected(false);
+            }
+        }
     }
 }
 
 fn execute_action(
     action: CliAction, // action to perform
     args: &CliArgs,    // action arguments
+    command: &str,     // the command line, as typed
     cmdline: &Cmdline,
     terminal: &mut Terminal, // this terminal
 ) {
@@ -113,8 +145,9 @@ fn execute_action(
                 .unwrap_or_else(|| cmdline.bind_address.clone());
             terminal.connect(&bind_addr, &path);
         }
+        CliAction::ExportState => execute_export_state(args, terminal),
         // all others are remote
-        _ => execute_remote_action(action, args, terminal),
+        _ => execute_remote_action(action, args, command, terminal),
     }
 }
 
@@ -152,7 +185,7 @@ fn process_command(
     if let Some(node) = cmds.find_best(input.get_tokens()) {
         if let Some(action) = &node.action {
             if let Ok(args) = process_args(input) {
-                execute_action(*action, &args, cmdline, terminal);
+                execute_action(*action, &args, input.get_line(), cmdline, terminal);
             }
         } else if node.depth > 0 {
             print_err!("No action associated to command");
@@ -181,7 +214,10 @@ fn proc_cmdline_commands(
     }
     for cmd in input_cmds {
         if let Some(mut input) = terminal.proc_line(cmd) {
-            println!("{}{}", terminal.read_prompt(), input.get_line());
+            // echoing the commands would break the documents of the other formats
+            if terminal.output == OutputFormat::Table {
+                println!("{}{}", terminal.read_prompt(), input.get_line());
+            }
             process_command(terminal, cmds, cmdline, &mut input);
         }
     }
@@ -194,6 +230,14 @@ fn main() {
     // build command tree
     let cmdtree = Arc::new(gw_cmd_tree());
     let mut terminal = Terminal::new("dataplane", &cmdtree);
+    terminal.output = cmdline.output;
+    match cmdline.token() {
+        Ok(token) => terminal.token = token,
+        Err(e) => {
+            print_err!("{e}");
+            return;
+        }
+    }
 
     // if a command is specified, handle it and exit
     if !cmdline.command.is_empty() {
@@ -0,0 +1,95 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Output formats of the results of the commands executed remotely
+
+use crate::print_err;
+use clap::ValueEnum;
+use colored::Colorize;
+use dataplane_cli::cliproto::CliFormat;
+use serde::Serialize;
+
+/// The format to print the results of commands in
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// The text rendered by the dataplane, as is
+    #[default]
+    Table,
+    /// A JSON document per command, for scripts
+    Json,
+    /// A YAML document per command, for scripts
+    Yaml,
+}
+
+impl From<OutputFormat> for CliFormat {
+    fn from(format: OutputFormat) -> Self {
+        match format {
+            OutputFormat::Table => CliFormat::Text,
+            OutputFormat::Json => CliFormat::Json,
+            OutputFormat::Yaml => CliFormat::Yaml,
+        }
+    }
+}
+
+/// The result of a command, as printed in the JSON and YAML formats. The output is the document
+/// that the dataplane rendered in the requested format.
+#[derive(Debug, Serialize)]
+struct CommandOutput<'a, T> {
+    command: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<T>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'a str>,
+}
+
+impl<'a, T> CommandOutput<'a, T> {
+    fn new<E>(
+        command: &'a str,
+        result: &'a Result<String, String>,
+        parse: impl Fn(&str) -> Result<T, E>,
+    ) -> Result<Self, E> {
+        match result {
+            Ok(data) => Ok(Self {
+                command,
+                output: Some(parse(data)?),
+                error: None,
+            }),
+            Err(e) => Ok(Self {
+                command,
+                output: None,
+                error: Some(e),
+            }),
+        }
+    }
+}
+
+impl OutputFormat {
+    /// Print the result of a command
+    pub fn print(self, command: &str, result: &Result<String, String>) {
+        let rendered = match self {
+            OutputFormat::Table => {
+                match result {
+                    Ok(data) => println!("{data}"),
+                    Err(e) => print_err!("{e}"),
+                }
+                return;
+            }
+            OutputFormat::Json => {
+                CommandOutput::new(command, result, serde_json::from_str::<serde_json::Value>)
+                    .and_then(|output| serde_json::to_string_pretty(&output))
+                    .map_err(|e| e.to_string())
+            }
+            OutputFormat::Yaml => CommandOutput::new(
+                command,
+                result,
+                serde_yaml_ng::from_str::<serde_yaml_ng::Value>,
+            )
+            .and_then(|output| serde_yaml_ng::to_string(&output))
+            .map_err(|e| e.to_string()),
+        };
+        match rendered {
+            Ok(rendered) => println!("{rendered}"),
+            Err(e) => print_err!("Failed to format output: {e}"),
+        }
+    }
+}
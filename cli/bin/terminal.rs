@@ -4,6 +4,7 @@
 //! User terminal frontend
 
 use crate::cmdtree::Node;
+use crate::output::OutputFormat;
 use colored::Colorize;
 use dataplane_cli::cliproto::CLI_RX_BUFF_SIZE;
 use nix::sys::socket::{setsockopt, sockopt::RcvBuf};
@@ -72,6 +73,10 @@ pub struct Terminal {
     run: bool,
     connected: bool,
     pub sock: UnixDatagram,
+    /// The token to present with requests, to be scoped to its view
+    pub token: Option<String>,
+    /// The format to print the results of commands in
+    pub output: OutputFormat,
 }
 
 #[derive(Debug, Default)]
@@ -128,6 +133,8 @@ impl Terminal {
             run: true,
             connected: false,
             sock: UnixDatagram::unbound().expect("Failed to create unix socket"),
+            token: None,
+            output: OutputFormat::default(),
         };
         term.set_prompt();
         term
@@ -4,8 +4,12 @@
 //! Defines the cli protocol for the dataplane
 
 use crate::iocache::IoCache;
+use nix::sys::socket::{ControlMessageOwned, MsgFlags, UnixAddr, UnixCredentials, recvmsg};
 use rkyv::util::AlignedVec;
+use std::io::IoSliceMut;
+use std::os::fd::AsRawFd;
 use std::os::unix::net::SocketAddr;
+use std::path::Path;
 
 /// The [`AlignedVec`] flavour returned by [`rkyv::to_bytes`] (i.e. with its
 /// default alignment).  Deserialization buffers must use the same alignment
@@ -37,6 +41,33 @@ const CLI_MSG_CHUNK_SIZE: usize = 2048;
 // Socket snd/rx size. This is a recommendation as it can't be enforced 100%
 pub const CLI_RX_BUFF_SIZE: usize = CLI_MSG_CHUNK_SIZE * 8192;
 
+/// Version of the cli protocol. Every message starts with it (little endian), so that a cli and
+/// a dataplane speaking different versions tell so instead of misinterpreting each other's
+/// messages. It must be bumped on any change to the messages, e.g. a new [`CliAction`].
+pub const CLI_PROTOCOL_VERSION: u16 = 5;
+
+// Size of the protocol version heading every message
+const CLI_VERSION_LEN: usize = size_of::<u16>();
+
+/// Prepend the protocol version to a serialized message
+fn add_version(payload: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(CLI_VERSION_LEN + payload.len());
+    message.extend_from_slice(&CLI_PROTOCOL_VERSION.to_le_bytes());
+    message.extend_from_slice(payload);
+    message
+}
+
+/// Check the protocol version of a message and strip it
+fn strip_version(message: &[u8]) -> Result<&[u8], CliSerdeError> {
+    let Some((version, payload)) = message.split_first_chunk::<CLI_VERSION_LEN>() else {
+        return Err(CliSerdeError::Deserialize("truncated message".to_string()));
+    };
+    match u16::from_le_bytes(*version) {
+        CLI_PROTOCOL_VERSION => Ok(payload),
+        version => Err(CliSerdeError::Version(version)),
+    }
+}
+
 #[derive(
     AsRefStr,
     EnumString,
@@ -59,18 +90,114 @@ pub enum RouteProtocol {
     Bgp,
 }
 
+/// A transport protocol, as used to describe a synthetic flow
+#[derive(
+    AsRefStr,
+    EnumString,
+    Debug,
+    Clone,
+    Copy,
+    EnumIter,
+    PartialEq,
+    Eq,
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+)]
+#[strum(ascii_case_insensitive)]
+pub enum TransportProtocol {
+    Tcp,
+    Udp,
+    Icmp,
+}
+
+/// The value to set a feature flag to at runtime
+#[derive(
+    AsRefStr,
+    EnumString,
+    Debug,
+    Clone,
+    Copy,
+    EnumIter,
+    PartialEq,
+    Eq,
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+)]
+#[strum(ascii_case_insensitive, serialize_all = "lowercase")]
+pub enum FlagValue {
+    On,
+    Off,
+    /// Remove the runtime override of the flag
+    Default,
+}
+
+/// The format of the data in the response to a request
+#[derive(
+    AsRefStr,
+    EnumString,
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    EnumIter,
+    PartialEq,
+    Eq,
+    rkyv::Archive,
+    rkyv::Serialize,
+    rkyv::Deserialize,
+)]
+#[strum(ascii_case_insensitive, serialize_all = "lowercase")]
+pub enum CliFormat {
+    /// Human-readable text, usually tables
+    #[default]
+    Text,
+    /// A JSON document
+    Json,
+    /// A YAML document
+    Yaml,
+}
+
+/// Where a packet capture is written to
+#[derive(Debug, Clone, PartialEq, Eq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize)]
+pub enum CaptureTarget {
+    /// The name of a new pcapng file, in the capture directory of the dataplane
+    File(String),
+    /// The name of a unix stream socket in the capture directory of the dataplane, to stream
+    /// the capture to
+    Socket(String),
+}
+
 /// Arguments to a cli request
 #[derive(
     Debug, Default, Clone, PartialEq, Eq, rkyv::Archive, rkyv::Serialize, rkyv::Deserialize,
 )]
 #[allow(unused)]
 pub struct RequestArgs {
-    pub address: Option<IpAddr>,         /* an IP address */
-    pub prefix: Option<(IpAddr, u8)>,    /* an IP prefix */
-    pub vrfid: Option<u32>,              /* Id of a VRF */
-    pub vni: Option<u32>,                /* Vxlan vni */
-    pub ifname: Option<String>,          /* name of interface */
-    pub protocol: Option<RouteProtocol>, /* a type of route or routing protocol */
+    pub address: Option<IpAddr>,              /* an IP address */
+    pub prefix: Option<(IpAddr, u8)>,         /* an IP prefix */
+    pub vrfid: Option<u32>,                   /* Id of a VRF */
+    pub vni: Option<u32>,                     /* Vxlan vni */
+    pub ifname: Option<String>,               /* name of interface */
+    pub protocol: Option<RouteProtocol>,      /* a type of route or routing protocol */
+    pub src_address: Option<IpAddr>,          /* source IP address of a flow */
+    pub dst_address: Option<IpAddr>,          /* destination IP address of a flow */
+    pub src_port: Option<u16>,                /* source transport port of a flow */
+    pub dst_port: Option<u16>,                /* destination transport port of a flow */
+    pub transport: Option<TransportProtocol>, /* transport protocol of a flow */
+    pub flag: Option<String>,                 /* name of a feature flag */
+    pub flag_value: Option<FlagValue>,        /* value to set a feature flag to */
+    pub tap: Option<String>,                  /* pipeline tap to capture packets at */
+    pub port: Option<u16>,                    /* source or destination transport port */
+    pub count: Option<u64>,                   /* max number of packets or messages */
+    pub capture_to: Option<CaptureTarget>,    /* where to write a packet capture */
+    pub namespace: Option<String>,            /* namespace of the state store */
+    pub report: Option<String>,               /* name of a crash report */
+    pub tracing: Option<String>,              /* tracing levels, as tag=level[,tag=level] */
+    pub genid: Option<i64>,                   /* generation id of a config */
+    pub dump_to: Option<String>,              /* file to write a dump of messages to */
+    pub format: CliFormat,                    /* format of the data in the response */
 }
 
 /// A Cli request
@@ -79,6 +206,16 @@ pub struct RequestArgs {
 pub struct CliRequest {
     pub action: CliAction,
     pub args: RequestArgs,
+    pub token: Option<String>, /* token scoping the request to a view of the dataplane */
+}
+
+/// The sender of a cli request
+#[derive(Debug)]
+pub struct CliPeer {
+    /// The address to respond to
+    pub addr: SocketAddr,
+    /// The user id of the sending process, if the socket passes credentials
+    pub uid: Option<u32>,
 }
 
 #[derive(Error, Debug)]
@@ -87,6 +224,8 @@ pub enum CliSerdeError {
     Serialize(String),
     #[error("Deserialize error: {0}")]
     Deserialize(String),
+    #[error("Protocol version mismatch: peer speaks version {0}, expected {CLI_PROTOCOL_VERSION}")]
+    Version(u16),
 }
 
 /// Convenience trait for serializing / deserializing CLI protocol messages
@@ -102,11 +241,12 @@ pub trait CliSerialize: Sized {
 impl CliSerialize for CliRequest {
     fn serialize(&self) -> Result<Vec<u8>, CliSerdeError> {
         rkyv::to_bytes::<rkyv::rancor::Error>(self)
-            .map(|aligned| aligned.to_vec())
+            .map(|aligned| add_version(&aligned))
             .map_err(|e| CliSerdeError::Serialize(e.to_string()))
     }
 
     fn deserialize(buf: &[u8]) -> Result<Self, CliSerdeError> {
+        let buf = strip_version(buf)?;
         let mut aligned = SerializerVec::with_capacity(buf.len());
         aligned.extend_from_slice(buf);
         rkyv::from_bytes::<Self, rkyv::rancor::Error>(&aligned)
@@ -117,11 +257,12 @@ impl CliSerialize for CliRequest {
 impl CliSerialize for CliResponse {
     fn serialize(&self) -> Result<Vec<u8>, CliSerdeError> {
         rkyv::to_bytes::<rkyv::rancor::Error>(self)
-            .map(|aligned| aligned.to_vec())
+            .map(|aligned| add_version(&aligned))
             .map_err(|e| CliSerdeError::Serialize(e.to_string()))
     }
 
     fn deserialize(buf: &[u8]) -> Result<Self, CliSerdeError> {
+        let buf = strip_version(buf)?;
         let mut aligned = SerializerVec::with_capacity(buf.len());
         aligned.extend_from_slice(buf);
         rkyv::from_bytes::<Self, rkyv::rancor::Error>(&aligned)
@@ -137,12 +278,18 @@ pub enum CliError {
     NotFound(String),
     #[error("Not supported: {0}")]
     NotSupported(String),
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+    #[error("Invalid argument: {0}")]
+    InvalidArgument(String),
 }
 
 #[derive(Error, Debug)]
 pub enum CliLocalError {
     #[error("Serialization error: {0}")]
     Serialization(#[from] CliSerdeError),
+    #[error("Request of unsupported protocol version {1} from {0:?}")]
+    UnsupportedVersion(CliPeer, u16),
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
 }
@@ -160,7 +307,16 @@ pub struct CliResponse {
 impl CliRequest {
     #[must_use]
     pub fn new(action: CliAction, args: RequestArgs) -> Self {
-        Self { action, args }
+        Self {
+            action,
+            args,
+            token: None,
+        }
+    }
+    #[must_use]
+    pub fn with_token(mut self, token: Option<String>) -> Self {
+        self.token = token;
+        self
     }
     pub fn send(&self, sock: &UnixDatagram) -> Result<usize, CliLocalError> {
         let serialized = self.serialize()?;
@@ -172,6 +328,42 @@ impl CliRequest {
         let request = CliRequest::deserialize(&rx_buf[0..len])?;
         Ok((peer, request))
     }
+    /// Receive a request along with the credentials of its sender. Credentials are only
+    /// available if the socket has `SO_PASSCRED` set.
+    pub fn recv_from_peer(sock: &UnixDatagram) -> Result<(CliPeer, Self), CliLocalError> {
+        let mut rx_buf = vec![0u8; CLI_MSG_CHUNK_SIZE];
+        let mut iov = [IoSliceMut::new(rx_buf.as_mut())];
+        let mut cmsg = nix::cmsg_space!(UnixCredentials);
+        let msg = recvmsg::<UnixAddr>(
+            sock.as_raw_fd(),
+            &mut iov,
+            Some(&mut cmsg),
+            MsgFlags::empty(),
+        )
+        .map_err(std::io::Error::from)?;
+        let len = msg.bytes;
+        let uid = msg.cmsgs().ok().and_then(|mut cmsgs| {
+            cmsgs.find_map(|cmsg| match cmsg {
+                ControlMessageOwned::ScmCredentials(creds) => Some(creds.uid()),
+                _ => None,
+            })
+        });
+        let path = msg
+            .address
+            .as_ref()
+            .and_then(UnixAddr::path)
+            .map(Path::to_path_buf)
+            .ok_or_else(|| std::io::Error::other("request from unbound peer"))?;
+        let addr = SocketAddr::from_pathname(path)?;
+        let peer = CliPeer { addr, uid };
+        match CliRequest::deserialize(&rx_buf[0..len]) {
+            Ok(request) => Ok((peer, request)),
+            Err(CliSerdeError::Version(version)) => {
+                Err(CliLocalError::UnsupportedVersion(peer, version))
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
 }
 
 #[allow(unused)]
@@ -233,6 +425,25 @@ impl CliResponse {
         Ok(())
     }
 
+    /// Tell a peer which sent a request of another protocol version which version is spoken
+    /// here. The peer can't be sent a response, whose layout it may not know: it is sent a
+    /// message made of the protocol version only, which fails to deserialize.
+    pub fn send_version(
+        peer: &SocketAddr,
+        sock: &UnixDatagram,
+        cache: &mut IoCache,
+    ) -> Result<(), CliLocalError> {
+        let mut raw = add_version(&[]);
+        raw.push(0); // no more chunks
+        if let Err(e) = sock.send_to_addr(raw.as_slice(), peer) {
+            if e.kind() != std::io::ErrorKind::WouldBlock {
+                return Err(e.into());
+            }
+            cache.push(peer.clone(), raw.as_slice());
+        }
+        Ok(())
+    }
+
     pub fn recv_sync(sock: &UnixDatagram) -> Result<Self, CliLocalError> {
         fn recv_chunk(sock: &UnixDatagram) -> Result<(Vec<u8>, bool), std::io::Error> {
             let mut rx_buff = vec![0u8; CLI_MSG_CHUNK_SIZE + 1];
@@ -274,6 +485,7 @@ pub enum CliAction {
 
     // config
     ShowConfigSummary,
+    RollbackConfig,
 
     // config: gateways & communities
     ShowGatewayGroups,
@@ -321,6 +533,7 @@ pub enum CliAction {
     ShowPortForwarding,
     ShowStaticNat,
     ShowMasquerading,
+    ShowNatAlg,
 
     // NF: flow table
     ShowFlowTable,
@@ -331,20 +544,54 @@ pub enum CliAction {
     // NF: Packet stats
     ShowPacketStats,
 
+    // NF: what-if lookups
+    SimulatePacket,
+
+    // NF: table generations
+    ShowTables,
+
+    // NF: packet capture
+    StartCapture,
+    StopCapture,
+    ShowCapture,
+
+    // kernel interfaces: required vs observed
+    ShowKernelInterfaces,
+
+    // state learned at runtime, persisted across restarts
+    ShowStateStore,
+
+    // crash reports, kept across restarts
+    ShowCrashReports,
+    ShowCrashReport,
+
+    // microbursts detected on the receive queues
+    ShowMicrobursts,
+
+    // recent BMP route monitoring messages, as MRT or JSON
+    DumpBmp,
+
     // internal config
     ShowConfigInternal,
 
+    // feature flags
+    ShowFeatureFlags,
+    SetFeatureFlag,
+
+    // tracing levels, at runtime
+    SetTracing,
+
     ShowTech,
 
+    // state export (local: the cli gathers the state and builds the archive)
+    ExportState,
+
     /* == Not supported yet == */
     // pipelines
     ShowPipeline,
     ShowPipelineStages,
     ShowPipelineStats,
 
-    // kernel
-    ShowKernelInterfaces,
-
     // DPDK
     ShowDpdkPort,
     ShowDpdkPortStats,
@@ -371,8 +618,26 @@ mod tests {
                 vni: Some(10_100),
                 ifname: Some("eth0".into()),
                 protocol: Some(RouteProtocol::Bgp),
+                src_address: Some(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))),
+                dst_address: Some(IpAddr::V4(Ipv4Addr::new(10, 0, 1, 1))),
+                src_port: Some(1024),
+                dst_port: Some(443),
+                transport: Some(TransportProtocol::Tcp),
+                flag: Some("reconcile-offloads".into()),
+                flag_value: Some(FlagValue::Default),
+                tap: Some("pre-filter".into()),
+                port: Some(53),
+                count: Some(1000),
+                capture_to: Some(CaptureTarget::Socket("capture.sock".into())),
+                namespace: Some("route-tables".into()),
+                report: Some("crash-1700000000000.json".into()),
+                tracing: Some("nat=debug,default=info".into()),
+                genid: Some(7),
+                dump_to: Some("/tmp/bmp.mrt".into()),
+                format: CliFormat::Json,
             },
         )
+        .with_token(Some("s3cr3t".into()))
     }
 
     /// Build a `CliResponse` that carries both the nested `CliRequest` and a
@@ -389,6 +654,20 @@ mod tests {
         assert_eq!(got, original);
     }
 
+    #[test]
+    fn version_mismatch() {
+        let mut bytes = sample_request().serialize().expect("serialize");
+        bytes[..CLI_VERSION_LEN].copy_from_slice(&(CLI_PROTOCOL_VERSION + 1).to_le_bytes());
+        let err = CliRequest::deserialize(&bytes).expect_err("other version");
+        assert!(matches!(err, CliSerdeError::Version(v) if v == CLI_PROTOCOL_VERSION + 1));
+
+        // the message sent to peers speaking another version
+        let err = CliResponse::deserialize(&add_version(&[])).expect_err("version only");
+        assert!(matches!(err, CliSerdeError::Deserialize(_)));
+        let err = CliResponse::deserialize(&[1]).expect_err("truncated");
+        assert!(matches!(err, CliSerdeError::Deserialize(_)));
+    }
+
     #[test]
     fn response_round_trip() {
         let original = sample_response();
@@ -0,0 +1,490 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Sequence-numbered change logs of left-right tables.
+//!
+//! Consumers following the state of a table from outside of the writer (e.g. inspection or
+//! standby processes) would otherwise need a full copy of the table each time it changes. Instead,
+//! the writer of a table records in a [`SharedChangeLog`] the changes of each publication, as
+//! deltas of the entries of the table, and gives out [`ChangeFeed`]s. A consumer takes a
+//! [`Checkpoint`] of the table from the feed once, then only fetches the changes published after
+//! the [`Seqno`] of its checkpoint and replays them onto it.
+//!
+//! The log retains a bounded number of changes. A consumer lagging beyond them must take a new
+//! checkpoint.
+
+use concurrency::sync::{Arc, Mutex};
+use left_right::ReadHandle;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Display;
+use std::hash::{BuildHasher, Hash};
+use thiserror::Error;
+
+/// The sequence number of a publication recorded in a [`ChangeLog`].
+///
+/// Sequence numbers start at 1. A checkpoint at [`Seqno::ZERO`] has not seen any publication.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Seqno(u64);
+
+impl Seqno {
+    pub const ZERO: Seqno = Seqno(0);
+
+    #[must_use]
+    pub fn new(seqno: u64) -> Self {
+        Self(seqno)
+    }
+    #[must_use]
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+    #[must_use]
+    fn next(self) -> Self {
+        Self(self.0 + 1)
+    }
+}
+
+impl Display for Seqno {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+/// The errors produced when reading a [`ChangeLog`]
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ChangeLogError {
+    #[error("Changes after {requested} are no longer available (oldest retained is {oldest})")]
+    Truncated { requested: Seqno, oldest: Seqno },
+    #[error("Sequence number {requested} is ahead of the log (last is {last})")]
+    Ahead { requested: Seqno, last: Seqno },
+}
+
+/// A change of a table `T`, which can be recorded in a [`ChangeLog`] and replayed onto a copy of
+/// the table.
+pub trait Delta<T>: Clone {
+    /// Apply this change to `table`.
+    fn replay(&self, table: &mut T);
+}
+
+/// The change of one entry of a table keyed by `K`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EntryDelta<K, V> {
+    /// The entry for the key was added or changed
+    Set(K, V),
+    /// The entry for the key was removed
+    Remove(K),
+}
+
+impl<K: Eq + Hash + Clone, V: Clone> EntryDelta<K, V> {
+    /// Apply this change to `map`
+    pub fn apply<S: BuildHasher>(&self, map: &mut HashMap<K, V, S>) {
+        match self {
+            EntryDelta::Set(key, value) => {
+                map.insert(key.clone(), value.clone());
+            }
+            EntryDelta::Remove(key) => {
+                map.remove(key);
+            }
+        }
+    }
+}
+
+/// Compute the changes of the entries turning a table with entries `old` into `new`. Entries
+/// equal in both are left out.
+pub fn diff_entries<'a, K, V, S>(
+    old: impl IntoIterator<Item = (&'a K, &'a V)>,
+    new: &'a HashMap<K, V, S>,
+) -> Vec<EntryDelta<K, V>>
+where
+    K: Eq + Hash + Clone + 'a,
+    V: PartialEq + Clone + 'a,
+    S: BuildHasher,
+{
+    let mut changes = vec![];
+    let mut seen = HashSet::new();
+    for (key, value) in old {
+        seen.insert(key);
+        match new.get(key) {
+            None => changes.push(EntryDelta::Remove(key.clone())),
+            Some(new_value) if new_value != value => {
+                changes.push(EntryDelta::Set(key.clone(), new_value.clone()));
+            }
+            Some(_) => {}
+        }
+    }
+    for (key, value) in new {
+        if !seen.contains(key) {
+            changes.push(EntryDelta::Set(key.clone(), value.clone()));
+        }
+    }
+    changes
+}
+
+/// A bounded, sequence-numbered log of the changes published for a table. Each publication is
+/// recorded with its own [`Seqno`], along with the changes it made visible.
+#[derive(Debug)]
+pub struct ChangeLog<C> {
+    publications: VecDeque<(Seqno, Vec<C>)>,
+    last: Seqno,
+    /// Number of changes retained
+    len: usize,
+    capacity: usize,
+}
+
+impl<C> Default for ChangeLog<C> {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+impl<C> ChangeLog<C> {
+    /// The default maximum number of retained changes.
+    pub const DEFAULT_CAPACITY: usize = 4096;
+
+    /// Create a log retaining at most `capacity` changes. The changes of the last publication are
+    /// always retained, even if more.
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            publications: VecDeque::new(),
+            last: Seqno::ZERO,
+            len: 0,
+            capacity,
+        }
+    }
+
+    /// The sequence number of the last publication.
+    #[must_use]
+    pub fn last(&self) -> Seqno {
+        self.last
+    }
+
+    /// The number of retained changes.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// True if no change is retained.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Record the changes of a publication, returning its sequence number.
+    pub fn record(&mut self, changes: Vec<C>) -> Seqno {
+        self.last = self.last.next();
+        self.len += changes.len();
+        self.publications.push_back((self.last, changes));
+        while self.len > self.capacity && self.publications.len() > 1 {
+            if let Some((_, dropped)) = self.publications.pop_front() {
+                self.len -= dropped.len();
+            }
+        }
+        self.last
+    }
+}
+
+impl<C: Clone> ChangeLog<C> {
+    /// Get the publications recorded after `seqno`, in order.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the changes needed to bring a copy of the table at `seqno` up to date are no
+    /// longer available, or if `seqno` is ahead of the log.
+    pub fn since(&self, seqno: Seqno) -> Result<Vec<(Seqno, Vec<C>)>, ChangeLogError> {
+        if seqno > self.last {
+            return Err(ChangeLogError::Ahead {
+                requested: seqno,
+                last: self.last,
+            });
+        }
+        if let Some((oldest, _)) = self.publications.front()
+            && *oldest > seqno.next()
+        {
+            return Err(ChangeLogError::Truncated {
+                requested: seqno,
+                oldest: *oldest,
+            });
+        }
+        Ok(self
+            .publications
+            .iter()
+            .filter(|(n, _)| *n > seqno)
+            .cloned()
+            .collect())
+    }
+}
+
+/// A copy of a table, at the publication with sequence number `seqno`.
+#[derive(Clone, Debug)]
+pub struct Checkpoint<T> {
+    pub seqno: Seqno,
+    pub table: T,
+}
+
+impl<T> Checkpoint<T> {
+    /// Replay the publications obtained from a [`ChangeLog`] (e.g. received from the process
+    /// owning it). Publications already applied to the checkpoint are skipped.
+    pub fn apply<C: Delta<T>>(&mut self, publications: &[(Seqno, Vec<C>)]) {
+        let from = self.seqno;
+        for (seqno, changes) in publications.iter().filter(|(n, _)| *n > from) {
+            for change in changes {
+                change.replay(&mut self.table);
+            }
+            self.seqno = *seqno;
+        }
+    }
+}
+
+/// A [`ChangeLog`] shared by the writer of a table with the [`ChangeFeed`]s it gives out
+pub struct SharedChangeLog<C>(Arc<Mutex<ChangeLog<C>>>);
+
+impl<C> Default for SharedChangeLog<C> {
+    fn default() -> Self {
+        Self(Arc::new(Mutex::new(ChangeLog::default())))
+    }
+}
+
+impl<C> Clone for SharedChangeLog<C> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<C> std::fmt::Debug for SharedChangeLog<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SharedChangeLog").finish_non_exhaustive()
+    }
+}
+
+impl<C> SharedChangeLog<C> {
+    /// Create a log retaining at most `capacity` changes
+    #[must_use]
+    pub fn new(capacity: usize) -> Self {
+        Self(Arc::new(Mutex::new(ChangeLog::new(capacity))))
+    }
+
+    /// Publish `changes` with `publish` and record them, returning the sequence number of the
+    /// publication. No checkpoint can be taken meanwhile, so that checkpoints are consistent with
+    /// the log.
+    pub fn record(&self, changes: Vec<C>, publish: impl FnOnce()) -> Seqno {
+        let mut log = self.0.lock();
+        publish();
+        log.record(changes)
+    }
+
+    /// The sequence number of the last publication
+    #[must_use]
+    pub fn last(&self) -> Seqno {
+        self.0.lock().last()
+    }
+
+    /// Give out the feed of the changes of the table read with `reader`
+    #[must_use]
+    pub fn feed<T>(&self, reader: ReadHandle<T>) -> ChangeFeed<T, C> {
+        ChangeFeed {
+            reader,
+            log: self.clone(),
+        }
+    }
+}
+
+/// The changes published for a table, along with a reader of the table to take checkpoints from.
+/// Given out by the writers of the tables.
+pub struct ChangeFeed<T, C> {
+    reader: ReadHandle<T>,
+    log: SharedChangeLog<C>,
+}
+
+impl<T, C> Clone for ChangeFeed<T, C> {
+    fn clone(&self) -> Self {
+        Self {
+            reader: self.reader.clone(),
+            log: self.log.clone(),
+        }
+    }
+}
+
+impl<T: Clone, C: Delta<T>> ChangeFeed<T, C> {
+    /// Take a copy of the published table. Returns `None` if the table was never published or its
+    /// writer is gone.
+    #[must_use]
+    pub fn checkpoint(&self) -> Option<Checkpoint<T>> {
+        let log = self.log.0.lock();
+        let table = self.reader.enter()?.clone();
+        Some(Checkpoint {
+            seqno: log.last(),
+            table,
+        })
+    }
+
+    /// Get the publications recorded after `seqno`, e.g. to send them to a copy of the table kept
+    /// by another process.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the log no longer has the changes needed, or if `seqno` is ahead of it.
+    pub fn changes_since(&self, seqno: Seqno) -> Result<Vec<(Seqno, Vec<C>)>, ChangeLogError> {
+        self.log.0.lock().since(seqno)
+    }
+
+    /// Bring `checkpoint` up to date, returning the number of publications replayed.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the log no longer has the changes needed. The checkpoint is left untouched, and a
+    /// new one must be taken.
+    pub fn catch_up(&self, checkpoint: &mut Checkpoint<T>) -> Result<usize, ChangeLogError> {
+        let publications = self.changes_since(checkpoint.seqno)?;
+        checkpoint.apply(&publications);
+        Ok(publications.len())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    type Table = HashMap<u32, &'static str>;
+
+    impl Delta<Table> for EntryDelta<u32, &'static str> {
+        fn replay(&self, table: &mut Table) {
+            self.apply(table);
+        }
+    }
+
+    fn sorted(
+        mut changes: Vec<EntryDelta<u32, &'static str>>,
+    ) -> Vec<EntryDelta<u32, &'static str>> {
+        let key = |change: &EntryDelta<u32, _>| match change {
+            EntryDelta::Set(key, _) | EntryDelta::Remove(key) => *key,
+        };
+        changes.sort_by_key(key);
+        changes
+    }
+
+    #[test]
+    fn test_diff_entries() {
+        let old = Table::from([(1, "one"), (2, "two"), (3, "three")]);
+        let new = Table::from([(1, "one"), (2, "deux"), (4, "four")]);
+        let changes = sorted(diff_entries(&old, &new));
+        assert_eq!(
+            changes,
+            [
+                EntryDelta::Set(2, "deux"),
+                EntryDelta::Remove(3),
+                EntryDelta::Set(4, "four"),
+            ]
+        );
+        assert!(diff_entries(&new, &new).is_empty());
+
+        let mut replayed = old.clone();
+        for change in &changes {
+            change.replay(&mut replayed);
+        }
+        assert_eq!(replayed, new);
+    }
+
+    #[test]
+    fn test_checkpoint_replay() {
+        let mut log = ChangeLog::new(8);
+        let mut checkpoint = Checkpoint {
+            seqno: Seqno::ZERO,
+            table: Table::new(),
+        };
+        log.record(vec![EntryDelta::Set(1, "one"), EntryDelta::Set(2, "two")]);
+        log.record(vec![EntryDelta::Remove(1)]);
+        checkpoint.apply(&log.since(checkpoint.seqno).unwrap());
+        assert_eq!(checkpoint.table, Table::from([(2, "two")]));
+        assert_eq!(checkpoint.seqno, Seqno::new(2));
+
+        // publications already replayed are skipped
+        log.record(vec![EntryDelta::Set(3, "three")]);
+        checkpoint.apply(&log.since(Seqno::ZERO).unwrap());
+        assert_eq!(checkpoint.table, Table::from([(2, "two"), (3, "three")]));
+        assert_eq!(checkpoint.seqno, log.last());
+        assert!(log.since(log.last()).unwrap().is_empty());
+        assert_eq!(
+            log.since(Seqno::new(42)),
+            Err(ChangeLogError::Ahead {
+                requested: Seqno::new(42),
+                last: Seqno::new(3)
+            })
+        );
+    }
+
+    #[test]
+    fn test_truncated_log() {
+        let mut log = ChangeLog::new(2);
+        log.record(vec![EntryDelta::Set(1, "one")]);
+        log.record(vec![EntryDelta::Set(2, "two")]);
+        log.record(vec![
+            EntryDelta::Set(3, "three"),
+            EntryDelta::Set(4, "four"),
+        ]);
+        assert_eq!(log.len(), 2);
+        assert_eq!(
+            log.since(Seqno::new(1)),
+            Err(ChangeLogError::Truncated {
+                requested: Seqno::new(1),
+                oldest: Seqno::new(3)
+            })
+        );
+        assert_eq!(log.since(Seqno::new(2)).unwrap().len(), 1);
+
+        // the last publication is retained even if larger than the log
+        log.record(vec![
+            EntryDelta::Remove(1),
+            EntryDelta::Remove(2),
+            EntryDelta::Remove(3),
+        ]);
+        assert_eq!(log.len(), 3);
+        assert_eq!(log.since(Seqno::new(3)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_change_feed() {
+        #[derive(Clone, Debug, Default)]
+        struct Published(Table);
+        impl left_right::Absorb<EntryDelta<u32, &'static str>> for Published {
+            fn absorb_first(&mut self, change: &mut EntryDelta<u32, &'static str>, _: &Self) {
+                change.apply(&mut self.0);
+            }
+            fn sync_with(&mut self, first: &Self) {
+                *self = first.clone();
+            }
+        }
+        impl Delta<Published> for EntryDelta<u32, &'static str> {
+            fn replay(&self, table: &mut Published) {
+                self.apply(&mut table.0);
+            }
+        }
+
+        let (mut writer, reader) = left_right::new_from_empty::<
+            Published,
+            EntryDelta<u32, &'static str>,
+        >(Published::default());
+        let log = SharedChangeLog::default();
+        let feed = log.feed(reader);
+        let mut publish = |changes: Vec<EntryDelta<u32, &'static str>>| {
+            for change in &changes {
+                writer.append(change.clone());
+            }
+            log.record(changes, || {
+                writer.publish();
+            });
+        };
+        assert!(feed.checkpoint().is_none());
+
+        publish(vec![EntryDelta::Set(1, "one")]);
+        let mut checkpoint = feed.checkpoint().unwrap();
+        assert_eq!(checkpoint.seqno, Seqno::new(1));
+        assert_eq!(checkpoint.table.0, Table::from([(1, "one")]));
+
+        publish(vec![EntryDelta::Set(2, "two")]);
+        publish(vec![EntryDelta::Remove(1)]);
+        assert_eq!(feed.catch_up(&mut checkpoint), Ok(2));
+        assert_eq!(checkpoint.table.0, Table::from([(2, "two")]));
+        assert_eq!(feed.catch_up(&mut checkpoint), Ok(0));
+    }
+}
@@ -9,9 +9,21 @@ use concurrency::sync::Arc;
 use left_right::ReadHandle;
 use std::fmt::Display;
 
+/// The part of the state that a restricted cli session may see
+pub trait CliScope {
+    /// Tell if the VPC with the given VNI is visible
+    fn sees_vni(&self, vni: u32) -> bool;
+}
+
 /// A trait for types that can produce contents for the cli
 pub trait CliDataProvider {
     fn provide(&self) -> String;
+
+    /// Produce the contents visible in a restricted scope, or `None` if the contents
+    /// cannot be restricted and must not be shown in such scope.
+    fn provide_scoped(&self, _scope: &dyn CliScope) -> Option<String> {
+        None
+    }
 }
 
 pub trait CliSource: Display {}
@@ -32,6 +44,9 @@ where
     fn provide(&self) -> String {
         self.as_ref().provide()
     }
+    fn provide_scoped(&self, scope: &dyn CliScope) -> Option<String> {
+        self.as_ref().provide_scoped(scope)
+    }
 }
 
 impl<T> CliDataProvider for Option<T>
@@ -44,6 +59,12 @@ where
             None => "(none)".to_string(),
         }
     }
+    fn provide_scoped(&self, scope: &dyn CliScope) -> Option<String> {
+        match self {
+            Some(value) => value.provide_scoped(scope),
+            None => Some("(none)".to_string()),
+        }
+    }
 }
 
 impl<T> CliDataProvider for ReadHandle<T>
@@ -57,6 +78,13 @@ where
             "inaccessible".to_string()
         }
     }
+    fn provide_scoped(&self, scope: &dyn CliScope) -> Option<String> {
+        if let Some(data) = &self.enter() {
+            data.provide_scoped(scope)
+        } else {
+            Some("inaccessible".to_string())
+        }
+    }
 }
 
 impl<T> CliDataProvider for ArcSwap<T>
@@ -66,6 +94,9 @@ where
     fn provide(&self) -> String {
         self.load().provide()
     }
+    fn provide_scoped(&self, scope: &dyn CliScope) -> Option<String> {
+        self.load().provide_scoped(scope)
+    }
 }
 
 impl<T> CliDataProvider for ArcSwapOption<T>
@@ -83,6 +114,11 @@ where
             .map(|p| p.provide())
             .unwrap_or_else(|| "(none)".to_string())
     }
+    fn provide_scoped(&self, scope: &dyn CliScope) -> Option<String> {
+        self.load()
+            .as_ref()
+            .map_or_else(|| Some("(none)".to_string()), |p| p.provide_scoped(scope))
+    }
 }
 
 impl<T> CliDataProvider for Slot<T>
@@ -92,6 +128,9 @@ where
     fn provide(&self) -> String {
         self.load().provide()
     }
+    fn provide_scoped(&self, scope: &dyn CliScope) -> Option<String> {
+        self.load().provide_scoped(scope)
+    }
 }
 
 impl<T> CliDataProvider for SlotOption<T>
@@ -104,6 +143,11 @@ where
             .map(|p| p.provide())
             .unwrap_or_else(|| "(none)".to_string())
     }
+    fn provide_scoped(&self, scope: &dyn CliScope) -> Option<String> {
+        self.load_full()
+            .as_ref()
+            .map_or_else(|| Some("(none)".to_string()), |p| p.provide_scoped(scope))
+    }
 }
 
 trait CliString: AsRef<str> + Display {}
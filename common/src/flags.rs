@@ -0,0 +1,325 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Automated, static registry of feature flags across all linked crates.
+//!
+//! Feature flags gate risky new behaviors, so that they can be rolled out (and back) in stages.
+//! A flag is declared with [`feature_flag!`](crate::feature_flag) next to the behavior it gates,
+//! with a default and a [`FlagScope`]. Its value is, by order of precedence:
+//! - the override set at runtime (e.g. from the cli), for flags of [`FlagScope::Runtime`],
+//! - the value given in the launch configuration,
+//! - its default.
+
+use linkme::distributed_slice;
+use std::fmt::Display;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// When the value of a flag can change
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FlagScope {
+    /// The flag is read once, at launch: it can only be given in the launch configuration
+    Launch,
+    /// The flag is read as the behavior it gates is exercised: it can be overridden at runtime
+    Runtime,
+}
+
+impl Display for FlagScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FlagScope::Launch => write!(f, "launch"),
+            FlagScope::Runtime => write!(f, "runtime"),
+        }
+    }
+}
+
+/// Errors setting feature flags
+#[derive(Debug, thiserror::Error)]
+pub enum FlagError {
+    #[error("Unknown feature flag {0}")]
+    Unknown(String),
+    #[error("Feature flag {0} can only be set at launch")]
+    LaunchOnly(&'static str),
+}
+
+/// An optional boolean, as stored in an [`AtomicU8`]
+const UNSET: u8 = 0;
+const OFF: u8 = 1;
+const ON: u8 = 2;
+
+fn encode(value: Option<bool>) -> u8 {
+    match value {
+        None => UNSET,
+        Some(false) => OFF,
+        Some(true) => ON,
+    }
+}
+
+fn decode(value: u8) -> Option<bool> {
+    match value {
+        UNSET => None,
+        OFF => Some(false),
+        ON => Some(true),
+        _ => unreachable!(),
+    }
+}
+
+/// A feature flag
+#[derive(Debug)]
+pub struct FeatureFlag {
+    name: &'static str,
+    description: &'static str,
+    default: bool,
+    scope: FlagScope,
+    launch: AtomicU8,
+    runtime: AtomicU8,
+}
+
+impl FeatureFlag {
+    #[must_use]
+    pub const fn new(
+        name: &'static str,
+        description: &'static str,
+        default: bool,
+        scope: FlagScope,
+    ) -> Self {
+        Self {
+            name,
+            description,
+            default,
+            scope,
+            launch: AtomicU8::new(UNSET),
+            runtime: AtomicU8::new(UNSET),
+        }
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    #[must_use]
+    pub fn description(&self) -> &'static str {
+        self.description
+    }
+
+    #[must_use]
+    pub fn default(&self) -> bool {
+        self.default
+    }
+
+    #[must_use]
+    pub fn scope(&self) -> FlagScope {
+        self.scope
+    }
+
+    /// The value given in the launch configuration, if any
+    #[must_use]
+    pub fn launch_value(&self) -> Option<bool> {
+        decode(self.launch.load(Ordering::Relaxed))
+    }
+
+    /// The value overriding the others at runtime, if any
+    #[must_use]
+    pub fn runtime_override(&self) -> Option<bool> {
+        decode(self.runtime.load(Ordering::Relaxed))
+    }
+
+    /// Tell if the behavior gated by the flag is enabled
+    #[must_use]
+    pub fn is_enabled(&self) -> bool {
+        self.runtime_override()
+            .or(self.launch_value())
+            .unwrap_or(self.default)
+    }
+
+    /// Override the value of the flag at runtime, or remove the override with `None`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`FlagError::LaunchOnly`] if the flag can't change at runtime.
+    pub fn set_override(&self, value: Option<bool>) -> Result<(), FlagError> {
+        if self.scope == FlagScope::Launch {
+            return Err(FlagError::LaunchOnly(self.name));
+        }
+        self.runtime.store(encode(value), Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+// make sure this is not optimized out
+#[used]
+#[distributed_slice]
+pub static FEATURE_FLAGS: [FeatureFlag];
+
+#[macro_export]
+/// Macro to declare a feature flag, with its name, default, scope and description.
+///
+/// Crates declaring flags must depend on `linkme`.
+macro_rules! feature_flag {
+    ($(#[$meta:meta])* $vis:vis static $ident:ident = ($name:expr, $default:expr, $scope:expr, $description:expr);) => {
+        $(#[$meta])*
+        #[linkme::distributed_slice($crate::flags::FEATURE_FLAGS)]
+        $vis static $ident: $crate::flags::FeatureFlag =
+            $crate::flags::FeatureFlag::new($name, $description, $default, $scope);
+    };
+}
+
+/// The feature flags of all the linked crates
+pub fn flags() -> impl Iterator<Item = &'static FeatureFlag> {
+    FEATURE_FLAGS.iter()
+}
+
+/// Look up a feature flag by name.
+///
+/// # Errors
+///
+/// Returns [`FlagError::Unknown`] if no flag has that name.
+pub fn lookup(name: &str) -> Result<&'static FeatureFlag, FlagError> {
+    flags()
+        .find(|flag| flag.name == name)
+        .ok_or_else(|| FlagError::Unknown(name.to_string()))
+}
+
+/// Apply the values of the feature flags given in a launch configuration. Flags not given go back
+/// to their defaults; runtime overrides are kept.
+///
+/// # Errors
+///
+/// Returns [`FlagError::Unknown`] if a flag is unknown, in which case no flag is changed.
+pub fn apply_launch_values<'a>(
+    values: impl IntoIterator<Item = (&'a str, bool)>,
+) -> Result<(), FlagError> {
+    set_launch_values(values, false)
+}
+
+/// Apply the values of the feature flags given in a later generation of the launch configuration,
+/// as [`apply_launch_values`] does.
+///
+/// # Errors
+///
+/// Returns [`FlagError::Unknown`] if a flag is unknown, and [`FlagError::LaunchOnly`] if the
+/// value of a flag which can't change at runtime would. No flag is changed in either case.
+pub fn reload_launch_values<'a>(
+    values: impl IntoIterator<Item = (&'a str, bool)>,
+) -> Result<(), FlagError> {
+    set_launch_values(values, true)
+}
+
+fn set_launch_values<'a>(
+    values: impl IntoIterator<Item = (&'a str, bool)>,
+    reload: bool,
+) -> Result<(), FlagError> {
+    let values = values
+        .into_iter()
+        .map(|(name, value)| Ok((lookup(name)?, value)))
+        .collect::<Result<Vec<_>, FlagError>>()?;
+    let value_of = |flag: &FeatureFlag| {
+        values
+            .iter()
+            .rev()
+            .find(|(given, _)| std::ptr::eq(*given, flag))
+            .map(|(_, value)| *value)
+    };
+    if reload
+        && let Some(flag) = flags()
+            .find(|flag| flag.scope == FlagScope::Launch && value_of(flag) != flag.launch_value())
+    {
+        return Err(FlagError::LaunchOnly(flag.name));
+    }
+    for flag in flags() {
+        flag.launch.store(encode(value_of(flag)), Ordering::Relaxed);
+    }
+    Ok(())
+}
+
+/// A table of the feature flags, for the cli
+pub struct FlagTable;
+
+impl Display for FlagTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let show = |value: Option<bool>| match value {
+            None => "-",
+            Some(false) => "off",
+            Some(true) => "on",
+        };
+        let mut flags: Vec<_> = flags().collect();
+        flags.sort_by_key(|flag| flag.name);
+        writeln!(
+            f,
+            " {:<28} {:<8} {:<8} {:<8} {:<8} {:<8} description",
+            "flag", "scope", "default", "launch", "override", "value"
+        )?;
+        for flag in flags {
+            writeln!(
+                f,
+                " {:<28} {:<8} {:<8} {:<8} {:<8} {:<8} {}",
+                flag.name,
+                flag.scope.to_string(),
+                show(Some(flag.default)),
+                show(flag.launch_value()),
+                show(flag.runtime_override()),
+                show(Some(flag.is_enabled())),
+                flag.description
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        FlagError, FlagScope, FlagTable, apply_launch_values, lookup, reload_launch_values,
+    };
+
+    crate::feature_flag! {
+        static TEST_RUNTIME_FLAG = ("test-runtime-flag", false, FlagScope::Runtime, "A test flag");
+    }
+    crate::feature_flag! {
+        static TEST_LAUNCH_FLAG = ("test-launch-flag", true, FlagScope::Launch, "A test flag");
+    }
+
+    #[test]
+    fn feature_flag_precedence() {
+        assert!(!TEST_RUNTIME_FLAG.is_enabled());
+        assert!(TEST_LAUNCH_FLAG.is_enabled());
+
+        apply_launch_values([("test-runtime-flag", true), ("test-launch-flag", false)]).unwrap();
+        assert!(TEST_RUNTIME_FLAG.is_enabled());
+        assert!(!TEST_LAUNCH_FLAG.is_enabled());
+
+        TEST_RUNTIME_FLAG.set_override(Some(false)).unwrap();
+        assert!(!TEST_RUNTIME_FLAG.is_enabled());
+        assert!(matches!(
+            TEST_LAUNCH_FLAG.set_override(Some(true)),
+            Err(FlagError::LaunchOnly(_))
+        ));
+
+        // flags of launch scope keep their launch value
+        assert!(matches!(
+            reload_launch_values([("test-runtime-flag", true)]),
+            Err(FlagError::LaunchOnly(_))
+        ));
+        reload_launch_values([("test-launch-flag", false)]).unwrap();
+        assert!(!TEST_LAUNCH_FLAG.is_enabled());
+
+        // the override outlives new launch values
+        apply_launch_values([]).unwrap();
+        assert!(!TEST_RUNTIME_FLAG.is_enabled());
+        assert!(TEST_LAUNCH_FLAG.is_enabled());
+        TEST_RUNTIME_FLAG.set_override(None).unwrap();
+        assert!(!TEST_RUNTIME_FLAG.is_enabled());
+
+        assert!(FlagTable.to_string().contains("test-launch-flag"));
+    }
+
+    #[test]
+    fn feature_flag_unknown() {
+        assert!(matches!(lookup("no-such-flag"), Err(FlagError::Unknown(_))));
+        assert!(apply_launch_values([("no-such-flag", true)]).is_err());
+        assert!(std::ptr::eq(
+            lookup("test-launch-flag").unwrap(),
+            &TEST_LAUNCH_FLAG
+        ));
+    }
+}
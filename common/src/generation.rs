@@ -0,0 +1,184 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Generations of left-right tables.
+//!
+//! Every change absorbed by a left-right table advances its [`TableGeneration`]. Since the
+//! generation is stored in the table itself, a reader always sees the generation matching the
+//! contents it reads. Consumers deriving data from a table (e.g. caches of lookups) can tell
+//! cheaply whether the table changed since they derived it, instead of comparing contents or
+//! relying on ad-hoc notifications. See [`DerivedCache`].
+//!
+//! Generations only tell whether a table changed: their values are not meaningful across
+//! tables, and a table may advance by more than one generation per publish.
+
+use left_right::ReadHandle;
+use std::fmt::Display;
+
+/// The generation of a table: it changes whenever the contents of the table change.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TableGeneration(u64);
+
+impl TableGeneration {
+    /// The generation of a table which has not absorbed any change.
+    pub const INITIAL: TableGeneration = TableGeneration(0);
+
+    #[must_use]
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+    /// The generation following this one.
+    #[must_use]
+    pub fn next(self) -> Self {
+        Self(self.0.wrapping_add(1))
+    }
+}
+
+impl Display for TableGeneration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "gen {}", self.0)
+    }
+}
+
+/// A table that keeps track of its [`TableGeneration`].
+pub trait Generational {
+    /// The current generation of the table.
+    fn generation(&self) -> TableGeneration;
+
+    /// Overwrite the generation of the table. Only meant to be used by the implementations of
+    /// `left_right::Absorb`, through [`Generational::advance_generation`] and
+    /// [`Generational::replace_contents`].
+    fn set_generation(&mut self, generation: TableGeneration);
+
+    /// Record that the table changed.
+    fn advance_generation(&mut self) {
+        self.set_generation(self.generation().next());
+    }
+
+    /// Replace the contents of the table, advancing its generation. The generation of `new` is
+    /// ignored, so that generations never go back when tables are replaced wholesale.
+    fn replace_contents(&mut self, new: Self)
+    where
+        Self: Sized,
+    {
+        let generation = self.generation().next();
+        *self = new;
+        self.set_generation(generation);
+    }
+}
+
+/// A source of the generation of a published table, regardless of its type.
+pub trait GenerationProvider {
+    /// The generation of the published table, if it can be accessed.
+    fn published_generation(&self) -> Option<TableGeneration>;
+}
+
+impl<T: Generational> GenerationProvider for ReadHandle<T> {
+    fn published_generation(&self) -> Option<TableGeneration> {
+        self.enter().map(|table| table.generation())
+    }
+}
+
+/// A value derived from a [`Generational`] table, recomputed only when the table changes.
+#[derive(Debug)]
+pub struct DerivedCache<T> {
+    entry: Option<(TableGeneration, T)>,
+}
+
+impl<T> Default for DerivedCache<T> {
+    fn default() -> Self {
+        Self { entry: None }
+    }
+}
+
+impl<T> DerivedCache<T> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// True if the cached value was derived from the current generation of `source`.
+    pub fn is_current(&self, source: &impl Generational) -> bool {
+        self.entry
+            .as_ref()
+            .is_some_and(|(generation, _)| *generation == source.generation())
+    }
+
+    /// Get the value derived from `source`, deriving it again if `source` changed.
+    pub fn get_or_derive<S: Generational>(
+        &mut self,
+        source: &S,
+        derive: impl FnOnce(&S) -> T,
+    ) -> &T {
+        if !self.is_current(source) {
+            self.entry = Some((source.generation(), derive(source)));
+        }
+        match &self.entry {
+            Some((_, value)) => value,
+            None => unreachable!(),
+        }
+    }
+
+    /// Drop the cached value.
+    pub fn invalidate(&mut self) {
+        self.entry = None;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Default)]
+    struct Table {
+        values: Vec<u32>,
+        generation: TableGeneration,
+    }
+
+    impl Generational for Table {
+        fn generation(&self) -> TableGeneration {
+            self.generation
+        }
+        fn set_generation(&mut self, generation: TableGeneration) {
+            self.generation = generation;
+        }
+    }
+
+    #[test]
+    fn replace_contents_advances_generation() {
+        let mut table = Table::default();
+        table.values.push(1);
+        table.advance_generation();
+        assert_eq!(table.generation(), TableGeneration::INITIAL.next());
+
+        table.replace_contents(Table {
+            values: vec![2, 3],
+            generation: TableGeneration::INITIAL,
+        });
+        assert_eq!(table.values, vec![2, 3]);
+        assert_eq!(table.generation(), TableGeneration::INITIAL.next().next());
+    }
+
+    #[test]
+    fn derived_cache_follows_generation() {
+        let mut table = Table::default();
+        let mut cache = DerivedCache::new();
+        let mut derivations = 0;
+        let mut sum = |t: &Table| {
+            derivations += 1;
+            t.values.iter().sum::<u32>()
+        };
+        assert_eq!(*cache.get_or_derive(&table, &mut sum), 0);
+        assert_eq!(*cache.get_or_derive(&table, &mut sum), 0);
+        assert!(cache.is_current(&table));
+
+        table.values.push(5);
+        table.advance_generation();
+        assert!(!cache.is_current(&table));
+        assert_eq!(*cache.get_or_derive(&table, &mut sum), 5);
+
+        cache.invalidate();
+        assert_eq!(*cache.get_or_derive(&table, &mut sum), 5);
+        assert_eq!(derivations, 3);
+    }
+}
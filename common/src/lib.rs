@@ -1,4 +1,9 @@
 // SPDX-License-Identifier: Apache-2.0
 // Copyright Open Network Fabric Authors
 
+pub mod changelog;
 pub mod cliprovider;
+pub mod flags;
+pub mod generation;
+pub mod publish;
+pub mod token;
@@ -0,0 +1,185 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Instrumentation of the publication of left-right tables.
+//!
+//! Changes to a left-right table are appended to its write handle, and only become visible to
+//! readers once published. Publishing swaps the copies of the table, then waits for all readers to
+//! leave the copy they were reading, so that it can absorb the changes too. A reader holding on to
+//! a table for long thus delays publication.
+//!
+//! [`InstrumentedWriteHandle`] wraps the write handle of a table to export, labeled with the name
+//! of the table:
+//!
+//! - `left_right_publishes`: the number of publications (i.e. swaps) of the table;
+//! - `left_right_publish_seconds`: how long publications take, including waiting for readers;
+//! - `left_right_publish_lag_seconds`: how long changes wait between being appended and being
+//!   visible to readers.
+//!
+//! Tables published otherwise, e.g. by swapping an `Arc`, record the same metrics with
+//! [`PublishMetrics`].
+//!
+//! This lives here rather than in the stats crate, so that the crates which the stats crate
+//! depends on can instrument their tables too.
+
+use left_right::{Absorb, WriteHandle};
+use metrics::{Counter, Histogram, Unit};
+use std::ops::Deref;
+use std::ptr::NonNull;
+use std::time::Instant;
+
+const PUBLISHES: &str = "left_right_publishes";
+const PUBLISH_SECONDS: &str = "left_right_publish_seconds";
+const PUBLISH_LAG_SECONDS: &str = "left_right_publish_lag_seconds";
+
+/// The metrics of the publication of a table
+#[derive(Clone, Debug)]
+pub struct PublishMetrics {
+    publishes: Counter,
+    latency: Histogram,
+    lag: Histogram,
+}
+
+impl PublishMetrics {
+    /// The metrics of the publication of a table, named `table` in the metrics.
+    #[must_use]
+    pub fn new(table: &str) -> Self {
+        metrics::describe_counter!(PUBLISHES, Unit::Count, "Publications of the table");
+        metrics::describe_histogram!(PUBLISH_SECONDS, Unit::Seconds, "Duration of publications");
+        metrics::describe_histogram!(
+            PUBLISH_LAG_SECONDS,
+            Unit::Seconds,
+            "Delay of changes until published"
+        );
+        let labels = [("table", table.to_string())];
+        Self {
+            publishes: metrics::counter!(PUBLISHES, &labels),
+            latency: metrics::histogram!(PUBLISH_SECONDS, &labels),
+            lag: metrics::histogram!(PUBLISH_LAG_SECONDS, &labels),
+        }
+    }
+
+    /// Record a publication which started at `start` and just ended, of changes pending since
+    /// `pending_since`, if any.
+    pub fn record(&self, start: Instant, pending_since: Option<Instant>) {
+        let end = Instant::now();
+        self.publishes.increment(1);
+        self.latency.record(end.duration_since(start).as_secs_f64());
+        if let Some(since) = pending_since {
+            self.lag.record(end.duration_since(since).as_secs_f64());
+        }
+    }
+}
+
+/// A left-right [`WriteHandle`] exporting metrics about the publication of its table.
+///
+/// Changes must be appended and published through the wrapper for them to be accounted. Reading
+/// the table is done through the wrapped handle, which the wrapper dereferences to.
+pub struct InstrumentedWriteHandle<T: Absorb<O>, O> {
+    handle: WriteHandle<T, O>,
+    metrics: PublishMetrics,
+    /// When the oldest change which was not published yet was appended
+    pending_since: Option<Instant>,
+}
+
+impl<T: Absorb<O>, O> InstrumentedWriteHandle<T, O> {
+    /// Instrument the write handle of a table, named `table` in the metrics.
+    #[must_use]
+    pub fn new(handle: WriteHandle<T, O>, table: &str) -> Self {
+        Self {
+            handle,
+            metrics: PublishMetrics::new(table),
+            pending_since: None,
+        }
+    }
+
+    /// Append a change to the table. It is visible to readers once published.
+    pub fn append(&mut self, op: O) -> &mut Self {
+        self.pending_since.get_or_insert_with(Instant::now);
+        self.handle.append(op);
+        self
+    }
+
+    /// Publish the changes appended so far, waiting for readers to leave the previous copy of the
+    /// table.
+    pub fn publish(&mut self) -> &mut Self {
+        let start = Instant::now();
+        self.handle.publish();
+        self.metrics.record(start, self.pending_since.take());
+        self
+    }
+
+    /// Access the copy of the table which changes are applied to, before being published. See
+    /// [`WriteHandle::raw_write_handle`] for when it is safe to use.
+    pub fn raw_write_handle(&mut self) -> NonNull<T> {
+        self.handle.raw_write_handle()
+    }
+
+    /// Give back the wrapped handle, e.g. to take ownership of the table.
+    #[must_use]
+    pub fn into_inner(self) -> WriteHandle<T, O> {
+        self.handle
+    }
+}
+
+impl<T: Absorb<O>, O> Deref for InstrumentedWriteHandle<T, O> {
+    type Target = WriteHandle<T, O>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.handle
+    }
+}
+
+impl<T: Absorb<O> + std::fmt::Debug, O: std::fmt::Debug> std::fmt::Debug
+    for InstrumentedWriteHandle<T, O>
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InstrumentedWriteHandle")
+            .field("handle", &self.handle)
+            .field("pending_since", &self.pending_since)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[derive(Clone, Debug, Default)]
+    struct Table(Vec<u32>);
+
+    impl Absorb<u32> for Table {
+        fn absorb_first(&mut self, op: &mut u32, _: &Self) {
+            self.0.push(*op);
+        }
+        fn sync_with(&mut self, first: &Self) {
+            *self = first.clone();
+        }
+    }
+
+    #[test]
+    fn test_instrumented_write_handle() {
+        let (handle, reader) = left_right::new_from_empty::<Table, u32>(Table::default());
+        let mut writer = InstrumentedWriteHandle::new(handle, "test");
+        // readers see nothing until the first publication
+        assert!(reader.enter().is_none());
+        writer.publish();
+        assert!(writer.pending_since.is_none());
+
+        writer.append(1).append(2);
+        assert!(writer.has_pending_operations());
+        assert!(writer.pending_since.is_some());
+        assert!(reader.enter().is_some_and(|table| table.0.is_empty()));
+
+        writer.publish();
+        assert!(!writer.has_pending_operations());
+        assert!(writer.pending_since.is_none());
+        assert_eq!(
+            reader.enter().map(|table| table.0.clone()),
+            Some(vec![1, 2])
+        );
+
+        // reads go through the wrapped handle
+        assert_eq!(writer.enter().map(|table| table.0.len()), Some(2));
+    }
+}
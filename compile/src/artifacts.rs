@@ -0,0 +1,367 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Compilation of a `GatewayAgent` CRD into the tables of the dataplane, and their artifacts.
+//!
+//! The artifacts are the validated spec, which the dataplane loads in k8s-less mode, renderings
+//! of the tables for reviews, and a manifest (see [`k8s_less::artifacts`]). The artifacts are
+//! loaded back with [`Artifacts::load`], which checks them against the digests of the manifest,
+//! and [`Artifacts::verify`] checks that they are what their spec compiles to.
+
+use config::converters::k8s::FromK8sConversionError;
+use config::external::overlay::vpc::ValidatedVpcTable;
+use config::{ConfigError, ExternalConfig, GenId};
+use flow_filter::FlowFilterTable;
+use k8s_intf::gateway_agent_crd::{GatewayAgent, GatewayAgentSpec};
+use k8s_less::artifacts::{
+    ArtifactError, ArtifactFile, MANIFEST_FILE, MANIFEST_FORMAT_VERSION, SPEC_FILE,
+    check_format_version, read_artifact,
+};
+use nat::portfw::build_port_forwarding_configuration;
+use nat::static_nat::setup::build_nat_configuration;
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+const FLOW_FILTER_FILE: &str = "flow-filter.txt";
+const STATIC_NAT_FILE: &str = "static-nat.txt";
+const PORT_FORWARDING_FILE: &str = "port-forwarding.txt";
+
+/// The errors that may occur when compiling a configuration
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum CompileError {
+    /// Errors when reading the input, or reading or writing the artifacts
+    #[error("Failed to access {}: {1}", .0.display())]
+    Io(PathBuf, #[source] std::io::Error),
+
+    /// Errors when deserializing the input, or (de)serializing the spec of the artifacts
+    #[error("Invalid YAML: {0}")]
+    Yaml(#[from] serde_yaml_ng::Error),
+
+    /// Errors when (de)serializing the manifest
+    #[error("Invalid manifest: {0}")]
+    Manifest(#[from] serde_json::Error),
+
+    /// Errors caused by incomplete or wrong metadata in the CRD
+    #[error("{0}")]
+    Metadata(#[source] FromK8sConversionError),
+
+    /// Errors when converting the CRD to a gateway configuration
+    #[error("{0}")]
+    Conversion(#[source] FromK8sConversionError),
+
+    /// Errors when validating the configuration or building the tables from it
+    #[error("{0}")]
+    Configuration(#[from] ConfigError),
+
+    /// Errors when loading the artifacts: unsupported manifest, or artifacts not matching it
+    #[error("{0}")]
+    Artifact(#[from] ArtifactError),
+
+    /// The artifacts are not what their spec compiles to
+    #[error("Artifact {0} differs from the one compiled from the spec")]
+    Mismatch(&'static str),
+}
+impl CompileError {
+    /// Provide a string indicating the type of error
+    pub(crate) fn get_type(&self) -> &str {
+        match self {
+            CompileError::Io(..) => "Environment",
+            CompileError::Yaml(_) | CompileError::Manifest(_) => "Serialization",
+            CompileError::Metadata(_) => "Metadata",
+            CompileError::Conversion(_) => "Conversion",
+            CompileError::Configuration(_) => "Configuration",
+            CompileError::Artifact(_) | CompileError::Mismatch(_) => "Artifacts",
+        }
+    }
+}
+
+/// An entry of the vpc map: the VNI of a VPC and its name
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct VpcMapEntry {
+    vni: u32,
+    name: String,
+}
+
+/// The public IPs of a masquerading expose, from which the dataplane allocates addresses
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct NatPool {
+    vpc: String,
+    peering: String,
+    prefixes: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    idle_timeout_secs: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Manifest {
+    format_version: u32,
+    gwname: String,
+    genid: GenId,
+    vpcmap: Vec<VpcMapEntry>,
+    nat_pools: Vec<NatPool>,
+    files: Vec<ArtifactFile>,
+}
+
+/// The artifacts compiled from a `GatewayAgent` CRD
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct Artifacts {
+    gwname: String,
+    genid: GenId,
+    spec: String,
+    flow_filter: String,
+    static_nat: String,
+    port_forwarding: String,
+    vpcmap: Vec<VpcMapEntry>,
+    nat_pools: Vec<NatPool>,
+}
+
+fn build_vpcmap(vpc_table: &ValidatedVpcTable) -> Vec<VpcMapEntry> {
+    vpc_table
+        .values()
+        .map(|vpc| VpcMapEntry {
+            vni: vpc.vni().as_u32(),
+            name: vpc.name().to_string(),
+        })
+        .collect()
+}
+
+fn build_nat_pools(vpc_table: &ValidatedVpcTable) -> Vec<NatPool> {
+    let mut pools = Vec::new();
+    for vpc in vpc_table.values() {
+        for peering in vpc.local_stateful_nat_peerings() {
+            let local = peering.local();
+            for expose in local
+                .masquerade_exposes_44()
+                .chain(local.masquerade_exposes_66())
+            {
+                pools.push(NatPool {
+                    vpc: vpc.name().to_string(),
+                    peering: peering.name().to_string(),
+                    prefixes: expose
+                        .public_ips()
+                        .iter()
+                        .map(ToString::to_string)
+                        .collect(),
+                    idle_timeout_secs: expose.idle_timeout().map(|timeout| timeout.as_secs()),
+                });
+            }
+        }
+    }
+    pools
+}
+
+/// Build the `GatewayAgent` of gateway `gwname` with configuration `spec`, the same way the
+/// dataplane does when running without k8s
+pub(crate) fn crd_from_spec(gwname: &str, genid: GenId, spec: GatewayAgentSpec) -> GatewayAgent {
+    let mut crd = GatewayAgent::new(gwname, spec);
+    crd.metadata.generation = Some(genid);
+    crd.metadata.namespace = Some("default".to_string());
+    crd
+}
+
+fn read_file(path: &Path) -> Result<String, CompileError> {
+    std::fs::read_to_string(path).map_err(|e| CompileError::Io(path.to_path_buf(), e))
+}
+
+fn write_file(path: &Path, contents: &str) -> Result<(), CompileError> {
+    std::fs::write(path, contents).map_err(|e| CompileError::Io(path.to_path_buf(), e))
+}
+
+impl Artifacts {
+    /// Validate the configuration in a `GatewayAgent` CRD and build the tables from it, the
+    /// same way the dataplane would do when applying it.
+    pub(crate) fn compile(crd: &GatewayAgent) -> Result<Self, CompileError> {
+        let external = ExternalConfig::try_from(crd).map_err(|e| match e {
+            FromK8sConversionError::K8sInfra(_) => CompileError::Metadata(e),
+            _ => CompileError::Conversion(e),
+        })?;
+        let config = external.validate()?;
+        let external = config.external();
+        let overlay = external.overlay();
+        let vpc_table = overlay.vpc_table();
+
+        let flow_filter = FlowFilterTable::build_from_overlay(overlay)?;
+        let static_nat = build_nat_configuration(vpc_table)?;
+        let port_forwarding =
+            build_port_forwarding_configuration(vpc_table, external.session_table())?;
+
+        let spec = serde_yaml_ng::to_string(&crd.spec)?;
+
+        Ok(Self {
+            gwname: external.gwname().to_string(),
+            genid: config.genid(),
+            spec,
+            flow_filter: flow_filter.to_string(),
+            static_nat: static_nat.to_string(),
+            port_forwarding: port_forwarding
+                .iter()
+                .fold(String::new(), |mut out, entry| {
+                    let _ = writeln!(out, "{entry}");
+                    out
+                }),
+            vpcmap: build_vpcmap(vpc_table),
+            nat_pools: build_nat_pools(vpc_table),
+        })
+    }
+
+    pub(crate) fn gwname(&self) -> &str {
+        &self.gwname
+    }
+
+    pub(crate) fn genid(&self) -> GenId {
+        self.genid
+    }
+
+    /// The files of the artifacts, along with their contents
+    fn files(&self) -> [(&'static str, &str); 4] {
+        [
+            (SPEC_FILE, &self.spec),
+            (FLOW_FILTER_FILE, &self.flow_filter),
+            (STATIC_NAT_FILE, &self.static_nat),
+            (PORT_FORWARDING_FILE, &self.port_forwarding),
+        ]
+    }
+
+    /// Write the artifacts to the given directory, creating it if needed. The manifest is
+    /// written last so that its presence indicates a complete set of artifacts.
+    pub(crate) fn write(&self, dir: &Path) -> Result<(), CompileError> {
+        std::fs::create_dir_all(dir).map_err(|e| CompileError::Io(dir.to_path_buf(), e))?;
+
+        let mut files = Vec::new();
+        for (name, contents) in self.files() {
+            write_file(&dir.join(name), contents)?;
+            files.push(ArtifactFile::new(name, contents.as_bytes()));
+        }
+
+        let manifest = Manifest {
+            format_version: MANIFEST_FORMAT_VERSION,
+            gwname: self.gwname.clone(),
+            genid: self.genid,
+            vpcmap: self.vpcmap.clone(),
+            nat_pools: self.nat_pools.clone(),
+            files,
+        };
+        let manifest = serde_json::to_string_pretty(&manifest)?;
+        write_file(&dir.join(MANIFEST_FILE), &manifest)
+    }
+
+    /// Load the artifacts written to the given directory, checking each of them against its
+    /// digest in the manifest.
+    pub(crate) fn load(dir: &Path) -> Result<Self, CompileError> {
+        let manifest: Manifest = serde_json::from_str(&read_file(&dir.join(MANIFEST_FILE))?)?;
+        check_format_version(manifest.format_version)?;
+        let load = |name| read_artifact(dir, &manifest.files, name);
+        Ok(Self {
+            spec: load(SPEC_FILE)?,
+            flow_filter: load(FLOW_FILTER_FILE)?,
+            static_nat: load(STATIC_NAT_FILE)?,
+            port_forwarding: load(PORT_FORWARDING_FILE)?,
+            gwname: manifest.gwname,
+            genid: manifest.genid,
+            vpcmap: manifest.vpcmap,
+            nat_pools: manifest.nat_pools,
+        })
+    }
+
+    /// Check that the artifacts are those their spec compiles to, e.g. to detect the changes
+    /// of the tables built by the dataplane from one version to the next.
+    pub(crate) fn verify(&self) -> Result<(), CompileError> {
+        let spec: GatewayAgentSpec = serde_yaml_ng::from_str(&self.spec)?;
+        let compiled = Self::compile(&crd_from_spec(&self.gwname, self.genid, spec))?;
+        for ((name, contents), (_, expected)) in self.files().into_iter().zip(compiled.files()) {
+            if contents != expected {
+                return Err(CompileError::Mismatch(name));
+            }
+        }
+        if self.vpcmap != compiled.vpcmap || self.nat_pools != compiled.nat_pools {
+            return Err(CompileError::Mismatch(MANIFEST_FILE));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use k8s_intf::bolero::LegalValue;
+    use k8s_less::artifacts::read_compiled_spec;
+
+    fn artifacts() -> Artifacts {
+        Artifacts {
+            gwname: "gw-1".to_string(),
+            genid: 3,
+            spec: "vpcs: {}\n".to_string(),
+            flow_filter: "flow-filter table\n".to_string(),
+            static_nat: "static nat table\n".to_string(),
+            port_forwarding: String::new(),
+            vpcmap: vec![VpcMapEntry {
+                vni: 3000,
+                name: "vpc-1".to_string(),
+            }],
+            nat_pools: vec![NatPool {
+                vpc: "vpc-1".to_string(),
+                peering: "vpc-1--vpc-2".to_string(),
+                prefixes: vec!["192.0.2.0/28".to_string()],
+                idle_timeout_secs: Some(60),
+            }],
+        }
+    }
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("{name}-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn test_artifacts_round_trip() {
+        let dir = temp_dir("compile-artifacts");
+        let artifacts = artifacts();
+        artifacts.write(&dir).unwrap();
+        assert_eq!(Artifacts::load(&dir).unwrap(), artifacts);
+        // as the dataplane loads them, in k8s-less mode
+        let spec = read_compiled_spec(&dir).unwrap();
+        assert_eq!(spec.as_deref(), Some(artifacts.spec.as_str()));
+
+        // altered artifacts are not loaded
+        std::fs::write(dir.join(STATIC_NAT_FILE), "altered").unwrap();
+        let err = Artifacts::load(&dir).unwrap_err();
+        let CompileError::Artifact(ArtifactError::Digest(name)) = &err else {
+            panic!("{err}");
+        };
+        assert_eq!(name, STATIC_NAT_FILE);
+
+        let manifest = std::fs::read_to_string(dir.join(MANIFEST_FILE)).unwrap();
+        let manifest = manifest.replace("\"format_version\": 1", "\"format_version\": 99");
+        std::fs::write(dir.join(MANIFEST_FILE), manifest).unwrap();
+        let err = Artifacts::load(&dir).unwrap_err();
+        assert!(
+            matches!(
+                err,
+                CompileError::Artifact(ArtifactError::FormatVersion(99))
+            ),
+            "{err}"
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn test_compile_round_trip() {
+        let dir = temp_dir("compile-round-trip");
+        bolero::check!()
+            .with_type::<LegalValue<GatewayAgent>>()
+            .with_iterations(10)
+            .for_each(|crd| {
+                // not all legal CRDs are valid configurations
+                let Ok(artifacts) = Artifacts::compile(crd.as_ref()) else {
+                    return;
+                };
+                artifacts.write(&dir).unwrap();
+                let loaded = Artifacts::load(&dir).unwrap();
+                assert_eq!(loaded, artifacts);
+                loaded.verify().unwrap();
+            });
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
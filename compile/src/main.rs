@@ -0,0 +1,162 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! An offline configuration compiler. The compiler takes a `GatewayAgent` CRD (or only its spec)
+//! in JSON or YAML, validates it as the dataplane would and builds the tables that the dataplane
+//! would derive from it (vpc map, flow-filter, static NAT, masquerade pools and port-forwarding).
+//! Any failure that would prevent the dataplane from applying the configuration is reported
+//! without having to deploy it.
+//!
+//! On success, the compiler writes the following artifacts to the output directory:
+//!  - `gateway-agent-spec.yaml`: the normalized spec, which the dataplane loads when running
+//!    without k8s with the output directory as its config directory, once checked against its
+//!    digest,
+//!  - a text rendering of each table, as shown by the dataplane CLI, suitable for reviews
+//!    and for diffing between configurations,
+//!  - `manifest.json`: the vpc map and the NAT pools, along with the digest of each artifact.
+//!
+//! The tables themselves are not serialized: the dataplane builds them from the spec when
+//! applying it, as the compiler does to validate it.
+//!
+//! With `--verify`, the compiler loads the artifacts of a directory instead, checks them against
+//! their digests and checks that they are what their spec compiles to, e.g. in CI before
+//! deploying artifacts compiled by another version of the dataplane.
+
+#![deny(clippy::all, clippy::pedantic)]
+
+mod artifacts;
+
+use artifacts::{Artifacts, CompileError, crd_from_spec};
+use clap::Parser;
+use k8s_intf::gateway_agent_crd::{GatewayAgent, GatewayAgentSpec};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+#[derive(Parser)]
+#[command(name = "dataplane-compile")]
+#[command(about = "Validate a GatewayAgent CRD and compile it into artifacts for the dataplane")]
+struct Args {
+    /// File with the `GatewayAgent` CRD or its spec, in JSON or YAML. Reads stdin if omitted.
+    #[arg(long, short)]
+    input: Option<PathBuf>,
+
+    /// Directory to write the artifacts to. It is created if it does not exist.
+    #[arg(long, short, required_unless_present = "verify")]
+    output: Option<PathBuf>,
+
+    /// Directory of artifacts to verify, instead of compiling a CRD
+    #[arg(long, conflicts_with_all = ["input", "output"])]
+    verify: Option<PathBuf>,
+
+    /// Name of the gateway, if the input only contains the spec of the CRD
+    #[arg(long, default_value = "gateway")]
+    name: String,
+}
+
+fn read_input(input: Option<&PathBuf>) -> Result<String, CompileError> {
+    let mut contents = String::new();
+    match input {
+        Some(path) => {
+            contents =
+                std::fs::read_to_string(path).map_err(|e| CompileError::Io(path.clone(), e))?;
+        }
+        None => {
+            io::stdin()
+                .read_to_string(&mut contents)
+                .map_err(|e| CompileError::Io(PathBuf::from("stdin"), e))?;
+        }
+    }
+    Ok(contents)
+}
+
+/// Deserialize a `GatewayAgent`. If the input only contains a spec, i.e. it has no `spec`,
+/// build the `GatewayAgent` the same way the dataplane does when running without k8s.
+fn deserialize(input: &str, gwname: &str) -> Result<GatewayAgent, CompileError> {
+    let value: serde_yaml_ng::Value = serde_yaml_ng::from_str(input)?;
+    if value.get("spec").is_some() {
+        return Ok(serde_yaml_ng::from_value(value)?);
+    }
+    let spec: GatewayAgentSpec = serde_yaml_ng::from_value(value)?;
+    Ok(crd_from_spec(gwname, 1, spec))
+}
+
+fn compile(args: &Args, output: &Path) -> Result<Artifacts, CompileError> {
+    let input = read_input(args.input.as_ref())?;
+    let crd = deserialize(&input, &args.name)?;
+    let artifacts = Artifacts::compile(&crd)?;
+    artifacts.write(output)?;
+    Ok(artifacts)
+}
+
+fn verify(dir: &Path) -> Result<Artifacts, CompileError> {
+    let artifacts = Artifacts::load(dir)?;
+    artifacts.verify()?;
+    Ok(artifacts)
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let (result, action, dir) = match (&args.verify, &args.output) {
+        (Some(dir), _) => (verify(dir), "Verified", dir),
+        (None, Some(output)) => (compile(&args, output), "Compiled", output),
+        (None, None) => unreachable!("clap requires an output without --verify"),
+    };
+    match result {
+        Ok(artifacts) => {
+            println!(
+                "{action} configuration for gateway {} (generation {}) in {}",
+                artifacts.gwname(),
+                artifacts.genid(),
+                dir.display()
+            );
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}: {e}", e.get_type());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use k8s_intf::bolero::LegalValue;
+
+    #[test]
+    fn test_deserialize_round_trip() {
+        bolero::check!()
+            .with_type::<LegalValue<GatewayAgent>>()
+            .with_iterations(10)
+            .for_each(|crd| {
+                let crd = crd.as_ref();
+                let spec = serde_yaml_ng::to_string(&crd.spec).unwrap();
+
+                let yaml = serde_yaml_ng::to_string(crd).unwrap();
+                let parsed = deserialize(&yaml, "ignored").unwrap();
+                assert_eq!(parsed.metadata.name, crd.metadata.name);
+                assert_eq!(parsed.metadata.generation, crd.metadata.generation);
+                assert_eq!(serde_yaml_ng::to_string(&parsed.spec).unwrap(), spec);
+
+                let parsed = deserialize(&spec, "gw-1").unwrap();
+                assert_eq!(parsed.metadata.name.as_deref(), Some("gw-1"));
+                assert_eq!(parsed.metadata.generation, Some(1));
+                assert_eq!(serde_yaml_ng::to_string(&parsed.spec).unwrap(), spec);
+            });
+    }
+
+    #[test]
+    fn test_deserialize_errors() {
+        // an invalid CRD is reported as such, rather than read as a spec
+        for input in [
+            "spec: 3",
+            "metadata: {}\nspec:\n  vpcs: 3\n",
+            "vpcs: [",
+            "vpcs: 3",
+        ] {
+            let err = deserialize(input, "gw-1").unwrap_err();
+            assert!(matches!(err, CompileError::Yaml(_)), "{input}: {err}");
+        }
+    }
+}
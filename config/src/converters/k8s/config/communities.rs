@@ -5,6 +5,7 @@ use crate::converters::k8s::FromK8sConversionError;
 use k8s_intf::gateway_agent_crd::GatewayAgentSpec;
 
 use crate::external::communities::PriorityCommunityTable;
+use crate::external::community_classes::{BgpCommunity, CommunityClassTable, parse_policy_class};
 
 impl TryFrom<&GatewayAgentSpec> for PriorityCommunityTable {
     type Error = FromK8sConversionError;
@@ -25,3 +26,19 @@ impl TryFrom<&GatewayAgentSpec> for PriorityCommunityTable {
         }
     }
 }
+
+impl TryFrom<&GatewayAgentSpec> for CommunityClassTable {
+    type Error = FromK8sConversionError;
+
+    fn try_from(spec: &GatewayAgentSpec) -> Result<Self, Self::Error> {
+        let mut table = CommunityClassTable::new();
+        let Some(map) = &spec.community_classes else {
+            return Ok(table);
+        };
+        for (community, class) in map {
+            let community = community.parse::<BgpCommunity>()?;
+            table.insert(community, parse_policy_class(class)?)?;
+        }
+        Ok(table)
+    }
+}
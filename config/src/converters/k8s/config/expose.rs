@@ -12,7 +12,7 @@ use lpm::prefix::{L4Protocol, PortRange, Prefix, PrefixString, PrefixWithOptiona
 
 use crate::converters::k8s::FromK8sConversionError;
 use crate::converters::k8s::config::SubnetMap;
-use crate::external::overlay::vpcpeering::VpcExpose;
+use crate::external::overlay::vpcpeering::{ExposeDirection, VpcExpose};
 
 pub(crate) fn parse_port_ranges(ports_str: &str) -> Result<Vec<PortRange>, FromK8sConversionError> {
     ports_str
@@ -117,6 +117,17 @@ fn process_as_block(
     Ok(vpc_expose)
 }
 
+fn process_direction(direction: Option<&str>) -> Result<ExposeDirection, FromK8sConversionError> {
+    match direction {
+        None | Some("both") => Ok(ExposeDirection::Both),
+        Some("inbound") => Ok(ExposeDirection::Inbound),
+        Some("outbound") => Ok(ExposeDirection::Outbound),
+        Some(other) => Err(FromK8sConversionError::InvalidData(format!(
+            "expose direction {other}"
+        ))),
+    }
+}
+
 fn process_nat_block(
     vpc_expose: VpcExpose,
     nat: Option<&GatewayAgentPeeringsPeeringExposeNat>,
@@ -282,7 +293,8 @@ impl TryFrom<(&SubnetMap, &GatewayAgentPeeringsPeeringExpose)> for VpcExposes {
     fn try_from(
         (subnets, expose): (&SubnetMap, &GatewayAgentPeeringsPeeringExpose),
     ) -> Result<Self, Self::Error> {
-        let mut vpc_expose = VpcExpose::empty();
+        let mut vpc_expose =
+            VpcExpose::empty().direction(process_direction(expose.direction.as_deref())?);
 
         // check if it is a default expose
         vpc_expose.default = expose.default.unwrap_or(false);
@@ -7,8 +7,11 @@ use k8s_intf::gateway_agent_crd::{GatewayAgent, GatewayAgentSpec};
 
 use crate::converters::k8s::FromK8sConversionError;
 use crate::external::communities::PriorityCommunityTable;
+use crate::external::community_classes::CommunityClassTable;
 use crate::external::gwgroup::GwGroupTable;
+use crate::external::mirror::MirrorConfig;
 use crate::external::overlay::Overlay;
+use crate::external::qos::QosConfig;
 use crate::external::underlay::Underlay;
 use crate::external::{ExternalConfig, ExternalConfigBuilder};
 use crate::{DeviceConfig, GenId};
@@ -94,8 +97,9 @@ impl TryFrom<&GatewayAgent> for ExternalConfig {
             .as_ref()
             .unwrap_or_else(|| unreachable!());
 
-        let device = DeviceConfig::try_from(ga_spec_gw)?;
-        let mut underlay = Underlay::try_from(ga_spec_gw)?;
+        let device = DeviceConfig::try_from(ga_spec_gw).map_err(|e| e.in_field(".spec.gateway"))?;
+        let mut underlay =
+            Underlay::try_from(ga_spec_gw).map_err(|e| e.in_field(".spec.gateway"))?;
 
         // fabricBFD variable check: enable BFD on fabric-facing BGP neighbors
         let fabric_bfd_enabled = ga
@@ -111,9 +115,13 @@ impl TryFrom<&GatewayAgent> for ExternalConfig {
             }
         }
 
-        let overlay = Overlay::try_from(&ga.spec)?;
-        let gwgroup_table = GwGroupTable::try_from(&ga.spec)?;
-        let comtable = PriorityCommunityTable::try_from(&ga.spec)?;
+        let in_spec = |e: FromK8sConversionError| e.in_field(".spec");
+        let overlay = Overlay::try_from(&ga.spec).map_err(in_spec)?;
+        let gwgroup_table = GwGroupTable::try_from(&ga.spec).map_err(in_spec)?;
+        let comtable = PriorityCommunityTable::try_from(&ga.spec).map_err(in_spec)?;
+        let community_classes = CommunityClassTable::try_from(&ga.spec).map_err(in_spec)?;
+        let qos = QosConfig::try_from(&ga.spec).map_err(in_spec)?;
+        let mirror = MirrorConfig::try_from(&ga.spec).map_err(in_spec)?;
 
         let flow_table_capacity = ga_spec_gw
             .flow_table_capacity
@@ -126,6 +134,7 @@ impl TryFrom<&GatewayAgent> for ExternalConfig {
                             "flowTableCapacity must be a non-zero value that fits in a usize"
                                 .to_string(),
                         )
+                        .in_field(".spec.gateway.flowTableCapacity")
                     })
             })
             .transpose()?;
@@ -138,6 +147,9 @@ impl TryFrom<&GatewayAgent> for ExternalConfig {
             .overlay(overlay)
             .gwgroups(gwgroup_table)
             .communities(comtable)
+            .community_classes(community_classes)
+            .qos(qos)
+            .mirror(mirror)
             .flow_table_capacity(flow_table_capacity)
             .build()
             .map_err(|e| {
@@ -1,9 +1,11 @@
 // SPDX-License-Identifier: Apache-2.0
 // Copyright Open Network Fabric Authors
 
-use crate::converters::k8s::FromK8sConversionError;
+use crate::converters::k8s::{FromK8sConversionError, index_segment, key_segment};
 use crate::converters::strings::parse_address;
-use k8s_intf::gateway_agent_crd::{GatewayAgentGroupsMembers, GatewayAgentSpec};
+use k8s_intf::gateway_agent_crd::{
+    GatewayAgentGroups, GatewayAgentGroupsMembers, GatewayAgentSpec,
+};
 
 use crate::external::gwgroup::{GwGroup, GwGroupMember, GwGroupTable};
 
@@ -15,14 +17,55 @@ impl TryFrom<&GatewayAgentGroupsMembers> for GwGroupMember {
         let ipaddress = parse_address(address)
             .map_err(|e| Self::Error::InvalidData(format!("ip address {address}: {e}")))?;
 
+        let nat_partition = value
+            .nat_partition
+            .map(|index| {
+                u16::try_from(index).map_err(|_| {
+                    Self::Error::InvalidData(format!(
+                        "NAT partition {index} of gateway {}",
+                        value.name
+                    ))
+                })
+            })
+            .transpose()?;
+
         Ok(Self {
             name: value.name.clone(),
             priority: value.priority,
             ipaddress,
+            nat_partition,
         })
     }
 }
 
+fn make_group(
+    name: &str,
+    gagroups: &GatewayAgentGroups,
+) -> Result<GwGroup, FromK8sConversionError> {
+    let mut group = GwGroup::new(name);
+    if let Some(total) = gagroups.nat_partitions {
+        let total = u16::try_from(total).map_err(|_| {
+            FromK8sConversionError::InvalidData(format!(
+                "{total} NAT partitions in gateway group {name}"
+            ))
+            .in_field(".natPartitions")
+        })?;
+        group.set_nat_partitions(total);
+    }
+    if let Some(members) = gagroups.members.as_ref() {
+        for (index, m) in members.iter().enumerate() {
+            let in_member =
+                |e: FromK8sConversionError| e.in_field(&index_segment("members", index));
+            let member = GwGroupMember::try_from(m).map_err(in_member)?;
+            group.add_member(member).map_err(|e| in_member(e.into()))?;
+        }
+    } else {
+        // we don't complain on empty groups, which are shared resources; a peering
+        // referring to an empty group is reported as a warning
+    }
+    Ok(group)
+}
+
 impl TryFrom<&GatewayAgentSpec> for GwGroupTable {
     type Error = FromK8sConversionError;
 
@@ -33,17 +76,12 @@ impl TryFrom<&GatewayAgentSpec> for GwGroupTable {
             None => Ok(group_table),
             Some(map) => {
                 for (name, gagroups) in map {
-                    let mut group = GwGroup::new(name);
-                    if let Some(members) = gagroups.members.as_ref() {
-                        for m in members {
-                            let member = GwGroupMember::try_from(m)?;
-                            group.add_member(member)?;
-                        }
-                    } else {
-                        // we don't complain on empty groups, which are shared resources
-                        // we may want to complain later if a peering refers to an empty group
-                    }
-                    group_table.add_group(group)?;
+                    let in_group =
+                        |e: FromK8sConversionError| e.in_field(&key_segment("groups", name));
+                    let group = make_group(name, gagroups).map_err(in_group)?;
+                    group_table
+                        .add_group(group)
+                        .map_err(|e| in_group(e.into()))?;
                 }
                 Ok(group_table)
             }
@@ -6,7 +6,7 @@ use net::interface::Mtu;
 
 use crate::converters::k8s::{FromK8sConversionError, ToK8sConversionError};
 use crate::internal::interfaces::interface::{
-    IfEthConfig, InterfaceAddress, InterfaceConfig, InterfaceType,
+    IfEthConfig, InterfaceAddress, InterfaceConfig, InterfaceType, UrpfMode,
 };
 
 impl TryFrom<(&str, &GatewayAgentGatewayInterfaces)> for InterfaceConfig {
@@ -47,6 +47,13 @@ impl TryFrom<(&str, &GatewayAgentGatewayInterfaces)> for InterfaceConfig {
             interface_config = interface_config.set_pci(pci);
         }
 
+        if let Some(urpf) = &iface.urpf {
+            let urpf = urpf
+                .parse::<UrpfMode>()
+                .map_err(|_| FromK8sConversionError::InvalidData(format!("uRPF mode '{urpf}'")))?;
+            interface_config = interface_config.set_urpf(urpf);
+        }
+
         Ok(interface_config)
     }
 }
@@ -68,9 +75,16 @@ impl TryFrom<&InterfaceConfig> for GatewayAgentGatewayInterfaces {
                 if_config.name
             )));
         }
+        if !if_config.static_neighbors.is_empty() {
+            return Err(ToK8sConversionError::Unsupported(format!(
+                "Static neighbors not supported by CRD {}",
+                if_config.name
+            )));
+        }
 
         let mtu = if_config.mtu.map(|m| m.to_u32());
         let pci = if_config.pci.as_ref().map(ToString::to_string);
+        let urpf = (if_config.urpf != UrpfMode::Off).then(|| if_config.urpf.to_string());
 
         let ips = if_config
             .addresses
@@ -83,6 +97,7 @@ impl TryFrom<&InterfaceConfig> for GatewayAgentGatewayInterfaces {
             kernel: None,
             mtu,
             pci,
+            urpf,
         })
     }
 }
@@ -0,0 +1,122 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+use std::net::IpAddr;
+
+use k8s_intf::gateway_agent_crd::{
+    GatewayAgentMirror, GatewayAgentMirrorDestination, GatewayAgentSpec,
+};
+use lpm::prefix::Prefix;
+
+use crate::converters::k8s::{FromK8sConversionError, key_segment};
+use crate::external::mirror::{MirrorConfig, MirrorDestination, MirrorFilter, MirrorSession};
+
+impl TryFrom<(&str, &GatewayAgentMirrorDestination)> for MirrorDestination {
+    type Error = FromK8sConversionError;
+
+    fn try_from(
+        (name, k8s_dest): (&str, &GatewayAgentMirrorDestination),
+    ) -> Result<Self, Self::Error> {
+        let remote = || {
+            let remote = k8s_dest
+                .remote
+                .as_ref()
+                .ok_or(FromK8sConversionError::MissingData(format!(
+                    "remote of mirror session {name}"
+                )))?;
+            remote.parse::<IpAddr>().map_err(|e| {
+                FromK8sConversionError::InvalidData(format!(
+                    "remote '{remote}' of mirror session {name}: {e}"
+                ))
+            })
+        };
+        match (
+            k8s_dest.interface.as_ref(),
+            k8s_dest.encapsulation.as_deref(),
+        ) {
+            (Some(interface), None) if k8s_dest.remote.is_none() => {
+                Ok(MirrorDestination::Interface(interface.clone()))
+            }
+            (None, Some("gre")) => Ok(MirrorDestination::Gre {
+                remote: remote()?,
+                key: k8s_dest.gre_key,
+            }),
+            (None, Some("erspan")) => {
+                let session_id = k8s_dest.erspan_session_id.unwrap_or_default();
+                Ok(MirrorDestination::Erspan {
+                    remote: remote()?,
+                    session_id: u16::try_from(session_id).map_err(|_| {
+                        FromK8sConversionError::InvalidData(format!(
+                            "ERSPAN session id {session_id} of mirror session {name}"
+                        ))
+                    })?,
+                })
+            }
+            (None, Some(other)) => Err(FromK8sConversionError::InvalidData(format!(
+                "encapsulation '{other}' of mirror session {name}"
+            ))),
+            (None, None) => Err(FromK8sConversionError::MissingData(format!(
+                "destination interface or encapsulation of mirror session {name}"
+            ))),
+            (Some(_), _) => Err(FromK8sConversionError::NotAllowed(format!(
+                "mirror session {name} has both a destination interface and a remote"
+            ))),
+        }
+    }
+}
+
+impl TryFrom<(&str, &GatewayAgentMirror)> for MirrorSession {
+    type Error = FromK8sConversionError;
+
+    fn try_from((name, k8s_mirror): (&str, &GatewayAgentMirror)) -> Result<Self, Self::Error> {
+        let k8s_dest =
+            k8s_mirror
+                .destination
+                .as_ref()
+                .ok_or(FromK8sConversionError::MissingData(format!(
+                    "destination of mirror session {name}"
+                )))?;
+        let mut session = MirrorSession::new(name, MirrorDestination::try_from((name, k8s_dest))?);
+        session.filter = MirrorFilter {
+            interface: k8s_mirror.interface.clone(),
+            vpc: k8s_mirror.vpc.clone(),
+            prefix: k8s_mirror
+                .prefix
+                .as_ref()
+                .map(|prefix| {
+                    prefix.parse::<Prefix>().map_err(|e| {
+                        FromK8sConversionError::InvalidData(format!(
+                            "prefix '{prefix}' of mirror session {name}: {e}"
+                        ))
+                    })
+                })
+                .transpose()?,
+        };
+        session.rate_pps = k8s_mirror.rate_pps;
+        session.truncate = k8s_mirror
+            .truncate
+            .map(|len| {
+                u16::try_from(len).map_err(|_| {
+                    FromK8sConversionError::InvalidData(format!(
+                        "truncation length {len} of mirror session {name}"
+                    ))
+                })
+            })
+            .transpose()?;
+        Ok(session)
+    }
+}
+
+impl TryFrom<&GatewayAgentSpec> for MirrorConfig {
+    type Error = FromK8sConversionError;
+
+    fn try_from(spec: &GatewayAgentSpec) -> Result<Self, Self::Error> {
+        let mut config = MirrorConfig::new();
+        for (name, k8s_mirror) in spec.mirror.iter().flatten() {
+            let session = MirrorSession::try_from((name.as_str(), k8s_mirror))
+                .map_err(|e| e.in_field(&key_segment("mirror", name)))?;
+            config.add_session(session);
+        }
+        Ok(config)
+    }
+}
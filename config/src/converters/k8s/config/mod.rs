@@ -13,8 +13,10 @@ pub mod expose;
 pub mod gateway_config;
 pub mod gwgroups;
 pub mod interface;
+pub mod mirror;
 pub mod overlay;
 pub mod peering;
+pub mod qos;
 pub mod tracecfg;
 pub mod underlay;
 pub mod vpc;
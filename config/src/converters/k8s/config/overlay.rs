@@ -6,8 +6,8 @@ use std::collections::BTreeMap;
 use k8s_intf::gateway_agent_crd::{GatewayAgentPeerings, GatewayAgentSpec, GatewayAgentVpcs};
 use lpm::prefix::Prefix;
 
-use crate::converters::k8s::FromK8sConversionError;
 use crate::converters::k8s::config::{SubnetMap, VpcSubnetMap};
+use crate::converters::k8s::{FromK8sConversionError, key_segment};
 use crate::external::overlay::Overlay;
 use crate::external::overlay::vpc::{Vpc, VpcTable};
 use crate::external::overlay::vpcpeering::{VpcPeering, VpcPeeringTable};
@@ -26,6 +26,11 @@ fn extract_subnets(
                 FromK8sConversionError::InvalidData(format!(
                     "vpc subnet CIDR {cidr} for vpc {vpc_name}: {e}"
                 ))
+                .in_field(&format!(
+                    "{}{}.cidr",
+                    key_segment("vpcs", vpc_name),
+                    key_segment("subnets", subnet_name)
+                ))
             })?;
             subnets.insert(subnet_name.clone(), prefix);
         }
@@ -39,9 +44,11 @@ fn make_vpc_table(
 ) -> Result<VpcTable, FromK8sConversionError> {
     let mut vpc_table = VpcTable::new();
     for (vpc_name, k8s_vpc) in vpcs {
-        let vpc = Vpc::try_from((vpc_name.as_str(), k8s_vpc))?;
+        let vpc = Vpc::try_from((vpc_name.as_str(), k8s_vpc))
+            .map_err(|e| e.in_field(&key_segment("vpcs", vpc_name)))?;
         vpc_table.add(vpc).map_err(|e| {
             FromK8sConversionError::InternalError(format!("Cannot add vpc {vpc_name}: {e}"))
+                .in_field(&key_segment("vpcs", vpc_name))
         })?;
     }
     Ok(vpc_table)
@@ -53,9 +60,11 @@ fn make_peering_table(
 ) -> Result<VpcPeeringTable, FromK8sConversionError> {
     let mut peering_table = VpcPeeringTable::new();
     for (peering_name, k8s_peering) in peerings {
-        let peering = VpcPeering::try_from((vpc_subnets, peering_name.as_str(), k8s_peering))?;
+        let peering = VpcPeering::try_from((vpc_subnets, peering_name.as_str(), k8s_peering))
+            .map_err(|e| e.in_field(&key_segment("peerings", peering_name)))?;
         peering_table.add(peering).map_err(|e| {
             FromK8sConversionError::InternalError(format!("Cannot add peering {peering_name}: {e}"))
+                .in_field(&key_segment("peerings", peering_name))
         })?;
     }
     Ok(peering_table)
@@ -70,7 +79,8 @@ impl TryFrom<&GatewayAgentSpec> for Overlay {
             (None, Some(peerings)) => Err(FromK8sConversionError::NotAllowed(format!(
                 "Found 0 vpcs but {} peerings",
                 peerings.len()
-            ))),
+            ))
+            .in_field(".peerings")),
             (Some(vpcs), None) => {
                 let vpc_table = make_vpc_table(vpcs)?;
                 let overlay = Overlay::new(vpc_table, VpcPeeringTable::new());
@@ -92,6 +102,7 @@ mod test {
     use super::*;
 
     use k8s_intf::bolero::LegalValue;
+    use k8s_intf::gateway_agent_crd::GatewayAgentVpcsSubnets;
 
     #[test]
     // Neither I (manish) nor AI can figure out the correct syntax to replace the closure with BTreeMap<String, GatewayAgentPeerings>::len and BTreeMap<String, GatewayAgentVpcs>::len>
@@ -114,4 +125,29 @@ mod test {
                 // Other assertions are from the conversion unwrap and type system
             });
     }
+
+    #[test]
+    fn test_bad_subnet_cidr_field() {
+        let vpc = GatewayAgentVpcs {
+            internal_id: None,
+            vni: None,
+            subnets: Some(BTreeMap::from([(
+                "subnet-1".to_string(),
+                GatewayAgentVpcsSubnets {
+                    cidr: Some("10.0.0.0/33".to_string()),
+                },
+            )])),
+            tcp_tracking: None,
+        };
+        let vpcs = BTreeMap::from([("vpc-1".to_string(), vpc)]);
+        let error = extract_subnets(&vpcs).unwrap_err().in_field(".spec");
+        assert_eq!(
+            error.field(),
+            Some(r#".spec.vpcs["vpc-1"].subnets["subnet-1"].cidr"#)
+        );
+        assert!(matches!(
+            error.cause(),
+            FromK8sConversionError::InvalidData(_)
+        ));
+    }
 }
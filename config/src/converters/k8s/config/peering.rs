@@ -4,8 +4,8 @@
 use super::expose::VpcExposes;
 use k8s_intf::gateway_agent_crd::{GatewayAgentPeerings, GatewayAgentPeeringsPeering};
 
-use crate::converters::k8s::FromK8sConversionError;
 use crate::converters::k8s::config::{SubnetMap, VpcSubnetMap};
+use crate::converters::k8s::{FromK8sConversionError, index_segment, key_segment};
 use crate::external::overlay::acl::Acl;
 use crate::external::overlay::vpcpeering::{VpcManifest, VpcPeering};
 
@@ -17,13 +17,16 @@ impl TryFrom<(&SubnetMap, &str, &GatewayAgentPeeringsPeering)> for VpcManifest {
     ) -> Result<Self, Self::Error> {
         let mut manifest = VpcManifest::new(vpc_name);
         if let Some(peering_exposes) = peering.expose.as_ref() {
-            for expose in peering_exposes {
-                manifest.add_exposes(VpcExposes::try_from((subnets, expose))?);
+            for (index, expose) in peering_exposes.iter().enumerate() {
+                let exposes = VpcExposes::try_from((subnets, expose))
+                    .map_err(|e| e.in_field(&index_segment("expose", index)))?;
+                manifest.add_exposes(exposes);
             }
         } else {
             return Err(Self::Error::MissingData(format!(
                 "VPC {vpc_name} has a peering with no exposes"
-            )));
+            ))
+            .in_field(".expose"));
         }
         Ok(manifest)
     }
@@ -38,9 +41,12 @@ impl TryFrom<(&VpcSubnetMap, &str, &GatewayAgentPeerings)> for VpcPeering {
         let gwgroup = peering
             .gateway_group
             .as_ref()
-            .ok_or(FromK8sConversionError::MissingData(format!(
-                "Peering {peering_name} is not mapped to any gateway group",
-            )))?
+            .ok_or(
+                FromK8sConversionError::MissingData(format!(
+                    "Peering {peering_name} is not mapped to any gateway group",
+                ))
+                .in_field(".gatewayGroup"),
+            )?
             .clone();
 
         let acl_spec = peering.acl.as_ref();
@@ -49,7 +55,8 @@ impl TryFrom<(&VpcSubnetMap, &str, &GatewayAgentPeerings)> for VpcPeering {
             if peering.len() != 2 {
                 return Err(FromK8sConversionError::MissingData(format!(
                     "Peering must be between 2 VPCs, found {num_peerings}"
-                )));
+                ))
+                .in_field(".peering"));
             }
             let mut manifests = peering
                 .iter()
@@ -58,6 +65,7 @@ impl TryFrom<(&VpcSubnetMap, &str, &GatewayAgentPeerings)> for VpcPeering {
                     // Should we require a subnet map for each VPC?  We will error out if the VPC references a subnet that does not exist regardless.
                     let subnets = vpc_subnets.get(vpc_name).unwrap_or(&empty_map);
                     VpcManifest::try_from((subnets, vpc_name.as_str(), peering_side))
+                        .map_err(|e| e.in_field(&key_segment("peering", vpc_name)))
                 })
                 .collect::<Result<Vec<_>, _>>()?;
 
@@ -71,15 +79,17 @@ impl TryFrom<(&VpcSubnetMap, &str, &GatewayAgentPeerings)> for VpcPeering {
                     vpc_peering.left.name.as_str(),
                     vpc_peering.right.name.as_str(),
                     acl,
-                ))?;
+                ))
+                .map_err(|e| e.in_field(".acl"))?;
                 vpc_peering.acl = Some(acl);
             }
 
             Ok(vpc_peering)
         } else {
-            Err(FromK8sConversionError::MissingData(
-                "Vpc reference in peering".to_string(),
-            ))
+            Err(
+                FromK8sConversionError::MissingData("Vpc reference in peering".to_string())
+                    .in_field(".peering"),
+            )
         }
     }
 }
@@ -0,0 +1,85 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+use k8s_intf::gateway_agent_crd::{GatewayAgentQos, GatewayAgentQosClasses, GatewayAgentSpec};
+
+use crate::converters::k8s::{FromK8sConversionError, key_segment};
+use crate::external::qos::{DropPolicy, QosClass, QosConfig, VpcQos, WredParams};
+
+impl TryFrom<(&str, &GatewayAgentQosClasses)> for QosClass {
+    type Error = FromK8sConversionError;
+
+    fn try_from((name, k8s_class): (&str, &GatewayAgentQosClasses)) -> Result<Self, Self::Error> {
+        let priority = k8s_class
+            .priority
+            .ok_or(FromK8sConversionError::MissingData(format!(
+                "priority of QoS class {name}"
+            )))?;
+        let mut class = QosClass::new(name, priority);
+        class.dscp = k8s_class.dscp.iter().flatten().copied().collect();
+        if let Some(depth) = k8s_class.queue_depth {
+            class.queue_depth = depth;
+        }
+        class.drop_policy = match k8s_class.drop_policy.as_deref() {
+            None | Some("drop-tail") => DropPolicy::DropTail,
+            Some("wred") => {
+                let missing = |param: &str| {
+                    FromK8sConversionError::MissingData(format!("WRED {param} of QoS class {name}"))
+                };
+                DropPolicy::Wred(WredParams {
+                    min_threshold: k8s_class
+                        .wred_min_threshold
+                        .ok_or_else(|| missing("min threshold"))?,
+                    max_threshold: k8s_class
+                        .wred_max_threshold
+                        .ok_or_else(|| missing("max threshold"))?,
+                    max_probability: k8s_class
+                        .wred_max_probability
+                        .ok_or_else(|| missing("max probability"))?,
+                })
+            }
+            Some(other) => {
+                return Err(FromK8sConversionError::InvalidData(format!(
+                    "drop policy '{other}' of QoS class {name}"
+                )));
+            }
+        };
+        Ok(class)
+    }
+}
+
+impl TryFrom<(&str, &GatewayAgentQos)> for VpcQos {
+    type Error = FromK8sConversionError;
+
+    fn try_from((vpc, k8s_qos): (&str, &GatewayAgentQos)) -> Result<Self, Self::Error> {
+        let rate_mbps = k8s_qos
+            .rate_mbps
+            .ok_or(FromK8sConversionError::MissingData(format!(
+                "QoS rate of vpc {vpc}"
+            )))?;
+        let mut qos = VpcQos::new(u64::from(rate_mbps) * 1_000_000);
+        if let Some(burst_kbytes) = k8s_qos.burst_kbytes {
+            qos.burst_bytes = u64::from(burst_kbytes) * 1000;
+        }
+        for (name, k8s_class) in k8s_qos.classes.iter().flatten() {
+            let class = QosClass::try_from((name.as_str(), k8s_class))
+                .map_err(|e| e.in_field(&key_segment("classes", name)))?;
+            qos.add_class(class);
+        }
+        Ok(qos)
+    }
+}
+
+impl TryFrom<&GatewayAgentSpec> for QosConfig {
+    type Error = FromK8sConversionError;
+
+    fn try_from(spec: &GatewayAgentSpec) -> Result<Self, Self::Error> {
+        let mut config = QosConfig::new();
+        for (vpc, k8s_qos) in spec.qos.iter().flatten() {
+            let qos = VpcQos::try_from((vpc.as_str(), k8s_qos))
+                .map_err(|e| e.in_field(&key_segment("qos", vpc)))?;
+            config.insert(vpc, qos);
+        }
+        Ok(config)
+    }
+}
@@ -16,7 +16,7 @@ use crate::internal::interfaces::interface::{
 use crate::internal::routing::bgp::{AfIpv4Ucast, AfL2vpnEvpn, BgpConfig, BgpNeighbor};
 use crate::internal::routing::vrf::VrfConfig;
 
-use crate::converters::k8s::FromK8sConversionError;
+use crate::converters::k8s::{FromK8sConversionError, key_segment};
 
 fn add_hardcoded_interfaces(
     vrf: &mut VrfConfig,
@@ -76,7 +76,8 @@ fn add_interfaces(
     add_hardcoded_interfaces(vrf, gateway)?;
     if let Some(interfaces) = gateway.interfaces.as_ref() {
         for (name, iface) in interfaces {
-            let iface_config = InterfaceConfig::try_from((name.as_str(), iface))?;
+            let iface_config = InterfaceConfig::try_from((name.as_str(), iface))
+                .map_err(|e| e.in_field(&key_segment("interfaces", name)))?;
             vrf.add_interface_config(iface_config);
         }
     }
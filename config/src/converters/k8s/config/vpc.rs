@@ -6,7 +6,7 @@ use std::convert::TryFrom;
 use k8s_intf::gateway_agent_crd::GatewayAgentVpcs;
 
 use crate::converters::k8s::FromK8sConversionError;
-use crate::external::overlay::vpc::Vpc;
+use crate::external::overlay::vpc::{TcpTracking, Vpc};
 
 impl TryFrom<(&str, &GatewayAgentVpcs)> for Vpc {
     type Error = FromK8sConversionError;
@@ -28,9 +28,18 @@ impl TryFrom<(&str, &GatewayAgentVpcs)> for Vpc {
             ))?;
 
         // Create a new VPC with name and VNI
-        Vpc::new(vpc_name, internal_id, *vni).map_err(|e| {
+        let mut vpc = Vpc::new(vpc_name, internal_id, *vni).map_err(|e| {
             FromK8sConversionError::InternalError(format!("Could not create VPC: {e}"))
-        })
+        })?;
+
+        if let Some(tcp_tracking) = &k8s_vpc.tcp_tracking {
+            let tcp_tracking = tcp_tracking.parse::<TcpTracking>().map_err(|_| {
+                FromK8sConversionError::InvalidData(format!("TCP tracking '{tcp_tracking}'"))
+            })?;
+            vpc.set_tcp_tracking(tcp_tracking);
+        }
+
+        Ok(vpc)
     }
 }
 
@@ -51,6 +60,10 @@ mod test {
 
                 assert_eq!(vpc.name, "test");
                 assert_eq!(Some(vpc.id.to_string()), k8s_vpc.internal_id);
+                assert_eq!(
+                    vpc.tcp_tracking.to_string(),
+                    k8s_vpc.tcp_tracking.as_deref().unwrap_or("loose")
+                );
             });
     }
 }
@@ -5,6 +5,7 @@
 
 pub mod config;
 pub mod status;
+pub mod warnings;
 
 use crate::ConfigError;
 use thiserror::Error;
@@ -34,6 +35,61 @@ pub enum FromK8sConversionError {
     /// A validation error, generally.
     #[error("Configuration error: {0}")]
     ConfigError(#[from] ConfigError),
+
+    /// An error in the field at `path` of the object converted, e.g. `.spec.vpcs["vpc-1"]`
+    #[error("{path}: {source}")]
+    InField {
+        path: String,
+        source: Box<FromK8sConversionError>,
+    },
+}
+
+impl FromK8sConversionError {
+    /// Locate the error in the field at `segment` of the object being converted. Segments are
+    /// prepended as the error goes up the conversion, so that the path ends up complete.
+    #[must_use]
+    pub fn in_field(self, segment: &str) -> Self {
+        match self {
+            FromK8sConversionError::InField { path, source } => FromK8sConversionError::InField {
+                path: format!("{segment}{path}"),
+                source,
+            },
+            error => FromK8sConversionError::InField {
+                path: segment.to_string(),
+                source: Box::new(error),
+            },
+        }
+    }
+
+    /// The path of the field in error, if known
+    #[must_use]
+    pub fn field(&self) -> Option<&str> {
+        match self {
+            FromK8sConversionError::InField { path, .. } => Some(path),
+            _ => None,
+        }
+    }
+
+    /// The error, regardless of the field it is in
+    #[must_use]
+    pub fn cause(&self) -> &FromK8sConversionError {
+        match self {
+            FromK8sConversionError::InField { source, .. } => source.cause(),
+            error => error,
+        }
+    }
+}
+
+/// The path segment of the entry `key` of the map `field` of a CRD, e.g. `.vpcs["vpc-1"]`
+#[must_use]
+pub fn key_segment(field: &str, key: &str) -> String {
+    format!(".{field}[{key:?}]")
+}
+
+/// The path segment of the item `index` of the list `field` of a CRD, e.g. `.expose[0]`
+#[must_use]
+pub fn index_segment(field: &str, index: usize) -> String {
+    format!(".{field}[{index}]")
 }
 
 #[derive(Debug, Error)]
@@ -47,3 +103,31 @@ pub enum ToK8sConversionError {
     #[error("Source configuration cannot be converted: {0}")]
     Unsupported(String),
 }
+
+/// The path, in a `GatewayAgent`, of the field a validation error of its config is about, if
+/// known.
+#[must_use]
+pub fn config_error_field(error: &ConfigError) -> Option<String> {
+    match error {
+        ConfigError::DuplicateVpcName(vpc) | ConfigError::NoRouteTableAvailable(vpc) => {
+            Some(format!(".spec{}", key_segment("vpcs", vpc)))
+        }
+        ConfigError::DuplicateVpcPeeringId(peering)
+        | ConfigError::DuplicateVpcPeerings(peering)
+        | ConfigError::IncompatibleNatModes(peering)
+        | ConfigError::NoReturnPath(peering, _) => {
+            Some(format!(".spec{}", key_segment("peerings", peering)))
+        }
+        ConfigError::DuplicateGroup(group) | ConfigError::InvalidNatPartitions(group, _) => {
+            Some(format!(".spec{}", key_segment("groups", group)))
+        }
+        ConfigError::BadVtepLocalAddress(..) => Some(".spec.gateway.vtepIP".to_string()),
+        ConfigError::BadVtepMacAddress(..) => Some(".spec.gateway.vtepMAC".to_string()),
+        ConfigError::BadStaticNeighbor(iface, ..) => {
+            Some(format!(".spec.gateway{}", key_segment("interfaces", iface)))
+        }
+        ConfigError::InvalidQos(_) => Some(".spec.qos".to_string()),
+        ConfigError::InvalidMirror(_) => Some(".spec.mirror".to_string()),
+        _ => None,
+    }
+}
@@ -0,0 +1,162 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Non-fatal issues of the spec of a `GatewayAgent`: values which are accepted by the conversion,
+//! but which are likely not what was meant, such as a subnet with no CIDR, which is ignored.
+
+use std::fmt::Display;
+
+use k8s_intf::gateway_agent_crd::GatewayAgentSpec;
+
+use crate::converters::k8s::key_segment;
+
+/// A non-fatal issue of the field at `path` of a `GatewayAgent`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ConversionWarning {
+    pub path: String,
+    pub message: String,
+}
+
+impl ConversionWarning {
+    fn new(path: String, message: String) -> Self {
+        Self { path, message }
+    }
+}
+
+impl Display for ConversionWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path, self.message)
+    }
+}
+
+/// The non-fatal issues of `spec`, the spec of a `GatewayAgent`
+#[must_use]
+pub fn conversion_warnings(spec: &GatewayAgentSpec) -> Vec<ConversionWarning> {
+    let mut warnings = vec![];
+    let peerings = spec.peerings.iter().flatten();
+
+    for (vpc_name, vpc) in spec.vpcs.iter().flatten() {
+        let vpc_path = format!(".spec{}", key_segment("vpcs", vpc_name));
+        for (subnet_name, subnet) in vpc.subnets.iter().flatten() {
+            if subnet.cidr.is_none() {
+                warnings.push(ConversionWarning::new(
+                    format!("{vpc_path}{}", key_segment("subnets", subnet_name)),
+                    format!("Subnet {subnet_name} of VPC {vpc_name} has no CIDR and is ignored"),
+                ));
+            }
+        }
+        let peered = peerings
+            .clone()
+            .filter_map(|(_, peering)| peering.peering.as_ref())
+            .any(|sides| sides.contains_key(vpc_name));
+        if !peered {
+            warnings.push(ConversionWarning::new(
+                vpc_path,
+                format!("VPC {vpc_name} is not peered with any VPC"),
+            ));
+        }
+    }
+
+    for (peering_name, peering) in peerings {
+        let Some(group_name) = peering.gateway_group.as_ref() else {
+            continue;
+        };
+        let empty = spec
+            .groups
+            .as_ref()
+            .and_then(|groups| groups.get(group_name))
+            .is_some_and(|group| group.members.as_ref().is_none_or(Vec::is_empty));
+        if empty {
+            warnings.push(ConversionWarning::new(
+                format!(
+                    ".spec{}.gatewayGroup",
+                    key_segment("peerings", peering_name)
+                ),
+                format!(
+                    "Peering {peering_name} is mapped to gateway group {group_name}, which has no members"
+                ),
+            ));
+        }
+    }
+    warnings
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use std::collections::BTreeMap;
+
+    use k8s_intf::gateway_agent_crd::{
+        GatewayAgentGroups, GatewayAgentPeerings, GatewayAgentPeeringsPeering, GatewayAgentVpcs,
+        GatewayAgentVpcsSubnets,
+    };
+
+    fn vpc(subnets: &[(&str, Option<&str>)]) -> GatewayAgentVpcs {
+        GatewayAgentVpcs {
+            internal_id: None,
+            vni: None,
+            subnets: Some(
+                subnets
+                    .iter()
+                    .map(|(name, cidr)| {
+                        let cidr = cidr.map(ToString::to_string);
+                        (name.to_string(), GatewayAgentVpcsSubnets { cidr })
+                    })
+                    .collect(),
+            ),
+            tcp_tracking: None,
+        }
+    }
+
+    #[test]
+    fn test_conversion_warnings() {
+        let side = GatewayAgentPeeringsPeering { expose: None };
+        let peering = GatewayAgentPeerings {
+            gateway_group: Some("empty".to_string()),
+            peering: Some(BTreeMap::from([
+                ("vpc-1".to_string(), side.clone()),
+                ("vpc-2".to_string(), side),
+            ])),
+            acl: None,
+        };
+        let spec = GatewayAgentSpec {
+            agent_version: None,
+            config: None,
+            groups: Some(BTreeMap::from([(
+                "empty".to_string(),
+                GatewayAgentGroups {
+                    members: None,
+                    nat_partitions: None,
+                },
+            )])),
+            communities: None,
+            community_classes: None,
+            gateway: None,
+            vpcs: Some(BTreeMap::from([
+                (
+                    "vpc-1".to_string(),
+                    vpc(&[("subnet-1", Some("10.0.0.0/24"))]),
+                ),
+                ("vpc-2".to_string(), vpc(&[("subnet-1", None)])),
+                ("vpc-3".to_string(), vpc(&[])),
+            ])),
+            peerings: Some(BTreeMap::from([("peering-1".to_string(), peering)])),
+            qos: None,
+            mirror: None,
+        };
+
+        let paths: Vec<_> = conversion_warnings(&spec)
+            .into_iter()
+            .map(|warning| warning.path)
+            .collect();
+        assert_eq!(
+            paths,
+            [
+                r#".spec.vpcs["vpc-2"].subnets["subnet-1"]"#,
+                r#".spec.vpcs["vpc-3"]"#,
+                r#".spec.peerings["peering-1"].gatewayGroup"#,
+            ]
+        );
+    }
+}
@@ -5,6 +5,7 @@
 
 #![allow(clippy::manual_string_new)]
 
+use std::collections::BTreeSet;
 use std::fmt::Display;
 
 use crate::GwConfigMeta;
@@ -12,8 +13,8 @@ use crate::external::overlay::vpc::{
     Peering, ValidatedPeering, ValidatedVpc, ValidatedVpcTable, Vpc, VpcId, VpcTable,
 };
 use crate::external::overlay::vpcpeering::{
-    ValidatedExpose, ValidatedManifest, VpcExpose, VpcExposeMasquerade, VpcExposeNatConfig,
-    VpcExposePortForwarding, VpcExposeStaticNat,
+    ExposeDirection, ExposeProtocol, ValidatedExpose, ValidatedManifest, VpcExpose,
+    VpcExposeMasquerade, VpcExposeNatConfig, VpcExposePortForwarding, VpcExposeStaticNat,
 };
 use crate::external::overlay::vpcpeering::{VpcManifest, VpcPeering, VpcPeeringTable};
 use crate::external::overlay::vpcrouting::{ExposeAction, VpcRoute, VpcRouteTable};
@@ -57,6 +58,47 @@ impl Display for VpcExposeNatConfig {
     }
 }
 
+impl Display for ExposeDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Both => write!(f, "both"),
+            Self::Inbound => write!(f, "inbound"),
+            Self::Outbound => write!(f, "outbound"),
+        }
+    }
+}
+
+impl Display for ExposeProtocol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tcp => write!(f, "tcp"),
+            Self::Udp => write!(f, "udp"),
+            Self::Icmp { icmp_type, code } => {
+                write!(f, "icmp")?;
+                if let Some(icmp_type) = icmp_type {
+                    write!(f, " type {icmp_type}")?;
+                }
+                if let Some(code) = code {
+                    write!(f, " code {code}")?;
+                }
+                Ok(())
+            }
+            Self::Number(number) => write!(f, "proto {number}"),
+        }
+    }
+}
+
+fn fmt_protocols(
+    f: &mut std::fmt::Formatter<'_>,
+    protocols: &BTreeSet<ExposeProtocol>,
+) -> std::fmt::Result {
+    if protocols.is_empty() {
+        return Ok(());
+    }
+    let protocols: Vec<_> = protocols.iter().map(ToString::to_string).collect();
+    write!(f, "\n{SEP}protocols: {}", protocols.join(", "))
+}
+
 impl Display for VpcExpose {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut carriage = false;
@@ -75,6 +117,13 @@ impl Display for VpcExpose {
                 let _ = write!(f, " {x}");
             });
         }
+        if self.direction != ExposeDirection::Both {
+            write!(f, "\n{SEP}direction: {}", self.direction)?;
+        }
+        fmt_protocols(f, &self.protocols)?;
+        if self.allow_reserved {
+            write!(f, "\n{SEP} reserved: allowed")?;
+        }
 
         writeln!(f)?;
 
@@ -111,6 +160,13 @@ impl Display for ValidatedExpose {
                 let _ = write!(f, " {x}");
             });
         }
+        if self.direction() != ExposeDirection::Both {
+            write!(f, "\n{SEP}direction: {}", self.direction())?;
+        }
+        fmt_protocols(f, self.protocols())?;
+        if self.allows_reserved() {
+            write!(f, "\n{SEP} reserved: allowed")?;
+        }
 
         writeln!(f)?;
 
@@ -349,10 +405,20 @@ impl Display for ValidatedVpcSummary<'_> {
     }
 }
 
-pub struct VpcTableRoutingTables<'a>(&'a ValidatedVpcTable);
+/// The VPCs of a [`ValidatedVpcTable`] to display: all of them, or those with the given names
+fn visible_vpcs<'a>(
+    table: &'a ValidatedVpcTable,
+    names: Option<&'a BTreeSet<String>>,
+) -> impl Iterator<Item = &'a ValidatedVpc> {
+    table
+        .values()
+        .filter(move |vpc| names.is_none_or(|names| names.contains(vpc.name())))
+}
+
+pub struct VpcTableRoutingTables<'a>(&'a ValidatedVpcTable, Option<&'a BTreeSet<String>>);
 impl Display for VpcTableRoutingTables<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for vpc in self.0.values() {
+        for vpc in visible_vpcs(self.0, self.1) {
             Heading(vpc.name().to_string()).fmt(f)?;
             vpc.route_table().fmt(f)?;
             writeln!(f)?;
@@ -373,12 +439,13 @@ impl Display for VpcTableSummary<'_> {
     }
 }
 
-pub struct ValidatedVpcTableSummary<'a>(&'a ValidatedVpcTable);
+pub struct ValidatedVpcTableSummary<'a>(&'a ValidatedVpcTable, Option<&'a BTreeSet<String>>);
 impl Display for ValidatedVpcTableSummary<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        Heading(format!("VPCs ({})", self.0.len())).fmt(f)?;
+        let count = visible_vpcs(self.0, self.1).count();
+        Heading(format!("VPCs ({count})")).fmt(f)?;
         fmt_vpc_table_heading(f)?;
-        for vpc in self.0.values() {
+        for vpc in visible_vpcs(self.0, self.1) {
             vpc.as_summary().fmt(f)?;
         }
         Ok(())
@@ -395,10 +462,10 @@ impl Display for VpcTablePeerings<'_> {
     }
 }
 
-pub struct ValidatedVpcTablePeerings<'a>(&'a ValidatedVpcTable);
+pub struct ValidatedVpcTablePeerings<'a>(&'a ValidatedVpcTable, Option<&'a BTreeSet<String>>);
 impl Display for ValidatedVpcTablePeerings<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for vpc in self.0.values() {
+        for vpc in visible_vpcs(self.0, self.1) {
             vpc.as_detailed().fmt(f)?;
         }
         Ok(())
@@ -419,15 +486,39 @@ impl VpcTable {
 impl ValidatedVpcTable {
     #[must_use]
     pub fn as_summary(&self) -> ValidatedVpcTableSummary<'_> {
-        ValidatedVpcTableSummary(self)
+        ValidatedVpcTableSummary(self, None)
     }
     #[must_use]
     pub fn as_peerings(&self) -> ValidatedVpcTablePeerings<'_> {
-        ValidatedVpcTablePeerings(self)
+        ValidatedVpcTablePeerings(self, None)
     }
     #[must_use]
     pub fn as_route_tables(&self) -> VpcTableRoutingTables<'_> {
-        VpcTableRoutingTables(self)
+        VpcTableRoutingTables(self, None)
+    }
+    /// Like [`Self::as_summary`], for the VPCs with the given names only
+    #[must_use]
+    pub fn as_summary_of<'a>(
+        &'a self,
+        names: &'a BTreeSet<String>,
+    ) -> ValidatedVpcTableSummary<'a> {
+        ValidatedVpcTableSummary(self, Some(names))
+    }
+    /// Like [`Self::as_peerings`], for the VPCs with the given names only
+    #[must_use]
+    pub fn as_peerings_of<'a>(
+        &'a self,
+        names: &'a BTreeSet<String>,
+    ) -> ValidatedVpcTablePeerings<'a> {
+        ValidatedVpcTablePeerings(self, Some(names))
+    }
+    /// Like [`Self::as_route_tables`], for the VPCs with the given names only
+    #[must_use]
+    pub fn as_route_tables_of<'a>(
+        &'a self,
+        names: &'a BTreeSet<String>,
+    ) -> VpcTableRoutingTables<'a> {
+        VpcTableRoutingTables(self, Some(names))
     }
 }
 
@@ -554,7 +645,7 @@ impl Display for VpcRouteTable {
 
 macro_rules! CONFIGDB_TBL_FMT {
     () => {
-        " {:>6} {:<25} {:<25} {} {}"
+        " {:>6} {:<12} {:<25} {:<25} {} {}"
     };
 }
 fn fmt_configdb_summary_heading(f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -563,7 +654,7 @@ fn fmt_configdb_summary_heading(f: &mut std::fmt::Formatter<'_>) -> std::fmt::Re
         "{}",
         format_args!(
             CONFIGDB_TBL_FMT!(),
-            "GenId", "created", "applied", "error", ""
+            "GenId", "apply-id", "created", "applied", "error", ""
         )
     )
 }
@@ -583,13 +674,16 @@ impl Display for GwConfigMeta {
             .map_or("none".to_string(), std::string::ToString::to_string);
 
         let is_rollback = if self.is_rollback { "(rollback)" } else { "" };
+        let apply_id = self
+            .apply_id
+            .map_or("--".to_string(), |apply_id| apply_id.to_string());
 
         writeln!(
             f,
             "{}",
             format_args!(
                 CONFIGDB_TBL_FMT!(),
-                self.genid, created, apply_time, error, is_rollback
+                self.genid, apply_id, created, apply_time, error, is_rollback
             )
         )
     }
@@ -9,7 +9,7 @@ use std::net::IpAddr;
 
 use crate::external::GenId;
 use crate::external::overlay::vpc::VpcId;
-use crate::external::overlay::vpcpeering::VpcExpose;
+use crate::external::overlay::vpcpeering::{ExposeProtocol, VpcExpose};
 
 use lpm::prefix::{Prefix, PrefixWithOptionalPorts, PrefixWithPortsSize};
 use net::eth::mac::Mac;
@@ -34,12 +34,20 @@ pub enum ConfigError {
     DuplicateMember(String),
     #[error("Duplicated gateway group member address {0}")]
     DuplicateMemberAddress(IpAddr),
+    #[error("Invalid NAT partitions in gateway group {0}: {1}")]
+    InvalidNatPartitions(String, String),
     #[error("A VPC peering object refers to non-existent VPC '{0}'")]
     NoSuchVpc(String),
     #[error("A VPC peering object refers to non-existent group '{0}'")]
     NoSuchGroup(String),
     #[error("'{0}' is not a valid VNI")]
     InvalidVpcVni(u32),
+    #[error("Route table {0} is already in use")]
+    DuplicateRouteTableId(u32),
+    #[error("Route table {0} is reserved for the {1} table")]
+    ReservedRouteTableId(u32, &'static str),
+    #[error("No route table is available for VPC {0}")]
+    NoRouteTableAvailable(String),
     #[error("Config with id {0} not found")]
     NoSuchConfig(GenId),
     #[error("Failure applying config: {0}")]
@@ -72,6 +80,8 @@ pub enum ConfigError {
         "Prefix {0} overlaps a special-use/reserved range ({1}) and cannot be used in a VpcExpose"
     )]
     SpecialUsePrefix(Prefix, &'static str),
+    #[error("Prefix {0} overlaps {1} and cannot be used in a VpcExpose")]
+    GatewayPrefix(Prefix, String),
     #[error("Invalid ACL configuration: {0}")]
     InvalidAcl(String),
     // NAT-specific
@@ -81,6 +91,10 @@ pub enum ConfigError {
     IncompatibleNatModes(String),
     #[error("Vpc {0} has a peering with no exposes")]
     NoExposes(String),
+    #[error("Peering {0} has an expose with no return path: {1}")]
+    NoReturnPath(String, String),
+    #[error("Invalid expose protocol '{0}': {1}")]
+    InvalidExposeProtocol(ExposeProtocol, &'static str),
 
     // Interface addresses
     #[error("Invalid interface address format: {0}")]
@@ -89,6 +103,8 @@ pub enum ConfigError {
     InvalidIpAddress(String),
     #[error("Invalid mask length in interface address: {0}")]
     InvalidMaskLength(String),
+    #[error("Bad static neighbor {1} on interface {0}: {2}")]
+    BadStaticNeighbor(String, IpAddr, &'static str),
     #[error("Invalid configuration: {0}")]
     Invalid(String),
 
@@ -107,8 +123,29 @@ pub enum ConfigError {
     #[error("Community {0} is mapped from distinct priorities")]
     DuplicateCommunity(String),
 
+    #[error("Invalid BGP community '{0}'")]
+    InvalidCommunity(String),
+
+    #[error("Invalid policy class '{0}'")]
+    InvalidPolicyClass(String),
+
+    #[error("Community {0} is mapped to more than one policy class")]
+    DuplicateCommunityClass(String),
+
     #[error("Failed to apply port-forwarding configuration: {0}")]
     PortForwarding(String),
+
+    #[error("Invalid QoS configuration: {0}")]
+    InvalidQos(String),
+
+    #[error("Invalid mirror configuration: {0}")]
+    InvalidMirror(String),
+
+    #[error("Invalid session logging configuration: {0}")]
+    InvalidSessionLog(String),
+
+    #[error("Invalid session table configuration: {0}")]
+    InvalidSessionTable(String),
 }
 
 /// Result-like type for configurations
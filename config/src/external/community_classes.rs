@@ -0,0 +1,217 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Dataplane configuration model: BGP community to policy class table
+
+use net::packet::PolicyClass;
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::ConfigError;
+
+/// A standard (RFC 1997) BGP community
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Ord, PartialOrd)]
+pub struct BgpCommunity(u32);
+
+impl BgpCommunity {
+    /// Well-known communities, by name
+    const WELL_KNOWN: &[(&str, u32)] = &[
+        ("graceful-shutdown", 0xFFFF_0000), // RFC 8326
+        ("blackhole", 0xFFFF_029A),         // RFC 7999
+        ("no-export", 0xFFFF_FF01),
+        ("no-advertise", 0xFFFF_FF02),
+        ("no-export-subconfed", 0xFFFF_FF03),
+    ];
+
+    #[must_use]
+    pub fn new(asn: u16, value: u16) -> Self {
+        Self((u32::from(asn) << 16) | u32::from(value))
+    }
+    #[must_use]
+    pub fn from_u32(community: u32) -> Self {
+        Self(community)
+    }
+    #[must_use]
+    pub fn as_u32(&self) -> u32 {
+        self.0
+    }
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn asn(&self) -> u16 {
+        (self.0 >> 16) as u16
+    }
+    #[must_use]
+    #[allow(clippy::cast_possible_truncation)]
+    pub fn value(&self) -> u16 {
+        self.0 as u16
+    }
+}
+
+impl FromStr for BgpCommunity {
+    type Err = ConfigError;
+
+    /// Parse a community either as `asn:value` or as the name of a well-known community
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some((_, community)) = Self::WELL_KNOWN
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(s))
+        {
+            return Ok(Self(*community));
+        }
+        let invalid = || ConfigError::InvalidCommunity(s.to_string());
+        let (asn, value) = s.split_once(':').ok_or_else(invalid)?;
+        let asn = asn.parse::<u16>().map_err(|_| invalid())?;
+        let value = value.parse::<u16>().map_err(|_| invalid())?;
+        Ok(Self::new(asn, value))
+    }
+}
+
+impl Display for BgpCommunity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.asn(), self.value())
+    }
+}
+
+/// Parse a policy class from its name in the configuration
+///
+/// # Errors
+///
+/// Returns [`ConfigError::InvalidPolicyClass`] if the name is not that of a known class.
+pub fn parse_policy_class(name: &str) -> Result<PolicyClass, ConfigError> {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "internet" => Ok(PolicyClass::Internet),
+        "private" => Ok(PolicyClass::Private),
+        "blackhole" => Ok(PolicyClass::Blackhole),
+        _ => Err(ConfigError::InvalidPolicyClass(name.to_string())),
+    }
+}
+
+/// Table mapping BGP communities to the policy class of the prefixes tagged with them
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CommunityClassTable(BTreeMap<BgpCommunity, PolicyClass>);
+impl CommunityClassTable {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Map a community to a policy class
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ConfigError::DuplicateCommunityClass`] if the community is already mapped.
+    pub fn insert(
+        &mut self,
+        community: BgpCommunity,
+        class: PolicyClass,
+    ) -> Result<(), ConfigError> {
+        if self.0.contains_key(&community) {
+            return Err(ConfigError::DuplicateCommunityClass(community.to_string()));
+        }
+        self.0.insert(community, class);
+        Ok(())
+    }
+    #[must_use]
+    pub fn get_class(&self, community: &BgpCommunity) -> Option<PolicyClass> {
+        self.0.get(community).copied()
+    }
+    /// Classify a prefix from the communities it is tagged with. If several of them map to
+    /// a class, the most restrictive class wins.
+    #[must_use]
+    pub fn classify<'a>(
+        &self,
+        communities: impl IntoIterator<Item = &'a BgpCommunity>,
+    ) -> Option<PolicyClass> {
+        communities
+            .into_iter()
+            .filter_map(|community| self.get_class(community))
+            .max()
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&BgpCommunity, &PolicyClass)> {
+        self.0.iter()
+    }
+}
+
+macro_rules! COMMUNITY_CLASS_FMT {
+    ($community:expr, $class:expr) => {
+        format_args!("   {:<16} {:<10}", $community, $class)
+    };
+}
+
+impl Display for CommunityClassTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "  ━━━━━━━ Community classes ━━━━━━━")?;
+        writeln!(f, "{}", COMMUNITY_CLASS_FMT!("community", "class"))?;
+        for (community, class) in &self.0 {
+            writeln!(f, "{}", COMMUNITY_CLASS_FMT!(community, class))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_community_parsing() {
+        let community: BgpCommunity = "65000:100".parse().unwrap();
+        assert_eq!(community, BgpCommunity::new(65000, 100));
+        assert_eq!(community.to_string(), "65000:100");
+        let blackhole: BgpCommunity = "blackhole".parse().unwrap();
+        assert_eq!(blackhole, BgpCommunity::new(65535, 666));
+
+        for bad in [
+            "",
+            "65000",
+            "65000:",
+            "70000:1",
+            "1:70000",
+            "a:b",
+            "no-such-name",
+        ] {
+            assert_eq!(
+                bad.parse::<BgpCommunity>(),
+                Err(ConfigError::InvalidCommunity(bad.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_community_classification() {
+        let inet = BgpCommunity::new(65000, 1);
+        let private = BgpCommunity::new(65000, 2);
+        let unknown = BgpCommunity::new(65000, 3);
+        let blackhole: BgpCommunity = "blackhole".parse().unwrap();
+
+        let mut table = CommunityClassTable::new();
+        table.insert(inet, PolicyClass::Internet).unwrap();
+        table.insert(private, PolicyClass::Private).unwrap();
+        table
+            .insert(blackhole, parse_policy_class("Blackhole").unwrap())
+            .unwrap();
+        assert_eq!(
+            table.insert(blackhole, PolicyClass::Internet),
+            Err(ConfigError::DuplicateCommunityClass(
+                "65535:666".to_string()
+            ))
+        );
+
+        assert_eq!(table.classify(&[]), None);
+        assert_eq!(table.classify(&[unknown]), None);
+        assert_eq!(
+            table.classify(&[unknown, inet]),
+            Some(PolicyClass::Internet)
+        );
+        assert_eq!(table.classify(&[inet, private]), Some(PolicyClass::Private));
+        assert_eq!(
+            table.classify(&[private, blackhole, inet]),
+            Some(PolicyClass::Blackhole)
+        );
+    }
+}
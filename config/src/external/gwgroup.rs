@@ -3,20 +3,39 @@
 
 //! Dataplane configuration model: gateway groups
 
-use crate::ConfigError;
+use crate::{ConfigError, ConfigResult};
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Display;
 use std::net::IpAddr;
 
+/// The maximum number of partitions the NAT port space of a group can be split into. Partitions
+/// are made of 256-port blocks, and each must keep enough of them to be usable.
+pub const MAX_NAT_PARTITIONS: u16 = 64;
+
+/// A [`NatPartition`] is the share of the NAT port space of a [`GwGroup`] that a gateway may
+/// allocate from, so that gateways sharing a NAT pool never pick the same address and port.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NatPartition {
+    pub index: u16,
+    pub total: u16,
+}
+impl Display for NatPartition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.index, self.total)
+    }
+}
+
 /// A [`GwGroupMember`] represents a gateway within a [`GwGroup`].
 /// Gateways are uniquely identified by their name. Within a group, each
-/// gateway has a priority.
+/// gateway has a priority and, if the group partitions its NAT port space,
+/// the index of its partition.
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct GwGroupMember {
     pub name: String,
     pub priority: u32,
     pub ipaddress: IpAddr,
+    pub nat_partition: Option<u16>,
 }
 impl GwGroupMember {
     #[must_use]
@@ -25,8 +44,14 @@ impl GwGroupMember {
             name: name.to_owned(),
             priority,
             ipaddress: address,
+            nat_partition: None,
         }
     }
+    #[must_use]
+    pub fn with_nat_partition(mut self, index: u16) -> Self {
+        self.nat_partition = Some(index);
+        self
+    }
 }
 
 impl Ord for GwGroupMember {
@@ -57,6 +82,7 @@ impl PartialOrd for GwGroupMember {
 pub struct GwGroup {
     name: String,
     members: Vec<GwGroupMember>,
+    nat_partitions: Option<u16>,
 }
 impl GwGroup {
     #[must_use]
@@ -64,8 +90,17 @@ impl GwGroup {
         Self {
             name: name.to_owned(),
             members: vec![],
+            nat_partitions: None,
         }
     }
+    /// Split the NAT port space of the group into `total` partitions, one per member.
+    pub fn set_nat_partitions(&mut self, total: u16) {
+        self.nat_partitions = Some(total);
+    }
+    #[must_use]
+    pub fn nat_partitions(&self) -> Option<u16> {
+        self.nat_partitions
+    }
     pub fn sort_members(&mut self) {
         //N.B. we reverse the operands since want the most preferred first
         self.members.sort_by(|m1, m2| m2.cmp(m1));
@@ -112,6 +147,60 @@ impl GwGroup {
     pub fn get_member_pos(&self, name: &str) -> Option<usize> {
         self.members.iter().position(|m| m.name.as_str() == name)
     }
+
+    /// The partition of the NAT port space that the member with the given name allocates from,
+    /// if the group is partitioned.
+    #[must_use]
+    pub fn nat_partition(&self, name: &str) -> Option<NatPartition> {
+        let total = self.nat_partitions?;
+        let index = self.get_member_by_name(name)?.nat_partition?;
+        Some(NatPartition { index, total })
+    }
+
+    /// Check the NAT partitions of the group: if the group is partitioned, every member must own
+    /// a distinct partition, so that no two members allocate from the same ports. If it is not,
+    /// no member may claim one.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ConfigError::InvalidNatPartitions`] if the partitions are inconsistent.
+    pub fn validate_nat_partitions(&self) -> ConfigResult {
+        let invalid = |reason: String| ConfigError::InvalidNatPartitions(self.name.clone(), reason);
+        let Some(total) = self.nat_partitions else {
+            return match self.members.iter().find(|m| m.nat_partition.is_some()) {
+                Some(member) => Err(invalid(format!(
+                    "member {} has a partition but the group is not partitioned",
+                    member.name
+                ))),
+                None => Ok(()),
+            };
+        };
+        if total == 0 || total > MAX_NAT_PARTITIONS {
+            return Err(invalid(format!(
+                "the number of partitions must be between 1 and {MAX_NAT_PARTITIONS}, not {total}"
+            )));
+        }
+        let mut owned = HashSet::new();
+        for member in &self.members {
+            let Some(index) = member.nat_partition else {
+                return Err(invalid(format!("member {} has no partition", member.name)));
+            };
+            if index >= total {
+                return Err(invalid(format!(
+                    "partition {index} of member {} is out of range (0-{})",
+                    member.name,
+                    total - 1
+                )));
+            }
+            if !owned.insert(index) {
+                return Err(invalid(format!(
+                    "partition {index} of member {} is already owned by another member",
+                    member.name
+                )));
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug, Default)]
@@ -159,24 +248,37 @@ impl GwGroupTable {
 }
 
 macro_rules! GW_GROUP_MEMBER_FMT {
-    ($name:expr, $prio:expr, $address:expr) => {
-        format_args!("   {:<16} {:<5} {:<40}", $name, $prio, $address)
+    ($name:expr, $prio:expr, $address:expr, $partition:expr) => {
+        format_args!(
+            "   {:<16} {:<5} {:<40} {:<9}",
+            $name, $prio, $address, $partition
+        )
     };
 }
 
 impl Display for GwGroupMember {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let partition = self
+            .nat_partition
+            .map_or_else(|| "--".to_string(), |index| index.to_string());
         write!(
             f,
             "{}",
-            GW_GROUP_MEMBER_FMT!(self.name, self.priority, self.ipaddress)
+            GW_GROUP_MEMBER_FMT!(self.name, self.priority, self.ipaddress, partition)
         )
     }
 }
 impl Display for GwGroup {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        writeln!(f, " {}:", self.name())?;
-        writeln!(f, "{}", GW_GROUP_MEMBER_FMT!("name", "prio", "address"))?;
+        match self.nat_partitions {
+            Some(total) => writeln!(f, " {} ({total} NAT partitions):", self.name())?,
+            None => writeln!(f, " {}:", self.name())?,
+        }
+        writeln!(
+            f,
+            "{}",
+            GW_GROUP_MEMBER_FMT!("name", "prio", "address", "partition")
+        )?;
         for member in self.iter() {
             writeln!(f, "{member}")?;
         }
@@ -196,7 +298,7 @@ impl Display for GwGroupTable {
 #[cfg(test)]
 #[rustfmt::skip]
 mod test {
-    use super::{GwGroup, GwGroupMember, GwGroupTable};
+    use super::{GwGroup, GwGroupMember, GwGroupTable, MAX_NAT_PARTITIONS, NatPartition};
     use crate::ConfigError;
     use crate::external::PriorityCommunityTable;
     use std::net::IpAddr;
@@ -291,6 +393,43 @@ mod test {
         println!("{group}");
     }
 
+    #[test]
+    fn test_gw_group_nat_partitions() {
+        let mut group = GwGroup::new("gw-group-1");
+        group.add_member(GwGroupMember::new("gw1", 1, IpAddr::from_str("172.128.0.1").unwrap()).with_nat_partition(0)).unwrap();
+        group.add_member(GwGroupMember::new("gw2", 2, IpAddr::from_str("172.128.0.2").unwrap()).with_nat_partition(1)).unwrap();
+
+        // members may not claim partitions of a group that is not partitioned
+        let r = group.validate_nat_partitions();
+        assert!(r.is_err_and(|e| matches!(e, ConfigError::InvalidNatPartitions(_, _))));
+
+        group.set_nat_partitions(2);
+        group.validate_nat_partitions().unwrap();
+        assert_eq!(group.nat_partition("gw2"), Some(NatPartition { index: 1, total: 2 }));
+        assert_eq!(group.nat_partition("gw3"), None);
+        println!("{group}");
+
+        // partitions may not overlap
+        let mut overlapping = group.clone();
+        overlapping.add_member(GwGroupMember::new("gw3", 3, IpAddr::from_str("172.128.0.3").unwrap()).with_nat_partition(1)).unwrap();
+        overlapping.set_nat_partitions(3);
+        let r = overlapping.validate_nat_partitions();
+        assert!(r.is_err_and(|e| matches!(e, ConfigError::InvalidNatPartitions(_, _))));
+
+        // every member needs a partition, within range
+        let mut missing = group.clone();
+        missing.add_member(GwGroupMember::new("gw3", 3, IpAddr::from_str("172.128.0.3").unwrap())).unwrap();
+        assert!(missing.validate_nat_partitions().is_err());
+        let mut out_of_range = group.clone();
+        out_of_range.add_member(GwGroupMember::new("gw3", 3, IpAddr::from_str("172.128.0.3").unwrap()).with_nat_partition(2)).unwrap();
+        assert!(out_of_range.validate_nat_partitions().is_err());
+
+        group.set_nat_partitions(0);
+        assert!(group.validate_nat_partitions().is_err());
+        group.set_nat_partitions(MAX_NAT_PARTITIONS + 1);
+        assert!(group.validate_nat_partitions().is_err());
+    }
+
     fn build_sample_gw_groups() -> GwGroupTable {
         let mut gwtable = GwGroupTable::new();
 
@@ -0,0 +1,272 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Dataplane configuration model: port mirroring (SPAN) sessions
+//!
+//! A mirror session copies the packets matching a filter to a destination: a local interface, or
+//! a remote collector reached over a GRE or ERSPAN (type II) tunnel. The packets matching the
+//! filter are those matching all of its criteria: an empty filter matches all packets. Copies may
+//! be truncated, and their rate limited, so that mirroring does not overwhelm the destination.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::net::IpAddr;
+
+use lpm::prefix::Prefix;
+
+use crate::external::overlay::vpc::ValidatedVpcTable;
+use crate::{ConfigError, ConfigResult};
+
+/// Max ERSPAN session id (10 bits)
+pub const MAX_ERSPAN_SESSION_ID: u16 = 1023;
+
+/// Min length copies may be truncated to: enough for the Ethernet, IP and transport headers
+pub const MIN_TRUNCATE: u16 = 64;
+
+/// The packets to mirror. A packet matches if it matches all the criteria given.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MirrorFilter {
+    pub interface: Option<String>, /* name of the interface the packet is received or sent on */
+    pub vpc: Option<String>,       /* name of the VPC the packet comes from or goes to */
+    pub prefix: Option<Prefix>,    /* prefix covering the source or destination address */
+}
+
+/// Where to send the copies of the packets
+#[derive(Clone, Debug, PartialEq)]
+pub enum MirrorDestination {
+    /// Send the copies out of a local interface
+    Interface(String),
+    /// Send the copies to a remote collector, in a GRE tunnel (transparent Ethernet bridging)
+    Gre { remote: IpAddr, key: Option<u32> },
+    /// Send the copies to a remote collector, in an ERSPAN type II tunnel
+    Erspan { remote: IpAddr, session_id: u16 },
+}
+
+/// A port mirroring session
+#[derive(Clone, Debug, PartialEq)]
+pub struct MirrorSession {
+    pub name: String,                   /* name of the session */
+    pub filter: MirrorFilter,           /* the packets to mirror */
+    pub destination: MirrorDestination, /* where to send the copies */
+    pub rate_pps: Option<u32>,          /* max rate of copies, in packets per second */
+    pub truncate: Option<u16>,          /* max length of copies, in octets */
+}
+
+impl MirrorSession {
+    #[must_use]
+    pub fn new(name: &str, destination: MirrorDestination) -> Self {
+        Self {
+            name: name.to_owned(),
+            filter: MirrorFilter::default(),
+            destination,
+            rate_pps: None,
+            truncate: None,
+        }
+    }
+
+    fn validate(&self, vpc_table: &ValidatedVpcTable) -> ConfigResult {
+        let invalid =
+            |reason: &str| ConfigError::InvalidMirror(format!("session '{}': {reason}", self.name));
+        if let Some(vpc) = &self.filter.vpc
+            && vpc_table.get_vpc(vpc).is_none()
+        {
+            return Err(ConfigError::NoSuchVpc(vpc.clone()));
+        }
+        if self.filter.interface.as_ref().is_some_and(String::is_empty) {
+            return Err(invalid("empty interface name"));
+        }
+        match &self.destination {
+            MirrorDestination::Interface(name) if name.is_empty() => {
+                return Err(invalid("empty destination interface name"));
+            }
+            MirrorDestination::Interface(name) if self.filter.interface.as_ref() == Some(name) => {
+                return Err(invalid("the destination interface is mirrored"));
+            }
+            MirrorDestination::Erspan { session_id, .. } if *session_id > MAX_ERSPAN_SESSION_ID => {
+                return Err(invalid(&format!(
+                    "ERSPAN session id must be in the range 0..={MAX_ERSPAN_SESSION_ID}"
+                )));
+            }
+            _ => {}
+        }
+        if self.rate_pps == Some(0) {
+            return Err(invalid("rate must be non-zero"));
+        }
+        if self.truncate.is_some_and(|len| len < MIN_TRUNCATE) {
+            return Err(invalid(&format!(
+                "copies can't be truncated to less than {MIN_TRUNCATE} octets"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// The port mirroring sessions, by name
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MirrorConfig(BTreeMap<String, MirrorSession>);
+
+impl MirrorConfig {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn add_session(&mut self, session: MirrorSession) {
+        self.0.insert(session.name.clone(), session);
+    }
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<&MirrorSession> {
+        self.0.get(name)
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &MirrorSession> {
+        self.0.values()
+    }
+
+    /// Validate the mirror sessions against the VPCs in the configuration
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ConfigError::NoSuchVpc`] if a session mirrors an unknown VPC, or
+    /// [`ConfigError::InvalidMirror`] if a session is not valid. ERSPAN sessions sharing a
+    /// collector must have distinct session ids, so that the collector can tell them apart.
+    pub fn validate(&self, vpc_table: &ValidatedVpcTable) -> ConfigResult {
+        let mut erspan = BTreeMap::new();
+        for session in self.iter() {
+            session.validate(vpc_table)?;
+            if let MirrorDestination::Erspan { remote, session_id } = session.destination
+                && let Some(other) = erspan.insert((remote, session_id), &session.name)
+            {
+                return Err(ConfigError::InvalidMirror(format!(
+                    "sessions '{other}' and '{}' have the same ERSPAN session id {session_id} towards {remote}",
+                    session.name
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for MirrorFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut criteria = vec![];
+        if let Some(interface) = &self.interface {
+            criteria.push(format!("interface {interface}"));
+        }
+        if let Some(vpc) = &self.vpc {
+            criteria.push(format!("vpc {vpc}"));
+        }
+        if let Some(prefix) = &self.prefix {
+            criteria.push(format!("prefix {prefix}"));
+        }
+        if criteria.is_empty() {
+            write!(f, "all")
+        } else {
+            write!(f, "{}", criteria.join(", "))
+        }
+    }
+}
+
+impl Display for MirrorDestination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MirrorDestination::Interface(name) => write!(f, "interface {name}"),
+            MirrorDestination::Gre { remote, key: None } => write!(f, "gre {remote}"),
+            MirrorDestination::Gre {
+                remote,
+                key: Some(key),
+            } => write!(f, "gre {remote} key {key}"),
+            MirrorDestination::Erspan { remote, session_id } => {
+                write!(f, "erspan {remote} session {session_id}")
+            }
+        }
+    }
+}
+
+impl Display for MirrorConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "  ━━━━━━━ Mirror sessions ━━━━━━━")?;
+        for session in self.iter() {
+            writeln!(
+                f,
+                "   {}: {} -> {}",
+                session.name, session.filter, session.destination
+            )?;
+            if let Some(rate) = session.rate_pps {
+                writeln!(f, "     rate: {rate} pps")?;
+            }
+            if let Some(len) = session.truncate {
+                writeln!(f, "     truncate: {len} octets")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::external::overlay::vpc::{Vpc, VpcTable};
+
+    fn vpc_table() -> ValidatedVpcTable {
+        let mut vpc_table = VpcTable::new();
+        vpc_table
+            .add(Vpc::new("vpc1", "AAAAA", 3000).unwrap())
+            .unwrap();
+        vpc_table.validate().unwrap()
+    }
+
+    fn erspan(name: &str, session_id: u16) -> MirrorSession {
+        let remote = "192.168.1.1".parse().unwrap();
+        MirrorSession::new(name, MirrorDestination::Erspan { remote, session_id })
+    }
+
+    #[test]
+    fn test_mirror_validation() {
+        let vpc_table = vpc_table();
+        let mut session = erspan("span1", 1);
+        session.filter.vpc = Some("vpc1".to_string());
+        session.filter.prefix = Some("10.0.0.0/24".parse().unwrap());
+        session.rate_pps = Some(1000);
+        session.truncate = Some(128);
+        let mut config = MirrorConfig::new();
+        config.add_session(session.clone());
+        assert!(config.validate(&vpc_table).is_ok());
+
+        // unknown vpc
+        let mut bad = session.clone();
+        bad.filter.vpc = Some("vpc2".to_string());
+        assert!(matches!(
+            bad.validate(&vpc_table),
+            Err(ConfigError::NoSuchVpc(_))
+        ));
+
+        // session id out of range
+        assert!(matches!(
+            erspan("span2", 1024).validate(&vpc_table),
+            Err(ConfigError::InvalidMirror(_))
+        ));
+
+        // truncated too short, zero rate
+        let mut bad = session.clone();
+        bad.truncate = Some(32);
+        assert!(bad.validate(&vpc_table).is_err());
+        let mut bad = session.clone();
+        bad.rate_pps = Some(0);
+        assert!(bad.validate(&vpc_table).is_err());
+
+        // mirroring to the mirrored interface
+        let mut bad = MirrorSession::new("local", MirrorDestination::Interface("eth1".to_string()));
+        bad.filter.interface = Some("eth1".to_string());
+        assert!(bad.validate(&vpc_table).is_err());
+
+        // sessions to the same collector with the same id
+        config.add_session(erspan("span2", 1));
+        assert!(matches!(
+            config.validate(&vpc_table),
+            Err(ConfigError::InvalidMirror(_))
+        ));
+    }
+}
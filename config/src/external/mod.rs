@@ -4,8 +4,13 @@
 //! Dataplane External/API configuration model. This model is the model assumed by the RPC.
 
 pub mod communities;
+pub mod community_classes;
 pub mod gwgroup;
+pub mod mirror;
 pub mod overlay;
+pub mod qos;
+pub mod session_log;
+pub mod session_table;
 pub mod underlay;
 
 use crate::ValidatedGwConfig;
@@ -13,9 +18,14 @@ use crate::external::overlay::vpc::ValidatedPeering;
 use crate::internal::device::DeviceConfig;
 use crate::{ConfigError, ConfigResult};
 use communities::PriorityCommunityTable;
+use community_classes::CommunityClassTable;
 use derive_builder::Builder;
 use gwgroup::GwGroupTable;
+use mirror::MirrorConfig;
 use overlay::{Overlay, ValidatedOverlay};
+use qos::QosConfig;
+use session_log::SessionLogConfig;
+use session_table::SessionTableConfig;
 use std::collections::HashSet;
 use std::num::NonZero;
 use tracing::debug;
@@ -35,6 +45,16 @@ pub struct ExternalConfig {
     pub gwgroups: GwGroupTable, /* gateway group table */
     pub communities: PriorityCommunityTable, /* priority-to-community table */
     #[builder(default)]
+    pub community_classes: CommunityClassTable, /* community-to-policy class table */
+    #[builder(default)]
+    pub qos: QosConfig, /* egress QoS of VPCs */
+    #[builder(default)]
+    pub mirror: MirrorConfig, /* port mirroring sessions */
+    #[builder(default)]
+    pub session_log: Option<SessionLogConfig>, /* session logging to an external collector */
+    #[builder(default)]
+    pub session_table: SessionTableConfig, /* idle timeouts of sessions and keepalive extension */
+    #[builder(default)]
     pub flow_table_capacity: Option<NonZero<usize>>, /* optional hard cap of flow table */
 }
 impl ExternalConfig {
@@ -51,6 +71,11 @@ impl ExternalConfig {
             overlay: Overlay::default(),
             gwgroups: GwGroupTable::new(),
             communities: PriorityCommunityTable::new(),
+            community_classes: CommunityClassTable::new(),
+            qos: QosConfig::new(),
+            mirror: MirrorConfig::new(),
+            session_log: None,
+            session_table: SessionTableConfig::default(),
             flow_table_capacity: None,
         }
     }
@@ -61,6 +86,7 @@ impl ExternalConfig {
 
         // check that for each group position, a community exists
         for group in self.gwgroups.iter() {
+            group.validate_nat_partitions()?;
             for member in group.iter() {
                 let rank = group
                     .get_member_pos(&member.name)
@@ -105,8 +131,15 @@ impl ExternalConfig {
         self.validate_gw_groups()?;
         let underlay = self.underlay.validate()?;
         let overlay = self.overlay.validate()?;
+        underlay.check_exposes(overlay.vpc_table())?;
         let peerings = overlay.vpc_table().peerings();
         self.check_peering_gwgroups_exist(peerings)?;
+        self.qos.validate(overlay.vpc_table())?;
+        self.mirror.validate(overlay.vpc_table())?;
+        if let Some(session_log) = &self.session_log {
+            session_log.validate()?;
+        }
+        self.session_table.validate()?;
 
         // if there are vpcs configured, there MUST be a vtep configured
         if !overlay.vpc_table().is_empty() && underlay.vtep.is_none() {
@@ -122,10 +155,25 @@ impl ExternalConfig {
             overlay,
             gwgroups: self.gwgroups,
             communities: self.communities,
+            community_classes: self.community_classes,
+            qos: self.qos,
+            mirror: self.mirror,
+            session_log: self.session_log,
+            session_table: self.session_table,
             flow_table_capacity: self.flow_table_capacity,
         };
         debug!("Community table:\n{}", validated_external.communities());
         debug!("Gateway-groups are:\n{}", validated_external.gwgroups);
+        debug!(
+            "Community classes:\n{}",
+            validated_external.community_classes()
+        );
+        debug!("QoS configuration:\n{}", validated_external.qos());
+        debug!("Mirror configuration:\n{}", validated_external.mirror());
+        if let Some(session_log) = validated_external.session_log() {
+            debug!("Session logging:\n{session_log}");
+        }
+        debug!("Session table:\n{}", validated_external.session_table());
         Ok(ValidatedGwConfig::new(validated_external))
     }
 
@@ -153,6 +201,11 @@ impl ExternalConfig {
             overlay: fake_valid_overlay,
             gwgroups: self.gwgroups,
             communities: self.communities,
+            community_classes: self.community_classes,
+            qos: self.qos,
+            mirror: self.mirror,
+            session_log: self.session_log,
+            session_table: self.session_table,
             flow_table_capacity: self.flow_table_capacity,
         }
     }
@@ -167,6 +220,11 @@ pub struct ValidatedExternalConfig {
     overlay: ValidatedOverlay, /* VPCs and peerings -- get highly developed in internal config */
     gwgroups: GwGroupTable,    /* gateway group table */
     communities: PriorityCommunityTable, /* priority-to-community table */
+    community_classes: CommunityClassTable, /* community-to-policy class table */
+    qos: QosConfig,            /* egress QoS of VPCs */
+    mirror: MirrorConfig,      /* port mirroring sessions */
+    session_log: Option<SessionLogConfig>, /* session logging to an external collector */
+    session_table: SessionTableConfig, /* idle timeouts of sessions and keepalive extension */
     flow_table_capacity: Option<NonZero<usize>>, /* optional hard cap of flow table */
 }
 
@@ -181,6 +239,11 @@ impl ValidatedExternalConfig {
             overlay: ValidatedOverlay::default(),
             gwgroups: GwGroupTable::new(),
             communities: PriorityCommunityTable::new(),
+            community_classes: CommunityClassTable::new(),
+            qos: QosConfig::new(),
+            mirror: MirrorConfig::new(),
+            session_log: None,
+            session_table: SessionTableConfig::default(),
             flow_table_capacity: None,
         }
     }
@@ -220,6 +283,31 @@ impl ValidatedExternalConfig {
         &self.communities
     }
 
+    #[must_use]
+    pub fn community_classes(&self) -> &CommunityClassTable {
+        &self.community_classes
+    }
+
+    #[must_use]
+    pub fn qos(&self) -> &QosConfig {
+        &self.qos
+    }
+
+    #[must_use]
+    pub fn mirror(&self) -> &MirrorConfig {
+        &self.mirror
+    }
+
+    #[must_use]
+    pub fn session_log(&self) -> Option<&SessionLogConfig> {
+        self.session_log.as_ref()
+    }
+
+    #[must_use]
+    pub fn session_table(&self) -> &SessionTableConfig {
+        &self.session_table
+    }
+
     #[must_use]
     pub fn flow_table_capacity(&self) -> Option<&NonZero<usize>> {
         self.flow_table_capacity.as_ref()
@@ -17,6 +17,7 @@ pub mod test {
 
     use lpm::prefix::ppsize_from;
     use lpm::prefix::{PortRange, Prefix, PrefixWithOptionalPorts};
+    use net::route::RouteTableId;
 
     /* Build sample manifests for a peering */
     fn build_manifest_vpc1() -> VpcManifest {
@@ -100,6 +101,37 @@ pub mod test {
         assert!(bad.is_err_and(|e| matches!(e, ConfigError::BadVpcId(_bad_id))));
     }
 
+    #[test]
+    fn test_vpc_table_id_checks() {
+        let mut vpc_table = VpcTable::new();
+
+        /* vpc with a reserved route table should be rejected */
+        let mut bad = Vpc::new("VPC-1", "AAAAA", 3000).expect("Should succeed");
+        bad.set_table_id(RouteTableId::try_from(254).unwrap());
+        assert_eq!(
+            bad.validate().err(),
+            Some(ConfigError::ReservedRouteTableId(254, "main"))
+        );
+
+        /* add vpc with route table 3000 */
+        let mut vpc1 = Vpc::new("VPC-1", "AAAAA", 3000).expect("Should succeed");
+        vpc1.set_table_id(RouteTableId::try_from(3000).unwrap());
+        assert!(vpc1.validate().is_ok());
+        vpc_table.add(vpc1).expect("Should succeed");
+
+        /* vpc with colliding route table should be rejected */
+        let mut bad = Vpc::new("VPC-2", "BBBBB", 4000).expect("Should succeed");
+        bad.set_table_id(RouteTableId::try_from(3000).unwrap());
+        assert_eq!(
+            vpc_table.add(bad),
+            Err(ConfigError::DuplicateRouteTableId(3000))
+        );
+
+        /* vpc without route table is fine */
+        let vpc2 = Vpc::new("VPC-2", "BBBBB", 4000).expect("Should succeed");
+        vpc_table.add(vpc2).expect("Should succeed");
+    }
+
     #[test]
     fn test_expose_validate() {
         let expose = VpcExpose::empty();
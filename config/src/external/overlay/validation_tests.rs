@@ -13,7 +13,8 @@ mod test {
     use crate::external::overlay::Overlay;
     use crate::external::overlay::vpc::{Vpc, VpcTable};
     use crate::external::overlay::vpcpeering::{
-        VpcExpose, VpcManifest, VpcPeering, VpcPeeringTable,
+        ExposeDirection, ExposeProtocol, MAX_EXPOSE_PROTOCOLS, VpcExpose, VpcManifest, VpcPeering,
+        VpcPeeringTable,
     };
 
     use lpm::prefix::{L4Protocol, PortRange, Prefix, PrefixWithOptionalPorts, ppsize_from};
@@ -234,6 +235,16 @@ mod test {
         );
     }
 
+    // Reserved prefixes are accepted when the expose explicitly allows them
+    #[test]
+    fn test_reserved_prefix_allowed() {
+        let result = VpcExpose::empty()
+            .allow_reserved()
+            .ip("127.0.0.0/8".into())
+            .validate();
+        assert!(result.is_ok(), "{result:?}");
+    }
+
     // Port 0 in port range should be rejected
     #[test]
     fn test_port_zero_rejected() {
@@ -1687,4 +1698,179 @@ mod test {
             "{result:?}",
         );
     }
+
+    // Directional expose without stateful NAT rejected: replies would be dropped
+    #[test]
+    fn test_directional_expose_without_return_path_rejected() {
+        for direction in [ExposeDirection::Inbound, ExposeDirection::Outbound] {
+            let peering = VpcPeering::with_default_group(
+                "Peering-1",
+                VpcManifest::with_exposes(
+                    "VPC-1",
+                    vec![
+                        VpcExpose::empty()
+                            .ip("1.0.0.0/24".into())
+                            .direction(direction),
+                    ],
+                ),
+                VpcManifest::with_exposes(
+                    "VPC-2",
+                    vec![VpcExpose::empty().ip("2.0.0.0/24".into())],
+                ),
+            );
+            let result = validate_overlay_with_peering(peering);
+            assert!(
+                matches!(result, Err(ConfigError::NoReturnPath(ref name, _)) if name == "Peering-1"),
+                "{result:?}",
+            );
+        }
+    }
+
+    // Directional exposes with the matching stateful NAT accepted
+    #[test]
+    fn test_directional_expose_with_stateful_nat_accepted() {
+        let peering = VpcPeering::with_default_group(
+            "Peering-1",
+            VpcManifest::with_exposes(
+                "VPC-1",
+                vec![
+                    VpcExpose::empty()
+                        .make_masquerade(None)
+                        .unwrap()
+                        .ip("1.0.0.0/24".into())
+                        .as_range("2.0.0.0/32".into())
+                        .unwrap()
+                        .direction(ExposeDirection::Outbound),
+                ],
+            ),
+            VpcManifest::with_exposes(
+                "VPC-2",
+                vec![
+                    VpcExpose::empty()
+                        .ip("3.0.0.0/24".into())
+                        .direction(ExposeDirection::Both),
+                ],
+            ),
+        );
+        let result = validate_overlay_with_peering(peering);
+        assert_eq!(result, Ok(()), "{result:?}");
+
+        let peering = VpcPeering::with_default_group(
+            "Peering-1",
+            VpcManifest::with_exposes(
+                "VPC-1",
+                vec![
+                    VpcExpose::empty()
+                        .make_port_forwarding(None, Some(L4Protocol::Tcp))
+                        .unwrap()
+                        .ip(prefix_with_ports("1.0.0.1/32", 80, 80))
+                        .as_range(prefix_with_ports("2.0.0.1/32", 8080, 8080))
+                        .unwrap()
+                        .direction(ExposeDirection::Inbound),
+                ],
+            ),
+            VpcManifest::with_exposes("VPC-2", vec![VpcExpose::empty().ip("3.0.0.0/24".into())]),
+        );
+        let result = validate_overlay_with_peering(peering);
+        assert_eq!(result, Ok(()), "{result:?}");
+    }
+
+    // Stateful NAT used against its direction rejected
+    #[test]
+    fn test_stateful_nat_with_wrong_direction_rejected() {
+        let expose = VpcExpose::empty()
+            .make_masquerade(None)
+            .unwrap()
+            .ip("1.0.0.0/24".into())
+            .as_range("2.0.0.0/32".into())
+            .unwrap()
+            .direction(ExposeDirection::Inbound);
+        assert!(matches!(expose.validate(), Err(ConfigError::Forbidden(_))));
+
+        let expose = VpcExpose::empty()
+            .make_port_forwarding(None, Some(L4Protocol::Tcp))
+            .unwrap()
+            .ip(prefix_with_ports("1.0.0.1/32", 80, 80))
+            .as_range(prefix_with_ports("2.0.0.1/32", 8080, 8080))
+            .unwrap()
+            .direction(ExposeDirection::Outbound);
+        assert!(matches!(expose.validate(), Err(ConfigError::Forbidden(_))));
+    }
+
+    // Exposes restricted to some protocols
+    #[test]
+    fn test_expose_protocols() {
+        let expose = VpcExpose::empty()
+            .ip("1.0.0.0/24".into())
+            .protocol(ExposeProtocol::Icmp {
+                icmp_type: Some(3),
+                code: Some(4),
+            })
+            .protocol(ExposeProtocol::Number(47));
+        let validated = expose.validate().unwrap();
+        assert_eq!(validated.protocols().len(), 2);
+
+        // Protocols with a name must be allowed by name
+        for number in [1, 6, 17, 58] {
+            let expose = VpcExpose::empty()
+                .ip("1.0.0.0/24".into())
+                .protocol(ExposeProtocol::Number(number));
+            assert!(matches!(
+                expose.validate(),
+                Err(ConfigError::InvalidExposeProtocol(ExposeProtocol::Number(n), _)) if n == number
+            ));
+        }
+
+        // ICMP codes only make sense for a given type
+        let expose = VpcExpose::empty()
+            .ip("1.0.0.0/24".into())
+            .protocol(ExposeProtocol::Icmp {
+                icmp_type: None,
+                code: Some(0),
+            });
+        assert!(matches!(
+            expose.validate(),
+            Err(ConfigError::InvalidExposeProtocol(_, _))
+        ));
+
+        // Too many protocols
+        let expose = (100u8..).take(MAX_EXPOSE_PROTOCOLS + 1).fold(
+            VpcExpose::empty().ip("1.0.0.0/24".into()),
+            |expose, number| expose.protocol(ExposeProtocol::Number(number)),
+        );
+        assert!(matches!(
+            expose.validate(),
+            Err(ConfigError::TooManyInstances(_, _))
+        ));
+    }
+
+    // Port ranges and port forwarding require the expose to allow TCP or UDP
+    #[test]
+    fn test_expose_protocols_with_ports() {
+        let expose = VpcExpose::empty()
+            .ip(prefix_with_ports("1.0.0.0/24", 80, 80))
+            .protocol(ExposeProtocol::ANY_ICMP);
+        assert!(matches!(expose.validate(), Err(ConfigError::Forbidden(_))));
+
+        let expose = VpcExpose::empty()
+            .ip(prefix_with_ports("1.0.0.0/24", 80, 80))
+            .protocol(ExposeProtocol::ANY_ICMP)
+            .protocol(ExposeProtocol::Tcp);
+        assert!(expose.validate().is_ok());
+
+        let port_forwarding = |protocol| {
+            VpcExpose::empty()
+                .make_port_forwarding(None, Some(L4Protocol::Tcp))
+                .unwrap()
+                .ip(prefix_with_ports("1.0.0.1/32", 80, 80))
+                .as_range(prefix_with_ports("2.0.0.1/32", 8080, 8080))
+                .unwrap()
+                .protocol(protocol)
+        };
+        assert!(port_forwarding(ExposeProtocol::Tcp).validate().is_ok());
+        assert!(matches!(
+            port_forwarding(ExposeProtocol::Udp).validate(),
+            Err(ConfigError::Forbidden(_))
+        ));
+    }
 }
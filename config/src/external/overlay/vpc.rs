@@ -5,9 +5,12 @@
 
 #![allow(clippy::missing_errors_doc)]
 
+use net::route::RouteTableId;
 use net::vxlan::Vni;
 use std::collections::BTreeMap;
 use std::collections::BTreeSet;
+use std::fmt::Display;
+use std::str::FromStr;
 #[allow(unused)]
 use tracing::{debug, error, warn};
 
@@ -68,6 +71,7 @@ impl Peering {
             acl,
         };
         valid_peering_candidate.validate_nat_combinations()?;
+        valid_peering_candidate.validate_return_paths()?;
 
         Ok(valid_peering_candidate)
     }
@@ -199,6 +203,34 @@ impl ValidatedPeering {
         }
         Ok(())
     }
+
+    fn validate_return_paths(&self) -> ConfigResult {
+        // Each side of the peering is validated from its own VPC, so we only look at the local
+        // manifest.
+        for expose in self.local.valexp() {
+            if expose.lacks_return_path() {
+                let direction = if expose.allows_inbound() {
+                    "inbound"
+                } else {
+                    "outbound"
+                };
+                let prefixes = expose
+                    .ips()
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                return Err(ConfigError::NoReturnPath(
+                    self.name.clone(),
+                    format!(
+                        "{direction}-only expose [{prefixes}] of VPC {} without stateful NAT",
+                        self.local.name()
+                    ),
+                ));
+            }
+        }
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Ord, PartialOrd, Eq)]
@@ -242,6 +274,37 @@ impl From<&Vpc> for VpcSummary {
 }
 type VpcMap = BTreeMap<String, VpcSummary>;
 
+/// How strictly the TCP sessions initiated from a VPC are tracked
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum TcpTracking {
+    /// Segments are not validated against the state of their session
+    #[default]
+    Loose,
+    /// Segments with invalid flags, invalid state transitions or out of window are dropped
+    Strict,
+}
+
+impl Display for TcpTracking {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TcpTracking::Loose => write!(f, "loose"),
+            TcpTracking::Strict => write!(f, "strict"),
+        }
+    }
+}
+
+impl FromStr for TcpTracking {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "loose" => Ok(TcpTracking::Loose),
+            "strict" => Ok(TcpTracking::Strict),
+            _ => Err(ConfigError::InvalidFormat(s.to_string())),
+        }
+    }
+}
+
 /// Representation of a VPC from the RPC
 #[derive(Clone, Debug)]
 pub struct Vpc {
@@ -250,8 +313,14 @@ pub struct Vpc {
     pub vni: Vni,                         /* mandatory */
     pub interfaces: InterfaceConfigTable, /* user-defined interfaces in this VPC */
     pub peerings: Vec<Peering>,           /* peerings of this VPC (collected) */
+    pub table_id: Option<RouteTableId>,   /* kernel route table; allocated if unset */
+    pub tcp_tracking: TcpTracking,        /* strictness of the tracking of TCP sessions */
 }
 impl Vpc {
+    /// Route tables reserved by linux, which VRFs may not use
+    const RESERVED_TABLE_IDS: [(u32, &'static str); 3] =
+        [(253, "default"), (254, "main"), (255, "local")];
+
     pub fn new(name: &str, id: &str, vni: u32) -> Result<Self, ConfigError> {
         let vni = Vni::new_checked(vni).map_err(|_| ConfigError::InvalidVpcVni(vni))?;
         Ok(Self {
@@ -260,9 +329,37 @@ impl Vpc {
             vni,
             interfaces: InterfaceConfigTable::new(),
             peerings: vec![],
+            table_id: None,
+            tcp_tracking: TcpTracking::default(),
         })
     }
 
+    /// Set the kernel route table of the VRF of this VPC, instead of having one allocated
+    pub fn set_table_id(&mut self, table_id: RouteTableId) {
+        self.table_id = Some(table_id);
+    }
+
+    /// Set how strictly the TCP sessions initiated from this VPC are tracked
+    pub fn set_tcp_tracking(&mut self, tcp_tracking: TcpTracking) {
+        self.tcp_tracking = tcp_tracking;
+    }
+
+    /// Check that a user-specified route table is not reserved
+    fn check_table_id(&self) -> ConfigResult {
+        let Some(table_id) = self.table_id else {
+            return Ok(());
+        };
+        let table_id = u32::from(table_id);
+        if let Some((_, name)) = Self::RESERVED_TABLE_IDS
+            .iter()
+            .find(|(reserved, _)| *reserved == table_id)
+        {
+            error!("VPC {} uses reserved route table {table_id}", self.name);
+            return Err(ConfigError::ReservedRouteTableId(table_id, name));
+        }
+        Ok(())
+    }
+
     /// Collect all peerings from the [`VpcPeeringTable`] table this vpc participates in
     fn set_peerings(&mut self, peering_table: &VpcPeeringTable, idmap: &VpcMap) {
         debug!("Collecting peerings for vpc '{}'...", self.name);
@@ -313,6 +410,7 @@ impl Vpc {
     pub fn validate(&self) -> Result<ValidatedVpc, ConfigError> {
         debug!("Validating config for VPC {}...", self.name);
         self.check_peering_count()?;
+        self.check_table_id()?;
 
         debug!("Checking peerings of VPC {}...", self.name);
         let validated_peerings: Vec<ValidatedPeering> = self
@@ -330,6 +428,8 @@ impl Vpc {
             interfaces: self.interfaces.clone(),
             peerings: validated_peerings,
             route_table,
+            table_id: self.table_id,
+            tcp_tracking: self.tcp_tracking,
         };
         Ok(validated_vpc)
     }
@@ -374,6 +474,8 @@ impl Vpc {
             interfaces: self.interfaces.clone(),
             peerings: fake_validated_peerings,
             route_table: not_validated_rt,
+            table_id: self.table_id,
+            tcp_tracking: self.tcp_tracking,
         }
     }
 }
@@ -386,6 +488,8 @@ pub struct ValidatedVpc {
     interfaces: InterfaceConfigTable, /* user-defined interfaces in this VPC */
     peerings: Vec<ValidatedPeering>,  /* peerings of this VPC - NOT set via gRPC */
     route_table: VpcRouteTable,
+    table_id: Option<RouteTableId>, /* user-specified kernel route table */
+    tcp_tracking: TcpTracking,
 }
 
 impl ValidatedVpc {
@@ -419,6 +523,18 @@ impl ValidatedVpc {
         &self.route_table
     }
 
+    /// The kernel route table requested for the VRF of this VPC, if any
+    #[must_use]
+    pub fn table_id(&self) -> Option<RouteTableId> {
+        self.table_id
+    }
+
+    /// How strictly the TCP sessions initiated from this VPC are tracked
+    #[must_use]
+    pub fn tcp_tracking(&self) -> TcpTracking {
+        self.tcp_tracking
+    }
+
     /// Tell how many peerings this VPC has
     #[must_use]
     pub fn num_peerings(&self) -> usize {
@@ -441,6 +557,7 @@ impl ValidatedVpc {
 pub struct VpcTable {
     vpcs: BTreeMap<String, Vpc>,
     vnis: BTreeSet<Vni>,
+    table_ids: BTreeSet<RouteTableId>,
     ids: BTreeMap<VpcId, String>, // name of vpc
 }
 impl VpcTable {
@@ -471,6 +588,11 @@ impl VpcTable {
         if self.vpcs.contains_key(&vpc.name) {
             return Err(ConfigError::DuplicateVpcName(vpc.name.clone()));
         }
+        if let Some(table_id) = vpc.table_id
+            && !self.table_ids.insert(table_id)
+        {
+            return Err(ConfigError::DuplicateRouteTableId(table_id.into()));
+        }
         self.vnis.insert(vpc.vni);
         self.ids.insert(vpc.id.clone(), vpc.name.clone());
         self.vpcs.insert(vpc.name.clone(), vpc);
@@ -10,7 +10,7 @@ use crate::utils::{
 };
 use concurrency::sync::LazyLock;
 use lpm::prefix::{IpRangeWithPorts, L4Protocol, Prefix, PrefixPortsSet, PrefixWithOptionalPorts};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::str::FromStr;
 use std::time::Duration;
 use tracing::warn;
@@ -124,6 +124,96 @@ fn empty_set() -> &'static PrefixPortsSet {
     &EMPTY_SET
 }
 
+/// The direction of the traffic that an expose allows, as seen from the VPC exposing it.
+///
+/// Exposes are independent: a VPC may expose some prefixes to its peer for both directions, and
+/// others only as destinations or only as sources of traffic.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ExposeDirection {
+    /// The prefixes can send traffic to the peer and receive traffic from it
+    #[default]
+    Both,
+    /// The prefixes can only receive traffic from the peer
+    Inbound,
+    /// The prefixes can only send traffic to the peer
+    Outbound,
+}
+
+impl ExposeDirection {
+    /// Tell if the exposed prefixes can be the destination of traffic from the peer
+    #[must_use]
+    pub fn allows_inbound(self) -> bool {
+        matches!(self, Self::Both | Self::Inbound)
+    }
+
+    /// Tell if the exposed prefixes can be the source of traffic towards the peer
+    #[must_use]
+    pub fn allows_outbound(self) -> bool {
+        matches!(self, Self::Both | Self::Outbound)
+    }
+}
+
+/// Max number of protocols that an expose can restrict its traffic to
+pub const MAX_EXPOSE_PROTOCOLS: usize = 8;
+
+/// A protocol that an expose allows traffic for.
+///
+/// Exposes allow all protocols, unless restricted to a list of them. The port ranges of the
+/// prefixes of an expose only apply to TCP and UDP. Protocols are checked on each packet, without
+/// state: an expose restricted to ICMP echo requests must also allow echo replies on the other
+/// side of the peering.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ExposeProtocol {
+    Tcp,
+    Udp,
+    /// ICMP, or ICMPv6 for IPv6 prefixes, optionally restricted to a message type, and code
+    Icmp {
+        icmp_type: Option<u8>,
+        code: Option<u8>,
+    },
+    /// Any other IP protocol, by number: 47 for GRE, 50 for ESP...
+    Number(u8),
+}
+
+impl ExposeProtocol {
+    /// ICMP, or ICMPv6, with all its messages
+    pub const ANY_ICMP: ExposeProtocol = ExposeProtocol::Icmp {
+        icmp_type: None,
+        code: None,
+    };
+
+    /// Protocol numbers which must be allowed by name, as they can be matched more precisely
+    const NAMED_NUMBERS: [u8; 4] = [1, 6, 17, 58]; // ICMP, TCP, UDP, ICMPv6
+
+    /// Tell if the protocol carries ports
+    #[must_use]
+    pub fn has_ports(self) -> bool {
+        matches!(self, Self::Tcp | Self::Udp)
+    }
+
+    /// Validate the [`ExposeProtocol`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ConfigError::InvalidExposeProtocol`] if the protocol number of TCP, UDP or ICMP
+    /// is used instead of its name, or if an ICMP code is given without a type.
+    pub fn validate(self) -> ConfigResult {
+        match self {
+            Self::Number(number) if Self::NAMED_NUMBERS.contains(&number) => Err(
+                ConfigError::InvalidExposeProtocol(self, "use the protocol name instead"),
+            ),
+            Self::Icmp {
+                icmp_type: None,
+                code: Some(_),
+            } => Err(ConfigError::InvalidExposeProtocol(
+                self,
+                "an ICMP code requires an ICMP type",
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
 use crate::{ConfigError, ConfigResult};
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct VpcExpose {
@@ -131,6 +221,10 @@ pub struct VpcExpose {
     pub ips: PrefixPortsSet,
     pub nots: PrefixPortsSet,
     pub nat: Option<VpcExposeNat>,
+    pub direction: ExposeDirection,
+    pub protocols: BTreeSet<ExposeProtocol>,
+    /// Whether the prefixes may be reserved, see [`VpcExpose::allow_reserved`]
+    pub allow_reserved: bool,
 }
 impl VpcExpose {
     /// Make the [`VpcExpose`] use static NAT.
@@ -235,6 +329,25 @@ impl VpcExpose {
         self.default = true;
         self
     }
+    /// Explicitly allow the expose to use reserved prefixes: special-use ones, like loopback or
+    /// link-local prefixes, and those of the underlay of the gateway. These are rejected
+    /// otherwise, since routing or NATing them would likely cut the gateway off.
+    #[must_use]
+    pub fn allow_reserved(mut self) -> Self {
+        self.allow_reserved = true;
+        self
+    }
+    #[must_use]
+    pub fn direction(mut self, direction: ExposeDirection) -> Self {
+        self.direction = direction;
+        self
+    }
+    /// Restrict the traffic of the expose to a protocol, in addition to those already allowed.
+    #[must_use]
+    pub fn protocol(mut self, protocol: ExposeProtocol) -> Self {
+        self.protocols.insert(protocol);
+        self
+    }
     #[must_use]
     pub fn ip(mut self, prefix: PrefixWithOptionalPorts) -> Self {
         self.ips.insert(prefix);
@@ -316,6 +429,46 @@ impl VpcExpose {
         Ok(())
     }
 
+    // Check the protocols the expose is restricted to, if any. Port ranges only apply to TCP and
+    // UDP, so an expose with port ranges must allow at least one of them.
+    fn validate_protocols(&self) -> ConfigResult {
+        if self.protocols.is_empty() {
+            return Ok(());
+        }
+        if self.protocols.len() > MAX_EXPOSE_PROTOCOLS {
+            return Err(ConfigError::TooManyInstances(
+                "expose protocols",
+                MAX_EXPOSE_PROTOCOLS,
+            ));
+        }
+        for protocol in &self.protocols {
+            protocol.validate()?;
+        }
+        let has_ports = self
+            .ips
+            .iter()
+            .chain(self.as_range_or_empty().iter())
+            .any(|prefix| prefix.ports().is_some());
+        if has_ports && !self.protocols.iter().any(|p| p.has_ports()) {
+            return Err(ConfigError::Forbidden(
+                "Port ranges require the expose to allow TCP or UDP",
+            ));
+        }
+        if let Some(nat) = &self.nat
+            && nat.is_port_forwarding()
+            && !self.protocols.iter().any(|p| match p {
+                ExposeProtocol::Tcp => nat.proto != L4Protocol::Udp,
+                ExposeProtocol::Udp => nat.proto != L4Protocol::Tcp,
+                _ => false,
+            })
+        {
+            return Err(ConfigError::Forbidden(
+                "Port forwarding requires the expose to allow its protocol",
+            ));
+        }
+        Ok(())
+    }
+
     /// Validate the [`VpcExpose`].
     ///
     /// # Errors
@@ -325,6 +478,7 @@ impl VpcExpose {
     pub fn validate(&self) -> Result<ValidatedExpose, ConfigError> {
         // Check default exposes and prefixes
         self.validate_default_expose()?;
+        self.validate_protocols()?;
 
         // Forbid empty ips list
         if self.ips.is_empty() && !self.default {
@@ -394,15 +548,22 @@ impl VpcExpose {
         // Reject reserved prefixes in the effective (post-exclusion) sets. Checking here rather than
         // on the raw input means a reserved prefix that is entirely removed by an exclusion is not
         // flagged, since it can never become an endpoint.
-        reject_special_use(&clone.ips)?;
-        if let Some(nat) = &clone.nat {
-            reject_special_use(&nat.as_range)?;
+        if self.allow_reserved {
+            warn!("Expose {self} is allowed to use reserved prefixes");
+        } else {
+            reject_special_use(&clone.ips)?;
+            if let Some(nat) = &clone.nat {
+                reject_special_use(&nat.as_range)?;
+            }
         }
 
         let collapsed_expose = ValidatedExpose {
             default: clone.default,
             ips: clone.ips,
             nat: clone.nat,
+            direction: clone.direction,
+            protocols: clone.protocols,
+            allow_reserved: clone.allow_reserved,
         };
 
         // Ensure we don't exclude all of the allowed prefixes
@@ -463,6 +624,19 @@ impl VpcExpose {
             }
         }
 
+        // Masquerade only translates traffic sent by the exposing VPC, and port forwarding only
+        // traffic sent by the peer
+        if collapsed_expose.has_masquerade() && !collapsed_expose.allows_outbound() {
+            return Err(ConfigError::Forbidden(
+                "Masquerade cannot be used with an inbound-only expose",
+            ));
+        }
+        if collapsed_expose.has_port_forwarding() && !collapsed_expose.allows_inbound() {
+            return Err(ConfigError::Forbidden(
+                "Port forwarding cannot be used with an outbound-only expose",
+            ));
+        }
+
         // For masquerade, we don't support port ranges
         if collapsed_expose.has_masquerade()
             && (collapsed_expose.ips().iter().any(|p| p.ports().is_some())
@@ -488,6 +662,9 @@ impl VpcExpose {
             default: self.default,
             ips: self.ips.clone(),
             nat: self.nat.clone(),
+            direction: self.direction,
+            protocols: self.protocols.clone(),
+            allow_reserved: self.allow_reserved,
         }
     }
 }
@@ -497,6 +674,9 @@ pub struct ValidatedExpose {
     default: bool,
     ips: PrefixPortsSet,
     nat: Option<VpcExposeNat>,
+    direction: ExposeDirection,
+    protocols: BTreeSet<ExposeProtocol>,
+    allow_reserved: bool,
 }
 
 impl ValidatedExpose {
@@ -505,6 +685,12 @@ impl ValidatedExpose {
         self.default
     }
 
+    /// Tell if the expose was explicitly allowed to use reserved prefixes
+    #[must_use]
+    pub fn allows_reserved(&self) -> bool {
+        self.allow_reserved
+    }
+
     #[must_use]
     pub fn ips(&self) -> &PrefixPortsSet {
         &self.ips
@@ -611,6 +797,42 @@ impl ValidatedExpose {
         self.nat.as_ref()
     }
 
+    #[must_use]
+    pub fn direction(&self) -> ExposeDirection {
+        self.direction
+    }
+
+    /// The protocols the traffic of the expose is restricted to. All protocols are allowed if
+    /// empty.
+    #[must_use]
+    pub fn protocols(&self) -> &BTreeSet<ExposeProtocol> {
+        &self.protocols
+    }
+
+    /// Tell if the prefixes of the expose can be the destination of traffic from the peer
+    #[must_use]
+    pub fn allows_inbound(&self) -> bool {
+        self.direction.allows_inbound()
+    }
+
+    /// Tell if the prefixes of the expose can be the source of traffic towards the peer
+    #[must_use]
+    pub fn allows_outbound(&self) -> bool {
+        self.direction.allows_outbound()
+    }
+
+    /// Tell if replies to the traffic allowed by the expose are dropped. Traffic in a single
+    /// direction has no return path, unless stateful NAT creates flows for it: masquerade for
+    /// outbound traffic, port forwarding for inbound traffic.
+    #[must_use]
+    pub fn lacks_return_path(&self) -> bool {
+        match self.direction {
+            ExposeDirection::Both => false,
+            ExposeDirection::Inbound => !self.has_port_forwarding(),
+            ExposeDirection::Outbound => !self.has_masquerade(),
+        }
+    }
+
     #[must_use]
     pub fn nat_config(&self) -> Option<&VpcExposeNatConfig> {
         self.nat.as_ref().map(|nat| &nat.config)
@@ -0,0 +1,423 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Dataplane configuration model: egress QoS of VPCs
+//!
+//! The egress traffic towards a VPC may be shaped to some rate. The traffic of the VPC is
+//! classified by DSCP into classes, each with its own queue. Queues are served in strict
+//! priority order, while the shaper allows it. Packets whose DSCP does not belong to any class
+//! are assigned to the class with the lowest priority.
+//!
+//! Independently of egress QoS, a VPC may have a policy on the DSCP of its VXLAN traffic: the
+//! outer DSCP may be copied from the inner header or fixed at encapsulation, and the inner DSCP
+//! may be rewritten with the outer one at decapsulation.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+
+use crate::external::overlay::vpc::ValidatedVpcTable;
+use crate::{ConfigError, ConfigResult};
+
+/// Max value of a DSCP
+const MAX_DSCP: u8 = 63;
+
+/// Max number of packets that a queue may hold
+pub const MAX_QUEUE_DEPTH: u32 = 65536;
+
+/// Parameters of Weighted Random Early Detection (WRED). Thresholds are in packets and apply
+/// to the average depth of the queue.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WredParams {
+    pub min_threshold: u32,  /* avg depth below which no packet is dropped */
+    pub max_threshold: u32,  /* avg depth above which all packets are dropped */
+    pub max_probability: u8, /* drop probability (percent) when reaching max_threshold */
+}
+
+/// The policy to drop packets when a queue builds up
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum DropPolicy {
+    /// Only drop packets when the queue is full
+    #[default]
+    DropTail,
+    /// Randomly drop packets as the queue builds up, before it gets full
+    Wred(WredParams),
+}
+
+/// A traffic class of a VPC, with its own queue
+#[derive(Clone, Debug, PartialEq)]
+pub struct QosClass {
+    pub name: String,       /* name of the class */
+    pub priority: u8,       /* priority: the smaller, the higher */
+    pub dscp: BTreeSet<u8>, /* DSCP values of the packets in this class */
+    pub queue_depth: u32,   /* max number of packets in the queue */
+    pub drop_policy: DropPolicy,
+}
+
+impl QosClass {
+    /// Default max number of packets in the queue of a class
+    pub const DEFAULT_QUEUE_DEPTH: u32 = 256;
+
+    #[must_use]
+    pub fn new(name: &str, priority: u8) -> Self {
+        Self {
+            name: name.to_owned(),
+            priority,
+            dscp: BTreeSet::new(),
+            queue_depth: Self::DEFAULT_QUEUE_DEPTH,
+            drop_policy: DropPolicy::DropTail,
+        }
+    }
+
+    fn validate(&self, vpc: &str) -> ConfigResult {
+        let invalid = |reason: String| {
+            ConfigError::InvalidQos(format!("class '{}' of vpc '{vpc}': {reason}", self.name))
+        };
+        if let Some(dscp) = self.dscp.iter().find(|dscp| **dscp > MAX_DSCP) {
+            return Err(invalid(format!("invalid DSCP {dscp}")));
+        }
+        if self.queue_depth == 0 || self.queue_depth > MAX_QUEUE_DEPTH {
+            return Err(invalid(format!(
+                "queue depth must be in the range 1..={MAX_QUEUE_DEPTH}"
+            )));
+        }
+        if let DropPolicy::Wred(wred) = &self.drop_policy {
+            if wred.min_threshold >= wred.max_threshold {
+                return Err(invalid(
+                    "WRED min threshold must be smaller than max threshold".to_string(),
+                ));
+            }
+            if wred.max_threshold > self.queue_depth {
+                return Err(invalid(
+                    "WRED max threshold exceeds the queue depth".to_string(),
+                ));
+            }
+            if wred.max_probability == 0 || wred.max_probability > 100 {
+                return Err(invalid(
+                    "WRED max probability must be in the range 1..=100".to_string(),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The egress QoS configuration of a VPC
+#[derive(Clone, Debug, PartialEq)]
+pub struct VpcQos {
+    pub rate_bps: u64,          /* rate the egress traffic of the VPC is shaped to */
+    pub burst_bytes: u64,       /* max burst allowed by the shaper */
+    pub classes: Vec<QosClass>, /* traffic classes, sorted by priority */
+}
+
+impl VpcQos {
+    /// Time worth of traffic at the shaping rate allowed in bursts, if not configured
+    const DEFAULT_BURST_MSECS: u64 = 10;
+
+    /// Create the QoS configuration for a VPC, shaping its traffic at `rate_bps`
+    #[must_use]
+    pub fn new(rate_bps: u64) -> Self {
+        Self {
+            rate_bps,
+            burst_bytes: rate_bps / 8 * Self::DEFAULT_BURST_MSECS / 1000,
+            classes: vec![],
+        }
+    }
+
+    /// Add a traffic class, keeping classes sorted by priority
+    pub fn add_class(&mut self, class: QosClass) {
+        self.classes.push(class);
+        self.classes.sort_by_key(|class| class.priority);
+    }
+
+    fn validate(&self, vpc: &str) -> ConfigResult {
+        if self.rate_bps == 0 {
+            return Err(ConfigError::InvalidQos(format!(
+                "rate of vpc '{vpc}' must be non-zero"
+            )));
+        }
+        let mut names = BTreeSet::new();
+        let mut priorities = BTreeSet::new();
+        let mut dscps = BTreeSet::new();
+        for class in &self.classes {
+            class.validate(vpc)?;
+            if !names.insert(class.name.as_str()) {
+                return Err(ConfigError::InvalidQos(format!(
+                    "duplicate class '{}' in vpc '{vpc}'",
+                    class.name
+                )));
+            }
+            if !priorities.insert(class.priority) {
+                return Err(ConfigError::InvalidQos(format!(
+                    "more than one class of vpc '{vpc}' has priority {}",
+                    class.priority
+                )));
+            }
+            if let Some(dscp) = class.dscp.iter().find(|dscp| !dscps.insert(**dscp)) {
+                return Err(ConfigError::InvalidQos(format!(
+                    "DSCP {dscp} belongs to more than one class in vpc '{vpc}'"
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How the outer DSCP of the VXLAN packets towards a VPC is set at encapsulation
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EncapDscp {
+    /// Keep the outer DSCP of the packet if it was decapsulated, and use 0 otherwise
+    #[default]
+    Preserve,
+    /// Copy the DSCP of the inner header
+    Copy,
+    /// Use a fixed DSCP
+    Fixed(u8),
+}
+
+/// How the inner DSCP of the VXLAN packets from a VPC is set at decapsulation
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DecapDscp {
+    /// Leave the inner DSCP untouched
+    #[default]
+    Keep,
+    /// Copy the DSCP of the outer header
+    Copy,
+}
+
+/// The DSCP policy of the VXLAN traffic of a VPC
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TunnelDscp {
+    pub encap: EncapDscp,
+    pub decap: DecapDscp,
+}
+
+impl TunnelDscp {
+    fn validate(&self, vpc: &str) -> ConfigResult {
+        match self.encap {
+            EncapDscp::Fixed(dscp) if dscp > MAX_DSCP => Err(ConfigError::InvalidQos(format!(
+                "invalid outer DSCP {dscp} of vpc '{vpc}'"
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The egress QoS configuration of VPCs, and the DSCP policy of their VXLAN traffic, by VPC name
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QosConfig {
+    vpcs: BTreeMap<String, VpcQos>,
+    tunnel: BTreeMap<String, TunnelDscp>,
+}
+
+impl QosConfig {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn insert(&mut self, vpc: &str, qos: VpcQos) {
+        self.vpcs.insert(vpc.to_owned(), qos);
+    }
+    #[must_use]
+    pub fn get(&self, vpc: &str) -> Option<&VpcQos> {
+        self.vpcs.get(vpc)
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.vpcs.is_empty() && self.tunnel.is_empty()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &VpcQos)> {
+        self.vpcs.iter()
+    }
+
+    /// Set the DSCP policy of the VXLAN traffic of a VPC
+    pub fn set_tunnel_dscp(&mut self, vpc: &str, tunnel: TunnelDscp) {
+        self.tunnel.insert(vpc.to_owned(), tunnel);
+    }
+    #[must_use]
+    pub fn tunnel_dscp(&self, vpc: &str) -> Option<&TunnelDscp> {
+        self.tunnel.get(vpc)
+    }
+    pub fn tunnel_iter(&self) -> impl Iterator<Item = (&String, &TunnelDscp)> {
+        self.tunnel.iter()
+    }
+
+    /// Validate the QoS configuration against the VPCs in the configuration
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ConfigError::NoSuchVpc`] if QoS is configured for an unknown VPC, or
+    /// [`ConfigError::InvalidQos`] if the QoS configuration of some VPC is not valid.
+    pub fn validate(&self, vpc_table: &ValidatedVpcTable) -> ConfigResult {
+        let mut vpcs = self.vpcs.keys().chain(self.tunnel.keys());
+        if let Some(vpc) = vpcs.find(|vpc| vpc_table.get_vpc(vpc).is_none()) {
+            return Err(ConfigError::NoSuchVpc(vpc.clone()));
+        }
+        for (vpc, qos) in &self.vpcs {
+            qos.validate(vpc)?;
+        }
+        for (vpc, tunnel) in &self.tunnel {
+            tunnel.validate(vpc)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for EncapDscp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EncapDscp::Preserve => write!(f, "preserve"),
+            EncapDscp::Copy => write!(f, "copy-inner"),
+            EncapDscp::Fixed(dscp) => write!(f, "fixed({dscp})"),
+        }
+    }
+}
+
+impl Display for DecapDscp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecapDscp::Keep => write!(f, "keep"),
+            DecapDscp::Copy => write!(f, "copy-outer"),
+        }
+    }
+}
+
+impl Display for DropPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DropPolicy::DropTail => write!(f, "drop-tail"),
+            DropPolicy::Wred(wred) => write!(
+                f,
+                "wred({}-{}, {}%)",
+                wred.min_threshold, wred.max_threshold, wred.max_probability
+            ),
+        }
+    }
+}
+
+macro_rules! QOS_CLASS_FMT {
+    ($name:expr, $prio:expr, $depth:expr, $drop:expr, $dscp:expr) => {
+        format_args!(
+            "     {:<16} {:>4} {:>6} {:<20} {}",
+            $name, $prio, $depth, $drop, $dscp
+        )
+    };
+}
+
+impl Display for QosConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "  ━━━━━━━ Egress QoS ━━━━━━━")?;
+        for (vpc, qos) in &self.vpcs {
+            writeln!(
+                f,
+                "   vpc {vpc}: rate {} bps, burst {} bytes",
+                qos.rate_bps, qos.burst_bytes
+            )?;
+            writeln!(
+                f,
+                "{}",
+                QOS_CLASS_FMT!("class", "prio", "depth", "drop", "dscp")
+            )?;
+            for class in &qos.classes {
+                let dscp: Vec<_> = class.dscp.iter().map(ToString::to_string).collect();
+                writeln!(
+                    f,
+                    "{}",
+                    QOS_CLASS_FMT!(
+                        class.name,
+                        class.priority,
+                        class.queue_depth,
+                        class.drop_policy.to_string(),
+                        dscp.join(",")
+                    )
+                )?;
+            }
+        }
+        if !self.tunnel.is_empty() {
+            writeln!(f, "  ━━━━━━━ VXLAN DSCP ━━━━━━━")?;
+        }
+        for (vpc, tunnel) in &self.tunnel {
+            writeln!(
+                f,
+                "   vpc {vpc}: encap {}, decap {}",
+                tunnel.encap, tunnel.decap
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_vpc_qos_validation() {
+        let mut qos = VpcQos::new(100_000_000);
+        assert_eq!(qos.burst_bytes, 125_000);
+
+        let mut voice = QosClass::new("voice", 0);
+        voice.dscp.insert(46);
+        let mut bulk = QosClass::new("bulk", 7);
+        bulk.dscp.insert(8);
+        bulk.drop_policy = DropPolicy::Wred(WredParams {
+            min_threshold: 64,
+            max_threshold: 192,
+            max_probability: 10,
+        });
+        qos.add_class(bulk.clone());
+        qos.add_class(voice.clone());
+        assert_eq!(qos.classes[0].name, "voice");
+        assert!(qos.validate("vpc-1").is_ok());
+
+        // dscp in two classes
+        let mut other = QosClass::new("other", 3);
+        other.dscp.insert(46);
+        let mut bad = qos.clone();
+        bad.add_class(other);
+        assert!(matches!(
+            bad.validate("vpc-1"),
+            Err(ConfigError::InvalidQos(_))
+        ));
+
+        // duplicate priority
+        let mut bad = qos.clone();
+        bad.add_class(QosClass::new("other", 7));
+        assert!(matches!(
+            bad.validate("vpc-1"),
+            Err(ConfigError::InvalidQos(_))
+        ));
+
+        // WRED thresholds exceeding queue depth
+        let mut bad = VpcQos::new(1_000_000);
+        bulk.queue_depth = 128;
+        bad.add_class(bulk);
+        assert!(matches!(
+            bad.validate("vpc-1"),
+            Err(ConfigError::InvalidQos(_))
+        ));
+
+        // zero rate
+        let mut bad = VpcQos::new(0);
+        bad.add_class(voice);
+        assert!(matches!(
+            bad.validate("vpc-1"),
+            Err(ConfigError::InvalidQos(_))
+        ));
+    }
+
+    #[test]
+    fn test_tunnel_dscp_validation() {
+        let mut tunnel = TunnelDscp {
+            encap: EncapDscp::Fixed(MAX_DSCP),
+            decap: DecapDscp::Copy,
+        };
+        assert!(tunnel.validate("vpc-1").is_ok());
+        tunnel.encap = EncapDscp::Fixed(MAX_DSCP + 1);
+        assert!(matches!(
+            tunnel.validate("vpc-1"),
+            Err(ConfigError::InvalidQos(_))
+        ));
+        assert_eq!(TunnelDscp::default().encap, EncapDscp::Preserve);
+        assert_eq!(TunnelDscp::default().decap, DecapDscp::Keep);
+    }
+}
@@ -0,0 +1,170 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Dataplane configuration model: session logging
+//!
+//! Session logging reports the creation and the teardown of sessions (their 5-tuple, VPCs, NAT
+//! mapping and, at teardown, their packet and byte counts) to an external collector. Events are
+//! formatted as syslog messages (RFC 5424), as CEF messages carried in syslog, or as JSON objects,
+//! and sent to the collector in batches, over UDP or TCP.
+
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crate::{ConfigError, ConfigResult};
+
+/// Max number of events sent to the collector at once
+pub const MAX_BATCH_SIZE: usize = 1024;
+
+/// Max time events may wait to be sent to the collector
+pub const MAX_FLUSH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The transport used to reach the collector
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SessionLogTransport {
+    /// One event per datagram (RFC 5426)
+    #[default]
+    Udp,
+    /// Events delimited by newlines on a TCP connection (RFC 6587, non-transparent framing)
+    Tcp,
+}
+
+/// The format of the events
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SessionLogFormat {
+    /// Syslog messages (RFC 5424), with the session in structured data
+    #[default]
+    Syslog,
+    /// ArcSight Common Event Format messages, carried in syslog
+    Cef,
+    /// JSON objects
+    Json,
+}
+
+/// The configuration of session logging
+#[derive(Clone, Debug, PartialEq)]
+pub struct SessionLogConfig {
+    pub collector: SocketAddr,          /* address and port of the collector */
+    pub transport: SessionLogTransport, /* transport used to reach the collector */
+    pub format: SessionLogFormat,       /* format of the events */
+    pub batch_size: usize,              /* max number of events sent at once */
+    pub flush_interval: Duration,       /* max time events wait to be sent */
+}
+
+impl SessionLogConfig {
+    /// Default max number of events sent at once
+    pub const DEFAULT_BATCH_SIZE: usize = 64;
+
+    /// Default max time events wait to be sent
+    pub const DEFAULT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+    #[must_use]
+    pub fn new(collector: SocketAddr) -> Self {
+        Self {
+            collector,
+            transport: SessionLogTransport::default(),
+            format: SessionLogFormat::default(),
+            batch_size: Self::DEFAULT_BATCH_SIZE,
+            flush_interval: Self::DEFAULT_FLUSH_INTERVAL,
+        }
+    }
+
+    /// Validate the configuration of session logging
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ConfigError::InvalidSessionLog`] if the collector can't be sent to, or if the
+    /// batching parameters are out of range.
+    pub fn validate(&self) -> ConfigResult {
+        let collector = self.collector;
+        if collector.ip().is_unspecified() || collector.ip().is_multicast() {
+            return Err(ConfigError::InvalidSessionLog(format!(
+                "invalid collector address {}",
+                collector.ip()
+            )));
+        }
+        if collector.port() == 0 {
+            return Err(ConfigError::InvalidSessionLog(
+                "the collector port must be non-zero".to_string(),
+            ));
+        }
+        if self.batch_size == 0 || self.batch_size > MAX_BATCH_SIZE {
+            return Err(ConfigError::InvalidSessionLog(format!(
+                "batch size must be in the range 1..={MAX_BATCH_SIZE}"
+            )));
+        }
+        if self.flush_interval.is_zero() || self.flush_interval > MAX_FLUSH_INTERVAL {
+            return Err(ConfigError::InvalidSessionLog(format!(
+                "flush interval must be non-zero and at most {} seconds",
+                MAX_FLUSH_INTERVAL.as_secs()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl Display for SessionLogTransport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionLogTransport::Udp => write!(f, "udp"),
+            SessionLogTransport::Tcp => write!(f, "tcp"),
+        }
+    }
+}
+
+impl Display for SessionLogFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionLogFormat::Syslog => write!(f, "syslog"),
+            SessionLogFormat::Cef => write!(f, "cef"),
+            SessionLogFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+impl Display for SessionLogConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "  ━━━━━━━ Session logging ━━━━━━━")?;
+        writeln!(
+            f,
+            "   collector: {} ({}), format: {}",
+            self.collector, self.transport, self.format
+        )?;
+        writeln!(
+            f,
+            "   batches of up to {} events, flushed every {} ms",
+            self.batch_size,
+            self.flush_interval.as_millis()
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_session_log_validation() {
+        let config = SessionLogConfig::new("192.168.1.1:514".parse().unwrap());
+        assert!(config.validate().is_ok());
+
+        let bad = SessionLogConfig::new("0.0.0.0:514".parse().unwrap());
+        assert!(matches!(
+            bad.validate(),
+            Err(ConfigError::InvalidSessionLog(_))
+        ));
+        let bad = SessionLogConfig::new("192.168.1.1:0".parse().unwrap());
+        assert!(bad.validate().is_err());
+
+        let mut bad = config.clone();
+        bad.batch_size = 0;
+        assert!(bad.validate().is_err());
+        bad.batch_size = MAX_BATCH_SIZE + 1;
+        assert!(bad.validate().is_err());
+
+        let mut bad = config.clone();
+        bad.flush_interval = Duration::ZERO;
+        assert!(bad.validate().is_err());
+    }
+}
@@ -0,0 +1,149 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Dataplane configuration model: session table
+//!
+//! The idle timeouts of the stateful sessions of each protocol, for the exposes which do not set
+//! their own, and the extension of TCP sessions upon keepalives. Long-lived TCP sessions, such as
+//! those to databases, may stay idle for longer than any reasonable idle timeout, with only the
+//! keepalives of their endpoints (sent every two hours by default on Linux) showing they are
+//! alive. When a keepalive timeout is set, the keepalives of a TCP session keep it in the session
+//! table for that long.
+
+use std::fmt::Display;
+use std::time::Duration;
+
+use net::ip::NextHeader;
+
+use crate::{ConfigError, ConfigResult};
+
+/// Max idle timeout of a session, for any protocol
+pub const MAX_SESSION_TIMEOUT: Duration = Duration::from_hours(24);
+
+/// The configuration of the session table
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SessionTableConfig {
+    pub tcp_established_timeout: Option<Duration>, /* idle timeout of established TCP sessions */
+    pub udp_timeout: Option<Duration>,             /* idle timeout of UDP sessions */
+    pub icmp_timeout: Option<Duration>,            /* idle timeout of ICMP query sessions */
+    pub tcp_keepalive_timeout: Option<Duration>,   /* time a TCP keepalive keeps its session up */
+}
+
+impl SessionTableConfig {
+    /// The idle timeout configured for the sessions of protocol `proto`, if any
+    #[must_use]
+    pub fn idle_timeout(&self, proto: NextHeader) -> Option<Duration> {
+        match proto {
+            NextHeader::TCP => self.tcp_established_timeout,
+            NextHeader::UDP => self.udp_timeout,
+            NextHeader::ICMP | NextHeader::ICMP6 => self.icmp_timeout,
+            _ => None,
+        }
+    }
+
+    /// Validate the configuration of the session table
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ConfigError::InvalidSessionTable`] if a timeout is zero or too long, or if the
+    /// keepalive timeout would not extend the established TCP sessions.
+    pub fn validate(&self) -> ConfigResult {
+        let timeouts = [
+            ("TCP established", self.tcp_established_timeout),
+            ("UDP", self.udp_timeout),
+            ("ICMP", self.icmp_timeout),
+            ("TCP keepalive", self.tcp_keepalive_timeout),
+        ];
+        for (name, timeout) in timeouts {
+            if let Some(timeout) = timeout
+                && (timeout.is_zero() || timeout > MAX_SESSION_TIMEOUT)
+            {
+                return Err(ConfigError::InvalidSessionTable(format!(
+                    "{name} timeout must be non-zero and at most {} seconds",
+                    MAX_SESSION_TIMEOUT.as_secs()
+                )));
+            }
+        }
+        if let (Some(keepalive), Some(established)) =
+            (self.tcp_keepalive_timeout, self.tcp_established_timeout)
+            && keepalive <= established
+        {
+            return Err(ConfigError::InvalidSessionTable(format!(
+                "TCP keepalive timeout ({}s) must exceed the TCP established timeout ({}s)",
+                keepalive.as_secs(),
+                established.as_secs()
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Format an optional timeout, `default` when not set
+struct Timeout(Option<Duration>);
+
+impl Display for Timeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(timeout) => write!(f, "{}s", timeout.as_secs()),
+            None => write!(f, "default"),
+        }
+    }
+}
+
+impl Display for SessionTableConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "  ━━━━━━━ Session table ━━━━━━━")?;
+        writeln!(
+            f,
+            "   idle timeouts: tcp-established: {}, udp: {}, icmp: {}",
+            Timeout(self.tcp_established_timeout),
+            Timeout(self.udp_timeout),
+            Timeout(self.icmp_timeout)
+        )?;
+        match self.tcp_keepalive_timeout {
+            Some(timeout) => writeln!(
+                f,
+                "   tcp keepalives extend sessions by {}s",
+                timeout.as_secs()
+            ),
+            None => writeln!(f, "   tcp keepalives do not extend sessions"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_session_table_validation() {
+        let mut config = SessionTableConfig::default();
+        assert!(config.validate().is_ok());
+        assert_eq!(config.idle_timeout(NextHeader::TCP), None);
+
+        config.tcp_established_timeout = Some(Duration::from_mins(30));
+        config.udp_timeout = Some(Duration::from_secs(30));
+        config.icmp_timeout = Some(Duration::from_secs(10));
+        config.tcp_keepalive_timeout = Some(Duration::from_hours(3));
+        assert!(config.validate().is_ok());
+        assert_eq!(
+            config.idle_timeout(NextHeader::ICMP6),
+            Some(Duration::from_secs(10))
+        );
+
+        let mut bad = config.clone();
+        bad.udp_timeout = Some(Duration::ZERO);
+        assert!(matches!(
+            bad.validate(),
+            Err(ConfigError::InvalidSessionTable(_))
+        ));
+
+        let mut bad = config.clone();
+        bad.icmp_timeout = Some(MAX_SESSION_TIMEOUT + Duration::from_secs(1));
+        assert!(bad.validate().is_err());
+
+        let mut bad = config;
+        bad.tcp_keepalive_timeout = Some(Duration::from_mins(10));
+        assert!(bad.validate().is_err());
+    }
+}
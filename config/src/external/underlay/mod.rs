@@ -3,11 +3,14 @@
 
 //! Underlay configuration
 
-use crate::ConfigError;
+use crate::external::overlay::vpc::ValidatedVpcTable;
 use crate::internal::interfaces::interface::{InterfaceConfig, InterfaceType};
 use crate::internal::routing::evpn::VtepConfig;
 use crate::internal::routing::vrf::VrfConfig;
+use crate::{ConfigError, ConfigResult};
 
+use ipnet::IpNet;
+use lpm::prefix::Prefix;
 use net::eth::mac::SourceMac;
 use net::ipv4::UnicastIpv4Addr;
 use std::net::IpAddr;
@@ -36,7 +39,11 @@ impl TryFrom<&InterfaceConfig> for VtepConfig {
                 let ip = UnicastIpv4Addr::new(vtep.local).map_err(|e| {
                     ConfigError::BadVtepLocalAddress(IpAddr::V4(e), "Invalid address")
                 })?;
-                Ok(VtepConfig::new(ip.into(), mac))
+                let config = VtepConfig::new(ip.into(), mac);
+                Ok(match intf.mtu {
+                    Some(mtu) => config.set_mtu(mtu),
+                    None => config,
+                })
             }
             _ => Err(ConfigError::InternalFailure(format!(
                 "Attempted to get vtep config from non-vtep interface {}",
@@ -92,4 +99,120 @@ impl Underlay {
             vtep: self.get_vtep_info()?,
         })
     }
+
+    /// The prefixes that the gateway itself uses in the underlay: the subnets of the interfaces
+    /// of the underlay VRF and the VTEP address, each with a description of its use.
+    #[must_use]
+    pub fn gateway_prefixes(&self) -> Vec<(Prefix, String)> {
+        let mut prefixes: Vec<_> = self
+            .vrf
+            .interfaces
+            .values()
+            .flat_map(|config| {
+                config.addresses.iter().filter_map(|address| {
+                    let net = IpNet::new(address.address, address.mask_len).ok()?;
+                    let use_ = format!("the subnet of underlay interface {}", config.name);
+                    Some((Prefix::from(net.trunc()), use_))
+                })
+            })
+            .collect();
+        if let Some(vtep) = &self.vtep {
+            prefixes.push((
+                Prefix::from(IpAddr::from(vtep.address)),
+                "the VTEP address".to_string(),
+            ));
+        }
+        prefixes
+    }
+
+    /// Check that no expose of the VPCs uses the prefixes of the underlay of the gateway, since
+    /// routing or NATing them in a VPC would likely cut the gateway off its underlay. Exposes
+    /// explicitly allowed to use reserved prefixes are not checked.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ConfigError::GatewayPrefix`] for the first prefix overlapping one of the gateway.
+    pub fn check_exposes(&self, vpc_table: &ValidatedVpcTable) -> ConfigResult {
+        let gateway_prefixes = self.gateway_prefixes();
+        let exposes = vpc_table
+            .peerings()
+            .flat_map(|peering| peering.local().valexp())
+            .filter(|expose| !expose.allows_reserved());
+        for expose in exposes {
+            for prefix in expose.ips().iter().chain(expose.as_range_or_empty()) {
+                let prefix = prefix.prefix();
+                if let Some((_, use_)) = gateway_prefixes
+                    .iter()
+                    .find(|(gateway_prefix, _)| gateway_prefix.collides_with(&prefix))
+                {
+                    return Err(ConfigError::GatewayPrefix(prefix, use_.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Underlay;
+    use crate::ConfigError;
+    use crate::external::overlay::Overlay;
+    use crate::external::overlay::vpc::{Vpc, VpcTable};
+    use crate::external::overlay::vpcpeering::{
+        VpcExpose, VpcManifest, VpcPeering, VpcPeeringTable,
+    };
+    use crate::internal::interfaces::interface::{InterfaceConfig, InterfaceType};
+    use crate::internal::routing::vrf::VrfConfig;
+
+    fn underlay() -> Underlay {
+        let mut vrf = VrfConfig::new("default", None, true);
+        vrf.add_interface_config(
+            InterfaceConfig::new("lo", InterfaceType::Loopback, false)
+                .add_address("172.16.0.1".parse().unwrap(), 24),
+        );
+        Underlay { vrf, vtep: None }
+    }
+
+    fn check_exposes(expose: VpcExpose) -> Result<(), ConfigError> {
+        let mut vpc_table = VpcTable::new();
+        vpc_table
+            .add(Vpc::new("VPC-1", "VPC01", 1).unwrap())
+            .unwrap();
+        vpc_table
+            .add(Vpc::new("VPC-2", "VPC02", 2).unwrap())
+            .unwrap();
+        let m1 = VpcManifest::with_exposes("VPC-1", vec![expose]);
+        let m2 =
+            VpcManifest::with_exposes("VPC-2", vec![VpcExpose::empty().ip("10.0.0.0/16".into())]);
+        let mut peering_table = VpcPeeringTable::new();
+        peering_table
+            .add(VpcPeering::with_default_group("VPC-1--VPC-2", m1, m2))
+            .unwrap();
+        let overlay = Overlay::new(vpc_table, peering_table).validate()?;
+        underlay().check_exposes(overlay.vpc_table())
+    }
+
+    #[test]
+    fn test_underlay_prefix_rejected() {
+        let result = check_exposes(VpcExpose::empty().ip("172.16.0.128/25".into()));
+        assert!(
+            matches!(&result, Err(ConfigError::GatewayPrefix(_, what)) if what.contains("lo")),
+            "{result:?}"
+        );
+        let result = check_exposes(VpcExpose::empty().ip("172.16.0.0/12".into()));
+        assert!(
+            matches!(result, Err(ConfigError::GatewayPrefix(..))),
+            "{result:?}"
+        );
+    }
+
+    #[test]
+    fn test_underlay_prefix_allowed() {
+        let expose = VpcExpose::empty()
+            .allow_reserved()
+            .ip("172.16.0.128/25".into());
+        assert!(check_exposes(expose).is_ok());
+        assert!(check_exposes(VpcExpose::empty().ip("172.17.0.0/16".into())).is_ok());
+    }
 }
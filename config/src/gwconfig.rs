@@ -8,8 +8,30 @@ use crate::external::{GenId, ValidatedExternalConfig};
 use crate::internal::InternalConfig;
 use concurrency::slot::Slot;
 use concurrency::sync::Arc;
+use concurrency::sync::atomic::{AtomicU64, Ordering};
+use std::fmt::Display;
 use std::time::SystemTime;
 
+/// Identifier of a request to apply a configuration, to correlate all the changes it causes in
+/// the dataplane. Unlike the generation id, it differs for each attempt to apply a config.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ApplyId(u64);
+
+impl ApplyId {
+    /// Allocate a new [`ApplyId`], unique within this process
+    #[must_use]
+    pub fn next() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+impl Display for ApplyId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "apply-{}", self.0)
+    }
+}
+
 /// Metadata associated to a gateway configuration
 #[derive(Clone, Debug)]
 pub struct GwConfigMeta {
@@ -27,6 +49,9 @@ pub struct GwConfigMeta {
 
     /// whether this config was applied as a rollback
     pub is_rollback: bool,
+
+    /// the request that caused this config to be applied, or rolled back to
+    pub apply_id: Option<ApplyId>,
 }
 impl GwConfigMeta {
     ////////////////////////////////////////////////////////////////////////////////
@@ -40,6 +65,7 @@ impl GwConfigMeta {
             apply_t: None,
             error: None,
             is_rollback: false,
+            apply_id: None,
         }
     }
     ////////////////////////////////////////////////////////////////////////////////
@@ -112,6 +138,7 @@ impl ValidatedGwConfig {
 
 #[cfg(test)]
 mod tests {
+    use super::{ApplyId, GwConfigMeta};
     use crate::ExternalConfig;
 
     #[test]
@@ -120,4 +147,24 @@ mod tests {
             .validate()
             .expect("Failed to validate blank config");
     }
+
+    #[test]
+    fn test_apply_ids_are_unique() {
+        let first = ApplyId::next();
+        let second = ApplyId::next();
+        assert!(second > first);
+        assert_eq!(second.to_string(), format!("apply-{}", second.0));
+    }
+
+    #[test]
+    fn test_apply_id_in_history() {
+        let mut meta = GwConfigMeta::new(7);
+        meta.apply_time();
+        assert!(meta.to_string().contains("--"), "{meta}");
+        let apply_id = ApplyId::next();
+        meta.apply_id = Some(apply_id);
+        let shown = meta.to_string();
+        assert!(shown.contains(&apply_id.to_string()), "{shown}");
+        assert!(!shown.contains("--"), "{shown}");
+    }
 }
@@ -0,0 +1,132 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Events of the dataplane, pushed to whoever subscribes to them.
+//!
+//! The components of the dataplane [`publish`] the notable changes of its state: the outcome of
+//! the configurations applied, the failures to reconcile the kernel state, the transitions of the
+//! interfaces, of the BGP sessions and of the connection to the FRR agent, and the exhaustion of
+//! NAT pools. Events are broadcast to the current subscribers only. A subscriber that lags more
+//! than [`EVENTS_CAPACITY`] events behind loses the oldest ones.
+
+use crate::external::GenId;
+use crate::gwconfig::ApplyId;
+use crate::internal::status::BgpNeighborSessionState;
+use chrono::{DateTime, Utc};
+use concurrency::sync::LazyLock;
+use std::fmt::Display;
+use tokio::sync::broadcast;
+
+/// Number of events kept for the subscribers which did not receive them yet
+pub const EVENTS_CAPACITY: usize = 1024;
+
+static EVENTS: LazyLock<broadcast::Sender<TimedEvent>> =
+    LazyLock::new(|| broadcast::channel(EVENTS_CAPACITY).0);
+
+/// A notable change of the state of the dataplane
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DataplaneEvent {
+    /// Configuration `genid` was applied by request `apply_id`
+    ConfigApplied { genid: GenId, apply_id: ApplyId },
+    /// Configuration `genid` failed to be applied by request `apply_id`
+    ConfigFailed {
+        genid: GenId,
+        apply_id: ApplyId,
+        error: String,
+    },
+    /// The kernel state could not be reconciled with configuration `genid`
+    ReconcileFailed { genid: GenId, error: String },
+    /// The operational state of an interface changed
+    InterfaceOperState { interface: String, up: bool },
+    /// The state of the BGP session with a neighbor changed
+    BgpSession {
+        neighbor: String,
+        asn: u32,
+        state: BgpNeighborSessionState,
+    },
+    /// The connection to the FRR agent was established or lost
+    FrrAgent { connected: bool },
+    /// A NAT pool ran out of addresses to allocate
+    NatPoolExhausted { pool: String },
+}
+
+impl Display for DataplaneEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DataplaneEvent::ConfigApplied { genid, apply_id } => {
+                write!(f, "Config {genid} applied by {apply_id}")
+            }
+            DataplaneEvent::ConfigFailed {
+                genid,
+                apply_id,
+                error,
+            } => write!(
+                f,
+                "Config {genid} failed to be applied by {apply_id}: {error}"
+            ),
+            DataplaneEvent::ReconcileFailed { genid, error } => {
+                write!(f, "Reconciliation with config {genid} failed: {error}")
+            }
+            DataplaneEvent::InterfaceOperState { interface, up } => {
+                let state = if *up { "up" } else { "down" };
+                write!(f, "Interface {interface} is {state}")
+            }
+            DataplaneEvent::BgpSession {
+                neighbor,
+                asn,
+                state,
+            } => write!(f, "BGP session with {neighbor} (ASN {asn}) is {state}"),
+            DataplaneEvent::FrrAgent { connected } => {
+                let state = if *connected {
+                    "connected"
+                } else {
+                    "disconnected"
+                };
+                write!(f, "FRR agent {state}")
+            }
+            DataplaneEvent::NatPoolExhausted { pool } => write!(f, "NAT pool {pool} exhausted"),
+        }
+    }
+}
+
+/// A [`DataplaneEvent`], along with the time it happened
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TimedEvent {
+    pub time: DateTime<Utc>,
+    pub event: DataplaneEvent,
+}
+
+/// Publish an event to the current subscribers, if any
+pub fn publish(event: DataplaneEvent) {
+    let event = TimedEvent {
+        time: Utc::now(),
+        event,
+    };
+    // there being no subscriber is no error
+    let _ = EVENTS.send(event);
+}
+
+/// Subscribe to the events published from now on
+#[must_use]
+pub fn subscribe() -> broadcast::Receiver<TimedEvent> {
+    EVENTS.subscribe()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{DataplaneEvent, publish, subscribe};
+
+    #[test]
+    fn test_events_reach_subscribers() {
+        // published before subscribing: not received
+        publish(DataplaneEvent::FrrAgent { connected: false });
+        let mut events = subscribe();
+        let pool = "test-events-reach-subscribers".to_string();
+        publish(DataplaneEvent::NatPoolExhausted { pool: pool.clone() });
+
+        // other tests may publish concurrently
+        let received = std::iter::from_fn(|| events.try_recv().ok())
+            .any(|timed| timed.event == DataplaneEvent::NatPoolExhausted { pool: pool.clone() });
+        assert!(received);
+    }
+}
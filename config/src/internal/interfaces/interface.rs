@@ -42,6 +42,41 @@ pub struct IfVtepConfig {
     pub local: Ipv4Addr,
 }
 
+/// Source address validation (uRPF) of the packets received on an interface
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum UrpfMode {
+    /// Sources are not validated
+    #[default]
+    Off,
+    /// The source must have a route, over any interface
+    Loose,
+    /// The source must have a route over the interface the packet was received on
+    Strict,
+}
+
+impl Display for UrpfMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            UrpfMode::Off => write!(f, "off"),
+            UrpfMode::Loose => write!(f, "loose"),
+            UrpfMode::Strict => write!(f, "strict"),
+        }
+    }
+}
+
+impl FromStr for UrpfMode {
+    type Err = ConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "off" => Ok(UrpfMode::Off),
+            "loose" => Ok(UrpfMode::Loose),
+            "strict" => Ok(UrpfMode::Strict),
+            _ => Err(ConfigError::InvalidFormat(s.to_string())),
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub enum InterfaceType {
     Loopback,
@@ -63,6 +98,8 @@ pub struct InterfaceConfig {
     pub internal: bool, /* true if automatically created */
     pub ospf: Option<OspfInterface>,
     pub pci: Option<net::pci::PciEbdf>,
+    pub static_neighbors: BTreeMap<IpAddr, Mac>,
+    pub urpf: UrpfMode,
 }
 
 #[derive(Clone, Debug, Default)]
@@ -117,6 +154,8 @@ impl InterfaceConfig {
             internal,
             ospf: None,
             pci: None,
+            static_neighbors: BTreeMap::new(),
+            urpf: UrpfMode::Off,
         }
     }
     #[must_use]
@@ -150,6 +189,17 @@ impl InterfaceConfig {
         self.pci = Some(pci);
         self
     }
+    /// Add a static neighbor (ARP / ND entry), replacing any other with the same address
+    #[must_use]
+    pub fn add_static_neighbor(mut self, address: IpAddr, mac: Mac) -> Self {
+        self.static_neighbors.insert(address, mac);
+        self
+    }
+    #[must_use]
+    pub fn set_urpf(mut self, urpf: UrpfMode) -> Self {
+        self.urpf = urpf;
+        self
+    }
     #[must_use]
     pub fn is_vtep(&self) -> bool {
         matches!(self.iftype, InterfaceType::Vtep(_))
@@ -159,12 +209,22 @@ impl InterfaceConfig {
     ///
     /// # Errors
     ///
-    /// Returns an error if the interface name is empty.
+    /// Returns an error if the interface name is empty or if a static neighbor is invalid.
     pub fn validate(&self) -> ConfigResult {
         // name is mandatory
         if self.name.is_empty() {
             return Err(ConfigError::MissingIdentifier("interface name"));
         }
+        for (address, mac) in &self.static_neighbors {
+            let bad_neighbor =
+                |reason| ConfigError::BadStaticNeighbor(self.name.clone(), *address, reason);
+            if address.is_unspecified() || address.is_loopback() || address.is_multicast() {
+                return Err(bad_neighbor("not a unicast address"));
+            }
+            if SourceMac::new(*mac).is_err() {
+                return Err(bad_neighbor("not a unicast mac address"));
+            }
+        }
         Ok(())
     }
 }
@@ -181,3 +241,35 @@ impl InterfaceConfigTable {
         self.0.values()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_static_neighbor_validation() {
+        let eth = |address: &str, mac: [u8; 6]| {
+            InterfaceConfig::new(
+                "eth0",
+                InterfaceType::Ethernet(IfEthConfig { mac: None }),
+                false,
+            )
+            .add_static_neighbor(address.parse().unwrap(), Mac::from(mac))
+        };
+        let unicast = [0x02, 0, 0, 0, 0, 0x01];
+        assert!(eth("10.0.0.1", unicast).validate().is_ok());
+        assert!(eth("2001:db8::1", unicast).validate().is_ok());
+        assert!(matches!(
+            eth("224.0.0.1", unicast).validate(),
+            Err(ConfigError::BadStaticNeighbor(..))
+        ));
+        assert!(matches!(
+            eth("10.0.0.1", [0xff; 6]).validate(),
+            Err(ConfigError::BadStaticNeighbor(..))
+        ));
+        assert!(matches!(
+            eth("10.0.0.1", [0; 6]).validate(),
+            Err(ConfigError::BadStaticNeighbor(..))
+        ));
+    }
+}
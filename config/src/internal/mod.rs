@@ -9,7 +9,9 @@
 #![allow(unused)]
 
 pub mod device;
+pub mod events;
 pub mod interfaces;
+pub mod readiness;
 pub mod routing;
 pub mod status;
 
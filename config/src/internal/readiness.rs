@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Readiness of the dataplane to forward traffic.
+//!
+//! The dataplane is ready once each of its [`Component`]s is: the driver has started, the router is
+//! connected to the FRR agent, a configuration was applied at least once, and the initial download
+//! of the routes from FRR is complete. The components
+//! [`set_ready`] as they change, and whoever reports the readiness [`watch`]es it.
+
+use concurrency::sync::LazyLock;
+use std::fmt::Display;
+use tokio::sync::watch;
+
+static READINESS: LazyLock<watch::Sender<Readiness>> =
+    LazyLock::new(|| watch::channel(Readiness::default()).0);
+
+/// A component which must be ready for the dataplane to be
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Component {
+    /// The driver has started its workers
+    Driver,
+    /// The router is connected to the FRR agent
+    Frr,
+    /// A configuration was applied at least once
+    Config,
+    /// The initial download of the routes from FRR is complete
+    Routes,
+}
+
+impl Component {
+    pub const ALL: [Component; 4] = [
+        Component::Driver,
+        Component::Frr,
+        Component::Config,
+        Component::Routes,
+    ];
+
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            Component::Driver => "driver",
+            Component::Frr => "frr",
+            Component::Config => "config",
+            Component::Routes => "routes",
+        }
+    }
+}
+
+impl Display for Component {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The readiness of each of the [`Component`]s
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Readiness {
+    driver: bool,
+    frr: bool,
+    config: bool,
+    routes: bool,
+}
+
+impl Readiness {
+    fn flag(&mut self, component: Component) -> &mut bool {
+        match component {
+            Component::Driver => &mut self.driver,
+            Component::Frr => &mut self.frr,
+            Component::Config => &mut self.config,
+            Component::Routes => &mut self.routes,
+        }
+    }
+
+    /// Tell if `component` is ready
+    #[must_use]
+    pub fn is_ready(mut self, component: Component) -> bool {
+        *self.flag(component)
+    }
+
+    /// Tell if all of the components are ready
+    #[must_use]
+    pub fn all_ready(self) -> bool {
+        Component::ALL.into_iter().all(|c| self.is_ready(c))
+    }
+}
+
+/// Record whether `component` is ready
+pub fn set_ready(component: Component, ready: bool) {
+    READINESS.send_if_modified(|readiness| {
+        let flag = readiness.flag(component);
+        let changed = *flag != ready;
+        *flag = ready;
+        changed
+    });
+}
+
+/// Watch the readiness of the components
+#[must_use]
+pub fn watch() -> watch::Receiver<Readiness> {
+    READINESS.subscribe()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Component, set_ready, watch};
+
+    #[test]
+    fn test_readiness() {
+        let mut readiness = watch();
+        assert!(!readiness.borrow_and_update().all_ready());
+
+        set_ready(Component::Driver, true);
+        set_ready(Component::Frr, true);
+        assert!(readiness.has_changed().unwrap());
+        assert!(!readiness.borrow_and_update().all_ready());
+
+        set_ready(Component::Config, true);
+        assert!(!readiness.borrow_and_update().all_ready());
+
+        set_ready(Component::Routes, true);
+        assert!(readiness.borrow_and_update().all_ready());
+
+        // no change, no notification
+        set_ready(Component::Config, true);
+        assert!(!readiness.has_changed().unwrap());
+
+        set_ready(Component::Frr, false);
+        let current = *readiness.borrow_and_update();
+        assert!(!current.is_ready(Component::Frr));
+        assert!(current.is_ready(Component::Driver));
+        assert!(!current.all_ready());
+    }
+}
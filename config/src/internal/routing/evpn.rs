@@ -4,6 +4,7 @@
 //! Dataplane configuration model: EVPN
 
 use net::eth::mac::{Mac, SourceMac};
+use net::interface::Mtu;
 use net::ip::UnicastIpAddr;
 
 /// The configuration of a VTEP (virtual tunnel endpoint) for the Hedgehog EVPN router.
@@ -13,6 +14,10 @@ pub struct VtepConfig {
     pub address: UnicastIpAddr,
     /// The source MAC address to be used by vxlan packets originating from this router.
     pub mac: SourceMac,
+    /// The MTU of the vxlan interfaces of VPCs, and of the bridges they are attached to. It must
+    /// leave room for the vxlan encapsulation in the MTU of the underlay. If `None`, the kernel
+    /// picks it.
+    pub mtu: Option<Mtu>,
 }
 
 impl VtepConfig {
@@ -22,6 +27,17 @@ impl VtepConfig {
     /// Creates a new VTEP configuration.
     #[must_use]
     pub fn new(address: UnicastIpAddr, mac: SourceMac) -> Self {
-        Self { address, mac }
+        Self {
+            address,
+            mac,
+            mtu: None,
+        }
+    }
+
+    /// Sets the MTU of the vxlan interfaces of VPCs.
+    #[must_use]
+    pub fn set_mtu(mut self, mtu: Mtu) -> Self {
+        self.mtu = Some(mtu);
+        self
     }
 }
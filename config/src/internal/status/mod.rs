@@ -159,6 +159,9 @@ impl InterfaceCounters {
 pub struct InterfaceRuntimeStatus {
     pub admin_status: InterfaceAdminStatusType,
     pub oper_status: InterfaceOperStatusType,
+    pub carrier: Option<bool>,
+    /// Link speed in Mb/s
+    pub speed: Option<u32>,
     pub mac: String,
     pub mtu: u32,
     pub counters: Option<InterfaceCounters>,
@@ -180,6 +183,16 @@ impl InterfaceRuntimeStatus {
         self
     }
     #[must_use]
+    pub fn set_carrier(mut self, carrier: Option<bool>) -> Self {
+        self.carrier = carrier;
+        self
+    }
+    #[must_use]
+    pub fn set_speed(mut self, speed: Option<u32>) -> Self {
+        self.speed = speed;
+        self
+    }
+    #[must_use]
     pub fn set_mac(mut self, mac: impl Into<String>) -> Self {
         self.mac = mac.into();
         self
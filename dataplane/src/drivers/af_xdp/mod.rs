@@ -0,0 +1,210 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! `AF_XDP` dataplane driver
+//!
+//! The driver runs the workers of the kernel driver, but has them exchange the frames of the
+//! interfaces given in the command line with the NICs through `AF_XDP` sockets rather than packet
+//! sockets, bypassing the kernel stack:
+//!
+//! - An XDP program is attached to each interface, natively if the NIC driver supports it and
+//!   generically otherwise. It redirects the frames of each queue to the socket of the worker
+//!   serving the queue: worker `i` serves queue `i` of every interface. NICs should be given as
+//!   many queues as there are workers (`ethtool -L`), since the frames of the queues without a
+//!   worker are left to the kernel stack.
+//! - Sockets are zero-copy where the NIC driver supports it: the NIC then receives and sends in
+//!   the UMEM of the socket directly. The pipeline still processes its own copies of the frames.
+//! - Native programs pass the RX hash of the NIC on to the sockets, in the metadata of the frames,
+//!   where the NIC driver gives it (linux 6.3 and later). The packets carry it in their
+//!   metadata, for the pipeline not to hash the flows of the frames again.
+//! - The tap interfaces of the host path are served with packet sockets, as the kernel driver
+//!   does.
+//!
+//! XDP programs are attached with BPF links (linux 5.9 and later), which detach them when the
+//! driver stops.
+
+#![deny(
+    unsafe_code,
+    clippy::all,
+    clippy::pedantic,
+    clippy::unwrap_used,
+    clippy::expect_used,
+    clippy::panic
+)]
+
+mod xsk;
+
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+
+use concurrency::sync::Arc;
+use concurrency::thread;
+use lifecycle::Subsystem;
+use net::buffer::test_buffer::TestBuffer;
+use net::interface::{InterfaceIndex, InterfaceName};
+use pipeline::DynPipeline;
+use tokio::sync::watch;
+use tracectl::trace_target;
+#[allow(unused)]
+use tracing::{debug, error, info, trace, warn};
+
+use super::DriverError;
+use super::batch::BatchLimits;
+use super::bpf::{XdpLink, XdpMode, XdpProgram, XskMap};
+use super::kernel::{
+    DrainHandle, DriverKernel, Kif, MicroburstLog, RxMode, Watchdog, bring_kifs_up, get_interfaces,
+};
+pub use xsk::{Xsk, XskRx, XskSettings, XskTx};
+
+trace_target!("af-xdp-driver", LevelFilter::INFO, &["driver"]);
+
+/// The XDP program of an interface, and the map of its sockets
+struct XdpInterface {
+    name: String,
+    mode: XdpMode,
+    map: XskMap,
+    /// Keeps the program attached
+    _link: XdpLink,
+}
+
+impl XdpInterface {
+    /// Attach the XDP program to an interface, natively if possible. Native programs are bound
+    /// to the interface, to give the RX hash of the NIC to the sockets.
+    fn attach(kif: &Kif, queues: u32) -> io::Result<Self> {
+        let map = XskMap::new(queues)?;
+        let load = |rx_hash| {
+            XdpProgram::load(&map, rx_hash)
+                .inspect_err(|e| error!("Failed to load XDP program for {}: {e}", kif.name))
+        };
+        let program = load(Some(kif.ifindex))?;
+        let (mode, link) = match XdpLink::attach(&program, kif.ifindex, XdpMode::Native) {
+            Ok(link) => (XdpMode::Native, link),
+            Err(e) => {
+                warn!(
+                    "Failed to attach native XDP program to {}: {e}. Falling back to generic XDP, which is slower",
+                    kif.name
+                );
+                // programs bound to an interface cannot run generically
+                let link = XdpLink::attach(&load(None)?, kif.ifindex, XdpMode::Generic)
+                    .inspect_err(|e| error!("Failed to attach XDP program to {}: {e}", kif.name))?;
+                (XdpMode::Generic, link)
+            }
+        };
+        info!("Attached {mode} XDP program to {}", kif.name);
+        Ok(Self {
+            name: kif.name.clone(),
+            mode,
+            map,
+            _link: link,
+        })
+    }
+}
+
+/// The interfaces served with `AF_XDP` sockets. The XDP programs are detached when dropped.
+pub struct XdpPorts {
+    settings: XskSettings,
+    interfaces: HashMap<InterfaceIndex, XdpInterface>,
+}
+
+impl XdpPorts {
+    fn attach(kifs: &[Kif], queues: usize, settings: XskSettings) -> io::Result<Self> {
+        let queues = u32::try_from(queues).unwrap_or_else(|_| unreachable!());
+        let mut interfaces = HashMap::new();
+        for kif in kifs {
+            interfaces.insert(kif.ifindex, XdpInterface::attach(kif, queues)?);
+        }
+        Ok(Self {
+            settings,
+            interfaces,
+        })
+    }
+
+    /// Open a socket on a queue of an interface, and register it with the XDP program of the
+    /// interface. Returns `None` if the interface is not served with `AF_XDP` sockets.
+    pub fn open(&self, ifindex: InterfaceIndex, queue: usize) -> Option<io::Result<Xsk>> {
+        let interface = self.interfaces.get(&ifindex)?;
+        let queue = u32::try_from(queue).unwrap_or_else(|_| unreachable!());
+        let zero_copy = self.settings.zero_copy && interface.mode == XdpMode::Native;
+        let xsk = Xsk::open(ifindex, queue, self.settings, zero_copy).or_else(|e| {
+            if !zero_copy {
+                return Err(e);
+            }
+            warn!(
+                "Failed to open zero-copy AF_XDP socket on queue {queue} of {}: {e}. Falling back to copy mode",
+                interface.name
+            );
+            Xsk::open(ifindex, queue, self.settings, false)
+        });
+        Some(
+            xsk.and_then(|xsk| {
+                interface.map.insert(queue, &xsk.tx.raw_fd())?;
+                Ok(xsk)
+            })
+            .inspect_err(|e| {
+                error!(
+                    "Failed to open AF_XDP socket on queue {queue} of {}: {e}",
+                    interface.name
+                );
+            }),
+        )
+    }
+}
+
+/// AF_XDP-based driver. Spawns N workers, each with an `AF_XDP` socket on a queue of every
+/// interface and its own pipelines.
+pub struct DriverAfXdp;
+
+impl DriverAfXdp {
+    /// Attach the XDP programs to the interfaces in `args`, and spawn the workers, the hot-attach
+    /// of the tap interfaces and the supervisor into `scope`, as [`DriverKernel::start`] does.
+    /// Returns the handle to drain the workers before they stop.
+    ///
+    /// # Errors
+    /// Returns [`DriverError`] on interface setup, XDP program attachment or thread spawn
+    /// failure.
+    #[allow(clippy::too_many_arguments)]
+    pub fn start<'scope>(
+        scope: &'scope thread::Scope<'scope, '_>,
+        workers_subsystem: &Subsystem,
+        args: impl IntoIterator<Item = impl AsRef<str> + Clone>,
+        num_workers: usize,
+        settings: XskSettings,
+        setup_pipeline: &Arc<dyn Send + Sync + Fn() -> DynPipeline<TestBuffer>>,
+        tap_interfaces: watch::Receiver<BTreeSet<InterfaceName>>,
+        watchdog: Watchdog,
+        microbursts: MicroburstLog,
+        batch: BatchLimits,
+    ) -> Result<DrainHandle, DriverError> {
+        debug_assert!(
+            tokio::runtime::Handle::try_current().is_err(),
+            "DriverAfXdp::start must not be invoked from within a tokio runtime context"
+        );
+
+        info!("Collecting interfaces from config");
+        let interfaces = get_interfaces(args)?;
+
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?
+            .block_on(bring_kifs_up(interfaces.as_slice()))?;
+
+        info!(
+            "Attaching XDP programs, with rings of {} descriptors",
+            settings.ring_size
+        );
+        let ports = XdpPorts::attach(interfaces.as_slice(), num_workers, settings)?;
+
+        DriverKernel::run(
+            scope,
+            workers_subsystem,
+            interfaces,
+            num_workers,
+            setup_pipeline,
+            tap_interfaces,
+            watchdog,
+            RxMode::Xdp(Arc::new(ports)),
+            microbursts,
+            batch,
+        )
+    }
+}
@@ -0,0 +1,616 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! `AF_XDP` sockets, and the UMEM rings they exchange frames with the NIC through.
+//!
+//! Each socket has its own UMEM, split in two halves: the frames of the first half are given to
+//! the NIC to receive in through the fill ring, and come back through the receive ring. The frames
+//! of the second half are sent through the transmit ring, and come back through the completion
+//! ring. Frames are copied out of the UMEM as soon as they are received, so received frames are
+//! given back right away and the fill ring never runs dry. So is the RX hash the XDP program may
+//! have written in the metadata ahead of a frame.
+
+use std::io;
+use std::marker::PhantomData;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use concurrency::sync::Arc;
+use memmap2::{MmapOptions, MmapRaw};
+use net::interface::InterfaceIndex;
+use nix::libc;
+
+use crate::drivers::bpf::{RX_META_LEN, RX_META_MAGIC};
+
+/// `AF_XDP`
+const AF_XDP: libc::c_int = 44;
+/// `SOL_XDP`, and the options of `AF_XDP` sockets
+const SOL_XDP: libc::c_int = 283;
+const XDP_MMAP_OFFSETS: libc::c_int = 1;
+const XDP_RX_RING: libc::c_int = 2;
+const XDP_TX_RING: libc::c_int = 3;
+const XDP_UMEM_REG: libc::c_int = 4;
+const XDP_UMEM_FILL_RING: libc::c_int = 5;
+const XDP_UMEM_COMPLETION_RING: libc::c_int = 6;
+/// The offsets of the rings, to map them
+const XDP_PGOFF_RX_RING: u64 = 0;
+const XDP_PGOFF_TX_RING: u64 = 0x8000_0000;
+const XDP_UMEM_PGOFF_FILL_RING: u64 = 0x1_0000_0000;
+const XDP_UMEM_PGOFF_COMPLETION_RING: u64 = 0x1_8000_0000;
+/// Flags of the binding of `AF_XDP` sockets
+const XDP_COPY: u16 = 1 << 1;
+const XDP_ZEROCOPY: u16 = 1 << 2;
+const XDP_USE_NEED_WAKEUP: u16 = 1 << 3;
+/// Flag of a ring telling that the kernel must be kicked to process it
+const XDP_RING_NEED_WAKEUP: u32 = 1;
+
+/// The size of the frames of the UMEM. Frames are aligned on their size, so that the frame of an
+/// address is found by masking the address.
+const FRAME_SIZE: u32 = 4096;
+
+/// `struct xdp_umem_reg`, in its original layout which all kernels accept
+#[repr(C)]
+struct UmemReg {
+    addr: u64,
+    len: u64,
+    chunk_size: u32,
+    headroom: u32,
+}
+
+/// `struct xdp_ring_offset`
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct RingOffsets {
+    producer: u64,
+    consumer: u64,
+    desc: u64,
+    flags: u64,
+}
+
+/// `struct xdp_mmap_offsets`
+#[repr(C)]
+#[derive(Default)]
+struct MmapOffsets {
+    rx: RingOffsets,
+    tx: RingOffsets,
+    fill: RingOffsets,
+    completion: RingOffsets,
+}
+
+/// `struct sockaddr_xdp`
+#[repr(C)]
+struct SockaddrXdp {
+    family: u16,
+    flags: u16,
+    ifindex: u32,
+    queue_id: u32,
+    shared_umem_fd: u32,
+}
+
+/// `struct xdp_desc`, the descriptors of the receive and transmit rings
+#[repr(C)]
+#[derive(Clone, Copy, Default)]
+struct XdpDesc {
+    addr: u64,
+    len: u32,
+    options: u32,
+}
+
+/// The settings of `AF_XDP` sockets
+#[derive(Debug, Clone, Copy)]
+pub struct XskSettings {
+    /// Number of descriptors of each ring, a power of 2
+    pub ring_size: u32,
+    /// Whether to bind sockets in zero-copy mode where possible
+    pub zero_copy: bool,
+}
+
+fn socklen<T>() -> libc::socklen_t {
+    libc::socklen_t::try_from(size_of::<T>()).unwrap_or_else(|_| unreachable!())
+}
+
+#[allow(unsafe_code)] // AF_XDP sockets have no safe wrapper
+fn set_option<T>(fd: &OwnedFd, option: libc::c_int, value: &T) -> io::Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            fd.as_raw_fd(),
+            SOL_XDP,
+            option,
+            std::ptr::from_ref(value).cast(),
+            socklen::<T>(),
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[allow(unsafe_code)] // AF_XDP sockets have no safe wrapper
+fn mmap_offsets(fd: &OwnedFd) -> io::Result<MmapOffsets> {
+    let mut offsets = MmapOffsets::default();
+    let mut len = socklen::<MmapOffsets>();
+    let ret = unsafe {
+        libc::getsockopt(
+            fd.as_raw_fd(),
+            SOL_XDP,
+            XDP_MMAP_OFFSETS,
+            (&raw mut offsets).cast(),
+            &raw mut len,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(offsets)
+}
+
+/// Kick the kernel to process the rings of a socket. Failures telling that the kernel is busy are
+/// transient, and ignored.
+#[allow(unsafe_code)] // AF_XDP sockets have no safe wrapper
+fn kick(fd: &OwnedFd, rx: bool) -> io::Result<()> {
+    let ret = unsafe {
+        if rx {
+            libc::recvfrom(
+                fd.as_raw_fd(),
+                std::ptr::null_mut(),
+                0,
+                libc::MSG_DONTWAIT,
+                std::ptr::null_mut(),
+                std::ptr::null_mut(),
+            )
+        } else {
+            libc::sendto(
+                fd.as_raw_fd(),
+                std::ptr::null(),
+                0,
+                libc::MSG_DONTWAIT,
+                std::ptr::null(),
+                0,
+            )
+        }
+    };
+    if ret < 0 {
+        let e = io::Error::last_os_error();
+        return match e.raw_os_error() {
+            Some(libc::EAGAIN | libc::EBUSY | libc::ENOBUFS | libc::ENETDOWN) => Ok(()),
+            _ => Err(e),
+        };
+    }
+    Ok(())
+}
+
+/// A ring shared with the kernel, of entries of type `T`
+struct Ring<T> {
+    map: MmapRaw,
+    offsets: RingOffsets,
+    size: u32,
+    /// The producer index, as last written by us or read from the kernel
+    cached_producer: u32,
+    /// The consumer index, as last written by us or read from the kernel
+    cached_consumer: u32,
+    entries: PhantomData<T>,
+}
+
+#[allow(unsafe_code)] // the indices and entries of the ring are shared with the kernel
+impl<T: Copy> Ring<T> {
+    fn map(fd: &OwnedFd, page_offset: u64, offsets: RingOffsets, size: u32) -> io::Result<Self> {
+        let len = usize::try_from(offsets.desc).unwrap_or_else(|_| unreachable!())
+            + size as usize * size_of::<T>();
+        let map = MmapOptions::new()
+            .offset(page_offset)
+            .len(len)
+            .populate()
+            .map_raw(fd.as_raw_fd())?;
+        let mut ring = Self {
+            map,
+            offsets,
+            size,
+            cached_producer: 0,
+            cached_consumer: 0,
+            entries: PhantomData,
+        };
+        ring.cached_producer = ring.producer().load(Ordering::Acquire);
+        ring.cached_consumer = ring.consumer().load(Ordering::Acquire);
+        Ok(ring)
+    }
+
+    fn index(&self, offset: u64) -> &AtomicU32 {
+        let offset = usize::try_from(offset).unwrap_or_else(|_| unreachable!());
+        // SAFETY: the kernel gives the offsets of the indices within the mapping, which lives as
+        // long as the ring
+        unsafe { &*self.map.as_ptr().add(offset).cast::<AtomicU32>() }
+    }
+
+    fn producer(&self) -> &AtomicU32 {
+        self.index(self.offsets.producer)
+    }
+
+    fn consumer(&self) -> &AtomicU32 {
+        self.index(self.offsets.consumer)
+    }
+
+    fn needs_wakeup(&self) -> bool {
+        self.index(self.offsets.flags).load(Ordering::Relaxed) & XDP_RING_NEED_WAKEUP != 0
+    }
+
+    fn entry(&self, index: u32) -> *mut T {
+        let desc = usize::try_from(self.offsets.desc).unwrap_or_else(|_| unreachable!());
+        let slot = (index & (self.size - 1)) as usize;
+        // SAFETY: the slot is within the mapping, the size of which accounts for all the entries
+        unsafe { self.map.as_mut_ptr().add(desc).cast::<T>().add(slot) }
+    }
+
+    /// The number of entries the kernel produced, up to `max`, in a ring we consume
+    fn available(&mut self, max: u32) -> u32 {
+        let producer = self.producer().load(Ordering::Acquire);
+        producer.wrapping_sub(self.cached_consumer).min(max)
+    }
+
+    /// Read the `nth` entry available in a ring we consume
+    fn read(&self, nth: u32) -> T {
+        // SAFETY: the entry was produced by the kernel, and is ours until released
+        unsafe { self.entry(self.cached_consumer.wrapping_add(nth)).read() }
+    }
+
+    /// Give `count` consumed entries back to the kernel
+    fn release(&mut self, count: u32) {
+        self.cached_consumer = self.cached_consumer.wrapping_add(count);
+        self.consumer()
+            .store(self.cached_consumer, Ordering::Release);
+    }
+
+    /// The number of free entries in a ring we produce
+    fn free(&self) -> u32 {
+        let consumer = self.consumer().load(Ordering::Acquire);
+        self.size - self.cached_producer.wrapping_sub(consumer)
+    }
+
+    /// Write the `nth` entry of those to submit in a ring we produce
+    fn write(&self, nth: u32, value: T) {
+        // SAFETY: the entry is free, the kernel does not read it until submitted
+        unsafe {
+            self.entry(self.cached_producer.wrapping_add(nth))
+                .write(value)
+        }
+    }
+
+    /// Hand `count` written entries over to the kernel
+    fn submit(&mut self, count: u32) {
+        self.cached_producer = self.cached_producer.wrapping_add(count);
+        self.producer()
+            .store(self.cached_producer, Ordering::Release);
+    }
+}
+
+/// The frames shared with the kernel
+struct Umem(MmapRaw);
+
+impl Umem {
+    /// The frame at `addr`, of `len` octets. Descriptors the kernel would get wrong are ignored.
+    #[allow(unsafe_code)] // the frames are shared with the kernel
+    fn frame(&self, addr: u64, len: u32) -> Option<&[u8]> {
+        let start = usize::try_from(addr).ok()?;
+        let end = start.checked_add(len as usize)?;
+        if end > self.0.len() {
+            return None;
+        }
+        // SAFETY: the frame is within the UMEM, and the kernel does not touch frames it handed
+        // over until they are given back
+        Some(unsafe { std::slice::from_raw_parts(self.0.as_ptr().add(start), end - start) })
+    }
+
+    /// The frame of a receive descriptor, with the RX hash the XDP program wrote in its metadata,
+    /// if any
+    fn received(&self, desc: XdpDesc) -> Option<(&[u8], Option<u32>)> {
+        let frame = self.frame(desc.addr, desc.len)?;
+        Some((frame, self.take_rx_hash(desc.addr)))
+    }
+
+    /// The RX hash in the metadata ahead of the received frame at `addr`, if the XDP program
+    /// wrote one. The magic is cleared, for the hash not to be taken for that of the next frame
+    /// received in the same place.
+    #[allow(unsafe_code)] // the frames are shared with the kernel
+    fn take_rx_hash(&self, addr: u64) -> Option<u32> {
+        let start = addr.checked_sub(u64::from(RX_META_LEN))?;
+        // the metadata is within the headroom of the frame
+        if start < addr & !(u64::from(FRAME_SIZE) - 1) {
+            return None;
+        }
+        let meta = self.frame(start, RX_META_LEN)?;
+        let word =
+            |at: usize| u32::from_ne_bytes([meta[at], meta[at + 1], meta[at + 2], meta[at + 3]]);
+        if word(4) != RX_META_MAGIC {
+            return None;
+        }
+        let hash = word(0);
+        let magic = usize::try_from(start).unwrap_or_else(|_| unreachable!()) + 4;
+        // SAFETY: the metadata is within the UMEM, in the headroom of a frame the kernel handed
+        // over and does not touch until given back
+        unsafe {
+            self.0
+                .as_mut_ptr()
+                .add(magic)
+                .cast::<u32>()
+                .write_unaligned(0);
+        }
+        Some(hash)
+    }
+
+    /// Copy a frame to send to the frame at `addr`
+    #[allow(unsafe_code)] // the frames are shared with the kernel
+    fn write(&self, addr: u64, frame: &[u8]) {
+        let start = usize::try_from(addr).unwrap_or_else(|_| unreachable!());
+        debug_assert!(frame.len() <= FRAME_SIZE as usize);
+        debug_assert!(start + FRAME_SIZE as usize <= self.0.len());
+        // SAFETY: the frame is within the UMEM, and is free: neither the kernel nor anyone else
+        // touches it
+        unsafe {
+            std::ptr::copy_nonoverlapping(
+                frame.as_ptr(),
+                self.0.as_mut_ptr().add(start),
+                frame.len(),
+            );
+        }
+    }
+}
+
+/// The receiving half of an `AF_XDP` socket
+pub struct XskRx {
+    fd: Arc<OwnedFd>,
+    umem: Arc<Umem>,
+    fill: Ring<u64>,
+    rx: Ring<XdpDesc>,
+}
+
+impl XskRx {
+    /// Receive up to `max` frames, handing them to `deliver` with their RX hash, if the XDP
+    /// program gave it. Fails with [`io::ErrorKind::WouldBlock`] once no frame is left to receive.
+    pub fn recv(
+        &mut self,
+        max: usize,
+        mut deliver: impl FnMut(&[u8], Option<u32>),
+    ) -> io::Result<usize> {
+        let max = u32::try_from(max).unwrap_or(u32::MAX);
+        let count = self.rx.available(max);
+        if count == 0 {
+            if self.fill.needs_wakeup() {
+                kick(&self.fd, true)?;
+            }
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        // all the frames of the first half of the UMEM are either in the fill or receive rings
+        debug_assert!(self.fill.free() >= count);
+        for nth in 0..count {
+            let desc = self.rx.read(nth);
+            if let Some((frame, rx_hash)) = self.umem.received(desc) {
+                deliver(frame, rx_hash);
+            }
+            self.fill
+                .write(nth, desc.addr & !(u64::from(FRAME_SIZE) - 1));
+        }
+        self.rx.release(count);
+        self.fill.submit(count);
+        if self.fill.needs_wakeup() {
+            kick(&self.fd, true)?;
+        }
+        Ok(count as usize)
+    }
+}
+
+/// The transmitting half of an `AF_XDP` socket
+pub struct XskTx {
+    fd: Arc<OwnedFd>,
+    umem: Arc<Umem>,
+    tx: Ring<XdpDesc>,
+    completion: Ring<u64>,
+    /// The frames of the second half of the UMEM which are neither sent nor being sent
+    free: Vec<u64>,
+}
+
+impl XskTx {
+    pub fn raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+
+    /// Take back the frames the kernel is done sending
+    fn reclaim(&mut self) {
+        let count = self.completion.available(self.completion.size);
+        for nth in 0..count {
+            self.free.push(self.completion.read(nth));
+        }
+        self.completion.release(count);
+    }
+
+    /// Send a frame. Fails with [`io::ErrorKind::WouldBlock`] if all the frames of the transmit
+    /// ring are in use.
+    pub fn send(&mut self, frame: &[u8]) -> io::Result<()> {
+        if frame.len() > FRAME_SIZE as usize {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("frame of {} octets exceeds {FRAME_SIZE}", frame.len()),
+            ));
+        }
+        self.reclaim();
+        let Some(addr) = self.free.pop() else {
+            kick(&self.fd, false)?;
+            return Err(io::ErrorKind::WouldBlock.into());
+        };
+        // there are as many frames to send as there are descriptors in the transmit ring
+        debug_assert!(self.tx.free() > 0);
+        self.umem.write(addr, frame);
+        let len = u32::try_from(frame.len()).unwrap_or_else(|_| unreachable!());
+        self.tx.write(
+            0,
+            XdpDesc {
+                addr,
+                len,
+                options: 0,
+            },
+        );
+        self.tx.submit(1);
+        if self.tx.needs_wakeup() {
+            kick(&self.fd, false)?;
+        }
+        Ok(())
+    }
+
+    /// The frames given to the kernel which it has not sent yet. Kicks the kernel if any.
+    pub fn pending(&mut self) -> io::Result<usize> {
+        self.reclaim();
+        let pending = self.tx.size as usize - self.free.len();
+        if pending > 0 {
+            kick(&self.fd, false)?;
+        }
+        Ok(pending)
+    }
+}
+
+/// An `AF_XDP` socket bound to a queue of an interface
+pub struct Xsk {
+    pub rx: XskRx,
+    pub tx: XskTx,
+}
+
+impl Xsk {
+    /// Open a socket on queue `queue` of interface `ifindex`.
+    #[allow(unsafe_code)] // AF_XDP sockets have no safe wrapper
+    pub(super) fn open(
+        ifindex: InterfaceIndex,
+        queue: u32,
+        settings: XskSettings,
+        zero_copy: bool,
+    ) -> io::Result<Self> {
+        let fd = unsafe { libc::socket(AF_XDP, libc::SOCK_RAW | libc::SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the descriptor was just created and is owned by no one else
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+
+        let size = settings.ring_size;
+        let umem_len = 2 * size as usize * FRAME_SIZE as usize;
+        let umem = MmapRaw::from(MmapOptions::new().len(umem_len).map_anon()?);
+        set_option(
+            &fd,
+            XDP_UMEM_REG,
+            &UmemReg {
+                addr: umem.as_ptr() as u64,
+                len: umem_len as u64,
+                chunk_size: FRAME_SIZE,
+                headroom: 0,
+            },
+        )?;
+        for option in [
+            XDP_UMEM_FILL_RING,
+            XDP_UMEM_COMPLETION_RING,
+            XDP_RX_RING,
+            XDP_TX_RING,
+        ] {
+            set_option(&fd, option, &size)?;
+        }
+
+        let offsets = mmap_offsets(&fd)?;
+        let mut fill = Ring::map(&fd, XDP_UMEM_PGOFF_FILL_RING, offsets.fill, size)?;
+        let completion = Ring::map(
+            &fd,
+            XDP_UMEM_PGOFF_COMPLETION_RING,
+            offsets.completion,
+            size,
+        )?;
+        let rx = Ring::map(&fd, XDP_PGOFF_RX_RING, offsets.rx, size)?;
+        let tx = Ring::map(&fd, XDP_PGOFF_TX_RING, offsets.tx, size)?;
+
+        // the first half of the UMEM is given to the NIC to receive in
+        for nth in 0..size {
+            fill.write(nth, u64::from(nth) * u64::from(FRAME_SIZE));
+        }
+        fill.submit(size);
+        let free = (size..2 * size)
+            .map(|nth| u64::from(nth) * u64::from(FRAME_SIZE))
+            .collect();
+
+        let addr = SockaddrXdp {
+            family: u16::try_from(AF_XDP).unwrap_or_else(|_| unreachable!()),
+            flags: XDP_USE_NEED_WAKEUP | if zero_copy { XDP_ZEROCOPY } else { XDP_COPY },
+            ifindex: ifindex.to_u32(),
+            queue_id: queue,
+            shared_umem_fd: 0,
+        };
+        let ret = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                (&raw const addr).cast(),
+                socklen::<SockaddrXdp>(),
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let fd = Arc::new(fd);
+        let umem = Arc::new(Umem(umem));
+        Ok(Self {
+            rx: XskRx {
+                fd: fd.clone(),
+                umem: umem.clone(),
+                fill,
+                rx,
+            },
+            tx: XskTx {
+                fd,
+                umem,
+                tx,
+                completion,
+                free,
+            },
+        })
+    }
+
+    /// Duplicate the socket, to wait for frames to receive on
+    pub fn try_clone_fd(&self) -> io::Result<OwnedFd> {
+        self.rx.fd.try_clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[allow(unsafe_code)]
+    fn test_umem_rx_hash() {
+        let len = 2 * FRAME_SIZE as usize;
+        let umem = Umem(MmapRaw::from(
+            MmapOptions::new().len(len).map_anon().unwrap(),
+        ));
+        let addr = u64::from(FRAME_SIZE) + 256;
+        let start = usize::try_from(addr).unwrap();
+        let meta = start - RX_META_LEN as usize;
+        umem.write(u64::from(FRAME_SIZE), &[0; 256]);
+        // SAFETY: the test owns the anonymous mapping, as the XDP program owns the frame
+        unsafe {
+            let octets = std::slice::from_raw_parts_mut(umem.0.as_mut_ptr(), len);
+            octets[meta..meta + 4].copy_from_slice(&0xdead_beef_u32.to_ne_bytes());
+            octets[meta + 4..start].copy_from_slice(&RX_META_MAGIC.to_ne_bytes());
+            octets[start..start + 4].copy_from_slice(b"data");
+        }
+        let desc = XdpDesc {
+            addr,
+            len: 4,
+            options: 0,
+        };
+        assert_eq!(umem.received(desc), Some((&b"data"[..], Some(0xdead_beef))));
+        // the hash is taken once, and not for the next frame received in the same place
+        assert_eq!(umem.received(desc), Some((&b"data"[..], None)));
+        // frames without headroom have no metadata, and descriptors past the UMEM no frame
+        assert_eq!(umem.take_rx_hash(u64::from(FRAME_SIZE)), None);
+        assert_eq!(umem.take_rx_hash(4), None);
+        let past = XdpDesc {
+            addr: u64::from(2 * FRAME_SIZE - 2),
+            len: 4,
+            options: 0,
+        };
+        assert_eq!(umem.received(past), None);
+    }
+}
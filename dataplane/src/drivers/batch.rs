@@ -0,0 +1,101 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Adaptive sizing of the batches of packets received by the drivers.
+//!
+//! Large batches amortize the cost of the pipeline over many packets, but a packet waits for the
+//! whole batch to be processed before it gets transmitted. The size of the batches of each queue
+//! therefore adapts to its load, within [`BatchLimits`]: it doubles after [`GROW_ROUNDS`]
+//! consecutive receptions fill the batch, since the queue keeps up with it, and halves after
+//! [`SHRINK_ROUNDS`] consecutive receptions fill less than a [`SHALLOW_RATIO`] of it, since the
+//! queue is shallow. Batches start at the minimum size, so that latency is favored until load
+//! builds up. The current size is reported as `rx_batch_size`, per interface and worker.
+
+use metrics::Gauge;
+use stats::{MetricSpec, Register};
+
+/// Number of consecutive full receptions after which a batch grows
+const GROW_ROUNDS: u32 = 4;
+
+/// Number of consecutive shallow receptions after which a batch shrinks. Shrinking is slower than
+/// growing, so that short lulls in sustained load do not cost throughput.
+const SHRINK_ROUNDS: u32 = 16;
+
+/// A reception is shallow when it fills less than a `1 / SHALLOW_RATIO` of the batch
+const SHALLOW_RATIO: usize = 4;
+
+/// The bounds of the size of the batches of packets received from a queue
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchLimits {
+    pub min: usize,
+    pub max: usize,
+}
+
+/// The size of the batches of packets received from a queue, adapted to its load
+pub(crate) struct AdaptiveBatch {
+    limits: BatchLimits,
+    size: usize,
+    /// Consecutive receptions which filled the batch
+    full: u32,
+    /// Consecutive receptions which filled less than a `1 / SHALLOW_RATIO` of the batch
+    shallow: u32,
+    metric: Gauge,
+}
+
+impl AdaptiveBatch {
+    /// Size the batches of the queue of `worker` on interface `if_name` within `limits`
+    pub(crate) fn new(limits: BatchLimits, worker: usize, if_name: &str) -> Self {
+        let labels = vec![
+            ("interface".to_string(), if_name.to_string()),
+            ("worker".to_string(), worker.to_string()),
+        ];
+        let metric: Gauge = MetricSpec::new("rx_batch_size", metrics::Unit::Count, labels)
+            .register()
+            .metric;
+        #[allow(clippy::cast_precision_loss)] // batches are tiny
+        metric.set(limits.min as f64);
+        Self {
+            limits,
+            size: limits.min,
+            full: 0,
+            shallow: 0,
+            metric,
+        }
+    }
+
+    /// The maximum number of packets to receive next
+    pub(crate) fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Account for the reception of `received` packets out of a batch of [`Self::size`], and
+    /// adapt the size of the next batches.
+    pub(crate) fn received(&mut self, received: usize) {
+        let size = if received >= self.size {
+            self.shallow = 0;
+            self.full += 1;
+            if self.full < GROW_ROUNDS {
+                return;
+            }
+            self.full = 0;
+            (self.size * 2).min(self.limits.max)
+        } else if received * SHALLOW_RATIO < self.size {
+            self.full = 0;
+            self.shallow += 1;
+            if self.shallow < SHRINK_ROUNDS {
+                return;
+            }
+            self.shallow = 0;
+            (self.size / 2).max(self.limits.min)
+        } else {
+            self.full = 0;
+            self.shallow = 0;
+            return;
+        };
+        if size != self.size {
+            self.size = size;
+            #[allow(clippy::cast_precision_loss)] // batches are tiny
+            self.metric.set(size as f64);
+        }
+    }
+}
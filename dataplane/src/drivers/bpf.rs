@@ -0,0 +1,967 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! The BPF objects of the drivers: maps, XDP programs and their attachment to interfaces.
+//!
+//! The programs are tiny, so they are assembled by the drivers with [`Asm`], which resolves the
+//! targets of their jumps from labels, rather than built with a BPF toolchain. The AF_XDP driver
+//! attaches one redirecting the frames of each queue of an interface to the socket registered
+//! for the queue in an XSKMAP, passing them to the kernel stack if there is none. Where the NIC
+//! driver tells it, the program writes the RX hash of the frames in their metadata, ahead of the
+//! frames, for the sockets to pick it up. Programs are attached with BPF links, so that they are
+//! detached when the link is closed, including when the dataplane dies.
+
+use std::fmt::Display;
+use std::io;
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+use net::interface::InterfaceIndex;
+use nix::libc;
+use tracing::debug;
+
+/// Commands of the bpf syscall (`enum bpf_cmd`)
+const BPF_MAP_CREATE: libc::c_int = 0;
+const BPF_MAP_LOOKUP_ELEM: libc::c_int = 1;
+const BPF_MAP_UPDATE_ELEM: libc::c_int = 2;
+const BPF_MAP_DELETE_ELEM: libc::c_int = 3;
+const BPF_PROG_LOAD: libc::c_int = 5;
+#[cfg(test)]
+const BPF_PROG_TEST_RUN: libc::c_int = 10;
+const BPF_LINK_CREATE: libc::c_int = 28;
+
+/// `BPF_MAP_TYPE_PERCPU_ARRAY`
+pub(super) const BPF_MAP_TYPE_PERCPU_ARRAY: u32 = 6;
+/// `BPF_MAP_TYPE_LPM_TRIE`
+pub(super) const BPF_MAP_TYPE_LPM_TRIE: u32 = 11;
+/// `BPF_MAP_TYPE_XSKMAP`
+const BPF_MAP_TYPE_XSKMAP: u32 = 17;
+/// `BPF_F_NO_PREALLOC`, which LPM tries require
+pub(super) const BPF_F_NO_PREALLOC: u32 = 1;
+/// `BPF_PROG_TYPE_XDP`
+const BPF_PROG_TYPE_XDP: u32 = 6;
+/// `BPF_XDP`, the attach type of XDP links
+const BPF_XDP: u32 = 37;
+/// `BPF_F_XDP_DEV_BOUND_ONLY`, loading a program for the device it is bound to only, which the
+/// kernel functions reading the metadata of the NIC require
+const BPF_F_XDP_DEV_BOUND_ONLY: u32 = 1 << 6;
+/// `BPF_PSEUDO_MAP_FD`, telling that the immediate of a 64-bit load is the fd of a map
+const BPF_PSEUDO_MAP_FD: u8 = 1;
+/// `BPF_PSEUDO_KFUNC_CALL`, telling that the immediate of a call is the BTF id of a kernel function
+const BPF_PSEUDO_KFUNC_CALL: u8 = 2;
+/// `BPF_FUNC_map_lookup_elem`
+pub(super) const BPF_FUNC_MAP_LOOKUP_ELEM: i32 = 1;
+/// `BPF_FUNC_redirect_map`
+const BPF_FUNC_REDIRECT_MAP: i32 = 51;
+/// `BPF_FUNC_xdp_adjust_meta`
+const BPF_FUNC_XDP_ADJUST_META: i32 = 54;
+/// The kernel function giving the RX hash of the NIC to XDP programs (linux 6.3 and later)
+const KFUNC_XDP_RX_HASH: &str = "bpf_xdp_metadata_rx_hash";
+/// Where the kernel exposes its BTF, by which kernel functions are called
+const VMLINUX_BTF: &str = "/sys/kernel/btf/vmlinux";
+/// `BTF_KIND_FUNC`
+const BTF_KIND_FUNC: u32 = 12;
+/// Actions of XDP programs
+pub(super) const XDP_DROP: i32 = 1;
+pub(super) const XDP_PASS: i32 = 2;
+/// The offsets of the fields of `struct xdp_md`
+pub(super) const XDP_MD_DATA: i16 = 0;
+pub(super) const XDP_MD_DATA_END: i16 = 4;
+const XDP_MD_DATA_META: i16 = 8;
+const XDP_MD_RX_QUEUE_INDEX: i16 = 16;
+/// Flags of the attachment of XDP programs
+const XDP_FLAGS_SKB_MODE: u32 = 1 << 1;
+const XDP_FLAGS_DRV_MODE: u32 = 1 << 2;
+
+/// The metadata the AF_XDP program writes ahead of the frames it has the RX hash of: the hash,
+/// and then [`RX_META_MAGIC`], both in host order
+pub(super) const RX_META_LEN: u32 = 8;
+/// Tells the metadata written by the program from the leftovers of the headroom of the frames
+pub(super) const RX_META_MAGIC: u32 = 0x6873_6872;
+
+/// A BPF instruction (`struct bpf_insn`)
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct BpfInsn {
+    code: u8,
+    /// Destination register in the low nibble, source register in the high one
+    regs: u8,
+    off: i16,
+    imm: i32,
+}
+
+impl BpfInsn {
+    pub(super) const fn new(code: u8, dst: u8, src: u8, off: i16, imm: i32) -> Self {
+        Self {
+            code,
+            regs: dst | (src << 4),
+            off,
+            imm,
+        }
+    }
+}
+
+/// The size of the accesses to memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Size {
+    U8 = 0x10,
+    U16 = 0x08,
+    U32 = 0x00,
+    U64 = 0x18,
+}
+
+/// The conditions of jumps, unsigned
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Cond {
+    Eq = 0x10,
+    Gt = 0x20,
+    Ne = 0x50,
+    Lt = 0xa0,
+}
+
+/// A place in a program being assembled, which jumps target
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) struct Label(usize);
+
+/// An assembler of BPF programs, resolving the offsets of the jumps from the labels they target.
+/// Registers are given by number, `r10` being the read-only frame pointer.
+#[derive(Default)]
+pub(super) struct Asm {
+    insns: Vec<BpfInsn>,
+    /// The index of the instruction each label is placed at, once placed
+    labels: Vec<Option<usize>>,
+    /// The jumps, by index, and the labels they target
+    jumps: Vec<(usize, Label)>,
+}
+
+impl Asm {
+    /// `BPF_ALU64`, with `BPF_K` or `BPF_X` operands
+    const ALU64: u8 = 0x07;
+    const ALU64_X: u8 = 0x0f;
+    const ADD: u8 = 0x00;
+    const AND: u8 = 0x50;
+    const LSH: u8 = 0x60;
+    const MOV: u8 = 0xb0;
+    /// `BPF_JMP`, with `BPF_K` or `BPF_X` operands
+    const JMP: u8 = 0x05;
+    const JMP_X: u8 = 0x0d;
+    const JA: u8 = 0x00;
+    const CALL: u8 = 0x85;
+    const EXIT: u8 = 0x95;
+    /// `BPF_LDX`, `BPF_STX` and `BPF_ST`, in `BPF_MEM` mode, and `BPF_LD | BPF_IMM | BPF_DW`
+    const LDX: u8 = 0x61;
+    const STX: u8 = 0x63;
+    const ST: u8 = 0x62;
+    const LD_IMM64: u8 = 0x18;
+
+    /// A label, to be placed
+    pub(super) fn label(&mut self) -> Label {
+        self.labels.push(None);
+        Label(self.labels.len() - 1)
+    }
+
+    /// Place `label` at the next instruction
+    pub(super) fn place(&mut self, label: Label) -> &mut Self {
+        debug_assert!(self.labels[label.0].is_none(), "label placed twice");
+        self.labels[label.0] = Some(self.insns.len());
+        self
+    }
+
+    /// Append an instruction
+    pub(super) fn insn(&mut self, code: u8, dst: u8, src: u8, off: i16, imm: i32) -> &mut Self {
+        self.insns.push(BpfInsn::new(code, dst, src, off, imm));
+        self
+    }
+
+    /// `dst = src`
+    pub(super) fn mov(&mut self, dst: u8, src: u8) -> &mut Self {
+        self.insn(Self::ALU64_X | Self::MOV, dst, src, 0, 0)
+    }
+
+    /// `dst = imm`
+    pub(super) fn mov_imm(&mut self, dst: u8, imm: i32) -> &mut Self {
+        self.insn(Self::ALU64 | Self::MOV, dst, 0, 0, imm)
+    }
+
+    /// `dst += src`
+    pub(super) fn add(&mut self, dst: u8, src: u8) -> &mut Self {
+        self.insn(Self::ALU64_X | Self::ADD, dst, src, 0, 0)
+    }
+
+    /// `dst += imm`
+    pub(super) fn add_imm(&mut self, dst: u8, imm: i32) -> &mut Self {
+        self.insn(Self::ALU64 | Self::ADD, dst, 0, 0, imm)
+    }
+
+    /// `dst &= imm`
+    pub(super) fn and_imm(&mut self, dst: u8, imm: i32) -> &mut Self {
+        self.insn(Self::ALU64 | Self::AND, dst, 0, 0, imm)
+    }
+
+    /// `dst <<= imm`
+    pub(super) fn lsh_imm(&mut self, dst: u8, imm: i32) -> &mut Self {
+        self.insn(Self::ALU64 | Self::LSH, dst, 0, 0, imm)
+    }
+
+    /// `dst = *(size *)(src + off)`
+    pub(super) fn load(&mut self, size: Size, dst: u8, src: u8, off: i16) -> &mut Self {
+        self.insn(Self::LDX | size as u8, dst, src, off, 0)
+    }
+
+    /// `*(size *)(dst + off) = src`
+    pub(super) fn store(&mut self, size: Size, dst: u8, off: i16, src: u8) -> &mut Self {
+        self.insn(Self::STX | size as u8, dst, src, off, 0)
+    }
+
+    /// `*(size *)(dst + off) = imm`
+    pub(super) fn store_imm(&mut self, size: Size, dst: u8, off: i16, imm: i32) -> &mut Self {
+        self.insn(Self::ST | size as u8, dst, 0, off, imm)
+    }
+
+    /// `dst = map`, the map of descriptor `fd`, on two instructions
+    pub(super) fn load_map(&mut self, dst: u8, fd: i32) -> &mut Self {
+        self.insn(Self::LD_IMM64, dst, BPF_PSEUDO_MAP_FD, 0, fd)
+            .insn(0, 0, 0, 0, 0)
+    }
+
+    /// `r0 = helper(r1, ..., r5)`
+    pub(super) fn call(&mut self, helper: i32) -> &mut Self {
+        self.insn(Self::CALL, 0, 0, 0, helper)
+    }
+
+    /// `r0 = kfunc(r1, ..., r5)`, the kernel function of BTF id `kfunc`
+    pub(super) fn call_kfunc(&mut self, kfunc: i32) -> &mut Self {
+        self.insn(Self::CALL, 0, BPF_PSEUDO_KFUNC_CALL, 0, kfunc)
+    }
+
+    /// `return r0`
+    pub(super) fn exit(&mut self) -> &mut Self {
+        self.insn(Self::EXIT, 0, 0, 0, 0)
+    }
+
+    /// `goto target`
+    pub(super) fn jump(&mut self, target: Label) -> &mut Self {
+        self.jumps.push((self.insns.len(), target));
+        self.insn(Self::JMP | Self::JA, 0, 0, 0, 0)
+    }
+
+    /// `if dst <cond> imm goto target`
+    pub(super) fn jump_if(&mut self, cond: Cond, dst: u8, imm: i32, target: Label) -> &mut Self {
+        self.jumps.push((self.insns.len(), target));
+        self.insn(Self::JMP | cond as u8, dst, 0, 0, imm)
+    }
+
+    /// `if dst <cond> src goto target`
+    pub(super) fn jump_if_reg(&mut self, cond: Cond, dst: u8, src: u8, target: Label) -> &mut Self {
+        self.jumps.push((self.insns.len(), target));
+        self.insn(Self::JMP_X | cond as u8, dst, src, 0, 0)
+    }
+
+    /// The instructions of the program, with the offsets of the jumps, from the instruction
+    /// following them, to their targets
+    pub(super) fn finish(self) -> io::Result<Vec<BpfInsn>> {
+        let mut insns = self.insns;
+        for (index, label) in self.jumps {
+            let target = self.labels[label.0]
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "label not placed"))?;
+            let off = i16::try_from(target.cast_signed() - index.cast_signed() - 1)
+                .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "jump out of range"))?;
+            insns[index].off = off;
+        }
+        Ok(insns)
+    }
+}
+
+/// The attributes of `BPF_MAP_CREATE`
+#[repr(C)]
+#[derive(Default)]
+struct MapCreateAttr {
+    map_type: u32,
+    key_size: u32,
+    value_size: u32,
+    max_entries: u32,
+    map_flags: u32,
+    inner_map_fd: u32,
+    numa_node: u32,
+    map_name: [u8; 16],
+}
+
+/// The attributes of `BPF_MAP_UPDATE_ELEM`
+#[repr(C)]
+#[derive(Default)]
+struct MapElemAttr {
+    map_fd: u32,
+    pad: u32,
+    key: u64,
+    value: u64,
+    flags: u64,
+}
+
+/// The attributes of `BPF_PROG_LOAD`
+#[repr(C)]
+#[derive(Default)]
+struct ProgLoadAttr {
+    prog_type: u32,
+    insn_cnt: u32,
+    insns: u64,
+    license: u64,
+    log_level: u32,
+    log_size: u32,
+    log_buf: u64,
+    kern_version: u32,
+    prog_flags: u32,
+    prog_name: [u8; 16],
+    prog_ifindex: u32,
+    expected_attach_type: u32,
+}
+
+/// The attributes of `BPF_PROG_TEST_RUN`
+#[cfg(test)]
+#[repr(C)]
+#[derive(Default)]
+struct ProgTestRunAttr {
+    prog_fd: u32,
+    retval: u32,
+    data_size_in: u32,
+    data_size_out: u32,
+    data_in: u64,
+    data_out: u64,
+    repeat: u32,
+    duration: u32,
+}
+
+/// The attributes of `BPF_LINK_CREATE`
+#[repr(C)]
+#[derive(Default)]
+struct LinkCreateAttr {
+    prog_fd: u32,
+    target_ifindex: u32,
+    attach_type: u32,
+    flags: u32,
+}
+
+/// Run the bpf syscall
+#[allow(unsafe_code)] // the bpf syscall has no safe wrapper
+fn bpf<T>(cmd: libc::c_int, attr: &T) -> io::Result<libc::c_long> {
+    let ret =
+        unsafe { libc::syscall(libc::SYS_bpf, cmd, std::ptr::from_ref(attr), size_of::<T>()) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ret)
+}
+
+/// Run a command of the bpf syscall creating an object, returning its fd
+#[allow(unsafe_code)]
+fn bpf_create<T>(cmd: libc::c_int, attr: &T) -> io::Result<OwnedFd> {
+    let fd = libc::c_int::try_from(bpf(cmd, attr)?).unwrap_or_else(|_| unreachable!());
+    // SAFETY: the descriptor was just created and is owned by no one else
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// The name of a map or program, which the kernel wants nul-terminated
+fn object_name(name: &str) -> [u8; 16] {
+    let mut object = [0; 16];
+    let len = name.len().min(object.len() - 1);
+    object[..len].copy_from_slice(&name.as_bytes()[..len]);
+    object
+}
+
+fn raw_fd(fd: &impl AsRawFd) -> u32 {
+    u32::try_from(fd.as_raw_fd()).unwrap_or_else(|_| unreachable!())
+}
+
+/// A BPF map, deleted once dropped and no longer used by any program
+pub(super) struct BpfMap(OwnedFd);
+
+impl BpfMap {
+    /// Create a map of type `map_type`, with room for `max_entries` entries of values of
+    /// `value_size` bytes, by keys of `key_size` bytes
+    pub(super) fn new(
+        map_type: u32,
+        key_size: usize,
+        value_size: usize,
+        max_entries: u32,
+        map_flags: u32,
+        name: &str,
+    ) -> io::Result<Self> {
+        let attr = MapCreateAttr {
+            map_type,
+            key_size: u32::try_from(key_size).unwrap_or_else(|_| unreachable!()),
+            value_size: u32::try_from(value_size).unwrap_or_else(|_| unreachable!()),
+            max_entries,
+            map_flags,
+            map_name: object_name(name),
+            ..MapCreateAttr::default()
+        };
+        bpf_create(BPF_MAP_CREATE, &attr).map(Self)
+    }
+
+    /// The descriptor of the map, for programs to refer to it
+    pub(super) fn fd(&self) -> i32 {
+        self.0.as_raw_fd()
+    }
+
+    /// Set the value of `key`, which must be the size of the keys of the map, as `value` must be
+    /// the size of its values
+    pub(super) fn update<K, V>(&self, key: &K, value: &V) -> io::Result<()> {
+        let attr = MapElemAttr {
+            map_fd: raw_fd(&self.0),
+            key: std::ptr::from_ref(key) as u64,
+            value: std::ptr::from_ref(value) as u64,
+            ..MapElemAttr::default()
+        };
+        bpf(BPF_MAP_UPDATE_ELEM, &attr).map(drop)
+    }
+
+    /// Remove `key` from the map
+    pub(super) fn delete<K>(&self, key: &K) -> io::Result<()> {
+        let attr = MapElemAttr {
+            map_fd: raw_fd(&self.0),
+            key: std::ptr::from_ref(key) as u64,
+            ..MapElemAttr::default()
+        };
+        bpf(BPF_MAP_DELETE_ELEM, &attr).map(drop)
+    }
+
+    /// Read the value of `key` into `value`, which must be the size of the values of the map, or
+    /// of the values of all the possible CPUs for per-CPU maps
+    pub(super) fn lookup<K, V: ?Sized>(&self, key: &K, value: &mut V) -> io::Result<()> {
+        let attr = MapElemAttr {
+            map_fd: raw_fd(&self.0),
+            key: std::ptr::from_ref(key) as u64,
+            value: std::ptr::from_mut(value).cast::<u8>() as u64,
+            ..MapElemAttr::default()
+        };
+        bpf(BPF_MAP_LOOKUP_ELEM, &attr).map(drop)
+    }
+}
+
+/// The map of the AF_XDP sockets of an interface, by queue
+pub(super) struct XskMap(BpfMap);
+
+impl XskMap {
+    /// Create a map with room for the sockets of `queues` queues
+    pub(super) fn new(queues: u32) -> io::Result<Self> {
+        BpfMap::new(BPF_MAP_TYPE_XSKMAP, 4, 4, queues, 0, "xsks_map").map(Self)
+    }
+
+    /// Register the socket of a queue. The kernel unregisters sockets as they get closed.
+    pub(super) fn insert(&self, queue: u32, xsk: &impl AsRawFd) -> io::Result<()> {
+        self.0.update(&queue, &raw_fd(xsk))
+    }
+}
+
+/// The id of the function `name` in `btf`, the BTF of the kernel, by which programs call it.
+/// `None` if there is no such function, or if `btf` is not valid.
+fn btf_func_id(btf: &[u8], name: &str) -> Option<u32> {
+    let u32_at = |offset: usize| -> Option<usize> {
+        let bytes = btf.get(offset..offset.checked_add(4)?)?;
+        usize::try_from(u32::from_ne_bytes(bytes.try_into().ok()?)).ok()
+    };
+    // the header: magic, version, flags, and then the length of the header and the offsets and
+    // lengths of the types and the strings, from its end
+    if btf.get(..2)? != 0xeb9f_u16.to_ne_bytes() {
+        return None;
+    }
+    let hdr_len = u32_at(4)?;
+    let types = hdr_len.checked_add(u32_at(8)?)?;
+    let types_end = types.checked_add(u32_at(12)?)?;
+    let strings = hdr_len.checked_add(u32_at(16)?)?;
+    let strings = btf.get(strings..strings.checked_add(u32_at(20)?)?)?;
+    let mut offset = types;
+    let mut id = 1;
+    while offset < types_end {
+        // `struct btf_type`: name, info, and then size or type, followed by data of the kind
+        let name_off = u32_at(offset)?;
+        let info = u32_at(offset + 4)?;
+        let kind = (info >> 24) & 0x1f;
+        let vlen = info & 0xffff;
+        if kind == BTF_KIND_FUNC as usize {
+            let found = strings.get(name_off..)?.split(|c| *c == 0).next()?;
+            if found == name.as_bytes() {
+                return Some(id);
+            }
+        }
+        let data = match kind {
+            2 | 7..=12 | 16 | 18 => 0,
+            1 | 14 | 17 => 4,
+            3 => 12,
+            6 | 13 => 8 * vlen,
+            4 | 5 | 15 | 19 => 12 * vlen,
+            _ => return None,
+        };
+        offset += 12 + data;
+        id += 1;
+    }
+    None
+}
+
+/// An XDP program
+pub(super) struct XdpProgram(OwnedFd);
+
+impl XdpProgram {
+    /// Load the program made of `insns`
+    pub(super) fn new(name: &str, insns: &[BpfInsn]) -> io::Result<Self> {
+        Self::new_with(name, insns, c"Apache-2.0", None)
+    }
+
+    /// Load the program made of `insns` under `license`, for the interface `bound` only if given
+    fn new_with(
+        name: &str,
+        insns: &[BpfInsn],
+        license: &std::ffi::CStr,
+        bound: Option<InterfaceIndex>,
+    ) -> io::Result<Self> {
+        let attr = ProgLoadAttr {
+            prog_type: BPF_PROG_TYPE_XDP,
+            insn_cnt: u32::try_from(insns.len()).unwrap_or_else(|_| unreachable!()),
+            insns: insns.as_ptr() as u64,
+            license: license.as_ptr() as u64,
+            prog_name: object_name(name),
+            prog_flags: bound.map_or(0, |_| BPF_F_XDP_DEV_BOUND_ONLY),
+            prog_ifindex: bound.map_or(0, InterfaceIndex::to_u32),
+            ..ProgLoadAttr::default()
+        };
+        bpf_create(BPF_PROG_LOAD, &attr).map(Self)
+    }
+
+    /// Run the program once on `frame`, returning its action
+    #[cfg(test)]
+    #[allow(unsafe_code)] // the kernel writes the outcome in the attributes
+    pub(super) fn test_run(&self, frame: &[u8]) -> io::Result<u32> {
+        let mut attr = ProgTestRunAttr {
+            prog_fd: raw_fd(&self.0),
+            data_size_in: u32::try_from(frame.len()).unwrap_or_else(|_| unreachable!()),
+            data_in: frame.as_ptr() as u64,
+            repeat: 1,
+            ..ProgTestRunAttr::default()
+        };
+        let attr_ptr = std::ptr::from_mut(&mut attr);
+        // SAFETY: the frame and the attributes outlive the syscall, which only reads the former
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_bpf,
+                BPF_PROG_TEST_RUN,
+                attr_ptr,
+                size_of::<ProgTestRunAttr>(),
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(attr.retval)
+    }
+
+    /// Load the program redirecting the frames of an interface to the sockets of `map`. If given
+    /// the interface in `rx_hash`, the program is bound to it and writes the RX hash of the NIC in
+    /// the metadata of the frames, where the kernel supports it.
+    pub(super) fn load(map: &XskMap, rx_hash: Option<InterfaceIndex>) -> io::Result<Self> {
+        if let Some(ifindex) = rx_hash {
+            match Self::load_rx_hash(map, ifindex) {
+                Ok(program) => return Ok(program),
+                Err(e) => debug!("Failed to load XDP program with RX hash for {ifindex}: {e}"),
+            }
+        }
+        Self::new("xsk_redirect", &Self::assemble(map.0.fd())?)
+    }
+
+    /// The program redirecting the frames to the sockets of the map of descriptor `map_fd`
+    fn assemble(map_fd: i32) -> io::Result<Vec<BpfInsn>> {
+        let mut asm = Asm::default();
+        asm.mov(6, 1);
+        Self::redirect(&mut asm, map_fd);
+        asm.finish()
+    }
+
+    /// Return the redirection of the frame of context `r6` to the socket of its queue in the map
+    /// of descriptor `map_fd`, or the action of passing it to the kernel stack if the queue has
+    /// none
+    fn redirect(asm: &mut Asm, map_fd: i32) {
+        asm.load(Size::U32, 2, 6, XDP_MD_RX_QUEUE_INDEX)
+            .load_map(1, map_fd)
+            .mov_imm(3, XDP_PASS)
+            .call(BPF_FUNC_REDIRECT_MAP)
+            .exit();
+    }
+
+    /// The program redirecting the frames to the sockets of the map of descriptor `map_fd`, with
+    /// their RX hash in their metadata, the kernel function giving the hash being of BTF id
+    /// `rx_hash`
+    #[allow(clippy::cast_possible_wrap)]
+    fn assemble_rx_hash(map_fd: i32, rx_hash: i32) -> io::Result<Vec<BpfInsn>> {
+        let mut asm = Asm::default();
+        let redirect = asm.label();
+        // r0 = bpf_xdp_metadata_rx_hash(ctx, r10 - 8, r10 - 4), to the zeroed stack
+        asm.mov(6, 1)
+            .store_imm(Size::U64, 10, -8, 0)
+            .mov(2, 10)
+            .add_imm(2, -8)
+            .mov(3, 10)
+            .add_imm(3, -4)
+            .call_kfunc(rx_hash)
+            .jump_if(Cond::Ne, 0, 0, redirect);
+        // make room for the metadata ahead of the frame
+        asm.mov(1, 6)
+            .mov_imm(2, -(RX_META_LEN as i32))
+            .call(BPF_FUNC_XDP_ADJUST_META)
+            .jump_if(Cond::Ne, 0, 0, redirect);
+        // r2 = ctx->data_meta, which must be followed by the metadata before ctx->data
+        asm.load(Size::U32, 2, 6, XDP_MD_DATA_META)
+            .load(Size::U32, 3, 6, XDP_MD_DATA)
+            .mov(4, 2)
+            .add_imm(4, RX_META_LEN as i32)
+            .jump_if_reg(Cond::Gt, 4, 3, redirect);
+        // write the hash and the magic
+        asm.load(Size::U32, 5, 10, -8)
+            .store(Size::U32, 2, 0, 5)
+            .store_imm(Size::U32, 2, 4, RX_META_MAGIC as i32);
+        asm.place(redirect);
+        Self::redirect(&mut asm, map_fd);
+        asm.finish()
+    }
+
+    /// Load the program redirecting the frames of `ifindex` to the sockets of `map`, with their
+    /// RX hash in their metadata. Kernel functions are for GPL-compatible programs only, hence
+    /// the license.
+    fn load_rx_hash(map: &XskMap, ifindex: InterfaceIndex) -> io::Result<Self> {
+        let btf = std::fs::read(VMLINUX_BTF)?;
+        let rx_hash = btf_func_id(&btf, KFUNC_XDP_RX_HASH)
+            .and_then(|id| i32::try_from(id).ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, KFUNC_XDP_RX_HASH))?;
+        let insns = Self::assemble_rx_hash(map.0.fd(), rx_hash)?;
+        Self::new_with("xsk_rx_hash", &insns, c"Dual MIT/GPL", Some(ifindex))
+    }
+}
+
+/// How an XDP program runs on an interface
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum XdpMode {
+    /// In the NIC driver, the only mode where AF_XDP sockets may be zero-copy
+    Native,
+    /// On socket buffers, for drivers without XDP support
+    Generic,
+}
+
+impl Display for XdpMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            XdpMode::Native => write!(f, "native"),
+            XdpMode::Generic => write!(f, "generic"),
+        }
+    }
+}
+
+/// The attachment of an [`XdpProgram`] to an interface. The program is detached when the link
+/// is dropped.
+pub(super) struct XdpLink(OwnedFd);
+
+impl XdpLink {
+    pub(super) fn attach(
+        program: &XdpProgram,
+        ifindex: InterfaceIndex,
+        mode: XdpMode,
+    ) -> io::Result<Self> {
+        let attr = LinkCreateAttr {
+            prog_fd: raw_fd(&program.0),
+            target_ifindex: ifindex.to_u32(),
+            attach_type: BPF_XDP,
+            flags: match mode {
+                XdpMode::Native => XDP_FLAGS_DRV_MODE,
+                XdpMode::Generic => XDP_FLAGS_SKB_MODE,
+            },
+        };
+        bpf_create(BPF_LINK_CREATE, &attr).map(Self)
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    /// An interpreter of the programs assembled here, checking what they do rather than how they
+    /// are encoded. Memory is flat, with the context, the frame and its headroom, the stack and
+    /// the values of maps at fixed addresses, so that pointers fit the 32-bit fields of
+    /// `struct xdp_md`. Accesses to the headroom and past the frame are caught, as the verifier
+    /// would.
+    pub(in crate::drivers) struct Vm {
+        pub(in crate::drivers) regs: [u64; 11],
+        pub(in crate::drivers) mem: Vec<u8>,
+        /// The start of the metadata ahead of the frame, of the frame and its end
+        pub(in crate::drivers) data_meta: u64,
+        data: u64,
+        data_end: u64,
+        rx_queue_index: u32,
+    }
+
+    impl Vm {
+        const CTX: u64 = 0x100;
+        const HEADROOM: u64 = 0x1000;
+        const DATA: u64 = 0x1100;
+        /// The end of the room of the frame, and the bottom of the stack
+        const FRAME_END: u64 = 0x8000;
+        const STACK: u64 = 0x9000;
+        /// Where helpers put the values of maps they look up
+        pub(in crate::drivers) const VALUES: u64 = 0xa000;
+        const LEN: usize = 0xb000;
+        /// What the registers a call clobbers are set to, for their use to be caught
+        const CLOBBERED: u64 = 0xdead_0000_0000_0000;
+
+        pub(in crate::drivers) fn new(frame: &[u8], rx_queue_index: u32) -> Self {
+            let mut vm = Self {
+                regs: [0; 11],
+                mem: vec![0; Self::LEN],
+                data_meta: Self::DATA,
+                data: Self::DATA,
+                data_end: Self::DATA + frame.len() as u64,
+                rx_queue_index,
+            };
+            vm.write(Self::DATA, frame);
+            vm
+        }
+
+        fn range(&self, addr: u64, len: usize) -> std::ops::Range<usize> {
+            let end = addr + len as u64;
+            if addr < Self::FRAME_END && end > Self::HEADROOM {
+                assert!(
+                    addr >= self.data_meta && end <= self.data_end,
+                    "access to {addr:#x}..{end:#x}, out of the frame"
+                );
+            }
+            let start = usize::try_from(addr).unwrap();
+            assert!(
+                start + len <= self.mem.len(),
+                "access to {addr:#x}, out of memory"
+            );
+            start..start + len
+        }
+
+        pub(in crate::drivers) fn read(&self, addr: u64, len: usize) -> &[u8] {
+            &self.mem[self.range(addr, len)]
+        }
+
+        pub(in crate::drivers) fn write(&mut self, addr: u64, octets: &[u8]) {
+            let range = self.range(addr, octets.len());
+            self.mem[range].copy_from_slice(octets);
+        }
+
+        fn load(&self, addr: u64, len: usize) -> u64 {
+            let mut value = [0; 8];
+            let read = self.read(addr, len);
+            if cfg!(target_endian = "little") {
+                value[..len].copy_from_slice(read);
+            } else {
+                value[8 - len..].copy_from_slice(read);
+            }
+            u64::from_ne_bytes(value)
+        }
+
+        fn store(&mut self, addr: u64, len: usize, value: u64) {
+            let value = value.to_ne_bytes();
+            if cfg!(target_endian = "little") {
+                self.write(addr, &value[..len]);
+            } else {
+                self.write(addr, &value[8 - len..]);
+            }
+        }
+
+        /// The frame, from its metadata if any
+        pub(in crate::drivers) fn frame(&self) -> &[u8] {
+            self.read(
+                self.data_meta,
+                usize::try_from(self.data_end - self.data_meta).unwrap(),
+            )
+        }
+
+        /// Run `insns` on the frame, with `call` standing for the helpers and kernel functions,
+        /// given the call instruction. Returns the outcome of the program.
+        pub(in crate::drivers) fn run(
+            &mut self,
+            insns: &[BpfInsn],
+            mut call: impl FnMut(&mut Vm, BpfInsn) -> u64,
+        ) -> u64 {
+            let mut pc = 0;
+            self.regs = [0; 11];
+            self.regs[1] = Self::CTX;
+            self.regs[10] = Self::STACK;
+            loop {
+                self.sync_ctx();
+                let insn = insns[pc];
+                pc += 1;
+                let dst = usize::from(insn.regs & 0xf);
+                let src = usize::from(insn.regs >> 4);
+                let imm = i64::from(insn.imm).cast_unsigned();
+                let off = i64::from(insn.off).cast_unsigned();
+                let len = match insn.code & 0x18 {
+                    0x10 => 1,
+                    0x08 => 2,
+                    0x00 => 4,
+                    _ => 8,
+                };
+                let operand = if insn.code & 0x08 == 0 {
+                    imm
+                } else {
+                    self.regs[src]
+                };
+                match insn.code & 0x07 {
+                    // ALU64
+                    0x07 => {
+                        self.regs[dst] = match insn.code & 0xf0 {
+                            0x00 => self.regs[dst].wrapping_add(operand),
+                            0x50 => self.regs[dst] & operand,
+                            0x60 => self.regs[dst] << operand,
+                            0xb0 => operand,
+                            _ => panic!("unsupported ALU instruction {insn:?}"),
+                        };
+                    }
+                    // LDX, STX, ST and LD_IMM64
+                    0x01 => self.regs[dst] = self.load(self.regs[src].wrapping_add(off), len),
+                    0x03 => self.store(self.regs[dst].wrapping_add(off), len, self.regs[src]),
+                    0x02 => self.store(self.regs[dst].wrapping_add(off), len, imm),
+                    0x00 => {
+                        assert_eq!(insn.code, Asm::LD_IMM64);
+                        let high = u64::from(insns[pc].imm.cast_unsigned()) << 32;
+                        self.regs[dst] = u64::from(insn.imm.cast_unsigned()) | high;
+                        pc += 1;
+                    }
+                    // JMP
+                    0x05 => {
+                        let taken = match insn.code & 0xf0 {
+                            0x00 => true,
+                            0x10 => self.regs[dst] == operand,
+                            0x20 => self.regs[dst] > operand,
+                            0x50 => self.regs[dst] != operand,
+                            0xa0 => self.regs[dst] < operand,
+                            0x80 => {
+                                self.regs[0] = call(self, insn);
+                                self.regs[1..=5].fill(Self::CLOBBERED);
+                                false
+                            }
+                            0x90 => return self.regs[0],
+                            _ => panic!("unsupported JMP instruction {insn:?}"),
+                        };
+                        if taken {
+                            pc = pc.checked_add_signed(isize::from(insn.off)).unwrap();
+                        }
+                    }
+                    _ => panic!("unsupported instruction {insn:?}"),
+                }
+            }
+        }
+
+        /// Write the fields of `struct xdp_md`, as the frame may have moved
+        fn sync_ctx(&mut self) {
+            let fields = [self.data, self.data_end, self.data_meta];
+            for (field, value) in [XDP_MD_DATA, XDP_MD_DATA_END, XDP_MD_DATA_META]
+                .into_iter()
+                .zip(fields)
+            {
+                let value = u32::try_from(value).unwrap().to_ne_bytes();
+                self.write(Self::CTX + u64::from(field.cast_unsigned()), &value);
+            }
+            let queue = self.rx_queue_index.to_ne_bytes();
+            let field = u64::from(XDP_MD_RX_QUEUE_INDEX.cast_unsigned());
+            self.write(Self::CTX + field, &queue);
+        }
+    }
+
+    /// A BTF of an int, a prototype of one parameter, and a function `bpf_xdp_metadata_rx_hash`
+    fn btf() -> Vec<u8> {
+        let strings = b"\0int\0bpf_xdp_metadata_rx_hash\0";
+        let mut types = Vec::new();
+        let mut add = |name: u32, kind: u32, vlen: u32, size: u32, data: &[u32]| {
+            for word in [name, (kind << 24) | vlen, size].iter().chain(data) {
+                types.extend(word.to_ne_bytes());
+            }
+        };
+        add(1, 1, 0, 4, &[32]);
+        add(0, 13, 1, 1, &[0, 1]);
+        add(5, BTF_KIND_FUNC, 0, 2, &[]);
+        let len = |octets: &[u8]| u32::try_from(octets.len()).unwrap();
+        let mut btf = 0xeb9f_u16.to_ne_bytes().to_vec();
+        btf.extend([1, 0]);
+        for word in [24, 0, len(&types), len(&types), len(strings)] {
+            btf.extend(u32::to_ne_bytes(word));
+        }
+        btf.extend(types);
+        btf.extend(strings);
+        btf
+    }
+
+    #[test]
+    fn test_btf_func_id() {
+        let btf = btf();
+        assert_eq!(btf_func_id(&btf, KFUNC_XDP_RX_HASH), Some(3));
+        // only functions are found
+        assert_eq!(btf_func_id(&btf, "int"), None);
+        assert_eq!(btf_func_id(&btf, "bpf_xdp_metadata_rx_timestamp"), None);
+        // nor anything in a truncated or foreign BTF
+        assert_eq!(btf_func_id(&btf[..btf.len() - 40], KFUNC_XDP_RX_HASH), None);
+        assert_eq!(btf_func_id(&btf[1..], KFUNC_XDP_RX_HASH), None);
+    }
+
+    #[test]
+    fn test_asm_labels() {
+        let mut asm = Asm::default();
+        let (back, forward) = (asm.label(), asm.label());
+        asm.place(back)
+            .mov_imm(0, 0)
+            .jump_if(Cond::Eq, 0, 1, forward)
+            .load_map(1, 3)
+            .jump(back)
+            .place(forward)
+            .exit();
+        let insns = asm.finish().unwrap();
+        assert_eq!(insns.len(), 6);
+        // offsets are from the instruction following the jump
+        assert_eq!(insns[1], BpfInsn::new(0x15, 0, 0, 3, 1));
+        assert_eq!(insns[4], BpfInsn::new(0x05, 0, 0, -5, 0));
+
+        let mut asm = Asm::default();
+        let unplaced = asm.label();
+        asm.jump(unplaced).exit();
+        assert_eq!(
+            asm.finish().unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+
+    /// Run an AF_XDP program on a frame of queue 3, the kernel function giving the RX hash of
+    /// BTF id 1234 returning `rx_hash`, and return the action and the frame with its metadata
+    fn run_xsk(insns: &[BpfInsn], rx_hash: Result<u32, i32>) -> (u64, Vec<u8>) {
+        let mut vm = Vm::new(b"frame", 3);
+        let action = vm.run(insns, |vm, insn| match (insn.regs >> 4, insn.imm) {
+            (BPF_PSEUDO_KFUNC_CALL, 1234) => match rx_hash {
+                Ok(hash) => {
+                    vm.write(vm.regs[2], &hash.to_ne_bytes());
+                    0
+                }
+                Err(errno) => i64::from(errno).cast_unsigned(),
+            },
+            (0, BPF_FUNC_XDP_ADJUST_META) => {
+                vm.data_meta = vm.data_meta.wrapping_add(vm.regs[2]);
+                0
+            }
+            (0, BPF_FUNC_REDIRECT_MAP) => {
+                assert_eq!(vm.regs[1..=3], [7, 3, 2]);
+                4
+            }
+            _ => panic!("unexpected call {insn:?}"),
+        });
+        (action, vm.frame().to_vec())
+    }
+
+    #[test]
+    fn test_xsk_programs() {
+        let redirect = XdpProgram::assemble(7).unwrap();
+        assert_eq!(run_xsk(&redirect, Ok(1)), (4, b"frame".to_vec()));
+
+        let rx_hash = XdpProgram::assemble_rx_hash(7, 1234).unwrap();
+        let (action, frame) = run_xsk(&rx_hash, Ok(0xdead_beef));
+        assert_eq!(action, 4);
+        assert_eq!(frame[..4], 0xdead_beef_u32.to_ne_bytes());
+        assert_eq!(frame[4..8], RX_META_MAGIC.to_ne_bytes());
+        assert_eq!(&frame[8..], b"frame");
+        // frames the NIC has no hash of are redirected without metadata
+        assert_eq!(run_xsk(&rx_hash, Err(-95)), (4, b"frame".to_vec()));
+    }
+}
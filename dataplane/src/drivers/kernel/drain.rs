@@ -0,0 +1,98 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Graceful drain of the workers (see [`Drain`]).
+//!
+//! Once quiesced, the reader of each interface stops receiving, pumps empty batches through its
+//! pipeline until the stages holding packets (egress, waiting for next-hop resolution, and the
+//! `QoS` scheduler) release no more, and waits for the sockets of the worker to have sent all
+//! they were given. It then reports having drained and idles until the workers stop.
+
+use std::time::Duration;
+
+use lifecycle::{CancellationToken, TaskTracker};
+use tracing::info;
+
+use crate::drivers::{Drain, DriverError};
+
+/// What the readers of the workers watch to start draining, and report having drained to
+#[derive(Clone, Debug, Default)]
+pub(super) struct DrainSignal {
+    /// Cancelled to quiesce RX
+    pub(super) quiesce: CancellationToken,
+    /// Each reader holds a token of the tracker until it has drained
+    pub(super) drained: TaskTracker,
+}
+
+/// The handle to drain the workers of the kernel and `AF_XDP` drivers
+#[derive(Debug)]
+pub struct DrainHandle(DrainSignal);
+
+impl DrainHandle {
+    pub(super) fn new(signal: DrainSignal) -> Self {
+        Self(signal)
+    }
+}
+
+impl Drain for DrainHandle {
+    fn drain(&self, timeout: Duration) -> impl Future<Output = Result<(), DriverError>> + Send {
+        let signal = self.0.clone();
+        async move {
+            info!("Quiescing RX and draining the workers");
+            signal.quiesce.cancel();
+            signal.drained.close();
+            tokio::time::timeout(timeout, signal.drained.wait())
+                .await
+                .map_err(|_| DriverError::DrainTimeout(timeout))?;
+            info!("Workers drained");
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use concurrency::sync::Arc;
+    use concurrency::sync::atomic::{AtomicUsize, Ordering};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    #[tokio::test]
+    async fn test_drain_without_readers() {
+        let signal = DrainSignal::default();
+        let handle = DrainHandle::new(signal.clone());
+        handle.drain(TIMEOUT).await.unwrap();
+        assert!(signal.quiesce.is_cancelled());
+    }
+
+    #[tokio::test]
+    async fn test_drain_waits_for_readers() {
+        let signal = DrainSignal::default();
+        let handle = DrainHandle::new(signal.clone());
+        let done = Arc::new(AtomicUsize::new(0));
+        for _ in 0..3 {
+            let signal = signal.clone();
+            let drained = signal.drained.token();
+            let done = done.clone();
+            tokio::spawn(async move {
+                signal.quiesce.cancelled().await;
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                done.fetch_add(1, Ordering::Relaxed);
+                drop(drained);
+            });
+        }
+        handle.drain(TIMEOUT).await.unwrap();
+        assert_eq!(done.load(Ordering::Relaxed), 3);
+    }
+
+    #[tokio::test]
+    async fn test_drain_timeout() {
+        let signal = DrainSignal::default();
+        let handle = DrainHandle::new(signal.clone());
+        let _stuck = signal.drained.token();
+        let timeout = Duration::from_millis(10);
+        let result = handle.drain(timeout).await;
+        assert!(matches!(result, Err(DriverError::DrainTimeout(t)) if t == timeout));
+    }
+}
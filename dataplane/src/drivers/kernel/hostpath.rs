@@ -0,0 +1,315 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Metrics of the host path.
+//!
+//! Packets for the host, which the pipeline punts to the kernel, are written to the tap
+//! interfaces published by management. Conversely, packets of the host are injected by the kernel
+//! in the tap interfaces, where workers read them. A slow host path mostly shows as a slow control
+//! plane (ARP and ND resolution, BGP sessions, BFD), so the workers report:
+//! - `host_punted_packets`, the packets punted, per [`PuntClass`],
+//! - `host_injected_packets`, the packets injected by the kernel,
+//! - `host_inject_latency`, the time from the decision of the pipeline to punt a packet to its
+//!   delivery to the kernel,
+//! - `host_tap_queue_octets`, the octets punted on a tap interface but not yet consumed by the
+//!   kernel, per interface and worker, which is the occupancy of the ring of the tap interface,
+//! - `host_queue_drops`, the packets dropped because a queue was full: the send queue of the
+//!   socket of a tap interface for punted packets, its receive queue for injected packets.
+
+use std::io;
+use std::os::fd::RawFd;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use concurrency::sync::Arc;
+use lifecycle::CancellationToken;
+use metrics::{Counter, Gauge, Histogram};
+use net::buffer::test_buffer::TestBuffer;
+use net::eth::ethtype::EthType;
+use net::headers::{Transport, TryHeaders};
+use net::packet::Packet;
+use nix::libc;
+use stats::{MetricSpec, Register};
+use tokio::sync::Mutex;
+use tracing::debug;
+
+use crate::drivers::kernel::worker::{WorkerId, WorkerInterfaceWriter};
+
+/// Port of BGP
+const BGP_PORT: u16 = 179;
+
+/// Ports of BFD: single hop, echo and multihop
+const BFD_PORTS: [u16; 3] = [3784, 3785, 4784];
+
+/// The classes of the packets punted to the host
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum PuntClass {
+    Arp,
+    /// ICMP and ICMPv6, neighbor discovery included
+    Icmp,
+    Bgp,
+    Bfd,
+    Other,
+}
+
+impl PuntClass {
+    /// All the classes, in the order of their discriminants
+    const ALL: [PuntClass; 5] = [
+        PuntClass::Arp,
+        PuntClass::Icmp,
+        PuntClass::Bgp,
+        PuntClass::Bfd,
+        PuntClass::Other,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            PuntClass::Arp => "arp",
+            PuntClass::Icmp => "icmp",
+            PuntClass::Bgp => "bgp",
+            PuntClass::Bfd => "bfd",
+            PuntClass::Other => "other",
+        }
+    }
+
+    /// Classify a packet punted to the host
+    pub(super) fn of(packet: &Packet<TestBuffer>) -> Self {
+        let headers = packet.headers();
+        if headers
+            .eth()
+            .is_some_and(|eth| eth.ether_type() == EthType::ARP)
+        {
+            return PuntClass::Arp;
+        }
+        match headers.transport() {
+            Some(Transport::Icmp4(_) | Transport::Icmp6(_)) => PuntClass::Icmp,
+            Some(Transport::Tcp(tcp))
+                if u16::from(tcp.source()) == BGP_PORT
+                    || u16::from(tcp.destination()) == BGP_PORT =>
+            {
+                PuntClass::Bgp
+            }
+            Some(Transport::Udp(udp)) if BFD_PORTS.contains(&u16::from(udp.destination())) => {
+                PuntClass::Bfd
+            }
+            _ => PuntClass::Other,
+        }
+    }
+}
+
+/// The metrics of the host path of a worker
+pub(super) struct HostPathMetrics {
+    id: WorkerId,
+    punted: [Counter; PuntClass::ALL.len()],
+    injected: Counter,
+    inject_latency: Histogram,
+    punt_drops: Counter,
+    inject_drops: Counter,
+}
+
+impl HostPathMetrics {
+    /// How often the queues of the tap interfaces are sampled
+    const SAMPLING_PERIOD: Duration = Duration::from_secs(1);
+
+    pub(super) fn new(id: WorkerId) -> Self {
+        let spec = |metric: &str, unit, labels: &[(&str, &str)]| {
+            let labels = labels
+                .iter()
+                .map(|(key, value)| ((*key).to_string(), (*value).to_string()))
+                .collect();
+            MetricSpec::new(metric, unit, labels)
+        };
+        let drops = |queue| {
+            spec(
+                "host_queue_drops",
+                metrics::Unit::Count,
+                &[("queue", queue)],
+            )
+            .register()
+            .metric
+        };
+        Self {
+            id,
+            punted: PuntClass::ALL.map(|class| {
+                spec(
+                    "host_punted_packets",
+                    metrics::Unit::Count,
+                    &[("class", class.label())],
+                )
+                .register()
+                .metric
+            }),
+            injected: spec("host_injected_packets", metrics::Unit::Count, &[])
+                .register()
+                .metric,
+            inject_latency: spec("host_inject_latency", metrics::Unit::Seconds, &[])
+                .register()
+                .metric,
+            punt_drops: drops("punt"),
+            inject_drops: drops("inject"),
+        }
+    }
+
+    /// Count a packet punted to the host
+    pub(super) fn punted(&self, class: PuntClass) {
+        self.punted[class as usize].increment(1);
+    }
+
+    /// Record the delivery to the kernel of a packet the pipeline decided to punt at `decided`
+    pub(super) fn delivered(&self, decided: Instant) {
+        self.inject_latency.record(decided.elapsed());
+    }
+
+    /// Record the failure to punt a packet. Only failures due to a full queue are counted as
+    /// drops: others are reported by the caller.
+    pub(super) fn punt_failed(&self, error: &io::Error) {
+        let full = error.kind() == io::ErrorKind::WouldBlock
+            || error.raw_os_error() == Some(libc::ENOBUFS);
+        if full {
+            self.punt_drops.increment(1);
+        }
+    }
+
+    /// Count packets injected by the kernel
+    pub(super) fn injected(&self, count: usize) {
+        self.injected.increment(count as u64);
+    }
+
+    /// Sample the queues of the socket of a tap interface, until cancelled
+    pub(super) async fn monitor_tap(
+        self: Rc<Self>,
+        writer: Arc<Mutex<WorkerInterfaceWriter>>,
+        cancel: CancellationToken,
+    ) {
+        let if_name = writer.lock().await.if_name.clone();
+        let labels = vec![
+            ("interface".to_string(), if_name.clone()),
+            ("worker".to_string(), self.id.to_string()),
+        ];
+        let occupancy: Gauge =
+            MetricSpec::new("host_tap_queue_octets", metrics::Unit::Bytes, labels)
+                .register()
+                .metric;
+        let mut ticker = tokio::time::interval(Self::SAMPLING_PERIOD);
+        loop {
+            tokio::select! {
+                () = cancel.cancelled() => break,
+                _ = ticker.tick() => {}
+            }
+            let fd = writer.lock().await.raw_fd();
+            match queued_octets(fd) {
+                Ok(octets) => occupancy.set(f64::from(octets)),
+                Err(e) => debug!(worker = self.id, "Failed to sample queue of {if_name}: {e}"),
+            }
+            match receive_drops(fd) {
+                Ok(drops) => self.inject_drops.increment(u64::from(drops)),
+                Err(e) => debug!(worker = self.id, "Failed to sample drops of {if_name}: {e}"),
+            }
+        }
+        occupancy.set(0.0);
+    }
+}
+
+/// The octets sent on a packet socket which the device has not consumed yet
+#[allow(unsafe_code)] // SIOCOUTQ has no safe wrapper
+pub(super) fn queued_octets(fd: RawFd) -> io::Result<u32> {
+    let mut octets: libc::c_int = 0;
+    if unsafe { libc::ioctl(fd, libc::TIOCOUTQ, &raw mut octets) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(octets.unsigned_abs())
+}
+
+/// The packets a packet socket dropped because its receive queue was full, since the last call.
+/// Reading the statistics of the socket resets them.
+#[allow(unsafe_code)] // PACKET_STATISTICS has no safe wrapper
+fn receive_drops(fd: RawFd) -> io::Result<u32> {
+    let mut stats = libc::tpacket_stats {
+        tp_packets: 0,
+        tp_drops: 0,
+    };
+    #[allow(clippy::cast_possible_truncation)] // the size of the statistics is tiny
+    let mut len = size_of::<libc::tpacket_stats>() as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_PACKET,
+            libc::PACKET_STATISTICS,
+            (&raw mut stats).cast(),
+            &raw mut len,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(stats.tp_drops)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use net::packet::test_utils::{
+        IcmpEchoDirection, build_test_icmp4_echo, build_test_ipv4_packet,
+        build_test_tcp_ipv4_packet, build_test_udp_ipv4_packet,
+    };
+    use std::net::{Ipv4Addr, UdpSocket};
+    use std::os::fd::AsRawFd;
+
+    fn arp_request() -> Packet<TestBuffer> {
+        let mut frame = vec![0xff; 6];
+        frame.extend([0x02, 0, 0, 0, 0, 1]);
+        frame.extend(0x0806u16.to_be_bytes());
+        // ARP request for 10.0.0.2 from 10.0.0.1
+        frame.extend([0, 1, 0x08, 0, 6, 4, 0, 1]);
+        frame.extend([0x02, 0, 0, 0, 0, 1, 10, 0, 0, 1]);
+        frame.extend([0, 0, 0, 0, 0, 0, 10, 0, 0, 2]);
+        Packet::new(TestBuffer::from_raw_data(&frame)).unwrap()
+    }
+
+    #[test]
+    fn test_punt_class() {
+        assert_eq!(PuntClass::of(&arp_request()), PuntClass::Arp);
+
+        let echo = build_test_icmp4_echo(
+            Ipv4Addr::new(10, 0, 0, 1),
+            Ipv4Addr::new(10, 0, 0, 2),
+            1,
+            IcmpEchoDirection::Request,
+        )
+        .unwrap();
+        assert_eq!(PuntClass::of(&echo), PuntClass::Icmp);
+
+        let bgp = build_test_tcp_ipv4_packet("10.0.0.1", "10.0.0.2", 40000, BGP_PORT);
+        assert_eq!(PuntClass::of(&bgp), PuntClass::Bgp);
+        let bgp = build_test_tcp_ipv4_packet("10.0.0.1", "10.0.0.2", BGP_PORT, 40000);
+        assert_eq!(PuntClass::of(&bgp), PuntClass::Bgp);
+
+        for port in BFD_PORTS {
+            let bfd = build_test_udp_ipv4_packet("10.0.0.1", "10.0.0.2", 49152, port);
+            assert_eq!(PuntClass::of(&bfd), PuntClass::Bfd);
+        }
+
+        let other = build_test_udp_ipv4_packet("10.0.0.1", "10.0.0.2", BFD_PORTS[0], 53);
+        assert_eq!(PuntClass::of(&other), PuntClass::Other);
+        let other = build_test_tcp_ipv4_packet("10.0.0.1", "10.0.0.2", 40000, 22);
+        assert_eq!(PuntClass::of(&other), PuntClass::Other);
+        assert_eq!(
+            PuntClass::of(&build_test_ipv4_packet(64).unwrap()),
+            PuntClass::Other
+        );
+    }
+
+    #[test]
+    fn test_punt_class_order() {
+        for (index, class) in PuntClass::ALL.into_iter().enumerate() {
+            assert_eq!(class as usize, index);
+        }
+    }
+
+    #[test]
+    fn test_queued_octets() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        assert_eq!(queued_octets(socket.as_raw_fd()).unwrap(), 0);
+        assert!(queued_octets(-1).is_err());
+    }
+}
@@ -0,0 +1,121 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Attachment of the interfaces that come and go with the configuration.
+//!
+//! Management publishes the tap interfaces it creates for a configuration. The [`KifAttacher`]
+//! follows those updates and tells every worker to open (or close) its sockets on the interfaces
+//! that appeared (or disappeared), so that workers never need to be restarted.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+
+use lifecycle::CancellationToken;
+use net::interface::InterfaceName;
+use rtnetlink::Handle;
+use tokio::sync::{mpsc, watch};
+
+use crate::drivers::kernel::kif::{Kif, get_interface_ifindex};
+
+use tracing::{error, info, warn};
+
+/// A change in the set of interfaces a worker does packet IO on
+#[derive(Debug, Clone)]
+pub(super) enum KifEvent {
+    Attach(Kif),
+    Detach(Kif),
+}
+
+/// Attaches the interfaces published by management to the workers, and detaches them when they
+/// are removed from the configuration.
+pub(super) struct KifAttacher {
+    workers: Vec<mpsc::UnboundedSender<KifEvent>>,
+    /// Interfaces given in the command line, which the workers attach on start and never detach
+    fixed: BTreeSet<String>,
+    /// Interfaces attached on behalf of the configuration, by name
+    attached: BTreeMap<InterfaceName, Kif>,
+}
+
+impl KifAttacher {
+    pub(super) fn new(workers: Vec<mpsc::UnboundedSender<KifEvent>>, fixed: &[Kif]) -> Self {
+        Self {
+            workers,
+            fixed: fixed.iter().map(|kif| kif.name.clone()).collect(),
+            attached: BTreeMap::new(),
+        }
+    }
+
+    /// Send an event to all workers
+    fn notify(&self, event: &KifEvent) {
+        for (id, worker) in self.workers.iter().enumerate() {
+            if worker.send(event.clone()).is_err() {
+                warn!(worker = id, "Worker is gone, can't notify {event:?}");
+            }
+        }
+    }
+
+    /// Detach the interfaces no longer wanted and attach the new ones
+    async fn reconcile(&mut self, wanted: &BTreeSet<InterfaceName>, handle: &Handle) {
+        let gone: Vec<_> = self
+            .attached
+            .keys()
+            .filter(|name| !wanted.contains(*name))
+            .cloned()
+            .collect();
+        for name in gone {
+            if let Some(kif) = self.attached.remove(&name) {
+                info!("Detaching interface {name} (ifindex {})", kif.ifindex);
+                self.notify(&KifEvent::Detach(kif));
+            }
+        }
+
+        let interfaces = netdev::get_interfaces();
+        for name in wanted {
+            if self.attached.contains_key(name) || self.fixed.contains(name.as_ref()) {
+                continue;
+            }
+            let kif = match get_interface_ifindex(&interfaces, name.as_ref())
+                .and_then(|ifindex| Kif::new(ifindex, name.as_ref()))
+            {
+                Ok(kif) => kif,
+                Err(e) => {
+                    warn!("Can't attach interface {name}: {e}");
+                    continue;
+                }
+            };
+            if let Err(e) = kif.bring_up(handle).await {
+                error!("Can't attach interface {name}: {e}");
+                continue;
+            }
+            info!("Attaching interface {name} (ifindex {})", kif.ifindex);
+            self.notify(&KifEvent::Attach(kif.clone()));
+            self.attached.insert(name.clone(), kif);
+        }
+    }
+
+    /// Follow the updates of the interfaces to attach, until cancelled or until management is
+    /// gone. Dropping the attacher on return lets the workers know that no more events will come.
+    pub(super) async fn run(
+        mut self,
+        mut updates: watch::Receiver<BTreeSet<InterfaceName>>,
+        cancel: CancellationToken,
+    ) -> io::Result<()> {
+        let (connection, handle, _) = rtnetlink::new_connection()?;
+        let connection = tokio::spawn(connection);
+        loop {
+            let wanted = updates.borrow_and_update().clone();
+            self.reconcile(&wanted, &handle).await;
+            tokio::select! {
+                () = cancel.cancelled() => break,
+                changed = updates.changed() => {
+                    if changed.is_err() {
+                        info!("Interface updates closed");
+                        break;
+                    }
+                }
+            }
+        }
+        connection.abort();
+        Ok(())
+    }
+}
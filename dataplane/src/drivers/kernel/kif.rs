@@ -23,7 +23,7 @@ pub struct Kif {
 impl Kif {
     /// Create a kernel interface entry.
     #[allow(clippy::unnecessary_wraps)] // Eventually we'll do work that could return an error
-    fn new(ifindex: InterfaceIndex, name: &str) -> io::Result<Self> {
+    pub(super) fn new(ifindex: InterfaceIndex, name: &str) -> io::Result<Self> {
         let iface = Self {
             ifindex,
             name: name.to_owned(),
@@ -32,7 +32,7 @@ impl Kif {
         Ok(iface)
     }
     /// Bring the kernel interface represented by a [`Kif`] up and double check it went up.
-    async fn bring_up(&self, handle: &Handle) -> io::Result<()> {
+    pub(super) async fn bring_up(&self, handle: &Handle) -> io::Result<()> {
         info!("Bringing interface {} up ...", self.name);
         handle
             .link()
@@ -0,0 +1,481 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Detection of microbursts on the receive queues.
+//!
+//! A microburst fills the receive queue of a socket within a few milliseconds and is gone long
+//! before any metric sampled every few seconds can show it: only its drops remain. To catch them,
+//! the workers poll the drops of the receive queue of each of their packet sockets every
+//! [`IDLE_PERIOD`]. When the drops increase, the occupancy of the queue is sampled every
+//! [`SAMPLING_PERIOD`] for a [`WINDOW`], and the runs of samples where the queue is more than
+//! [`BURST_THRESHOLD`] percent full are recorded as bursts. The workers report:
+//! - `rx_queue_drops`, the packets dropped because a receive queue was full, per interface and
+//!   worker,
+//! - `rx_microbursts`, the bursts detected, per interface and worker,
+//! - `rx_microburst_octets`, the peak occupancy of the receive queue during a burst,
+//! - `rx_microburst_duration`, the duration of a burst.
+//!
+//! The most recent bursts are kept in a [`MicroburstLog`], which `show microbursts` displays.
+//! The receive queues of the `AF_XDP` sockets are not watched, nor are those of the tap
+//! interfaces of the host path, whose drops [`super::hostpath`] reports.
+
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::io;
+use std::os::fd::RawFd;
+use std::time::{Duration, Instant};
+
+use common::cliprovider::{CliDataProvider, Heading};
+use concurrency::sync::Arc;
+use lifecycle::CancellationToken;
+use metrics::{Counter, Histogram};
+use nix::libc;
+use stats::{MetricSpec, Register};
+use tokio::sync::Mutex;
+use tokio::time::MissedTickBehavior;
+use tracing::{debug, info};
+
+use crate::drivers::kernel::worker::{WorkerId, WorkerInterfaceWriter};
+
+/// How often the drops of a receive queue are polled, outside of sampling windows
+const IDLE_PERIOD: Duration = Duration::from_millis(10);
+
+/// How often the occupancy of a receive queue is sampled during a window
+const SAMPLING_PERIOD: Duration = Duration::from_millis(1);
+
+/// How long the occupancy of a receive queue is sampled once its drops increase
+const WINDOW: Duration = Duration::from_millis(100);
+
+/// Occupancy of a receive queue, in percent of its capacity, from which samples belong to a burst
+const BURST_THRESHOLD: u64 = 50;
+
+/// Number of bursts kept in a [`MicroburstLog`]
+const LOG_CAPACITY: usize = 64;
+
+/// The `SO_MEMINFO` socket option, which libc does not export
+const SO_MEMINFO: libc::c_int = 55;
+
+/// Number of values reported by `SO_MEMINFO`, and the indices of those used (`SK_MEMINFO_*`)
+const SK_MEMINFO_VARS: usize = 9;
+const SK_MEMINFO_RMEM_ALLOC: usize = 0;
+const SK_MEMINFO_RCVBUF: usize = 1;
+const SK_MEMINFO_DROPS: usize = 8;
+
+/// A sample of the receive queue of a socket
+#[derive(Clone, Copy, Debug)]
+struct QueueSample {
+    /// Octets held by the queue, overhead of the buffers included
+    octets: u32,
+    /// Octets the queue may hold before dropping packets
+    capacity: u32,
+    /// Packets dropped by the socket since it was opened
+    drops: u32,
+}
+
+impl QueueSample {
+    fn is_filled(&self) -> bool {
+        u64::from(self.octets) * 100 >= u64::from(self.capacity) * BURST_THRESHOLD
+    }
+}
+
+/// Sample the receive queue of a socket
+#[allow(unsafe_code)] // SO_MEMINFO has no safe wrapper
+fn sample_queue(fd: RawFd) -> io::Result<QueueSample> {
+    let mut meminfo = [0u32; SK_MEMINFO_VARS];
+    #[allow(clippy::cast_possible_truncation)] // the size of the values is tiny
+    let mut len = size_of_val(&meminfo) as libc::socklen_t;
+    let ret = unsafe {
+        libc::getsockopt(
+            fd,
+            libc::SOL_SOCKET,
+            SO_MEMINFO,
+            meminfo.as_mut_ptr().cast(),
+            &raw mut len,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(QueueSample {
+        octets: meminfo[SK_MEMINFO_RMEM_ALLOC],
+        capacity: meminfo[SK_MEMINFO_RCVBUF],
+        drops: meminfo[SK_MEMINFO_DROPS],
+    })
+}
+
+/// A burst detected on the receive queue of an interface
+#[derive(Clone, Debug)]
+struct Microburst {
+    /// When the burst ended
+    ended: Instant,
+    worker: WorkerId,
+    interface: String,
+    /// Peak occupancy of the queue, in octets
+    peak_octets: u32,
+    /// Capacity of the queue, in octets
+    capacity: u32,
+    /// Time the queue stayed filled, from the first sample of the window at most
+    duration: Duration,
+    /// Packets dropped during the burst, those which triggered the sampling included
+    drops: u32,
+}
+
+/// A burst which is not over yet
+struct OpenBurst {
+    started: Instant,
+    peak_octets: u32,
+    capacity: u32,
+    drops: u32,
+}
+
+/// Tracks the bursts in the samples of a window
+struct BurstTracker {
+    /// Drops seen by the last sample
+    drops: u32,
+    open: Option<OpenBurst>,
+    bursts: Vec<Microburst>,
+}
+
+impl BurstTracker {
+    /// Start tracking bursts. `drops` are the drops of the queue before they increased, so that
+    /// the drops which triggered the sampling count towards the first burst.
+    fn new(drops: u32) -> Self {
+        Self {
+            drops,
+            open: None,
+            bursts: vec![],
+        }
+    }
+
+    fn sample(&mut self, at: Instant, sample: QueueSample, worker: WorkerId, interface: &str) {
+        let drops = sample.drops.wrapping_sub(self.drops);
+        self.drops = sample.drops;
+        match (&mut self.open, sample.is_filled()) {
+            (Some(open), true) => {
+                open.peak_octets = open.peak_octets.max(sample.octets);
+                open.drops = open.drops.saturating_add(drops);
+            }
+            (None, true) => {
+                self.open = Some(OpenBurst {
+                    started: at,
+                    peak_octets: sample.octets,
+                    capacity: sample.capacity,
+                    drops,
+                });
+            }
+            (Some(_), false) => self.close(at, worker, interface),
+            (None, false) => {}
+        }
+    }
+
+    /// End the open burst, if any
+    fn close(&mut self, at: Instant, worker: WorkerId, interface: &str) {
+        if let Some(open) = self.open.take() {
+            self.bursts.push(Microburst {
+                ended: at,
+                worker,
+                interface: interface.to_string(),
+                peak_octets: open.peak_octets,
+                capacity: open.capacity,
+                duration: at.duration_since(open.started),
+                drops: open.drops,
+            });
+        }
+    }
+}
+
+/// The metrics of the receive queue of an interface, for a worker
+struct RxQueueMetrics {
+    drops: Counter,
+    bursts: Counter,
+    peak_octets: Histogram,
+    duration: Histogram,
+}
+
+impl RxQueueMetrics {
+    fn new(id: WorkerId, if_name: &str) -> Self {
+        let spec = |metric: &str, unit| {
+            let labels = vec![
+                ("interface".to_string(), if_name.to_string()),
+                ("worker".to_string(), id.to_string()),
+            ];
+            MetricSpec::new(metric, unit, labels)
+        };
+        Self {
+            drops: spec("rx_queue_drops", metrics::Unit::Count)
+                .register()
+                .metric,
+            bursts: spec("rx_microbursts", metrics::Unit::Count)
+                .register()
+                .metric,
+            peak_octets: spec("rx_microburst_octets", metrics::Unit::Bytes)
+                .register()
+                .metric,
+            duration: spec("rx_microburst_duration", metrics::Unit::Seconds)
+                .register()
+                .metric,
+        }
+    }
+}
+
+/// Watch the receive queue of the packet socket of an interface for microbursts, recording them
+/// in `log`, until cancelled
+pub(super) async fn monitor_rx_queue(
+    id: WorkerId,
+    writer: Arc<Mutex<WorkerInterfaceWriter>>,
+    log: MicroburstLog,
+    cancel: CancellationToken,
+) {
+    // the writer, held until cancelled, keeps the socket open
+    let (if_name, fd) = {
+        let writer = writer.lock().await;
+        (writer.if_name.clone(), writer.raw_fd())
+    };
+    let metrics = RxQueueMetrics::new(id, &if_name);
+    let mut drops = None;
+    let mut ticker = tokio::time::interval(IDLE_PERIOD);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            () = cancel.cancelled() => break,
+            _ = ticker.tick() => {}
+        }
+        let sample = match sample_queue(fd) {
+            Ok(sample) => sample,
+            Err(e) => {
+                debug!(
+                    worker = id,
+                    "Failed to sample receive queue of {if_name}: {e}"
+                );
+                continue;
+            }
+        };
+        let Some(last) = drops.replace(sample.drops) else {
+            continue;
+        };
+        if sample.drops == last {
+            continue;
+        }
+        let mut tracker = BurstTracker::new(last);
+        tracker.sample(Instant::now(), sample, id, &if_name);
+        let mut sampler = tokio::time::interval(SAMPLING_PERIOD);
+        sampler.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let window = Instant::now() + WINDOW;
+        while Instant::now() < window {
+            tokio::select! {
+                () = cancel.cancelled() => break,
+                _ = sampler.tick() => {}
+            }
+            if let Ok(sample) = sample_queue(fd) {
+                tracker.sample(Instant::now(), sample, id, &if_name);
+            }
+        }
+        tracker.close(Instant::now(), id, &if_name);
+        metrics
+            .drops
+            .increment(u64::from(tracker.drops.wrapping_sub(last)));
+        drops = Some(tracker.drops);
+        for burst in tracker.bursts {
+            info!(
+                worker = id,
+                "Microburst on {if_name}: {} octets queued at peak for {:?}, {} drops",
+                burst.peak_octets,
+                burst.duration,
+                burst.drops
+            );
+            metrics.bursts.increment(1);
+            metrics.peak_octets.record(f64::from(burst.peak_octets));
+            metrics.duration.record(burst.duration);
+            log.record(burst);
+        }
+        ticker.reset();
+    }
+}
+
+/// The most recent microbursts detected by the workers
+#[derive(Clone, Default)]
+pub struct MicroburstLog(Arc<concurrency::sync::Mutex<VecDeque<Microburst>>>);
+
+impl MicroburstLog {
+    fn record(&self, burst: Microburst) {
+        let mut bursts = self.0.lock();
+        if bursts.len() == LOG_CAPACITY {
+            bursts.pop_front();
+        }
+        bursts.push_back(burst);
+    }
+}
+
+macro_rules! MICROBURST_FMT {
+    ($age:expr, $interface:expr, $worker:expr, $peak:expr, $fill:expr, $duration:expr, $drops:expr) => {
+        format_args!(
+            " {:>10} {:<16} {:>6} {:>12} {:>6} {:>14} {:>8}",
+            $age, $interface, $worker, $peak, $fill, $duration, $drops
+        )
+    };
+}
+
+impl Display for MicroburstLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{}",
+            MICROBURST_FMT!(
+                "age (s)",
+                "interface",
+                "worker",
+                "peak octets",
+                "fill",
+                "duration (us)",
+                "drops"
+            )
+        )?;
+        let now = Instant::now();
+        // most recent first
+        for burst in self.0.lock().iter().rev() {
+            let fill = u64::from(burst.peak_octets) * 100 / u64::from(burst.capacity.max(1));
+            writeln!(
+                f,
+                "{}",
+                MICROBURST_FMT!(
+                    now.duration_since(burst.ended).as_secs(),
+                    burst.interface,
+                    burst.worker,
+                    burst.peak_octets,
+                    format!("{fill}%"),
+                    burst.duration.as_micros(),
+                    burst.drops
+                )
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl CliDataProvider for MicroburstLog {
+    fn provide(&self) -> String {
+        let count = self.0.lock().len();
+        Heading(format!("Microbursts on receive queues ({count})")).to_string() + &self.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::UdpSocket;
+    use std::os::fd::AsRawFd;
+
+    const CAPACITY: u32 = 1000;
+
+    fn sample(octets: u32, drops: u32) -> QueueSample {
+        QueueSample {
+            octets,
+            capacity: CAPACITY,
+            drops,
+        }
+    }
+
+    fn burst(interface: &str) -> Microburst {
+        Microburst {
+            ended: Instant::now(),
+            worker: 0,
+            interface: interface.to_string(),
+            peak_octets: 900,
+            capacity: CAPACITY,
+            duration: Duration::from_millis(3),
+            drops: 7,
+        }
+    }
+
+    #[test]
+    fn test_queue_filled() {
+        assert!(!sample(0, 0).is_filled());
+        assert!(!sample(CAPACITY / 2 - 1, 0).is_filled());
+        assert!(sample(CAPACITY / 2, 0).is_filled());
+        assert!(sample(CAPACITY, 0).is_filled());
+        // no overflow with large queues
+        let large = QueueSample {
+            octets: u32::MAX,
+            capacity: u32::MAX,
+            drops: 0,
+        };
+        assert!(large.is_filled());
+    }
+
+    #[test]
+    fn test_burst_tracker() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut tracker = BurstTracker::new(10);
+
+        // a first burst of 3ms, whose drops triggered the sampling
+        tracker.sample(at(0), sample(600, 15), 1, "eth0");
+        tracker.sample(at(1), sample(950, 18), 1, "eth0");
+        tracker.sample(at(2), sample(700, 18), 1, "eth0");
+        tracker.sample(at(3), sample(100, 18), 1, "eth0");
+        tracker.sample(at(4), sample(0, 18), 1, "eth0");
+
+        // a second burst, still open at the end of the window
+        tracker.sample(at(5), sample(800, 20), 1, "eth0");
+        tracker.close(at(7), 1, "eth0");
+        tracker.close(at(8), 1, "eth0");
+
+        assert_eq!(tracker.drops, 20);
+        let bursts: Vec<_> = tracker
+            .bursts
+            .iter()
+            .map(|b| (b.peak_octets, b.duration, b.drops))
+            .collect();
+        assert_eq!(
+            bursts,
+            [
+                (950, Duration::from_millis(3), 8),
+                (800, Duration::from_millis(2), 2)
+            ]
+        );
+        assert!(
+            tracker
+                .bursts
+                .iter()
+                .all(|b| b.worker == 1 && b.interface == "eth0")
+        );
+    }
+
+    #[test]
+    fn test_burst_tracker_drops_wrap() {
+        let mut tracker = BurstTracker::new(u32::MAX - 1);
+        let now = Instant::now();
+        tracker.sample(now, sample(CAPACITY, 2), 0, "eth0");
+        tracker.close(now, 0, "eth0");
+        assert_eq!(tracker.bursts[0].drops, 4);
+    }
+
+    #[test]
+    fn test_microburst_log() {
+        let log = MicroburstLog::default();
+        for i in 0..LOG_CAPACITY + 2 {
+            log.record(burst(&format!("eth{i}")));
+        }
+        {
+            let bursts = log.0.lock();
+            assert_eq!(bursts.len(), LOG_CAPACITY);
+            assert_eq!(bursts.front().unwrap().interface, "eth2");
+        }
+        let shown = log.provide();
+        assert!(shown.contains(&format!("({LOG_CAPACITY})")));
+        // most recent first
+        let last = format!("eth{}", LOG_CAPACITY + 1);
+        assert!(shown.find(&last).unwrap() < shown.find("eth2 ").unwrap());
+        assert!(shown.contains("90%"));
+    }
+
+    #[test]
+    fn test_sample_queue() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let sample = sample_queue(socket.as_raw_fd()).unwrap();
+        assert_eq!(sample.octets, 0);
+        assert_eq!(sample.drops, 0);
+        assert!(sample.capacity > 0);
+        assert!(sample_queue(-1).is_err());
+    }
+}
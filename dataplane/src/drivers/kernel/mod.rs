@@ -12,27 +12,59 @@
     clippy::panic
 )]
 
+mod drain;
 mod fanout;
+mod hostpath;
+mod hotplug;
 mod kif;
+mod microburst;
+mod prefilter;
+mod ring;
+mod watchdog;
 mod worker;
 
+use std::collections::BTreeSet;
+
+use acl_filter::PrefilterRules;
 use concurrency::sync::Arc;
 use concurrency::thread;
 #[allow(unused_imports)] // used under loom/shuttle backends
 use concurrency::thread::BuilderExt;
 use lifecycle::Subsystem;
 use net::buffer::test_buffer::TestBuffer;
+use net::interface::InterfaceName;
 use pipeline::DynPipeline;
+use tokio::sync::{mpsc, watch};
 use tracectl::trace_target;
 #[allow(unused)]
 use tracing::{debug, error, info, trace, warn};
 
 use super::DriverError;
-use kif::{Kif, bring_kifs_up};
-use worker::Worker;
+use super::af_xdp::XdpPorts;
+use super::batch::BatchLimits;
+pub use drain::DrainHandle;
+use drain::DrainSignal;
+use hotplug::{KifAttacher, KifEvent};
+pub(crate) use kif::{Kif, bring_kifs_up, get_interfaces};
+pub use microburst::MicroburstLog;
+use prefilter::Prefilter;
+pub use watchdog::Watchdog;
+use worker::{Worker, thread_name};
 
 trace_target!("kernel-driver", LevelFilter::INFO, &["driver"]);
 
+/// How the workers receive the frames of the interfaces given to the driver. Those of the tap
+/// interfaces of the host path are received with `recv` on packet sockets.
+#[derive(Clone)]
+pub(crate) enum RxMode {
+    /// With `recv` on packet sockets
+    Recv,
+    /// Through the receive rings of packet sockets, which tell the RX hash of the frames
+    Ring,
+    /// With `AF_XDP` sockets, for the interfaces of the ports
+    Xdp(Arc<XdpPorts>),
+}
+
 /// AF_PACKET-based kernel driver. Spawns N workers with symmetric-hash
 /// fanout and per-worker pipelines.
 pub struct DriverKernel;
@@ -40,38 +72,90 @@ pub struct DriverKernel;
 #[allow(clippy::cast_possible_truncation)]
 impl DriverKernel {
     /// Spawn `num_workers` worker threads into `scope`, each with its own
-    /// pipeline. Bails on the first spawn failure; workers that did spawn
-    /// drain via the scope join.
+    /// pipeline, and a heartbeat registered with `watchdog`. Bails on the
+    /// first spawn failure; workers that did spawn drain via the scope join.
+    /// Returns, along with the handles, the channels to attach or detach
+    /// interfaces to the workers.
+    #[allow(clippy::type_complexity, clippy::too_many_arguments)]
     fn spawn_workers_scoped<'scope>(
         scope: &'scope thread::Scope<'scope, '_>,
         workers_subsystem: &Subsystem,
         num_workers: usize,
         setup_pipeline: &Arc<dyn Send + Sync + Fn() -> DynPipeline<TestBuffer>>,
         interfaces: &[Kif],
-    ) -> Result<Vec<thread::ScopedJoinHandle<'scope, Result<(), std::io::Error>>>, std::io::Error>
-    {
+        watchdog: &mut Watchdog,
+        rx: &RxMode,
+        drain: &DrainSignal,
+        microbursts: &MicroburstLog,
+        batch: BatchLimits,
+    ) -> Result<
+        (
+            Vec<thread::ScopedJoinHandle<'scope, Result<(), std::io::Error>>>,
+            Vec<mpsc::UnboundedSender<KifEvent>>,
+        ),
+        std::io::Error,
+    > {
         info!("Spawning {num_workers} workers");
-        (0..num_workers)
-            .map(|wid| {
-                let builder = thread::Builder::new().name(format!("dp-worker-{wid}"));
-                Worker::new(wid, num_workers, setup_pipeline, workers_subsystem.clone())
-                    .start(scope, builder, interfaces)
-            })
-            .collect()
+        let mut handles = Vec::with_capacity(num_workers);
+        let mut events = Vec::with_capacity(num_workers);
+        for wid in 0..num_workers {
+            let (events_tx, events_rx) = mpsc::unbounded_channel();
+            let builder = thread::Builder::new().name(thread_name(wid));
+            let handle = Worker::new(
+                wid,
+                num_workers,
+                setup_pipeline,
+                workers_subsystem.clone(),
+                watchdog.heartbeat(wid),
+                rx.clone(),
+                drain.clone(),
+                microbursts.clone(),
+                batch,
+            )
+            .start(scope, builder, interfaces, events_rx)?;
+            handles.push(handle);
+            events.push(events_tx);
+        }
+        Ok((handles, events))
     }
 
     /// Spawn worker threads + supervisor into `scope`. The scope joins
     /// all driver threads on closure return.
     ///
+    /// Besides the interfaces in `args`, workers do packet IO on the tap
+    /// interfaces published by management in `tap_interfaces`, which get
+    /// attached and detached as the configuration changes.
+    ///
+    /// The `watchdog` watches the workers, and reports those that stop
+    /// making progress. The microbursts the workers detect on their
+    /// receive queues are recorded in `microbursts`. The batches of
+    /// packets the workers receive are sized within `batch`.
+    ///
+    /// If given the rules published by management in `prefilter`, an
+    /// XDP program attached to the interfaces in `args` drops the
+    /// frames they tell are unwanted before they reach the workers.
+    /// The frames of the interfaces in `args` are received through
+    /// rings shared with the kernel if `rx_ring`, which tell their RX
+    /// hash.
+    ///
+    /// Returns the handle to drain the workers before they stop.
+    ///
     /// # Errors
     /// Returns [`DriverError`] on interface setup or thread spawn failure.
+    #[allow(clippy::too_many_arguments)]
     pub fn start<'scope>(
         scope: &'scope thread::Scope<'scope, '_>,
         workers_subsystem: &Subsystem,
         args: impl IntoIterator<Item = impl AsRef<str> + Clone>,
         num_workers: usize,
         setup_pipeline: &Arc<dyn Send + Sync + Fn() -> DynPipeline<TestBuffer>>,
-    ) -> Result<(), DriverError> {
+        tap_interfaces: watch::Receiver<BTreeSet<InterfaceName>>,
+        watchdog: Watchdog,
+        microbursts: MicroburstLog,
+        batch: BatchLimits,
+        prefilter: Option<watch::Receiver<PrefilterRules>>,
+        rx_ring: bool,
+    ) -> Result<DrainHandle, DriverError> {
         // A current_thread runtime built inside another tokio runtime
         // panics; catch nesting in debug.
         debug_assert!(
@@ -80,21 +164,106 @@ impl DriverKernel {
         );
 
         info!("Collecting interfaces from config");
-        let interfaces = kif::get_interfaces(args)?;
+        let interfaces = get_interfaces(args)?;
 
         tokio::runtime::Builder::new_current_thread()
             .enable_all()
             .build()?
             .block_on(bring_kifs_up(interfaces.as_slice()))?;
 
-        let worker_handles = Self::spawn_workers_scoped(
+        let prefilter = match prefilter {
+            Some(rules) => Some((Prefilter::attach(interfaces.as_slice())?, rules)),
+            None => None,
+        };
+
+        let drain = Self::run(
+            scope,
+            workers_subsystem,
+            interfaces,
+            num_workers,
+            setup_pipeline,
+            tap_interfaces,
+            watchdog,
+            if rx_ring { RxMode::Ring } else { RxMode::Recv },
+            microbursts,
+            batch,
+        )?;
+
+        // The pre-filter follows the rules published by management, and
+        // is detached when it stops, along with the workers.
+        if let Some((prefilter, rules)) = prefilter {
+            let prefilter_rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()?;
+            let cancel = workers_subsystem.cancel_token();
+            let prefilter_builder =
+                thread::Builder::new().name("kernel-driver-prefilter".to_string());
+            prefilter_builder.spawn_scoped(scope, move || {
+                prefilter_rt.block_on(prefilter.run(rules, cancel));
+            })?;
+        }
+
+        Ok(drain)
+    }
+
+    /// Spawn the workers doing packet IO on `interfaces`, which must be up, the hot-attach of the
+    /// tap interfaces and the supervisor into `scope`. Workers receive the frames of the interfaces
+    /// as told by `rx`. The microbursts of those they receive with `recv` get recorded in
+    /// `microbursts`. Batches are sized within `batch`. Returns the handle to drain the workers.
+    ///
+    /// # Errors
+    /// Returns [`DriverError`] on thread spawn failure.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn run<'scope>(
+        scope: &'scope thread::Scope<'scope, '_>,
+        workers_subsystem: &Subsystem,
+        interfaces: Vec<Kif>,
+        num_workers: usize,
+        setup_pipeline: &Arc<dyn Send + Sync + Fn() -> DynPipeline<TestBuffer>>,
+        tap_interfaces: watch::Receiver<BTreeSet<InterfaceName>>,
+        mut watchdog: Watchdog,
+        rx: RxMode,
+        microbursts: MicroburstLog,
+        batch: BatchLimits,
+    ) -> Result<DrainHandle, DriverError> {
+        let drain = DrainSignal::default();
+        let (worker_handles, worker_events) = Self::spawn_workers_scoped(
             scope,
             workers_subsystem,
             num_workers,
             setup_pipeline,
             interfaces.as_slice(),
+            &mut watchdog,
+            &rx,
+            &drain,
+            &microbursts,
+            batch,
         )?;
 
+        // The attacher follows the tap interfaces published by management
+        // and attaches (or detaches) them to all workers while they run.
+        let attacher = KifAttacher::new(worker_events, interfaces.as_slice());
+        let attacher_rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let cancel = workers_subsystem.cancel_token();
+        let attacher_builder = thread::Builder::new().name("kernel-driver-hotplug".to_string());
+        attacher_builder.spawn_scoped(scope, move || {
+            if let Err(e) = attacher_rt.block_on(attacher.run(tap_interfaces, cancel)) {
+                error!("Interface hot-attach failed: {e}");
+            }
+            info!("Interface hot-attach stopped");
+        })?;
+
+        let watchdog_rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        let cancel = workers_subsystem.cancel_token();
+        let watchdog_builder = thread::Builder::new().name("kernel-driver-watchdog".to_string());
+        watchdog_builder.spawn_scoped(scope, move || {
+            watchdog_rt.block_on(watchdog.run(cancel));
+        })?;
+
         // The supervisor just joins-and-logs; worker fatal reporting is
         // handled by the `ExitGuard` inside each worker thread.
         let supervisor_builder =
@@ -109,8 +278,10 @@ impl DriverKernel {
                 }
             }
             info!("All workers joined");
+            // detach the XDP programs, if any, once the workers are gone
+            drop(rx);
         })?;
 
-        Ok(())
+        Ok(DrainHandle::new(drain))
     }
 }
@@ -0,0 +1,754 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! XDP pre-filter of the kernel driver.
+//!
+//! The workers receive, with packet sockets, copies of all the frames the kernel stack gets,
+//! including those the pipeline would only drop. The pre-filter is an XDP program attached to the
+//! interfaces of the driver, dropping such frames before they reach the kernel stack, as compiled
+//! by mgmt into [`PrefilterRules`]:
+//!
+//! - IPv4 and IPv6 frames from a spoofed source, looked up by address in LPM tries;
+//! - VXLAN frames, on IPv4 with or without options or on IPv6, from a denied inner IPv4 or IPv6
+//!   source, looked up by VNI and address in other LPM tries.
+//!
+//! Other frames are passed, including those tagged with a VLAN, IPv4 fragments but the first,
+//! and IPv6 packets with extension headers. The tries are updated as mgmt publishes the rules of
+//! new configs. The program counts its verdicts per CPU, which are summed up into the
+//! `kernel_prefilter_packets` metric, by verdict.
+
+use std::collections::BTreeSet;
+use std::io;
+use std::net::IpAddr;
+use std::time::Duration;
+
+use acl_filter::PrefilterRules;
+use lifecycle::CancellationToken;
+use lpm::prefix::Prefix;
+use metrics::{Counter, Unit};
+use net::vxlan::Vni;
+use stats::{MetricSpec, Register};
+use tokio::sync::watch;
+use tracing::{debug, error, info, warn};
+
+use super::Kif;
+use crate::drivers::bpf::{
+    Asm, BPF_F_NO_PREALLOC, BPF_FUNC_MAP_LOOKUP_ELEM, BPF_MAP_TYPE_LPM_TRIE,
+    BPF_MAP_TYPE_PERCPU_ARRAY, BpfInsn, BpfMap, Cond, Label, Size, XDP_DROP, XDP_MD_DATA,
+    XDP_MD_DATA_END, XDP_PASS, XdpLink, XdpMode, XdpProgram,
+};
+
+/// The maximum number of spoofed sources, of each IP version
+const MAX_SPOOFED: u32 = 1024;
+/// The maximum number of denied sources, of all VNIs, of each IP version
+const MAX_DENIED: u32 = 65536;
+/// The period of the export of the counters
+const COUNTERS_PERIOD: Duration = Duration::from_secs(5);
+
+/// The offsets of the fields the program looks at, from the start of their headers
+const ETH_TYPE: i16 = 12;
+const ETH_LEN: i16 = 14;
+const IPV4_FRAG: i16 = 6;
+const IPV4_PROTO: i16 = 9;
+const IPV4_SRC: i16 = 12;
+const IPV4_LEN: i16 = 20;
+const IPV6_NEXT: i16 = 6;
+const IPV6_SRC: i16 = 8;
+const IPV6_LEN: i16 = 40;
+/// The offsets of the fields of VXLAN frames, from the start of the UDP header: the destination
+/// port, the VNI, the ethertype of the inner frame and its IP header
+const UDP_DST_PORT: i16 = 2;
+const VXLAN_VNI: i16 = 8 + 4;
+const INNER_ETH_TYPE: i16 = 8 + 8 + 12;
+const INNER_IP: i16 = 8 + 8 + 14;
+/// The IP protocol of UDP
+const UDP: i32 = 17;
+/// Where the program keeps, on its stack, the keys it looks up in the tries and the index of the
+/// counter of its verdict
+const KEY: i16 = -24;
+const COUNTER: i16 = -28;
+
+/// The verdicts of the program, indexing its counters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+enum Verdict {
+    Passed = 0,
+    Spoofed = 1,
+    Denied = 2,
+}
+
+const VERDICTS: [Verdict; 3] = [Verdict::Passed, Verdict::Spoofed, Verdict::Denied];
+
+impl Verdict {
+    fn label(self) -> &'static str {
+        match self {
+            Verdict::Passed => "passed",
+            Verdict::Spoofed => "spoofed",
+            Verdict::Denied => "denied",
+        }
+    }
+}
+
+/// The `N` octets of `addr`, if of the IP version of that length
+fn octets<const N: usize>(addr: IpAddr) -> Option<[u8; N]> {
+    match addr {
+        IpAddr::V4(addr) => addr.octets().as_slice().try_into().ok(),
+        IpAddr::V6(addr) => addr.octets().as_slice().try_into().ok(),
+    }
+}
+
+/// A key of the tries of the spoofed sources (`struct bpf_lpm_trie_key`, with an IPv4 or IPv6
+/// address of `N` octets)
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct SpoofedKey<const N: usize> {
+    prefixlen: u32,
+    addr: [u8; N],
+}
+
+impl<const N: usize> SpoofedKey<N> {
+    fn new(prefix: &Prefix) -> Option<Self> {
+        Some(Self {
+            prefixlen: u32::from(prefix.length()),
+            addr: octets(prefix.network())?,
+        })
+    }
+}
+
+/// A key of the tries of the denied sources: the VNI, as in the VXLAN header with the reserved
+/// byte following it cleared, and then the IPv4 or IPv6 address
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct DeniedKey<const N: usize> {
+    prefixlen: u32,
+    vni: [u8; 4],
+    addr: [u8; N],
+}
+
+impl<const N: usize> DeniedKey<N> {
+    fn new((vni, prefix): &(Vni, Prefix)) -> Option<Self> {
+        let [_, high, mid, low] = vni.as_u32().to_be_bytes();
+        Some(Self {
+            prefixlen: 32 + u32::from(prefix.length()),
+            vni: [high, mid, low, 0],
+            addr: octets(prefix.network())?,
+        })
+    }
+}
+
+/// The descriptors of the maps of the program
+#[derive(Clone, Copy)]
+struct MapFds {
+    spoofed: i32,
+    spoofed6: i32,
+    denied: i32,
+    denied6: i32,
+    counters: i32,
+}
+
+/// Pass the frame unless the packet pointer `ptr` is followed by `len` octets of it. Uses `r1`,
+/// and expects the end of the frame in `r8`.
+fn check_len(asm: &mut Asm, ptr: u8, len: i16, pass: Label) {
+    asm.mov(1, ptr)
+        .add_imm(1, i32::from(len))
+        .jump_if_reg(Cond::Gt, 1, 8, pass);
+}
+
+/// Copy `len` octets, a multiple of 4, from `ptr + from` to the stack at `to`
+fn copy(asm: &mut Asm, ptr: u8, from: i16, to: i16, len: i16) {
+    for offset in (0..len).step_by(4) {
+        asm.load(Size::U32, 1, ptr, from + offset)
+            .store(Size::U32, 10, to + offset, 1);
+    }
+}
+
+/// Jump to `found` if the key on the stack is in the map of descriptor `fd`
+fn lookup(asm: &mut Asm, fd: i32, found: Label) {
+    asm.load_map(1, fd)
+        .mov(2, 10)
+        .add_imm(2, i32::from(KEY))
+        .call(BPF_FUNC_MAP_LOOKUP_ELEM)
+        .jump_if(Cond::Ne, 0, 0, found);
+}
+
+/// The program, looking sources up in the maps of `fds`. Keeps the context and then the action
+/// in `r6`, the frame in `r7`, its end in `r8`, and the header it is at in `r9`.
+fn assemble(fds: MapFds) -> io::Result<Vec<BpfInsn>> {
+    let mut asm = Asm::default();
+    let [ipv6, udp, inner_ipv6, pass, spoofed, denied, count, out] = [(); 8].map(|()| asm.label());
+    // pass frames other than IPv4 and IPv6, including those tagged with a VLAN
+    asm.mov(6, 1)
+        .load(Size::U32, 7, 6, XDP_MD_DATA)
+        .load(Size::U32, 8, 6, XDP_MD_DATA_END);
+    check_len(&mut asm, 7, ETH_LEN, pass);
+    asm.load(Size::U8, 1, 7, ETH_TYPE)
+        .load(Size::U8, 2, 7, ETH_TYPE + 1)
+        .mov(9, 7)
+        .add_imm(9, i32::from(ETH_LEN))
+        .jump_if(Cond::Eq, 1, 0x86, ipv6)
+        .jump_if(Cond::Ne, 1, 0x08, pass)
+        .jump_if(Cond::Ne, 2, 0x00, pass);
+
+    // IPv4: drop frames from spoofed sources
+    check_len(&mut asm, 9, IPV4_LEN, pass);
+    asm.store_imm(Size::U32, 10, KEY, 32);
+    copy(&mut asm, 9, IPV4_SRC, KEY + 4, 4);
+    lookup(&mut asm, fds.spoofed, spoofed);
+    // pass fragments but the first and protocols other than UDP, and skip the options
+    asm.load(Size::U8, 1, 9, IPV4_FRAG)
+        .and_imm(1, 0x1f)
+        .jump_if(Cond::Ne, 1, 0, pass)
+        .load(Size::U8, 1, 9, IPV4_FRAG + 1)
+        .jump_if(Cond::Ne, 1, 0, pass)
+        .load(Size::U8, 1, 9, IPV4_PROTO)
+        .jump_if(Cond::Ne, 1, UDP, pass)
+        .load(Size::U8, 1, 9, 0)
+        .and_imm(1, 0x0f)
+        .jump_if(Cond::Lt, 1, 5, pass)
+        .lsh_imm(1, 2)
+        .add(9, 1)
+        .jump(udp);
+
+    // IPv6: drop frames from spoofed sources, and pass protocols other than UDP
+    asm.place(ipv6).jump_if(Cond::Ne, 2, 0xdd, pass);
+    check_len(&mut asm, 9, IPV6_LEN, pass);
+    asm.store_imm(Size::U32, 10, KEY, 128);
+    copy(&mut asm, 9, IPV6_SRC, KEY + 4, 16);
+    lookup(&mut asm, fds.spoofed6, spoofed);
+    asm.load(Size::U8, 1, 9, IPV6_NEXT)
+        .jump_if(Cond::Ne, 1, UDP, pass)
+        .add_imm(9, i32::from(IPV6_LEN));
+
+    // UDP: pass frames other than VXLAN, with the VNI in the keys of the denied sources
+    asm.place(udp);
+    check_len(&mut asm, 9, INNER_IP, pass);
+    asm.load(Size::U8, 1, 9, UDP_DST_PORT)
+        .jump_if(Cond::Ne, 1, 0x12, pass)
+        .load(Size::U8, 1, 9, UDP_DST_PORT + 1)
+        .jump_if(Cond::Ne, 1, 0xb5, pass);
+    copy(&mut asm, 9, VXLAN_VNI, KEY + 4, 4);
+    asm.store_imm(Size::U8, 10, KEY + 7, 0)
+        .load(Size::U8, 1, 9, INNER_ETH_TYPE)
+        .load(Size::U8, 2, 9, INNER_ETH_TYPE + 1)
+        .jump_if(Cond::Eq, 1, 0x86, inner_ipv6)
+        .jump_if(Cond::Ne, 1, 0x08, pass)
+        .jump_if(Cond::Ne, 2, 0x00, pass);
+    // drop frames from denied inner IPv4 sources
+    check_len(&mut asm, 9, INNER_IP + IPV4_SRC + 4, pass);
+    asm.store_imm(Size::U32, 10, KEY, 64);
+    copy(&mut asm, 9, INNER_IP + IPV4_SRC, KEY + 8, 4);
+    lookup(&mut asm, fds.denied, denied);
+    asm.jump(pass);
+    // and from denied inner IPv6 sources
+    asm.place(inner_ipv6).jump_if(Cond::Ne, 2, 0xdd, pass);
+    check_len(&mut asm, 9, INNER_IP + IPV6_SRC + 16, pass);
+    asm.store_imm(Size::U32, 10, KEY, 160);
+    copy(&mut asm, 9, INNER_IP + IPV6_SRC, KEY + 8, 16);
+    lookup(&mut asm, fds.denied6, denied);
+
+    // the verdicts: r6 = action, with the index of the counter on the stack
+    for (label, action, verdict) in [
+        (pass, XDP_PASS, Verdict::Passed),
+        (spoofed, XDP_DROP, Verdict::Spoofed),
+        (denied, XDP_DROP, Verdict::Denied),
+    ] {
+        asm.place(label)
+            .mov_imm(6, action)
+            .store_imm(Size::U32, 10, COUNTER, verdict as i32)
+            .jump(count);
+    }
+    // increment the counter of the verdict, for this CPU, and return the action
+    asm.place(count)
+        .load_map(1, fds.counters)
+        .mov(2, 10)
+        .add_imm(2, i32::from(COUNTER))
+        .call(BPF_FUNC_MAP_LOOKUP_ELEM)
+        .jump_if(Cond::Eq, 0, 0, out)
+        .load(Size::U64, 1, 0, 0)
+        .add_imm(1, 1)
+        .store(Size::U64, 0, 0, 1)
+        .place(out)
+        .mov(0, 6)
+        .exit();
+    asm.finish()
+}
+
+/// The number of possible CPUs, which per-CPU maps have a value for
+fn possible_cpus() -> io::Result<usize> {
+    let possible = std::fs::read_to_string("/sys/devices/system/cpu/possible")?;
+    let parse = |cpu: &str| {
+        cpu.parse::<usize>()
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    };
+    let mut cpus = 0;
+    for range in possible.trim().split(',') {
+        let (first, last) = range.split_once('-').unwrap_or((range, range));
+        cpus += (parse(last)? + 1).saturating_sub(parse(first)?);
+    }
+    Ok(cpus)
+}
+
+/// An LPM trie of sources, by keys `K`, and the keys it has
+struct Trie<K> {
+    map: BpfMap,
+    keys: BTreeSet<K>,
+}
+
+impl<K: Ord + Copy> Trie<K> {
+    fn new(max_entries: u32, name: &str) -> io::Result<Self> {
+        let map = BpfMap::new(
+            BPF_MAP_TYPE_LPM_TRIE,
+            size_of::<K>(),
+            4,
+            max_entries,
+            BPF_F_NO_PREALLOC,
+            name,
+        )?;
+        Ok(Self {
+            map,
+            keys: BTreeSet::new(),
+        })
+    }
+
+    /// Make the keys of the trie those of `keys`, returning the number of keys that could not be
+    /// added or removed. Keys are added before others are removed, so that no source is let
+    /// through in between.
+    fn sync(&mut self, keys: BTreeSet<K>) -> usize {
+        let mut failures = 0;
+        for key in keys.difference(&self.keys) {
+            if let Err(e) = self.map.update(key, &1u32) {
+                debug!("Failed to add a source to the pre-filter: {e}");
+                failures += 1;
+            }
+        }
+        for key in self.keys.difference(&keys) {
+            if let Err(e) = self.map.delete(key) {
+                debug!("Failed to remove a source from the pre-filter: {e}");
+                failures += 1;
+            }
+        }
+        self.keys = keys;
+        failures
+    }
+}
+
+/// The pre-filter attached to the interfaces of the kernel driver. The program is detached when
+/// dropped.
+pub(crate) struct Prefilter {
+    spoofed: Trie<SpoofedKey<4>>,
+    spoofed6: Trie<SpoofedKey<16>>,
+    denied: Trie<DeniedKey<4>>,
+    denied6: Trie<DeniedKey<16>>,
+    counters: BpfMap,
+    program: XdpProgram,
+    cpus: usize,
+    metrics: [Counter; 3],
+    /// Keep the program attached
+    links: Vec<XdpLink>,
+}
+
+impl Prefilter {
+    /// Create the maps and load the program
+    fn load() -> io::Result<Self> {
+        let spoofed = Trie::new(MAX_SPOOFED, "spoofed")?;
+        let spoofed6 = Trie::new(MAX_SPOOFED, "spoofed6")?;
+        let denied = Trie::new(MAX_DENIED, "denied")?;
+        let denied6 = Trie::new(MAX_DENIED, "denied6")?;
+        let counters = BpfMap::new(BPF_MAP_TYPE_PERCPU_ARRAY, 4, 8, 3, 0, "prefilter_stats")?;
+        let insns = assemble(MapFds {
+            spoofed: spoofed.map.fd(),
+            spoofed6: spoofed6.map.fd(),
+            denied: denied.map.fd(),
+            denied6: denied6.map.fd(),
+            counters: counters.fd(),
+        })?;
+        let program = XdpProgram::new("prefilter", &insns)
+            .inspect_err(|e| error!("Failed to load the pre-filter XDP program: {e}"))?;
+        let metrics = VERDICTS.map(|verdict| {
+            let labels = vec![("verdict".to_string(), verdict.label().to_string())];
+            MetricSpec::new("kernel_prefilter_packets", Unit::Count, labels)
+                .register()
+                .metric
+        });
+        Ok(Self {
+            spoofed,
+            spoofed6,
+            denied,
+            denied6,
+            counters,
+            program,
+            cpus: possible_cpus()?,
+            metrics,
+            links: Vec::new(),
+        })
+    }
+
+    /// Load the program, and attach it to `kifs`, natively if possible
+    pub(crate) fn attach(kifs: &[Kif]) -> io::Result<Self> {
+        let mut prefilter = Self::load()?;
+        for kif in kifs {
+            let program = &prefilter.program;
+            let link = XdpLink::attach(program, kif.ifindex, XdpMode::Native).or_else(|e| {
+                warn!(
+                    "Failed to attach the native pre-filter to {}: {e}. Falling back to generic XDP",
+                    kif.name
+                );
+                XdpLink::attach(program, kif.ifindex, XdpMode::Generic)
+            });
+            prefilter.links.push(link.inspect_err(|e| {
+                error!("Failed to attach the pre-filter to {}: {e}", kif.name);
+            })?);
+            info!("Attached the pre-filter to {}", kif.name);
+        }
+        Ok(prefilter)
+    }
+
+    /// Have the program drop the frames from the sources of `rules`, instead of those before
+    fn update(&mut self, rules: &PrefilterRules) {
+        let failures = self
+            .spoofed
+            .sync(rules.spoofed().filter_map(SpoofedKey::new).collect())
+            + self
+                .spoofed6
+                .sync(rules.spoofed().filter_map(SpoofedKey::new).collect())
+            + self
+                .denied
+                .sync(rules.denied().filter_map(DeniedKey::new).collect())
+            + self
+                .denied6
+                .sync(rules.denied().filter_map(DeniedKey::new).collect());
+        if failures > 0 {
+            warn!("Failed to update {failures} sources of the pre-filter");
+        }
+        info!(
+            "Pre-filter drops frames from {} spoofed sources, and {} denied sources",
+            self.spoofed.keys.len() + self.spoofed6.keys.len(),
+            self.denied.keys.len() + self.denied6.keys.len()
+        );
+    }
+
+    /// The counter of `verdict`, summed up over all CPUs
+    fn counter(&self, verdict: Verdict) -> io::Result<u64> {
+        let mut values = vec![0u64; self.cpus];
+        self.counters
+            .lookup(&(verdict as u32), values.as_mut_slice())?;
+        Ok(values.iter().sum())
+    }
+
+    /// Sum the counters of the verdicts of all CPUs up, into the metrics
+    fn export_counters(&self) -> io::Result<()> {
+        for (verdict, metric) in VERDICTS.iter().zip(&self.metrics) {
+            metric.absolute(self.counter(*verdict)?);
+        }
+        Ok(())
+    }
+
+    /// Follow the rules published by mgmt, and export the counters, until cancelled
+    pub(crate) async fn run(
+        mut self,
+        mut rules: watch::Receiver<PrefilterRules>,
+        cancel: CancellationToken,
+    ) {
+        let mut ticker = tokio::time::interval(COUNTERS_PERIOD);
+        self.update(&rules.borrow_and_update());
+        loop {
+            tokio::select! {
+                () = cancel.cancelled() => break,
+                changed = rules.changed() => {
+                    if changed.is_err() {
+                        info!("No more rules for the pre-filter");
+                        break;
+                    }
+                    self.update(&rules.borrow_and_update());
+                }
+                _ = ticker.tick() => {
+                    if let Err(e) = self.export_counters() {
+                        warn!("Failed to read the counters of the pre-filter: {e}");
+                    }
+                }
+            }
+        }
+        info!("Pre-filter stopped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use caps::Capability;
+    use fixin::wrap;
+    use test_utils::with_caps;
+
+    use super::*;
+    use crate::drivers::bpf::tests::Vm;
+
+    const FDS: MapFds = MapFds {
+        spoofed: 1,
+        spoofed6: 2,
+        denied: 3,
+        denied6: 4,
+        counters: 5,
+    };
+
+    fn prefix(prefix: &str) -> Prefix {
+        prefix.parse().unwrap()
+    }
+
+    fn vni(vni: u32) -> Vni {
+        Vni::new_checked(vni).unwrap()
+    }
+
+    /// The keys of the tries: spoofed IPv4 and IPv6 sources, and denied IPv4 and IPv6 sources of
+    /// VNI 42
+    fn spoofed() -> BTreeSet<SpoofedKey<4>> {
+        [SpoofedKey::new(&prefix("10.1.0.0/16")).unwrap()].into()
+    }
+
+    fn spoofed6() -> BTreeSet<SpoofedKey<16>> {
+        [SpoofedKey::new(&prefix("fd00::/8")).unwrap()].into()
+    }
+
+    fn denied() -> BTreeSet<DeniedKey<4>> {
+        [DeniedKey::new(&(vni(42), prefix("192.168.1.0/24"))).unwrap()].into()
+    }
+
+    fn denied6() -> BTreeSet<DeniedKey<16>> {
+        [DeniedKey::new(&(vni(42), prefix("2001:db8::/32"))).unwrap()].into()
+    }
+
+    fn eth(ethertype: u16, payload: &[u8]) -> Vec<u8> {
+        let mut frame = vec![0; 12];
+        frame.extend(ethertype.to_be_bytes());
+        frame.extend(payload);
+        frame
+    }
+
+    /// An IPv4 packet from `src`, with `options` octets of options and fragment flags and offset
+    /// `frag`
+    fn ipv4(src: [u8; 4], proto: u8, options: u8, frag: u16, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x45 + options / 4, 0, 0, 0, 0, 0];
+        packet.extend(frag.to_be_bytes());
+        packet.extend([64, proto, 0, 0]);
+        packet.extend(src);
+        packet.extend([10, 0, 0, 1]);
+        packet.extend(vec![1; usize::from(options)]);
+        packet.extend(payload);
+        packet
+    }
+
+    fn ipv6(src: &str, next: u8, payload: &[u8]) -> Vec<u8> {
+        let src: std::net::Ipv6Addr = src.parse().unwrap();
+        let mut packet = vec![0x60, 0, 0, 0, 0, 0, next, 64];
+        packet.extend(src.octets());
+        packet.extend([0xfd; 16]);
+        packet.extend(payload);
+        packet
+    }
+
+    /// A UDP datagram to `port`, of VXLAN header of `vni`, and then an inner frame of ethertype
+    /// and packet `inner`
+    fn vxlan(port: u16, vni: u32, inner: &[u8]) -> Vec<u8> {
+        let mut udp = vec![0x80, 0];
+        udp.extend(port.to_be_bytes());
+        udp.extend([0, 0, 0, 0, 0x08, 0, 0, 0]);
+        udp.extend(&vni.to_be_bytes()[1..]);
+        udp.push(0xff);
+        udp.extend([0; 12]);
+        udp.extend(inner);
+        udp
+    }
+
+    /// Frames, and the verdicts of the program on them
+    fn frames() -> Vec<(Vec<u8>, Verdict)> {
+        let inner4 = |src| [&[0x08, 0x00][..], &ipv4(src, 6, 0, 0, b"inner")].concat();
+        let inner6 = |src| [&[0x86, 0xdd][..], &ipv6(src, 6, b"inner")].concat();
+        let tcp4 = |src| eth(0x0800, &ipv4(src, 6, 0, 0, b"tcp"));
+        let udp4 =
+            |options, frag, udp: &[u8]| eth(0x0800, &ipv4([10, 2, 0, 1], 17, options, frag, udp));
+        let udp6 = |udp: &[u8]| eth(0x86dd, &ipv6("fe80::1", 17, udp));
+        let denied = vxlan(4789, 42, &inner4([192, 168, 1, 5]));
+        let denied6 = vxlan(4789, 42, &inner6("2001:db8::5"));
+        let mut ihl4 = udp4(0, 0, &denied);
+        ihl4[14] = 0x44;
+        vec![
+            (b"short".to_vec(), Verdict::Passed),
+            (eth(0x8100, &[0; 64]), Verdict::Passed),
+            (tcp4([10, 1, 2, 3]), Verdict::Spoofed),
+            (tcp4([10, 2, 0, 1]), Verdict::Passed),
+            (udp4(0, 0, &denied), Verdict::Denied),
+            (udp4(8, 0, &denied), Verdict::Denied),
+            (udp4(40, 0, &denied), Verdict::Denied),
+            (udp4(0, 0x2000, &denied), Verdict::Denied),
+            // other VNIs, sources and ports, and fragments but the first are passed
+            (
+                udp4(8, 0, &vxlan(4789, 43, &inner4([192, 168, 1, 5]))),
+                Verdict::Passed,
+            ),
+            (
+                udp4(8, 0, &vxlan(4789, 42, &inner4([192, 168, 2, 5]))),
+                Verdict::Passed,
+            ),
+            (
+                udp4(0, 0, &vxlan(4790, 42, &inner4([192, 168, 1, 5]))),
+                Verdict::Passed,
+            ),
+            (udp4(0, 0x2001, &denied), Verdict::Passed),
+            // as are truncated and invalid frames
+            (udp4(0, 0, &denied[..40]), Verdict::Passed),
+            (tcp4([10, 1, 2, 3])[..26].to_vec(), Verdict::Passed),
+            (ihl4, Verdict::Passed),
+            // IPv6, outer and inner
+            (eth(0x86dd, &ipv6("fd12::1", 6, b"tcp")), Verdict::Spoofed),
+            (
+                eth(0x86dd, &ipv6("2001:db8::1", 6, b"tcp")),
+                Verdict::Passed,
+            ),
+            (udp6(&denied), Verdict::Denied),
+            (udp6(&denied6), Verdict::Denied),
+            (
+                udp6(&vxlan(4789, 42, &inner6("2001:db9::5"))),
+                Verdict::Passed,
+            ),
+            (
+                udp6(&vxlan(4789, 43, &inner6("2001:db8::5"))),
+                Verdict::Passed,
+            ),
+            (udp6(&denied6[..50]), Verdict::Passed),
+            (
+                eth(0x86dd, &ipv6("fd12::1", 17, &[])[..30]),
+                Verdict::Passed,
+            ),
+            // IPv6 packets with extension headers are passed
+            (eth(0x86dd, &ipv6("fe80::1", 0, &denied)), Verdict::Passed),
+        ]
+    }
+
+    /// Tell if `key`, of its maximum length, matches any of the `(prefixlen, data)` of `trie`
+    fn lpm_match(trie: &[(u32, Vec<u8>)], key: &[u8]) -> bool {
+        trie.iter().any(|(prefixlen, data)| {
+            let bits = usize::try_from(*prefixlen).unwrap();
+            (0..bits).all(|bit| {
+                let mask = 0x80 >> (bit % 8);
+                data[bit / 8] & mask == key[bit / 8] & mask
+            })
+        })
+    }
+
+    /// Run the program on `frame`, with the maps of [`spoofed`], [`spoofed6`], [`denied`] and
+    /// [`denied6`], and return its action and the counter it incremented
+    fn run(insns: &[BpfInsn], frame: &[u8]) -> (u64, Verdict) {
+        let tries = [
+            (
+                4,
+                spoofed()
+                    .iter()
+                    .map(|k| (k.prefixlen, k.addr.to_vec()))
+                    .collect::<Vec<_>>(),
+            ),
+            (
+                16,
+                spoofed6()
+                    .iter()
+                    .map(|k| (k.prefixlen, k.addr.to_vec()))
+                    .collect(),
+            ),
+            (
+                8,
+                denied()
+                    .iter()
+                    .map(|k| (k.prefixlen, [&k.vni[..], &k.addr].concat()))
+                    .collect(),
+            ),
+            (
+                20,
+                denied6()
+                    .iter()
+                    .map(|k| (k.prefixlen, [&k.vni[..], &k.addr].concat()))
+                    .collect(),
+            ),
+        ];
+        let mut vm = Vm::new(frame, 0);
+        let action = vm.run(insns, |vm, _| {
+            // the program only looks up maps, given by descriptor
+            let fd = usize::try_from(vm.regs[1]).unwrap();
+            if fd == 5 {
+                let index = u32::from_ne_bytes(vm.read(vm.regs[2], 4).try_into().unwrap());
+                return Vm::VALUES + 8 * u64::from(index);
+            }
+            let (len, trie) = &tries[fd - 1];
+            // the key is given with its length, that of the address, or of the VNI and address
+            let prefixlen = u32::from_ne_bytes(vm.read(vm.regs[2], 4).try_into().unwrap());
+            assert_eq!(usize::try_from(prefixlen).unwrap(), len * 8);
+            let key = vm.read(vm.regs[2] + 4, *len);
+            if lpm_match(trie, key) { Vm::VALUES } else { 0 }
+        });
+        let counted: Vec<_> = VERDICTS
+            .into_iter()
+            .filter(|verdict| {
+                let counter = vm.read(Vm::VALUES + 8 * u64::from(*verdict as u32), 8);
+                u64::from_ne_bytes(counter.try_into().unwrap()) == 1
+            })
+            .collect();
+        assert_eq!(counted.len(), 1, "{counted:?} counted");
+        (action, counted[0])
+    }
+
+    #[test]
+    fn test_prefilter_verdicts() {
+        let insns = assemble(FDS).unwrap();
+        for (index, (frame, verdict)) in frames().into_iter().enumerate() {
+            let action = match verdict {
+                Verdict::Passed => XDP_PASS,
+                Verdict::Spoofed | Verdict::Denied => XDP_DROP,
+            };
+            let expected = (u64::try_from(action).unwrap(), verdict);
+            assert_eq!(run(&insns, &frame), expected, "frame {index}");
+        }
+    }
+
+    #[test]
+    fn test_prefilter_keys() {
+        let key = SpoofedKey::<4>::new(&prefix("10.1.0.0/16")).unwrap();
+        assert_eq!((key.prefixlen, key.addr), (16, [10, 1, 0, 0]));
+        assert!(SpoofedKey::<16>::new(&prefix("10.1.0.0/16")).is_none());
+        assert!(SpoofedKey::<4>::new(&prefix("fd00::/8")).is_none());
+        let key = DeniedKey::<16>::new(&(vni(0x12_3456), prefix("2001:db8::/32"))).unwrap();
+        assert_eq!(key.prefixlen, 64);
+        assert_eq!(key.vni, [0x12, 0x34, 0x56, 0]);
+        assert_eq!(key.addr[..4], [0x20, 0x01, 0x0d, 0xb8]);
+        assert_eq!(size_of::<SpoofedKey<16>>(), 20);
+        assert_eq!(size_of::<DeniedKey<4>>(), 12);
+    }
+
+    #[test]
+    #[n_vm::in_vm]
+    #[wrap(with_caps([Capability::CAP_SYS_ADMIN]))]
+    fn test_prefilter_test_run() {
+        let mut prefilter = Prefilter::load().unwrap();
+        assert_eq!(prefilter.spoofed.sync(spoofed()), 0);
+        assert_eq!(prefilter.spoofed6.sync(spoofed6()), 0);
+        assert_eq!(prefilter.denied.sync(denied()), 0);
+        assert_eq!(prefilter.denied6.sync(denied6()), 0);
+        let mut expected = [0; 3];
+        // the kernel only runs programs on frames with an Ethernet header
+        for (frame, verdict) in frames().into_iter().filter(|(frame, _)| frame.len() >= 14) {
+            let action = match verdict {
+                Verdict::Passed => XDP_PASS,
+                Verdict::Spoofed | Verdict::Denied => XDP_DROP,
+            };
+            let outcome = prefilter.program.test_run(&frame).unwrap();
+            assert_eq!(outcome, action.cast_unsigned(), "{frame:x?}");
+            expected[verdict as usize] += 1;
+        }
+        for verdict in VERDICTS {
+            assert_eq!(
+                prefilter.counter(verdict).unwrap(),
+                expected[verdict as usize]
+            );
+        }
+        // sources removed are no longer dropped
+        assert_eq!(prefilter.spoofed.sync(BTreeSet::new()), 0);
+        let frame = eth(0x0800, &ipv4([10, 1, 2, 3], 6, 0, 0, b"tcp"));
+        let outcome = prefilter.program.test_run(&frame).unwrap();
+        assert_eq!(outcome, XDP_PASS.cast_unsigned());
+    }
+}
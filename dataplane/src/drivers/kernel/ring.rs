@@ -0,0 +1,318 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! `TPACKET_V3` receive rings of the packet sockets of the kernel driver.
+//!
+//! `recv` on a packet socket gives the octets of a frame only. The kernel writes the frames it
+//! receives in the blocks of a ring shared with the socket instead, with the RX hash of their
+//! socket buffers, computed by the NIC or by the kernel. A block is handed over once full, or once
+//! [`BLOCK_TIMEOUT_MS`] elapsed since its first frame. Frames are copied out of the ring as they
+//! are read, and blocks given back once all their frames are read.
+
+use std::io;
+use std::os::fd::{AsFd, AsRawFd};
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use memmap2::{MmapOptions, MmapRaw};
+use nix::libc;
+
+/// `TPACKET_V3`, the version of the rings
+const TPACKET_V3: libc::c_int = 2;
+/// `TP_FT_REQ_FILL_RXHASH`, asking the kernel for the RX hash of the frames
+const TP_FT_REQ_FILL_RXHASH: u32 = 1;
+/// The status of a block, telling who owns it
+const TP_STATUS_KERNEL: u32 = 0;
+const TP_STATUS_USER: u32 = 1;
+
+/// The blocks of a ring. A block holds up to a jumbo frame, and the ring as much as the receive
+/// buffers of the packet sockets of the driver.
+const BLOCK_SIZE: u32 = 1 << 16;
+const BLOCK_COUNT: u32 = 64;
+/// The size of the frames, which the kernel only checks with `TPACKET_V3`
+const FRAME_SIZE: u32 = 2048;
+/// The time after which the kernel hands a block over even if not full, the latency of the ring
+/// under low load
+const BLOCK_TIMEOUT_MS: u32 = 1;
+
+/// The offsets of the fields of the header of a block (`struct tpacket_block_desc`)
+const BLOCK_STATUS: usize = 8;
+const BLOCK_NUM_PKTS: usize = 12;
+const BLOCK_FIRST_PKT: usize = 16;
+/// The offsets of the fields of the header of a frame (`struct tpacket3_hdr`), and its length
+const FRAME_NEXT: usize = 0;
+const FRAME_SNAPLEN: usize = 12;
+const FRAME_MAC: usize = 24;
+const FRAME_RXHASH: usize = 28;
+const FRAME_HDR_LEN: usize = 48;
+
+/// `struct tpacket_req3`
+#[repr(C)]
+struct TpacketReq3 {
+    block_size: u32,
+    block_nr: u32,
+    frame_size: u32,
+    frame_nr: u32,
+    retire_blk_tov: u32,
+    sizeof_priv: u32,
+    feature_req_word: u32,
+}
+
+#[allow(unsafe_code)] // packet sockets have no safe wrapper for rings
+fn set_option<T>(fd: &impl AsRawFd, option: libc::c_int, value: &T) -> io::Result<()> {
+    let len = libc::socklen_t::try_from(size_of::<T>()).unwrap_or_else(|_| unreachable!());
+    let ret = unsafe {
+        libc::setsockopt(
+            fd.as_raw_fd(),
+            libc::SOL_PACKET,
+            option,
+            std::ptr::from_ref(value).cast(),
+            len,
+        )
+    };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn u32_at(octets: &[u8], offset: usize) -> Option<u32> {
+    let bytes = octets.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_ne_bytes(bytes.try_into().ok()?))
+}
+
+fn u16_at(octets: &[u8], offset: usize) -> Option<u16> {
+    let bytes = octets.get(offset..offset.checked_add(2)?)?;
+    Some(u16::from_ne_bytes(bytes.try_into().ok()?))
+}
+
+/// The frame at `offset` in `block`, with its RX hash if the kernel has one, and the offset of
+/// the next frame. `None` if the header of the frame is out of the block.
+fn frame_at(block: &[u8], offset: usize) -> Option<(&[u8], Option<u32>, usize)> {
+    let header = block.get(offset..offset.checked_add(FRAME_HDR_LEN)?)?;
+    let next = usize::try_from(u32_at(header, FRAME_NEXT)?).ok()?;
+    let len = usize::try_from(u32_at(header, FRAME_SNAPLEN)?).ok()?;
+    let start = offset.checked_add(usize::from(u16_at(header, FRAME_MAC)?))?;
+    let frame = block.get(start..start.checked_add(len)?)?;
+    // the kernel tells no hash as 0
+    let rx_hash = u32_at(header, FRAME_RXHASH).filter(|hash| *hash != 0);
+    Some((frame, rx_hash, offset.checked_add(next)?))
+}
+
+/// The receive ring of a packet socket
+pub(super) struct RxRing {
+    map: MmapRaw,
+    /// The block to read next
+    block: u32,
+    /// The frames of the block left to read, once begun, and the offset of the next one
+    left: u32,
+    offset: usize,
+}
+
+impl RxRing {
+    /// Set up the receive ring of the packet socket `fd`, which must not be part of a fanout
+    /// group yet
+    pub(super) fn new(fd: &impl AsFd) -> io::Result<Self> {
+        let fd = fd.as_fd();
+        set_option(&fd, libc::PACKET_VERSION, &TPACKET_V3)?;
+        set_option(
+            &fd,
+            libc::PACKET_RX_RING,
+            &TpacketReq3 {
+                block_size: BLOCK_SIZE,
+                block_nr: BLOCK_COUNT,
+                frame_size: FRAME_SIZE,
+                frame_nr: BLOCK_SIZE / FRAME_SIZE * BLOCK_COUNT,
+                retire_blk_tov: BLOCK_TIMEOUT_MS,
+                sizeof_priv: 0,
+                feature_req_word: TP_FT_REQ_FILL_RXHASH,
+            },
+        )?;
+        let map = MmapOptions::new()
+            .len(BLOCK_SIZE as usize * BLOCK_COUNT as usize)
+            .map_raw(fd.as_raw_fd())?;
+        Ok(Self::from_map(map))
+    }
+
+    fn from_map(map: MmapRaw) -> Self {
+        Self {
+            map,
+            block: 0,
+            left: 0,
+            offset: 0,
+        }
+    }
+
+    #[allow(unsafe_code)] // the blocks are shared with the kernel
+    fn status(&self, block: u32) -> &AtomicU32 {
+        let offset = (block * BLOCK_SIZE) as usize + BLOCK_STATUS;
+        // SAFETY: the status is within the mapping, which lives as long as the ring
+        unsafe { &*self.map.as_ptr().add(offset).cast::<AtomicU32>() }
+    }
+
+    #[allow(unsafe_code)] // the blocks are shared with the kernel
+    fn octets(&self, block: u32) -> &[u8] {
+        let offset = (block * BLOCK_SIZE) as usize;
+        // SAFETY: the block is within the mapping, and is ours: the kernel does not touch the
+        // blocks it handed over until given back
+        unsafe { std::slice::from_raw_parts(self.map.as_ptr().add(offset), BLOCK_SIZE as usize) }
+    }
+
+    /// Give the block being read back to the kernel, and move on to the next one
+    fn release(&mut self) {
+        self.status(self.block)
+            .store(TP_STATUS_KERNEL, Ordering::Release);
+        self.block = (self.block + 1) % BLOCK_COUNT;
+        self.left = 0;
+    }
+
+    /// Receive up to `max` frames, handing them to `deliver` with their RX hash, if the kernel has
+    /// one. Fails with [`io::ErrorKind::WouldBlock`] if no frame was left to receive.
+    pub(super) fn recv(
+        &mut self,
+        max: usize,
+        mut deliver: impl FnMut(&[u8], Option<u32>),
+    ) -> io::Result<usize> {
+        let mut count = 0;
+        while count < max {
+            if self.left == 0 {
+                if self.status(self.block).load(Ordering::Acquire) & TP_STATUS_USER == 0 {
+                    break;
+                }
+                let block = self.octets(self.block);
+                let left = u32_at(block, BLOCK_NUM_PKTS).unwrap_or_else(|| unreachable!());
+                let first = u32_at(block, BLOCK_FIRST_PKT).unwrap_or_else(|| unreachable!());
+                if left == 0 {
+                    self.release();
+                    continue;
+                }
+                self.left = left;
+                self.offset = first as usize;
+            }
+            match frame_at(self.octets(self.block), self.offset) {
+                Some((frame, rx_hash, next)) => {
+                    deliver(frame, rx_hash);
+                    count += 1;
+                    self.offset = next;
+                    self.left -= 1;
+                    if self.left == 0 {
+                        self.release();
+                    }
+                }
+                // the rest of a block the kernel would get wrong is dropped
+                None => self.release(),
+            }
+        }
+        if count == 0 {
+            return Err(io::ErrorKind::WouldBlock.into());
+        }
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+pub(super) mod tests {
+    use super::*;
+
+    /// Write a frame of `octets` with `rx_hash` at `offset` of `block`, followed by the next one
+    /// at `next`, as the kernel does
+    fn write_frame(block: &mut [u8], offset: usize, next: u32, rx_hash: u32, octets: &[u8]) {
+        let mac = 80u16;
+        let put = |block: &mut [u8], at: usize, value: &[u8]| {
+            block[offset + at..offset + at + value.len()].copy_from_slice(value);
+        };
+        put(block, FRAME_NEXT, &next.to_ne_bytes());
+        let len = u32::try_from(octets.len()).unwrap();
+        put(block, FRAME_SNAPLEN, &len.to_ne_bytes());
+        put(block, FRAME_MAC, &mac.to_ne_bytes());
+        put(block, FRAME_RXHASH, &rx_hash.to_ne_bytes());
+        put(block, usize::from(mac), octets);
+    }
+
+    /// The octets of a block of `ring`, as the kernel writes them
+    #[allow(unsafe_code)]
+    fn block_mut(ring: &RxRing, block: u32) -> &mut [u8] {
+        // SAFETY: the test owns the anonymous mapping, and reads the block once handed over
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                ring.map.as_mut_ptr().add((block * BLOCK_SIZE) as usize),
+                BLOCK_SIZE as usize,
+            )
+        }
+    }
+
+    /// Hand a block of `frames`, each with its RX hash, over to the reader of `ring`
+    pub(in crate::drivers::kernel) fn fill_block(
+        ring: &RxRing,
+        block: u32,
+        frames: &[(u32, &[u8])],
+    ) {
+        let octets = block_mut(ring, block);
+        let first: u32 = 64;
+        let count = u32::try_from(frames.len()).unwrap();
+        octets[BLOCK_NUM_PKTS..BLOCK_NUM_PKTS + 4].copy_from_slice(&count.to_ne_bytes());
+        octets[BLOCK_FIRST_PKT..BLOCK_FIRST_PKT + 4].copy_from_slice(&first.to_ne_bytes());
+        let mut offset = first as usize;
+        for (rx_hash, frame) in frames {
+            write_frame(octets, offset, 256, *rx_hash, frame);
+            offset += 256;
+        }
+        ring.status(block).store(TP_STATUS_USER, Ordering::Release);
+    }
+
+    pub(in crate::drivers::kernel) fn ring() -> RxRing {
+        let len = BLOCK_SIZE as usize * BLOCK_COUNT as usize;
+        RxRing::from_map(MmapRaw::from(
+            MmapOptions::new().len(len).map_anon().unwrap(),
+        ))
+    }
+
+    #[test]
+    fn test_ring_recv() {
+        let mut ring = ring();
+        let mut received = Vec::new();
+        let mut recv = |ring: &mut RxRing, max| {
+            ring.recv(max, |frame, rx_hash| {
+                received.push((frame.to_vec(), rx_hash))
+            })
+        };
+        let err = recv(&mut ring, 8).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+
+        fill_block(&ring, 0, &[(0xdead_beef, b"first"), (0, b"second")]);
+        fill_block(&ring, 1, &[(7, b"third")]);
+        // stops within a block, and resumes where it stopped
+        assert_eq!(recv(&mut ring, 1).unwrap(), 1);
+        assert_eq!(ring.status(0).load(Ordering::Acquire), TP_STATUS_USER);
+        assert_eq!(recv(&mut ring, 8).unwrap(), 2);
+        assert_eq!(ring.status(0).load(Ordering::Acquire), TP_STATUS_KERNEL);
+        assert_eq!(ring.status(1).load(Ordering::Acquire), TP_STATUS_KERNEL);
+        let err = recv(&mut ring, 8).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::WouldBlock);
+        assert_eq!(
+            received,
+            vec![
+                (b"first".to_vec(), Some(0xdead_beef)),
+                (b"second".to_vec(), None),
+                (b"third".to_vec(), Some(7)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ring_recv_bad_frame() {
+        let mut ring = ring();
+        fill_block(&ring, 0, &[(1, b"bad"), (2, b"dropped")]);
+        fill_block(&ring, 1, &[(3, b"good")]);
+        // the first frame of the first block runs past the block
+        let snaplen = 64 + FRAME_SNAPLEN;
+        block_mut(&ring, 0)[snaplen..snaplen + 4].copy_from_slice(&BLOCK_SIZE.to_ne_bytes());
+        assert!(frame_at(ring.octets(0), 64).is_none());
+        assert!(frame_at(ring.octets(0), BLOCK_SIZE as usize - 8).is_none());
+
+        let mut received = Vec::new();
+        let count = ring.recv(8, |frame, rx_hash| received.push((frame.to_vec(), rx_hash)));
+        assert_eq!(count.unwrap(), 1);
+        assert_eq!(received, vec![(b"good".to_vec(), Some(3))]);
+        assert_eq!(ring.status(0).load(Ordering::Acquire), TP_STATUS_KERNEL);
+    }
+}
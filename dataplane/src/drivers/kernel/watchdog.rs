@@ -0,0 +1,304 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Watchdog of the kernel driver workers.
+//!
+//! Workers count the batches of packets they process in a [`Heartbeat`]. The [`Watchdog`]
+//! periodically checks that the workers busy with a batch make progress: a worker whose batch
+//! count has not changed for the stall timeout while busy is likely deadlocked or spinning. Such
+//! workers are reported with an error log, the `worker_stalled` gauge and the `worker_stalls`
+//! counter. Idle workers, waiting for packets, are never reported.
+//!
+//! Optionally, the stacks of a stalled worker are sampled with pprof and stored in a file for
+//! later diagnosis. Sampling is driven by CPU time, so only workers spinning show samples: the
+//! stack file of a deadlocked worker is empty. Sampling is process-wide and must not be combined
+//! with continuous profiling.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use concurrency::sync::Arc;
+use concurrency::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use lifecycle::CancellationToken;
+use metrics::{Counter, Gauge};
+use stats::{MetricSpec, Register};
+use thiserror::Error;
+#[allow(unused)]
+use tracing::{debug, error, info, warn};
+
+use crate::drivers::kernel::worker::{WorkerId, thread_name};
+
+/// Progress of a worker
+#[derive(Debug, Default)]
+pub struct Heartbeat {
+    /// Number of batches processed
+    beats: AtomicU64,
+    /// Number of batches being processed. Readers of a worker interleave, so there may be many.
+    busy: AtomicUsize,
+}
+
+impl Heartbeat {
+    /// Mark the start of a batch. The batch is counted when the returned guard is dropped.
+    #[must_use]
+    pub fn batch(&self) -> BatchGuard<'_> {
+        self.busy.fetch_add(1, Ordering::Relaxed);
+        BatchGuard(self)
+    }
+}
+
+/// A batch in progress
+pub struct BatchGuard<'a>(&'a Heartbeat);
+
+impl Drop for BatchGuard<'_> {
+    fn drop(&mut self) {
+        self.0.beats.fetch_add(1, Ordering::Relaxed);
+        self.0.busy.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Errors when capturing the stacks of a stalled worker
+#[derive(Error, Debug)]
+enum CaptureError {
+    #[error("Profiler failure: {0}")]
+    Profiler(#[from] pprof::Error),
+    #[error("Failed to store stacks: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+/// The watchdog of a single worker
+struct WorkerWatch {
+    id: WorkerId,
+    heartbeat: Arc<Heartbeat>,
+    /// Batch count at the last sign of progress
+    beats: u64,
+    /// Time of the last sign of progress
+    since: Instant,
+    stalled: bool,
+    stalled_gauge: Gauge,
+    stalls: Counter,
+}
+
+impl WorkerWatch {
+    fn new(id: WorkerId, heartbeat: Arc<Heartbeat>) -> Self {
+        let spec = |metric: &str| {
+            let labels = vec![("worker".to_string(), id.to_string())];
+            MetricSpec::new(metric, metrics::Unit::Count, labels)
+        };
+        let stalled_gauge: Gauge = spec("worker_stalled").register().metric;
+        stalled_gauge.set(0.0);
+        Self {
+            id,
+            beats: heartbeat.beats.load(Ordering::Relaxed),
+            heartbeat,
+            since: Instant::now(),
+            stalled: false,
+            stalled_gauge,
+            stalls: spec("worker_stalls").register().metric,
+        }
+    }
+
+    /// Check the progress of the worker. Returns true if the worker just stalled.
+    fn check(&mut self, now: Instant, timeout: Duration) -> bool {
+        let beats = self.heartbeat.beats.load(Ordering::Relaxed);
+        let busy = self.heartbeat.busy.load(Ordering::Relaxed) > 0;
+        if beats != self.beats || !busy {
+            self.beats = beats;
+            self.since = now;
+            if self.stalled {
+                info!(worker = self.id, "Worker resumed processing packets");
+                self.stalled = false;
+                self.stalled_gauge.set(0.0);
+            }
+            return false;
+        }
+        if self.stalled || now.saturating_duration_since(self.since) < timeout {
+            return false;
+        }
+        error!(
+            worker = self.id,
+            "Worker made no progress on a batch of packets for {:?}; it may be deadlocked or looping",
+            now.saturating_duration_since(self.since)
+        );
+        self.stalled = true;
+        self.stalled_gauge.set(1.0);
+        self.stalls.increment(1);
+        true
+    }
+}
+
+/// Detects the workers which stop making progress
+pub struct Watchdog {
+    heartbeats: Vec<(WorkerId, Arc<Heartbeat>)>,
+    timeout: Duration,
+    profile_dir: Option<PathBuf>,
+}
+
+impl Watchdog {
+    /// How long to sample the stacks of a stalled worker for
+    const CAPTURE_DURATION: Duration = Duration::from_secs(1);
+
+    /// Sampling frequency of the stacks of a stalled worker, in Hz
+    const CAPTURE_FREQUENCY: i32 = 997;
+
+    #[must_use]
+    pub fn new(timeout: Duration, profile_dir: Option<PathBuf>) -> Self {
+        Self {
+            heartbeats: vec![],
+            timeout,
+            profile_dir,
+        }
+    }
+
+    /// Create the heartbeat of a worker to watch
+    pub fn heartbeat(&mut self, id: WorkerId) -> Arc<Heartbeat> {
+        let heartbeat = Arc::new(Heartbeat::default());
+        self.heartbeats.push((id, heartbeat.clone()));
+        heartbeat
+    }
+
+    /// Sample the stacks of the process, and store those of a worker in `dir`
+    async fn capture(dir: &Path, id: WorkerId) -> Result<PathBuf, CaptureError> {
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(Self::CAPTURE_FREQUENCY)
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()?;
+        tokio::time::sleep(Self::CAPTURE_DURATION).await;
+        let report = guard.report().build()?;
+        drop(guard);
+
+        let name = thread_name(id);
+        let mut stacks: Vec<_> = report
+            .data
+            .iter()
+            .filter(|(frames, _)| frames.thread_name == name)
+            .collect();
+        stacks.sort_by_key(|(_, count)| std::cmp::Reverse(**count));
+        let mut out = format!(
+            "Stacks of stalled worker {id} (thread {name}), sampled for {:?}\n",
+            Self::CAPTURE_DURATION
+        );
+        for (frames, count) in stacks {
+            let _ = writeln!(out, "\n{count} samples:");
+            for symbol in frames.frames.iter().flatten() {
+                let _ = writeln!(out, "    {}", symbol.name());
+            }
+        }
+
+        let secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        std::fs::create_dir_all(dir)?;
+        let path = dir.join(format!("worker-{id}-stall-{secs}.txt"));
+        std::fs::write(&path, out)?;
+        Ok(path)
+    }
+
+    /// Check the workers every fraction of the stall timeout, until cancelled
+    pub async fn run(self, cancel: CancellationToken) {
+        let period = self.timeout / 4;
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        let mut watches: Option<Vec<WorkerWatch>> = None;
+        info!(
+            "Watching {} workers, with stall timeout {:?}",
+            self.heartbeats.len(),
+            self.timeout
+        );
+        loop {
+            tokio::select! {
+                () = cancel.cancelled() => break,
+                _ = ticker.tick() => {}
+            }
+            // register the metrics late, not to race with the setup of the metrics recorder
+            let watches = watches.get_or_insert_with(|| {
+                self.heartbeats
+                    .iter()
+                    .map(|(id, heartbeat)| WorkerWatch::new(*id, heartbeat.clone()))
+                    .collect()
+            });
+            let now = Instant::now();
+            let stalled: Vec<_> = watches
+                .iter_mut()
+                .filter_map(|watch| watch.check(now, self.timeout).then_some(watch.id))
+                .collect();
+            let Some(dir) = &self.profile_dir else {
+                continue;
+            };
+            for id in stalled {
+                match Self::capture(dir, id).await {
+                    Ok(path) => info!(worker = id, "Stored stacks in {}", path.display()),
+                    Err(e) => warn!(worker = id, "Failed to capture stacks: {e}"),
+                }
+            }
+        }
+        info!("Worker watchdog stopped");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    #[test]
+    fn test_heartbeat() {
+        let heartbeat = Heartbeat::default();
+        let batch = heartbeat.batch();
+        let nested = heartbeat.batch();
+        assert_eq!(heartbeat.busy.load(Ordering::Relaxed), 2);
+        assert_eq!(heartbeat.beats.load(Ordering::Relaxed), 0);
+        drop(nested);
+        drop(batch);
+        assert_eq!(heartbeat.busy.load(Ordering::Relaxed), 0);
+        assert_eq!(heartbeat.beats.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn test_idle_worker_never_stalls() {
+        let mut watch = WorkerWatch::new(0, Arc::new(Heartbeat::default()));
+        let start = watch.since;
+        assert!(!watch.check(start + TIMEOUT * 10, TIMEOUT));
+        assert!(!watch.stalled);
+    }
+
+    #[test]
+    fn test_progressing_worker_never_stalls() {
+        let heartbeat = Arc::new(Heartbeat::default());
+        let mut watch = WorkerWatch::new(0, heartbeat.clone());
+        let start = watch.since;
+        let batch = heartbeat.batch();
+        for step in 1..10 {
+            // a batch completes and another starts between each check
+            drop(heartbeat.batch());
+            assert!(!watch.check(start + TIMEOUT * step, TIMEOUT));
+        }
+        drop(batch);
+        assert!(!watch.stalled);
+    }
+
+    #[test]
+    fn test_stalled_worker() {
+        let heartbeat = Arc::new(Heartbeat::default());
+        let mut watch = WorkerWatch::new(0, heartbeat.clone());
+        let start = watch.since;
+        let batch = heartbeat.batch();
+
+        // busy on a batch, but not for long enough
+        assert!(!watch.check(start + TIMEOUT / 2, TIMEOUT));
+        assert!(!watch.stalled);
+
+        // stalled: reported once only
+        assert!(watch.check(start + TIMEOUT, TIMEOUT));
+        assert!(watch.stalled);
+        assert!(!watch.check(start + TIMEOUT * 2, TIMEOUT));
+        assert!(watch.stalled);
+
+        // the batch completes
+        drop(batch);
+        assert!(!watch.check(start + TIMEOUT * 3, TIMEOUT));
+        assert!(!watch.stalled);
+        assert_eq!(watch.since, start + TIMEOUT * 3);
+    }
+}
@@ -4,47 +4,116 @@
 // We want to avoid Packet moves, so allow Vec<Box<_>> to be sure
 #![allow(clippy::vec_box)]
 
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::io;
-use std::os::fd::AsRawFd;
+use std::os::fd::{AsRawFd, RawFd};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 use afpacket::tokio::RawPacketStream;
 use tokio::io::unix::AsyncFd;
 use tokio::io::{AsyncWriteExt, Interest};
-use tokio::sync::Mutex;
+use tokio::sync::{Mutex, mpsc};
 
 use concurrency::sync::Arc;
 use concurrency::thread;
 #[allow(unused_imports)] // used under loom/shuttle backends
 use concurrency::thread::BuilderExt;
-use lifecycle::Subsystem;
+use lifecycle::{CancellationToken, Subsystem};
 use net::buffer::test_buffer::TestBuffer;
 use net::interface::InterfaceIndex;
 use net::packet::{DoneReason, Packet};
-use pipeline::{DynPipeline, NetworkFunction};
+use pipeline::{DynPipeline, NetworkFunction, TablesEpoch};
 
+use crate::drivers::af_xdp::{XdpPorts, Xsk, XskRx, XskTx};
+use crate::drivers::batch::{AdaptiveBatch, BatchLimits};
+use crate::drivers::kernel::RxMode;
+use crate::drivers::kernel::drain::DrainSignal;
 use crate::drivers::kernel::fanout::{PacketFanoutType, set_packet_fanout};
+use crate::drivers::kernel::hostpath::{HostPathMetrics, PuntClass, queued_octets};
+use crate::drivers::kernel::hotplug::KifEvent;
 use crate::drivers::kernel::kif::Kif;
+use crate::drivers::kernel::microburst::{MicroburstLog, monitor_rx_queue};
+use crate::drivers::kernel::ring::RxRing;
+use crate::drivers::kernel::watchdog::Heartbeat;
 
 use tracing::{debug, error, info, trace, warn};
 
-type WorkerId = usize;
+pub type WorkerId = usize;
 
-struct WorkerInterfaceWriter {
-    if_name: String,
+/// The name of the thread of a worker
+pub fn thread_name(id: WorkerId) -> String {
+    format!("dp-worker-{id}")
+}
+
+/// The socket frames are sent on
+enum TxSocket {
+    Packet(RawPacketStream),
+    Xsk(XskTx),
+}
+
+pub(super) struct WorkerInterfaceWriter {
+    pub(super) if_name: String,
     #[allow(unused)]
     if_index: InterfaceIndex,
-    sock: RawPacketStream,
+    /// Whether the interface is a tap interface of the host path
+    host: bool,
+    sock: TxSocket,
+}
+
+impl WorkerInterfaceWriter {
+    pub(super) fn raw_fd(&self) -> RawFd {
+        match &self.sock {
+            TxSocket::Packet(sock) => sock.as_raw_fd(),
+            TxSocket::Xsk(xsk) => xsk.raw_fd(),
+        }
+    }
+
+    /// Send a frame on the interface
+    async fn write(&mut self, frame: &[u8]) -> io::Result<()> {
+        match &mut self.sock {
+            TxSocket::Packet(sock) => sock.write(frame).await.map(|_| ()),
+            TxSocket::Xsk(xsk) => xsk.send(frame),
+        }
+    }
+
+    /// The octets, for packet sockets, or frames, for `AF_XDP` sockets, written on the interface
+    /// which are not sent yet
+    fn pending(&mut self) -> io::Result<usize> {
+        match &mut self.sock {
+            TxSocket::Packet(sock) => queued_octets(sock.as_raw_fd()).map(|octets| octets as usize),
+            TxSocket::Xsk(xsk) => xsk.pending(),
+        }
+    }
 }
 
 struct WorkerInterfaceReader {
     if_name: String,
     if_index: InterfaceIndex,
+    /// Whether the interface is a tap interface of the host path
+    host: bool,
     read_fd: AsyncFd<std::os::unix::io::OwnedFd>,
+    rx: RxSocket,
+}
+
+/// Where the frames of an interface are read from
+enum RxSocket {
+    /// With `recv` on the packet socket of `read_fd`
+    Packet,
+    /// From the receive ring of the packet socket of `read_fd`
+    Ring(RxRing),
+    /// From the receiving half of the `AF_XDP` socket of the interface
+    Xsk(XskRx),
 }
 
-type WorkerInterfaceReaders = Vec<WorkerInterfaceReader>;
 type WorkerIfTable = HashMap<InterfaceIndex, Arc<Mutex<WorkerInterfaceWriter>>>;
+type PipelineSetup = Arc<dyn Send + Sync + Fn() -> DynPipeline<TestBuffer>>;
+
+/// The interval at which empty batches are pumped through the pipeline while draining
+const DRAIN_PERIOD: Duration = Duration::from_millis(10);
+/// The number of consecutive empty batches releasing no packet after which the pipeline is drained
+const DRAIN_IDLE_ROUNDS: usize = 10;
 
 #[allow(unsafe_code)]
 fn create_worker_interface(
@@ -52,6 +121,8 @@ fn create_worker_interface(
     total_workers: usize,
     if_name: &str,
     if_index: InterfaceIndex,
+    host: bool,
+    ring: bool,
 ) -> io::Result<(WorkerInterfaceWriter, WorkerInterfaceReader)> {
     let mut sock = RawPacketStream::new()?;
     sock.bind(if_name)
@@ -74,6 +145,21 @@ fn create_worker_interface(
 
     let read_fd_owned = nix::unistd::dup(bfd).map_err(io::Error::from)?;
     let read_fd = AsyncFd::with_interest(read_fd_owned, Interest::READABLE)?;
+    // the ring must be set up before the socket joins the fanout group
+    let rx = if ring {
+        match RxRing::new(read_fd.get_ref()) {
+            Ok(ring) => RxSocket::Ring(ring),
+            Err(e) => {
+                warn!(
+                    worker = id,
+                    "Failed to set up receive ring for interface {if_name}: {e}. Falling back to recv"
+                );
+                RxSocket::Packet
+            }
+        }
+    } else {
+        RxSocket::Packet
+    };
     let fanout_type = set_packet_fanout(if_index, &read_fd);
     if total_workers > 1 {
         match fanout_type {
@@ -114,35 +200,90 @@ fn create_worker_interface(
         WorkerInterfaceWriter {
             if_name: String::from(if_name),
             if_index,
-            sock,
+            host,
+            sock: TxSocket::Packet(sock),
         },
         WorkerInterfaceReader {
             if_name: String::from(if_name),
             if_index,
+            host,
             read_fd,
+            rx,
         },
     ))
 }
 
+/// Open the `AF_XDP` socket of a worker on an interface, on the queue of the index of the worker.
+/// Returns `None` if the interface is not served with `AF_XDP` sockets.
+fn create_worker_xsk_interface(
+    id: WorkerId,
+    xdp: &XdpPorts,
+    if_name: &str,
+    if_index: InterfaceIndex,
+) -> Option<io::Result<(WorkerInterfaceWriter, WorkerInterfaceReader)>> {
+    let open = |xsk: Xsk| {
+        let read_fd = AsyncFd::with_interest(xsk.try_clone_fd()?, Interest::READABLE)?;
+        info!(
+            worker = id,
+            "Using AF_XDP socket on queue {id} of interface {if_name}"
+        );
+        Ok((
+            WorkerInterfaceWriter {
+                if_name: String::from(if_name),
+                if_index,
+                host: false,
+                sock: TxSocket::Xsk(xsk.tx),
+            },
+            WorkerInterfaceReader {
+                if_name: String::from(if_name),
+                if_index,
+                host: false,
+                read_fd,
+                rx: RxSocket::Xsk(xsk.rx),
+            },
+        ))
+    };
+    xdp.open(if_index, id).map(|xsk| xsk.and_then(open))
+}
+
 pub struct Worker {
     id: WorkerId,
     total_workers: usize,
     setup_pipeline: Arc<dyn Send + Sync + Fn() -> DynPipeline<TestBuffer>>,
     subsystem: Subsystem,
+    heartbeat: Arc<Heartbeat>,
+    /// How the frames of the interfaces are received
+    rx: RxMode,
+    drain: DrainSignal,
+    /// Where the microbursts detected on the receive queues are recorded
+    microbursts: MicroburstLog,
+    /// The bounds of the batches of packets received from the interfaces
+    batch: BatchLimits,
 }
 
 impl Worker {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: WorkerId,
         total_workers: usize,
         setup_pipeline: &Arc<dyn Send + Sync + Fn() -> DynPipeline<TestBuffer>>,
         subsystem: Subsystem,
+        heartbeat: Arc<Heartbeat>,
+        rx: RxMode,
+        drain: DrainSignal,
+        microbursts: MicroburstLog,
+        batch: BatchLimits,
     ) -> Self {
         Worker {
             id,
             total_workers,
             setup_pipeline: setup_pipeline.clone(),
             subsystem,
+            heartbeat,
+            rx,
+            drain,
+            microbursts,
+            batch,
         }
     }
 
@@ -152,11 +293,17 @@ impl Worker {
         scope: &'scope thread::Scope<'scope, '_>,
         thread_builder: thread::Builder,
         interfaces: &[Kif],
+        mut events: mpsc::UnboundedReceiver<KifEvent>,
     ) -> Result<thread::ScopedJoinHandle<'scope, Result<(), io::Error>>, io::Error> {
         let id = self.id;
         let total_workers = self.total_workers;
         let setup = self.setup_pipeline.clone();
         let subsystem = self.subsystem.clone();
+        let heartbeat = self.heartbeat.clone();
+        let rx = self.rx.clone();
+        let drain = self.drain.clone();
+        let microbursts = self.microbursts.clone();
+        let batch = self.batch;
         let cancel = subsystem.cancel_token();
         let interfaces = interfaces.to_vec();
 
@@ -199,96 +346,46 @@ impl Worker {
                 .build_local(tokio::runtime::LocalOptions::default())?;
 
             let result = rt.block_on(async {
-                let (readers, if_table) =
-                    match build_interface_table(id, total_workers, interfaces.as_slice()) {
-                        Ok(table) => table,
-                        Err(e) => {
-                            error!(worker = id, "Error building interface table: {}", e);
-                            return Err(e);
-                        }
-                    };
-
-                let setup = setup.clone();
-                let if_table = if_table.clone();
-                let cancel = cancel.clone();
-
-                let mut reader_handles = tokio::task::JoinSet::new();
-
-                for intf in readers {
-                    let setup = setup.clone();
-                    let if_table = if_table.clone();
-                    let cancel = cancel.clone();
-                    reader_handles.spawn_local(async move {
-                        let intf = intf;
-                        let mut pipeline = setup();
-                        loop {
-                            debug!(worker = id, "awaiting packets");
-
-                            let packets_vec = tokio::select! {
-                                () = cancel.cancelled() => {
-                                    info!(
-                                        worker = id,
-                                        rx_intf_name = intf.if_name,
-                                        "cancellation observed; exiting reader"
-                                    );
-                                    break;
-                                }
-                                result = read_packets_from_interface(id, &intf) => match result {
-                                    Ok(packets) => packets,
-                                    Err(e) => {
-                                        error!(
-                                            worker = id,
-                                            rx_intf_name = intf.if_name,
-                                            "Error reading packets from interface: {e}"
-                                        );
-                                        vec![]
-                                    }
-                                }
-                            };
-
-                            debug!(
-                                worker = id,
-                                rx_intf_name = intf.if_name,
-                                "Read {} packets from interface {}",
-                                packets_vec.len(),
-                                intf.if_name
-                            );
-
-                            let packets = packets_vec.into_iter();
-
-                            let mut count = 0;
-                            let out_pkts = pipeline
-                                .process(packets.map(|pkt| *pkt))
-                                .collect::<Vec<_>>();
-                            for out_pkt in out_pkts {
-                                trace!(
-                                    worker = id,
-                                    rx_intf_name = intf.if_name,
-                                    "Tx packet after pipeline for interface {}",
-                                    intf.if_name
-                                );
-                                tx_packet(id, &intf.if_name, &if_table, out_pkt).await;
-                                count += 1;
+                let mut attached = match WorkerInterfaces::new(
+                    id,
+                    total_workers,
+                    setup.clone(),
+                    heartbeat,
+                    rx,
+                    drain,
+                    microbursts,
+                    batch,
+                    &interfaces,
+                    &cancel,
+                ) {
+                    Ok(attached) => attached,
+                    Err(e) => {
+                        error!(worker = id, "Error building interface table: {}", e);
+                        return Err(e);
+                    }
+                };
+
+                loop {
+                    tokio::select! {
+                        () = cancel.cancelled() => break,
+                        Some(event) = events.recv() => match event {
+                            KifEvent::Attach(kif) => attached.attach(&kif, &cancel),
+                            KifEvent::Detach(kif) => attached.detach(&kif),
+                        },
+                        Some(res) = attached.readers.join_next() => {
+                            if let Err(e) = res {
+                                error!(worker = id, "Reader handle failed: {e}");
+                                return Err(e.into());
                             }
-
-                            tracing::debug!(
-                                worker = id,
-                                rx_intf_name = intf.if_name,
-                                "processed {count} packets from interface {}",
-                                intf.if_name
-                            );
                         }
-                    });
+                    }
                 }
 
                 // Wait for all reader handles to complete
-                while let Some(res) = reader_handles.join_next().await {
-                    match res {
-                        Ok(()) => {}
-                        Err(e) => {
-                            error!(worker = id, "Reader handle failed: {e}");
-                            return Err(e.into());
-                        }
+                while let Some(res) = attached.readers.join_next().await {
+                    if let Err(e) = res {
+                        error!(worker = id, "Reader handle failed: {e}");
+                        return Err(e.into());
                     }
                 }
 
@@ -306,35 +403,348 @@ impl Worker {
     }
 }
 
-fn build_interface_table(
+/// The interfaces a worker does packet IO on, each of them with a reader task
+struct WorkerInterfaces {
     id: WorkerId,
     total_workers: usize,
-    interfaces: &[Kif],
-) -> Result<(WorkerInterfaceReaders, Arc<WorkerIfTable>), io::Error> {
-    let mut if_table = HashMap::new();
-    let mut readers = Vec::new();
-    for kif in interfaces {
-        let (writer, reader) = create_worker_interface(id, total_workers, &kif.name, kif.ifindex)?;
-        if_table.insert(kif.ifindex, Arc::new(Mutex::new(writer)));
-        readers.push(reader);
+    setup: PipelineSetup,
+    heartbeat: Arc<Heartbeat>,
+    rx: RxMode,
+    drain: DrainSignal,
+    host_path: Rc<HostPathMetrics>,
+    microbursts: MicroburstLog,
+    batch: BatchLimits,
+    if_table: Rc<RefCell<WorkerIfTable>>,
+    /// Cancellation of the readers, to detach their interface
+    stop: HashMap<InterfaceIndex, CancellationToken>,
+    readers: tokio::task::JoinSet<()>,
+}
+
+impl WorkerInterfaces {
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        id: WorkerId,
+        total_workers: usize,
+        setup: PipelineSetup,
+        heartbeat: Arc<Heartbeat>,
+        rx: RxMode,
+        drain: DrainSignal,
+        microbursts: MicroburstLog,
+        batch: BatchLimits,
+        interfaces: &[Kif],
+        cancel: &CancellationToken,
+    ) -> Result<Self, io::Error> {
+        let mut attached = Self {
+            id,
+            total_workers,
+            setup,
+            heartbeat,
+            rx,
+            drain,
+            host_path: Rc::new(HostPathMetrics::new(id)),
+            microbursts,
+            batch,
+            if_table: Rc::new(RefCell::new(HashMap::new())),
+            stop: HashMap::new(),
+            readers: tokio::task::JoinSet::new(),
+        };
+        for kif in interfaces {
+            attached.open(kif, false, cancel)?;
+        }
+        Ok(attached)
+    }
+
+    /// Open the sockets of an interface and start its reader, with its own pipeline. The queues
+    /// of the tap interfaces of the host path are monitored as well, and so are the receive
+    /// queues of the packet sockets of the other interfaces read with `recv`, for microbursts.
+    fn open(&mut self, kif: &Kif, host: bool, cancel: &CancellationToken) -> Result<(), io::Error> {
+        let xsk = match &self.rx {
+            RxMode::Xdp(xdp) => create_worker_xsk_interface(self.id, xdp, &kif.name, kif.ifindex),
+            RxMode::Recv | RxMode::Ring => None,
+        };
+        let (writer, reader) = match xsk {
+            Some(xsk) => xsk?,
+            None => create_worker_interface(
+                self.id,
+                self.total_workers,
+                &kif.name,
+                kif.ifindex,
+                host,
+                !host && matches!(self.rx, RxMode::Ring),
+            )?,
+        };
+        let packet_socket = matches!(reader.rx, RxSocket::Packet);
+        let writer = Arc::new(Mutex::new(writer));
+        self.if_table
+            .borrow_mut()
+            .insert(kif.ifindex, writer.clone());
+        let stop = cancel.child_token();
+        self.stop.insert(kif.ifindex, stop.clone());
+        if host {
+            self.readers
+                .spawn_local(self.host_path.clone().monitor_tap(writer, stop.clone()));
+        } else if packet_socket {
+            self.readers.spawn_local(monitor_rx_queue(
+                self.id,
+                writer,
+                self.microbursts.clone(),
+                stop.clone(),
+            ));
+        }
+        let batch = AdaptiveBatch::new(self.batch, self.id, &kif.name);
+        self.readers.spawn_local(run_reader(
+            self.id,
+            reader,
+            batch,
+            (self.setup)(),
+            self.if_table.clone(),
+            self.heartbeat.clone(),
+            self.host_path.clone(),
+            self.drain.clone(),
+            stop,
+        ));
+        Ok(())
+    }
+
+    /// Start packet IO on an interface. Interfaces are attached as the tap interfaces of the
+    /// host path get published.
+    fn attach(&mut self, kif: &Kif, cancel: &CancellationToken) {
+        if self.stop.contains_key(&kif.ifindex) {
+            debug!(worker = self.id, "Interface {} already attached", kif.name);
+            return;
+        }
+        if let Err(e) = self.open(kif, true, cancel) {
+            error!(
+                worker = self.id,
+                "Failed to attach interface {}: {e}", kif.name
+            );
+            return;
+        }
+        info!(worker = self.id, "Attached interface {}", kif.name);
+    }
+
+    /// Stop packet IO on an interface. Its sockets get closed once its reader exits and
+    /// in-flight transmissions complete.
+    fn detach(&mut self, kif: &Kif) {
+        self.if_table.borrow_mut().remove(&kif.ifindex);
+        if let Some(stop) = self.stop.remove(&kif.ifindex) {
+            stop.cancel();
+            info!(worker = self.id, "Detached interface {}", kif.name);
+        }
+    }
+}
+
+/// Read packets from an interface in batches sized by `batch`, process them and transmit the
+/// outcome, until cancelled. Empty batches are processed as well when the pipeline requests them. Once quiesced, stop reading and drain the pipeline and the sockets of
+/// the worker first.
+#[allow(clippy::too_many_arguments)]
+async fn run_reader(
+    id: WorkerId,
+    mut intf: WorkerInterfaceReader,
+    mut batch: AdaptiveBatch,
+    mut pipeline: DynPipeline<TestBuffer>,
+    if_table: Rc<RefCell<WorkerIfTable>>,
+    heartbeat: Arc<Heartbeat>,
+    host_path: Rc<HostPathMetrics>,
+    drain: DrainSignal,
+    cancel: CancellationToken,
+) {
+    let drained = drain.drained.token();
+    let data = pipeline.get_data();
+    let mut tables = data.tables_epoch();
+    loop {
+        debug!(worker = id, "awaiting packets");
+
+        let packets_vec = tokio::select! {
+            () = cancel.cancelled() => {
+                info!(
+                    worker = id,
+                    rx_intf_name = intf.if_name,
+                    "cancellation observed; exiting reader"
+                );
+                return;
+            }
+            () = drain.quiesce.cancelled() => break,
+            // stages with packets of their own, such as OAM probes, inject them in empty batches
+            () = data.batch_requested() => vec![],
+            result = read_packets_from_interface(id, &mut intf, batch.size()) => match result {
+                Ok(packets) => {
+                    batch.received(packets.len());
+                    packets
+                }
+                Err(e) => {
+                    error!(
+                        worker = id,
+                        rx_intf_name = intf.if_name,
+                        "Error reading packets from interface: {e}"
+                    );
+                    vec![]
+                }
+            }
+        };
+
+        debug!(
+            worker = id,
+            rx_intf_name = intf.if_name,
+            "Read {} packets from interface {}",
+            packets_vec.len(),
+            intf.if_name
+        );
+
+        if intf.host {
+            host_path.injected(packets_vec.len());
+        }
+        let count = process_batch(
+            id,
+            &intf.if_name,
+            &mut pipeline,
+            &mut tables,
+            packets_vec,
+            &if_table,
+            &heartbeat,
+            &host_path,
+        )
+        .await;
+
+        tracing::debug!(
+            worker = id,
+            rx_intf_name = intf.if_name,
+            "processed {count} packets from interface {}",
+            intf.if_name
+        );
     }
-    Ok((readers, Arc::new(if_table)))
+
+    info!(
+        worker = id,
+        rx_intf_name = intf.if_name,
+        "RX quiesced; draining"
+    );
+    // the stages holding packets only release them when given a batch
+    let mut idle = 0;
+    while idle < DRAIN_IDLE_ROUNDS {
+        tokio::select! {
+            () = cancel.cancelled() => return,
+            () = tokio::time::sleep(DRAIN_PERIOD) => {}
+        }
+        let count = process_batch(
+            id,
+            &intf.if_name,
+            &mut pipeline,
+            &mut tables,
+            vec![],
+            &if_table,
+            &heartbeat,
+            &host_path,
+        )
+        .await;
+        idle = if count == 0 { idle + 1 } else { 0 };
+    }
+    loop {
+        let writers = if_table.borrow().values().cloned().collect::<Vec<_>>();
+        let mut pending = 0;
+        for writer in writers {
+            let mut writer = writer.lock().await;
+            match writer.pending() {
+                Ok(count) => pending += count,
+                Err(e) => warn!(
+                    worker = id,
+                    rx_intf_name = intf.if_name,
+                    "Unable to get the pending TX of interface {}: {e}",
+                    writer.if_name
+                ),
+            }
+        }
+        if pending == 0 {
+            break;
+        }
+        tokio::select! {
+            () = cancel.cancelled() => return,
+            () = tokio::time::sleep(DRAIN_PERIOD) => {}
+        }
+    }
+    info!(worker = id, rx_intf_name = intf.if_name, "Drained");
+    drop(drained);
+    cancel.cancelled().await;
 }
 
-/// Tries to receive frames from the indicated interface and builds `Packet`s
-/// out of them. Returns a vector of [`Packet`]s.
+/// Process a batch of packets received on interface `if_name` and transmit the outcome, with the
+/// packets held by the pipeline that it releases. Returns the number of packets transmitted.
+#[allow(clippy::too_many_arguments)]
+async fn process_batch(
+    id: WorkerId,
+    if_name: &str,
+    pipeline: &mut DynPipeline<TestBuffer>,
+    tables: &mut TablesEpoch,
+    packets: Vec<Box<Packet<TestBuffer>>>,
+    if_table: &RefCell<WorkerIfTable>,
+    heartbeat: &Heartbeat,
+    host_path: &HostPathMetrics,
+) -> usize {
+    // the watchdog expects the batch to complete, transmissions included
+    let _batch = heartbeat.batch();
+    let mut count = 0;
+    let out_pkts = {
+        // the tables of a config are not published while the batch goes through the pipeline
+        let _tables = tables.enter();
+        pipeline
+            .process(packets.into_iter().map(|pkt| *pkt))
+            .collect::<Vec<_>>()
+    };
+    let decided = Instant::now();
+    for out_pkt in out_pkts {
+        trace!(
+            worker = id,
+            rx_intf_name = if_name,
+            "Tx packet after pipeline for interface {}",
+            if_name
+        );
+        tx_packet(id, if_name, if_table, host_path, decided, out_pkt).await;
+        count += 1;
+    }
+    count
+}
+
+/// Build the packet of a frame received on an interface, with the RX hash of the frame if the
+/// socket it was read from tells it
+fn new_packet(
+    id: WorkerId,
+    if_name: &str,
+    if_index: InterfaceIndex,
+    frame: &[u8],
+    rx_hash: Option<u32>,
+) -> Option<Box<Packet<TestBuffer>>> {
+    match Packet::new(TestBuffer::from_raw_data(frame)) {
+        Ok(mut incoming) => {
+            incoming.meta_mut().iif = Some(if_index);
+            incoming.meta_mut().rx_hash = rx_hash;
+            Some(Box::new(incoming))
+        }
+        Err(e) => {
+            // Parsing errors happen; avoid logspam for loopback
+            if if_name != "lo" {
+                error!(
+                    worker = id,
+                    rx_intf_name = if_name,
+                    "Failed to parse packet on '{}': {e}",
+                    if_name
+                );
+            }
+            None
+        }
+    }
+}
+
+/// Tries to receive up to `max_to_read` frames from the indicated interface, handing them to
+/// `deliver`. Packet sockets do not tell the RX hash of the frames.
 fn packet_recv(
     id: WorkerId,
     if_name: &str,
     if_fd: i32,
-    if_index: InterfaceIndex,
     max_to_read: usize,
-    pkts: &mut Vec<Box<Packet<TestBuffer>>>,
+    mut deliver: impl FnMut(&[u8], Option<u32>),
 ) -> Result<(), nix::Error> {
     let mut raw = [0u8; 9600];
-    let mut ret = Ok(());
-    pkts.clear();
-    while pkts.len() < max_to_read {
+    for _ in 0..max_to_read {
         match nix::sys::socket::recv(
             if_fd,
             &mut raw,
@@ -352,41 +762,18 @@ fn packet_recv(
                         raw.len()
                     );
                 }
-                let buf = TestBuffer::from_raw_data(&raw[..std::cmp::min(raw.len(), bytes)]);
-                match Packet::new(buf) {
-                    Ok(mut incoming) => {
-                        incoming.meta_mut().iif = Some(if_index);
-                        pkts.push(Box::new(incoming));
-                    }
-                    Err(e) => {
-                        // Parsing errors happen; avoid logspam for loopback
-                        if if_name != "lo" {
-                            error!(
-                                worker = id,
-                                rx_intf_name = if_name,
-                                "Failed to parse packet on '{}': {e}",
-                                if_name
-                            );
-                        }
-                    }
-                }
-            }
-            Err(e) if e == nix::errno::Errno::EWOULDBLOCK => {
-                ret = Err(e);
-                break;
-            }
-            Err(e) => {
-                ret = Err(e);
-                break;
+                deliver(&raw[..std::cmp::min(raw.len(), bytes)], None);
             }
+            Err(e) => return Err(e),
         }
     }
-    ret
+    Ok(())
 }
 
 async fn read_packets_from_interface(
     id: WorkerId,
-    intf: &WorkerInterfaceReader,
+    intf: &mut WorkerInterfaceReader,
+    max_to_read: usize,
 ) -> Result<Vec<Box<Packet<TestBuffer>>>, io::Error> {
     let fd = &intf.read_fd;
     let mut guard = match fd.readable().await {
@@ -407,17 +794,18 @@ async fn read_packets_from_interface(
             "Would block",
         ));
     }
-    let mut pkts = Vec::with_capacity(128);
-    match guard.try_io(|fd| {
-        packet_recv(
-            id,
-            intf.if_name.as_str(),
-            fd.as_raw_fd(),
-            intf.if_index,
-            128,
-            &mut pkts,
-        )
-        .map_err(std::convert::Into::into)
+    let mut pkts = Vec::with_capacity(max_to_read);
+    let if_name = intf.if_name.as_str();
+    let if_index = intf.if_index;
+    let mut deliver = |frame: &[u8], rx_hash| {
+        pkts.extend(new_packet(id, if_name, if_index, frame, rx_hash));
+    };
+    let rx = &mut intf.rx;
+    match guard.try_io(|fd| match rx {
+        RxSocket::Xsk(xsk) => xsk.recv(max_to_read, &mut deliver).map(|_| ()),
+        RxSocket::Ring(ring) => ring.recv(max_to_read, &mut deliver).map(|_| ()),
+        RxSocket::Packet => packet_recv(id, if_name, fd.as_raw_fd(), max_to_read, &mut deliver)
+            .map_err(std::convert::Into::into),
     }) {
         Ok(result) => match result {
             Ok(()) => (),
@@ -445,10 +833,14 @@ async fn read_packets_from_interface(
     Ok(pkts)
 }
 
+/// Transmit a packet the pipeline is done with at `decided` on its outgoing interface. Packets
+/// transmitted on tap interfaces are punted to the host, and accounted for as such.
 async fn tx_packet(
     id: WorkerId,
     rx_if_name: &str,
-    if_table: &WorkerIfTable,
+    if_table: &RefCell<WorkerIfTable>,
+    host_path: &HostPathMetrics,
+    decided: Instant,
     pkt: Packet<TestBuffer>,
 ) {
     // get outgoing interface marking. Should have one, except if packet is to be dropped.
@@ -474,7 +866,8 @@ async fn tx_packet(
         return;
     };
     // lookup interface
-    let Some(outgoing_unlocked) = if_table.get(&oif) else {
+    let outgoing = if_table.borrow().get(&oif).cloned();
+    let Some(outgoing_unlocked) = outgoing else {
         warn!(
             worker = id,
             rx_intf_name = rx_if_name,
@@ -485,9 +878,12 @@ async fn tx_packet(
     };
 
     // serialize and xmit
+    let mut outgoing = outgoing_unlocked.lock().await;
+    if outgoing.host {
+        host_path.punted(PuntClass::of(&pkt));
+    }
     match pkt.serialize() {
         Ok(out) => {
-            let mut outgoing = outgoing_unlocked.lock().await;
             let len = out.as_ref().len();
             trace!(
                 worker = id,
@@ -495,7 +891,10 @@ async fn tx_packet(
                 "TXing {len} bytes on interface {}",
                 &outgoing.if_name
             );
-            if let Err(e) = outgoing.sock.write(out.as_ref()).await {
+            if let Err(e) = outgoing.write(out.as_ref()).await {
+                if outgoing.host {
+                    host_path.punt_failed(&e);
+                }
                 warn!(
                     worker = id,
                     rx_intf_name = rx_if_name,
@@ -509,6 +908,9 @@ async fn tx_packet(
                     "TX {len} bytes on interface {}",
                     &outgoing.if_name
                 );
+                if outgoing.host {
+                    host_path.delivered(decided);
+                }
             }
         }
         Err(e) => {
@@ -520,3 +922,37 @@ async fn tx_packet(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::drivers::kernel::ring::tests::{fill_block, ring};
+    use net::packet::test_utils::build_test_udp_ipv4_packet;
+
+    #[test]
+    fn test_ring_rx_hash() {
+        let built = build_test_udp_ipv4_packet("10.0.0.1", "10.0.0.2", 40000, 53);
+        let computed = built.flow_hash();
+        let frame = built.serialize().unwrap();
+        let if_index = InterfaceIndex::try_new(2).unwrap();
+
+        let mut ring = ring();
+        fill_block(
+            &ring,
+            0,
+            &[(0xdead_beef, frame.as_ref()), (0, frame.as_ref())],
+        );
+        let mut pkts = Vec::new();
+        let count = ring.recv(8, |frame, rx_hash| {
+            pkts.extend(new_packet(0, "eth0", if_index, frame, rx_hash));
+        });
+        assert_eq!(count.unwrap(), 2);
+        assert_eq!(pkts.len(), 2);
+        assert_eq!(pkts[0].meta().iif, Some(if_index));
+        // the hash of the kernel is the flow hash, and is computed when the kernel has none
+        assert_eq!(pkts[0].meta().rx_hash, Some(0xdead_beef));
+        assert_eq!(pkts[0].flow_hash(), 0xdead_beef);
+        assert_eq!(pkts[1].meta().rx_hash, None);
+        assert_eq!(pkts[1].flow_hash(), computed);
+    }
+}
@@ -1,12 +1,31 @@
 // SPDX-License-Identifier: Apache-2.0
 // Copyright Open Network Fabric Authors
 
+use std::time::Duration;
+
 use thiserror::Error;
 
+pub mod af_xdp;
+pub mod batch;
+mod bpf;
 pub mod kernel;
 
 #[derive(Error, Debug)]
 pub enum DriverError {
     #[error("IO error: {0}")]
     IoError(#[from] std::io::Error),
+    #[error("Packets still in flight after draining for {0:?}")]
+    DrainTimeout(Duration),
+}
+
+/// Graceful stop of a driver: quiesce RX, let the pipelines finish with the packets in flight and
+/// flush TX, so that stopping the driver afterwards drops no packet mid-pipeline.
+pub trait Drain {
+    /// Drain the driver, giving up after `timeout`. RX is not resumed: the driver is meant to be
+    /// stopped next.
+    ///
+    /// # Errors
+    /// Returns [`DriverError::DrainTimeout`] if packets are still in flight after `timeout`. They
+    /// are dropped when the driver stops.
+    fn drain(&self, timeout: Duration) -> impl Future<Output = Result<(), DriverError>> + Send;
 }
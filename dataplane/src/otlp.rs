@@ -0,0 +1,310 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Export of traces and metrics to an OpenTelemetry collector, with OTLP/HTTP.
+//!
+//! Spans are handed over to OpenTelemetry by a layer of the tracing subscriber, so they are
+//! subject to the tracing configuration like any other output. Root spans are sampled with the
+//! ratio of the launch configuration, and the others follow the decision made for their parent.
+//!
+//! Metrics are recorded by the Prometheus recorder serving the `/metrics` endpoint and, next to
+//! it, by an [`OtlpRecorder`] mirroring them in OpenTelemetry instruments, which are exported
+//! every [`EXPORT_INTERVAL`].
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use args::OtlpHeader;
+use concurrency::sync::Mutex;
+use concurrency::sync::atomic::{AtomicU64, Ordering};
+use metrics::{
+    Counter, CounterFn, Gauge, GaugeFn, Histogram, HistogramFn, Key, KeyName, Metadata, Recorder,
+    SharedString, Unit,
+};
+use opentelemetry::KeyValue;
+use opentelemetry::metrics::{Meter, MeterProvider as _};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{
+    ExporterBuildError, MetricExporter, SpanExporter, WithExportConfig, WithHttpConfig,
+};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::metrics::{PeriodicReader, SdkMeterProvider};
+use opentelemetry_sdk::trace::{Sampler, SdkTracerProvider};
+use tracectl::ExportLayer;
+use tracing::error;
+use tracing_subscriber::Layer;
+
+/// The name the dataplane reports its telemetry under
+const SERVICE_NAME: &str = "hedgehog-dataplane";
+
+/// How often metrics are exported
+const EXPORT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Errors when setting up the OTLP export
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum OtlpError {
+    #[error("Failed to build the OTLP trace exporter: {0}")]
+    Traces(ExporterBuildError),
+    #[error("Failed to build the OTLP metric exporter: {0}")]
+    Metrics(ExporterBuildError),
+}
+
+/// The providers of the traces and metrics exported over OTLP
+pub(crate) struct Otlp {
+    tracer_provider: SdkTracerProvider,
+    meter_provider: SdkMeterProvider,
+}
+
+/// The URL a signal is exported to, under the base URL of the collector
+fn signal_url(endpoint: &str, signal: &str) -> String {
+    format!("{}/v1/{signal}", endpoint.trim_end_matches('/'))
+}
+
+impl Otlp {
+    /// Set up the export to the collector at `endpoint`, identifying this dataplane as
+    /// `instance`. Nothing is sent until spans are closed or metrics are due.
+    ///
+    /// This must be called outside of any tokio runtime, since the exporters use blocking HTTP
+    /// clients, from threads of their own.
+    pub(crate) fn start<'a>(
+        endpoint: &str,
+        headers: impl Iterator<Item = &'a OtlpHeader>,
+        sampling_ratio: f64,
+        instance: &str,
+    ) -> Result<Self, OtlpError> {
+        let headers: HashMap<String, String> = headers
+            .map(|header| (header.name.clone(), header.value.clone()))
+            .collect();
+        let resource = Resource::builder()
+            .with_service_name(SERVICE_NAME)
+            .with_attribute(KeyValue::new("service.instance.id", instance.to_string()))
+            .with_attribute(KeyValue::new(
+                "service.version",
+                option_env!("VERSION").unwrap_or("dev"),
+            ))
+            .build();
+
+        let spans = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(signal_url(endpoint, "traces"))
+            .with_headers(headers.clone())
+            .build()
+            .map_err(OtlpError::Traces)?;
+        let tracer_provider = SdkTracerProvider::builder()
+            .with_batch_exporter(spans)
+            .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+                sampling_ratio,
+            ))))
+            .with_resource(resource.clone())
+            .build();
+
+        let metrics = MetricExporter::builder()
+            .with_http()
+            .with_endpoint(signal_url(endpoint, "metrics"))
+            .with_headers(headers)
+            .build()
+            .map_err(OtlpError::Metrics)?;
+        let meter_provider = SdkMeterProvider::builder()
+            .with_reader(
+                PeriodicReader::builder(metrics)
+                    .with_interval(EXPORT_INTERVAL)
+                    .build(),
+            )
+            .with_resource(resource)
+            .build();
+
+        Ok(Self {
+            tracer_provider,
+            meter_provider,
+        })
+    }
+
+    /// The layer handing the spans over to OpenTelemetry, for the tracing subscriber
+    pub(crate) fn tracing_layer(&self) -> ExportLayer {
+        let tracer = self.tracer_provider.tracer(SERVICE_NAME);
+        tracing_opentelemetry::layer().with_tracer(tracer).boxed()
+    }
+
+    /// A recorder mirroring the metrics in OpenTelemetry instruments
+    pub(crate) fn recorder(&self) -> OtlpRecorder {
+        OtlpRecorder {
+            meter: self.meter_provider.meter(SERVICE_NAME),
+            descriptions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Export what is pending and stop exporting
+    pub(crate) fn shutdown(self) {
+        if let Err(e) = self.tracer_provider.shutdown() {
+            error!("Failed to flush OTLP traces: {e}");
+        }
+        if let Err(e) = self.meter_provider.shutdown() {
+            error!("Failed to flush OTLP metrics: {e}");
+        }
+    }
+}
+
+/// The unit and description of a metric
+type Description = (Option<&'static str>, String);
+
+/// Build an instrument, with the unit and description of its metric if it was described
+macro_rules! instrument {
+    ($builder:expr, $description:expr) => {{
+        let mut builder = $builder;
+        if let Some((unit, description)) = $description {
+            builder = builder.with_description(description);
+            if let Some(unit) = unit {
+                builder = builder.with_unit(unit);
+            }
+        }
+        builder.build()
+    }};
+}
+
+/// A [`Recorder`] mirroring the metrics in OpenTelemetry instruments. Labels become attributes.
+pub(crate) struct OtlpRecorder {
+    meter: Meter,
+    descriptions: Mutex<HashMap<String, Description>>,
+}
+
+impl OtlpRecorder {
+    fn describe(&self, key: &KeyName, unit: Option<Unit>, description: &SharedString) {
+        let unit = unit.map(|unit| unit.as_canonical_label());
+        self.descriptions
+            .lock()
+            .insert(key.as_str().to_string(), (unit, description.to_string()));
+    }
+
+    fn description(&self, key: &Key) -> Option<Description> {
+        self.descriptions.lock().get(key.name()).cloned()
+    }
+
+    fn attributes(key: &Key) -> Vec<KeyValue> {
+        key.labels()
+            .map(|label| KeyValue::new(label.key().to_string(), label.value().to_string()))
+            .collect()
+    }
+}
+
+impl Recorder for OtlpRecorder {
+    fn describe_counter(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.describe(&key, unit, &description);
+    }
+
+    fn describe_gauge(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.describe(&key, unit, &description);
+    }
+
+    fn describe_histogram(&self, key: KeyName, unit: Option<Unit>, description: SharedString) {
+        self.describe(&key, unit, &description);
+    }
+
+    fn register_counter(&self, key: &Key, _metadata: &Metadata<'_>) -> Counter {
+        let counter = instrument!(
+            self.meter.u64_counter(key.name().to_string()),
+            self.description(key)
+        );
+        Counter::from_arc(Arc::new(OtlpCounter {
+            counter,
+            attributes: Self::attributes(key),
+            total: AtomicU64::new(0),
+        }))
+    }
+
+    fn register_gauge(&self, key: &Key, _metadata: &Metadata<'_>) -> Gauge {
+        let gauge = instrument!(
+            self.meter.f64_gauge(key.name().to_string()),
+            self.description(key)
+        );
+        Gauge::from_arc(Arc::new(OtlpGauge {
+            gauge,
+            attributes: Self::attributes(key),
+            value: AtomicU64::new(0f64.to_bits()),
+        }))
+    }
+
+    fn register_histogram(&self, key: &Key, _metadata: &Metadata<'_>) -> Histogram {
+        let histogram = instrument!(
+            self.meter.f64_histogram(key.name().to_string()),
+            self.description(key)
+        );
+        Histogram::from_arc(Arc::new(OtlpHistogram {
+            histogram,
+            attributes: Self::attributes(key),
+        }))
+    }
+}
+
+/// A counter, whose total is kept to turn absolute values into increments
+struct OtlpCounter {
+    counter: opentelemetry::metrics::Counter<u64>,
+    attributes: Vec<KeyValue>,
+    total: AtomicU64,
+}
+
+impl CounterFn for OtlpCounter {
+    fn increment(&self, value: u64) {
+        self.total.fetch_add(value, Ordering::Relaxed);
+        self.counter.add(value, &self.attributes);
+    }
+
+    fn absolute(&self, value: u64) {
+        let total = self.total.swap(value, Ordering::Relaxed);
+        self.counter
+            .add(value.saturating_sub(total), &self.attributes);
+    }
+}
+
+/// A gauge, whose value is kept (as bits) to apply increments and decrements
+struct OtlpGauge {
+    gauge: opentelemetry::metrics::Gauge<f64>,
+    attributes: Vec<KeyValue>,
+    value: AtomicU64,
+}
+
+impl OtlpGauge {
+    fn update(&self, update: impl Fn(f64) -> f64) {
+        let mut current = self.value.load(Ordering::Relaxed);
+        loop {
+            let value = update(f64::from_bits(current));
+            match self.value.compare_exchange_weak(
+                current,
+                value.to_bits(),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    self.gauge.record(value, &self.attributes);
+                    return;
+                }
+                Err(actual) => current = actual,
+            }
+        }
+    }
+}
+
+impl GaugeFn for OtlpGauge {
+    fn increment(&self, value: f64) {
+        self.update(|current| current + value);
+    }
+
+    fn decrement(&self, value: f64) {
+        self.update(|current| current - value);
+    }
+
+    fn set(&self, value: f64) {
+        self.update(|_| value);
+    }
+}
+
+struct OtlpHistogram {
+    histogram: opentelemetry::metrics::Histogram<f64>,
+    attributes: Vec<KeyValue>,
+}
+
+impl HistogramFn for OtlpHistogram {
+    fn record(&self, value: f64) {
+        self.histogram.record(value, &self.attributes);
+    }
+}
@@ -0,0 +1,41 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Packet capture at the taps of the pipeline, driven from the cli
+
+use cli::cliproto::CaptureTarget;
+use mirror::{CaptureControl, CaptureFilter, CaptureOutput};
+use routing::{CaptureRequest, PacketCapture};
+
+/// The taps of the pipeline, as seen by the cli
+pub(crate) struct PipelineCapture(CaptureControl);
+
+impl PipelineCapture {
+    pub(crate) fn new(control: CaptureControl) -> Self {
+        Self(control)
+    }
+}
+
+impl PacketCapture for PipelineCapture {
+    fn start(&self, request: CaptureRequest) -> std::io::Result<()> {
+        let filter = CaptureFilter {
+            tap: request.tap,
+            vpcd: request.vpcd,
+            prefix: request.prefix,
+            port: request.port,
+        };
+        let output = match request.target {
+            CaptureTarget::File(name) => CaptureOutput::File(name),
+            CaptureTarget::Socket(name) => CaptureOutput::Socket(name),
+        };
+        self.0.start(filter, output, request.count)
+    }
+
+    fn stop(&self) -> Option<String> {
+        self.0.stop()
+    }
+
+    fn status(&self) -> String {
+        self.0.to_string()
+    }
+}
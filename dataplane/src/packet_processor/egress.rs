@@ -6,6 +6,7 @@
 use std::net::IpAddr;
 use tracing::{debug, error, warn};
 
+use concurrency::sync::Arc;
 use net::buffer::PacketBufferMut;
 use net::eth::Eth;
 use net::eth::ethtype::EthType;
@@ -13,18 +14,27 @@ use net::eth::mac::DestinationMac;
 use net::headers::{TryEthMut, TryIpv4, TryIpv6};
 use net::interface::InterfaceIndex;
 use net::packet::{DoneReason, Packet};
-use pipeline::NetworkFunction;
+use pipeline::{NetworkFunction, PipelineData};
 
-use routing::{AtableReader, IfState, IfTable, IfTableReader, IfType, Interface};
+use routing::{
+    AtResolveRequester, AtableReader, IfState, IfTable, IfTableReader, IfType, Interface,
+};
+
+use super::resolution::{Hold, NextHop, PendingResolutions};
 
 use tracectl::trace_target;
 trace_target!("egress", LevelFilter::WARN, &["pipeline"]);
 
 #[allow(unused)]
-pub struct Egress {
+pub struct Egress<Buf: PacketBufferMut> {
     name: String,
     iftr: IfTableReader,
     atabler: AtableReader,
+    requester: AtResolveRequester,
+    pending: PendingResolutions<Buf>,
+    pipeline_data: Arc<PipelineData>,
+    /// Whether the pipeline was told that this stage holds packets
+    holding: bool,
 }
 
 #[allow(dead_code)]
@@ -42,16 +52,34 @@ fn determine_ether_type<Buf: PacketBufferMut>(packet: &Packet<Buf>) -> Option<Et
 }
 
 #[allow(dead_code)]
-impl Egress {
-    pub fn new(name: &str, iftr: IfTableReader, atabler: AtableReader) -> Self {
+impl<Buf: PacketBufferMut> Egress<Buf> {
+    pub fn new(
+        name: &str,
+        iftr: IfTableReader,
+        atabler: AtableReader,
+        requester: AtResolveRequester,
+    ) -> Self {
         let name = name.to_owned();
         Self {
             name,
             iftr,
             atabler,
+            requester,
+            pending: PendingResolutions::new(),
+            pipeline_data: Arc::from(PipelineData::default()),
+            holding: false,
+        }
+    }
+
+    /// Tell the pipeline whether this stage holds packets, if that changed
+    fn update_holding(&mut self) {
+        let holding = !self.pending.is_empty();
+        if holding != self.holding {
+            self.pipeline_data.set_holding(holding);
+            self.holding = holding;
         }
     }
-    fn interface_egress_ethernet<Buf: PacketBufferMut>(
+    fn interface_egress_ethernet(
         &self,
         interface: &Interface,
         dst_mac: DestinationMac,
@@ -87,7 +115,7 @@ impl Egress {
         packet.done(DoneReason::Delivered);
     }
 
-    fn interface_egress<Buf: PacketBufferMut>(
+    fn interface_egress(
         &self,
         interface: &Interface,
         packet: &mut Packet<Buf>,
@@ -107,73 +135,75 @@ impl Egress {
         }
     }
 
-    fn get_adj_mac<Buf: PacketBufferMut>(
+    /// Get the MAC of the adjacency for `addr` over the interface with the given ifindex.
+    /// If there is no such adjacency, the next-hop to resolve is returned as an error.
+    fn get_adj_mac(
         &self,
         packet: &mut Packet<Buf>,
         addr: IpAddr,
         ifindex: InterfaceIndex,
-    ) -> Option<DestinationMac> {
+    ) -> Result<Option<DestinationMac>, NextHop> {
         let nfi = &self.name;
 
         if let Some(atable) = self.atabler.enter() {
             /* do lookup on the adjacency table */
             let Some(adj) = atable.get_adjacency(addr, ifindex) else {
-                warn!("{nfi}: missing L2 info for {addr}");
-
-                /* Todo: Trigger ARP */
-
-                packet.done(DoneReason::MissL2resolution);
-                return None;
+                debug!("{nfi}: missing L2 info for {addr}");
+                return Err((addr, ifindex));
             };
             /* get the mac from the adjacency */
             let adj_mac = adj.get_mac();
             let Ok(dst_mac) = DestinationMac::new(adj_mac) else {
                 warn!("{nfi}, Can't use mac {adj_mac} as destination!");
                 packet.done(DoneReason::InvalidDstMac);
-                return None;
+                return Ok(None);
             };
-            Some(dst_mac)
+            Ok(Some(dst_mac))
         } else {
             warn!("{nfi}: atable not readable!");
             packet.done(DoneReason::InternalFailure);
-            None
+            Ok(None)
         }
     }
 
-    fn resolve_next_mac<Buf: PacketBufferMut>(
+    fn resolve_next_mac(
         &self,
         ifindex: InterfaceIndex,
         packet: &mut Packet<Buf>,
-    ) -> Option<DestinationMac> {
+    ) -> Result<Option<DestinationMac>, NextHop> {
         let nfi = &self.name;
         // if packet was annotated with a next-hop address, try to resolve it using the
         // adjacency table. Otherwise, that means that the packet is directly connected
         // to us (on the same subnet). So, fetch the destination IP address and try to
-        // resolve it with the adjacency table as well. If that fails, that's where the
-        // ARP/ND would need to be triggered.
+        // resolve it with the adjacency table as well. If that fails, the next-hop needs
+        // to be resolved with ARP/ND.
         if let Some(nh_addr) = packet.meta().nh_addr {
             self.get_adj_mac(packet, nh_addr, ifindex)
         } else if let Some(destination) = packet.ip_destination() {
             self.get_adj_mac(packet, destination, ifindex)
         } else {
             warn!("{nfi}: could not determine packet destination IP address");
-            None
+            Ok(None)
         }
     }
 
+    /// Process a packet for egress. If the L2 address of its next-hop is unknown, the packet
+    /// is left untouched and the next-hop to resolve is returned.
     #[inline]
-    fn egress_process<Buf: PacketBufferMut>(&self, packet: &mut Packet<Buf>, iftable: &IfTable) {
+    fn egress_process(&self, packet: &mut Packet<Buf>, iftable: &IfTable) -> Option<NextHop> {
         let Some(oif) = packet.meta().oif else {
             warn!("{}: Missing oif metadata!", &self.name);
             packet.done(DoneReason::RouteFailure);
-            return;
+            return None;
         };
 
         /* resolve destination mac */
-        let Some(dst_mac) = self.resolve_next_mac(oif, packet) else {
+        let dst_mac = match self.resolve_next_mac(oif, packet) {
+            Ok(Some(dst_mac)) => dst_mac,
             // we could not figure out the destination MAC.
             // resolve_next_mac() already calls packet.done()
-            return;
+            Ok(None) => return None,
+            Err(nexthop) => return Some(nexthop),
         };
 
         /* get interface to send packet over */
@@ -183,24 +213,100 @@ impl Egress {
             warn!("{}: Unknown interface with id {oif}", &self.name);
             packet.done(DoneReason::InterfaceUnknown);
         }
+        None
+    }
+
+    /// Hold a packet until its next-hop gets resolved, triggering the resolution if the
+    /// packet is the first one to wait for it. Packets that can't be held are dropped.
+    fn hold(&mut self, nexthop: NextHop, packet: Packet<Buf>) -> Option<Packet<Buf>> {
+        let (addr, ifindex) = nexthop;
+        match self.pending.hold(nexthop, packet) {
+            Hold::First => {
+                debug!("{}: resolving {addr} over {ifindex}", &self.name);
+                self.requester.request(addr, ifindex);
+                self.update_holding();
+                None
+            }
+            Hold::Queued => None,
+            Hold::Full(mut packet) => {
+                warn!("{}: missing L2 info for {addr}", &self.name);
+                packet.done(DoneReason::MissL2resolution);
+                packet.enforce()
+            }
+        }
+    }
+
+    /// Release the packets held for the next-hops that got resolved, processing them for
+    /// egress, as well as those whose next-hop did not resolve in time, to be dropped.
+    fn release_pending(&mut self) -> Vec<Packet<Buf>> {
+        if self.pending.is_empty() {
+            return Vec::new();
+        }
+        let Some(atable) = self.atabler.enter() else {
+            return Vec::new();
+        };
+        let released = self
+            .pending
+            .release(|(addr, ifindex)| atable.get_adjacency(*addr, *ifindex).is_some());
+        drop(atable);
+        self.update_holding();
+
+        let iftable = self.iftr.enter();
+        released
+            .into_iter()
+            .filter_map(|mut packet| {
+                if !packet.is_done() {
+                    if let Some(iftable) = &iftable {
+                        if self.egress_process(&mut packet, iftable).is_some() {
+                            packet.done(DoneReason::MissL2resolution);
+                        }
+                    } else {
+                        warn!("{}: Fib iftable no longer readable!", &self.name);
+                        packet.done(DoneReason::InternalFailure);
+                    }
+                }
+                packet.enforce()
+            })
+            .collect()
     }
 }
 
-impl<Buf: PacketBufferMut> NetworkFunction<Buf> for Egress {
+impl<Buf: PacketBufferMut> Drop for Egress<Buf> {
+    fn drop(&mut self) {
+        if self.holding {
+            self.pipeline_data.set_holding(false);
+        }
+    }
+}
+
+impl<Buf: PacketBufferMut> NetworkFunction<Buf> for Egress<Buf> {
     fn process<'a, Input: Iterator<Item = Packet<Buf>> + 'a>(
         &'a mut self,
         input: Input,
     ) -> impl Iterator<Item = Packet<Buf>> + 'a {
-        input.filter_map(move |mut packet| {
-            if !packet.is_done() {
-                if let Some(iftable) = self.iftr.enter() {
-                    self.egress_process(&mut packet, &iftable);
-                } else {
-                    warn!("{}: Fib iftable no longer readable!", &self.name);
-                    packet.done(DoneReason::InternalFailure);
+        // packets held for resolution are checked when the stage runs, which the pipeline
+        // makes happen periodically while packets are held, even with no traffic
+        let released = self.release_pending();
+        released
+            .into_iter()
+            .chain(input.filter_map(move |mut packet| {
+                if !packet.is_done() {
+                    let unresolved = if let Some(iftable) = self.iftr.enter() {
+                        self.egress_process(&mut packet, &iftable)
+                    } else {
+                        warn!("{}: Fib iftable no longer readable!", &self.name);
+                        packet.done(DoneReason::InternalFailure);
+                        None
+                    };
+                    if let Some(nexthop) = unresolved {
+                        return self.hold(nexthop, packet);
+                    }
                 }
-            }
-            packet.enforce()
-        })
+                packet.enforce()
+            }))
+    }
+
+    fn set_data(&mut self, data: Arc<PipelineData>) {
+        self.pipeline_data = data;
     }
 }
@@ -2,11 +2,20 @@
 // Copyright Open Network Fabric Authors
 //
 //! Implements an ingress stage
+//!
+//! Besides assigning packets to the VRF of their incoming interface, ingress applies a policy to
+//! the packets for destinations which are not meant to be routed (see [`DestinationClass`]):
+//! forward them as the others, punt them to the kernel or drop them. Packets of each class are
+//! counted in `ingress_reserved_destination_packets`, labelled with the class and the action.
 
 #![allow(clippy::collapsible_if)]
 #[allow(unused)]
 use tracing::{debug, trace, warn};
 
+use std::net::IpAddr;
+
+use args::DestinationAction;
+use metrics::{Counter, Unit};
 use net::buffer::PacketBufferMut;
 use net::eth::mac::Mac;
 use net::headers::{TryEth, TryIp};
@@ -14,23 +23,86 @@ use net::packet::{DoneReason, Packet};
 use pipeline::NetworkFunction;
 
 use routing::{Attachment, IfState, IfTableReader, IfType, Interface};
+use stats::{MetricSpec, Register};
 
 use tracectl::trace_target;
 trace_target!("ingress", LevelFilter::WARN, &["pipeline"]);
 
+/// The classes of destinations which are not meant to be routed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum DestinationClass {
+    /// 169.254.0.0/16 and fe80::/10
+    LinkLocal,
+    /// 224.0.0.0/4 and ff00::/8
+    Multicast,
+}
+
+impl DestinationClass {
+    /// All the classes, in the order of their discriminants
+    const ALL: [DestinationClass; 2] = [DestinationClass::LinkLocal, DestinationClass::Multicast];
+
+    fn label(self) -> &'static str {
+        match self {
+            DestinationClass::LinkLocal => "link-local",
+            DestinationClass::Multicast => "multicast",
+        }
+    }
+
+    /// The class of the destination of an IP packet, if it has one
+    fn of<Buf: PacketBufferMut>(packet: &Packet<Buf>) -> Option<Self> {
+        match packet.try_ip()?.dst_addr() {
+            IpAddr::V4(address) if address.is_link_local() => Some(DestinationClass::LinkLocal),
+            IpAddr::V6(address) if address.is_unicast_link_local() => {
+                Some(DestinationClass::LinkLocal)
+            }
+            address if address.is_multicast() => Some(DestinationClass::Multicast),
+            _ => None,
+        }
+    }
+}
+
+/// The actions of ingress on the packets for each [`DestinationClass`]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct IngressPolicy {
+    pub link_local: DestinationAction,
+    pub multicast: DestinationAction,
+}
+
+impl IngressPolicy {
+    fn action(&self, class: DestinationClass) -> DestinationAction {
+        match class {
+            DestinationClass::LinkLocal => self.link_local,
+            DestinationClass::Multicast => self.multicast,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct Ingress {
     name: String,
     iftr: IfTableReader,
+    policy: IngressPolicy,
+    /// Packets of each [`DestinationClass`]. They aggregate those of all workers.
+    reserved: [Counter; DestinationClass::ALL.len()],
 }
 
 #[allow(dead_code)]
 impl Ingress {
     /// Creates a new [`Ingress`] stage
-    pub fn new(name: &str, iftr: IfTableReader) -> Self {
+    pub fn new(name: &str, iftr: IfTableReader, policy: IngressPolicy) -> Self {
         Self {
             name: name.to_owned(),
             iftr,
+            policy,
+            reserved: DestinationClass::ALL.map(|class| {
+                let labels = vec![
+                    ("class".to_string(), class.label().to_string()),
+                    ("action".to_string(), policy.action(class).to_string()),
+                ];
+                MetricSpec::new("ingress_reserved_destination_packets", Unit::Count, labels)
+                    .register()
+                    .metric
+            }),
         }
     }
 
@@ -38,7 +110,28 @@ impl Ingress {
         &self.name
     }
 
-    fn interface_ingress_eth_ucast_local<Buf: PacketBufferMut>(
+    /// Apply the policy to the packets for link-local and multicast destinations
+    fn apply_destination_policy<Buf: PacketBufferMut>(&self, packet: &mut Packet<Buf>) {
+        let Some(class) = DestinationClass::of(packet) else {
+            return;
+        };
+        self.reserved[class as usize].increment(1);
+        let nfi = self.name();
+        match self.policy.action(class) {
+            DestinationAction::Forward => {}
+            DestinationAction::Punt => {
+                debug!("{nfi}: Punting packet for {} destination", class.label());
+                packet.done(DoneReason::Local);
+            }
+            DestinationAction::Drop => {
+                debug!("{nfi}: Dropping packet for {} destination", class.label());
+                packet.done(DoneReason::Filtered);
+            }
+        }
+    }
+
+    /// Process a frame for the gateway: unicast to the mac of the interface, or multicast
+    fn interface_ingress_eth_local<Buf: PacketBufferMut>(
         &self,
         interface: &Interface,
         packet: &mut Packet<Buf>,
@@ -55,6 +148,7 @@ impl Ingress {
                 let vrfid = fibkey.as_u32();
                 debug!("{nfi}: Packet is for VRF {vrfid}");
                 packet.meta_mut().vrf = Some(vrfid);
+                self.apply_destination_policy(packet);
             }
             Some(Attachment::BridgeDomain) => {
                 debug!("{nfi}: Bridge domains are not supported");
@@ -99,6 +193,25 @@ impl Ingress {
         );
     }
 
+    #[tracing::instrument(level = "trace")]
+    fn interface_ingress_eth_mcast<Buf: PacketBufferMut>(
+        &self,
+        interface: &Interface,
+        dst_mac: Mac,
+        packet: &mut Packet<Buf>,
+    ) {
+        if DestinationClass::of(packet) == Some(DestinationClass::Multicast) {
+            self.interface_ingress_eth_local(interface, packet);
+        } else {
+            debug!(
+                "{nfi}: Ignoring frame for multicast mac {dst_mac} without multicast destination over {ifname}",
+                nfi = self.name(),
+                ifname = interface.name
+            );
+            packet.done(DoneReason::Unhandled);
+        }
+    }
+
     #[tracing::instrument(level = "trace")]
     fn interface_ingress_eth<Buf: PacketBufferMut>(
         &self,
@@ -117,8 +230,10 @@ impl Ingress {
                     let dmac = eth.destination().inner();
                     if dmac.is_broadcast() {
                         self.interface_ingress_eth_bcast(interface, packet);
+                    } else if dmac.is_multicast() {
+                        self.interface_ingress_eth_mcast(interface, dmac, packet);
                     } else if dmac == if_mac.inner() {
-                        self.interface_ingress_eth_ucast_local(interface, packet);
+                        self.interface_ingress_eth_local(interface, packet);
                     } else {
                         self.interface_ingress_eth_non_local(interface, dmac, packet);
                     }
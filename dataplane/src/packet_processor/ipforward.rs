@@ -5,7 +5,7 @@
 
 #![allow(clippy::similar_names)]
 
-use net::headers::{TryHeadersMut, TryIpv4Mut, TryIpv6Mut};
+use net::headers::{TryHeadersMut, TryIp, TryIpMut, TryIpv4Mut, TryIpv6Mut};
 use net::packet::{DoneReason, Packet};
 use net::{buffer::PacketBufferMut, checksum::Checksum};
 use pipeline::NetworkFunction;
@@ -20,11 +20,15 @@ use routing::{
 use net::headers::{Headers, Net};
 use net::interface::InterfaceIndex;
 use net::ip::NextHeader;
+use net::ip::dscp::Dscp;
 use net::ipv4::{Ipv4, UnicastIpv4Addr};
 use net::ipv6::{Ipv6, UnicastIpv6Addr};
 use net::packet::VpcDiscriminant;
 use net::udp::UdpEncap;
-use net::vxlan::{Vxlan, VxlanEncap};
+use net::vxlan::{Vni, Vxlan, VxlanEncap};
+
+use config::external::qos::{DecapDscp, EncapDscp};
+use qos::QosTableReader;
 
 use tracectl::{custom_target, tdebug, trace_target};
 trace_target!("ip-forward", LevelFilter::WARN, &["pipeline"]);
@@ -37,15 +41,66 @@ custom_target!(VXLAN_E, LevelFilter::OFF, &["vxlan"]);
 pub struct IpForwarder {
     name: String,
     fibtr: FibTableReader,
+    qostr: QosTableReader,
 }
 
 impl IpForwarder {
-    /// Build a new IP forwarding stage to use the indicated [`FibTableReader`]
+    /// Build a new IP forwarding stage to use the indicated [`FibTableReader`], and the DSCP
+    /// policies of the VXLAN traffic of VPCs in the indicated [`QosTableReader`]
     #[must_use]
-    pub fn new(name: &str, fibtr: FibTableReader) -> Self {
+    pub fn new(name: &str, fibtr: FibTableReader, qostr: QosTableReader) -> Self {
         Self {
             name: name.to_owned(),
             fibtr,
+            qostr,
+        }
+    }
+
+    /// The DSCP of the IP header of a packet, which is the inner one if encapsulated
+    fn inner_dscp<Buf: PacketBufferMut>(packet: &Packet<Buf>) -> Option<Dscp> {
+        match packet.headers().try_ip()? {
+            Net::Ipv4(ipv4) => Some(Dscp::from(ipv4.dscp())),
+            Net::Ipv6(ipv6) => Some(Dscp::from(ipv6.dscp())),
+        }
+    }
+
+    /// Rewrite the DSCP of the IP header of a decapsulated packet
+    fn set_inner_dscp<Buf: PacketBufferMut>(packet: &mut Packet<Buf>, dscp: Dscp) {
+        match packet.headers_mut().try_ip_mut() {
+            Some(Net::Ipv4(ipv4)) => {
+                ipv4.set_dscp(dscp);
+                ipv4.update_checksum(&())
+                    .unwrap_or_else(|()| unreachable!()); // IPv4 checksum update never fails
+            }
+            Some(Net::Ipv6(ipv6)) => {
+                ipv6.set_dscp(dscp);
+            }
+            None => {}
+        }
+    }
+
+    /// Apply the decap DSCP policy of the VPC of `vni` to a decapsulated packet
+    fn apply_decap_dscp<Buf: PacketBufferMut>(&self, packet: &mut Packet<Buf>, vni: Vni) {
+        let policy = self.qostr.tunnel_dscp(&VpcDiscriminant::VNI(vni));
+        if let Some(DecapDscp::Copy) = policy.map(|policy| policy.decap)
+            && let Some(dscp) = packet.meta().dscp
+        {
+            Self::set_inner_dscp(packet, dscp);
+        }
+    }
+
+    /// Set the outer DSCP that a packet will get when encapsulated with `vni`, as per the encap
+    /// DSCP policy of the VPC of `vni`.
+    fn apply_encap_dscp<Buf: PacketBufferMut>(&self, packet: &mut Packet<Buf>, vni: Vni) {
+        let policy = self.qostr.tunnel_dscp(&VpcDiscriminant::VNI(vni));
+        match policy.map(|policy| policy.encap).unwrap_or_default() {
+            EncapDscp::Preserve => {}
+            EncapDscp::Copy => packet.meta_mut().dscp = Self::inner_dscp(packet),
+            EncapDscp::Fixed(dscp) => {
+                // validation ensures that fixed DSCPs are legal
+                packet.meta_mut().dscp =
+                    Some(Dscp::new(dscp).unwrap_or_else(|e| unreachable!("{e}")));
+            }
         }
     }
 
@@ -136,6 +191,7 @@ impl IpForwarder {
                 let vni = vxlan.vni();
                 tdebug!(VXLAN_D, "DECAPSULATED vxlan packet (vni={vni}):\n{packet}");
                 debug!("{nfi}: DECAPSULATED vxlan packet, vni = {vni}");
+                self.apply_decap_dscp(packet, vni);
 
                 // access fib for Vni vni
                 let fibkey = FibKey::from_vni(vni);
@@ -263,6 +319,9 @@ impl IpForwarder {
             unreachable!()
         }
 
+        // set the outer DSCP as per the policy of the VPC
+        self.apply_encap_dscp(packet, vxlan.vni);
+
         // build vxlan headers for encapsulation
         match Self::build_vxlan_headers(vxlan, vtep) {
             Err(e) => {
@@ -1,37 +1,64 @@
 // SPDX-License-Identifier: Apache-2.0
 // Copyright Open Network Fabric Authors
 
+mod capture;
 mod egress;
 mod ingress;
 mod ipforward;
+mod oam;
+mod policy;
+mod resolution;
+mod simulate;
+mod urpf;
 
+use super::drivers::kernel::MicroburstLog;
+use super::packet_processor::capture::PipelineCapture;
 #[allow(unused)]
 use super::packet_processor::egress::Egress;
 use super::packet_processor::ingress::Ingress;
+pub(crate) use super::packet_processor::ingress::IngressPolicy;
 use super::packet_processor::ipforward::IpForwarder;
+pub(crate) use super::packet_processor::oam::OamParams;
+use super::packet_processor::oam::{OamControl, OamSource, OamTap, ProbeBuffer};
+use super::packet_processor::policy::PolicyClassifier;
+use super::packet_processor::simulate::PipelineSimulator;
+use super::packet_processor::urpf::UrpfValidator;
 
 use concurrency::sync::Arc;
+use config::GenId;
+use std::path::Path;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tracing::warn;
 
 use acl_filter::{AclFilter, AclFilterContextWriter};
 use flow_entry::flow_table::{FlowLookup, FlowTable};
 use flow_filter::{FlowFilter, FlowFilterTableWriter};
+use kvstore::KvStore;
+use mgmt::vpc_manager::InterfaceView;
 
-use nat::masquerade::NatAllocatorWriter;
+use mirror::{CaptureControl, Mirror, MirrorExporter, MirrorTableWriter, PcapTap};
+use nat::masquerade::{NatAllocatorWriter, NatLeases};
 use nat::portfw::{PortForwarder, PortFwTableWriter};
 use nat::static_nat::NatTablesWriter;
-use nat::{IcmpErrorHandler, Masquerade, StaticNat};
+use nat::{AlgConfig, AlgStats, IcmpErrorHandler, Masquerade, StaticNat};
 use net::packet::PacketStats;
+use qos::{QosScheduler, QosTableWriter};
 
 use net::buffer::PacketBufferMut;
 use pipeline::sample_nfs::{PacketDumper, PacketStatsNF};
 use pipeline::{DynPipeline, PipelineData};
 
-use routing::{CliSources, Router, RouterError, RouterParams};
+use routing::{BmpStore, CliSources, Router, RouterError, RouterParams};
 
 use vpcmap::map::VpcMapWriter;
 
 use stats::{Stats, StatsCollector, VpcMapName, VpcStatsStore};
 
+/// Period of the batches processed while stages hold packets, such as those waiting for the
+/// resolution of their next-hop or for their shaper, so that they are released with no traffic
+const DRAIN_PERIOD: Duration = Duration::from_millis(10);
+
 pub(crate) struct InternalSetup<Buf>
 where
     Buf: PacketBufferMut,
@@ -47,12 +74,25 @@ where
     pub stats: StatsCollector,
     pub vpc_stats_store: Arc<VpcStatsStore>,
     pub portfw_w: PortFwTableWriter,
+    pub qostablesw: QosTableWriter,
+    pub mirrortablesw: MirrorTableWriter,
+    pub interface_view: InterfaceView,
+    pub rollback_requests: mpsc::Receiver<GenId>,
 }
 
 /// Start a router and provide the associated pipeline
-pub(crate) fn start_router<Buf: PacketBufferMut>(
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn start_router<Buf: ProbeBuffer>(
     router: &lifecycle::Subsystem,
     params: RouterParams,
+    alg: AlgConfig,
+    nat_leases: Option<Arc<NatLeases>>,
+    ingress: IngressPolicy,
+    state_store: Arc<dyn KvStore>,
+    microbursts: MicroburstLog,
+    oam_params: OamParams,
+    bmp_store: Option<Arc<BmpStore>>,
+    capture_dir: &Path,
 ) -> Result<InternalSetup<Buf>, RouterError> {
     let vpcmapw = VpcMapWriter::<VpcMapName>::new();
     let vpc_stats_store: Arc<VpcStatsStore> = VpcStatsStore::new();
@@ -69,13 +109,35 @@ pub(crate) fn start_router<Buf: PacketBufferMut>(
     let aclfiltertablesw = AclFilterContextWriter::new();
     let aclfiltertablesr_factory = aclfiltertablesw.get_reader_factory();
     let nattablesw = NatTablesWriter::new();
-    let natallocatorw = NatAllocatorWriter::new();
+    let natallocatorw = match nat_leases {
+        Some(leases) => NatAllocatorWriter::new().with_leases(leases),
+        None => NatAllocatorWriter::new(),
+    };
     let nattabler_factory = nattablesw.get_reader_factory();
     let natallocator_factory = natallocatorw.get_reader_factory();
     let portfw_w = PortFwTableWriter::new();
     let portfw_factory = portfw_w.reader().factory();
+    let qostablesw = QosTableWriter::new();
+    let qostablesr_factory = qostablesw.get_reader_factory();
+    let mirrortablesw = MirrorTableWriter::new();
+    let mirrortablesr_factory = mirrortablesw.get_reader_factory();
+    let (mirror_exporter, mirror_sender) = MirrorExporter::new();
+    if let Err(e) = mirror_exporter.spawn() {
+        warn!("Failed to spawn the mirror exporter: {e}");
+    }
+    let capture = CaptureControl::new(capture_dir);
+    let interface_view = InterfaceView::new();
     let pdata = Arc::from(PipelineData::new(0));
     let pkt_stats = Arc::from(PacketStats::new());
+    let alg_stats = Arc::new(AlgStats::default());
+    let (config_rollback, rollback_requests) = mpsc::channel(1);
+    let oam = OamControl::new(&oam_params.probes, pdata.clone());
+    if let Err(e) = oam.start(oam_params.interval) {
+        warn!("Failed to spawn the OAM prober: {e}");
+    }
+    if let Err(e) = PipelineData::start_drain(&pdata, DRAIN_PERIOD, router.cancel_token()) {
+        warn!("Failed to spawn the pipeline drain: {e}");
+    }
 
     // collect readers and the like for cli
     let cli_sources = CliSources {
@@ -84,7 +146,29 @@ pub(crate) fn start_router<Buf: PacketBufferMut>(
         portfw_table: Some(Box::new(portfw_w.reader().inner())),
         nat_tables: Some(Box::new(nattabler_factory.handle().inner())),
         masquerade_state: Some(Box::new(natallocator_factory.handle().inner())),
+        nat_alg: Some(Box::new(alg_stats.clone())),
         pkt_stats: Some(Box::new(pkt_stats.clone())),
+        flow_simulator: Some(Box::new(PipelineSimulator::new(
+            flowfiltertablesw.get_reader_factory(),
+            nattablesw.get_reader_factory(),
+        ))),
+        packet_capture: Some(Box::new(PipelineCapture::new(capture.clone()))),
+        interfaces: Some(Box::new(interface_view.clone())),
+        microbursts: Some(Box::new(microbursts)),
+        state_store: Some(state_store),
+        bmp_store,
+        table_generations: vec![
+            ("vpc-map", Box::new(vpcmapw.get_reader().inner())),
+            (
+                "flow-filter",
+                Box::new(flowfiltertablesr_factory.handle().inner()),
+            ),
+            ("static-nat", Box::new(nattabler_factory.handle().inner())),
+            ("port-forwarding", Box::new(portfw_w.reader().inner())),
+            ("qos", Box::new(qostablesr_factory.handle().inner())),
+            ("mirror", Box::new(mirrortablesr_factory.handle().inner())),
+        ],
+        config_rollback: Some(config_rollback),
     };
 
     // create router
@@ -92,6 +176,8 @@ pub(crate) fn start_router<Buf: PacketBufferMut>(
     let iftr_factory = router.get_iftabler_factory();
     let fibtr_factory = router.get_fibtr_factory();
     let atabler_factory = router.get_atabler_factory();
+    let atable_requester = router.get_atable_requester();
+    let policyr_factory = router.get_policy_classr_factory();
 
     // create pipeline builder
     let flow_table_clone = flow_table.clone();
@@ -99,16 +185,31 @@ pub(crate) fn start_router<Buf: PacketBufferMut>(
         let pdata_clone = pdata.clone();
 
         // Build network functions
-        let stage_ingress = Ingress::new("Ingress", iftr_factory.handle());
-        let stage_egress = Egress::new("Egress", iftr_factory.handle(), atabler_factory.handle());
-        let iprouter1 = IpForwarder::new("IP-Forward-1", fibtr_factory.handle());
-        let iprouter2 = IpForwarder::new("IP-Forward-2", fibtr_factory.handle());
+        let stage_ingress = Ingress::new("Ingress", iftr_factory.handle(), ingress);
+        let urpf = UrpfValidator::new("uRPF", iftr_factory.handle(), fibtr_factory.handle());
+        let stage_egress = Egress::new(
+            "Egress",
+            iftr_factory.handle(),
+            atabler_factory.handle(),
+            atable_requester.clone(),
+        );
+        let iprouter1 = IpForwarder::new(
+            "IP-Forward-1",
+            fibtr_factory.handle(),
+            qostablesr_factory.handle(),
+        );
+        let iprouter2 = IpForwarder::new(
+            "IP-Forward-2",
+            fibtr_factory.handle(),
+            qostablesr_factory.handle(),
+        );
         let static_nat = StaticNat::with_reader("static-NAT-1", nattabler_factory.handle());
         let masquerade = Masquerade::new(
             "masquerade",
             flow_table_clone.clone(),
             natallocator_factory.handle(),
-        );
+        )
+        .with_alg(alg, alg_stats.clone());
         let pktdump = PacketDumper::new("pipeline-end", true, None);
         let stats_stage = Stats::new("stats", stats_w.clone());
         let flow_filter = FlowFilter::new("flow-filter", flowfiltertablesr_factory.handle());
@@ -121,22 +222,41 @@ pub(crate) fn start_router<Buf: PacketBufferMut>(
             flow_table_clone.clone(),
         );
         let pkt_stats_nf = PacketStatsNF::new(pkt_stats.clone());
+        let policy_classifier = PolicyClassifier::new("policy-class", policyr_factory.handle());
+        let qos_scheduler = QosScheduler::new("qos-scheduler", qostablesr_factory.handle());
+        let mirror = Mirror::new(
+            "mirror",
+            mirrortablesr_factory.handle(),
+            mirror_sender.clone(),
+        );
+        let tap_pre_filter = PcapTap::new("pre-filter", capture.clone());
+        let tap_post_nat = PcapTap::new("post-nat", capture.clone());
+        let oam_source = OamSource::new("oam-source", oam.clone(), fibtr_factory.handle());
+        let oam_tap = OamTap::new(oam.clone());
 
         // Build the pipeline for a router. The composition of the pipeline (in stages) is currently
         // hard-coded. Flow expiration is handled by per-flow tokio timers; no ExpirationsNF needed.
         DynPipeline::new()
             .set_data(pdata_clone)
             .add_stage(stage_ingress)
+            .add_stage(urpf)
             .add_stage(iprouter1)
+            .add_stage(oam_source)
             .add_stage(icmp_error_handler)
             .add_stage(flow_lookup)
+            .add_stage(policy_classifier)
+            .add_stage(tap_pre_filter)
             .add_stage(flow_filter)
             .add_stage(acl_filter)
             .add_stage(static_nat)
             .add_stage(portfw)
             .add_stage(masquerade)
+            .add_stage(tap_post_nat)
             .add_stage(iprouter2)
+            .add_stage(mirror)
+            .add_stage(qos_scheduler)
             .add_stage(stage_egress)
+            .add_stage(oam_tap)
             .add_stage(pktdump)
             .add_stage(pkt_stats_nf)
             .add_stage(stats_stage)
@@ -154,5 +274,9 @@ pub(crate) fn start_router<Buf: PacketBufferMut>(
         stats,
         vpc_stats_store,
         portfw_w,
+        qostablesw,
+        mirrortablesw,
+        interface_view,
+        rollback_requests,
     })
 }
@@ -0,0 +1,462 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Verification of the connectivity between endpoints of VPCs with probes originated by the
+//! gateway (OAM).
+//!
+//! The paths verified are given with `--oam-probe`, each from an endpoint of a VPC to an endpoint
+//! of another VPC. Every probe interval, the prober originates a probe on each path: a UDP packet
+//! from the source endpoint to the destination endpoint, which the [`OamSource`] stage injects in
+//! the pipeline as if it had just been decapsulated from the VNI of the source VPC. The probe then
+//! goes through the stages that the traffic of the VPC goes through: flow filtering, NAT, routing
+//! and encapsulation. The [`OamTap`] stage, at the end of the pipeline, consumes the probes, so
+//! that they are never transmitted, and checks that they were delivered encapsulated with the VNI
+//! of the VPC expected. Once the forward leg of a probe is verified, the tap originates its return
+//! leg: a reply from the addresses and ports the forward leg was delivered with, which the
+//! pipeline must deliver back to the source endpoint, in the VPC of the source endpoint.
+//!
+//! A path is up if its last probe made it there and back before the next one was originated. The
+//! latency of a path is the time from the origination of a probe to the verification of its return
+//! leg. The workers process empty batches when probes are originated, so that paths are verified
+//! with no traffic at all. Per path, labeled with the path, the prober exports:
+//! - `oam_path_up`, 1 if the path is up and 0 otherwise,
+//! - `oam_path_latency`, the latency of the probes that made it back,
+//! - `oam_probe_failures`, the probes that did not make it back.
+
+use std::collections::{HashMap, VecDeque};
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+use args::OamProbeArg;
+use concurrency::sync::atomic::{AtomicBool, Ordering};
+use concurrency::sync::{Arc, Mutex};
+use metrics::{Counter, Gauge, Histogram};
+use net::buffer::{PacketBufferMut, TestBuffer};
+use net::headers::builder::HeaderStack;
+use net::headers::{Headers, TryIpv4, TryUdp, TryVxlan};
+use net::ipv4::UnicastIpv4Addr;
+use net::packet::{DoneReason, Packet, VpcDiscriminant};
+use net::parse::{DeParse, Parse};
+use net::udp::port::UdpPort;
+use net::vxlan::Vni;
+use pipeline::{NetworkFunction, PipelineData};
+use routing::{FibKey, FibTableReader};
+use stats::{MetricSpec, Register};
+use tracing::{debug, info, warn};
+
+/// The UDP port the probes are sent from and to
+const OAM_PORT: u16 = 33434;
+
+/// The payload of a probe starts with this, followed by the id of the probe
+const PROBE_MAGIC: [u8; 8] = *b"DPOAMv1\0";
+
+/// The TTL of the probes
+const PROBE_TTL: u8 = 64;
+
+/// The paths verified with OAM probes, and the period of the probes
+pub(crate) struct OamParams {
+    pub probes: Vec<OamProbeArg>,
+    pub interval: Duration,
+}
+
+/// The buffers the probes are built in
+pub(crate) trait ProbeBuffer: PacketBufferMut {
+    /// A buffer holding a copy of `frame`
+    fn from_frame(frame: &[u8]) -> Self;
+}
+
+impl ProbeBuffer for TestBuffer {
+    fn from_frame(frame: &[u8]) -> Self {
+        TestBuffer::from_raw_data(frame)
+    }
+}
+
+/// The legs of a probe
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Leg {
+    /// From the source endpoint of the path to its destination endpoint
+    Forward,
+    /// Back to the source endpoint
+    Return,
+}
+
+/// A probe to inject in the pipeline
+struct Probe {
+    id: u64,
+    /// The VNI the probe is injected from
+    vni: Vni,
+    src: (Ipv4Addr, u16),
+    dst: (Ipv4Addr, u16),
+}
+
+/// A probe injected in the pipeline, expected at the tap
+struct InFlight {
+    path: usize,
+    leg: Leg,
+    /// When the forward leg of the probe was originated
+    originated: Instant,
+}
+
+/// A path verified with probes, and its metrics
+struct OamPath {
+    probe: OamProbeArg,
+    /// Whether the last probe made it there and back, if any was verified yet
+    up: Option<bool>,
+    up_gauge: Gauge,
+    latency: Histogram,
+    failures: Counter,
+}
+
+impl OamPath {
+    fn new(probe: OamProbeArg) -> Self {
+        let spec = |metric: &str, unit| {
+            MetricSpec::new(metric, unit, vec![("path".to_string(), probe.to_string())])
+        };
+        Self {
+            probe,
+            up: None,
+            up_gauge: spec("oam_path_up", metrics::Unit::Count).register().metric,
+            latency: spec("oam_path_latency", metrics::Unit::Seconds)
+                .register()
+                .metric,
+            failures: spec("oam_probe_failures", metrics::Unit::Count)
+                .register()
+                .metric,
+        }
+    }
+
+    /// Record that a probe made it there and back, after `latency`
+    fn succeeded(&mut self, latency: Duration) {
+        if self.up != Some(true) {
+            info!("OAM path {} is up", self.probe);
+        }
+        self.up = Some(true);
+        self.up_gauge.set(1.0);
+        self.latency.record(latency);
+    }
+
+    /// Record that a probe did not make it there and back, because of `reason`
+    fn failed(&mut self, reason: &str) {
+        if self.up == Some(false) {
+            debug!("OAM probe of path {} failed: {reason}", self.probe);
+        } else {
+            warn!("OAM path {} is down: {reason}", self.probe);
+        }
+        self.up = Some(false);
+        self.up_gauge.set(0.0);
+        self.failures.increment(1);
+    }
+}
+
+struct OamState {
+    paths: Vec<OamPath>,
+    /// The probes to inject in the pipeline
+    pending: VecDeque<Probe>,
+    in_flight: HashMap<u64, InFlight>,
+    next_id: u64,
+}
+
+impl OamState {
+    fn queue(
+        &mut self,
+        path: usize,
+        leg: Leg,
+        originated: Instant,
+        probe: impl FnOnce(u64) -> Probe,
+    ) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.in_flight.insert(
+            id,
+            InFlight {
+                path,
+                leg,
+                originated,
+            },
+        );
+        self.pending.push_back(probe(id));
+    }
+}
+
+/// The probes of the paths verified, shared by the prober and the OAM stages of the pipelines
+#[derive(Clone)]
+pub(crate) struct OamControl {
+    /// Whether probes are pending, checked by the sources before taking the lock
+    pending: Arc<AtomicBool>,
+    state: Arc<Mutex<OamState>>,
+    data: Arc<PipelineData>,
+}
+
+impl OamControl {
+    /// The control of the probes of the paths of `probes`, injected by the pipelines of `data`
+    pub(crate) fn new(probes: &[OamProbeArg], data: Arc<PipelineData>) -> Self {
+        let state = OamState {
+            paths: probes.iter().copied().map(OamPath::new).collect(),
+            pending: VecDeque::new(),
+            in_flight: HashMap::new(),
+            next_id: 0,
+        };
+        Self {
+            pending: Arc::new(AtomicBool::new(false)),
+            state: Arc::new(Mutex::new(state)),
+            data,
+        }
+    }
+
+    /// Have the pipelines inject the pending probes
+    fn request_injection(&self) {
+        self.pending.store(true, Ordering::Relaxed);
+        self.data.request_batch();
+    }
+
+    /// Originate a probe on each path. The paths whose previous probe is still in flight are
+    /// down.
+    fn originate(&self) {
+        let now = Instant::now();
+        {
+            let mut state = self.state.lock();
+            let lost: Vec<_> = state
+                .in_flight
+                .drain()
+                .map(|(_, probe)| (probe.path, probe.leg))
+                .collect();
+            for (path, leg) in lost {
+                let reason = match leg {
+                    Leg::Forward => "the probe did not reach the end of the pipeline",
+                    Leg::Return => "the reply did not reach the end of the pipeline",
+                };
+                state.paths[path].failed(reason);
+            }
+            state.pending.clear();
+            for path in 0..state.paths.len() {
+                let probe = state.paths[path].probe;
+                state.queue(path, Leg::Forward, now, |id| Probe {
+                    id,
+                    vni: probe.src_vni,
+                    src: (probe.src, OAM_PORT),
+                    dst: (probe.dst, OAM_PORT),
+                });
+            }
+        }
+        self.request_injection();
+    }
+
+    /// Originate a probe on each path every `interval`, in a thread of its own. Nothing is
+    /// originated if there are no paths.
+    pub(crate) fn start(&self, interval: Duration) -> std::io::Result<()> {
+        if self.state.lock().paths.is_empty() {
+            return Ok(());
+        }
+        let control = self.clone();
+        std::thread::Builder::new()
+            .name("oam-prober".to_string())
+            .spawn(move || {
+                loop {
+                    control.originate();
+                    std::thread::sleep(interval);
+                }
+            })?;
+        Ok(())
+    }
+
+    /// Take the probes to inject in the pipeline
+    fn take_pending(&self) -> Vec<Probe> {
+        if !self.pending.swap(false, Ordering::Relaxed) {
+            return vec![];
+        }
+        self.state.lock().pending.drain(..).collect()
+    }
+
+    /// Verify a probe, consumed by the tap at the end of the pipeline
+    fn verify<Buf: PacketBufferMut>(&self, packet: &Packet<Buf>) {
+        let Some((inner, id)) = probe_of(packet) else {
+            debug!("Ignoring a probe with no id");
+            return;
+        };
+        let mut state = self.state.lock();
+        let Some(probe) = state.in_flight.remove(&id) else {
+            debug!("Ignoring probe {id}, which is not in flight anymore");
+            return;
+        };
+        let path = state.paths[probe.path].probe;
+        let (expected, leg) = match probe.leg {
+            Leg::Forward => (path.dst_vni, "probe"),
+            Leg::Return => (path.src_vni, "reply"),
+        };
+        if let Err(reason) = outcome(packet, expected) {
+            state.paths[probe.path].failed(&format!("the {leg} {reason}"));
+            return;
+        }
+        let Some((src, dst)) = inner.as_ref().and_then(addresses) else {
+            state.paths[probe.path].failed(&format!("the {leg} lost its UDP header"));
+            return;
+        };
+        match probe.leg {
+            Leg::Forward => {
+                state.queue(probe.path, Leg::Return, probe.originated, |id| Probe {
+                    id,
+                    vni: path.dst_vni,
+                    src: dst,
+                    dst: src,
+                });
+                drop(state);
+                self.request_injection();
+            }
+            Leg::Return if dst != (path.src, OAM_PORT) => {
+                let reason = format!("the reply was delivered to {}:{}", dst.0, dst.1);
+                state.paths[probe.path].failed(&reason);
+            }
+            Leg::Return => state.paths[probe.path].succeeded(probe.originated.elapsed()),
+        }
+    }
+}
+
+/// The headers of the probe a packet carries, if it is encapsulated, and the id of the probe
+fn probe_of<Buf: PacketBufferMut>(packet: &Packet<Buf>) -> Option<(Option<Headers>, u64)> {
+    let payload = packet.payload().as_ref();
+    let (inner, data) = if packet.try_vxlan().is_some() {
+        let (headers, consumed) = Headers::parse(payload).ok()?;
+        (Some(headers), payload.get(usize::from(consumed.get())..)?)
+    } else {
+        (None, payload)
+    };
+    let id = data.strip_prefix(&PROBE_MAGIC)?.get(..8)?;
+    Some((inner, u64::from_be_bytes(id.try_into().ok()?)))
+}
+
+/// The source and destination addresses and ports of the headers of a probe
+fn addresses(headers: &Headers) -> Option<((Ipv4Addr, u16), (Ipv4Addr, u16))> {
+    let ipv4 = headers.try_ipv4()?;
+    let udp = headers.try_udp()?;
+    Some((
+        (ipv4.source().inner(), udp.source().as_u16()),
+        (ipv4.destination(), udp.destination().as_u16()),
+    ))
+}
+
+/// Check that the pipeline delivered a probe encapsulated with VNI `expected`
+fn outcome<Buf: PacketBufferMut>(packet: &Packet<Buf>, expected: Vni) -> Result<(), String> {
+    match packet.get_done() {
+        Some(DoneReason::Delivered) | None => {}
+        Some(reason) => return Err(format!("was dropped: {reason:?}")),
+    }
+    if packet.meta().oif.is_none() {
+        return Err("was not forwarded".to_string());
+    }
+    match packet.try_vxlan().map(|vxlan| vxlan.vni()) {
+        Some(vni) if vni == expected => Ok(()),
+        Some(vni) => Err(format!("was encapsulated with VNI {vni}, not {expected}")),
+        None => Err(format!("was not encapsulated with VNI {expected}")),
+    }
+}
+
+/// The pipeline stage injecting the probes, as decapsulated from the VNI of their source VPC
+pub(crate) struct OamSource {
+    name: String,
+    control: OamControl,
+    fibtr: FibTableReader,
+}
+
+impl OamSource {
+    /// Create a new [`OamSource`], looking up the VRFs of the VNIs of the probes with `fibtr`
+    pub(crate) fn new(name: &str, control: OamControl, fibtr: FibTableReader) -> Self {
+        Self {
+            name: name.to_string(),
+            control,
+            fibtr,
+        }
+    }
+
+    /// Build the packet of a probe
+    fn build<Buf: ProbeBuffer>(&self, probe: &Probe) -> Option<Packet<Buf>> {
+        let nfi = &self.name;
+        let mut payload = PROBE_MAGIC.to_vec();
+        payload.extend(probe.id.to_be_bytes());
+        let src = UnicastIpv4Addr::new(probe.src.0).ok()?;
+        let sport = UdpPort::try_from(probe.src.1).ok()?;
+        let dport = UdpPort::try_from(probe.dst.1).ok()?;
+        let headers = HeaderStack::new()
+            .eth(|_| {})
+            .ipv4(|ip| {
+                ip.set_source(src);
+                ip.set_destination(probe.dst.0);
+                ip.set_ttl(PROBE_TTL);
+            })
+            .udp(|udp| {
+                udp.set_source(sport);
+                udp.set_destination(dport);
+            })
+            .build_headers_with_payload(&payload)
+            .inspect_err(|e| warn!("{nfi}: Failed to build probe {}: {e}", probe.id))
+            .ok()?;
+        let len = usize::from(headers.size().get());
+        let mut frame = vec![0; len + payload.len()];
+        headers.deparse(&mut frame[..len]).ok()?;
+        frame[len..].copy_from_slice(&payload);
+        let mut packet = Packet::new(Buf::from_frame(&frame)).ok()?;
+
+        let fibkey = FibKey::from_vni(probe.vni);
+        let Some(vrf) = self
+            .fibtr
+            .get_fib_reader(fibkey)
+            .ok()
+            .and_then(|fibr| fibr.get_id())
+            .map(|id| id.as_u32())
+        else {
+            debug!(
+                "{nfi}: No VRF for VNI {}, probe {} dropped",
+                probe.vni, probe.id
+            );
+            packet.done(DoneReason::Unroutable);
+            return Some(packet);
+        };
+        let meta = packet.meta_mut();
+        meta.src_vpcd = Some(VpcDiscriminant::VNI(probe.vni));
+        meta.vrf = Some(vrf);
+        meta.set_overlay(true);
+        meta.set_oam_probe(true);
+        Some(packet)
+    }
+}
+
+impl<Buf: ProbeBuffer> NetworkFunction<Buf> for OamSource {
+    fn process<'a, Input: Iterator<Item = Packet<Buf>> + 'a>(
+        &'a mut self,
+        input: Input,
+    ) -> impl Iterator<Item = Packet<Buf>> + 'a {
+        let probes: Vec<Packet<Buf>> = self
+            .control
+            .take_pending()
+            .iter()
+            .filter_map(|probe| self.build(probe))
+            .collect();
+        input.chain(probes)
+    }
+}
+
+/// The pipeline stage consuming and verifying the probes, at the end of the pipeline
+pub(crate) struct OamTap {
+    control: OamControl,
+}
+
+impl OamTap {
+    pub(crate) fn new(control: OamControl) -> Self {
+        Self { control }
+    }
+}
+
+impl<Buf: PacketBufferMut> NetworkFunction<Buf> for OamTap {
+    fn process<'a, Input: Iterator<Item = Packet<Buf>> + 'a>(
+        &'a mut self,
+        input: Input,
+    ) -> impl Iterator<Item = Packet<Buf>> + 'a {
+        input.filter_map(move |mut packet| {
+            if !packet.meta().is_oam_probe() {
+                return Some(packet);
+            }
+            self.control.verify(&packet);
+            packet.done(DoneReason::Delivered);
+            None
+        })
+    }
+}
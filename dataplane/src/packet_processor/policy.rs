@@ -0,0 +1,69 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+//
+//! Implements a stage to classify packets by the policy class of their destination
+
+use net::buffer::PacketBufferMut;
+use net::packet::{DoneReason, Packet, PolicyClass};
+use pipeline::NetworkFunction;
+use routing::PolicyClassTableReader;
+
+use tracectl::trace_target;
+use tracing::debug;
+trace_target!("policy-class", LevelFilter::WARN, &["pipeline"]);
+
+/// Annotates packets with the [`PolicyClass`] of their destination, as learnt from the BGP
+/// communities of the matching route, and drops those destined to blackholed prefixes.
+pub struct PolicyClassifier {
+    name: String,
+    tabler: PolicyClassTableReader,
+}
+
+impl PolicyClassifier {
+    /// Build a new policy classification stage to use the indicated [`PolicyClassTableReader`]
+    #[must_use]
+    pub fn new(name: &str, tabler: PolicyClassTableReader) -> Self {
+        Self {
+            name: name.to_owned(),
+            tabler,
+        }
+    }
+
+    fn classify_packet<Buf: PacketBufferMut>(&self, packet: &mut Packet<Buf>) {
+        // prefixes overlap across VRFs: classify in the VRF the packet is routed in, if any
+        let Some(vrfid) = packet.meta().vrf else {
+            return;
+        };
+        let Some(dst) = packet.ip_destination() else {
+            return;
+        };
+        let Some(table) = self.tabler.enter() else {
+            return;
+        };
+        let Some((prefix, class)) = table.lookup(vrfid, dst) else {
+            return;
+        };
+        packet.meta_mut().policy_class = Some(class);
+        if class == PolicyClass::Blackhole {
+            debug!(
+                "{}: dropping packet to {dst}: {prefix} of vrf {vrfid} is blackholed",
+                self.name
+            );
+            packet.done(DoneReason::Filtered);
+        }
+    }
+}
+
+impl<Buf: PacketBufferMut> NetworkFunction<Buf> for PolicyClassifier {
+    fn process<'a, Input: Iterator<Item = Packet<Buf>> + 'a>(
+        &'a mut self,
+        input: Input,
+    ) -> impl Iterator<Item = Packet<Buf>> + 'a {
+        input.filter_map(move |mut packet| {
+            if !packet.is_done() {
+                self.classify_packet(&mut packet);
+            }
+            packet.enforce()
+        })
+    }
+}
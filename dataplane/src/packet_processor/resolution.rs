@@ -0,0 +1,180 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+//
+//! Packets held while the L2 address of their next-hop gets resolved
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use net::buffer::PacketBufferMut;
+use net::interface::InterfaceIndex;
+use net::packet::{DoneReason, Packet};
+
+/// Max number of next-hops for which packets can be held simultaneously
+const MAX_PENDING_NEXTHOPS: usize = 256;
+
+/// Max number of packets held for a single next-hop
+const MAX_PENDING_PACKETS: usize = 8;
+
+/// Time to wait for a next-hop to resolve before dropping the packets held for it
+const RESOLUTION_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// A next-hop to resolve: its address and the interface it is reachable over
+pub(crate) type NextHop = (IpAddr, InterfaceIndex);
+
+struct PendingQueue<Buf: PacketBufferMut> {
+    since: Instant,
+    packets: Vec<Packet<Buf>>,
+}
+
+/// The outcome of holding a packet
+pub(crate) enum Hold<Buf: PacketBufferMut> {
+    /// The packet is the first one held for its next-hop, whose resolution should be triggered
+    First,
+    /// The packet was held, along with others for the same next-hop
+    Queued,
+    /// The packet could not be held, because too many are already
+    Full(Packet<Buf>),
+}
+
+/// Bounded per-next-hop queues of packets that wait for their next-hop to be resolved
+pub(crate) struct PendingResolutions<Buf: PacketBufferMut> {
+    queues: HashMap<NextHop, PendingQueue<Buf>>,
+}
+
+impl<Buf: PacketBufferMut> PendingResolutions<Buf> {
+    pub(crate) fn new() -> Self {
+        Self {
+            queues: HashMap::new(),
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.queues.is_empty()
+    }
+
+    /// Hold a packet until its next-hop resolves or the resolution times out
+    pub(crate) fn hold(&mut self, nexthop: NextHop, packet: Packet<Buf>) -> Hold<Buf> {
+        if let Some(queue) = self.queues.get_mut(&nexthop) {
+            if queue.packets.len() >= MAX_PENDING_PACKETS {
+                return Hold::Full(packet);
+            }
+            queue.packets.push(packet);
+            return Hold::Queued;
+        }
+        if self.queues.len() >= MAX_PENDING_NEXTHOPS {
+            return Hold::Full(packet);
+        }
+        let queue = PendingQueue {
+            since: Instant::now(),
+            packets: vec![packet],
+        };
+        self.queues.insert(nexthop, queue);
+        Hold::First
+    }
+
+    /// Release the packets held for the next-hops that `resolved` reports as resolved. The
+    /// packets held for too long are released too, after marking them to be dropped.
+    pub(crate) fn release(&mut self, resolved: impl Fn(&NextHop) -> bool) -> Vec<Packet<Buf>> {
+        self.release_at(Instant::now(), resolved)
+    }
+
+    fn release_at(
+        &mut self,
+        now: Instant,
+        resolved: impl Fn(&NextHop) -> bool,
+    ) -> Vec<Packet<Buf>> {
+        let mut released = Vec::new();
+        self.queues.retain(|nexthop, queue| {
+            if resolved(nexthop) {
+                released.append(&mut queue.packets);
+                false
+            } else if now.duration_since(queue.since) >= RESOLUTION_TIMEOUT {
+                for mut packet in queue.packets.drain(..) {
+                    packet.done(DoneReason::L2ResolutionTimeout);
+                    released.push(packet);
+                }
+                false
+            } else {
+                true
+            }
+        });
+        released
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Hold, MAX_PENDING_NEXTHOPS, MAX_PENDING_PACKETS, NextHop, PendingResolutions,
+        RESOLUTION_TIMEOUT,
+    };
+    use net::buffer::TestBuffer;
+    use net::interface::InterfaceIndex;
+    use net::packet::test_utils::build_test_ipv4_packet;
+    use net::packet::{DoneReason, Packet};
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::time::Instant;
+
+    fn nexthop(host: u32) -> NextHop {
+        let addr = IpAddr::V4(Ipv4Addr::from(0x0a00_0000 + host));
+        (addr, InterfaceIndex::try_new(2).unwrap())
+    }
+
+    fn packet() -> Packet<TestBuffer> {
+        build_test_ipv4_packet(64).unwrap()
+    }
+
+    #[test]
+    fn test_hold_limits() {
+        let mut pending = PendingResolutions::new();
+        assert!(matches!(pending.hold(nexthop(1), packet()), Hold::First));
+        for _ in 1..MAX_PENDING_PACKETS {
+            assert!(matches!(pending.hold(nexthop(1), packet()), Hold::Queued));
+        }
+        assert!(matches!(pending.hold(nexthop(1), packet()), Hold::Full(_)));
+
+        let max = u32::try_from(MAX_PENDING_NEXTHOPS).unwrap();
+        for host in 2..=max {
+            assert!(matches!(pending.hold(nexthop(host), packet()), Hold::First));
+        }
+        let full = pending.hold(nexthop(max + 1), packet());
+        assert!(matches!(full, Hold::Full(_)));
+    }
+
+    #[test]
+    fn test_release_resolved() {
+        let mut pending = PendingResolutions::new();
+        pending.hold(nexthop(1), packet());
+        pending.hold(nexthop(1), packet());
+        pending.hold(nexthop(2), packet());
+
+        let released = pending.release(|nh| *nh == nexthop(1));
+        assert_eq!(released.len(), 2);
+        assert!(released.iter().all(|packet| !packet.is_done()));
+        assert!(!pending.is_empty());
+
+        let released = pending.release(|nh| *nh == nexthop(2));
+        assert_eq!(released.len(), 1);
+        assert!(pending.is_empty());
+    }
+
+    #[test]
+    fn test_release_timed_out() {
+        let mut pending = PendingResolutions::new();
+        pending.hold(nexthop(1), packet());
+        pending.hold(nexthop(1), packet());
+
+        let released = pending.release(|_| false);
+        assert!(released.is_empty());
+
+        let later = Instant::now() + RESOLUTION_TIMEOUT;
+        let released = pending.release_at(later, |_| false);
+        assert_eq!(released.len(), 2);
+        for packet in released {
+            assert_eq!(packet.get_done(), Some(DoneReason::L2ResolutionTimeout));
+        }
+        assert!(pending.is_empty());
+    }
+}
@@ -0,0 +1,128 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Evaluation of the VPC stages of the pipeline for "what-if" lookups
+
+use cli::cliproto::TransportProtocol;
+use flow_filter::{FlowFilterTableReaderFactory, FlowVerdict};
+use lpm::prefix::L4Protocol;
+use nat::static_nat::natrw::NatTablesReaderFactory;
+use net::packet::VpcDiscriminant;
+use routing::{FlowSimulator, SimulatedFlow, StageDecision, StageVerdict};
+
+/// Evaluates the flow-filter and NAT stages of the pipeline, reading the same tables as the
+/// network functions do.
+pub(crate) struct PipelineSimulator {
+    flow_filter: FlowFilterTableReaderFactory,
+    nat_tables: NatTablesReaderFactory,
+}
+
+impl PipelineSimulator {
+    pub(crate) fn new(
+        flow_filter: FlowFilterTableReaderFactory,
+        nat_tables: NatTablesReaderFactory,
+    ) -> Self {
+        Self {
+            flow_filter,
+            nat_tables,
+        }
+    }
+
+    fn flow_filter(&self, flow: &mut SimulatedFlow) -> (StageDecision, Option<FlowVerdict>) {
+        const STAGE: &str = "flow-filter";
+        let Some(src_vpcd) = flow.src_vpcd else {
+            return (
+                StageDecision::new(STAGE, StageVerdict::Skip, "not an overlay packet"),
+                None,
+            );
+        };
+        let reader = self.flow_filter.handle().inner();
+        let Some(table) = reader.enter() else {
+            return (
+                StageDecision::new(STAGE, StageVerdict::Stop, "unable to read table"),
+                None,
+            );
+        };
+        let proto = match flow.transport {
+            Some(TransportProtocol::Tcp) => L4Protocol::Tcp,
+            Some(TransportProtocol::Udp) => L4Protocol::Udp,
+            _ => L4Protocol::Any,
+        };
+        let verdict = table.evaluate(src_vpcd, &flow.src_addr, &flow.dst_addr, proto, flow.ports);
+        let decision = if let FlowVerdict::Allowed { dst_vpcd, .. } = &verdict {
+            flow.dst_vpcd = Some(*dst_vpcd);
+            StageDecision::new(STAGE, StageVerdict::Pass, verdict.to_string())
+        } else {
+            StageDecision::new(STAGE, StageVerdict::Stop, verdict.to_string())
+        };
+        (decision, Some(verdict))
+    }
+
+    fn static_nat(&self, flow: &mut SimulatedFlow, src: bool, dst: bool) -> StageDecision {
+        const STAGE: &str = "static-nat";
+        if !src && !dst {
+            return StageDecision::new(STAGE, StageVerdict::Skip, "not required");
+        }
+        let (Some(VpcDiscriminant::VNI(src_vni)), Some(VpcDiscriminant::VNI(dst_vni))) =
+            (flow.src_vpcd, flow.dst_vpcd)
+        else {
+            return StageDecision::new(STAGE, StageVerdict::Stop, "missing VPC annotations");
+        };
+        let reader = self.nat_tables.handle();
+        let Some(tables) = reader.enter() else {
+            return StageDecision::new(STAGE, StageVerdict::Stop, "unable to read tables");
+        };
+        let Some(table) = tables.get_table(src_vni) else {
+            return StageDecision::new(
+                STAGE,
+                StageVerdict::Stop,
+                format!("no NAT tables for vni {src_vni}"),
+            );
+        };
+        let (mut src_port, mut dst_port) = flow.ports.unzip();
+        let mut translations = vec![];
+        if src && let Some((addr, port)) = table.find_src_mapping(&flow.src_addr, src_port, dst_vni)
+        {
+            translations.push(format!("src {} -> {addr}", flow.src_addr));
+            flow.src_addr = addr.inner();
+            src_port = port.map(|p| p.get()).or(src_port);
+        }
+        if dst && let Some((addr, port)) = table.find_dst_mapping(&flow.dst_addr, dst_port) {
+            translations.push(format!("dst {} -> {addr}", flow.dst_addr));
+            flow.dst_addr = addr;
+            dst_port = port.map(|p| p.get()).or(dst_port);
+        }
+        flow.ports = src_port.zip(dst_port);
+        if translations.is_empty() {
+            StageDecision::new(STAGE, StageVerdict::Pass, "no matching mapping")
+        } else {
+            StageDecision::new(STAGE, StageVerdict::Pass, translations.join(", "))
+        }
+    }
+}
+
+impl FlowSimulator for PipelineSimulator {
+    fn simulate(&self, flow: &mut SimulatedFlow) -> Vec<StageDecision> {
+        let mut decisions = vec![];
+        let (decision, verdict) = self.flow_filter(flow);
+        decisions.push(decision);
+        let Some(FlowVerdict::Allowed { nat, .. }) = verdict else {
+            return decisions;
+        };
+        decisions.push(self.static_nat(flow, nat.static_src, nat.static_dst));
+        // Masquerading and port-forwarding allocate or look up state per flow: we can only tell
+        // whether they would apply.
+        for (stage, required) in [
+            ("port-forwarding", nat.port_forwarding),
+            ("masquerade", nat.masquerade),
+        ] {
+            let (verdict, detail) = if required {
+                (StageVerdict::Pass, "required: translation set up per flow")
+            } else {
+                (StageVerdict::Skip, "not required")
+            };
+            decisions.push(StageDecision::new(stage, verdict, detail));
+        }
+        decisions
+    }
+}
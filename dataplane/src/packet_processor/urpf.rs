@@ -0,0 +1,260 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+//
+//! Implements a stage validating the source addresses of packets (unicast reverse path
+//! forwarding)
+//!
+//! The source of the packets received on an interface with a [`UrpfMode`] other than off is
+//! looked up in the FIB of the VRF of the interface. In loose mode, packets are dropped if the
+//! source has no route, or only one that drops packets. In strict mode, they are also dropped
+//! unless one of the next-hops of the route is the interface they were received on. Dropped
+//! packets are counted in `urpf_violations`, per interface and mode.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use config::internal::interfaces::interface::UrpfMode;
+use metrics::{Counter, Unit};
+use net::buffer::PacketBufferMut;
+use net::interface::InterfaceIndex;
+use net::packet::{DoneReason, Packet};
+use pipeline::NetworkFunction;
+use routing::{FibEntry, FibKey, FibTableReader, IfTableReader, PktInstruction};
+use stats::{MetricSpec, Register};
+
+use tracectl::trace_target;
+use tracing::debug;
+trace_target!("urpf", LevelFilter::WARN, &["pipeline"]);
+
+/// The outcome of the validation of the source of a packet
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Verdict {
+    Valid,
+    /// The source has no usable route
+    Unreachable,
+    /// The source is not routed over the incoming interface
+    WrongInterface,
+}
+
+/// Tell if a source whose route has the given entries is valid for packets received on interface
+/// `iif`, in the given mode
+fn verdict<'a>(
+    entries: impl IntoIterator<Item = &'a FibEntry>,
+    iif: InterfaceIndex,
+    mode: UrpfMode,
+) -> Verdict {
+    let mut reachable = false;
+    for entry in entries {
+        for instruction in entry.iter() {
+            match instruction {
+                PktInstruction::Egress(egress) => {
+                    if mode == UrpfMode::Loose || *egress.ifindex() == Some(iif) {
+                        return Verdict::Valid;
+                    }
+                    reachable = true;
+                }
+                PktInstruction::Encap(_) => {
+                    if mode == UrpfMode::Loose {
+                        return Verdict::Valid;
+                    }
+                    reachable = true;
+                }
+                // our own addresses are not legitimate sources
+                PktInstruction::Drop | PktInstruction::Local(_) => {}
+            }
+        }
+    }
+    if reachable {
+        Verdict::WrongInterface
+    } else {
+        Verdict::Unreachable
+    }
+}
+
+/// The violation counter of an interface, with the name and mode it was registered for
+struct Violations {
+    name: String,
+    mode: UrpfMode,
+    counter: Counter,
+}
+
+/// Drops the packets whose source fails the uRPF check of their incoming interface
+pub struct UrpfValidator {
+    name: String,
+    iftr: IfTableReader,
+    fibtr: FibTableReader,
+    violations: HashMap<InterfaceIndex, Violations>,
+}
+
+impl UrpfValidator {
+    /// Build a new uRPF stage, reading the modes of the interfaces in the indicated
+    /// [`IfTableReader`] and the routes in the indicated [`FibTableReader`]
+    #[must_use]
+    pub fn new(name: &str, iftr: IfTableReader, fibtr: FibTableReader) -> Self {
+        Self {
+            name: name.to_owned(),
+            iftr,
+            fibtr,
+            violations: HashMap::new(),
+        }
+    }
+
+    /// Validate a source against the FIB of a VRF
+    fn validate(
+        &self,
+        fibkey: FibKey,
+        source: IpAddr,
+        iif: InterfaceIndex,
+        mode: UrpfMode,
+    ) -> Option<Verdict> {
+        let fibr = self.fibtr.get_fib_reader(fibkey).ok()?;
+        let fib = fibr.enter()?;
+        Some(verdict(fib.lpm(&source).entries(), iif, mode))
+    }
+
+    /// Count a violation on an interface
+    fn count_violation(
+        violations: &mut HashMap<InterfaceIndex, Violations>,
+        iif: InterfaceIndex,
+        ifname: &str,
+        mode: UrpfMode,
+    ) {
+        let violations = violations.entry(iif).or_insert_with(|| Violations {
+            name: String::new(),
+            mode: UrpfMode::Off,
+            counter: Counter::noop(),
+        });
+        if violations.name != ifname || violations.mode != mode {
+            let labels = vec![
+                ("interface".to_string(), ifname.to_string()),
+                ("mode".to_string(), mode.to_string()),
+            ];
+            violations.name = ifname.to_string();
+            violations.mode = mode;
+            violations.counter = MetricSpec::new("urpf_violations", Unit::Count, labels)
+                .register()
+                .metric;
+        }
+        violations.counter.increment(1);
+    }
+
+    fn check_packet<Buf: PacketBufferMut>(&mut self, packet: &mut Packet<Buf>) {
+        let (Some(iif), Some(vrfid)) = (packet.meta().iif, packet.meta().vrf) else {
+            return;
+        };
+        // unspecified sources (e.g. of DHCP discoveries) are never routed
+        let Some(source) = packet.ip_source().filter(|source| !source.is_unspecified()) else {
+            return;
+        };
+        let Some(iftable) = self.iftr.enter() else {
+            return;
+        };
+        let Some(interface) = iftable.get_interface(iif) else {
+            return;
+        };
+        let mode = interface.urpf;
+        if mode == UrpfMode::Off {
+            return;
+        }
+        let verdict = self.validate(FibKey::from_vrfid(vrfid), source, iif, mode);
+        match verdict {
+            None | Some(Verdict::Valid) => {}
+            Some(verdict) => {
+                let ifname = interface.name.as_str();
+                debug!(
+                    "{}: dropping packet from {source} received on {ifname}: {verdict:?} ({mode})",
+                    self.name
+                );
+                Self::count_violation(&mut self.violations, iif, ifname, mode);
+                packet.done(DoneReason::UrpfDrop);
+            }
+        }
+    }
+}
+
+impl<Buf: PacketBufferMut> NetworkFunction<Buf> for UrpfValidator {
+    fn process<'a, Input: Iterator<Item = Packet<Buf>> + 'a>(
+        &'a mut self,
+        input: Input,
+    ) -> impl Iterator<Item = Packet<Buf>> + 'a {
+        input.filter_map(move |mut packet| {
+            if !packet.is_done() {
+                self.check_packet(&mut packet);
+            }
+            packet.enforce()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Verdict, verdict};
+    use config::internal::interfaces::interface::UrpfMode;
+    use net::interface::InterfaceIndex;
+    use routing::{EgressObject, FibEntry, PktInstruction};
+
+    fn ifindex(index: u32) -> InterfaceIndex {
+        InterfaceIndex::try_new(index).unwrap()
+    }
+
+    fn egress(index: u32) -> FibEntry {
+        let egress = EgressObject::new(Some(ifindex(index)), None, None);
+        FibEntry::with_inst(PktInstruction::Egress(egress))
+    }
+
+    #[test]
+    fn test_urpf_strict() {
+        // the route of the source goes over interfaces 2 and 3
+        let route = [egress(2), egress(3)];
+        for iif in [2, 3] {
+            assert_eq!(
+                verdict(&route, ifindex(iif), UrpfMode::Strict),
+                Verdict::Valid
+            );
+        }
+        let result = verdict(&route, ifindex(4), UrpfMode::Strict);
+        assert_eq!(result, Verdict::WrongInterface);
+        let drop = [FibEntry::drop_fibentry()];
+        let result = verdict(&drop, ifindex(2), UrpfMode::Strict);
+        assert_eq!(result, Verdict::Unreachable);
+    }
+
+    #[test]
+    fn test_urpf_loose() {
+        let route = [egress(2)];
+        for iif in [2, 4] {
+            assert_eq!(
+                verdict(&route, ifindex(iif), UrpfMode::Loose),
+                Verdict::Valid
+            );
+        }
+        let drop = [FibEntry::drop_fibentry()];
+        let result = verdict(&drop, ifindex(2), UrpfMode::Loose);
+        assert_eq!(result, Verdict::Unreachable);
+        let result = verdict(&[], ifindex(2), UrpfMode::Loose);
+        assert_eq!(result, Verdict::Unreachable);
+    }
+
+    #[test]
+    fn test_urpf_default_route() {
+        // sources only covered by the default route are valid in loose mode if it forwards
+        // packets, and in strict mode only if it does so over the incoming interface
+        let default = [egress(1)];
+        assert_eq!(
+            verdict(&default, ifindex(2), UrpfMode::Loose),
+            Verdict::Valid
+        );
+        assert_eq!(
+            verdict(&default, ifindex(1), UrpfMode::Strict),
+            Verdict::Valid
+        );
+        let result = verdict(&default, ifindex(2), UrpfMode::Strict);
+        assert_eq!(result, Verdict::WrongInterface);
+
+        // the default route of a VRF without one drops packets
+        let default = [FibEntry::drop_fibentry()];
+        for mode in [UrpfMode::Loose, UrpfMode::Strict] {
+            assert_eq!(verdict(&default, ifindex(2), mode), Verdict::Unreachable);
+        }
+    }
+}
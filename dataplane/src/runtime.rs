@@ -1,41 +1,68 @@
 // SPDX-License-Identifier: Apache-2.0
 // Copyright Open Network Fabric Authors
 
-use crate::packet_processor::start_router;
-use crate::statistics::spawn_metrics;
-use args::{CmdArgs, Parser};
-
-use crate::drivers::kernel::DriverKernel;
+use crate::otlp::Otlp;
+use crate::packet_processor::{IngressPolicy, OamParams, start_router};
+use crate::statistics::{LookingGlass, spawn_metrics};
+use acl_filter::PrefilterRules;
+use args::generation::{ConfigGeneration, GenerationError, GenerationListener, RuntimeSection};
+use args::{
+    CmdArgs, DriverConfigSection, FeatureFlagArg, LaunchConfiguration, LaunchVerifyingKey,
+    TracingConfigSection, TracingRateLimit,
+};
+use common::flags;
+
+use crate::drivers::Drain;
+use crate::drivers::af_xdp::{DriverAfXdp, XskSettings};
+use crate::drivers::batch::BatchLimits;
+use crate::drivers::kernel::{DrainHandle, DriverKernel, MicroburstLog, Watchdog};
+use kvstore::{KvStore, MemoryStore, RedbStore};
+use lifecycle::startup::{Startup, StartupStep, default_timeouts};
 use lifecycle::{
-    CancellationToken, DpSignal, Shutdown, default_deadlines, spawn_shutdown_watchdog,
+    CancellationToken, DpSignal, Shutdown, Subsystem, default_deadlines, spawn_shutdown_watchdog,
 };
-use mgmt::{ConfigProcessorParams, LaunchError, MgmtParams, run_mgmt};
+use mgmt::{ConfigProcessorParams, MgmtParams, TableWriters, run_mgmt};
+use nat::AlgConfig;
+use nat::masquerade::{NAT_LEASES_FLUSH_INTERVAL, NatLeases};
 
 use nix::unistd::gethostname;
 use pyroscope::backend::{BackendConfig, PprofConfig, pprof_backend};
 use pyroscope::pyroscope::{PyroscopeAgentBuilder, PyroscopeConfig};
-use routing::{BmpServerParams, RouterCtlSender, RouterParamsBuilder, spawn_bmp_server};
+use routing::{
+    BmpServerParams, BmpStore, BmpStoreConfig, Router, RouterCtlSender, RouterParamsBuilder,
+    spawn_bmp_server,
+};
 use tracectl::{
     TracingControl, TracingRateLimitConfig, custom_target, get_trace_ctl, trace_target,
 };
 
-use tracing::{error, info, level_filters::LevelFilter};
+use tracing::{error, info, level_filters::LevelFilter, warn};
 
-use concurrency::sync::Arc;
+use concurrency::sync::{Arc, Mutex};
+use config::internal::readiness::{self, Component};
 use config::internal::routing::bmp::BmpOptions;
 use config::internal::status::DataplaneStatus;
+use net::buffer::TestBuffer;
 use net::tcp::TcpPort;
+use pipeline::DynPipeline;
+use stats::StatsCollector;
+use std::collections::BTreeSet;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
-use tokio::sync::RwLock;
+use tokio::sync::{RwLock, watch};
 
 trace_target!("dataplane", LevelFilter::DEBUG, &[]);
 custom_target!("Pyroscope", LevelFilter::INFO, &["third-party"]);
+custom_target!("opentelemetry", LevelFilter::WARN, &["third-party"]);
 custom_target!("kube", LevelFilter::WARN, &["third-party"]);
 custom_target!("hyper", LevelFilter::WARN, &["third-party"]);
 custom_target!("tower", LevelFilter::WARN, &["third-party"]);
 
 const PYROSCOPE_APP_NAME: &str = "hedgehog-dataplane";
 
+type PipelineFactory = Arc<dyn Send + Sync + Fn() -> DynPipeline<TestBuffer>>;
+
 fn init_name(args: &CmdArgs) -> Result<String, String> {
     if let Some(name) = args.get_name() {
         Ok(name.clone())
@@ -48,18 +75,32 @@ fn init_name(args: &CmdArgs) -> Result<String, String> {
         Ok(name.to_string())
     }
 }
-fn init_logging(args: &CmdArgs, gwname: &str) {
+fn rate_limit_config(rate_limit: Option<&TracingRateLimit>) -> TracingRateLimitConfig {
+    rate_limit.map_or_else(TracingRateLimitConfig::default, |rate_limit| {
+        TracingRateLimitConfig {
+            burst: rate_limit.burst,
+            replenish_per_second: rate_limit.replenish_per_second,
+        }
+    })
+}
+/// Set up the export of traces and metrics over OTLP, if a collector is given
+fn init_otlp(args: &CmdArgs, gwname: &str) -> Result<Option<Otlp>, String> {
+    let Some(endpoint) = args.otlp_endpoint() else {
+        return Ok(None);
+    };
+    let ratio = args.otlp_sampling_ratio().as_f64();
+    Otlp::start(endpoint.as_str(), args.otlp_headers(), ratio, gwname)
+        .map(Some)
+        .map_err(|e| e.to_string())
+}
+fn init_logging(args: &CmdArgs, gwname: &str, otlp: Option<&Otlp>) {
     // Log throttling is on by default; a missing --tracing-rate-limit uses the
     // default. It can be disabled at runtime via the dataplane CLI.
-    let rate_limit =
-        args.tracing_rate_limit()
-            .map_or_else(TracingRateLimitConfig::default, |rate_limit| {
-                TracingRateLimitConfig {
-                    burst: rate_limit.burst,
-                    replenish_per_second: rate_limit.replenish_per_second,
-                }
-            });
-    TracingControl::init_with_rate_limit(Some(rate_limit));
+    let rate_limit = rate_limit_config(args.tracing_rate_limit());
+    match otlp {
+        Some(otlp) => TracingControl::init_with_export(Some(rate_limit), otlp.tracing_layer()),
+        None => TracingControl::init_with_rate_limit(Some(rate_limit)),
+    }
 
     let tctl = get_trace_ctl();
     info!(
@@ -103,6 +144,85 @@ fn process_tracing_cmds(args: &CmdArgs) {
     }
 }
 
+/// Apply the tracing section of a later generation of the launch configuration. Targets the
+/// configuration does not mention keep their level.
+fn reload_tracing(tracing: &TracingConfigSection) {
+    let tctl = get_trace_ctl();
+    if let Some(config) = &tracing.config
+        && let Err(e) = tctl.setup_from_string(config)
+    {
+        error!("Invalid tracing configuration: {e}");
+    }
+    tctl.reload_rate_limit(Some(rate_limit_config(tracing.rate_limit.as_ref())));
+}
+
+/// The values of the feature flags of the launch configuration
+fn flag_values(values: &[FeatureFlagArg]) -> impl Iterator<Item = (&str, bool)> {
+    values.iter().map(|flag| (flag.name.as_str(), flag.enabled))
+}
+
+/// Receive the later generations of the launch configuration sent to `path`, and apply the
+/// sections they change: the tracing configuration, the address of the metrics endpoint, the
+/// path of the CLI socket and the values of the feature flags.
+fn spawn_generation_listener(
+    path: &str,
+    generations: ConfigGeneration,
+    handle: tokio::runtime::Handle,
+    rtr_ctl: RouterCtlSender,
+    metrics_addr: watch::Sender<SocketAddr>,
+    cancel: CancellationToken,
+) -> Result<(), String> {
+    // generations are verified with the key the launch configuration is verified with, if any
+    let key = LaunchVerifyingKey::from_env().map_err(|e| e.to_string())?;
+    let listener = GenerationListener::bind(Path::new(path), key).map_err(|e| e.to_string())?;
+    // not scoped: the listener blocks until a generation is received and must not hold the
+    // shutdown of the dataplane
+    concurrency::thread::Builder::new()
+        .name("generations".to_string())
+        .spawn(move || {
+            while !cancel.is_cancelled() {
+                let changed = match listener
+                    .recv()
+                    .and_then(|(number, config)| generations.apply(number, config))
+                {
+                    Ok(changed) => changed,
+                    Err(GenerationError::Io(e)) if e.kind() != std::io::ErrorKind::Interrupted => {
+                        error!("Stopped receiving launch configuration generations: {e}");
+                        break;
+                    }
+                    Err(e) => {
+                        error!("Rejected launch configuration generation: {e}");
+                        continue;
+                    }
+                };
+                let generation = generations.current();
+                let config = &generation.config;
+                for section in changed {
+                    match section {
+                        RuntimeSection::Tracing => reload_tracing(&config.tracing),
+                        RuntimeSection::MetricsAddress => {
+                            metrics_addr.send_replace(config.metrics.address);
+                        }
+                        RuntimeSection::CliSocket => {
+                            let path = config.cli.cli_sock_path.clone();
+                            if let Err(e) = handle.block_on(rtr_ctl.move_cli_sock(path)) {
+                                error!("Failed to move the CLI socket: {e}");
+                            }
+                        }
+                        RuntimeSection::FeatureFlags => {
+                            let values = flag_values(&config.feature_flags);
+                            if let Err(e) = flags::reload_launch_values(values) {
+                                error!("Failed to apply feature flags: {e}");
+                            }
+                        }
+                    }
+                }
+            }
+        })
+        .map_err(|e| format!("Failed to spawn generation listener: {e}"))?;
+    Ok(())
+}
+
 fn parse_bmp_params(args: &CmdArgs) -> (Option<BmpServerParams>, Option<BmpOptions>) {
     if args.bmp_enabled() {
         let bind_addr = args.bmp_address();
@@ -129,14 +249,106 @@ fn parse_bmp_params(args: &CmdArgs) -> (Option<BmpServerParams>, Option<BmpOptio
     }
 }
 
+/// Create the store of the recent BMP route monitoring messages, if the BMP server is enabled
+/// and either of its bounds is set. The bound not set is unlimited.
+fn create_bmp_store(args: &CmdArgs) -> Option<Arc<BmpStore>> {
+    if !args.bmp_enabled()
+        || (args.bmp_store_messages().is_none() && args.bmp_store_bytes().is_none())
+    {
+        return None;
+    }
+    let config = BmpStoreConfig {
+        max_messages: args
+            .bmp_store_messages()
+            .map_or(usize::MAX, |max| usize::try_from(max).unwrap_or(usize::MAX)),
+        max_bytes: args
+            .bmp_store_bytes()
+            .map_or(usize::MAX, |max| usize::try_from(max).unwrap_or(usize::MAX)),
+    };
+    info!("BMP: keeping recent route monitoring messages: {config:?}");
+    Some(Arc::new(BmpStore::new(config)))
+}
+
+/// Open the store persisting the state learned at runtime. If it can't be opened, the state is
+/// kept in memory, and thus lost on restart, rather than failing to start.
+fn open_state_store(path: &str) -> Arc<dyn KvStore> {
+    match RedbStore::open(Path::new(path)) {
+        Ok(store) => Arc::new(store),
+        Err(e) => {
+            error!("Failed to open state store {path}: {e}. State won't persist across restarts");
+            Arc::new(MemoryStore::default())
+        }
+    }
+}
+
+/// Set up the leases of the bindings of masquerading, if enabled, persisted in `state_store`.
+/// They are flushed every [`NAT_LEASES_FLUSH_INTERVAL`] by a task of `subsystem`, and once more
+/// when it is drained, not to lose the leases renewed meanwhile.
+fn start_nat_leases(
+    duration: Option<Duration>,
+    state_store: &Arc<dyn KvStore>,
+    subsystem: &Subsystem,
+    handle: &tokio::runtime::Handle,
+) -> Option<Arc<NatLeases>> {
+    let duration = duration?;
+    info!(
+        "NAT: leasing the bindings of masquerading for {} seconds",
+        duration.as_secs()
+    );
+    let leases = Arc::new(NatLeases::new(duration, Some(state_store.clone())));
+    let flushed = leases.clone();
+    let cancel = subsystem.cancel_token();
+    subsystem.spawn_on(
+        async move {
+            let mut interval = tokio::time::interval(NAT_LEASES_FLUSH_INTERVAL);
+            loop {
+                let stopping = tokio::select! {
+                    () = cancel.cancelled() => true,
+                    _ = interval.tick() => false,
+                };
+                // the leases are written to the state store off the runtime threads
+                let leases = flushed.clone();
+                if let Err(e) = tokio::task::spawn_blocking(move || leases.flush()).await {
+                    error!("Failed to flush NAT leases: {e}");
+                }
+                if stopping {
+                    info!("Flushed NAT leases upon shutdown");
+                    return;
+                }
+            }
+        },
+        handle,
+    );
+    Some(leases)
+}
+
+/// Read the bearer token of an API, e.g. the looking glass
+fn read_token(path: &str, api: &str) -> Result<String, String> {
+    let token = std::fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {api} token from {path}: {e}"))?;
+    let token = token.trim();
+    if token.is_empty() {
+        return Err(format!("{api} token file {path} is empty"));
+    }
+    Ok(token.to_string())
+}
+
 fn start_bmp(
     mgmt: &lifecycle::Subsystem,
     mgmt_handle: &tokio::runtime::Handle,
     bmp_params: &BmpServerParams,
     dp_status: Arc<RwLock<DataplaneStatus>>,
     rtr_ctl: RouterCtlSender,
+    bmp_store: Option<Arc<BmpStore>>,
 ) -> tokio::task::JoinHandle<()> {
-    spawn_bmp_server(mgmt, mgmt_handle, bmp_params.bind_addr, dp_status, rtr_ctl)
+    spawn_bmp_server(
+        mgmt,
+        mgmt_handle,
+        bmp_params.bind_addr,
+        dp_status,
+        rtr_ctl,
+        bmp_store,
+    )
 }
 
 // Main signal handling of dataplane occurs here
@@ -166,7 +378,19 @@ fn spawn_signal_handler(
 
 #[allow(clippy::too_many_lines)]
 pub fn main() {
-    let args = CmdArgs::parse();
+    let args = CmdArgs::parse_with_config_file();
+    if args.validate_only() {
+        match args.dry_run() {
+            Ok(config) => {
+                print!("{config}");
+                std::process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("Invalid launch configuration: {e:?}");
+                std::process::exit(1);
+            }
+        }
+    }
     let gwname = match init_name(&args) {
         Ok(name) => name,
         Err(e) => {
@@ -174,7 +398,28 @@ pub fn main() {
             std::process::exit(1);
         }
     };
-    init_logging(&args, &gwname);
+    // before logging, which hands the spans over to the exporter, and before any tokio runtime
+    let otlp = match init_otlp(&args, &gwname) {
+        Ok(otlp) => otlp,
+        Err(e) => {
+            eprintln!("Failed to set up OTLP export: {e}");
+            std::process::exit(1);
+        }
+    };
+    init_logging(&args, &gwname, otlp.as_ref());
+
+    // after logging, so that the hook installed with the subscriber is kept
+    lifecycle::crash::install(
+        Path::new(&args.crash_dir()),
+        option_env!("VERSION").unwrap_or("dev"),
+        tracectl::recent_events,
+    );
+
+    let launch_flags: Vec<_> = args.feature_flags().cloned().collect();
+    if let Err(e) = flags::apply_launch_values(flag_values(&launch_flags)) {
+        error!("Invalid feature flags: {e}");
+        std::process::exit(1);
+    }
 
     // Initialize a minimal EAL as early as possible. The ACL filter builds rte_acl
     // classifiers when configuration is applied (which happens before any packet driver starts),
@@ -200,6 +445,7 @@ pub fn main() {
     ]);
 
     let (bmp_server_params, bmp_client_opts) = parse_bmp_params(&args);
+    let bmp_store = create_bmp_store(&args);
 
     let dp_status: Arc<RwLock<DataplaneStatus>> = Arc::new(RwLock::new(DataplaneStatus::new()));
 
@@ -260,116 +506,327 @@ pub fn main() {
     let mut binding = RouterParamsBuilder::default();
     let rp_builder = binding
         .cli_sock_path(args.cli_sock_path())
+        .cli_views_path(args.cli_views().map(PathBuf::from))
         .cpi_sock_path(args.cpi_sock_path())
-        .frr_agent_path(args.frr_agent_path());
+        .frr_agent_path(args.frr_agent_path())
+        .frr_grace_period(args.frr_grace_period());
 
     let Ok(router_params) = rp_builder.build() else {
         error!("Bad router configuration");
         panic!("Bad router configuration");
     };
 
-    // start router
-    let mut setup = start_router(&shutdown.router, router_params).expect("failed to start router");
-
-    // start bmp server if indicated via cmd line. It is fine to start it after the router since no bgp session may be up
-    // until a configuration is applied, and the mgmt is not yet up.
-    let _bmp_handle = if let Some(bmp_params) = &bmp_server_params {
-        Some(start_bmp(
-            &shutdown.mgmt,
-            &mgmt_handle,
-            bmp_params,
-            dp_status.clone(),
-            setup.router.get_ctl_tx(),
-        ))
-    } else {
-        None
+    // application layer gateways of masquerading
+    let alg = AlgConfig {
+        ftp: args.nat_alg_ftp(),
+        sip: args.nat_alg_sip(),
+    };
+
+    // handling of the packets for destinations which are not meant to be routed
+    let ingress = IngressPolicy {
+        link_local: args.link_local_policy(),
+        multicast: args.multicast_policy(),
+    };
+
+    // paths between endpoints of VPCs verified with probes
+    let oam = OamParams {
+        probes: args.oam_probes().copied().collect(),
+        interval: args.oam_interval(),
     };
 
-    spawn_metrics(
-        &shutdown.metrics,
+    // driver-private NIC counters are only available for interfaces managed by the kernel
+    let nic_interfaces = match args.driver_name() {
+        "kernel" | "af_xdp" => args.kernel_interfaces(),
+        _ => vec![],
+    };
+
+    // state learned at runtime, kept across restarts
+    let state_store = open_state_store(&args.state_store());
+
+    // bindings of masquerading leased to inside hosts, kept across restarts
+    let nat_leases = start_nat_leases(
+        args.nat_lease_duration(),
+        &state_store,
+        &shutdown.router,
         &mgmt_handle,
-        args.metrics_address(),
-        setup.stats,
     );
 
-    let pipeline_factory = setup.pipeline;
+    // microbursts detected by the workers, shown by the cli
+    let microbursts = MicroburstLog::default();
 
-    concurrency::thread::scope(|scope| {
-        let mgmt_result = run_mgmt(
-            &mgmt_handle,
-            &shutdown.mgmt,
-            MgmtParams {
-                config_dir: args.config_dir().cloned(),
-                hostname: gwname.clone(),
-                interfaces: args.interfaces().map(|i| i.interface).collect(),
-                processor_params: ConfigProcessorParams {
-                    router_ctl: setup.router.get_ctl_tx(),
-                    pipeline_data: pipeline_factory().get_data(),
-                    flow_table: setup.flow_table,
-                    vpcmapw: setup.vpcmapw,
-                    nattablesw: setup.nattablesw,
-                    natallocatorw: setup.natallocatorw,
-                    flowfilterw: setup.flowfiltertablesw,
-                    aclfilterw: setup.aclfiltertablesw,
-                    portfw_w: setup.portfw_w,
-                    vpc_stats_store: setup.vpc_stats_store,
-                    dp_status_r: dp_status.clone(),
-                    bmp_options: bmp_client_opts,
-                },
-            },
-        );
+    // state handed over by the router to the subsystems that depend on it
+    let router: Mutex<Option<Router>> = Mutex::new(None);
+    let router_ctl: Mutex<Option<RouterCtlSender>> = Mutex::new(None);
+    let stats: Mutex<Option<StatsCollector>> = Mutex::new(None);
+    let processor_params: Mutex<Option<ConfigProcessorParams>> = Mutex::new(None);
+    let pipeline_factory: Mutex<Option<PipelineFactory>> = Mutex::new(None);
+    let driver_drain: Mutex<Option<DrainHandle>> = Mutex::new(None);
 
-        match mgmt_result {
-            Ok(()) => {
-                info!("Management is running now");
+    // tap interfaces created by mgmt for the config, which the driver attaches to
+    let (tap_interfaces_tx, tap_interfaces_rx) = watch::channel(BTreeSet::new());
 
-                let driver_result = match args.driver_name() {
-                    "dpdk" => {
-                        info!("Using driver DPDK...");
-                        todo!();
-                    }
-                    "kernel" => {
-                        info!("Using driver kernel...");
-                        Some(DriverKernel::start(
-                            scope,
-                            &shutdown.workers,
-                            args.kernel_interfaces(),
-                            args.kernel_num_workers(),
-                            &pipeline_factory,
-                        ))
-                    }
-                    other => {
-                        error!("Unknown driver '{other}'. Stopping dataplane...");
-                        shutdown.fail();
-                        None
-                    }
-                };
+    // rules of the pre-filter of the kernel driver, compiled by mgmt from the config
+    let (prefilter_tx, prefilter_rx) = watch::channel(PrefilterRules::default());
 
-                if let Some(Err(e)) = driver_result {
-                    error!("Failed to start driver: {e}");
-                    shutdown.fail();
-                }
-            }
-            Err(LaunchError::Cancelled) => {
-                // Don't call shutdown.fail() — that flips the fatal flag
-                // and turns a graceful SIGINT into a non-zero exit, which
-                // systemd would restart-loop.
-                info!("Mgmt init cancelled; proceeding to shutdown");
+    // address of the metrics endpoint, which later generations of the launch configuration change
+    let (metrics_addr_tx, metrics_addr_rx) = watch::channel(args.metrics_address());
+
+    concurrency::thread::scope(|scope| {
+        let start_router_step = StartupStep::new("router", default_timeouts::ROUTER, |_| {
+            let setup = start_router(
+                &shutdown.router,
+                router_params,
+                alg,
+                nat_leases.clone(),
+                ingress,
+                state_store.clone(),
+                microbursts.clone(),
+                oam,
+                bmp_store.clone(),
+                Path::new(&args.capture_dir()),
+            )?;
+            *router_ctl.lock() = Some(setup.router.get_ctl_tx());
+            *stats.lock() = Some(setup.stats);
+            let pipeline_data = (setup.pipeline)().get_data();
+            let table_writers = TableWriters {
+                vpcmapw: setup.vpcmapw,
+                flowfilterw: setup.flowfiltertablesw,
+                aclfilterw: setup.aclfiltertablesw,
+                nattablesw: setup.nattablesw,
+                natallocatorw: setup.natallocatorw,
+                portfw_w: setup.portfw_w,
+                qosw: setup.qostablesw,
+                mirrorw: setup.mirrortablesw,
+                prefilter: Some(prefilter_tx),
+                flow_table: setup.flow_table.clone(),
+                pipeline_data: pipeline_data.clone(),
+            };
+            *processor_params.lock() = Some(ConfigProcessorParams {
+                router_ctl: setup.router.get_ctl_tx(),
+                pipeline_data,
+                flow_table: setup.flow_table,
+                table_events: table_writers.start(&mgmt_handle),
+                vpc_stats_store: setup.vpc_stats_store,
+                dp_status_r: dp_status.clone(),
+                bmp_options: bmp_client_opts,
+                tap_interfaces: Some(tap_interfaces_tx),
+                interface_view: setup.interface_view,
+                route_table_range: args.route_table_range(),
+                state_store: Some(state_store.clone()),
+                rollback_requests: Some(setup.rollback_requests),
+                unmanaged_interfaces: args.unmanaged_interfaces().cloned().collect(),
+            });
+            *pipeline_factory.lock() = Some(setup.pipeline);
+            *router.lock() = Some(setup.router);
+            Ok(())
+        });
+
+        // It is fine to start the BMP server once the router is up, since no bgp session may be up
+        // until a configuration is applied by mgmt.
+        let start_bmp_step = StartupStep::new("bmp", default_timeouts::BMP, |_| {
+            if let Some(bmp_params) = &bmp_server_params {
+                let rtr_ctl = router_ctl.lock().take().unwrap_or_else(|| unreachable!());
+                start_bmp(
+                    &shutdown.mgmt,
+                    &mgmt_handle,
+                    bmp_params,
+                    dp_status.clone(),
+                    rtr_ctl,
+                    bmp_store.clone(),
+                );
             }
+            Ok(())
+        })
+        .after(&["router"]);
+
+        let start_metrics_step = StartupStep::new("metrics", default_timeouts::METRICS, |_| {
+            let stats = stats.lock().take().unwrap_or_else(|| unreachable!());
+            let looking_glass = match args.looking_glass_token_file() {
+                None => None,
+                Some(path) => {
+                    let token = read_token(path, "Looking glass")?;
+                    let router = router.lock();
+                    let router = router.as_ref().unwrap_or_else(|| unreachable!());
+                    Some(LookingGlass::new(
+                        &token,
+                        router.get_fibtr_factory(),
+                        router.get_iftabler_factory(),
+                        dp_status.clone(),
+                    ))
+                }
+            };
+            spawn_metrics(
+                &shutdown.metrics,
+                &mgmt_handle,
+                metrics_addr_rx,
+                stats,
+                nic_interfaces,
+                looking_glass,
+                otlp.as_ref().map(Otlp::recorder),
+            );
+            Ok(())
+        })
+        .after(&["router"]);
+
+        let start_mgmt_step = StartupStep::new("mgmt", default_timeouts::MGMT, |_| {
+            let processor_params = processor_params
+                .lock()
+                .take()
+                .unwrap_or_else(|| unreachable!());
+            let grpc_token = args
+                .grpc_token_file()
+                .map(|path| read_token(path, "gRPC"))
+                .transpose()?
+                .map(Arc::from);
+            run_mgmt(
+                &mgmt_handle,
+                &shutdown.mgmt,
+                MgmtParams {
+                    config_dir: args.config_dir().cloned(),
+                    hostname: gwname.clone(),
+                    interfaces: args.interfaces().map(|i| i.interface).collect(),
+                    processor_params,
+                    grpc_address: args.grpc_address(),
+                    grpc_observer_address: args.grpc_observer_address(),
+                    grpc_token,
+                    config_cache_dir: args.config_cache_dir().map(str::to_string),
+                    config_replay: args.config_replay(),
+                },
+            )?;
+            Ok(())
+        })
+        .after(&["router"]);
+
+        let start_driver_step = StartupStep::new("driver", default_timeouts::DRIVER, |ready| {
+            let pipeline_factory = pipeline_factory
+                .lock()
+                .clone()
+                .unwrap_or_else(|| unreachable!());
+            let batch = BatchLimits {
+                min: args.rx_batch_size().min.into(),
+                max: args.rx_batch_size().max.into(),
+            };
+            let handle = match args.driver_name() {
+                "dpdk" => {
+                    info!("Using driver DPDK...");
+                    let config = LaunchConfiguration::try_from(&args)?;
+                    if let DriverConfigSection::Dpdk(dpdk) = &config.driver {
+                        for matched in &dpdk.matched {
+                            ready.note(matched.to_string());
+                        }
+                        for shortage in &dpdk.hugepage_shortages {
+                            warn!("{shortage}");
+                            ready.note(shortage.to_string());
+                        }
+                    }
+                    todo!();
+                }
+                "kernel" => {
+                    info!("Using driver kernel...");
+                    DriverKernel::start(
+                        scope,
+                        &shutdown.workers,
+                        args.kernel_interfaces(),
+                        args.kernel_num_workers(),
+                        &pipeline_factory,
+                        tap_interfaces_rx,
+                        Watchdog::new(
+                            args.worker_stall_timeout(),
+                            args.worker_stall_profile_dir().map(PathBuf::from),
+                        ),
+                        microbursts.clone(),
+                        batch,
+                        args.kernel_prefilter().then_some(prefilter_rx),
+                        args.kernel_rx_ring(),
+                    )?
+                }
+                "af_xdp" => {
+                    info!("Using driver AF_XDP...");
+                    DriverAfXdp::start(
+                        scope,
+                        &shutdown.workers,
+                        args.kernel_interfaces(),
+                        args.kernel_num_workers(),
+                        XskSettings {
+                            ring_size: args.xdp_ring_size(),
+                            zero_copy: !args.xdp_force_copy(),
+                        },
+                        &pipeline_factory,
+                        tap_interfaces_rx,
+                        Watchdog::new(
+                            args.worker_stall_timeout(),
+                            args.worker_stall_profile_dir().map(PathBuf::from),
+                        ),
+                        microbursts.clone(),
+                        batch,
+                    )?
+                }
+                other => return Err(format!("Unknown driver '{other}'").into()),
+            };
+            *driver_drain.lock() = Some(handle);
+            readiness::set_ready(Component::Driver, true);
+            Ok(())
+        })
+        // the metrics of the workers are only recorded once the recorder is installed
+        .after(&["mgmt", "metrics"]);
+
+        let start_generations_step =
+            StartupStep::new("generations", default_timeouts::GENERATIONS, |_| {
+                let Some(path) = args.generation_socket() else {
+                    return Ok(());
+                };
+                let config = LaunchConfiguration::try_from(&args)?;
+                let rtr_ctl = router
+                    .lock()
+                    .as_ref()
+                    .unwrap_or_else(|| unreachable!())
+                    .get_ctl_tx();
+                spawn_generation_listener(
+                    path,
+                    ConfigGeneration::new(config),
+                    mgmt_handle.clone(),
+                    rtr_ctl,
+                    metrics_addr_tx,
+                    shutdown.root.clone(),
+                )?;
+                Ok(())
+            })
+            .after(&["router", "metrics"]);
+
+        let startup = Startup::new()
+            .step(start_router_step)
+            .step(start_bmp_step)
+            .step(start_metrics_step)
+            .step(start_mgmt_step)
+            .step(start_driver_step)
+            .step(start_generations_step);
+
+        match startup.run(&shutdown) {
+            Ok(report) if report.is_success() => info!("{report}"),
+            Ok(report) => error!("{report}"),
             Err(e) => {
-                error!("Failed to start mgmt: {e}. Stopping dataplane...");
+                error!("Failed to start dataplane: {e}");
                 shutdown.fail();
             }
         }
 
         mgmt_handle.block_on(shutdown.root.cancelled());
         info!("Shutting down dataplane");
+        // the workers stop with the packets in flight unless drained first
+        readiness::set_ready(Component::Driver, false);
+        if let Some(handle) = driver_drain.lock().take()
+            && let Err(e) = mgmt_handle.block_on(handle.drain(default_deadlines::DRIVER))
+        {
+            warn!("Failed to drain the driver, dropping the packets in flight: {e}");
+        }
         mgmt_handle.block_on(shutdown.drain_in_order());
     });
 
     let exit_code = i32::from(shutdown.is_fatal());
 
-    setup.router.stop();
+    if let Some(mut router) = router.lock().take() {
+        router.stop();
+    }
     mgmt_runtime.shutdown_timeout(Duration::from_secs(2));
 
     if let Some(running) = agent_running {
@@ -378,6 +835,9 @@ pub fn main() {
             Err(e) => error!("Pyroscope stop failed: {e}"),
         }
     }
+    if let Some(otlp) = otlp {
+        otlp.shutdown();
+    }
     info!("Dataplane shutdown completed");
     std::process::exit(exit_code);
 }
@@ -0,0 +1,395 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Collection of driver-private NIC counters for physical interfaces managed by the kernel.
+//!
+//! The standard kernel interface statistics do not account for many NIC-specific drop causes
+//! (e.g. packets missed because the NIC ran out of descriptors, or PHY-level errors). Drivers
+//! expose those as "private" ethtool statistics, whose names are driver-specific. Those are only
+//! available through the `SIOCETHTOOL` ioctl (ethtool netlink only reports the standard groups).
+//!
+//! We keep a curated mapping of the most useful driver counters to normalized metric names, so
+//! that the same metric can be used regardless of the NIC vendor.
+
+use metrics::Counter;
+use nix::errno::Errno;
+use nix::libc;
+use nix::sys::socket::{AddressFamily, SockFlag, SockType, socket};
+use stats::{MetricSpec, Register};
+use std::collections::{BTreeMap, HashMap};
+use std::os::fd::{AsRawFd, OwnedFd};
+use std::path::Path;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+const SIOCETHTOOL: libc::c_ulong = 0x8946;
+const ETHTOOL_GSTRINGS: u32 = 0x1b;
+const ETHTOOL_GSTATS: u32 = 0x1d;
+const ETHTOOL_GSSET_INFO: u32 = 0x37;
+const ETH_SS_STATS: u32 = 1;
+const ETH_GSTRING_LEN: usize = 32;
+
+/// Size of the header of `struct ethtool_gstrings { u32 cmd; u32 string_set; u32 len; u8 data[]; }`
+const GSTRINGS_HEADER: usize = 3 * size_of::<u32>();
+
+/// Number of attempts at reading the private statistics of an interface, whose number may change
+/// between the requests, e.g. if the driver reconfigures the queues of the NIC
+const ATTEMPTS: usize = 3;
+
+/// Curated mapping of driver-private counter names to normalized metric names.
+///
+/// When a driver exposes more than one counter mapping to the same metric, the first one in
+/// this list wins.
+const NIC_COUNTERS: &[(&str, &str)] = &[
+    // packets dropped because the NIC had no room for them
+    ("rx_missed_errors", "nic_rx_missed_packets"), // ixgbe, igb, e1000e
+    ("rx_missed", "nic_rx_missed_packets"),
+    ("rx_out_of_buffer", "nic_rx_no_buffer_packets"), // mlx5
+    ("rx_no_buffer_count", "nic_rx_no_buffer_packets"), // igb, e1000e
+    ("rx_buff_alloc_err", "nic_rx_no_buffer_packets"), // mlx5
+    ("rx_fifo_errors", "nic_rx_fifo_errors"),
+    // drops at the port, before the packets reach the host
+    ("rx_discards_phy", "nic_rx_port_discards"),  // mlx5
+    ("port.rx_discards", "nic_rx_port_discards"), // i40e, ice
+    ("rx_dropped_phy", "nic_rx_port_discards"),
+    // frame and PHY errors
+    ("rx_crc_errors_phy", "nic_rx_crc_errors"),  // mlx5
+    ("port.rx_crc_errors", "nic_rx_crc_errors"), // i40e, ice
+    ("rx_crc_errors", "nic_rx_crc_errors"),
+    ("rx_length_errors_phy", "nic_rx_length_errors"), // mlx5
+    ("port.rx_length_errors", "nic_rx_length_errors"), // i40e, ice
+    ("rx_length_errors", "nic_rx_length_errors"),
+    ("rx_symbol_err_phy", "nic_phy_symbol_errors"), // mlx5
+    ("rx_pcs_symbol_err_phy", "nic_phy_symbol_errors"),
+    ("port.illegal_bytes", "nic_phy_symbol_errors"), // i40e, ice
+    ("rx_corrected_bits_phy", "nic_phy_corrected_bits"), // mlx5
+    // transmit side
+    ("tx_timeout_count", "nic_tx_timeouts"), // ixgbe, igb
+    ("tx_timeout", "nic_tx_timeouts"),
+    ("tx_discards_phy", "nic_tx_port_discards"), // mlx5
+    ("port.tx_dropped_link_down", "nic_tx_port_discards"), // i40e, ice
+];
+
+/// Errors when reading private NIC counters
+#[derive(Debug, thiserror::Error)]
+pub enum EthtoolError {
+    #[error("Failed to open socket for ethtool requests: {0}")]
+    Socket(Errno),
+    #[error("Invalid interface name '{0}'")]
+    InvalidName(String),
+    #[error("Ethtool request {cmd:#x} failed for interface {ifname}: {errno}")]
+    Request {
+        ifname: String,
+        cmd: u32,
+        errno: Errno,
+    },
+    #[error("The number of private statistics of interface {0} keeps changing")]
+    CountChanged(String),
+}
+
+/// Parse the reply to an `ETHTOOL_GSSET_INFO` request for the statistics string set
+fn parse_sset_info(buf: &[u8]) -> usize {
+    let mask = u64::from_ne_bytes(buf[8..16].try_into().unwrap_or_else(|_| unreachable!()));
+    if mask & (1 << ETH_SS_STATS) == 0 {
+        return 0;
+    }
+    let count = u32::from_ne_bytes(buf[16..20].try_into().unwrap_or_else(|_| unreachable!()));
+    count as usize
+}
+
+/// Parse the reply to an `ETHTOOL_GSTRINGS` request for `count` strings. Returns `None` if the
+/// kernel reported another number of strings.
+fn parse_gstrings(buf: &[u8], count: usize) -> Option<Vec<String>> {
+    let len = u32::from_ne_bytes(buf[8..12].try_into().unwrap_or_else(|_| unreachable!()));
+    if len as usize != count {
+        return None;
+    }
+    let names = buf[GSTRINGS_HEADER..]
+        .chunks_exact(ETH_GSTRING_LEN)
+        .take(count)
+        .map(|raw| {
+            let len = raw.iter().position(|c| *c == 0).unwrap_or(raw.len());
+            String::from_utf8_lossy(&raw[..len]).into_owned()
+        })
+        .collect();
+    Some(names)
+}
+
+/// Parse the reply to an `ETHTOOL_GSTATS` request for `count` values. Returns `None` if the
+/// kernel reported another number of values.
+fn parse_gstats(buf: &[u64], count: usize) -> Option<Vec<u64>> {
+    let (header, values) = buf.split_first()?;
+    let header = header.to_ne_bytes();
+    let n_stats = u32::from_ne_bytes(header[4..8].try_into().unwrap_or_else(|_| unreachable!()));
+    (n_stats as usize == count && values.len() >= count).then(|| values[..count].to_vec())
+}
+
+/// A socket used to issue `SIOCETHTOOL` requests
+struct EthtoolSocket(OwnedFd);
+
+impl EthtoolSocket {
+    fn new() -> Result<Self, EthtoolError> {
+        socket(
+            AddressFamily::Inet,
+            SockType::Datagram,
+            SockFlag::SOCK_CLOEXEC,
+            None,
+        )
+        .map(Self)
+        .map_err(EthtoolError::Socket)
+    }
+
+    /// Issue an ethtool request. `data` must point to a buffer starting with the `u32` command
+    /// and large enough for the kernel to fill in the reply for that command.
+    fn request(&self, ifname: &str, cmd: u32, data: *mut libc::c_void) -> Result<(), EthtoolError> {
+        // SAFETY: ifreq is a plain C struct for which all-zeroes is a valid value
+        let mut ifr: libc::ifreq = unsafe { std::mem::zeroed() };
+        let name = ifname.as_bytes();
+        if name.is_empty() || name.len() >= ifr.ifr_name.len() {
+            return Err(EthtoolError::InvalidName(ifname.to_string()));
+        }
+        for (dst, src) in ifr.ifr_name.iter_mut().zip(name) {
+            *dst = libc::c_char::from_ne_bytes([*src]);
+        }
+        ifr.ifr_ifru.ifru_data = data.cast();
+        // SAFETY: the caller guarantees that data is suitable for the command
+        let ret = unsafe { libc::ioctl(self.0.as_raw_fd(), SIOCETHTOOL as _, &mut ifr) };
+        if ret < 0 {
+            return Err(EthtoolError::Request {
+                ifname: ifname.to_string(),
+                cmd,
+                errno: Errno::last(),
+            });
+        }
+        Ok(())
+    }
+
+    /// Get the number of private statistics of an interface
+    fn num_stats(&self, ifname: &str) -> Result<usize, EthtoolError> {
+        // struct ethtool_sset_info { u32 cmd; u32 reserved; u64 sset_mask; u32 data[]; }
+        let mut buf = [0u8; 2 * size_of::<u32>() + size_of::<u64>() + size_of::<u32>()];
+        buf[0..4].copy_from_slice(&ETHTOOL_GSSET_INFO.to_ne_bytes());
+        buf[8..16].copy_from_slice(&(1u64 << ETH_SS_STATS).to_ne_bytes());
+        self.request(ifname, ETHTOOL_GSSET_INFO, buf.as_mut_ptr().cast())?;
+        Ok(parse_sset_info(&buf))
+    }
+
+    /// Get the names of the private statistics of an interface, if there are still `count`
+    fn stat_names(&self, ifname: &str, count: usize) -> Result<Option<Vec<String>>, EthtoolError> {
+        let mut buf = vec![0u8; GSTRINGS_HEADER + count * ETH_GSTRING_LEN];
+        buf[0..4].copy_from_slice(&ETHTOOL_GSTRINGS.to_ne_bytes());
+        buf[4..8].copy_from_slice(&ETH_SS_STATS.to_ne_bytes());
+        #[allow(clippy::cast_possible_truncation)] // the count was reported as u32
+        buf[8..12].copy_from_slice(&(count as u32).to_ne_bytes());
+        self.request(ifname, ETHTOOL_GSTRINGS, buf.as_mut_ptr().cast())?;
+        Ok(parse_gstrings(&buf, count))
+    }
+
+    /// Get the values of the private statistics of an interface, if there are still `count`
+    fn stat_values(&self, ifname: &str, count: usize) -> Result<Option<Vec<u64>>, EthtoolError> {
+        // struct ethtool_stats { u32 cmd; u32 n_stats; u64 data[]; }
+        let mut buf = vec![0u64; 1 + count];
+        let mut header = [0u8; 8];
+        header[0..4].copy_from_slice(&ETHTOOL_GSTATS.to_ne_bytes());
+        #[allow(clippy::cast_possible_truncation)] // the count was reported as u32
+        header[4..8].copy_from_slice(&(count as u32).to_ne_bytes());
+        buf[0] = u64::from_ne_bytes(header);
+        self.request(ifname, ETHTOOL_GSTATS, buf.as_mut_ptr().cast())?;
+        Ok(parse_gstats(&buf, count))
+    }
+
+    /// Get all the private statistics of an interface, by name. The names and values are read
+    /// again if their number changed between the requests, so that they are not mismatched.
+    fn private_stats(&self, ifname: &str) -> Result<HashMap<String, u64>, EthtoolError> {
+        for _ in 0..ATTEMPTS {
+            let count = self.num_stats(ifname)?;
+            if count == 0 {
+                return Ok(HashMap::new());
+            }
+            let Some(names) = self.stat_names(ifname, count)? else {
+                debug!("The number of private statistics of {ifname} changed, reading them again");
+                continue;
+            };
+            let Some(values) = self.stat_values(ifname, count)? else {
+                debug!("The number of private statistics of {ifname} changed, reading them again");
+                continue;
+            };
+            return Ok(names.into_iter().zip(values).collect());
+        }
+        Err(EthtoolError::CountChanged(ifname.to_string()))
+    }
+}
+
+/// Map driver-private counters to normalized metric names, using [`NIC_COUNTERS`].
+fn normalize(stats: &HashMap<String, u64>) -> BTreeMap<&'static str, u64> {
+    let mut normalized = BTreeMap::new();
+    for (driver_name, metric) in NIC_COUNTERS {
+        if normalized.contains_key(metric) {
+            continue;
+        }
+        if let Some(value) = stats.get(*driver_name) {
+            normalized.insert(*metric, *value);
+        }
+    }
+    normalized
+}
+
+/// Tell if an interface is backed by a device (as opposed to a virtual interface)
+fn is_physical(ifname: &str) -> bool {
+    Path::new("/sys/class/net")
+        .join(ifname)
+        .join("device")
+        .exists()
+}
+
+/// Periodically exports the normalized private counters of a set of physical interfaces
+pub struct NicStatsCollector {
+    socket: EthtoolSocket,
+    interfaces: Vec<String>,
+    counters: HashMap<(String, &'static str), Counter>,
+    failing: HashMap<String, bool>,
+}
+
+impl NicStatsCollector {
+    /// Polling period of the private counters
+    pub const PERIOD: Duration = Duration::from_secs(10);
+
+    /// Create a collector for the physical interfaces among `interfaces`.
+    /// Returns `None` if there are none.
+    pub fn new(interfaces: impl IntoIterator<Item = String>) -> Result<Option<Self>, EthtoolError> {
+        let interfaces: Vec<_> = interfaces
+            .into_iter()
+            .filter(|ifname| {
+                let physical = is_physical(ifname);
+                if !physical {
+                    debug!("Not collecting NIC counters for virtual interface {ifname}");
+                }
+                physical
+            })
+            .collect();
+        if interfaces.is_empty() {
+            return Ok(None);
+        }
+        info!("Collecting NIC counters for interfaces {interfaces:?}");
+        Ok(Some(Self {
+            socket: EthtoolSocket::new()?,
+            interfaces,
+            counters: HashMap::new(),
+            failing: HashMap::new(),
+        }))
+    }
+
+    fn poll_interface(&mut self, ifname: &str) -> Result<(), EthtoolError> {
+        let stats = self.socket.private_stats(ifname)?;
+        for (metric, value) in normalize(&stats) {
+            self.counters
+                .entry((ifname.to_string(), metric))
+                .or_insert_with(|| {
+                    let labels = vec![("interface".to_string(), ifname.to_string())];
+                    MetricSpec::new(metric, metrics::Unit::Count, labels)
+                        .register()
+                        .metric
+                })
+                .absolute(value);
+        }
+        Ok(())
+    }
+
+    fn poll(&mut self) {
+        for ifname in self.interfaces.clone() {
+            let result = self.poll_interface(&ifname);
+            let failing = self.failing.entry(ifname.clone()).or_default();
+            match result {
+                Ok(()) if *failing => {
+                    info!("Reading NIC counters of {ifname} succeeds again");
+                    *failing = false;
+                }
+                Err(e) if !*failing => {
+                    warn!("Failed to read NIC counters: {e}");
+                    *failing = true;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Poll the counters every [`Self::PERIOD`], forever
+    pub async fn run(mut self) {
+        let mut ticker = tokio::time::interval(Self::PERIOD);
+        loop {
+            ticker.tick().await;
+            self.poll();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gstrings(names: &[&str], len: u32) -> Vec<u8> {
+        let mut buf = vec![0u8; GSTRINGS_HEADER + names.len() * ETH_GSTRING_LEN];
+        buf[0..4].copy_from_slice(&ETHTOOL_GSTRINGS.to_ne_bytes());
+        buf[4..8].copy_from_slice(&ETH_SS_STATS.to_ne_bytes());
+        buf[8..12].copy_from_slice(&len.to_ne_bytes());
+        for (name, raw) in names
+            .iter()
+            .zip(buf[GSTRINGS_HEADER..].chunks_exact_mut(ETH_GSTRING_LEN))
+        {
+            raw[..name.len()].copy_from_slice(name.as_bytes());
+        }
+        buf
+    }
+
+    fn gstats(values: &[u64], n_stats: u32) -> Vec<u64> {
+        let mut header = [0u8; 8];
+        header[0..4].copy_from_slice(&ETHTOOL_GSTATS.to_ne_bytes());
+        header[4..8].copy_from_slice(&n_stats.to_ne_bytes());
+        let mut buf = vec![u64::from_ne_bytes(header)];
+        buf.extend_from_slice(values);
+        buf
+    }
+
+    #[test]
+    fn test_parse_sset_info() {
+        let mut buf = [0u8; 20];
+        buf[16..20].copy_from_slice(&42u32.to_ne_bytes());
+        assert_eq!(parse_sset_info(&buf), 0, "the string set is not supported");
+        buf[8..16].copy_from_slice(&(1u64 << ETH_SS_STATS).to_ne_bytes());
+        assert_eq!(parse_sset_info(&buf), 42);
+    }
+
+    #[test]
+    fn test_parse_gstrings() {
+        let longest = "x".repeat(ETH_GSTRING_LEN);
+        let names = ["rx_missed_errors", "port.rx_discards", longest.as_str()];
+        let buf = gstrings(&names, 3);
+        assert_eq!(parse_gstrings(&buf, 3).unwrap(), names);
+        // the number of statistics changed since it was queried
+        assert!(parse_gstrings(&gstrings(&names, 2), 3).is_none());
+        assert!(parse_gstrings(&gstrings(&names, 4), 3).is_none());
+    }
+
+    #[test]
+    fn test_parse_gstats() {
+        let buf = gstats(&[1, 2, 3], 3);
+        assert_eq!(parse_gstats(&buf, 3).unwrap(), [1, 2, 3]);
+        assert!(parse_gstats(&gstats(&[1, 2, 3], 4), 3).is_none());
+        assert!(parse_gstats(&gstats(&[1, 2], 2), 3).is_none());
+        assert!(parse_gstats(&[], 0).is_none());
+    }
+
+    #[test]
+    fn test_normalize() {
+        let stats = HashMap::from([
+            ("rx_out_of_buffer".to_string(), 5),
+            ("rx_buff_alloc_err".to_string(), 7),
+            ("rx_crc_errors".to_string(), 1),
+            ("unknown".to_string(), 9),
+        ]);
+        let normalized = normalize(&stats);
+        // the first counter in the mapping wins
+        let expected = BTreeMap::from([("nic_rx_no_buffer_packets", 5), ("nic_rx_crc_errors", 1)]);
+        assert_eq!(normalized, expected);
+    }
+}
@@ -0,0 +1,362 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Read-only looking glass API, served alongside the metrics.
+//!
+//! It lets NOC tooling query a gateway without the CLI:
+//!
+//! - `GET /lookup?vrf=X&ip=Y`: longest prefix match of `Y` in the FIB of the VRF with id `X`
+//! - `GET /bgp/peers`: the status of the BGP sessions, as reported over BMP
+//! - `GET /interfaces`: a summary of the interfaces known to the router
+//!
+//! Every request must present the configured token as `Authorization: Bearer <token>`.
+
+use axum::extract::{Query, Request, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::{Json, Router};
+use concurrency::sync::Arc;
+use config::internal::status::{BgpNeighborStatus, DataplaneStatus};
+use routing::{Attachment, FibKey, FibTableReaderFactory, IfTableReaderFactory, IfType, VrfId};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+use tokio::sync::RwLock;
+use tracing::debug;
+
+/// The state the looking glass reports on
+#[derive(Clone)]
+pub struct LookingGlass {
+    token: Arc<str>,
+    fibtr: Arc<FibTableReaderFactory>,
+    iftr: Arc<IfTableReaderFactory>,
+    dp_status: Arc<RwLock<DataplaneStatus>>,
+}
+
+impl LookingGlass {
+    pub fn new(
+        token: &str,
+        fibtr: FibTableReaderFactory,
+        iftr: IfTableReaderFactory,
+        dp_status: Arc<RwLock<DataplaneStatus>>,
+    ) -> Self {
+        Self {
+            token: Arc::from(token),
+            fibtr: Arc::new(fibtr),
+            iftr: Arc::new(iftr),
+            dp_status,
+        }
+    }
+
+    /// The routes of the looking glass, to be merged in the metrics server
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/lookup", get(lookup_handler))
+            .route("/bgp/peers", get(bgp_peers_handler))
+            .route("/interfaces", get(interfaces_handler))
+            .route_layer(middleware::from_fn_with_state(self.clone(), require_token))
+            .with_state(self)
+    }
+}
+
+/// Compare a presented token with the configured one, in constant time
+fn tokens_match(expected: &str, presented: &str) -> bool {
+    let expected = expected.as_bytes();
+    let presented = presented.as_bytes();
+    expected.len() == presented.len()
+        && expected
+            .iter()
+            .zip(presented)
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
+
+/// The bearer token in the `Authorization` header of a request, if any
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+/// Reject the requests not bearing the configured token
+async fn require_token(State(lg): State<LookingGlass>, request: Request, next: Next) -> Response {
+    let authorized =
+        bearer_token(request.headers()).is_some_and(|token| tokens_match(&lg.token, token));
+    if !authorized {
+        debug!(
+            "Rejecting unauthorized looking glass request to {}",
+            request.uri()
+        );
+        return (StatusCode::UNAUTHORIZED, "missing or invalid token").into_response();
+    }
+    next.run(request).await
+}
+
+#[derive(Deserialize)]
+struct LookupQuery {
+    vrf: VrfId,
+    ip: IpAddr,
+}
+
+#[derive(Serialize)]
+struct LookupResult {
+    vrf: VrfId,
+    ip: IpAddr,
+    prefix: String,
+    /// The packet instructions of each of the ECMP entries of the route
+    entries: Vec<Vec<String>>,
+}
+
+/// Longest prefix match of an address in the FIB of a VRF
+fn lookup(fibtr: &FibTableReaderFactory, query: &LookupQuery) -> Result<LookupResult, Response> {
+    let fibtable = fibtr.handle();
+    let fib = fibtable
+        .get_fib_reader(FibKey::Id(query.vrf))
+        .map_err(|_| (StatusCode::NOT_FOUND, format!("no vrf {}", query.vrf)).into_response())?;
+    let Some((prefix, route)) = fib.lpm_route_with_prefix(query.ip) else {
+        return Err((StatusCode::SERVICE_UNAVAILABLE, "fib is not available").into_response());
+    };
+    Ok(LookupResult {
+        vrf: query.vrf,
+        ip: query.ip,
+        prefix: prefix.to_string(),
+        entries: route
+            .entries()
+            .map(|entry| entry.iter().map(ToString::to_string).collect())
+            .collect(),
+    })
+}
+
+async fn lookup_handler(
+    State(lg): State<LookingGlass>,
+    Query(query): Query<LookupQuery>,
+) -> Response {
+    match lookup(&lg.fibtr, &query) {
+        Ok(result) => Json(result).into_response(),
+        Err(response) => response,
+    }
+}
+
+#[derive(Serialize)]
+struct BgpPeer {
+    vrf: String,
+    peer: String,
+    enabled: bool,
+    local_as: u32,
+    peer_as: u32,
+    remote_router_id: String,
+    state: String,
+    established_transitions: u64,
+    connections_dropped: u64,
+    last_reset_reason: Option<String>,
+    prefixes_received: u32,
+    prefixes_sent: u32,
+}
+
+impl BgpPeer {
+    fn new(vrf: &str, peer: &str, status: &BgpNeighborStatus) -> Self {
+        let prefixes = [
+            &status.ipv4_unicast_prefixes,
+            &status.ipv6_unicast_prefixes,
+            &status.l2vpn_evpn_prefixes,
+        ];
+        Self {
+            vrf: vrf.to_string(),
+            peer: peer.to_string(),
+            enabled: status.enabled,
+            local_as: status.local_as,
+            peer_as: status.peer_as,
+            remote_router_id: status.remote_router_id.clone(),
+            state: status.session_state.to_string(),
+            established_transitions: status.established_transitions,
+            connections_dropped: status.connections_dropped,
+            last_reset_reason: status.last_reset_reason.clone(),
+            prefixes_received: prefixes
+                .iter()
+                .flat_map(|p| p.as_ref())
+                .map(|p| p.received)
+                .sum(),
+            prefixes_sent: prefixes
+                .iter()
+                .flat_map(|p| p.as_ref())
+                .map(|p| p.sent)
+                .sum(),
+        }
+    }
+}
+
+/// The BGP peers in the status, sorted by VRF and peer
+fn bgp_peers(status: &DataplaneStatus) -> Vec<BgpPeer> {
+    let mut peers: Vec<_> = status
+        .bgp
+        .iter()
+        .flat_map(|bgp| bgp.vrfs.iter())
+        .flat_map(|(vrf, vrf_status)| {
+            vrf_status
+                .neighbors
+                .iter()
+                .map(move |(peer, neighbor)| BgpPeer::new(vrf, peer, neighbor))
+        })
+        .collect();
+    peers.sort_by(|a, b| (&a.vrf, &a.peer).cmp(&(&b.vrf, &b.peer)));
+    peers
+}
+
+async fn bgp_peers_handler(State(lg): State<LookingGlass>) -> Json<Vec<BgpPeer>> {
+    Json(bgp_peers(&*lg.dp_status.read().await))
+}
+
+#[derive(Serialize)]
+struct InterfaceSummary {
+    name: String,
+    ifindex: u32,
+    kind: &'static str,
+    admin_state: String,
+    oper_state: String,
+    mtu: Option<u32>,
+    addresses: Vec<String>,
+    vrf: Option<VrfId>,
+}
+
+/// Summarize the interfaces of the router
+fn interfaces(iftr: &IfTableReaderFactory) -> Vec<InterfaceSummary> {
+    let iftable = iftr.handle();
+    let Some(iftable) = iftable.enter() else {
+        return vec![];
+    };
+    let mut summaries: Vec<_> = iftable
+        .values()
+        .map(|iface| {
+            let mut addresses: Vec<_> = iface.addresses.iter().map(ToString::to_string).collect();
+            addresses.sort();
+            InterfaceSummary {
+                name: iface.name.clone(),
+                ifindex: iface.ifindex.to_u32(),
+                kind: match iface.iftype {
+                    IfType::Unknown => "unknown",
+                    IfType::Ethernet(_) => "ethernet",
+                    IfType::Dot1q(_) => "dot1q",
+                    IfType::Loopback => "loopback",
+                    IfType::Vxlan => "vxlan",
+                },
+                admin_state: iface.admin_state.to_string(),
+                oper_state: iface.oper_state.to_string(),
+                mtu: iface.mtu.map(|mtu| mtu.to_u32()),
+                addresses,
+                vrf: match iface.attachment {
+                    Some(Attachment::Vrf(FibKey::Id(vrfid))) => Some(vrfid),
+                    _ => None,
+                },
+            }
+        })
+        .collect();
+    summaries.sort_by_key(|summary| summary.ifindex);
+    summaries
+}
+
+async fn interfaces_handler(State(lg): State<LookingGlass>) -> Json<Vec<InterfaceSummary>> {
+    Json(interfaces(&lg.iftr))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use config::internal::status::{
+        BgpNeighborPrefixes, BgpNeighborSessionState, BgpStatus, BgpVrfStatus,
+    };
+
+    #[test]
+    fn test_tokens_match() {
+        assert!(tokens_match("s3cr3t", "s3cr3t"));
+        assert!(!tokens_match("s3cr3t", "s3cr3T"));
+        assert!(!tokens_match("s3cr3t", "s3cr3"));
+        assert!(!tokens_match("s3cr3t", "s3cr3t0"));
+        assert!(!tokens_match("s3cr3t", ""));
+    }
+
+    #[test]
+    fn test_bearer_token() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(bearer_token(&headers), None);
+
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer s3cr3t "),
+        );
+        assert_eq!(bearer_token(&headers), Some("s3cr3t"));
+
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Basic s3cr3t"),
+        );
+        assert_eq!(bearer_token(&headers), None);
+    }
+
+    fn neighbor(peer_as: u32, received: u32, sent: u32) -> BgpNeighborStatus {
+        BgpNeighborStatus {
+            enabled: true,
+            peer_as,
+            session_state: BgpNeighborSessionState::Established,
+            ipv4_unicast_prefixes: Some(BgpNeighborPrefixes {
+                received,
+                received_pre_policy: received,
+                sent,
+            }),
+            l2vpn_evpn_prefixes: Some(BgpNeighborPrefixes {
+                received: 1,
+                received_pre_policy: 1,
+                sent: 1,
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_bgp_peers() {
+        assert!(bgp_peers(&DataplaneStatus::default()).is_empty());
+
+        let mut bgp = BgpStatus::default();
+        let mut default = BgpVrfStatus::default();
+        default
+            .neighbors
+            .insert("10.0.0.2".to_string(), neighbor(65002, 10, 20));
+        default
+            .neighbors
+            .insert("10.0.0.1".to_string(), neighbor(65001, 0, 0));
+        bgp.vrfs.insert("default".to_string(), default);
+        let mut blue = BgpVrfStatus::default();
+        blue.neighbors
+            .insert("192.168.0.1".to_string(), neighbor(65100, 5, 0));
+        bgp.vrfs.insert("blue".to_string(), blue);
+        let status = DataplaneStatus {
+            bgp: Some(bgp),
+            ..Default::default()
+        };
+
+        let peers = bgp_peers(&status);
+        let keys: Vec<_> = peers
+            .iter()
+            .map(|peer| (peer.vrf.as_str(), peer.peer.as_str()))
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                ("blue", "192.168.0.1"),
+                ("default", "10.0.0.1"),
+                ("default", "10.0.0.2")
+            ]
+        );
+        // prefixes are summed over the address families
+        assert_eq!(peers[2].peer_as, 65002);
+        assert_eq!(peers[2].prefixes_received, 11);
+        assert_eq!(peers[2].prefixes_sent, 21);
+        assert_eq!(
+            peers[2].state,
+            BgpNeighborSessionState::Established.to_string()
+        );
+    }
+}
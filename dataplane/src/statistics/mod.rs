@@ -1,12 +1,23 @@
 // SPDX-License-Identifier: Apache-2.0
 // Copyright Open Network Fabric Authors
 
+mod ethtool;
+mod looking_glass;
+mod timehealth;
+
 use axum::{Router, response::Response, routing::get};
 use lifecycle::Subsystem;
 use metrics_exporter_prometheus::{Matcher, PrometheusBuilder, PrometheusHandle};
+use metrics_util::layers::FanoutBuilder;
 use stats::StatsCollector;
 use std::time::Duration;
-use tracing::{error, info};
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+use crate::otlp::OtlpRecorder;
+use ethtool::NicStatsCollector;
+pub use looking_glass::LookingGlass;
+use timehealth::TimeHealthMonitor;
 
 use tracectl::trace_target;
 trace_target!("stats-server", LevelFilter::INFO, &[]);
@@ -17,8 +28,9 @@ pub struct PrometheusHandler {
 }
 
 impl PrometheusHandler {
-    pub fn new() -> Self {
-        let prometheus_handle = PrometheusBuilder::new()
+    /// Install the Prometheus recorder, along with `otlp` if the metrics are exported over OTLP
+    pub fn new(otlp: Option<OtlpRecorder>) -> Self {
+        let prometheus = PrometheusBuilder::new()
             .set_buckets_for_metric(
                 Matcher::Full("http_request_duration_seconds".to_string()),
                 &[
@@ -26,8 +38,28 @@ impl PrometheusHandler {
                 ],
             )
             .unwrap()
-            .install_recorder()
-            .unwrap();
+            .set_buckets_for_metric(
+                Matcher::Full("host_inject_latency".to_string()),
+                &[
+                    0.000_01, 0.000_05, 0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.05, 0.1,
+                ],
+            )
+            .unwrap()
+            .build_recorder();
+        let prometheus_handle = prometheus.handle();
+        let installed = match otlp {
+            None => metrics::set_global_recorder(prometheus).map_err(|e| e.to_string()),
+            Some(otlp) => metrics::set_global_recorder(
+                FanoutBuilder::default()
+                    .add_recorder(prometheus)
+                    .add_recorder(otlp)
+                    .build(),
+            )
+            .map_err(|e| e.to_string()),
+        };
+        if let Err(e) = installed {
+            panic!("Failed to install metrics recorder: {e}");
+        }
 
         Self {
             handle: prometheus_handle,
@@ -45,19 +77,27 @@ async fn metrics_handler(
         .unwrap()
 }
 
-/// Spawn the `/metrics` endpoint on `addr`, a 30s upkeep ticker, and the
-/// stats collector onto `handle`, tracked under `metrics`. Uses
+/// Spawn the `/metrics` endpoint on the address of `addr`, a 30s upkeep
+/// ticker, and the stats collector onto `handle`, tracked under `metrics`.
+/// The endpoint moves whenever the address changes. Driver-private
+/// counters are collected for the physical NICs among `nic_interfaces`, and
+/// the health of the system clocks is monitored. The looking glass API, if
+/// any, is served on the same endpoint. The metrics are also exported over
+/// OTLP with `otlp`, if any. Uses
 /// [`Subsystem::spawn_on`] — a dead metrics endpoint should not take down
 /// the dataplane.
 pub fn spawn_metrics(
     metrics: &Subsystem,
     handle: &tokio::runtime::Handle,
-    addr: std::net::SocketAddr,
+    mut addr: watch::Receiver<std::net::SocketAddr>,
     stats: StatsCollector,
+    nic_interfaces: Vec<String>,
+    looking_glass: Option<LookingGlass>,
+    otlp: Option<OtlpRecorder>,
 ) {
     let PrometheusHandler {
         handle: prom_handle,
-    } = PrometheusHandler::new();
+    } = PrometheusHandler::new(otlp);
 
     let upkeep_handle = prom_handle.clone();
     let upkeep_cancel = metrics.cancel_token();
@@ -87,22 +127,65 @@ pub fn spawn_metrics(
         handle,
     );
 
+    let clock_monitor = TimeHealthMonitor::new();
+    let clock_cancel = metrics.cancel_token();
+    metrics.spawn_on(
+        async move {
+            tokio::select! {
+                () = clock_cancel.cancelled() => {}
+                () = clock_monitor.run() => {}
+            }
+        },
+        handle,
+    );
+
+    match NicStatsCollector::new(nic_interfaces) {
+        Ok(Some(collector)) => {
+            let nic_cancel = metrics.cancel_token();
+            metrics.spawn_on(
+                async move {
+                    tokio::select! {
+                        () = nic_cancel.cancelled() => {}
+                        () = collector.run() => {}
+                    }
+                },
+                handle,
+            );
+        }
+        Ok(None) => {}
+        Err(e) => warn!("NIC counters will not be collected: {e}"),
+    }
+
     let server_cancel = metrics.cancel_token();
     metrics.spawn_on(
         async move {
-            let app = Router::new()
+            let mut app = Router::new()
                 .route("/metrics", get(metrics_handler))
                 .with_state(prom_handle);
+            if let Some(looking_glass) = looking_glass {
+                info!("looking glass enabled");
+                app = app.merge(looking_glass.router());
+            }
 
-            info!("metrics server listening on {}", addr);
+            loop {
+                let bind_addr = *addr.borrow_and_update();
+                info!("metrics server listening on {}", bind_addr);
+                let server = axum_server::bind(bind_addr).serve(app.clone().into_make_service());
 
-            tokio::select! {
-                () = server_cancel.cancelled() => {
-                    info!("metrics server shutdown requested");
-                }
-                res = axum_server::bind(addr).serve(app.into_make_service()) => {
-                    if let Err(e) = res {
-                        error!("metrics server error: {}", e);
+                tokio::select! {
+                    () = server_cancel.cancelled() => {
+                        info!("metrics server shutdown requested");
+                        break;
+                    }
+                    // once the sender is gone, the address won't change anymore
+                    Ok(()) = addr.changed() => {
+                        info!("metrics server moving away from {}", bind_addr);
+                    }
+                    res = server => {
+                        if let Err(e) = res {
+                            error!("metrics server error: {}", e);
+                        }
+                        break;
                     }
                 }
             }
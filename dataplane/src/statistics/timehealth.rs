@@ -0,0 +1,287 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Health of the system clocks.
+//!
+//! Flow aging, rate limiting and other timers in the dataplane are based on the monotonic clock
+//! (`Instant`) and are unaffected by changes of the realtime clock. Timestamps shared with other
+//! processes or hosts (e.g. for session state sync) use the realtime clock, however, and are only
+//! meaningful if that clock is synchronized and does not jump.
+//!
+//! This module periodically samples both clocks and reports:
+//! - the skew of the realtime clock relative to the monotonic clock since startup, which
+//!   accumulates the frequency corrections and steps applied by NTP,
+//! - the realtime jumps (steps) beyond [`TimeHealthMonitor::JUMP_THRESHOLD`],
+//! - whether the kernel considers the clock synchronized.
+
+use metrics::{Counter, Gauge};
+use nix::libc;
+use stats::{MetricSpec, Register};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// A simultaneous reading of the monotonic and realtime clocks
+#[derive(Clone, Copy, Debug)]
+struct ClockSample {
+    monotonic: Instant,
+    realtime: SystemTime,
+}
+
+impl ClockSample {
+    fn now() -> Self {
+        Self {
+            monotonic: Instant::now(),
+            realtime: SystemTime::now(),
+        }
+    }
+
+    /// Realtime clock, in nanoseconds since the epoch (negative if before it)
+    fn realtime_ns(&self) -> i128 {
+        match self.realtime.duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_nanos().cast_signed(),
+            Err(e) => -e.duration().as_nanos().cast_signed(),
+        }
+    }
+}
+
+/// The result of comparing a [`ClockSample`] with the previous ones
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct ClockObservation {
+    /// Realtime minus monotonic elapsed time since the first sample, in seconds
+    skew: f64,
+    /// Realtime minus monotonic elapsed time since the previous sample, in seconds
+    step: f64,
+}
+
+/// Tracks the offset between the realtime and monotonic clocks over successive samples
+#[derive(Debug)]
+struct ClockTracker {
+    first: ClockSample,
+    last: ClockSample,
+}
+
+impl ClockTracker {
+    fn new(sample: ClockSample) -> Self {
+        Self {
+            first: sample,
+            last: sample,
+        }
+    }
+
+    /// Difference between the realtime and monotonic time elapsed between two samples
+    #[allow(clippy::cast_precision_loss)] // nanosecond precision is irrelevant here
+    fn drift(from: &ClockSample, to: &ClockSample) -> f64 {
+        let real = to.realtime_ns() - from.realtime_ns();
+        let mono = to
+            .monotonic
+            .saturating_duration_since(from.monotonic)
+            .as_nanos()
+            .cast_signed();
+        (real - mono) as f64 / 1e9
+    }
+
+    fn observe(&mut self, sample: ClockSample) -> ClockObservation {
+        let observation = ClockObservation {
+            skew: Self::drift(&self.first, &sample),
+            step: Self::drift(&self.last, &sample),
+        };
+        self.last = sample;
+        observation
+    }
+}
+
+/// Tell if the kernel considers the realtime clock synchronized (e.g. by an NTP daemon)
+fn clock_synchronized() -> Option<bool> {
+    // SAFETY: timex is a plain C struct for which all-zeroes is a valid value. With no mode
+    // bits set, adjtimex only reads the state of the kernel clock.
+    let mut timex: libc::timex = unsafe { std::mem::zeroed() };
+    // SAFETY: timex is valid and exclusively borrowed for the duration of the call
+    let state = unsafe { libc::adjtimex(&raw mut timex) };
+    match state {
+        -1 => None,
+        libc::TIME_ERROR => Some(false),
+        _ => Some(true),
+    }
+}
+
+/// Periodically checks the health of the system clocks and exports it as metrics
+pub struct TimeHealthMonitor {
+    tracker: ClockTracker,
+    synchronized: Option<bool>,
+    skew: Gauge,
+    jumps: Counter,
+    synced: Gauge,
+}
+
+impl TimeHealthMonitor {
+    /// Sampling period of the clocks
+    pub const PERIOD: Duration = Duration::from_secs(5);
+
+    /// Realtime steps beyond this threshold are reported as jumps. Slewing by NTP never gets
+    /// anywhere close to it within a [`Self::PERIOD`].
+    pub const JUMP_THRESHOLD: f64 = 0.5;
+
+    #[must_use]
+    pub fn new() -> Self {
+        let spec = |id: &str, unit| MetricSpec::new(id, unit, vec![]);
+        Self {
+            tracker: ClockTracker::new(ClockSample::now()),
+            synchronized: None,
+            skew: spec("clock_realtime_skew", metrics::Unit::Seconds)
+                .register()
+                .metric,
+            jumps: spec("clock_realtime_jumps", metrics::Unit::Count)
+                .register()
+                .metric,
+            synced: spec("clock_synchronized", metrics::Unit::Count)
+                .register()
+                .metric,
+        }
+    }
+
+    fn check(&mut self) {
+        let observation = self.tracker.observe(ClockSample::now());
+        self.skew.set(observation.skew);
+        if observation.step.abs() > Self::JUMP_THRESHOLD {
+            self.jumps.increment(1);
+            warn!(
+                "Realtime clock jumped by {:+.3}s (skew since startup {:+.3}s)",
+                observation.step, observation.skew
+            );
+        }
+
+        let synchronized = clock_synchronized();
+        if let Some(synced) = synchronized {
+            self.synced.set(if synced { 1.0 } else { 0.0 });
+        }
+        if synchronized != self.synchronized {
+            match synchronized {
+                Some(true) => info!("Realtime clock is synchronized"),
+                Some(false) => warn!("Realtime clock is not synchronized"),
+                None => warn!("Unable to tell if the realtime clock is synchronized"),
+            }
+            self.synchronized = synchronized;
+        }
+    }
+
+    /// Check the clocks every [`Self::PERIOD`], forever
+    pub async fn run(mut self) {
+        let mut ticker = tokio::time::interval(Self::PERIOD);
+        loop {
+            ticker.tick().await;
+            self.check();
+        }
+    }
+}
+
+impl Default for TimeHealthMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sample `mono` and `real` after the `base` one, as if read from drifting clocks
+    fn sample_after(base: &ClockSample, mono: Duration, real: Duration) -> ClockSample {
+        ClockSample {
+            monotonic: base.monotonic + mono,
+            realtime: base.realtime + real,
+        }
+    }
+
+    #[test]
+    fn test_realtime_ns() {
+        let sample = ClockSample {
+            monotonic: Instant::now(),
+            realtime: UNIX_EPOCH + Duration::from_millis(1500),
+        };
+        assert_eq!(sample.realtime_ns(), 1_500_000_000);
+        let sample = ClockSample {
+            monotonic: Instant::now(),
+            realtime: UNIX_EPOCH - Duration::from_secs(2),
+        };
+        assert_eq!(sample.realtime_ns(), -2_000_000_000);
+    }
+
+    #[test]
+    fn test_clocks_in_step() {
+        let base = ClockSample::now();
+        let mut tracker = ClockTracker::new(base);
+        for secs in 1..=3 {
+            let elapsed = Duration::from_secs(secs);
+            let observation = tracker.observe(sample_after(&base, elapsed, elapsed));
+            assert_eq!(
+                observation,
+                ClockObservation {
+                    skew: 0.0,
+                    step: 0.0
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_clock_jumps() {
+        let base = ClockSample::now();
+        let mut tracker = ClockTracker::new(base);
+
+        // realtime jumps 2s ahead
+        let observation = tracker.observe(sample_after(
+            &base,
+            Duration::from_secs(5),
+            Duration::from_secs(7),
+        ));
+        assert_eq!(
+            observation,
+            ClockObservation {
+                skew: 2.0,
+                step: 2.0
+            }
+        );
+
+        // no jump since the previous sample, but the skew since the first one remains
+        let observation = tracker.observe(sample_after(
+            &base,
+            Duration::from_secs(10),
+            Duration::from_secs(12),
+        ));
+        assert_eq!(
+            observation,
+            ClockObservation {
+                skew: 2.0,
+                step: 0.0
+            }
+        );
+
+        // realtime steps 3s back
+        let observation = tracker.observe(sample_after(
+            &base,
+            Duration::from_secs(15),
+            Duration::from_secs(14),
+        ));
+        assert_eq!(
+            observation,
+            ClockObservation {
+                skew: -1.0,
+                step: -3.0
+            }
+        );
+        assert!(observation.step.abs() > TimeHealthMonitor::JUMP_THRESHOLD);
+    }
+
+    #[test]
+    fn test_clock_slew_is_not_a_jump() {
+        let base = ClockSample::now();
+        let mut tracker = ClockTracker::new(base);
+        let observation = tracker.observe(sample_after(
+            &base,
+            TimeHealthMonitor::PERIOD,
+            TimeHealthMonitor::PERIOD + Duration::from_millis(2),
+        ));
+        assert!((observation.step - 0.002).abs() < 1e-9);
+        assert!(observation.step.abs() < TimeHealthMonitor::JUMP_THRESHOLD);
+    }
+}
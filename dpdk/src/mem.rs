@@ -543,6 +543,18 @@ impl Mbuf {
         }
     }
 
+    /// Get the RSS hash computed by the NIC on reception of the packet, if it provided one.
+    #[must_use]
+    pub fn rss_hash(&self) -> Option<u32> {
+        /// `RTE_MBUF_F_RX_RSS_HASH`: the rss hash of the mbuf is valid
+        const RX_RSS_HASH: u64 = 1 << 1;
+        let raw = unsafe { self.raw.as_ref() };
+        if raw.ol_flags & RX_RSS_HASH == 0 {
+            return None;
+        }
+        Some(unsafe { raw.annon2.annon1.hash.rss })
+    }
+
     #[tracing::instrument(level = "trace")]
     fn prepend_to_headroom(&mut self, len: u16) -> Result<&mut [u8], NotEnoughHeadRoom> {
         let val = unsafe { rte_pktmbuf_prepend(self.raw.as_mut(), len) };
@@ -8,6 +8,7 @@ use crate::mem::Mbuf;
 use crate::socket::SocketId;
 use crate::{dev, mem, socket};
 use errno::Errno;
+use net::packet::{InvalidPacket, Packet};
 use std::ffi::c_int;
 use std::ptr::null_mut;
 use tracing::{trace, warn};
@@ -200,6 +201,19 @@ impl RxQueue {
         // of the receive buffer
         (0..nb_rx).map(move |i| unsafe { Mbuf::new_from_raw_unchecked(pkts[i as usize]) })
     }
+
+    /// Receive a burst of packets from the queue, parsed, with the RSS hash the NIC computed as
+    /// their RX hash
+    pub fn receive_packets(
+        &self,
+    ) -> impl Iterator<Item = Result<Packet<Mbuf>, InvalidPacket<Mbuf>>> {
+        self.receive().map(|mbuf| {
+            let rx_hash = mbuf.rss_hash();
+            let mut packet = Packet::new(mbuf)?;
+            packet.meta_mut().rx_hash = rx_hash;
+            Ok(packet)
+        })
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -0,0 +1,118 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! A cache of the flows a stage recently looked up, in front of the [`FlowTable`].
+//!
+//! The packets of a flow share their flow hash, which the NIC or the kernel usually computed on
+//! reception. The cache keeps, for each slot of flow hashes, the last flow found, and finds it
+//! again for the next packets of the flow without hashing their key nor locking the table.
+//!
+//! A cached flow is only used for its own key and while it is active, which it only is while in
+//! the table: the cache never finds a flow the table would not.
+
+use concurrency::sync::Arc;
+use net::FlowKey;
+use net::buffer::PacketBufferMut;
+use net::flows::FlowInfo;
+use net::packet::Packet;
+
+use crate::flow_table::FlowTable;
+
+/// A direct-mapped cache of flows, indexed by the flow hash of packets. Each stage looking up
+/// flows owns its own.
+pub struct FlowCache {
+    slots: Box<[Option<Arc<FlowInfo>>]>,
+    mask: u64,
+}
+
+impl FlowCache {
+    /// The number of slots of the caches of the stages
+    pub const DEFAULT_SLOTS: usize = 4096;
+
+    /// A cache of `slots` slots, rounded up to a power of two
+    #[must_use]
+    pub fn new(slots: usize) -> Self {
+        let slots = slots.max(1).next_power_of_two();
+        Self {
+            slots: vec![None; slots].into_boxed_slice(),
+            mask: slots as u64 - 1,
+        }
+    }
+
+    /// Look up the flow of `packet`, of key `flow_key`, in the cache and, if not there, in
+    /// `flow_table`
+    pub fn lookup<Buf: PacketBufferMut>(
+        &mut self,
+        flow_table: &FlowTable,
+        packet: &Packet<Buf>,
+        flow_key: &FlowKey,
+    ) -> Option<Arc<FlowInfo>> {
+        #[allow(clippy::cast_possible_truncation)] // masked to the number of slots
+        let slot = &mut self.slots[(packet.flow_hash() & self.mask) as usize];
+        if let Some(cached) = slot.as_ref()
+            && cached.is_active()
+            && cached.flowkey() == flow_key
+        {
+            return Some(cached.clone());
+        }
+        let found = flow_table.lookup(flow_key);
+        slot.clone_from(&found);
+        found
+    }
+}
+
+impl Default for FlowCache {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_SLOTS)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use concurrency::sync::Arc;
+    use net::FlowKey;
+    use net::packet::test_utils::build_test_udp_ipv4_packet;
+    use std::time::{Duration, Instant};
+
+    use crate::flow_table::{FlowCache, FlowInfo, FlowTable};
+
+    #[tokio::test]
+    async fn test_flow_cache() {
+        let flow_table = FlowTable::default();
+        // a single slot, for the flows to collide
+        let mut cache = FlowCache::new(1);
+        let first = build_test_udp_ipv4_packet("10.0.0.1", "10.0.0.2", 40000, 53);
+        let second = build_test_udp_ipv4_packet("10.0.0.3", "10.0.0.2", 40000, 53);
+        let first_key = FlowKey::try_from(&first).unwrap();
+        let second_key = FlowKey::try_from(&second).unwrap();
+        assert!(cache.lookup(&flow_table, &first, &first_key).is_none());
+
+        let expires_at = Instant::now() + Duration::from_secs(10);
+        flow_table
+            .insert(FlowInfo::new(first_key, expires_at))
+            .unwrap();
+        flow_table
+            .insert(FlowInfo::new(second_key, expires_at))
+            .unwrap();
+        let found = cache.lookup(&flow_table, &first, &first_key).unwrap();
+        assert_eq!(found.flowkey(), &first_key);
+        let cached = cache.lookup(&flow_table, &first, &first_key).unwrap();
+        assert!(Arc::ptr_eq(&found, &cached));
+
+        // flows sharing a slot are told apart by their keys
+        let found = cache.lookup(&flow_table, &second, &second_key).unwrap();
+        assert_eq!(found.flowkey(), &second_key);
+        let found = cache.lookup(&flow_table, &first, &first_key).unwrap();
+        assert_eq!(found.flowkey(), &first_key);
+
+        // flows replaced in or removed from the table are not found in the cache
+        flow_table
+            .insert(FlowInfo::new(first_key, expires_at))
+            .unwrap();
+        let replaced = cache.lookup(&flow_table, &first, &first_key).unwrap();
+        assert!(!Arc::ptr_eq(&found, &replaced));
+        assert!(replaced.is_active());
+        flow_table.remove(&first_key).unwrap();
+        assert!(cache.lookup(&flow_table, &first, &first_key).is_none());
+    }
+}
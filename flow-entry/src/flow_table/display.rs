@@ -2,22 +2,58 @@
 // Copyright Open Network Fabric Authors
 
 use crate::flow_table::FlowTable;
-use common::cliprovider::{CliSource, Heading};
-use std::fmt::Display;
+use common::cliprovider::{CliDataProvider, CliScope, Heading};
+use net::FlowKey;
+use net::flows::FlowInfo;
+use net::packet::VpcDiscriminant;
+use std::fmt::{Display, Write};
 
-impl CliSource for FlowTable {}
+impl FlowTable {
+    /// Format the flows of the table for which `visible` holds
+    fn fmt_flows<F>(&self, f: &mut impl Write, visible: F) -> std::fmt::Result
+    where
+        F: Fn(&FlowKey, &FlowInfo) -> bool,
+    {
+        let Some(table) = self.table.try_read() else {
+            return write!(f, "Failed to lock flow table");
+        };
+        let mut count = 0;
+        let mut flows = String::new();
+        for entry in table.iter() {
+            if visible(entry.key(), entry.value()) {
+                count += 1;
+                writeln!(flows, "{}\n{}", entry.key(), entry.value())?;
+            }
+        }
+        write!(
+            f,
+            "{}{flows}",
+            Heading(format!("Flow Table ({count} entries)"))
+        )
+    }
+}
 
 impl Display for FlowTable {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        if let Some(table) = self.table.try_read() {
-            Heading(format!("Flow Table ({} entries)", table.len())).fmt(f)?;
-            for entry in table.iter() {
-                let key = entry.key();
-                writeln!(f, "{key}\n{}", entry.value())?;
-            }
-        } else {
-            write!(f, "Failed to lock flow table")?;
-        }
-        Ok(())
+        self.fmt_flows(f, |_, _| true)
+    }
+}
+
+impl CliDataProvider for FlowTable {
+    fn provide(&self) -> String {
+        self.to_string()
+    }
+
+    /// Only flows from or to a visible VPC are shown
+    fn provide_scoped(&self, scope: &dyn CliScope) -> Option<String> {
+        let sees = |vpcd: Option<VpcDiscriminant>| {
+            vpcd.is_some_and(|VpcDiscriminant::VNI(vni)| scope.sees_vni(vni.as_u32()))
+        };
+        let mut out = String::new();
+        self.fmt_flows(&mut out, |key, info| {
+            sees(key.src_vpcd()) || sees(info.get_dst_vpcd())
+        })
+        .ok()?;
+        Some(out)
     }
 }
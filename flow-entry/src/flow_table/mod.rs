@@ -1,15 +1,19 @@
 // SPDX-License-Identifier: Apache-2.0
 // Copyright Open Network Fabric Authors
 
+pub mod cache;
 mod display;
 pub mod nf_lookup;
 pub mod table;
+pub mod tcp_tracker;
 
 #[cfg(test)]
 mod concurrent_fuzz;
 
+pub use cache::FlowCache;
 pub use nf_lookup::FlowLookup;
 pub use table::{FlowTable, FlowTableReadGuard};
+pub use tcp_tracker::{TcpDirection, TcpState, TcpTracker, TcpViolation};
 
 pub use net::flows::atomic_instant::AtomicInstant;
 pub use net::flows::flow_info::*;
@@ -2,15 +2,34 @@
 // Copyright Open Network Fabric Authors
 
 //! Network Function specific flow table.
+//!
+//! Besides tagging packets with the session they belong to, the lookup tracks the TCP
+//! sessions initiated from the VPCs configured for strict TCP tracking, with a
+//! [`TcpTracker`]. Segments that are invalid for the state of their session are dropped and
+//! counted in `flow_tcp_invalid`, per VPC and reason.
+//!
+//! If the TCP keepalives extend sessions, the TCP sessions of all the VPCs are tracked, and the
+//! keepalives of an established session keep it, in both directions, in the flow table for the
+//! keepalive timeout of the table. Only the segments of the VPCs with strict tracking get dropped.
+//!
+//! The flows are looked up through a [`FlowCache`] indexed by the flow hash of the packets.
+
+use std::collections::HashMap;
 
 use tracing::debug;
 
-use concurrency::sync::Arc;
+use concurrency::sync::{Arc, Weak};
+use config::external::overlay::vpc::TcpTracking;
+use metrics::{Counter, Unit};
 use net::buffer::PacketBufferMut;
-use net::packet::Packet;
+use net::flows::{ExtractMut, FlowInfo};
+use net::headers::TryTcp;
+use net::packet::{DoneReason, Packet, VpcDiscriminant};
 use pipeline::NetworkFunction;
+use stats::{MetricSpec, Register};
 
-use crate::flow_table::FlowTable;
+use crate::flow_table::tcp_tracker::{TcpDirection, TcpTracker, TcpViolation};
+use crate::flow_table::{FlowCache, FlowTable};
 use net::FlowKey;
 
 use tracectl::trace_target;
@@ -19,6 +38,8 @@ trace_target!("flow-lookup", LevelFilter::INFO, &["pipeline"]);
 pub struct FlowLookup {
     name: String,
     flow_table: Arc<FlowTable>,
+    cache: FlowCache,
+    tcp_violations: HashMap<(VpcDiscriminant, TcpViolation), Counter>,
 }
 
 impl FlowLookup {
@@ -27,6 +48,96 @@ impl FlowLookup {
         Self {
             name: name.to_string(),
             flow_table,
+            cache: FlowCache::default(),
+            tcp_violations: HashMap::new(),
+        }
+    }
+
+    /// Count a segment found invalid in a session of a VPC
+    fn count_violation(&mut self, vpcd: VpcDiscriminant, violation: TcpViolation) {
+        self.tcp_violations
+            .entry((vpcd, violation))
+            .or_insert_with(|| {
+                let labels = vec![
+                    ("vpc".to_string(), vpcd.to_string()),
+                    ("reason".to_string(), violation.to_string()),
+                ];
+                MetricSpec::new("flow_tcp_invalid", Unit::Count, labels)
+                    .register()
+                    .metric
+            })
+            .increment(1);
+    }
+
+    /// Track a TCP segment in its session, if the session was initiated from a VPC with strict
+    /// TCP tracking or if keepalives extend sessions, and tell if the segment is a keepalive.
+    /// The tracker lives in the flow that initiated the session.
+    fn track_tcp<Buf: PacketBufferMut>(
+        &mut self,
+        packet: &Packet<Buf>,
+        flow_info: &Arc<FlowInfo>,
+    ) -> Result<bool, TcpViolation> {
+        let Some(tcp) = packet.try_tcp() else {
+            return Ok(false);
+        };
+        let related;
+        let (initiator, direction) = if flow_info.is_initiator() {
+            (flow_info, TcpDirection::Original)
+        } else {
+            related = flow_info.related.as_ref().and_then(Weak::upgrade);
+            match &related {
+                Some(initiator) => (initiator, TcpDirection::Reply),
+                None => return Ok(false),
+            }
+        };
+        let Some(vpcd) = initiator.flowkey().src_vpcd() else {
+            return Ok(false);
+        };
+        let strict = self.flow_table.tcp_tracking(vpcd) == TcpTracking::Strict;
+        if !strict && self.flow_table.tcp_keepalive_timeout().is_none() {
+            return Ok(false);
+        }
+        let result = {
+            let mut locked = initiator.locked.write();
+            let tcp_state = locked
+                .tcp_state
+                .get_or_insert_with(|| Box::new(TcpTracker::for_segment(tcp)));
+            match tcp_state.extract_mut::<TcpTracker>() {
+                Some(tracker) => {
+                    let payload_len = packet.payload_len();
+                    let keepalive = tracker.is_keepalive(direction, tcp, payload_len);
+                    tracker
+                        .track(direction, tcp, payload_len)
+                        .map(|()| keepalive)
+                }
+                None => Ok(false),
+            }
+        };
+        match result {
+            Err(violation) if strict => {
+                self.count_violation(vpcd, violation);
+                Err(violation)
+            }
+            // the segments of loosely tracked sessions are never dropped
+            Err(_) => Ok(false),
+            Ok(keepalive) => Ok(keepalive),
+        }
+    }
+
+    /// Keep the session of a flow, in both directions, in the flow table for the keepalive
+    /// timeout, unless it is already to stay longer.
+    fn extend_session(&self, flow_info: &FlowInfo) {
+        let Some(timeout) = self.flow_table.tcp_keepalive_timeout() else {
+            return;
+        };
+        debug!(
+            "{}: Extending session of flow {} by {timeout:?} upon keepalive",
+            self.name,
+            flow_info.flowkey()
+        );
+        let _ = flow_info.reset_expiry(timeout);
+        if let Some(related) = flow_info.related.as_ref().and_then(Weak::upgrade) {
+            let _ = related.reset_expiry(timeout);
         }
     }
 }
@@ -40,9 +151,29 @@ impl<Buf: PacketBufferMut> NetworkFunction<Buf> for FlowLookup {
             let nfi = &self.name;
             if !packet.is_done() && packet.meta().is_overlay() && packet.meta().dst_vpcd.is_none() {
                 if let Ok(flow_key) = FlowKey::try_from(&packet) {
-                    if let Some(flow_info) = self.flow_table.lookup(&flow_key) {
-                        debug!("{nfi}: Tagging packet with flow info for flow key {flow_key}",);
-                        packet.meta_mut().flow_info = Some(flow_info);
+                    if let Some(flow_info) =
+                        self.cache.lookup(&self.flow_table, &packet, &flow_key)
+                    {
+                        match self.track_tcp(&packet, &flow_info) {
+                            Err(violation) => {
+                                debug!(
+                                    "{}: Dropping invalid TCP segment of flow {flow_key}: {violation}",
+                                    self.name
+                                );
+                                packet.done(DoneReason::TcpInvalid);
+                            }
+                            Ok(keepalive) => {
+                                if keepalive {
+                                    self.extend_session(&flow_info);
+                                }
+                                debug!(
+                                    "{}: Tagging packet with flow info for flow key {flow_key}",
+                                    self.name
+                                );
+                                flow_info.account(u64::from(packet.total_len()));
+                                packet.meta_mut().flow_info = Some(flow_info);
+                            }
+                        }
                     } else {
                         debug!("{nfi}: No flow info found for flow key {flow_key}",);
                     }
@@ -58,12 +189,15 @@ impl<Buf: PacketBufferMut> NetworkFunction<Buf> for FlowLookup {
 #[cfg(test)]
 mod test {
     use concurrency::sync::Arc;
+    use config::external::overlay::vpc::TcpTracking;
     use net::FlowKey;
     use net::buffer::PacketBufferMut;
     use net::buffer::TestBuffer;
     use net::flows::{FlowInfo, FlowInfoFlags};
+    use net::headers::TryTcpMut;
     use net::ip::NextHeader;
     use net::ip::UnicastIpAddr;
+    use net::packet::DoneReason;
     use net::packet::Packet;
     use net::packet::VpcDiscriminant;
     use net::packet::test_utils::{
@@ -110,6 +244,86 @@ mod test {
         assert!(output.meta().flow_info.is_some());
     }
 
+    #[tokio::test]
+    async fn test_lookup_nf_strict_tcp() {
+        let flow_table = Arc::new(FlowTable::default());
+        let mut lookup_nf = FlowLookup::new("test_lookup_nf_strict_tcp", flow_table.clone());
+        let src_vpcd = VpcDiscriminant::VNI(Vni::new_checked(100).unwrap());
+        flow_table.set_tcp_tracking([(src_vpcd, TcpTracking::Strict)]);
+
+        // A retransmitted SYN, then a data segment of the session, whose handshake did not
+        // complete
+        let mut packet = build_test_ipv4_packet_with_transport(100, Some(NextHeader::TCP)).unwrap();
+        packet.meta_mut().src_vpcd = Some(src_vpcd);
+        packet.meta_mut().set_overlay(true);
+        let mut syn = packet.clone();
+        syn.try_tcp_mut().unwrap().set_syn(true);
+        packet.try_tcp_mut().unwrap().set_ack(true);
+
+        let flow_key = FlowKey::try_from(&packet).unwrap();
+        let flow_info = FlowInfo::new(flow_key, Instant::now() + Duration::from_secs(10));
+        flow_table.insert(flow_info).unwrap();
+
+        let output = lookup_nf.process(std::iter::once(syn)).next().unwrap();
+        assert!(output.get_done().is_none());
+
+        // The segment is dropped while the session is tracked strictly
+        let output = lookup_nf
+            .process(std::iter::once(packet.clone()))
+            .next()
+            .unwrap();
+        assert_eq!(output.get_done(), Some(DoneReason::TcpInvalid));
+        assert!(output.meta().flow_info.is_none());
+
+        // and tagged once it is tracked loosely
+        flow_table.set_tcp_tracking([(src_vpcd, TcpTracking::Loose)]);
+        let output = lookup_nf
+            .process(std::iter::once(packet.clone()))
+            .next()
+            .unwrap();
+        assert!(output.get_done().is_none());
+        assert!(output.meta().flow_info.is_some());
+
+        // still, once tracked for its keepalives, without extending the session
+        flow_table.set_tcp_keepalive_timeout(Some(Duration::from_hours(2)));
+        let output = lookup_nf.process(std::iter::once(packet)).next().unwrap();
+        assert!(output.get_done().is_none());
+        let flow_info = output.meta().flow_info.as_ref().unwrap();
+        assert!(flow_info.expires_at() <= Instant::now() + Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_lookup_nf_strict_tcp_mid_stream() {
+        let flow_table = Arc::new(FlowTable::default());
+        let mut lookup_nf = FlowLookup::new("test_lookup_nf_mid_stream", flow_table.clone());
+        let src_vpcd = VpcDiscriminant::VNI(Vni::new_checked(100).unwrap());
+
+        // A data segment of an established session, tracked loosely
+        let mut packet = build_test_ipv4_packet_with_transport(100, Some(NextHeader::TCP)).unwrap();
+        packet.meta_mut().src_vpcd = Some(src_vpcd);
+        packet.meta_mut().set_overlay(true);
+        packet.try_tcp_mut().unwrap().set_ack(true);
+        let mut syn = packet.clone();
+        syn.try_tcp_mut().unwrap().set_syn(true);
+
+        let flow_key = FlowKey::try_from(&packet).unwrap();
+        let flow_info = FlowInfo::new(flow_key, Instant::now() + Duration::from_secs(10));
+        flow_table.insert(flow_info).unwrap();
+        let output = lookup_nf
+            .process(std::iter::once(packet.clone()))
+            .next()
+            .unwrap();
+        assert!(output.get_done().is_none());
+
+        // The session survives strict tracking once enabled, as an established one
+        flow_table.set_tcp_tracking([(src_vpcd, TcpTracking::Strict)]);
+        let output = lookup_nf.process(std::iter::once(packet)).next().unwrap();
+        assert!(output.get_done().is_none());
+        assert!(output.meta().flow_info.is_some());
+        let output = lookup_nf.process(std::iter::once(syn)).next().unwrap();
+        assert_eq!(output.get_done(), Some(DoneReason::TcpInvalid));
+    }
+
     // A dummy NF that creates a flow entry for each packet, with a configurable lifetime
     struct FlowInfoCreator {
         flow_table: Arc<FlowTable>,
@@ -2,17 +2,24 @@
 // Copyright Open Network Fabric Authors
 
 use ahash::RandomState;
-use concurrency::sync::atomic::{AtomicUsize, Ordering};
+use concurrency::slot::Slot;
+use concurrency::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use concurrency::sync::{Arc, RwLock, RwLockReadGuard, Weak};
+use config::external::overlay::vpc::TcpTracking;
+use config::external::session_log::SessionLogConfig;
 use dashmap::DashMap;
 use net::FlowKey;
 use net::flows::{FlowInfo, FlowStatus};
+use net::packet::VpcDiscriminant;
 use std::borrow::Borrow;
+use std::collections::HashSet;
 use std::fmt::{Debug, Display};
 use std::hash::Hash;
 use std::time::Duration;
 use tracing::debug;
 
+use crate::session_log::{SessionEventKind, SessionLog};
+
 #[derive(Debug, thiserror::Error)]
 pub enum FlowTableError {
     #[error("Flow table capacity exceeded")]
@@ -26,6 +33,13 @@ pub struct FlowTable {
     // TODO(mvachhar) move this to a cross beam sharded lock
     pub(crate) table: Arc<RwLock<Table>>,
     capacity: AtomicUsize,
+    session_log: Arc<SessionLog>,
+    // VPCs whose TCP sessions are tracked strictly, published with no lock for the lookups,
+    // and whether there is any
+    strict_tcp: Slot<HashSet<VpcDiscriminant>>,
+    any_strict_tcp: AtomicBool,
+    // Seconds a TCP keepalive keeps its session up, or 0 if keepalives don't extend sessions
+    tcp_keepalive_secs: AtomicU64,
 }
 
 impl Default for FlowTable {
@@ -67,6 +81,10 @@ impl FlowTable {
                 num_shards,
             ))),
             capacity: AtomicUsize::new(Self::DEFAULT_CAPACITY),
+            session_log: Arc::new(SessionLog::new()),
+            strict_tcp: Slot::from_pointee(HashSet::new()),
+            any_strict_tcp: AtomicBool::new(false),
+            tcp_keepalive_secs: AtomicU64::new(0),
         }
     }
 
@@ -78,6 +96,54 @@ impl FlowTable {
         self.capacity.store(capacity, Ordering::Relaxed);
     }
 
+    /// Log the creation and the teardown of sessions to a collector, as reported by `hostname`,
+    /// or stop logging them if `config` is `None`.
+    pub fn set_session_log(&self, config: Option<&SessionLogConfig>, hostname: &str) {
+        self.session_log.configure(config, hostname);
+    }
+
+    /// Set how strictly the TCP sessions initiated from each VPC are tracked. Those of the VPCs
+    /// not listed are tracked loosely.
+    pub fn set_tcp_tracking(
+        &self,
+        tracking: impl IntoIterator<Item = (VpcDiscriminant, TcpTracking)>,
+    ) {
+        let strict: HashSet<_> = tracking
+            .into_iter()
+            .filter_map(|(vpcd, tracking)| (tracking == TcpTracking::Strict).then_some(vpcd))
+            .collect();
+        let any = !strict.is_empty();
+        self.strict_tcp.store(Arc::new(strict));
+        self.any_strict_tcp.store(any, Ordering::Release);
+    }
+
+    /// How strictly the TCP sessions initiated from a VPC are tracked
+    #[must_use]
+    pub fn tcp_tracking(&self, vpcd: VpcDiscriminant) -> TcpTracking {
+        // spare the load of the set as long as no VPC asks for strict tracking
+        if self.any_strict_tcp.load(Ordering::Acquire) && self.strict_tcp.load().contains(&vpcd) {
+            TcpTracking::Strict
+        } else {
+            TcpTracking::Loose
+        }
+    }
+
+    /// Let the TCP keepalives keep their sessions up for `timeout`, or stop extending sessions
+    /// upon keepalives if `None`.
+    pub fn set_tcp_keepalive_timeout(&self, timeout: Option<Duration>) {
+        let secs = timeout.map_or(0, |timeout| timeout.as_secs());
+        self.tcp_keepalive_secs.store(secs, Ordering::Relaxed);
+    }
+
+    /// How long a TCP keepalive keeps its session up, if keepalives extend sessions
+    #[must_use]
+    pub fn tcp_keepalive_timeout(&self) -> Option<Duration> {
+        match self.tcp_keepalive_secs.load(Ordering::Relaxed) {
+            0 => None,
+            secs => Some(Duration::from_secs(secs)),
+        }
+    }
+
     /// Reshard the flow table into the given number of shards.
     ///
     /// # Errors
@@ -151,9 +217,13 @@ impl FlowTable {
         self.insert_common(flow_info)
     }
 
-    /// Start a timer task for a flow
+    /// Start a timer task for a flow. The session of the flow ends with the task.
     #[allow(unused)]
-    fn start_timer(table: Arc<RwLock<Table>>, flow_info: Arc<FlowInfo>) {
+    fn start_timer(
+        table: Arc<RwLock<Table>>,
+        session_log: Arc<SessionLog>,
+        flow_info: Arc<FlowInfo>,
+    ) {
         tokio::task::spawn(async move {
             let table = table;
             let flow_key = flow_info.flowkey();
@@ -182,6 +252,8 @@ impl FlowTable {
                     },
                 }
             }
+            session_log.log(SessionEventKind::Teardown, &flow_info);
+
             // no need to remove
             if flow_info.status() == FlowStatus::Detached {
                 return;
@@ -234,9 +306,10 @@ impl FlowTable {
         // and a freshly inserted flow always has expires_at in the future.
         val.update_status(FlowStatus::Active);
         drop(table);
+        self.session_log.log(SessionEventKind::Create, val);
 
         #[cfg(not(any(feature = "shuttle", feature = "loom")))]
-        Self::start_timer(self.table.clone(), val.clone());
+        Self::start_timer(self.table.clone(), self.session_log.clone(), val.clone());
 
         if let Some(old) = result.as_ref() {
             old.update_status(FlowStatus::Detached);
@@ -436,6 +509,73 @@ mod tests {
             assert_eq!(result.0, flow_key);
         }
 
+        #[tokio::test]
+        async fn test_flow_table_session_log() {
+            use config::external::session_log::SessionLogFormat;
+            use net::flows::FlowInfoFlags;
+
+            let collector = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+            collector
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut config = SessionLogConfig::new(collector.local_addr().unwrap());
+            config.format = SessionLogFormat::Json;
+            config.batch_size = 1;
+            let flow_table = FlowTable::default();
+            flow_table.set_session_log(Some(&config), "gw1");
+
+            let tcp = |vni, src: &str, sport, dst: &str, dport| {
+                FlowKey::new(
+                    Some(VpcDiscriminant::VNI(Vni::new_checked(vni).unwrap())),
+                    src.parse::<IpAddr>().unwrap(),
+                    dst.parse::<IpAddr>().unwrap(),
+                    IpProtoKey::Tcp(TcpProtoKey {
+                        src_port: TcpPort::new_checked(sport).unwrap(),
+                        dst_port: TcpPort::new_checked(dport).unwrap(),
+                    }),
+                )
+            };
+            // a session masqueraded to 192.168.0.1:40000
+            let key = tcp(1, "10.0.0.1", 1025, "20.0.0.1", 80);
+            let reply = tcp(2, "20.0.0.1", 80, "192.168.0.1", 40000);
+            let (forward, reverse) = FlowInfo::related_pair(
+                Instant::now() + Duration::from_secs(60),
+                key,
+                FlowInfoFlags::default(),
+                reply,
+                FlowInfoFlags::default(),
+            );
+            flow_table.insert_from_arc(&forward).unwrap();
+            flow_table.insert_from_arc(&reverse).unwrap();
+            forward.account(100);
+            reverse.account(1500);
+            reverse.account(1500);
+
+            let mut buf = [0u8; 1024];
+            let len = collector.recv(&mut buf).unwrap();
+            let create = std::str::from_utf8(&buf[..len]).unwrap();
+            assert!(create.contains("\"event\":\"create\""), "{create}");
+            assert!(
+                create.contains("\"xlate_src\":\"192.168.0.1\",\"xlate_sport\":40000"),
+                "{create}"
+            );
+
+            // only the initiator of the session reports its teardown, with the counts of both
+            // of its directions
+            flow_table.remove(&reply);
+            flow_table.remove(&key);
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let len = collector.recv(&mut buf).unwrap();
+            let teardown = std::str::from_utf8(&buf[..len]).unwrap();
+            assert!(teardown.contains("\"event\":\"teardown\""), "{teardown}");
+            assert!(
+                teardown.contains(
+                    "\"packets_out\":1,\"bytes_out\":100,\"packets_in\":2,\"bytes_in\":3000"
+                ),
+                "{teardown}"
+            );
+        }
+
         // start_paused so the timer task's sleep_until and the test's sleeps share tokio's
         // virtual clock; otherwise miri's slow interpretation can drift the wall clock far
         // enough between Instant::now() and the first sleep that the deadline elapses early.
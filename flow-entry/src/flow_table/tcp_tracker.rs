@@ -0,0 +1,555 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Strict tracking of the state of TCP sessions.
+//!
+//! A [`TcpTracker`] follows the segments of a session in both directions and rejects those
+//! which a well-behaved endpoint would never send: segments with contradictory flags, segments
+//! that don't fit the state of the session (e.g. data before the handshake completed, or a SYN
+//! on an established session) and segments out of window, which includes blind RSTs.
+//!
+//! Sessions are created by the stage which handles their first segment, before they can be
+//! looked up. Trackers therefore start assuming that the initiator sent a SYN, if the first
+//! segment they see is part of the handshake. Likewise, the window scale offered by the
+//! initiator is unknown unless its SYN is retransmitted, in which case the largest scale is
+//! assumed for the windows it advertises. Sessions picked up mid-stream, e.g. when strict
+//! tracking gets enabled, start established, with the largest scale assumed for the windows of
+//! both endpoints, so that they survive strict tracking.
+//!
+//! Trackers also tell the keepalives of established sessions from other segments, so that
+//! keepalives may extend the lifetime of sessions which are otherwise idle.
+
+use net::tcp::Tcp;
+use std::fmt::Display;
+
+/// The largest shift count of the window scale option (RFC 7323)
+const MAX_WINDOW_SCALE: u8 = 14;
+
+/// The smallest range of acknowledgment numbers accepted below the data sent by the peer
+const MAX_ACK_WINDOW: u32 = 66_000;
+
+/// The direction of a segment in its session
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TcpDirection {
+    /// From the initiator of the session
+    Original,
+    /// From the responder
+    Reply,
+}
+
+/// The state of a TCP session
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TcpState {
+    /// The initiator sent a SYN
+    SynSent,
+    /// The responder answered with a SYN-ACK
+    SynReceived,
+    /// The initiator acknowledged the SYN-ACK
+    Established,
+    /// One of the endpoints, in the indicated direction, sent a FIN
+    FinWait(TcpDirection),
+    /// Both endpoints sent a FIN
+    Closing,
+    /// One of the endpoints sent a RST
+    Reset,
+}
+
+impl Display for TcpState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TcpState::SynSent => write!(f, "syn-sent"),
+            TcpState::SynReceived => write!(f, "syn-received"),
+            TcpState::Established => write!(f, "established"),
+            TcpState::FinWait(_) => write!(f, "fin-wait"),
+            TcpState::Closing => write!(f, "closing"),
+            TcpState::Reset => write!(f, "reset"),
+        }
+    }
+}
+
+/// The reason a segment was found invalid
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum TcpViolation {
+    /// The combination of flags is never legitimate
+    Flags,
+    /// The segment is not expected in the state of the session
+    Transition,
+    /// The sequence or the acknowledgment number is out of window
+    Window,
+}
+
+impl Display for TcpViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TcpViolation::Flags => write!(f, "flags"),
+            TcpViolation::Transition => write!(f, "transition"),
+            TcpViolation::Window => write!(f, "window"),
+        }
+    }
+}
+
+/// Tell if sequence number `a` precedes `b`, modulo 2^32
+fn before(a: u32, b: u32) -> bool {
+    a.wrapping_sub(b).cast_signed() < 0
+}
+
+/// Tell if sequence number `a` follows `b`, modulo 2^32
+fn after(a: u32, b: u32) -> bool {
+    before(b, a)
+}
+
+/// The sequence number following a segment
+fn segment_end(tcp: &Tcp, payload_len: u16) -> u32 {
+    tcp.sequence_number()
+        .wrapping_add(u32::from(payload_len))
+        .wrapping_add(u32::from(tcp.syn()))
+        .wrapping_add(u32::from(tcp.fin()))
+}
+
+/// Reject the combinations of flags that no endpoint sends
+fn check_flags(tcp: &Tcp) -> Result<(), TcpViolation> {
+    let (syn, ack, fin, rst) = (tcp.syn(), tcp.ack(), tcp.fin(), tcp.rst());
+    if (syn && (fin || rst)) || (fin && !ack) || !(syn || ack || fin || rst) {
+        return Err(TcpViolation::Flags);
+    }
+    Ok(())
+}
+
+/// What is known of the segments sent by one endpoint
+#[derive(Clone, Copy, Debug, Default)]
+struct Endpoint {
+    /// Whether a segment of the endpoint was seen
+    seen: bool,
+    /// The sequence number following the data sent
+    end: u32,
+    /// The right edge of the window advertised by the peer
+    max_end: u32,
+    /// Whether the peer advertised a window
+    bounded: bool,
+    /// The largest window advertised, scaled
+    max_win: u32,
+    /// The scale of the windows advertised
+    scale: u8,
+    /// The scale offered in the SYN, if any
+    offered_scale: Option<u8>,
+}
+
+/// The state of a TCP session, as seen from the segments of both of its directions
+#[derive(Debug)]
+pub struct TcpTracker {
+    state: TcpState,
+    original: Endpoint,
+    reply: Endpoint,
+}
+
+impl Default for TcpTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Display for TcpTracker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.state)
+    }
+}
+
+impl TcpTracker {
+    /// Build a tracker for a session whose initiator sent a SYN
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            state: TcpState::SynSent,
+            original: Endpoint::default(),
+            reply: Endpoint::default(),
+        }
+    }
+
+    /// Build a tracker for a session picked up mid-stream: established, with windows of unknown
+    /// scale
+    #[must_use]
+    pub fn mid_stream() -> Self {
+        let endpoint = Endpoint {
+            scale: MAX_WINDOW_SCALE,
+            ..Endpoint::default()
+        };
+        Self {
+            state: TcpState::Established,
+            original: endpoint,
+            reply: endpoint,
+        }
+    }
+
+    /// Build a tracker for a session whose first tracked segment is `tcp`: a session whose
+    /// initiator sent a SYN if the segment is part of the handshake, or one picked up mid-stream
+    #[must_use]
+    pub fn for_segment(tcp: &Tcp) -> Self {
+        if tcp.syn() {
+            Self::new()
+        } else {
+            Self::mid_stream()
+        }
+    }
+
+    /// The state of the session
+    #[must_use]
+    pub fn state(&self) -> TcpState {
+        self.state
+    }
+
+    /// Tell if a segment, carrying `payload_len` octets of data, in the indicated direction,
+    /// is a keepalive of the established session: a bare ACK whose sequence number precedes
+    /// the next one of its sender, carrying at most one octet, which was already acknowledged.
+    #[must_use]
+    pub fn is_keepalive(&self, direction: TcpDirection, tcp: &Tcp, payload_len: u16) -> bool {
+        let sender = match direction {
+            TcpDirection::Original => &self.original,
+            TcpDirection::Reply => &self.reply,
+        };
+        self.state == TcpState::Established
+            && sender.seen
+            && tcp.ack()
+            && !(tcp.syn() || tcp.fin() || tcp.rst())
+            && payload_len <= 1
+            && tcp.sequence_number().wrapping_add(1) == sender.end
+    }
+
+    /// The sender and the receiver of a segment in the indicated direction
+    fn endpoints(&mut self, direction: TcpDirection) -> (&mut Endpoint, &mut Endpoint) {
+        match direction {
+            TcpDirection::Original => (&mut self.original, &mut self.reply),
+            TcpDirection::Reply => (&mut self.reply, &mut self.original),
+        }
+    }
+
+    /// Track a segment, carrying `payload_len` octets of data, in the indicated direction.
+    ///
+    /// # Errors
+    ///
+    /// Returns the [`TcpViolation`] of an invalid segment, which leaves the tracker unchanged.
+    pub fn track(
+        &mut self,
+        direction: TcpDirection,
+        tcp: &Tcp,
+        payload_len: u16,
+    ) -> Result<(), TcpViolation> {
+        check_flags(tcp)?;
+        // a closed session may be reopened by the initiator, reusing the same ports
+        if matches!(self.state, TcpState::Closing | TcpState::Reset)
+            && direction == TcpDirection::Original
+            && tcp.syn()
+            && !tcp.ack()
+        {
+            *self = Self::new();
+        }
+        let state = self.next_state(direction, tcp)?;
+        self.check_window(direction, tcp, payload_len)?;
+        self.update(direction, tcp, payload_len);
+        self.state = state;
+        Ok(())
+    }
+
+    /// The state of the session after a segment, if the segment is expected
+    fn next_state(&self, direction: TcpDirection, tcp: &Tcp) -> Result<TcpState, TcpViolation> {
+        use TcpDirection::{Original, Reply};
+        let (syn, ack, fin) = (tcp.syn(), tcp.ack(), tcp.fin());
+        if tcp.rst() {
+            return match self.state {
+                // the responder may only refuse the SYN by acknowledging it
+                TcpState::SynSent if direction == Reply && !ack => Err(TcpViolation::Transition),
+                _ => Ok(TcpState::Reset),
+            };
+        }
+        match (self.state, direction) {
+            (TcpState::Reset, _) => Err(TcpViolation::Transition),
+            // retransmissions of the handshake
+            (TcpState::SynSent | TcpState::SynReceived, Original) if syn && !ack => Ok(self.state),
+            (TcpState::SynSent | TcpState::SynReceived, Reply) if syn && ack => {
+                Ok(TcpState::SynReceived)
+            }
+            (TcpState::SynReceived, Original) if !syn && fin => Ok(TcpState::FinWait(Original)),
+            (TcpState::SynReceived, Original) if !syn => Ok(TcpState::Established),
+            // SYNs past the handshake, or in the wrong direction, and data before its completion
+            (_, _) if syn => Err(TcpViolation::Transition),
+            (TcpState::SynSent | TcpState::SynReceived, _) => Err(TcpViolation::Transition),
+            (TcpState::Established, _) if fin => Ok(TcpState::FinWait(direction)),
+            (TcpState::FinWait(closed), _) if fin && closed != direction => Ok(TcpState::Closing),
+            (state, _) => Ok(state),
+        }
+    }
+
+    /// Check that a segment fits in the windows of the session. Nothing is checked against the
+    /// windows of an endpoint not seen yet.
+    fn check_window(
+        &mut self,
+        direction: TcpDirection,
+        tcp: &Tcp,
+        payload_len: u16,
+    ) -> Result<(), TcpViolation> {
+        let (sender, receiver) = self.endpoints(direction);
+        if !receiver.seen {
+            return Ok(());
+        }
+        let seq = tcp.sequence_number();
+        let end = segment_end(tcp, payload_len);
+        // beyond the window of the receiver
+        if sender.bounded && after(seq, sender.max_end) {
+            return Err(TcpViolation::Window);
+        }
+        // older than any retransmission the receiver may still expect
+        if sender.seen && before(end, sender.end.wrapping_sub(receiver.max_win)) {
+            return Err(TcpViolation::Window);
+        }
+        // acknowledging data that was never sent, or long acknowledged
+        if tcp.ack() && !tcp.rst() {
+            let ack = tcp.ack_number();
+            let ack_window = sender.max_win.max(MAX_ACK_WINDOW);
+            if after(ack, receiver.end) || before(ack, receiver.end.wrapping_sub(ack_window)) {
+                return Err(TcpViolation::Window);
+            }
+        }
+        Ok(())
+    }
+
+    /// Record the sequence numbers and the window of a valid segment
+    fn update(&mut self, direction: TcpDirection, tcp: &Tcp, payload_len: u16) {
+        let end = segment_end(tcp, payload_len);
+        let (sender, receiver) = self.endpoints(direction);
+        if tcp.syn() {
+            if tcp.ack() {
+                // windows are scaled only if both endpoints offered to
+                if let Some(shift) = tcp.window_scale() {
+                    sender.scale = shift.min(MAX_WINDOW_SCALE);
+                    receiver.scale = receiver
+                        .offered_scale
+                        .unwrap_or(MAX_WINDOW_SCALE)
+                        .min(MAX_WINDOW_SCALE);
+                } else {
+                    sender.scale = 0;
+                    receiver.scale = 0;
+                }
+            } else {
+                sender.offered_scale = tcp.window_scale();
+            }
+        }
+        // the windows of SYNs are never scaled
+        let shift = if tcp.syn() { 0 } else { sender.scale };
+        let window = (u32::from(tcp.window_size()) << shift).max(1);
+        if !sender.seen || after(end, sender.end) {
+            sender.end = end;
+        }
+        sender.seen = true;
+        sender.max_win = sender.max_win.max(window);
+        if tcp.ack() && !tcp.rst() {
+            let edge = tcp.ack_number().wrapping_add(window);
+            if !receiver.bounded || after(edge, receiver.max_end) {
+                receiver.max_end = edge;
+                receiver.bounded = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TcpDirection, TcpState, TcpTracker, TcpViolation};
+    use net::tcp::{Tcp, TcpPort};
+
+    const CLIENT_ISN: u32 = 1000;
+    const SERVER_ISN: u32 = u32::MAX - 10; // wraps around during the session
+
+    fn segment(seq: u32, ack: Option<u32>, flags: &str) -> Tcp {
+        let mut tcp = Tcp::new(
+            TcpPort::new_checked(1025).unwrap(),
+            TcpPort::new_checked(80).unwrap(),
+        );
+        tcp.set_sequence_number(seq)
+            .set_window_size(65_535)
+            .set_syn(flags.contains('S'))
+            .set_fin(flags.contains('F'))
+            .set_rst(flags.contains('R'));
+        if let Some(ack) = ack {
+            tcp.set_ack(true).set_ack_number(ack);
+        }
+        tcp
+    }
+
+    /// A tracker past the SYN-ACK and the ACK completing the handshake
+    fn established() -> TcpTracker {
+        let mut tracker = TcpTracker::new();
+        let synack = segment(SERVER_ISN, Some(CLIENT_ISN + 1), "S");
+        tracker.track(TcpDirection::Reply, &synack, 0).unwrap();
+        assert_eq!(tracker.state(), TcpState::SynReceived);
+        let ack = segment(CLIENT_ISN + 1, Some(SERVER_ISN.wrapping_add(1)), "");
+        tracker.track(TcpDirection::Original, &ack, 0).unwrap();
+        assert_eq!(tracker.state(), TcpState::Established);
+        tracker
+    }
+
+    #[test]
+    fn test_tcp_tracker_session() {
+        let mut tracker = established();
+        let server_seq = SERVER_ISN.wrapping_add(1);
+
+        let data = segment(CLIENT_ISN + 1, Some(server_seq), "");
+        tracker.track(TcpDirection::Original, &data, 100).unwrap();
+        let data = segment(server_seq, Some(CLIENT_ISN + 101), "");
+        tracker.track(TcpDirection::Reply, &data, 1000).unwrap();
+        let server_seq = server_seq.wrapping_add(1000);
+
+        let fin = segment(CLIENT_ISN + 101, Some(server_seq), "F");
+        tracker.track(TcpDirection::Original, &fin, 0).unwrap();
+        assert_eq!(tracker.state(), TcpState::FinWait(TcpDirection::Original));
+        let fin = segment(server_seq, Some(CLIENT_ISN + 102), "F");
+        tracker.track(TcpDirection::Reply, &fin, 0).unwrap();
+        assert_eq!(tracker.state(), TcpState::Closing);
+        let ack = segment(CLIENT_ISN + 102, Some(server_seq.wrapping_add(1)), "");
+        tracker.track(TcpDirection::Original, &ack, 0).unwrap();
+        assert_eq!(tracker.state(), TcpState::Closing);
+
+        // the ports may be reused for a new session
+        let syn = segment(5000, None, "S");
+        tracker.track(TcpDirection::Original, &syn, 0).unwrap();
+        assert_eq!(tracker.state(), TcpState::SynSent);
+    }
+
+    #[test]
+    fn test_tcp_tracker_keepalive() {
+        let mut tracker = established();
+        let server_seq = SERVER_ISN.wrapping_add(1);
+        let data = segment(CLIENT_ISN + 1, Some(server_seq), "");
+        tracker.track(TcpDirection::Original, &data, 100).unwrap();
+        assert!(!tracker.is_keepalive(TcpDirection::Original, &data, 100));
+
+        // keepalives, with no data or one octet of garbage, and the ACK of the server
+        for payload_len in [0, 1] {
+            let keepalive = segment(CLIENT_ISN + 100, Some(server_seq), "");
+            assert!(tracker.is_keepalive(TcpDirection::Original, &keepalive, payload_len));
+            tracker
+                .track(TcpDirection::Original, &keepalive, payload_len)
+                .unwrap();
+            let ack = segment(server_seq, Some(CLIENT_ISN + 101), "");
+            assert!(!tracker.is_keepalive(TcpDirection::Reply, &ack, 0));
+            tracker.track(TcpDirection::Reply, &ack, 0).unwrap();
+        }
+
+        // a bare ACK at the next sequence number is not a keepalive
+        let ack = segment(CLIENT_ISN + 101, Some(server_seq), "");
+        assert!(!tracker.is_keepalive(TcpDirection::Original, &ack, 0));
+    }
+
+    #[test]
+    fn test_tcp_tracker_invalid_flags() {
+        let mut tracker = established();
+        let server_seq = SERVER_ISN.wrapping_add(1);
+        for flags in ["SF", "SR"] {
+            let tcp = segment(CLIENT_ISN + 1, Some(server_seq), flags);
+            assert_eq!(
+                tracker.track(TcpDirection::Original, &tcp, 0),
+                Err(TcpViolation::Flags)
+            );
+        }
+        // a FIN without ACK, and no flags at all
+        for flags in ["F", ""] {
+            let tcp = segment(CLIENT_ISN + 1, None, flags);
+            assert_eq!(
+                tracker.track(TcpDirection::Original, &tcp, 0),
+                Err(TcpViolation::Flags)
+            );
+        }
+        assert_eq!(tracker.state(), TcpState::Established);
+    }
+
+    #[test]
+    fn test_tcp_tracker_invalid_transitions() {
+        // data before the handshake completed
+        let mut tracker = TcpTracker::new();
+        let data = segment(CLIENT_ISN + 1, Some(SERVER_ISN), "");
+        assert_eq!(
+            tracker.track(TcpDirection::Original, &data, 10),
+            Err(TcpViolation::Transition)
+        );
+        // the responder may not open the session
+        let syn = segment(SERVER_ISN, None, "S");
+        assert_eq!(
+            tracker.track(TcpDirection::Reply, &syn, 0),
+            Err(TcpViolation::Transition)
+        );
+
+        // a SYN on an established session
+        let mut tracker = established();
+        let syn = segment(CLIENT_ISN + 1, None, "S");
+        assert_eq!(
+            tracker.track(TcpDirection::Original, &syn, 0),
+            Err(TcpViolation::Transition)
+        );
+
+        // nothing but RSTs once reset
+        let rst = segment(SERVER_ISN.wrapping_add(1), None, "R");
+        tracker.track(TcpDirection::Reply, &rst, 0).unwrap();
+        assert_eq!(tracker.state(), TcpState::Reset);
+        let data = segment(CLIENT_ISN + 1, Some(SERVER_ISN.wrapping_add(1)), "");
+        assert_eq!(
+            tracker.track(TcpDirection::Original, &data, 10),
+            Err(TcpViolation::Transition)
+        );
+    }
+
+    #[test]
+    fn test_tcp_tracker_out_of_window() {
+        let mut tracker = established();
+        let server_seq = SERVER_ISN.wrapping_add(1);
+
+        // beyond the window advertised by the server
+        let data = segment(CLIENT_ISN + 1 + 70_000, Some(server_seq), "");
+        assert_eq!(
+            tracker.track(TcpDirection::Original, &data, 100),
+            Err(TcpViolation::Window)
+        );
+        // acknowledging data the server never sent
+        let data = segment(CLIENT_ISN + 1, Some(server_seq.wrapping_add(5000)), "");
+        assert_eq!(
+            tracker.track(TcpDirection::Original, &data, 100),
+            Err(TcpViolation::Window)
+        );
+        // a blind RST
+        let rst = segment(server_seq.wrapping_add(1 << 30), None, "R");
+        assert_eq!(
+            tracker.track(TcpDirection::Reply, &rst, 0),
+            Err(TcpViolation::Window)
+        );
+        assert_eq!(tracker.state(), TcpState::Established);
+
+        // retransmissions are fine
+        let data = segment(CLIENT_ISN + 1, Some(server_seq), "");
+        tracker.track(TcpDirection::Original, &data, 100).unwrap();
+        tracker.track(TcpDirection::Original, &data, 100).unwrap();
+    }
+
+    #[test]
+    fn test_tcp_tracker_mid_stream() {
+        let server_seq = SERVER_ISN.wrapping_add(1);
+        let data = segment(CLIENT_ISN + 1, Some(server_seq), "");
+        let mut tracker = TcpTracker::for_segment(&data);
+        assert_eq!(tracker.state(), TcpState::Established);
+        tracker.track(TcpDirection::Original, &data, 100).unwrap();
+
+        // windows of unknown scale are assumed to be as large as they may be
+        let data = segment(server_seq, Some(CLIENT_ISN + 101), "");
+        tracker.track(TcpDirection::Reply, &data, 1000).unwrap();
+        let data = segment(CLIENT_ISN + 101 + 1_000_000, Some(server_seq), "");
+        tracker.track(TcpDirection::Original, &data, 100).unwrap();
+
+        // but the session is established: no SYN
+        let syn = segment(CLIENT_ISN + 1, None, "S");
+        assert_eq!(
+            tracker.track(TcpDirection::Original, &syn, 0),
+            Err(TcpViolation::Transition)
+        );
+
+        // trackers whose first segment is part of the handshake expect the rest of it
+        let synack = segment(SERVER_ISN, Some(CLIENT_ISN + 1), "S");
+        let tracker = TcpTracker::for_segment(&synack);
+        assert_eq!(tracker.state(), TcpState::SynSent);
+    }
+}
@@ -4,3 +4,4 @@
 #![deny(clippy::all, clippy::pedantic)]
 
 pub mod flow_table;
+mod session_log;
@@ -0,0 +1,160 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! The exporter of session events.
+//!
+//! The exporter waits for an event, then for more until it has a full batch or the flush
+//! interval elapses, and sends the batch to the collector. Over UDP, each event is sent in a
+//! datagram of its own. Over TCP, events are delimited by newlines; the connection is opened as
+//! needed and dropped on failure, along with the batch being sent. Sending is bounded in time, so
+//! that a slow collector fills up the queue, dropping events, rather than stalling the exporter.
+
+use std::io::{self, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, TcpStream, UdpSocket};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use config::external::session_log::{SessionLogConfig, SessionLogTransport};
+use tracing::{debug, warn};
+
+use super::format::format_event;
+use super::{SessionEvent, SessionLogMetrics};
+
+/// The max number of events queued for the exporter
+const QUEUE_CAPACITY: usize = 4096;
+
+/// The max time to connect to a TCP collector, or to send it a batch
+const TCP_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// The exporter of session events
+pub(super) struct SessionLogExporter {
+    queue: mpsc::Receiver<SessionEvent>,
+    config: SessionLogConfig,
+    hostname: String,
+    metrics: SessionLogMetrics,
+    udp: Option<UdpSocket>,
+    tcp: Option<TcpStream>,
+}
+
+impl SessionLogExporter {
+    /// Create an exporter, with the sender events are queued with
+    pub(super) fn new(
+        config: &SessionLogConfig,
+        hostname: &str,
+        metrics: SessionLogMetrics,
+    ) -> (Self, mpsc::SyncSender<SessionEvent>) {
+        let (tx, rx) = mpsc::sync_channel(QUEUE_CAPACITY);
+        let exporter = Self {
+            queue: rx,
+            config: config.clone(),
+            hostname: hostname.to_string(),
+            metrics,
+            udp: None,
+            tcp: None,
+        };
+        (exporter, tx)
+    }
+
+    /// Wait for the next batch of events. Returns `None` once all senders are gone and the
+    /// queue is empty.
+    fn next_batch(&self) -> Option<Vec<SessionEvent>> {
+        let first = self.queue.recv().ok()?;
+        let deadline = Instant::now() + self.config.flush_interval;
+        let mut batch = Vec::with_capacity(self.config.batch_size);
+        batch.push(first);
+        while batch.len() < self.config.batch_size {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            let Ok(event) = self.queue.recv_timeout(timeout) else {
+                break;
+            };
+            batch.push(event);
+        }
+        Some(batch)
+    }
+
+    /// Send messages to the collector in datagrams, returning how many were sent
+    fn send_udp(&mut self, messages: &[String]) -> io::Result<usize> {
+        let collector = self.config.collector;
+        if self.udp.is_none() {
+            let local = match collector {
+                SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+                SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+            };
+            self.udp = Some(UdpSocket::bind(local)?);
+        }
+        let socket = self.udp.as_ref().unwrap_or_else(|| unreachable!());
+        let mut sent = 0;
+        for message in messages {
+            match socket.send_to(message.as_bytes(), collector) {
+                Ok(_) => sent += 1,
+                Err(e) => debug!("Failed to send session event to {collector}: {e}"),
+            }
+        }
+        Ok(sent)
+    }
+
+    /// Send messages to the collector on the TCP connection, opening it if needed
+    fn send_tcp(&mut self, messages: &[String]) -> io::Result<usize> {
+        if self.tcp.is_none() {
+            let stream = TcpStream::connect_timeout(&self.config.collector, TCP_TIMEOUT)?;
+            stream.set_write_timeout(Some(TCP_TIMEOUT))?;
+            stream.set_nodelay(true)?;
+            self.tcp = Some(stream);
+        }
+        let stream = self.tcp.as_mut().unwrap_or_else(|| unreachable!());
+        let mut data = messages.join("\n");
+        data.push('\n');
+        if let Err(e) = stream.write_all(data.as_bytes()) {
+            self.tcp = None;
+            return Err(e);
+        }
+        Ok(messages.len())
+    }
+
+    fn export(&mut self, batch: &[SessionEvent]) {
+        let messages: Vec<_> = batch
+            .iter()
+            .map(|event| format_event(event, self.config.format, &self.hostname))
+            .collect();
+        let result = match self.config.transport {
+            SessionLogTransport::Udp => self.send_udp(&messages),
+            SessionLogTransport::Tcp => self.send_tcp(&messages),
+        };
+        let sent = result.unwrap_or_else(|e| {
+            debug!(
+                "Failed to send {} session events to {}: {e}",
+                messages.len(),
+                self.config.collector
+            );
+            0
+        });
+        self.metrics.sent.increment(sent as u64);
+        self.metrics
+            .dropped
+            .increment((messages.len() - sent) as u64);
+    }
+
+    /// Send the queued events in batches, until all the senders are gone
+    fn run(mut self) {
+        while let Some(batch) = self.next_batch() {
+            self.export(&batch);
+        }
+        debug!(
+            "Session log exporter to {} stopped: no sender left",
+            self.config.collector
+        );
+    }
+
+    /// Run the exporter in its own thread
+    ///
+    /// # Errors
+    ///
+    /// Fails if the thread can't be spawned.
+    pub(super) fn spawn(self) -> io::Result<JoinHandle<()>> {
+        std::thread::Builder::new()
+            .name("session-log".to_string())
+            .spawn(move || self.run())
+            .inspect_err(|e| warn!("Failed to spawn session log exporter: {e}"))
+    }
+}
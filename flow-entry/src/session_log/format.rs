@@ -0,0 +1,328 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Formatting of session events: as syslog messages (RFC 5424) with the session in structured
+//! data, as CEF messages carried in syslog, or as JSON objects.
+
+use std::fmt::{Display, Write};
+use std::time::UNIX_EPOCH;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use config::external::session_log::SessionLogFormat;
+use net::packet::VpcDiscriminant;
+
+use super::{SessionEvent, SessionEventKind};
+
+/// The priority of the syslog messages: facility local0, severity informational
+const SYSLOG_PRI: u8 = 16 * 8 + 6;
+
+/// The name of the application in syslog messages
+const APP_NAME: &str = "dataplane";
+
+/// The id of the structured data of sessions. The enterprise number is the one reserved for
+/// documentation (RFC 5612).
+const SD_ID: &str = "session@32473";
+
+/// The vendor and product in CEF headers
+const CEF_VENDOR: &str = "Hedgehog";
+const CEF_PRODUCT: &str = "dataplane";
+
+/// The severity of session events in CEF
+const CEF_SEVERITY: u8 = 3;
+
+/// A field of an event: its name, its key in CEF, and its value
+struct Field {
+    name: &'static str,
+    cef: &'static str,
+    value: String,
+    numeric: bool,
+}
+
+/// The fields of an event, in the order they are reported
+#[derive(Default)]
+struct Fields(Vec<Field>);
+
+impl Fields {
+    fn text(&mut self, name: &'static str, cef: &'static str, value: impl Display) {
+        self.0.push(Field {
+            name,
+            cef,
+            value: value.to_string(),
+            numeric: false,
+        });
+    }
+
+    fn number(&mut self, name: &'static str, cef: &'static str, value: impl Into<u64>) {
+        self.0.push(Field {
+            name,
+            cef,
+            value: value.into().to_string(),
+            numeric: true,
+        });
+    }
+}
+
+fn vni(vpcd: VpcDiscriminant) -> u32 {
+    let VpcDiscriminant::VNI(vni) = vpcd;
+    vni.as_u32()
+}
+
+fn fields(event: &SessionEvent) -> Vec<Field> {
+    let mut fields = Fields::default();
+    let key = &event.original;
+    fields.text("proto", "proto", key.proto());
+    fields.text("src", "src", key.src_ip());
+    fields.text("dst", "dst", key.dst_ip());
+    if let Some((sport, dport)) = key.ports() {
+        fields.number("sport", "spt", sport.get());
+        fields.number("dport", "dpt", dport.get());
+    }
+    if let Some(vpcd) = key.src_vpcd() {
+        fields.number("src_vni", "cn1", vni(vpcd));
+    }
+    if let Some(vpcd) = event.dst_vpcd {
+        fields.number("dst_vni", "cn2", vni(vpcd));
+    }
+
+    // The reply flow goes from where the initiator sends packets to, to where it gets packets
+    // from. Where these differ from the addresses of the initiator, the session is translated.
+    if let Some(reply) = &event.reply {
+        if reply.dst_ip() != key.src_ip() {
+            fields.text("xlate_src", "sourceTranslatedAddress", reply.dst_ip());
+        }
+        if reply.src_ip() != key.dst_ip() {
+            fields.text("xlate_dst", "destinationTranslatedAddress", reply.src_ip());
+        }
+        if let (Some((sport, dport)), Some((reply_sport, reply_dport))) =
+            (key.ports(), reply.ports())
+        {
+            if reply_dport != sport {
+                fields.number("xlate_sport", "sourceTranslatedPort", reply_dport.get());
+            }
+            if reply_sport != dport {
+                fields.number(
+                    "xlate_dport",
+                    "destinationTranslatedPort",
+                    reply_sport.get(),
+                );
+            }
+        }
+    }
+
+    if let Some(counts) = &event.counts {
+        fields.number("packets_out", "cn3", counts.packets_out);
+        fields.number("bytes_out", "out", counts.octets_out);
+        fields.number("packets_in", "cn4", counts.packets_in);
+        fields.number("bytes_in", "in", counts.octets_in);
+    }
+    fields.0
+}
+
+/// The labels of the custom CEF fields
+fn cef_label(key: &str) -> Option<&'static str> {
+    match key {
+        "cn1" => Some("srcVni"),
+        "cn2" => Some("dstVni"),
+        "cn3" => Some("packetsOut"),
+        "cn4" => Some("packetsIn"),
+        _ => None,
+    }
+}
+
+/// The header of a syslog message (RFC 5424), up to the structured data
+fn syslog_header(out: &mut String, event: &SessionEvent, hostname: &str) -> std::fmt::Result {
+    let time = DateTime::<Utc>::from(event.time).to_rfc3339_opts(SecondsFormat::Micros, true);
+    let hostname = if hostname.is_empty() { "-" } else { hostname };
+    let msgid = match event.kind {
+        SessionEventKind::Create => "SESSION_CREATE",
+        SessionEventKind::Teardown => "SESSION_TEARDOWN",
+    };
+    write!(
+        out,
+        "<{SYSLOG_PRI}>1 {time} {hostname} {APP_NAME} - {msgid} "
+    )
+}
+
+/// A summary of the session, for humans
+fn summary(out: &mut String, event: &SessionEvent) -> std::fmt::Result {
+    let key = &event.original;
+    write!(out, "session {} {}", event.kind, key.proto())?;
+    match key.ports() {
+        Some((sport, dport)) => {
+            write!(out, " {}:{sport} -> {}:{dport}", key.src_ip(), key.dst_ip())
+        }
+        None => write!(out, " {} -> {}", key.src_ip(), key.dst_ip()),
+    }
+}
+
+fn format_syslog(out: &mut String, event: &SessionEvent, hostname: &str) -> std::fmt::Result {
+    syslog_header(out, event, hostname)?;
+    write!(out, "[{SD_ID}")?;
+    for field in fields(event) {
+        // the values never hold characters that would need escaping: '"', '\' or ']'
+        write!(out, " {}=\"{}\"", field.name, field.value)?;
+    }
+    write!(out, "] ")?;
+    summary(out, event)
+}
+
+fn format_cef(out: &mut String, event: &SessionEvent, hostname: &str) -> std::fmt::Result {
+    syslog_header(out, event, hostname)?;
+    let (signature, name) = match event.kind {
+        SessionEventKind::Create => ("session-create", "Session created"),
+        SessionEventKind::Teardown => ("session-teardown", "Session torn down"),
+    };
+    let millis = event
+        .time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis());
+    write!(
+        out,
+        "- CEF:0|{CEF_VENDOR}|{CEF_PRODUCT}|{}|{signature}|{name}|{CEF_SEVERITY}|rt={millis}",
+        env!("CARGO_PKG_VERSION")
+    )?;
+    for field in fields(event) {
+        if let Some(label) = cef_label(field.cef) {
+            write!(out, " {}Label={label}", field.cef)?;
+        }
+        write!(out, " {}={}", field.cef, field.value)?;
+    }
+    Ok(())
+}
+
+/// Quote a JSON string
+fn json_string(out: &mut String, value: &str) -> std::fmt::Result {
+    out.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            c if c.is_control() => write!(out, "\\u{:04x}", u32::from(c))?,
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    Ok(())
+}
+
+fn format_json(out: &mut String, event: &SessionEvent, hostname: &str) -> std::fmt::Result {
+    let time = DateTime::<Utc>::from(event.time).to_rfc3339_opts(SecondsFormat::Micros, true);
+    write!(out, "{{\"time\":\"{time}\",\"host\":")?;
+    json_string(out, hostname)?;
+    write!(out, ",\"event\":\"{}\"", event.kind)?;
+    for field in fields(event) {
+        if field.numeric {
+            write!(out, ",\"{}\":{}", field.name, field.value)?;
+        } else {
+            write!(out, ",\"{}\":", field.name)?;
+            json_string(out, &field.value)?;
+        }
+    }
+    out.push('}');
+    Ok(())
+}
+
+/// Format an event, as reported by `hostname`
+pub(super) fn format_event(
+    event: &SessionEvent,
+    format: SessionLogFormat,
+    hostname: &str,
+) -> String {
+    let mut out = String::with_capacity(256);
+    let result = match format {
+        SessionLogFormat::Syslog => format_syslog(&mut out, event, hostname),
+        SessionLogFormat::Cef => format_cef(&mut out, event, hostname),
+        SessionLogFormat::Json => format_json(&mut out, event, hostname),
+    };
+    // writing to a string never fails
+    result.unwrap_or_else(|_| unreachable!());
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::session_log::SessionCounts;
+    use net::FlowKey;
+    use net::IpProtoKey;
+    use net::TcpProtoKey;
+    use net::tcp::TcpPort;
+    use net::vxlan::Vni;
+    use std::time::Duration;
+
+    fn tcp_key(vni: u32, src: &str, sport: u16, dst: &str, dport: u16) -> FlowKey {
+        FlowKey::new(
+            Some(VpcDiscriminant::from_vni(Vni::new_checked(vni).unwrap())),
+            src.parse().unwrap(),
+            dst.parse().unwrap(),
+            IpProtoKey::Tcp(TcpProtoKey {
+                src_port: TcpPort::new_checked(sport).unwrap(),
+                dst_port: TcpPort::new_checked(dport).unwrap(),
+            }),
+        )
+    }
+
+    fn event(kind: SessionEventKind) -> SessionEvent {
+        SessionEvent {
+            kind,
+            time: UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            original: tcp_key(100, "10.0.0.1", 1025, "20.0.0.1", 80),
+            // masqueraded to 192.168.0.1:40000
+            reply: Some(tcp_key(200, "20.0.0.1", 80, "192.168.0.1", 40000)),
+            dst_vpcd: Some(VpcDiscriminant::from_vni(Vni::new_checked(200).unwrap())),
+            counts: (kind == SessionEventKind::Teardown).then_some(SessionCounts {
+                packets_out: 10,
+                octets_out: 1000,
+                packets_in: 8,
+                octets_in: 6000,
+            }),
+        }
+    }
+
+    #[test]
+    fn test_format_syslog() {
+        let message = format_event(
+            &event(SessionEventKind::Create),
+            SessionLogFormat::Syslog,
+            "gw1",
+        );
+        assert_eq!(
+            message,
+            "<134>1 2023-11-14T22:13:20.000000Z gw1 dataplane - SESSION_CREATE [session@32473 \
+             proto=\"TCP\" src=\"10.0.0.1\" dst=\"20.0.0.1\" sport=\"1025\" dport=\"80\" \
+             src_vni=\"100\" dst_vni=\"200\" xlate_src=\"192.168.0.1\" xlate_sport=\"40000\"] \
+             session create TCP 10.0.0.1:1025 -> 20.0.0.1:80"
+        );
+    }
+
+    #[test]
+    fn test_format_cef() {
+        let message = format_event(
+            &event(SessionEventKind::Teardown),
+            SessionLogFormat::Cef,
+            "gw1",
+        );
+        let (header, cef) = message.split_once(" - CEF:").unwrap();
+        assert!(header.ends_with("gw1 dataplane - SESSION_TEARDOWN"));
+        assert!(cef.starts_with("0|Hedgehog|dataplane|"));
+        assert!(cef.contains("|session-teardown|Session torn down|3|rt=1700000000000 "));
+        assert!(cef.contains(" sourceTranslatedAddress=192.168.0.1 sourceTranslatedPort=40000"));
+        assert!(
+            cef.contains(" cn3Label=packetsOut cn3=10 out=1000 cn4Label=packetsIn cn4=8 in=6000")
+        );
+    }
+
+    #[test]
+    fn test_format_json() {
+        let mut event = event(SessionEventKind::Teardown);
+        event.reply = None;
+        let message = format_event(&event, SessionLogFormat::Json, "gw\"1");
+        assert_eq!(
+            message,
+            "{\"time\":\"2023-11-14T22:13:20.000000Z\",\"host\":\"gw\\\"1\",\"event\":\"teardown\",\
+             \"proto\":\"TCP\",\"src\":\"10.0.0.1\",\"dst\":\"20.0.0.1\",\"sport\":1025,\"dport\":80,\
+             \"src_vni\":100,\"dst_vni\":200,\"packets_out\":10,\"bytes_out\":1000,\
+             \"packets_in\":8,\"bytes_in\":6000}"
+        );
+    }
+}
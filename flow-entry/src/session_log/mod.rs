@@ -0,0 +1,199 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Logging of sessions to an external collector.
+//!
+//! The flow table reports the creation of a session when the flow initiating it is inserted,
+//! and its teardown when that flow leaves the table. Events are handed over to an exporter
+//! through a bounded queue, so that logging never blocks packet processing: events which do not
+//! fit in the queue are dropped and accounted. The exporter runs in its own thread and sends the
+//! events to the collector in batches.
+
+mod exporter;
+mod format;
+
+use std::fmt::Display;
+use std::sync::mpsc;
+use std::time::SystemTime;
+
+use concurrency::sync::atomic::{AtomicBool, Ordering};
+use concurrency::sync::{RwLock, Weak};
+use config::external::session_log::SessionLogConfig;
+use metrics::{Counter, Unit};
+use net::FlowKey;
+use net::flows::FlowInfo;
+use net::packet::VpcDiscriminant;
+use stats::{MetricSpec, Register};
+use tracing::{info, warn};
+
+use exporter::SessionLogExporter;
+
+/// The kind of a session event
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SessionEventKind {
+    Create,
+    Teardown,
+}
+
+impl Display for SessionEventKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SessionEventKind::Create => write!(f, "create"),
+            SessionEventKind::Teardown => write!(f, "teardown"),
+        }
+    }
+}
+
+/// The packets and octets of a session, in each of its directions
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) struct SessionCounts {
+    pub(crate) packets_out: u64,
+    pub(crate) octets_out: u64,
+    pub(crate) packets_in: u64,
+    pub(crate) octets_in: u64,
+}
+
+/// An event in the life of a session
+#[derive(Clone, Debug)]
+pub(crate) struct SessionEvent {
+    pub(crate) kind: SessionEventKind,
+    pub(crate) time: SystemTime,
+    /// The key of the flow initiating the session
+    pub(crate) original: FlowKey,
+    /// The key of the flow replying to it, if known. It tells the NAT mapping of the session.
+    pub(crate) reply: Option<FlowKey>,
+    pub(crate) dst_vpcd: Option<VpcDiscriminant>,
+    /// The counts of the session, at teardown
+    pub(crate) counts: Option<SessionCounts>,
+}
+
+impl SessionEvent {
+    pub(crate) fn new(kind: SessionEventKind, flow_info: &FlowInfo) -> Self {
+        let reply = flow_info
+            .related
+            .as_ref()
+            .and_then(Weak::upgrade)
+            .map(|reply| *reply.flowkey());
+        let counts = (kind == SessionEventKind::Teardown).then(|| {
+            let (original, reply) = flow_info.counters();
+            SessionCounts {
+                packets_out: original.packets(),
+                octets_out: original.octets(),
+                packets_in: reply.packets(),
+                octets_in: reply.octets(),
+            }
+        });
+        Self {
+            kind,
+            time: SystemTime::now(),
+            original: *flow_info.flowkey(),
+            reply,
+            dst_vpcd: flow_info.get_dst_vpcd(),
+            counts,
+        }
+    }
+}
+
+/// The metrics of session logging
+#[derive(Clone, Debug)]
+struct SessionLogMetrics {
+    /// Events sent to the collector
+    sent: Counter,
+    /// Events dropped, because the queue was full or because they could not be sent
+    dropped: Counter,
+}
+
+impl SessionLogMetrics {
+    fn new() -> Self {
+        let counter =
+            |id: &str| -> Counter { MetricSpec::new(id, Unit::Count, vec![]).register().metric };
+        Self {
+            sent: counter("session_log_events"),
+            dropped: counter("session_log_dropped_events"),
+        }
+    }
+}
+
+/// A running session logger
+struct SessionLogger {
+    config: SessionLogConfig,
+    hostname: String,
+    queue: mpsc::SyncSender<SessionEvent>,
+    metrics: SessionLogMetrics,
+}
+
+/// Session logging, as configured. Dropping the logger of a previous configuration disconnects
+/// its exporter, which sends the events it still has and stops.
+pub struct SessionLog {
+    enabled: AtomicBool,
+    logger: RwLock<Option<SessionLogger>>,
+}
+
+impl std::fmt::Debug for SessionLog {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.logger.read().as_ref() {
+            Some(logger) => write!(f, "SessionLog({})", logger.config.collector),
+            None => write!(f, "SessionLog(disabled)"),
+        }
+    }
+}
+
+impl SessionLog {
+    pub(crate) fn new() -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            logger: RwLock::new(None),
+        }
+    }
+
+    /// Log sessions as configured, or stop logging them if `config` is `None`. Events are
+    /// reported as coming from `hostname`.
+    pub(crate) fn configure(&self, config: Option<&SessionLogConfig>, hostname: &str) {
+        let mut logger = self.logger.write();
+        if let (Some(current), Some(config)) = (logger.as_ref(), config)
+            && current.config == *config
+            && current.hostname == hostname
+        {
+            return;
+        }
+        self.enabled.store(false, Ordering::Relaxed);
+        *logger = None;
+        let Some(config) = config else {
+            return;
+        };
+        let metrics = SessionLogMetrics::new();
+        let (exporter, queue) = SessionLogExporter::new(config, hostname, metrics.clone());
+        if exporter.spawn().is_err() {
+            return;
+        }
+        info!(
+            "Logging sessions to {} ({})",
+            config.collector, config.format
+        );
+        *logger = Some(SessionLogger {
+            config: config.clone(),
+            hostname: hostname.to_string(),
+            queue,
+            metrics,
+        });
+        self.enabled.store(true, Ordering::Relaxed);
+    }
+
+    /// Log an event of the session of a flow, if the flow initiated it. Events that do not fit
+    /// in the queue of the exporter are dropped.
+    pub(crate) fn log(&self, kind: SessionEventKind, flow_info: &FlowInfo) {
+        if !self.enabled.load(Ordering::Relaxed) || !flow_info.is_initiator() {
+            return;
+        }
+        let logger = self.logger.read();
+        let Some(logger) = logger.as_ref() else {
+            return;
+        };
+        if let Err(e) = logger.queue.try_send(SessionEvent::new(kind, flow_info)) {
+            if matches!(e, mpsc::TrySendError::Disconnected(_)) {
+                warn!("Session log exporter is gone");
+            }
+            logger.metrics.dropped.increment(1);
+        }
+    }
+}
@@ -45,6 +45,11 @@ impl Display for FlowFilterTable {
             write!(indented(f).with_str("    "), "{table}")?;
             writeln!(f)?;
         }
+
+        writeln!(f, "peering rule hits:")?;
+        for (rule, hits) in self.peering_rule_hits() {
+            writeln!(f, "  {rule}: {hits}")?;
+        }
         Ok(())
     }
 }
@@ -151,6 +156,15 @@ impl Display for RemoteData {
         } else {
             write!(f, "destination NAT: -")?;
         }
+        for (side, protocols) in [
+            ("source", self.src_protocols),
+            ("destination", self.dst_protocols),
+        ] {
+            let protocols: Vec<_> = protocols.protocols().map(ToString::to_string).collect();
+            if !protocols.is_empty() {
+                write!(f, ", {side} protocols: {}", protocols.join(" "))?;
+            }
+        }
         Ok(())
     }
 }
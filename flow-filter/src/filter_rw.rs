@@ -2,23 +2,44 @@
 // Copyright Open Network Fabric Authors
 
 //! Left-right integration for [`FlowFilterTable`]
+//!
+//! The writer publishes the changes of the entries of the table, rather than the whole table, and
+//! records them in a change log, so that a [`ChangeFeed`] can replay them onto a copy of it.
 
-use crate::tables::FlowFilterTable;
-use left_right::{Absorb, ReadGuard, ReadHandle, ReadHandleFactory, WriteHandle, new_from_empty};
+use crate::origin::PeeringRules;
+use crate::tables::{FlowFilterTable, VpcConnectionsTable};
+use common::changelog::{ChangeFeed, Delta, EntryDelta, SharedChangeLog, diff_entries};
+use common::generation::Generational;
+use left_right::{Absorb, ReadGuard, ReadHandle, ReadHandleFactory, new_from_empty};
+use net::packet::VpcDiscriminant;
+use stats::InstrumentedWriteHandle;
 use tracing::debug;
 
-#[derive(Debug)]
-pub(crate) enum FlowFilterTableChange {
-    UpdateFlowFilterTable(FlowFilterTable),
+#[derive(Debug, Clone)]
+enum Change {
+    WithPorts(EntryDelta<VpcDiscriminant, VpcConnectionsTable>),
+    NoPorts(EntryDelta<VpcDiscriminant, VpcConnectionsTable>),
+    Rules(PeeringRules),
+}
+
+/// A change of a [`FlowFilterTable`], as published by its writer
+#[derive(Debug, Clone)]
+pub struct FlowFilterTableChange(Change);
+
+impl Delta<FlowFilterTable> for FlowFilterTableChange {
+    fn replay(&self, table: &mut FlowFilterTable) {
+        match &self.0 {
+            Change::WithPorts(delta) => delta.apply(&mut table.with_ports.0),
+            Change::NoPorts(delta) => delta.apply(&mut table.no_ports.0),
+            Change::Rules(rules) => table.rules = rules.clone(),
+        }
+        table.advance_generation();
+    }
 }
 
 impl Absorb<FlowFilterTableChange> for FlowFilterTable {
     fn absorb_first(&mut self, change: &mut FlowFilterTableChange, _: &Self) {
-        match change {
-            FlowFilterTableChange::UpdateFlowFilterTable(table) => {
-                *self = table.clone();
-            }
-        }
+        change.replay(self);
     }
     fn drop_first(self: Box<Self>) {}
     fn sync_with(&mut self, first: &Self) {
@@ -55,8 +76,18 @@ impl FlowFilterTableReaderFactory {
     }
 }
 
-#[derive(Debug)]
-pub struct FlowFilterTableWriter(WriteHandle<FlowFilterTable, FlowFilterTableChange>);
+pub struct FlowFilterTableWriter {
+    handle: InstrumentedWriteHandle<FlowFilterTable, FlowFilterTableChange>,
+    log: SharedChangeLog<FlowFilterTableChange>,
+}
+
+impl std::fmt::Debug for FlowFilterTableWriter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("FlowFilterTableWriter")
+            .field("handle", &self.handle)
+            .finish_non_exhaustive()
+    }
+}
 
 impl FlowFilterTableWriter {
     #[must_use]
@@ -64,22 +95,54 @@ impl FlowFilterTableWriter {
     pub fn new() -> FlowFilterTableWriter {
         let (w, _r) =
             new_from_empty::<FlowFilterTable, FlowFilterTableChange>(FlowFilterTable::new());
-        FlowFilterTableWriter(w)
+        FlowFilterTableWriter {
+            handle: InstrumentedWriteHandle::new(w, "flow-filter"),
+            log: SharedChangeLog::default(),
+        }
     }
 
     #[must_use]
     pub fn get_reader(&self) -> FlowFilterTableReader {
-        FlowFilterTableReader(self.0.clone())
+        FlowFilterTableReader(self.handle.clone())
     }
 
     pub fn get_reader_factory(&self) -> FlowFilterTableReaderFactory {
         self.get_reader().factory()
     }
 
-    pub fn update_flow_filter_table(&mut self, table: FlowFilterTable) {
-        self.0
-            .append(FlowFilterTableChange::UpdateFlowFilterTable(table));
-        self.0.publish();
-        debug!("Updated flow filter table");
+    /// Get the feed of the changes published for the table
+    #[must_use]
+    pub fn change_feed(&self) -> ChangeFeed<FlowFilterTable, FlowFilterTableChange> {
+        self.log.feed(self.handle.clone())
+    }
+
+    /// Publish the changes of the entries turning the current table into `table`
+    pub fn update_flow_filter_table(&mut self, mut table: FlowFilterTable) {
+        let changes = {
+            let empty = FlowFilterTable::new();
+            let current = self.handle.enter();
+            let current = current.as_deref().unwrap_or(&empty);
+            table.rules.carry_hits(&current.rules);
+            let with_ports = diff_entries(&current.with_ports.0, &table.with_ports.0);
+            let no_ports = diff_entries(&current.no_ports.0, &table.no_ports.0);
+            let mut changes: Vec<_> = with_ports
+                .into_iter()
+                .map(Change::WithPorts)
+                .chain(no_ports.into_iter().map(Change::NoPorts))
+                .map(FlowFilterTableChange)
+                .collect();
+            if !table.rules.same_rules(&current.rules) {
+                changes.push(FlowFilterTableChange(Change::Rules(table.rules)));
+            }
+            changes
+        };
+        for change in &changes {
+            self.handle.append(change.clone());
+        }
+        let count = changes.len();
+        self.log.record(changes, || {
+            self.handle.publish();
+        });
+        debug!("Updated flow filter table: {count} changes");
     }
 }
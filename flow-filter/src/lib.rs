@@ -11,9 +11,15 @@
 //! - It validates that the packet is associated with an existing peering connection, as defined in
 //!   the user-provided configuration. Packets that do not have a source IP, port and destination
 //!   IP, port corresponding to existing, valid connections between the prefixes in exposed lists of
-//!   peerings, get dropped.
+//!   peerings, get dropped. So do packets of protocols that the matching exposes do not allow, if
+//!   they restrict their traffic to some protocols (ICMP messages, GRE, ESP...). Exposed lists
+//!   may hold IPv4 and IPv6 prefixes alike; packets only match prefixes of their IP version.
+//!
+//! Packets matching a peering rule are accounted for that rule, whether they are allowed or
+//! dropped (see [`PeeringRuleHits`]), and exported as metrics.
 
-use crate::tables::{NatRequirement, RemoteData, VpcdLookupResult};
+use crate::origin::RuleOutcome;
+use crate::tables::{NatRequirement, PacketProtocol, RemoteData, VpcdLookupResult};
 use lpm::prefix::L4Protocol;
 use net::FlowKey;
 use net::buffer::PacketBufferMut;
@@ -30,12 +36,19 @@ use tracing::{debug, error};
 
 mod display;
 mod filter_rw;
+mod origin;
 mod setup;
+mod simulate;
 mod tables;
 #[cfg(test)]
 mod tests;
 
-pub use filter_rw::{FlowFilterTableReader, FlowFilterTableReaderFactory, FlowFilterTableWriter};
+pub use filter_rw::{
+    FlowFilterTableChange, FlowFilterTableReader, FlowFilterTableReaderFactory,
+    FlowFilterTableWriter,
+};
+pub use origin::{PeeringRule, PeeringRuleHits};
+pub use simulate::{FlowVerdict, NatRequirements};
 pub use tables::FlowFilterTable;
 
 use tracectl::trace_target;
@@ -47,6 +60,8 @@ pub struct FlowFilter {
     name: String,
     tablesr: FlowFilterTableReader,
     pipeline_data: Arc<PipelineData>,
+    /// The shard of the peering rule hit counters this stage accounts hits in
+    shard: usize,
 }
 
 impl FlowFilter {
@@ -56,6 +71,7 @@ impl FlowFilter {
             name: name.to_string(),
             tablesr,
             pipeline_data: Arc::from(PipelineData::default()),
+            shard: origin::next_hit_shard(),
         }
     }
 
@@ -172,13 +188,15 @@ impl FlowFilter {
         };
 
         // data_set may actually contain RemoteData objects that do not apply to our packet, because the
-        // table lookup does not account for TCP vs. UDP, we only deal with the protocol when looking at
-        // NAT requirements. Here we filter out RemoteData objects that do not apply to our packet.
+        // table lookup does not account for the protocol, we only deal with it when looking at NAT
+        // requirements and at the protocols allowed by exposes. Here we filter out RemoteData objects
+        // that do not apply to our packet.
 
         let packet_proto = get_l4_proto(packet);
+        let protocol = get_packet_protocol(packet);
         let data_set = data_set
             .iter()
-            .filter(|d| d.applies_to(packet_proto))
+            .filter(|d| d.applies_to(packet_proto) && protocol.is_none_or(|p| d.allows(p)))
             .collect::<HashSet<_>>();
 
         if data_set.is_empty() {
@@ -386,15 +404,38 @@ impl FlowFilter {
                 .map(NonZero::get)
                 .zip(t.dst_port().map(NonZero::get))
         });
+        // We know the packet has an IP header
+        let protocol = get_packet_protocol(packet).unwrap_or_else(|| unreachable!());
 
         // For Display
         let tuple = FlowTuple::new(src_vpcd, src_ip, dst_ip, ports);
 
-        let dst_vpcd = match tablesr.lookup(src_vpcd, &src_ip, &dst_ip, ports) {
+        let lookup = tablesr.lookup_with_origin(src_vpcd, &src_ip, &dst_ip, ports);
+        let matched = lookup.as_ref().map(|(_, matched)| *matched);
+        let dst_vpcd = match lookup.map(|(result, _)| result) {
             None => {
                 debug!("{nfi}: No valid destination VPC found for flow {tuple}");
                 None
             }
+            Some(VpcdLookupResult::Single(dst_data)) if !dst_data.allows(protocol) => {
+                // The most specific match decides: we do not fall back to less specific prefixes
+                // of exposes which would allow the protocol.
+                debug!(
+                    "{nfi}: Protocol {protocol:?} is not allowed for flow {tuple}, dropping packet"
+                );
+                if let Some(matched) = matched {
+                    tablesr.record_rule_hit(
+                        src_vpcd,
+                        dst_data.vpcd,
+                        matched,
+                        RuleOutcome::Filtered,
+                        self.shard,
+                    );
+                }
+                packet.invalidate_flows();
+                packet.done(DoneReason::Filtered);
+                return;
+            }
             Some(VpcdLookupResult::Single(dst_data)) => {
                 // Check NAT requirements are sensible
                 if self
@@ -404,6 +445,15 @@ impl FlowFilter {
                     debug!(
                         "{nfi}: Invalid NAT requirements found for flow {tuple}, dropping packet"
                     );
+                    if let Some(matched) = matched {
+                        tablesr.record_rule_hit(
+                            src_vpcd,
+                            dst_data.vpcd,
+                            matched,
+                            RuleOutcome::Filtered,
+                            self.shard,
+                        );
+                    }
                     packet.invalidate_flows();
                     packet.done(DoneReason::Filtered);
                     return;
@@ -415,7 +465,23 @@ impl FlowFilter {
                 debug!(
                     "{nfi}: Found multiple matches for destination VPC for flow {tuple}, trying to figure out destination VPC"
                 );
-                self.deal_with_multiple_matches(packet, &data_set, &tuple)
+                let dst_vpcd = self.deal_with_multiple_matches(packet, &data_set, &tuple);
+                // account the drop to each of the rules the packet matched
+                if dst_vpcd.is_none()
+                    && let Some(matched) = matched
+                {
+                    let candidates: HashSet<_> = data_set.iter().map(|data| data.vpcd).collect();
+                    for candidate in candidates {
+                        tablesr.record_rule_hit(
+                            src_vpcd,
+                            candidate,
+                            matched,
+                            RuleOutcome::Ambiguous,
+                            self.shard,
+                        );
+                    }
+                }
+                dst_vpcd
             }
         };
 
@@ -429,6 +495,17 @@ impl FlowFilter {
         debug!("{nfi}: Flow {tuple} is allowed. Dst VPC is {dst_vpcd}");
         packet.meta_mut().dst_vpcd = Some(dst_vpcd);
 
+        // Record the peering rule that allowed the packet
+        if let Some(matched) = matched {
+            packet.meta_mut().peering_rule = tablesr.record_rule_hit(
+                src_vpcd,
+                dst_vpcd,
+                matched,
+                RuleOutcome::Allowed,
+                self.shard,
+            );
+        }
+
         // Port forwarding or masquerading used in combination with static NAT need to keep track of
         // the initial IP addresses for creating the right flow table entries, so we may have to
         // attach the flow key to packet's metadata.
@@ -518,6 +595,28 @@ impl Display for FlowTuple {
     }
 }
 
+/// Get the protocol of a packet, to match against the protocols allowed by exposes. Returns `None`
+/// if the packet has no IP header.
+pub(crate) fn get_packet_protocol<Buf: PacketBufferMut>(
+    packet: &Packet<Buf>,
+) -> Option<PacketProtocol> {
+    match packet.try_transport() {
+        Some(Transport::Tcp(_)) => Some(PacketProtocol::Tcp),
+        Some(Transport::Udp(_)) => Some(PacketProtocol::Udp),
+        Some(Transport::Icmp4(icmp)) => Some(PacketProtocol::Icmp {
+            icmp_type: icmp.type_u8(),
+            code: icmp.code_u8(),
+        }),
+        Some(Transport::Icmp6(icmp)) => Some(PacketProtocol::Icmp {
+            icmp_type: icmp.type_u8(),
+            code: icmp.code_u8(),
+        }),
+        None => packet
+            .upper_layer_proto()
+            .map(|proto| PacketProtocol::Other(proto.as_u8())),
+    }
+}
+
 pub(crate) fn get_l4_proto<Buf: PacketBufferMut>(packet: &Packet<Buf>) -> L4Protocol {
     match packet.try_transport() {
         Some(Transport::Tcp(_)) => L4Protocol::Tcp,
@@ -0,0 +1,322 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Identification of the peering rules allowing packets, and accounting of their hits.
+//!
+//! A peering rule is the combination of the source VPC, the local exposed prefix matching the
+//! source address, the remote exposed prefix matching the destination address, and the resulting
+//! destination VPC. Rules are enumerated (and assigned a [`PeeringRuleId`]) once, when the
+//! [`FlowFilterTable`] is built, so that tagging an allowed packet only costs a hash lookup and an
+//! increment of a counter of the worker.
+//!
+//! Hits are accounted by outcome: packets allowed by a rule, packets matching a rule but dropped
+//! because their NAT requirements can't be met, and packets matching several rules that can't be
+//! told apart. Each worker accounts hits in its own shard of the counters, so that workers don't
+//! contend on them, and the shards are summed up when the hits are read. The counters of a rule
+//! are carried over to the tables built afterwards with the same rule. They are exported as
+//! metrics too, labelled with the rule.
+//!
+//! [`FlowFilterTable`]: crate::FlowFilterTable
+
+use concurrency::sync::Arc;
+use concurrency::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use lpm::prefix::Prefix;
+use metrics::{Counter, Unit};
+use net::packet::{PeeringRuleId, VpcDiscriminant};
+use stats::{MetricSpec, Register};
+use std::collections::HashMap;
+use std::fmt::Display;
+
+/// A rule allowing traffic from one VPC to another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PeeringRule {
+    /// The VPC the traffic originates from.
+    pub src_vpcd: VpcDiscriminant,
+    /// The VPC the traffic is destined to.
+    pub dst_vpcd: VpcDiscriminant,
+    /// The local exposed prefix matching the source address, or `None` for a default expose.
+    pub local: Option<Prefix>,
+    /// The remote exposed prefix matching the destination address, or `None` for a default expose.
+    pub remote: Option<Prefix>,
+}
+
+impl Display for PeeringRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:", self.src_vpcd)?;
+        match &self.local {
+            Some(prefix) => write!(f, "{prefix}")?,
+            None => write!(f, "default")?,
+        }
+        write!(f, " -> {}:", self.dst_vpcd)?;
+        match &self.remote {
+            Some(prefix) => write!(f, "{prefix}"),
+            None => write!(f, "default"),
+        }
+    }
+}
+
+/// What became of a packet matching a peering rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RuleOutcome {
+    /// The packet was allowed
+    Allowed,
+    /// The packet was dropped, as its NAT requirements could not be met
+    Filtered,
+    /// The packet matched other rules too, which could not be told apart, and was dropped
+    Ambiguous,
+}
+
+/// The hit counters of a [`PeeringRule`], by outcome
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PeeringRuleHits {
+    /// Packets allowed by the rule
+    pub allowed: u64,
+    /// Packets matching the rule, dropped as their NAT requirements could not be met
+    pub filtered: u64,
+    /// Packets matching the rule and others, dropped as the rules could not be told apart
+    pub ambiguous: u64,
+}
+
+impl Display for PeeringRuleHits {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "allowed {}, filtered {}, ambiguous {}",
+            self.allowed, self.filtered, self.ambiguous
+        )
+    }
+}
+
+/// Number of shards of the hit counters. Workers beyond it share shards, which is correct but
+/// makes them contend on the counters.
+const HIT_SHARDS: usize = 16;
+
+/// Number of hits a shard accumulates before adding them to the metrics. Hits are also added to
+/// the metrics when read.
+const METRICS_BATCH: u64 = 256;
+
+/// The shard of the hit counters to be used by the next worker
+static NEXT_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+/// Pick the shard of the hit counters of a worker, so that workers account hits in distinct ones
+pub(crate) fn next_hit_shard() -> usize {
+    NEXT_SHARD.fetch_add(1, Ordering::Relaxed) % HIT_SHARDS
+}
+
+/// The hits of a rule accounted by the workers using a shard, by outcome. Shards are cache line
+/// aligned, so that the workers don't bounce the lines of each other.
+#[derive(Debug, Default)]
+#[repr(align(64))]
+struct HitShard {
+    hits: [AtomicU64; 3],
+    /// Hits not added to the metrics yet
+    unexported: [AtomicU64; 3],
+}
+
+/// The hit counters of a rule, and their metrics
+#[derive(Debug)]
+struct RuleCounters {
+    shards: [HitShard; HIT_SHARDS],
+    metrics: [Counter; 3],
+}
+
+impl RuleCounters {
+    fn new(rule: &PeeringRule) -> Self {
+        let show = |prefix: Option<Prefix>| prefix.map_or("default".to_string(), |p| p.to_string());
+        let counter = |outcome: &str| -> Counter {
+            let labels = vec![
+                ("src_vpc".to_string(), rule.src_vpcd.to_string()),
+                ("dst_vpc".to_string(), rule.dst_vpcd.to_string()),
+                ("local".to_string(), show(rule.local)),
+                ("remote".to_string(), show(rule.remote)),
+                ("outcome".to_string(), outcome.to_string()),
+            ];
+            MetricSpec::new("flow_filter_rule_hits", Unit::Count, labels)
+                .register()
+                .metric
+        };
+        Self {
+            shards: Default::default(),
+            metrics: [
+                counter("allowed"),
+                counter("filtered"),
+                counter("ambiguous"),
+            ],
+        }
+    }
+
+    fn account(&self, outcome: RuleOutcome, shard: usize) {
+        let index = match outcome {
+            RuleOutcome::Allowed => 0,
+            RuleOutcome::Filtered => 1,
+            RuleOutcome::Ambiguous => 2,
+        };
+        let shard = &self.shards[shard % HIT_SHARDS];
+        shard.hits[index].fetch_add(1, Ordering::Relaxed);
+        if shard.unexported[index].fetch_add(1, Ordering::Relaxed) + 1 >= METRICS_BATCH {
+            self.metrics[index].increment(shard.unexported[index].swap(0, Ordering::Relaxed));
+        }
+    }
+
+    /// Sum up the hits of all the shards, and add those not exported yet to the metrics
+    fn snapshot(&self) -> PeeringRuleHits {
+        let mut hits = [0; 3];
+        for shard in &self.shards {
+            for (index, total) in hits.iter_mut().enumerate() {
+                *total += shard.hits[index].load(Ordering::Relaxed);
+                let unexported = shard.unexported[index].swap(0, Ordering::Relaxed);
+                if unexported > 0 {
+                    self.metrics[index].increment(unexported);
+                }
+            }
+        }
+        PeeringRuleHits {
+            allowed: hits[0],
+            filtered: hits[1],
+            ambiguous: hits[2],
+        }
+    }
+}
+
+/// The set of [`PeeringRule`]s known to a flow filter table, along with their hit counters.
+///
+/// Counters are shared between clones, so that both copies of a left-right table account hits in
+/// the same place.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PeeringRules {
+    ids: HashMap<PeeringRule, PeeringRuleId>,
+    rules: Vec<PeeringRule>,
+    hits: Vec<Arc<RuleCounters>>,
+}
+
+impl PeeringRules {
+    /// Build the rule set from the enumerated rules. Duplicates are ignored.
+    pub(crate) fn new(rules: impl IntoIterator<Item = PeeringRule>) -> Self {
+        let mut ids = HashMap::new();
+        let mut list = Vec::new();
+        for rule in rules {
+            ids.entry(rule).or_insert_with(|| {
+                #[allow(clippy::cast_possible_truncation)] // we won't have 4 billion rules
+                let id = PeeringRuleId::with_id(list.len() as u32);
+                list.push(rule);
+                id
+            });
+        }
+        let hits = list
+            .iter()
+            .map(|rule| Arc::new(RuleCounters::new(rule)))
+            .collect();
+        Self {
+            ids,
+            rules: list,
+            hits,
+        }
+    }
+
+    /// Get the identifier of a rule, if known.
+    pub(crate) fn id(&self, rule: &PeeringRule) -> Option<PeeringRuleId> {
+        self.ids.get(rule).copied()
+    }
+
+    /// Take over the hit counters of the rules of `previous` which are also in this set, so that
+    /// the hits of a rule are not reset when the table is rebuilt.
+    pub(crate) fn carry_hits(&mut self, previous: &PeeringRules) {
+        for (rule, counters) in self.rules.iter().zip(self.hits.iter_mut()) {
+            if let Some(id) = previous.id(rule) {
+                *counters = previous.hits[id.get_id() as usize].clone();
+            }
+        }
+    }
+
+    /// Tell if `other` has the same rules, with the same identifiers.
+    pub(crate) fn same_rules(&self, other: &PeeringRules) -> bool {
+        self.rules == other.rules
+    }
+
+    /// Account a hit for a rule, in the given shard of its counters.
+    pub(crate) fn hit(&self, id: PeeringRuleId, outcome: RuleOutcome, shard: usize) {
+        if let Some(counters) = self.hits.get(id.get_id() as usize) {
+            counters.account(outcome, shard);
+        }
+    }
+
+    /// Snapshot the hit counters of all the rules, in rule id order.
+    pub(crate) fn hits(&self) -> Vec<(PeeringRule, PeeringRuleHits)> {
+        self.rules
+            .iter()
+            .zip(self.hits.iter())
+            .map(|(rule, counters)| (*rule, counters.snapshot()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use net::vxlan::Vni;
+
+    fn vpcd(vni: u32) -> VpcDiscriminant {
+        VpcDiscriminant::VNI(Vni::new_checked(vni).unwrap())
+    }
+
+    fn rule(local: Option<&str>, remote: Option<&str>) -> PeeringRule {
+        PeeringRule {
+            src_vpcd: vpcd(100),
+            dst_vpcd: vpcd(200),
+            local: local.map(Prefix::from),
+            remote: remote.map(Prefix::from),
+        }
+    }
+
+    #[test]
+    fn test_peering_rules_ids_and_hits() {
+        let first = rule(Some("10.0.0.0/24"), Some("20.0.0.0/24"));
+        let second = rule(None, Some("20.0.0.0/24"));
+        let rules = PeeringRules::new([first, second, first]);
+
+        let first_id = rules.id(&first).unwrap();
+        let second_id = rules.id(&second).unwrap();
+        assert_ne!(first_id, second_id);
+        assert!(rules.id(&rule(None, None)).is_none());
+
+        // the hits of the shards are summed up
+        rules.hit(first_id, RuleOutcome::Allowed, 0);
+        rules.hit(first_id, RuleOutcome::Allowed, 3);
+        rules.hit(first_id, RuleOutcome::Ambiguous, HIT_SHARDS + 3);
+        // clones share counters
+        rules.clone().hit(second_id, RuleOutcome::Filtered, 1);
+        let hits = |allowed, filtered, ambiguous| PeeringRuleHits {
+            allowed,
+            filtered,
+            ambiguous,
+        };
+        assert_eq!(
+            rules.hits(),
+            vec![(first, hits(2, 0, 1)), (second, hits(0, 1, 0))]
+        );
+    }
+
+    #[test]
+    fn test_peering_rules_hits_carried_over() {
+        let first = rule(Some("10.0.0.0/24"), Some("20.0.0.0/24"));
+        let second = rule(None, Some("20.0.0.0/24"));
+        let third = rule(None, None);
+        let previous = PeeringRules::new([first, second]);
+        for _ in 0..METRICS_BATCH + 1 {
+            previous.hit(previous.id(&first).unwrap(), RuleOutcome::Allowed, 2);
+        }
+        previous.hit(previous.id(&second).unwrap(), RuleOutcome::Filtered, 0);
+
+        // the hits of the rules still in the table are kept, and keep being accounted together
+        let mut rules = PeeringRules::new([third, first]);
+        rules.carry_hits(&previous);
+        rules.hit(rules.id(&first).unwrap(), RuleOutcome::Allowed, 5);
+        let allowed = |allowed| PeeringRuleHits {
+            allowed,
+            ..PeeringRuleHits::default()
+        };
+        let expected = vec![(third, allowed(0)), (first, allowed(METRICS_BATCH + 2))];
+        assert_eq!(rules.hits(), expected);
+        assert_eq!(previous.hits()[0], (first, allowed(METRICS_BATCH + 2)));
+    }
+}
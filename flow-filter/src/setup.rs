@@ -2,7 +2,9 @@
 // Copyright Open Network Fabric Authors
 
 use crate::FlowFilterTable;
-use crate::tables::{FlowFilterSubtable, NatRequirement, RemoteData, VpcdLookupResult};
+use crate::tables::{
+    FlowFilterSubtable, NatRequirement, ProtocolFilter, RemoteData, VpcdLookupResult,
+};
 use config::ConfigError;
 #[cfg(test)]
 use config::external::overlay::Overlay;
@@ -27,6 +29,7 @@ impl FlowFilterTable {
                 table.add_peering(overlay, vpc, peering)?;
             }
         }
+        table.index_rules();
         debug!("Flow filter table successfully built: {table:?}");
         Ok(table)
     }
@@ -40,6 +43,17 @@ impl FlowFilterTable {
         let local_vpcd = VpcDiscriminant::VNI(vpc.vni());
         let dst_vpcd = VpcDiscriminant::VNI(overlay.vpc_table().get_remote_vni(peering));
 
+        // Exposes may only allow traffic in one direction: local default expose is only used as a
+        // source, and remote default expose only as a destination.
+        let local_default_expose = peering
+            .local()
+            .default_expose()
+            .filter(|expose| is_source(expose));
+        let remote_default_expose = peering
+            .remote()
+            .default_expose()
+            .filter(|expose| is_destination(expose));
+
         let (local_prefixes, remote_prefixes) =
             get_prefixes_for_processing(overlay, vpc, peering, dst_vpcd, false);
 
@@ -48,8 +62,8 @@ impl FlowFilterTable {
             dst_vpcd,
             local_prefixes,
             remote_prefixes,
-            peering.local().default_expose(),
-            peering.remote().default_expose(),
+            local_default_expose,
+            remote_default_expose,
         )?;
 
         let (local_prefixes, remote_prefixes) =
@@ -60,16 +74,27 @@ impl FlowFilterTable {
             dst_vpcd,
             local_prefixes,
             remote_prefixes,
-            peering.local().default_expose(),
-            peering.remote().default_expose(),
+            local_default_expose,
+            remote_default_expose,
         )
     }
 }
 
+// Tell whether a local expose allows traffic towards the remote VPC of the peering
+fn is_source(expose: &ValidatedExpose) -> bool {
+    expose.allows_outbound()
+}
+
+// Tell whether a remote expose allows traffic coming from the local VPC of the peering
+fn is_destination(expose: &ValidatedExpose) -> bool {
+    expose.allows_inbound()
+}
+
 type PrefixWithData = (
     PrefixWithOptionalPorts,
     VpcdLookupResult,
     Option<NatRequirement>,
+    ProtocolFilter,
 );
 
 fn get_prefixes_for_processing(
@@ -87,6 +112,7 @@ fn get_prefixes_for_processing(
     let local_prefixes = get_split_prefixes_for_manifest(
         peering.local(),
         &dst_vpcd,
+        is_source,
         |expose| expose.ips(),
         overlap_trie,
         skip_ports,
@@ -97,6 +123,7 @@ fn get_prefixes_for_processing(
     let remote_prefixes = get_split_prefixes_for_manifest(
         peering.remote(),
         &dst_vpcd,
+        is_destination,
         |expose| expose.public_ips(),
         overlap_trie,
         skip_ports,
@@ -117,21 +144,26 @@ impl FlowFilterSubtable {
     ) -> Result<(), ConfigError> {
         // Handle local default expose (for all remote prefixes)
         if let Some(local_default_expose) = local_default_expose {
-            for (remote_prefix, remote_vpcd_result, remote_nat_req) in &remote_prefixes {
+            let local_protocols = ProtocolFilter::from_expose(local_default_expose);
+            for (remote_prefix, remote_vpcd_result, remote_nat_req, remote_protocols) in
+                &remote_prefixes
+            {
                 let dst_data_result = match remote_vpcd_result {
-                    VpcdLookupResult::Single(dst_data) => {
-                        VpcdLookupResult::Single(RemoteData::new(
+                    VpcdLookupResult::Single(dst_data) => VpcdLookupResult::Single(
+                        RemoteData::new(
                             dst_data.vpcd,
                             get_nat_requirement(local_default_expose),
                             *remote_nat_req,
-                        ))
-                    }
+                        )
+                        .with_protocols(local_protocols, *remote_protocols),
+                    ),
                     VpcdLookupResult::MultipleMatches(dst_data) => {
                         let data = dst_data
                             .iter()
                             .cloned()
                             .map(|mut d| {
                                 d.src_nat_req = get_nat_requirement(local_default_expose);
+                                d.src_protocols = local_protocols;
                                 d
                             })
                             .collect();
@@ -149,12 +181,15 @@ impl FlowFilterSubtable {
 
         // Handle remote default expose (for all local prefixes)
         if let Some(remote_default_expose) = remote_default_expose {
-            for (local_prefix, local_vpcd_result, local_nat_req) in &local_prefixes {
+            let remote_protocols = ProtocolFilter::from_expose(remote_default_expose);
+            for (local_prefix, local_vpcd_result, local_nat_req, local_protocols) in &local_prefixes
+            {
                 let remote_data = RemoteData::new(
                     dst_vpcd,
                     *local_nat_req,
                     get_nat_requirement(remote_default_expose),
-                );
+                )
+                .with_protocols(*local_protocols, remote_protocols);
                 let dst_data_result = match local_vpcd_result {
                     VpcdLookupResult::Single(_) => VpcdLookupResult::Single(remote_data),
                     VpcdLookupResult::MultipleMatches(_) => {
@@ -178,6 +213,10 @@ impl FlowFilterSubtable {
                 dst_vpcd,
                 get_nat_requirement(local_default_expose),
                 get_nat_requirement(remote_default_expose),
+            )
+            .with_protocols(
+                ProtocolFilter::from_expose(local_default_expose),
+                ProtocolFilter::from_expose(remote_default_expose),
             );
             self.insert_default_source_to_default_remote(
                 local_vpcd,
@@ -186,8 +225,18 @@ impl FlowFilterSubtable {
         }
 
         // Now, handle all the other, regular prefixes
-        for (local_prefix, local_vpcd_result, local_nat_req) in &local_prefixes {
-            for (remote_prefix, remote_vpcd_result, remote_nat_req) in &remote_prefixes {
+        for (local_prefix, local_vpcd_result, local_nat_req, local_protocols) in &local_prefixes {
+            for (remote_prefix, remote_vpcd_result, remote_nat_req, remote_protocols) in
+                &remote_prefixes
+            {
+                // Manifests may mix IPv4 and IPv6 prefixes, but a packet never goes from one IP
+                // version to the other: only pair prefixes of the same version
+                if !local_prefix
+                    .prefix()
+                    .matches_version(remote_prefix.prefix())
+                {
+                    continue;
+                }
                 let remote_vpcd_to_use = match (remote_vpcd_result, local_vpcd_result) {
                     (
                         VpcdLookupResult::MultipleMatches(dst_data),
@@ -199,6 +248,7 @@ impl FlowFilterSubtable {
                             .cloned()
                             .map(|mut d| {
                                 d.src_nat_req = *local_nat_req;
+                                d.src_protocols = *local_protocols;
                                 d
                             })
                             .collect();
@@ -225,6 +275,7 @@ impl FlowFilterSubtable {
                             .map(|mut d| {
                                 d.vpcd = local_dst_data.vpcd;
                                 d.src_nat_req = *local_nat_req;
+                                d.src_protocols = *local_protocols;
                                 d
                             })
                             .collect();
@@ -235,14 +286,14 @@ impl FlowFilterSubtable {
                             dst_data.vpcd,
                             *local_nat_req,
                             *remote_nat_req,
-                        )]))
+                        )
+                        .with_protocols(*local_protocols, *remote_protocols)]))
                     }
                     (VpcdLookupResult::Single(dst_data), VpcdLookupResult::Single(_)) => {
-                        VpcdLookupResult::Single(RemoteData::new(
-                            dst_data.vpcd,
-                            *local_nat_req,
-                            *remote_nat_req,
-                        ))
+                        VpcdLookupResult::Single(
+                            RemoteData::new(dst_data.vpcd, *local_nat_req, *remote_nat_req)
+                                .with_protocols(*local_protocols, *remote_protocols),
+                        )
                     }
                 };
 
@@ -286,6 +337,7 @@ fn get_manifests_overlap(
             other_peering.local(),
             dst_vpcd,
             other_dst_vpcd,
+            is_source,
             |expose| expose.ips(),
             compare_to_self,
             skip_ports,
@@ -296,6 +348,7 @@ fn get_manifests_overlap(
             other_peering.remote(),
             dst_vpcd,
             other_dst_vpcd,
+            is_destination,
             |expose| expose.public_ips(),
             compare_to_self,
             skip_ports,
@@ -329,12 +382,15 @@ where
 // - second manifest exposes 1.0.0.0/23, 2.0.0.0/24, and 3.0.0.0/8
 // - the function returns [1.0.0.0/24, 2.0.0.128/25]
 //
-// Exclude the "default"-destination expose blocks from overlap calculation.
+// Exclude the "default"-destination expose blocks from overlap calculation, as well as the expose
+// blocks that do not allow traffic in the direction under consideration.
+#[allow(clippy::too_many_arguments)]
 fn get_manifest_ips_overlap(
     manifest_left: &ValidatedManifest,
     manifest_right: &ValidatedManifest,
     dst_vpcd_left: VpcDiscriminant,
     dst_vpcd_right: VpcDiscriminant,
+    use_expose: fn(&ValidatedExpose) -> bool,
     get_ips: fn(&ValidatedExpose) -> &BTreeSet<PrefixWithOptionalPorts>,
     compare_to_self: bool,
     skip_ports: bool,
@@ -343,12 +399,12 @@ fn get_manifest_ips_overlap(
     for expose_left in manifest_left
         .valexp()
         .iter()
-        .filter(|expose| !expose.is_default())
+        .filter(|expose| !expose.is_default() && use_expose(expose))
     {
         for expose_right in manifest_right
             .valexp()
             .iter()
-            .filter(|expose| !expose.is_default())
+            .filter(|expose| !expose.is_default() && use_expose(expose))
         {
             if compare_to_self && expose_left == expose_right {
                 // We're comparing the expose to itself: skip
@@ -369,11 +425,19 @@ fn get_manifest_ips_overlap(
                             dst_vpcd_left,
                             None, // Unknown at this stage
                             get_nat_requirement(expose_left),
+                        )
+                        .with_protocols(
+                            ProtocolFilter::default(), // Unknown at this stage
+                            ProtocolFilter::from_expose(expose_left),
                         );
                         let remote_data_2 = RemoteData::new(
                             dst_vpcd_right,
                             None, // Unknown at this stage
                             get_nat_requirement(expose_right),
+                        )
+                        .with_protocols(
+                            ProtocolFilter::default(), // Unknown at this stage
+                            ProtocolFilter::from_expose(expose_right),
                         );
                         if let Some(entry) = overlap.get_mut(&intersection) {
                             entry.insert(remote_data_1);
@@ -437,13 +501,15 @@ fn consolidate_overlap_list(
 fn get_split_prefixes_for_manifest(
     manifest: &ValidatedManifest,
     vpcd: &VpcDiscriminant,
+    use_expose: fn(&ValidatedExpose) -> bool,
     get_ips: fn(&ValidatedExpose) -> &PrefixPortsSet,
     overlaps: BTreeMap<PrefixWithOptionalPorts, HashSet<RemoteData>>,
     skip_ports: bool,
 ) -> Vec<PrefixWithData> {
     let mut prefixes_with_vpcd = Vec::new();
-    for expose in manifest.valexp() {
+    for expose in manifest.valexp().iter().filter(|expose| use_expose(expose)) {
         let nat_req = get_nat_requirement(expose);
+        let protocols = ProtocolFilter::from_expose(expose);
         for prefix in get_ips(expose) {
             if skip_ports && prefix.ports().is_some() {
                 continue;
@@ -472,6 +538,7 @@ fn get_split_prefixes_for_manifest(
                             fragment,
                             VpcdLookupResult::MultipleMatches(overlap_data.clone()),
                             nat_req,
+                            protocols,
                         ));
                     }
                 }
@@ -481,6 +548,7 @@ fn get_split_prefixes_for_manifest(
                         fragment,
                         VpcdLookupResult::Single(RemoteData::new(*vpcd, None, None)),
                         nat_req,
+                        protocols,
                     ));
                 }
             }
@@ -694,6 +762,7 @@ mod tests {
             &manifest2,
             vpcd1,
             vpcd2,
+            is_source,
             |expose| expose.ips(),
             false,
             false,
@@ -727,6 +796,7 @@ mod tests {
             &manifest2,
             vpcd1,
             vpcd2,
+            is_source,
             |expose| expose.ips(),
             false,
             false,
@@ -769,6 +839,7 @@ mod tests {
             &manifest2,
             vpcd1,
             vpcd2,
+            is_source,
             |expose| expose.ips(),
             false,
             false,
@@ -824,6 +895,7 @@ mod tests {
             &manifest2,
             vpcd1,
             vpcd2,
+            is_source,
             |expose| expose.ips(),
             false,
             false,
@@ -917,6 +989,7 @@ mod tests {
             &manifest,
             vpcd,
             vpcd,
+            is_destination,
             |expose| expose.public_ips(),
             true,
             false,
@@ -1078,6 +1151,7 @@ mod tests {
         let result = get_split_prefixes_for_manifest(
             &manifest,
             &vpcd,
+            is_source,
             |expose| expose.ips(),
             overlaps,
             false,
@@ -1116,11 +1190,12 @@ mod tests {
         let mut result = get_split_prefixes_for_manifest(
             &manifest,
             &vpcd,
+            is_source,
             |expose| expose.ips(),
             overlaps,
             false,
         );
-        result.sort_by_key(|(prefix, _, _)| *prefix);
+        result.sort_by_key(|(prefix, _, _, _)| *prefix);
 
         // Should split into multiple prefixes
         assert_eq!(result.len(), 2);
@@ -1180,11 +1255,12 @@ mod tests {
         let mut result = get_split_prefixes_for_manifest(
             &manifest,
             &vpcd_a,
+            is_source,
             |expose| expose.ips(),
             overlaps,
             false,
         );
-        result.sort_by_key(|(prefix, _, _)| *prefix);
+        result.sort_by_key(|(prefix, _, _, _)| *prefix);
 
         assert_eq!(result.len(), 2, "expected two fragments after splitting");
 
@@ -0,0 +1,168 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Evaluation of synthetic flows against the [`FlowFilterTable`], without a packet.
+//!
+//! This is used to answer "what-if" questions from operators. Unlike packet processing, the
+//! evaluation has no side effects: peering rule hit counters are left untouched.
+
+use crate::origin::PeeringRule;
+use crate::tables::{
+    FlowFilterTable, NatRequirement, PacketProtocol, RemoteData, VpcdLookupResult,
+};
+use lpm::prefix::L4Protocol;
+use net::packet::VpcDiscriminant;
+use std::fmt::Display;
+use std::net::IpAddr;
+
+/// The NAT operations that the flow filter requires for an allowed flow.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct NatRequirements {
+    pub static_src: bool,
+    pub static_dst: bool,
+    pub masquerade: bool,
+    pub port_forwarding: bool,
+}
+
+impl NatRequirements {
+    fn new(data: &RemoteData, proto: L4Protocol) -> Self {
+        Self {
+            static_src: data.requires_static_nat_src(),
+            static_dst: data.requires_static_nat_dst(),
+            masquerade: data.requires_masquerade(),
+            port_forwarding: data.requires_port_forwarding(proto),
+        }
+    }
+}
+
+impl Display for NatRequirements {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let required: Vec<_> = [
+            (self.static_src, "static-src"),
+            (self.static_dst, "static-dst"),
+            (self.masquerade, "masquerade"),
+            (self.port_forwarding, "port-forwarding"),
+        ]
+        .into_iter()
+        .filter_map(|(required, name)| required.then_some(name))
+        .collect();
+        if required.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", required.join(", "))
+        }
+    }
+}
+
+/// The fate of a flow according to the flow filter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FlowVerdict {
+    /// The flow is allowed towards `dst_vpcd`.
+    Allowed {
+        dst_vpcd: VpcDiscriminant,
+        rule: Option<PeeringRule>,
+        nat: NatRequirements,
+    },
+    /// Several peerings match the flow. For actual packets, the destination VPC may be determined
+    /// from an existing flow table entry.
+    Ambiguous(Vec<VpcDiscriminant>),
+    /// The flow requires NAT operations which cannot be set up by a new flow.
+    Unsupported { dst_vpcd: VpcDiscriminant },
+    /// The exposes matching the flow do not allow its protocol.
+    ProtocolDenied { dst_vpcd: VpcDiscriminant },
+    /// No peering allows the flow.
+    Denied,
+}
+
+impl Display for FlowVerdict {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FlowVerdict::Allowed {
+                dst_vpcd,
+                rule,
+                nat,
+            } => {
+                write!(f, "allowed to VPC {dst_vpcd}")?;
+                if let Some(rule) = rule {
+                    write!(f, " by rule {rule}")?;
+                }
+                write!(f, ", NAT: {nat}")
+            }
+            FlowVerdict::Ambiguous(candidates) => {
+                let candidates: Vec<_> = candidates.iter().map(ToString::to_string).collect();
+                write!(
+                    f,
+                    "ambiguous destination VPC ({}), allowed only for existing flows",
+                    candidates.join(", ")
+                )
+            }
+            FlowVerdict::Unsupported { dst_vpcd } => write!(
+                f,
+                "denied: NAT towards VPC {dst_vpcd} can only be used by existing flows"
+            ),
+            FlowVerdict::ProtocolDenied { dst_vpcd } => write!(
+                f,
+                "denied: protocol not allowed by the exposes towards VPC {dst_vpcd}"
+            ),
+            FlowVerdict::Denied => write!(f, "denied: no matching peering"),
+        }
+    }
+}
+
+impl FlowFilterTable {
+    /// Evaluate a new flow (i.e. one with no flow table entry) against the table.
+    ///
+    /// The protocols that exposes may restrict their traffic to are only checked for TCP and UDP
+    /// flows, other flows being evaluated as if all protocols were allowed.
+    #[must_use]
+    pub fn evaluate(
+        &self,
+        src_vpcd: VpcDiscriminant,
+        src_addr: &IpAddr,
+        dst_addr: &IpAddr,
+        proto: L4Protocol,
+        ports: Option<(u16, u16)>,
+    ) -> FlowVerdict {
+        let Some((result, matched)) = self.lookup_with_origin(src_vpcd, src_addr, dst_addr, ports)
+        else {
+            return FlowVerdict::Denied;
+        };
+        let data = match result {
+            VpcdLookupResult::Single(data) => data,
+            VpcdLookupResult::MultipleMatches(set) => {
+                let mut candidates: Vec<_> = set.iter().map(|data| data.vpcd).collect();
+                candidates.sort();
+                candidates.dedup();
+                return FlowVerdict::Ambiguous(candidates);
+            }
+        };
+        let packet_proto = match proto {
+            L4Protocol::Tcp => Some(PacketProtocol::Tcp),
+            L4Protocol::Udp => Some(PacketProtocol::Udp),
+            L4Protocol::Any => None,
+        };
+        if packet_proto.is_some_and(|p| !data.allows(p)) {
+            return FlowVerdict::ProtocolDenied {
+                dst_vpcd: data.vpcd,
+            };
+        }
+        if matches!(data.dst_nat_req, Some(NatRequirement::Masquerade))
+            || matches!(data.src_nat_req, Some(NatRequirement::PortForwarding(_)))
+        {
+            return FlowVerdict::Unsupported {
+                dst_vpcd: data.vpcd,
+            };
+        }
+        let rule = PeeringRule {
+            src_vpcd,
+            dst_vpcd: data.vpcd,
+            local: matched.local,
+            remote: matched.remote,
+        };
+        FlowVerdict::Allowed {
+            dst_vpcd: data.vpcd,
+            rule: self.rules.id(&rule).map(|_| rule),
+            nat: NatRequirements::new(&data, proto),
+        }
+    }
+}
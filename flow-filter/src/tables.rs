@@ -3,12 +3,16 @@
 
 //! A module implementing a structure to back the flow filter lookups.
 
+use crate::origin::{PeeringRule, PeeringRuleHits, PeeringRules, RuleOutcome};
+use common::generation::{Generational, TableGeneration};
 use config::ConfigError;
-use config::external::overlay::vpcpeering::{VpcExposeNat, VpcExposeNatConfig};
+use config::external::overlay::vpcpeering::{
+    ExposeProtocol, MAX_EXPOSE_PROTOCOLS, ValidatedExpose, VpcExposeNat, VpcExposeNatConfig,
+};
 use lpm::prefix::range_map::DisjointRangesBTreeMap;
 use lpm::prefix::{L4Protocol, PortRange, Prefix};
 use lpm::trie::{IpPortPrefixTrie, ValueWithAssociatedRanges};
-use net::packet::VpcDiscriminant;
+use net::packet::{PeeringRuleId, VpcDiscriminant};
 use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::net::IpAddr;
@@ -58,6 +62,25 @@ pub(crate) enum VpcdLookupResult {
 pub struct FlowFilterTable {
     pub(crate) with_ports: FlowFilterSubtable,
     pub(crate) no_ports: FlowFilterSubtable,
+    pub(crate) rules: PeeringRules,
+    generation: TableGeneration,
+}
+
+/// The exposed prefixes which matched the addresses of a packet during a lookup.
+/// `None` indicates that a default expose matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct MatchedPrefixes {
+    pub(crate) local: Option<Prefix>,
+    pub(crate) remote: Option<Prefix>,
+}
+
+impl Generational for FlowFilterTable {
+    fn generation(&self) -> TableGeneration {
+        self.generation
+    }
+    fn set_generation(&mut self, generation: TableGeneration) {
+        self.generation = generation;
+    }
 }
 
 impl FlowFilterTable {
@@ -66,7 +89,50 @@ impl FlowFilterTable {
         Self {
             with_ports: FlowFilterSubtable::new(), // For TCP, UDP
             no_ports: FlowFilterSubtable::new(),   // For ICMP
+            rules: PeeringRules::default(),
+            generation: TableGeneration::INITIAL,
+        }
+    }
+
+    /// Enumerate all the peering rules in the table and assign them identifiers.
+    /// This must be called once the table is fully built.
+    pub(crate) fn index_rules(&mut self) {
+        let mut rules = Vec::new();
+        for subtable in [&self.with_ports, &self.no_ports] {
+            for (src_vpcd, table) in &subtable.0 {
+                table.enumerate_rules(*src_vpcd, &mut rules);
+            }
         }
+        rules.sort();
+        self.rules = PeeringRules::new(rules);
+    }
+
+    /// Get the hit counters of all the peering rules in the table, in rule identifier order.
+    ///
+    /// Rules with no hits are reported too, which allows identifying unused or overly broad rules.
+    #[must_use]
+    pub fn peering_rule_hits(&self) -> Vec<(PeeringRule, PeeringRuleHits)> {
+        self.rules.hits()
+    }
+
+    /// Identify the peering rule that a packet matched and account a hit for it, with the outcome
+    /// of the packet, in the given shard of the counters of the rule.
+    pub(crate) fn record_rule_hit(
+        &self,
+        src_vpcd: VpcDiscriminant,
+        dst_vpcd: VpcDiscriminant,
+        matched: MatchedPrefixes,
+        outcome: RuleOutcome,
+        shard: usize,
+    ) -> Option<PeeringRuleId> {
+        let id = self.rules.id(&PeeringRule {
+            src_vpcd,
+            dst_vpcd,
+            local: matched.local,
+            remote: matched.remote,
+        })?;
+        self.rules.hit(id, outcome, shard);
+        Some(id)
     }
 
     fn get_map_for_lookup(&self, ports: Option<(u16, u16)>) -> &FlowFilterSubtable {
@@ -77,6 +143,7 @@ impl FlowFilterTable {
         }
     }
 
+    #[cfg(test)]
     pub(crate) fn lookup(
         &self,
         src_vpcd: VpcDiscriminant,
@@ -84,6 +151,26 @@ impl FlowFilterTable {
         dst_addr: &IpAddr,
         ports: Option<(u16, u16)>,
     ) -> Option<VpcdLookupResult> {
+        self.lookup_with_origin(src_vpcd, src_addr, dst_addr, ports)
+            .map(|(result, _)| result)
+    }
+
+    /// Look up the destination VPC information for a packet, along with the exposed prefixes
+    /// which matched its addresses.
+    pub(crate) fn lookup_with_origin(
+        &self,
+        src_vpcd: VpcDiscriminant,
+        src_addr: &IpAddr,
+        dst_addr: &IpAddr,
+        ports: Option<(u16, u16)>,
+    ) -> Option<(VpcdLookupResult, MatchedPrefixes)> {
+        // Default exposes match addresses of both IP versions, but no peering allows translating
+        // from one version to the other
+        if src_addr.is_ipv4() != dst_addr.is_ipv4() {
+            debug!("Mismatched IP versions for src:{src_addr}, dst:{dst_addr}");
+            return None;
+        }
+
         // Get the table related to the source VPC for the packet
         let Some(table) = self.get_map_for_lookup(ports).get(&src_vpcd) else {
             debug!("Could not find connections table for VPC {src_vpcd}");
@@ -93,7 +180,7 @@ impl FlowFilterTable {
         let (src_port, dst_port) = ports.unzip();
         // Look for valid connections information in the table that matches the source address and port.
         // If nothing matches, use the default source entry, if any.
-        let Some(src_connection_data) = table.lookup(src_addr, src_port) else {
+        let Some((local, src_connection_data)) = table.lookup(src_addr, src_port) else {
             debug!("Could not find src connection data for src:{src_addr}, src_port:{src_port:?}");
             return None;
         };
@@ -110,7 +197,8 @@ impl FlowFilterTable {
 
         // We have a dst_connection_data object for our source VPC, IP, port. From this object, we
         // need to retrieve the prefix information associated to our destination IP and port.
-        let Some(remote_prefix_data) = dst_connection_data.lookup(dst_addr, dst_port) else {
+        let Some((remote, remote_prefix_data)) = dst_connection_data.lookup(dst_addr, dst_port)
+        else {
             debug!("Could not find remote prefix data for dst:{dst_addr}, dst_port:{dst_port:?}");
             return None;
         };
@@ -119,7 +207,10 @@ impl FlowFilterTable {
         // We have a remote_prefix_data object for our destination address, and the port ranges
         // associated to this IP: we may need to find the right item for this entry based on the
         // destination port
-        remote_prefix_data.get(dst_port).cloned()
+        remote_prefix_data
+            .get(dst_port)
+            .cloned()
+            .map(|result| (result, MatchedPrefixes { local, remote }))
     }
 
     #[cfg(test)]
@@ -228,7 +319,7 @@ impl FlowFilterSubtable {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) struct VpcConnectionsTable {
     pub(crate) trie: IpPortPrefixTrie<SrcConnectionData>,
     pub(crate) default_source: Option<SrcConnectionData>,
@@ -242,9 +333,28 @@ impl VpcConnectionsTable {
         }
     }
 
-    fn lookup(&self, addr: &IpAddr, port: Option<u16>) -> Option<&SrcConnectionData> {
-        let (_, data) = self.trie.lookup(addr, port).unzip();
-        data.or(self.default_source.as_ref())
+    fn lookup(
+        &self,
+        addr: &IpAddr,
+        port: Option<u16>,
+    ) -> Option<(Option<Prefix>, &SrcConnectionData)> {
+        match self.trie.lookup(addr, port) {
+            Some((prefix, data)) => Some((Some(prefix), data)),
+            None => self.default_source.as_ref().map(|data| (None, data)),
+        }
+    }
+
+    fn enumerate_rules(&self, src_vpcd: VpcDiscriminant, rules: &mut Vec<PeeringRule>) {
+        let sources = self
+            .trie
+            .iter()
+            .map(|(prefix, data)| (Some(prefix), data))
+            .chain(self.default_source.iter().map(|data| (None, data)));
+        for (local, src_data) in sources {
+            for dst_data in src_data.values() {
+                dst_data.enumerate_rules(src_vpcd, local, rules);
+            }
+        }
     }
 
     #[cfg(test)]
@@ -340,7 +450,7 @@ impl VpcConnectionsTable {
 ///
 /// When no port range is specified, the value applies to all ports (`AllPorts`).
 /// Otherwise, one or more port ranges are mapped to their respective values (`Ranges`).
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) enum PortRangeMap<T> {
     AllPorts(T),
     Ranges(DisjointRangesBTreeMap<PortRange, T>),
@@ -357,6 +467,14 @@ impl<T> PortRangeMap<T> {
         }
     }
 
+    /// Returns an iterator over all the values in the map.
+    fn values(&self) -> Box<dyn Iterator<Item = &T> + '_> {
+        match self {
+            PortRangeMap::AllPorts(value) => Box::new(std::iter::once(value)),
+            PortRangeMap::Ranges(ranges) => Box::new(ranges.iter().map(|(_, value)| value)),
+        }
+    }
+
     /// Returns a reference to the value for the given port, if any.
     fn get(&self, port: Option<u16>) -> Option<&T> {
         match self {
@@ -479,11 +597,84 @@ impl NatRequirement {
     }
 }
 
+/// The protocol of a packet, as matched against the protocols allowed by exposes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PacketProtocol {
+    Tcp,
+    Udp,
+    /// ICMP or ICMPv6, with the type and code of the message
+    Icmp {
+        icmp_type: u8,
+        code: u8,
+    },
+    /// Any other IP protocol, by number
+    Other(u8),
+}
+
+/// The protocols allowed by an expose, `None` meaning all of them.
+///
+/// Exposes allow a handful of protocols at most, so we keep them inline rather than on the heap,
+/// for [`RemoteData`] to remain cheap to copy.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub(crate) struct ProtocolFilter(Option<[Option<ExposeProtocol>; MAX_EXPOSE_PROTOCOLS]>);
+
+impl ProtocolFilter {
+    pub(crate) fn new<'a>(protocols: impl ExactSizeIterator<Item = &'a ExposeProtocol>) -> Self {
+        if protocols.len() == 0 {
+            return Self(None);
+        }
+        // Validation bounds the number of protocols of an expose
+        debug_assert!(protocols.len() <= MAX_EXPOSE_PROTOCOLS);
+        let mut allowed = [None; MAX_EXPOSE_PROTOCOLS];
+        for (slot, protocol) in allowed.iter_mut().zip(protocols) {
+            *slot = Some(*protocol);
+        }
+        Self(Some(allowed))
+    }
+
+    pub(crate) fn from_expose(expose: &ValidatedExpose) -> Self {
+        Self::new(expose.protocols().iter())
+    }
+
+    pub(crate) fn allows(&self, packet_proto: PacketProtocol) -> bool {
+        let Some(allowed) = &self.0 else {
+            return true;
+        };
+        allowed
+            .iter()
+            .flatten()
+            .any(|protocol| match (*protocol, packet_proto) {
+                (ExposeProtocol::Tcp, PacketProtocol::Tcp)
+                | (ExposeProtocol::Udp, PacketProtocol::Udp) => true,
+                (
+                    ExposeProtocol::Icmp { icmp_type, code },
+                    PacketProtocol::Icmp {
+                        icmp_type: packet_type,
+                        code: packet_code,
+                    },
+                ) => {
+                    icmp_type.is_none_or(|t| t == packet_type)
+                        && code.is_none_or(|c| c == packet_code)
+                }
+                (ExposeProtocol::Number(number), PacketProtocol::Other(packet_number)) => {
+                    number == packet_number
+                }
+                _ => false,
+            })
+    }
+
+    pub(crate) fn protocols(&self) -> impl Iterator<Item = &ExposeProtocol> {
+        self.0.iter().flatten().flatten()
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub(crate) struct RemoteData {
     pub(crate) vpcd: VpcDiscriminant,
     pub(crate) src_nat_req: Option<NatRequirement>,
     pub(crate) dst_nat_req: Option<NatRequirement>,
+    pub(crate) src_protocols: ProtocolFilter,
+    pub(crate) dst_protocols: ProtocolFilter,
 }
 
 impl RemoteData {
@@ -496,9 +687,28 @@ impl RemoteData {
             vpcd,
             src_nat_req,
             dst_nat_req,
+            src_protocols: ProtocolFilter::default(),
+            dst_protocols: ProtocolFilter::default(),
         }
     }
 
+    /// Restrict the traffic to the protocols allowed by both the source and destination exposes.
+    #[must_use]
+    pub(crate) fn with_protocols(
+        mut self,
+        src_protocols: ProtocolFilter,
+        dst_protocols: ProtocolFilter,
+    ) -> Self {
+        self.src_protocols = src_protocols;
+        self.dst_protocols = dst_protocols;
+        self
+    }
+
+    // Determines whether the source and destination exposes both allow the protocol of a packet.
+    pub(crate) fn allows(&self, packet_proto: PacketProtocol) -> bool {
+        self.src_protocols.allows(packet_proto) && self.dst_protocols.allows(packet_proto)
+    }
+
     pub(crate) fn requires_masquerade(&self) -> bool {
         self.src_nat_req == Some(NatRequirement::Masquerade)
             || self.dst_nat_req == Some(NatRequirement::Masquerade)
@@ -536,7 +746,7 @@ impl RemoteData {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub(crate) struct DstConnectionData {
     pub(crate) trie: IpPortPrefixTrie<RemotePortRangesData>,
     pub(crate) default_remote_data: Option<RemotePortRangesData>,
@@ -557,11 +767,42 @@ impl DstConnectionData {
         }
     }
 
-    fn lookup(&self, addr: &IpAddr, port: Option<u16>) -> Option<&RemotePortRangesData> {
-        self.trie
-            .lookup(addr, port)
-            .map(|(_, data)| data)
-            .or(self.default_remote_data.as_ref())
+    fn lookup(
+        &self,
+        addr: &IpAddr,
+        port: Option<u16>,
+    ) -> Option<(Option<Prefix>, &RemotePortRangesData)> {
+        match self.trie.lookup(addr, port) {
+            Some((prefix, data)) => Some((Some(prefix), data)),
+            None => self.default_remote_data.as_ref().map(|data| (None, data)),
+        }
+    }
+
+    fn enumerate_rules(
+        &self,
+        src_vpcd: VpcDiscriminant,
+        local: Option<Prefix>,
+        rules: &mut Vec<PeeringRule>,
+    ) {
+        let remotes = self
+            .trie
+            .iter()
+            .map(|(prefix, data)| (Some(prefix), data))
+            .chain(self.default_remote_data.iter().map(|data| (None, data)));
+        for (remote, remote_data) in remotes {
+            for result in remote_data.values() {
+                let dst_vpcds: Vec<_> = match result {
+                    VpcdLookupResult::Single(data) => vec![data.vpcd],
+                    VpcdLookupResult::MultipleMatches(set) => set.iter().map(|d| d.vpcd).collect(),
+                };
+                rules.extend(dst_vpcds.into_iter().map(|dst_vpcd| PeeringRule {
+                    src_vpcd,
+                    dst_vpcd,
+                    local,
+                    remote,
+                }));
+            }
+        }
     }
 
     // Update the remote data for a given prefix and port range.
@@ -3,12 +3,16 @@
 
 use crate::tables::NatRequirement;
 use crate::{
-    FlowFilter, FlowFilterTable, FlowFilterTableWriter, FlowTuple, RemoteData, VpcdLookupResult,
+    FlowFilter, FlowFilterTable, FlowFilterTableWriter, FlowTuple, FlowVerdict, NatRequirements,
+    PeeringRule, PeeringRuleHits, RemoteData, VpcdLookupResult,
 };
+use common::changelog::Seqno;
 use config::ConfigError;
 use config::external::overlay::Overlay;
 use config::external::overlay::vpc::{Vpc, VpcTable};
-use config::external::overlay::vpcpeering::{VpcExpose, VpcManifest, VpcPeering, VpcPeeringTable};
+use config::external::overlay::vpcpeering::{
+    ExposeDirection, ExposeProtocol, VpcExpose, VpcManifest, VpcPeering, VpcPeeringTable,
+};
 use lpm::prefix::{L4Protocol, PortRange, Prefix, PrefixWithOptionalPorts};
 use net::FlowKey;
 use net::buffer::{PacketBufferMut, TestBuffer};
@@ -250,6 +254,51 @@ fn test_flow_filter_packet_allowed() {
     assert_eq!(packet_out.meta().dst_vpcd, Some(dst_data.vpcd));
 }
 
+#[test]
+fn test_flow_filter_table_change_feed() {
+    let table = |dst: &str| {
+        let mut table = FlowFilterTable::new();
+        for (src, dst) in [(100, "20.0.0.0/24"), (300, dst)] {
+            table
+                .insert(
+                    vpcd(src),
+                    VpcdLookupResult::Single(RemoteData::new(vpcd(200), None, None)),
+                    Prefix::from("10.0.0.0/24"),
+                    None,
+                    Prefix::from(dst),
+                    None,
+                )
+                .unwrap();
+        }
+        table.index_rules();
+        table
+    };
+
+    let mut writer = FlowFilterTableWriter::new();
+    let feed = writer.change_feed();
+    writer.update_flow_filter_table(table("30.0.0.0/24"));
+    let mut checkpoint = feed.checkpoint().unwrap();
+    assert_eq!(checkpoint.seqno, Seqno::new(1));
+
+    // only the entries of the VPC whose peering changed, with and without ports, and the rules
+    // are published
+    writer.update_flow_filter_table(table("40.0.0.0/24"));
+    let publications = feed.changes_since(checkpoint.seqno).unwrap();
+    assert_eq!(publications.len(), 1);
+    assert_eq!(publications[0].1.len(), 3);
+
+    // the same table again publishes no change
+    writer.update_flow_filter_table(table("40.0.0.0/24"));
+    assert!(feed.changes_since(Seqno::new(2)).unwrap()[0].1.is_empty());
+
+    assert_eq!(feed.catch_up(&mut checkpoint), Ok(2));
+    let published = writer.get_reader();
+    let published = published.enter().unwrap();
+    assert!(checkpoint.table.with_ports.0 == published.with_ports.0);
+    assert!(checkpoint.table.no_ports.0 == published.no_ports.0);
+    assert!(checkpoint.table.rules.same_rules(&published.rules));
+}
+
 #[test]
 fn test_flow_filter_packet_filtered() {
     // Setup table
@@ -363,6 +412,8 @@ fn test_flow_filter_multiple_matches_no_dst_vpcd() {
             None,
         )
         .unwrap();
+    table.index_rules();
+    let stats = table.clone();
 
     let mut writer = FlowFilterTableWriter::new();
     writer.update_flow_filter_table(table);
@@ -381,6 +432,15 @@ fn test_flow_filter_multiple_matches_no_dst_vpcd() {
     // Without table flow lookup we can't find the right dst_vpcd, so we should drop the packet
     assert!(packet_out.is_done());
     assert!(packet_out.meta().dst_vpcd.is_none());
+
+    // The drop is accounted to both rules
+    let hits = stats.peering_rule_hits();
+    assert_eq!(hits.len(), 2);
+    assert!(hits.iter().all(|(_, hits)| *hits
+        == PeeringRuleHits {
+            ambiguous: 1,
+            ..PeeringRuleHits::default()
+        }));
 }
 
 #[test]
@@ -733,6 +793,148 @@ fn test_flow_filter_table_from_overlay() {
     assert_eq!(packet_out.meta().dst_vpcd, None);
 }
 
+#[test]
+fn test_flow_filter_peering_rule_hits() {
+    let mut vpc_table = VpcTable::new();
+    vpc_table
+        .add(Vpc::new("vpc1", "VPC01", 100).unwrap())
+        .unwrap();
+    vpc_table
+        .add(Vpc::new("vpc2", "VPC02", 200).unwrap())
+        .unwrap();
+
+    let mut peering_table = VpcPeeringTable::new();
+    peering_table
+        .add(VpcPeering::with_default_group(
+            "vpc1-to-vpc2",
+            VpcManifest::with_exposes("vpc1", vec![VpcExpose::empty().ip("1.0.0.0/24".into())]),
+            VpcManifest::with_exposes(
+                "vpc2",
+                vec![
+                    VpcExpose::empty().ip("5.0.0.0/24".into()),
+                    VpcExpose::empty().set_default(),
+                ],
+            ),
+        ))
+        .unwrap();
+
+    let overlay = Overlay::new(vpc_table, peering_table).validate().unwrap();
+    let table = FlowFilterTable::build_from_overlay(&overlay).unwrap();
+    // Clones share the hit counters
+    let stats = table.clone();
+    let mut writer = FlowFilterTableWriter::new();
+    writer.update_flow_filter_table(table);
+    let mut flow_filter = FlowFilter::new("test-filter", writer.get_reader());
+
+    let rule_to_prefix = PeeringRule {
+        src_vpcd: vpcd(100),
+        dst_vpcd: vpcd(200),
+        local: Some("1.0.0.0/24".into()),
+        remote: Some("5.0.0.0/24".into()),
+    };
+    let rule_to_default = PeeringRule {
+        remote: None,
+        ..rule_to_prefix
+    };
+
+    let allowed = |allowed| PeeringRuleHits {
+        allowed,
+        ..PeeringRuleHits::default()
+    };
+
+    // All the rules are known, even before they get hit
+    let hits = stats.peering_rule_hits();
+    assert!(hits.contains(&(rule_to_prefix, allowed(0))));
+    assert!(hits.contains(&(rule_to_default, allowed(0))));
+
+    let packets = [
+        ("1.0.0.5", "5.0.0.10"),
+        ("1.0.0.6", "5.0.0.11"),
+        ("1.0.0.7", "17.34.51.68"),
+    ]
+    .map(|(src, dst)| {
+        create_test_packet(Some(vpcd(100)), src.parse().unwrap(), dst.parse().unwrap())
+    });
+    let packets_out: Vec<_> = flow_filter.process(packets.into_iter()).collect();
+    assert!(packets_out.iter().all(|p| !p.is_done()));
+    assert_eq!(
+        packets_out[0].meta().peering_rule,
+        packets_out[1].meta().peering_rule
+    );
+    assert_ne!(
+        packets_out[0].meta().peering_rule,
+        packets_out[2].meta().peering_rule
+    );
+
+    let hits = stats.peering_rule_hits();
+    assert!(hits.contains(&(rule_to_prefix, allowed(2))));
+    assert!(hits.contains(&(rule_to_default, allowed(1))));
+
+    // Filtered packets are not tagged
+    let packet = create_test_packet(
+        Some(vpcd(100)),
+        "2.0.0.1".parse().unwrap(),
+        "5.0.0.10".parse().unwrap(),
+    );
+    let packet_out = flow_filter.process([packet].into_iter()).next().unwrap();
+    assert!(packet_out.is_done());
+    assert_eq!(packet_out.meta().peering_rule, None);
+}
+
+#[test]
+fn test_flow_filter_evaluate() {
+    let mut table = FlowFilterTable::new();
+    let src_vpcd = vpcd(100);
+    table
+        .insert(
+            src_vpcd,
+            VpcdLookupResult::Single(RemoteData::new(vpcd(200), None, None)),
+            Prefix::from("10.0.0.0/24"),
+            None,
+            Prefix::from("20.0.0.0/24"),
+            None,
+        )
+        .unwrap();
+    table.index_rules();
+    let src = "10.0.0.5".parse().unwrap();
+
+    let verdict = table.evaluate(
+        src_vpcd,
+        &src,
+        &"20.0.0.10".parse().unwrap(),
+        L4Protocol::Tcp,
+        Some((1024, 443)),
+    );
+    let FlowVerdict::Allowed {
+        dst_vpcd,
+        rule,
+        nat,
+    } = verdict
+    else {
+        panic!("unexpected verdict {verdict}");
+    };
+    assert_eq!(dst_vpcd, vpcd(200));
+    assert_eq!(rule.unwrap().remote, Some(Prefix::from("20.0.0.0/24")));
+    assert_eq!(nat, NatRequirements::default());
+
+    let verdict = table.evaluate(
+        src_vpcd,
+        &src,
+        &"30.0.0.10".parse().unwrap(),
+        L4Protocol::Tcp,
+        Some((1024, 443)),
+    );
+    assert_eq!(verdict, FlowVerdict::Denied);
+
+    // Evaluation does not account rule hits
+    assert!(
+        table
+            .peering_rule_hits()
+            .iter()
+            .all(|(_, hits)| *hits == PeeringRuleHits::default())
+    );
+}
+
 #[cfg_attr(not(emulated), traced_test)]
 #[test]
 fn test_flow_filter_table_check_send_from_default() {
@@ -888,6 +1090,7 @@ fn test_flow_filter_table_check_nat_requirements() {
     let overlay = unsafe { overlay.fake_validated_overlay_for_tests() };
 
     let table = FlowFilterTable::build_from_overlay(&overlay).unwrap();
+    let stats = table.clone();
     let mut writer = FlowFilterTableWriter::new();
     writer.update_flow_filter_table(table);
     let mut flow_filter = FlowFilter::new("test-filter", writer.get_reader());
@@ -937,6 +1140,18 @@ fn test_flow_filter_table_check_nat_requirements() {
     // These are invalid NAT requirements because we cannot currently initiate a connection towards
     // an expose using masquerading, and here there is no flow info attached to packet.
     assert_eq!(packet_out.get_done(), Some(DoneReason::Filtered));
+    // The drop is accounted to the rule the packet matched
+    let rule = PeeringRule {
+        src_vpcd: vpcd(vni1.into()),
+        dst_vpcd: vpcd(vni2.into()),
+        local: Some("1.0.0.0/24".into()),
+        remote: Some("70.0.0.0/24".into()),
+    };
+    let filtered = PeeringRuleHits {
+        filtered: 1,
+        ..PeeringRuleHits::default()
+    };
+    assert!(stats.peering_rule_hits().contains(&(rule, filtered)));
 
     // src: masquerade, dst: default (no NAT)
     let packet = create_test_packet(
@@ -1970,3 +2185,271 @@ fn test_format_packet_addrs_ports() {
         "srcVpc=VNI(3000) src=10.0.0.1 dst=20.0.0.2"
     );
 }
+
+#[cfg_attr(not(emulated), traced_test)]
+#[test]
+fn test_flow_filter_table_directional_exposes() {
+    let vni1 = Vni::new_checked(100).unwrap();
+    let vni2 = Vni::new_checked(200).unwrap();
+
+    let mut vpc_table = VpcTable::new();
+    vpc_table
+        .add(Vpc::new("vpc1", "VPC01", vni1.as_u32()).unwrap())
+        .unwrap();
+    vpc_table
+        .add(Vpc::new("vpc2", "VPC02", vni2.as_u32()).unwrap())
+        .unwrap();
+
+    let mut peering_table = VpcPeeringTable::new();
+    peering_table
+        .add(VpcPeering::with_default_group(
+            "vpc1-to-vpc2",
+            VpcManifest::with_exposes(
+                "vpc1",
+                vec![
+                    VpcExpose::empty().ip("1.0.0.0/24".into()),
+                    VpcExpose::empty()
+                        .make_masquerade(None)
+                        .unwrap()
+                        .ip("3.0.0.0/24".into())
+                        .as_range("30.0.0.0/32".into())
+                        .unwrap()
+                        .direction(ExposeDirection::Outbound),
+                ],
+            ),
+            VpcManifest::with_exposes("vpc2", vec![VpcExpose::empty().ip("5.0.0.0/24".into())]),
+        ))
+        .unwrap();
+
+    let overlay = Overlay::new(vpc_table, peering_table).validate().unwrap();
+    let table = FlowFilterTable::build_from_overlay(&overlay).unwrap();
+    let mut writer = FlowFilterTableWriter::new();
+    writer.update_flow_filter_table(table);
+    let mut flow_filter = FlowFilter::new("test-filter", writer.get_reader());
+
+    // Bidirectional expose: vpc1 and vpc2 can reach each other
+    for (src_vni, src, dst) in [(vni1, "1.0.0.5", "5.0.0.10"), (vni2, "5.0.0.10", "1.0.0.5")] {
+        let packet = create_test_packet(
+            Some(src_vni.into()),
+            src.parse().unwrap(),
+            dst.parse().unwrap(),
+        );
+        let packet_out = flow_filter.process([packet].into_iter()).next().unwrap();
+        assert!(!packet_out.is_done(), "{:?}", packet_out.get_done());
+    }
+
+    // Outbound-only expose: vpc1 can send traffic to vpc2...
+    let packet = create_test_packet(
+        Some(vni1.into()),
+        "3.0.0.5".parse().unwrap(),
+        "5.0.0.10".parse().unwrap(),
+    );
+    let packet_out = flow_filter.process([packet].into_iter()).next().unwrap();
+    assert!(!packet_out.is_done(), "{:?}", packet_out.get_done());
+    assert_eq!(packet_out.meta().dst_vpcd, Some(vpcd(vni2.into())));
+
+    // ... but vpc2 cannot initiate traffic towards the exposed prefixes
+    let packet = create_test_packet(
+        Some(vni2.into()),
+        "5.0.0.10".parse().unwrap(),
+        "30.0.0.0".parse().unwrap(),
+    );
+    let packet_out = flow_filter.process([packet].into_iter()).next().unwrap();
+    assert_eq!(packet_out.get_done(), Some(DoneReason::Filtered));
+}
+
+fn create_test_ipv4_packet_with_proto(
+    src_vpcd: Option<VpcDiscriminant>,
+    src_addr: Ipv4Addr,
+    dst_addr: Ipv4Addr,
+    proto: u8,
+) -> Packet<TestBuffer> {
+    let mut packet = build_test_ipv4_packet_with_transport(100, None).unwrap();
+    let Net::Ipv4(ip) = packet.headers_mut().try_ip_mut().unwrap() else {
+        unreachable!()
+    };
+    ip.set_source(UnicastIpv4Addr::new(src_addr).unwrap());
+    ip.set_destination(dst_addr);
+    ip.set_next_header(NextHeader::new(proto));
+    packet.meta_mut().src_vpcd = src_vpcd;
+    packet.meta_mut().set_overlay(true);
+    packet
+}
+
+#[cfg_attr(not(emulated), traced_test)]
+#[test]
+fn test_flow_filter_table_expose_protocols() {
+    let vni1 = Vni::new_checked(100).unwrap();
+    let vni2 = Vni::new_checked(200).unwrap();
+
+    let mut vpc_table = VpcTable::new();
+    vpc_table
+        .add(Vpc::new("vpc1", "VPC01", vni1.as_u32()).unwrap())
+        .unwrap();
+    vpc_table
+        .add(Vpc::new("vpc2", "VPC02", vni2.as_u32()).unwrap())
+        .unwrap();
+
+    // vpc1 only allows echo requests and GRE, vpc2 allows all protocols
+    let mut peering_table = VpcPeeringTable::new();
+    peering_table
+        .add(VpcPeering::with_default_group(
+            "vpc1-to-vpc2",
+            VpcManifest::with_exposes(
+                "vpc1",
+                vec![
+                    VpcExpose::empty()
+                        .ip("1.0.0.0/24".into())
+                        .protocol(ExposeProtocol::Icmp {
+                            icmp_type: Some(8),
+                            code: None,
+                        })
+                        .protocol(ExposeProtocol::Number(47)),
+                ],
+            ),
+            VpcManifest::with_exposes("vpc2", vec![VpcExpose::empty().ip("5.0.0.0/24".into())]),
+        ))
+        .unwrap();
+
+    let overlay = Overlay::new(vpc_table, peering_table).validate().unwrap();
+    let table = FlowFilterTable::build_from_overlay(&overlay).unwrap();
+    let mut writer = FlowFilterTableWriter::new();
+    writer.update_flow_filter_table(table);
+    let mut flow_filter = FlowFilter::new("test-filter", writer.get_reader());
+
+    let vpc1_addr = Ipv4Addr::from_str("1.0.0.5").unwrap();
+    let vpc2_addr = Ipv4Addr::from_str("5.0.0.10").unwrap();
+
+    // Echo requests are allowed, echo replies are not
+    let packet = create_test_icmp_v4_packet(Some(vni1.into()), vpc1_addr, vpc2_addr);
+    let packet_out = flow_filter.process([packet].into_iter()).next().unwrap();
+    assert!(!packet_out.is_done(), "{:?}", packet_out.get_done());
+    assert_eq!(packet_out.meta().dst_vpcd, Some(vpcd(vni2.into())));
+
+    let mut packet =
+        build_test_icmp4_echo(vpc2_addr, vpc1_addr, 1, IcmpEchoDirection::Reply).unwrap();
+    packet.meta_mut().src_vpcd = Some(vni2.into());
+    packet.meta_mut().set_overlay(true);
+    let packet_out = flow_filter.process([packet].into_iter()).next().unwrap();
+    assert_eq!(packet_out.get_done(), Some(DoneReason::Filtered));
+
+    // GRE is allowed both ways
+    for (src_vni, src, dst) in [(vni1, vpc1_addr, vpc2_addr), (vni2, vpc2_addr, vpc1_addr)] {
+        let packet = create_test_ipv4_packet_with_proto(Some(src_vni.into()), src, dst, 47);
+        let packet_out = flow_filter.process([packet].into_iter()).next().unwrap();
+        assert!(!packet_out.is_done(), "{:?}", packet_out.get_done());
+    }
+
+    // ESP and UDP are not allowed
+    let packet = create_test_ipv4_packet_with_proto(Some(vni1.into()), vpc1_addr, vpc2_addr, 50);
+    let packet_out = flow_filter.process([packet].into_iter()).next().unwrap();
+    assert_eq!(packet_out.get_done(), Some(DoneReason::Filtered));
+
+    let packet = create_test_packet(
+        Some(vni2.into()),
+        IpAddr::V4(vpc2_addr),
+        IpAddr::V4(vpc1_addr),
+    );
+    let packet_out = flow_filter.process([packet].into_iter()).next().unwrap();
+    assert_eq!(packet_out.get_done(), Some(DoneReason::Filtered));
+}
+
+#[cfg_attr(not(emulated), traced_test)]
+#[test]
+fn test_flow_filter_table_dual_stack_peering() {
+    let mut vpc_table = VpcTable::new();
+    vpc_table
+        .add(Vpc::new("vpc1", "VPC01", 100).unwrap())
+        .unwrap();
+    vpc_table
+        .add(Vpc::new("vpc2", "VPC02", 200).unwrap())
+        .unwrap();
+
+    let mut peering_table = VpcPeeringTable::new();
+    peering_table
+        .add(VpcPeering::with_default_group(
+            "vpc1-to-vpc2",
+            VpcManifest::with_exposes(
+                "vpc1",
+                vec![
+                    VpcExpose::empty().ip("1.0.0.0/24".into()),
+                    VpcExpose::empty().ip("2001:db8:1::/48".into()),
+                ],
+            ),
+            VpcManifest::with_exposes(
+                "vpc2",
+                vec![
+                    VpcExpose::empty().ip("5.0.0.0/24".into()),
+                    VpcExpose::empty().ip("2001:db8:5::/48".into()),
+                ],
+            ),
+        ))
+        .unwrap();
+
+    let overlay = Overlay::new(vpc_table, peering_table).validate().unwrap();
+    let table = FlowFilterTable::build_from_overlay(&overlay).unwrap();
+
+    // Prefixes are only paired with prefixes of the same IP version
+    let rules: Vec<_> = table
+        .peering_rule_hits()
+        .into_iter()
+        .map(|(rule, _)| rule)
+        .filter(|rule| rule.src_vpcd == vpcd(100))
+        .collect();
+    assert_eq!(rules.len(), 2, "{rules:?}");
+    assert!(rules.iter().all(|rule| {
+        let (Some(local), Some(remote)) = (rule.local, rule.remote) else {
+            return false;
+        };
+        local.matches_version(remote)
+    }));
+
+    let mut writer = FlowFilterTableWriter::new();
+    writer.update_flow_filter_table(table);
+    let mut flow_filter = FlowFilter::new("test-filter", writer.get_reader());
+
+    for (src_vpcd, src, dst, dst_vpcd) in [
+        (vpcd(100), "1.0.0.5", "5.0.0.10", vpcd(200)),
+        (vpcd(200), "5.0.0.10", "1.0.0.5", vpcd(100)),
+        (vpcd(100), "2001:db8:1::5", "2001:db8:5::10", vpcd(200)),
+        (vpcd(200), "2001:db8:5::10", "2001:db8:1::5", vpcd(100)),
+    ] {
+        let packet = create_test_packet(Some(src_vpcd), src.parse().unwrap(), dst.parse().unwrap());
+        let packet_out = flow_filter.process([packet].into_iter()).next().unwrap();
+        assert!(!packet_out.is_done(), "{:?}", packet_out.get_done());
+        assert_eq!(packet_out.meta().dst_vpcd, Some(dst_vpcd));
+    }
+
+    // IPv6 addresses outside of the exposed prefixes are filtered
+    let packet = create_test_packet(
+        Some(vpcd(100)),
+        "2001:db8:1::5".parse().unwrap(),
+        "2001:db8:6::10".parse().unwrap(),
+    );
+    let packet_out = flow_filter.process([packet].into_iter()).next().unwrap();
+    assert_eq!(packet_out.get_done(), Some(DoneReason::Filtered));
+}
+
+#[test]
+fn test_flow_filter_table_mixed_ip_versions_lookup() {
+    let mut table = FlowFilterTable::new();
+    let dst_data = RemoteData::new(vpcd(200), None, None);
+    table
+        .insert(
+            vpcd(100),
+            VpcdLookupResult::Single(dst_data),
+            Prefix::from("2001:db8::/32"),
+            None,
+            Prefix::from("20.0.0.0/24"),
+            None,
+        )
+        .unwrap();
+
+    let src: IpAddr = "2001:db8::1".parse().unwrap();
+    let dst: IpAddr = "20.0.0.1".parse().unwrap();
+    assert_eq!(table.lookup(vpcd(100), &src, &dst, None), None);
+    assert_eq!(
+        table.evaluate(vpcd(100), &src, &dst, L4Protocol::Any, None),
+        FlowVerdict::Denied
+    );
+}
@@ -0,0 +1,427 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! NUMA-aware assignment of lcores to the workers serving NICs.
+//!
+//! Packets received by a NIC are written by DMA to the memory of the NUMA node the NIC is attached
+//! to. A worker running on another NUMA node pays for remote memory accesses on every packet, so
+//! [`assign`] keeps the workers of each NIC on the lcores local to the NIC, and reports the NUMA
+//! node of each NIC as the memory socket its buffers should be allocated from.
+//!
+//! Lcores are the logical processors (PUs) of the topology, identified by their OS index. The
+//! first hardware thread of each core is used before the sibling threads of any core.
+//!
+//! # Examples
+//!
+//! ```
+//! # use dataplane_hardware::Node;
+//! # use dataplane_hardware::affinity::{AffinityError, assign};
+//! # use dataplane_hardware::pci::address::PciAddress;
+//! # use std::collections::BTreeSet;
+//! # use std::num::NonZero;
+//! #
+//! fn eal_lcores(system: &Node, nics: &[PciAddress]) -> Result<Vec<usize>, AffinityError> {
+//!     // keep lcore 0 for the kernel
+//!     let reserved = BTreeSet::from([0]);
+//!     let assignment = assign(system, nics, NonZero::new(2).unwrap(), &reserved)?;
+//!     Ok(assignment.lcores())
+//! }
+//! ```
+
+use std::collections::BTreeSet;
+use std::num::NonZero;
+
+use crate::pci::address::PciAddress;
+use crate::{Node, NodeAttributes};
+
+/// Errors assigning lcores to the workers of NICs
+#[derive(Debug, thiserror::Error)]
+pub enum AffinityError {
+    /// The NIC is not in the topology.
+    #[error("PCI device {0} is not in the hardware topology")]
+    UnknownNic(PciAddress),
+    /// No NUMA node shares lcores with the NIC.
+    #[error("PCI device {0} is not local to any NUMA node")]
+    NoLocalNode(PciAddress),
+    /// The NUMA node of a NIC has too few lcores left for its workers.
+    #[error(
+        "NUMA node {node} has {available} lcores left for the workers of {nic}, {needed} needed"
+    )]
+    NotEnoughLcores {
+        /// The NIC whose workers could not be placed
+        nic: PciAddress,
+        /// The NUMA node of the NIC
+        node: usize,
+        /// The number of lcores needed
+        needed: usize,
+        /// The number of lcores left on the node
+        available: usize,
+    },
+    /// All the lcores are taken by workers or reserved.
+    #[error("No lcore is left for the main lcore")]
+    NoMainLcore,
+}
+
+/// A NUMA node, with the lcores local to it
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NumaLocality {
+    /// The OS index of the NUMA node
+    pub node: usize,
+    /// The local lcores, the first hardware thread of each core first
+    pub lcores: Vec<usize>,
+}
+
+/// The lcores of the workers serving a NIC
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct NicAssignment {
+    /// The PCI address of the NIC
+    pub nic: PciAddress,
+    /// The NUMA node of the NIC, from which its buffers should be allocated
+    pub numa_node: usize,
+    /// The lcores of the workers serving the NIC, all local to its NUMA node
+    pub lcores: Vec<usize>,
+}
+
+/// An assignment of lcores to the workers serving NICs
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Assignment {
+    /// The lcore of the main thread, which serves no NIC
+    pub main_lcore: usize,
+    /// The lcores of the workers of each NIC, in the order the NICs were given
+    pub nics: Vec<NicAssignment>,
+}
+
+impl Assignment {
+    /// All the lcores of the assignment, the main lcore first
+    #[must_use]
+    pub fn lcores(&self) -> Vec<usize> {
+        std::iter::once(self.main_lcore)
+            .chain(self.nics.iter().flat_map(|nic| nic.lcores.iter().copied()))
+            .collect()
+    }
+
+    /// The NUMA nodes memory should be allocated from
+    #[must_use]
+    pub fn numa_nodes(&self) -> BTreeSet<usize> {
+        self.nics.iter().map(|nic| nic.numa_node).collect()
+    }
+}
+
+/// The lcores under a node of the topology, as (rank of the thread in its core, OS index)
+fn collect_lcores(node: &Node, rank: usize, lcores: &mut Vec<(usize, usize)>) {
+    if node.type_() == "PU" {
+        if let Some(index) = node.os_index() {
+            lcores.push((rank, index));
+        }
+        return;
+    }
+    let is_core = node.type_() == "Core";
+    for (i, child) in node.children().iter().enumerate() {
+        collect_lcores(child, if is_core { i } else { rank }, lcores);
+    }
+}
+
+/// The lcores under a node of the topology, the first hardware thread of each core first
+fn lcores_under(node: &Node) -> Vec<usize> {
+    let mut lcores = vec![];
+    collect_lcores(node, 0, &mut lcores);
+    lcores.sort_unstable();
+    let mut seen = BTreeSet::new();
+    lcores
+        .into_iter()
+        .map(|(_, index)| index)
+        .filter(|index| seen.insert(*index))
+        .collect()
+}
+
+fn collect_localities(node: &Node, localities: &mut Vec<NumaLocality>) {
+    for child in node.children() {
+        if let (Some(NodeAttributes::NumaNode(_)), Some(index)) =
+            (child.attributes(), child.os_index())
+        {
+            // a NUMA node is attached to the object whose lcores are local to it
+            localities.push(NumaLocality {
+                node: index,
+                lcores: lcores_under(node),
+            });
+        }
+        collect_localities(child, localities);
+    }
+}
+
+/// The NUMA nodes of a topology, with their local lcores, by OS index
+#[must_use]
+pub fn numa_localities(topology: &Node) -> Vec<NumaLocality> {
+    let mut localities = vec![];
+    collect_localities(topology, &mut localities);
+    localities.sort_by_key(|locality| locality.node);
+    localities
+}
+
+/// The path from `node` to the PCI device with the given address, if under `node`
+fn path_to<'a>(node: &'a Node, address: PciAddress, path: &mut Vec<&'a Node>) -> bool {
+    path.push(node);
+    if matches!(node.attributes(), Some(NodeAttributes::Pci(pci)) if pci.address() == address) {
+        return true;
+    }
+    if node
+        .children()
+        .iter()
+        .any(|child| path_to(child, address, path))
+    {
+        return true;
+    }
+    path.pop();
+    false
+}
+
+/// The NUMA node local to a NIC: the node sharing the most lcores with the closest ancestor of the
+/// NIC having any lcore. Ties go to the node with the lowest index.
+///
+/// # Errors
+///
+/// Returns an error if the NIC is not in the topology, or no NUMA node is local to it.
+pub fn locality_of<'a>(
+    topology: &Node,
+    localities: &'a [NumaLocality],
+    nic: PciAddress,
+) -> Result<&'a NumaLocality, AffinityError> {
+    let mut path = vec![];
+    if !path_to(topology, nic, &mut path) {
+        return Err(AffinityError::UnknownNic(nic));
+    }
+    let local: BTreeSet<_> = path
+        .iter()
+        .rev()
+        .map(|ancestor| lcores_under(ancestor))
+        .find(|lcores| !lcores.is_empty())
+        .unwrap_or_default()
+        .into_iter()
+        .collect();
+    localities
+        .iter()
+        .map(|locality| {
+            let shared = locality
+                .lcores
+                .iter()
+                .filter(|lcore| local.contains(lcore))
+                .count();
+            (shared, locality)
+        })
+        .filter(|(shared, _)| *shared > 0)
+        .max_by(|(a, x), (b, y)| a.cmp(b).then(y.node.cmp(&x.node)))
+        .map(|(_, locality)| locality)
+        .ok_or(AffinityError::NoLocalNode(nic))
+}
+
+/// Assign `workers_per_nic` lcores local to each NIC to its workers, and an lcore to the main
+/// thread. Lcores in `reserved` are left alone, as are lcores assigned to a NIC given earlier.
+///
+/// The main lcore is taken on the NUMA node of the first NIC if possible, after the workers.
+///
+/// # Errors
+///
+/// Returns an error if a NIC is not in the topology or local to no NUMA node, or if there are not
+/// enough lcores left.
+pub fn assign(
+    topology: &Node,
+    nics: &[PciAddress],
+    workers_per_nic: NonZero<usize>,
+    reserved: &BTreeSet<usize>,
+) -> Result<Assignment, AffinityError> {
+    let localities = numa_localities(topology);
+    let mut used = reserved.clone();
+    let mut assigned = Vec::with_capacity(nics.len());
+    for nic in nics {
+        let locality = locality_of(topology, &localities, *nic)?;
+        let free: Vec<_> = locality
+            .lcores
+            .iter()
+            .copied()
+            .filter(|lcore| !used.contains(lcore))
+            .collect();
+        if free.len() < workers_per_nic.get() {
+            return Err(AffinityError::NotEnoughLcores {
+                nic: *nic,
+                node: locality.node,
+                needed: workers_per_nic.get(),
+                available: free.len(),
+            });
+        }
+        let lcores = free[..workers_per_nic.get()].to_vec();
+        used.extend(&lcores);
+        assigned.push(NicAssignment {
+            nic: *nic,
+            numa_node: locality.node,
+            lcores,
+        });
+    }
+    let first_node = assigned.first().map(|nic| nic.numa_node);
+    let main_lcore = localities
+        .iter()
+        .filter(|locality| Some(locality.node) == first_node)
+        .chain(localities.iter())
+        .flat_map(|locality| locality.lcores.iter().copied())
+        .chain(lcores_under(topology))
+        .find(|lcore| !used.contains(lcore))
+        .ok_or(AffinityError::NoMainLcore)?;
+    Ok(Assignment {
+        main_lcore,
+        nics: assigned,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::num::NonZero;
+
+    use id::Id;
+
+    use super::{AffinityError, NumaLocality, assign, numa_localities};
+    use crate::mem::numa::NumaNodeAttributes;
+    use crate::pci::PciDeviceAttributes;
+    use crate::pci::address::PciAddress;
+    use crate::{Node, NodeAttributes};
+
+    fn node(type_: &str, os_index: Option<usize>, children: Vec<Node>) -> Node {
+        static NEXT_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+        Node {
+            id: Id::from(NEXT_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed)),
+            type_: type_.to_string(),
+            subtype: None,
+            os_index,
+            name: None,
+            properties: BTreeMap::new(),
+            attributes: None,
+            children,
+        }
+    }
+
+    fn numa(index: usize) -> Node {
+        let mut numa = node("NUMANode", Some(index), vec![]);
+        numa.attributes = Some(NodeAttributes::NumaNode(NumaNodeAttributes::new(
+            None,
+            BTreeSet::new(),
+        )));
+        numa
+    }
+
+    fn nic(address: &str) -> Node {
+        let attributes: PciDeviceAttributes = serde_yaml_ng::from_str(&format!(
+            "
+address: \"{address}\"
+revision: 0
+device: {{vendor_id: \"8086\", vendor_name: null, device_id: \"1592\", device_name: null}}
+sub_device: {{vendor_id: \"8086\", vendor_name: null, device_id: \"0002\", device_name: null}}
+link_speed: \"16.0 GT/s\"
+"
+        ))
+        .unwrap();
+        let mut nic = node("PCIDev", None, vec![]);
+        nic.attributes = Some(NodeAttributes::Pci(attributes));
+        nic
+    }
+
+    fn address(address: &str) -> PciAddress {
+        PciAddress::try_from(address).unwrap()
+    }
+
+    /// A package of a NUMA node with 4 cores of 2 threads, whose siblings are offset by 8
+    fn package(index: usize, nics: Vec<Node>) -> Node {
+        let cores = (0..4)
+            .map(|core| {
+                let first = index * 4 + core;
+                node(
+                    "Core",
+                    Some(first),
+                    vec![
+                        node("PU", Some(first), vec![]),
+                        node("PU", Some(first + 8), vec![]),
+                    ],
+                )
+            })
+            .collect();
+        let l3 = node("L3Cache", None, cores);
+        let bridge = node("Bridge", None, nics);
+        node("Package", Some(index), vec![numa(index), l3, bridge])
+    }
+
+    /// Two NUMA nodes, with a NIC on each
+    fn system() -> Node {
+        node(
+            "Machine",
+            None,
+            vec![
+                package(0, vec![nic("0000:01:00.0")]),
+                package(1, vec![nic("0000:81:00.0")]),
+            ],
+        )
+    }
+
+    #[test]
+    fn numa_localities_of_system() {
+        assert_eq!(
+            numa_localities(&system()),
+            [
+                NumaLocality {
+                    node: 0,
+                    lcores: vec![0, 1, 2, 3, 8, 9, 10, 11],
+                },
+                NumaLocality {
+                    node: 1,
+                    lcores: vec![4, 5, 6, 7, 12, 13, 14, 15],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn workers_are_local_to_their_nic() {
+        let nics = [address("0000:81:00.0"), address("0000:01:00.0")];
+        let reserved = BTreeSet::from([0]);
+        let assignment = assign(&system(), &nics, NonZero::new(3).unwrap(), &reserved).unwrap();
+        assert_eq!(assignment.nics[0].numa_node, 1);
+        assert_eq!(assignment.nics[0].lcores, [4, 5, 6]);
+        assert_eq!(assignment.nics[1].numa_node, 0);
+        assert_eq!(assignment.nics[1].lcores, [1, 2, 3]);
+        // the main lcore goes to the node of the first NIC
+        assert_eq!(assignment.main_lcore, 7);
+        assert_eq!(assignment.lcores(), [7, 4, 5, 6, 1, 2, 3]);
+        assert_eq!(assignment.numa_nodes(), BTreeSet::from([0, 1]));
+    }
+
+    #[test]
+    fn workers_take_sibling_threads_last() {
+        let nics = [address("0000:01:00.0")];
+        let assignment =
+            assign(&system(), &nics, NonZero::new(6).unwrap(), &BTreeSet::new()).unwrap();
+        assert_eq!(assignment.nics[0].lcores, [0, 1, 2, 3, 8, 9]);
+        assert_eq!(assignment.main_lcore, 10);
+    }
+
+    #[test]
+    fn assignment_errors() {
+        let nics = [address("0000:01:00.0")];
+        assert!(matches!(
+            assign(&system(), &nics, NonZero::new(9).unwrap(), &BTreeSet::new()),
+            Err(AffinityError::NotEnoughLcores {
+                node: 0,
+                needed: 9,
+                available: 8,
+                ..
+            })
+        ));
+        let nics = [address("0000:02:00.0")];
+        assert!(matches!(
+            assign(&system(), &nics, NonZero::new(1).unwrap(), &BTreeSet::new()),
+            Err(AffinityError::UnknownNic(_))
+        ));
+        let nics = [address("0000:01:00.0")];
+        let reserved = (0..16).filter(|lcore| *lcore != 1).collect();
+        assert!(matches!(
+            assign(&system(), &nics, NonZero::new(1).unwrap(), &reserved),
+            Err(AffinityError::NoMainLcore)
+        ));
+    }
+}
@@ -15,6 +15,7 @@ use crate::os::OsDeviceAttributes;
 use crate::pci::PciDeviceAttributes;
 use crate::pci::bridge::BridgeAttributes;
 
+pub mod affinity;
 pub mod group;
 pub mod mem;
 #[cfg(unix)]
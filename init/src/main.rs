@@ -4,7 +4,30 @@
 #![doc = include_str!("../README.md")]
 #![deny(clippy::pedantic, missing_docs)]
 
+use std::collections::BTreeSet;
+use std::num::NonZero;
+use std::os::unix::process::CommandExt;
+use std::process::Command;
+
+use args::{CmdArgs, DriverConfigSection, LaunchConfiguration, SocketMemPlan};
+use hardware::Node;
+use hardware::affinity::assign;
 use hardware::nic::{BindToVfioPci, PciNic};
+use hardware::pci::address::PciAddress;
+use tracing::{error, info};
+
+/// The number of workers serving each NIC
+// TODO: take from the command line
+const WORKERS_PER_NIC: NonZero<usize> = NonZero::new(2).unwrap();
+
+/// The dataplane, as installed in the image
+const DATAPLANE: &str = "/bin/dataplane";
+
+/// Log why the dataplane can't be launched, and give up
+fn fail(message: &str) -> ! {
+    error!("{message}");
+    std::process::exit(1);
+}
 
 fn main() {
     tracing_subscriber::fmt()
@@ -13,12 +36,72 @@ fn main() {
         .with_level(true)
         .with_line_number(true)
         .init();
-    // TODO: proper argument parsing
-    // -- hack add a real command line parser
-    let mut args = std::env::args().skip(1);
-    // -- end hack
-    // TODO: fix unwraps in the next PR.  These can't be properly addressed before the arg parser is done.
-    let address = hardware::pci::address::PciAddress::try_from(args.next().unwrap()).unwrap();
-    let mut device = PciNic::new(address).unwrap();
-    device.bind_to_vfio_pci().unwrap();
+    // the command line of the dataplane, which the lcores and memory assigned here complete
+    let argv: Vec<String> = std::env::args().collect();
+    let args = CmdArgs::try_parse_with_config_file_from(&argv).unwrap_or_else(|e| e.exit());
+    let config = LaunchConfiguration::try_from(&args)
+        .unwrap_or_else(|e| fail(&format!("Invalid launch configuration: {e}")));
+    let DriverConfigSection::Dpdk(dpdk) = &config.driver else {
+        fail("Only the DPDK driver is launched by dataplane-init");
+    };
+    if !dpdk.eal.lcores.is_empty() || !dpdk.eal.socket_mem.is_empty() {
+        fail("The lcores and the memory of the DPDK driver are assigned by dataplane-init");
+    }
+    let Some(pool_mb) = args.eal_pool_size() else {
+        fail("The size of the pools of the NICs is needed to reserve their memory");
+    };
+    let addresses: Vec<_> = dpdk
+        .eal
+        .allow
+        .iter()
+        .map(|device| {
+            PciAddress::try_from(device.address.to_string())
+                .unwrap_or_else(|e| fail(&format!("Invalid PCI address {}: {e}", device.address)))
+        })
+        .collect();
+    for address in &addresses {
+        let mut device = PciNic::new(*address)
+            .unwrap_or_else(|e| fail(&format!("Failed to find NIC {address}: {e}")));
+        if let Err(e) = device.bind_to_vfio_pci() {
+            fail(&format!("Failed to bind NIC {address} to vfio-pci: {e}"));
+        }
+    }
+    // keep the workers of each NIC on its NUMA node, leaving lcore 0 to the kernel
+    let system = Node::scan_all();
+    let assignment = assign(&system, &addresses, WORKERS_PER_NIC, &BTreeSet::from([0]))
+        .unwrap_or_else(|e| fail(&format!("Failed to assign lcores to the NICs: {e}")));
+    for nic in &assignment.nics {
+        info!(
+            "NIC {} is on NUMA node {}, with workers on lcores {:?}",
+            nic.nic, nic.numa_node, nic.lcores
+        );
+    }
+    // the NICs of the assignment are in the order of the allowed devices
+    let plan = SocketMemPlan::new(
+        dpdk.eal
+            .allow
+            .iter()
+            .zip(&assignment.nics)
+            .map(|(device, nic)| (device.address.clone(), u16::try_from(nic.numa_node).ok())),
+        pool_mb,
+    );
+    let lcores: Vec<_> = assignment
+        .lcores()
+        .iter()
+        .map(ToString::to_string)
+        .collect();
+    let socket_mem: Vec<_> = plan.socket_mem().iter().map(ToString::to_string).collect();
+    info!(
+        "Launching the dataplane on lcores {}, with hugepage memory (MB, by NUMA node) {}",
+        lcores.join(","),
+        socket_mem.join(",")
+    );
+    let e = Command::new(DATAPLANE)
+        .args(&argv[1..])
+        .arg("--eal-lcores")
+        .arg(lcores.join(","))
+        .arg("--eal-socket-mem")
+        .arg(socket_mem.join(","))
+        .exec();
+    fail(&format!("Failed to exec {DATAPLANE}: {e}"));
 }
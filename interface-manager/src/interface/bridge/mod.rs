@@ -5,10 +5,14 @@ use derive_builder::Builder;
 use multi_index_map::MultiIndexMap;
 use net::eth::ethtype::EthType;
 use net::interface::BridgeProperties;
-use rekon::AsRequirement;
+use rekon::{AsRequirement, Normalize};
 use serde::{Deserialize, Serialize};
 
 pub mod fdb;
+mod vlan;
+
+#[allow(unused_imports)] // re-export
+pub use vlan::*;
 
 /// The "planned" properties for a bridge.
 #[derive(
@@ -35,6 +39,15 @@ pub struct BridgePropertiesSpec {
     pub vlan_protocol: EthType,
 }
 
+impl Normalize for BridgePropertiesSpec {
+    /// An unspecified (zero) vlan protocol makes the kernel default to an 802.1Q bridge.
+    fn normalize(&mut self) {
+        if self.vlan_protocol.as_u16() == 0 {
+            self.vlan_protocol = EthType::VLAN;
+        }
+    }
+}
+
 impl AsRequirement<BridgePropertiesSpec> for BridgeProperties {
     type Requirement<'a>
         = BridgePropertiesSpec
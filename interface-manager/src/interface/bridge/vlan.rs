@@ -0,0 +1,458 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Reconcile the VLAN membership of the ports of vlan aware bridges.
+//!
+//! Only the ports which a [`BridgeVlanSpec`] is given for are managed: their VLANs are made to
+//! match the spec exactly, e.g. the default VLAN the kernel adds to new ports is removed unless
+//! required. The VLANs of other ports are left alone.
+
+use crate::Manager;
+use futures::TryStreamExt;
+use net::interface::{InterfaceIndex, InterfaceIndexError, InterfaceName};
+use net::vlan::{InvalidVid, Vid};
+use rekon::{Create, Normalize, Observe, Op, Reconcile, Update};
+use rtnetlink::LinkUnspec;
+use rtnetlink::packet_route::AddressFamily;
+use rtnetlink::packet_route::link::{
+    AfSpecBridge, BridgeVlanInfo, BridgeVlanInfoFlags, LinkAttribute, LinkExtentMask, LinkMessage,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use tracing::debug;
+
+/// An inclusive range of VLAN ids
+#[derive(Copy, Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct VidRange {
+    first: Vid,
+    last: Vid,
+}
+
+/// Errors building a [`VidRange`]
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidVidRange {
+    #[error("VLAN range {0}-{1} is empty")]
+    Empty(Vid, Vid),
+}
+
+impl VidRange {
+    /// The range of VLANs from `first` to `last`, both included.
+    ///
+    /// # Errors
+    ///
+    /// Fails if `first` is greater than `last`.
+    pub fn new(first: Vid, last: Vid) -> Result<Self, InvalidVidRange> {
+        if first > last {
+            return Err(InvalidVidRange::Empty(first, last));
+        }
+        Ok(Self { first, last })
+    }
+
+    #[must_use]
+    pub fn first(&self) -> Vid {
+        self.first
+    }
+
+    #[must_use]
+    pub fn last(&self) -> Vid {
+        self.last
+    }
+
+    /// The VLANs of the range
+    pub fn iter(&self) -> impl Iterator<Item = Vid> {
+        (self.first.as_u16()..=self.last.as_u16()).filter_map(|vid| Vid::new(vid).ok())
+    }
+}
+
+impl From<Vid> for VidRange {
+    fn from(vid: Vid) -> Self {
+        Self {
+            first: vid,
+            last: vid,
+        }
+    }
+}
+
+/// Merge overlapping and adjacent ranges, so that a set of VLANs has a single representation.
+fn coalesce(ranges: &BTreeSet<VidRange>) -> BTreeSet<VidRange> {
+    let mut merged: Vec<VidRange> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.first.as_u16() <= last.last.as_u16().saturating_add(1) => {
+                last.last = last.last.max(range.last);
+            }
+            _ => merged.push(*range),
+        }
+    }
+    merged.into_iter().collect()
+}
+
+/// How a port is a member of a VLAN
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct VlanMembership {
+    /// Untagged frames received by the port are assigned to this VLAN.
+    pub pvid: bool,
+    /// Frames of this VLAN are sent untagged by the port.
+    pub untagged: bool,
+}
+
+/// The VLAN membership required on a port of a vlan aware bridge
+#[derive(Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct BridgeVlanSpec {
+    /// The name of the bridge port.
+    pub port: InterfaceName,
+    /// The VLANs the port is a member of.
+    pub vids: BTreeSet<VidRange>,
+    /// The VLAN untagged frames received by the port are assigned to, if any.
+    pub pvid: Option<Vid>,
+    /// The VLANs whose frames are sent untagged by the port.
+    pub untagged: BTreeSet<VidRange>,
+}
+
+impl BridgeVlanSpec {
+    /// The required VLANs of the port, with their membership
+    #[must_use]
+    pub fn vlans(&self) -> BTreeMap<Vid, VlanMembership> {
+        let mut vlans: BTreeMap<_, _> = self
+            .vids
+            .iter()
+            .flat_map(VidRange::iter)
+            .map(|vid| (vid, VlanMembership::default()))
+            .collect();
+        for vid in self.untagged.iter().flat_map(VidRange::iter) {
+            vlans.entry(vid).or_default().untagged = true;
+        }
+        if let Some(pvid) = self.pvid {
+            vlans.entry(pvid).or_default().pvid = true;
+        }
+        vlans
+    }
+}
+
+impl Normalize for BridgeVlanSpec {
+    /// The pvid and the untagged VLANs are VLANs the port is a member of, and the ranges are
+    /// merged, so that specs requiring the same VLANs compare equal.
+    fn normalize(&mut self) {
+        self.vids.extend(self.untagged.iter().copied());
+        self.vids.extend(self.pvid.map(VidRange::from));
+        self.vids = coalesce(&self.vids);
+        self.untagged = coalesce(&self.untagged);
+    }
+}
+
+/// The observed VLANs of a bridge port
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct BridgePortVlans {
+    /// The index of the bridge port.
+    pub ifindex: InterfaceIndex,
+    /// The VLANs of the port, with their membership
+    pub vlans: BTreeMap<Vid, VlanMembership>,
+}
+
+/// Errors which may occur when building a [`BridgePortVlans`] from a netlink message.
+#[derive(Debug, thiserror::Error)]
+pub enum BridgeVlanMessageError {
+    /// The message refers to an illegal interface index.
+    #[error(transparent)]
+    Ifindex(#[from] InterfaceIndexError),
+    /// The message reports an illegal VLAN.
+    #[error(transparent)]
+    Vid(#[from] InvalidVid),
+    /// The message does not report VLANs.
+    #[error("link message has no bridge VLANs")]
+    NoVlans,
+}
+
+impl TryFrom<&LinkMessage> for BridgePortVlans {
+    type Error = BridgeVlanMessageError;
+
+    fn try_from(message: &LinkMessage) -> Result<Self, Self::Error> {
+        let ifindex = InterfaceIndex::try_new(message.header.index)?;
+        let infos = message
+            .attributes
+            .iter()
+            .find_map(|attribute| match attribute {
+                LinkAttribute::AfSpecBridge(spec) => Some(spec),
+                _ => None,
+            })
+            .ok_or(BridgeVlanMessageError::NoVlans)?;
+        let mut vlans = BTreeMap::new();
+        let mut range_begin = None;
+        for spec in infos {
+            let AfSpecBridge::VlanInfo(info) = spec else {
+                continue;
+            };
+            let membership = VlanMembership {
+                pvid: info.flags.contains(BridgeVlanInfoFlags::Pvid),
+                untagged: info.flags.contains(BridgeVlanInfoFlags::Untagged),
+            };
+            // compressed dumps report ranges of VLANs with the same membership
+            if info.flags.contains(BridgeVlanInfoFlags::RangeBegin) {
+                range_begin = Some(info.vid);
+                continue;
+            }
+            let first = range_begin.take().unwrap_or(info.vid);
+            for vid in first..=info.vid {
+                vlans.insert(Vid::new(vid)?, membership);
+            }
+        }
+        Ok(Self { ifindex, vlans })
+    }
+}
+
+impl PartialEq<BridgePortVlans> for BridgeVlanSpec {
+    /// Note that the port is not compared: callers are expected to match it by index.
+    fn eq(&self, other: &BridgePortVlans) -> bool {
+        self.vlans() == other.vlans
+    }
+}
+
+impl Observe for Manager<BridgePortVlans> {
+    type Observation<'a>
+        = Result<Vec<BridgePortVlans>, rtnetlink::Error>
+    where
+        Self: 'a;
+
+    /// Observe the VLANs of all the bridge ports (and of the vlan aware bridges themselves)
+    async fn observe<'a>(&self) -> Self::Observation<'a>
+    where
+        Self: 'a,
+    {
+        let mut request = self.handle.link().get();
+        let message = request.message_mut();
+        message.header.interface_family = AddressFamily::Bridge;
+        message
+            .attributes
+            .push(LinkAttribute::ExtMask(vec![LinkExtentMask::Brvlan]));
+        let mut stream = request.execute();
+        let mut observed = vec![];
+        while let Some(message) = stream.try_next().await? {
+            match BridgePortVlans::try_from(&message) {
+                Ok(vlans) => observed.push(vlans),
+                Err(err) => debug!("{err}"),
+            }
+        }
+        Ok(observed)
+    }
+}
+
+fn vlan_info(vid: Vid, membership: VlanMembership) -> AfSpecBridge {
+    let mut flags = BridgeVlanInfoFlags::empty();
+    if membership.pvid {
+        flags |= BridgeVlanInfoFlags::Pvid;
+    }
+    if membership.untagged {
+        flags |= BridgeVlanInfoFlags::Untagged;
+    }
+    AfSpecBridge::VlanInfo(BridgeVlanInfo {
+        flags,
+        vid: vid.as_u16(),
+    })
+}
+
+impl Manager<BridgePortVlans> {
+    /// Add VLANs to a bridge port, or change their membership
+    async fn add_vlans(
+        &self,
+        ifindex: InterfaceIndex,
+        vlans: Vec<AfSpecBridge>,
+    ) -> Result<(), rtnetlink::Error> {
+        if vlans.is_empty() {
+            return Ok(());
+        }
+        let mut message = LinkUnspec::new_with_index(ifindex.to_u32()).build();
+        message.header.interface_family = AddressFamily::Bridge;
+        message.attributes.push(LinkAttribute::AfSpecBridge(vlans));
+        self.handle.link().set(message).execute().await
+    }
+
+    /// Remove VLANs from a bridge port
+    async fn del_vlans(
+        &self,
+        ifindex: InterfaceIndex,
+        vlans: Vec<AfSpecBridge>,
+    ) -> Result<(), rtnetlink::Error> {
+        if vlans.is_empty() {
+            return Ok(());
+        }
+        // a bridge family message only removes the VLANs it lists, not the link
+        let mut request = self.handle.link().del(ifindex.to_u32());
+        let message = request.message_mut();
+        message.header.interface_family = AddressFamily::Bridge;
+        message.attributes.push(LinkAttribute::AfSpecBridge(vlans));
+        request.execute().await
+    }
+}
+
+impl Create for Manager<BridgePortVlans> {
+    type Requirement<'a>
+        = (InterfaceIndex, &'a BridgeVlanSpec)
+    where
+        Self: 'a;
+    type Outcome<'a>
+        = Result<(), rtnetlink::Error>
+    where
+        Self: 'a;
+
+    async fn create<'a>(
+        &self,
+        (ifindex, requirement): (InterfaceIndex, &'a BridgeVlanSpec),
+    ) -> Result<(), rtnetlink::Error>
+    where
+        Self: 'a,
+    {
+        let vlans = requirement
+            .vlans()
+            .into_iter()
+            .map(|(vid, membership)| vlan_info(vid, membership))
+            .collect();
+        self.add_vlans(ifindex, vlans).await
+    }
+}
+
+impl Update for Manager<BridgePortVlans> {
+    type Requirement<'a>
+        = (InterfaceIndex, &'a BridgeVlanSpec)
+    where
+        Self: 'a;
+    type Observation<'a>
+        = &'a BridgePortVlans
+    where
+        Self: 'a;
+    type Outcome<'a>
+        = Result<(), rtnetlink::Error>
+    where
+        Self: 'a;
+
+    /// Remove the VLANs which are not required, then add those missing or whose membership
+    /// differs. Moving the pvid to another VLAN takes it away from the former one.
+    async fn update<'a>(
+        &self,
+        (ifindex, requirement): (InterfaceIndex, &'a BridgeVlanSpec),
+        observation: &'a BridgePortVlans,
+    ) -> Result<(), rtnetlink::Error>
+    where
+        Self: 'a,
+    {
+        let required = requirement.vlans();
+        let unwanted = observation
+            .vlans
+            .iter()
+            .filter(|(vid, _)| !required.contains_key(vid))
+            .map(|(vid, membership)| vlan_info(*vid, *membership))
+            .collect();
+        self.del_vlans(ifindex, unwanted).await?;
+        let missing = required
+            .iter()
+            .filter(|(vid, membership)| observation.vlans.get(vid) != Some(membership))
+            .map(|(vid, membership)| vlan_info(*vid, *membership))
+            .collect();
+        self.add_vlans(ifindex, missing).await
+    }
+}
+
+impl Reconcile for Manager<BridgePortVlans> {
+    type Requirement<'a>
+        = (InterfaceIndex, &'a BridgeVlanSpec)
+    where
+        Self: 'a;
+    type Observation<'a>
+        = Option<&'a BridgePortVlans>
+    where
+        Self: 'a;
+    type Outcome<'a>
+        = Option<Op<'a, Self>>
+    where
+        Self: 'a;
+
+    async fn reconcile<'a>(
+        &self,
+        requirement: (InterfaceIndex, &'a BridgeVlanSpec),
+        observation: Option<&'a BridgePortVlans>,
+    ) -> Self::Outcome<'a>
+    where
+        Self: 'a,
+    {
+        match observation {
+            None => Some(Op::Create(self.create(requirement).await)),
+            Some(observed) => {
+                if requirement.1 == observed {
+                    return None;
+                }
+                Some(Op::Update(self.update(requirement, observed).await))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn vid(vid: u16) -> Vid {
+        Vid::new(vid).unwrap()
+    }
+
+    fn range(first: u16, last: u16) -> VidRange {
+        VidRange::new(vid(first), vid(last)).unwrap()
+    }
+
+    fn spec() -> BridgeVlanSpec {
+        BridgeVlanSpec {
+            port: InterfaceName::try_from("vtep1").unwrap(),
+            vids: [range(10, 12), range(20, 20)].into(),
+            pvid: Some(vid(30)),
+            untagged: [range(30, 30)].into(),
+        }
+    }
+
+    #[test]
+    fn vid_range() {
+        assert!(VidRange::new(vid(2), vid(1)).is_err());
+        let vids: Vec<_> = range(3, 5).iter().map(Vid::as_u16).collect();
+        assert_eq!(vids, [3, 4, 5]);
+        let ranges = [range(1, 3), range(4, 6), range(5, 8), range(10, 10)].into();
+        assert_eq!(coalesce(&ranges), [range(1, 8), range(10, 10)].into());
+    }
+
+    #[test]
+    fn bridge_vlan_spec_normalization() {
+        let mut normalized = spec();
+        normalized.normalize();
+        assert_eq!(
+            normalized.vids,
+            [range(10, 12), range(20, 20), range(30, 30)].into()
+        );
+        assert_eq!(normalized.vlans(), spec().vlans());
+    }
+
+    #[test]
+    fn bridge_port_vlans_from_message() {
+        let mut message = LinkMessage::default();
+        message.header.index = 5;
+        message.header.interface_family = AddressFamily::Bridge;
+        let info = |flags, vid| AfSpecBridge::VlanInfo(BridgeVlanInfo { flags, vid });
+        message.attributes.push(LinkAttribute::AfSpecBridge(vec![
+            info(BridgeVlanInfoFlags::RangeBegin, 10),
+            info(BridgeVlanInfoFlags::RangeEnd, 12),
+            info(BridgeVlanInfoFlags::empty(), 20),
+            info(
+                BridgeVlanInfoFlags::Pvid | BridgeVlanInfoFlags::Untagged,
+                30,
+            ),
+        ]));
+        let observed = BridgePortVlans::try_from(&message).unwrap();
+        assert_eq!(observed.ifindex, InterfaceIndex::try_new(5).unwrap());
+        assert!(spec() == observed);
+
+        let mut observed = observed;
+        observed.vlans.insert(vid(1), VlanMembership::default());
+        assert!(spec() != observed);
+
+        message.attributes.clear();
+        assert!(matches!(
+            BridgePortVlans::try_from(&message),
+            Err(BridgeVlanMessageError::NoVlans)
+        ));
+    }
+}
@@ -32,6 +32,8 @@ mod netdevsim;
 #[allow(unused_imports)]
 pub use netdevsim::*;
 
+use crate::netns::NetnsName;
+use crate::offload::OffloadSpec;
 use crate::{Manager, manager_of};
 use derive_builder::Builder;
 use futures::TryFutureExt;
@@ -48,7 +50,7 @@ use net::ipv4::addr::UnicastIpv4Addr;
 use net::pci::PciEbdf;
 use net::route::RouteTableId;
 use net::vxlan::InvalidVni;
-use rekon::{AsRequirement, Create, Op, Reconcile, Remove, Update};
+use rekon::{AsRequirement, Create, Normalize, Op, Reconcile, Remove, Update};
 use rtnetlink::packet_route::link::{
     InfoBridge, InfoData, InfoKind, InfoVrf, InfoVxlan, LinkAttribute, LinkFlags, LinkInfo,
     LinkMessage, State,
@@ -108,6 +110,20 @@ pub struct InterfaceSpec {
     pub controller: Option<InterfaceIndex>,
     /// Interface-specific properties.
     pub properties: InterfacePropertiesSpec,
+    /// The intended offload settings of the interface.
+    ///
+    /// Offloads are not part of the observed [`Interface`], and are reconciled on their own (see
+    /// [`crate::offload`]).
+    #[builder(default)]
+    #[serde(default)]
+    pub offloads: OffloadSpec,
+    /// The network namespace the interface should be in, the current one if `None`.
+    ///
+    /// The observed [`Interface`] does not carry its namespace: moving the interface is up to
+    /// the caller, which observes each namespace on its own (see [`crate::netns`]).
+    #[builder(default)]
+    #[serde(default)]
+    pub netns: Option<NetnsName>,
 }
 
 impl AsRequirement<InterfaceSpec> for Interface {
@@ -124,10 +140,18 @@ impl AsRequirement<InterfaceSpec> for Interface {
             admin_state: self.admin_state,
             controller: self.controller,
             properties: self.properties.as_requirement()?,
+            offloads: OffloadSpec::default(),
+            netns: None,
         })
     }
 }
 
+impl Normalize for InterfaceSpec {
+    fn normalize(&mut self) {
+        self.properties.normalize();
+    }
+}
+
 impl Create for Manager<Interface> {
     type Requirement<'a>
         = &'a InterfaceSpec
@@ -181,6 +205,9 @@ impl Create for Manager<Interface> {
                 .attributes
                 .push(LinkAttribute::Address(mac.inner().0.to_vec()));
         }
+        if let Some(mtu) = requirement.mtu {
+            message.attributes.push(LinkAttribute::Mtu(mtu.to_u32()));
+        }
         self.handle.link().add(message).execute().await
     }
 }
@@ -567,6 +594,9 @@ impl PartialEq<Interface> for InterfaceSpec {
                 if self.mtu.is_none() {
                     other.mtu = None;
                 }
+                // offloads and namespaces are reconciled on their own
+                other.offloads = self.offloads;
+                other.netns.clone_from(&self.netns);
                 *self == other
             }
         }
@@ -686,6 +716,26 @@ fn extract_bridge_info(builder: &mut BridgePropertiesBuilder, datas: &[InfoBridg
     }
 }
 
+/// Read the link speed (in Mb/s) of an interface of the current network namespace from sysfs.
+///
+/// The speed is not reported in netlink link messages. Returns `None` for the interfaces
+/// without a known speed, like virtual interfaces or links without carrier (which report -1).
+#[must_use]
+pub fn link_speed(name: &InterfaceName) -> Option<u32> {
+    let path = std::path::Path::new("/sys/class/net")
+        .join(name.to_string())
+        .join("speed");
+    parse_link_speed(&std::fs::read_to_string(path).ok()?)
+}
+
+/// Parse the link speed of an interface, as reported by sysfs
+fn parse_link_speed(speed: &str) -> Option<u32> {
+    match speed.trim().parse::<u32>() {
+        Ok(0) | Err(_) => None,
+        Ok(speed) => Some(speed),
+    }
+}
+
 impl TryFromLinkMessage for Interface {
     type Error = InterfaceBuilderError;
 
@@ -778,6 +828,9 @@ impl TryFromLinkMessage for Interface {
                         builder.operational_state(OperationalState::Complex);
                     }
                 },
+                LinkAttribute::Carrier(carrier) => {
+                    builder.carrier(Some(*carrier != 0));
+                }
                 LinkAttribute::PhysSwitchId(phys_link) => {
                     if phys_link.len > SwitchId::MAX_LEN {
                         warn!(
@@ -857,7 +910,7 @@ impl TryFromLinkMessage for Interface {
 
 #[cfg(test)]
 mod tests {
-    use crate::interface::InterfaceSpec;
+    use crate::interface::{InterfaceSpec, parse_link_speed};
     use net::interface::{Interface, InterfaceProperties};
     use rekon::AsRequirement;
 
@@ -903,4 +956,14 @@ mod tests {
             },
         );
     }
+
+    #[test]
+    fn link_speed_parsing() {
+        assert_eq!(parse_link_speed("25000\n"), Some(25000));
+        assert_eq!(parse_link_speed("100"), Some(100));
+        // no carrier, or no known speed
+        assert_eq!(parse_link_speed("-1\n"), None);
+        assert_eq!(parse_link_speed("0\n"), None);
+        assert_eq!(parse_link_speed(""), None);
+    }
 }
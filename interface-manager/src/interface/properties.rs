@@ -4,7 +4,7 @@
 use crate::interface::bridge::BridgePropertiesSpec;
 use crate::interface::{PciNetdevPropertiesSpec, VrfPropertiesSpec, VtepPropertiesSpec};
 use net::interface::InterfaceProperties;
-use rekon::AsRequirement;
+use rekon::{AsRequirement, Normalize};
 use serde::{Deserialize, Serialize};
 
 /// The planned properties of a network interface.
@@ -23,6 +23,18 @@ pub enum InterfacePropertiesSpec {
     Vrf(VrfPropertiesSpec),
 }
 
+impl Normalize for InterfacePropertiesSpec {
+    fn normalize(&mut self) {
+        match self {
+            InterfacePropertiesSpec::Bridge(props) => props.normalize(),
+            InterfacePropertiesSpec::Vtep(props) => props.normalize(),
+            InterfacePropertiesSpec::Tap
+            | InterfacePropertiesSpec::Pci(_)
+            | InterfacePropertiesSpec::Vrf(_) => {}
+        }
+    }
+}
+
 impl AsRequirement<InterfacePropertiesSpec> for InterfaceProperties {
     type Requirement<'a>
         = Option<InterfacePropertiesSpec>
@@ -8,7 +8,7 @@ use net::interface::{Interface, InterfaceProperties, VtepProperties};
 use net::ipv4::UnicastIpv4Addr;
 use net::udp::port::UdpPort;
 use net::vxlan::{Vni, Vxlan};
-use rekon::{AsRequirement, Remove, Update};
+use rekon::{AsRequirement, Normalize, Remove, Update};
 use serde::{Deserialize, Serialize};
 
 /// The "planned" properties of a VTEP / vxlan device.
@@ -33,13 +33,28 @@ pub struct VtepPropertiesSpec {
     /// The local IPv4 address to be used for this device.
     pub local: UnicastIpv4Addr,
     /// The ttl to be used for packets encapsulated by this device.
-    #[builder(default = 64)]
+    #[builder(default = VtepPropertiesSpec::DEFAULT_TTL)]
     pub ttl: u8,
     /// The UDP port on which the tunnel is terminated
     #[builder(default = Vxlan::PORT)]
     pub port: UdpPort,
 }
 
+impl VtepPropertiesSpec {
+    /// The ttl used for encapsulated packets, unless specified otherwise
+    pub const DEFAULT_TTL: u8 = 64;
+}
+
+impl Normalize for VtepPropertiesSpec {
+    /// A ttl of zero leaves the choice of the ttl to the kernel, which then reports zero while
+    /// using its default: make the default explicit.
+    fn normalize(&mut self) {
+        if self.ttl == 0 {
+            self.ttl = Self::DEFAULT_TTL;
+        }
+    }
+}
+
 impl AsRequirement<VtepPropertiesSpec> for VtepProperties {
     type Requirement<'a>
         = Option<VtepPropertiesSpec>
@@ -138,7 +153,7 @@ mod contract {
 mod tests {
     use crate::interface::VtepPropertiesSpec;
     use net::interface::VtepProperties;
-    use rekon::AsRequirement;
+    use rekon::{AsRequirement, Normalize};
 
     #[test]
     fn as_requirement_obeys_contract() {
@@ -179,4 +194,21 @@ mod tests {
             },
         );
     }
+
+    #[test]
+    fn normalize_is_idempotent() {
+        bolero::check!()
+            .with_type()
+            .for_each(|required: &VtepPropertiesSpec| {
+                let mut normalized = required.clone();
+                normalized.normalize();
+                assert_ne!(normalized.ttl, 0);
+                if required.ttl != 0 {
+                    assert_eq!(&normalized, required);
+                }
+                let mut twice = normalized.clone();
+                twice.normalize();
+                assert_eq!(twice, normalized);
+            });
+    }
 }
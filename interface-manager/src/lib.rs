@@ -20,6 +20,9 @@ use std::marker::PhantomData;
 
 pub mod interface;
 pub mod monitor;
+pub mod neighbor;
+pub mod netns;
+pub mod offload;
 pub mod tc;
 
 use rtnetlink::Handle;
@@ -0,0 +1,142 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! A watcher of the changes of the kernel interfaces and of their addresses.
+//!
+//! Rather than observing the kernel periodically to find out whether it diverged from what is
+//! required, reconciliation can wait for the kernel to notify changes. The [`ChangeWatcher`]
+//! subscribes to the netlink notifications of links and addresses and, once they settle, that
+//! is, once no notification was received for the debounce period, bumps a counter of changes
+//! in a watch channel. A burst of changes, like those that reconciliation itself causes, thus
+//! results in a single wake-up of the subscribers.
+
+use concurrency::sync::Arc;
+use rtnetlink::MulticastGroup;
+use rtnetlink::packet_core::NetlinkPayload;
+use rtnetlink::packet_route::RouteNetlinkMessage;
+use tokio::sync::watch;
+use tokio::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+#[allow(unused)]
+use tracing::{debug, error, info, warn};
+
+/// Watcher of the changes of the kernel interfaces and of their addresses
+pub struct ChangeWatcher {
+    debounce: Duration,
+    tx: watch::Sender<u64>,
+    ct: CancellationToken,
+}
+
+/// Tell if a netlink notification reports a change of an interface or of its addresses
+fn is_change(msg: &RouteNetlinkMessage) -> bool {
+    matches!(
+        msg,
+        RouteNetlinkMessage::NewLink(_)
+            | RouteNetlinkMessage::DelLink(_)
+            | RouteNetlinkMessage::NewAddress(_)
+            | RouteNetlinkMessage::DelAddress(_)
+    )
+}
+
+impl ChangeWatcher {
+    /// Create a watcher notifying changes once no other change was seen for `debounce`
+    #[must_use]
+    pub fn new(ct: CancellationToken, debounce: Duration) -> Self {
+        let (tx, _) = watch::channel(0);
+        Self { debounce, tx, ct }
+    }
+
+    /// Subscribe to the changes. The value received is the number of changes seen so far.
+    #[must_use]
+    pub fn subscribe(&self) -> watch::Receiver<u64> {
+        self.tx.subscribe()
+    }
+
+    /// Watch the changes of the kernel interfaces until cancelled
+    ///
+    /// # Errors
+    ///
+    /// This method fails if a netlink connection cannot be created.
+    pub async fn run(watcher: Arc<Self>) -> Result<(), ()> {
+        info!(
+            "Starting kernel change watcher, with a debounce of {:?}",
+            watcher.debounce
+        );
+        let groups = [
+            MulticastGroup::Link,
+            MulticastGroup::Ipv4Ifaddr,
+            MulticastGroup::Ipv6Ifaddr,
+        ];
+        let (conn, _, mut messages) = rtnetlink::new_multicast_connection(&groups)
+            .inspect_err(|e| error!("Failed to open netlink connection: {e}"))
+            .map_err(|_| ())?;
+
+        tokio::spawn(conn);
+
+        // number of changes seen since they were last notified, and when they settle
+        let mut pending: u64 = 0;
+        let mut settled = Instant::now();
+        loop {
+            tokio::select! {
+                nlmsg = messages.recv() => {
+                    match nlmsg {
+                        Ok((msg, _)) => {
+                            let (_hdr, payload) = msg.into_parts();
+                            if let NetlinkPayload::InnerMessage(msg) = payload
+                                && is_change(&msg)
+                            {
+                                pending += 1;
+                                settled = Instant::now() + watcher.debounce;
+                            }
+                        }
+                        Err(e) => {
+                            error!("Recv error in netlink socket: {e}");
+                            break;
+                        }
+                    }
+                }
+                () = tokio::time::sleep_until(settled), if pending > 0 => {
+                    debug!("Kernel interfaces changed ({pending} notifications)");
+                    // unlike send, this records the changes even if there are no subscribers yet
+                    watcher.tx.send_modify(|changes| *changes += pending);
+                    pending = 0;
+                }
+                () = watcher.ct.cancelled() => {
+                    info!("Kernel change watcher got cancelled");
+                    break;
+                }
+            }
+        }
+        info!("Kernel change watcher is shutting down now");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::is_change;
+    use rtnetlink::packet_route::RouteNetlinkMessage;
+    use rtnetlink::packet_route::address::AddressMessage;
+    use rtnetlink::packet_route::link::LinkMessage;
+    use rtnetlink::packet_route::route::RouteMessage;
+
+    #[test]
+    fn test_is_change() {
+        assert!(is_change(&RouteNetlinkMessage::NewLink(
+            LinkMessage::default()
+        )));
+        assert!(is_change(&RouteNetlinkMessage::DelLink(
+            LinkMessage::default()
+        )));
+        assert!(is_change(&RouteNetlinkMessage::NewAddress(
+            AddressMessage::default()
+        )));
+        assert!(is_change(&RouteNetlinkMessage::DelAddress(
+            AddressMessage::default()
+        )));
+        assert!(!is_change(&RouteNetlinkMessage::NewRoute(
+            RouteMessage::default()
+        )));
+    }
+}
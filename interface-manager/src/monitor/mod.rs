@@ -5,8 +5,22 @@
 //! and disseminates them over a broadcast channel. It does not make any attempt to interpret
 //! the events received via netlink. The interface monitor reports events on ethernet interfaces.
 //! For testing, it can be allowed to report events for other types of network devices.
+//!
+//! In addition to the raw events, the monitor disseminates the operational state transitions
+//! derived from them (see [`LinkTransition`]) over a second broadcast channel.
+//!
+//! The [`ChangeWatcher`] notifies, instead, that interfaces or their addresses changed, for
+//! reconciliation to happen on changes rather than periodically.
 
-use concurrency::sync::Arc;
+mod changes;
+mod transition;
+
+pub use changes::ChangeWatcher;
+
+#[allow(unused_imports)] // re-export
+pub use transition::*;
+
+use concurrency::sync::{Arc, Mutex};
 use net::interface::{InterfaceIndex, InterfaceName};
 use rtnetlink::MulticastGroup;
 use rtnetlink::packet_core::{NetlinkMessage, NetlinkPayload};
@@ -48,15 +62,27 @@ impl std::fmt::Display for EthEvent {
 /// Interface monitor
 pub struct InterfaceMonitor {
     tx: broadcast::Sender<EthEvent>,
+    transitions: broadcast::Sender<LinkTransition>,
+    tracker: Mutex<TransitionTracker>,
     ct: CancellationToken,
     tracked: Vec<InterfaceName>,
 }
+impl std::fmt::Debug for InterfaceMonitor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("InterfaceMonitor")
+            .field("tracked", &self.tracked)
+            .finish_non_exhaustive()
+    }
+}
 impl InterfaceMonitor {
     #[must_use]
     pub fn new(ct: CancellationToken, track: &[InterfaceName]) -> Self {
         let (tx, _) = broadcast::channel::<EthEvent>(100);
+        let (transitions, _) = broadcast::channel::<LinkTransition>(100);
         Self {
             tx,
+            transitions,
+            tracker: Mutex::new(TransitionTracker::default()),
             ct,
             tracked: track.into(),
         }
@@ -65,6 +91,33 @@ impl InterfaceMonitor {
     pub fn subscribe(&self) -> broadcast::Receiver<EthEvent> {
         self.tx.subscribe()
     }
+    /// Subscribe to the operational state transitions of the tracked interfaces.
+    #[must_use]
+    pub fn subscribe_transitions(&self) -> broadcast::Receiver<LinkTransition> {
+        self.transitions.subscribe()
+    }
+    /// Attribute the next administrative state change of `ifindex` to the reconciler.
+    pub fn expect_reconcile(&self, ifindex: InterfaceIndex) {
+        self.tracker.lock().expect_reconcile(ifindex);
+    }
+    /// Tell if the next administrative state change of `ifindex` is attributed to the reconciler.
+    #[must_use]
+    pub fn expects_reconcile(&self, ifindex: InterfaceIndex) -> bool {
+        self.tracker.lock().expects_reconcile(ifindex)
+    }
+    /// Derive a [`LinkTransition`] from an event (if any) and disseminate it.
+    fn publish_transition(&self, event: &EthEvent) {
+        let Some(transition) = self.tracker.lock().observe(event) else {
+            return;
+        };
+        if transition.flapping {
+            warn!("{transition}");
+        } else {
+            info!("{transition}");
+        }
+        // not having subscribers to transitions is perfectly normal
+        let _ = self.transitions.send(transition);
+    }
 
     /// Convert a netlink message to an `EthEvent` if it is a `NewLink` message for a tracked interface
     fn netlink_to_event(&self, msg: NetlinkMessage<RouteNetlinkMessage>) -> Option<EthEvent> {
@@ -138,8 +191,11 @@ impl InterfaceMonitor {
                 nlmsg = messages.recv() => {
                     match nlmsg {
                         Ok((msg, _)) => {
-                            if let Some(event) = monitor.netlink_to_event(msg) && tx.send(event).is_err() {
-                                warn!("Warning, there are no link event readers!");
+                            if let Some(event) = monitor.netlink_to_event(msg) {
+                                monitor.publish_transition(&event);
+                                if tx.send(event).is_err() {
+                                    warn!("Warning, there are no link event readers!");
+                                }
                             }
                         }
                         Err(e) => {
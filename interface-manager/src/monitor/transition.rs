@@ -0,0 +1,289 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Interpretation of raw [`EthEvent`]s as operational state transitions.
+//!
+//! The [`InterfaceMonitor`] reports every netlink link message it receives for a tracked interface,
+//! most of which do not change anything of interest.
+//! The [`TransitionTracker`] remembers the last known state of each interface and only reports
+//! actual changes of the operational state, annotated with a timestamp, a (best-effort) cause, and
+//! whether the interface is currently flapping.
+//!
+//! [`InterfaceMonitor`]: crate::monitor::InterfaceMonitor
+
+use crate::monitor::EthEvent;
+use net::interface::{InterfaceIndex, InterfaceName};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Display;
+use std::time::{Duration, Instant, SystemTime};
+
+/// The operational state of a link as derived from an [`EthEvent`].
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum LinkOperState {
+    /// The interface is up, its lower layer is up, it is running and has carrier.
+    Up,
+    /// Any other combination of flags.
+    Down,
+}
+
+impl From<&EthEvent> for LinkOperState {
+    fn from(event: &EthEvent) -> Self {
+        if event.ifup && event.iflowerup && event.ifrunning && event.carrier {
+            LinkOperState::Up
+        } else {
+            LinkOperState::Down
+        }
+    }
+}
+
+impl Display for LinkOperState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkOperState::Up => f.pad("up"),
+            LinkOperState::Down => f.pad("down"),
+        }
+    }
+}
+
+/// The (best-effort) cause of an operational state transition.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum TransitionCause {
+    /// The carrier of the interface changed while its administrative state did not.
+    Carrier,
+    /// The administrative state of the interface changed out-of-band.
+    Admin,
+    /// The administrative state of the interface was changed by the reconciler.
+    Reconcile,
+}
+
+impl Display for TransitionCause {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransitionCause::Carrier => f.pad("carrier"),
+            TransitionCause::Admin => f.pad("admin"),
+            TransitionCause::Reconcile => f.pad("reconcile"),
+        }
+    }
+}
+
+/// An operational state transition of a tracked interface.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LinkTransition {
+    pub name: InterfaceName,
+    pub ifindex: InterfaceIndex,
+    pub timestamp: SystemTime,
+    pub from: LinkOperState,
+    pub to: LinkOperState,
+    pub cause: TransitionCause,
+    /// True if the interface changed state at least [`FlapPolicy::threshold`] times within the
+    /// last [`FlapPolicy::window`] (including this transition).
+    pub flapping: bool,
+}
+
+impl Display for LinkTransition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} ({}): oper state {} -> {} (cause: {})",
+            self.name, self.ifindex, self.from, self.to, self.cause
+        )?;
+        if self.flapping {
+            write!(f, " [flapping]")?;
+        }
+        Ok(())
+    }
+}
+
+/// Policy used to decide when an interface is flapping.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FlapPolicy {
+    /// Sliding window over which transitions are counted.
+    pub window: Duration,
+    /// Number of transitions within `window` above which an interface is considered flapping.
+    pub threshold: usize,
+}
+
+impl Default for FlapPolicy {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(30),
+            threshold: 4,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct LinkHistory {
+    admin_up: bool,
+    oper: LinkOperState,
+    recent: VecDeque<Instant>,
+}
+
+/// Tracks the state of a set of interfaces and turns [`EthEvent`]s into [`LinkTransition`]s.
+#[derive(Debug, Default)]
+pub struct TransitionTracker {
+    policy: FlapPolicy,
+    links: HashMap<InterfaceIndex, LinkHistory>,
+    reconciling: HashSet<InterfaceIndex>,
+}
+
+impl TransitionTracker {
+    #[must_use]
+    pub fn new(policy: FlapPolicy) -> Self {
+        Self {
+            policy,
+            links: HashMap::new(),
+            reconciling: HashSet::new(),
+        }
+    }
+
+    /// Attribute the next administrative state change of `ifindex` to the reconciler.
+    pub fn expect_reconcile(&mut self, ifindex: InterfaceIndex) {
+        self.reconciling.insert(ifindex);
+    }
+
+    /// Tell if the next administrative state change of `ifindex` is attributed to the reconciler.
+    #[must_use]
+    pub fn expects_reconcile(&self, ifindex: InterfaceIndex) -> bool {
+        self.reconciling.contains(&ifindex)
+    }
+
+    /// Feed an event to the tracker.
+    ///
+    /// Returns a [`LinkTransition`] if the operational state of the interface changed.
+    /// The first event seen for an interface only establishes a baseline and never produces a
+    /// transition.
+    pub fn observe(&mut self, event: &EthEvent) -> Option<LinkTransition> {
+        self.observe_at(event, Instant::now(), SystemTime::now())
+    }
+
+    fn observe_at(
+        &mut self,
+        event: &EthEvent,
+        now: Instant,
+        timestamp: SystemTime,
+    ) -> Option<LinkTransition> {
+        let oper = LinkOperState::from(event);
+        let Some(history) = self.links.get_mut(&event.ifindex) else {
+            self.links.insert(
+                event.ifindex,
+                LinkHistory {
+                    admin_up: event.ifup,
+                    oper,
+                    recent: VecDeque::new(),
+                },
+            );
+            return None;
+        };
+        let admin_changed = history.admin_up != event.ifup;
+        history.admin_up = event.ifup;
+        let cause = if admin_changed {
+            if self.reconciling.remove(&event.ifindex) {
+                TransitionCause::Reconcile
+            } else {
+                TransitionCause::Admin
+            }
+        } else {
+            TransitionCause::Carrier
+        };
+        if history.oper == oper {
+            return None;
+        }
+        let from = history.oper;
+        history.oper = oper;
+        while let Some(oldest) = history.recent.front() {
+            if now.duration_since(*oldest) > self.policy.window {
+                history.recent.pop_front();
+            } else {
+                break;
+            }
+        }
+        history.recent.push_back(now);
+        Some(LinkTransition {
+            name: event.name.clone(),
+            ifindex: event.ifindex,
+            timestamp,
+            from,
+            to: oper,
+            cause,
+            flapping: history.recent.len() >= self.policy.threshold,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::num::NonZero;
+
+    fn event(ifup: bool, carrier: bool) -> EthEvent {
+        EthEvent {
+            name: InterfaceName::try_from("eth0").unwrap(),
+            ifindex: InterfaceIndex::new(NonZero::new(2).unwrap()),
+            ifup,
+            iflowerup: carrier,
+            ifrunning: carrier,
+            carrier,
+            carrierup: 0,
+            carrierdown: 0,
+        }
+    }
+
+    #[test]
+    fn first_event_is_baseline() {
+        let mut tracker = TransitionTracker::default();
+        assert!(tracker.observe(&event(true, true)).is_none());
+        assert!(tracker.observe(&event(true, true)).is_none());
+    }
+
+    #[test]
+    fn causes_are_attributed() {
+        let mut tracker = TransitionTracker::new(FlapPolicy::default());
+        let ifindex = event(true, true).ifindex;
+        tracker.observe(&event(true, true));
+
+        let t = tracker.observe(&event(true, false)).unwrap();
+        assert_eq!(
+            (t.from, t.to, t.cause),
+            (
+                LinkOperState::Up,
+                LinkOperState::Down,
+                TransitionCause::Carrier
+            )
+        );
+
+        let t = tracker.observe(&event(false, false));
+        assert!(
+            t.is_none(),
+            "admin down of an oper down link is not a transition"
+        );
+
+        tracker.expect_reconcile(ifindex);
+        let t = tracker.observe(&event(true, true)).unwrap();
+        assert_eq!(t.cause, TransitionCause::Reconcile);
+
+        let t = tracker.observe(&event(false, true)).unwrap();
+        assert_eq!(t.cause, TransitionCause::Admin);
+    }
+
+    #[test]
+    fn flapping_is_detected_within_window() {
+        let policy = FlapPolicy {
+            window: Duration::from_secs(10),
+            threshold: 3,
+        };
+        let mut tracker = TransitionTracker::new(policy);
+        let start = Instant::now();
+        let ts = SystemTime::now();
+        tracker.observe_at(&event(true, true), start, ts);
+        let t1 = tracker.observe_at(&event(true, false), start + Duration::from_secs(1), ts);
+        let t2 = tracker.observe_at(&event(true, true), start + Duration::from_secs(2), ts);
+        let t3 = tracker.observe_at(&event(true, false), start + Duration::from_secs(3), ts);
+        assert!(!t1.unwrap().flapping);
+        assert!(!t2.unwrap().flapping);
+        assert!(t3.unwrap().flapping);
+        // long after the window, transitions are no longer flapping
+        let t4 = tracker.observe_at(&event(true, true), start + Duration::from_secs(60), ts);
+        assert!(!t4.unwrap().flapping);
+    }
+}
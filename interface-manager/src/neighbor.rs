@@ -0,0 +1,337 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Reconcile the intended static neighbors (ARP / ND entries) of the linux interfaces with the
+//! observed neighbor tables.
+
+use crate::Manager;
+use net::eth::mac::{Mac, SourceMac};
+use net::interface::{InterfaceIndex, InterfaceIndexError, InterfaceName};
+use rekon::{Create, Normalize, Op, Reconcile, Remove, Update};
+use rtnetlink::packet_route::AddressFamily;
+use rtnetlink::packet_route::neighbour::{
+    NeighbourAddress, NeighbourAttribute, NeighbourMessage, NeighbourState,
+};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+/// The state of an observed neighbor, as far as reconciliation is concerned.
+#[derive(Copy, Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum NeighborState {
+    /// Configured, never expires.
+    Permanent,
+    /// Learned (or installed by a routing daemon), whether known to be reachable or not.
+    Learned,
+    /// Resolution is in progress or failed.
+    Unresolved,
+}
+
+impl From<NeighbourState> for NeighborState {
+    fn from(state: NeighbourState) -> Self {
+        match state {
+            NeighbourState::Permanent => NeighborState::Permanent,
+            NeighbourState::Reachable
+            | NeighbourState::Stale
+            | NeighbourState::Delay
+            | NeighbourState::Probe
+            | NeighbourState::Noarp => NeighborState::Learned,
+            _ => NeighborState::Unresolved,
+        }
+    }
+}
+
+/// An observed neighbor (ARP or ND entry) of a network interface.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct Neighbor {
+    /// The index of the interface the neighbor is reachable over.
+    pub ifindex: InterfaceIndex,
+    /// The IP address of the neighbor.
+    pub address: IpAddr,
+    /// The MAC address of the neighbor, if known.
+    pub mac: Option<Mac>,
+    /// The state of the entry.
+    pub state: NeighborState,
+}
+
+impl Neighbor {
+    /// True if the MAC address of the neighbor may be used to reach it.
+    #[must_use]
+    pub fn is_resolved(&self) -> bool {
+        self.mac.is_some() && self.state != NeighborState::Unresolved
+    }
+}
+
+/// Errors which may occur when building a [`Neighbor`] from a netlink message.
+#[derive(Debug, thiserror::Error)]
+pub enum NeighborMessageError {
+    /// The message refers to an illegal interface index.
+    #[error(transparent)]
+    Ifindex(#[from] InterfaceIndexError),
+    /// The message has no IP destination (e.g. it is a bridge FDB entry).
+    #[error("neighbor message has no IP destination")]
+    NoDestination,
+}
+
+impl TryFrom<&NeighbourMessage> for Neighbor {
+    type Error = NeighborMessageError;
+
+    fn try_from(message: &NeighbourMessage) -> Result<Self, Self::Error> {
+        let ifindex = InterfaceIndex::try_new(message.header.ifindex)?;
+        let mut address = None;
+        let mut mac = None;
+        for attribute in &message.attributes {
+            match attribute {
+                NeighbourAttribute::Destination(NeighbourAddress::Inet(ip)) => {
+                    address = Some(IpAddr::V4(*ip));
+                }
+                NeighbourAttribute::Destination(NeighbourAddress::Inet6(ip)) => {
+                    address = Some(IpAddr::V6(*ip));
+                }
+                NeighbourAttribute::LinkLocalAddress(raw) => {
+                    mac = <[u8; 6]>::try_from(raw.as_slice()).ok().map(Mac::from);
+                }
+                _ => {}
+            }
+        }
+        Ok(Self {
+            ifindex,
+            address: address.ok_or(NeighborMessageError::NoDestination)?,
+            mac,
+            state: message.header.state.into(),
+        })
+    }
+}
+
+/// A static neighbor (ARP or ND entry) required on a network interface.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub struct NeighborSpec {
+    /// The name of the interface the neighbor is reachable over.
+    pub interface: InterfaceName,
+    /// The IP address of the neighbor.
+    pub address: IpAddr,
+    /// The MAC address of the neighbor.
+    pub mac: SourceMac,
+}
+
+impl Normalize for NeighborSpec {
+    /// IPv4-mapped IPv6 addresses stand for IPv4 neighbors, which are ARP entries reported with
+    /// their IPv4 address.
+    fn normalize(&mut self) {
+        self.address = self.address.to_canonical();
+    }
+}
+
+impl PartialEq<Neighbor> for NeighborSpec {
+    /// Note that the interface is not compared: callers are expected to match it by index.
+    fn eq(&self, other: &Neighbor) -> bool {
+        self.address == other.address
+            && other.state == NeighborState::Permanent
+            && other.mac == Some(self.mac.inner())
+    }
+}
+
+impl Create for Manager<Neighbor> {
+    type Requirement<'a>
+        = (InterfaceIndex, &'a NeighborSpec)
+    where
+        Self: 'a;
+    type Outcome<'a>
+        = Result<(), rtnetlink::Error>
+    where
+        Self: 'a;
+
+    async fn create<'a>(
+        &self,
+        (ifindex, requirement): (InterfaceIndex, &'a NeighborSpec),
+    ) -> Result<(), rtnetlink::Error>
+    where
+        Self: 'a,
+    {
+        self.handle
+            .neighbours()
+            .add(ifindex.to_u32(), requirement.address)
+            .link_local_address(&requirement.mac.inner().0)
+            .state(NeighbourState::Permanent)
+            .execute()
+            .await
+    }
+}
+
+impl Update for Manager<Neighbor> {
+    type Requirement<'a>
+        = (InterfaceIndex, &'a NeighborSpec)
+    where
+        Self: 'a;
+    type Observation<'a>
+        = &'a Neighbor
+    where
+        Self: 'a;
+    type Outcome<'a>
+        = Result<(), rtnetlink::Error>
+    where
+        Self: 'a;
+
+    async fn update<'a>(
+        &self,
+        (ifindex, requirement): (InterfaceIndex, &'a NeighborSpec),
+        _observation: &'a Neighbor,
+    ) -> Result<(), rtnetlink::Error>
+    where
+        Self: 'a,
+    {
+        self.handle
+            .neighbours()
+            .add(ifindex.to_u32(), requirement.address)
+            .link_local_address(&requirement.mac.inner().0)
+            .state(NeighbourState::Permanent)
+            .replace()
+            .execute()
+            .await
+    }
+}
+
+impl Remove for Manager<Neighbor> {
+    type Observation<'a>
+        = &'a Neighbor
+    where
+        Self: 'a;
+    type Outcome<'a>
+        = Result<(), rtnetlink::Error>
+    where
+        Self: 'a;
+
+    async fn remove<'a>(&self, observation: &'a Neighbor) -> Result<(), rtnetlink::Error>
+    where
+        Self: 'a,
+    {
+        let mut message = NeighbourMessage::default();
+        message.header.ifindex = observation.ifindex.to_u32();
+        let destination = match observation.address {
+            IpAddr::V4(ip) => {
+                message.header.family = AddressFamily::Inet;
+                NeighbourAddress::Inet(ip)
+            }
+            IpAddr::V6(ip) => {
+                message.header.family = AddressFamily::Inet6;
+                NeighbourAddress::Inet6(ip)
+            }
+        };
+        message
+            .attributes
+            .push(NeighbourAttribute::Destination(destination));
+        self.handle.neighbours().del(message).execute().await
+    }
+}
+
+impl Reconcile for Manager<Neighbor> {
+    type Requirement<'a>
+        = (InterfaceIndex, &'a NeighborSpec)
+    where
+        Self: 'a;
+    type Observation<'a>
+        = Option<&'a Neighbor>
+    where
+        Self: 'a;
+    type Outcome<'a>
+        = Option<Op<'a, Self>>
+    where
+        Self: 'a;
+
+    async fn reconcile<'a>(
+        &self,
+        requirement: (InterfaceIndex, &'a NeighborSpec),
+        observation: Option<&'a Neighbor>,
+    ) -> Self::Outcome<'a>
+    where
+        Self: 'a,
+    {
+        match observation {
+            None => Some(Op::Create(self.create(requirement).await)),
+            Some(observed) => {
+                if requirement.1 == observed {
+                    return None;
+                }
+                Some(Op::Update(self.update(requirement, observed).await))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn message(state: NeighbourState, mac: Option<[u8; 6]>) -> NeighbourMessage {
+        let mut message = NeighbourMessage::default();
+        message.header.ifindex = 2;
+        message.header.state = state;
+        message
+            .attributes
+            .push(NeighbourAttribute::Destination(NeighbourAddress::Inet(
+                Ipv4Addr::new(10, 0, 0, 1),
+            )));
+        if let Some(mac) = mac {
+            message
+                .attributes
+                .push(NeighbourAttribute::LinkLocalAddress(mac.to_vec()));
+        }
+        message
+    }
+
+    #[test]
+    fn neighbor_from_message() {
+        let mac = [0x02, 0, 0, 0, 0, 0x01];
+        let neighbor = Neighbor::try_from(&message(NeighbourState::Reachable, Some(mac))).unwrap();
+        assert_eq!(neighbor.address, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+        assert_eq!(neighbor.mac, Some(Mac::from(mac)));
+        assert_eq!(neighbor.state, NeighborState::Learned);
+        assert!(neighbor.is_resolved());
+
+        let neighbor = Neighbor::try_from(&message(NeighbourState::Incomplete, None)).unwrap();
+        assert_eq!(neighbor.state, NeighborState::Unresolved);
+        assert!(!neighbor.is_resolved());
+
+        let mut fdb = message(NeighbourState::Permanent, Some(mac));
+        fdb.attributes.remove(0);
+        assert!(matches!(
+            Neighbor::try_from(&fdb),
+            Err(NeighborMessageError::NoDestination)
+        ));
+    }
+
+    #[test]
+    fn neighbor_spec_is_met() {
+        let mac = Mac::from([0x02, 0, 0, 0, 0, 0x01]);
+        let spec = NeighborSpec {
+            interface: InterfaceName::try_from("eth0-tap").unwrap(),
+            address: IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)),
+            mac: SourceMac::new(mac).unwrap(),
+        };
+        let mut observed =
+            Neighbor::try_from(&message(NeighbourState::Permanent, Some(mac.0))).unwrap();
+        assert!(spec == observed);
+        observed.state = NeighborState::Learned;
+        assert!(spec != observed);
+        observed.state = NeighborState::Permanent;
+        observed.mac = Some(Mac::from([0x02, 0, 0, 0, 0, 0x02]));
+        assert!(spec != observed);
+    }
+
+    #[test]
+    fn neighbor_spec_normalization() {
+        let mut spec = NeighborSpec {
+            interface: InterfaceName::try_from("eth0-tap").unwrap(),
+            address: IpAddr::V6(Ipv4Addr::new(10, 0, 0, 1).to_ipv6_mapped()),
+            mac: SourceMac::new(Mac::from([0x02, 0, 0, 0, 0, 0x01])).unwrap(),
+        };
+        spec.normalize();
+        assert_eq!(spec.address, IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1)));
+        let observed = Neighbor::try_from(&message(
+            NeighbourState::Permanent,
+            Some(spec.mac.inner().0),
+        ))
+        .unwrap();
+        assert!(spec == observed);
+    }
+}
@@ -0,0 +1,570 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Manage network interfaces across network namespaces.
+//!
+//! A netlink socket operates on the network namespace it was opened in, whichever thread uses it
+//! afterwards. [`NetnsHandles`] therefore keeps a netlink handle per (named) network namespace:
+//! the socket of each is opened by a short-lived thread which joins the namespace, while the
+//! connection task runs on the tokio runtime of the caller like any other.
+//!
+//! Links are moved from one namespace to another with [`Manager<NetnsName>`].
+//!
+//! Work that must happen inside a namespace, e.g. opening sockets or configuring the loopback
+//! of a fixture, is handed over to a [`NetnsExecutor`]: a thread which joins (and possibly
+//! creates) the namespace and then runs the closures it is given there, on a tokio runtime of
+//! its own.
+
+use crate::Manager;
+use concurrency::sync::{Arc, Mutex};
+use net::interface::Interface;
+use nix::sched::CloneFlags;
+use rekon::Update;
+use rtnetlink::{Handle, LinkUnspec, NetworkNamespace};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs::File;
+use std::future::Future;
+use std::os::fd::AsRawFd;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::thread::JoinHandle;
+use tokio::runtime::Runtime;
+use tokio::sync::oneshot;
+use tracing::{debug, error};
+
+/// The directory where named network namespaces are mounted (as `ip netns` does)
+pub const NETNS_DIR: &str = "/run/netns";
+
+/// The network namespace of the current thread
+const CURRENT_NETNS: &str = "/proc/thread-self/ns/net";
+
+/// The name of a network namespace mounted in [`NETNS_DIR`]
+#[derive(Clone, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct NetnsName(String);
+
+/// Errors when managing network namespaces
+#[derive(Debug, thiserror::Error)]
+pub enum NetnsError {
+    #[error("Illegal network namespace name '{0}'")]
+    IllegalName(String),
+    #[error("Failed to open network namespace {0}: {1}")]
+    Open(NetnsName, std::io::Error),
+    #[error("Failed to join network namespace {0}: {1}")]
+    Join(NetnsName, nix::Error),
+    #[error("Failed to open a netlink connection in network namespace {0}: {1}")]
+    Connection(NetnsName, std::io::Error),
+    #[error("A thread in network namespace {0} panicked")]
+    Thread(NetnsName),
+    #[error("No tokio runtime to run the netlink connection on")]
+    NoRuntime,
+    #[error("Failed to create network namespace {0}: {1}")]
+    Create(NetnsName, rtnetlink::Error),
+    #[error("Failed to remove network namespace {0}: {1}")]
+    Remove(NetnsName, rtnetlink::Error),
+    #[error("Failed to spawn a thread in network namespace {0}: {1}")]
+    Spawn(NetnsName, std::io::Error),
+    #[error("Failed to build a tokio runtime in network namespace {0}: {1}")]
+    Runtime(NetnsName, std::io::Error),
+    #[error("A closure run in network namespace {0} panicked")]
+    Panicked(NetnsName),
+    #[error("The executor of network namespace {0} is stopped")]
+    Stopped(NetnsName),
+}
+
+impl NetnsName {
+    /// The maximum length of a network namespace name, that of a file name
+    pub const MAX_LEN: usize = 255;
+
+    /// The path where the network namespace is mounted
+    #[must_use]
+    pub fn path(&self) -> PathBuf {
+        Path::new(NETNS_DIR).join(&self.0)
+    }
+
+    /// Open the network namespace, e.g. to move links into it
+    ///
+    /// # Errors
+    ///
+    /// Fails if the network namespace does not exist.
+    pub fn open(&self) -> Result<File, NetnsError> {
+        File::open(self.path()).map_err(|e| NetnsError::Open(self.clone(), e))
+    }
+}
+
+impl TryFrom<String> for NetnsName {
+    type Error = NetnsError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let legal = !value.is_empty()
+            && value.len() <= Self::MAX_LEN
+            && value != "."
+            && value != ".."
+            && !value.contains(['/', '\0']);
+        if legal {
+            Ok(Self(value))
+        } else {
+            Err(NetnsError::IllegalName(value))
+        }
+    }
+}
+
+impl TryFrom<&str> for NetnsName {
+    type Error = NetnsError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        Self::try_from(value.to_string())
+    }
+}
+
+impl From<NetnsName> for String {
+    fn from(value: NetnsName) -> Self {
+        value.0
+    }
+}
+
+impl AsRef<str> for NetnsName {
+    fn as_ref(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl Display for NetnsName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// Move the current thread into a network namespace
+fn join(netns: &NetnsName) -> Result<(), NetnsError> {
+    let file = netns.open()?;
+    nix::sched::setns(&file, CloneFlags::CLONE_NEWNET)
+        .map_err(|e| NetnsError::Join(netns.clone(), e))
+}
+
+/// Open a netlink connection in a network namespace, whose task is spawned on the current tokio
+/// runtime.
+fn connect(netns: &NetnsName) -> Result<Handle, NetnsError> {
+    let runtime = tokio::runtime::Handle::try_current().map_err(|_| NetnsError::NoRuntime)?;
+    // only the thread which joins the namespace is affected, and it exits right after
+    std::thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                join(netns)?;
+                let _guard = runtime.enter();
+                let (connection, handle, _) = rtnetlink::new_connection()
+                    .map_err(|e| NetnsError::Connection(netns.clone(), e))?;
+                runtime.spawn(connection);
+                Ok(handle)
+            })
+            .join()
+            .unwrap_or_else(|_| Err(NetnsError::Thread(netns.clone())))
+    })
+}
+
+/// Netlink handles for the current network namespace and for named network namespaces
+#[derive(Debug)]
+pub struct NetnsHandles {
+    current: Arc<Handle>,
+    handles: Mutex<BTreeMap<NetnsName, Arc<Handle>>>,
+}
+
+impl NetnsHandles {
+    /// Create a pool of handles, using `current` for the current network namespace
+    #[must_use]
+    pub fn new(current: Arc<Handle>) -> Self {
+        Self {
+            current,
+            handles: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// The handle of a network namespace, that of the current one if `netns` is `None`. Returns
+    /// `None` if no handle was opened for the namespace.
+    #[must_use]
+    pub fn get(&self, netns: Option<&NetnsName>) -> Option<Arc<Handle>> {
+        match netns {
+            None => Some(self.current.clone()),
+            Some(netns) => self.handles.lock().get(netns).cloned(),
+        }
+    }
+
+    /// Open handles for the network namespaces in `netns`, unless they are open already, and
+    /// close those of the other namespaces.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a network namespace does not exist or can't be joined, or if there is no current
+    /// tokio runtime to run the netlink connection on. The handles of the other namespaces are
+    /// opened regardless.
+    pub fn retain<'a>(
+        &self,
+        netns: impl IntoIterator<Item = &'a NetnsName>,
+    ) -> Result<(), NetnsError> {
+        let mut handles = self.handles.lock();
+        let mut retained = BTreeMap::new();
+        let mut result = Ok(());
+        for netns in netns {
+            if retained.contains_key(netns) {
+                continue;
+            }
+            let handle = match handles.remove(netns) {
+                Some(handle) => handle,
+                None => match connect(netns) {
+                    Ok(handle) => {
+                        debug!("Opened netlink connection in network namespace {netns}");
+                        Arc::new(handle)
+                    }
+                    Err(e) => {
+                        result = Err(e);
+                        continue;
+                    }
+                },
+            };
+            retained.insert(netns.clone(), handle);
+        }
+        for netns in handles.keys() {
+            debug!("Closing netlink connection in network namespace {netns}");
+        }
+        *handles = retained;
+        result
+    }
+
+    /// The named network namespaces with an open handle
+    #[must_use]
+    pub fn namespaces(&self) -> Vec<(NetnsName, Arc<Handle>)> {
+        self.handles
+            .lock()
+            .iter()
+            .map(|(netns, handle)| (netns.clone(), handle.clone()))
+            .collect()
+    }
+}
+
+/// A closure handed over to the thread of a [`NetnsExecutor`]
+type Job = Box<dyn FnOnce(&Runtime) + Send>;
+
+/// A thread living in a network namespace, which runs the closures it is given there.
+///
+/// The closures are run one at a time, in the order they are given, on a current-thread tokio
+/// runtime owned by the executor: sockets they open belong to the namespace, and so do the
+/// netlink connections, whose tasks may be spawned on that runtime. A closure which panics fails
+/// the call which handed it over, not the executor.
+///
+/// An executor [created](Self::create) with its namespace removes it when it is shut down (or
+/// dropped); one which [entered](Self::enter) an existing namespace leaves it in place.
+#[derive(Debug)]
+pub struct NetnsExecutor {
+    netns: NetnsName,
+    jobs: Option<mpsc::Sender<Job>>,
+    thread: Option<JoinHandle<Result<(), NetnsError>>>,
+}
+
+impl NetnsExecutor {
+    /// Create the network namespace `netns` and start an executor in it
+    ///
+    /// # Errors
+    ///
+    /// Fails if the network namespace exists already or can't be created, or if the thread of
+    /// the executor can't be started.
+    pub fn create(netns: NetnsName) -> Result<Self, NetnsError> {
+        Self::start(netns, true)
+    }
+
+    /// Start an executor in the existing network namespace `netns`
+    ///
+    /// # Errors
+    ///
+    /// Fails if the network namespace does not exist or can't be joined, or if the thread of the
+    /// executor can't be started.
+    pub fn enter(netns: NetnsName) -> Result<Self, NetnsError> {
+        Self::start(netns, false)
+    }
+
+    /// The network namespace of the executor
+    #[must_use]
+    pub fn netns(&self) -> &NetnsName {
+        &self.netns
+    }
+
+    fn start(netns: NetnsName, create: bool) -> Result<Self, NetnsError> {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let (ready_tx, ready_rx) = mpsc::sync_channel(1);
+        let name = netns.clone();
+        let thread = std::thread::Builder::new()
+            .name(format!("netns-{netns}"))
+            .spawn(move || {
+                let setup = tokio::runtime::Builder::new_current_thread()
+                    .enable_io()
+                    .enable_time()
+                    .build()
+                    .map_err(|e| NetnsError::Runtime(name.clone(), e))
+                    .and_then(|runtime| {
+                        if create {
+                            runtime
+                                .block_on(NetworkNamespace::add(name.to_string()))
+                                .map_err(|e| NetnsError::Create(name.clone(), e))?;
+                        }
+                        if let Err(e) = join(&name) {
+                            // don't leave behind a namespace nobody will use
+                            if create {
+                                let _ = runtime.block_on(NetworkNamespace::del(name.to_string()));
+                            }
+                            return Err(e);
+                        }
+                        Ok(runtime)
+                    });
+                let runtime = match setup {
+                    Ok(runtime) => {
+                        let _ = ready_tx.send(Ok(()));
+                        runtime
+                    }
+                    Err(e) => {
+                        let _ = ready_tx.send(Err(e));
+                        return Ok(());
+                    }
+                };
+                while let Ok(job) = queue.recv() {
+                    job(&runtime);
+                }
+                if create {
+                    debug!("Removing network namespace {name}");
+                    runtime
+                        .block_on(NetworkNamespace::del(name.to_string()))
+                        .map_err(|e| NetnsError::Remove(name.clone(), e))?;
+                }
+                Ok(())
+            })
+            .map_err(|e| NetnsError::Spawn(netns.clone(), e))?;
+        let executor = Self {
+            netns,
+            jobs: Some(jobs),
+            thread: Some(thread),
+        };
+        match ready_rx.recv() {
+            Ok(Ok(())) => Ok(executor),
+            Ok(Err(e)) => Err(e),
+            Err(_) => Err(NetnsError::Thread(executor.netns.clone())),
+        }
+    }
+
+    /// Hand a closure over to the thread of the executor, which sends back its outcome
+    fn submit<F, Fut, T>(&self, f: F, done: impl FnOnce(Option<T>) + Send + 'static) -> bool
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = T>,
+        T: Send + 'static,
+    {
+        let job: Job = Box::new(move |runtime: &Runtime| {
+            let outcome = catch_unwind(AssertUnwindSafe(|| runtime.block_on(f())));
+            done(outcome.ok());
+        });
+        self.jobs
+            .as_ref()
+            .is_some_and(|jobs| jobs.send(job).is_ok())
+    }
+
+    /// Run the (async) closure `f` in the network namespace, waiting for its outcome.
+    ///
+    /// This blocks the current thread: from async code, use [`NetnsExecutor::run_async`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if the closure panics, or if the executor is stopped.
+    pub fn run<F, Fut, T>(&self, f: F) -> Result<T, NetnsError>
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = T>,
+        T: Send + 'static,
+    {
+        let (tx, rx) = mpsc::sync_channel(1);
+        if !self.submit(f, move |outcome| {
+            let _ = tx.send(outcome);
+        }) {
+            return Err(NetnsError::Stopped(self.netns.clone()));
+        }
+        match rx.recv() {
+            Ok(Some(out)) => Ok(out),
+            Ok(None) => Err(NetnsError::Panicked(self.netns.clone())),
+            Err(_) => Err(NetnsError::Stopped(self.netns.clone())),
+        }
+    }
+
+    /// Run the (async) closure `f` in the network namespace, without blocking the current
+    /// thread while it runs.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the closure panics, or if the executor is stopped.
+    pub async fn run_async<F, Fut, T>(&self, f: F) -> Result<T, NetnsError>
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = T>,
+        T: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        if !self.submit(f, move |outcome| {
+            let _ = tx.send(outcome);
+        }) {
+            return Err(NetnsError::Stopped(self.netns.clone()));
+        }
+        match rx.await {
+            Ok(Some(out)) => Ok(out),
+            Ok(None) => Err(NetnsError::Panicked(self.netns.clone())),
+            Err(_) => Err(NetnsError::Stopped(self.netns.clone())),
+        }
+    }
+
+    /// Stop the thread of the executor once the closures handed over are done, and remove the
+    /// network namespace if the executor created it.
+    fn stop(&mut self) -> Result<(), NetnsError> {
+        self.jobs.take();
+        match self.thread.take().map(JoinHandle::join) {
+            None => Ok(()),
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(NetnsError::Thread(self.netns.clone())),
+        }
+    }
+
+    /// Stop the executor, once the closures handed over are done. The network namespace is
+    /// removed if the executor created it.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the network namespace can't be removed.
+    pub fn shutdown(mut self) -> Result<(), NetnsError> {
+        self.stop()
+    }
+}
+
+impl Drop for NetnsExecutor {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop() {
+            error!("{e}");
+        }
+    }
+}
+
+impl Update for Manager<NetnsName> {
+    type Requirement<'a>
+        = Option<&'a NetnsName>
+    where
+        Self: 'a;
+    type Observation<'a>
+        = &'a Interface
+    where
+        Self: 'a;
+    type Outcome<'a>
+        = Result<(), rtnetlink::Error>
+    where
+        Self: 'a;
+
+    /// Move a link of the namespace of the manager into the network namespace `requirement`,
+    /// or into the network namespace of the current thread if it is `None`.
+    async fn update<'a>(
+        &self,
+        requirement: Option<&'a NetnsName>,
+        observation: &Interface,
+    ) -> Result<(), rtnetlink::Error> {
+        let netns = match requirement {
+            Some(netns) => netns.open().map_err(|e| format!("{e}")),
+            None => File::open(CURRENT_NETNS)
+                .map_err(|e| format!("Failed to open the current network namespace: {e}")),
+        }
+        .map_err(rtnetlink::Error::NamespaceError)?;
+        self.handle
+            .link()
+            .set(
+                LinkUnspec::new_with_index(observation.index.to_u32())
+                    .down()
+                    .setns_by_fd(netns.as_raw_fd())
+                    .build(),
+            )
+            .execute()
+            .await
+    }
+}
+
+#[cfg(any(test, feature = "bolero"))]
+mod contract {
+    use super::NetnsName;
+    use bolero::{Driver, TypeGenerator};
+
+    impl TypeGenerator for NetnsName {
+        fn generate<D: Driver>(driver: &mut D) -> Option<Self> {
+            Some(NetnsName(format!("ns{}", driver.produce::<u8>()?)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{NetnsError, NetnsExecutor, NetnsName};
+    use caps::Capability;
+    use fixin::wrap;
+    use test_utils::with_caps;
+
+    #[test]
+    fn netns_names() {
+        for legal in ["vpc-1", "ns.a", "a"] {
+            assert!(NetnsName::try_from(legal).is_ok(), "{legal}");
+        }
+        let too_long = "n".repeat(NetnsName::MAX_LEN + 1);
+        for illegal in ["", ".", "..", "a/b", "a\0b", too_long.as_str()] {
+            assert!(NetnsName::try_from(illegal).is_err(), "{illegal}");
+        }
+        let name = NetnsName::try_from("vpc-1").unwrap();
+        assert_eq!(name.path().to_str(), Some("/run/netns/vpc-1"));
+    }
+
+    #[test]
+    #[wrap(with_caps([Capability::CAP_SYS_ADMIN, Capability::CAP_NET_ADMIN]))]
+    #[n_vm::in_vm]
+    #[ignore = "disabled until nv_m support is re-enabled"]
+    fn netns_executor() {
+        let netns = NetnsName::try_from("executor-test").unwrap();
+        let executor = NetnsExecutor::create(netns.clone()).unwrap();
+        assert!(netns.path().exists());
+
+        // a fresh namespace only has a loopback
+        let links = executor
+            .run(|| async {
+                let (connection, handle, _) = rtnetlink::new_connection().unwrap();
+                tokio::spawn(connection);
+                let mut links = handle.link().get().execute();
+                let mut count = 0;
+                while futures::TryStreamExt::try_next(&mut links)
+                    .await
+                    .unwrap()
+                    .is_some()
+                {
+                    count += 1;
+                }
+                count
+            })
+            .unwrap();
+        assert_eq!(links, 1);
+
+        let panicked = executor.run(|| async { panic!("in the namespace") });
+        assert!(matches!(panicked, Err(NetnsError::Panicked(_))));
+        assert_eq!(executor.run(|| async { 42 }).unwrap(), 42);
+
+        assert!(matches!(
+            NetnsExecutor::create(netns.clone()),
+            Err(NetnsError::Create(..))
+        ));
+        let entered = NetnsExecutor::enter(netns.clone()).unwrap();
+        entered.shutdown().unwrap();
+        assert!(netns.path().exists());
+
+        executor.shutdown().unwrap();
+        assert!(!netns.path().exists());
+        assert!(matches!(
+            NetnsExecutor::enter(netns),
+            Err(NetnsError::Open(..))
+        ));
+    }
+}
@@ -0,0 +1,417 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Reconcile the intended offload settings of the linux interfaces with their observed settings.
+//!
+//! The pipeline sees the frames of the interfaces it does packet IO on as they are handed over
+//! to (or by) the kernel, so some offloads must be set specific ways for correctness. For
+//! instance, VLAN offloads move the tags out of the frames, GRO and TSO hand over frames larger
+//! than the MTU, and checksum offloads hand over frames whose checksums are yet to be computed.
+//!
+//! Offloads are read and set with the `SIOCETHTOOL` requests dedicated to each of them, which the
+//! kernel supports for any network device.
+
+use crate::Manager;
+use derive_builder::Builder;
+use net::interface::InterfaceName;
+use nix::errno::Errno;
+use nix::libc;
+use nix::sys::socket::{AddressFamily, SockFlag, SockType, socket};
+use rekon::{Reconcile, Update};
+use serde::{Deserialize, Serialize};
+use std::os::fd::{AsRawFd, OwnedFd};
+use tracing::{debug, warn};
+
+const SIOCETHTOOL: libc::c_ulong = 0x8946;
+
+nix::ioctl_readwrite_bad!(
+    /// Issue an ethtool request on a network interface
+    ethtool_request,
+    SIOCETHTOOL,
+    libc::ifreq
+);
+
+/// Flags of the `ETHTOOL_GFLAGS` and `ETHTOOL_SFLAGS` requests
+const ETH_FLAG_TXVLAN: u32 = 1 << 7;
+const ETH_FLAG_RXVLAN: u32 = 1 << 8;
+const ETH_FLAG_LRO: u32 = 1 << 15;
+
+/// The ethtool requests reading an offload. The request setting it is the next one.
+#[derive(Clone, Copy, Debug)]
+enum OffloadRequest {
+    RxChecksum = 0x14,
+    TxChecksum = 0x16,
+    Tso = 0x1e,
+    Gso = 0x23,
+    Flags = 0x25,
+    Gro = 0x2b,
+}
+
+impl OffloadRequest {
+    fn get(self) -> u32 {
+        self as u32
+    }
+
+    fn set(self) -> u32 {
+        self as u32 + 1
+    }
+}
+
+/// The value of an ethtool request on an offload (`struct ethtool_value`)
+#[repr(C)]
+struct EthtoolValue {
+    cmd: u32,
+    data: u32,
+}
+
+/// Errors which may occur when reading or setting offloads.
+#[derive(Debug, thiserror::Error)]
+pub enum OffloadError {
+    /// No socket to issue ethtool requests on.
+    #[error("failed to open socket for ethtool requests: {0}")]
+    Socket(Errno),
+    /// An ethtool request failed.
+    #[error("ethtool request {cmd:#x} failed for interface {interface}: {errno}")]
+    Request {
+        /// The interface of the request
+        interface: InterfaceName,
+        /// The ethtool command of the request
+        cmd: u32,
+        /// The error returned by the kernel
+        errno: Errno,
+    },
+}
+
+/// A socket to issue ethtool requests on
+struct EthtoolSocket(OwnedFd);
+
+impl EthtoolSocket {
+    fn open() -> Result<Self, OffloadError> {
+        socket(
+            AddressFamily::Inet,
+            SockType::Datagram,
+            SockFlag::SOCK_CLOEXEC,
+            None,
+        )
+        .map(Self)
+        .map_err(OffloadError::Socket)
+    }
+
+    #[allow(unsafe_code)] // the request points at a value which outlives it
+    fn request(&self, interface: &InterfaceName, cmd: u32, data: u32) -> Result<u32, OffloadError> {
+        let mut value = EthtoolValue { cmd, data };
+        let mut ifreq = libc::ifreq {
+            ifr_name: [0; libc::IF_NAMESIZE],
+            ifr_ifru: libc::__c_anonymous_ifr_ifru {
+                ifru_data: (&raw mut value).cast(),
+            },
+        };
+        // interface names are ASCII and shorter than IF_NAMESIZE, per the InterfaceName contract
+        for (i, byte) in interface.as_ref().as_bytes().iter().enumerate() {
+            #[allow(clippy::cast_possible_wrap)]
+            {
+                ifreq.ifr_name[i] = *byte as libc::c_char;
+            }
+        }
+        unsafe { ethtool_request(self.0.as_raw_fd(), &raw mut ifreq) }.map_err(|errno| {
+            OffloadError::Request {
+                interface: interface.clone(),
+                cmd,
+                errno,
+            }
+        })?;
+        Ok(value.data)
+    }
+
+    fn get(&self, interface: &InterfaceName, request: OffloadRequest) -> Result<u32, OffloadError> {
+        self.request(interface, request.get(), 0)
+    }
+
+    fn set(
+        &self,
+        interface: &InterfaceName,
+        request: OffloadRequest,
+        data: u32,
+    ) -> Result<(), OffloadError> {
+        self.request(interface, request.set(), data).map(|_| ())
+    }
+}
+
+/// The offload settings required on a network interface.
+///
+/// Offloads set to `None` are left as they are.
+#[derive(
+    Builder,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Deserialize,
+    Eq,
+    Hash,
+    Ord,
+    PartialEq,
+    PartialOrd,
+    Serialize,
+)]
+#[cfg_attr(any(test, feature = "bolero"), derive(bolero::TypeGenerator))]
+pub struct OffloadSpec {
+    /// Checksum validation of received frames (`rx-checksumming`).
+    #[builder(default)]
+    #[serde(default)]
+    pub rx_checksum: Option<bool>,
+    /// Checksum computation of transmitted frames (`tx-checksumming`).
+    #[builder(default)]
+    #[serde(default)]
+    pub tx_checksum: Option<bool>,
+    /// Stripping of the VLAN tags of received frames (`rx-vlan-offload`).
+    #[builder(default)]
+    #[serde(default)]
+    pub rx_vlan: Option<bool>,
+    /// Insertion of the VLAN tags of transmitted frames (`tx-vlan-offload`).
+    #[builder(default)]
+    #[serde(default)]
+    pub tx_vlan: Option<bool>,
+    /// Generic receive offload (`generic-receive-offload`).
+    #[builder(default)]
+    #[serde(default)]
+    pub gro: Option<bool>,
+    /// Large receive offload (`large-receive-offload`).
+    #[builder(default)]
+    #[serde(default)]
+    pub lro: Option<bool>,
+    /// TCP segmentation offload (`tcp-segmentation-offload`).
+    #[builder(default)]
+    #[serde(default)]
+    pub tso: Option<bool>,
+    /// Generic segmentation offload (`generic-segmentation-offload`).
+    #[builder(default)]
+    #[serde(default)]
+    pub gso: Option<bool>,
+}
+
+/// The observed offload settings of a network interface.
+#[derive(Clone, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub struct Offloads {
+    /// The name of the interface.
+    pub interface: InterfaceName,
+    /// Checksum validation of received frames (`rx-checksumming`).
+    pub rx_checksum: bool,
+    /// Checksum computation of transmitted frames (`tx-checksumming`).
+    pub tx_checksum: bool,
+    /// Stripping of the VLAN tags of received frames (`rx-vlan-offload`).
+    pub rx_vlan: bool,
+    /// Insertion of the VLAN tags of transmitted frames (`tx-vlan-offload`).
+    pub tx_vlan: bool,
+    /// Generic receive offload (`generic-receive-offload`).
+    pub gro: bool,
+    /// Large receive offload (`large-receive-offload`).
+    pub lro: bool,
+    /// TCP segmentation offload (`tcp-segmentation-offload`).
+    pub tso: bool,
+    /// Generic segmentation offload (`generic-segmentation-offload`).
+    pub gso: bool,
+}
+
+impl PartialEq<Offloads> for OffloadSpec {
+    fn eq(&self, other: &Offloads) -> bool {
+        [
+            (self.rx_checksum, other.rx_checksum),
+            (self.tx_checksum, other.tx_checksum),
+            (self.rx_vlan, other.rx_vlan),
+            (self.tx_vlan, other.tx_vlan),
+            (self.gro, other.gro),
+            (self.lro, other.lro),
+            (self.tso, other.tso),
+            (self.gso, other.gso),
+        ]
+        .into_iter()
+        .all(|(required, observed)| required.is_none_or(|required| required == observed))
+    }
+}
+
+impl OffloadSpec {
+    /// The `ETHTOOL_SFLAGS` flags meeting the requirements, from the observed `flags`
+    fn flags(&self, mut flags: u32) -> u32 {
+        for (required, flag) in [
+            (self.rx_vlan, ETH_FLAG_RXVLAN),
+            (self.tx_vlan, ETH_FLAG_TXVLAN),
+            (self.lro, ETH_FLAG_LRO),
+        ] {
+            match required {
+                None => {}
+                Some(true) => flags |= flag,
+                Some(false) => flags &= !flag,
+            }
+        }
+        flags
+    }
+}
+
+impl Manager<Offloads> {
+    /// Read the offload settings of a network interface.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the settings can't be read, e.g. if the interface does not exist.
+    pub fn get(&self, interface: &InterfaceName) -> Result<Offloads, OffloadError> {
+        let sock = EthtoolSocket::open()?;
+        let enabled = |request| sock.get(interface, request).map(|data| data != 0);
+        let flags = sock.get(interface, OffloadRequest::Flags)?;
+        Ok(Offloads {
+            interface: interface.clone(),
+            rx_checksum: enabled(OffloadRequest::RxChecksum)?,
+            tx_checksum: enabled(OffloadRequest::TxChecksum)?,
+            rx_vlan: flags & ETH_FLAG_RXVLAN != 0,
+            tx_vlan: flags & ETH_FLAG_TXVLAN != 0,
+            gro: enabled(OffloadRequest::Gro)?,
+            lro: flags & ETH_FLAG_LRO != 0,
+            tso: enabled(OffloadRequest::Tso)?,
+            gso: enabled(OffloadRequest::Gso)?,
+        })
+    }
+
+    fn set(&self, requirement: &OffloadSpec, observation: &Offloads) -> Result<(), OffloadError> {
+        let sock = EthtoolSocket::open()?;
+        let interface = &observation.interface;
+        for (required, observed, request) in [
+            (
+                requirement.rx_checksum,
+                observation.rx_checksum,
+                OffloadRequest::RxChecksum,
+            ),
+            (
+                requirement.tx_checksum,
+                observation.tx_checksum,
+                OffloadRequest::TxChecksum,
+            ),
+            (requirement.gro, observation.gro, OffloadRequest::Gro),
+            (requirement.tso, observation.tso, OffloadRequest::Tso),
+            (requirement.gso, observation.gso, OffloadRequest::Gso),
+        ] {
+            if let Some(required) = required
+                && required != observed
+            {
+                debug!("setting {request:?} offload of {interface} to {required}");
+                sock.set(interface, request, u32::from(required))?;
+            }
+        }
+        let flags = sock.get(interface, OffloadRequest::Flags)?;
+        let required = requirement.flags(flags);
+        if required != flags {
+            debug!("setting offload flags of {interface} to {required:#x}");
+            sock.set(interface, OffloadRequest::Flags, required)?;
+        }
+        Ok(())
+    }
+}
+
+impl Update for Manager<Offloads> {
+    type Requirement<'a>
+        = &'a OffloadSpec
+    where
+        Self: 'a;
+    type Observation<'a>
+        = &'a Offloads
+    where
+        Self: 'a;
+    type Outcome<'a>
+        = Result<(), rtnetlink::Error>
+    where
+        Self: 'a;
+
+    async fn update<'a>(
+        &self,
+        requirement: &'a OffloadSpec,
+        observation: &'a Offloads,
+    ) -> Result<(), rtnetlink::Error>
+    where
+        Self: 'a,
+    {
+        self.set(requirement, observation).map_err(|err| {
+            warn!("failed to set offloads: {err}");
+            rtnetlink::Error::RequestFailed
+        })
+    }
+}
+
+impl Reconcile for Manager<Offloads> {
+    type Requirement<'a>
+        = &'a OffloadSpec
+    where
+        Self: 'a;
+    type Observation<'a>
+        = &'a Offloads
+    where
+        Self: 'a;
+    type Outcome<'a>
+        = Option<Result<(), rtnetlink::Error>>
+    where
+        Self: 'a;
+
+    async fn reconcile<'a>(
+        &self,
+        requirement: &'a OffloadSpec,
+        observation: &'a Offloads,
+    ) -> Self::Outcome<'a>
+    where
+        Self: 'a,
+    {
+        if requirement == observation {
+            return None;
+        }
+        Some(self.update(requirement, observation).await)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn observed() -> Offloads {
+        Offloads {
+            interface: InterfaceName::try_from("eth0-tap").unwrap(),
+            rx_checksum: true,
+            tx_checksum: true,
+            rx_vlan: true,
+            tx_vlan: false,
+            gro: true,
+            lro: false,
+            tso: true,
+            gso: true,
+        }
+    }
+
+    #[test]
+    fn offload_spec_is_met() {
+        let mut observed = observed();
+        assert!(OffloadSpec::default() == observed);
+
+        let spec = OffloadSpecBuilder::default()
+            .rx_vlan(Some(false))
+            .tx_checksum(Some(false))
+            .gro(Some(false))
+            .build()
+            .unwrap();
+        assert!(spec != observed);
+        observed.rx_vlan = false;
+        observed.tx_checksum = false;
+        assert!(spec != observed);
+        observed.gro = false;
+        assert!(spec == observed);
+        observed.lro = true;
+        assert!(spec == observed);
+    }
+
+    #[test]
+    fn offload_spec_flags() {
+        let flags = ETH_FLAG_RXVLAN | ETH_FLAG_LRO | 1 << 28;
+        assert_eq!(OffloadSpec::default().flags(flags), flags);
+        let spec = OffloadSpec {
+            rx_vlan: Some(false),
+            tx_vlan: Some(true),
+            ..OffloadSpec::default()
+        };
+        assert_eq!(spec.flags(flags), ETH_FLAG_TXVLAN | ETH_FLAG_LRO | 1 << 28);
+    }
+}
@@ -113,6 +113,7 @@ impl ValueGenerator for LegalValueExposeGenerator<'_> {
             r#as: Some(final_as).filter(|f| !f.is_empty()),
             ips: Some(final_ips).filter(|f| !f.is_empty()),
             default: None,
+            direction: None,
             nat: if has_as {
                 Some(
                     d.produce::<LegalValue<GatewayAgentPeeringsPeeringExposeNat>>()?
@@ -26,6 +26,7 @@ impl TypeGenerator for GatewayAgentGroupsMembers {
             name: driver.produce::<String>()?,
             priority: driver.gen_u32(Bound::Included(&0), Bound::Included(&10))?,
             vtep_ip: driver.produce::<UnicastIpv4Addr>()?.to_string(),
+            nat_partition: None,
         };
         Some(gmember)
     }
@@ -38,8 +39,17 @@ impl TypeGenerator for GatewayAgentGroups {
         if num_members > 0 {
             members.push(driver.produce::<GatewayAgentGroupsMembers>()?);
         }
+        // partitioned groups give each member a distinct partition of the NAT port space
+        let mut nat_partitions = None;
+        if driver.produce::<bool>()? {
+            for (index, member) in members.iter_mut().enumerate() {
+                member.nat_partition = Some(u32::try_from(index).ok()?);
+            }
+            nat_partitions = Some(u32::try_from(members.len().max(1)).ok()?);
+        }
         Some(GatewayAgentGroups {
             members: Some(members),
+            nat_partitions,
         })
     }
 }
@@ -38,6 +38,12 @@ impl TypeGenerator for LegalValue<GatewayAgentGatewayInterfaces> {
             } else {
                 None
             },
+            // "off" is the default, which is left unset
+            urpf: match d.gen_u8(Bound::Included(&0), Bound::Included(&2))? {
+                0 => None,
+                1 => Some("loose".to_string()),
+                _ => Some("strict".to_string()),
+            },
         }))
     }
 }
@@ -61,6 +67,7 @@ impl Normalize for GatewayAgentGatewayInterfaces {
             kernel: self.kernel.clone(),
             mtu: self.mtu,
             pci: self.pci.clone(),
+            urpf: self.urpf.clone().filter(|urpf| urpf != "off"),
         }
     }
 }
@@ -0,0 +1,68 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+use std::ops::Bound;
+
+use bolero::{Driver, TypeGenerator};
+
+use crate::bolero::LegalValue;
+use crate::gateway_agent_crd::{GatewayAgentMirror, GatewayAgentMirrorDestination};
+
+/// Generate a legal mirror session, without a VPC in its filter. ERSPAN sessions get session id 0:
+/// sessions towards the same collector must be given distinct ids.
+impl TypeGenerator for LegalValue<GatewayAgentMirror> {
+    fn generate<D: Driver>(d: &mut D) -> Option<Self> {
+        let remote = format!(
+            "192.168.{}.{}",
+            d.gen_u8(Bound::Included(&0), Bound::Included(&255))?,
+            d.gen_u8(Bound::Included(&1), Bound::Included(&254))?
+        );
+        let destination = match d.gen_u8(Bound::Included(&0), Bound::Included(&2))? {
+            0 => GatewayAgentMirrorDestination {
+                interface: Some("mirror0".to_string()),
+                remote: None,
+                encapsulation: None,
+                erspan_session_id: None,
+                gre_key: None,
+            },
+            1 => GatewayAgentMirrorDestination {
+                interface: None,
+                remote: Some(remote),
+                encapsulation: Some("gre".to_string()),
+                erspan_session_id: None,
+                gre_key: d.produce()?,
+            },
+            _ => GatewayAgentMirrorDestination {
+                interface: None,
+                remote: Some(remote),
+                encapsulation: Some("erspan".to_string()),
+                erspan_session_id: Some(0),
+                gre_key: None,
+            },
+        };
+        let prefix = if d.gen_bool(None)? {
+            Some(format!(
+                "10.{}.0.0/16",
+                d.gen_u8(Bound::Included(&0), Bound::Included(&255))?
+            ))
+        } else {
+            None
+        };
+        Some(LegalValue(GatewayAgentMirror {
+            interface: None,
+            vpc: None,
+            prefix,
+            destination: Some(destination),
+            rate_pps: if d.gen_bool(None)? {
+                Some(d.gen_u32(Bound::Included(&1), Bound::Included(&1_000_000))?)
+            } else {
+                None
+            },
+            truncate: if d.gen_bool(None)? {
+                Some(d.gen_u32(Bound::Included(&64), Bound::Included(&9000))?)
+            } else {
+                None
+            },
+        }))
+    }
+}
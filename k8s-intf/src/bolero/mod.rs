@@ -8,7 +8,9 @@ pub mod gateway;
 pub mod gwgroups;
 pub mod interface;
 pub mod logs;
+pub mod mirror;
 pub mod peering;
+pub mod qos;
 pub mod spec;
 pub mod support;
 pub mod vpc;
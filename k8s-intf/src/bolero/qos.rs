@@ -0,0 +1,55 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+use std::collections::BTreeMap;
+use std::ops::Bound;
+
+use bolero::{Driver, TypeGenerator};
+
+use crate::bolero::LegalValue;
+use crate::gateway_agent_crd::{GatewayAgentQos, GatewayAgentQosClasses};
+
+const MAX_CLASSES: u8 = 8;
+
+impl TypeGenerator for LegalValue<GatewayAgentQos> {
+    fn generate<D: Driver>(d: &mut D) -> Option<Self> {
+        let num_classes = d.gen_u8(Bound::Included(&0), Bound::Included(&MAX_CLASSES))?;
+        let mut classes = BTreeMap::new();
+        for i in 0..num_classes {
+            // Split DSCPs among classes so that no DSCP belongs to two of them
+            let dscp = (0..64u8)
+                .filter(|dscp| dscp % MAX_CLASSES == i)
+                .take(usize::from(
+                    d.gen_u8(Bound::Included(&0), Bound::Included(&8))?,
+                ))
+                .collect::<Vec<_>>();
+            let queue_depth = d.gen_u32(Bound::Included(&16), Bound::Included(&4096))?;
+            let wred = d.gen_bool(None)?;
+            let (min, max, prob) = if wred {
+                let max = d.gen_u32(Bound::Included(&2), Bound::Included(&queue_depth))?;
+                let min = d.gen_u32(Bound::Included(&1), Bound::Excluded(&max))?;
+                let prob = d.gen_u8(Bound::Included(&1), Bound::Included(&100))?;
+                (Some(min), Some(max), Some(prob))
+            } else {
+                (None, None, None)
+            };
+            classes.insert(
+                format!("class{i}"),
+                GatewayAgentQosClasses {
+                    priority: Some(i),
+                    dscp: Some(dscp).filter(|dscp| !dscp.is_empty()),
+                    queue_depth: Some(queue_depth),
+                    drop_policy: Some(if wred { "wred" } else { "drop-tail" }.to_string()),
+                    wred_min_threshold: min,
+                    wred_max_threshold: max,
+                    wred_max_probability: prob,
+                },
+            );
+        }
+        Some(LegalValue(GatewayAgentQos {
+            rate_mbps: Some(d.gen_u32(Bound::Included(&1), Bound::Included(&100_000))?),
+            burst_kbytes: Some(d.gen_u32(Bound::Included(&1), Bound::Included(&10_000))?),
+            classes: Some(classes).filter(|c| !c.is_empty()),
+        }))
+    }
+}
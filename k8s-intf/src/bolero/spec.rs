@@ -11,7 +11,8 @@ use lpm::prefix::Prefix;
 use crate::bolero::peering::LegalValuePeeringsGenerator;
 use crate::bolero::{LegalValue, SubnetMap, VpcSubnetMap};
 use crate::gateway_agent_crd::{
-    GatewayAgentGateway, GatewayAgentGroups, GatewayAgentSpec, GatewayAgentVpcs,
+    GatewayAgentGateway, GatewayAgentGroups, GatewayAgentMirror, GatewayAgentQos, GatewayAgentSpec,
+    GatewayAgentVpcs,
 };
 
 fn extract_subnets(vpcs: &BTreeMap<String, GatewayAgentVpcs>) -> VpcSubnetMap {
@@ -99,14 +100,52 @@ impl TypeGenerator for LegalValue<GatewayAgentSpec> {
             communities.insert(i.to_string(), community);
         }
 
+        const CLASSES: [&str; 3] = ["internet", "private", "blackhole"];
+        let num_classes = d.gen_usize(Bound::Included(&0), Bound::Included(&6))?;
+        let mut community_classes = BTreeMap::new();
+        for i in 0..num_classes {
+            let class =
+                CLASSES[d.gen_usize(Bound::Included(&0), Bound::Excluded(&CLASSES.len()))?];
+            community_classes.insert(format!("65001:{}", 100 + i), class.to_string());
+        }
+
+        let mut qos = BTreeMap::new();
+        for vpc_name in vpcs.keys() {
+            if d.gen_bool(None)? {
+                qos.insert(
+                    vpc_name.clone(),
+                    d.produce::<LegalValue<GatewayAgentQos>>()?.take(),
+                );
+            }
+        }
+
+        let num_mirrors = d.gen_usize(Bound::Included(&0), Bound::Included(&4))?;
+        let mut mirror = BTreeMap::new();
+        for i in 0..num_mirrors {
+            let mut session = d.produce::<LegalValue<GatewayAgentMirror>>()?.take();
+            // mirror the traffic of some vpc, if any, and keep ERSPAN session ids distinct
+            if d.gen_bool(None)? {
+                session.vpc = vpcs.keys().nth(i).cloned();
+            }
+            if let Some(destination) = session.destination.as_mut()
+                && destination.erspan_session_id.is_some()
+            {
+                destination.erspan_session_id = Some(u32::try_from(i).expect("too many sessions"));
+            }
+            mirror.insert(format!("mirror{i}"), session);
+        }
+
         Some(LegalValue(GatewayAgentSpec {
             agent_version: None,
             config: None,
             groups: Some(groups),
             communities: Some(communities),
+            community_classes: Some(community_classes).filter(|c| !c.is_empty()),
             gateway: Some(d.produce::<LegalValue<GatewayAgentGateway>>()?.take()),
             vpcs: Some(vpcs).filter(|v| !v.is_empty()),
             peerings: Some(peerings).filter(|p| !p.is_empty()),
+            qos: Some(qos).filter(|q| !q.is_empty()),
+            mirror: Some(mirror).filter(|m| !m.is_empty()),
         }))
     }
 }
@@ -51,6 +51,11 @@ impl TypeGenerator for LegalValue<GatewayAgentVpcs> {
             internal_id: Some(internal_id),
             vni: Some(vni.into()),
             subnets: Some(subnets).filter(|s| !s.is_empty()),
+            tcp_tracking: match d.gen_u8(Bound::Included(&0), Bound::Included(&2))? {
+                0 => None,
+                1 => Some("loose".to_string()),
+                _ => Some("strict".to_string()),
+            },
         }))
     }
 }
@@ -2,15 +2,15 @@
 // Copyright Open Network Fabric Authors
 
 use concurrency::sync::Arc;
-use futures::{StreamExt, TryStreamExt};
 use kube::api::PostParams;
-use kube::runtime::{WatchStreamExt, watcher};
+use kube::runtime::watcher;
 use kube::{Api, Client};
 use std::time::Duration;
 
 use tracectl::trace_target;
 use tracing::{error, info, warn};
 
+use crate::controller::{Controller, CrdEvent, WatchPolicy};
 use crate::gateway_agent_crd::{GatewayAgent, GatewayAgentStatus};
 
 trace_target!("k8s-client", LevelFilter::INFO, &["management"]);
@@ -28,7 +28,7 @@ pub enum ReplaceStatusError {
 const KUBE_CLIENT_RETRY_TIME: Duration = Duration::from_secs(10);
 
 /// Loop forever until we connect to K8s
-async fn init_kube_client() -> Client {
+pub async fn init_kube_client() -> Client {
     loop {
         match Client::try_default().await {
             Ok(client) => {
@@ -43,20 +43,13 @@ async fn init_kube_client() -> Client {
     }
 }
 
-/// Connect a kube client to the K8s infra, retrying indefinitely.
-/// On success, watch for `GatewayAgent` CRD and call callback for all changes.
-async fn run_watcher(
-    gateway_object_name: &str,
-    callback: &impl AsyncFn(&GatewayAgent),
-) -> Result<(), crate::client::watcher::Error> {
-    info!("Starting K8s GatewayAgent watcher...");
-
-    let client = init_kube_client().await;
-
-    // Relevant gateway agent objects are in the "default" namespace
-    let gws: Api<GatewayAgent> = Api::namespaced(client.clone(), "default");
+/// The namespace of the `GatewayAgent` objects
+pub const GATEWAY_AGENT_NAMESPACE: &str = "default";
 
-    let watch_config = watcher::Config {
+/// The config of a watch of the `GatewayAgent` object of a gateway
+#[must_use]
+pub fn gateway_agent_watch_config(gateway_object_name: &str) -> watcher::Config {
+    watcher::Config {
         // The service account for this gateway only has access to its corresponding
         // gateway agent object, so specifically filter for that to avoid an auth error
         // and to not apply incorrect configurations intended for other gateways
@@ -68,32 +61,44 @@ async fn run_watcher(
         // watch config that includes the field selector.
         initial_list_strategy: watcher::InitialListStrategy::StreamingList,
         ..Default::default()
-    };
-    let mut stream = watcher(gws, watch_config)
-        .default_backoff()
-        .applied_objects()
-        .boxed();
+    }
+}
 
-    loop {
-        match stream.try_next().await {
-            Ok(Some(ga)) => callback(&ga).await,
-            Ok(None) => {}
-            Err(e) => return Err(e),
+/// Connect a kube client to the K8s infra, retrying indefinitely, and watch the CRDs of the
+/// gateway with a [`Controller`], calling callback for all their changes, until the watches end.
+async fn run_controller(
+    gateway_object_name: &str,
+    policy: WatchPolicy,
+    callback: &impl AsyncFn(CrdEvent),
+) {
+    info!("Starting K8s CRD controller...");
+
+    let client = init_kube_client().await;
+    let (mut controller, mut events) = Controller::<CrdEvent>::new(client, policy);
+    controller.watch::<GatewayAgent>(
+        GATEWAY_AGENT_NAMESPACE,
+        gateway_agent_watch_config(gateway_object_name),
+    );
+    let handle_events = async {
+        while let Some(event) = events.recv().await {
+            callback(event).await;
         }
-    }
+    };
+    tokio::join!(controller.run(), handle_events);
 }
 
-/// Create a kube client (and retry forever). On success, watch `GatewayAgent` CRD and call callback for all changes
+/// Create a kube client (and retry forever). On success, watch the CRDs of the gateway according
+/// to `policy` and call callback for all changes, delivered as [`CrdEvent`]s.
 /// Note: This function loops forever and should be executed as an async task.
-pub async fn watch_gateway_agent_crd(
+pub async fn watch_crds(
     gateway_object_name: &str,
-    callback: Arc<impl AsyncFn(&GatewayAgent)>,
+    policy: WatchPolicy,
+    callback: Arc<impl AsyncFn(CrdEvent)>,
 ) -> ! {
     loop {
-        if let Err(e) = run_watcher(gateway_object_name, callback.as_ref()).await {
-            error!("Watcher error: {e}. Restarting kube client in 2s...");
-            tokio::time::sleep(Duration::from_secs(2)).await;
-        }
+        run_controller(gateway_object_name, policy, callback.as_ref()).await;
+        error!("CRD controller stopped. Restarting kube client in 2s...");
+        tokio::time::sleep(Duration::from_secs(2)).await;
     }
 }
 
@@ -108,7 +113,7 @@ pub async fn replace_gateway_status(
     status: &GatewayAgentStatus,
 ) -> Result<(), ReplaceStatusError> {
     let client = Client::try_default().await?;
-    let api: Api<GatewayAgent> = Api::namespaced(client.clone(), "default");
+    let api: Api<GatewayAgent> = Api::namespaced(client.clone(), GATEWAY_AGENT_NAMESPACE);
 
     for attempt_num in 0..NUM_CONFLICT_RETRIES {
         let mut status_obj = api.get_status(gateway_object_name).await?;
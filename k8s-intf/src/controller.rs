@@ -0,0 +1,248 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! A small controller watching the CRDs the dataplane is configured with.
+//!
+//! A [`Controller`] watches any number of kinds of objects with a single kube client, and delivers
+//! their changes over one channel, as events of a type `E` built from the [`ObjectEvent`]s of
+//! each kind, such as [`CrdEvent`]. The objects of each kind are kept in a reflector [`Store`],
+//! which is shared with whoever needs to look them up.
+//!
+//! Each kind is watched with the same [`WatchPolicy`]: errors of the watch are retried with an
+//! exponential backoff, and all the objects in the store may be delivered again periodically, so
+//! that a change failing to apply is eventually retried.
+
+use std::fmt::Debug;
+use std::hash::Hash;
+// `std::sync::Arc` (not `concurrency::sync::Arc`) is required: the store of the reflector shares
+// the objects it keeps as `std::sync::Arc`s.
+use std::sync::Arc; // nosemgrep: rust-no-direct-std-sync-import
+use std::time::Duration;
+
+use futures::future::{BoxFuture, join_all};
+use futures::{FutureExt, StreamExt};
+use kube::runtime::reflector::{self, Store};
+use kube::runtime::{WatchStreamExt, watcher};
+use kube::{Api, Client, Resource};
+use serde::de::DeserializeOwned;
+use tokio::sync::mpsc;
+use tracing::{debug, warn};
+
+use crate::gateway_agent_crd::GatewayAgent;
+
+/// The number of events buffered in the channel of a [`Controller`]
+const EVENT_CHANNEL_CAPACITY: usize = 64;
+
+/// A change of a watched object of kind `K`
+#[derive(Debug)]
+pub enum ObjectEvent<K> {
+    /// The object was created or modified, or delivered again upon a resync
+    Applied(Arc<K>),
+    /// The object was deleted
+    Deleted(Arc<K>),
+}
+
+/// The changes of the CRDs watched by the dataplane
+#[derive(Debug)]
+pub enum CrdEvent {
+    GatewayAgent(ObjectEvent<GatewayAgent>),
+}
+
+impl From<ObjectEvent<GatewayAgent>> for CrdEvent {
+    fn from(event: ObjectEvent<GatewayAgent>) -> Self {
+        CrdEvent::GatewayAgent(event)
+    }
+}
+
+/// How the kinds of objects of a [`Controller`] are watched
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WatchPolicy {
+    /// The delay before retrying a watch failing for the first time
+    pub min_backoff: Duration,
+    /// The longest delay before retrying a watch failing repeatedly
+    pub max_backoff: Duration,
+    /// The period after which all the objects watched are delivered again, if any
+    pub resync: Option<Duration>,
+}
+
+impl Default for WatchPolicy {
+    fn default() -> Self {
+        Self {
+            min_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            resync: None,
+        }
+    }
+}
+
+impl WatchPolicy {
+    /// The delay before retrying a watch which failed after `delay`, or for the first time
+    fn next_backoff(&self, delay: Option<Duration>) -> Duration {
+        delay.map_or(self.min_backoff, |delay| (delay * 2).min(self.max_backoff))
+    }
+}
+
+/// A controller watching kinds of objects, delivering their changes as events of type `E`
+pub struct Controller<E> {
+    client: Client,
+    policy: WatchPolicy,
+    tx: mpsc::Sender<E>,
+    watches: Vec<BoxFuture<'static, ()>>,
+}
+
+/// Deliver `event` over `tx`, telling if the receiver is still there
+async fn deliver<K, E>(tx: &mpsc::Sender<E>, event: ObjectEvent<K>) -> bool
+where
+    E: From<ObjectEvent<K>>,
+{
+    tx.send(E::from(event)).await.is_ok()
+}
+
+/// Watch the objects of `api`, keeping them in the store of `writer`, and deliver their changes
+/// over `tx` until the receiver is dropped.
+async fn run_watch<K, E>(
+    api: Api<K>,
+    config: watcher::Config,
+    writer: reflector::store::Writer<K>,
+    store: Store<K>,
+    policy: WatchPolicy,
+    tx: mpsc::Sender<E>,
+) where
+    K: Resource + Clone + DeserializeOwned + Debug + Send + Sync + 'static,
+    K::DynamicType: Default + Eq + Hash + Clone,
+    E: From<ObjectEvent<K>> + Send + 'static,
+{
+    let kind = K::kind(&K::DynamicType::default()).to_string();
+    let mut stream = watcher(api, config).reflect(writer).boxed();
+    let mut resync = policy.resync.map(|period| {
+        let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        interval
+    });
+    let mut backoff = None;
+    loop {
+        let resync_tick = async {
+            match resync.as_mut() {
+                Some(interval) => interval.tick().await,
+                None => std::future::pending().await,
+            }
+        };
+        let delivered = tokio::select! {
+            event = stream.next() => match event {
+                Some(Ok(watcher::Event::Apply(object) | watcher::Event::InitApply(object))) => {
+                    backoff = None;
+                    deliver(&tx, ObjectEvent::Applied(Arc::new(object))).await
+                }
+                Some(Ok(watcher::Event::Delete(object))) => {
+                    backoff = None;
+                    deliver(&tx, ObjectEvent::Deleted(Arc::new(object))).await
+                }
+                Some(Ok(watcher::Event::Init | watcher::Event::InitDone)) => true,
+                Some(Err(e)) => {
+                    let delay = policy.next_backoff(backoff);
+                    backoff = Some(delay);
+                    warn!("Watch of {kind} objects failed: {e}. Retrying in {delay:?}...");
+                    tokio::time::sleep(delay).await;
+                    true
+                }
+                None => {
+                    warn!("Watch of {kind} objects ended");
+                    return;
+                }
+            },
+            () = resync_tick => {
+                let objects = store.state();
+                debug!("Resyncing {} {kind} objects", objects.len());
+                let mut delivered = true;
+                for object in objects {
+                    if !deliver(&tx, ObjectEvent::Applied(object)).await {
+                        delivered = false;
+                        break;
+                    }
+                }
+                delivered
+            }
+        };
+        if !delivered {
+            debug!("No receiver left for the {kind} objects, stopping their watch");
+            return;
+        }
+    }
+}
+
+impl<E: Send + 'static> Controller<E> {
+    /// A controller watching objects with `client` according to `policy`, and the receiver of
+    /// the events it delivers.
+    #[must_use]
+    pub fn new(client: Client, policy: WatchPolicy) -> (Self, mpsc::Receiver<E>) {
+        let (tx, rx) = mpsc::channel(EVENT_CHANNEL_CAPACITY);
+        let controller = Self {
+            client,
+            policy,
+            tx,
+            watches: vec![],
+        };
+        (controller, rx)
+    }
+
+    /// Watch the objects of kind `K` in `namespace` selected by `config`, once the controller
+    /// runs. Returns the store the objects are kept in.
+    pub fn watch<K>(&mut self, namespace: &str, config: watcher::Config) -> Store<K>
+    where
+        K: Resource<Scope = kube::core::NamespaceResourceScope>
+            + Clone
+            + DeserializeOwned
+            + Debug
+            + Send
+            + Sync
+            + 'static,
+        K::DynamicType: Default + Eq + Hash + Clone,
+        E: From<ObjectEvent<K>>,
+    {
+        let api: Api<K> = Api::namespaced(self.client.clone(), namespace);
+        let (store, writer) = reflector::store();
+        let watch = run_watch(
+            api,
+            config,
+            writer,
+            store.clone(),
+            self.policy,
+            self.tx.clone(),
+        );
+        self.watches.push(watch.boxed());
+        store
+    }
+
+    /// Run all the watches, until the receiver of the events is dropped.
+    pub async fn run(self) {
+        // the watches have their own senders
+        drop(self.tx);
+        join_all(self.watches).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::WatchPolicy;
+    use std::time::Duration;
+
+    #[test]
+    fn watch_backoff_is_exponential_and_bounded() {
+        let policy = WatchPolicy {
+            min_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(5),
+            resync: None,
+        };
+        let mut delays = vec![];
+        let mut delay = None;
+        for _ in 0..5 {
+            delay = Some(policy.next_backoff(delay));
+            delays.extend(delay);
+        }
+        assert_eq!(
+            delays,
+            [1, 2, 4, 5, 5].map(Duration::from_secs).to_vec(),
+            "{delays:?}"
+        );
+    }
+}
@@ -10,6 +10,8 @@ pub mod bolero;
 #[cfg(feature = "client")]
 pub mod client;
 #[cfg(feature = "client")]
+pub mod controller;
+#[cfg(feature = "client")]
 pub mod utils;
 
 #[allow(clippy::all, clippy::pedantic)]
@@ -19,4 +21,4 @@ pub mod gateway_agent_crd {
 }
 
 #[cfg(feature = "client")]
-pub use client::watch_gateway_agent_crd;
+pub use client::watch_crds;
@@ -0,0 +1,129 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! The artifacts of the offline configuration compiler (`dataplane-compile`).
+//!
+//! The compiler validates a `GatewayAgent` spec and writes it to a directory, along with renderings
+//! of the tables the dataplane builds from it, for reviews, and a manifest listing the digests of
+//! all of them. The tables themselves are not serialized: the dataplane builds them from the spec
+//! when applying it, as the compiler does to validate it. A directory with a manifest is thus
+//! loaded from its spec only, once checked against its digest, rather than by merging all its
+//! files.
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+/// Version of the format of the manifest. To be bumped on incompatible changes.
+pub const MANIFEST_FORMAT_VERSION: u32 = 1;
+/// The manifest, written last, so that its presence indicates a complete set of artifacts
+pub const MANIFEST_FILE: &str = "manifest.json";
+/// The validated spec, which the dataplane loads
+pub const SPEC_FILE: &str = "gateway-agent-spec.yaml";
+
+/// The errors that may occur when reading artifacts
+#[derive(Debug, thiserror::Error)]
+pub enum ArtifactError {
+    #[error("Failed to read {}: {1}", .0.display())]
+    Io(PathBuf, #[source] std::io::Error),
+
+    #[error("Invalid manifest: {0}")]
+    Manifest(#[from] serde_json::Error),
+
+    #[error("Unsupported manifest format version {0}, expected {MANIFEST_FORMAT_VERSION}")]
+    FormatVersion(u32),
+
+    #[error("Artifact {0} is not in the manifest")]
+    MissingArtifact(String),
+
+    #[error("Artifact {0} does not match its digest")]
+    Digest(String),
+}
+
+/// A file of the artifacts, along with its SHA-256 digest
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactFile {
+    pub name: String,
+    pub sha256: String,
+}
+
+impl ArtifactFile {
+    /// The entry of the manifest of file `name`, of contents `contents`
+    #[must_use]
+    pub fn new(name: &str, contents: &[u8]) -> Self {
+        Self {
+            name: name.to_string(),
+            sha256: sha256_hex(contents),
+        }
+    }
+}
+
+/// The part of the manifest common to all its versions, listing the files. The compiler adds
+/// the vpc map and the NAT pools, which are not needed to load the spec.
+#[derive(Deserialize)]
+struct ManifestFiles {
+    format_version: u32,
+    files: Vec<ArtifactFile>,
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+    Sha256::digest(data)
+        .iter()
+        .fold(String::with_capacity(64), |mut hex, byte| {
+            let _ = write!(hex, "{byte:02x}");
+            hex
+        })
+}
+
+fn read_file(path: &Path) -> Result<String, ArtifactError> {
+    std::fs::read_to_string(path).map_err(|e| ArtifactError::Io(path.to_path_buf(), e))
+}
+
+/// Check that a manifest of format `format_version` can be read
+///
+/// # Errors
+/// Returns an error if the format of the manifest is not supported.
+pub fn check_format_version(format_version: u32) -> Result<(), ArtifactError> {
+    if format_version != MANIFEST_FORMAT_VERSION {
+        return Err(ArtifactError::FormatVersion(format_version));
+    }
+    Ok(())
+}
+
+/// Read the artifact `name` of directory `dir`, checking it against its digest in `files`, the
+/// files listed in the manifest of the directory
+///
+/// # Errors
+/// Returns an error if the artifact can't be read, is not listed or does not match its digest.
+pub fn read_artifact(
+    dir: &Path,
+    files: &[ArtifactFile],
+    name: &str,
+) -> Result<String, ArtifactError> {
+    let file = files
+        .iter()
+        .find(|file| file.name == name)
+        .ok_or_else(|| ArtifactError::MissingArtifact(name.to_string()))?;
+    let contents = read_file(&dir.join(name))?;
+    if sha256_hex(contents.as_bytes()) != file.sha256 {
+        return Err(ArtifactError::Digest(name.to_string()));
+    }
+    Ok(contents)
+}
+
+/// Read the spec compiled to directory `dir`, checked against its digest. Returns `None` if the
+/// directory holds no manifest, i.e. no compiled artifacts.
+///
+/// # Errors
+/// Returns an error if the manifest or the spec can't be read, or if the spec does not match its
+/// digest.
+pub fn read_compiled_spec(dir: &Path) -> Result<Option<String>, ArtifactError> {
+    let path = dir.join(MANIFEST_FILE);
+    if !path.exists() {
+        return Ok(None);
+    }
+    let manifest: ManifestFiles = serde_json::from_str(&read_file(&path)?)?;
+    check_format_version(manifest.format_version)?;
+    read_artifact(dir, &manifest.files, SPEC_FILE).map(Some)
+}
@@ -1,8 +1,9 @@
 // SPDX-License-Identifier: Apache-2.0
 // Copyright Open Network Fabric Authors
 
-//! Support for k8s-less mode where CRDs are learnt from a file
+//! Support for k8s-less mode where CRDs are learnt from the files of a directory
 
+pub mod artifacts;
 mod local;
 
 pub use local::kubeless_watch_gateway_agent_crd;
@@ -1,57 +1,118 @@
 // SPDX-License-Identifier: Apache-2.0
 // Copyright Open Network Fabric Authors
 
+use crate::artifacts::read_compiled_spec;
 use inotify::{Event, EventMask, Inotify, WatchMask};
-use k8s_intf::gateway_agent_crd::GatewayAgent;
-use k8s_intf::utils::load_crd_from_file;
-use std::collections::BTreeSet;
+use k8s_intf::gateway_agent_crd::{GatewayAgent, GatewayAgentSpec};
+use serde::Deserialize;
+use serde_yaml_ng::Value;
 use std::ffi::OsStr;
 use std::os::fd::AsRawFd;
-use std::path::{Path, PathBuf};
+use std::path::Path;
 use tokio::fs::create_dir_all;
 use tokio::io::unix::AsyncFd;
 
 #[allow(unused)]
 use tracing::{debug, error, trace, warn};
 
-/// Tell if an event reported by `Inotify` is worth checking according
-/// to our configuration. This function is somewhat heuristic to accommodate
-/// for changes done by text editors, which may create temporary files when
-/// editing them instead of modifying them in-place. This helper returns
-/// the name of the file to process or `None` if the event should be ignored.
-fn check_event(event: &Event<&OsStr>, dir: &str) -> Option<PathBuf> {
-    // trace!("event: {:?}, name: {:?}", event.mask, event.name);
+/// Tell if a file of the watched directory holds configuration. Some editors create '.swp/.swx'
+/// (e.g. nano) files or temporary hidden files (vi) when editing files instead of modifying them
+/// in-place: these are ignored.
+fn is_config_file(filename: &str) -> bool {
+    !filename.contains(".sw") && !filename.starts_with('.')
+}
 
+/// Tell if an event reported by `Inotify` changes the configuration held in the watched
+/// directory: a configuration file was written, or moved in or out of the directory, or deleted.
+fn check_event(event: &Event<&OsStr>) -> bool {
     // we watch a directory; so `Inotify` should report the name of a file.
-    let filename = event.name?;
-
-    // OsStr should be valid Unicode
-    let filename = filename.to_str()?;
+    let Some(filename) = event.name.and_then(OsStr::to_str) else {
+        return false;
+    };
+    let changes =
+        EventMask::CLOSE_WRITE | EventMask::MOVED_TO | EventMask::MOVED_FROM | EventMask::DELETE;
+    is_config_file(filename) && event.mask.intersects(changes)
+}
 
-    // some editors create '.swp/.swx' (e.g. nano) files or temporary hidden files (vi)
-    if filename.contains(".sw") || filename.starts_with('.') {
-        return None;
+/// Merge YAML document `doc` into `merged`: mappings are merged key by key, recursively, and any
+/// other value of `doc` replaces the one in `merged`.
+fn merge_yaml(merged: &mut Value, doc: Value) {
+    match (merged, doc) {
+        (_, Value::Null) => {}
+        (Value::Mapping(merged), Value::Mapping(doc)) => {
+            for (key, value) in doc {
+                match merged.get_mut(&key) {
+                    Some(entry) => merge_yaml(entry, value),
+                    None => {
+                        merged.insert(key, value);
+                    }
+                }
+            }
+        }
+        (merged, doc) => *merged = doc,
     }
+}
 
-    // This is sanity
-    if event.mask != EventMask::CLOSE_WRITE {
-        return None;
+/// Read the configuration held in directory `dir`: all the YAML (or JSON) documents of all its
+/// configuration files, merged in the order of the names of the files and of the documents in
+/// each file, or the spec alone if the directory holds artifacts of the compiler. Returns `None`
+/// if the directory holds no document.
+///
+/// # Errors
+/// Returns an error if the directory or a file can't be read, a file is not valid YAML, or the
+/// documents merged are not a `GatewayAgentSpec`, or if compiled artifacts are not consistent.
+fn load_crd_from_dir(dir: &Path) -> Result<Option<GatewayAgentSpec>, String> {
+    if let Some(spec) = read_compiled_spec(dir).map_err(|e| e.to_string())? {
+        return serde_yaml_ng::from_str(&spec)
+            .map(Some)
+            .map_err(|e| format!("Failed to deserialize compiled spec: {e}"));
     }
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read directory {}: {e}", dir.display()))?;
+    let mut files: Vec<_> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.is_file())
+        .filter(|path| {
+            path.file_name()
+                .and_then(OsStr::to_str)
+                .is_some_and(is_config_file)
+        })
+        .collect();
+    files.sort();
 
-    // name of file to read a crd spec from
-    Some(Path::new(dir).join(filename))
+    let mut merged = Value::Null;
+    for file in &files {
+        let text = std::fs::read_to_string(file)
+            .map_err(|e| format!("Failed to read file {}: {e}", file.display()))?;
+        for doc in serde_yaml_ng::Deserializer::from_str(&text) {
+            let doc = Value::deserialize(doc)
+                .map_err(|e| format!("Failed to parse file {}: {e}", file.display()))?;
+            merge_yaml(&mut merged, doc);
+        }
+    }
+    if merged.is_null() {
+        return Ok(None);
+    }
+    serde_yaml_ng::from_value(merged)
+        .map(Some)
+        .map_err(|e| format!("Failed to deserialize CRD from {}: {e}", dir.display()))
 }
 
 /// Watch for changes in the directory named `path`. If the directory does not exist,
-/// it gets created. When files are created or modified in the watched directory:
-///    - read their contents (assumed to contain a crd spec in yaml or json)
-///    - deserialize them into a `GatewayAgentSpec`
+/// it gets created. Upon start, and whenever files are created, modified, moved or deleted in
+/// the watched directory:
+///    - read the contents of all its files (assumed to contain crd specs in yaml or json, possibly
+///      split in multiple documents and files)
+///    - merge them and deserialize them into a `GatewayAgentSpec`
 ///    - build a GatewayAgent object
 ///    - call the caller-specified callback.
 ///
+/// This way, the directory can be managed like a GitOps repository, with each file holding a part
+/// of the configuration (e.g. a file per VPC).
+///
 /// The generation id of the GatewayAgent is automatically set by this function and
 /// monotonically increases every time a `GatewayAgentSpec` is successfully deserialized
-/// from a file.
+/// from the directory.
 ///
 /// # Errors
 /// Returns an error if the directory or the corresponding watch cannot be created.
@@ -65,9 +126,11 @@ pub async fn kubeless_watch_gateway_agent_crd(
         .map_err(|e| format!("Failed to create directory '{path}': {e}"))?;
 
     let mut inotify = Inotify::init().map_err(|e| format!("Failed to initialize inotify: {e}"))?;
+    let mask =
+        WatchMask::CLOSE_WRITE | WatchMask::MOVED_TO | WatchMask::MOVED_FROM | WatchMask::DELETE;
     inotify
         .watches()
-        .add(Path::new(path), WatchMask::CLOSE_WRITE)
+        .add(Path::new(path), mask)
         .map_err(|e| format!("Failed to add watch for path {path}: {e}"))?;
 
     // generation id is automatically set and will monotonically increase
@@ -78,37 +141,40 @@ pub async fn kubeless_watch_gateway_agent_crd(
 
     debug!("Starting kubeless watcher for directory '{path}'");
 
+    // the files already in the directory make the first configuration
+    let mut changed = true;
     loop {
+        if changed {
+            debug!("Loading configuration from directory '{path}'...");
+            match load_crd_from_dir(Path::new(path)) {
+                Ok(Some(crd_spec)) => {
+                    let mut crd = GatewayAgent::new(gwname, crd_spec);
+                    crd.metadata.generation = Some(generation);
+                    crd.metadata.namespace = Some("default".to_string());
+                    generation += 1;
+                    callback(&crd).await;
+                }
+                Ok(None) => debug!("No configuration in directory '{path}'"),
+                Err(e) => error!("Failed to load crd spec from directory: {e}"),
+            }
+        }
+
         trace!("Waiting for changes...");
         let Ok(mut guard) = async_fd.readable().await else {
             error!("Failure checking async fd readiness");
+            changed = false;
             continue;
         };
 
         let mut buffer = [0u8; 4096];
-        match inotify.read_events(&mut buffer) {
-            Ok(events) => {
-                // collapse all events by filename: `check_event` will filter out unwanted events.
-                let files: BTreeSet<PathBuf> =
-                    events.filter_map(|e| check_event(&e, path)).collect();
-
-                // iterate over the set of files. Deserialize their contents and call user callback.
-                for file in files.iter() {
-                    debug!("Processing file {file:#?}...");
-                    match load_crd_from_file(file.to_str().unwrap()) {
-                        Ok(crd_spec) => {
-                            let mut crd = GatewayAgent::new(gwname, crd_spec);
-                            crd.metadata.generation = Some(generation);
-                            crd.metadata.namespace = Some("default".to_string());
-                            generation += 1;
-                            callback(&crd).await;
-                        }
-                        Err(e) => error!("Failed to load crd spec from file: {e}"),
-                    };
-                }
+        // collapse all the events read: the whole directory is loaded again upon any change
+        changed = match inotify.read_events(&mut buffer) {
+            Ok(mut events) => events.any(|e| check_event(&e)),
+            Err(e) => {
+                error!("Failed to read events from file: {e}");
+                false
             }
-            Err(e) => error!("Failed to read events from file: {e}"),
-        }
+        };
         guard.clear_ready();
     }
 }
@@ -120,10 +186,76 @@ mod test {
     use std::path::PathBuf;
     use std::time::Duration;
 
-    use super::kubeless_watch_gateway_agent_crd;
+    use super::{kubeless_watch_gateway_agent_crd, load_crd_from_dir, merge_yaml};
+    use crate::artifacts::{ArtifactFile, MANIFEST_FILE, MANIFEST_FORMAT_VERSION, SPEC_FILE};
+    use serde_yaml_ng::Value;
     use tracing::debug;
     use tracing_test::traced_test;
 
+    #[test]
+    fn kubeless_merge_yaml() {
+        let mut merged = Value::Null;
+        for doc in [
+            "gateway:\n  asn: 65000\n  workers: 4\nvpcs:\n  vpc-1: {}\n",
+            "gateway:\n  asn: 65001\nvpcs:\n  vpc-2: {}\n",
+            "~",
+        ] {
+            merge_yaml(&mut merged, serde_yaml_ng::from_str(doc).unwrap());
+        }
+        let expected: Value = serde_yaml_ng::from_str(
+            "gateway:\n  asn: 65001\n  workers: 4\nvpcs:\n  vpc-1: {}\n  vpc-2: {}\n",
+        )
+        .unwrap();
+        assert_eq!(merged, expected);
+    }
+
+    #[test]
+    fn kubeless_load_directory() {
+        let dir = std::env::temp_dir().join(format!("kubeless-load-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        assert!(load_crd_from_dir(&dir).unwrap().is_none());
+
+        std::fs::write(dir.join("00-gateway.yaml"), "agentVersion: MINIMAL\n").unwrap();
+        std::fs::write(
+            dir.join("10-asn.yaml"),
+            "gateway:\n  asn: 65000\n---\ngateway:\n  asn: 65001\n",
+        )
+        .unwrap();
+        // left behind by an editor
+        std::fs::write(dir.join(".10-asn.yaml.swp"), "not: [yaml").unwrap();
+        let spec = load_crd_from_dir(&dir).unwrap().unwrap();
+        assert_eq!(spec.agent_version.as_deref(), Some("MINIMAL"));
+        assert_eq!(spec.gateway.as_ref().unwrap().asn, Some(65001));
+
+        std::fs::write(dir.join("20-broken.yaml"), "gateway: [").unwrap();
+        assert!(load_crd_from_dir(&dir).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn kubeless_load_artifacts() {
+        let dir = std::env::temp_dir().join(format!("kubeless-artifacts-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let spec = "agentVersion: COMPILED\n";
+        std::fs::write(dir.join(SPEC_FILE), spec).unwrap();
+        // the renderings of the tables are not configuration
+        std::fs::write(dir.join("flow-filter.txt"), "not: [yaml").unwrap();
+        let manifest = serde_json::json!({
+            "format_version": MANIFEST_FORMAT_VERSION,
+            "gwname": "gw-1",
+            "files": [ArtifactFile::new(SPEC_FILE, spec.as_bytes())],
+        });
+        std::fs::write(dir.join(MANIFEST_FILE), manifest.to_string()).unwrap();
+        let loaded = load_crd_from_dir(&dir).unwrap().unwrap();
+        assert_eq!(loaded.agent_version.as_deref(), Some("COMPILED"));
+
+        // nor is a spec not matching its digest loaded
+        std::fs::write(dir.join(SPEC_FILE), "agentVersion: ALTERED\n").unwrap();
+        let err = load_crd_from_dir(&dir).unwrap_err();
+        assert!(err.contains("does not match its digest"), "{err}");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
     #[tokio::test]
     #[cfg_attr(not(emulated), traced_test)]
     #[ignore = "test is incorrect and needs reworked"]
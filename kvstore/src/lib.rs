@@ -0,0 +1,250 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! A small embedded key-value store for the state that the dataplane learns at runtime and must
+//! keep across restarts, such as the route tables allocated to VPCs.
+//!
+//! Stores implement [`KvStore`]. Subsystems don't use a store directly, but their own
+//! [`Namespace`] of it, so that their keys never clash. Keys are strings and values are opaque
+//! bytes, whose encoding is up to each subsystem.
+//!
+//! Two stores are provided:
+//!
+//! - [`RedbStore`] persists its contents to a file. Every write is a transaction, durable once
+//!   the write returns, so that the store is never left half-written by a crash.
+//! - [`MemoryStore`] keeps its contents in memory. It is meant for tests, and as a fallback when
+//!   no file can be used.
+
+#![deny(clippy::all, clippy::unwrap_used, clippy::expect_used, clippy::panic)]
+
+mod memory;
+mod redb_store;
+
+pub use memory::MemoryStore;
+pub use redb_store::RedbStore;
+
+use concurrency::sync::Arc;
+use std::fmt::Display;
+use thiserror::Error;
+
+/// The errors of a [`KvStore`]
+#[derive(Debug, Error)]
+pub enum KvError {
+    #[error("Invalid namespace name '{0}'")]
+    InvalidNamespace(String),
+    #[error("Storage failure: {0}")]
+    Storage(Box<redb::Error>),
+}
+
+/// The entries of a namespace, sorted by key
+pub type Entries = Vec<(String, Vec<u8>)>;
+
+/// A key-value store, whose keys are grouped in namespaces.
+///
+/// Reading a namespace which was never written to yields nothing, rather than an error.
+pub trait KvStore: Send + Sync {
+    /// Get the value of a key
+    ///
+    /// # Errors
+    ///
+    /// Fails if the store can't be read.
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, KvError>;
+
+    /// Set the value of a key
+    ///
+    /// # Errors
+    ///
+    /// Fails if the store can't be written. The previous value is kept then.
+    fn set(&self, namespace: &str, key: &str, value: &[u8]) -> Result<(), KvError>;
+
+    /// Remove a key, telling if it was present
+    ///
+    /// # Errors
+    ///
+    /// Fails if the store can't be written. The key is kept then.
+    fn remove(&self, namespace: &str, key: &str) -> Result<bool, KvError>;
+
+    /// Replace all the entries of a namespace at once
+    ///
+    /// # Errors
+    ///
+    /// Fails if the store can't be written. The previous entries are kept then.
+    fn replace(&self, namespace: &str, entries: Entries) -> Result<(), KvError>;
+
+    /// Get all the entries of a namespace
+    ///
+    /// # Errors
+    ///
+    /// Fails if the store can't be read.
+    fn entries(&self, namespace: &str) -> Result<Entries, KvError>;
+
+    /// Get the names of the namespaces holding entries
+    ///
+    /// # Errors
+    ///
+    /// Fails if the store can't be read.
+    fn namespaces(&self) -> Result<Vec<String>, KvError>;
+}
+
+/// The namespace of a [`KvStore`] reserved to a subsystem
+#[derive(Clone)]
+pub struct Namespace {
+    store: Arc<dyn KvStore>,
+    name: String,
+}
+
+impl Namespace {
+    /// Get the namespace `name` of a store. Names are made of lowercase letters, digits and
+    /// dashes.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the name is not valid.
+    pub fn new(store: Arc<dyn KvStore>, name: &str) -> Result<Self, KvError> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if !valid {
+            return Err(KvError::InvalidNamespace(name.to_string()));
+        }
+        Ok(Self {
+            store,
+            name: name.to_string(),
+        })
+    }
+
+    /// The name of the namespace
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the value of a key. See [`KvStore::get`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if the store can't be read.
+    pub fn get(&self, key: &str) -> Result<Option<Vec<u8>>, KvError> {
+        self.store.get(&self.name, key)
+    }
+
+    /// Set the value of a key. See [`KvStore::set`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if the store can't be written.
+    pub fn set(&self, key: &str, value: &[u8]) -> Result<(), KvError> {
+        self.store.set(&self.name, key, value)
+    }
+
+    /// Remove a key. See [`KvStore::remove`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if the store can't be written.
+    pub fn remove(&self, key: &str) -> Result<bool, KvError> {
+        self.store.remove(&self.name, key)
+    }
+
+    /// Replace all the entries of the namespace. See [`KvStore::replace`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if the store can't be written.
+    pub fn replace(&self, entries: Entries) -> Result<(), KvError> {
+        self.store.replace(&self.name, entries)
+    }
+
+    /// Get all the entries of the namespace. See [`KvStore::entries`].
+    ///
+    /// # Errors
+    ///
+    /// Fails if the store can't be read.
+    pub fn entries(&self) -> Result<Entries, KvError> {
+        self.store.entries(&self.name)
+    }
+}
+
+impl std::fmt::Debug for Namespace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Namespace")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Display of a value: as text if it is printable, in hexadecimal otherwise
+pub struct ValueDisplay<'a>(pub &'a [u8]);
+
+impl Display for ValueDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match std::str::from_utf8(self.0) {
+            Ok(text) if !text.chars().any(char::is_control) => write!(f, "{text}"),
+            _ => {
+                write!(f, "0x")?;
+                self.0.iter().try_for_each(|byte| write!(f, "{byte:02x}"))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Exercise a store through a namespace
+    pub(crate) fn exercise(store: &Arc<dyn KvStore>) {
+        let first = Namespace::new(store.clone(), "first").unwrap();
+        let second = Namespace::new(store.clone(), "second").unwrap();
+        assert_eq!(first.get("key").unwrap(), None);
+        assert!(first.entries().unwrap().is_empty());
+
+        first.set("key", b"one").unwrap();
+        second.set("key", b"two").unwrap();
+        assert_eq!(first.get("key").unwrap(), Some(b"one".to_vec()));
+        assert_eq!(second.get("key").unwrap(), Some(b"two".to_vec()));
+        assert_eq!(
+            store.namespaces().unwrap(),
+            vec!["first".to_string(), "second".to_string()]
+        );
+
+        assert!(first.remove("key").unwrap());
+        assert!(!first.remove("key").unwrap());
+        assert_eq!(first.get("key").unwrap(), None);
+        assert_eq!(second.get("key").unwrap(), Some(b"two".to_vec()));
+
+        second
+            .replace(vec![
+                ("a".to_string(), b"1".to_vec()),
+                ("b".to_string(), b"2".to_vec()),
+            ])
+            .unwrap();
+        assert_eq!(
+            second.entries().unwrap(),
+            vec![
+                ("a".to_string(), b"1".to_vec()),
+                ("b".to_string(), b"2".to_vec())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_namespace_names() {
+        let store: Arc<dyn KvStore> = Arc::new(MemoryStore::default());
+        assert!(Namespace::new(store.clone(), "route-tables").is_ok());
+        assert!(Namespace::new(store.clone(), "nat2").is_ok());
+        for bad in ["", "Route-Tables", "route tables", "route/tables"] {
+            assert!(matches!(
+                Namespace::new(store.clone(), bad),
+                Err(KvError::InvalidNamespace(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_value_display() {
+        assert_eq!(ValueDisplay(b"10000").to_string(), "10000");
+        assert_eq!(ValueDisplay(&[0, 255]).to_string(), "0x00ff");
+    }
+}
@@ -0,0 +1,84 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! A [`KvStore`] kept in memory
+
+use crate::{Entries, KvError, KvStore};
+use concurrency::sync::Mutex;
+use std::collections::BTreeMap;
+
+/// A [`KvStore`] kept in memory, whose contents are lost when it is dropped
+#[derive(Debug, Default)]
+pub struct MemoryStore(Mutex<BTreeMap<String, BTreeMap<String, Vec<u8>>>>);
+
+impl KvStore for MemoryStore {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, KvError> {
+        Ok(self
+            .0
+            .lock()
+            .get(namespace)
+            .and_then(|entries| entries.get(key))
+            .cloned())
+    }
+
+    fn set(&self, namespace: &str, key: &str, value: &[u8]) -> Result<(), KvError> {
+        self.0
+            .lock()
+            .entry(namespace.to_string())
+            .or_default()
+            .insert(key.to_string(), value.to_vec());
+        Ok(())
+    }
+
+    fn remove(&self, namespace: &str, key: &str) -> Result<bool, KvError> {
+        let mut namespaces = self.0.lock();
+        let Some(entries) = namespaces.get_mut(namespace) else {
+            return Ok(false);
+        };
+        let removed = entries.remove(key).is_some();
+        if entries.is_empty() {
+            namespaces.remove(namespace);
+        }
+        Ok(removed)
+    }
+
+    fn replace(&self, namespace: &str, entries: Entries) -> Result<(), KvError> {
+        let mut namespaces = self.0.lock();
+        if entries.is_empty() {
+            namespaces.remove(namespace);
+        } else {
+            namespaces.insert(namespace.to_string(), entries.into_iter().collect());
+        }
+        Ok(())
+    }
+
+    fn entries(&self, namespace: &str) -> Result<Entries, KvError> {
+        Ok(self
+            .0
+            .lock()
+            .get(namespace)
+            .map(|entries| {
+                entries
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    fn namespaces(&self) -> Result<Vec<String>, KvError> {
+        Ok(self.0.lock().keys().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use concurrency::sync::Arc;
+
+    #[test]
+    fn test_memory_store() {
+        let store: Arc<dyn KvStore> = Arc::new(MemoryStore::default());
+        crate::test::exercise(&store);
+    }
+}
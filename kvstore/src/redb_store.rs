@@ -0,0 +1,180 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! A [`KvStore`] persisted to a file by the [redb](https://docs.rs/redb) embedded database
+
+use crate::{Entries, KvError, KvStore};
+use redb::{
+    Database, ReadableTable, ReadableTableMetadata, TableDefinition, TableError, TableHandle,
+};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info};
+
+/// The table of a namespace, created on its first write
+fn namespace_table(namespace: &str) -> TableDefinition<'_, &'static str, &'static [u8]> {
+    TableDefinition::new(namespace)
+}
+
+fn storage(e: impl Into<redb::Error>) -> KvError {
+    KvError::Storage(Box::new(e.into()))
+}
+
+/// A [`KvStore`] persisted to a file, with one table per namespace.
+///
+/// Every write is a transaction of its own, which is durable once the write returns.
+pub struct RedbStore {
+    db: Database,
+    path: PathBuf,
+}
+
+impl RedbStore {
+    /// Open the store persisted in the file at `path`, creating it and its directory if needed.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file can't be created or opened, e.g. if it is not a store, or if another
+    /// process has it open.
+    pub fn open(path: &Path) -> Result<Self, KvError> {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(storage)?;
+        }
+        let db = Database::create(path).map_err(storage)?;
+        info!("Opened state store {}", path.display());
+        Ok(Self {
+            db,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// The file the store is persisted to
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl std::fmt::Debug for RedbStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RedbStore")
+            .field("path", &self.path)
+            .finish_non_exhaustive()
+    }
+}
+
+impl KvStore for RedbStore {
+    fn get(&self, namespace: &str, key: &str) -> Result<Option<Vec<u8>>, KvError> {
+        let txn = self.db.begin_read().map_err(storage)?;
+        let table = match txn.open_table(namespace_table(namespace)) {
+            Ok(table) => table,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(None),
+            Err(e) => return Err(storage(e)),
+        };
+        let value = table.get(key).map_err(storage)?;
+        Ok(value.map(|value| value.value().to_vec()))
+    }
+
+    fn set(&self, namespace: &str, key: &str, value: &[u8]) -> Result<(), KvError> {
+        let txn = self.db.begin_write().map_err(storage)?;
+        {
+            let mut table = txn
+                .open_table(namespace_table(namespace))
+                .map_err(storage)?;
+            table.insert(key, value).map_err(storage)?;
+        }
+        txn.commit().map_err(storage)?;
+        debug!("Set {namespace}/{key} in state store");
+        Ok(())
+    }
+
+    fn remove(&self, namespace: &str, key: &str) -> Result<bool, KvError> {
+        let txn = self.db.begin_write().map_err(storage)?;
+        let (removed, empty) = {
+            let mut table = txn
+                .open_table(namespace_table(namespace))
+                .map_err(storage)?;
+            let removed = table.remove(key).map_err(storage)?.is_some();
+            (removed, table.is_empty().map_err(storage)?)
+        };
+        // namespaces holding no entries are not kept
+        if empty {
+            txn.delete_table(namespace_table(namespace))
+                .map_err(storage)?;
+        }
+        txn.commit().map_err(storage)?;
+        debug!("Removed {namespace}/{key} from state store");
+        Ok(removed)
+    }
+
+    fn replace(&self, namespace: &str, entries: Entries) -> Result<(), KvError> {
+        let txn = self.db.begin_write().map_err(storage)?;
+        txn.delete_table(namespace_table(namespace))
+            .map_err(storage)?;
+        if !entries.is_empty() {
+            let mut table = txn
+                .open_table(namespace_table(namespace))
+                .map_err(storage)?;
+            for (key, value) in &entries {
+                table
+                    .insert(key.as_str(), value.as_slice())
+                    .map_err(storage)?;
+            }
+        }
+        txn.commit().map_err(storage)?;
+        debug!(
+            "Replaced namespace {namespace} of state store with {} entries",
+            entries.len()
+        );
+        Ok(())
+    }
+
+    fn entries(&self, namespace: &str) -> Result<Entries, KvError> {
+        let txn = self.db.begin_read().map_err(storage)?;
+        let table = match txn.open_table(namespace_table(namespace)) {
+            Ok(table) => table,
+            Err(TableError::TableDoesNotExist(_)) => return Ok(Entries::new()),
+            Err(e) => return Err(storage(e)),
+        };
+        table
+            .iter()
+            .map_err(storage)?
+            .map(|entry| {
+                let (key, value) = entry.map_err(storage)?;
+                Ok((key.value().to_string(), value.value().to_vec()))
+            })
+            .collect()
+    }
+
+    fn namespaces(&self) -> Result<Vec<String>, KvError> {
+        let txn = self.db.begin_read().map_err(storage)?;
+        let mut namespaces: Vec<_> = txn
+            .list_tables()
+            .map_err(storage)?
+            .map(|table| table.name().to_string())
+            .collect();
+        namespaces.sort();
+        Ok(namespaces)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use concurrency::sync::Arc;
+
+    #[test]
+    fn test_redb_store() {
+        let path = std::env::temp_dir().join(format!("kvstore-{}.redb", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let store: Arc<dyn KvStore> = Arc::new(RedbStore::open(&path).unwrap());
+        crate::test::exercise(&store);
+        drop(store);
+
+        // the contents survive reopening the store
+        let store = RedbStore::open(&path).unwrap();
+        assert_eq!(store.namespaces().unwrap(), vec!["second".to_string()]);
+        assert_eq!(store.get("second", "b").unwrap(), Some(b"2".to_vec()));
+        drop(store);
+        let _ = std::fs::remove_file(&path);
+    }
+}
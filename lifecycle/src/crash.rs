@@ -0,0 +1,274 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Crash reports.
+//!
+//! [`install`] sets a panic hook writing a [`CrashReport`] to a directory each time a thread of
+//! the dataplane panics. The reports are kept across restarts, up to [`MAX_CRASH_REPORTS`] of
+//! them, so that they can be listed and fetched with [`CrashReports`] once the dataplane is back.
+
+use concurrency::sync::OnceLock;
+use concurrency::sync::atomic::{AtomicI64, Ordering};
+use std::backtrace::Backtrace;
+use std::io::Write;
+use std::panic::PanicHookInfo;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// Maximum number of crash reports kept: the oldest ones are removed beyond it
+pub const MAX_CRASH_REPORTS: usize = 16;
+
+const REPORT_PREFIX: &str = "crash-";
+const REPORT_SUFFIX: &str = ".json";
+
+/// Errors when listing or reading crash reports
+#[derive(Debug, thiserror::Error)]
+pub enum CrashReportError {
+    /// The name does not designate a crash report
+    #[error("Invalid crash report name '{0}'")]
+    InvalidName(String),
+    /// The report or its directory could not be read
+    #[error("Failed to read crash reports: {0}")]
+    Io(#[from] std::io::Error),
+    /// The report is not valid
+    #[error("Failed to parse crash report: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+/// A report of the crash of the dataplane
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct CrashReport {
+    /// Time of the crash, in seconds since the unix epoch
+    pub time: u64,
+    /// Version of the dataplane which crashed
+    pub version: String,
+    /// Generation id of the configuration applied at the time of the crash (0 if none was)
+    pub config_genid: i64,
+    /// Name of the thread which panicked
+    pub thread: String,
+    /// Panic message
+    pub message: String,
+    /// Source location of the panic, if known
+    pub location: Option<String>,
+    /// Backtrace of the panicking thread
+    pub backtrace: String,
+    /// Most recent log events, oldest first
+    pub journal: Vec<String>,
+}
+
+/// A summary of a crash report, as listed by [`CrashReports::list`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CrashReportEntry {
+    /// Name of the report, to fetch it with [`CrashReports::get`]
+    pub name: String,
+    /// Size of the report, in bytes
+    pub size: u64,
+}
+
+/// The crash reports written to a directory
+#[derive(Clone, Debug)]
+pub struct CrashReports {
+    dir: PathBuf,
+}
+
+impl CrashReports {
+    /// The crash reports written to `dir`
+    #[must_use]
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// The directory the reports are written to
+    #[must_use]
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// List the crash reports, from the oldest to the most recent
+    ///
+    /// # Errors
+    ///
+    /// Fails if the directory of the reports exists but can't be read.
+    pub fn list(&self) -> Result<Vec<CrashReportEntry>, CrashReportError> {
+        let entries = match std::fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(e.into()),
+        };
+        let mut reports = vec![];
+        for entry in entries {
+            let entry = entry?;
+            let Some(name) = entry.file_name().to_str().map(ToString::to_string) else {
+                continue;
+            };
+            if is_report_name(&name) {
+                reports.push(CrashReportEntry {
+                    name,
+                    size: entry.metadata()?.len(),
+                });
+            }
+        }
+        // the names embed the time of the crash
+        reports.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(reports)
+    }
+
+    /// Read the crash report named `name`
+    ///
+    /// # Errors
+    ///
+    /// Fails if there is no such report or if it can't be read or parsed.
+    pub fn get(&self, name: &str) -> Result<CrashReport, CrashReportError> {
+        if !is_report_name(name) || name.contains(std::path::is_separator) {
+            return Err(CrashReportError::InvalidName(name.to_string()));
+        }
+        let contents = std::fs::read(self.dir.join(name))?;
+        Ok(serde_json::from_slice(&contents)?)
+    }
+
+    /// Write a crash report, then remove the oldest ones beyond [`MAX_CRASH_REPORTS`]
+    fn write(&self, report: &CrashReport, millis: u32) -> Result<PathBuf, CrashReportError> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!(
+            "{REPORT_PREFIX}{}{millis:03}{REPORT_SUFFIX}",
+            report.time
+        ));
+        // streamed to the file rather than serialized in memory first
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        serde_json::to_writer_pretty(&mut file, report)?;
+        file.flush()?;
+        let reports = self.list()?;
+        let excess = reports.len().saturating_sub(MAX_CRASH_REPORTS);
+        for old in &reports[..excess] {
+            std::fs::remove_file(self.dir.join(&old.name))?;
+        }
+        Ok(path)
+    }
+}
+
+fn is_report_name(name: &str) -> bool {
+    name.starts_with(REPORT_PREFIX) && name.ends_with(REPORT_SUFFIX)
+}
+
+/// What the panic hook needs to write crash reports
+struct CrashReporter {
+    reports: CrashReports,
+    version: String,
+    journal: fn() -> Vec<String>,
+}
+
+static REPORTER: OnceLock<CrashReporter> = OnceLock::new();
+static CONFIG_GENID: AtomicI64 = AtomicI64::new(0);
+
+/// Record the generation id of the configuration applied, to be included in crash reports
+pub fn set_config_genid(genid: i64) {
+    CONFIG_GENID.store(genid, Ordering::Relaxed);
+}
+
+/// The crash reports written by the panic hook, if [`install`] was called
+#[must_use]
+pub fn crash_reports() -> Option<&'static CrashReports> {
+    REPORTER.get().map(|reporter| &reporter.reports)
+}
+
+/// Install a panic hook writing crash reports to `dir`. The hook which was installed before,
+/// if any, is called afterwards. `journal` provides the recent log events to include in the
+/// reports. Only the first call installs the hook.
+pub fn install(dir: &Path, version: &str, journal: fn() -> Vec<String>) {
+    let reporter = CrashReporter {
+        reports: CrashReports::new(dir),
+        version: version.to_string(),
+        journal,
+    };
+    if REPORTER.set(reporter).is_err() {
+        warn!("Crash reporter already installed");
+        return;
+    }
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |panic| {
+        if let Some(reporter) = REPORTER.get() {
+            write_report(reporter, panic);
+        }
+        previous(panic);
+    }));
+    info!("Crash reports are written to {}", dir.display());
+}
+
+fn write_report(reporter: &CrashReporter, panic: &PanicHookInfo<'_>) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let message = if let Some(message) = panic.payload().downcast_ref::<&str>() {
+        (*message).to_string()
+    } else if let Some(message) = panic.payload().downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown".to_string()
+    };
+    let report = CrashReport {
+        time: now.as_secs(),
+        version: reporter.version.clone(),
+        config_genid: CONFIG_GENID.load(Ordering::Relaxed),
+        thread: std::thread::current()
+            .name()
+            .unwrap_or("unnamed")
+            .to_string(),
+        message,
+        location: panic.location().map(ToString::to_string),
+        backtrace: Backtrace::force_capture().to_string(),
+        journal: (reporter.journal)(),
+    };
+    // not logged with tracing, whose subscriber may hold the lock of the journal
+    match reporter.reports.write(&report, now.subsec_millis()) {
+        Ok(path) => eprintln!("Crash report written to {}", path.display()),
+        Err(e) => eprintln!("Failed to write crash report: {e}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(time: u64) -> CrashReport {
+        CrashReport {
+            time,
+            version: "test".to_string(),
+            config_genid: 7,
+            thread: "main".to_string(),
+            message: "boom".to_string(),
+            location: Some("src/lib.rs:1:1".to_string()),
+            backtrace: String::new(),
+            journal: vec!["INFO event".to_string()],
+        }
+    }
+
+    #[test]
+    fn crash_reports_are_listed_and_pruned() {
+        let dir = std::env::temp_dir().join(format!("crash-reports-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let reports = CrashReports::new(&dir);
+        assert!(reports.list().unwrap().is_empty());
+
+        for time in 0..(MAX_CRASH_REPORTS as u64 + 2) {
+            reports.write(&report(1_000 + time), 0).unwrap();
+        }
+        let listed = reports.list().unwrap();
+        assert_eq!(listed.len(), MAX_CRASH_REPORTS);
+        // the oldest reports were removed
+        assert_eq!(listed[0].name, "crash-1002000.json");
+        assert_eq!(reports.get(&listed[0].name).unwrap(), report(1_002));
+
+        assert!(matches!(
+            reports.get("../crash-1002000.json"),
+            Err(CrashReportError::InvalidName(_))
+        ));
+        assert!(matches!(
+            reports.get("crash-0.json"),
+            Err(CrashReportError::Io(_))
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
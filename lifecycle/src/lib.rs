@@ -6,7 +6,9 @@
 //! [`Shutdown`] bundles a root [`CancellationToken`] and one [`Subsystem`]
 //! per long-lived component. Each subsystem owns a cancel token and a
 //! [`TaskTracker`]; [`Shutdown::drain_in_order`] drains them in topological
-//! order with per-subsystem deadlines.
+//! order with per-subsystem deadlines. [`startup::Startup`] starts them in
+//! dependency order. [`crash::install`] has a report written whenever
+//! the process panics.
 
 #![deny(
     unsafe_code,
@@ -18,6 +20,9 @@
     clippy::panic
 )]
 
+pub mod crash;
+pub mod startup;
+
 use concurrency::sync::Arc;
 use concurrency::sync::atomic::{AtomicBool, Ordering};
 use std::future::Future;
@@ -163,6 +168,8 @@ impl Subsystem {
 /// process-level ceiling enforced by [`spawn_shutdown_watchdog`].
 pub mod default_deadlines {
     use std::time::Duration;
+    /// Drain the packets in flight in the driver, before its workers stop.
+    pub const DRIVER: Duration = Duration::from_secs(2);
     /// Drain workers' tokio tasks.
     pub const WORKERS: Duration = Duration::from_secs(5);
     /// Drain RIO's tokio tasks.
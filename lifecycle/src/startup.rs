@@ -0,0 +1,617 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Dependency-aware startup of subsystems.
+//!
+//! A [`Startup`] is a set of [`StartupStep`]s, each declaring the steps it depends on and how
+//! long it may take to become ready. [`Startup::run`] starts every step as soon as all of its
+//! dependencies are ready, concurrently with any other step whose dependencies are ready, and
+//! returns a [`StartupReport`] telling what started, how long each step took and what failed.
+//!
+//! A step is ready when its start function signals its [`Readiness`], or when it returns `Ok`.
+//! Start functions may also leave notes on what they started, which are kept in the report.
+//! If a step fails or times out, the steps depending on it are skipped, no further step is
+//! started and [`Shutdown::fail`] is called. Start functions should observe the root
+//! cancellation token: [`Startup::run`] only returns once all started functions have returned.
+
+use crate::Shutdown;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt::Display;
+use std::panic::{AssertUnwindSafe, catch_unwind};
+use std::sync::mpsc::{Sender, channel};
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+/// Default time that subsystems have to become ready.
+pub mod default_timeouts {
+    use std::time::Duration;
+    /// Start the router (RIO, CPI and CLI).
+    pub const ROUTER: Duration = Duration::from_secs(10);
+    /// Start the management plane. Covers reaching the k8s API.
+    pub const MGMT: Duration = Duration::from_secs(60);
+    /// Start the metrics endpoint and stats collection.
+    pub const METRICS: Duration = Duration::from_secs(5);
+    /// Start the BMP server.
+    pub const BMP: Duration = Duration::from_secs(5);
+    /// Start the packet driver and its workers.
+    pub const DRIVER: Duration = Duration::from_secs(30);
+    /// Start listening for later generations of the launch configuration.
+    pub const GENERATIONS: Duration = Duration::from_secs(5);
+}
+
+/// Errors in the declaration of the startup steps.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum StartupError {
+    /// Two steps have the same name.
+    #[error("Duplicate startup step '{0}'")]
+    DuplicateStep(&'static str),
+    /// A step depends on a step that was not declared.
+    #[error("Startup step '{0}' depends on unknown step '{1}'")]
+    UnknownDependency(&'static str, &'static str),
+    /// Steps depend on each other.
+    #[error("Startup step '{0}' is part of a dependency cycle")]
+    DependencyCycle(&'static str),
+}
+
+/// The error of a start function. The errors of the subsystems convert into it with `?`.
+pub type StepError = Box<dyn Error + Send + Sync>;
+
+/// The error of a start function which panicked, with the message of the panic.
+#[derive(Debug, thiserror::Error)]
+#[error("panicked: {0}")]
+pub struct StepPanicked(pub String);
+
+enum Event {
+    Ready,
+    Note(String),
+    Returned(Result<(), StepError>),
+}
+
+/// The handle for a start function to signal that its subsystem is ready.
+#[derive(Clone, Debug)]
+pub struct Readiness {
+    step: usize,
+    tx: Sender<(usize, Event)>,
+}
+
+impl Readiness {
+    /// Signal that the subsystem is ready, so that the steps depending on it can start.
+    pub fn ready(&self) {
+        let _ = self.tx.send((self.step, Event::Ready));
+    }
+
+    /// Record a note in the report of the step, e.g. on the resources it picked.
+    pub fn note(&self, note: impl Into<String>) {
+        let _ = self.tx.send((self.step, Event::Note(note.into())));
+    }
+}
+
+type StartFn<'env> = Box<dyn FnOnce(Readiness) -> Result<(), StepError> + Send + 'env>;
+
+/// A subsystem to start.
+pub struct StartupStep<'env> {
+    name: &'static str,
+    deps: Vec<&'static str>,
+    timeout: Duration,
+    start: StartFn<'env>,
+}
+
+impl<'env> StartupStep<'env> {
+    /// Declare a step which has `timeout` to become ready after `start` is called.
+    #[must_use]
+    pub fn new<F>(name: &'static str, timeout: Duration, start: F) -> Self
+    where
+        F: FnOnce(Readiness) -> Result<(), StepError> + Send + 'env,
+    {
+        Self {
+            name,
+            deps: vec![],
+            timeout,
+            start: Box::new(start),
+        }
+    }
+
+    /// Declare the steps that must be ready before this one starts.
+    #[must_use]
+    pub fn after(mut self, deps: &[&'static str]) -> Self {
+        self.deps.extend_from_slice(deps);
+        self
+    }
+}
+
+/// The outcome of a startup step.
+#[derive(Debug)]
+pub enum StepOutcome {
+    /// The step became ready after the given time.
+    Ready(Duration),
+    /// The step failed after the given time, with the given error.
+    Failed(Duration, StepError),
+    /// The step did not become ready within its timeout.
+    TimedOut(Duration),
+    /// The step was not started since the given dependency did not become ready.
+    Skipped(&'static str),
+    /// The step failed or was not started because startup was cancelled.
+    Cancelled,
+}
+
+impl StepOutcome {
+    /// Tell if the step became ready.
+    #[must_use]
+    pub fn is_ready(&self) -> bool {
+        matches!(self, StepOutcome::Ready(_))
+    }
+}
+
+impl Display for StepOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StepOutcome::Ready(took) => write!(f, "ready in {took:?}"),
+            StepOutcome::Failed(took, e) => write!(f, "failed after {took:?}: {e}"),
+            StepOutcome::TimedOut(timeout) => write!(f, "not ready within {timeout:?}"),
+            StepOutcome::Skipped(dep) => write!(f, "skipped: '{dep}' is not ready"),
+            StepOutcome::Cancelled => write!(f, "cancelled"),
+        }
+    }
+}
+
+/// The report of a startup step.
+#[derive(Debug)]
+pub struct StepReport {
+    /// The name of the step.
+    pub name: &'static str,
+    /// When the step was started, relative to the start of startup, if it was.
+    pub started: Option<Duration>,
+    /// The outcome of the step.
+    pub outcome: StepOutcome,
+    /// The notes left by the step.
+    pub notes: Vec<String>,
+}
+
+/// The report of a startup, with the steps in the order they were declared.
+#[derive(Debug)]
+pub struct StartupReport {
+    /// The reports of the steps.
+    pub steps: Vec<StepReport>,
+    /// The time it took for all steps to be ready, or for startup to fail.
+    pub total: Duration,
+}
+
+impl StartupReport {
+    /// Tell if all the steps became ready.
+    #[must_use]
+    pub fn is_success(&self) -> bool {
+        self.steps.iter().all(|step| step.outcome.is_ready())
+    }
+
+    /// The steps that did not become ready.
+    pub fn failures(&self) -> impl Iterator<Item = &StepReport> {
+        self.steps.iter().filter(|step| !step.outcome.is_ready())
+    }
+}
+
+impl Display for StartupReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, " ━━━━━━ Startup report ━━━━━━")?;
+        for step in &self.steps {
+            let started = step
+                .started
+                .map_or_else(|| "-".to_string(), |at| format!("+{at:?}"));
+            writeln!(f, "  {:<12} {:>14}  {}", step.name, started, step.outcome)?;
+            for note in &step.notes {
+                writeln!(f, "  {:<12} {:>14}  {note}", "", "")?;
+            }
+        }
+        write!(
+            f,
+            "  {} in {:?}",
+            if self.is_success() {
+                "started"
+            } else {
+                "FAILED"
+            },
+            self.total
+        )
+    }
+}
+
+/// The error of a start function which panicked with `payload`
+fn panic_error(payload: &(dyn std::any::Any + Send)) -> StepError {
+    let message = if let Some(message) = payload.downcast_ref::<&str>() {
+        (*message).to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown".to_string()
+    };
+    Box::new(StepPanicked(message))
+}
+
+enum State {
+    Pending,
+    Running { started: Instant },
+    Done(StepOutcome),
+}
+
+/// A set of subsystems to start in dependency order.
+#[derive(Default)]
+pub struct Startup<'env> {
+    steps: Vec<StartupStep<'env>>,
+}
+
+impl<'env> Startup<'env> {
+    /// Create an empty startup.
+    #[must_use]
+    pub fn new() -> Self {
+        Self { steps: vec![] }
+    }
+
+    /// Add a step.
+    #[must_use]
+    pub fn step(mut self, step: StartupStep<'env>) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    fn check(&self) -> Result<(), StartupError> {
+        let mut deps: BTreeMap<&str, &[&'static str]> = BTreeMap::new();
+        for step in &self.steps {
+            if deps.insert(step.name, &step.deps).is_some() {
+                return Err(StartupError::DuplicateStep(step.name));
+            }
+        }
+        for step in &self.steps {
+            if let Some(dep) = step.deps.iter().find(|dep| !deps.contains_key(*dep)) {
+                return Err(StartupError::UnknownDependency(step.name, *dep));
+            }
+        }
+        // repeatedly resolve the steps whose dependencies are all resolved
+        let mut resolved = BTreeSet::new();
+        while resolved.len() < self.steps.len() {
+            let before = resolved.len();
+            for step in &self.steps {
+                if step.deps.iter().all(|dep| resolved.contains(dep)) {
+                    resolved.insert(step.name);
+                }
+            }
+            if resolved.len() == before {
+                let step = self
+                    .steps
+                    .iter()
+                    .find(|step| !resolved.contains(step.name))
+                    .unwrap_or_else(|| unreachable!());
+                return Err(StartupError::DependencyCycle(step.name));
+            }
+        }
+        Ok(())
+    }
+
+    /// Start all the steps, each as soon as its dependencies are ready, and wait until all
+    /// of them are ready or startup fails.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the steps are not properly declared, in which case none is started.
+    #[allow(clippy::too_many_lines)]
+    pub fn run(self, shutdown: &Shutdown) -> Result<StartupReport, StartupError> {
+        self.check()?;
+        let begin = Instant::now();
+        let names: Vec<_> = self.steps.iter().map(|step| step.name).collect();
+        let deps: Vec<Vec<usize>> = self
+            .steps
+            .iter()
+            .map(|step| {
+                step.deps
+                    .iter()
+                    .filter_map(|dep| names.iter().position(|name| name == dep))
+                    .collect()
+            })
+            .collect();
+        let timeouts: Vec<_> = self.steps.iter().map(|step| step.timeout).collect();
+        let mut starts: Vec<_> = self
+            .steps
+            .into_iter()
+            .map(|step| Some(step.start))
+            .collect();
+        let mut states: Vec<_> = names.iter().map(|_| State::Pending).collect();
+        let mut started_at: Vec<Option<Duration>> = vec![None; names.len()];
+        let mut notes: Vec<Vec<String>> = vec![vec![]; names.len()];
+        let mut failed = false;
+
+        let (tx, rx) = channel();
+        std::thread::scope(|scope| {
+            loop {
+                let cancelled = shutdown.root.is_cancelled();
+
+                // start the steps whose dependencies are ready; skip those that can't start
+                for index in 0..states.len() {
+                    if !matches!(states[index], State::Pending) {
+                        continue;
+                    }
+                    let not_ready = deps[index].iter().find(|dep| match &states[**dep] {
+                        State::Done(outcome) => !outcome.is_ready(),
+                        _ => false,
+                    });
+                    if let Some(dep) = not_ready {
+                        states[index] = State::Done(StepOutcome::Skipped(names[*dep]));
+                        continue;
+                    }
+                    if failed || cancelled {
+                        states[index] = State::Done(StepOutcome::Cancelled);
+                        continue;
+                    }
+                    let deps_ready = deps[index]
+                        .iter()
+                        .all(|dep| matches!(&states[*dep], State::Done(o) if o.is_ready()));
+                    if !deps_ready {
+                        continue;
+                    }
+                    let Some(start) = starts[index].take() else {
+                        unreachable!();
+                    };
+                    let readiness = Readiness {
+                        step: index,
+                        tx: tx.clone(),
+                    };
+                    let tx = tx.clone();
+                    info!("Starting {}...", names[index]);
+                    started_at[index] = Some(begin.elapsed());
+                    states[index] = State::Running {
+                        started: Instant::now(),
+                    };
+                    scope.spawn(move || {
+                        let result = catch_unwind(AssertUnwindSafe(|| start(readiness)))
+                            .unwrap_or_else(|panic| Err(panic_error(panic.as_ref())));
+                        let _ = tx.send((index, Event::Returned(result)));
+                    });
+                }
+
+                // wait for the next event, or for the nearest deadline
+                let now = Instant::now();
+                let deadline = states
+                    .iter()
+                    .zip(&timeouts)
+                    .filter_map(|(state, timeout)| match state {
+                        State::Running { started } => Some(*started + *timeout),
+                        _ => None,
+                    })
+                    .min();
+                let Some(deadline) = deadline else {
+                    break;
+                };
+                // poll cancellation while waiting
+                let wait = deadline
+                    .saturating_duration_since(now)
+                    .min(Duration::from_millis(100));
+                if let Ok((index, event)) = rx.recv_timeout(wait) {
+                    if let Event::Note(note) = event {
+                        notes[index].push(note);
+                        continue;
+                    }
+                    let State::Running { started } = states[index] else {
+                        continue;
+                    };
+                    let outcome = match event {
+                        Event::Note(_) => unreachable!(),
+                        Event::Ready | Event::Returned(Ok(())) => {
+                            info!("{} is ready", names[index]);
+                            StepOutcome::Ready(started.elapsed())
+                        }
+                        Event::Returned(Err(_)) if shutdown.root.is_cancelled() => {
+                            StepOutcome::Cancelled
+                        }
+                        Event::Returned(Err(e)) => {
+                            error!("Failed to start {}: {e}", names[index]);
+                            failed = true;
+                            StepOutcome::Failed(started.elapsed(), e)
+                        }
+                    };
+                    states[index] = State::Done(outcome);
+                }
+                let now = Instant::now();
+                for (index, state) in states.iter_mut().enumerate() {
+                    if let State::Running { started } = state
+                        && now >= *started + timeouts[index]
+                    {
+                        error!(
+                            "{} did not become ready within {:?}",
+                            names[index], timeouts[index]
+                        );
+                        failed = true;
+                        *state = State::Done(StepOutcome::TimedOut(timeouts[index]));
+                    }
+                }
+                if failed {
+                    // let the steps still running observe cancellation
+                    shutdown.fail();
+                }
+            }
+        });
+        // the notes left by the steps still running when startup was over
+        for (index, event) in rx.try_iter() {
+            if let Event::Note(note) = event {
+                notes[index].push(note);
+            }
+        }
+
+        let steps = names
+            .into_iter()
+            .zip(started_at)
+            .zip(states)
+            .zip(notes)
+            .map(|(((name, started), state), notes)| StepReport {
+                name,
+                started,
+                outcome: match state {
+                    State::Done(outcome) => outcome,
+                    State::Pending | State::Running { .. } => StepOutcome::Cancelled,
+                },
+                notes,
+            })
+            .collect();
+        Ok(StartupReport {
+            steps,
+            total: begin.elapsed(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use concurrency::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn startup_respects_dependencies() {
+        let shutdown = Shutdown::new();
+        let first_ready = AtomicBool::new(false);
+        let report = Startup::new()
+            .step(
+                StartupStep::new("second", Duration::from_secs(1), |_| {
+                    if first_ready.load(Ordering::SeqCst) {
+                        Ok(())
+                    } else {
+                        Err("started too early".into())
+                    }
+                })
+                .after(&["first"]),
+            )
+            .step(StartupStep::new("first", Duration::from_secs(1), |_| {
+                first_ready.store(true, Ordering::SeqCst);
+                Ok(())
+            }))
+            .run(&shutdown)
+            .unwrap();
+        assert!(report.is_success(), "{report}");
+        assert!(!shutdown.is_fatal());
+    }
+
+    #[test]
+    fn startup_signals_readiness_before_returning() {
+        let shutdown = Shutdown::new();
+        let report = Startup::new()
+            .step(StartupStep::new("slow", Duration::from_secs(1), |ready| {
+                ready.ready();
+                std::thread::sleep(Duration::from_millis(500));
+                Ok(())
+            }))
+            .step(StartupStep::new("next", Duration::from_secs(1), |_| Ok(())).after(&["slow"]))
+            .run(&shutdown)
+            .unwrap();
+        assert!(report.is_success(), "{report}");
+        let slow = report.steps[0].started.unwrap();
+        let next = report.steps[1].started.unwrap();
+        assert!(next - slow < Duration::from_millis(500));
+    }
+
+    #[test]
+    fn startup_keeps_notes() {
+        let shutdown = Shutdown::new();
+        let report = Startup::new()
+            .step(StartupStep::new("noted", Duration::from_secs(1), |ready| {
+                ready.note("picked 0000:01:00.0");
+                ready.ready();
+                ready.note("picked 0000:02:00.0");
+                Ok(())
+            }))
+            .run(&shutdown)
+            .unwrap();
+        assert!(report.is_success(), "{report}");
+        assert_eq!(
+            report.steps[0].notes,
+            ["picked 0000:01:00.0", "picked 0000:02:00.0"]
+        );
+        assert!(report.to_string().contains("picked 0000:02:00.0"));
+    }
+
+    #[test]
+    fn startup_failure_skips_dependents() {
+        let shutdown = Shutdown::new();
+        let report = Startup::new()
+            .step(StartupStep::new("broken", Duration::from_secs(1), |_| {
+                Err("boom".into())
+            }))
+            .step(
+                StartupStep::new("dependent", Duration::from_secs(1), |_| Ok(()))
+                    .after(&["broken"]),
+            )
+            .run(&shutdown)
+            .unwrap();
+        assert!(!report.is_success());
+        assert!(
+            matches!(&report.steps[0].outcome, StepOutcome::Failed(_, e) if e.to_string() == "boom")
+        );
+        assert!(matches!(
+            report.steps[1].outcome,
+            StepOutcome::Skipped("broken")
+        ));
+        assert!(shutdown.is_fatal());
+    }
+
+    #[test]
+    fn startup_times_out() {
+        let shutdown = Shutdown::new();
+        let root = shutdown.root.clone();
+        let report = Startup::new()
+            .step(StartupStep::new(
+                "stuck",
+                Duration::from_millis(50),
+                move |_| {
+                    // only returns when startup is abandoned
+                    while !root.is_cancelled() {
+                        std::thread::sleep(Duration::from_millis(10));
+                    }
+                    Err("cancelled".into())
+                },
+            ))
+            .run(&shutdown)
+            .unwrap();
+        assert!(matches!(
+            report.steps[0].outcome,
+            StepOutcome::TimedOut(timeout) if timeout == Duration::from_millis(50)
+        ));
+        assert!(shutdown.is_fatal());
+    }
+
+    #[test]
+    fn startup_panic_is_a_failure() {
+        let shutdown = Shutdown::new();
+        let report = Startup::new()
+            .step(StartupStep::new("panicky", Duration::from_secs(1), |_| {
+                panic!("synthetic panic");
+            }))
+            .run(&shutdown)
+            .unwrap();
+        let StepOutcome::Failed(_, e) = &report.steps[0].outcome else {
+            panic!("{report}");
+        };
+        let panicked = e.downcast_ref::<StepPanicked>().unwrap();
+        assert_eq!(panicked.0, "synthetic panic");
+    }
+
+    #[test]
+    fn startup_rejects_bad_declarations() {
+        let shutdown = Shutdown::new();
+        let nop = |_| Ok(());
+        let result = Startup::new()
+            .step(StartupStep::new("a", Duration::from_secs(1), nop).after(&["b"]))
+            .run(&shutdown);
+        assert_eq!(
+            result.err(),
+            Some(StartupError::UnknownDependency("a", "b"))
+        );
+
+        let result = Startup::new()
+            .step(StartupStep::new("a", Duration::from_secs(1), nop).after(&["b"]))
+            .step(StartupStep::new("b", Duration::from_secs(1), nop).after(&["a"]))
+            .run(&shutdown);
+        assert_eq!(result.err(), Some(StartupError::DependencyCycle("a")));
+
+        let result = Startup::new()
+            .step(StartupStep::new("a", Duration::from_secs(1), nop))
+            .step(StartupStep::new("a", Duration::from_secs(1), nop))
+            .run(&shutdown);
+        assert_eq!(result.err(), Some(StartupError::DuplicateStep("a")));
+        assert!(!shutdown.is_fatal());
+    }
+}
@@ -39,7 +39,7 @@ pub trait ValueWithAssociatedRanges {
 /// - For each matching prefix, check if the port range associated with it covers the given port
 /// - Return the first match we find: as the combinations (IP prefix, port range) are disjoint,
 ///   there can be no more than one match.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct IpPortPrefixTrie<V>(IpPrefixTrie<V>)
 where
     V: Debug + Clone + ValueWithAssociatedRanges;
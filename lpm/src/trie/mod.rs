@@ -65,7 +65,7 @@ pub trait TrieMap {
         B: Borrow<Self::Prefix>;
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct IpPrefixTrie<V> {
     ipv4: PrefixMapTrie<Ipv4Prefix, V>,
     ipv6: PrefixMapTrie<Ipv6Prefix, V>,
@@ -55,6 +55,18 @@ pub struct PrefixMapTrie<P, V>(PrefixMap<IpPrefixW<P>, V>)
 where
     P: IpPrefix;
 
+impl<P, V> PartialEq for PrefixMapTrie<P, V>
+where
+    P: IpPrefix,
+    V: PartialEq,
+{
+    // The trie iterates over its prefixes in lexicographic order, whatever the order they were
+    // inserted in
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().eq(other.iter())
+    }
+}
+
 impl<P, V> TrieMapFactory<PrefixMapTrie<P, V>> for PrefixMapTrie<P, V>
 where
     P: IpPrefix,
@@ -0,0 +1,84 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Cost of the reconciliation pass the config processor runs on every configuration, on a system
+//! which already meets the requirements. Such passes issue no netlink request, so this measures
+//! the bookkeeping of the reconciler alone, which must stay roughly linear in the number of
+//! interfaces.
+
+#![allow(clippy::unwrap_used, clippy::expect_used)]
+
+use std::hint::black_box;
+
+use concurrency::sync::Arc;
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use dataplane_mgmt::vpc_manager::{
+    ObservedInformationBaseBuilder, RequiredInformationBase, RequiredInformationBaseBuilder,
+    VpcManager,
+};
+use rekon::Reconcile;
+use test_utils::scale::Scale;
+
+fn scales() -> Vec<Scale> {
+    vec![
+        Scale::new(16, 1, 1),
+        Scale::new(64, 1, 1),
+        Scale::new(256, 1, 1),
+        Scale::new(1024, 1, 1),
+        Scale::new(256, 2, 2),
+    ]
+}
+
+fn bench_reconcile_converged(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .build()
+        .unwrap();
+    let manager = runtime.block_on(async {
+        let (connection, handle, _) = rtnetlink::new_connection().unwrap();
+        tokio::spawn(connection);
+        VpcManager::<RequiredInformationBase>::new(Arc::new(handle))
+    });
+
+    let mut group = c.benchmark_group("reconcile-converged");
+    for scale in scales() {
+        let generated = scale.generate();
+        let observed = generated.observed();
+        let observed = ObservedInformationBaseBuilder::default()
+            .interfaces(observed.interfaces)
+            .vrfs(observed.vrfs)
+            .vteps(observed.vteps)
+            .build()
+            .unwrap();
+        let rib = RequiredInformationBaseBuilder::default()
+            .interfaces(generated.interfaces)
+            .vrfs(generated.vrfs)
+            .vteps(generated.vteps)
+            .associations(generated.associations)
+            .build()
+            .unwrap();
+        assert!(
+            runtime
+                .block_on(manager.reconcile(&mut rib.clone(), &observed))
+                .is_reconciled(),
+            "generated observation does not meet the requirements"
+        );
+
+        let id = format!(
+            "{}x{}x{}",
+            scale.vrfs, scale.bridges_per_vrf, scale.vteps_per_bridge
+        );
+        group.throughput(Throughput::Elements(scale.interfaces() as u64));
+        group.bench_function(BenchmarkId::from_parameter(id), |b| {
+            b.iter_batched(
+                || rib.clone(),
+                |mut rib| black_box(runtime.block_on(manager.reconcile(&mut rib, &observed))),
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_reconcile_converged);
+criterion_main!(benches);
@@ -0,0 +1,23 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+/// The definitions of the gRPC services of the dataplane
+const PROTOS: [&str; 3] = [
+    "proto/config.proto",
+    "proto/events.proto",
+    "proto/routing.proto",
+];
+
+fn main() {
+    // the protoc of the environment if given, as in builds where the vendored one can't run
+    let mut config = prost_build::Config::new();
+    if std::env::var_os("PROTOC").is_none() {
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("No vendored protoc");
+        config.protoc_executable(protoc);
+    }
+    tonic_prost_build::configure()
+        .build_client(false)
+        .build_transport(false)
+        .compile_with_config(config, &PROTOS, &["proto"])
+        .expect("Failed to generate the gRPC services");
+}
@@ -0,0 +1,289 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! gRPC endpoint operating on the configuration of the dataplane.
+//!
+//! The `dataplane.config.v1.Config` service, defined in `mgmt/proto/config.proto`, lets operators
+//! roll back to a configuration applied before, e.g. when the one applied last causes the kernel
+//! state to fail to reconcile, get the status and the counters of the dataplane, and fetch the
+//! reports of its crashes, kept across restarts.
+//!
+//! The service is served with an [`Access`], which each method checks first: the observer
+//! endpoint, meant for monitoring systems, only serves the read-only methods. The methods changing
+//! the dataplane also require the clients to present the bearer token of the endpoint, in the
+//! `authorization` metadata: without a token, they are denied. Calls to methods the service does
+//! not define fail with `UNIMPLEMENTED`.
+
+mod proto;
+
+use common::token::token_matches;
+use concurrency::sync::Arc;
+use config::ConfigError;
+use lifecycle::crash::{CrashReportError, CrashReports, crash_reports};
+use tonic::metadata::MetadataMap;
+use tonic::{Request, Response, Status};
+
+use crate::processor::mgmt_client::{ConfigClient, ConfigProcessorError};
+use proto::config_server::Config;
+pub(crate) use proto::config_server::ConfigServer;
+use proto::{
+    CrashReport, GetCrashReportRequest, GetStatsRequest, GetStatusRequest, ListCrashReportsRequest,
+    ListCrashReportsResponse, RollbackRequest, RollbackResponse, StatsResponse, StatusResponse,
+};
+
+/// What the clients of an endpoint may do
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Access {
+    /// Only call the methods which do not change the dataplane
+    ReadOnly,
+    /// Call any method
+    ReadWrite,
+}
+
+/// The config service, which forwards the requests to the config processor
+pub(crate) struct ConfigService {
+    client: ConfigClient,
+    access: Access,
+    /// The bearer token the methods changing the dataplane require, if they may be called
+    token: Option<Arc<str>>,
+}
+
+impl ConfigService {
+    pub(crate) fn new(client: ConfigClient, access: Access) -> Self {
+        Self {
+            client,
+            access,
+            token: None,
+        }
+    }
+
+    /// Require `token` to call the methods changing the dataplane
+    #[must_use]
+    pub(crate) fn with_token(mut self, token: Option<Arc<str>>) -> Self {
+        self.token = token;
+        self
+    }
+
+    /// Check that the `authorization` metadata of a request carries the bearer token of the
+    /// service
+    fn authenticate(&self, metadata: &MetadataMap) -> Result<(), Status> {
+        let Some(token) = &self.token else {
+            return Err(Status::unauthenticated(
+                "No token is configured to change the dataplane on this endpoint",
+            ));
+        };
+        let presented = metadata
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match presented {
+            Some(presented) if token_matches(token, presented) => Ok(()),
+            _ => Err(Status::unauthenticated("Missing or invalid bearer token")),
+        }
+    }
+
+    /// Check that `method`, which needs the access `needed`, may be called with the access of
+    /// the service, and with the token presented in the `metadata` of the request if it changes
+    /// the dataplane
+    fn check(&self, method: &str, needed: Access, metadata: &MetadataMap) -> Result<(), Status> {
+        match needed {
+            needed if needed > self.access => Err(Status::permission_denied(format!(
+                "Method {method} is not allowed on this endpoint"
+            ))),
+            Access::ReadWrite => self.authenticate(metadata),
+            Access::ReadOnly => Ok(()),
+        }
+    }
+}
+
+fn to_status(error: &ConfigProcessorError) -> Status {
+    match error {
+        ConfigProcessorError::ApplyConfigError(e @ ConfigError::NoSuchConfig(_)) => {
+            Status::not_found(e.to_string())
+        }
+        ConfigProcessorError::ApplyConfigError(e) => Status::aborted(e.to_string()),
+        e => Status::unavailable(e.to_string()),
+    }
+}
+
+fn crash_report_status(error: &CrashReportError) -> Status {
+    match error {
+        CrashReportError::InvalidName(_) => Status::invalid_argument(error.to_string()),
+        CrashReportError::Io(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Status::not_found(error.to_string())
+        }
+        CrashReportError::Io(_) | CrashReportError::Parse(_) => Status::internal(error.to_string()),
+    }
+}
+
+/// Read the crash reports with `read`, off the threads of the runtime since it reads files
+async fn read_crash_reports<T: Send + 'static>(
+    read: impl FnOnce(&CrashReports) -> Result<T, CrashReportError> + Send + 'static,
+) -> Result<T, Status> {
+    let Some(reports) = crash_reports() else {
+        return Err(Status::failed_precondition("Crash reports are not enabled"));
+    };
+    tokio::task::spawn_blocking(move || read(reports))
+        .await
+        .map_err(|e| Status::internal(e.to_string()))?
+        .map_err(|e| crash_report_status(&e))
+}
+
+#[tonic::async_trait]
+impl Config for ConfigService {
+    async fn rollback(
+        &self,
+        request: Request<RollbackRequest>,
+    ) -> Result<Response<RollbackResponse>, Status> {
+        self.check("Rollback", Access::ReadWrite, request.metadata())?;
+        let genid = request.into_inner().genid;
+        let apply_id = self
+            .client
+            .rollback(genid)
+            .await
+            .map_err(|e| to_status(&e))?;
+        Ok(Response::new(RollbackResponse {
+            apply_id: apply_id.to_string(),
+        }))
+    }
+
+    async fn get_status(
+        &self,
+        request: Request<GetStatusRequest>,
+    ) -> Result<Response<StatusResponse>, Status> {
+        self.check("GetStatus", Access::ReadOnly, request.metadata())?;
+        let genid = self
+            .client
+            .get_generation()
+            .await
+            .map_err(|e| to_status(&e))?;
+        let status = self.client.get_status().await.map_err(|e| to_status(&e))?;
+        Ok(Response::new(StatusResponse::new(genid, &status)))
+    }
+
+    async fn get_stats(
+        &self,
+        request: Request<GetStatsRequest>,
+    ) -> Result<Response<StatsResponse>, Status> {
+        self.check("GetStats", Access::ReadOnly, request.metadata())?;
+        let status = self.client.get_status().await.map_err(|e| to_status(&e))?;
+        Ok(Response::new(StatsResponse::from(&status)))
+    }
+
+    async fn list_crash_reports(
+        &self,
+        request: Request<ListCrashReportsRequest>,
+    ) -> Result<Response<ListCrashReportsResponse>, Status> {
+        self.check("ListCrashReports", Access::ReadOnly, request.metadata())?;
+        let entries = read_crash_reports(CrashReports::list).await?;
+        Ok(Response::new(ListCrashReportsResponse {
+            reports: entries.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn get_crash_report(
+        &self,
+        request: Request<GetCrashReportRequest>,
+    ) -> Result<Response<CrashReport>, Status> {
+        self.check("GetCrashReport", Access::ReadOnly, request.metadata())?;
+        let name = request.into_inner().name;
+        let report = read_crash_reports(move |reports| reports.get(&name)).await?;
+        Ok(Response::new(report.into()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Access, ConfigServer, ConfigService, crash_report_status, to_status};
+    use crate::processor::mgmt_client::{ConfigClient, ConfigProcessorError};
+    use config::ConfigError;
+    use lifecycle::crash::CrashReportError;
+    use tokio::sync::mpsc;
+    use tonic::codegen::{Service, http};
+    use tonic::metadata::MetadataMap;
+    use tonic::{Code, Status};
+
+    fn bearer(token: &str) -> MetadataMap {
+        let mut metadata = MetadataMap::new();
+        metadata.insert("authorization", format!("Bearer {token}").parse().unwrap());
+        metadata
+    }
+
+    #[test]
+    fn observer_access() {
+        let (tx, _rx) = mpsc::channel(1);
+        let token = Some("secret".into());
+        let observer = ConfigService::new(ConfigClient::new(tx.clone()), Access::ReadOnly)
+            .with_token(token.clone());
+        let full = ConfigService::new(ConfigClient::new(tx), Access::ReadWrite).with_token(token);
+        let metadata = bearer("secret");
+        for service in [&observer, &full] {
+            assert!(
+                service
+                    .check("GetStatus", Access::ReadOnly, &metadata)
+                    .is_ok()
+            );
+            let anonymous = MetadataMap::new();
+            assert!(
+                service
+                    .check("GetStats", Access::ReadOnly, &anonymous)
+                    .is_ok()
+            );
+        }
+        assert!(full.check("Rollback", Access::ReadWrite, &metadata).is_ok());
+        let denied = observer
+            .check("Rollback", Access::ReadWrite, &metadata)
+            .unwrap_err();
+        assert_eq!(denied.code(), Code::PermissionDenied);
+    }
+
+    #[test]
+    fn rollback_token() {
+        let (tx, _rx) = mpsc::channel(1);
+        let full = ConfigService::new(ConfigClient::new(tx.clone()), Access::ReadWrite)
+            .with_token(Some("secret".into()));
+        for metadata in [MetadataMap::new(), bearer("secreT"), bearer("secret2")] {
+            let denied = full
+                .check("Rollback", Access::ReadWrite, &metadata)
+                .unwrap_err();
+            assert_eq!(denied.code(), Code::Unauthenticated);
+        }
+
+        // without a token, the dataplane can't be changed
+        let tokenless = ConfigService::new(ConfigClient::new(tx), Access::ReadWrite);
+        let denied = tokenless.check("Rollback", Access::ReadWrite, &bearer("secret"));
+        assert_eq!(denied.unwrap_err().code(), Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn unknown_method() {
+        let (tx, _rx) = mpsc::channel(1);
+        let service = ConfigService::new(ConfigClient::new(tx), Access::ReadWrite);
+        let mut server = ConfigServer::new(service);
+        let request = http::Request::builder()
+            .uri("/dataplane.config.v1.Config/Apply")
+            .body(tonic::body::Body::empty())
+            .unwrap();
+        let response = server.call(request).await.unwrap();
+        let status = Status::from_header_map(response.headers()).unwrap();
+        assert_eq!(status.code(), Code::Unimplemented);
+    }
+
+    #[test]
+    fn rollback_status() {
+        let error = ConfigProcessorError::ApplyConfigError(ConfigError::NoSuchConfig(3));
+        assert_eq!(to_status(&error).code(), Code::NotFound);
+        let error = ConfigProcessorError::ApplyConfigError(ConfigError::FailureApply("x".into()));
+        assert_eq!(to_status(&error).code(), Code::Aborted);
+    }
+
+    #[test]
+    fn crash_report_status_codes() {
+        let error = CrashReportError::InvalidName("../passwd".to_string());
+        assert_eq!(crash_report_status(&error).code(), Code::InvalidArgument);
+        let error = CrashReportError::Io(std::io::ErrorKind::NotFound.into());
+        assert_eq!(crash_report_status(&error).code(), Code::NotFound);
+        let error = CrashReportError::Io(std::io::ErrorKind::PermissionDenied.into());
+        assert_eq!(crash_report_status(&error).code(), Code::Internal);
+    }
+}
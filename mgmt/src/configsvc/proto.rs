@@ -0,0 +1,137 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! The messages and the service of the config service, generated from `mgmt/proto/config.proto`
+
+use config::GenId;
+use config::internal::status::DataplaneStatus;
+use lifecycle::crash;
+
+tonic::include_proto!("dataplane.config.v1");
+
+impl From<crash::CrashReportEntry> for CrashReportEntry {
+    fn from(entry: crash::CrashReportEntry) -> Self {
+        Self {
+            name: entry.name,
+            size: entry.size,
+        }
+    }
+}
+
+impl From<crash::CrashReport> for CrashReport {
+    fn from(report: crash::CrashReport) -> Self {
+        Self {
+            time: report.time,
+            version: report.version,
+            config_genid: report.config_genid,
+            thread: report.thread,
+            message: report.message,
+            location: report.location.unwrap_or_default(),
+            backtrace: report.backtrace,
+            journal: report.journal,
+        }
+    }
+}
+
+impl StatusResponse {
+    /// The status of the dataplane, with the generation id `genid` applied. The entries are
+    /// sorted by name.
+    pub(crate) fn new(genid: GenId, status: &DataplaneStatus) -> Self {
+        let mut interfaces: Vec<_> = status
+            .interface_runtime
+            .iter()
+            .map(|(name, runtime)| InterfaceState {
+                name: name.clone(),
+                admin_status: format!("{:?}", runtime.admin_status),
+                oper_status: format!("{:?}", runtime.oper_status),
+            })
+            .collect();
+        interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut bgp_neighbors: Vec<_> = status
+            .bgp
+            .iter()
+            .flat_map(|bgp| &bgp.vrfs)
+            .flat_map(|(vrf, vrf_status)| {
+                vrf_status
+                    .neighbors
+                    .iter()
+                    .map(move |(address, neighbor)| BgpNeighbor {
+                        vrf: vrf.clone(),
+                        address: address.clone(),
+                        peer_as: neighbor.peer_as,
+                        state: neighbor.session_state.to_string(),
+                    })
+            })
+            .collect();
+        bgp_neighbors.sort_by(|a, b| (&a.vrf, &a.address).cmp(&(&b.vrf, &b.address)));
+        let mut vpcs: Vec<_> = status
+            .vpcs
+            .values()
+            .map(|vpc| Vpc {
+                name: vpc.name.clone(),
+                vni: vpc.vni,
+                route_count: vpc.route_count,
+            })
+            .collect();
+        vpcs.sort_by(|a, b| a.name.cmp(&b.name));
+        Self {
+            genid,
+            status: status
+                .dataplane_status
+                .as_ref()
+                .map_or_else(String::new, |info| format!("{:?}", info.status)),
+            interfaces,
+            bgp_neighbors,
+            vpcs,
+        }
+    }
+}
+
+impl From<&DataplaneStatus> for StatsResponse {
+    fn from(status: &DataplaneStatus) -> Self {
+        let mut interfaces: Vec<_> = status
+            .interface_runtime
+            .iter()
+            .filter_map(|(name, runtime)| {
+                let counters = runtime.counters.as_ref()?;
+                Some(InterfaceCounters {
+                    name: name.clone(),
+                    rx_bits: counters.rx_bits,
+                    rx_errors: counters.rx_errors,
+                    tx_bits: counters.tx_bits,
+                    tx_errors: counters.tx_errors,
+                })
+            })
+            .collect();
+        interfaces.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut vpcs: Vec<_> = status
+            .vpc_counters
+            .values()
+            .map(|counters| VpcCounters {
+                name: counters.name.clone(),
+                packets: counters.packets,
+                bytes: counters.bytes,
+                drops: counters.drops,
+            })
+            .collect();
+        vpcs.sort_by(|a, b| a.name.cmp(&b.name));
+        let mut peerings: Vec<_> = status
+            .vpc_peering_counters
+            .values()
+            .map(|counters| PeeringCounters {
+                name: counters.name.clone(),
+                src_vpc: counters.src_vpc.clone(),
+                dst_vpc: counters.dst_vpc.clone(),
+                packets: counters.packets,
+                bytes: counters.bytes,
+                drops: counters.drops,
+            })
+            .collect();
+        peerings.sort_by(|a, b| a.name.cmp(&b.name));
+        Self {
+            interfaces,
+            vpcs,
+            peerings,
+        }
+    }
+}
@@ -0,0 +1,96 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! gRPC endpoint streaming the events of the dataplane.
+//!
+//! The `Subscribe` method of the `dataplane.events.v1.Events` service, defined in
+//! `mgmt/proto/events.proto`, streams the [`DataplaneEvent`]s published from the time of the
+//! subscription on, so that the gateway agent and the observability stack need not poll the
+//! status of the dataplane. Subscribers too slow to receive the events get told how many they
+//! missed.
+//!
+//! [`DataplaneEvent`]: config::internal::events::DataplaneEvent
+
+mod proto;
+
+use std::pin::Pin;
+
+use config::internal::events;
+use futures::{Stream, StreamExt};
+use lifecycle::CancellationToken;
+use tokio::sync::broadcast::error::RecvError;
+use tonic::{Request, Response, Status};
+use tracing::debug;
+
+use proto::events_server::Events;
+pub(crate) use proto::events_server::EventsServer;
+use proto::{Event, SubscribeRequest};
+
+type EventStream = Pin<Box<dyn Stream<Item = Result<Event, Status>> + Send>>;
+
+/// The events service. The event streams end when `cancel` is cancelled.
+pub(crate) struct EventsService {
+    cancel: CancellationToken,
+}
+
+impl EventsService {
+    pub(crate) fn new(cancel: CancellationToken) -> Self {
+        Self { cancel }
+    }
+}
+
+#[tonic::async_trait]
+impl Events for EventsService {
+    type SubscribeStream = EventStream;
+
+    /// Stream the events published from now on
+    async fn subscribe(
+        &self,
+        request: Request<SubscribeRequest>,
+    ) -> Result<Response<EventStream>, Status> {
+        debug!("New subscriber to events: {:?}", request.remote_addr());
+        let stream = futures::stream::unfold(events::subscribe(), |mut rx| async move {
+            let event = match rx.recv().await {
+                Ok(timed) => Event::from(&timed),
+                Err(RecvError::Lagged(count)) => Event::lost(count),
+                Err(RecvError::Closed) => return None,
+            };
+            Some((Ok(event), rx))
+        });
+        let stream = stream.take_until(self.cancel.clone().cancelled_owned());
+        Ok(Response::new(Box::pin(stream)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::proto::{Event, EventsLost, event};
+    use config::internal::events::{DataplaneEvent, TimedEvent};
+    use config::internal::status::BgpNeighborSessionState;
+
+    #[test]
+    fn event_messages() {
+        let timed = TimedEvent {
+            time: chrono::DateTime::from_timestamp_micros(1_000_000).unwrap(),
+            event: DataplaneEvent::BgpSession {
+                neighbor: "192.168.1.1".to_string(),
+                asn: 65000,
+                state: BgpNeighborSessionState::Established,
+            },
+        };
+        let message = Event::from(&timed);
+        assert_eq!(message.timestamp_us, 1_000_000);
+        let Some(event::Kind::BgpSession(session)) = message.kind else {
+            panic!("Unexpected event {message:?}");
+        };
+        assert_eq!(session.neighbor, "192.168.1.1");
+        assert_eq!(session.asn, 65000);
+        assert_eq!(session.state, "Established");
+
+        let lost = Event::lost(3);
+        assert!(matches!(
+            lost.kind,
+            Some(event::Kind::EventsLost(EventsLost { count: 3 }))
+        ));
+    }
+}
@@ -0,0 +1,74 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! The messages and the service of the events service, generated from `mgmt/proto/events.proto`
+
+use config::internal::events::{DataplaneEvent, TimedEvent};
+
+tonic::include_proto!("dataplane.events.v1");
+
+impl Event {
+    /// The event telling a subscriber that it missed `count` events
+    pub(crate) fn lost(count: u64) -> Self {
+        Self {
+            timestamp_us: chrono::Utc::now().timestamp_micros(),
+            description: format!("{count} events lost"),
+            kind: Some(event::Kind::EventsLost(EventsLost { count })),
+        }
+    }
+}
+
+impl From<&TimedEvent> for Event {
+    fn from(timed: &TimedEvent) -> Self {
+        use event::Kind;
+        let kind = match &timed.event {
+            DataplaneEvent::ConfigApplied { genid, apply_id } => {
+                Kind::ConfigApplied(ConfigApplied {
+                    genid: *genid,
+                    apply_id: apply_id.to_string(),
+                })
+            }
+            DataplaneEvent::ConfigFailed {
+                genid,
+                apply_id,
+                error,
+            } => Kind::ConfigFailed(ConfigFailed {
+                genid: *genid,
+                apply_id: apply_id.to_string(),
+                error: error.clone(),
+            }),
+            DataplaneEvent::ReconcileFailed { genid, error } => {
+                Kind::ReconcileFailed(ReconcileFailed {
+                    genid: *genid,
+                    error: error.clone(),
+                })
+            }
+            DataplaneEvent::InterfaceOperState { interface, up } => {
+                Kind::InterfaceOperState(InterfaceOperState {
+                    interface: interface.clone(),
+                    up: *up,
+                })
+            }
+            DataplaneEvent::BgpSession {
+                neighbor,
+                asn,
+                state,
+            } => Kind::BgpSession(BgpSession {
+                neighbor: neighbor.clone(),
+                asn: *asn,
+                state: state.to_string(),
+            }),
+            DataplaneEvent::FrrAgent { connected } => Kind::FrrAgent(FrrAgent {
+                connected: *connected,
+            }),
+            DataplaneEvent::NatPoolExhausted { pool } => {
+                Kind::NatPoolExhausted(NatPoolExhausted { pool: pool.clone() })
+            }
+        };
+        Self {
+            timestamp_us: timed.time.timestamp_micros(),
+            description: timed.event.to_string(),
+            kind: Some(kind),
+        }
+    }
+}
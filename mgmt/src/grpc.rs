@@ -0,0 +1,88 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! The gRPC server of the dataplane.
+//!
+//! It serves the stream of the events of the dataplane (see [`crate::events`]), the operations on
+//! its configuration (see [`crate::configsvc`]), the snapshots of its routes (see
+//! [`crate::routesvc`]), and the standard `grpc.health.v1.Health` service
+//! reporting its readiness, so that Kubernetes probes can gate traffic on it. The readiness of
+//! each [`Component`] is reported as the status of the service named after it (e.g. `frr`), and
+//! that of the whole dataplane as the status of the server (the empty service name).
+//!
+//! The server may also be run as an observer endpoint, granted to monitoring systems, on which
+//! the methods changing the dataplane are denied (see [`Access`]). Elsewhere, those methods
+//! require a bearer token.
+
+use std::net::SocketAddr;
+
+use concurrency::sync::Arc;
+use config::internal::readiness::{self, Component};
+use lifecycle::CancellationToken;
+use routing::RouterCtlSender;
+use tonic_health::ServingStatus;
+use tonic_health::server::HealthReporter;
+use tracing::{error, info};
+
+use crate::configsvc::{Access, ConfigServer, ConfigService};
+use crate::events::{EventsServer, EventsService};
+use crate::processor::mgmt_client::ConfigClient;
+use crate::routesvc::{RoutingServer, RoutingService};
+
+fn serving_status(ready: bool) -> ServingStatus {
+    if ready {
+        ServingStatus::Serving
+    } else {
+        ServingStatus::NotServing
+    }
+}
+
+/// Report the readiness of the dataplane on `reporter`, as it changes
+async fn report_readiness(reporter: HealthReporter) {
+    let mut readiness = readiness::watch();
+    loop {
+        let current = *readiness.borrow_and_update();
+        for component in Component::ALL {
+            let status = serving_status(current.is_ready(component));
+            reporter.set_service_status(component.name(), status).await;
+        }
+        let status = serving_status(current.all_ready());
+        reporter.set_service_status("", status).await;
+        info!("Dataplane readiness: {current:?}");
+        if readiness.changed().await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Serve the gRPC services of the dataplane on `address` until `cancel` is cancelled. The
+/// operations on the configuration are requested with `client`, if allowed by `access` and, for
+/// those changing the dataplane, if the clients present `token`. The snapshots of the routes are
+/// requested with `router`.
+pub(crate) async fn serve(
+    address: SocketAddr,
+    cancel: CancellationToken,
+    client: ConfigClient,
+    router: RouterCtlSender,
+    access: Access,
+    token: Option<Arc<str>>,
+) {
+    info!("Serving the gRPC endpoints of the dataplane on {address} ({access:?})");
+    let (reporter, health) = tonic_health::server::health_reporter();
+    let server = tonic::transport::Server::builder()
+        .add_service(health)
+        .add_service(EventsServer::new(EventsService::new(cancel.clone())))
+        .add_service(ConfigServer::new(
+            ConfigService::new(client, access).with_token(token),
+        ))
+        .add_service(RoutingServer::new(RoutingService::new(router)))
+        .serve_with_shutdown(address, cancel.cancelled_owned());
+    tokio::select! {
+        () = report_readiness(reporter) => {}
+        result = server => {
+            if let Err(e) = result {
+                error!("Failed to serve the gRPC endpoints of the dataplane on {address}: {e}");
+            }
+        }
+    }
+}
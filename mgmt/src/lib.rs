@@ -3,12 +3,17 @@
 
 //! Dataplane management module
 
+mod configsvc;
+mod events;
+mod grpc;
 mod processor;
+mod routesvc;
 mod tests;
 pub mod vpc_manager;
 
 pub use processor::launch::{LaunchError, MgmtParams, run_mgmt};
 pub use processor::proc::ConfigProcessorParams;
+pub use processor::table_events::{TableEventSender, TableWriters};
 
 use tracectl::trace_target;
 trace_target!("mgmt", LevelFilter::DEBUG, &["management"]);
@@ -16,11 +16,10 @@ use config::external::overlay::vpcpeering::ValidatedManifest;
 use config::{ConfigError, ConfigResult};
 
 use lpm::prefix::Prefix;
-use net::route::RouteTableId;
-use net::vxlan::Vni;
 use std::net::Ipv4Addr;
 
 use crate::processor::confbuild::namegen::{VpcConfigNames, VpcInterfacesNames};
+use crate::processor::route_tables::RouteTableIds;
 use config::internal::routing::bfd::peers_from_bgp_neighbors;
 use config::internal::routing::bgp::{AfIpv4Ucast, AfL2vpnEvpn, BgpNeighAF, NeighSendCommunities};
 use config::internal::routing::bgp::{BgpConfig, BgpNeighCapabilities, BgpOptions, VrfImports};
@@ -213,7 +212,7 @@ fn vpc_vrf_bgp_config(vpc: &ValidatedVpc, asn: u32, router_id: Option<Ipv4Addr>)
 }
 
 /// Build VRF config for a VPC
-fn vpc_vrf_config(vpc: &ValidatedVpc) -> Result<VrfConfig, ConfigError> {
+fn vpc_vrf_config(vpc: &ValidatedVpc, table_ids: &RouteTableIds) -> Result<VrfConfig, ConfigError> {
     debug!("Building VRF config for vpc '{}'", vpc.name());
     /* build vrf config */
     let mut vrf_cfg = VrfConfig::new(&vpc.vrf_name(), Some(vpc.vni()), false)
@@ -221,20 +220,8 @@ fn vpc_vrf_config(vpc: &ValidatedVpc) -> Result<VrfConfig, ConfigError> {
         .set_description(vpc.name());
 
     // Here we set the table-id for the VRF. This is the table-id that will be used to create a VRF net device.
-    // Table ids should be unique per VRF. We could track them and pick unused ones. Alternatively, we need
-    // a 1:1 mapping to VNIs which are guaranteed to be unique. The easiest is to let table ids match the Vni,
-    // except for Vnis that could match reserved table ids such as 253-255
-    let table_id = match vpc.vni().as_u32() {
-        253_u32 => Vni::MAX + 1, // local
-        254_u32 => Vni::MAX + 2, // main
-        255_u32 => Vni::MAX + 3, // default
-        _ => vpc.vni().as_u32(),
-    };
-    let table_id = RouteTableId::try_from(table_id).map_err(|_| {
-        let emsg = format!(
-            "Could not create RouteTableId from {table_id} for VPC {}",
-            vpc.name()
-        );
+    let table_id = table_ids.get(vpc.id()).copied().ok_or_else(|| {
+        let emsg = format!("No route table was allocated for VPC {}", vpc.name());
         error!(emsg);
         ConfigError::InternalFailure(emsg)
     })?;
@@ -256,12 +243,13 @@ fn build_vpc_internal_config(
     vpc: &ValidatedVpc,
     asn: u32,
     router_id: Option<Ipv4Addr>,
+    table_ids: &RouteTableIds,
     internal: &mut InternalConfig,
 ) -> ConfigResult {
     debug!("Building internal config for vpc '{}'", vpc.name());
 
     /* build VRF config */
-    let mut vrf_cfg = vpc_vrf_config(vpc)?;
+    let mut vrf_cfg = vpc_vrf_config(vpc, table_ids)?;
 
     /* build bgp config */
     let mut bgp = vpc_vrf_bgp_config(vpc, asn, router_id);
@@ -297,6 +285,7 @@ fn build_internal_overlay_config(
     overlay: &ValidatedOverlay,
     asn: u32,
     router_id: Option<Ipv4Addr>,
+    table_ids: &RouteTableIds,
     internal: &mut InternalConfig,
 ) -> ConfigResult {
     debug!(
@@ -306,7 +295,7 @@ fn build_internal_overlay_config(
 
     /* Vpcs and peerings */
     for vpc in overlay.vpc_table().values() {
-        build_vpc_internal_config(vpc, asn, router_id, internal)?;
+        build_vpc_internal_config(vpc, asn, router_id, table_ids, internal)?;
     }
     Ok(())
 }
@@ -346,6 +335,7 @@ fn configure_bgp_peers(vrf: &mut VrfConfig, internal: &mut InternalConfig) {
 pub fn build_internal_config(
     config: &ValidatedGwConfig,
     bmp: Option<BmpOptions>,
+    table_ids: &RouteTableIds,
 ) -> Result<InternalConfig, ConfigError> {
     let genid = config.genid();
     debug!("Building internal config for gen {genid}");
@@ -384,7 +374,13 @@ pub fn build_internal_config(
         let asn = bgp.asn;
         let router_id = bgp.router_id;
         if !external.overlay().vpc_table().is_empty() {
-            build_internal_overlay_config(external.overlay(), asn, router_id, &mut internal)?;
+            build_internal_overlay_config(
+                external.overlay(),
+                asn,
+                router_id,
+                table_ids,
+                &mut internal,
+            )?;
         } else {
             debug!("The configuration does not specify any VPCs...");
         }
@@ -54,7 +54,7 @@ fn generate_router_vtep_config(internal: &InternalConfig, router_config: &mut Ro
 
 /// Build an interface config for the router from the kernel interface and the interface configuration
 fn build_router_interface_config(
-    _if_config: &InterfaceConfig,
+    if_config: &InterfaceConfig,
     kiface: &NetDevInterface,
     vrfid: VrfId,
 ) -> Result<RouterInterfaceConfig, ConfigError> {
@@ -102,6 +102,9 @@ fn build_router_interface_config(
         }
     }
 
+    // validate the sources of the packets received, if requested
+    new.set_urpf(if_config.urpf);
+
     // attach to the indicated VRF
     new.set_attach_cfg(Some(AttachConfig::Vrf(vrfid)));
 
@@ -0,0 +1,225 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Cache of the last config applied, replayed on a cold start.
+//!
+//! A dataplane restarting would otherwise run with no config until the gateway agent pushes one
+//! again. Instead, the config processor keeps the config applied last, or rolled back to, in a
+//! directory, and it is applied again at startup, before connecting to k8s, unless replaying it is
+//! disabled.
+//!
+//! The `GatewayAgent` object the config is built from is kept, as YAML, rather than the
+//! [`ExternalConfig`] itself, so configs applied otherwise, e.g. over gRPC, are not kept: the cache
+//! is emptied when one is applied, not to replay an older config. The file is sealed: it starts with the SHA-256 checksum of the
+//! object, is replaced atomically and is read-only, so that a cache corrupted on disk is
+//! detected rather than applied.
+
+use std::fs::{self, Permissions};
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+use config::ExternalConfig;
+use k8s_intf::gateway_agent_crd::GatewayAgent;
+use sha2::Digest;
+use tracing::{info, warn};
+
+use crate::processor::mgmt_client::ConfigClient;
+
+/// The name of the file keeping the config applied last
+const CACHE_FILE: &str = "last-applied.yaml";
+
+/// The prefix of the first line of the file, giving the checksum of the rest
+const CHECKSUM_PREFIX: &str = "# sha256:";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigCacheError {
+    #[error("Failed to access config cache {0}: {1}")]
+    Io(String, std::io::Error),
+    #[error("Config cache {0} is corrupted: {1}")]
+    Corrupted(String, String),
+    #[error("Failed to serialize the config to cache: {0}")]
+    Serialize(String),
+}
+
+/// The cache of the config applied last
+#[derive(Clone, Debug)]
+pub struct ConfigCache {
+    dir: PathBuf,
+}
+
+fn checksum(body: &str) -> String {
+    let hash = sha2::Sha256::digest(body.as_bytes());
+    hash.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Prefix `body` with its checksum
+fn seal(body: &str) -> String {
+    format!("{CHECKSUM_PREFIX}{}\n{body}", checksum(body))
+}
+
+/// Check the checksum of sealed `content`, and return what it seals
+fn unseal(content: &str) -> Result<&str, String> {
+    let (first, body) = content
+        .split_once('\n')
+        .ok_or("missing checksum".to_string())?;
+    let expected = first
+        .strip_prefix(CHECKSUM_PREFIX)
+        .ok_or("missing checksum".to_string())?;
+    let actual = checksum(body);
+    if actual != expected {
+        return Err(format!(
+            "checksum mismatch: expected {expected}, got {actual}"
+        ));
+    }
+    Ok(body)
+}
+
+impl ConfigCache {
+    /// A cache of the config applied last, kept in directory `dir`
+    #[must_use]
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn path(&self) -> PathBuf {
+        self.dir.join(CACHE_FILE)
+    }
+
+    /// Keep `ga`, the object the config applied last was built from, in place of the one kept
+    /// before.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the object can't be serialized or written.
+    pub fn store(&self, ga: &GatewayAgent) -> Result<(), ConfigCacheError> {
+        let path = self.path();
+        let io_err = |e| ConfigCacheError::Io(path.display().to_string(), e);
+        let body =
+            serde_yaml_ng::to_string(ga).map_err(|e| ConfigCacheError::Serialize(e.to_string()))?;
+        fs::create_dir_all(&self.dir).map_err(io_err)?;
+        // write aside then rename, so that the cache is never left half written
+        let tmp = path.with_extension("tmp");
+        let _ = fs::remove_file(&tmp);
+        let mut file = fs::File::create(&tmp).map_err(io_err)?;
+        file.write_all(seal(&body).as_bytes()).map_err(io_err)?;
+        file.sync_all().map_err(io_err)?;
+        fs::set_permissions(&tmp, Permissions::from_mode(0o400)).map_err(io_err)?;
+        fs::rename(&tmp, &path).map_err(io_err)?;
+        Ok(())
+    }
+
+    /// Get the object the config applied last was built from, if kept.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file can't be read, or its content is corrupted.
+    pub fn load(&self) -> Result<Option<GatewayAgent>, ConfigCacheError> {
+        let path = self.path();
+        let name = path.display().to_string();
+        let content = match fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(ConfigCacheError::Io(name, e)),
+        };
+        let body = unseal(&content).map_err(|e| ConfigCacheError::Corrupted(name.clone(), e))?;
+        let ga = serde_yaml_ng::from_str(body)
+            .map_err(|e| ConfigCacheError::Corrupted(name, e.to_string()))?;
+        Ok(Some(ga))
+    }
+
+    /// Forget the object kept, if any.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the file can't be removed.
+    pub fn clear(&self) -> Result<(), ConfigCacheError> {
+        let path = self.path();
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                Err(ConfigCacheError::Io(path.display().to_string(), e))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Keep `ga`, the object the config applied was built from, or forget the object kept if the
+    /// config was not built from one. Failures are logged: failing to keep a config must not fail
+    /// its application.
+    pub(crate) fn keep(&self, ga: Option<&GatewayAgent>) {
+        let result = match ga {
+            Some(ga) => self.store(ga),
+            None => self.clear(),
+        };
+        if let Err(e) = result {
+            warn!("Failed to keep the config applied: {e}");
+        }
+    }
+
+    /// Apply the config kept, if any, with `client`.
+    pub(crate) async fn replay(&self, client: &ConfigClient) {
+        let ga = match self.load() {
+            Ok(Some(ga)) => ga,
+            Ok(None) => {
+                info!("No config to replay in {}", self.dir.display());
+                return;
+            }
+            Err(e) => {
+                warn!("Not replaying the config kept: {e}");
+                return;
+            }
+        };
+        let config = match ExternalConfig::try_from(&ga) {
+            Ok(config) => config,
+            Err(e) => {
+                warn!("Not replaying the config kept, which can't be converted: {e}");
+                return;
+            }
+        };
+        let genid = config.genid;
+        info!("Replaying the config for generation {genid} applied before restarting");
+        if let Err(e) = client.apply_gateway_agent(config, &ga).await {
+            warn!("Failed to replay the config for generation {genid}: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConfigCache, seal, unseal};
+    use k8s_intf::bolero::LegalValue;
+    use k8s_intf::gateway_agent_crd::GatewayAgent;
+
+    #[test]
+    fn config_cache_sealing() {
+        let sealed = seal("spec:\n  vpcs: {}\n");
+        assert!(sealed.starts_with("# sha256:"));
+        assert_eq!(unseal(&sealed).unwrap(), "spec:\n  vpcs: {}\n");
+
+        let tampered = sealed.replace("vpcs", "vpc");
+        assert!(unseal(&tampered).unwrap_err().contains("checksum mismatch"));
+        assert!(unseal("spec:\n  vpcs: {}\n").is_err());
+        assert!(unseal("").is_err());
+    }
+
+    #[test]
+    fn config_cache_keep() {
+        let dir = std::env::temp_dir().join(format!("config-cache-{}", std::process::id()));
+        let cache = ConfigCache::new(&dir);
+        bolero::check!()
+            .with_type::<LegalValue<GatewayAgent>>()
+            .with_iterations(10)
+            .for_each(|ga| {
+                let ga = ga.as_ref();
+                cache.keep(Some(ga));
+                let kept = cache.load().unwrap().expect("a config should be kept");
+                assert_eq!(kept.metadata.name, ga.metadata.name);
+                assert_eq!(kept.metadata.generation, ga.metadata.generation);
+
+                // a config applied which was not pushed as a GatewayAgent empties the cache
+                cache.keep(None);
+                assert!(cache.load().unwrap().is_none());
+            });
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
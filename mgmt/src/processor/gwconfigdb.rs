@@ -4,14 +4,30 @@
 //! Configuration database
 
 use crate::processor::confbuild::internal::build_internal_config;
+use crate::processor::route_tables::RouteTableIds;
 use concurrency::sync::Arc;
-use config::{ConfigSummary, GenId, GwConfigMeta, ValidatedGwConfig};
+use config::{ConfigSummary, ExternalConfig, GenId, GwConfigMeta, ValidatedGwConfig};
+use k8s_intf::gateway_agent_crd::GatewayAgent;
+use std::collections::VecDeque;
 use tracing::{debug, info};
 
+/// Number of configs previously applied which are kept, to be rolled back to
+const PREVIOUS_CONFIGS: usize = 8;
+
+/// A config that was applied, along with the route tables allocated to its VPCs
+#[derive(Clone)]
+pub(crate) struct AppliedConfig {
+    pub(crate) config: Arc<ValidatedGwConfig>,
+    pub(crate) tables: RouteTableIds,
+    /// The object the config was built from, if it was pushed as a `GatewayAgent`
+    pub(crate) source: Option<Arc<GatewayAgent>>,
+}
+
 /// Configuration database, keeps a set of [`GwConfig`]s keyed by generation id [`GenId`]
 pub(crate) struct GwConfigDatabase {
-    applied: Arc<ValidatedGwConfig>, /* Currently applied config or blank */
-    history: Vec<GwConfigMeta>,      /* event history */
+    applied: AppliedConfig,            /* Currently applied config or blank */
+    previous: VecDeque<AppliedConfig>, /* Configs applied before, most recent first */
+    history: Vec<GwConfigMeta>,        /* event history */
 }
 
 impl GwConfigDatabase {
@@ -19,10 +35,16 @@ impl GwConfigDatabase {
     pub fn new() -> Self {
         debug!("Building config database...");
         let mut blank = ValidatedGwConfig::blank();
-        let internal = build_internal_config(&blank, None).unwrap_or_else(|_| unreachable!());
+        let internal = build_internal_config(&blank, None, &RouteTableIds::new())
+            .unwrap_or_else(|_| unreachable!());
         blank.set_internal_config(internal);
         GwConfigDatabase {
-            applied: Arc::from(blank),
+            applied: AppliedConfig {
+                config: Arc::from(blank),
+                tables: RouteTableIds::new(),
+                source: None,
+            },
+            previous: VecDeque::new(),
             history: vec![],
         }
     }
@@ -42,21 +64,87 @@ impl GwConfigDatabase {
         &mut self.history
     }
 
-    /// Store the given config
-    pub fn store(&mut self, config: Arc<ValidatedGwConfig>) {
-        info!("Storing config for generation '{}' in db", config.genid());
-        self.applied = config;
+    /// Store the given config, which was applied. The config it replaces is kept, to be rolled
+    /// back to, unless it is blank. Only the last [`PREVIOUS_CONFIGS`] ones are kept.
+    pub fn store(&mut self, applied: AppliedConfig) {
+        let genid = applied.config.genid();
+        info!("Storing config for generation '{genid}' in db");
+        let replaced = std::mem::replace(&mut self.applied, applied);
+        self.previous.retain(|c| c.config.genid() != genid);
+        if replaced.config.genid() != ExternalConfig::BLANK_GENID
+            && replaced.config.genid() != genid
+        {
+            self.previous.push_front(replaced);
+            self.previous.truncate(PREVIOUS_CONFIGS);
+        }
     }
 
     /// Get the generation Id of the currently applied config, if any.
     #[must_use]
     pub fn get_current_gen(&self) -> GenId {
-        self.applied.genid()
+        self.applied.config.genid()
     }
 
     /// Get a refcounted reference to the applied `GwConfig`
     #[must_use]
     pub fn get_current_config(&self) -> Arc<ValidatedGwConfig> {
-        self.applied.clone()
+        self.applied.config.clone()
+    }
+
+    /// Get the config with generation id `genid`, if it is applied or kept to be rolled back to
+    #[must_use]
+    pub fn get_config(&self, genid: GenId) -> Option<AppliedConfig> {
+        std::iter::once(&self.applied)
+            .chain(&self.previous)
+            .find(|c| c.config.genid() == genid)
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AppliedConfig, GwConfigDatabase, PREVIOUS_CONFIGS};
+    use crate::processor::route_tables::RouteTableIds;
+    use concurrency::sync::Arc;
+    use config::{ExternalConfig, GenId};
+
+    fn applied(genid: GenId) -> AppliedConfig {
+        let mut external = ExternalConfig::new("test-gw");
+        external.genid = genid;
+        AppliedConfig {
+            config: Arc::new(external.validate().expect("Should be valid")),
+            tables: RouteTableIds::new(),
+            source: None,
+        }
+    }
+
+    fn previous(db: &GwConfigDatabase) -> Vec<GenId> {
+        db.previous.iter().map(|c| c.config.genid()).collect()
+    }
+
+    #[test]
+    fn test_previous_configs() {
+        let mut db = GwConfigDatabase::new();
+        db.store(applied(1));
+        db.store(applied(2));
+        db.store(applied(3));
+        assert_eq!(db.get_current_gen(), 3);
+        assert_eq!(previous(&db), vec![2, 1]);
+        assert!(db.get_config(3).is_some());
+        assert!(db.get_config(1).is_some());
+        assert!(db.get_config(ExternalConfig::BLANK_GENID).is_none());
+
+        // rolling back to a previous config keeps the one it replaces
+        db.store(applied(1));
+        assert_eq!(db.get_current_gen(), 1);
+        assert_eq!(previous(&db), vec![3, 2]);
+
+        // only the most recent configs are kept
+        for genid in 10..20 {
+            db.store(applied(genid));
+        }
+        assert_eq!(previous(&db).len(), PREVIOUS_CONFIGS);
+        assert_eq!(previous(&db)[0], 18);
+        assert!(db.get_config(1).is_none());
     }
 }
@@ -10,9 +10,10 @@ use chrono::{TimeZone, Utc};
 
 use config::ExternalConfig;
 use config::converters::k8s::status::dataplane_status::DataplaneStatusForK8sConversion;
-use k8s_intf::client::{ReplaceStatusError, replace_gateway_status, watch_gateway_agent_crd};
+use k8s_intf::client::{ReplaceStatusError, replace_gateway_status, watch_crds};
+use k8s_intf::controller::{CrdEvent, ObjectEvent, WatchPolicy};
 use k8s_intf::gateway_agent_crd::{
-    GatewayAgentStatus, GatewayAgentStatusState, GatewayAgentStatusStateDataplane,
+    GatewayAgent, GatewayAgentStatus, GatewayAgentStatusState, GatewayAgentStatusStateDataplane,
 };
 use tracing::{debug, error, info};
 
@@ -138,42 +139,58 @@ impl K8sClient {
         }
     }
 
-    /// This is a wrapper around `watch_gateway_agent_crd` for a particular callback.
-    /// Note: this method never returns
-    pub async fn k8s_start_config_watch(k8s_client: Arc<Self>) -> ! {
-        let k8s_client2 = k8s_client.clone();
-
-        let callback = async move |ga: &k8s_intf::gateway_agent_crd::GatewayAgent| {
-            match ExternalConfig::try_from(ga) {
-                Err(e) => error!("Failed to convert K8sGatewayAgent to ExternalConfig: {e}"),
-                Ok(external_config) => {
-                    let genid = external_config.genid;
-                    let applied_genid = match k8s_client.client.get_generation().await {
-                        Ok(genid) => genid,
-                        Err(e) => {
-                            error!("Failed to get current config generation: {e}");
-                            return;
-                        }
-                    };
-                    if applied_genid == genid {
-                        debug!("Config generation unchanged (old={applied_genid}, new={genid})");
+    /// Apply the config of a `GatewayAgent` object, unless its generation is applied already.
+    async fn apply_gateway_agent(&self, ga: &GatewayAgent) {
+        match ExternalConfig::try_from(ga) {
+            Err(e) => error!("Failed to convert K8sGatewayAgent to ExternalConfig: {e}"),
+            Ok(external_config) => {
+                let genid = external_config.genid;
+                let applied_genid = match self.client.get_generation().await {
+                    Ok(genid) => genid,
+                    Err(e) => {
+                        error!("Failed to get current config generation: {e}");
                         return;
                     }
+                };
+                if applied_genid == genid {
+                    debug!("Config generation unchanged (old={applied_genid}, new={genid})");
+                    return;
+                }
 
-                    // request the config processor to apply the config and update status on success
-                    if let Err(e) = k8s_client.client.apply_config(external_config).await {
-                        error!("Failed to apply the config for genid {genid}: {e}");
-                    } else {
-                        info!("Config for genid {genid} successfully applied. Updating status...");
-                        k8s_client.update_gateway_status().await;
-                    }
+                // request the config processor to apply the config and update status on success
+                if let Err(e) = self.client.apply_gateway_agent(external_config, ga).await {
+                    error!("Failed to apply the config for genid {genid}: {e}");
+                } else {
+                    info!("Config for genid {genid} successfully applied. Updating status...");
+                    self.update_gateway_status().await;
                 }
             }
+        }
+    }
+
+    /// This is a wrapper around `watch_crds` handling the changes of the CRDs watched.
+    /// Note: this method never returns
+    pub async fn k8s_start_config_watch(k8s_client: Arc<Self>) -> ! {
+        let k8s_client2 = k8s_client.clone();
+
+        let callback = async move |event: CrdEvent| match event {
+            CrdEvent::GatewayAgent(ObjectEvent::Applied(ga)) => {
+                k8s_client.apply_gateway_agent(&ga).await;
+            }
+            CrdEvent::GatewayAgent(ObjectEvent::Deleted(_)) => {
+                // keep running with the config applied last
+                info!("GatewayAgent object deleted, keeping the config applied");
+            }
         };
         let callback = Arc::from(callback);
 
         // infinite loop
-        watch_gateway_agent_crd(&k8s_client2.hostname, callback.clone()).await;
+        watch_crds(
+            &k8s_client2.hostname,
+            WatchPolicy::default(),
+            callback.clone(),
+        )
+        .await;
     }
 
     pub async fn k8s_start_status_update(&self, status_update_interval: &std::time::Duration) {
@@ -97,7 +97,7 @@ impl K8sLess {
                     info!("Current configuration is {applied_genid}");
 
                     // request the config processor to apply the config and update status on success
-                    match k8sless.client.apply_config(external_config).await {
+                    match k8sless.client.apply_gateway_agent(external_config, ga).await {
                         Ok(()) => {
                             info!("Config for generation {genid} was successfully applied. Updating status...");
                             k8sless.update_gateway_status().await;
@@ -3,17 +3,29 @@
 
 //! The configuration processor
 
+use crate::configsvc::Access;
+use crate::grpc;
+use crate::processor::config_cache::ConfigCache;
 use crate::processor::k8s_client::{K8sClient, K8sClientError};
 use crate::processor::k8s_less_client::{K8sLess, K8sLessError};
 use crate::processor::mgmt_client::ConfigClient;
 use crate::processor::proc::ConfigProcessor;
 use crate::processor::proc::ConfigProcessorParams;
-use interface_manager::monitor::{EthEvent, InterfaceMonitor};
+use crate::vpc_manager::VpcManagerError;
+use interface_manager::monitor::{
+    ChangeWatcher, EthEvent, InterfaceMonitor, LinkOperState, LinkTransition,
+};
 
 use concurrency::sync::Arc;
+use config::internal::events::{self, DataplaneEvent};
+use config::internal::status::{
+    DataplaneStatus, InterfaceAdminStatusType, InterfaceOperStatusType,
+};
 use lifecycle::{CancellationToken, Subsystem};
 use net::interface::InterfaceName;
 use routing::RouterCtlSender;
+use std::net::SocketAddr;
+use tokio::sync::RwLock;
 use tracing::{debug, error, info, warn};
 
 #[derive(Debug, thiserror::Error)]
@@ -24,6 +36,8 @@ pub enum LaunchError {
     K8sClientError(#[from] K8sClientError),
     #[error("Error in k8s-less mode: {0}")]
     K8LessError(#[from] K8sLessError),
+    #[error("Failed to create the vpc manager: {0}")]
+    VpcManagerError(#[from] VpcManagerError),
     #[error("Mgmt init cancelled before completion")]
     Cancelled,
 }
@@ -33,12 +47,24 @@ pub struct MgmtParams {
     pub hostname: String,
     pub interfaces: Vec<InterfaceName>,
     pub processor_params: ConfigProcessorParams,
+    /// Address to serve the gRPC endpoints of the dataplane on, if any
+    pub grpc_address: Option<SocketAddr>,
+    /// Address to serve the read-only gRPC endpoints of the dataplane on, if any
+    pub grpc_observer_address: Option<SocketAddr>,
+    /// Bearer token the gRPC methods changing the dataplane require. They are denied without it.
+    pub grpc_token: Option<Arc<str>>,
+    /// Directory where the config applied last is kept, if any
+    pub config_cache_dir: Option<String>,
+    /// Whether to apply the config kept in the cache at startup
+    pub config_replay: bool,
 }
 
 use std::time::Duration;
 const K8S_STATUS_UPD: Duration = Duration::from_secs(15);
 const K8S_INIT_RETRY_TIME: Duration = Duration::from_secs(5);
 const K8S_INIT_MAX_RETRIES: u8 = 10;
+/// Time the kernel interfaces must stay unchanged before being reconciled after they changed
+const KERNEL_CHANGES_DEBOUNCE: Duration = Duration::from_millis(500);
 
 /// Run `init` under `cancel`. Returns [`LaunchError::Cancelled`] on cancel.
 async fn init_cancellable<F, E>(init: F, cancel: &CancellationToken) -> Result<(), LaunchError>
@@ -114,8 +140,47 @@ async fn interface_event_notify(
     }
 }
 
+/// Reflect interface operational state transitions in the shared dataplane status, so that the
+/// status reporter picks them up without waiting for a full refresh, and publish them as events.
+async fn interface_transition_notify(
+    mut rx: tokio::sync::broadcast::Receiver<LinkTransition>,
+    dp_status: Arc<RwLock<DataplaneStatus>>,
+) {
+    use tokio::sync::broadcast::error::RecvError;
+    loop {
+        match rx.recv().await {
+            Ok(transition) => {
+                let oper_status = match transition.to {
+                    LinkOperState::Up => InterfaceOperStatusType::OperUp,
+                    LinkOperState::Down => InterfaceOperStatusType::OperDown,
+                };
+                events::publish(DataplaneEvent::InterfaceOperState {
+                    interface: transition.name.to_string(),
+                    up: transition.to == LinkOperState::Up,
+                });
+                let mut status = dp_status.write().await;
+                let runtime = status
+                    .interface_runtime
+                    .entry(transition.name.to_string())
+                    .or_default();
+                runtime.oper_status = oper_status;
+                if oper_status == InterfaceOperStatusType::OperUp {
+                    runtime.admin_status = InterfaceAdminStatusType::Up;
+                }
+            }
+            Err(RecvError::Lagged(n)) => {
+                warn!("Dropped {n} interface transitions (rx lag)");
+            }
+            Err(RecvError::Closed) => {
+                warn!("Interface transition channel was closed. Will no longer track transitions");
+                break;
+            }
+        }
+    }
+}
+
 /// Init mgmt synchronously on `handle`, then spawn the long-lived tasks
-/// (config processor, status updater, config watcher) tracked under
+/// (config processor, status updater, config watcher, grpc server) tracked under
 /// `mgmt`. Init observes `mgmt.root_token()` so SIGINT during init returns
 /// [`LaunchError::Cancelled`] within cancel latency.
 ///
@@ -133,9 +198,10 @@ pub fn run_mgmt(
         params.interfaces.as_slice(),
     ));
     let if_subsc = ifmonitor.subscribe();
+    let if_transitions = ifmonitor.subscribe_transitions();
     mgmt.spawn_fatal_on_exit(
         "interface monitor",
-        InterfaceMonitor::run(ifmonitor),
+        InterfaceMonitor::run(ifmonitor.clone()),
         handle,
     );
     mgmt.spawn_fatal_on_exit(
@@ -143,11 +209,71 @@ pub fn run_mgmt(
         interface_event_notify(if_subsc, params.processor_params.router_ctl.clone()),
         handle,
     );
+    mgmt.spawn_fatal_on_exit(
+        "interface transition relay",
+        interface_transition_notify(if_transitions, params.processor_params.dp_status_r.clone()),
+        handle,
+    );
+
+    // start watching the changes of the kernel interfaces, to reconcile them on changes
+    let kernel_changes = Arc::new(ChangeWatcher::new(
+        mgmt.cancel_token(),
+        KERNEL_CHANGES_DEBOUNCE,
+    ));
+    let kernel_changes_rx = kernel_changes.subscribe();
+    mgmt.spawn_fatal_on_exit(
+        "kernel change watcher",
+        ChangeWatcher::run(kernel_changes),
+        handle,
+    );
 
     // create config processor and run it
-    let (processor, client) = ConfigProcessor::new(params.processor_params, handle);
+    let router_ctl = params.processor_params.router_ctl.clone();
+    let (processor, client) = ConfigProcessor::new(params.processor_params, handle)?;
+    let cache = params.config_cache_dir.as_deref().map(ConfigCache::new);
+    let processor = processor
+        .with_kernel_changes(kernel_changes_rx)
+        .with_interface_monitor(ifmonitor)
+        .with_config_cache(cache.clone());
     mgmt.spawn_fatal_on_exit("k8s-less config processor", processor.run(), handle);
 
+    // serve the events, the configuration operations and the health of the dataplane
+    if let Some(address) = params.grpc_address {
+        mgmt.spawn_fatal_on_exit(
+            "grpc server",
+            grpc::serve(
+                address,
+                mgmt.cancel_token(),
+                client.clone(),
+                router_ctl.clone(),
+                Access::ReadWrite,
+                params.grpc_token.clone(),
+            ),
+            handle,
+        );
+    }
+    if let Some(address) = params.grpc_observer_address {
+        mgmt.spawn_fatal_on_exit(
+            "grpc observer server",
+            grpc::serve(
+                address,
+                mgmt.cancel_token(),
+                client.clone(),
+                router_ctl.clone(),
+                Access::ReadOnly,
+                None,
+            ),
+            handle,
+        );
+    }
+
+    // apply the config applied before restarting, not to come up empty until one is pushed
+    if let Some(cache) = &cache
+        && params.config_replay
+    {
+        handle.block_on(cache.replay(&client));
+    }
+
     if let Some(config_dir) = &params.config_dir {
         warn!("Running in k8s-less mode....");
         handle.block_on(run_k8s_less(
@@ -295,6 +421,13 @@ mod tests {
         assert!(matches!(result, Err(LaunchError::K8LessError(_))));
     }
 
+    #[test]
+    fn vpc_manager_failure_is_a_launch_error() {
+        let error = LaunchError::from(VpcManagerError::NoRuntime);
+        assert!(matches!(error, LaunchError::VpcManagerError(_)));
+        assert!(error.to_string().contains("No tokio runtime"));
+    }
+
     /// Locks in the main.rs contract: SIGTERM during k8s init must yield
     /// exit 0 (else systemd restart-loops the unit). Mirrors the match
     /// arms in runtime.rs.
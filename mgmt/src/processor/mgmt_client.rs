@@ -3,6 +3,7 @@
 
 //! Interface to management processor
 
+use config::ApplyId;
 use config::ConfigError;
 use config::ConfigResult;
 use config::GenId;
@@ -10,6 +11,7 @@ use config::internal::status::DataplaneStatus;
 use config::{ExternalConfig, ValidatedGwConfig};
 
 use concurrency::sync::Arc;
+use k8s_intf::gateway_agent_crd::GatewayAgent;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::oneshot;
 use tokio::sync::oneshot::Receiver;
@@ -21,10 +23,11 @@ use thiserror::Error;
 /// A request type to the `ConfigProcessor`
 #[derive(Debug)]
 pub(crate) enum ConfigRequest {
-    ApplyConfig(Box<ExternalConfig>),
+    ApplyConfig(Box<ExternalConfig>, Option<Arc<GatewayAgent>>, ApplyId),
     GetCurrentConfig,
     GetGeneration,
     GetDataplaneStatus,
+    Rollback(GenId, ApplyId),
 }
 
 /// A response from the `ConfigProcessor`
@@ -34,6 +37,7 @@ pub(crate) enum ConfigResponse {
     GetCurrentConfig(Arc<ValidatedGwConfig>),
     GetGeneration(GenId),
     GetDataplaneStatus(Box<DataplaneStatus>),
+    Rollback(ConfigResult),
 }
 type ConfigResponseChannel = oneshot::Sender<ConfigResponse>;
 
@@ -76,13 +80,43 @@ impl ConfigClient {
         Self { tx: channel_tx }
     }
 
-    /// Apply the provided `ExternalConfig`
+    /// Apply the provided `ExternalConfig`. The request gets an [`ApplyId`] that is attached to
+    /// the logs of all the changes that it causes, and recorded in the config history.
     ///
     /// # Errors
     /// This method returns `ConfigProcessorError` if the config request could not be sent, the response
     /// could not be received or the response was a failure.
     pub async fn apply_config(&self, external: ExternalConfig) -> Result<(), ConfigProcessorError> {
-        let (req, rx) = ConfigChannelRequest::new(ConfigRequest::ApplyConfig(Box::new(external)));
+        self.request_apply(external, None).await
+    }
+
+    /// Apply `external`, the config of `ga`. Once applied, `ga` is kept in the config cache of
+    /// the config processor, if any, to be replayed upon restarting.
+    ///
+    /// # Errors
+    /// This method returns `ConfigProcessorError` if the config request could not be sent, the response
+    /// could not be received or the response was a failure.
+    pub async fn apply_gateway_agent(
+        &self,
+        external: ExternalConfig,
+        ga: &GatewayAgent,
+    ) -> Result<(), ConfigProcessorError> {
+        self.request_apply(external, Some(Arc::new(ga.clone())))
+            .await
+    }
+
+    async fn request_apply(
+        &self,
+        external: ExternalConfig,
+        source: Option<Arc<GatewayAgent>>,
+    ) -> Result<(), ConfigProcessorError> {
+        let apply_id = ApplyId::next();
+        info!(
+            "Requesting to apply config {} as {apply_id}",
+            external.genid
+        );
+        let request = ConfigRequest::ApplyConfig(Box::new(external), source, apply_id);
+        let (req, rx) = ConfigChannelRequest::new(request);
         self.tx.send(req).await?;
         match rx.await? {
             ConfigResponse::ApplyConfig(Err(e)) => Err(e.into()),
@@ -91,6 +125,24 @@ impl ConfigClient {
         }
     }
 
+    /// Roll back to the config with generation id `genid`, applied before. The request gets an
+    /// [`ApplyId`], like requests to apply a config, and returns it.
+    ///
+    /// # Errors
+    /// This method returns `ConfigProcessorError` if the request could not be sent, the response
+    /// could not be received or the config could not be rolled back to.
+    pub async fn rollback(&self, genid: GenId) -> Result<ApplyId, ConfigProcessorError> {
+        let apply_id = ApplyId::next();
+        info!("Requesting to roll back to config {genid} as {apply_id}");
+        let (req, rx) = ConfigChannelRequest::new(ConfigRequest::Rollback(genid, apply_id));
+        self.tx.send(req).await?;
+        match rx.await? {
+            ConfigResponse::Rollback(Err(e)) => Err(e.into()),
+            ConfigResponse::Rollback(Ok(())) => Ok(apply_id),
+            _ => unreachable!(),
+        }
+    }
+
     /// Get the config currently applied.
     ///
     /// # Errors
@@ -5,9 +5,13 @@
 //! This module implements the core logic to determine and build internal configurations.
 
 pub(crate) mod confbuild;
+pub(crate) mod config_cache;
 pub(crate) mod gwconfigdb;
 pub(crate) mod k8s_client;
 pub(crate) mod k8s_less_client;
 pub(crate) mod launch;
 pub(crate) mod mgmt_client;
 pub(crate) mod proc;
+pub(crate) mod route_tables;
+pub(crate) mod staging;
+pub(crate) mod table_events;
@@ -3,51 +3,51 @@
 
 //! Configuration processor
 
-use acl_filter::AclFilterContext;
-use acl_filter::AclFilterContextWriter;
+use args::RouteTableRange;
 use concurrency::sync::Arc;
-use config::external::overlay::ValidatedOverlay;
 use flow_entry::flow_table::FlowTable;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use tokio::sync::RwLock;
-use tokio::sync::mpsc;
+use tokio::sync::{mpsc, watch};
 
-use config::external::overlay::vpc::ValidatedVpcTable;
 use config::internal::device::tracecfg::TracingConfig;
+use config::internal::events::{self, DataplaneEvent};
+use config::internal::readiness::{self, Component};
 use config::internal::status::{
-    DataplaneStatus, FrrStatus, VpcCounters, VpcPeeringCounters, VpcStatus,
+    DataplaneStatus, FrrStatus, InterfaceOperStatusType, VpcCounters, VpcPeeringCounters, VpcStatus,
 };
+use config::{ApplyId, DeviceConfig, ExternalConfig, GenId, InternalConfig, ValidatedGwConfig};
 use config::{ConfigError, ConfigResult, stringify};
-use config::{DeviceConfig, ExternalConfig, GenId, InternalConfig, ValidatedGwConfig};
 
 use crate::processor::confbuild::internal::build_internal_config;
 use crate::processor::confbuild::router::generate_router_config;
-use flow_filter::{FlowFilterTable, FlowFilterTableWriter};
-use nat::masquerade::{MasqueradeConfig, NatAllocatorWriter};
-use nat::portfw::PortFwTableWriter;
-use nat::portfw::build_port_forwarding_configuration;
-use nat::static_nat::NatTablesWriter;
-use nat::static_nat::setup::build_nat_configuration;
+use crate::processor::config_cache::ConfigCache;
+use k8s_intf::gateway_agent_crd::GatewayAgent;
+use kvstore::{KvStore, Namespace};
 use pipeline::PipelineData;
 
-use crate::processor::gwconfigdb::GwConfigDatabase;
+use crate::processor::gwconfigdb::{AppliedConfig, GwConfigDatabase};
 use crate::processor::mgmt_client::{
     ConfigChannelRequest, ConfigClient, ConfigRequest, ConfigResponse,
 };
+use crate::processor::route_tables::RouteTableAllocator;
+use crate::processor::staging::StagedTables;
+use crate::processor::table_events::TableEventSender;
 
-use crate::vpc_manager::{RequiredInformationBase, VpcManager};
+use crate::vpc_manager::{
+    InterfaceView, RequiredInformationBase, VpcManager, VpcManagerError, tap_interfaces,
+};
+use interface_manager::monitor::InterfaceMonitor;
 use rekon::{Observe, Reconcile};
 use tracectl::{TracingRateLimitConfig, get_trace_ctl};
-use tracing::{debug, error, info, warn};
+use tracing::{Instrument, debug, error, info, info_span, warn};
 
 use net::interface::display::MultiIndexInterfaceMapView;
-use net::interface::{Interface, InterfaceName};
+use net::interface::{Interface, InterfaceName, OperationalState};
 use routing::{FrrAppliedConfig, RouterCtlSender};
 
-use stats::VpcMapName;
 use stats::VpcStatsStore;
 use vpcmap::VpcDiscriminant;
-use vpcmap::map::{VpcMap, VpcMapWriter};
 
 // bring in BmpOptions to pass through to internal config builder
 use config::internal::routing::bmp::BmpOptions;
@@ -69,7 +69,14 @@ pub(crate) struct ConfigProcessor {
     config_db: GwConfigDatabase,
     rx: mpsc::Receiver<ConfigChannelRequest>,
     vpc_mgr: VpcManager<RequiredInformationBase>,
+    route_tables: RouteTableAllocator,
     proc_params: ConfigProcessorParams,
+    /// Changes of the kernel interfaces, upon which they get reconciled again, if watched
+    kernel_changes: Option<watch::Receiver<u64>>,
+    /// Requests to roll back to a config applied before, from the cli
+    rollback_requests: Option<mpsc::Receiver<GenId>>,
+    /// Cache where the config applied last is kept, if any
+    config_cache: Option<ConfigCache>,
 }
 
 pub struct ConfigProcessorParams {
@@ -82,23 +89,8 @@ pub struct ConfigProcessorParams {
     // flow table
     pub flow_table: Arc<FlowTable>,
 
-    // writer for vpc mapping table
-    pub vpcmapw: VpcMapWriter<VpcMapName>,
-
-    // writer for static NAT tables
-    pub nattablesw: NatTablesWriter,
-
-    // writer for masquerade allocator
-    pub natallocatorw: NatAllocatorWriter,
-
-    // writer for flow filter table
-    pub flowfilterw: FlowFilterTableWriter,
-
-    // writer for ACL filter tables
-    pub aclfilterw: AclFilterContextWriter,
-
-    // writer for port forwarding table
-    pub portfw_w: PortFwTableWriter,
+    // ordered channel to the writers of the tables of the pipeline
+    pub table_events: TableEventSender,
 
     // store for vpc stats
     pub vpc_stats_store: Arc<VpcStatsStore>,
@@ -108,6 +100,24 @@ pub struct ConfigProcessorParams {
 
     // BMP options to inject into InternalConfig
     pub bmp_options: Option<BmpOptions>,
+
+    // publisher of the tap interfaces created for the config, for the driver to attach to
+    pub tap_interfaces: Option<watch::Sender<BTreeSet<InterfaceName>>>,
+
+    // view of the kernel interfaces, required vs observed, shown by the cli
+    pub interface_view: InterfaceView,
+
+    // range of the route tables allocated to the VRFs of VPCs
+    pub route_table_range: RouteTableRange,
+
+    // store persisting the state learned at runtime, like the route tables allocated to VPCs
+    pub state_store: Option<Arc<dyn KvStore>>,
+
+    // requests to roll back to a config applied before, from the cli
+    pub rollback_requests: Option<mpsc::Receiver<GenId>>,
+
+    // kernel interfaces which are never observed, updated or removed
+    pub unmanaged_interfaces: BTreeSet<InterfaceName>,
 }
 
 impl ConfigProcessor {
@@ -116,23 +126,25 @@ impl ConfigProcessor {
     /////////////////////////////////////////////////////////////////////////////////
     /// Create a [`ConfigProcessor`] and return a [`ConfigClient`] to interact with it
     /////////////////////////////////////////////////////////////////////////////////
-    #[must_use]
     pub(crate) fn new(
-        proc_params: ConfigProcessorParams,
+        mut proc_params: ConfigProcessorParams,
         handle: &tokio::runtime::Handle,
-    ) -> (Self, ConfigClient) {
+    ) -> Result<(Self, ConfigClient), VpcManagerError> {
         debug!("Creating config processor...");
         let _g = handle.enter();
 
-        // create netlink socket and spawn background task
-        let Ok((connection, netlink, _)) = rtnetlink::new_connection() else {
-            panic!("failed to create connection");
-        };
-        handle.spawn(connection);
+        // build vpc manager, with its own netlink connection and a cache of the kernel links
+        let vpc_mgr = VpcManager::<RequiredInformationBase>::builder()
+            .cache_links()
+            .unmanaged(proc_params.unmanaged_interfaces.clone())
+            .build()?;
 
-        // build vpc manager
-        let netlink = Arc::new(netlink);
-        let vpc_mgr = VpcManager::<RequiredInformationBase>::new(netlink);
+        // build route table allocator, restoring the previous allocations
+        let route_table_state = proc_params.state_store.as_ref().map(|store| {
+            Namespace::new(store.clone(), "route-tables").unwrap_or_else(|_| unreachable!())
+        });
+        let route_tables =
+            RouteTableAllocator::new(proc_params.route_table_range, route_table_state);
 
         // create processor
         let (tx, rx) = mpsc::channel(Self::CHANNEL_SIZE);
@@ -140,18 +152,62 @@ impl ConfigProcessor {
             config_db: GwConfigDatabase::new(),
             rx,
             vpc_mgr,
+            route_tables,
+            rollback_requests: proc_params.rollback_requests.take(),
             proc_params,
+            kernel_changes: None,
+            config_cache: None,
         };
-        (processor, ConfigClient::new(tx))
+        Ok((processor, ConfigClient::new(tx)))
+    }
+
+    /// Tell `monitor` of the administrative state changes made to the interfaces when
+    /// reconciling them, so that it attributes the transitions they cause to reconciliation.
+    #[must_use]
+    pub(crate) fn with_interface_monitor(mut self, monitor: Arc<InterfaceMonitor>) -> Self {
+        self.vpc_mgr = self.vpc_mgr.with_interface_monitor(monitor);
+        self
+    }
+
+    /// Reconcile the kernel interfaces with the applied configuration each time `changes`
+    /// notifies that they changed, so that they don't stay diverged from the configuration.
+    #[must_use]
+    pub(crate) fn with_kernel_changes(mut self, changes: watch::Receiver<u64>) -> Self {
+        self.kernel_changes = Some(changes);
+        self
+    }
+
+    /// Keep the config applied last, and those rolled back to, in `cache`, to be replayed upon
+    /// restarting.
+    #[must_use]
+    pub(crate) fn with_config_cache(mut self, cache: Option<ConfigCache>) -> Self {
+        self.config_cache = cache;
+        self
     }
 
     /// Main entry point for new configurations
-    pub(crate) async fn process_incoming_config(&mut self, config: ExternalConfig) -> ConfigResult {
+    pub(crate) async fn process_incoming_config(
+        &mut self,
+        config: ExternalConfig,
+        source: Option<Arc<GatewayAgent>>,
+        apply_id: ApplyId,
+    ) -> ConfigResult {
         let mut validated_config = config.validate()?;
-        let internal =
-            build_internal_config(&validated_config, self.proc_params.bmp_options.clone())?;
+        let table_ids = self
+            .route_tables
+            .allocate(validated_config.external().overlay().vpc_table())?;
+        let internal = build_internal_config(
+            &validated_config,
+            self.proc_params.bmp_options.clone(),
+            &table_ids,
+        )?;
         validated_config.set_internal_config(internal);
-        self.apply(validated_config).await
+        let applied = AppliedConfig {
+            config: Arc::from(validated_config),
+            tables: table_ids,
+            source,
+        };
+        self.apply(applied, apply_id, false).await
     }
 
     async fn update_history(
@@ -159,12 +215,14 @@ impl ConfigProcessor {
         config: &ValidatedGwConfig,
         result: &ConfigResult,
         is_rollback: bool,
+        apply_id: ApplyId,
     ) {
         let guard = config.meta().load();
         let mut meta = guard.as_ref().clone();
         meta.apply_time();
         meta.error(result);
         meta.is_rollback = is_rollback;
+        meta.apply_id = Some(apply_id);
         self.config_db.history_mut().push(meta.clone());
         if !is_rollback {
             config.meta().store(Arc::from(meta));
@@ -175,42 +233,103 @@ impl ConfigProcessor {
         self.config_db.log();
     }
 
-    /// Apply a configuration. On success, store it. On failure, roll-back. Update the history in either case.
-    async fn apply(&mut self, config: ValidatedGwConfig) -> ConfigResult {
-        let config = Arc::from(config);
+    /// Apply a configuration, or roll back to it if `is_rollback`. On success, store it, keep it
+    /// in the config cache and commit its route tables. On failure, roll-back. Update the history
+    /// in either case.
+    async fn apply(
+        &mut self,
+        applied: AppliedConfig,
+        apply_id: ApplyId,
+        is_rollback: bool,
+    ) -> ConfigResult {
+        let config = applied.config.clone();
         let result = self.apply_gw_config(config.clone()).await;
-        self.update_history(&config, &result, false).await;
+        self.update_history(&config, &result, is_rollback, apply_id)
+            .await;
         if result.is_ok() {
-            self.config_db.store(config);
+            lifecycle::crash::set_config_genid(config.genid());
+            self.route_tables.commit(applied.tables.clone());
+            if let Some(cache) = &self.config_cache {
+                cache.keep(applied.source.as_deref());
+            }
+            self.config_db.store(applied);
         } else {
-            self.rollback().await;
+            self.rollback(apply_id).await;
         }
         result
     }
 
-    /// Attempt to apply the previously applied config.
-    async fn rollback(&mut self) {
+    /// Attempt to apply the previously applied config, after the request `apply_id` failed.
+    async fn rollback(&mut self, apply_id: ApplyId) {
         let active = self.config_db.get_current_config();
         let active_genid = active.genid();
         info!("Rolling back to config with genid {}...", active.genid());
         let result = self.apply_gw_config(active.clone()).await.map(drop);
-        self.update_history(&active, &result, true).await;
+        self.update_history(&active, &result, true, apply_id).await;
         match &result {
             Ok(_) => debug!("Successfully rolled back to config {active_genid}"),
             Err(e) => error!("Rolling back to config {active_genid} failed: {e}"),
         };
     }
 
-    /// RPC handler: store and apply the provided config
-    async fn handle_apply_config(&mut self, config: ExternalConfig) -> ConfigResponse {
+    /// RPC handler: store and apply the provided config. Everything logged while doing so, by
+    /// the processor, the table writers, the router and the reconciliation of the kernel state,
+    /// is within a span carrying the id of the request.
+    async fn handle_apply_config(
+        &mut self,
+        config: ExternalConfig,
+        source: Option<Arc<GatewayAgent>>,
+        apply_id: ApplyId,
+    ) -> ConfigResponse {
         let genid = config.genid;
-        debug!("━━━━━━ Handling apply configuration request. Genid {genid} ━━━━━━");
-        let result = self.process_incoming_config(config).await;
-        debug!(
-            "━━━━━━ Completed configuration for Genid {genid}: {} ━━━━━━",
-            stringify(&result)
-        );
-        ConfigResponse::ApplyConfig(result)
+        let span = info_span!("config_apply", %apply_id, genid);
+        async {
+            debug!("━━━━━━ Handling apply configuration request. Genid {genid} ━━━━━━");
+            let result = self.process_incoming_config(config, source, apply_id).await;
+            let outcome = stringify(&result);
+            debug!("━━━━━━ Completed configuration for Genid {genid}: {outcome} ━━━━━━");
+            info!("Request {apply_id} to apply config {genid} completed: {outcome}");
+            if result.is_ok() {
+                readiness::set_ready(Component::Config, true);
+            }
+            events::publish(match &result {
+                Ok(()) => DataplaneEvent::ConfigApplied { genid, apply_id },
+                Err(e) => DataplaneEvent::ConfigFailed {
+                    genid,
+                    apply_id,
+                    error: e.to_string(),
+                },
+            });
+            ConfigResponse::ApplyConfig(result)
+        }
+        .instrument(span)
+        .await
+    }
+
+    /// RPC handler: roll back to the config with generation id `genid`, which was applied
+    /// before, re-publishing its tables and re-rendering its FRR config.
+    async fn handle_rollback(&mut self, genid: GenId, apply_id: ApplyId) -> ConfigResponse {
+        let span = info_span!("config_rollback", %apply_id, genid);
+        async {
+            info!("Request {apply_id} to roll back to config {genid}");
+            let result = match self.config_db.get_config(genid) {
+                Some(applied) => self.apply(applied, apply_id, true).await,
+                None => Err(ConfigError::NoSuchConfig(genid)),
+            };
+            let outcome = stringify(&result);
+            info!("Request {apply_id} to roll back to config {genid} completed: {outcome}");
+            events::publish(match &result {
+                Ok(()) => DataplaneEvent::ConfigApplied { genid, apply_id },
+                Err(e) => DataplaneEvent::ConfigFailed {
+                    genid,
+                    apply_id,
+                    error: e.to_string(),
+                },
+            });
+            ConfigResponse::Rollback(result)
+        }
+        .instrument(span)
+        .await
     }
 
     /// RPC handler: get current config generation id
@@ -231,6 +350,26 @@ impl ConfigProcessor {
             guard.clone()
         };
 
+        // reflect the link state last observed. Operational state transitions relayed by the
+        // interface monitor are more recent than the observations, so they are not overridden
+        for (name, link) in self.proc_params.interface_view.link_states() {
+            let runtime = status
+                .interface_runtime
+                .entry(name.to_string())
+                .or_default();
+            if runtime.oper_status == InterfaceOperStatusType::Unknown {
+                runtime.oper_status = match link.oper {
+                    OperationalState::Up => InterfaceOperStatusType::OperUp,
+                    OperationalState::Down => InterfaceOperStatusType::OperDown,
+                    OperationalState::Unknown | OperationalState::Complex => {
+                        InterfaceOperStatusType::Unknown
+                    }
+                };
+            }
+            runtime.carrier = link.carrier;
+            runtime.speed = link.speed;
+        }
+
         //flush old VPC stats
         status.vpcs.clear();
         status.vpc_peering_counters.clear();
@@ -363,34 +502,102 @@ impl ConfigProcessor {
     pub async fn run(mut self) {
         info!("Starting config processor...");
         loop {
-            // receive config requests over channel from a `ConfigClient`
-            match self.rx.recv().await {
-                Some(req) => {
-                    let response = match req.request {
-                        ConfigRequest::ApplyConfig(config) => {
-                            self.handle_apply_config(*config).await
+            tokio::select! {
+                // receive config requests over channel from a `ConfigClient`
+                req = self.rx.recv() => match req {
+                    Some(req) => {
+                        let response = match req.request {
+                            ConfigRequest::ApplyConfig(config, source, apply_id) => {
+                                self.handle_apply_config(*config, source, apply_id).await
+                            }
+                            ConfigRequest::GetCurrentConfig => self.handle_get_config(),
+                            ConfigRequest::GetGeneration => self.handle_get_generation(),
+                            ConfigRequest::GetDataplaneStatus => {
+                                self.handle_get_dataplane_status().await
+                            }
+                            ConfigRequest::Rollback(genid, apply_id) => {
+                                self.handle_rollback(genid, apply_id).await
+                            }
+                        };
+                        if req.reply_tx.send(response).is_err() {
+                            warn!("Failed to send reply from config processor: receiver dropped?");
                         }
-                        ConfigRequest::GetCurrentConfig => self.handle_get_config(),
-                        ConfigRequest::GetGeneration => self.handle_get_generation(),
-                        ConfigRequest::GetDataplaneStatus => {
-                            self.handle_get_dataplane_status().await
-                        }
-                    };
-                    if req.reply_tx.send(response).is_err() {
-                        warn!("Failed to send reply from config processor: receiver dropped?");
                     }
-                }
-                None => {
-                    warn!("Channel to config processor was closed!");
+                    None => {
+                        warn!("Channel to config processor was closed!");
+                    }
+                },
+                genid = rollback_requested(self.rollback_requests.as_mut()) => match genid {
+                    Some(genid) => {
+                        self.handle_rollback(genid, ApplyId::next()).await;
+                    }
+                    None => {
+                        warn!("Channel of rollback requests was closed!");
+                        self.rollback_requests = None;
+                    }
+                },
+                changed = kernel_changed(self.kernel_changes.as_mut()) => {
+                    if changed {
+                        self.handle_kernel_changes().await;
+                    } else {
+                        warn!("Kernel changes are no longer watched");
+                        self.kernel_changes = None;
+                    }
                 }
             }
         }
     }
+
+    /// Reconcile the kernel interfaces with the applied configuration, after they changed.
+    /// Changes caused by reconciliation itself trigger a pass as well, which finds nothing to do.
+    async fn handle_kernel_changes(&mut self) {
+        let config = self.config_db.get_current_config();
+        let genid = config.genid();
+        // nothing was applied yet
+        if genid == ExternalConfig::BLANK_GENID {
+            return;
+        }
+        let Some(internal) = config.internal() else {
+            return;
+        };
+        debug!("Kernel interfaces changed: reconciling them with config {genid}");
+        let view = &self.proc_params.interface_view;
+        if let Err(e) = self.vpc_mgr.apply_config(internal, genid, view).await {
+            error!("Failed to reconcile kernel interfaces after they changed: {e}");
+            events::publish(DataplaneEvent::ReconcileFailed {
+                genid,
+                error: e.to_string(),
+            });
+        }
+    }
+}
+
+/// Wait for the kernel interfaces to change. Returns false if changes can no longer be watched,
+/// and never returns if they are not watched.
+async fn kernel_changed(changes: Option<&mut watch::Receiver<u64>>) -> bool {
+    match changes {
+        Some(changes) => changes.changed().await.is_ok(),
+        None => std::future::pending().await,
+    }
+}
+
+/// Wait for a request to roll back to a config. Returns None if requests can no longer be
+/// received, and never returns if there are none to receive.
+async fn rollback_requested(requests: Option<&mut mpsc::Receiver<GenId>>) -> Option<GenId> {
+    match requests {
+        Some(requests) => requests.recv().await,
+        None => std::future::pending().await,
+    }
 }
 
 impl VpcManager<RequiredInformationBase> {
     /// Apply the provided [`InternalConfig`]
-    async fn apply_config(&self, internal: &InternalConfig, genid: GenId) -> ConfigResult {
+    async fn apply_config(
+        &self,
+        internal: &InternalConfig,
+        genid: GenId,
+        view: &InterfaceView,
+    ) -> ConfigResult {
         /* build required information base from internal config */
         let mut rib: RequiredInformationBase = match internal.try_into() {
             Ok(rib) => rib,
@@ -403,14 +610,46 @@ impl VpcManager<RequiredInformationBase> {
 
         debug!("Required information base for genid {genid} is:\n{rib:?}");
 
+        if let Err(err) = self.open_namespaces(&rib) {
+            let msg = format!("Couldn't open the network namespaces of the interfaces: {err}");
+            error!("{msg}");
+            return Err(ConfigError::FailureApply(msg));
+        }
+
         let mut required_passes = 0;
-        while !self
-            .reconcile(&mut rib, &self.observe().await.unwrap())
-            .await
-        {
+        let mut rechecked = false;
+        loop {
+            let observed = self.observe().await.map_err(|e| {
+                ConfigError::FailureApply(format!("Failed to observe interface state: {e}"))
+            })?;
+            view.update(&rib, &observed);
+            let report = self.reconcile(&mut rib, &observed).await;
+            view.record(&report);
+            if report.is_reconciled() {
+                // the links may have changed while this pass compared them: check them again once
+                if self.is_stale(&observed) && !rechecked {
+                    debug!("Kernel links changed during the reconciliation pass, checking again");
+                    rechecked = true;
+                    continue;
+                }
+                break;
+            }
+            debug!("Interface reconciliation pass {required_passes}: {report}");
+            // further passes would fail the same way
+            if report.permanent_failures().next().is_some() {
+                let msg = format!(
+                    "Interface reconciliation failed: {}",
+                    report.describe_failures()
+                );
+                error!("{msg}");
+                return Err(ConfigError::FailureApply(msg));
+            }
             required_passes += 1;
             if required_passes >= 300 {
-                let msg = "Interface reconciliation not achieved after 300 passes".to_string();
+                let mut msg = "Interface reconciliation not achieved after 300 passes".to_string();
+                if report.failures().next().is_some() {
+                    msg += &format!(": {}", report.describe_failures());
+                }
                 error!("{msg}");
                 return Err(ConfigError::FailureApply(msg));
             }
@@ -420,6 +659,7 @@ impl VpcManager<RequiredInformationBase> {
         let obs_rib = self.observe().await.map_err(|_| {
             ConfigError::InternalFailure("Failed to observe interface state".to_string())
         })?;
+        view.update(&rib, &obs_rib);
 
         debug!(
             "The current kernel interfaces are:\n{}",
@@ -474,90 +714,6 @@ async fn apply_router_config(
     Ok(())
 }
 
-/// refresh mappings for per vpc statistics
-///
-/// Returns the list of `(VpcDiscriminant, name)` so the caller can seed the stats store.
-fn update_stats_vpc_mappings(
-    config: &ValidatedGwConfig,
-    vpcmapw: &mut VpcMapWriter<VpcMapName>,
-) -> Vec<(VpcDiscriminant, String)> {
-    // create a mapping table from the vpc table in the config
-    // FIXME(fredi): visibility
-    // FIXME(fredi): generalize the vpcmapName table
-    let vpc_table = config.external().overlay().vpc_table();
-    let mut vpcmap = VpcMap::<VpcMapName>::new();
-    let mut pairs: Vec<(VpcDiscriminant, String)> = Vec::with_capacity(vpc_table.len());
-
-    for vpc in vpc_table.values() {
-        let disc = VpcDiscriminant::VNI(vpc.vni());
-        let name = vpc.name().to_string();
-        let map = VpcMapName::new(disc, &name);
-        vpcmap.add(disc, map).unwrap_or_else(|_| unreachable!());
-        pairs.push((disc, name));
-    }
-
-    vpcmapw.set_map(vpcmap);
-    pairs
-}
-
-fn apply_flow_filtering_config(
-    overlay: &ValidatedOverlay,
-    flowfilterw: &mut FlowFilterTableWriter,
-) -> ConfigResult {
-    let flow_filter_table = FlowFilterTable::build_from_overlay(overlay)?;
-    flowfilterw.update_flow_filter_table(flow_filter_table);
-    debug!("Successfully updated flow-filter table");
-    Ok(())
-}
-
-fn apply_acl_filter_config(
-    overlay: &ValidatedOverlay,
-    aclfilterw: &mut AclFilterContextWriter,
-) -> ConfigResult {
-    let acl_filter_context = AclFilterContext::try_from(overlay)?;
-    aclfilterw.store(acl_filter_context);
-    debug!("Successfully updated acl-filter tables");
-    Ok(())
-}
-
-/// Update the Nat tables for static NAT
-fn apply_static_nat_config(
-    vpc_table: &ValidatedVpcTable,
-    nattablesw: &mut NatTablesWriter,
-) -> ConfigResult {
-    let nat_table = build_nat_configuration(vpc_table)?;
-    nattablesw.update_nat_tables(nat_table);
-    debug!("Successfully updated the static NAT configuration");
-    Ok(())
-}
-
-/// Update the config for masquerade.
-/// This is now infallible. Validation should ensure it is.
-fn apply_masquerade_config(
-    vpc_table: &ValidatedVpcTable,
-    flow_table: &FlowTable,
-    natallocatorw: &mut NatAllocatorWriter,
-    genid: GenId,
-) {
-    let nat_config = MasqueradeConfig::new(vpc_table, genid).set_randomize(true);
-    natallocatorw.update_nat_allocator(nat_config, flow_table);
-    debug!("Updated masquerade NAT allocator");
-}
-
-fn apply_port_forwarding_config(
-    vpc_table: &ValidatedVpcTable,
-    portfw_w: &mut PortFwTableWriter,
-) -> ConfigResult {
-    let ruleset = build_port_forwarding_configuration(vpc_table)?;
-    debug!("Port-forwarding configuration successfully built. Applying changes ...");
-    portfw_w
-        .update_table(&ruleset)
-        .map_err(|e| ConfigError::PortForwarding(e.to_string()))?;
-
-    debug!("Successfully updated the port-forwarding table");
-    Ok(())
-}
-
 fn apply_tracing_config(tracing: &Option<TracingConfig>) -> ConfigResult {
     // Apply tracing config if provided. Otherwise, apply an empty/default config.
     let default = TracingConfig::default();
@@ -578,6 +734,25 @@ fn apply_tracing_config(tracing: &Option<TracingConfig>) -> ConfigResult {
     Ok(())
 }
 
+/// Publish the tap interfaces of a config, once the vpc manager has created them
+fn publish_tap_interfaces(
+    internal: &InternalConfig,
+    tap_interfaces_tx: Option<&watch::Sender<BTreeSet<InterfaceName>>>,
+) {
+    let Some(tx) = tap_interfaces_tx else {
+        return;
+    };
+    let taps = tap_interfaces(internal);
+    tx.send_if_modified(|current| {
+        if *current == taps {
+            return false;
+        }
+        debug!("Tap interfaces changed: {taps:?}");
+        *current = taps;
+        true
+    });
+}
+
 fn apply_device_config(device: &DeviceConfig) -> ConfigResult {
     apply_tracing_config(&device.tracing)?;
     Ok(())
@@ -591,13 +766,8 @@ impl ConfigProcessor {
 
         let vpc_mgr = &self.vpc_mgr;
         let router_ctl = &self.proc_params.router_ctl;
-        let vpcmapw = &mut self.proc_params.vpcmapw;
-        let nattablesw = &mut self.proc_params.nattablesw;
-        let natallocatorw = &mut self.proc_params.natallocatorw;
-        let flowfilterw = &mut self.proc_params.flowfilterw;
-        let aclfilterw = &mut self.proc_params.aclfilterw;
-        let portfw_w = &mut self.proc_params.portfw_w;
         let flow_table = &self.proc_params.flow_table;
+        let interface_view = &self.proc_params.interface_view;
 
         // internal config should be available
         let internal = config.internal().unwrap_or_else(|| unreachable!());
@@ -613,13 +783,36 @@ impl ConfigProcessor {
                 .map_or(FlowTable::DEFAULT_CAPACITY, |gwc| gwc.get()),
         );
 
+        /* apply session logging config */
+        flow_table.set_session_log(config.external().session_log(), config.external().gwname());
+
+        /* let the TCP keepalives extend their sessions, if configured */
+        flow_table
+            .set_tcp_keepalive_timeout(config.external().session_table().tcp_keepalive_timeout);
+
+        /* apply the strictness of the tracking of the TCP sessions of each VPC */
+        flow_table.set_tcp_tracking(
+            config
+                .external()
+                .overlay()
+                .vpc_table()
+                .values()
+                .map(|vpc| (VpcDiscriminant::VNI(vpc.vni()), vpc.tcp_tracking())),
+        );
+
         if genid == ExternalConfig::BLANK_GENID {
             /* apply config with VPC manager */
-            vpc_mgr.apply_config(internal, genid).await?;
+            vpc_mgr
+                .apply_config(internal, genid, interface_view)
+                .await?;
+            publish_tap_interfaces(internal, self.proc_params.tap_interfaces.as_ref());
             info!("Successfully applied config for genid {genid}");
             return Ok(());
         }
 
+        /* build all the tables of the pipeline, so that none is published unless all build */
+        let staged = StagedTables::build(&config)?;
+
         /* lock the CPI to prevent updates on the routing db */
         let _guard = router_ctl
             .lock()
@@ -627,38 +820,24 @@ impl ConfigProcessor {
             .map_err(|_| ConfigError::InternalFailure("Could not lock the CPI".to_string()))?;
 
         /* apply config with VPC manager */
-        vpc_mgr.apply_config(internal, genid).await?;
+        vpc_mgr
+            .apply_config(internal, genid, &self.proc_params.interface_view)
+            .await?;
+
+        /* let the driver attach to the tap interfaces that were created */
+        publish_tap_interfaces(internal, self.proc_params.tap_interfaces.as_ref());
 
         /* get vrf interfaces from kernel and build a hashmap keyed by name */
         let kernel_vrfs = vpc_mgr.get_kernel_vrfs().await?;
 
-        let overlay = config.external().overlay();
-
-        /* apply flow filtering config */
-        apply_flow_filtering_config(overlay, flowfilterw)?;
-
-        /* apply ACL filter config */
-        apply_acl_filter_config(overlay, aclfilterw)?;
-
-        /* apply static NAT config */
-        apply_static_nat_config(overlay.vpc_table(), nattablesw)?;
-
-        /* apply masquerade config */
-        apply_masquerade_config(
-            overlay.vpc_table(),
-            flow_table.as_ref(),
-            natallocatorw,
-            genid,
-        );
-
-        /* apply port-forwarding config */
-        apply_port_forwarding_config(overlay.vpc_table(), portfw_w)?;
-
-        /* update stats mappings and seed names to the stats store */
-        let _ = update_stats_vpc_mappings(&config, vpcmapw);
-
-        /* apply config in router */
-        apply_router_config(&kernel_vrfs, config.clone(), router_ctl).await?;
+        /* apply config in router, then publish the tables of the pipeline: flow filtering, ACLs,
+         * NAT, port-forwarding, QoS, mirroring and the mappings for per-vpc stats, all in one
+         * application. None is published if the router fails to apply the config. */
+        let router_config = apply_router_config(&kernel_vrfs, config.clone(), router_ctl);
+        self.proc_params
+            .table_events
+            .apply(staged.genid(), staged.into_deltas(), router_config)
+            .await?;
 
         /* update the pipeline generation id, iff config was applied */
         self.proc_params.pipeline_data.set_genid(genid);
@@ -0,0 +1,231 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Allocation of the kernel route tables of the VRFs of VPCs.
+//!
+//! VPCs may request a specific route table. All others get one from a configured range, which
+//! user-specified tables may not overlap. Allocations are kept across configurations, and
+//! persisted in the state store so that VRFs are not renumbered when the dataplane restarts.
+
+use args::RouteTableRange;
+use config::ConfigError;
+use config::external::overlay::vpc::{ValidatedVpcTable, VpcId};
+use kvstore::Namespace;
+use net::route::RouteTableId;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[allow(unused)]
+use tracing::{debug, error, info, warn};
+
+/// The route table of the VRF of each VPC, by VPC id
+pub(crate) type RouteTableIds = BTreeMap<VpcId, RouteTableId>;
+
+/// Allocator of the route tables of VPCs
+pub(crate) struct RouteTableAllocator {
+    range: RouteTableRange,
+    state: Option<Namespace>,
+    allocated: RouteTableIds,
+}
+
+impl RouteTableAllocator {
+    /// Create an allocator for the given range, restoring the allocations persisted in `state`.
+    pub(crate) fn new(range: RouteTableRange, state: Option<Namespace>) -> Self {
+        let mut allocator = Self {
+            range,
+            state,
+            allocated: RouteTableIds::new(),
+        };
+        allocator.load();
+        allocator
+    }
+
+    fn in_range(&self, table_id: RouteTableId) -> bool {
+        (self.range.first..=self.range.last).contains(&u32::from(table_id))
+    }
+
+    /// Determine the route tables of the VPCs in a configuration. VPCs keep the table they were
+    /// allocated previously, if any. Allocations only take effect once committed.
+    pub(crate) fn allocate(&self, vpcs: &ValidatedVpcTable) -> Result<RouteTableIds, ConfigError> {
+        let mut tables = RouteTableIds::new();
+        let mut used = BTreeSet::new();
+
+        // user-specified tables, and tables allocated previously, are kept
+        for vpc in vpcs.values() {
+            let table_id = match vpc.table_id() {
+                Some(table_id) if self.in_range(table_id) => {
+                    return Err(ConfigError::Invalid(format!(
+                        "Route table {table_id} of VPC {} lies in the range {}-{} reserved for allocation",
+                        vpc.name(),
+                        self.range.first,
+                        self.range.last
+                    )));
+                }
+                Some(table_id) => table_id,
+                None => match self.allocated.get(vpc.id()) {
+                    Some(table_id) if self.in_range(*table_id) => *table_id,
+                    _ => continue,
+                },
+            };
+            used.insert(u32::from(table_id));
+            tables.insert(vpc.id().clone(), table_id);
+        }
+
+        // the others get the lowest free table of the range
+        let mut candidates =
+            (self.range.first..=self.range.last).filter(|table_id| !used.contains(table_id));
+        for vpc in vpcs.values().filter(|vpc| !tables.contains_key(vpc.id())) {
+            let table_id = candidates
+                .next()
+                .and_then(|table_id| RouteTableId::try_from(table_id).ok())
+                .ok_or_else(|| ConfigError::NoRouteTableAvailable(vpc.name().to_string()))?;
+            debug!("Allocated route table {table_id} to VPC {}", vpc.name());
+            tables.insert(vpc.id().clone(), table_id);
+        }
+        Ok(tables)
+    }
+
+    /// Record the route tables of an applied configuration, and persist them.
+    pub(crate) fn commit(&mut self, tables: RouteTableIds) {
+        if tables == self.allocated {
+            return;
+        }
+        self.allocated = tables;
+        self.save();
+    }
+
+    fn load(&mut self) {
+        let Some(state) = &self.state else {
+            return;
+        };
+        let persisted = match state.entries() {
+            Ok(persisted) => persisted,
+            Err(e) => {
+                warn!("Failed to read route tables from state store: {e}");
+                return;
+            }
+        };
+        for (vpc_id, table_id) in persisted {
+            let table_id = std::str::from_utf8(&table_id)
+                .ok()
+                .and_then(|table_id| table_id.parse::<u32>().ok())
+                .and_then(|table_id| RouteTableId::try_from(table_id).ok());
+            match (VpcId::try_from(vpc_id.as_str()), table_id) {
+                (Ok(vpc_id), Some(table_id)) => {
+                    self.allocated.insert(vpc_id, table_id);
+                }
+                _ => warn!("Ignoring bad route table allocation of VPC {vpc_id}"),
+            }
+        }
+        info!(
+            "Restored {} route table allocations from state store",
+            self.allocated.len()
+        );
+    }
+
+    fn save(&self) {
+        let Some(state) = &self.state else {
+            return;
+        };
+        // tables are stored in decimal, to be readable when inspecting the store
+        let persisted = self
+            .allocated
+            .iter()
+            .map(|(vpc_id, table_id)| {
+                let table_id = u32::from(*table_id).to_string();
+                (vpc_id.to_string(), table_id.into_bytes())
+            })
+            .collect();
+        if let Err(e) = state.replace(persisted) {
+            warn!("Failed to persist route tables to state store: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use concurrency::sync::Arc;
+    use config::external::overlay::vpc::{Vpc, VpcTable};
+    use kvstore::{KvStore, MemoryStore};
+
+    fn vpc_table(vpcs: &[(&str, &str, u32, Option<u32>)]) -> ValidatedVpcTable {
+        let mut table = VpcTable::new();
+        for (name, id, vni, table_id) in vpcs {
+            let mut vpc = Vpc::new(name, id, *vni).unwrap();
+            if let Some(table_id) = table_id {
+                vpc.set_table_id(RouteTableId::try_from(*table_id).unwrap());
+            }
+            table.add(vpc).unwrap();
+        }
+        table.validate().unwrap()
+    }
+
+    fn table_of(tables: &RouteTableIds, id: &str) -> u32 {
+        u32::from(tables[&VpcId::try_from(id).unwrap()])
+    }
+
+    const RANGE: RouteTableRange = RouteTableRange {
+        first: 1000,
+        last: 1002,
+    };
+
+    #[test]
+    fn test_allocation() {
+        let mut allocator = RouteTableAllocator::new(RANGE, None);
+        let vpcs = vpc_table(&[
+            ("VPC-1", "AAAAA", 100, None),
+            ("VPC-2", "BBBBB", 200, Some(300)),
+        ]);
+        let tables = allocator.allocate(&vpcs).unwrap();
+        assert_eq!(table_of(&tables, "AAAAA"), 1000);
+        assert_eq!(table_of(&tables, "BBBBB"), 300);
+        allocator.commit(tables);
+
+        // VPC-1 keeps its table, even though a lower one got freed
+        let vpcs = vpc_table(&[
+            ("VPC-0", "ZZZZZ", 50, None),
+            ("VPC-1", "AAAAA", 100, None),
+            ("VPC-3", "CCCCC", 300, None),
+        ]);
+        let tables = allocator.allocate(&vpcs).unwrap();
+        assert_eq!(table_of(&tables, "AAAAA"), 1000);
+        assert_eq!(table_of(&tables, "ZZZZZ"), 1001);
+        assert_eq!(table_of(&tables, "CCCCC"), 1002);
+        allocator.commit(tables);
+
+        // the range is exhausted
+        let vpcs = vpc_table(&[
+            ("VPC-0", "ZZZZZ", 50, None),
+            ("VPC-1", "AAAAA", 100, None),
+            ("VPC-3", "CCCCC", 300, None),
+            ("VPC-4", "DDDDD", 400, None),
+        ]);
+        assert_eq!(
+            allocator.allocate(&vpcs),
+            Err(ConfigError::NoRouteTableAvailable("VPC-4".to_string()))
+        );
+
+        // user-specified tables may not lie in the range
+        let vpcs = vpc_table(&[("VPC-1", "AAAAA", 100, Some(1001))]);
+        assert!(matches!(
+            allocator.allocate(&vpcs),
+            Err(ConfigError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_persistence() {
+        let store: Arc<dyn KvStore> = Arc::new(MemoryStore::default());
+        let state = || Some(Namespace::new(store.clone(), "route-tables").unwrap());
+
+        let mut allocator = RouteTableAllocator::new(RANGE, state());
+        let vpcs = vpc_table(&[("VPC-1", "AAAAA", 100, None), ("VPC-2", "BBBBB", 200, None)]);
+        let tables = allocator.allocate(&vpcs).unwrap();
+        allocator.commit(tables.clone());
+
+        // a restarted allocator hands out the same tables, whatever the order of the VPCs
+        let restored = RouteTableAllocator::new(RANGE, state());
+        let vpcs = vpc_table(&[("VPC-0", "BBBBB", 200, None), ("VPC-1", "AAAAA", 100, None)]);
+        assert_eq!(restored.allocate(&vpcs).unwrap(), tables);
+    }
+}
@@ -0,0 +1,108 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Staging of the tables of the pipeline.
+//!
+//! Applying a config used to build and publish the tables of the pipeline one after the other,
+//! so that a table failing to build left the pipeline with the tables published before it from
+//! the new config and those after it from the old one. Instead, all the tables of a config are
+//! first built into [`StagedTables`], which is fallible and has no effect on the pipeline, and
+//! then handed to the table writers as the deltas of one application, which they publish
+//! together once the router applied the config. A config failing to build a table, or failing to
+//! apply in the router, is abandoned, leaving all the tables of the old config in place.
+//!
+//! The rules of the pre-filter of the kernel driver, compiled from the ACLs, are published along
+//! with the tables.
+
+use acl_filter::{AclFilterContext, PrefilterRules};
+use config::{ConfigError, GenId, ValidatedGwConfig};
+use flow_filter::FlowFilterTable;
+use mirror::MirrorTable;
+use nat::masquerade::MasqueradeConfig;
+use nat::portfw::{ValidatedRuleset, build_port_forwarding_configuration};
+use nat::static_nat::setup::build_nat_configuration;
+use nat::static_nat::setup::tables::NatTables;
+use qos::QosTable;
+use stats::VpcMapName;
+use tracing::debug;
+use vpcmap::VpcDiscriminant;
+use vpcmap::map::VpcMap;
+
+use crate::processor::table_events::TableDelta;
+
+/// The tables of the pipeline built for a config, not published yet
+pub(crate) struct StagedTables {
+    genid: GenId,
+    vpcmap: VpcMap<VpcMapName>,
+    flow_filter: FlowFilterTable,
+    acl_filter: AclFilterContext,
+    static_nat: NatTables,
+    masquerade: MasqueradeConfig,
+    port_forwarding: ValidatedRuleset,
+    qos: QosTable,
+    mirror: MirrorTable,
+    prefilter: PrefilterRules,
+}
+
+/// Build the mapping of the VNIs of the VPCs to their names, for per-VPC statistics
+fn build_vpc_map(config: &ValidatedGwConfig) -> VpcMap<VpcMapName> {
+    let mut vpcmap = VpcMap::<VpcMapName>::new();
+    for vpc in config.external().overlay().vpc_table().values() {
+        let disc = VpcDiscriminant::VNI(vpc.vni());
+        let map = VpcMapName::new(disc, vpc.name());
+        vpcmap.add(disc, map).unwrap_or_else(|_| unreachable!());
+    }
+    vpcmap
+}
+
+impl StagedTables {
+    /// Build all the tables of the pipeline for `config`, without publishing any.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of the first table failing to build.
+    pub(crate) fn build(config: &ValidatedGwConfig) -> Result<Self, ConfigError> {
+        let external = config.external();
+        let overlay = external.overlay();
+        let vpc_table = overlay.vpc_table();
+        let ruleset = build_port_forwarding_configuration(vpc_table, external.session_table())?;
+        let staged = Self {
+            genid: config.genid(),
+            vpcmap: build_vpc_map(config),
+            flow_filter: FlowFilterTable::build_from_overlay(overlay)?,
+            acl_filter: AclFilterContext::try_from(overlay)?,
+            static_nat: build_nat_configuration(vpc_table)?,
+            masquerade: MasqueradeConfig::new(vpc_table, config.genid())
+                .set_randomize(true)
+                .set_session_table(external.session_table())
+                .set_partitions(external.gwgroups(), external.gwname()),
+            port_forwarding: ValidatedRuleset::new(ruleset)
+                .map_err(|e| ConfigError::PortForwarding(e.to_string()))?,
+            qos: QosTable::build(external.qos(), vpc_table)?,
+            mirror: MirrorTable::build(external.mirror(), vpc_table)?,
+            prefilter: PrefilterRules::build(overlay, external.underlay().vtep.as_ref()),
+        };
+        debug!("Staged the tables of config {}", staged.genid);
+        Ok(staged)
+    }
+
+    /// The generation of the config the tables were built for
+    pub(crate) fn genid(&self) -> GenId {
+        self.genid
+    }
+
+    /// The tables, as deltas of the application of the config, in the order they get published
+    pub(crate) fn into_deltas(self) -> [TableDelta; 9] {
+        [
+            TableDelta::FlowFilter(self.flow_filter),
+            TableDelta::AclFilter(self.acl_filter),
+            TableDelta::StaticNat(self.static_nat),
+            TableDelta::Masquerade(self.masquerade),
+            TableDelta::PortForwarding(self.port_forwarding),
+            TableDelta::Qos(self.qos),
+            TableDelta::Mirror(self.mirror),
+            TableDelta::VpcMap(self.vpcmap),
+            TableDelta::Prefilter(self.prefilter),
+        ]
+    }
+}
@@ -0,0 +1,379 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Ordered events between the config processor and the writers of the tables of the pipeline.
+//!
+//! The config processor does not publish the tables of a config itself. It sends, over one
+//! channel, the [`TableEvent`]s of their application: a [`TableEvent::Begin`], a
+//! [`TableEvent::Delta`] for each table replaced, and then a [`TableEvent::Commit`] once the rest
+//! of the config was applied, or a [`TableEvent::Abort`] if that failed. The [`TableWriters`],
+//! which own the writers of all the tables, consume the events in order. The deltas of an
+//! application are held until it commits, and are then published all together while the commit
+//! guard of the [`PipelineData`] is held, so that no batch of packets is processed with some tables
+//! of a generation and some of another. An application aborted, or begun again before it commits,
+//! is dropped, leaving the tables published before in place.
+//!
+//! Every commit gets the next [`CommitEpoch`], exported as the `config_commit_epoch` metric, so
+//! that the stats tell which set of tables the pipeline runs with.
+
+use acl_filter::{AclFilterContext, AclFilterContextWriter, PrefilterRules};
+use concurrency::sync::Arc;
+use config::{ConfigError, GenId};
+use flow_entry::flow_table::FlowTable;
+use flow_filter::{FlowFilterTable, FlowFilterTableWriter};
+use metrics::{Gauge, Unit};
+use mirror::{MirrorTable, MirrorTableWriter};
+use nat::masquerade::{MasqueradeConfig, NatAllocatorWriter};
+use nat::portfw::{PortFwTableWriter, ValidatedRuleset};
+use nat::static_nat::NatTablesWriter;
+use nat::static_nat::setup::tables::NatTables;
+use pipeline::PipelineData;
+use qos::{QosTable, QosTableWriter};
+use stats::{MetricSpec, Register, VpcMapName};
+use tokio::sync::{mpsc, oneshot, watch};
+use tracing::{debug, info, warn};
+use vpcmap::map::{VpcMap, VpcMapWriter};
+
+/// The new content of a table of the pipeline
+pub(crate) enum TableDelta {
+    FlowFilter(FlowFilterTable),
+    AclFilter(AclFilterContext),
+    StaticNat(NatTables),
+    Masquerade(MasqueradeConfig),
+    PortForwarding(ValidatedRuleset),
+    Qos(QosTable),
+    Mirror(MirrorTable),
+    VpcMap(VpcMap<VpcMapName>),
+    Prefilter(PrefilterRules),
+}
+
+impl TableDelta {
+    /// The name of the table
+    fn table(&self) -> &'static str {
+        match self {
+            TableDelta::FlowFilter(_) => "flow filter",
+            TableDelta::AclFilter(_) => "ACL filter",
+            TableDelta::StaticNat(_) => "static NAT",
+            TableDelta::Masquerade(_) => "masquerade",
+            TableDelta::PortForwarding(_) => "port forwarding",
+            TableDelta::Qos(_) => "QoS",
+            TableDelta::Mirror(_) => "mirror",
+            TableDelta::VpcMap(_) => "VPC map",
+            TableDelta::Prefilter(_) => "pre-filter",
+        }
+    }
+}
+
+/// An event of the application of the tables of a config
+pub(crate) enum TableEvent {
+    /// The application of the tables of a config begins
+    Begin(GenId),
+    /// A table of the config being applied
+    Delta(TableDelta),
+    /// All the tables of the config were sent: publish them, and reply with the commit epoch
+    Commit(GenId, oneshot::Sender<u64>),
+    /// The config failed to apply: drop its tables
+    Abort(GenId),
+}
+
+/// The sending end of the channel of the [`TableEvent`]s consumed by the [`TableWriters`]
+#[derive(Clone)]
+pub struct TableEventSender {
+    tx: mpsc::UnboundedSender<TableEvent>,
+}
+
+impl TableEventSender {
+    /// Apply `deltas`, the tables of config `genid`, once `before_commit`, the application of
+    /// the rest of the config, succeeded, and wait for them to be published. None of the tables
+    /// are published if `before_commit` fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error of `before_commit`, or fails if the table writers are gone, or did not
+    /// commit the tables.
+    pub(crate) async fn apply(
+        &self,
+        genid: GenId,
+        deltas: impl IntoIterator<Item = TableDelta>,
+        before_commit: impl Future<Output = Result<(), ConfigError>>,
+    ) -> Result<u64, ConfigError> {
+        let staged = self.stage(genid, deltas)?;
+        before_commit.await?;
+        staged.commit().await
+    }
+
+    /// Send `deltas`, the tables of config `genid`, to the table writers. They hold them until
+    /// the returned [`StagedApply`] is committed, and drop them if it is dropped instead.
+    fn stage(
+        &self,
+        genid: GenId,
+        deltas: impl IntoIterator<Item = TableDelta>,
+    ) -> Result<StagedApply, ConfigError> {
+        self.tx.send(TableEvent::Begin(genid)).map_err(|_| gone())?;
+        let staged = StagedApply {
+            tx: self.tx.clone(),
+            genid,
+            committed: false,
+        };
+        for delta in deltas {
+            self.tx.send(TableEvent::Delta(delta)).map_err(|_| gone())?;
+        }
+        Ok(staged)
+    }
+}
+
+fn gone() -> ConfigError {
+    ConfigError::InternalFailure("The table writers are gone".to_string())
+}
+
+/// The tables of a config sent to the table writers, not published yet. They are aborted if
+/// this is dropped without being committed.
+struct StagedApply {
+    tx: mpsc::UnboundedSender<TableEvent>,
+    genid: GenId,
+    committed: bool,
+}
+
+impl StagedApply {
+    /// Publish the tables, and wait for them to be published.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the table writers are gone, or did not commit the tables.
+    async fn commit(mut self) -> Result<u64, ConfigError> {
+        let genid = self.genid;
+        let (done, committed) = oneshot::channel();
+        self.committed = true;
+        self.tx
+            .send(TableEvent::Commit(genid, done))
+            .map_err(|_| gone())?;
+        committed.await.map_err(|_| {
+            ConfigError::InternalFailure(format!("The tables of config {genid} were not committed"))
+        })
+    }
+}
+
+impl Drop for StagedApply {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = self.tx.send(TableEvent::Abort(self.genid));
+        }
+    }
+}
+
+/// The number of sets of tables published, since start
+#[derive(Default)]
+pub(crate) struct CommitEpoch {
+    epoch: u64,
+    /// The metric exporting the epoch, registered upon the first commit
+    gauge: Option<Gauge>,
+}
+
+impl CommitEpoch {
+    fn advance(&mut self, genid: GenId) -> u64 {
+        self.epoch += 1;
+        let gauge = self.gauge.get_or_insert_with(|| {
+            MetricSpec::new("config_commit_epoch", Unit::Count, vec![])
+                .register()
+                .metric
+        });
+        #[allow(clippy::cast_precision_loss)]
+        gauge.set(self.epoch as f64);
+        info!(
+            "Committed the tables of config {genid} as epoch {}",
+            self.epoch
+        );
+        self.epoch
+    }
+}
+
+/// The writers of the tables of the pipeline, consuming the [`TableEvent`]s of the config
+/// processor
+pub struct TableWriters {
+    pub vpcmapw: VpcMapWriter<VpcMapName>,
+    pub flowfilterw: FlowFilterTableWriter,
+    pub aclfilterw: AclFilterContextWriter,
+    pub nattablesw: NatTablesWriter,
+    pub natallocatorw: NatAllocatorWriter,
+    pub portfw_w: PortFwTableWriter,
+    pub qosw: QosTableWriter,
+    pub mirrorw: MirrorTableWriter,
+    /// publisher of the rules of the pre-filter of the kernel driver, if any
+    pub prefilter: Option<watch::Sender<PrefilterRules>>,
+    /// the flow table, whose flows the masquerade allocator is updated with
+    pub flow_table: Arc<FlowTable>,
+    /// the data of the pipeline, whose commit guard is held while publishing
+    pub pipeline_data: Arc<PipelineData>,
+}
+
+/// The tables of a config received since it began
+struct PendingApply {
+    genid: GenId,
+    deltas: Vec<TableDelta>,
+}
+
+impl TableWriters {
+    /// Consume the table events sent with the returned sender, in a task spawned on `handle`.
+    #[must_use]
+    pub fn start(self, handle: &tokio::runtime::Handle) -> TableEventSender {
+        let (tx, rx) = mpsc::unbounded_channel();
+        handle.spawn(self.run(rx));
+        TableEventSender { tx }
+    }
+
+    async fn run(mut self, mut rx: mpsc::UnboundedReceiver<TableEvent>) {
+        let mut epoch = CommitEpoch::default();
+        let mut pending: Option<PendingApply> = None;
+        while let Some(event) = rx.recv().await {
+            match event {
+                TableEvent::Begin(genid) => {
+                    let begun = PendingApply {
+                        genid,
+                        deltas: vec![],
+                    };
+                    if let Some(dropped) = pending.replace(begun) {
+                        warn!(
+                            "Dropping the tables of config {}, which did not commit",
+                            dropped.genid
+                        );
+                    }
+                }
+                TableEvent::Delta(delta) => match pending.as_mut() {
+                    Some(pending) => pending.deltas.push(delta),
+                    None => warn!("Ignoring the {} table of no config", delta.table()),
+                },
+                TableEvent::Commit(genid, done) => match pending.take() {
+                    Some(pending) if pending.genid == genid => {
+                        self.publish(pending.deltas);
+                        let _ = done.send(epoch.advance(genid));
+                    }
+                    _ => warn!("Not committing the tables of config {genid}, which did not begin"),
+                },
+                TableEvent::Abort(genid) => {
+                    if pending
+                        .as_ref()
+                        .is_some_and(|pending| pending.genid == genid)
+                    {
+                        pending = None;
+                        info!("Dropped the tables of config {genid}, which failed to apply");
+                    }
+                }
+            }
+        }
+        debug!("Table events channel closed");
+    }
+
+    /// Publish `deltas`, with no batch of packets processed meanwhile.
+    fn publish(&mut self, deltas: Vec<TableDelta>) {
+        let _commit = self.pipeline_data.commit_guard();
+        for delta in deltas {
+            match delta {
+                TableDelta::FlowFilter(table) => self.flowfilterw.update_flow_filter_table(table),
+                TableDelta::AclFilter(context) => self.aclfilterw.store(context),
+                TableDelta::StaticNat(tables) => self.nattablesw.update_nat_tables(tables),
+                TableDelta::Masquerade(config) => self
+                    .natallocatorw
+                    .update_nat_allocator(config, &self.flow_table),
+                TableDelta::PortForwarding(ruleset) => self.portfw_w.publish_ruleset(ruleset),
+                TableDelta::Qos(table) => self.qosw.update_qos_table(table),
+                TableDelta::Mirror(table) => self.mirrorw.update_mirror_table(table),
+                TableDelta::VpcMap(map) => self.vpcmapw.set_map(map),
+                TableDelta::Prefilter(rules) => {
+                    if let Some(prefilter) = &self.prefilter {
+                        prefilter.send_replace(rules);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn table_writers(prefilter: watch::Sender<PrefilterRules>) -> TableWriters {
+        TableWriters {
+            vpcmapw: VpcMapWriter::new(),
+            flowfilterw: FlowFilterTableWriter::new(),
+            aclfilterw: AclFilterContextWriter::new(),
+            nattablesw: NatTablesWriter::new(),
+            natallocatorw: NatAllocatorWriter::new(),
+            portfw_w: PortFwTableWriter::new(),
+            qosw: QosTableWriter::new(),
+            mirrorw: MirrorTableWriter::new(),
+            prefilter: Some(prefilter),
+            flow_table: Arc::new(FlowTable::new(1)),
+            pipeline_data: Arc::new(PipelineData::default()),
+        }
+    }
+
+    fn router_failure() -> ConfigError {
+        ConfigError::InternalFailure("Router config error".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_table_events_not_published_on_failure() {
+        let (prefilter, mut rules) = watch::channel(PrefilterRules::default());
+        let events = table_writers(prefilter).start(&tokio::runtime::Handle::current());
+        let deltas = || [TableDelta::Prefilter(PrefilterRules::default())];
+
+        // the router failing to apply the config, none of its tables is published
+        let result = events
+            .apply(1, deltas(), async { Err(router_failure()) })
+            .await;
+        assert!(matches!(result, Err(ConfigError::InternalFailure(_))));
+
+        // the events are consumed in order: once a later config is committed, the failed one
+        // was dropped
+        let epoch = events
+            .apply(2, Vec::<TableDelta>::new(), async { Ok(()) })
+            .await;
+        assert_eq!(epoch.unwrap(), 1);
+        assert!(!rules.has_changed().unwrap());
+
+        let epoch = events.apply(3, deltas(), async { Ok(()) }).await;
+        assert_eq!(epoch.unwrap(), 2);
+        assert!(rules.has_changed().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_table_events_apply_begun_again() {
+        let (prefilter, mut rules) = watch::channel(PrefilterRules::default());
+        let events = table_writers(prefilter).start(&tokio::runtime::Handle::current());
+        let deltas = || [TableDelta::Prefilter(PrefilterRules::default())];
+
+        // a config whose application begins again before it commits is dropped
+        let first = events.stage(1, deltas()).unwrap();
+        let second = events.stage(2, Vec::<TableDelta>::new()).unwrap();
+        assert_eq!(second.commit().await.unwrap(), 1);
+        assert!(!rules.has_changed().unwrap());
+        assert!(matches!(
+            first.commit().await,
+            Err(ConfigError::InternalFailure(_))
+        ));
+        assert!(!rules.has_changed().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_table_events_abort_of_other_config() {
+        let (prefilter, mut rules) = watch::channel(PrefilterRules::default());
+        let events = table_writers(prefilter).start(&tokio::runtime::Handle::current());
+
+        // deltas of no config are ignored
+        events
+            .tx
+            .send(TableEvent::Delta(TableDelta::Prefilter(
+                PrefilterRules::default(),
+            )))
+            .unwrap();
+
+        // aborting another config leaves the one being applied alone
+        let staged = events
+            .stage(2, [TableDelta::Prefilter(PrefilterRules::default())])
+            .unwrap();
+        events.tx.send(TableEvent::Abort(1)).unwrap();
+        assert_eq!(staged.commit().await.unwrap(), 1);
+        assert!(rules.has_changed().unwrap());
+    }
+}
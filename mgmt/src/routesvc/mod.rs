@@ -0,0 +1,144 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! gRPC endpoint streaming snapshots of the routes of the dataplane.
+//!
+//! The `GetRoutes` method of the `dataplane.routing.v1.Routing` service, defined in
+//! `mgmt/proto/routing.proto`, streams the routes of the FIB of each VRF, and optionally of its
+//! RIB, with their next-hops, encapsulations and egress interfaces, so that external tooling can
+//! diff the forwarding state of the dataplane against the view of FRR. The snapshot is taken by
+//! the router at once, and streamed in pages so that full tables fit in gRPC messages.
+//!
+//! The method is read-only, and is also served on the observer endpoint.
+
+mod proto;
+
+use std::pin::Pin;
+
+use futures::Stream;
+use routing::{RouteSnapshotRequest, RouterCtlSender};
+use tonic::{Request, Response, Status};
+use tracing::debug;
+
+use proto::routing_server::Routing;
+pub(crate) use proto::routing_server::RoutingServer;
+use proto::{GetRoutesRequest, RoutesPage};
+
+type RoutesStream = Pin<Box<dyn Stream<Item = Result<RoutesPage, Status>> + Send>>;
+
+/// Number of routes in a page, if the client does not tell
+const DEFAULT_PAGE_SIZE: usize = 1000;
+/// Max number of routes in a page
+const MAX_PAGE_SIZE: usize = 10_000;
+
+/// The number of routes in each page, for a requested `page_size`
+fn page_size(page_size: u32) -> usize {
+    match usize::try_from(page_size) {
+        Ok(0) => DEFAULT_PAGE_SIZE,
+        Ok(size) => size.min(MAX_PAGE_SIZE),
+        Err(_) => MAX_PAGE_SIZE,
+    }
+}
+
+/// The routing service, which gets the snapshots from the router
+pub(crate) struct RoutingService {
+    router: RouterCtlSender,
+}
+
+impl RoutingService {
+    pub(crate) fn new(router: RouterCtlSender) -> Self {
+        Self { router }
+    }
+}
+
+#[tonic::async_trait]
+impl Routing for RoutingService {
+    type GetRoutesStream = RoutesStream;
+
+    async fn get_routes(
+        &self,
+        request: Request<GetRoutesRequest>,
+    ) -> Result<Response<RoutesStream>, Status> {
+        let request = request.into_inner();
+        debug!("Requested snapshot of routes: {request:?}");
+        let size = page_size(request.page_size);
+        let snapshot = RouteSnapshotRequest {
+            vrf: Some(request.vrf).filter(|vrf| !vrf.is_empty()),
+            rib: request.rib,
+        };
+        let vrfs = self
+            .router
+            .get_routes(snapshot)
+            .await
+            .map_err(|e| Status::unavailable(e.to_string()))?;
+        let pages = vrfs
+            .into_iter()
+            .flat_map(move |vrf| RoutesPage::paginate(&vrf, size))
+            .map(Ok);
+        let stream: RoutesStream = Box::pin(futures::stream::iter(pages));
+        Ok(Response::new(stream))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::proto::RoutesPage;
+    use super::{DEFAULT_PAGE_SIZE, MAX_PAGE_SIZE, page_size};
+    use lpm::prefix::Prefix;
+    use net::vxlan::Vni;
+    use routing::{FibRouteSnapshot, NhopAction, NhopSnapshot, VrfSnapshot};
+    use std::str::FromStr;
+
+    #[test]
+    fn page_sizes() {
+        assert_eq!(page_size(0), DEFAULT_PAGE_SIZE);
+        assert_eq!(page_size(10), 10);
+        assert_eq!(page_size(u32::MAX), MAX_PAGE_SIZE);
+    }
+
+    #[test]
+    fn routes_pages() {
+        let route = |prefix: &str| FibRouteSnapshot {
+            prefix: Prefix::from_str(prefix).unwrap(),
+            entries: vec![NhopSnapshot {
+                action: NhopAction::Drop,
+                ..Default::default()
+            }],
+        };
+        let mut vrf = VrfSnapshot {
+            name: "vpc-1".to_string(),
+            vrfid: 2,
+            vni: Some(Vni::new_checked(3000).unwrap()),
+            fib: vec![],
+            rib: vec![],
+        };
+        let pages = RoutesPage::paginate(&vrf, 2);
+        assert_eq!(pages.len(), 1);
+        assert!(pages[0].fib.is_empty());
+        assert_eq!(pages[0].vni, 3000);
+
+        vrf.fib = [
+            "10.0.0.0/24",
+            "10.0.1.0/24",
+            "10.0.2.0/24",
+            "::/0",
+            "10.0.3.0/24",
+        ]
+        .into_iter()
+        .map(route)
+        .collect();
+        let pages = RoutesPage::paginate(&vrf, 2);
+        assert_eq!(pages.len(), 3);
+        assert!(
+            pages
+                .iter()
+                .all(|page| page.vrf == "vpc-1" && page.vrfid == 2)
+        );
+        assert_eq!(pages[0].fib[1].prefix, "10.0.1.0/24");
+        assert_eq!(pages[1].fib[1].prefix, "::/0");
+        assert_eq!(pages[2].fib.len(), 1);
+        let entry = &pages[2].fib[0].entries[0];
+        assert_eq!(entry.action, "drop");
+        assert!(entry.egress.is_none());
+    }
+}
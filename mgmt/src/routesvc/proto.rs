@@ -0,0 +1,124 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! The messages and the service of the routing service, generated from
+//! `mgmt/proto/routing.proto`
+
+use routing::{
+    EgressSnapshot, FibRouteSnapshot, NhopAction, NhopSnapshot, RibNhopSnapshot, RibRouteSnapshot,
+    VrfSnapshot, VxlanSnapshot,
+};
+
+tonic::include_proto!("dataplane.routing.v1");
+
+/// The string of an optional value, empty if None
+fn or_empty<T: ToString>(value: Option<&T>) -> String {
+    value.map(ToString::to_string).unwrap_or_default()
+}
+
+impl From<&EgressSnapshot> for Egress {
+    fn from(egress: &EgressSnapshot) -> Self {
+        Self {
+            ifindex: egress.ifindex.map(u32::from).unwrap_or_default(),
+            ifname: egress.ifname.clone().unwrap_or_default(),
+            oper_state: or_empty(egress.oper_state.as_ref()),
+        }
+    }
+}
+
+impl From<&VxlanSnapshot> for Vxlan {
+    fn from(vxlan: &VxlanSnapshot) -> Self {
+        Self {
+            vni: vxlan.vni.as_u32(),
+            remote: vxlan.remote.to_string(),
+            source: or_empty(vxlan.source.as_ref()),
+            dmac: or_empty(vxlan.dmac.as_ref()),
+        }
+    }
+}
+
+impl From<&NhopSnapshot> for Nexthop {
+    fn from(nhop: &NhopSnapshot) -> Self {
+        let action = match nhop.action {
+            NhopAction::Forward => "forward",
+            NhopAction::Drop => "drop",
+            NhopAction::Local => "local",
+        };
+        Self {
+            action: action.to_string(),
+            address: or_empty(nhop.address.as_ref()),
+            egress: nhop.egress.as_ref().map(Egress::from),
+            vxlan: nhop.vxlan.as_ref().map(Vxlan::from),
+        }
+    }
+}
+
+impl From<&FibRouteSnapshot> for FibRoute {
+    fn from(route: &FibRouteSnapshot) -> Self {
+        Self {
+            prefix: route.prefix.to_string(),
+            entries: route.entries.iter().map(Nexthop::from).collect(),
+        }
+    }
+}
+
+impl From<&RibNhopSnapshot> for RibNexthop {
+    fn from(nhop: &RibNhopSnapshot) -> Self {
+        Self {
+            nexthop: Some(Nexthop::from(&nhop.nhop)),
+            vrfid: nhop.vrfid.unwrap_or_default(),
+            invalid: nhop.invalid,
+            resolved: nhop.resolved.iter().map(Nexthop::from).collect(),
+        }
+    }
+}
+
+impl From<&RibRouteSnapshot> for RibRoute {
+    fn from(route: &RibRouteSnapshot) -> Self {
+        Self {
+            prefix: route.prefix.to_string(),
+            origin: route.origin.to_string(),
+            distance: u32::from(route.distance),
+            metric: route.metric,
+            stale: route.stale,
+            nexthops: route.nhops.iter().map(RibNexthop::from).collect(),
+        }
+    }
+}
+
+impl RoutesPage {
+    /// An empty page for the routes of `vrf`
+    fn empty(vrf: &VrfSnapshot) -> Self {
+        Self {
+            vrf: vrf.name.clone(),
+            vrfid: vrf.vrfid,
+            vni: vrf.vni.map(|vni| vni.as_u32()).unwrap_or_default(),
+            fib: vec![],
+            rib: vec![],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.fib.len() + self.rib.len()
+    }
+
+    /// Split the routes of `vrf` in pages of at most `size` routes, those of the FIB first
+    pub(crate) fn paginate(vrf: &VrfSnapshot, size: usize) -> Vec<Self> {
+        let mut pages = vec![];
+        let mut page = Self::empty(vrf);
+        for route in &vrf.fib {
+            if page.len() >= size {
+                pages.push(std::mem::replace(&mut page, Self::empty(vrf)));
+            }
+            page.fib.push(FibRoute::from(route));
+        }
+        for route in &vrf.rib {
+            if page.len() >= size {
+                pages.push(std::mem::replace(&mut page, Self::empty(vrf)));
+            }
+            page.rib.push(RibRoute::from(route));
+        }
+        pages.push(page);
+        pages
+    }
+}
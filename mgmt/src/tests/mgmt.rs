@@ -15,6 +15,7 @@ pub mod test {
     use net::eth::mac::Mac;
     use net::interface::Mtu;
     use pipeline::PipelineData;
+    use std::collections::BTreeSet;
     use std::net::IpAddr;
     use std::net::Ipv4Addr;
     use std::str::FromStr;
@@ -28,7 +29,6 @@ pub mod test {
     };
     use config::external::underlay::Underlay;
 
-    use config::ExternalConfig;
     use config::internal::device::DeviceConfig;
     use config::internal::interfaces::interface::{
         IfEthConfig, IfVtepConfig, InterfaceConfig, InterfaceType,
@@ -36,17 +36,24 @@ pub mod test {
     use config::internal::routing::bgp::*;
     use config::internal::routing::ospf::{Ospf, OspfInterface, OspfNetwork};
     use config::internal::routing::vrf::VrfConfig;
+    use config::{ApplyId, ExternalConfig};
 
     use routing::Render;
 
     use crate::processor::confbuild::internal::build_internal_config;
     use crate::processor::proc::{ConfigProcessor, ConfigProcessorParams};
+    use crate::processor::route_tables::RouteTableAllocator;
+    use crate::processor::table_events::TableWriters;
+    use crate::vpc_manager::{InterfaceView, tap_interfaces};
+    use args::RouteTableRange;
     use concurrency::sync::Arc;
     use config::internal::status::DataplaneStatus;
     use flow_filter::FlowFilterTableWriter;
+    use mirror::MirrorTableWriter;
     use nat::masquerade::NatAllocatorWriter;
     use nat::portfw::PortFwTableWriter;
     use nat::static_nat::NatTablesWriter;
+    use qos::QosTableWriter;
     use routing::{Router, RouterParamsBuilder};
     use stats::VpcMapName;
     use stats::VpcStatsStore;
@@ -400,12 +407,32 @@ pub mod test {
             println!("\n{}\n{peering_table}", vpc_table.as_summary());
         }
         let bmp_config = None;
-        let internal =
-            build_internal_config(&validated_config, bmp_config).expect("Should succeed");
+        let table_ids = RouteTableAllocator::new(RouteTableRange::DEFAULT, None)
+            .allocate(validated_config.external().overlay().vpc_table())
+            .expect("Should succeed");
+        let internal = build_internal_config(&validated_config, bmp_config, &table_ids)
+            .expect("Should succeed");
         let rendered = internal.render(&validated_config.genid());
         println!("{rendered}");
     }
 
+    #[test]
+    fn check_tap_interfaces() {
+        let validated_config = sample_external_config()
+            .validate()
+            .expect("Config validation failed");
+        let table_ids = RouteTableAllocator::new(RouteTableRange::DEFAULT, None)
+            .allocate(validated_config.external().overlay().vpc_table())
+            .expect("Should succeed");
+        let internal =
+            build_internal_config(&validated_config, None, &table_ids).expect("Should succeed");
+        let taps: Vec<_> = tap_interfaces(&internal)
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(taps, ["eth0-tap", "eth1-tap", "eth2-tap"]);
+    }
+
     #[ignore = "temporarily disabled during vm test runner refactor"]
     #[n_vm::in_vm]
     #[tokio::test]
@@ -462,6 +489,12 @@ pub mod test {
         /* create port forwarding table */
         let portfw_w = PortFwTableWriter::new();
 
+        /* create QoS table for egress scheduling */
+        let qosw = QosTableWriter::new();
+
+        /* create mirror table */
+        let mirrorw = MirrorTableWriter::new();
+
         /* create VPC stats store (Arc) */
         let vpc_stats_store = VpcStatsStore::new();
 
@@ -471,30 +504,49 @@ pub mod test {
         /* flow table */
         let flow_table = Arc::from(FlowTable::new(16));
 
+        let rth = tokio::runtime::Handle::current();
+
+        /* start the writers of the tables of the pipeline */
+        let table_writers = TableWriters {
+            vpcmapw,
+            flowfilterw,
+            aclfilterw,
+            nattablesw,
+            natallocatorw,
+            portfw_w,
+            qosw,
+            mirrorw,
+            prefilter: None,
+            flow_table: flow_table.clone(),
+            pipeline_data: pipeline_data.clone(),
+        };
+
         /* build configuration of mgmt config processor */
         let processor_config = ConfigProcessorParams {
             router_ctl,
             pipeline_data,
             flow_table,
-            vpcmapw,
-            nattablesw,
-            natallocatorw,
-            flowfilterw,
-            aclfilterw,
-            portfw_w,
+            table_events: table_writers.start(&rth),
             vpc_stats_store,
             dp_status_r,
             bmp_options: None,
+            tap_interfaces: None,
+            interface_view: InterfaceView::new(),
+            route_table_range: RouteTableRange::DEFAULT,
+            state_store: None,
+            rollback_requests: None,
+            unmanaged_interfaces: BTreeSet::new(),
         };
 
-        let rth = tokio::runtime::Handle::current();
-
         /* start config processor to test the processing of a config. The processor embeds the
         config database . In this test, we don't use any channel to communicate the config. */
-        let (mut processor, _) = ConfigProcessor::new(processor_config, &rth);
+        let (mut processor, _) = ConfigProcessor::new(processor_config, &rth).unwrap();
 
         /* let the processor process the config */
-        match processor.process_incoming_config(external).await {
+        match processor
+            .process_incoming_config(external, None, ApplyId::next())
+            .await
+        {
             Ok(()) => {}
             Err(e) => {
                 error!("{e}");
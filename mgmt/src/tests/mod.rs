@@ -5,3 +5,5 @@
 
 #[cfg(test)]
 mod mgmt;
+#[cfg(test)]
+mod proto;
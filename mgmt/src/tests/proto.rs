@@ -0,0 +1,402 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Checks of the definitions of the gRPC services in `mgmt/proto` against their released ones.
+//!
+//! The definitions are parsed (only the subset of proto3 they use) and compared with the
+//! snapshots of the released ones in `mgmt/proto/golden`, to catch changes breaking existing
+//! clients: removed messages, fields or methods, and fields whose tag is reused or whose type
+//! changed. Adding messages, fields or methods is allowed. The snapshots are updated when a new
+//! version of the services is released.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A field of a message
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ProtoField {
+    pub(crate) name: String,
+    pub(crate) ty: String,
+    pub(crate) tag: u32,
+    pub(crate) repeated: bool,
+    /// The oneof the field is a member of, if any
+    pub(crate) oneof: Option<String>,
+}
+
+/// The request or response type of a method
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ProtoRpcType {
+    pub(crate) ty: String,
+    pub(crate) stream: bool,
+}
+
+impl std::fmt::Display for ProtoRpcType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.stream {
+            write!(f, "stream ")?;
+        }
+        write!(f, "{}", self.ty)
+    }
+}
+
+/// A method of a service
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct ProtoRpc {
+    pub(crate) request: ProtoRpcType,
+    pub(crate) response: ProtoRpcType,
+}
+
+/// The definitions of a proto file
+#[derive(Debug, Default)]
+pub(crate) struct ProtoFile {
+    pub(crate) package: String,
+    pub(crate) messages: BTreeMap<String, Vec<ProtoField>>,
+    /// The reserved tags of each message
+    pub(crate) reserved: BTreeMap<String, BTreeSet<u32>>,
+    /// The methods of the services, by path (`/package.Service/Method`)
+    pub(crate) methods: BTreeMap<String, ProtoRpc>,
+}
+
+/// Split the definitions in tokens, without the comments
+fn tokenize(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    for line in text.lines() {
+        let line = line.split("//").next().unwrap_or_default();
+        let mut current = String::new();
+        for c in line.chars() {
+            if c.is_whitespace() || "{}();=,".contains(c) {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+                if !c.is_whitespace() {
+                    tokens.push(c.to_string());
+                }
+            } else {
+                current.push(c);
+            }
+        }
+        if !current.is_empty() {
+            tokens.push(current);
+        }
+    }
+    tokens
+}
+
+struct Parser {
+    tokens: std::vec::IntoIter<String>,
+}
+
+impl Parser {
+    fn next(&mut self) -> String {
+        self.tokens
+            .next()
+            .expect("unexpected end of the definitions")
+    }
+
+    fn expect(&mut self, expected: &str) {
+        let token = self.next();
+        assert_eq!(token, expected, "unexpected token");
+    }
+
+    fn field(&mut self, first: String, oneof: Option<&str>) -> ProtoField {
+        let repeated = first == "repeated";
+        let ty = if repeated { self.next() } else { first };
+        let name = self.next();
+        self.expect("=");
+        let tag = self.next().parse().expect("invalid tag");
+        self.expect(";");
+        ProtoField {
+            name,
+            ty,
+            tag,
+            repeated,
+            oneof: oneof.map(str::to_string),
+        }
+    }
+
+    /// Parse the tags of a `reserved` statement. Reserved names are skipped.
+    fn reserved(&mut self, reserved: &mut BTreeSet<u32>) {
+        let mut previous = None;
+        loop {
+            let token = self.next();
+            match token.as_str() {
+                ";" => return,
+                "," => {}
+                "to" => {
+                    let first = previous.take().expect("invalid range");
+                    let last: u32 = self.next().parse().expect("invalid range");
+                    reserved.extend(first..=last);
+                }
+                name if name.starts_with('"') => {}
+                tag => {
+                    let tag = tag.parse().expect("invalid reserved tag");
+                    reserved.insert(tag);
+                    previous = Some(tag);
+                }
+            }
+        }
+    }
+
+    fn message(&mut self) -> (Vec<ProtoField>, BTreeSet<u32>) {
+        self.expect("{");
+        let mut fields = Vec::new();
+        let mut reserved = BTreeSet::new();
+        loop {
+            match self.next().as_str() {
+                "}" => return (fields, reserved),
+                "reserved" => self.reserved(&mut reserved),
+                "oneof" => {
+                    let oneof = self.next();
+                    self.expect("{");
+                    loop {
+                        let token = self.next();
+                        if token == "}" {
+                            break;
+                        }
+                        fields.push(self.field(token, Some(&oneof)));
+                    }
+                }
+                token => fields.push(self.field(token.to_string(), None)),
+            }
+        }
+    }
+
+    /// Parse the type of a request or response
+    fn rpc_type(&mut self) -> ProtoRpcType {
+        self.expect("(");
+        let mut ty = self.next();
+        let stream = ty == "stream";
+        if stream {
+            ty = self.next();
+        }
+        self.expect(")");
+        ProtoRpcType { ty, stream }
+    }
+
+    fn service(&mut self, file: &mut ProtoFile, service: &str) {
+        self.expect("{");
+        loop {
+            match self.next().as_str() {
+                "}" => return,
+                "rpc" => {
+                    let name = self.next();
+                    let request = self.rpc_type();
+                    self.expect("returns");
+                    let response = self.rpc_type();
+                    self.expect(";");
+                    let path = format!("/{}.{service}/{name}", file.package);
+                    file.methods.insert(path, ProtoRpc { request, response });
+                }
+                token => panic!("unexpected token {token} in service {service}"),
+            }
+        }
+    }
+}
+
+impl ProtoFile {
+    /// Parse the definitions of a proto file
+    pub(crate) fn parse(text: &str) -> Self {
+        let mut parser = Parser {
+            tokens: tokenize(text).into_iter(),
+        };
+        let mut file = ProtoFile::default();
+        while let Some(token) = parser.tokens.next() {
+            match token.as_str() {
+                "syntax" => {
+                    parser.expect("=");
+                    parser.next();
+                    parser.expect(";");
+                }
+                "package" => {
+                    file.package = parser.next();
+                    parser.expect(";");
+                }
+                "message" => {
+                    let name = parser.next();
+                    let (fields, reserved) = parser.message();
+                    file.reserved.insert(name.clone(), reserved);
+                    file.messages.insert(name, fields);
+                }
+                "service" => {
+                    let name = parser.next();
+                    parser.service(&mut file, &name);
+                }
+                token => panic!("unexpected token {token}"),
+            }
+        }
+        file
+    }
+
+    /// The changes from the `golden` definitions which break their existing clients. Fields may
+    /// only be removed if their tag is reserved, so that it is not reused.
+    pub(crate) fn breaking_changes(&self, golden: &ProtoFile) -> Vec<String> {
+        let mut changes = Vec::new();
+        if self.package != golden.package {
+            changes.push(format!(
+                "package {} renamed {}",
+                golden.package, self.package
+            ));
+        }
+        for (name, old_fields) in &golden.messages {
+            let Some(fields) = self.messages.get(name) else {
+                changes.push(format!("message {name} removed"));
+                continue;
+            };
+            let reserved = self.reserved.get(name);
+            for old in old_fields {
+                let Some(field) = fields.iter().find(|field| field.tag == old.tag) else {
+                    if !reserved.is_some_and(|reserved| reserved.contains(&old.tag)) {
+                        changes.push(format!("{name}.{} removed", old.name));
+                    }
+                    continue;
+                };
+                if field.name != old.name {
+                    let msg = format!(
+                        "{name}: tag {} of {} reused by {}",
+                        old.tag, old.name, field.name
+                    );
+                    changes.push(msg);
+                } else if field.ty != old.ty || field.repeated != old.repeated {
+                    changes.push(format!("{name}.{}: type changed", old.name));
+                } else if field.oneof != old.oneof {
+                    changes.push(format!("{name}.{}: oneof changed", old.name));
+                }
+            }
+            let old_reserved = golden.reserved.get(name);
+            for field in fields {
+                if old_reserved.is_some_and(|reserved| reserved.contains(&field.tag)) {
+                    let msg = format!(
+                        "{name}: reserved tag {} reused by {}",
+                        field.tag, field.name
+                    );
+                    changes.push(msg);
+                }
+            }
+        }
+        for (path, old) in &golden.methods {
+            match self.methods.get(path) {
+                None => changes.push(format!("method {path} removed")),
+                Some(rpc) if rpc.request != old.request => changes.push(format!(
+                    "method {path}: request changed from {} to {}",
+                    old.request, rpc.request
+                )),
+                Some(rpc) if rpc.response != old.response => changes.push(format!(
+                    "method {path}: response changed from {} to {}",
+                    old.response, rpc.response
+                )),
+                Some(_) => {}
+            }
+        }
+        changes
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Check that the definitions of a service don't break its released ones
+    fn check_compatible(current: &str, golden: &str) {
+        let changes = ProtoFile::parse(current).breaking_changes(&ProtoFile::parse(golden));
+        assert!(changes.is_empty(), "breaking changes: {changes:#?}");
+    }
+
+    #[test]
+    fn test_proto_compatible_with_golden() {
+        check_compatible(
+            include_str!("../../proto/config.proto"),
+            include_str!("../../proto/golden/config.proto"),
+        );
+        check_compatible(
+            include_str!("../../proto/events.proto"),
+            include_str!("../../proto/golden/events.proto"),
+        );
+        check_compatible(
+            include_str!("../../proto/routing.proto"),
+            include_str!("../../proto/golden/routing.proto"),
+        );
+    }
+
+    const GOLDEN: &str = "
+syntax = \"proto3\";
+package test;
+service Test {
+  rpc Get(Request) returns (stream Reply);
+}
+message Request {
+  string name = 1;
+  reserved 2, 4 to 5;
+  oneof filter {
+    uint32 vni = 3;
+  }
+}
+message Reply {
+  repeated string routes = 1;
+}
+";
+
+    fn breaking_changes(current: &str) -> Vec<String> {
+        ProtoFile::parse(current).breaking_changes(&ProtoFile::parse(GOLDEN))
+    }
+
+    #[test]
+    fn test_proto_breaking_changes() {
+        assert!(breaking_changes(GOLDEN).is_empty());
+
+        // new fields, messages and methods are fine, as is removing a field reserving its tag
+        let extended = GOLDEN
+            .replace("uint32 vni = 3;", "uint32 vni = 3;\n    string vpc = 6;")
+            .replace("string name = 1;", "reserved 1;")
+            .replace(
+                "service Test {",
+                "service Test {\n  rpc Put(Reply) returns (Reply);",
+            )
+            .replace("message Reply {", "message Empty {}\nmessage Reply {");
+        assert!(breaking_changes(&extended).is_empty(), "{extended}");
+
+        let cases = [
+            ("repeated string routes = 1;", "", "Reply.routes removed"),
+            (
+                "string name = 1;",
+                "bytes name = 1;",
+                "Request.name: type changed",
+            ),
+            ("repeated string", "string", "Reply.routes: type changed"),
+            (
+                "string name = 1;",
+                "string vpc = 1;",
+                "Request: tag 1 of name reused by vpc",
+            ),
+            (
+                "string name = 1;",
+                "string name = 1;\n  string vpc = 5;",
+                "Request: reserved tag 5",
+            ),
+            ("string name = 1;", "", "Request.name removed"),
+            (
+                "oneof filter {",
+                "oneof match {",
+                "Request.vni: oneof changed",
+            ),
+            ("message Reply", "message Routes", "message Reply removed"),
+            ("rpc Get", "rpc Fetch", "method /test.Test/Get removed"),
+            (
+                "stream Reply",
+                "Reply",
+                "response changed from stream Reply to Reply",
+            ),
+            (
+                "package test;",
+                "package test.v2;",
+                "package test renamed test.v2",
+            ),
+        ];
+        for (from, to, expected) in cases {
+            let current = GOLDEN.replace(from, to);
+            let changes = breaking_changes(&current);
+            assert!(
+                changes.iter().any(|change| change.contains(expected)),
+                "{expected}: {changes:?}"
+            );
+        }
+    }
+}
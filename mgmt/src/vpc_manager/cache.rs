@@ -0,0 +1,221 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! A cache of the kernel links, updated from netlink notifications.
+//!
+//! Dumping all the links of the kernel on each observation is costly on hosts with many
+//! interfaces. A [`LinkCache`] dumps them once and then applies the notifications of the link
+//! multicast group. The group is joined before the links are dumped, so no change is missed:
+//! the notifications received meanwhile are applied on top of the dump once it completes. Since
+//! each notification carries the full state of a link and they are received in order, replaying
+//! those older than the dump is harmless: the most recent one of each link wins.
+//!
+//! The cache counts the changes it applied in a generation, which observations record so that
+//! the changes made after an observation can be detected. The links are dumped again every
+//! [`RESYNC_PERIOD`], and whenever the notifications are lost, e.g. because the socket buffer
+//! overflowed.
+
+use concurrency::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use concurrency::sync::{Arc, Mutex, Weak};
+use futures::TryStreamExt;
+use interface_manager::interface::TryFromLinkMessage;
+use net::interface::{Interface, InterfaceIndex};
+use rtnetlink::packet_core::NetlinkPayload;
+use rtnetlink::packet_route::RouteNetlinkMessage;
+use rtnetlink::{Handle, MulticastGroup};
+use std::collections::BTreeMap;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+/// How often the links are dumped again, to recover from any drift of the cache
+const RESYNC_PERIOD: Duration = Duration::from_secs(60);
+
+/// How long to wait before subscribing again, if subscribing failed
+const RETRY_PERIOD: Duration = Duration::from_secs(1);
+
+/// A cache of the kernel links, seeded with a dump and updated from netlink notifications
+#[derive(Debug, Default)]
+pub(crate) struct LinkCache {
+    links: Mutex<BTreeMap<InterfaceIndex, Interface>>,
+    /// Whether the links reflect a dump and the notifications received since
+    seeded: AtomicBool,
+    /// Number of changes applied to the links
+    generation: AtomicU64,
+}
+
+/// Apply a netlink notification to the links. Returns true if the links changed.
+fn apply(links: &mut BTreeMap<InterfaceIndex, Interface>, msg: &RouteNetlinkMessage) -> bool {
+    match msg {
+        RouteNetlinkMessage::NewLink(message) => {
+            match Interface::try_from_link_message(message) {
+                Ok(interface) => {
+                    links.insert(interface.index, interface);
+                    true
+                }
+                Err(err) => {
+                    // the link is no longer one we can represent
+                    debug!("{err:?}");
+                    InterfaceIndex::try_new(message.header.index)
+                        .is_ok_and(|index| links.remove(&index).is_some())
+                }
+            }
+        }
+        RouteNetlinkMessage::DelLink(message) => InterfaceIndex::try_new(message.header.index)
+            .is_ok_and(|index| links.remove(&index).is_some()),
+        _ => false,
+    }
+}
+
+impl LinkCache {
+    /// Create a cache, whose links are maintained by a task spawned on the current tokio
+    /// runtime. The task stops once the cache is dropped.
+    pub(crate) fn spawn(handle: Arc<Handle>, runtime: &tokio::runtime::Handle) -> Arc<Self> {
+        let cache = Arc::new(Self::default());
+        runtime.spawn(Self::run(Arc::downgrade(&cache), handle));
+        cache
+    }
+
+    /// The number of changes applied to the links so far
+    pub(crate) fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    /// The links and the generation they belong to, unless the cache is not seeded
+    pub(crate) fn links(&self) -> Option<(Vec<Interface>, u64)> {
+        let links = self.links.lock();
+        if !self.seeded.load(Ordering::Acquire) {
+            return None;
+        }
+        Some((links.values().cloned().collect(), self.generation()))
+    }
+
+    fn bump(&self, changes: u64) {
+        self.generation.fetch_add(changes, Ordering::AcqRel);
+    }
+
+    /// Dump the links, replacing those cached
+    async fn seed(&self, handle: &Handle) -> Result<(), rtnetlink::Error> {
+        let mut links = BTreeMap::new();
+        let mut req = handle.link().get().execute();
+        while let Some(message) = req.try_next().await? {
+            match Interface::try_from_link_message(&message) {
+                Ok(interface) => {
+                    links.insert(interface.index, interface);
+                }
+                Err(err) => debug!("{err:?}"),
+            }
+        }
+        *self.links.lock() = links;
+        self.seeded.store(true, Ordering::Release);
+        self.bump(1);
+        Ok(())
+    }
+
+    /// Maintain the links of the cache until it is dropped
+    async fn run(cache: Weak<Self>, handle: Arc<Handle>) {
+        info!("Starting kernel link cache");
+        while cache.strong_count() > 0 {
+            let Ok((conn, _, mut messages)) =
+                rtnetlink::new_multicast_connection(&[MulticastGroup::Link])
+                    .inspect_err(|e| error!("Failed to subscribe to link notifications: {e}"))
+            else {
+                tokio::time::sleep(RETRY_PERIOD).await;
+                continue;
+            };
+            let conn = tokio::spawn(conn);
+            {
+                let Some(cache) = cache.upgrade() else { break };
+                if let Err(e) = cache.seed(&handle).await {
+                    warn!("Failed to dump kernel links: {e}");
+                    cache.seeded.store(false, Ordering::Release);
+                    conn.abort();
+                    tokio::time::sleep(RETRY_PERIOD).await;
+                    continue;
+                }
+            }
+            let resync = tokio::time::sleep(RESYNC_PERIOD);
+            tokio::pin!(resync);
+            loop {
+                tokio::select! {
+                    nlmsg = messages.recv() => {
+                        let Some(cache) = cache.upgrade() else { break };
+                        match nlmsg {
+                            Ok((msg, _)) => {
+                                let (_hdr, payload) = msg.into_parts();
+                                if let NetlinkPayload::InnerMessage(msg) = payload
+                                    && apply(&mut cache.links.lock(), &msg)
+                                {
+                                    cache.bump(1);
+                                }
+                            }
+                            Err(e) => {
+                                warn!("Lost kernel link notifications: {e}");
+                                cache.seeded.store(false, Ordering::Release);
+                                break;
+                            }
+                        }
+                    }
+                    () = &mut resync => break,
+                }
+            }
+            conn.abort();
+            debug!("Dumping kernel links again");
+        }
+        info!("Kernel link cache is shutting down now");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::apply;
+    use net::interface::InterfaceIndex;
+    use rtnetlink::packet_route::RouteNetlinkMessage;
+    use rtnetlink::packet_route::link::{LinkAttribute, LinkMessage, State};
+    use std::collections::BTreeMap;
+
+    fn link(index: u32, name: &str) -> LinkMessage {
+        let mut message = LinkMessage::default();
+        message.header.index = index;
+        message.attributes = vec![
+            LinkAttribute::IfName(name.to_string()),
+            LinkAttribute::OperState(State::Up),
+        ];
+        message
+    }
+
+    #[test]
+    fn notifications_update_links() {
+        let mut links = BTreeMap::new();
+        let index = InterfaceIndex::try_new(3).unwrap();
+
+        assert!(apply(
+            &mut links,
+            &RouteNetlinkMessage::NewLink(link(3, "eth3"))
+        ));
+        assert_eq!(links[&index].name.as_ref(), "eth3");
+
+        // a rename replaces the link
+        assert!(apply(
+            &mut links,
+            &RouteNetlinkMessage::NewLink(link(3, "uplink"))
+        ));
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[&index].name.as_ref(), "uplink");
+
+        // links which can't be represented are dropped
+        assert!(!apply(
+            &mut links,
+            &RouteNetlinkMessage::NewLink(LinkMessage::default())
+        ));
+
+        assert!(apply(
+            &mut links,
+            &RouteNetlinkMessage::DelLink(link(3, "uplink"))
+        ));
+        assert!(links.is_empty());
+        assert!(!apply(
+            &mut links,
+            &RouteNetlinkMessage::DelLink(link(3, "uplink"))
+        ));
+    }
+}
@@ -0,0 +1,257 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Out-of-band disruption of managed interfaces for resilience testing of the [`VpcManager`].
+//!
+//! The reconciler is expected to converge back to the required state no matter what happens to
+//! the interfaces it manages behind its back.
+//! This module provides the tools needed to check that claim repeatably: a (bolero generated)
+//! [`ChaosPlan`] describing what to break, a [`ChaosMonkey`] which breaks it, and [`converge`],
+//! which drives the reconciler until it either settles or exceeds a bound.
+//!
+//! This module is only available with the `chaos` feature and is not intended for production use.
+
+use crate::vpc_manager::{
+    ObservationError, ObservedInformationBase, RequiredInformationBase, VpcManager,
+};
+use concurrency::sync::Arc;
+use interface_manager::Manager;
+use interface_manager::interface::InterfaceAssociation;
+use net::interface::{
+    AdminState, IllegalInterfaceName, Interface, InterfaceName, InterfaceProperties,
+};
+use rekon::{Observe, Reconcile, Remove, Update};
+use rtnetlink::Handle;
+use tracing::{debug, info};
+
+/// The kinds of out-of-band mischief which may be inflicted on a managed interface.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum DisruptionKind {
+    /// Delete the interface outright.
+    Remove,
+    /// Set the interface administratively down.
+    AdminDown,
+    /// Remove the interface from its controller (if any).
+    Detach,
+    /// Rename the interface to something the reconciler does not expect.
+    Rename,
+}
+
+/// A single disruption to be applied to a managed interface.
+///
+/// The target is expressed as an offset into the (name ordered) list of managed interfaces
+/// observed at the time the disruption is inflicted.
+/// This keeps plans meaningful for any required state, including states generated by bolero.
+#[derive(Copy, Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Disruption {
+    /// Offset (modulo the number of managed interfaces) of the interface to disrupt.
+    pub target: usize,
+    /// What to do to the targeted interface.
+    pub kind: DisruptionKind,
+}
+
+/// An ordered list of [`Disruption`]s to be applied in a single round.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct ChaosPlan(pub Vec<Disruption>);
+
+impl ChaosPlan {
+    /// The maximum number of disruptions generated for a single plan.
+    pub const MAX_DISRUPTIONS: usize = 16;
+}
+
+/// Errors which may occur while inflicting a [`Disruption`].
+#[derive(Debug, thiserror::Error)]
+pub enum ChaosError {
+    /// Netlink refused the disruption.
+    #[error("netlink error while inflicting chaos: {0}")]
+    Netlink(#[from] rtnetlink::Error),
+    /// A rename target could not be constructed.
+    #[error(transparent)]
+    IllegalName(#[from] IllegalInterfaceName),
+}
+
+/// Errors which may occur while driving the reconciler towards convergence.
+#[derive(Debug, thiserror::Error)]
+pub enum ConvergenceError {
+    /// The system could not be observed.
+    #[error("failed to observe system state: {0}")]
+    Observation(#[from] ObservationError),
+    /// The reconciler did not converge within the allotted number of passes.
+    #[error("reconciler failed to converge within {passes} passes")]
+    Diverged {
+        /// The number of passes attempted.
+        passes: usize,
+    },
+}
+
+/// Inflicts [`Disruption`]s on the interfaces managed by a [`VpcManager`].
+#[derive(Clone, Debug)]
+pub struct ChaosMonkey {
+    handle: Arc<Handle>,
+}
+
+impl ChaosMonkey {
+    /// Create a new `ChaosMonkey` from an [`Arc<Handle>`].
+    #[must_use]
+    pub fn new(handle: Arc<Handle>) -> Self {
+        Self { handle }
+    }
+
+    /// List the observed interfaces which are also required (and thus managed by the reconciler).
+    ///
+    /// PCI devices are excluded as they can neither be removed nor recreated by the reconciler.
+    #[must_use]
+    pub fn managed<'a>(
+        requirement: &RequiredInformationBase,
+        observation: &'a ObservedInformationBase,
+    ) -> Vec<&'a Interface> {
+        let mut managed: Vec<_> = observation
+            .interfaces
+            .iter()
+            .map(|(_, interface)| interface)
+            .filter(|interface| !matches!(interface.properties, InterfaceProperties::Pci(_)))
+            .filter(|interface| {
+                requirement
+                    .interfaces
+                    .get_by_name(&interface.name)
+                    .is_some()
+            })
+            .collect();
+        managed.sort_by(|a, b| a.name.cmp(&b.name));
+        managed
+    }
+
+    /// Apply a single [`Disruption`] to an observed interface.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`ChaosError`] if netlink rejects the disruption.
+    pub async fn inflict(
+        &self,
+        disruption: Disruption,
+        interface: &Interface,
+    ) -> Result<(), ChaosError> {
+        debug!("inflicting {disruption:?} on {}", interface.name);
+        match disruption.kind {
+            DisruptionKind::Remove => {
+                Manager::<Interface>::new(self.handle.clone())
+                    .remove(interface)
+                    .await?;
+            }
+            DisruptionKind::AdminDown => {
+                Manager::<AdminState>::new(self.handle.clone())
+                    .update(AdminState::Down, interface)
+                    .await?;
+            }
+            DisruptionKind::Detach => {
+                if interface.controller.is_some() {
+                    Manager::<InterfaceAssociation>::new(self.handle.clone())
+                        .update(None, interface)
+                        .await?;
+                }
+            }
+            DisruptionKind::Rename => {
+                let name = InterfaceName::try_from(format!("chaos{}", interface.index.to_u32()))?;
+                Manager::<InterfaceName>::new(self.handle.clone())
+                    .update(&name, interface)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Apply every [`Disruption`] in a [`ChaosPlan`] to the currently managed interfaces.
+    ///
+    /// Disruptions which fail (e.g., because an earlier disruption already removed the target) are
+    /// logged and skipped; chaos is not expected to be tidy.
+    ///
+    /// Returns the number of disruptions which were successfully inflicted.
+    pub async fn unleash(
+        &self,
+        plan: &ChaosPlan,
+        requirement: &RequiredInformationBase,
+        observation: &ObservedInformationBase,
+    ) -> usize {
+        let managed = Self::managed(requirement, observation);
+        if managed.is_empty() {
+            return 0;
+        }
+        let mut inflicted = 0;
+        for disruption in &plan.0 {
+            let interface = managed[disruption.target % managed.len()];
+            match self.inflict(*disruption, interface).await {
+                Ok(()) => inflicted += 1,
+                Err(err) => debug!("disruption {disruption:?} not applied: {err}"),
+            }
+        }
+        info!(
+            "inflicted {inflicted} of {} planned disruptions",
+            plan.0.len()
+        );
+        inflicted
+    }
+}
+
+/// Drive the reconciler until it reports the system as reconciled.
+///
+/// Returns the number of passes which were required to converge.
+///
+/// # Errors
+///
+/// Returns [`ConvergenceError::Diverged`] if the system is not reconciled after `bound` passes, or
+/// [`ConvergenceError::Observation`] if the system could not be observed.
+pub async fn converge(
+    manager: &VpcManager<RequiredInformationBase>,
+    requirement: &mut RequiredInformationBase,
+    bound: usize,
+) -> Result<usize, ConvergenceError> {
+    for passes in 0..bound {
+        let observation = manager.observe().await?;
+        if manager
+            .reconcile(requirement, &observation)
+            .await
+            .is_reconciled()
+        {
+            return Ok(passes);
+        }
+    }
+    Err(ConvergenceError::Diverged { passes: bound })
+}
+
+#[cfg(any(test, feature = "bolero"))]
+mod contract {
+    use crate::vpc_manager::chaos::{ChaosPlan, Disruption, DisruptionKind};
+    use bolero::{Driver, TypeGenerator};
+
+    impl TypeGenerator for DisruptionKind {
+        fn generate<D: Driver>(driver: &mut D) -> Option<Self> {
+            match driver.produce::<u8>()? % 4 {
+                0 => Some(DisruptionKind::Remove),
+                1 => Some(DisruptionKind::AdminDown),
+                2 => Some(DisruptionKind::Detach),
+                3 => Some(DisruptionKind::Rename),
+                _ => unreachable!(),
+            }
+        }
+    }
+
+    impl TypeGenerator for Disruption {
+        fn generate<D: Driver>(driver: &mut D) -> Option<Self> {
+            Some(Self {
+                target: driver.produce()?,
+                kind: driver.produce()?,
+            })
+        }
+    }
+
+    impl TypeGenerator for ChaosPlan {
+        fn generate<D: Driver>(driver: &mut D) -> Option<Self> {
+            let len = driver.produce::<usize>()? % (ChaosPlan::MAX_DISRUPTIONS + 1);
+            let mut disruptions = Vec::with_capacity(len);
+            for _ in 0..len {
+                disruptions.push(driver.produce()?);
+            }
+            Some(ChaosPlan(disruptions))
+        }
+    }
+}
@@ -1,8 +1,18 @@
 // SPDX-License-Identifier: Apache-2.0
 // Copyright Open Network Fabric Authors
 
+mod cache;
+#[cfg(feature = "chaos")]
+pub mod chaos;
+mod view;
+
+pub use view::{InterfaceView, LinkState};
+
 use crate::processor::confbuild::namegen::VpcInterfacesNames;
+use cache::LinkCache;
 
+use common::feature_flag;
+use common::flags::FlagScope;
 use concurrency::sync::Arc;
 use config::InternalConfig;
 use config::internal::interfaces::interface::{InterfaceConfigTable, InterfaceType};
@@ -11,45 +21,244 @@ use derive_builder::Builder;
 use futures::TryStreamExt;
 use interface_manager::Manager;
 use interface_manager::interface::{
-    BridgePropertiesSpec, InterfaceAssociationSpec, InterfacePropertiesSpec, InterfaceSpecBuilder,
+    BridgePortVlans, BridgePropertiesSpec, BridgeVlanSpec, InterfaceAssociationSpec,
+    InterfacePropertiesSpec, InterfaceSpec, InterfaceSpecBuilder,
     MultiIndexInterfaceAssociationSpecMap, MultiIndexInterfaceSpecMap,
     MultiIndexVrfPropertiesSpecMap, MultiIndexVtepPropertiesSpecMap, TryFromLinkMessage,
-    VrfPropertiesSpec, VtepPropertiesSpec,
+    VrfPropertiesSpec, VtepPropertiesSpec, link_speed,
 };
+use interface_manager::monitor::InterfaceMonitor;
+use interface_manager::neighbor::{Neighbor, NeighborSpec, NeighborState};
+use interface_manager::netns::{NetnsError, NetnsHandles, NetnsName};
+use interface_manager::offload::{OffloadSpec, Offloads};
 use multi_index_map::MultiIndexMap;
 use net::eth::ethtype::EthType;
 use net::eth::mac::SourceMac;
 use net::interface::{
-    AdminState, Interface, InterfaceName, InterfaceProperties, MultiIndexInterfaceMap,
-    MultiIndexVrfPropertiesMap, MultiIndexVtepPropertiesMap,
+    AdminState, IllegalInterfaceName, Interface, InterfaceName, InterfaceProperties,
+    MultiIndexInterfaceMap, MultiIndexVrfPropertiesMap, MultiIndexVtepPropertiesMap,
 };
 use net::ip::UnicastIpAddr;
 use net::route::RouteTableId;
 use net::vxlan::{Vni, Vxlan};
-use rekon::{Observe, Op, Reconcile, Remove};
+use rekon::{Normalize, Observe, Op, Reconcile, Remove, Update};
 use rtnetlink::Handle;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+use std::io::ErrorKind;
 use std::marker::PhantomData;
+use std::net::IpAddr;
 use tracing::{debug, error, warn};
 
+/// Reconciles the kernel network interfaces (VRFs, bridges, VTEPs and taps) with those required by
+/// a configuration.
+///
+/// # Thread safety
+///
+/// A `VpcManager` is `Send + Sync` and cheap to clone: clones share the same netlink handle, whose
+/// requests are multiplexed over a single netlink connection. Observations and reconciliation
+/// passes may be issued from any task or thread. However, concurrent reconciliation passes are not
+/// coordinated, whether issued by the same manager, its clones or distinct managers: passes with
+/// distinct requirements would undo each other's work. Callers should serialize reconciliation
+/// passes, as the config processor does.
+///
+/// # Link cache
+///
+/// By default, each observation dumps all the kernel links. A manager built with
+/// [`VpcManagerBuilder::cache_links`] instead keeps them in a cache updated from netlink
+/// notifications, which clones share. Observations then record the generation of the cache, so
+/// that [`VpcManager::is_stale`] tells whether the links changed since.
+///
+/// # Network namespaces
+///
+/// Interfaces are required in the current network namespace unless their spec names another
+/// one. The manager keeps a netlink handle for each of the namespaces required, opened by
+/// [`VpcManager::open_namespaces`], and observes the links of all of them. Interfaces are created
+/// in their namespace, except taps which are created in the current one, and moved to the
+/// namespace they are required in. Neighbors, bridge vlans and offloads are only managed in the
+/// current namespace.
+///
+/// # Unmanaged interfaces
+///
+/// The interfaces given to [`VpcManagerBuilder::unmanaged`], like the management NIC, are never
+/// touched: observations skip them, and reconciliation passes neither update nor remove them, nor
+/// create them if required.
+///
+/// # Interface monitor
+///
+/// A manager given an [`InterfaceMonitor`] with [`VpcManager::with_interface_monitor`] tells it
+/// of the administrative state changes it is about to make to the interfaces of the current
+/// namespace, so that the transitions they cause are attributed to reconciliation.
 #[derive(Clone, Debug)]
 pub struct VpcManager<R> {
     handle: Arc<Handle>,
+    cache: Option<Arc<LinkCache>>,
+    netns: Arc<NetnsHandles>,
+    unmanaged: Arc<BTreeSet<InterfaceName>>,
+    monitor: Option<Arc<InterfaceMonitor>>,
     _marker: PhantomData<R>,
 }
 
 impl<R> VpcManager<R> {
     pub fn new(handle: Arc<Handle>) -> Self {
         VpcManager {
+            netns: Arc::new(NetnsHandles::new(handle.clone())),
             handle,
+            cache: None,
+            unmanaged: Arc::new(BTreeSet::new()),
+            monitor: None,
+            _marker: PhantomData,
+        }
+    }
+
+    #[must_use]
+    pub fn builder() -> VpcManagerBuilder<R> {
+        VpcManagerBuilder {
+            handle: None,
+            cache_links: false,
+            unmanaged: BTreeSet::new(),
             _marker: PhantomData,
         }
     }
+
+    /// Tell `monitor` of the administrative state changes made by reconciliation passes.
+    #[must_use]
+    pub fn with_interface_monitor(mut self, monitor: Arc<InterfaceMonitor>) -> Self {
+        self.monitor = Some(monitor);
+        self
+    }
+
+    /// Tell the interface monitor, if any, that the administrative state of `observed` is about
+    /// to be changed to the one `required`, if they differ. Only the interfaces of the current
+    /// namespace are monitored.
+    fn expect_admin_change(
+        &self,
+        required: &InterfaceSpec,
+        observed: &Interface,
+        netns: Option<&NetnsName>,
+    ) {
+        if let Some(monitor) = &self.monitor
+            && netns.is_none()
+            && required.admin_state != observed.admin_state
+        {
+            monitor.expect_reconcile(observed.index);
+        }
+    }
+
+    /// Tell if the interface `name` must never be touched
+    #[must_use]
+    pub fn is_unmanaged(&self, name: &InterfaceName) -> bool {
+        self.unmanaged.contains(name)
+    }
+
+    /// Tell if the kernel links changed since `observed` was observed. This is never the case
+    /// for a manager without a link cache, whose observations are always fresh dumps.
+    #[must_use]
+    pub fn is_stale(&self, observed: &ObservedInformationBase) -> bool {
+        self.cache
+            .as_ref()
+            .is_some_and(|cache| cache.generation() != observed.generation)
+    }
+
+    /// Open netlink handles in the network namespaces the interfaces of `required` should be in,
+    /// so that they are observed, and close those of the namespaces no longer required. The
+    /// interfaces left in those are no longer managed.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a network namespace does not exist or can't be joined. The other namespaces are
+    /// opened regardless.
+    pub fn open_namespaces(&self, required: &RequiredInformationBase) -> Result<(), NetnsError> {
+        self.netns.retain(
+            required
+                .interfaces
+                .iter()
+                .filter_map(|(_, spec)| spec.netns.as_ref()),
+        )
+    }
+}
+
+/// Errors building a [`VpcManager`]
+#[derive(Debug, thiserror::Error)]
+pub enum VpcManagerError {
+    #[error("Failed to open a netlink connection: {0}")]
+    Connection(#[from] std::io::Error),
+    #[error("No tokio runtime to run the netlink connection on")]
+    NoRuntime,
+}
+
+/// Builder of a [`VpcManager`]
+#[derive(Debug)]
+pub struct VpcManagerBuilder<R> {
+    handle: Option<Arc<Handle>>,
+    cache_links: bool,
+    unmanaged: BTreeSet<InterfaceName>,
+    _marker: PhantomData<R>,
+}
+
+impl<R> VpcManagerBuilder<R> {
+    /// Use an existing netlink handle, e.g. one shared with other components of the binary,
+    /// rather than opening a new connection.
+    #[must_use]
+    pub fn handle(mut self, handle: Arc<Handle>) -> Self {
+        self.handle = Some(handle);
+        self
+    }
+
+    /// Keep the kernel links in a cache updated from netlink notifications, rather than dumping
+    /// them on each observation. The cache is maintained by a task spawned on the current tokio
+    /// runtime.
+    #[must_use]
+    pub fn cache_links(mut self) -> Self {
+        self.cache_links = true;
+        self
+    }
+
+    /// Never touch the interfaces `names`, e.g. the management NIC or the ports shared with the
+    /// IPMI, whatever the configurations require.
+    #[must_use]
+    pub fn unmanaged(mut self, names: impl IntoIterator<Item = InterfaceName>) -> Self {
+        self.unmanaged.extend(names);
+        self
+    }
+
+    /// Build the [`VpcManager`]. Unless a handle was provided, this opens a netlink connection,
+    /// whose task is spawned on the current tokio runtime.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no handle was provided and a netlink connection can't be opened, or if there is no
+    /// current tokio runtime to run it or the link cache on.
+    pub fn build(self) -> Result<VpcManager<R>, VpcManagerError> {
+        let runtime =
+            || tokio::runtime::Handle::try_current().map_err(|_| VpcManagerError::NoRuntime);
+        let handle = match self.handle {
+            Some(handle) => handle,
+            None => {
+                let (connection, handle, _) = rtnetlink::new_connection()?;
+                runtime()?.spawn(connection);
+                Arc::new(handle)
+            }
+        };
+        let mut manager = VpcManager::new(handle);
+        manager.unmanaged = Arc::new(self.unmanaged);
+        if self.cache_links {
+            manager.cache = Some(LinkCache::spawn(manager.handle.clone(), &runtime()?));
+        }
+        Ok(manager)
+    }
 }
 
 impl<T, U> From<&VpcManager<T>> for VpcManager<U> {
     fn from(handle: &VpcManager<T>) -> Self {
-        Self::new(handle.handle.clone())
+        Self {
+            handle: handle.handle.clone(),
+            cache: handle.cache.clone(),
+            netns: handle.netns.clone(),
+            unmanaged: handle.unmanaged.clone(),
+            _marker: PhantomData,
+        }
     }
 }
 
@@ -91,6 +300,14 @@ pub struct RequiredInformationBase {
     pub vrfs: MultiIndexVrfPropertiesSpecMap,
     pub vteps: MultiIndexVtepPropertiesSpecMap,
     pub associations: MultiIndexInterfaceAssociationSpecMap,
+    /// Static neighbors required on the proxy tap interfaces
+    #[builder(default)]
+    #[serde(default)]
+    pub neighbors: BTreeSet<NeighborSpec>,
+    /// VLAN membership required on the ports of vlan aware bridges
+    #[builder(default)]
+    #[serde(default)]
+    pub bridge_vlans: BTreeSet<BridgeVlanSpec>,
 }
 
 #[derive(Clone, Debug, Deserialize, Serialize, Default, Builder)]
@@ -98,11 +315,370 @@ pub struct ObservedInformationBase {
     pub interfaces: MultiIndexInterfaceMap,
     pub vrfs: MultiIndexVrfPropertiesMap,
     pub vteps: MultiIndexVtepPropertiesMap,
+    /// Neighbors of the managed interfaces
+    #[builder(default)]
+    #[serde(default)]
+    pub neighbors: Vec<Neighbor>,
+    /// Offload settings of the managed interfaces
+    #[builder(default)]
+    #[serde(default)]
+    pub offloads: Vec<Offloads>,
+    /// VLANs of the bridge ports
+    #[builder(default)]
+    #[serde(default)]
+    pub bridge_vlans: Vec<BridgePortVlans>,
+    /// Generation of the link cache the interfaces were taken from, if any
+    #[builder(default)]
+    #[serde(default)]
+    pub generation: u64,
+    /// Interfaces of the other network namespaces managed
+    #[builder(default)]
+    #[serde(default)]
+    pub netns_interfaces: BTreeMap<NetnsName, MultiIndexInterfaceMap>,
+}
+
+impl ObservedInformationBase {
+    /// The interfaces observed in a network namespace, the current one if `netns` is `None`.
+    /// Returns `None` if the namespace was not observed.
+    #[must_use]
+    pub fn interfaces_in(&self, netns: Option<&NetnsName>) -> Option<&MultiIndexInterfaceMap> {
+        match netns {
+            None => Some(&self.interfaces),
+            Some(netns) => self.netns_interfaces.get(netns),
+        }
+    }
+
+    /// The interfaces observed in each network namespace, starting with the current one
+    pub fn namespaces(
+        &self,
+    ) -> impl Iterator<Item = (Option<&NetnsName>, &MultiIndexInterfaceMap)> {
+        std::iter::once((None, &self.interfaces)).chain(
+            self.netns_interfaces
+                .iter()
+                .map(|(netns, interfaces)| (Some(netns), interfaces)),
+        )
+    }
+}
+
+impl RequiredInformationBase {
+    /// Drop the requirements about the interfaces `unmanaged`, which must not be touched. Returns
+    /// the names of those which were required.
+    pub fn exclude(&mut self, unmanaged: &BTreeSet<InterfaceName>) -> Vec<InterfaceName> {
+        let excluded: Vec<InterfaceName> = self
+            .interfaces
+            .iter()
+            .filter(|(_, spec)| unmanaged.contains(&spec.name))
+            .map(|(_, spec)| spec.name.clone())
+            .collect();
+        for name in &excluded {
+            self.interfaces.remove_by_name(name);
+        }
+        let associated: Vec<InterfaceName> = self
+            .associations
+            .iter()
+            .filter(|(_, spec)| unmanaged.contains(&spec.name))
+            .map(|(_, spec)| spec.name.clone())
+            .collect();
+        for name in &associated {
+            self.associations.remove_by_name(name);
+        }
+        self.neighbors
+            .retain(|spec| !unmanaged.contains(&spec.interface));
+        self.bridge_vlans
+            .retain(|spec| !unmanaged.contains(&spec.port));
+        excluded
+    }
+}
+
+impl Normalize for RequiredInformationBase {
+    /// Normalize all the specs, so that they compare equal to the kernel objects meeting them.
+    /// Normalization leaves the names of interfaces and the vnis of vteps untouched, so they remain
+    /// unique.
+    fn normalize(&mut self) {
+        let mut interfaces = MultiIndexInterfaceSpecMap::default();
+        for (_, spec) in self.interfaces.iter() {
+            let mut spec = spec.clone();
+            spec.normalize();
+            interfaces
+                .try_insert(spec)
+                .unwrap_or_else(|_| unreachable!());
+        }
+        let mut vteps = MultiIndexVtepPropertiesSpecMap::default();
+        for (_, spec) in self.vteps.iter() {
+            let mut spec = spec.clone();
+            spec.normalize();
+            vteps.try_insert(spec).unwrap_or_else(|_| unreachable!());
+        }
+        self.interfaces = interfaces;
+        self.vteps = vteps;
+        self.neighbors = std::mem::take(&mut self.neighbors)
+            .into_iter()
+            .map(|mut spec| {
+                spec.normalize();
+                spec
+            })
+            .collect();
+        self.bridge_vlans = std::mem::take(&mut self.bridge_vlans)
+            .into_iter()
+            .map(|mut spec| {
+                spec.normalize();
+                spec
+            })
+            .collect();
+    }
+}
+
+/// Errors observing the kernel network interfaces
+#[derive(Debug, thiserror::Error)]
+pub enum ObservationError {
+    #[error("Failed to dump kernel links, neighbors or bridge vlans: {0}")]
+    Netlink(#[from] rtnetlink::Error),
+    #[error("Failed to build observed information base: {0}")]
+    Build(#[from] ObservedInformationBaseBuilderError),
+}
+
+/// The kernel object a [`ReconcileOp`] operates on
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReconcileObject {
+    Interface(InterfaceName),
+    Neighbor {
+        interface: InterfaceName,
+        address: IpAddr,
+    },
+    Offloads(InterfaceName),
+    BridgeVlans(InterfaceName),
+}
+
+impl Display for ReconcileObject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReconcileObject::Interface(name) => write!(f, "interface {name}"),
+            ReconcileObject::Neighbor { interface, address } => {
+                write!(f, "neighbor {address} on interface {interface}")
+            }
+            ReconcileObject::Offloads(name) => write!(f, "offloads of interface {name}"),
+            ReconcileObject::BridgeVlans(name) => write!(f, "vlans of bridge port {name}"),
+        }
+    }
+}
+
+/// An operation on a kernel object
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReconcileAction {
+    Create,
+    Update,
+    Remove,
+}
+
+impl Display for ReconcileAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReconcileAction::Create => write!(f, "create"),
+            ReconcileAction::Update => write!(f, "update"),
+            ReconcileAction::Remove => write!(f, "remove"),
+        }
+    }
+}
+
+/// The category of the error of a failed [`ReconcileOp`]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReconcileErrorKind {
+    /// The kernel was busy or short of resources
+    Transient,
+    /// The object exists already, e.g. because another pass or process created it meanwhile
+    Conflict,
+    /// The object, or one it depends on, does not exist (yet)
+    Missing,
+    /// The kernel does not support the operation
+    Unsupported,
+    /// The operation is not permitted
+    Denied,
+    /// The kernel rejected the settings of the object
+    Invalid,
+    /// Any other error
+    Other,
+}
+
+impl ReconcileErrorKind {
+    /// Tell if retrying the operation on a later pass may succeed. Errors which are not
+    /// retryable can only be solved by changing the configuration or the host.
+    #[must_use]
+    pub fn is_retryable(self) -> bool {
+        !matches!(
+            self,
+            ReconcileErrorKind::Unsupported
+                | ReconcileErrorKind::Denied
+                | ReconcileErrorKind::Invalid
+        )
+    }
+}
+
+impl From<&rtnetlink::Error> for ReconcileErrorKind {
+    fn from(err: &rtnetlink::Error) -> Self {
+        let rtnetlink::Error::NetlinkError(message) = err else {
+            return ReconcileErrorKind::Other;
+        };
+        match message.to_io().kind() {
+            ErrorKind::ResourceBusy
+            | ErrorKind::WouldBlock
+            | ErrorKind::Interrupted
+            | ErrorKind::OutOfMemory
+            | ErrorKind::TimedOut => ReconcileErrorKind::Transient,
+            ErrorKind::AlreadyExists => ReconcileErrorKind::Conflict,
+            ErrorKind::NotFound => ReconcileErrorKind::Missing,
+            ErrorKind::Unsupported => ReconcileErrorKind::Unsupported,
+            ErrorKind::PermissionDenied => ReconcileErrorKind::Denied,
+            ErrorKind::InvalidInput | ErrorKind::InvalidData => ReconcileErrorKind::Invalid,
+            _ => ReconcileErrorKind::Other,
+        }
+    }
+}
+
+impl Display for ReconcileErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReconcileErrorKind::Transient => write!(f, "transient"),
+            ReconcileErrorKind::Conflict => write!(f, "conflict"),
+            ReconcileErrorKind::Missing => write!(f, "missing"),
+            ReconcileErrorKind::Unsupported => write!(f, "unsupported"),
+            ReconcileErrorKind::Denied => write!(f, "denied"),
+            ReconcileErrorKind::Invalid => write!(f, "invalid"),
+            ReconcileErrorKind::Other => write!(f, "other"),
+        }
+    }
+}
+
+/// An operation carried out by a reconciliation pass, and its result
+#[derive(Debug)]
+pub struct ReconcileOp {
+    pub object: ReconcileObject,
+    pub action: ReconcileAction,
+    pub result: Result<(), rtnetlink::Error>,
+}
+
+impl Display for ReconcileOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.action, self.object)?;
+        if let Err(err) = &self.result {
+            write!(f, " failed ({}): {err}", ReconcileErrorKind::from(err))?;
+        }
+        Ok(())
+    }
+}
+
+impl ReconcileOp {
+    /// The category of the error of the operation, if it failed
+    #[must_use]
+    pub fn error_kind(&self) -> Option<ReconcileErrorKind> {
+        self.result.as_ref().err().map(ReconcileErrorKind::from)
+    }
+
+    fn interface(interface: &InterfaceName, op: Op<'_, Manager<Interface>>) -> Self {
+        let (action, result) = match op {
+            Op::Create(result) => (ReconcileAction::Create, result),
+            Op::Update(result) => (ReconcileAction::Update, result),
+            Op::Remove(result) => (ReconcileAction::Remove, result),
+        };
+        Self {
+            object: ReconcileObject::Interface(interface.clone()),
+            action,
+            result,
+        }
+    }
+
+    fn neighbor(spec: &NeighborSpec, op: Op<'_, Manager<Neighbor>>) -> Self {
+        let (action, result) = match op {
+            Op::Create(result) => (ReconcileAction::Create, result),
+            Op::Update(result) => (ReconcileAction::Update, result),
+            Op::Remove(result) => (ReconcileAction::Remove, result),
+        };
+        Self {
+            object: ReconcileObject::Neighbor {
+                interface: spec.interface.clone(),
+                address: spec.address,
+            },
+            action,
+            result,
+        }
+    }
+
+    fn bridge_vlans(spec: &BridgeVlanSpec, op: Op<'_, Manager<BridgePortVlans>>) -> Self {
+        let (action, result) = match op {
+            Op::Create(result) => (ReconcileAction::Create, result),
+            Op::Update(result) => (ReconcileAction::Update, result),
+            Op::Remove(result) => (ReconcileAction::Remove, result),
+        };
+        Self {
+            object: ReconcileObject::BridgeVlans(spec.port.clone()),
+            action,
+            result,
+        }
+    }
+}
+
+/// The operations carried out by a reconciliation pass.
+///
+/// The system is reconciled once a pass has nothing to do. Otherwise, another pass (with a fresh
+/// observation) is needed to check that the operations had the intended effect.
+#[derive(Debug, Default)]
+pub struct ReconcileReport {
+    ops: Vec<ReconcileOp>,
+}
+
+impl ReconcileReport {
+    fn push(&mut self, op: ReconcileOp) {
+        if op.result.is_err() {
+            error!("{op}");
+        }
+        self.ops.push(op);
+    }
+
+    /// True if the observed state met the requirements, so no operation was needed
+    #[must_use]
+    pub fn is_reconciled(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    #[must_use]
+    pub fn ops(&self) -> &[ReconcileOp] {
+        &self.ops
+    }
+
+    /// The operations that failed
+    pub fn failures(&self) -> impl Iterator<Item = &ReconcileOp> {
+        self.ops.iter().filter(|op| op.result.is_err())
+    }
+
+    /// The operations that failed in a way that further passes can't fix
+    pub fn permanent_failures(&self) -> impl Iterator<Item = &ReconcileOp> {
+        self.ops
+            .iter()
+            .filter(|op| op.error_kind().is_some_and(|kind| !kind.is_retryable()))
+    }
+
+    /// Describe the failed operations
+    #[must_use]
+    pub fn describe_failures(&self) -> String {
+        self.failures()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+impl Display for ReconcileReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_reconciled() {
+            return write!(f, "reconciled");
+        }
+        let failures = self.failures().count();
+        write!(f, "{} operations, {failures} failed", self.ops.len())
+    }
 }
 
 impl Observe for VpcManager<RequiredInformationBase> {
     type Observation<'a>
-        = Result<ObservedInformationBase, ObservedInformationBaseBuilderError>
+        = Result<ObservedInformationBase, ObservationError>
     where
         Self: 'a;
 
@@ -112,18 +688,23 @@ impl Observe for VpcManager<RequiredInformationBase> {
     {
         let mut ob = ObservedInformationBaseBuilder::default();
         let mut observations = MultiIndexInterfaceMap::with_capacity(512);
-        let mut req = self.handle.link().get().execute();
-        while let Ok(Some(message)) = req.try_next().await {
-            match Interface::try_from_link_message(&message) {
-                Ok(interface) => match observations.try_insert(interface) {
-                    Ok(_) => {}
-                    Err(uniqueness_error) => {
-                        error!("{uniqueness_error:?}");
-                    }
-                },
-                Err(err) => {
-                    debug!("{err:?}");
-                }
+        let (links, generation) = match self.cache.as_ref().and_then(|cache| cache.links()) {
+            Some(cached) => cached,
+            None => (dump_links(&self.handle).await?, 0),
+        };
+        for mut interface in links {
+            if self.is_unmanaged(&interface.name) {
+                continue;
+            }
+            // only the devices of the current namespace have a speed, which netlink doesn't report
+            if matches!(
+                interface.properties,
+                InterfaceProperties::Pci(_) | InterfaceProperties::Other
+            ) {
+                interface.speed = link_speed(&interface.name);
+            }
+            if let Err(uniqueness_error) = observations.try_insert(interface) {
+                error!("{uniqueness_error:?}");
             }
         }
         let mut vtep_properties = MultiIndexVtepPropertiesMap::default();
@@ -158,19 +739,93 @@ impl Observe for VpcManager<RequiredInformationBase> {
         for sliced in indexes_to_remove {
             observations.remove_by_index(&sliced);
         }
-        match ob
+
+        // only the neighbors of the interfaces we manage are of interest
+        let mut neighbors = vec![];
+        let mut req = self.handle.neighbours().get().execute();
+        while let Some(message) = req.try_next().await? {
+            let neighbor = match Neighbor::try_from(&message) {
+                Ok(neighbor) => neighbor,
+                Err(err) => {
+                    debug!("{err}");
+                    continue;
+                }
+            };
+            match observations.get_by_index(&neighbor.ifindex) {
+                None
+                | Some(Interface {
+                    properties: InterfaceProperties::Other | InterfaceProperties::Pci(_),
+                    ..
+                }) => {}
+                Some(_) => neighbors.push(neighbor),
+            }
+        }
+
+        // only the vlans of the ports we manage are of interest
+        let bridge_vlans = Manager::<BridgePortVlans>::new(self.handle.clone())
+            .observe()
+            .await?
+            .into_iter()
+            .filter(|vlans| {
+                observations
+                    .get_by_index(&vlans.ifindex)
+                    .is_some_and(|interface| interface.properties != InterfaceProperties::Other)
+            })
+            .collect();
+
+        // offloads can't be observed for the interfaces which are not there yet: they are checked
+        // on the next pass
+        let offload_handle = Manager::<Offloads>::new(self.handle.clone());
+        let mut offloads = vec![];
+        for (_, interface) in observations.iter() {
+            if interface.properties == InterfaceProperties::Other {
+                continue;
+            }
+            match offload_handle.get(&interface.name) {
+                Ok(observed) => offloads.push(observed),
+                Err(err) => debug!("{err}"),
+            }
+        }
+        let mut netns_interfaces = BTreeMap::new();
+        for (netns, handle) in self.netns.namespaces() {
+            let mut interfaces = MultiIndexInterfaceMap::default();
+            for interface in dump_links(&handle).await? {
+                if self.is_unmanaged(&interface.name) {
+                    continue;
+                }
+                if let Err(uniqueness_error) = interfaces.try_insert(interface) {
+                    error!("{uniqueness_error:?}");
+                }
+            }
+            netns_interfaces.insert(netns, interfaces);
+        }
+
+        Ok(ob
             .interfaces(observations)
             .vteps(vtep_properties)
             .vrfs(vrf_properties)
-            .build()
-        {
-            Ok(ob) => Ok(ob),
+            .neighbors(neighbors)
+            .offloads(offloads)
+            .bridge_vlans(bridge_vlans)
+            .generation(generation)
+            .netns_interfaces(netns_interfaces)
+            .build()?)
+    }
+}
+
+/// Dump the kernel links of the namespace of a handle, skipping those which can't be represented
+async fn dump_links(handle: &Handle) -> Result<Vec<Interface>, rtnetlink::Error> {
+    let mut links = vec![];
+    let mut req = handle.link().get().execute();
+    while let Some(message) = req.try_next().await? {
+        match Interface::try_from_link_message(&message) {
+            Ok(interface) => links.push(interface),
             Err(err) => {
-                error!("{err:?}");
-                Err(err)
+                debug!("{err:?}");
             }
         }
     }
+    Ok(links)
 }
 
 impl Reconcile for VpcManager<RequiredInformationBase> {
@@ -183,12 +838,11 @@ impl Reconcile for VpcManager<RequiredInformationBase> {
     where
         Self: 'a;
     type Outcome<'a>
-        = bool
-    // true if reconciled
+        = ReconcileReport
     where
         Self: 'a;
 
-    /// Returns true if the system is reconciled.
+    /// Carry out a reconciliation pass, reporting the operations that were needed.
     async fn reconcile<'a>(
         &self,
         requirement: &'a mut RequiredInformationBase,
@@ -197,58 +851,101 @@ impl Reconcile for VpcManager<RequiredInformationBase> {
     where
         Self: 'a,
     {
-        let mut reconciled = true;
+        let mut report = ReconcileReport::default();
+        for name in requirement.exclude(&self.unmanaged) {
+            warn!("Interface {name} is required but unmanaged: leaving it alone");
+        }
+        requirement.normalize();
         // update the requirements to reflect which interfaces can be associated with which
         for (_, association) in requirement.associations.iter() {
-            requirement
-                .interfaces
-                .update_by_name(&association.name, |_, _, _, controller, _| {
-                    *controller =
-                        association
-                            .controller_name
-                            .as_ref()
-                            .and_then(|controller_name| {
-                                observation
-                                    .interfaces
-                                    .get_by_name(controller_name)
-                                    .map(|controller| controller.index)
-                            });
+            requirement.interfaces.update_by_name(
+                &association.name,
+                |_, _, _, controller, _, _, netns| {
+                    // indexes are only meaningful within a namespace
+                    *controller = association.controller_name.as_ref().and_then(|name| {
+                        observation
+                            .interfaces_in(netns.as_ref())?
+                            .get_by_name(name)
+                            .map(|controller| controller.index)
+                    });
+                },
+            );
+        }
+
+        // remove the stale neighbors first, since the interfaces they point at may be going away
+        let neighbor_handle = Manager::<Neighbor>::new(self.handle.clone());
+        for neighbor in &observation.neighbors {
+            let Some(interface) = observation.interfaces.get_by_index(&neighbor.ifindex) else {
+                continue;
+            };
+            if self.is_unmanaged(&interface.name) {
+                continue;
+            }
+            let required = requirement
+                .neighbors
+                .iter()
+                .any(|spec| spec.interface == interface.name && spec.address == neighbor.address);
+            if required {
+                continue;
+            }
+            // entries we did not configure are left to the kernel, unless they point at a vtep
+            // which is no longer wanted
+            let stale_vtep = interface.is_vtep()
+                && requirement
+                    .interfaces
+                    .get_by_name(&interface.name)
+                    .is_none();
+            if neighbor.state == NeighborState::Permanent || stale_vtep {
+                let result = neighbor_handle.remove(neighbor).await;
+                report.push(ReconcileOp {
+                    object: ReconcileObject::Neighbor {
+                        interface: interface.name.clone(),
+                        address: neighbor.address,
+                    },
+                    action: ReconcileAction::Remove,
+                    result,
                 });
+            }
         }
 
-        // reconciling the extant interfaces as much as possible
-        let iface_handle = Manager::<Interface>::new(self.handle.clone());
-        for (_, interface) in observation.interfaces.iter() {
-            match requirement.interfaces.get_by_name(&interface.name) {
-                None => match interface.properties {
-                    InterfaceProperties::Other | InterfaceProperties::Pci(_) => {}
-                    _ => {
-                        reconciled = false;
-                        match iface_handle.remove(interface).await {
-                            Ok(()) => {}
-                            Err(err) => {
-                                error!("{err:?}")
-                            }
+        // reconciling the extant interfaces as much as possible, in each namespace
+        for (netns, interfaces) in observation.namespaces() {
+            let Some(handle) = self.netns.get(netns) else {
+                continue;
+            };
+            let iface_handle = Manager::<Interface>::new(handle.clone());
+            // observations skip the unmanaged interfaces, unless they were not made by this manager
+            for (_, interface) in interfaces
+                .iter()
+                .filter(|(_, interface)| !self.is_unmanaged(&interface.name))
+            {
+                match requirement.interfaces.get_by_name(&interface.name) {
+                    None => match interface.properties {
+                        InterfaceProperties::Other | InterfaceProperties::Pci(_) => {}
+                        _ => {
+                            let result = iface_handle.remove(interface).await;
+                            report.push(ReconcileOp {
+                                object: ReconcileObject::Interface(interface.name.clone()),
+                                action: ReconcileAction::Remove,
+                                result,
+                            });
                         }
+                    },
+                    Some(requirement) if requirement.netns.as_ref() != netns => {
+                        let result = Manager::<NetnsName>::new(handle.clone())
+                            .update(requirement.netns.as_ref(), interface)
+                            .await;
+                        report.push(ReconcileOp {
+                            object: ReconcileObject::Interface(interface.name.clone()),
+                            action: ReconcileAction::Update,
+                            result,
+                        });
                     }
-                },
-                Some(requirement) => {
-                    match iface_handle.reconcile(requirement, Some(interface)).await {
-                        None => {}
-                        Some(
-                            Op::Create(Err(err)) | Op::Update(Err(err)) | Op::Remove(Err(err)),
-                        ) => {
-                            reconciled = false;
-                            error!("{err:?}");
-                        }
-                        Some(Op::Create(Ok(()))) => {
-                            reconciled = false;
-                        }
-                        Some(Op::Update(Ok(()))) => {
-                            reconciled = false;
-                        }
-                        Some(Op::Remove(Ok(()))) => {
-                            reconciled = false;
+                    Some(requirement) => {
+                        self.expect_admin_change(requirement, interface, netns);
+                        if let Some(op) = iface_handle.reconcile(requirement, Some(interface)).await
+                        {
+                            report.push(ReconcileOp::interface(&interface.name, op));
                         }
                     }
                 }
@@ -256,27 +953,95 @@ impl Reconcile for VpcManager<RequiredInformationBase> {
         }
 
         // go through the requirement list and create anything missing (and reconcile anything out
-        // of sync)
+        // of sync), in the namespace it is required in
         for (_, interface) in requirement.interfaces.iter() {
-            match iface_handle
-                .reconcile(
-                    interface,
-                    observation.interfaces.get_by_name(&interface.name),
-                )
+            let netns = interface.netns.as_ref();
+            let (Some(handle), Some(interfaces)) =
+                (self.netns.get(netns), observation.interfaces_in(netns))
+            else {
+                continue;
+            };
+            let observed = interfaces.get_by_name(&interface.name);
+            // interfaces found in another namespace are being moved by now
+            let elsewhere = observation.namespaces().any(|(other, interfaces)| {
+                other != netns && interfaces.get_by_name(&interface.name).is_some()
+            });
+            if observed.is_none() && elsewhere {
+                continue;
+            }
+            if let Some(observed) = observed {
+                self.expect_admin_change(interface, observed, netns);
+            }
+            if let Some(op) = Manager::<Interface>::new(handle)
+                .reconcile(interface, observed)
                 .await
             {
-                None => {}
-                Some(Op::Create(Err(err)) | Op::Update(Err(err)) | Op::Remove(Err(err))) => {
-                    reconciled = false;
-                    error!("{err:?}");
-                }
-                _ => {
-                    reconciled = false;
-                }
+                report.push(ReconcileOp::interface(&interface.name, op));
+            }
+        }
+
+        // install the required static neighbors on the interfaces which exist by now; those on
+        // interfaces just created are installed by the next pass
+        for spec in &requirement.neighbors {
+            let Some(interface) = observation.interfaces.get_by_name(&spec.interface) else {
+                continue;
+            };
+            let observed = observation
+                .neighbors
+                .iter()
+                .find(|n| n.ifindex == interface.index && n.address == spec.address);
+            if let Some(op) = neighbor_handle
+                .reconcile((interface.index, spec), observed)
+                .await
+            {
+                report.push(ReconcileOp::neighbor(spec, op));
+            }
+        }
+
+        // program the vlans of the bridges, and of the bridge ports which exist and are enslaved
+        // by now; the others are programmed by the next pass
+        let vlan_handle = Manager::<BridgePortVlans>::new(self.handle.clone());
+        for spec in &requirement.bridge_vlans {
+            let Some(port) = observation.interfaces.get_by_name(&spec.port) else {
+                continue;
+            };
+            let is_bridge = matches!(port.properties, InterfaceProperties::Bridge(_));
+            if port.controller.is_none() && !is_bridge {
+                continue;
+            }
+            let observed = observation
+                .bridge_vlans
+                .iter()
+                .find(|vlans| vlans.ifindex == port.index);
+            if let Some(op) = vlan_handle.reconcile((port.index, spec), observed).await {
+                report.push(ReconcileOp::bridge_vlans(spec, op));
             }
         }
 
-        reconciled
+        // repair the offload settings which drifted
+        let offload_handle = Manager::<Offloads>::new(self.handle.clone());
+        let offloads = if RECONCILE_OFFLOADS.is_enabled() {
+            observation.offloads.as_slice()
+        } else {
+            &[]
+        };
+        for observed in offloads {
+            let Some(interface) = requirement.interfaces.get_by_name(&observed.interface) else {
+                continue;
+            };
+            if let Some(result) = offload_handle
+                .reconcile(&interface.offloads, observed)
+                .await
+            {
+                report.push(ReconcileOp {
+                    object: ReconcileObject::Offloads(observed.interface.clone()),
+                    action: ReconcileAction::Update,
+                    result,
+                });
+            }
+        }
+
+        report
     }
 }
 
@@ -300,13 +1065,53 @@ impl Vpc {
     }
 }
 
+/// The name of the proxy tap interface of an interface
+fn tap_name(name: &str) -> Result<InterfaceName, IllegalInterfaceName> {
+    InterfaceName::try_from(format!("{name}-tap"))
+}
+
+/// The names of the proxy tap interfaces that the vpc manager creates for a configuration
+#[must_use]
+pub fn tap_interfaces(internal: &InternalConfig) -> BTreeSet<InterfaceName> {
+    internal
+        .vrfs
+        .all_vrfs()
+        .flat_map(|vrfconfig| vrfconfig.interfaces.values())
+        .filter(|iface| matches!(iface.iftype, InterfaceType::Ethernet(_)))
+        .filter_map(|iface| tap_name(iface.name.as_str()).ok())
+        .collect()
+}
+
+feature_flag! {
+    /// Gates the repair of the offload settings of the interfaces
+    static RECONCILE_OFFLOADS = (
+        "reconcile-offloads",
+        true,
+        FlagScope::Runtime,
+        "Repair the offload settings of the interfaces which drifted"
+    );
+}
+
+/// The offloads of the proxy tap interfaces. The workers read the frames the kernel sends over the
+/// taps, which must carry their VLAN tags and complete checksums, and fit the MTU.
+const TAP_OFFLOADS: OffloadSpec = OffloadSpec {
+    rx_checksum: None,
+    tx_checksum: Some(false),
+    rx_vlan: Some(false),
+    tx_vlan: Some(false),
+    gro: Some(false),
+    lro: Some(false),
+    tso: Some(false),
+    gso: Some(false),
+};
+
 /// Create an InterfaceSpec for an InterfaceConfig
 fn add_interface_specs(interfaces: &mut MultiIndexInterfaceSpecMap, ifaces: &InterfaceConfigTable) {
     for iface in ifaces.values() {
         match &iface.iftype {
             InterfaceType::Ethernet(eth) => {
                 let mut tap = InterfaceSpecBuilder::default();
-                match InterfaceName::try_from(format!("{}-tap", iface.name.as_str())) {
+                match tap_name(iface.name.as_str()) {
                     Ok(name) => {
                         tap.name(name);
                     }
@@ -328,6 +1133,7 @@ fn add_interface_specs(interfaces: &mut MultiIndexInterfaceSpecMap, ifaces: &Int
                     }
                 };
                 tap.properties(InterfacePropertiesSpec::Tap);
+                tap.offloads(TAP_OFFLOADS);
                 tap.mtu(iface.mtu);
                 tap.admin_state(AdminState::Up);
                 match tap.build() {
@@ -352,6 +1158,36 @@ fn add_interface_specs(interfaces: &mut MultiIndexInterfaceSpecMap, ifaces: &Int
     }
 }
 
+/// Create a NeighborSpec for each static neighbor of an InterfaceConfig, on its proxy tap
+fn add_neighbor_specs(neighbors: &mut BTreeSet<NeighborSpec>, ifaces: &InterfaceConfigTable) {
+    for iface in ifaces.values() {
+        if !matches!(iface.iftype, InterfaceType::Ethernet(_)) {
+            continue;
+        }
+        let interface = match tap_name(iface.name.as_str()) {
+            Ok(name) => name,
+            Err(e) => {
+                error!("{e}");
+                continue;
+            }
+        };
+        for (address, mac) in &iface.static_neighbors {
+            match SourceMac::new(*mac) {
+                Ok(mac) => {
+                    neighbors.insert(NeighborSpec {
+                        interface: interface.clone(),
+                        address: *address,
+                        mac,
+                    });
+                }
+                Err(e) => {
+                    error!("{e}");
+                }
+            }
+        }
+    }
+}
+
 // TODO: break up this method into smaller components
 impl TryFrom<&InternalConfig> for RequiredInformationBase {
     type Error = RequiredInformationBaseBuilderError;
@@ -363,11 +1199,17 @@ impl TryFrom<&InternalConfig> for RequiredInformationBase {
         let mut vrfs = MultiIndexVrfPropertiesSpecMap::default();
         let mut vteps = MultiIndexVtepPropertiesSpecMap::default();
         let mut associations = MultiIndexInterfaceAssociationSpecMap::default();
+        let mut neighbors = BTreeSet::new();
 
         // non-default VRFs
         for vrfconfig in internal.vrfs.iter_by_tableid().filter(|cfg| !cfg.default) {
             add_interface_specs(&mut interfaces, &vrfconfig.interfaces);
-            let main_vtep = internal.vtep.as_ref().unwrap_or_else(|| unreachable!());
+            add_neighbor_specs(&mut neighbors, &vrfconfig.interfaces);
+            let main_vtep = internal.vtep.as_ref().ok_or_else(|| {
+                RequiredInformationBaseBuilderError::ValidationError(
+                    "VPCs are configured but there is no vtep".to_string(),
+                )
+            })?;
             let vtep_ip = match main_vtep.address {
                 UnicastIpAddr::V4(vtep_ip) => vtep_ip,
                 UnicastIpAddr::V6(unsupported_ip) => {
@@ -382,7 +1224,11 @@ impl TryFrom<&InternalConfig> for RequiredInformationBase {
             vrf.controller(None);
             vrf.admin_state(AdminState::Up);
             match vrfconfig.tableid {
-                None => unreachable!(),
+                None => {
+                    return Err(RequiredInformationBaseBuilderError::ValidationError(
+                        format!("no route table set for vrf {}", vrfconfig.name),
+                    ));
+                }
                 Some(route_table_id) => {
                     debug!("route_table set for config: {vrfconfig:?}");
                     vrf.properties(InterfacePropertiesSpec::Vrf(VrfPropertiesSpec {
@@ -390,8 +1236,9 @@ impl TryFrom<&InternalConfig> for RequiredInformationBase {
                     }));
                     match &vrfconfig.vpc_id {
                         None => {
-                            error!("no vpc_id set for config: {vrfconfig:?}");
-                            panic!("no vpc_id set for config: {vrfconfig:?}");
+                            return Err(RequiredInformationBaseBuilderError::ValidationError(
+                                format!("no vpc_id set for vrf {}", vrfconfig.name),
+                            ));
                         }
                         Some(id) => {
                             vrf.name(id.vrf_name());
@@ -406,7 +1253,12 @@ impl TryFrom<&InternalConfig> for RequiredInformationBase {
                         vlan_protocol: EthType::VLAN,
                     }));
                     vtep.properties(InterfacePropertiesSpec::Vtep(VtepPropertiesSpec {
-                        vni: vrfconfig.vni.expect("vni not set"),
+                        vni: vrfconfig.vni.ok_or_else(|| {
+                            RequiredInformationBaseBuilderError::ValidationError(format!(
+                                "no vni set for vrf {}",
+                                vrfconfig.name
+                            ))
+                        })?,
                         local: vtep_ip,
                         ttl: VtepConfig::TTL,
                         port: Vxlan::PORT,
@@ -414,6 +1266,8 @@ impl TryFrom<&InternalConfig> for RequiredInformationBase {
                     vrf.mac(Some(main_vtep.mac));
                     bridge.mac(Some(main_vtep.mac));
                     vtep.mac(Some(main_vtep.mac));
+                    bridge.mtu(main_vtep.mtu);
+                    vtep.mtu(main_vtep.mtu);
                 }
             }
             match (vrf.build(), bridge.build(), vtep.build()) {
@@ -506,20 +1360,347 @@ impl TryFrom<&InternalConfig> for RequiredInformationBase {
         }
 
         // default VRF
-        let vrfconfig = internal
-            .vrfs
-            .default_vrf_config()
-            .unwrap_or_else(|| unreachable!());
+        let vrfconfig = internal.vrfs.default_vrf_config().ok_or_else(|| {
+            RequiredInformationBaseBuilderError::ValidationError("no default vrf".to_string())
+        })?;
         add_interface_specs(&mut interfaces, &vrfconfig.interfaces);
+        add_neighbor_specs(&mut neighbors, &vrfconfig.interfaces);
 
         rb_builder.interfaces(interfaces);
         rb_builder.vteps(vteps);
         rb_builder.vrfs(vrfs);
         rb_builder.associations(associations);
+        rb_builder.neighbors(neighbors);
         rb_builder.build()
     }
 }
 
+#[cfg(test)]
+mod test {
+    use super::*;
+    use config::DeviceConfig;
+    use config::external::overlay::vpc::VpcId;
+    use config::internal::interfaces::interface::{IfEthConfig, InterfaceConfig};
+    use config::internal::routing::vrf::VrfConfig;
+    use lifecycle::CancellationToken;
+    use net::eth::mac::Mac;
+    use net::interface::{InterfaceIndex, Mtu, OperationalState};
+
+    #[test]
+    fn test_required_information_base_invalid_config() {
+        // no default VRF
+        let internal = InternalConfig::new("gw", DeviceConfig::new());
+        let err = RequiredInformationBase::try_from(&internal).unwrap_err();
+        assert!(err.to_string().contains("no default vrf"), "{err}");
+
+        // a VPC but no vtep
+        let mut internal = InternalConfig::new("gw", DeviceConfig::new());
+        internal
+            .add_vrf_config(VrfConfig::new("default", None, true))
+            .unwrap();
+        let vni = Vni::new_checked(3000).unwrap();
+        let vrf = VrfConfig::new("vpc-1", Some(vni), false)
+            .set_table_id(RouteTableId::try_from(3000).unwrap());
+        internal.add_vrf_config(vrf).unwrap();
+        let err = RequiredInformationBase::try_from(&internal).unwrap_err();
+        assert!(err.to_string().contains("no vtep"), "{err}");
+    }
+
+    #[test]
+    fn test_required_neighbors() {
+        let mac = Mac::from([0x02, 0, 0, 0, 0, 0x01]);
+        let mut vrf = VrfConfig::new("default", None, true);
+        vrf.add_interface_config(
+            InterfaceConfig::new(
+                "eth0",
+                InterfaceType::Ethernet(IfEthConfig { mac: None }),
+                false,
+            )
+            .add_static_neighbor("10.0.0.1".parse().unwrap(), mac),
+        );
+        let mut internal = InternalConfig::new("gw", DeviceConfig::new());
+        internal.add_vrf_config(vrf).unwrap();
+        let required = RequiredInformationBase::try_from(&internal).unwrap();
+        let neighbors: Vec<_> = required.neighbors.iter().collect();
+        assert_eq!(
+            neighbors,
+            [&NeighborSpec {
+                interface: InterfaceName::try_from("eth0-tap").unwrap(),
+                address: "10.0.0.1".parse().unwrap(),
+                mac: SourceMac::new(mac).unwrap(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_required_information_base_exclude() {
+        let mac = Mac::from([0x02, 0, 0, 0, 0, 0x01]);
+        let mut vrf = VrfConfig::new("default", None, true);
+        for name in ["eth0", "mgmt0"] {
+            vrf.add_interface_config(
+                InterfaceConfig::new(
+                    name,
+                    InterfaceType::Ethernet(IfEthConfig { mac: None }),
+                    false,
+                )
+                .add_static_neighbor("10.0.0.1".parse().unwrap(), mac),
+            );
+        }
+        let mut internal = InternalConfig::new("gw", DeviceConfig::new());
+        internal.add_vrf_config(vrf).unwrap();
+        let mut required = RequiredInformationBase::try_from(&internal).unwrap();
+
+        let managed = InterfaceName::try_from("eth0-tap").unwrap();
+        let unmanaged = InterfaceName::try_from("mgmt0-tap").unwrap();
+        let excluded = required.exclude(&BTreeSet::from([unmanaged.clone()]));
+        assert_eq!(excluded, [unmanaged.clone()]);
+        assert!(required.interfaces.get_by_name(&unmanaged).is_none());
+        assert!(required.interfaces.get_by_name(&managed).is_some());
+        assert!(
+            required
+                .neighbors
+                .iter()
+                .all(|spec| spec.interface == managed)
+        );
+        assert_eq!(required.neighbors.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_leaves_unmanaged_interfaces() {
+        let (connection, handle, _) = rtnetlink::new_connection().unwrap();
+        tokio::spawn(connection);
+        let name = InterfaceName::try_from("mgmt0").unwrap();
+        let manager = VpcManager::<RequiredInformationBase>::builder()
+            .handle(Arc::new(handle))
+            .unmanaged([name.clone()])
+            .build()
+            .unwrap();
+        assert!(manager.is_unmanaged(&name));
+
+        // an unmanaged interface which is observed but not required is not removed
+        let mut observed = ObservedInformationBase::default();
+        observed
+            .interfaces
+            .try_insert(Interface {
+                index: InterfaceIndex::try_new(1000).unwrap(),
+                name: name.clone(),
+                mac: None,
+                mtu: None,
+                admin_state: AdminState::Up,
+                operational_state: OperationalState::Up,
+                carrier: None,
+                speed: None,
+                controller: None,
+                properties: InterfaceProperties::Tap,
+            })
+            .unwrap();
+        let mut required = RequiredInformationBase::default();
+        let report = manager.reconcile(&mut required, &observed).await;
+        assert!(report.is_reconciled(), "{:?}", report.ops());
+
+        // nor is it updated, or created, when required
+        let spec = InterfaceSpecBuilder::default()
+            .name(name)
+            .admin_state(AdminState::Down)
+            .properties(InterfacePropertiesSpec::Tap)
+            .build()
+            .unwrap();
+        required.interfaces.try_insert(spec).unwrap();
+        let report = manager.reconcile(&mut required.clone(), &observed).await;
+        assert!(report.is_reconciled(), "{:?}", report.ops());
+        let report = manager
+            .reconcile(&mut required, &ObservedInformationBase::default())
+            .await;
+        assert!(report.is_reconciled(), "{:?}", report.ops());
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_tells_admin_changes_to_monitor() {
+        let (connection, handle, _) = rtnetlink::new_connection().unwrap();
+        tokio::spawn(connection);
+        let monitor = Arc::new(InterfaceMonitor::new(CancellationToken::new(), &[]));
+        let manager = VpcManager::<RequiredInformationBase>::builder()
+            .handle(Arc::new(handle))
+            .build()
+            .unwrap()
+            .with_interface_monitor(monitor.clone());
+
+        let name = InterfaceName::try_from("reconcile-tap").unwrap();
+        let interface = |index, admin_state| Interface {
+            index: InterfaceIndex::try_new(index).unwrap(),
+            name: name.clone(),
+            mac: None,
+            mtu: None,
+            admin_state,
+            operational_state: OperationalState::Down,
+            carrier: None,
+            speed: None,
+            controller: None,
+            properties: InterfaceProperties::Tap,
+        };
+        let mut observed = ObservedInformationBase::default();
+        let down = interface(1000, AdminState::Down);
+        observed.interfaces.try_insert(down.clone()).unwrap();
+        let spec = InterfaceSpecBuilder::default()
+            .name(name.clone())
+            .admin_state(AdminState::Up)
+            .properties(InterfacePropertiesSpec::Tap)
+            .build()
+            .unwrap();
+        let mut required = RequiredInformationBase::default();
+        required.interfaces.try_insert(spec.clone()).unwrap();
+
+        // the interface does not exist, so the update fails, but it is expected nonetheless
+        let _ = manager.reconcile(&mut required, &observed).await;
+        assert!(monitor.expects_reconcile(down.index));
+
+        // nothing is expected of interfaces whose admin state is right, or in other namespaces
+        let up = interface(1001, AdminState::Up);
+        manager.expect_admin_change(&spec, &up, None);
+        assert!(!monitor.expects_reconcile(up.index));
+        let other = interface(1002, AdminState::Down);
+        let netns = NetnsName::try_from("tenant").unwrap();
+        manager.expect_admin_change(&spec, &other, Some(&netns));
+        assert!(!monitor.expects_reconcile(other.index));
+    }
+
+    #[test]
+    fn test_required_vtep_mtu() {
+        let mut internal = InternalConfig::new("gw", DeviceConfig::new());
+        internal
+            .add_vrf_config(VrfConfig::new("default", None, true))
+            .unwrap();
+        let mtu = Mtu::try_from(9000).unwrap();
+        let mac = SourceMac::new(Mac::from([0x02, 0, 0, 0, 0, 0x01])).unwrap();
+        let address = "192.168.0.1".parse::<UnicastIpAddr>().unwrap();
+        internal.set_vtep(Some(VtepConfig::new(address, mac).set_mtu(mtu)));
+        let vni = Vni::new_checked(3000).unwrap();
+        let vpc_id = VpcId::try_from("AAAAA").unwrap();
+        let vrf = VrfConfig::new("vpc-1", Some(vni), false)
+            .set_table_id(RouteTableId::try_from(3000).unwrap())
+            .set_vpc_id(vpc_id.clone());
+        internal.add_vrf_config(vrf).unwrap();
+
+        let required = RequiredInformationBase::try_from(&internal).unwrap();
+        let mtu_of = |name: InterfaceName| required.interfaces.get_by_name(&name).unwrap().mtu;
+        assert_eq!(mtu_of(vpc_id.vtep_name()), Some(mtu));
+        assert_eq!(mtu_of(vpc_id.bridge_name()), Some(mtu));
+        assert_eq!(mtu_of(vpc_id.vrf_name()), None);
+    }
+
+    #[test]
+    fn test_required_information_base_normalization() {
+        let mac = Mac::from([0x02, 0, 0, 0, 0, 0x01]);
+        let mut vrf = VrfConfig::new("default", None, true);
+        vrf.add_interface_config(
+            InterfaceConfig::new(
+                "eth0",
+                InterfaceType::Ethernet(IfEthConfig { mac: None }),
+                false,
+            )
+            .add_static_neighbor("::ffff:10.0.0.1".parse().unwrap(), mac),
+        );
+        let mut internal = InternalConfig::new("gw", DeviceConfig::new());
+        internal.add_vrf_config(vrf).unwrap();
+        let mut required = RequiredInformationBase::try_from(&internal).unwrap();
+        let vni = Vni::new_checked(3000).unwrap();
+        required
+            .vteps
+            .try_insert(VtepPropertiesSpec {
+                vni,
+                local: "192.168.0.1".parse().unwrap(),
+                ttl: 0,
+                port: Vxlan::PORT,
+            })
+            .unwrap();
+
+        required.normalize();
+        let addresses: Vec<_> = required.neighbors.iter().map(|n| n.address).collect();
+        assert_eq!(addresses, ["10.0.0.1".parse::<IpAddr>().unwrap()]);
+        let vtep = required.vteps.get_by_vni(&vni).unwrap();
+        assert_eq!(vtep.ttl, VtepPropertiesSpec::DEFAULT_TTL);
+        let tap = InterfaceName::try_from("eth0-tap").unwrap();
+        assert!(required.interfaces.get_by_name(&tap).is_some());
+    }
+
+    #[test]
+    fn test_reconcile_report() {
+        let mut report = ReconcileReport::default();
+        assert!(report.is_reconciled());
+        assert_eq!(report.to_string(), "reconciled");
+
+        let name = InterfaceName::try_from("vrf1").unwrap();
+        report.push(ReconcileOp {
+            object: ReconcileObject::Interface(name.clone()),
+            action: ReconcileAction::Create,
+            result: Ok(()),
+        });
+        report.push(ReconcileOp {
+            object: ReconcileObject::Neighbor {
+                interface: name,
+                address: "10.0.0.1".parse().unwrap(),
+            },
+            action: ReconcileAction::Remove,
+            result: Err(rtnetlink::Error::RequestFailed),
+        });
+        assert!(!report.is_reconciled());
+        assert_eq!(report.ops().len(), 2);
+        let failures: Vec<_> = report.failures().map(|op| op.action).collect();
+        assert_eq!(failures, [ReconcileAction::Remove]);
+        assert_eq!(
+            report.ops()[1].object.to_string(),
+            "neighbor 10.0.0.1 on interface vrf1"
+        );
+        assert_eq!(report.to_string(), "2 operations, 1 failed");
+        assert_eq!(
+            report.ops()[1].error_kind(),
+            Some(ReconcileErrorKind::Other)
+        );
+        assert_eq!(report.permanent_failures().count(), 0);
+        assert_eq!(
+            report.describe_failures(),
+            format!(
+                "remove neighbor 10.0.0.1 on interface vrf1 failed (other): {}",
+                rtnetlink::Error::RequestFailed
+            )
+        );
+    }
+
+    #[test]
+    fn test_reconcile_error_kinds() {
+        let netlink_error = |errno: i32| {
+            let mut message = rtnetlink::packet_core::ErrorMessage::default();
+            message.code = std::num::NonZeroI32::new(-errno);
+            rtnetlink::Error::NetlinkError(message)
+        };
+        let kind = |errno| ReconcileErrorKind::from(&netlink_error(errno));
+        // errno values of linux
+        assert_eq!(kind(16), ReconcileErrorKind::Transient); // EBUSY
+        assert_eq!(kind(17), ReconcileErrorKind::Conflict); // EEXIST
+        assert_eq!(kind(2), ReconcileErrorKind::Missing); // ENOENT
+        assert_eq!(kind(95), ReconcileErrorKind::Unsupported); // EOPNOTSUPP
+        assert_eq!(kind(1), ReconcileErrorKind::Denied); // EPERM
+        assert_eq!(kind(22), ReconcileErrorKind::Invalid); // EINVAL
+
+        let mut report = ReconcileReport::default();
+        report.push(ReconcileOp {
+            object: ReconcileObject::Interface(InterfaceName::try_from("vtep").unwrap()),
+            action: ReconcileAction::Create,
+            result: Err(netlink_error(95)),
+        });
+        report.push(ReconcileOp {
+            object: ReconcileObject::Interface(InterfaceName::try_from("vrf1").unwrap()),
+            action: ReconcileAction::Create,
+            result: Err(netlink_error(17)),
+        });
+        let permanent: Vec<_> = report
+            .permanent_failures()
+            .map(|op| op.object.to_string())
+            .collect();
+        assert_eq!(permanent, ["interface vtep"]);
+    }
+}
+
 #[cfg(any(test, feature = "bolero"))]
 mod contract {
     use crate::vpc_manager::{RequiredInformationBase, Vpc, VpcDiscriminant};
@@ -527,6 +1708,7 @@ mod contract {
     use interface_manager::interface::{
         InterfaceAssociationSpec, InterfacePropertiesSpec, InterfaceSpec,
     };
+    use interface_manager::offload::OffloadSpec;
     use net::interface::AdminState;
 
     impl TypeGenerator for VpcDiscriminant {
@@ -561,6 +1743,8 @@ mod contract {
             for _ in 0..num_vpcs {
                 let mut interface: InterfaceSpec = driver.produce()?;
                 interface.controller = None;
+                // the devices under test may not support arbitrary offloads
+                interface.offloads = OffloadSpec::default();
                 interface.admin_state = AdminState::Up;
                 match &interface.properties {
                     InterfacePropertiesSpec::Bridge(_) => {
@@ -0,0 +1,484 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! A view of the kernel network interfaces joining what the configuration requires of them
+//! ([`RequiredInformationBase`]) with what was last observed ([`ObservedInformationBase`]),
+//! along with the last operation that reconciliation carried out on each of them.
+//!
+//! The view also keeps the operational state, carrier and speed last observed for the interfaces
+//! that the configuration requires, and exports them as metrics.
+
+use super::{
+    ObservedInformationBase, ReconcileAction, ReconcileObject, ReconcileReport,
+    RequiredInformationBase,
+};
+use chrono::{DateTime, Local};
+use common::cliprovider::{CliDataProvider, Heading};
+use concurrency::sync::{Arc, Mutex};
+use metrics::{Gauge, Unit};
+use net::eth::mac::SourceMac;
+use net::interface::{AdminState, InterfaceName, Mtu, OperationalState};
+use stats::{MetricSpec, Register};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Display;
+
+/// The settings of an interface that reconciliation acts upon
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Settings {
+    admin_state: AdminState,
+    controller: Option<InterfaceName>,
+    mtu: Option<Mtu>,
+    mac: Option<SourceMac>,
+}
+
+/// A setting whose observed value differs from the required one
+#[derive(Debug, PartialEq, Eq)]
+struct Divergence {
+    setting: &'static str,
+    required: String,
+    observed: String,
+}
+
+fn show<T: Display>(value: Option<&T>) -> String {
+    value.map_or_else(|| "-".to_string(), ToString::to_string)
+}
+
+impl Settings {
+    /// The settings in which `observed` diverges from `self`. The MTU and MAC are only required
+    /// if specified, while interfaces without a required controller must not be controlled.
+    fn divergences(&self, observed: &Settings) -> Vec<Divergence> {
+        let mut divergences = vec![];
+        let mut check = |setting, differs: bool, required: String, observed: String| {
+            if differs {
+                divergences.push(Divergence {
+                    setting,
+                    required,
+                    observed,
+                });
+            }
+        };
+        check(
+            "admin state",
+            self.admin_state != observed.admin_state,
+            self.admin_state.to_string(),
+            observed.admin_state.to_string(),
+        );
+        check(
+            "controller",
+            self.controller != observed.controller,
+            show(self.controller.as_ref()),
+            show(observed.controller.as_ref()),
+        );
+        check(
+            "mtu",
+            self.mtu.is_some() && self.mtu != observed.mtu,
+            show(self.mtu.as_ref()),
+            show(observed.mtu.as_ref()),
+        );
+        check(
+            "mac",
+            self.mac.is_some() && self.mac != observed.mac,
+            show(self.mac.as_ref()),
+            show(observed.mac.as_ref()),
+        );
+        divergences
+    }
+}
+
+/// The last operation that reconciliation carried out on an interface
+#[derive(Clone, Debug)]
+struct LastOp {
+    action: ReconcileAction,
+    time: DateTime<Local>,
+    error: Option<String>,
+}
+
+impl Display for LastOp {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at {}", self.action, self.time.format("%H:%M:%S"))?;
+        if self.error.is_some() {
+            write!(f, " (failed)")?;
+        }
+        Ok(())
+    }
+}
+
+/// The observed state of the link of an interface
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LinkState {
+    pub oper: OperationalState,
+    pub carrier: Option<bool>,
+    /// Link speed in Mb/s
+    pub speed: Option<u32>,
+}
+
+/// The metrics reporting the [`LinkState`] of a required interface
+struct LinkGauges {
+    oper_up: Gauge,
+    carrier: Gauge,
+    speed: Gauge,
+}
+
+impl LinkGauges {
+    fn new(name: &InterfaceName) -> Self {
+        let spec = |id: &str, unit| {
+            let labels = vec![("interface".to_string(), name.to_string())];
+            MetricSpec::new(id, unit, labels)
+        };
+        Self {
+            oper_up: spec("interface_oper_up", Unit::Count).register().metric,
+            carrier: spec("interface_carrier", Unit::Count).register().metric,
+            speed: spec("interface_speed", Unit::MegabitsPerSecond)
+                .register()
+                .metric,
+        }
+    }
+
+    /// Report the state of the link, or a down link if the interface was not observed
+    fn set(&self, link: Option<&LinkState>) {
+        let flag = |value: bool| if value { 1.0 } else { 0.0 };
+        let oper_up = link.is_some_and(|link| link.oper == OperationalState::Up);
+        let carrier = link.is_some_and(|link| link.carrier == Some(true));
+        let speed = link.and_then(|link| link.speed).unwrap_or_default();
+        self.oper_up.set(flag(oper_up));
+        self.carrier.set(flag(carrier));
+        self.speed.set(f64::from(speed));
+    }
+}
+
+impl std::fmt::Debug for LinkGauges {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LinkGauges").finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Default)]
+struct InterfaceStates {
+    required: BTreeMap<InterfaceName, Settings>,
+    observed: BTreeMap<InterfaceName, Settings>,
+    links: BTreeMap<InterfaceName, LinkState>,
+    gauges: BTreeMap<InterfaceName, LinkGauges>,
+    last_ops: BTreeMap<InterfaceName, LastOp>,
+    observed_at: Option<DateTime<Local>>,
+}
+
+/// The state of the kernel network interfaces, required and observed. It is updated by the VPC
+/// manager on every reconciliation pass, and shown by the cli.
+#[derive(Clone, Debug, Default)]
+pub struct InterfaceView(Arc<Mutex<InterfaceStates>>);
+
+impl InterfaceView {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Update the view with the requirements and the observation of a reconciliation pass
+    pub(crate) fn update(
+        &self,
+        required: &RequiredInformationBase,
+        observed: &ObservedInformationBase,
+    ) {
+        let required: BTreeMap<_, _> = required
+            .interfaces
+            .iter()
+            .map(|(_, spec)| {
+                let controller = required
+                    .associations
+                    .get_by_name(&spec.name)
+                    .and_then(|association| association.controller_name.clone());
+                let settings = Settings {
+                    admin_state: spec.admin_state,
+                    controller,
+                    mtu: spec.mtu,
+                    mac: spec.mac,
+                };
+                (spec.name.clone(), settings)
+            })
+            .collect();
+        let links: BTreeMap<_, _> = observed
+            .interfaces
+            .iter()
+            .filter(|(_, interface)| required.contains_key(&interface.name))
+            .map(|(_, interface)| {
+                let link = LinkState {
+                    oper: interface.operational_state,
+                    carrier: interface.carrier,
+                    speed: interface.speed,
+                };
+                (interface.name.clone(), link)
+            })
+            .collect();
+        let observed = observed
+            .interfaces
+            .iter()
+            .map(|(_, interface)| {
+                let controller = interface
+                    .controller
+                    .and_then(|index| observed.interfaces.get_by_index(&index))
+                    .map(|controller| controller.name.clone());
+                let settings = Settings {
+                    admin_state: interface.admin_state,
+                    controller,
+                    mtu: interface.mtu,
+                    mac: interface.mac,
+                };
+                (interface.name.clone(), settings)
+            })
+            .collect();
+        let mut states = self.0.lock();
+        states.gauges.retain(|name, _| required.contains_key(name));
+        for name in required.keys() {
+            states
+                .gauges
+                .entry(name.clone())
+                .or_insert_with(|| LinkGauges::new(name))
+                .set(links.get(name));
+        }
+        states.required = required;
+        states.observed = observed;
+        states.links = links;
+        states.observed_at = Some(Local::now());
+    }
+
+    /// The link state last observed for each of the interfaces required by the configuration
+    #[must_use]
+    pub fn link_states(&self) -> BTreeMap<InterfaceName, LinkState> {
+        self.0.lock().links.clone()
+    }
+
+    /// Record the operations of a reconciliation pass on interfaces
+    pub(crate) fn record(&self, report: &ReconcileReport) {
+        let time = Local::now();
+        let mut states = self.0.lock();
+        for op in report.ops() {
+            let ReconcileObject::Interface(name) = &op.object else {
+                continue;
+            };
+            let last = LastOp {
+                action: op.action,
+                time,
+                error: op.result.as_ref().err().map(ToString::to_string),
+            };
+            states.last_ops.insert(name.clone(), last);
+        }
+    }
+}
+
+macro_rules! INTERFACE_FMT {
+    ($name:expr, $status:expr, $admin:expr, $controller:expr, $mtu:expr, $mac:expr, $op:expr) => {
+        format_args!(
+            " {:<16} {:<10} {:<6} {:<16} {:>6} {:<18} {}",
+            $name, $status, $admin, $controller, $mtu, $mac, $op
+        )
+    };
+}
+
+impl Display for InterfaceStates {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let Some(observed_at) = self.observed_at else {
+            return writeln!(f, " interfaces were not observed yet");
+        };
+        writeln!(
+            f,
+            " observed at {}\n",
+            observed_at.format("%Y-%m-%d %H:%M:%S")
+        )?;
+        writeln!(
+            f,
+            "{}",
+            INTERFACE_FMT!(
+                "interface",
+                "status",
+                "admin",
+                "controller",
+                "mtu",
+                "mac",
+                "last operation"
+            )
+        )?;
+        let names: BTreeSet<_> = self.required.keys().chain(self.observed.keys()).collect();
+        for name in names {
+            let required = self.required.get(name);
+            let observed = self.observed.get(name);
+            let divergences = match (required, observed) {
+                (Some(required), Some(observed)) => required.divergences(observed),
+                _ => vec![],
+            };
+            let status = match (required, observed) {
+                (None, _) => "unmanaged",
+                (Some(_), None) => "missing",
+                (Some(_), Some(_)) if divergences.is_empty() => "in sync",
+                (Some(_), Some(_)) => "diverged",
+            };
+            // show the observed settings, or the required ones if the interface is missing
+            let Some(settings) = observed.or(required) else {
+                unreachable!()
+            };
+            let last_op = self.last_ops.get(name);
+            writeln!(
+                f,
+                "{}",
+                INTERFACE_FMT!(
+                    name.to_string(),
+                    status,
+                    settings.admin_state.to_string(),
+                    show(settings.controller.as_ref()),
+                    show(settings.mtu.as_ref()),
+                    show(settings.mac.as_ref()),
+                    show(last_op)
+                )
+            )?;
+            for divergence in &divergences {
+                writeln!(
+                    f,
+                    "   ! {}: required {}, observed {}",
+                    divergence.setting, divergence.required, divergence.observed
+                )?;
+            }
+            if let Some(error) = last_op.and_then(|op| op.error.as_ref()) {
+                writeln!(f, "   ! last operation failed: {error}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CliDataProvider for InterfaceView {
+    fn provide(&self) -> String {
+        Heading("Interfaces: required vs observed").to_string() + &self.0.lock().to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::vpc_manager::ReconcileOp;
+    use interface_manager::interface::{InterfacePropertiesSpec, InterfaceSpec};
+    use interface_manager::offload::OffloadSpec;
+    use net::eth::mac::Mac;
+    use net::interface::{Interface, InterfaceIndex, InterfaceProperties};
+
+    fn settings() -> Settings {
+        Settings {
+            admin_state: AdminState::Up,
+            controller: Some(InterfaceName::try_from("vrf1").unwrap()),
+            mtu: None,
+            mac: None,
+        }
+    }
+
+    #[test]
+    fn test_divergences() {
+        let required = settings();
+        let mut observed = settings();
+        observed.mtu = Some(Mtu::try_from(9000).unwrap());
+        observed.mac = Some(SourceMac::new(Mac::from([0x02, 0, 0, 0, 0, 0x01])).unwrap());
+        // the mtu and mac are not required
+        assert!(required.divergences(&observed).is_empty());
+
+        observed.admin_state = AdminState::Down;
+        observed.controller = None;
+        let divergences = required.divergences(&observed);
+        let diverged: Vec<_> = divergences.iter().map(|d| d.setting).collect();
+        assert_eq!(diverged, ["admin state", "controller"]);
+        assert_eq!(divergences[1].required, "vrf1");
+        assert_eq!(divergences[1].observed, "-");
+    }
+
+    #[test]
+    fn test_interface_view() {
+        let view = InterfaceView::new();
+        assert!(view.provide().contains("not observed yet"));
+
+        let name = InterfaceName::try_from("vtep").unwrap();
+        let mut report = ReconcileReport::default();
+        report.push(ReconcileOp {
+            object: ReconcileObject::Interface(name.clone()),
+            action: ReconcileAction::Update,
+            result: Err(rtnetlink::Error::RequestFailed),
+        });
+        view.record(&report);
+        {
+            let mut states = view.0.lock();
+            states.required.insert(name.clone(), settings());
+            states.observed_at = Some(Local::now());
+        }
+        let output = view.provide();
+        assert!(output.contains("missing"), "{output}");
+        assert!(output.contains("last operation failed"), "{output}");
+    }
+
+    fn observed(index: u32, name: &str, carrier: Option<bool>, speed: Option<u32>) -> Interface {
+        Interface {
+            index: InterfaceIndex::try_from(index).unwrap(),
+            name: InterfaceName::try_from(name).unwrap(),
+            mac: None,
+            mtu: None,
+            admin_state: AdminState::Up,
+            operational_state: if carrier == Some(true) {
+                OperationalState::Up
+            } else {
+                OperationalState::Down
+            },
+            carrier,
+            speed,
+            controller: None,
+            properties: InterfaceProperties::Tap,
+        }
+    }
+
+    #[test]
+    fn test_link_states() {
+        let view = InterfaceView::new();
+        let mut required = RequiredInformationBase::default();
+        for name in ["eth1", "eth2", "eth3"] {
+            required.interfaces.insert(InterfaceSpec {
+                name: InterfaceName::try_from(name).unwrap(),
+                mac: None,
+                mtu: None,
+                admin_state: AdminState::Up,
+                controller: None,
+                properties: InterfacePropertiesSpec::Tap,
+                offloads: OffloadSpec::default(),
+                netns: None,
+            });
+        }
+        let mut observed_base = ObservedInformationBase::default();
+        observed_base
+            .interfaces
+            .insert(observed(1, "eth1", Some(true), Some(25000)));
+        observed_base
+            .interfaces
+            .insert(observed(2, "eth2", Some(false), None));
+        // not required
+        observed_base
+            .interfaces
+            .insert(observed(9, "eth9", Some(true), Some(100)));
+        view.update(&required, &observed_base);
+
+        let links = view.link_states();
+        let name = |name: &str| InterfaceName::try_from(name).unwrap();
+        assert_eq!(links.len(), 2);
+        assert_eq!(
+            links[&name("eth1")],
+            LinkState {
+                oper: OperationalState::Up,
+                carrier: Some(true),
+                speed: Some(25000),
+            }
+        );
+        assert_eq!(
+            links[&name("eth2")],
+            LinkState {
+                oper: OperationalState::Down,
+                carrier: Some(false),
+                speed: None,
+            }
+        );
+        // the missing interface has gauges, reporting a down link
+        let states = view.0.lock();
+        assert!(states.gauges.contains_key(&name("eth3")));
+        assert!(!states.gauges.contains_key(&name("eth9")));
+    }
+}
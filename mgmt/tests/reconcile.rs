@@ -20,7 +20,8 @@ use net::vxlan::Vxlan;
 use rekon::{Observe, Reconcile};
 use rtnetlink::sys::AsyncSocket;
 use std::net::Ipv4Addr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use test_utils::scale::Scale;
 use test_utils::with_caps;
 use tracing::info;
 use tracing_test::traced_test;
@@ -67,6 +68,7 @@ fn reconcile_fuzz() {
                 while !manager
                     .reconcile(&mut rib, &manager.observe().await.unwrap())
                     .await
+                    .is_reconciled()
                 {
                     required_passes += 1;
                     if required_passes >= 30 {
@@ -77,11 +79,141 @@ fn reconcile_fuzz() {
                     manager
                         .reconcile(&mut rib, &manager.observe().await.unwrap())
                         .await
+                        .is_reconciled()
                 )
             });
         });
 }
 
+/// Resilience suite: reconcile an arbitrary required state, break the result out-of-band according
+/// to an arbitrary [`ChaosPlan`], and assert that the reconciler converges back within a bound.
+#[cfg(feature = "chaos")]
+#[test]
+#[n_vm::in_vm]
+#[wrap(with_caps([Capability::CAP_NET_ADMIN]))]
+#[cfg_attr(not(emulated), traced_test)]
+fn reconcile_chaos() {
+    use mgmt::vpc_manager::chaos::{ChaosMonkey, ChaosPlan, converge};
+
+    /// Maximum number of reconcile passes permitted to recover from a round of chaos.
+    const CONVERGENCE_BOUND: usize = 30;
+    /// Number of rounds of chaos inflicted on each generated required state.
+    const ROUNDS: usize = 3;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .enable_time()
+        .build()
+        .unwrap();
+
+    let handle = runtime.block_on(async {
+        let Ok((connection, handle, _)) = rtnetlink::new_connection() else {
+            panic!("failed to create connection");
+        };
+        tokio::spawn(connection);
+        // See reconcile_fuzz for why std's Mutex is used here.
+        #[allow(clippy::disallowed_types)]
+        std::sync::Mutex::new(Arc::new(handle)) // nosemgrep: rust-no-direct-std-sync-import
+    });
+    bolero::check!()
+        .with_type()
+        .with_test_time(Duration::from_secs(2))
+        .for_each(|(rib, plan): &(RequiredInformationBase, ChaosPlan)| {
+            runtime.block_on(async {
+                let handle = match handle.lock() {
+                    Ok(guard) => (*guard).clone(),
+                    Err(poison) => {
+                        panic!("mutex poisoned: {poison}");
+                    }
+                };
+                let mut rib = rib.clone();
+                let manager = VpcManager::<RequiredInformationBase>::new(handle.clone());
+                let monkey = ChaosMonkey::new(handle);
+                converge(&manager, &mut rib, CONVERGENCE_BOUND)
+                    .await
+                    .expect("initial reconcile failed to converge");
+                for round in 0..ROUNDS {
+                    let observed = manager.observe().await.unwrap();
+                    let inflicted = monkey.unleash(plan, &rib, &observed).await;
+                    let passes = converge(&manager, &mut rib, CONVERGENCE_BOUND)
+                        .await
+                        .unwrap_or_else(|err| {
+                            panic!("round {round} ({inflicted} disruptions): {err}")
+                        });
+                    info!(
+                        "round {round}: recovered from {inflicted} disruptions in {passes} passes"
+                    );
+                }
+            });
+        });
+}
+
+/// Build the required information base of some generated requirements
+fn scaled_rib(scale: Scale) -> RequiredInformationBase {
+    let generated = scale.generate();
+    RequiredInformationBaseBuilder::default()
+        .interfaces(generated.interfaces)
+        .vrfs(generated.vrfs)
+        .vteps(generated.vteps)
+        .associations(generated.associations)
+        .build()
+        .unwrap()
+}
+
+/// Stress suite: reconcile thousands of interfaces, then remove most of them, timing every pass.
+#[test]
+#[ignore = "long-running stress test"]
+#[n_vm::in_vm]
+#[wrap(with_caps([Capability::CAP_NET_ADMIN]))]
+#[cfg_attr(not(emulated), traced_test)]
+fn reconcile_scale() {
+    /// Maximum number of reconcile passes permitted to converge.
+    const CONVERGENCE_BOUND: usize = 30;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_io()
+        .enable_time()
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let Ok((mut connection, handle, _)) = rtnetlink::new_connection() else {
+            panic!("failed to create connection");
+        };
+        connection
+            .socket_mut()
+            .socket_mut()
+            .set_rx_buf_sz(8 * 1024 * 1024)
+            .unwrap();
+        tokio::spawn(connection);
+        let manager = VpcManager::<RequiredInformationBase>::new(Arc::new(handle));
+
+        for scale in [Scale::new(512, 2, 2), Scale::new(64, 1, 1)] {
+            let mut rib = scaled_rib(scale);
+            let mut passes = 0;
+            loop {
+                let start = Instant::now();
+                let observed = manager.observe().await.unwrap();
+                let observe_time = start.elapsed();
+                let report = manager.reconcile(&mut rib, &observed).await;
+                info!(
+                    "{} interfaces, pass {passes}: observed in {observe_time:?}, reconciled in {:?}: {report}",
+                    scale.interfaces(),
+                    start.elapsed() - observe_time,
+                );
+                if report.is_reconciled() {
+                    break;
+                }
+                passes += 1;
+                assert!(
+                    passes < CONVERGENCE_BOUND,
+                    "took more than {CONVERGENCE_BOUND} passes to reconcile {} interfaces",
+                    scale.interfaces()
+                );
+            }
+        }
+    });
+}
+
 #[allow(clippy::too_many_lines)] // this is an integration test and is expected to be long
 #[tokio::test]
 #[wrap(with_caps([Capability::CAP_NET_ADMIN]))]
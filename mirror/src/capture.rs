@@ -0,0 +1,584 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Packet capture at taps of the pipeline.
+//!
+//! [`PcapTap`] stages may be inserted anywhere in the pipeline. They let all packets through
+//! untouched and, while a capture is started with the [`CaptureControl`] they share, copy those
+//! matching its filter to a writer thread. The writer writes the copies as pcapng to a new file, or
+//! streams them to a unix socket that e.g. `wireshark -k -i <socket>` reads from. Both are named
+//! in the capture directory, outside of which captures are never written. Copies are
+//! dropped rather than slowing workers down. Packets the pipeline is done with are captured too,
+//! with the reason in the comment of the packet, to tell the decisions of the stages before the
+//! tap.
+
+use std::fmt::Display;
+use std::fs::{DirBuilder, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, OpenOptionsExt};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::SystemTime;
+
+use concurrency::sync::{Arc, Mutex};
+use lpm::prefix::Prefix;
+use net::buffer::PacketBufferMut;
+use net::headers::{TryIp, TryTransport};
+use net::packet::{Packet, VpcDiscriminant};
+use pipeline::NetworkFunction;
+use tokio::sync::mpsc;
+use tracing::{debug, info, warn};
+
+use crate::encap::FrameCopy;
+use crate::pcapng::PcapngWriter;
+
+/// The max number of copies queued for the writer
+const QUEUE_CAPACITY: usize = 4096;
+/// The max length of captured frames
+const SNAPLEN: u16 = u16::MAX;
+
+/// Where the packets of a capture are written, by name in the capture directory
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CaptureOutput {
+    /// A pcapng file, which must not exist
+    File(String),
+    /// A unix stream socket, which is connected to
+    Socket(String),
+}
+
+impl CaptureOutput {
+    fn name(&self) -> &str {
+        match self {
+            CaptureOutput::File(name) | CaptureOutput::Socket(name) => name,
+        }
+    }
+
+    /// The path of the output in the capture directory `dir`. Names which could resolve
+    /// outside of it are rejected.
+    fn path(&self, dir: &Path) -> io::Result<PathBuf> {
+        let name = self.name();
+        if name.is_empty() || name == "." || name.contains('/') || name.contains("..") {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid capture name '{name}'"),
+            ));
+        }
+        Ok(dir.join(name))
+    }
+
+    fn open(&self, dir: &Path) -> io::Result<Box<dyn Write + Send>> {
+        let path = self.path(dir)?;
+        match self {
+            CaptureOutput::File(_) => {
+                DirBuilder::new().recursive(true).mode(0o700).create(dir)?;
+                // never overwrite a file, nor follow a link planted in the directory
+                let file = OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .mode(0o600)
+                    .custom_flags(nix::fcntl::OFlag::O_NOFOLLOW.bits())
+                    .open(&path)?;
+                Ok(Box::new(BufWriter::new(file)))
+            }
+            CaptureOutput::Socket(_) => {
+                if !std::fs::symlink_metadata(&path)?.file_type().is_socket() {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidInput,
+                        format!("{} is not a socket", path.display()),
+                    ));
+                }
+                Ok(Box::new(BufWriter::new(UnixStream::connect(path)?)))
+            }
+        }
+    }
+}
+
+impl Display for CaptureOutput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CaptureOutput::File(name) => write!(f, "file {name}"),
+            CaptureOutput::Socket(name) => write!(f, "socket {name}"),
+        }
+    }
+}
+
+/// The packets to capture. A packet matches if it matches all the criteria given.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CaptureFilter {
+    /// The name of the tap to capture at. Packets are captured at all taps otherwise.
+    pub tap: Option<String>,
+    /// The VPC the packet comes from or goes to
+    pub vpcd: Option<VpcDiscriminant>,
+    /// A prefix covering the source or destination address of the packet
+    pub prefix: Option<Prefix>,
+    /// The source or destination transport port of the packet
+    pub port: Option<u16>,
+}
+
+impl CaptureFilter {
+    /// Tell if a packet seen at tap `tap` matches the filter
+    fn matches<Buf: PacketBufferMut>(&self, tap: &str, packet: &Packet<Buf>) -> bool {
+        if self.tap.as_ref().is_some_and(|name| name != tap) {
+            return false;
+        }
+        let meta = packet.meta();
+        if let Some(vpcd) = self.vpcd
+            && meta.src_vpcd != Some(vpcd)
+            && meta.dst_vpcd != Some(vpcd)
+        {
+            return false;
+        }
+        if let Some(prefix) = &self.prefix
+            && !packet.try_ip().is_some_and(|net| {
+                prefix.covers_addr(&net.src_addr()) || prefix.covers_addr(&net.dst_addr())
+            })
+        {
+            return false;
+        }
+        if let Some(port) = self.port {
+            let ports = packet
+                .try_transport()
+                .map(|transport| [transport.src_port(), transport.dst_port()]);
+            if !ports.is_some_and(|ports| ports.iter().flatten().any(|p| p.get() == port)) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl Display for CaptureFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut empty = true;
+        let mut criterion = |f: &mut std::fmt::Formatter<'_>, name, value: &dyn Display| {
+            let sep = if empty { "" } else { " " };
+            empty = false;
+            write!(f, "{sep}{name} {value}")
+        };
+        if let Some(tap) = &self.tap {
+            criterion(f, "tap", tap)?;
+        }
+        if let Some(vpcd) = &self.vpcd {
+            criterion(f, "vpc", vpcd)?;
+        }
+        if let Some(prefix) = &self.prefix {
+            criterion(f, "prefix", prefix)?;
+        }
+        if let Some(port) = &self.port {
+            criterion(f, "port", port)?;
+        }
+        if empty {
+            write!(f, "all packets")?;
+        }
+        Ok(())
+    }
+}
+
+/// A packet captured at a tap
+struct CapturedFrame {
+    time: SystemTime,
+    data: Vec<u8>,
+    len: usize,
+    comment: String,
+}
+
+/// The output of a capture, shared by the taps and the writer
+#[derive(Debug)]
+struct CaptureSink {
+    output: CaptureOutput,
+    /// Set once the writer stops, on errors writing the capture
+    failed: AtomicBool,
+    captured: AtomicU64,
+}
+
+impl CaptureSink {
+    /// Write the copies to the output, until all the taps let go of the capture
+    fn write(
+        &self,
+        mut writer: PcapngWriter<Box<dyn Write + Send>>,
+        mut queue: mpsc::Receiver<CapturedFrame>,
+    ) {
+        while let Some(frame) = queue.blocking_recv() {
+            let mut written =
+                writer.write_packet(frame.time, &frame.data, frame.len, Some(&frame.comment));
+            // flush as soon as there is nothing else to write, for live readers
+            if written.is_ok() && queue.is_empty() {
+                written = writer.flush();
+            }
+            if let Err(e) = written {
+                warn!("Stopping capture to {}: {e}", self.output);
+                self.failed.store(true, Ordering::Relaxed);
+                return;
+            }
+            self.captured.fetch_add(1, Ordering::Relaxed);
+        }
+        debug!("Capture to {} complete", self.output);
+    }
+}
+
+/// A capture in progress
+#[derive(Debug)]
+struct Capture {
+    filter: CaptureFilter,
+    sink: Arc<CaptureSink>,
+    /// The number of packets left to capture, if limited
+    remaining: Option<AtomicU64>,
+    dropped: AtomicU64,
+    queue: mpsc::Sender<CapturedFrame>,
+}
+
+impl Capture {
+    /// Whether the capture has captured all the packets it was asked for, or failed
+    fn is_over(&self) -> bool {
+        self.sink.failed.load(Ordering::Relaxed)
+            || self
+                .remaining
+                .as_ref()
+                .is_some_and(|remaining| remaining.load(Ordering::Relaxed) == 0)
+    }
+
+    /// Take one of the packets left to capture, if limited
+    fn admit(&self) -> bool {
+        self.remaining.as_ref().is_none_or(|remaining| {
+            remaining
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |left| {
+                    left.checked_sub(1)
+                })
+                .is_ok()
+        })
+    }
+
+    /// Copy a packet seen at tap `tap`, if it matches the filter
+    fn capture<Buf: PacketBufferMut>(&self, tap: &str, time: SystemTime, packet: &Packet<Buf>) {
+        if !self.filter.matches(tap, packet) || !self.admit() {
+            return;
+        }
+        let Some(copy) = FrameCopy::of(packet, Some(SNAPLEN)) else {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        let comment = match packet.get_done() {
+            Some(reason) => format!("tap {tap}, done: {reason:?}"),
+            None => format!("tap {tap}"),
+        };
+        let frame = CapturedFrame {
+            time,
+            len: copy.len,
+            data: copy.into_frame(),
+            comment,
+        };
+        if self.queue.try_send(frame).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Display for Capture {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            " capturing {} to {}: {} captured, {} dropped",
+            self.filter,
+            self.sink.output,
+            self.sink.captured.load(Ordering::Relaxed),
+            self.dropped.load(Ordering::Relaxed),
+        )?;
+        if let Some(remaining) = &self.remaining {
+            write!(f, ", {} left", remaining.load(Ordering::Relaxed))?;
+        }
+        if self.sink.failed.load(Ordering::Relaxed) {
+            write!(f, " (failed)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Starts and stops the capture of the [`PcapTap`]s. There is at most one capture at a time.
+#[derive(Clone, Debug)]
+pub struct CaptureControl {
+    /// Whether a capture is in progress, checked by the taps before taking the lock
+    active: Arc<AtomicBool>,
+    capture: Arc<Mutex<Option<Arc<Capture>>>>,
+    /// The directory where the outputs of captures are
+    dir: PathBuf,
+}
+
+impl CaptureControl {
+    /// Create a [`CaptureControl`] writing captures in the directory `dir`, which is created
+    /// if needed
+    #[must_use]
+    pub fn new(dir: &Path) -> Self {
+        Self {
+            active: Arc::default(),
+            capture: Arc::default(),
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    /// Start capturing the packets matching `filter` to `output`, up to `count` packets if given.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a capture is already in progress, if the output is not a file or socket of the
+    /// capture directory, if the file exists, or if the output can't be opened or the writer
+    /// thread spawned.
+    pub fn start(
+        &self,
+        filter: CaptureFilter,
+        output: CaptureOutput,
+        count: Option<u64>,
+    ) -> io::Result<()> {
+        let mut current = self.capture.lock();
+        if current.as_ref().is_some_and(|capture| !capture.is_over()) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                "a capture is in progress",
+            ));
+        }
+        let if_name = filter.tap.as_deref().unwrap_or("dataplane");
+        let writer = PcapngWriter::new(output.open(&self.dir)?, u32::from(SNAPLEN), if_name)?;
+        let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+        let sink = Arc::new(CaptureSink {
+            output,
+            failed: AtomicBool::new(false),
+            captured: AtomicU64::new(0),
+        });
+        let writing = sink.clone();
+        std::thread::Builder::new()
+            .name("pcap-writer".to_string())
+            .spawn(move || writing.write(writer, rx))?;
+        let capture = Arc::new(Capture {
+            filter,
+            sink,
+            remaining: count.map(AtomicU64::new),
+            dropped: AtomicU64::new(0),
+            queue: tx,
+        });
+        info!("Started {capture}");
+        *current = Some(capture);
+        self.active.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Stop the capture in progress, if any. Returns its summary. The capture is complete once
+    /// the taps let go of it.
+    #[must_use]
+    pub fn stop(&self) -> Option<String> {
+        let capture = self.capture.lock().take()?;
+        self.active.store(false, Ordering::Relaxed);
+        info!("Stopped {capture}");
+        Some(capture.to_string())
+    }
+
+    /// The capture in progress, if any. Captures which are over are stopped.
+    fn current(&self) -> Option<Arc<Capture>> {
+        if !self.active.load(Ordering::Relaxed) {
+            return None;
+        }
+        let mut current = self.capture.lock();
+        if current.as_ref().is_some_and(|capture| capture.is_over()) {
+            // the queue is closed once the taps drop their references
+            *current = None;
+            self.active.store(false, Ordering::Relaxed);
+        }
+        current.clone()
+    }
+}
+
+impl Display for CaptureControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.capture.lock().as_ref() {
+            Some(capture) => writeln!(f, "{capture}"),
+            None => writeln!(f, " no capture in progress"),
+        }
+    }
+}
+
+/// The packet capture pipeline stage
+pub struct PcapTap {
+    name: String,
+    control: CaptureControl,
+}
+
+impl PcapTap {
+    /// Create a new [`PcapTap`] instance. Captures select the taps they capture at by `name`.
+    #[must_use]
+    pub fn new(name: &str, control: CaptureControl) -> Self {
+        Self {
+            name: name.to_string(),
+            control,
+        }
+    }
+}
+
+impl<Buf: PacketBufferMut> NetworkFunction<Buf> for PcapTap {
+    fn process<'a, Input: Iterator<Item = Packet<Buf>> + 'a>(
+        &'a mut self,
+        input: Input,
+    ) -> impl Iterator<Item = Packet<Buf>> + 'a {
+        // the capture is looked up once per batch
+        let capture = self.control.current();
+        let time = SystemTime::now();
+        input.inspect(move |packet| {
+            if let Some(capture) = &capture {
+                capture.capture(&self.name, time, packet);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use net::buffer::TestBuffer;
+    use net::packet::test_utils::build_test_ipv4_packet;
+    use std::str::FromStr;
+
+    /// The number of complete enhanced packet blocks of a pcapng capture
+    fn count_packet_blocks(data: &[u8]) -> usize {
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+        let mut count = 0;
+        let mut offset = 0;
+        while offset + 8 <= data.len() {
+            let len = u32_at(offset + 4) as usize;
+            if offset + len > data.len() {
+                break;
+            }
+            if u32_at(offset) == 6 {
+                count += 1;
+            }
+            offset += len;
+        }
+        count
+    }
+
+    #[test]
+    fn test_capture_filter() {
+        let packet: Packet<TestBuffer> = build_test_ipv4_packet(64).unwrap();
+        let src = packet.try_ip().unwrap().src_addr();
+
+        assert!(CaptureFilter::default().matches("pre-filter", &packet));
+        let filter = CaptureFilter {
+            tap: Some("post-nat".to_string()),
+            ..CaptureFilter::default()
+        };
+        assert!(!filter.matches("pre-filter", &packet));
+        assert!(filter.matches("post-nat", &packet));
+
+        let filter = CaptureFilter {
+            prefix: Some(Prefix::from(src)),
+            ..CaptureFilter::default()
+        };
+        assert!(filter.matches("pre-filter", &packet));
+        let filter = CaptureFilter {
+            prefix: Some(Prefix::from_str("203.0.113.0/24").unwrap()),
+            ..CaptureFilter::default()
+        };
+        assert!(!filter.matches("pre-filter", &packet));
+
+        let vpcd = VpcDiscriminant::from_vni(net::vxlan::Vni::new_checked(3000).unwrap());
+        let filter = CaptureFilter {
+            vpcd: Some(vpcd),
+            ..CaptureFilter::default()
+        };
+        assert!(!filter.matches("pre-filter", &packet));
+        let mut packet = packet;
+        packet.meta_mut().dst_vpcd = Some(vpcd);
+        assert!(filter.matches("pre-filter", &packet));
+    }
+
+    fn test_dir(test: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("captures-{test}-{}", std::process::id()))
+    }
+
+    #[test]
+    fn test_capture_output_names() {
+        let dir = test_dir("names");
+        for name in [
+            "",
+            ".",
+            "..",
+            "../etc/passwd",
+            "a/b",
+            "x..y",
+            "/tmp/capture.pcapng",
+        ] {
+            let output = CaptureOutput::File(name.to_string());
+            let err = output.open(&dir).err().unwrap();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidInput, "{name}");
+            assert!(CaptureOutput::Socket(name.to_string()).open(&dir).is_err());
+        }
+        // sockets are not created, and must be sockets
+        assert!(!dir.exists());
+        DirBuilder::new().recursive(true).create(&dir).unwrap();
+        std::fs::write(dir.join("file"), b"").unwrap();
+        assert!(
+            CaptureOutput::Socket("file".to_string())
+                .open(&dir)
+                .is_err()
+        );
+        assert!(
+            CaptureOutput::Socket("none".to_string())
+                .open(&dir)
+                .is_err()
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_capture_file_exclusive() {
+        let dir = test_dir("exclusive");
+        let output = CaptureOutput::File("capture.pcapng".to_string());
+        assert!(output.open(&dir).is_ok());
+        // existing files are not overwritten, and links not followed
+        assert!(output.open(&dir).is_err());
+        let target = dir.join("target");
+        std::os::unix::fs::symlink(&target, dir.join("link")).unwrap();
+        assert!(CaptureOutput::File("link".to_string()).open(&dir).is_err());
+        assert!(!target.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_capture_to_file() {
+        let dir = test_dir("file");
+        let path = dir.join("capture.pcapng");
+        let control = CaptureControl::new(&dir);
+        let mut tap = PcapTap::new("pre-filter", control.clone());
+
+        // nothing is captured until started
+        let packets = (0..4).map(|_| build_test_ipv4_packet(64).unwrap());
+        let output: Vec<Packet<TestBuffer>> = tap.process(packets).collect();
+        assert_eq!(output.len(), 4);
+
+        let output_path = CaptureOutput::File("capture.pcapng".to_string());
+        control
+            .start(CaptureFilter::default(), output_path.clone(), Some(3))
+            .unwrap();
+        assert!(
+            control
+                .start(CaptureFilter::default(), output_path, None)
+                .is_err()
+        );
+        let packets = (0..4).map(|_| build_test_ipv4_packet(64).unwrap());
+        let output: Vec<Packet<TestBuffer>> = tap.process(packets).collect();
+        assert_eq!(output.len(), 4);
+
+        // the capture is over after 3 packets, and complete once the tap lets go of it
+        let packets = std::iter::empty();
+        let _: Vec<Packet<TestBuffer>> = tap.process(packets).collect();
+        assert!(control.stop().is_none());
+        let mut packets = 0;
+        for _ in 0..100 {
+            packets = count_packet_blocks(&std::fs::read(&path).unwrap());
+            if packets == 3 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(10));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(packets, 3);
+    }
+}
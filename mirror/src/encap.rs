@@ -0,0 +1,170 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Copies of mirrored packets, and their GRE and ERSPAN (type II) encapsulation.
+//!
+//! Encapsulated copies are sent on raw IP sockets, so the outer IP header is left to the kernel:
+//! copies start with the GRE header.
+
+use config::external::mirror::MirrorDestination;
+use net::buffer::PacketBufferMut;
+use net::headers::TryHeaders;
+use net::packet::Packet;
+use net::parse::DeParse;
+
+/// GRE flag telling that the key is present
+const GRE_KEY_PRESENT: u16 = 0x2000;
+/// GRE flag telling that the sequence number is present
+const GRE_SEQUENCE_PRESENT: u16 = 0x1000;
+/// GRE protocol of Transparent Ethernet Bridging
+const GRE_PROTO_TEB: u16 = 0x6558;
+/// GRE protocol of ERSPAN type II
+const GRE_PROTO_ERSPAN: u16 = 0x88BE;
+/// The version of ERSPAN type II, in the first word of its header
+const ERSPAN_VERSION_2: u16 = 1 << 12;
+/// ERSPAN flag telling that the frame was truncated
+const ERSPAN_TRUNCATED: u16 = 1 << 10;
+/// The mask of the index of an ERSPAN type II header (20 bits)
+const ERSPAN_INDEX_MASK: u32 = 0x000f_ffff;
+
+/// The max length of the headers of an encapsulated copy: GRE with key and sequence number, or
+/// GRE with sequence number and ERSPAN type II
+const MAX_ENCAP_LEN: usize = 16;
+
+/// A copy of a mirrored packet
+#[derive(Debug)]
+pub(crate) struct FrameCopy {
+    /// The octets of the frame, possibly truncated
+    pub(crate) data: Vec<u8>,
+    /// Whether the frame was truncated
+    pub(crate) truncated: bool,
+    /// The length of the packet, before truncation
+    pub(crate) len: usize,
+}
+
+impl FrameCopy {
+    /// Copy a packet, as it would be serialized, up to `truncate` octets. Room is left at the front
+    /// of the copy for its encapsulation.
+    pub(crate) fn of<Buf: PacketBufferMut>(
+        packet: &Packet<Buf>,
+        truncate: Option<u16>,
+    ) -> Option<Self> {
+        let headers = packet.headers();
+        let header_len = usize::from(headers.size().get());
+        let payload = packet.payload().as_ref();
+        let len = header_len + payload.len();
+        let max = truncate.map_or(len, |max| usize::from(max).min(len));
+
+        let mut data = vec![0; MAX_ENCAP_LEN + header_len.max(max)];
+        headers.deparse(&mut data[MAX_ENCAP_LEN..]).ok()?;
+        data.truncate(MAX_ENCAP_LEN + header_len.min(max));
+        if max > header_len {
+            data.extend_from_slice(&payload[..max - header_len]);
+        }
+        Some(Self {
+            data,
+            truncated: max < len,
+            len,
+        })
+    }
+
+    /// The octets of the copy, without encapsulation
+    pub(crate) fn into_frame(mut self) -> Vec<u8> {
+        self.data.drain(..MAX_ENCAP_LEN);
+        self.data
+    }
+
+    /// Encapsulate the copy for its destination, returning the octets to send. `index` identifies
+    /// the port the packet was mirrored on, for ERSPAN.
+    pub(crate) fn encapsulate(
+        mut self,
+        destination: &MirrorDestination,
+        sequence: u32,
+        index: u32,
+    ) -> Vec<u8> {
+        let mut encap = Vec::with_capacity(MAX_ENCAP_LEN);
+        match destination {
+            MirrorDestination::Interface(_) => {}
+            MirrorDestination::Gre { key, .. } => {
+                let flags = GRE_SEQUENCE_PRESENT | key.map_or(0, |_| GRE_KEY_PRESENT);
+                encap.extend_from_slice(&flags.to_be_bytes());
+                encap.extend_from_slice(&GRE_PROTO_TEB.to_be_bytes());
+                if let Some(key) = key {
+                    encap.extend_from_slice(&key.to_be_bytes());
+                }
+                encap.extend_from_slice(&sequence.to_be_bytes());
+            }
+            MirrorDestination::Erspan { session_id, .. } => {
+                encap.extend_from_slice(&GRE_SEQUENCE_PRESENT.to_be_bytes());
+                encap.extend_from_slice(&GRE_PROTO_ERSPAN.to_be_bytes());
+                encap.extend_from_slice(&sequence.to_be_bytes());
+                // no VLAN, COS 0, no encapsulation of the original frame
+                encap.extend_from_slice(&ERSPAN_VERSION_2.to_be_bytes());
+                let truncated = if self.truncated { ERSPAN_TRUNCATED } else { 0 };
+                encap.extend_from_slice(&(truncated | session_id).to_be_bytes());
+                encap.extend_from_slice(&(index & ERSPAN_INDEX_MASK).to_be_bytes());
+            }
+        }
+        let start = MAX_ENCAP_LEN - encap.len();
+        self.data[start..MAX_ENCAP_LEN].copy_from_slice(&encap);
+        self.data.drain(..start);
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use net::packet::test_utils::build_test_ipv4_packet;
+
+    #[test]
+    fn test_frame_copy() {
+        let packet = build_test_ipv4_packet(64).unwrap();
+        let len = usize::from(packet.total_len());
+
+        let copy = FrameCopy::of(&packet, None).unwrap();
+        assert!(!copy.truncated);
+        let frame = copy.encapsulate(&MirrorDestination::Interface("eth0".to_string()), 0, 0);
+        assert_eq!(frame.len(), len);
+
+        // truncated within the headers
+        let copy = FrameCopy::of(&packet, Some(20)).unwrap();
+        assert!(copy.truncated);
+        assert_eq!(copy.data.len(), MAX_ENCAP_LEN + 20);
+        assert_eq!(&copy.data[MAX_ENCAP_LEN..], &frame[..20]);
+        assert_eq!(copy.len, len);
+        assert_eq!(copy.into_frame(), &frame[..20]);
+    }
+
+    #[test]
+    fn test_encapsulation() {
+        let packet = build_test_ipv4_packet(64).unwrap();
+        let len = usize::from(packet.total_len());
+        let remote = "192.168.1.1".parse().unwrap();
+
+        let gre = MirrorDestination::Gre {
+            remote,
+            key: Some(7),
+        };
+        let frame = FrameCopy::of(&packet, None)
+            .unwrap()
+            .encapsulate(&gre, 5, 0);
+        assert_eq!(frame.len(), 12 + len);
+        assert_eq!(&frame[..12], &[0x30, 0, 0x65, 0x58, 0, 0, 0, 7, 0, 0, 0, 5]);
+
+        let erspan = MirrorDestination::Erspan {
+            remote,
+            session_id: 0x123,
+        };
+        let frame = FrameCopy::of(&packet, Some(20))
+            .unwrap()
+            .encapsulate(&erspan, 1, 2);
+        assert_eq!(frame.len(), 16 + 20);
+        assert_eq!(
+            &frame[..16],
+            &[
+                0x10, 0, 0x88, 0xbe, 0, 0, 0, 1, 0x10, 0, 0x05, 0x23, 0, 0, 0, 2
+            ]
+        );
+    }
+}
@@ -0,0 +1,179 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! The exporter of the copies of mirrored packets.
+//!
+//! Workers hand the copies over to the exporter through a bounded queue, so that sending them
+//! never blocks packet processing: copies which do not fit in the queue are dropped. The exporter
+//! runs in its own thread and sends the copies out of local interfaces on a packet socket, or to
+//! remote collectors on raw IP sockets of protocol GRE.
+
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::thread::JoinHandle;
+
+use concurrency::sync::Arc;
+use config::external::mirror::MirrorDestination;
+use nix::libc;
+use nix::sys::socket::{MsgFlags, SockaddrStorage, sendto};
+use tokio::sync::mpsc;
+use tracing::debug;
+
+use crate::tables::SessionTap;
+
+/// The max number of copies queued for the exporter
+const QUEUE_CAPACITY: usize = 4096;
+
+/// A copy of a mirrored packet, ready to be sent to the destination of its session
+pub(crate) struct MirroredFrame {
+    pub(crate) session: Arc<SessionTap>,
+    pub(crate) data: Vec<u8>,
+}
+
+/// The sending side of the queue of the exporter, cloned by each worker
+#[derive(Clone, Debug)]
+pub struct MirrorSender(mpsc::Sender<MirroredFrame>);
+
+impl MirrorSender {
+    /// Queue a copy. Copies that do not fit in the queue are dropped.
+    pub(crate) fn send(&self, frame: MirroredFrame) {
+        if let Err(e) = self.0.try_send(frame) {
+            let (mpsc::error::TrySendError::Full(frame) | mpsc::error::TrySendError::Closed(frame)) =
+                e;
+            frame.session.metrics.dropped.increment(1);
+        }
+    }
+}
+
+/// Create a raw socket
+#[allow(unsafe_code)] // raw sockets of protocol GRE have no safe wrapper
+fn raw_socket(domain: libc::c_int, protocol: libc::c_int) -> io::Result<OwnedFd> {
+    let fd = unsafe { libc::socket(domain, libc::SOCK_RAW | libc::SOCK_CLOEXEC, protocol) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // SAFETY: the descriptor was just created and is owned by no one else
+    Ok(unsafe { OwnedFd::from_raw_fd(fd) })
+}
+
+/// Send a frame out of an interface, on a packet socket
+#[allow(unsafe_code)] // nix can't build link-layer addresses
+fn send_on_interface(socket: &OwnedFd, ifindex: u32, frame: &[u8]) -> io::Result<()> {
+    let mut addr: libc::sockaddr_ll = unsafe { std::mem::zeroed() };
+    addr.sll_family = u16::try_from(libc::AF_PACKET).unwrap_or_else(|_| unreachable!());
+    addr.sll_ifindex = libc::c_int::try_from(ifindex).map_err(|_| io::ErrorKind::InvalidInput)?;
+    #[allow(clippy::cast_possible_truncation)] // the size of the address is tiny
+    let len = size_of::<libc::sockaddr_ll>() as libc::socklen_t;
+    let sent = unsafe {
+        libc::sendto(
+            socket.as_raw_fd(),
+            frame.as_ptr().cast(),
+            frame.len(),
+            0,
+            (&raw const addr).cast(),
+            len,
+        )
+    };
+    if sent < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The sockets of the exporter, opened as destinations need them
+#[derive(Default)]
+struct Sockets {
+    packet: Option<OwnedFd>,
+    gre4: Option<OwnedFd>,
+    gre6: Option<OwnedFd>,
+    /// The index of the destination interfaces, by name
+    ifindex: HashMap<String, u32>,
+}
+
+impl Sockets {
+    fn send(&mut self, destination: &MirrorDestination, data: &[u8]) -> io::Result<()> {
+        match destination {
+            MirrorDestination::Interface(name) => {
+                let ifindex = match self.ifindex.get(name) {
+                    Some(ifindex) => *ifindex,
+                    None => {
+                        let ifindex = nix::net::if_::if_nametoindex(name.as_str())?;
+                        self.ifindex.insert(name.clone(), ifindex);
+                        ifindex
+                    }
+                };
+                if self.packet.is_none() {
+                    self.packet = Some(raw_socket(libc::AF_PACKET, 0)?);
+                }
+                let socket = self.packet.as_ref().unwrap_or_else(|| unreachable!());
+                send_on_interface(socket, ifindex, data).inspect_err(|_| {
+                    // the interface may have been re-created
+                    self.ifindex.remove(name);
+                })
+            }
+            MirrorDestination::Gre { remote, .. } | MirrorDestination::Erspan { remote, .. } => {
+                let (socket, domain) = match remote {
+                    IpAddr::V4(_) => (&mut self.gre4, libc::AF_INET),
+                    IpAddr::V6(_) => (&mut self.gre6, libc::AF_INET6),
+                };
+                if socket.is_none() {
+                    *socket = Some(raw_socket(domain, libc::IPPROTO_GRE)?);
+                }
+                let socket = socket.as_ref().unwrap_or_else(|| unreachable!());
+                let addr = SockaddrStorage::from(SocketAddr::new(*remote, 0));
+                sendto(socket.as_raw_fd(), data, &addr, MsgFlags::empty())?;
+                Ok(())
+            }
+        }
+    }
+}
+
+/// The exporter of the copies of mirrored packets
+pub struct MirrorExporter {
+    pub(crate) queue: mpsc::Receiver<MirroredFrame>,
+    sockets: Sockets,
+}
+
+impl MirrorExporter {
+    /// Create an exporter, with the sender workers queue copies with
+    #[must_use]
+    pub fn new() -> (Self, MirrorSender) {
+        let (tx, rx) = mpsc::channel(QUEUE_CAPACITY);
+        let exporter = Self {
+            queue: rx,
+            sockets: Sockets::default(),
+        };
+        (exporter, MirrorSender(tx))
+    }
+
+    /// Send the queued copies, until all the senders are gone
+    fn run(mut self) {
+        while let Some(frame) = self.queue.blocking_recv() {
+            let session = &frame.session;
+            match self.sockets.send(&session.destination, &frame.data) {
+                Ok(()) => session.metrics.mirrored.increment(1),
+                Err(e) => {
+                    debug!(
+                        "Failed to send copy of mirror session {} to {}: {e}",
+                        session.name, session.destination
+                    );
+                    session.metrics.dropped.increment(1);
+                }
+            }
+        }
+        debug!("Mirror exporter stopped: no sender left");
+    }
+
+    /// Run the exporter in its own thread
+    ///
+    /// # Errors
+    ///
+    /// Fails if the thread can't be spawned.
+    pub fn spawn(self) -> io::Result<JoinHandle<()>> {
+        std::thread::Builder::new()
+            .name("mirror-exporter".to_string())
+            .spawn(move || self.run())
+    }
+}
@@ -0,0 +1,172 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Port mirroring (SPAN) pipeline stage
+//!
+//! [`Mirror`] is a tap: it lets all packets through untouched, and copies those matching the
+//! filter of a mirror session to the destination of the session:
+//!
+//! - Filters match the interfaces a packet is received and sent on, the VPCs it comes from and
+//!   goes to, and its source and destination addresses.
+//! - Copies may be truncated, and their rate limited per session. Rate limiters are shared by all
+//!   workers, so the rate of a session applies to the aggregate of its copies.
+//! - Copies are sent by the [`MirrorExporter`], out of a local interface or to a remote collector
+//!   in a GRE or ERSPAN (type II) tunnel. Copies are dropped rather than slowing workers down.
+//!
+//! [`PcapTap`] is a tap as well, capturing packets to pcapng on demand, for debugging (see
+//! [`capture`]).
+
+#![deny(clippy::all, clippy::pedantic)]
+
+pub mod capture;
+mod encap;
+mod exporter;
+mod limiter;
+mod mirror_rw;
+mod pcapng;
+mod tables;
+
+pub use capture::{CaptureControl, CaptureFilter, CaptureOutput, PcapTap};
+pub use exporter::{MirrorExporter, MirrorSender};
+pub use mirror_rw::{MirrorTableReader, MirrorTableReaderFactory, MirrorTableWriter};
+pub use tables::{MirrorTable, SessionTap};
+
+use crate::encap::FrameCopy;
+use crate::exporter::MirroredFrame;
+use net::buffer::PacketBufferMut;
+use net::headers::TryIp;
+use net::packet::Packet;
+use pipeline::NetworkFunction;
+use std::time::Instant;
+use tracing::error;
+
+use tracectl::trace_target;
+trace_target!("mirror", LevelFilter::INFO, &["pipeline"]);
+
+/// The port mirroring pipeline stage
+pub struct Mirror {
+    name: String,
+    tablesr: MirrorTableReader,
+    sender: MirrorSender,
+}
+
+impl Mirror {
+    /// Create a new [`Mirror`] instance, queuing copies to the exporter with `sender`.
+    #[must_use]
+    pub fn new(name: &str, tablesr: MirrorTableReader, sender: MirrorSender) -> Self {
+        Self {
+            name: name.to_string(),
+            tablesr,
+            sender,
+        }
+    }
+
+    /// Copy a packet to the sessions whose filter it matches
+    fn mirror_packet<Buf: PacketBufferMut>(&self, table: &MirrorTable, packet: &Packet<Buf>) {
+        let meta = packet.meta();
+        let interfaces = [meta.iif, meta.oif];
+        let vpcds = [meta.src_vpcd, meta.dst_vpcd];
+        let addresses = packet.try_ip().map(|net| [net.src_addr(), net.dst_addr()]);
+        let mut now = None;
+        for session in table.sessions() {
+            if !session.filter.matches(interfaces, vpcds, addresses) {
+                continue;
+            }
+            if !session.admit(*now.get_or_insert_with(Instant::now)) {
+                continue;
+            }
+            let Some(copy) = FrameCopy::of(packet, session.truncate) else {
+                session.metrics.dropped.increment(1);
+                continue;
+            };
+            let index = meta.iif.map_or(0, u32::from);
+            let data = copy.encapsulate(&session.destination, session.next_sequence(), index);
+            self.sender.send(MirroredFrame {
+                session: session.clone(),
+                data,
+            });
+        }
+    }
+}
+
+impl<Buf: PacketBufferMut> NetworkFunction<Buf> for Mirror {
+    fn process<'a, Input: Iterator<Item = Packet<Buf>> + 'a>(
+        &'a mut self,
+        input: Input,
+    ) -> impl Iterator<Item = Packet<Buf>> + 'a {
+        input.inspect(|packet| {
+            if packet.is_done() {
+                return;
+            }
+            if let Some(table) = &self.tablesr.enter() {
+                if !table.is_empty() {
+                    self.mirror_packet(table, packet);
+                }
+            } else {
+                error!("{}: failed to read mirror table", self.name);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use config::external::mirror::{MirrorConfig, MirrorDestination, MirrorSession};
+    use config::external::overlay::vpc::{Vpc, VpcTable};
+    use net::buffer::TestBuffer;
+    use net::packet::VpcDiscriminant;
+    use net::packet::test_utils::build_test_ipv4_packet;
+    use net::vxlan::Vni;
+
+    #[test]
+    fn test_mirror() {
+        let vni = Vni::new_checked(3000).unwrap();
+        let mut vpc_table = VpcTable::new();
+        vpc_table
+            .add(Vpc::new("vpc1", "AAAAA", vni.as_u32()).unwrap())
+            .unwrap();
+        let vpc_table = vpc_table.validate().unwrap();
+
+        let remote = "192.168.1.1".parse().unwrap();
+        let mut session = MirrorSession::new(
+            "span1",
+            MirrorDestination::Erspan {
+                remote,
+                session_id: 1,
+            },
+        );
+        session.filter.vpc = Some("vpc1".to_string());
+        session.rate_pps = Some(5);
+        session.truncate = Some(64);
+        let mut config = MirrorConfig::new();
+        config.add_session(session);
+
+        let mut tablesw = MirrorTableWriter::new();
+        tablesw.update_mirror_table(MirrorTable::build(&config, &vpc_table).unwrap());
+        let (mut exporter, sender) = MirrorExporter::new();
+        let mut mirror = Mirror::new("mirror", tablesw.get_reader(), sender);
+
+        // all packets go through, those of the vpc get copied, up to the rate
+        let packets = (0..20).map(|i| {
+            let mut packet: Packet<TestBuffer> = build_test_ipv4_packet(64).unwrap();
+            if i % 2 == 0 {
+                packet.meta_mut().src_vpcd = Some(VpcDiscriminant::from_vni(vni));
+            }
+            packet
+        });
+        let output: Vec<_> = mirror.process(packets).collect();
+        assert_eq!(output.len(), 20);
+
+        let mut copies = vec![];
+        while let Ok(frame) = exporter.queue.try_recv() {
+            copies.push(frame);
+        }
+        assert_eq!(copies.len(), 5);
+        let sequences: Vec<_> = copies
+            .iter()
+            .map(|frame| u32::from_be_bytes(frame.data[4..8].try_into().unwrap()))
+            .collect();
+        assert_eq!(sequences, [0, 1, 2, 3, 4]);
+    }
+}
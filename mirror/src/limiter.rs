@@ -0,0 +1,78 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Rate limiter of the copies of a mirror session
+
+use std::time::{Duration, Instant};
+
+/// A token bucket, with tokens in packets, allowing bursts of one second worth of packets
+#[derive(Debug)]
+pub(crate) struct RateLimiter {
+    rate_pps: u32,
+    tokens: u32,
+    last: Instant,
+}
+
+impl RateLimiter {
+    /// Create a rate limiter, initially full
+    pub(crate) fn new(rate_pps: u32) -> Self {
+        Self {
+            rate_pps,
+            tokens: rate_pps,
+            last: Instant::now(),
+        }
+    }
+
+    /// Add the tokens accrued since the last refill, up to the burst. Only the time worth of the
+    /// tokens added is accounted for, so that frequent refills do not lose fractions of tokens.
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last);
+        let accrued = u128::from(self.rate_pps) * elapsed.as_nanos() / 1_000_000_000;
+        if accrued == 0 {
+            return;
+        }
+        let accrued = u32::try_from(accrued).unwrap_or(u32::MAX);
+        if self.tokens.saturating_add(accrued) >= self.rate_pps {
+            self.tokens = self.rate_pps;
+            self.last = now;
+        } else {
+            self.tokens += accrued;
+            let nanos = u64::from(accrued) * 1_000_000_000 / u64::from(self.rate_pps);
+            self.last += Duration::from_nanos(nanos);
+        }
+    }
+
+    /// Take a token for a packet. Fails if the bucket has no tokens left.
+    pub(crate) fn try_take(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens == 0 {
+            return false;
+        }
+        self.tokens -= 1;
+        true
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut limiter = RateLimiter::new(1000);
+        let start = limiter.last;
+        assert!((0..1000).all(|_| limiter.try_take(start)));
+        assert!(!limiter.try_take(start));
+
+        // a token every msec, fractions of tokens are kept
+        assert!(!limiter.try_take(start + Duration::from_micros(500)));
+        assert!(limiter.try_take(start + Duration::from_micros(1000)));
+        assert!(!limiter.try_take(start + Duration::from_micros(1500)));
+        assert!(limiter.try_take(start + Duration::from_micros(2000)));
+
+        // after a long time, the bucket is capped to the burst
+        let later = start + Duration::from_secs(10);
+        assert!((0..1000).all(|_| limiter.try_take(later)));
+        assert!(!limiter.try_take(later));
+    }
+}
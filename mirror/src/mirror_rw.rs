@@ -0,0 +1,85 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Left-right integration for [`MirrorTable`]
+
+use crate::tables::MirrorTable;
+use common::generation::Generational;
+use left_right::{Absorb, ReadGuard, ReadHandle, ReadHandleFactory, new_from_empty};
+use stats::InstrumentedWriteHandle;
+use tracing::debug;
+
+#[derive(Debug, Clone)]
+pub enum MirrorTableChange {
+    UpdateMirrorTable(MirrorTable),
+}
+
+impl Absorb<MirrorTableChange> for MirrorTable {
+    fn absorb_first(&mut self, change: &mut MirrorTableChange, _: &Self) {
+        match change {
+            MirrorTableChange::UpdateMirrorTable(new) => {
+                self.replace_contents(new.clone());
+            }
+        }
+    }
+    fn drop_first(self: Box<Self>) {}
+    fn sync_with(&mut self, first: &Self) {
+        *self = first.clone();
+    }
+}
+
+#[derive(Debug)]
+pub struct MirrorTableReader(ReadHandle<MirrorTable>);
+
+impl MirrorTableReader {
+    pub(crate) fn enter(&self) -> Option<ReadGuard<'_, MirrorTable>> {
+        self.0.enter()
+    }
+
+    #[must_use]
+    pub fn factory(&self) -> MirrorTableReaderFactory {
+        MirrorTableReaderFactory(self.0.factory())
+    }
+
+    #[must_use]
+    pub fn inner(&self) -> ReadHandle<MirrorTable> {
+        self.0.clone()
+    }
+}
+
+#[derive(Debug)]
+pub struct MirrorTableReaderFactory(ReadHandleFactory<MirrorTable>);
+
+impl MirrorTableReaderFactory {
+    #[must_use]
+    pub fn handle(&self) -> MirrorTableReader {
+        MirrorTableReader(self.0.handle())
+    }
+}
+
+#[derive(Debug)]
+pub struct MirrorTableWriter(InstrumentedWriteHandle<MirrorTable, MirrorTableChange>);
+
+impl MirrorTableWriter {
+    #[must_use]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> MirrorTableWriter {
+        let (w, _r) = new_from_empty::<MirrorTable, MirrorTableChange>(MirrorTable::new());
+        MirrorTableWriter(InstrumentedWriteHandle::new(w, "mirror"))
+    }
+
+    #[must_use]
+    pub fn get_reader(&self) -> MirrorTableReader {
+        MirrorTableReader(self.0.clone())
+    }
+
+    pub fn get_reader_factory(&self) -> MirrorTableReaderFactory {
+        self.get_reader().factory()
+    }
+
+    pub fn update_mirror_table(&mut self, table: MirrorTable) {
+        self.0.append(MirrorTableChange::UpdateMirrorTable(table));
+        self.0.publish();
+        debug!("Updated mirror table");
+    }
+}
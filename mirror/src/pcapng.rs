@@ -0,0 +1,210 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! A minimal writer of pcapng captures: a section with a single Ethernet interface, and enhanced
+//! packet blocks with an optional comment. Blocks are written in little-endian order, which
+//! readers detect from the byte-order magic of the section header.
+
+use std::io::{self, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Block type of the section header block
+const BLOCK_SECTION_HEADER: u32 = 0x0A0D_0D0A;
+/// Block type of the interface description block
+const BLOCK_INTERFACE_DESCRIPTION: u32 = 1;
+/// Block type of the enhanced packet block
+const BLOCK_ENHANCED_PACKET: u32 = 6;
+/// Byte-order magic of the section header block
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+/// Link type of Ethernet
+const LINKTYPE_ETHERNET: u16 = 1;
+/// Option code of comments
+const OPT_COMMENT: u16 = 1;
+/// Option code of the name of an interface
+const OPT_IF_NAME: u16 = 2;
+/// Option code ending the options of a block
+const OPT_END: u16 = 0;
+
+/// The padding of `len` octets to 32 bits
+fn padding(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
+
+/// The length of an option with a value of `len` octets, padding included
+fn option_len(len: usize) -> usize {
+    4 + len + padding(len)
+}
+
+/// A block being built
+struct Block(Vec<u8>);
+
+impl Block {
+    fn new(block_type: u32, capacity: usize) -> Self {
+        let mut data = Vec::with_capacity(12 + capacity);
+        data.extend_from_slice(&block_type.to_le_bytes());
+        // the length is set once the block is complete
+        data.extend_from_slice(&0u32.to_le_bytes());
+        Self(data)
+    }
+
+    fn u16(&mut self, value: u16) -> &mut Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn u32(&mut self, value: u32) -> &mut Self {
+        self.0.extend_from_slice(&value.to_le_bytes());
+        self
+    }
+
+    fn padded(&mut self, data: &[u8]) -> &mut Self {
+        self.0.extend_from_slice(data);
+        self.0.resize(self.0.len() + padding(data.len()), 0);
+        self
+    }
+
+    fn option(&mut self, code: u16, value: &[u8]) -> &mut Self {
+        // options longer than their length field allows are dropped
+        let Ok(len) = u16::try_from(value.len()) else {
+            return self;
+        };
+        self.u16(code).u16(len).padded(value)
+    }
+
+    /// Set the length of the block at both of its ends, and write it
+    fn write(&mut self, out: &mut impl Write) -> io::Result<()> {
+        let len = u32::try_from(self.0.len() + 4)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "block too long"))?;
+        self.0[4..8].copy_from_slice(&len.to_le_bytes());
+        self.u32(len);
+        out.write_all(&self.0)
+    }
+}
+
+/// A writer of a pcapng capture
+pub(crate) struct PcapngWriter<W: Write> {
+    out: W,
+}
+
+impl<W: Write> PcapngWriter<W> {
+    /// Start a capture on `out`, of frames truncated to `snaplen` octets, captured on interface
+    /// `if_name`.
+    pub(crate) fn new(mut out: W, snaplen: u32, if_name: &str) -> io::Result<Self> {
+        let mut section = Block::new(BLOCK_SECTION_HEADER, 16);
+        section.u32(BYTE_ORDER_MAGIC).u16(1).u16(0);
+        // the length of the section is not known
+        section.0.extend_from_slice(&(-1i64).to_le_bytes());
+        section.write(&mut out)?;
+
+        let mut interface = Block::new(BLOCK_INTERFACE_DESCRIPTION, 12 + option_len(if_name.len()));
+        interface
+            .u16(LINKTYPE_ETHERNET)
+            .u16(0)
+            .u32(snaplen)
+            .option(OPT_IF_NAME, if_name.as_bytes())
+            .u16(OPT_END)
+            .u16(0);
+        interface.write(&mut out)?;
+        Ok(Self { out })
+    }
+
+    /// Write a frame captured at `time`, of `len` octets before truncation, with an optional
+    /// comment.
+    pub(crate) fn write_packet(
+        &mut self,
+        time: SystemTime,
+        frame: &[u8],
+        len: usize,
+        comment: Option<&str>,
+    ) -> io::Result<()> {
+        let too_long = |_| io::Error::new(io::ErrorKind::InvalidInput, "frame too long");
+        let captured = u32::try_from(frame.len()).map_err(too_long)?;
+        let len = u32::try_from(len).map_err(too_long)?;
+        // timestamps are in microseconds, the default resolution
+        let micros = time.duration_since(UNIX_EPOCH).map_or(0, |since| {
+            u64::try_from(since.as_micros()).unwrap_or(u64::MAX)
+        });
+        let options = comment.map_or(0, |comment| option_len(comment.len()) + 4);
+
+        let mut block = Block::new(
+            BLOCK_ENHANCED_PACKET,
+            20 + frame.len() + padding(frame.len()) + options,
+        );
+        #[allow(clippy::cast_possible_truncation)] // the timestamp is split in two halves
+        let (high, low) = ((micros >> 32) as u32, micros as u32);
+        block
+            .u32(0)
+            .u32(high)
+            .u32(low)
+            .u32(captured)
+            .u32(len)
+            .padded(frame);
+        if let Some(comment) = comment {
+            block
+                .option(OPT_COMMENT, comment.as_bytes())
+                .u16(OPT_END)
+                .u16(0);
+        }
+        block.write(&mut self.out)
+    }
+
+    /// Flush the capture
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    fn u32_at(data: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn test_pcapng_blocks() {
+        let mut writer = PcapngWriter::new(vec![], 128, "pre-filter").unwrap();
+        let time = UNIX_EPOCH + Duration::from_micros(0x1_0000_0002);
+        writer
+            .write_packet(time, &[0xaa; 5], 200, Some("dropped"))
+            .unwrap();
+        writer.write_packet(time, &[0xbb; 8], 8, None).unwrap();
+        let data = writer.out;
+
+        // the blocks follow each other, with their length at both ends
+        let mut blocks = vec![];
+        let mut offset = 0;
+        while offset < data.len() {
+            let len = u32_at(&data, offset + 4) as usize;
+            assert_eq!(len % 4, 0);
+            assert_eq!(u32_at(&data, offset + len - 4) as usize, len);
+            blocks.push((u32_at(&data, offset), &data[offset..offset + len]));
+            offset += len;
+        }
+        assert_eq!(offset, data.len());
+        let types: Vec<_> = blocks.iter().map(|(block_type, _)| *block_type).collect();
+        assert_eq!(types, [BLOCK_SECTION_HEADER, 1, 6, 6]);
+
+        let section = blocks[0].1;
+        assert_eq!(section.len(), 28);
+        assert_eq!(u32_at(section, 8), BYTE_ORDER_MAGIC);
+
+        let interface = blocks[1].1;
+        assert_eq!(u32_at(interface, 12), 128);
+        assert_eq!(&interface[20..30], b"pre-filter");
+
+        let packet = blocks[2].1;
+        assert_eq!(u32_at(packet, 12), 1);
+        assert_eq!(u32_at(packet, 16), 2);
+        assert_eq!(u32_at(packet, 20), 5);
+        assert_eq!(u32_at(packet, 24), 200);
+        assert_eq!(&packet[28..33], &[0xaa; 5]);
+        assert_eq!(&packet[36..40], &[1, 0, 7, 0]);
+        assert_eq!(&packet[40..47], b"dropped");
+
+        let packet = blocks[3].1;
+        assert_eq!(packet.len(), 32 + 8);
+    }
+}
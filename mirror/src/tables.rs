@@ -0,0 +1,249 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! The mirror table: the taps of the configured mirror sessions
+
+use common::generation::{Generational, TableGeneration};
+use concurrency::sync::{Arc, Mutex};
+use config::ConfigError;
+use config::external::mirror::{MirrorConfig, MirrorDestination, MirrorSession};
+use config::external::overlay::vpc::ValidatedVpcTable;
+use lpm::prefix::Prefix;
+use metrics::{Counter, Unit};
+use net::interface::InterfaceIndex;
+use net::packet::VpcDiscriminant;
+use stats::{MetricSpec, Register};
+use std::fmt::Display;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Instant;
+use tracing::warn;
+
+use crate::limiter::RateLimiter;
+
+/// The metrics of a mirror session. They aggregate those of all workers.
+#[derive(Debug)]
+pub(crate) struct SessionMetrics {
+    pub(crate) mirrored: Counter,
+    pub(crate) rate_limited: Counter,
+    pub(crate) dropped: Counter,
+}
+
+impl SessionMetrics {
+    fn new(session: &str) -> Self {
+        let counter = |id: &str| -> Counter {
+            let labels = vec![("session".to_string(), session.to_string())];
+            MetricSpec::new(id, Unit::Count, labels).register().metric
+        };
+        Self {
+            mirrored: counter("mirror_packets"),
+            rate_limited: counter("mirror_rate_limited_packets"),
+            dropped: counter("mirror_dropped_packets"),
+        }
+    }
+}
+
+/// The criteria of a session, resolved
+#[derive(Debug, Default)]
+pub(crate) struct TapFilter {
+    /// The interface to mirror, if any. A session whose interface does not exist matches nothing.
+    pub(crate) interface: Option<Option<InterfaceIndex>>,
+    pub(crate) vpcd: Option<VpcDiscriminant>,
+    pub(crate) prefix: Option<Prefix>,
+}
+
+impl TapFilter {
+    /// Tell if a packet matches the filter
+    pub(crate) fn matches(
+        &self,
+        interfaces: [Option<InterfaceIndex>; 2],
+        vpcds: [Option<VpcDiscriminant>; 2],
+        addresses: Option<[IpAddr; 2]>,
+    ) -> bool {
+        if let Some(interface) = self.interface
+            && (interface.is_none() || !interfaces.contains(&interface))
+        {
+            return false;
+        }
+        if let Some(vpcd) = self.vpcd
+            && !vpcds.contains(&Some(vpcd))
+        {
+            return false;
+        }
+        if let Some(prefix) = &self.prefix
+            && !addresses.is_some_and(|addrs| addrs.iter().any(|addr| prefix.covers_addr(addr)))
+        {
+            return false;
+        }
+        true
+    }
+}
+
+/// The tap of a mirror session
+#[derive(Debug)]
+pub struct SessionTap {
+    pub(crate) name: String,
+    pub(crate) filter: TapFilter,
+    pub(crate) destination: MirrorDestination,
+    pub(crate) truncate: Option<u16>,
+    pub(crate) limiter: Option<Mutex<RateLimiter>>,
+    /// The sequence number of the next copy, for GRE and ERSPAN destinations
+    pub(crate) sequence: AtomicU32,
+    pub(crate) metrics: SessionMetrics,
+}
+
+impl SessionTap {
+    fn new(session: &MirrorSession, vpc_table: &ValidatedVpcTable) -> Result<Self, ConfigError> {
+        let vpcd = session
+            .filter
+            .vpc
+            .as_ref()
+            .map(|name| {
+                vpc_table
+                    .get_vpc(name)
+                    .map(|vpc| VpcDiscriminant::from_vni(vpc.vni()))
+                    .ok_or_else(|| ConfigError::NoSuchVpc(name.clone()))
+            })
+            .transpose()?;
+        let interface = session.filter.interface.as_ref().map(|name| {
+            let index = nix::net::if_::if_nametoindex(name.as_str())
+                .ok()
+                .and_then(|index| InterfaceIndex::try_new(index).ok());
+            if index.is_none() {
+                warn!(
+                    "Interface {name} of mirror session {} does not exist: nothing is mirrored",
+                    session.name
+                );
+            }
+            index
+        });
+        Ok(Self {
+            name: session.name.clone(),
+            filter: TapFilter {
+                interface,
+                vpcd,
+                prefix: session.filter.prefix,
+            },
+            destination: session.destination.clone(),
+            truncate: session.truncate,
+            limiter: session
+                .rate_pps
+                .map(|rate| Mutex::new(RateLimiter::new(rate))),
+            sequence: AtomicU32::new(0),
+            metrics: SessionMetrics::new(&session.name),
+        })
+    }
+
+    /// Take a token for a copy from the rate limiter of the session, if any
+    pub(crate) fn admit(&self, now: Instant) -> bool {
+        let admitted = self
+            .limiter
+            .as_ref()
+            .is_none_or(|limiter| limiter.lock().try_take(now));
+        if !admitted {
+            self.metrics.rate_limited.increment(1);
+        }
+        admitted
+    }
+
+    /// The sequence number of the next copy
+    pub(crate) fn next_sequence(&self) -> u32 {
+        self.sequence.fetch_add(1, Ordering::Relaxed)
+    }
+}
+
+/// The taps of the mirror sessions
+#[derive(Clone, Debug, Default)]
+pub struct MirrorTable {
+    sessions: Vec<Arc<SessionTap>>,
+    generation: TableGeneration,
+}
+
+impl Generational for MirrorTable {
+    fn generation(&self) -> TableGeneration {
+        self.generation
+    }
+    fn set_generation(&mut self, generation: TableGeneration) {
+        self.generation = generation;
+    }
+}
+
+impl MirrorTable {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the mirror table from the mirror configuration
+    ///
+    /// # Errors
+    ///
+    /// Fails if a session mirrors a VPC that does not exist. Validation should prevent it.
+    pub fn build(
+        mirror: &MirrorConfig,
+        vpc_table: &ValidatedVpcTable,
+    ) -> Result<Self, ConfigError> {
+        let mut table = Self::new();
+        for session in mirror.iter() {
+            table
+                .sessions
+                .push(Arc::new(SessionTap::new(session, vpc_table)?));
+        }
+        Ok(table)
+    }
+
+    pub fn sessions(&self) -> impl Iterator<Item = &Arc<SessionTap>> {
+        self.sessions.iter()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+}
+
+impl Display for MirrorTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for session in &self.sessions {
+            writeln!(f, " {} -> {}", session.name, session.destination)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_tap_filter() {
+        let if1 = InterfaceIndex::try_new(1).ok();
+        let if2 = InterfaceIndex::try_new(2).ok();
+        let vpcd = VpcDiscriminant::from_vni(net::vxlan::Vni::new_checked(3000).unwrap());
+        let addrs = Some([
+            IpAddr::from_str("10.0.0.1").unwrap(),
+            IpAddr::from_str("192.168.0.1").unwrap(),
+        ]);
+
+        // the empty filter matches all
+        assert!(TapFilter::default().matches([None, None], [None, None], None));
+
+        let filter = TapFilter {
+            interface: Some(if2),
+            vpcd: Some(vpcd),
+            prefix: Some(Prefix::from_str("192.168.0.0/16").unwrap()),
+        };
+        assert!(filter.matches([if1, if2], [None, Some(vpcd)], addrs));
+        assert!(!filter.matches([if1, None], [None, Some(vpcd)], addrs));
+        assert!(!filter.matches([if1, if2], [None, None], addrs));
+        assert!(!filter.matches([if1, if2], [None, Some(vpcd)], None));
+
+        // an interface which does not exist matches nothing
+        let filter = TapFilter {
+            interface: Some(None),
+            ..TapFilter::default()
+        };
+        assert!(!filter.matches([None, None], [None, None], None));
+    }
+}
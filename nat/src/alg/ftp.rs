@@ -0,0 +1,248 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! FTP application layer gateway.
+//!
+//! In active mode, the client tells the server where to open the data connection to with the
+//! `PORT` or `EPRT` commands (RFC 959, RFC 2428). Those carry the private address of the client:
+//! they are rewritten with the masquerading address and a port allocated for the data connection,
+//! whose flows are set up in advance since the server opens it.
+//!
+//! In passive mode, the server answers `PASV` with the address to open the data connection to
+//! (`227` replies). The client opens the data connection, so masquerading needs no help, but the
+//! address is rewritten if the client reaches the server under another address (e.g. static NAT).
+//! `EPSV` replies carry no address and are left alone.
+//!
+//! Commands and replies are expected to start a TCP segment and not to span several of them.
+
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::ops::Range;
+
+/// Port of the FTP control connection
+pub(crate) const FTP_PORT: u16 = 21;
+
+/// Port the servers open active mode data connections from
+pub(crate) const FTP_DATA_PORT: u16 = 20;
+
+/// The kind of message announcing the endpoint of a data connection
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Announcement {
+    /// `PORT h1,h2,h3,h4,p1,p2`, from the client
+    Port,
+    /// `EPRT |1|a.b.c.d|port|` from the client, with its delimiter
+    Eprt(u8),
+    /// `227 Entering Passive Mode (h1,h2,h3,h4,p1,p2)`, from the server
+    Pasv,
+}
+
+/// The endpoint of a data connection, found in a message of the control connection
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct Endpoint {
+    pub(crate) kind: Announcement,
+    pub(crate) addr: SocketAddrV4,
+    /// The octets of the payload which encode the endpoint
+    pub(crate) range: Range<usize>,
+}
+
+impl Endpoint {
+    /// Encode another endpoint as this one is, to replace it in the payload
+    pub(crate) fn encode(&self, addr: SocketAddrV4) -> Vec<u8> {
+        let [h1, h2, h3, h4] = addr.ip().octets();
+        let [p1, p2] = addr.port().to_be_bytes();
+        match self.kind {
+            Announcement::Port | Announcement::Pasv => {
+                format!("{h1},{h2},{h3},{h4},{p1},{p2}").into_bytes()
+            }
+            Announcement::Eprt(delim) => {
+                format!("{}{}{}", addr.ip(), char::from(delim), addr.port()).into_bytes()
+            }
+        }
+    }
+}
+
+/// Parse a decimal number of at most `max` at the start of `input`, returning the number and
+/// the count of digits.
+fn parse_number(input: &[u8], max: u32) -> Option<(u32, usize)> {
+    let digits = input.iter().take_while(|c| c.is_ascii_digit()).count();
+    if digits == 0 || digits > 5 {
+        return None;
+    }
+    let value = input[..digits]
+        .iter()
+        .fold(0, |acc, c| acc * 10 + u32::from(c - b'0'));
+    (value <= max).then_some((value, digits))
+}
+
+/// Parse `h1,h2,h3,h4,p1,p2` at the start of `input`, returning the endpoint and its length
+fn parse_host_port(input: &[u8]) -> Option<(SocketAddrV4, usize)> {
+    let mut fields = [0u8; 6];
+    let mut pos = 0;
+    for (n, field) in fields.iter_mut().enumerate() {
+        if n > 0 {
+            if input.get(pos) != Some(&b',') {
+                return None;
+            }
+            pos += 1;
+        }
+        let (value, len) = parse_number(&input[pos..], u32::from(u8::MAX))?;
+        *field = u8::try_from(value).ok()?;
+        pos += len;
+    }
+    let [h1, h2, h3, h4, p1, p2] = fields;
+    let addr = SocketAddrV4::new(Ipv4Addr::new(h1, h2, h3, h4), u16::from_be_bytes([p1, p2]));
+    Some((addr, pos))
+}
+
+/// The first line of a message, without its terminator
+fn first_line(payload: &[u8]) -> Option<&[u8]> {
+    let end = payload.windows(2).position(|w| w == b"\r\n")?;
+    Some(&payload[..end])
+}
+
+/// Parse the `PORT` and `EPRT` commands, which a client sends to open an active mode data
+/// connection. IPv6 endpoints are not supported.
+pub(crate) fn parse_command(payload: &[u8]) -> Option<Endpoint> {
+    let line = first_line(payload)?;
+    if line.len() < 5 || line[4] != b' ' {
+        return None;
+    }
+    let args = &line[5..];
+    let start = 5 + args.iter().take_while(|c| **c == b' ').count();
+    let args = &line[start..];
+    match &line[..4] {
+        cmd if cmd.eq_ignore_ascii_case(b"PORT") => {
+            let (addr, len) = parse_host_port(args)?;
+            (len == args.len()).then_some(Endpoint {
+                kind: Announcement::Port,
+                addr,
+                range: start..start + len,
+            })
+        }
+        cmd if cmd.eq_ignore_ascii_case(b"EPRT") => {
+            // <d><af><d><address><d><port><d>, with any printable delimiter
+            let delim = *args.first().filter(|d| (33..=126).contains(*d))?;
+            let mut fields = args[1..].split(|c| *c == delim);
+            let (af, ip, port) = (fields.next()?, fields.next()?, fields.next()?);
+            if af != b"1" || fields.next() != Some(b"".as_slice()) || fields.next().is_some() {
+                return None;
+            }
+            let (port_num, port_len) = parse_number(port, u32::from(u16::MAX))?;
+            if port_len != port.len() {
+                return None;
+            }
+            let from = start + af.len() + 2;
+            Some(Endpoint {
+                kind: Announcement::Eprt(delim),
+                addr: SocketAddrV4::new(
+                    std::str::from_utf8(ip).ok()?.parse().ok()?,
+                    u16::try_from(port_num).ok()?,
+                ),
+                range: from..from + ip.len() + 1 + port.len(),
+            })
+        }
+        _ => None,
+    }
+}
+
+/// Parse the `227` replies of a server to `PASV`
+pub(crate) fn parse_reply(payload: &[u8]) -> Option<Endpoint> {
+    let line = first_line(payload)?;
+    if !line.starts_with(b"227") {
+        return None;
+    }
+    // the format of the text is not mandated: the endpoint is the first sequence of six numbers
+    (3..line.len())
+        .filter(|pos| line[*pos].is_ascii_digit() && !line[*pos - 1].is_ascii_digit())
+        .find_map(|pos| {
+            let (addr, len) = parse_host_port(&line[pos..])?;
+            Some(Endpoint {
+                kind: Announcement::Pasv,
+                addr,
+                range: pos..pos + len,
+            })
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddrV4 {
+        s.parse().unwrap()
+    }
+
+    fn rewrite(payload: &[u8], endpoint: &Endpoint, to: SocketAddrV4) -> Vec<u8> {
+        let mut out = payload[..endpoint.range.start].to_vec();
+        out.extend(endpoint.encode(to));
+        out.extend(&payload[endpoint.range.end..]);
+        out
+    }
+
+    #[test]
+    fn test_port() {
+        let payload = b"PORT 10,0,0,1,4,1\r\n";
+        let endpoint = parse_command(payload).unwrap();
+        assert_eq!(endpoint.kind, Announcement::Port);
+        assert_eq!(endpoint.addr, addr("10.0.0.1:1025"));
+        assert_eq!(
+            rewrite(payload, &endpoint, addr("192.168.100.200:40000")),
+            b"PORT 192,168,100,200,156,64\r\n"
+        );
+
+        assert!(parse_command(b"port 10,0,0,1,4,1\r\n").is_some());
+        assert!(parse_command(b"PORT 10,0,0,1,4\r\n").is_none());
+        assert!(parse_command(b"PORT 10,0,0,256,4,1\r\n").is_none());
+        assert!(parse_command(b"PORT 10,0,0,1,4,1").is_none());
+        assert!(parse_command(b"PORT 10,0,0,1,4,1 x\r\n").is_none());
+        assert!(parse_command(b"USER anonymous\r\n").is_none());
+    }
+
+    #[test]
+    fn test_eprt() {
+        let payload = b"EPRT |1|10.0.0.1|1025|\r\n";
+        let endpoint = parse_command(payload).unwrap();
+        assert_eq!(endpoint.kind, Announcement::Eprt(b'|'));
+        assert_eq!(endpoint.addr, addr("10.0.0.1:1025"));
+        assert_eq!(
+            rewrite(payload, &endpoint, addr("192.168.100.200:40000")),
+            b"EPRT |1|192.168.100.200|40000|\r\n"
+        );
+
+        let payload = b"EPRT !1!10.0.0.1!1025!\r\n";
+        let endpoint = parse_command(payload).unwrap();
+        assert_eq!(
+            rewrite(payload, &endpoint, addr("1.2.3.4:5")),
+            b"EPRT !1!1.2.3.4!5!\r\n"
+        );
+
+        assert!(parse_command(b"EPRT |2|::1|1025|\r\n").is_none());
+        assert!(parse_command(b"EPRT |1|10.0.0.1|1025\r\n").is_none());
+        assert!(parse_command(b"EPRT |1|10.0.0.1|70000|\r\n").is_none());
+    }
+
+    #[test]
+    fn test_pasv() {
+        let payload = b"227 Entering Passive Mode (10,1,1,1,195,80).\r\n";
+        let endpoint = parse_reply(payload).unwrap();
+        assert_eq!(endpoint.kind, Announcement::Pasv);
+        assert_eq!(endpoint.addr, addr("10.1.1.1:50000"));
+        assert_eq!(
+            rewrite(payload, &endpoint, addr("172.16.0.1:50000")),
+            b"227 Entering Passive Mode (172,16,0,1,195,80).\r\n"
+        );
+
+        let endpoint = parse_reply(b"227 =10,1,1,1,195,80\r\n").unwrap();
+        assert_eq!(endpoint.addr, addr("10.1.1.1:50000"));
+        assert!(parse_reply(b"229 Entering Extended Passive Mode (|||50000|)\r\n").is_none());
+        assert!(parse_reply(b"200 PORT command successful\r\n").is_none());
+    }
+
+    #[test]
+    fn test_parse_garbage() {
+        bolero::check!().for_each(|payload: &[u8]| {
+            if let Some(endpoint) = parse_command(payload).or_else(|| parse_reply(payload)) {
+                assert!(endpoint.range.end <= payload.len());
+            }
+        });
+    }
+}
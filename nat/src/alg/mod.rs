@@ -0,0 +1,382 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Application layer gateways (ALGs) of masquerading.
+//!
+//! Some protocols carry addresses and ports in their payload, which masquerading would otherwise
+//! leave pointing to the private side. ALGs track the flows of such protocols, rewrite the
+//! addresses in their payload, and set up the flows of the connections they announce.
+//!
+//! ALGs are attached to the flows that masquerading creates, by destination port. Each one can be
+//! enabled separately with an [`AlgConfig`]. Their activity is counted in [`AlgStats`].
+
+mod ftp;
+mod seqadj;
+mod sip;
+
+use crate::common::NatAction;
+use crate::masquerade::NatTranslate;
+use common::cliprovider::{CliSource, Heading};
+use concurrency::sync::atomic::{AtomicU64, Ordering};
+use concurrency::sync::{Arc, Mutex};
+use net::FlowKey;
+use net::buffer::PacketBufferMut;
+use net::flows::{ExtractRef, FlowInfo};
+use net::headers::{TryTcp, TryTcpMut};
+use net::ip::NextHeader;
+use net::packet::{Packet, PayloadEditError};
+use seqadj::SeqAdjust;
+use std::fmt::Display;
+use std::net::{IpAddr, SocketAddrV4};
+
+#[allow(unused)]
+use tracing::{debug, warn};
+
+pub(crate) use ftp::FTP_DATA_PORT;
+
+/// The application layer gateways
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlgKind {
+    /// File Transfer Protocol, over TCP port 21
+    Ftp,
+    /// Session Initiation Protocol, over UDP port 5060
+    Sip,
+}
+
+impl Display for AlgKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlgKind::Ftp => f.pad("FTP"),
+            AlgKind::Sip => f.pad("SIP"),
+        }
+    }
+}
+
+/// The application layer gateways to enable
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct AlgConfig {
+    pub ftp: bool,
+    pub sip: bool,
+}
+
+impl AlgConfig {
+    /// Tell if any ALG is enabled
+    #[must_use]
+    pub fn any(&self) -> bool {
+        self.ftp || self.sip
+    }
+
+    /// The ALG to track a new flow with, given the key of its first packet
+    pub(crate) fn alg_for(&self, key: &FlowKey) -> Option<AlgKind> {
+        if !key.dst_ip().is_ipv4() {
+            return None;
+        }
+        let dst_port = key.dst_port()?.get();
+        match key.proto() {
+            NextHeader::TCP if self.ftp && dst_port == ftp::FTP_PORT => Some(AlgKind::Ftp),
+            NextHeader::UDP if self.sip && dst_port == sip::SIP_PORT => Some(AlgKind::Sip),
+            _ => None,
+        }
+    }
+}
+
+/// Counters of an application layer gateway
+#[derive(Debug, Default)]
+pub(crate) struct AlgCounters {
+    /// Flows tracked
+    pub(crate) sessions: AtomicU64,
+    /// Payloads rewritten
+    pub(crate) rewrites: AtomicU64,
+    /// Connections set up in advance
+    pub(crate) expectations: AtomicU64,
+    /// Payloads which could not be processed
+    pub(crate) errors: AtomicU64,
+}
+
+/// Counters of the application layer gateways
+#[derive(Debug, Default)]
+pub struct AlgStats {
+    ftp: AlgCounters,
+    sip: AlgCounters,
+}
+
+impl AlgStats {
+    /// The counters of an ALG
+    pub(crate) fn counters(&self, kind: AlgKind) -> &AlgCounters {
+        match kind {
+            AlgKind::Ftp => &self.ftp,
+            AlgKind::Sip => &self.sip,
+        }
+    }
+}
+
+macro_rules! ALG_STATS {
+    () => {
+        "    {:<8} {:>12} {:>12} {:>12} {:>12}"
+    };
+}
+
+impl CliSource for AlgStats {}
+
+impl Display for AlgStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Heading("NAT application layer gateways").fmt(f)?;
+        writeln!(
+            f,
+            ALG_STATS!(),
+            "ALG", "sessions", "rewrites", "expectations", "errors"
+        )?;
+        for kind in [AlgKind::Ftp, AlgKind::Sip] {
+            let counters = self.counters(kind);
+            writeln!(
+                f,
+                ALG_STATS!(),
+                kind,
+                counters.sessions.load(Ordering::Relaxed),
+                counters.rewrites.load(Ordering::Relaxed),
+                counters.expectations.load(Ordering::Relaxed),
+                counters.errors.load(Ordering::Relaxed)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Errors of the application layer gateways. Packets are let through unmodified on error.
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum AlgError {
+    #[error("failed to edit payload: {0}")]
+    Edit(#[from] PayloadEditError),
+    #[error("failed to set up data connection for {0}")]
+    NoExpectation(SocketAddrV4),
+}
+
+/// The state of a flow tracked by an ALG, shared by the two flows of a connection
+#[derive(Debug, Clone)]
+pub(crate) struct AlgState {
+    kind: AlgKind,
+    /// Sequence number adjustments of each direction, indexed by [`Self::direction`]
+    seqadj: Arc<Mutex<[SeqAdjust; 2]>>,
+}
+
+impl Display for AlgState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+/// The endpoints of a flow key, if IPv4
+fn endpoints(key: &FlowKey) -> Option<(SocketAddrV4, SocketAddrV4)> {
+    let (IpAddr::V4(src), IpAddr::V4(dst)) = (key.src_ip(), key.dst_ip()) else {
+        return None;
+    };
+    let (src_port, dst_port) = key.ports()?;
+    Some((
+        SocketAddrV4::new(*src, src_port.get()),
+        SocketAddrV4::new(*dst, dst_port.get()),
+    ))
+}
+
+impl AlgState {
+    pub(crate) fn new(kind: AlgKind) -> Self {
+        Self {
+            kind,
+            seqadj: Arc::new(Mutex::new([SeqAdjust::default(); 2])),
+        }
+    }
+
+    /// The ALG state of a flow, if tracked by an ALG
+    pub(crate) fn of(flow_info: &FlowInfo) -> Option<Self> {
+        flow_info
+            .locked
+            .read()
+            .alg_state
+            .extract_ref::<AlgState>()
+            .cloned()
+    }
+
+    /// Index of the direction of the flow with the given translation: 0 for the direction of the
+    /// client, which is source-translated, 1 for the direction of the server.
+    fn direction(action: NatAction) -> usize {
+        match action {
+            NatAction::SrcNat => 0,
+            NatAction::DstNat => 1,
+        }
+    }
+
+    /// Process a packet of a tracked flow, once translated. `flow_key` is the key of the flow of
+    /// the packet, and `translate` its translation. `expect` sets up the flows of an active mode
+    /// FTP data connection to the given client endpoint, returning the endpoint the server must
+    /// connect to.
+    pub(crate) fn process<Buf, F>(
+        &self,
+        packet: &mut Packet<Buf>,
+        flow_key: &FlowKey,
+        translate: &NatTranslate,
+        stats: &AlgStats,
+        expect: F,
+    ) where
+        Buf: PacketBufferMut,
+        F: FnOnce(SocketAddrV4) -> Option<SocketAddrV4>,
+    {
+        let counters = stats.counters(self.kind);
+        let result = match self.kind {
+            AlgKind::Ftp => self.process_ftp(packet, flow_key, translate, counters, expect),
+            AlgKind::Sip => Self::process_sip(packet, flow_key, translate),
+        };
+        match result {
+            Ok(true) => {
+                counters.rewrites.fetch_add(1, Ordering::Relaxed);
+            }
+            Ok(false) => {}
+            Err(e) => {
+                counters.errors.fetch_add(1, Ordering::Relaxed);
+                debug!(
+                    "{} ALG failed to process packet of flow {flow_key}: {e}",
+                    self.kind
+                );
+            }
+        }
+    }
+
+    /// Rewrite the endpoints of data connections announced on an FTP control connection, and keep
+    /// the sequence numbers of the connection consistent. Returns true if the payload was rewritten.
+    fn process_ftp<Buf, F>(
+        &self,
+        packet: &mut Packet<Buf>,
+        flow_key: &FlowKey,
+        translate: &NatTranslate,
+        counters: &AlgCounters,
+        expect: F,
+    ) -> Result<bool, AlgError>
+    where
+        Buf: PacketBufferMut,
+        F: FnOnce(SocketAddrV4) -> Option<SocketAddrV4>,
+    {
+        let Some(tcp) = packet.try_tcp() else {
+            return Ok(false);
+        };
+        let (seq, ack, has_ack) = (tcp.sequence_number(), tcp.ack_number(), tcp.ack());
+        let direction = Self::direction(translate.action);
+
+        let payload = packet.payload().as_ref();
+        let edit = match translate.action {
+            // active mode: only data connections to the client itself are set up
+            NatAction::SrcNat => match ftp::parse_command(payload) {
+                Some(endpoint) if IpAddr::V4(*endpoint.addr.ip()) == *flow_key.src_ip() => {
+                    let mapped =
+                        expect(endpoint.addr).ok_or(AlgError::NoExpectation(endpoint.addr))?;
+                    counters.expectations.fetch_add(1, Ordering::Relaxed);
+                    Some((endpoint.range.clone(), endpoint.encode(mapped)))
+                }
+                _ => None,
+            },
+            // passive mode: the server may be known to the client under another address
+            NatAction::DstNat => match (ftp::parse_reply(payload), packet.try_ip()) {
+                (Some(endpoint), Some(ip))
+                    if IpAddr::V4(*endpoint.addr.ip()) == *flow_key.src_ip()
+                        && ip.src_addr() != *flow_key.src_ip() =>
+                {
+                    let IpAddr::V4(seen) = ip.src_addr() else {
+                        return Ok(false);
+                    };
+                    let mapped = SocketAddrV4::new(seen, endpoint.addr.port());
+                    Some((endpoint.range.clone(), endpoint.encode(mapped)))
+                }
+                _ => None,
+            },
+        };
+
+        let mut seqadj = self.seqadj.lock();
+        let rewritten = if let Some((range, with)) = edit {
+            let delta = i32::try_from(with.len()).unwrap_or_else(|_| unreachable!())
+                - i32::try_from(range.len()).unwrap_or_else(|_| unreachable!());
+            packet.splice_payload(range, &with)?;
+            seqadj[direction].record(seq, delta);
+            true
+        } else {
+            false
+        };
+
+        let new_seq = seqadj[direction].seq(seq);
+        let new_ack = seqadj[1 - direction].ack(ack);
+        drop(seqadj);
+        if new_seq != seq || (has_ack && new_ack != ack) {
+            let Some(tcp) = packet.try_tcp_mut() else {
+                unreachable!()
+            };
+            tcp.set_sequence_number(new_seq);
+            if has_ack {
+                tcp.set_ack_number(new_ack);
+            }
+            packet.meta_mut().set_checksum_refresh(true);
+        }
+        Ok(rewritten)
+    }
+
+    /// Rewrite the endpoint of the user agent in a SIP message. Returns true if the payload was
+    /// rewritten.
+    fn process_sip<Buf: PacketBufferMut>(
+        packet: &mut Packet<Buf>,
+        flow_key: &FlowKey,
+        translate: &NatTranslate,
+    ) -> Result<bool, AlgError> {
+        let (Some((src, dst)), IpAddr::V4(use_ip)) = (endpoints(flow_key), translate.use_ip) else {
+            return Ok(false);
+        };
+        let translated = SocketAddrV4::new(use_ip, translate.nat_port.as_u16());
+        let original = match translate.action {
+            NatAction::SrcNat => src,
+            NatAction::DstNat => dst,
+        };
+        let payload = packet.payload().as_ref();
+        let Some(message) = sip::rewrite(payload, original, translated) else {
+            return Ok(false);
+        };
+        let len = payload.len();
+        packet.splice_payload(0..len, &message)?;
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use net::{IpProtoKey, TcpProtoKey, UdpProtoKey};
+
+    fn key(proto: IpProtoKey) -> FlowKey {
+        FlowKey::new(
+            None,
+            "10.0.0.1".parse().unwrap(),
+            "172.16.0.1".parse().unwrap(),
+            proto,
+        )
+    }
+
+    #[test]
+    fn test_alg_for() {
+        let ftp = key(IpProtoKey::Tcp(TcpProtoKey::try_from((1025, 21)).unwrap()));
+        let sip = key(IpProtoKey::Udp(
+            UdpProtoKey::try_from((5060, 5060)).unwrap(),
+        ));
+        let http = key(IpProtoKey::Tcp(TcpProtoKey::try_from((1025, 80)).unwrap()));
+
+        let config = AlgConfig::default();
+        assert!(!config.any());
+        assert_eq!(config.alg_for(&ftp), None);
+
+        let config = AlgConfig {
+            ftp: true,
+            sip: false,
+        };
+        assert_eq!(config.alg_for(&ftp), Some(AlgKind::Ftp));
+        assert_eq!(config.alg_for(&sip), None);
+        assert_eq!(config.alg_for(&http), None);
+
+        let config = AlgConfig {
+            ftp: true,
+            sip: true,
+        };
+        assert_eq!(config.alg_for(&sip), Some(AlgKind::Sip));
+    }
+}
@@ -0,0 +1,104 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Sequence number adjustment of the TCP connections whose payload an ALG resized.
+//!
+//! Once a segment grows or shrinks, the sequence numbers of the segments that follow in the same
+//! direction are off by the size difference, and so are the acknowledgements of the peer. As in
+//! Linux, only the position of the last resize is recorded: segments before it (retransmissions)
+//! are shifted by the previous offset, segments after it by the current one.
+
+/// The shift of the sequence numbers of one direction of a connection
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct SeqAdjust {
+    /// Sequence number of the last resized segment
+    correction_pos: u32,
+    /// Shift of the segments up to `correction_pos`
+    offset_before: i32,
+    /// Shift of the segments after `correction_pos`
+    offset_after: i32,
+}
+
+/// Tell if sequence number `a` comes after `b`, accounting for wrap-around
+#[allow(clippy::cast_possible_wrap)]
+fn after(a: u32, b: u32) -> bool {
+    (a.wrapping_sub(b) as i32) > 0
+}
+
+impl SeqAdjust {
+    /// Record that the segment starting at sequence number `seq` grew by `delta` octets, or
+    /// shrunk if `delta` is negative. `seq` is the sequence number before adjustment.
+    pub(crate) fn record(&mut self, seq: u32, delta: i32) {
+        if delta == 0 {
+            return;
+        }
+        // a retransmitted segment is resized again, but must not be accounted for twice
+        if self.offset_before == self.offset_after || after(seq, self.correction_pos) {
+            self.correction_pos = seq;
+            self.offset_before = self.offset_after;
+            self.offset_after += delta;
+        }
+    }
+
+    /// Adjust the sequence number of a segment of this direction
+    pub(crate) fn seq(&self, seq: u32) -> u32 {
+        let offset = if after(seq, self.correction_pos) {
+            self.offset_after
+        } else {
+            self.offset_before
+        };
+        seq.wrapping_add_signed(offset)
+    }
+
+    /// Adjust the acknowledgement number of a segment of the other direction
+    pub(crate) fn ack(&self, ack: u32) -> u32 {
+        let offset = if after(
+            ack.wrapping_add_signed(self.offset_before.wrapping_neg()),
+            self.correction_pos,
+        ) {
+            self.offset_after
+        } else {
+            self.offset_before
+        };
+        ack.wrapping_add_signed(offset.wrapping_neg())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::SeqAdjust;
+
+    #[test]
+    fn test_seqadj() {
+        let mut adj = SeqAdjust::default();
+        assert_eq!(adj.seq(1000), 1000);
+        assert_eq!(adj.ack(1000), 1000);
+
+        // segment [1000, 1020) grew by 3 octets
+        adj.record(1000, 3);
+        assert_eq!(adj.seq(1000), 1000);
+        assert_eq!(adj.seq(1020), 1023);
+        assert_eq!(adj.ack(1023), 1020);
+        assert_eq!(adj.ack(1000), 1000);
+
+        // a retransmission is not accounted for twice
+        adj.record(1000, 3);
+        assert_eq!(adj.seq(1020), 1023);
+
+        // segment [1020, 1050) shrunk by 5 octets
+        adj.record(1020, -5);
+        assert_eq!(adj.seq(1020), 1023);
+        assert_eq!(adj.seq(1050), 1048);
+        assert_eq!(adj.ack(1048), 1050);
+        assert_eq!(adj.ack(1023), 1020);
+    }
+
+    #[test]
+    fn test_seqadj_wraparound() {
+        let mut adj = SeqAdjust::default();
+        adj.record(u32::MAX - 9, 4);
+        assert_eq!(adj.seq(u32::MAX - 9), u32::MAX - 9);
+        assert_eq!(adj.seq(10), 14);
+        assert_eq!(adj.ack(14), 10);
+    }
+}
@@ -0,0 +1,170 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! SIP application layer gateway.
+//!
+//! SIP user agents announce where to send them responses, requests and media in the `Via` and
+//! `Contact` headers and in the SDP body of their messages (RFC 3261, RFC 4566). The addresses
+//! of outgoing messages are rewritten with the masquerading address and port, and those of
+//! incoming messages restored, so that the peer reaches back through the masquerading flow.
+//!
+//! Only SIP over UDP is supported. The ports of media streams are not mapped: media from the peer
+//! only get through once the user agent sent some from the announced port (symmetric RTP).
+
+use std::net::SocketAddrV4;
+
+/// Port of SIP signalling
+pub(crate) const SIP_PORT: u16 = 5060;
+
+/// Tell if a header carries the address of the user agent: `Via` and `Contact`, in their full or
+/// compact form.
+fn is_address_header(name: &str) -> bool {
+    ["via", "v", "contact", "m"]
+        .iter()
+        .any(|h| name.trim().eq_ignore_ascii_case(h))
+}
+
+/// Tell if a header is `Content-Length`, in its full or compact form
+fn is_content_length(name: &str) -> bool {
+    let name = name.trim();
+    name.eq_ignore_ascii_case("content-length") || name.eq_ignore_ascii_case("l")
+}
+
+/// Replace the occurrences of `from` in `text` with `to`, where `from` is not part of a longer
+/// address or number.
+fn replace_token(text: &str, from: &str, to: &str) -> String {
+    let is_part = |c: Option<char>| c.is_some_and(|c| c.is_ascii_digit() || c == '.');
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(at) = rest.find(from) {
+        let end = at + from.len();
+        let isolated =
+            !is_part(rest[..at].chars().next_back()) && !is_part(rest[end..].chars().next());
+        out.push_str(&rest[..at]);
+        out.push_str(if isolated { to } else { from });
+        rest = &rest[end..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Rewrite the addresses of a SIP message which refer to endpoint `from` with endpoint `to`.
+/// Returns the rewritten message, or `None` if the message is left unchanged.
+pub(crate) fn rewrite(message: &[u8], from: SocketAddrV4, to: SocketAddrV4) -> Option<Vec<u8>> {
+    let message = std::str::from_utf8(message).ok()?;
+    let (head, body) = message.split_once("\r\n\r\n")?;
+    let (from_ip, to_ip) = (from.ip().to_string(), to.ip().to_string());
+
+    // headers: the address and port of the user agent, then any other mention of its address
+    let mut lines: Vec<String> = head
+        .split("\r\n")
+        .map(|line| match line.split_once(':') {
+            Some((name, value)) if is_address_header(name) => {
+                let value = replace_token(value, &from.to_string(), &to.to_string());
+                format!("{name}:{}", replace_token(&value, &from_ip, &to_ip))
+            }
+            _ => line.to_string(),
+        })
+        .collect();
+
+    // sdp: the addresses of the connection and of the originator of the session
+    let new_body: String = body
+        .split_inclusive("\r\n")
+        .map(|line| {
+            if line.starts_with("c=") || line.starts_with("o=") {
+                replace_token(line, &from_ip, &to_ip)
+            } else {
+                line.to_string()
+            }
+        })
+        .collect();
+    if new_body.len() != body.len() {
+        for line in &mut lines {
+            if let Some((name, _)) = line.split_once(':')
+                && is_content_length(name)
+            {
+                *line = format!("{name}: {}", new_body.len());
+            }
+        }
+    }
+
+    let rewritten = format!("{}\r\n\r\n{new_body}", lines.join("\r\n"));
+    (rewritten != message).then(|| rewritten.into_bytes())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn addr(s: &str) -> SocketAddrV4 {
+        s.parse().unwrap()
+    }
+
+    const INVITE: &str = "INVITE sip:bob@example.com SIP/2.0\r\n\
+        Via: SIP/2.0/UDP 10.0.0.1:5060;branch=z9hG4bK776asdhds\r\n\
+        From: Alice <sip:alice@example.com>;tag=1928301774\r\n\
+        To: Bob <sip:bob@example.com>\r\n\
+        Call-ID: a84b4c76e66710@10.0.0.1\r\n\
+        CSeq: 314159 INVITE\r\n\
+        Contact: <sip:alice@10.0.0.1:5060>\r\n\
+        Content-Type: application/sdp\r\n\
+        Content-Length: 108\r\n\
+        \r\n\
+        v=0\r\n\
+        o=alice 2890844526 2890844526 IN IP4 10.0.0.1\r\n\
+        s=-\r\n\
+        c=IN IP4 10.0.0.1\r\n\
+        t=0 0\r\n\
+        m=audio 49170 RTP/AVP 0\r\n";
+
+    #[test]
+    fn test_rewrite_outgoing() {
+        assert_eq!(INVITE.split_once("\r\n\r\n").unwrap().1.len(), 108);
+        let rewritten = rewrite(
+            INVITE.as_bytes(),
+            addr("10.0.0.1:5060"),
+            addr("192.168.100.200:40000"),
+        )
+        .unwrap();
+        let rewritten = String::from_utf8(rewritten).unwrap();
+        assert!(rewritten.contains("Via: SIP/2.0/UDP 192.168.100.200:40000;branch"));
+        assert!(rewritten.contains("Contact: <sip:alice@192.168.100.200:40000>"));
+        // the call id is opaque
+        assert!(rewritten.contains("Call-ID: a84b4c76e66710@10.0.0.1"));
+        assert!(rewritten.contains("c=IN IP4 192.168.100.200\r\n"));
+        assert!(rewritten.contains("IN IP4 192.168.100.200\r\ns=-"));
+        let body = rewritten.split_once("\r\n\r\n").unwrap().1;
+        assert!(rewritten.contains(&format!("Content-Length: {}\r\n", body.len())));
+    }
+
+    #[test]
+    fn test_rewrite_incoming() {
+        let response = "SIP/2.0 200 OK\r\n\
+            v: SIP/2.0/UDP 192.168.100.200:40000;branch=z9hG4bK776asdhds\r\n\
+            l: 0\r\n\
+            \r\n";
+        let rewritten = rewrite(
+            response.as_bytes(),
+            addr("192.168.100.200:40000"),
+            addr("10.0.0.1:5060"),
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(rewritten).unwrap(),
+            "SIP/2.0 200 OK\r\n\
+             v: SIP/2.0/UDP 10.0.0.1:5060;branch=z9hG4bK776asdhds\r\n\
+             l: 0\r\n\
+             \r\n"
+        );
+    }
+
+    #[test]
+    fn test_rewrite_unrelated() {
+        // addresses which merely start like the one to rewrite are left alone
+        let message = "OPTIONS sip:bob@example.com SIP/2.0\r\n\
+            Via: SIP/2.0/UDP 10.0.0.10:5060\r\n\
+            \r\n";
+        assert!(rewrite(message.as_bytes(), addr("10.0.0.1:5060"), addr("1.2.3.4:5")).is_none());
+        assert!(rewrite(b"\xff\xfe", addr("10.0.0.1:5060"), addr("1.2.3.4:5")).is_none());
+    }
+}
@@ -19,6 +19,7 @@
 //! - Only NAT44 is supported (no NAT46, NAT64, or NAT66)
 //! - "Expose" objects mixing IPv4 and IPv6 endpoints or list of exposed IPs are not supported
 
+pub mod alg;
 mod common;
 mod icmp_handler;
 pub mod masquerade;
@@ -29,6 +30,7 @@ pub mod static_nat;
 #[cfg(test)]
 mod test;
 
+pub use alg::{AlgConfig, AlgStats};
 pub use icmp_handler::nf::IcmpErrorHandler;
 pub use masquerade::Masquerade;
 pub use port::NatPort;
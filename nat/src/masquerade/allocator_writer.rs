@@ -2,13 +2,18 @@
 // Copyright Open Network Fabric Authors
 
 use crate::masquerade::apalloc::NatAllocator;
+use crate::masquerade::lease::NatLeases;
 use concurrency::slot::SlotOption;
 use concurrency::sync::Arc;
 use config::GenId;
+use config::external::gwgroup::{GwGroupTable, NatPartition};
 use config::external::overlay::vpc::{ValidatedPeering, ValidatedVpcTable};
 use config::external::overlay::vpcpeering::ValidatedExpose;
+use config::external::session_table::SessionTableConfig;
 use flow_entry::flow_table::FlowTable;
 use net::packet::VpcDiscriminant;
+use stats::PublishMetrics;
+use std::time::Instant;
 use tracing::debug;
 
 use crate::masquerade::flows::check_masquerading_flows;
@@ -20,17 +25,21 @@ pub(crate) struct MasqueradePeering {
     pub(crate) src_vpcd: VpcDiscriminant,
     pub(crate) dst_vpcd: VpcDiscriminant,
     pub(crate) peering: ValidatedPeering,
+    pub(crate) partition: Option<NatPartition>,
 }
 #[derive(Debug, Default, Clone)]
 pub struct MasqueradeConfig {
     genid: GenId,
     peerings: Vec<MasqueradePeering>,
     randomize: bool,
+    session_table: SessionTableConfig,
 }
 impl PartialEq for MasqueradeConfig {
     fn eq(&self, other: &Self) -> bool {
         // we exclude genid from comparison
-        self.peerings == other.peerings && self.randomize == other.randomize
+        self.peerings == other.peerings
+            && self.randomize == other.randomize
+            && self.session_table == other.session_table
     }
 }
 
@@ -44,6 +53,7 @@ impl MasqueradeConfig {
                     src_vpcd: VpcDiscriminant::from_vni(vpc.vni()),
                     dst_vpcd: VpcDiscriminant::from_vni(vpc_table.get_remote_vni(peering)),
                     peering: peering.clone(),
+                    partition: None,
                 });
             }
         }
@@ -51,6 +61,7 @@ impl MasqueradeConfig {
             genid,
             peerings,
             randomize: true, // randomize by default
+            session_table: SessionTableConfig::default(),
         }
     }
 
@@ -70,6 +81,31 @@ impl MasqueradeConfig {
         self.randomize
     }
 
+    /// Use the idle timeouts of the session table for the sessions of the exposes which do not
+    /// set their own.
+    #[must_use]
+    pub fn set_session_table(mut self, session_table: &SessionTableConfig) -> Self {
+        self.session_table = session_table.clone();
+        self
+    }
+
+    #[must_use]
+    pub fn session_table(&self) -> &SessionTableConfig {
+        &self.session_table
+    }
+
+    /// Restrict the allocations for the peerings served by a partitioned gateway group to the
+    /// partition of the port space owned by the gateway with name `gwname`.
+    #[must_use]
+    pub fn set_partitions(mut self, gwgroups: &GwGroupTable, gwname: &str) -> Self {
+        for p in &mut self.peerings {
+            p.partition = gwgroups
+                .get_group(p.peering.gwgroup())
+                .and_then(|group| group.nat_partition(gwname));
+        }
+        self
+    }
+
     pub(crate) fn iter(&self) -> impl Iterator<Item = &MasqueradePeering> {
         self.peerings.iter()
     }
@@ -95,17 +131,35 @@ impl MasqueradeConfig {
 }
 
 #[derive(Debug)]
-pub struct NatAllocatorWriter(Arc<SlotOption<NatAllocator>>);
+pub struct NatAllocatorWriter {
+    allocator: Arc<SlotOption<NatAllocator>>,
+    leases: Option<Arc<NatLeases>>,
+    /// The allocator is published by swapping it, rather than through a left-right table. Its
+    /// swaps are recorded like the publications of the tables.
+    metrics: PublishMetrics,
+}
 
 impl NatAllocatorWriter {
     #[must_use]
     pub fn new() -> Self {
-        Self(Arc::new(SlotOption::empty()))
+        Self {
+            allocator: Arc::new(SlotOption::empty()),
+            leases: None,
+            metrics: PublishMetrics::new("masquerade-allocator"),
+        }
+    }
+
+    /// Have the allocators honor and record the leases of the bindings in `leases`. The leases
+    /// are kept across allocators, so that hosts keep their bindings across config changes.
+    #[must_use]
+    pub fn with_leases(mut self, leases: Arc<NatLeases>) -> Self {
+        self.leases = Some(leases);
+        self
     }
 
     #[must_use]
     pub fn get_reader(&self) -> NatAllocatorReader {
-        NatAllocatorReader(self.0.clone())
+        NatAllocatorReader(self.allocator.clone())
     }
 
     #[must_use]
@@ -120,8 +174,9 @@ impl NatAllocatorWriter {
     /// previous one be either invalidated or adapted to use the new allocator: their ports/ips
     /// will be transferred (reserved) in the new allocator.
     pub fn update_nat_allocator(&mut self, nat_config: MasqueradeConfig, flow_table: &FlowTable) {
+        let requested = Instant::now();
         let genid = nat_config.genid();
-        let curr_allocator = self.0.load_full();
+        let curr_allocator = self.allocator.load_full();
 
         // keep state as-is if config did not change, and just upgrade flows
         if let Some(current) = curr_allocator.as_ref()
@@ -136,25 +191,32 @@ impl NatAllocatorWriter {
         if !nat_config.has_masquerading_peerings() {
             if curr_allocator.is_some() {
                 debug!("No masquerade is required anymore: will invalidate flows");
-                self.0.store(None);
+                self.store(None, requested);
                 invalidate_all_masquerading_flows(flow_table);
             }
             return;
         }
 
-        let mut allocator = NatAllocator::new(nat_config);
+        let mut allocator = NatAllocator::new(nat_config).set_leases(self.leases.clone());
         if curr_allocator.is_some() {
             let guard = check_masquerading_flows(flow_table, &mut allocator);
             debug!("Replacing masquerade NAT allocator...");
-            self.0.store(Some(Arc::new(allocator)));
+            self.store(Some(Arc::new(allocator)), requested);
             debug!("NAT allocator has been replaced");
             drop(guard);
         } else {
             debug!("Installing new masquerade NAT allocator...");
-            self.0.store(Some(Arc::new(allocator)));
+            self.store(Some(Arc::new(allocator)), requested);
             debug!("NAT allocator is installed");
         }
     }
+
+    /// Publish `allocator`, replacing the current one, as requested at `requested`
+    fn store(&self, allocator: Option<Arc<NatAllocator>>, requested: Instant) {
+        let start = Instant::now();
+        self.allocator.store(allocator);
+        self.metrics.record(start, Some(requested));
+    }
 }
 
 impl Default for NatAllocatorWriter {
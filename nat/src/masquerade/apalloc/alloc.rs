@@ -9,19 +9,21 @@
 //!
 //! See also the architecture diagram at the top of mod.rs.
 
+use super::partition::PortPartition;
 use super::{NatIpWithBitmap, port_alloc};
 use crate::masquerade::allocation::AllocatorError;
 use crate::masquerade::natip::NatIp;
 use crate::port::NatPort;
 use crate::ranges::IpRange;
 use concurrency::sync::{Arc, RwLock, RwLockReadGuard, Weak};
+use config::internal::events::{self, DataplaneEvent};
 use lpm::prefix::range_map::DisjointRangesBTreeMap;
 use lpm::prefix::{IpPrefix, PortRange, Prefix};
 use roaring::RoaringBitmap;
 use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::net::{IpAddr, Ipv6Addr};
 use std::time::Duration;
-use tracing::debug;
+use tracing::{debug, warn};
 
 ///////////////////////////////////////////////////////////////////////////////
 // IpAllocator
@@ -123,6 +125,24 @@ impl<I: NatIpWithBitmap> IpAllocator<I> {
             .reserve_from_pool(ip, self.clone(), self.randomize)
     }
 
+    /// Allocate a port for `ip`, preferably from the port block starting at `base_port`, as
+    /// recorded by a NAT lease. Fails if `ip` is not in the pool, or has no free port left.
+    pub(crate) fn allocate_preferred(
+        &self,
+        ip: I,
+        base_port: u16,
+        allow_null: bool,
+    ) -> Result<port_alloc::AllocatedPort<I>, AllocatorError> {
+        let allocated_ip = self.get_allocated_ip(ip)?;
+        if let Ok(port) = allocated_ip
+            .clone()
+            .allocate_port_in_block_for_ip(base_port, allow_null)
+        {
+            return Ok(port);
+        }
+        allocated_ip.allocate_port_for_ip(allow_null)
+    }
+
     pub(crate) fn reserve(
         &self,
         ip: I,
@@ -162,6 +182,7 @@ impl<I: NatIpWithBitmap> AllocatedIp<I> {
         reserved_port_range: Option<PortRange>,
         randomize: bool,
         exclude_wellknown_ports: bool,
+        partition: Option<PortPartition>,
     ) -> Self {
         Self {
             ip,
@@ -169,6 +190,7 @@ impl<I: NatIpWithBitmap> AllocatedIp<I> {
                 reserved_port_range,
                 randomize,
                 exclude_wellknown_ports,
+                partition,
             ),
             ip_allocator,
         }
@@ -207,6 +229,15 @@ impl<I: NatIpWithBitmap> AllocatedIp<I> {
         Ok(alloc_port)
     }
 
+    fn allocate_port_in_block_for_ip(
+        self: Arc<Self>,
+        base_port: u16,
+        allow_null: bool,
+    ) -> Result<port_alloc::AllocatedPort<I>, AllocatorError> {
+        self.port_allocator
+            .allocate_port_in_block(self.clone(), base_port, allow_null)
+    }
+
     fn reserve_port_for_ip(
         self: Arc<Self>,
         port: NatPort,
@@ -230,6 +261,10 @@ impl<I: NatIpWithBitmap> Drop for AllocatedIp<I> {
 /// the allocated IPs potentially available for use (if they still have free ports)
 #[derive(Debug, Clone)]
 pub(crate) struct NatPool<I: NatIpWithBitmap> {
+    /// The name of the pool, for the events reporting it
+    name: String,
+    /// Whether the pool ran out of addresses, since it was last reported so
+    exhausted: bool,
     bitmap: PoolBitmap,
     bitmap_mapping: BTreeMap<u32, u128>,
     reverse_bitmap_mapping: BTreeMap<u128, u32>,
@@ -237,18 +272,24 @@ pub(crate) struct NatPool<I: NatIpWithBitmap> {
     reserved_prefixes_ports: Option<DisjointRangesBTreeMap<IpRange, PortRange>>,
     idle_timeout: Duration,
     exclude_wellknown_ports: bool,
+    partition: Option<PortPartition>,
 }
 
 impl<I: NatIpWithBitmap> NatPool<I> {
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn new(
+        name: String,
         bitmap: PoolBitmap,
         bitmap_mapping: BTreeMap<u32, u128>,
         reverse_bitmap_mapping: BTreeMap<u128, u32>,
         reserved_prefixes_ports: Option<DisjointRangesBTreeMap<IpRange, PortRange>>,
         idle_timeout: Duration,
         exclude_wellknown_ports: bool,
+        partition: Option<PortPartition>,
     ) -> Self {
         Self {
+            name,
+            exhausted: false,
             bitmap,
             bitmap_mapping,
             reverse_bitmap_mapping,
@@ -256,6 +297,7 @@ impl<I: NatIpWithBitmap> NatPool<I> {
             reserved_prefixes_ports,
             idle_timeout,
             exclude_wellknown_ports,
+            partition,
         }
     }
 
@@ -293,29 +335,47 @@ impl<I: NatIpWithBitmap> NatPool<I> {
         randomize: bool,
     ) -> Result<AllocatedIp<I>, AllocatorError> {
         // Retrieve the first available offset
-        let offset = self.bitmap.pop_ip()?;
+        let offset = self
+            .bitmap
+            .pop_ip()
+            .inspect_err(|_| self.report_exhausted())?;
 
         let ip = I::try_from_offset(offset, &self.bitmap_mapping)?;
 
-        // Check if the IP is in a reserved prefix, retrieve the reserved port range if any
-        let reserved_port_range = self
-            .reserved_prefixes_ports
-            .as_ref()
-            .and_then(|ranges| ranges.lookup(&ip.to_ip_addr()).map(|(_, range)| *range));
-
         Ok(AllocatedIp::new(
             ip,
             ip_allocator,
-            reserved_port_range,
+            self.reserved_port_range(ip),
             randomize,
             self.exclude_wellknown_ports,
+            self.partition.clone(),
         ))
     }
 
+    // Check if the IP is in a reserved prefix, retrieve the reserved port range if any
+    fn reserved_port_range(&self, ip: I) -> Option<PortRange> {
+        self.reserved_prefixes_ports
+            .as_ref()
+            .and_then(|ranges| ranges.lookup(&ip.to_ip_addr()).map(|(_, range)| *range))
+    }
+
     fn deallocate_from_pool(&mut self, ip: I) {
         debug!("Address {ip} was deallocated");
         let offset = I::try_to_offset(ip, &self.reverse_bitmap_mapping).unwrap();
         self.bitmap.set_ip_free(offset);
+        self.exhausted = false;
+    }
+
+    /// Report that the pool ran out of addresses, unless it was already since it last freed one
+    fn report_exhausted(&mut self) {
+        if self.exhausted {
+            return;
+        }
+        self.exhausted = true;
+        warn!("NAT pool {} is exhausted", self.name);
+        events::publish(DataplaneEvent::NatPoolExhausted {
+            pool: self.name.clone(),
+        });
     }
 
     fn reserve_from_pool(
@@ -347,11 +407,13 @@ impl<I: NatIpWithBitmap> NatPool<I> {
         let arc_ip = Arc::new(AllocatedIp::new(
             ip,
             ip_allocator,
-            None,
+            self.reserved_port_range(ip),
             randomize,
             // Keep the low-port exclusion policy for explicitly reserved IPs as well, so
-            // reserve() follows the same TCP/UDP allocation rules as allocate().
+            // reserve() follows the same TCP/UDP allocation rules as allocate(). Same for the
+            // partition of the port space.
             self.exclude_wellknown_ports,
+            self.partition.clone(),
         ));
         self.add_in_use(&arc_ip);
         Ok(arc_ip)
@@ -422,6 +484,11 @@ impl PoolBitmap {
         Self(RoaringBitmap::new())
     }
 
+    /// Number of free addresses
+    pub(crate) fn count(&self) -> u64 {
+        self.0.len()
+    }
+
     fn pop_ip(&mut self) -> Result<u32, AllocatorError> {
         let offset = self.0.min().ok_or(AllocatorError::NoFreeIp)?;
         self.0.remove(offset);
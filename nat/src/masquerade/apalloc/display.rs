@@ -24,6 +24,14 @@ impl Display for NatAllocator {
         Heading("Masquerade NAT allocator table").fmt(f)?;
 
         writeln!(f, "randomize: {}", self.randomize)?;
+        if let Some(leases) = &self.leases {
+            writeln!(
+                f,
+                "leases: {} (lasting {}s)",
+                leases.len(),
+                leases.duration().as_secs()
+            )?;
+        }
 
         writeln!(f, "source pools (IPv4):")?;
         writeln!(with_indent!(f), "{}", self.pools_src44)?;
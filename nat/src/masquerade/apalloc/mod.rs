@@ -67,7 +67,9 @@ use super::allocation::{AllocationResult, AllocatorError};
 use crate::NatPort;
 use crate::masquerade::MasqueradeConfig;
 pub use crate::masquerade::apalloc::natip_with_bitmap::NatIpWithBitmap;
+use crate::masquerade::lease::NatLeases;
 use crate::masquerade::natip::NatIp;
+use concurrency::sync::Arc;
 use config::GenId;
 use net::ip::NextHeader;
 use net::packet::VpcDiscriminant;
@@ -82,6 +84,7 @@ trace_target!("nat-allocation", LevelFilter::ERROR, &["masquerade"]);
 mod alloc;
 mod display;
 mod natip_with_bitmap;
+mod partition;
 mod port_alloc;
 mod setup;
 mod test_alloc;
@@ -220,6 +223,7 @@ pub struct NatAllocator {
     pools_src44: PoolTable<Ipv4Addr, Ipv4Addr>,
     pools_src66: PoolTable<Ipv6Addr, Ipv6Addr>,
     randomize: bool,
+    leases: Option<Arc<NatLeases>>,
 }
 
 impl NatAllocator {
@@ -231,14 +235,27 @@ impl NatAllocator {
             pools_src44: PoolTable::new(),
             pools_src66: PoolTable::new(),
             randomize: config.randomize(),
+            leases: None,
         };
         for nat_peering in config.iter() {
-            allocator.add_peering_addresses(&nat_peering.peering, nat_peering.dst_vpcd);
+            allocator.add_peering_addresses(
+                &nat_peering.peering,
+                nat_peering.dst_vpcd,
+                nat_peering.partition,
+                config.session_table(),
+            );
         }
         allocator.config = config;
         allocator
     }
 
+    /// Honor and record the leases of the bindings in `leases`, if any
+    #[must_use]
+    pub(crate) fn set_leases(mut self, leases: Option<Arc<NatLeases>>) -> Self {
+        self.leases = leases;
+        self
+    }
+
     pub(crate) fn config(&self) -> &MasqueradeConfig {
         &self.config
     }
@@ -317,7 +334,20 @@ impl NatAllocator {
             })?;
 
         let allow_null = next_header == NextHeader::ICMP || next_header == NextHeader::ICMP6;
-        let mut allocation = pool.allocate(allow_null)?;
+        let mut allocation = match self.leased_binding::<I>(dst_vpcd, src_ip) {
+            Some((ip, base_port)) => {
+                pool.allocate_preferred(ip, base_port, allow_null)
+                    .or_else(|e| {
+                        debug!("Can't honor the NAT lease of {src_ip} ({ip}): {e}");
+                        pool.allocate(allow_null)
+                    })?
+            }
+            None => pool.allocate(allow_null)?,
+        };
+        if let Some(leases) = &self.leases {
+            let external = allocation.ip().to_ip_addr();
+            leases.record(dst_vpcd, src_ip, external, allocation.port().as_u16());
+        }
         allocation.set_genid(self.config.genid());
         let idle_timeout = pool.idle_timeout();
 
@@ -327,6 +357,17 @@ impl NatAllocator {
         })
     }
 
+    // The external IP and the first port of the port block leased to `src_ip`, if any
+    fn leased_binding<I: NatIpWithBitmap>(
+        &self,
+        dst_vpcd: VpcDiscriminant,
+        src_ip: IpAddr,
+    ) -> Option<(I, u16)> {
+        let lease = self.leases.as_ref()?.lookup(dst_vpcd, src_ip)?;
+        let ip = I::try_from_addr(lease.external).ok()?;
+        Some((ip, lease.port_block))
+    }
+
     fn reserve_ipv4_port(
         &self,
         protocol: NextHeader,
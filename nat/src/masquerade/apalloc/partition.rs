@@ -0,0 +1,156 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Partitioning of the port space of the NAT pools shared by the gateways of a group.
+//!
+//! When several gateways masquerade with the same pool, each of them must only allocate ports
+//! that the others never pick. The port space of every address is made of 256 blocks of 256
+//! ports: a gateway owning partition `index` out of `total` only allocates from the blocks whose
+//! number, modulo `total`, is `index`. Interleaving the blocks keeps the partitions of equal sizes
+//! when the well-known ports are excluded from allocation.
+//!
+//! The utilization of a partition is reported by two gauges: the blocks the partition owns in the
+//! port spaces of all the addresses of the pools using it, and those of them in use. Both count
+//! every address of every pool, so that their ratio is the utilization of the partition.
+
+use concurrency::sync::Arc;
+use config::external::gwgroup::NatPartition;
+use metrics::{Gauge, Unit};
+use stats::{MetricSpec, Register};
+use std::fmt::Debug;
+
+/// [`PortPartition`] is the partition of the port space that this gateway allocates from, along
+/// with the metrics reporting its utilization.
+#[derive(Clone)]
+pub(crate) struct PortPartition {
+    partition: NatPartition,
+    /// Number of blocks of the port space of an address owned by the partition
+    owned: u16,
+    blocks: Gauge,
+    used_blocks: Gauge,
+    /// The share of the pool using this partition in the owned blocks, if any
+    pool: Option<Arc<PoolBlocks>>,
+}
+
+/// The blocks owned by a partition in the port spaces of the addresses of a pool. They are
+/// withdrawn from the gauge of the owned blocks once the pool is gone.
+struct PoolBlocks {
+    blocks: Gauge,
+    count: f64,
+}
+
+impl Drop for PoolBlocks {
+    fn drop(&mut self) {
+        self.blocks.decrement(self.count);
+    }
+}
+
+impl PortPartition {
+    pub(crate) fn new(partition: NatPartition, gwgroup: &str) -> Self {
+        let spec = |id: &str| {
+            let labels = vec![
+                ("gwgroup".to_string(), gwgroup.to_string()),
+                ("partition".to_string(), partition.to_string()),
+            ];
+            MetricSpec::new(id, Unit::Count, labels)
+        };
+        let blocks = spec("nat_partition_port_blocks").register().metric;
+        let used_blocks = spec("nat_partition_port_blocks_used").register().metric;
+        let owned: u16 = (0..=u8::MAX)
+            .filter(|&block| Self::owns(partition, block))
+            .map(|_| 1)
+            .sum();
+        Self {
+            partition,
+            owned,
+            blocks,
+            used_blocks,
+            pool: None,
+        }
+    }
+
+    /// Get the partition to be used by a pool with the given number of addresses, accounting the
+    /// blocks it owns in their port spaces for as long as the pool uses it.
+    pub(crate) fn for_pool(&self, addresses: u64) -> Self {
+        #[allow(clippy::cast_precision_loss)] // at most 2^40 blocks, exactly represented
+        let count = (addresses * u64::from(self.owned)) as f64;
+        self.blocks.increment(count);
+        let pool = PoolBlocks {
+            blocks: self.blocks.clone(),
+            count,
+        };
+        Self {
+            pool: Some(Arc::new(pool)),
+            ..self.clone()
+        }
+    }
+
+    fn owns(partition: NatPartition, block: u8) -> bool {
+        u16::from(block) % partition.total == partition.index
+    }
+
+    /// Tell if the block of ports with the given number (port / 256) belongs to the partition
+    pub(crate) fn owns_block(&self, block: u8) -> bool {
+        Self::owns(self.partition, block)
+    }
+
+    /// Tell if the port belongs to the partition
+    pub(crate) fn owns_port(&self, port: u16) -> bool {
+        self.owns_block(port.to_be_bytes()[0])
+    }
+
+    pub(crate) fn block_allocated(&self) {
+        self.used_blocks.increment(1);
+    }
+
+    pub(crate) fn block_released(&self) {
+        self.used_blocks.decrement(1);
+    }
+}
+
+impl Debug for PortPartition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PortPartition")
+            .field("partition", &self.partition)
+            .field("owned", &self.owned)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PortPartition;
+    use concurrency::sync::Arc;
+    use config::external::gwgroup::NatPartition;
+
+    #[test]
+    fn test_partitions_do_not_overlap() {
+        let total = 3;
+        let partitions: Vec<_> = (0..total)
+            .map(|index| PortPartition::new(NatPartition { index, total }, "gw-group-1"))
+            .collect();
+        for port in [0, 255, 256, 1024, 8080, 65535] {
+            let owners = partitions.iter().filter(|p| p.owns_port(port)).count();
+            assert_eq!(owners, 1, "port {port} has {owners} owners");
+        }
+        assert!(partitions[0].owns_block(0));
+        assert!(partitions[1].owns_port(256));
+        assert!(partitions[2].owns_port(2 * 256 + 255));
+        assert!(partitions[0].owns_port(3 * 256));
+    }
+
+    #[test]
+    fn test_partition_blocks_of_pools() {
+        let partition = PortPartition::new(NatPartition { index: 1, total: 3 }, "gw-group-1");
+        assert_eq!(partition.owned, 85);
+        assert!(partition.pool.is_none());
+
+        // the blocks of a pool are accounted for all its addresses, until the pool is gone
+        let pool = partition.for_pool(4);
+        let blocks = pool.pool.as_ref().map(|pool| pool.count);
+        assert_eq!(blocks, Some(4.0 * 85.0));
+        let allocator = pool.clone();
+        drop(pool);
+        assert_eq!(allocator.pool.as_ref().map(Arc::strong_count), Some(1));
+    }
+}
@@ -10,6 +10,7 @@
 
 use super::NatIpWithBitmap;
 use super::alloc::AllocatedIp;
+use super::partition::PortPartition;
 use crate::masquerade::allocation::AllocatorError;
 use crate::port::NatPort;
 use concurrency::concurrency_mode;
@@ -87,20 +88,19 @@ pub(crate) struct PortAllocator<I: NatIpWithBitmap> {
     allocated_blocks: AllocatedPortBlockMap<I>,
     reserved_port_range: Option<PortRange>,
     exclude_wellknown_ports: bool,
+    partition: Option<PortPartition>,
 }
 
 /// Ports 0..=1023 cover the IANA system/well-known range and should not be
 /// allocated by masquerade NAT for TCP or UDP.
 const IANA_WELLKNOWN_PORT_LIMIT: u16 = 1024;
 
-/// Number of 256-port blocks covering the IANA well-known port range (0-1023).
-const IANA_WELLKNOWN_BLOCKS: u16 = IANA_WELLKNOWN_PORT_LIMIT / 256;
-
 impl<I: NatIpWithBitmap> PortAllocator<I> {
     pub(crate) fn new(
         reserved_port_range: Option<PortRange>,
         randomize: bool,
         exclude_wellknown_ports: bool,
+        partition: Option<PortPartition>,
     ) -> Self {
         let mut base_ports = (0..=255).collect::<Vec<_>>();
 
@@ -115,18 +115,29 @@ impl<I: NatIpWithBitmap> PortAllocator<I> {
             let block = AllocatorPortBlock::new(base_ports[i]);
             // Pre-mark IANA well-known port blocks (0-1023) as permanently non-free so they are
             // never handed out by masquerade NAT for TCP or UDP.
-            if exclude_wellknown_ports && block.to_port_number() < IANA_WELLKNOWN_PORT_LIMIT {
+            // Likewise for the blocks outside of the partition of this gateway, if the port space
+            // is shared with other gateways.
+            let wellknown =
+                exclude_wellknown_ports && block.to_port_number() < IANA_WELLKNOWN_PORT_LIMIT;
+            let foreign = partition
+                .as_ref()
+                .is_some_and(|partition| !partition.owns_block(block.random_index));
+            if wellknown || foreign {
                 block
                     .free
                     .store(false, concurrency::sync::atomic::Ordering::Relaxed);
             }
             block
         });
-        let usable_blocks = if exclude_wellknown_ports {
-            256 - IANA_WELLKNOWN_BLOCKS
-        } else {
-            256
-        };
+        let usable_blocks: u16 = blocks
+            .iter()
+            .filter(|block| {
+                block
+                    .free
+                    .load(concurrency::sync::atomic::Ordering::Relaxed)
+            })
+            .map(|_| 1)
+            .sum();
         Self {
             blocks,
             usable_blocks: AtomicU16::new(usable_blocks),
@@ -135,6 +146,7 @@ impl<I: NatIpWithBitmap> PortAllocator<I> {
             allocated_blocks: AllocatedPortBlockMap::new(),
             reserved_port_range,
             exclude_wellknown_ports,
+            partition,
         }
     }
 
@@ -143,7 +155,7 @@ impl<I: NatIpWithBitmap> PortAllocator<I> {
         reserved_port_range: Option<PortRange>,
         exclude_wellknown_ports: bool,
     ) -> Self {
-        Self::new(reserved_port_range, false, exclude_wellknown_ports)
+        Self::new(reserved_port_range, false, exclude_wellknown_ports, None)
     }
 
     #[concurrency_mode(std)]
@@ -201,6 +213,9 @@ impl<I: NatIpWithBitmap> PortAllocator<I> {
             .store(true, concurrency::sync::atomic::Ordering::Relaxed);
         self.usable_blocks
             .fetch_add(1, concurrency::sync::atomic::Ordering::Relaxed);
+        if let Some(partition) = &self.partition {
+            partition.block_released();
+        }
     }
 
     fn has_allocated_blocks_with_free_ports(&self) -> bool {
@@ -228,29 +243,32 @@ impl<I: NatIpWithBitmap> PortAllocator<I> {
                 }
 
                 // Check if this block is fully contained in the reserved range
-                if let Some(reserved_range) = self.reserved_port_range
-                    && reserved_range.len() >= 255
-                {
-                    // Corner case: reserved_range is 1-255+, but 0 cannot be allocated so
-                    // reserved_range effectively renders the block unusable (except maybe for ICMP
-                    // but never mind)
-                    let adjusted_reserved_range = if reserved_range.start() == 1 {
-                        PortRange::new(0, reserved_range.end()).unwrap_or_else(|_| unreachable!())
-                    } else {
-                        reserved_range
-                    };
-
-                    let block_range = PortRange::from(*block);
-                    if adjusted_reserved_range.covers(block_range) {
-                        return false;
-                    }
-                }
-                true
+                !self.is_reserved_block(block)
             })
             .ok_or(AllocatorError::NoPortBlock)?;
         Ok((index, block.to_port_number()))
     }
 
+    // Tell if a block is fully contained in the reserved range
+    fn is_reserved_block(&self, block: &AllocatorPortBlock) -> bool {
+        if let Some(reserved_range) = self.reserved_port_range
+            && reserved_range.len() >= 255
+        {
+            // Corner case: reserved_range is 1-255+, but 0 cannot be allocated so
+            // reserved_range effectively renders the block unusable (except maybe for ICMP
+            // but never mind)
+            let adjusted_reserved_range = if reserved_range.start() == 1 {
+                PortRange::new(0, reserved_range.end()).unwrap_or_else(|_| unreachable!())
+            } else {
+                reserved_range
+            };
+
+            let block_range = PortRange::from(block);
+            return adjusted_reserved_range.covers(block_range);
+        }
+        false
+    }
+
     fn allocate_block(
         &self,
         ip: Arc<AllocatedIp<I>>,
@@ -266,8 +284,22 @@ impl<I: NatIpWithBitmap> PortAllocator<I> {
         self.current_alloc_index
             .store(index, concurrency::sync::atomic::Ordering::Relaxed);
 
+        self.init_block(ip, index, base_port_index, allow_null)
+    }
+
+    // Account for the block at `index`, just marked as non-free, and set it up for allocations
+    fn init_block(
+        &self,
+        ip: Arc<AllocatedIp<I>>,
+        index: usize,
+        base_port_index: u16,
+        allow_null: bool,
+    ) -> Result<AllocatedPortBlock<I>, AllocatorError> {
         self.usable_blocks
             .fetch_sub(1, concurrency::sync::atomic::Ordering::Relaxed);
+        if let Some(partition) = &self.partition {
+            partition.block_allocated();
+        }
 
         let reserved_port_range_for_block = self.reserved_port_range.and_then(|range| {
             range.intersection(
@@ -307,6 +339,50 @@ impl<I: NatIpWithBitmap> PortAllocator<I> {
         block.allocate_port_from_block(allow_null)
     }
 
+    /// Allocate a port from the block starting at `base_port`, as preferred by a NAT lease. This
+    /// fails if the block is reserved, or already in use for this IP and full.
+    pub(crate) fn allocate_port_in_block(
+        &self,
+        ip: Arc<AllocatedIp<I>>,
+        base_port: u16,
+        allow_null: bool,
+    ) -> Result<AllocatedPort<I>, AllocatorError> {
+        let (index, block) = self
+            .blocks
+            .iter()
+            .enumerate()
+            .find(|(_, block)| block.to_port_number() == base_port)
+            .ok_or(AllocatorError::NoPortBlock)?;
+
+        // Blocks excluded by policy, or owned by other gateways, are never free
+        if block
+            .free
+            .compare_exchange(
+                true,
+                false,
+                concurrency::sync::atomic::Ordering::Relaxed,
+                concurrency::sync::atomic::Ordering::Relaxed,
+            )
+            .is_ok()
+        {
+            if self.is_reserved_block(block) {
+                block
+                    .free
+                    .store(true, concurrency::sync::atomic::Ordering::Relaxed);
+                return Err(AllocatorError::NoPortBlock);
+            }
+            let block = Arc::new(self.init_block(ip, index, base_port, allow_null)?);
+            self.allocated_blocks
+                .insert(block.index, Arc::downgrade(&block));
+            return block.allocate_port_from_block(allow_null);
+        }
+
+        match self.allocated_blocks.get(index) {
+            Some(block) if !block.is_full() => block.allocate_port_from_block(allow_null),
+            _ => Err(AllocatorError::NoPortBlock),
+        }
+    }
+
     fn try_to_reserve_block(&self, port: NatPort) -> Result<(bool, usize), AllocatorError> {
         let (index, block) = self
             .cycle_blocks()
@@ -341,6 +417,9 @@ impl<I: NatIpWithBitmap> PortAllocator<I> {
     ) -> Result<Arc<AllocatedPortBlock<I>>, AllocatorError> {
         self.usable_blocks
             .fetch_sub(1, concurrency::sync::atomic::Ordering::Relaxed);
+        if let Some(partition) = &self.partition {
+            partition.block_allocated();
+        }
         let block = Arc::new(AllocatedPortBlock::new(
             ip,
             index,
@@ -387,6 +466,13 @@ impl<I: NatIpWithBitmap> PortAllocator<I> {
             debug!("Explicit reservation for well-known port {port} denied by allocator policy");
             return Err(AllocatorError::Denied);
         }
+        // Likewise for ports owned by other gateways sharing the pool
+        if let Some(partition) = &self.partition
+            && !partition.owns_port(port.as_u16())
+        {
+            debug!("Explicit reservation for port {port} outside of the NAT partition denied");
+            return Err(AllocatorError::Denied);
+        }
         let block = self.find_block_for_port(ip, port)?;
         block.reserve_port_from_block(port)
     }
@@ -3,11 +3,14 @@
 
 use super::NatIpWithBitmap;
 use super::alloc::{IpAllocator, NatPool, PoolBitmap};
+use super::partition::PortPartition;
 use super::{NatAllocator, PoolTable, PoolTableKey};
 use crate::masquerade::natip::NatIp;
 use crate::ranges::IpRange;
+use config::external::gwgroup::NatPartition;
 use config::external::overlay::vpc::ValidatedPeering;
 use config::external::overlay::vpcpeering::{ValidatedExpose, ValidatedManifest};
+use config::external::session_table::SessionTableConfig;
 use lpm::prefix::range_map::DisjointRangesBTreeMap;
 use lpm::prefix::{
     IpPrefix, L4Protocol, PortRange, Prefix, PrefixPortsSet, PrefixWithOptionalPorts,
@@ -25,7 +28,11 @@ impl NatAllocator {
         &mut self,
         peering: &ValidatedPeering,
         dst_vpc_id: VpcDiscriminant,
+        partition: Option<NatPartition>,
+        session_table: &SessionTableConfig,
     ) {
+        let partition = partition.map(|partition| PortPartition::new(partition, peering.gwgroup()));
+
         build_nat_pool_generic(
             peering.local(),
             dst_vpc_id,
@@ -34,6 +41,8 @@ impl NatAllocator {
             &mut self.pools_src44,
             NextHeader::ICMP,
             self.randomize,
+            partition.as_ref(),
+            session_table,
         );
 
         build_nat_pool_generic(
@@ -44,6 +53,8 @@ impl NatAllocator {
             &mut self.pools_src66,
             NextHeader::ICMP6,
             self.randomize,
+            partition.as_ref(),
+            session_table,
         );
     }
 }
@@ -59,6 +70,10 @@ fn build_nat_pool_generic<'a, I: NatIpWithBitmap, J: NatIpWithBitmap, F, FIter,
     table: &mut PoolTable<I, J>,
     icmp_proto: NextHeader,
     randomize: bool,
+    // The partition of the port space of this gateway, if it shares the pools with others
+    partition: Option<&PortPartition>,
+    // The default idle timeouts of the sessions of each protocol
+    session_table: &SessionTableConfig,
 ) where
     F: FnOnce(&'a ValidatedManifest) -> FIter,
     FIter: Iterator<Item = &'a ValidatedExpose>,
@@ -72,33 +87,50 @@ fn build_nat_pool_generic<'a, I: NatIpWithBitmap, J: NatIpWithBitmap, F, FIter,
         let prefixes_and_ports_to_exclude_from_pools =
             find_masquerade_portfw_overlap(&port_forwarding_exposes, expose);
 
-        let idle_timeout = expose
-            .idle_timeout()
-            .unwrap_or(DEFAULT_MASQUERADE_IDLE_TIMEOUT);
+        // the idle timeout of the expose, if any, applies to all protocols
+        let idle_timeout = |proto: NextHeader| {
+            expose
+                .idle_timeout()
+                .or_else(|| session_table.idle_timeout(proto))
+                .unwrap_or(DEFAULT_MASQUERADE_IDLE_TIMEOUT)
+        };
+
+        let as_range: Vec<_> = expose
+            .as_range_or_empty()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        let pool_name = |proto: &str| format!("{} ({proto})", as_range.join(" "));
 
         // TCP/UDP masquerade allocators should avoid the IANA system/well-known range
         // (0-1023). ICMP identifiers are allocated independently and are not subject to that
         // TCP/UDP source-port policy.
         let tcp_ip_allocator = ip_allocator_for_prefixes(
+            pool_name("tcp"),
             expose.as_range_or_empty(),
-            idle_timeout,
+            idle_timeout(NextHeader::TCP),
             &prefixes_and_ports_to_exclude_from_pools.tcp,
             randomize,
             true,
+            partition,
         );
         let udp_ip_allocator = ip_allocator_for_prefixes(
+            pool_name("udp"),
             expose.as_range_or_empty(),
-            idle_timeout,
+            idle_timeout(NextHeader::UDP),
             &prefixes_and_ports_to_exclude_from_pools.udp,
             randomize,
             true,
+            partition,
         );
         let icmp_ip_allocator = ip_allocator_for_prefixes(
+            pool_name("icmp"),
             expose.as_range_or_empty(),
-            idle_timeout,
+            idle_timeout(icmp_proto),
             &PrefixPortsSet::default(),
             randomize,
             false,
+            partition,
         );
 
         add_pool_entries(
@@ -183,26 +215,32 @@ fn add_pool_entries<I: NatIpWithBitmap, J: NatIpWithBitmap>(
 }
 
 fn ip_allocator_for_prefixes<J: NatIpWithBitmap>(
+    name: String,
     prefixes: &PrefixPortsSet,
     idle_timeout: Duration,
     prefixes_and_ports_to_exclude_from_pools: &PrefixPortsSet,
     randomize: bool,
     exclude_wellknown_ports: bool,
+    partition: Option<&PortPartition>,
 ) -> IpAllocator<J> {
     let pool = create_natpool(
+        name,
         prefixes,
         prefixes_and_ports_to_exclude_from_pools,
         idle_timeout,
         exclude_wellknown_ports,
+        partition,
     );
     IpAllocator::new(pool, randomize)
 }
 
 fn create_natpool<J: NatIpWithBitmap>(
+    name: String,
     prefixes: &PrefixPortsSet,
     prefixes_and_ports_to_exclude_from_pools: &PrefixPortsSet,
     idle_timeout: Duration,
     exclude_wellknown_ports: bool,
+    partition: Option<&PortPartition>,
 ) -> NatPool<J> {
     // Build mappings for IPv6 <-> u32 bitmap translation
     let (bitmap_mapping, reverse_bitmap_mapping) = create_ipv6_bitmap_mappings(
@@ -222,14 +260,17 @@ fn create_natpool<J: NatIpWithBitmap>(
 
     let reserved_prefixes_ports =
         build_reserved_prefixes_ports(prefixes_and_ports_to_exclude_from_pools);
+    let addresses = bitmap.count();
 
     NatPool::new(
+        name,
         bitmap,
         bitmap_mapping,
         reverse_bitmap_mapping,
         reserved_prefixes_ports,
         idle_timeout,
         exclude_wellknown_ports,
+        partition.map(|partition| partition.for_pool(addresses)),
     )
 }
 
@@ -11,6 +11,7 @@ mod context {
     use crate::masquerade::allocator_writer::MasqueradeConfig;
     use crate::masquerade::apalloc::alloc::IpAllocator;
     use crate::masquerade::apalloc::{NatAllocator, PoolTable, PoolTableKey};
+    use config::external::gwgroup::{GwGroup, GwGroupMember, GwGroupTable};
     use config::external::overlay::vpc::{Peering, ValidatedVpcTable, Vpc, VpcTable};
     use config::external::overlay::vpcpeering::{VpcExpose, VpcManifest};
     use net::ip::NextHeader;
@@ -133,6 +134,27 @@ mod context {
         let config = MasqueradeConfig::new(&vpc_table, 1);
         NatAllocator::new(config)
     }
+
+    // Build the allocator of gateway `gwname`, in a group of two gateways partitioning the port
+    // space of their pools: gw1 owns partition 0 and gw2 owns partition 1.
+    #[allow(dead_code)]
+    pub fn build_partitioned_allocator(gwname: &str) -> NatAllocator {
+        let mut group = GwGroup::new("default");
+        group.set_nat_partitions(2);
+        for (index, name) in ["gw1", "gw2"].into_iter().enumerate() {
+            let address = ipaddr(&format!("172.128.0.{}", index + 1));
+            let member = GwGroupMember::new(name, 1, address);
+            group
+                .add_member(member.with_nat_partition(u16::try_from(index).unwrap()))
+                .unwrap();
+        }
+        let mut gwgroups = GwGroupTable::new();
+        gwgroups.add_group(group).unwrap();
+
+        let vpc_table = build_context();
+        let config = MasqueradeConfig::new(&vpc_table, 1).set_partitions(&gwgroups, gwname);
+        NatAllocator::new(config)
+    }
 }
 
 mod tests {
@@ -381,6 +403,112 @@ mod std_tests {
         assert_eq!(bitmap.len(), 2); // 2 free IP addresses left to NAT 1.1.0.0 (UDP)
         assert_eq!(in_use.len(), 1); // 1 allocated, in use
     }
+
+    // Gateways sharing a pool allocate from distinct partitions of the port space, and never
+    // reserve ports from the partition of another gateway.
+    #[test]
+    fn test_partitioned_allocations() {
+        use crate::NatPort;
+        use std::collections::HashSet;
+        use std::net::IpAddr;
+
+        let allocator1 = build_partitioned_allocator("gw1");
+        let allocator2 = build_partitioned_allocator("gw2");
+
+        let mut allocations = vec![];
+        let mut ports = [HashSet::new(), HashSet::new()];
+        for (index, allocator) in [&allocator1, &allocator2].into_iter().enumerate() {
+            // enough allocations to span several blocks of 256 ports
+            for _ in 0..1000 {
+                let allocation = allocator
+                    .allocate_v4(vpcd2(), addr_v4("1.1.0.0"), NextHeader::TCP)
+                    .unwrap()
+                    .allocation;
+                let port = allocation.port().as_u16();
+                assert_eq!(usize::from(port / 256) % 2, index);
+                ports[index].insert((allocation.ip(), port));
+                allocations.push(allocation);
+            }
+        }
+        assert_eq!(ports[0].len(), 1000);
+        assert!(ports[0].is_disjoint(&ports[1]));
+
+        // gw1 does not take over ports of the partition of gw2
+        let foreign = NatPort::new_port_checked(5 * 256 + 42).unwrap();
+        let owned = NatPort::new_port_checked(6 * 256 + 42).unwrap();
+        let nat_ip = IpAddr::V4(addr_v4("10.1.0.2"));
+        let src_ip = IpAddr::V4(addr_v4("1.1.0.1"));
+        assert!(
+            allocator1
+                .reserve_port(NextHeader::TCP, vpcd2(), src_ip, nat_ip, foreign)
+                .is_err()
+        );
+        allocator1
+            .reserve_port(NextHeader::TCP, vpcd2(), src_ip, nat_ip, owned)
+            .unwrap();
+    }
+
+    // Hosts get the address and port block of their lease, if still valid, after a restart.
+    #[test]
+    fn test_leases() {
+        use crate::masquerade::NatLeases;
+        use concurrency::sync::Arc;
+        use kvstore::{KvStore, MemoryStore, Namespace};
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+        let store: Arc<dyn KvStore> = Arc::new(MemoryStore::default());
+        let duration = Duration::from_secs(600);
+        let leases = Arc::new(NatLeases::new(duration, Some(store.clone())));
+        let allocator = build_allocator().set_leases(Some(leases.clone()));
+        let first = allocator
+            .allocate_v4(vpcd2(), addr_v4("1.1.0.5"), NextHeader::TCP)
+            .unwrap()
+            .allocation;
+        assert_eq!(first.ip(), addr_v4("10.1.0.0"));
+        let first_block = first.port().as_u16() / 256;
+        leases.flush();
+        drop(first);
+        drop(allocator);
+
+        // leases for another address of the pool, and for an address out of it
+        let expires = SystemTime::now().duration_since(UNIX_EPOCH).unwrap() + duration;
+        let state = Namespace::new(store.clone(), "nat-leases").unwrap();
+        let lease = format!("v1 10.1.0.2 4096 {}", expires.as_secs());
+        state.set("200/1.1.0.9", lease.as_bytes()).unwrap();
+        let lease = format!("v1 10.9.9.9 4096 {}", expires.as_secs());
+        state.set("200/1.1.0.10", lease.as_bytes()).unwrap();
+
+        // restart
+        let leases = Arc::new(NatLeases::new(duration, Some(store)));
+        assert_eq!(leases.len(), 3);
+        let allocator = build_allocator().set_leases(Some(leases.clone()));
+        let busy = allocator
+            .allocate_v4(vpcd2(), addr_v4("1.1.0.7"), NextHeader::TCP)
+            .unwrap()
+            .allocation;
+        assert_eq!(busy.ip(), addr_v4("10.1.0.0"));
+        let again = allocator
+            .allocate_v4(vpcd2(), addr_v4("1.1.0.5"), NextHeader::TCP)
+            .unwrap()
+            .allocation;
+        assert_eq!(again.ip(), addr_v4("10.1.0.0"));
+        assert_eq!(again.port().as_u16() / 256, first_block);
+        let leased = allocator
+            .allocate_v4(vpcd2(), addr_v4("1.1.0.9"), NextHeader::TCP)
+            .unwrap()
+            .allocation;
+        assert_eq!(leased.ip(), addr_v4("10.1.0.2"));
+        assert_eq!(leased.port().as_u16() / 256, 16);
+        let moved = allocator
+            .allocate_v4(vpcd2(), addr_v4("1.1.0.10"), NextHeader::TCP)
+            .unwrap()
+            .allocation;
+        assert_eq!(moved.ip(), addr_v4("10.1.0.0"));
+        let lease = leases
+            .lookup(vpcd2(), std::net::IpAddr::V4(addr_v4("1.1.0.10")))
+            .unwrap();
+        assert_eq!(lease.external, std::net::IpAddr::V4(addr_v4("10.1.0.0")));
+    }
 }
 
 // Loom's Weak shim keeps allocator liveness entries alive forever.
@@ -0,0 +1,500 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Leases of the bindings of masquerading, for inside hosts to keep the same external address
+//! across restarts of the dataplane, e.g. for remote peers allow-listing them.
+//!
+//! When enabled, the external address and port block allocated to an inside address, for a given
+//! destination VPC, are recorded in a lease, renewed as the host keeps opening sessions. The
+//! allocator tries the address and port block of a valid lease first, and falls back to a regular
+//! allocation if they are not available, e.g. because the pool changed or the block is used for
+//! other hosts. The lease then follows the new binding.
+//!
+//! The allocators look the leases up without locking, and queue the leases to record, which are
+//! applied when the leases are flushed. Leases are flushed every [`NAT_LEASES_FLUSH_INTERVAL`],
+//! and upon shutdown, off the packet path: they are persisted in the `nat-leases` namespace of the
+//! state store, so that they are honored when the dataplane restarts. Each lease is keyed by
+//! `<vni>/<inside address>`, and stored as a versioned, human-readable, value:
+//! `v1 <external address> <first port of the block> <expiry>`, the expiry being in seconds since
+//! the epoch. Leases of an unknown version, or expired, are dropped.
+
+use concurrency::slot::Slot;
+use concurrency::sync::{Arc, Mutex};
+use kvstore::{KvStore, Namespace};
+use net::packet::VpcDiscriminant;
+use net::vxlan::Vni;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::mpsc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use thiserror::Error;
+
+#[allow(unused)]
+use tracing::{debug, error, info, warn};
+
+/// The version of the encoding of the leases persisted
+const LEASE_VERSION: &str = "v1";
+
+/// Interval at which the leases are persisted and the expired ones purged
+pub const NAT_LEASES_FLUSH_INTERVAL: Duration = Duration::from_secs(10);
+
+/// The max number of leases queued to be recorded. Leases recorded when the queue is full are
+/// dropped, and recorded again upon the next allocation.
+const RECORD_QUEUE_CAPACITY: usize = 4096;
+
+/// Errors decoding a persisted lease
+#[derive(Debug, Error, PartialEq, Eq)]
+pub(crate) enum NatLeaseDecodeError {
+    #[error("lease is not UTF-8")]
+    NotUtf8,
+    #[error("empty lease")]
+    Empty,
+    #[error("unknown version {0}")]
+    UnknownVersion(String),
+    #[error("bad lease '{0}'")]
+    Malformed(String),
+    #[error("bad {0} in lease '{1}'")]
+    BadField(&'static str, String),
+}
+
+/// An inside address, for the sessions towards a destination VPC
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+struct LeaseKey {
+    dst_vpcd: VpcDiscriminant,
+    inside: IpAddr,
+}
+
+impl LeaseKey {
+    fn encode(&self) -> String {
+        let VpcDiscriminant::VNI(vni) = self.dst_vpcd;
+        format!("{}/{}", vni.as_u32(), self.inside)
+    }
+
+    fn decode(key: &str) -> Option<Self> {
+        let (vni, inside) = key.split_once('/')?;
+        let vni = Vni::new_checked(vni.parse().ok()?).ok()?;
+        Some(Self {
+            dst_vpcd: VpcDiscriminant::from_vni(vni),
+            inside: inside.parse().ok()?,
+        })
+    }
+}
+
+/// The binding leased to an inside address
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct NatLease {
+    pub(crate) external: IpAddr,
+    /// The first port of the port block
+    pub(crate) port_block: u16,
+    pub(crate) expires: SystemTime,
+}
+
+impl NatLease {
+    fn encode(&self) -> String {
+        let expires = self
+            .expires
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        format!(
+            "{LEASE_VERSION} {} {} {expires}",
+            self.external, self.port_block
+        )
+    }
+
+    fn decode(value: &str) -> Result<Self, NatLeaseDecodeError> {
+        let mut fields = value.split_whitespace();
+        match fields.next() {
+            Some(LEASE_VERSION) => {}
+            Some(version) => return Err(NatLeaseDecodeError::UnknownVersion(version.to_string())),
+            None => return Err(NatLeaseDecodeError::Empty),
+        }
+        let (Some(external), Some(port_block), Some(expires), None) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            return Err(NatLeaseDecodeError::Malformed(value.to_string()));
+        };
+        let bad = |field| NatLeaseDecodeError::BadField(field, value.to_string());
+        Ok(Self {
+            external: external.parse().map_err(|_| bad("address"))?,
+            port_block: port_block.parse().map_err(|_| bad("port block"))?,
+            expires: UNIX_EPOCH + Duration::from_secs(expires.parse().map_err(|_| bad("expiry"))?),
+        })
+    }
+
+    fn is_valid(&self, now: SystemTime) -> bool {
+        self.expires > now
+    }
+}
+
+/// A lease to record, queued by an allocator
+struct LeaseRecord {
+    key: LeaseKey,
+    external: IpAddr,
+    port: u16,
+    now: SystemTime,
+}
+
+/// The state of the flushing of the leases
+struct LeaseFlusher {
+    /// The queue of the leases to record
+    records: mpsc::Receiver<LeaseRecord>,
+    /// The leases changed since they were last persisted
+    dirty: bool,
+}
+
+/// The leases of the bindings of masquerading, shared by the successive allocators
+pub struct NatLeases {
+    duration: Duration,
+    state: Option<Namespace>,
+    /// The leases, replaced as a whole when flushed, for the allocators not to lock them
+    leases: Slot<BTreeMap<LeaseKey, NatLease>>,
+    /// The sending end of the queue of the leases to record
+    records: mpsc::SyncSender<LeaseRecord>,
+    flusher: Mutex<LeaseFlusher>,
+}
+
+impl std::fmt::Debug for NatLeases {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("NatLeases")
+            .field("duration", &self.duration)
+            .field("leases", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl NatLeases {
+    /// Create leases lasting `duration`, restoring those persisted in `store`, if any. Leases are
+    /// kept in memory only if there is no store.
+    #[must_use]
+    pub fn new(duration: Duration, store: Option<Arc<dyn KvStore>>) -> Self {
+        let state = store
+            .map(|store| Namespace::new(store, "nat-leases").unwrap_or_else(|_| unreachable!()));
+        let (records, queue) = mpsc::sync_channel(RECORD_QUEUE_CAPACITY);
+        let leases = Self {
+            duration,
+            state,
+            leases: Slot::from_pointee(BTreeMap::new()),
+            records,
+            flusher: Mutex::new(LeaseFlusher {
+                records: queue,
+                dirty: false,
+            }),
+        };
+        leases.load(SystemTime::now());
+        leases
+    }
+
+    /// The duration of the leases
+    #[must_use]
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The number of leases
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.leases.load().len()
+    }
+
+    /// Tell if there are no leases
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Get the valid lease of `inside`, for the sessions towards `dst_vpcd`
+    pub(crate) fn lookup(&self, dst_vpcd: VpcDiscriminant, inside: IpAddr) -> Option<NatLease> {
+        self.lookup_at(dst_vpcd, inside, SystemTime::now())
+    }
+
+    fn lookup_at(
+        &self,
+        dst_vpcd: VpcDiscriminant,
+        inside: IpAddr,
+        now: SystemTime,
+    ) -> Option<NatLease> {
+        let key = LeaseKey { dst_vpcd, inside };
+        self.leases
+            .load()
+            .get(&key)
+            .filter(|lease| lease.is_valid(now))
+            .copied()
+    }
+
+    /// Record that `inside` got `external` and `port`, for the sessions towards `dst_vpcd`. The
+    /// lease is only renewed once half of it elapsed, to spare writes to the store. The lease is
+    /// queued, and recorded when the leases are next flushed.
+    pub(crate) fn record(
+        &self,
+        dst_vpcd: VpcDiscriminant,
+        inside: IpAddr,
+        external: IpAddr,
+        port: u16,
+    ) {
+        self.record_at(dst_vpcd, inside, external, port, SystemTime::now());
+    }
+
+    fn record_at(
+        &self,
+        dst_vpcd: VpcDiscriminant,
+        inside: IpAddr,
+        external: IpAddr,
+        port: u16,
+        now: SystemTime,
+    ) {
+        let key = LeaseKey { dst_vpcd, inside };
+        if let Some(lease) = self.leases.load().get(&key)
+            && lease.external == external
+            && lease.expires > now + self.duration / 2
+        {
+            return;
+        }
+        let record = LeaseRecord {
+            key,
+            external,
+            port,
+            now,
+        };
+        if self.records.try_send(record).is_err() {
+            debug!("Too many NAT leases to record, not recording the lease of {inside}");
+        }
+    }
+
+    /// Apply `record` to `leases`. Returns whether the leases changed.
+    fn apply(&self, leases: &mut BTreeMap<LeaseKey, NatLease>, record: &LeaseRecord) -> bool {
+        let LeaseRecord {
+            key,
+            external,
+            port,
+            now,
+        } = *record;
+        if let Some(lease) = leases.get(&key)
+            && lease.external == external
+            && lease.expires > now + self.duration / 2
+        {
+            return false;
+        }
+        let lease = match leases.get(&key) {
+            // keep the port block of the lease, which ports of other blocks may be taken from
+            Some(lease) if lease.external == external && lease.is_valid(now) => NatLease {
+                expires: now + self.duration,
+                ..*lease
+            },
+            _ => {
+                debug!(
+                    "Leasing {external} to {}, towards {}",
+                    key.inside, key.dst_vpcd
+                );
+                NatLease {
+                    external,
+                    port_block: (port / 256) * 256,
+                    expires: now + self.duration,
+                }
+            }
+        };
+        leases.insert(key, lease);
+        true
+    }
+
+    fn load(&self, now: SystemTime) {
+        let Some(state) = &self.state else {
+            return;
+        };
+        let persisted = match state.entries() {
+            Ok(persisted) => persisted,
+            Err(e) => {
+                warn!("Failed to read NAT leases from state store: {e}");
+                return;
+            }
+        };
+        let mut flusher = self.flusher.lock();
+        let mut leases = BTreeMap::new();
+        for (key, value) in persisted {
+            let lease = std::str::from_utf8(&value)
+                .map_err(|_| NatLeaseDecodeError::NotUtf8)
+                .and_then(NatLease::decode);
+            match (LeaseKey::decode(&key), lease) {
+                (Some(key), Ok(lease)) if lease.is_valid(now) => {
+                    leases.insert(key, lease);
+                }
+                (Some(_), Ok(_)) => flusher.dirty = true,
+                (None, _) => {
+                    warn!("Ignoring NAT lease with bad key {key}");
+                    flusher.dirty = true;
+                }
+                (Some(_), Err(e)) => {
+                    warn!("Ignoring NAT lease of {key}: {e}");
+                    flusher.dirty = true;
+                }
+            }
+        }
+        info!("Restored {} NAT leases from state store", leases.len());
+        self.leases.store(Arc::new(leases));
+    }
+
+    /// Record the leases queued, purge the expired ones, and persist them if they changed
+    pub fn flush(&self) {
+        self.flush_at(SystemTime::now());
+    }
+
+    fn flush_at(&self, now: SystemTime) {
+        let mut flusher = self.flusher.lock();
+        let mut leases = BTreeMap::clone(&self.leases.load());
+        let mut changed = false;
+        for record in flusher.records.try_iter() {
+            changed |= self.apply(&mut leases, &record);
+        }
+        let before = leases.len();
+        leases.retain(|_, lease| lease.is_valid(now));
+        if leases.len() != before {
+            debug!("Purged {} expired NAT leases", before - leases.len());
+            changed = true;
+        }
+        if changed {
+            self.leases.store(Arc::new(leases));
+            flusher.dirty = true;
+        }
+        let Some(state) = &self.state else {
+            return;
+        };
+        if !flusher.dirty {
+            return;
+        }
+        let persisted = self
+            .leases
+            .load()
+            .iter()
+            .map(|(key, lease)| (key.encode(), lease.encode().into_bytes()))
+            .collect();
+        match state.replace(persisted) {
+            Ok(()) => flusher.dirty = false,
+            Err(e) => warn!("Failed to persist NAT leases to state store: {e}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LeaseKey, NatLease, NatLeaseDecodeError, NatLeases};
+    use concurrency::sync::Arc;
+    use kvstore::{KvStore, MemoryStore, Namespace};
+    use net::packet::VpcDiscriminant;
+    use net::vxlan::Vni;
+    use std::net::IpAddr;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    fn addr(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    fn vpcd(vni: u32) -> VpcDiscriminant {
+        VpcDiscriminant::from_vni(Vni::new_checked(vni).unwrap())
+    }
+
+    #[test]
+    fn lease_encoding() {
+        let key = LeaseKey {
+            dst_vpcd: vpcd(3000),
+            inside: addr("10.0.0.1"),
+        };
+        assert_eq!(key.encode(), "3000/10.0.0.1");
+        assert_eq!(LeaseKey::decode(&key.encode()), Some(key));
+        assert_eq!(LeaseKey::decode("0/10.0.0.1"), None);
+
+        let lease = NatLease {
+            external: addr("2001:db8::1"),
+            port_block: 4096,
+            expires: UNIX_EPOCH + Duration::from_secs(1_800_000_000),
+        };
+        assert_eq!(lease.encode(), "v1 2001:db8::1 4096 1800000000");
+        assert_eq!(NatLease::decode(&lease.encode()), Ok(lease));
+        assert_eq!(
+            NatLease::decode("v2 1.1.1.1 4096 1800000000"),
+            Err(NatLeaseDecodeError::UnknownVersion("v2".to_string()))
+        );
+        assert!(matches!(
+            NatLease::decode("v1 1.1.1.1 4096"),
+            Err(NatLeaseDecodeError::Malformed(_))
+        ));
+        assert!(matches!(
+            NatLease::decode("v1 1.1.1.1 4096 1800000000 0"),
+            Err(NatLeaseDecodeError::Malformed(_))
+        ));
+        assert!(matches!(
+            NatLease::decode("v1 1.1.1.1 65536 1800000000"),
+            Err(NatLeaseDecodeError::BadField("port block", _))
+        ));
+        assert_eq!(NatLease::decode(""), Err(NatLeaseDecodeError::Empty));
+    }
+
+    #[test]
+    fn leases_record_and_expire() {
+        let leases = NatLeases::new(Duration::from_secs(100), None);
+        let now = SystemTime::now();
+        let (inside, external) = (addr("10.0.0.1"), addr("1.1.1.1"));
+        assert!(leases.lookup_at(vpcd(2), inside, now).is_none());
+
+        // recorded leases are queued until flushed
+        leases.record_at(vpcd(2), inside, external, 1234, now);
+        assert!(leases.lookup_at(vpcd(2), inside, now).is_none());
+        leases.flush_at(now);
+        let lease = leases.lookup_at(vpcd(2), inside, now).unwrap();
+        assert_eq!(lease.external, external);
+        assert_eq!(lease.port_block, 1024);
+        assert!(leases.lookup_at(vpcd(3), inside, now).is_none());
+
+        // not renewed before half of it elapsed, and keeping its block once renewed
+        let later = now + Duration::from_secs(40);
+        leases.record_at(vpcd(2), inside, external, 5000, later);
+        leases.flush_at(later);
+        assert_eq!(leases.lookup_at(vpcd(2), inside, later), Some(lease));
+        let later = now + Duration::from_secs(60);
+        leases.record_at(vpcd(2), inside, external, 5000, later);
+        leases.flush_at(later);
+        let renewed = leases.lookup_at(vpcd(2), inside, later).unwrap();
+        assert_eq!(renewed.port_block, 1024);
+        assert_eq!(renewed.expires, later + Duration::from_secs(100));
+
+        // following a new binding
+        leases.record_at(vpcd(2), inside, addr("1.1.1.2"), 5000, later);
+        leases.flush_at(later);
+        let moved = leases.lookup_at(vpcd(2), inside, later).unwrap();
+        assert_eq!(moved.external, addr("1.1.1.2"));
+        assert_eq!(moved.port_block, 4864);
+
+        let expired = later + Duration::from_secs(100);
+        assert!(leases.lookup_at(vpcd(2), inside, expired).is_none());
+        leases.flush_at(expired);
+        assert!(leases.is_empty());
+    }
+
+    #[test]
+    fn leases_persistence() {
+        let store: Arc<dyn KvStore> = Arc::new(MemoryStore::default());
+        let state = Namespace::new(store.clone(), "nat-leases").unwrap();
+        let now = SystemTime::now();
+        let leases = NatLeases::new(Duration::from_secs(100), Some(store.clone()));
+        leases.record_at(vpcd(2), addr("10.0.0.1"), addr("1.1.1.1"), 2048, now);
+        leases.record_at(vpcd(2), addr("10.0.0.2"), addr("1.1.1.2"), 2048, now);
+        leases.flush_at(now);
+        assert_eq!(state.entries().unwrap().len(), 2);
+
+        // leases of an unknown version, or expired, are dropped on restart
+        state.set("2/10.0.0.3", b"v9 1.1.1.3 2048 0").unwrap();
+        state.set("2/10.0.0.4", b"v1 1.1.1.4 2048 0").unwrap();
+        let restored = NatLeases::new(Duration::from_secs(100), Some(store.clone()));
+        assert_eq!(restored.len(), 2);
+        let lease = restored.lookup(vpcd(2), addr("10.0.0.1")).unwrap();
+        assert_eq!(lease.external, addr("1.1.1.1"));
+        assert_eq!(lease.port_block, 2048);
+        restored.flush();
+        let keys: Vec<_> = state
+            .entries()
+            .unwrap()
+            .into_iter()
+            .map(|(k, _)| k)
+            .collect();
+        assert_eq!(keys, vec!["2/10.0.0.1", "2/10.0.0.2"]);
+    }
+}
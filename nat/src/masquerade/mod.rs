@@ -6,6 +6,7 @@ mod allocator_writer;
 pub mod apalloc;
 pub(crate) mod flows;
 pub(crate) mod icmp_handling;
+mod lease;
 mod natip;
 mod nf;
 mod packet;
@@ -16,7 +17,9 @@ mod test;
 // re exports
 pub use allocator_writer::MasqueradeConfig;
 pub use allocator_writer::NatAllocatorWriter;
+pub use lease::{NAT_LEASES_FLUSH_INTERVAL, NatLeases};
 pub use nf::Masquerade;
+pub(crate) use packet::NatTranslate;
 
 use tracectl::trace_target;
 trace_target!("masquerade", LevelFilter::INFO, &["nat", "pipeline"]);
@@ -4,6 +4,7 @@
 //! Masquerade NF
 
 use crate::NatPort;
+use crate::alg::{AlgConfig, AlgState, AlgStats, FTP_DATA_PORT};
 use crate::common::NatFlowStatus;
 use crate::masquerade::NatAllocatorWriter;
 use crate::masquerade::allocation::{AllocationResult, AllocatorError};
@@ -13,18 +14,19 @@ use crate::masquerade::flows::check_masquerading_flow;
 use crate::masquerade::packet::{NatPacketError, NatTranslate, masquerade};
 use crate::masquerade::protocol::next_flow_status;
 use crate::masquerade::state::MasqueradeState;
+use concurrency::sync::atomic::Ordering;
 use concurrency::sync::{Arc, Weak};
 use flow_entry::flow_table::table::{FlowTable, FlowTableError};
 use net::buffer::PacketBufferMut;
 use net::flow_key::IcmpProtoKey;
-use net::flows::{ExtractRef, FlowInfo};
+use net::flows::{ExtractRef, FlowInfo, FlowInfoFlags};
 use net::headers::{TryIp, TryTcp};
 use net::ip::UnicastIpAddr;
 use net::packet::{DoneReason, Packet, VpcDiscriminant};
-use net::{FlowKey, IpProtoKey};
+use net::{FlowKey, IpProtoKey, TcpProtoKey};
 use pipeline::{NetworkFunction, PipelineData};
 use std::fmt::Debug;
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddrV4};
 use std::time::{Duration, Instant};
 
 #[allow(unused)]
@@ -66,6 +68,8 @@ pub struct Masquerade {
     flow_table: Arc<FlowTable>,
     allocator: NatAllocatorReader,
     pipeline_data: Arc<PipelineData>,
+    alg: AlgConfig,
+    alg_stats: Arc<AlgStats>,
 }
 
 impl Masquerade {
@@ -73,6 +77,8 @@ impl Masquerade {
     pub const MASQUERADE_ONEWAY_TIMEOUT: Duration = Duration::from_secs(5);
     pub const MASQUERADE_TWOWAY_TIMEOUT: Duration = Duration::from_secs(3);
     pub const MASQUERADE_CLOSING_TIMEOUT: Duration = Duration::from_secs(2);
+    // Time an application layer gateway leaves the server to open an announced connection
+    pub const ALG_EXPECTATION_TIMEOUT: Duration = Duration::from_secs(30);
 
     /// Creates a new [`Masquerade`] processor from provided parameters.
     #[must_use]
@@ -82,9 +88,20 @@ impl Masquerade {
             flow_table,
             allocator,
             pipeline_data: Arc::from(PipelineData::default()),
+            alg: AlgConfig::default(),
+            alg_stats: Arc::new(AlgStats::default()),
         }
     }
 
+    /// Enable application layer gateways, counting their activity in `stats`. ALGs are disabled
+    /// by default.
+    #[must_use]
+    pub fn with_alg(mut self, config: AlgConfig, stats: Arc<AlgStats>) -> Self {
+        self.alg = config;
+        self.alg_stats = stats;
+        self
+    }
+
     /// Creates a new [`Masquerade`] processor with empty allocator and session table, returning a
     /// [`NatAllocatorWriter`] object.
     #[must_use]
@@ -170,8 +187,11 @@ impl Masquerade {
     }
 
     // Get the flow info referred to by the packet and, if found, check its masquerade state.
-    // Refresh the flow status and update the flow or invalidate it
-    fn get_masquerade_state<Buf: PacketBufferMut>(packet: &Packet<Buf>) -> Option<NatTranslate> {
+    // Refresh the flow status and update the flow or invalidate it. Also return the state of the
+    // application layer gateway of the flow and its key, if tracked by one.
+    fn get_masquerade_state<Buf: PacketBufferMut>(
+        packet: &Packet<Buf>,
+    ) -> Option<(NatTranslate, Option<(AlgState, FlowKey)>)> {
         let flow_info = packet.meta().flow_info.as_ref()?;
         if !flow_info.is_active() {
             debug!("Hit INACTIVE flow: {}", flow_info.logfmt());
@@ -185,7 +205,11 @@ impl Masquerade {
         };
         let xlate = state.as_translate();
         Self::refresh_masquerade_state(packet, flow_info, state);
-        Some(xlate)
+        let alg = locked
+            .alg_state
+            .extract_ref::<AlgState>()
+            .map(|alg| (alg.clone(), *flow_info.flowkey()));
+        Some((xlate, alg))
     }
 
     // Look up for a session by passing the parameters that make up a flow key.
@@ -244,10 +268,7 @@ impl Masquerade {
         initial_flow_key: &FlowKey,
         current_flow_key: &FlowKey,
         alloc: AllocationResult<Allocation>,
-    ) -> Result<(), MasqueradeError> {
-        let idle_timeout = alloc.idle_timeout;
-        let genid = alloc.allocation.genid();
-
+    ) -> Result<Arc<FlowInfo>, MasqueradeError> {
         // src and dst vpc of this packet
         let src_vpc_id = packet.meta().src_vpcd.unwrap_or_else(|| unreachable!());
         let dst_vpc_id = packet.meta().dst_vpcd.unwrap_or_else(|| unreachable!());
@@ -258,26 +279,72 @@ impl Masquerade {
         // packet
         let reverse_key = Self::new_reverse_session(current_flow_key, &alloc, dst_vpc_id)?;
 
+        // track the flows with an application layer gateway, if relevant
+        let alg = self.alg.alg_for(current_flow_key).map(|kind| {
+            self.alg_stats
+                .counters(kind)
+                .sessions
+                .fetch_add(1, Ordering::Relaxed);
+            AlgState::new(kind)
+        });
+
+        let expires_at = Instant::now() + Self::MASQUERADE_ONEWAY_TIMEOUT;
+        let flags = (
+            packet.meta().compute_flow_flags_forward(),
+            packet.meta().compute_flow_flags_reverse(),
+        );
+        let vpcds = (src_vpc_id, dst_vpc_id);
+        self.install_flow_pair(
+            *initial_flow_key,
+            reverse_key,
+            alloc,
+            vpcds,
+            flags,
+            expires_at,
+            alg,
+        )
+    }
+
+    /// Install in the flow table a pair of masquerading flows, from the keys of both directions
+    /// and the allocation to use. `vpcds` are the source and destination VPCs of the forward flow.
+    /// Returns the forward flow.
+    #[allow(clippy::too_many_arguments)]
+    fn install_flow_pair(
+        &self,
+        forward_key: FlowKey,
+        reverse_key: FlowKey,
+        alloc: AllocationResult<Allocation>,
+        (src_vpc_id, dst_vpc_id): (VpcDiscriminant, VpcDiscriminant),
+        (flags_forward, flags_reverse): (FlowInfoFlags, FlowInfoFlags),
+        expires_at: Instant,
+        alg: Option<AlgState>,
+    ) -> Result<Arc<FlowInfo>, MasqueradeError> {
+        let idle_timeout = alloc.idle_timeout;
+        let genid = alloc.allocation.genid();
+
         // get original src address and port/Id
-        let (src_ip, src_port) = Self::get_reverse_mapping(initial_flow_key)?;
+        let (src_ip, src_port) = Self::get_reverse_mapping(&forward_key)?;
 
         // build NAT state for both flows
         let (forward_state, reverse_state) =
             MasqueradeState::new_pair(alloc.allocation, src_ip, src_port, idle_timeout);
 
         // build a flow pair from the keys (without NAT state)
-        let expires_at = Instant::now() + Self::MASQUERADE_ONEWAY_TIMEOUT;
         let (forward, reverse) = FlowInfo::related_pair(
             expires_at,
-            *initial_flow_key,
-            packet.meta().compute_flow_flags_forward(),
+            forward_key,
+            flags_forward,
             reverse_key,
-            packet.meta().compute_flow_flags_reverse(),
+            flags_reverse,
         );
 
         // set up their NAT state
         Self::setup_flow_masquerade_state(&forward, forward_state, dst_vpc_id);
         Self::setup_flow_masquerade_state(&reverse, reverse_state, src_vpc_id);
+        if let Some(alg) = alg {
+            forward.locked.write().alg_state = Some(Box::new(alg.clone()));
+            reverse.locked.write().alg_state = Some(Box::new(alg));
+        }
 
         // set the genid of the flows
         forward.set_genid_pair(genid);
@@ -298,7 +365,82 @@ impl Masquerade {
             debug_assert!(false, "reverse flow insert failed: {e:?}");
             return Err(MasqueradeError::CapacityExceeded);
         }
-        Ok(())
+        Ok(forward)
+    }
+
+    /// Set up the flows of an active mode FTP data connection, which the server opens from its
+    /// data port to the `client` endpoint announced on the control connection `control_key`.
+    /// Returns the endpoint the server must connect to instead.
+    fn expect_ftp_data(
+        &self,
+        control_key: &FlowKey,
+        server: IpAddr,
+        vpcds: (VpcDiscriminant, VpcDiscriminant),
+        flags: (FlowInfoFlags, FlowInfoFlags),
+        client: SocketAddrV4,
+    ) -> Option<SocketAddrV4> {
+        let allocator = self.allocator.get()?;
+        let client_ip = IpAddr::V4(*client.ip());
+        let alloc = allocator
+            .allocate(vpcds.1, client_ip, net::ip::NextHeader::TCP)
+            .inspect_err(|e| debug!("Failed to allocate port for FTP data connection: {e}"))
+            .ok()?;
+        let IpAddr::V4(nat_ip) = alloc.allocation.ip() else {
+            return None;
+        };
+        let mapped = SocketAddrV4::new(nat_ip, alloc.allocation.port().as_u16());
+
+        // the forward key is that of the client, had it opened the connection
+        let forward_proto = TcpProtoKey::try_from((client.port(), FTP_DATA_PORT)).ok()?;
+        let forward_key = FlowKey::new(
+            Some(vpcds.0),
+            client_ip,
+            *control_key.dst_ip(),
+            IpProtoKey::Tcp(forward_proto),
+        );
+        let reverse_proto = TcpProtoKey::try_from((FTP_DATA_PORT, mapped.port())).ok()?;
+        let reverse_key = FlowKey::new(
+            Some(vpcds.1),
+            server,
+            IpAddr::V4(nat_ip),
+            IpProtoKey::Tcp(reverse_proto),
+        );
+        let expires_at = Instant::now() + Self::ALG_EXPECTATION_TIMEOUT;
+        self.install_flow_pair(
+            forward_key,
+            reverse_key,
+            alloc,
+            vpcds,
+            flags,
+            expires_at,
+            None,
+        )
+        .inspect_err(|e| debug!("Failed to set up FTP data connection: {e}"))
+        .ok()?;
+        debug!("Expecting FTP data connection {server}:{FTP_DATA_PORT} -> {mapped} for {client}");
+        Some(mapped)
+    }
+
+    /// Let the application layer gateway of a flow process a translated packet of the flow
+    fn apply_alg<Buf: PacketBufferMut>(
+        &self,
+        packet: &mut Packet<Buf>,
+        alg: &AlgState,
+        flow_key: &FlowKey,
+        translate: &NatTranslate,
+    ) {
+        let (Some(src_vpcd), Some(dst_vpcd)) = (packet.meta().src_vpcd, packet.meta().dst_vpcd)
+        else {
+            return;
+        };
+        let server = packet.try_ip().map(|ip| ip.dst_addr());
+        let flags = (
+            packet.meta().compute_flow_flags_forward(),
+            packet.meta().compute_flow_flags_reverse(),
+        );
+        alg.process(packet, flow_key, translate, &self.alg_stats, |client| {
+            self.expect_ftp_data(flow_key, server?, (src_vpcd, dst_vpcd), flags, client)
+        });
     }
 
     fn new_reverse_session(
@@ -357,8 +499,12 @@ impl Masquerade {
         let nfi = self.name();
 
         // Hot path: if we have a session with masquerade state, translate the packet
-        if let Some(translate) = Self::get_masquerade_state(packet) {
-            return Ok(masquerade(packet, &translate)?);
+        if let Some((translate, alg)) = Self::get_masquerade_state(packet) {
+            masquerade(packet, &translate)?;
+            if let Some((alg, flow_key)) = alg {
+                self.apply_alg(packet, &alg, &flow_key, &translate);
+            }
+            return Ok(());
         }
 
         // If no allocator has been configured, drop the packet
@@ -403,14 +549,9 @@ impl Masquerade {
 
         debug!("{nfi}: Allocated: {alloc}");
 
-        // create flow pair
-        self.create_flow_pair(packet, &initial_flow_key, &current_flow_key, alloc)?;
-
-        // lookup the flow (forward) just created. We should always find it.
-        let installed = self
-            .flow_table
-            .lookup(&initial_flow_key)
-            .ok_or(MasqueradeError::Bug("Unexpected flow lookup failure"))?;
+        // create flow pair, and keep the forward flow
+        let installed =
+            self.create_flow_pair(packet, &initial_flow_key, &current_flow_key, alloc)?;
 
         // check that the masquerade state is readable
         let translate = installed
@@ -447,6 +588,9 @@ impl Masquerade {
                     allocator.as_ref(),
                 );
                 if installed.is_active() {
+                    if let Some(alg) = AlgState::of(&installed) {
+                        self.apply_alg(packet, &alg, installed.flowkey(), &translate);
+                    }
                     Ok(())
                 } else {
                     // we invalidated the flow. Signal that packet should be dropped
@@ -7,8 +7,11 @@
 use super::super::build_port_forwarding_configuration;
 use super::PortFwTableError;
 use super::objects::{PortFwEntry, PortFwTable};
+use common::generation::Generational;
 use config::external::overlay::vpc::ValidatedVpcTable;
-use left_right::{Absorb, ReadGuard, ReadHandle, ReadHandleFactory, WriteHandle};
+use config::external::session_table::SessionTableConfig;
+use left_right::{Absorb, ReadGuard, ReadHandle, ReadHandleFactory};
+use stats::InstrumentedWriteHandle;
 
 #[allow(unused)]
 use tracing::{debug, error, warn};
@@ -22,11 +25,12 @@ impl Absorb<PortFwTableChange> for PortFwTable {
         match operation {
             PortFwTableChange::Update(ruleset) => self.update(&ruleset.clone()),
         }
+        self.advance_generation();
     }
     fn sync_with(&mut self, _first: &Self) {}
 }
 
-pub struct PortFwTableWriter(WriteHandle<PortFwTable, PortFwTableChange>);
+pub struct PortFwTableWriter(InstrumentedWriteHandle<PortFwTable, PortFwTableChange>);
 pub struct PortFwTableReader(ReadHandle<PortFwTable>);
 
 #[allow(clippy::unnecessary_wraps)]
@@ -36,11 +40,28 @@ fn validate_ruleset(_ruleset: &[PortFwEntry]) -> Result<(), PortFwTableError> {
     Ok(())
 }
 
+/// A port-forwarding ruleset checked to be valid, which can be published without failing
+#[derive(Debug, Clone)]
+pub struct ValidatedRuleset(Vec<PortFwEntry>);
+
+impl ValidatedRuleset {
+    /// Check a ruleset.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the ruleset is not valid.
+    pub fn new(ruleset: Vec<PortFwEntry>) -> Result<Self, PortFwTableError> {
+        validate_ruleset(&ruleset)?;
+        Ok(Self(ruleset))
+    }
+}
+
 impl PortFwTableWriter {
     #[must_use]
     #[allow(clippy::new_without_default)]
     pub fn new() -> PortFwTableWriter {
-        let (mut write, _) = left_right::new::<PortFwTable, PortFwTableChange>();
+        let (write, _) = left_right::new::<PortFwTable, PortFwTableChange>();
+        let mut write = InstrumentedWriteHandle::new(write, "port-forwarding");
         write.publish();
         PortFwTableWriter(write)
     }
@@ -53,18 +74,23 @@ impl PortFwTableWriter {
         PortFwTableReader(self.0.clone())
     }
     pub fn update_table(&mut self, ruleset: &[PortFwEntry]) -> Result<(), PortFwTableError> {
-        validate_ruleset(ruleset)?;
-        self.0.append(PortFwTableChange::Update(ruleset.to_vec()));
+        self.publish_ruleset(ValidatedRuleset::new(ruleset.to_vec())?);
+        Ok(())
+    }
+    /// Publish a ruleset already validated.
+    pub fn publish_ruleset(&mut self, ruleset: ValidatedRuleset) {
+        self.0.append(PortFwTableChange::Update(ruleset.0));
         self.0.publish();
         self.0.publish(); // intended
-        Ok(())
     }
     pub fn update_from_vpc_table(
         &mut self,
         vpc_table: &ValidatedVpcTable,
     ) -> Result<(), PortFwTableError> {
-        let ruleset = build_port_forwarding_configuration(vpc_table)
-            .map_err(|e| PortFwTableError::Unsupported(e.to_string()))?;
+        // the rules get the default idle timeouts of their protocol
+        let ruleset =
+            build_port_forwarding_configuration(vpc_table, &SessionTableConfig::default())
+                .map_err(|e| PortFwTableError::Unsupported(e.to_string()))?;
         self.update_table(&ruleset)
     }
 }
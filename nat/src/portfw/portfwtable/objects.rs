@@ -4,6 +4,7 @@
 //! Port forwarding objects
 
 use ahash::RandomState;
+use common::generation::{Generational, TableGeneration};
 use concurrency::sync::Arc;
 #[cfg(test)]
 use concurrency::sync::Weak;
@@ -239,10 +240,24 @@ impl Debug for PortFwKey {
 /// IP addresses and ports to re-write and among which VPCs, when that
 /// cannot determined from the flow table. That is, this table is only consulted
 /// in the slow path when no flow state exists in the flow table.
-pub struct PortFwTable(HashMap<PortFwKey, PortForwarder, RandomState>);
+pub struct PortFwTable(
+    HashMap<PortFwKey, PortForwarder, RandomState>,
+    TableGeneration,
+);
 impl Default for PortFwTable {
     fn default() -> Self {
-        Self(HashMap::with_hasher(RandomState::with_seed(0)))
+        Self(
+            HashMap::with_hasher(RandomState::with_seed(0)),
+            TableGeneration::INITIAL,
+        )
+    }
+}
+impl Generational for PortFwTable {
+    fn generation(&self) -> TableGeneration {
+        self.1
+    }
+    fn set_generation(&mut self, generation: TableGeneration) {
+        self.1 = generation;
     }
 }
 impl PortFwTable {
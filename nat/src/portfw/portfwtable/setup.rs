@@ -8,6 +8,7 @@ use crate::portfw::{PortFwEntry, PortFwKey, PortFwTableError};
 use config::ConfigError;
 use config::external::overlay::vpc::{ValidatedPeering, ValidatedVpc, ValidatedVpcTable};
 use config::external::overlay::vpcpeering::ValidatedExpose;
+use config::external::session_table::SessionTableConfig;
 use lpm::prefix::L4Protocol;
 use net::ip::NextHeader;
 use net::packet::VpcDiscriminant;
@@ -21,6 +22,7 @@ fn expose_to_portfw_rule(
     proto: NextHeader,
     src_vpc: VpcDiscriminant,
     dst_vpc: VpcDiscriminant,
+    session_table: &SessionTableConfig,
 ) -> Result<PortFwEntry, PortFwTableError> {
     let nat = expose.nat().unwrap_or_else(|| unreachable!());
     debug_assert!(nat.is_port_forwarding());
@@ -42,8 +44,11 @@ fn expose_to_portfw_rule(
         as_range.ports().unwrap_or_else(|| unreachable!()),
     );
 
-    // the idle timeout of the api gets mapped to the established timeout in port-forwarding
-    let idle_timeout = expose.idle_timeout();
+    // the idle timeout of the api gets mapped to the established timeout in port-forwarding,
+    // falling back to the one of the session table for the protocol
+    let idle_timeout = expose
+        .idle_timeout()
+        .or_else(|| session_table.idle_timeout(proto));
 
     // build the rule
     let key = PortFwKey::new(src_vpc, proto);
@@ -62,6 +67,7 @@ fn vpc_port_fw_peering(
     vpc_table: &ValidatedVpcTable,
     dst_vpc: VpcDiscriminant,
     peering: &ValidatedPeering,
+    session_table: &SessionTableConfig,
 ) -> Result<Vec<PortFwEntry>, PortFwTableError> {
     let mut rules = vec![];
     for expose in peering.local().port_forwarding_exposes() {
@@ -69,18 +75,42 @@ fn vpc_port_fw_peering(
         let src_vpc = VpcDiscriminant::from_vni(remote_vpc_vni);
         match port_fw_proto(expose) {
             L4Protocol::Tcp => {
-                let rule = expose_to_portfw_rule(expose, NextHeader::TCP, src_vpc, dst_vpc)?;
+                let rule = expose_to_portfw_rule(
+                    expose,
+                    NextHeader::TCP,
+                    src_vpc,
+                    dst_vpc,
+                    session_table,
+                )?;
                 rules.push(rule);
             }
             L4Protocol::Udp => {
-                let rule = expose_to_portfw_rule(expose, NextHeader::UDP, src_vpc, dst_vpc)?;
+                let rule = expose_to_portfw_rule(
+                    expose,
+                    NextHeader::UDP,
+                    src_vpc,
+                    dst_vpc,
+                    session_table,
+                )?;
                 rules.push(rule);
             }
             L4Protocol::Any => {
-                let rule = expose_to_portfw_rule(expose, NextHeader::TCP, src_vpc, dst_vpc)?;
+                let rule = expose_to_portfw_rule(
+                    expose,
+                    NextHeader::TCP,
+                    src_vpc,
+                    dst_vpc,
+                    session_table,
+                )?;
                 rules.push(rule);
 
-                let rule = expose_to_portfw_rule(expose, NextHeader::UDP, src_vpc, dst_vpc)?;
+                let rule = expose_to_portfw_rule(
+                    expose,
+                    NextHeader::UDP,
+                    src_vpc,
+                    dst_vpc,
+                    session_table,
+                )?;
                 rules.push(rule);
             }
         }
@@ -90,23 +120,31 @@ fn vpc_port_fw_peering(
 fn vpc_port_fw(
     vpc_table: &ValidatedVpcTable,
     vpc: &ValidatedVpc,
+    session_table: &SessionTableConfig,
 ) -> Result<Vec<PortFwEntry>, PortFwTableError> {
     let mut collected = vec![];
     let dst_vpc = VpcDiscriminant::from_vni(vpc.vni());
     for peering in vpc.peerings() {
-        let mut rules = vpc_port_fw_peering(vpc_table, dst_vpc, peering)?;
+        let mut rules = vpc_port_fw_peering(vpc_table, dst_vpc, peering, session_table)?;
         collected.append(&mut rules);
     }
     Ok(collected)
 }
 
+/// Build the port-forwarding rules of the VPCs of `vpc_table`. The rules of the exposes which
+/// do not set an idle timeout get the one of `session_table` for their protocol, if any.
+///
+/// # Errors
+///
+/// Returns [`ConfigError::PortForwarding`] if a rule can't be built.
 pub fn build_port_forwarding_configuration(
     vpc_table: &ValidatedVpcTable,
+    session_table: &SessionTableConfig,
 ) -> Result<Vec<PortFwEntry>, ConfigError> {
     let mut ruleset = vec![];
     for vpc in vpc_table.values() {
-        let mut rules =
-            vpc_port_fw(vpc_table, vpc).map_err(|e| ConfigError::PortForwarding(e.to_string()))?;
+        let mut rules = vpc_port_fw(vpc_table, vpc, session_table)
+            .map_err(|e| ConfigError::PortForwarding(e.to_string()))?;
         ruleset.append(&mut rules);
     }
     Ok(ruleset)
@@ -2,24 +2,32 @@
 // Copyright Open Network Fabric Authors
 
 //! Static NAT left-right configuration wrapper
+//!
+//! The writer publishes the changes of the per-VNI tables, rather than all the tables, and records
+//! them in a change log, so that a [`ChangeFeed`] can replay them onto a copy of the tables.
 
+use common::changelog::{ChangeFeed, Delta, EntryDelta, SharedChangeLog, diff_entries};
+use common::generation::Generational;
 use left_right::new_from_empty;
-use left_right::{Absorb, ReadGuard, ReadHandle, ReadHandleFactory, WriteHandle};
+use left_right::{Absorb, ReadGuard, ReadHandle, ReadHandleFactory};
+use stats::InstrumentedWriteHandle;
 use tracing::debug;
 
-use crate::static_nat::setup::tables::NatTables;
+use crate::static_nat::setup::tables::{NatTables, PerVniTable};
 
-enum NatTablesChange {
-    UpdateNatTables(NatTables),
+/// A change of the [`NatTables`]: the table of a source VNI was set or removed
+pub type NatTablesChange = EntryDelta<u32, PerVniTable>;
+
+impl Delta<NatTables> for NatTablesChange {
+    fn replay(&self, tables: &mut NatTables) {
+        self.apply(&mut tables.0);
+        tables.advance_generation();
+    }
 }
 
 impl Absorb<NatTablesChange> for NatTables {
     fn absorb_first(&mut self, change: &mut NatTablesChange, _: &Self) {
-        match change {
-            NatTablesChange::UpdateNatTables(nat_tables) => {
-                *self = nat_tables.clone();
-            }
-        }
+        change.replay(self);
     }
     fn drop_first(self: Box<Self>) {}
     fn sync_with(&mut self, first: &Self) {
@@ -27,7 +35,10 @@ impl Absorb<NatTablesChange> for NatTables {
     }
 }
 
-pub struct NatTablesWriter(WriteHandle<NatTables, NatTablesChange>);
+pub struct NatTablesWriter {
+    handle: InstrumentedWriteHandle<NatTables, NatTablesChange>,
+    log: SharedChangeLog<NatTablesChange>,
+}
 #[derive(Debug)]
 pub struct NatTablesReader(ReadHandle<NatTables>);
 impl NatTablesReader {
@@ -58,19 +69,37 @@ impl NatTablesWriter {
     #[allow(clippy::new_without_default)]
     pub fn new() -> NatTablesWriter {
         let (w, _) = new_from_empty::<NatTables, NatTablesChange>(NatTables::new());
-        NatTablesWriter(w)
+        NatTablesWriter {
+            handle: InstrumentedWriteHandle::new(w, "static-nat"),
+            log: SharedChangeLog::default(),
+        }
     }
     #[must_use]
     pub fn get_reader(&self) -> NatTablesReader {
-        NatTablesReader(self.0.clone())
+        NatTablesReader(self.handle.clone())
     }
     #[must_use]
     pub fn get_reader_factory(&self) -> NatTablesReaderFactory {
         self.get_reader().factory()
     }
+    /// Get the feed of the changes published for the tables
+    #[must_use]
+    pub fn change_feed(&self) -> ChangeFeed<NatTables, NatTablesChange> {
+        self.log.feed(self.handle.clone())
+    }
+    /// Publish the changes of the per-VNI tables turning the current tables into `nat_tables`
     pub fn update_nat_tables(&mut self, nat_tables: NatTables) {
-        self.0.append(NatTablesChange::UpdateNatTables(nat_tables));
-        self.0.publish();
-        debug!("Updated tables for static NAT");
+        let changes = match self.handle.enter() {
+            Some(current) => diff_entries(&current.0, &nat_tables.0),
+            None => diff_entries(&NatTables::new().0, &nat_tables.0),
+        };
+        for change in &changes {
+            self.handle.append(change.clone());
+        }
+        let count = changes.len();
+        self.log.record(changes, || {
+            self.handle.publish();
+        });
+        debug!("Updated tables for static NAT: {count} changes");
     }
 }
@@ -4,6 +4,7 @@
 use crate::ranges::{IpPort, IpPortRange, IpPortRangeBounds, IpRange};
 use ahash::RandomState;
 use bnum::cast::CastFrom;
+use common::generation::{Generational, TableGeneration};
 use lpm::prefix::range_map::DisjointRangesBTreeMap;
 use lpm::prefix::{
     IpPrefix, IpRangeWithPorts, PortRange, Prefix, PrefixSize, PrefixWithPortsSize, ppsize_from,
@@ -33,13 +34,19 @@ pub enum NatTablesError {
 /// An object containing the rules for the NAT pipeline stage, not in terms of states for the
 /// different connections established, but instead holding the base rules for static NAT.
 #[derive(Debug, Clone)]
-pub struct NatTables(HashMap<u32, PerVniTable, RandomState>);
+pub struct NatTables(
+    pub(crate) HashMap<u32, PerVniTable, RandomState>,
+    TableGeneration,
+);
 
 impl NatTables {
     /// Creates a new empty [`NatTables`]
     #[must_use]
     pub fn new() -> Self {
-        Self(HashMap::with_hasher(RandomState::with_seed(0)))
+        Self(
+            HashMap::with_hasher(RandomState::with_seed(0)),
+            TableGeneration::INITIAL,
+        )
     }
 
     /// Returns true if the table is empty
@@ -65,6 +72,15 @@ impl NatTables {
     }
 }
 
+impl Generational for NatTables {
+    fn generation(&self) -> TableGeneration {
+        self.1
+    }
+    fn set_generation(&mut self, generation: TableGeneration) {
+        self.1 = generation;
+    }
+}
+
 impl Default for NatTables {
     fn default() -> Self {
         Self::new()
@@ -73,7 +89,7 @@ impl Default for NatTables {
 
 /// A table containing all rules for both source and destination static NAT, for packets with a
 /// given source VNI.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct PerVniTable {
     pub dst_nat: NatRuleTable,
     pub src_nat: HashMap<Vni, NatRuleTable>,
@@ -209,7 +225,7 @@ fn addr_offset_in_prefix_with_ports(
 }
 
 /// From a current address prefix, find the target address prefix.
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq)]
 pub struct NatRuleTable(IpPortPrefixTrie<NatTableValue>);
 
 impl NatRuleTable {
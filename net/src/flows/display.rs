@@ -42,6 +42,12 @@ impl Display for FlowInfoLocked {
         if let Some(data) = &self.nat_state {
             writeln!(f, "      masquerading:{data}")?;
         }
+        if let Some(data) = &self.alg_state {
+            writeln!(f, "      alg:{data}")?;
+        }
+        if let Some(data) = &self.tcp_state {
+            writeln!(f, "      tcp:{data}")?;
+        }
         Ok(())
     }
 }
@@ -81,6 +87,12 @@ impl Display for FlowInfoLockedOneLiner<'_> {
         if let Some(data) = &locked.nat_state {
             write!(f, "masquerading:{data} ")?;
         }
+        if let Some(data) = &locked.alg_state {
+            write!(f, "alg:{data} ")?;
+        }
+        if let Some(data) = &locked.tcp_state {
+            write!(f, "tcp:{data} ")?;
+        }
         Ok(())
     }
 }
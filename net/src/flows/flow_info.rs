@@ -8,7 +8,7 @@ use bitflags::bitflags;
 use concurrency::sync::Arc;
 use concurrency::sync::RwLock;
 use concurrency::sync::Weak;
-use concurrency::sync::atomic::{AtomicI64, AtomicU8, Ordering};
+use concurrency::sync::atomic::{AtomicI64, AtomicU8, AtomicU64, Ordering};
 use std::fmt::{Debug, Display};
 use std::mem::MaybeUninit;
 use std::time::{Duration, Instant};
@@ -169,6 +169,45 @@ pub struct FlowInfoLocked {
 
     // State information for port forwarding
     pub port_fw_state: Option<Box<dyn FlowInfoItem>>,
+
+    // State information for application layer gateways (see AlgState)
+    pub alg_state: Option<Box<dyn FlowInfoItem>>,
+
+    // State information for the strict tracking of TCP sessions (see TcpTracker)
+    pub tcp_state: Option<Box<dyn FlowInfoItem>>,
+}
+
+/// The packets and octets seen in one direction of a session
+#[derive(Debug)]
+pub struct FlowCounters {
+    packets: AtomicU64,
+    octets: AtomicU64,
+}
+
+impl FlowCounters {
+    fn new() -> Self {
+        Self {
+            packets: AtomicU64::new(0),
+            octets: AtomicU64::new(0),
+        }
+    }
+
+    fn add(&self, octets: u64) {
+        self.packets.fetch_add(1, Ordering::Relaxed);
+        self.octets.fetch_add(octets, Ordering::Relaxed);
+    }
+
+    /// The number of packets counted
+    #[must_use]
+    pub fn packets(&self) -> u64 {
+        self.packets.load(Ordering::Relaxed)
+    }
+
+    /// The number of octets counted
+    #[must_use]
+    pub fn octets(&self) -> u64 {
+        self.octets.load(Ordering::Relaxed)
+    }
 }
 
 /// Object that represents a flow of packets.
@@ -177,7 +216,8 @@ pub struct FlowInfoLocked {
 /// the flow table gets a key automatically. `genid` is the last generation id where
 /// this flow is valid (accepted by the flow-filter). As such, it increases on config
 /// changes (if the flow is acceptable under a new configuration), or the flow should
-/// no longer have status `Active`.
+/// no longer have status `Active`. The flow that initiated a session (the first of a
+/// related pair, or a flow on its own) counts the packets of both of its directions.
 #[derive(Debug)]
 pub struct FlowInfo {
     expires_at: AtomicInstant,
@@ -185,6 +225,9 @@ pub struct FlowInfo {
     genid: AtomicI64,
     status: AtomicFlowStatus,
     flags: FlowInfoFlags,
+    initiator: bool,
+    original: FlowCounters,
+    reply: FlowCounters,
     pub locked: RwLock<FlowInfoLocked>,
     pub related: Option<Weak<FlowInfo>>,
     pub token: CancellationToken,
@@ -202,6 +245,9 @@ impl FlowInfo {
             genid: AtomicI64::new(0),
             status: AtomicFlowStatus::from(FlowStatus::Detached),
             flags: FlowInfoFlags::default(),
+            initiator: true,
+            original: FlowCounters::new(),
+            reply: FlowCounters::new(),
             locked: RwLock::new(FlowInfoLocked::default()),
             related: None,
             token: CancellationToken::new(),
@@ -220,6 +266,35 @@ impl FlowInfo {
         self
     }
 
+    /// Tell a flow it replies to its related flow, which initiated the session
+    fn set_reply(mut self) -> Self {
+        self.initiator = false;
+        self
+    }
+
+    /// Tell if a flow initiated its session, i.e. it is not the reply to a related flow
+    #[must_use]
+    pub fn is_initiator(&self) -> bool {
+        self.initiator
+    }
+
+    /// Account a packet of `octets` on the session of the flow. Packets are counted by the flow
+    /// that initiated the session, in the direction of the flow they matched.
+    pub fn account(&self, octets: u64) {
+        if self.initiator {
+            self.original.add(octets);
+        } else if let Some(initiator) = self.related.as_ref().and_then(Weak::upgrade) {
+            initiator.reply.add(octets);
+        }
+    }
+
+    /// The packets of the session in the direction of the flow that initiated it, and in the
+    /// reply direction. Only meaningful for initiators.
+    #[must_use]
+    pub fn counters(&self) -> (&FlowCounters, &FlowCounters) {
+        (&self.original, &self.reply)
+    }
+
     #[must_use]
     pub fn get_flags(&self) -> FlowInfoFlags {
         self.flags
@@ -272,7 +347,7 @@ impl FlowInfo {
     ///
     /// This associated function creates a pair of related `FlowInfo`s by construction. The intended usage is
     /// to call this function when a couple of related flow entries are needed and later insert them in the
-    /// flow-table.
+    /// flow-table. The first flow is taken as the initiator of the session, the second one as its reply.
     ///
     /// # Panics
     ///   This function panics if two equal keys are provided
@@ -318,7 +393,8 @@ impl FlowInfo {
             two_p.write(
                 FlowInfo::new(key2, expires_at)
                     .set_flags(flags2)
-                    .set_related(one_weak),
+                    .set_related(one_weak)
+                    .set_reply(),
             );
             // turn back into Arc's
             (one.assume_init(), two.assume_init())
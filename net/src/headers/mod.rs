@@ -188,6 +188,21 @@ pub enum NetExt {
     Ipv6Auth(Ipv6Auth),
 }
 
+impl NetExt {
+    /// Get the protocol number of the header following this extension header.
+    #[must_use]
+    pub fn next_header(&self) -> NextHeader {
+        match self {
+            NetExt::HopByHop(h) => h.next_header(),
+            NetExt::DestOpts(h) => h.next_header(),
+            NetExt::Routing(h) => h.next_header(),
+            NetExt::Fragment(h) => h.next_header(),
+            NetExt::Ipv4Auth(h) => h.next_header(),
+            NetExt::Ipv6Auth(h) => h.next_header(),
+        }
+    }
+}
+
 impl DeParse for NetExt {
     type Error = ();
 
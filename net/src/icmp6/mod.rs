@@ -549,6 +549,18 @@ impl Icmp6 {
         Icmp6Type::from(self.0.icmp_type)
     }
 
+    /// Get the type of the ICMP message, as a number.
+    #[must_use]
+    pub fn type_u8(&self) -> u8 {
+        self.0.icmp_type.type_u8()
+    }
+
+    /// Get the code of the ICMP message, as a number.
+    #[must_use]
+    pub fn code_u8(&self) -> u8 {
+        self.0.icmp_type.code_u8()
+    }
+
     /// Return a mutable reference to the raw etherparse type field.
     ///
     /// This is `pub(crate)` to keep etherparse out of the public API.
@@ -13,7 +13,7 @@ use std::string::ToString;
 
 macro_rules! kernel_interface_fmt {
     () => {
-        " {:>8} {:>8} {:>16} {:>8} {:>20} {:>8} {:>8} {:>8} {:}"
+        " {:>8} {:>8} {:>16} {:>8} {:>20} {:>8} {:>8} {:>8} {:>8} {:>8} {:}"
     };
 }
 
@@ -23,7 +23,17 @@ fn fmt_kernel_interface_heading(f: &mut Formatter<'_>) -> std::fmt::Result {
         "{}",
         format_args!(
             kernel_interface_fmt!(),
-            "index", "contrl", "name", "mtu", "mac", "Adm", "Oper", "type", "properties"
+            "index",
+            "contrl",
+            "name",
+            "mtu",
+            "mac",
+            "Adm",
+            "Oper",
+            "Carrier",
+            "Speed",
+            "type",
+            "properties"
         )
     )
 }
@@ -131,6 +141,15 @@ impl Display for Interface {
             .map(|mac| mac.to_string())
             .unwrap_or_default();
         let mtu = self.mtu.map(|mtu| mtu.to_string()).unwrap_or_default();
+        let carrier = match self.carrier {
+            Some(true) => "yes",
+            Some(false) => "no",
+            None => "",
+        };
+        let speed = self
+            .speed
+            .map(|speed| speed.to_string())
+            .unwrap_or_default();
         writeln!(
             f,
             "{}",
@@ -143,6 +162,8 @@ impl Display for Interface {
                 mac,
                 self.admin_state.to_string(),
                 self.operational_state.to_string(),
+                carrier,
+                speed,
                 ifproperty_to_str(&self.properties),
                 self.properties.to_string(),
             )
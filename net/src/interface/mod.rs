@@ -281,6 +281,14 @@ pub struct Interface {
     pub admin_state: AdminState,
     /// The observed `OperationalState` of the network interface.
     pub operational_state: OperationalState,
+    /// Whether the interface has carrier (if reported).
+    #[builder(default)]
+    #[serde(default)]
+    pub carrier: Option<bool>,
+    /// The link speed of the interface in Mb/s (if known).
+    #[builder(default)]
+    #[serde(default)]
+    pub speed: Option<u32>,
     /// The controller (i.e., the bridge, bond, or VRF which this interface is a member of).
     pub controller: Option<InterfaceIndex>,
     /// The type-specific properties of this interface.
@@ -410,6 +418,7 @@ mod contract {
         fn generate<D: Driver>(driver: &mut D) -> Option<Self> {
             Some(Self {
                 admin_state: driver.produce()?,
+                carrier: driver.produce()?,
                 controller: driver.produce()?,
                 index: driver.produce()?,
                 mac: driver.produce()?,
@@ -417,6 +426,7 @@ mod contract {
                 name: driver.produce()?,
                 operational_state: driver.produce()?,
                 properties: driver.produce()?,
+                speed: driver.produce()?,
             })
         }
     }
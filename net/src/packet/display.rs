@@ -16,7 +16,7 @@ use crate::vlan::Vlan;
 use crate::buffer::PacketBufferMut;
 use crate::checksum::Checksum;
 #[allow(deprecated)]
-use crate::packet::{BridgeDomain, InvalidPacket, Packet, PacketMeta};
+use crate::packet::{BridgeDomain, InvalidPacket, Packet, PacketMeta, PeeringRuleId, PolicyClass};
 use std::fmt::{Display, Formatter};
 
 impl Display for Eth {
@@ -358,6 +358,21 @@ impl Display for BridgeDomain {
         write!(f, "{}", self.get_id())
     }
 }
+impl Display for PolicyClass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PolicyClass::Internet => write!(f, "internet"),
+            PolicyClass::Private => write!(f, "private"),
+            PolicyClass::Blackhole => write!(f, "blackhole"),
+        }
+    }
+}
+
+impl Display for PeeringRuleId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.get_id())
+    }
+}
 
 #[inline]
 fn fmt_metadata_flags(meta: &PacketMeta, f: &mut Formatter<'_>) -> std::fmt::Result {
@@ -401,7 +416,9 @@ impl Display for PacketMeta {
         fmt_opt(f, " iif", self.iif, false)?;
         fmt_opt(f, " oif", self.oif, false)?;
         fmt_opt(f, "    src-vpcd", self.src_vpcd, false)?;
-        fmt_opt(f, "    dst-vpcd", self.dst_vpcd, true)?;
+        fmt_opt(f, "    dst-vpcd", self.dst_vpcd, false)?;
+        fmt_opt(f, "    peering-rule", self.peering_rule, false)?;
+        fmt_opt(f, "    policy-class", self.policy_class, true)?;
         fmt_opt(f, "    vrf", self.vrf, false)?;
         fmt_opt(f, "    bd", self.bridge, true)?;
         fmt_opt(f, "    next-hop", self.nh_addr, true)?;
@@ -60,12 +60,26 @@ impl<Buf: PacketBufferMut> Packet<Buf> {
         self.hash_ip(state);
     }
 
+    /// Provides the flow hash of a `Packet`: the one computed on reception by the NIC or the
+    /// kernel if there is one in the metadata, or else a hash over the invariant fields of its ip
+    /// and transport headers. Stages which need to spread packets per flow should use it rather
+    /// than hashing the headers again.
+    #[must_use]
+    pub fn flow_hash(&self) -> u64 {
+        match self.meta.rx_hash {
+            Some(hash) => u64::from(hash),
+            None => {
+                let mut hasher = RapidHasher::default();
+                self.hash_ip(&mut hasher);
+                hasher.finish()
+            }
+        }
+    }
+
     #[allow(unused)]
-    /// Uses the ip hash `Packet` method to provide a value in the range [first, last].
+    /// Uses the flow hash of a `Packet` to provide a value in the range [first, last].
     pub fn packet_hash_ecmp(&self, first: u8, last: u8) -> u64 {
-        let mut hasher = RapidHasher::default();
-        self.hash_ip(&mut hasher);
-        hasher.finish() % u64::from(last - first + 1) + u64::from(first)
+        self.flow_hash() % u64::from(last - first + 1) + u64::from(first)
     }
 
     #[allow(unused)]
@@ -151,4 +165,19 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_flow_hash_rx_hash() {
+        let mut packet = build_test_udp_ipv4_packet("10.0.0.1", "10.0.0.2", 1234, 80);
+        let software = packet.flow_hash();
+
+        // the hash computed on reception is preferred
+        packet.meta_mut().rx_hash = Some(0xdead_beef);
+        assert_eq!(packet.flow_hash(), 0xdead_beef);
+        assert_eq!(packet.packet_hash_ecmp(0, 9), 0xdead_beef % 10);
+
+        // and the headers are hashed in its absence
+        packet.meta_mut().rx_hash = None;
+        assert_eq!(packet.flow_hash(), software);
+    }
 }
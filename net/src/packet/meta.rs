@@ -35,6 +35,33 @@ impl BridgeDomain {
     }
 }
 
+/// Identifier of the peering rule (a pair of exposed prefixes) which allowed a packet.
+///
+/// Identifiers are assigned by the flow-filter when building its tables and are only meaningful
+/// with respect to the table (and thus configuration generation) which assigned them.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Ord, PartialOrd)]
+pub struct PeeringRuleId(u32);
+impl PeeringRuleId {
+    #[must_use]
+    pub fn get_id(&self) -> u32 {
+        self.0
+    }
+    #[must_use]
+    pub fn with_id(id: u32) -> Self {
+        Self(id)
+    }
+}
+
+/// The policy class of the destination of a packet, derived from the BGP communities of the
+/// route it matches. Later stages may act upon it (e.g. select NAT pools or policers).
+/// Variants are ordered from the least to the most restrictive.
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Ord, PartialOrd, Serialize)]
+pub enum PolicyClass {
+    Internet,
+    Private,
+    Blackhole,
+}
+
 /// A dataplane-level discriminant to identify (traffic pertaining to) a Vpc
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, Ord, PartialOrd, Serialize)]
 #[cfg_attr(any(test, feature = "bolero"), derive(bolero::TypeGenerator))]
@@ -96,17 +123,22 @@ pub enum DoneReason {
     NotIp,                /* could not get IP header or packet is not IP */
     RouteFailure,         /* missing routing information */
     RouteDrop,            /* routing explicitly requests pkts to be dropped */
+    UrpfDrop,             /* the source failed the uRPF check of the incoming interface */
     HopLimitExceeded,     /* TTL / Hop count was exceeded */
     MissL2resolution,     /* adjacency failure: we don't know mac of some ip next-hop */
+    L2ResolutionTimeout,  /* next-hop did not resolve while packet was held */
     VxlanDecapFailure,    /* Failed to decap a Vxlan packet */
     VxlanEncapFailure,    /* Failed to encap a packet in vxlan */
     Filtered,             /* The packet was administratively filtered */
     AclDropped,           /* The packet was dropped by an ACL rule */
     NatOutOfResources,    /* can't do NAT due to lack of resources */
     FlowCapacityExceeded, /* could not create flow state: flow table capacity exceeded */
+    TcpInvalid,           /* the TCP segment is invalid for the state of its session */
     NatUnsupportedProto,  /* unsupported transport protocol for NATing */
     NatFailure,           /* It was not possible to NAT the packet */
     NatNotPortForwarded,  /* Packet was sent to port forwarder and it rejected it */
+    QosTailDrop,          /* the egress QoS queue of the packet was full */
+    QosWredDrop,          /* the packet was randomly dropped by the WRED policy of its queue */
     Malformed,            /* the packet does not conform / is malformed */
     Unroutable,           /* we don't have state to forward the packet */
     InvalidChecksum,      /* the validation of a checksum for this packet failed */
@@ -132,6 +164,7 @@ bitflags! {
         const REQ_PORT_FORWARDING = 0b0001_0000_0000; /* Packet requires port forwarding */
         const REQ_STATIC_NAT_SRC  = 0b0010_0000_0000;      /* Packet requires static NAT (source) */
         const REQ_STATIC_NAT_DST  = 0b0100_0000_0000;      /* Packet requires static NAT (destination) */
+        const IS_OAM_PROBE        = 0b1000_0000_0000;      /* Packet is a connectivity probe originated by the gateway */
     }
 }
 
@@ -151,6 +184,9 @@ pub struct PacketMeta {
     pub dscp: Option<Dscp>,               /* Dscp to preserve for egress traffic */
     pub ecn: Option<Ecn>,                 /* Ecn to preserve for egress traffic */
     pub flow_key: Option<Box<FlowKey>>,   /* the flow key to use for NAT flow creation */
+    pub peering_rule: Option<PeeringRuleId>, /* the peering rule that allowed the packet: set by flow-filter */
+    pub policy_class: Option<PolicyClass>, /* policy class of the destination, from BGP communities */
+    pub rx_hash: Option<u32>, /* flow hash computed on reception by the NIC or the kernel, if any */
 }
 impl PacketMeta {
     #[must_use]
@@ -245,6 +281,14 @@ impl PacketMeta {
         self.set_flag(MetaFlags::IS_OVERLAY, value);
     }
 
+    #[must_use]
+    pub fn is_oam_probe(&self) -> bool {
+        self.flags.contains(MetaFlags::IS_OAM_PROBE)
+    }
+    pub fn set_oam_probe(&mut self, value: bool) {
+        self.set_flag(MetaFlags::IS_OAM_PROBE, value);
+    }
+
     #[must_use]
     pub fn checksum_refresh(&self) -> bool {
         self.flags.contains(MetaFlags::REFR_CHKSUM)
@@ -21,7 +21,9 @@ pub use contract::*;
 #[cfg(any(doc, test, feature = "test_buffer"))]
 pub mod test_utils;
 
-use crate::buffer::{Headroom, PacketBufferMut, Prepend, Tailroom, TrimFromStart};
+use crate::buffer::{
+    Append, Headroom, PacketBufferMut, Prepend, Tailroom, TrimFromEnd, TrimFromStart,
+};
 use crate::eth::Eth;
 use crate::eth::EthError;
 use crate::flows::{FlowInfo, FlowStatus};
@@ -41,6 +43,7 @@ pub use hash::*;
 #[allow(unused_imports)] // re-export
 pub use meta::*;
 use std::num::NonZero;
+use std::ops::Range;
 
 pub mod utils;
 
@@ -72,6 +75,20 @@ pub enum SerializeError<E> {
     DeparseError(DeParseError<E>),
 }
 
+/// Errors which may occur when editing the payload of a [`Packet`]
+#[derive(Debug, thiserror::Error)]
+#[allow(missing_docs)]
+pub enum PayloadEditError {
+    #[error("Range {0:?} exceeds the payload")]
+    OutOfRange(Range<usize>),
+    #[error("Not enough tailroom")]
+    NoTailRoom,
+    #[error("Edited packet would be too long")]
+    TooLong,
+    #[error("Only the payload of UDP or TCP over IPv4 may be edited")]
+    Unsupported,
+}
+
 impl<Buf: PacketBufferMut> Packet<Buf> {
     /// Map a `PacketBufferMut` to a `Packet` if the buffer contains a valid ethernet packet.
     ///
@@ -100,6 +117,78 @@ impl<Buf: PacketBufferMut> Packet<Buf> {
         &self.payload
     }
 
+    /// Replace the octets in `range` of the payload of a UDP or TCP over IPv4 packet with `with`,
+    /// growing or shrinking the payload as needed. The length fields of the IP and UDP headers
+    /// are updated accordingly, and the checksums marked for refresh.
+    ///
+    /// Changing the length of a TCP payload shifts the sequence numbers of the segments that
+    /// follow: keeping track of that is up to the caller.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [`PayloadEditError`] if the packet is not UDP or TCP over IPv4, the range exceeds
+    /// the payload, or the buffer can't grow enough. The packet is left untouched on error.
+    pub fn splice_payload(
+        &mut self,
+        range: Range<usize>,
+        with: &[u8],
+    ) -> Result<(), PayloadEditError> {
+        let old_len = self.payload.as_ref().len();
+        if range.start > range.end || range.end > old_len {
+            return Err(PayloadEditError::OutOfRange(range));
+        }
+        let new_len = old_len - range.len() + with.len();
+        let transport_len = match (&self.headers.net, &self.headers.transport) {
+            (Some(Net::Ipv4(_)), Some(tp @ (Transport::Udp(_) | Transport::Tcp(_)))) => {
+                usize::from(tp.size().get())
+            }
+            _ => return Err(PayloadEditError::Unsupported),
+        };
+        let ip_payload_len =
+            u16::try_from(transport_len + new_len).map_err(|_| PayloadEditError::TooLong)?;
+
+        // check the new length fits the ip header before touching the buffer
+        let Some(Net::Ipv4(ipv4)) = &mut self.headers.net else {
+            unreachable!()
+        };
+        let mut edited = ipv4.clone();
+        edited
+            .set_payload_len(ip_payload_len)
+            .map_err(|_| PayloadEditError::TooLong)?;
+
+        let tail = range.end..old_len;
+        let at = range.start + with.len();
+        if new_len > old_len {
+            let grow = u16::try_from(new_len - old_len).map_err(|_| PayloadEditError::TooLong)?;
+            self.payload
+                .append(grow)
+                .map_err(|_| PayloadEditError::NoTailRoom)?;
+            let buf = self.payload.as_mut();
+            buf.copy_within(tail, at);
+            buf[range.start..at].copy_from_slice(with);
+        } else {
+            let buf = self.payload.as_mut();
+            buf.copy_within(tail, at);
+            buf[range.start..at].copy_from_slice(with);
+            #[allow(clippy::cast_possible_truncation)] // shorter than the current payload
+            self.payload
+                .trim_from_end((old_len - new_len) as u16)
+                .unwrap_or_else(|_| unreachable!());
+        }
+
+        *ipv4 = edited;
+        if let Some(Transport::Udp(udp)) = &mut self.headers.transport {
+            let udp_len = NonZero::new(ip_payload_len).unwrap_or_else(|| unreachable!());
+            // SAFETY: the length is that of the udp header and its (edited) payload
+            #[allow(unsafe_code)]
+            unsafe {
+                udp.set_length(udp_len);
+            }
+        }
+        self.meta.set_checksum_refresh(true);
+        Ok(())
+    }
+
     /// Add / Replace Ethernet header
     pub fn set_eth(&mut self, eth: Eth) {
         self.headers.set_eth(eth);
@@ -243,6 +332,9 @@ impl<Buf: PacketBufferMut> Packet<Buf> {
                             self.meta.dscp = dscp;
                             self.meta.ecn = ecn;
                             self.headers = headers;
+                            // the hash computed on reception covers the outer headers, which
+                            // may not tell apart the flows of the overlay
+                            self.meta.rx_hash = None;
 
                             Some(Ok(vxlan))
                         }
@@ -29,20 +29,26 @@ impl Display for DoneReason {
             Self::NotIp => f.pad("IP:  packet is not IP"),
             Self::RouteFailure => f.pad("IP:  missing routing info"),
             Self::RouteDrop => f.pad("IP:  route drop"),
+            Self::UrpfDrop => f.pad("IP:  uRPF drop"),
             Self::HopLimitExceeded => f.pad("IP:  TTL exceeded"),
             Self::MissL2resolution => f.pad("IP:  L2 resolution failure"),
+            Self::L2ResolutionTimeout => f.pad("IP:  L2 resolution timeout"),
             Self::VxlanEncapFailure => f.pad("VxLAN: encap failure"),
             Self::VxlanDecapFailure => f.pad("VxLAN: decap failure"),
 
             Self::Filtered => f.pad("Filtered"),
             Self::AclDropped => f.pad("Dropped by ACL"),
             Self::FlowCapacityExceeded => f.pad("Flow capacity exceeded"),
+            Self::TcpInvalid => f.pad("Flow: invalid TCP segment"),
 
             Self::NatOutOfResources => f.pad("NAT: out of resources"),
             Self::NatFailure => f.pad("NAT: failure"),
             Self::NatUnsupportedProto => f.pad("NAT: Unsupported protocol"),
             Self::NatNotPortForwarded => f.pad("NAT: not port-forwarded"),
 
+            Self::QosTailDrop => f.pad("QoS: queue full"),
+            Self::QosWredDrop => f.pad("QoS: WRED drop"),
+
             Self::Malformed => f.pad("Malformed packet"),
             Self::Unroutable => f.pad("Unroutable"),
             Self::InvalidChecksum => f.pad("Invalid checksum"),
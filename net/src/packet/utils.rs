@@ -13,8 +13,9 @@ use crate::eth::mac::{
 };
 use crate::headers::Net::{Ipv4, Ipv6};
 use crate::headers::{
-    EmbeddedTransport, Transport, TryEmbeddedHeaders, TryEmbeddedTransportMut, TryEth, TryEthMut,
-    TryIcmpAny, TryInnerIpMut, TryIp, TryIpMut, TryTcp, TryTransport, TryTransportMut, TryUdp,
+    EmbeddedTransport, NetExt, Transport, TryEmbeddedHeaders, TryEmbeddedTransportMut, TryEth,
+    TryEthMut, TryHeaders, TryIcmpAny, TryInnerIpMut, TryIp, TryIpMut, TryTcp, TryTransport,
+    TryTransportMut, TryUdp,
 };
 use crate::icmp_any::TruncatedIcmpAny;
 use crate::ip::{NextHeader, UnicastIpAddr};
@@ -154,6 +155,18 @@ impl<Buf: PacketBufferMut> Packet<Buf> {
         })
     }
 
+    /// Get the protocol carried by an IPv4 / IPv6 [`Packet`], past its extension headers, if any.
+    /// Returns None if the packet does not have an IP header
+    pub fn upper_layer_proto(&self) -> Option<NextHeader> {
+        let ip_proto = self.ip_proto()?;
+        Some(
+            self.headers()
+                .net_ext()
+                .last()
+                .map_or(ip_proto, NetExt::next_header),
+        )
+    }
+
     /// Is this a TCP packet?
     pub fn is_tcp(&self) -> bool {
         self.try_transport()
@@ -204,6 +204,19 @@ impl Tcp {
         Some(&self.0.options.as_slice()[..self.0.options.len()])
     }
 
+    /// Returns the shift count of the window scale option of this header, if present.
+    ///
+    /// See [rfc7323](https://datatracker.ietf.org/doc/html/rfc7323) for details.
+    #[must_use]
+    pub fn window_scale(&self) -> Option<u8> {
+        TcpOptions(self.0.options.clone())
+            .iter()
+            .find_map(|option| match option {
+                Ok(TcpOption::WindowScale(shift)) => Some(shift),
+                _ => None,
+            })
+    }
+
     /// Set the syn flag
     pub fn set_syn(&mut self, syn: bool) -> &mut Self {
         self.0.syn = syn;
@@ -0,0 +1,151 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Regression tests of the parsing and decapsulation of VXLAN edge cases, over the captures
+//! checked in at `testdata/vxlan`. See the README there for the contents of each capture.
+
+#![cfg(test)]
+
+use crate::buffer::TestBuffer;
+use crate::headers::{Net, TryHeaders};
+use crate::packet::{DoneReason, Packet};
+
+/// What becomes of a frame received by the gateway and addressed to it
+#[derive(Debug, PartialEq, Eq)]
+enum Outcome {
+    /// The frame does not parse as ethernet and is dropped on reception
+    Invalid,
+    /// The frame is done with, for the reason the router gives it when attempting the decap
+    Done(DoneReason),
+    /// The frame was decapsulated
+    Decap {
+        vni: u32,
+        /// The vids of the tags of the inner frame
+        vlans: Vec<u16>,
+        inner: Inner,
+    },
+}
+
+/// The network header of a decapsulated frame
+#[derive(Debug, PartialEq, Eq)]
+enum Inner {
+    Ipv4,
+    Ipv6,
+    NotIp,
+}
+
+/// Split a pcap file into its frames. Only little-endian, microsecond resolution captures of
+/// ethernet frames are supported.
+fn frames(pcap: &[u8]) -> Vec<&[u8]> {
+    let u32_at = |at: usize| u32::from_le_bytes(pcap[at..at + 4].try_into().unwrap());
+    assert_eq!(u32_at(0), 0xa1b2_c3d4, "not a little-endian pcap file");
+    assert_eq!(u32_at(20), 1, "not a capture of ethernet frames");
+    let mut frames = vec![];
+    let mut at = 24;
+    while at < pcap.len() {
+        let len = u32_at(at + 8) as usize;
+        assert_eq!(
+            len,
+            u32_at(at + 12) as usize,
+            "frame {} is cut",
+            frames.len()
+        );
+        frames.push(&pcap[at + 16..at + 16 + len]);
+        at += 16 + len;
+    }
+    frames
+}
+
+/// Classify a frame the way the router does when it is addressed to the gateway
+fn classify(frame: &[u8]) -> Outcome {
+    let Ok(mut packet) = Packet::new(TestBuffer::from_raw_data(frame)) else {
+        return Outcome::Invalid;
+    };
+    match packet.vxlan_decap() {
+        None => Outcome::Done(DoneReason::Local),
+        Some(Err(_)) => Outcome::Done(DoneReason::VxlanDecapFailure),
+        Some(Ok(vxlan)) => {
+            let headers = packet.headers();
+            Outcome::Decap {
+                vni: vxlan.vni().as_u32(),
+                vlans: headers.vlan().iter().map(|v| v.vid().as_u16()).collect(),
+                inner: match headers.net() {
+                    Some(Net::Ipv4(_)) => Inner::Ipv4,
+                    Some(Net::Ipv6(_)) => Inner::Ipv6,
+                    None => Inner::NotIp,
+                },
+            }
+        }
+    }
+}
+
+fn check_capture(pcap: &[u8], expected: &[Outcome]) {
+    let outcomes: Vec<_> = frames(pcap).into_iter().map(classify).collect();
+    assert_eq!(outcomes, expected);
+}
+
+fn decap(vni: u32, vlans: &[u16], inner: Inner) -> Outcome {
+    Outcome::Decap {
+        vni,
+        vlans: vlans.to_vec(),
+        inner,
+    }
+}
+
+#[test]
+fn zero_udp_checksum() {
+    // the checksum of the outer UDP header is not checked: zero is legal over IPv4 (RFC 7348)
+    // and for tunnels over IPv6 (RFC 6935)
+    check_capture(
+        include_bytes!("../../testdata/vxlan/zero-udp-checksum.pcap"),
+        &[
+            decap(100, &[], Inner::Ipv4),
+            decap(100, &[], Inner::Ipv4),
+            decap(300, &[], Inner::Ipv4),
+        ],
+    );
+}
+
+#[test]
+fn inner_vlan() {
+    check_capture(
+        include_bytes!("../../testdata/vxlan/inner-vlan.pcap"),
+        &[
+            decap(200, &[42], Inner::Ipv4),
+            decap(200, &[100, 42], Inner::Ipv4),
+            // vid 0 is not a legal vid: the parse of the inner frame stops at its ethernet header
+            decap(200, &[], Inner::NotIp),
+        ],
+    );
+}
+
+#[test]
+fn truncated_headers() {
+    check_capture(
+        include_bytes!("../../testdata/vxlan/truncated.pcap"),
+        &[
+            Outcome::Invalid,
+            Outcome::Done(DoneReason::Local),
+            Outcome::Done(DoneReason::Local),
+            Outcome::Done(DoneReason::VxlanDecapFailure),
+            decap(400, &[], Inner::NotIp),
+        ],
+    );
+}
+
+#[test]
+fn flags_and_reserved_fields() {
+    // reserved flags are ignored on receipt, but the I flag is required and the reserved octets
+    // must be zero: frames which don't conform are not VXLAN and go to the kernel
+    check_capture(
+        include_bytes!("../../testdata/vxlan/flags.pcap"),
+        &[
+            decap(500, &[], Inner::Ipv4),
+            decap(500, &[], Inner::Ipv4),
+            Outcome::Done(DoneReason::Local),
+            Outcome::Done(DoneReason::Local),
+            Outcome::Done(DoneReason::Local),
+            Outcome::Done(DoneReason::Local),
+        ],
+    );
+}
@@ -5,6 +5,7 @@
 //!
 //! [RFC7348]: https://datatracker.ietf.org/doc/html/rfc7348#section-5
 
+mod corpus;
 mod encap;
 mod vni;
 
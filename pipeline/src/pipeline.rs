@@ -5,14 +5,18 @@
 
 use crate::dyn_nf::DynNetworkFunctionImpl;
 use crate::{DynNetworkFunction, NetworkFunction, nf_dyn};
-use concurrency::sync::Arc;
-use concurrency::sync::atomic::{AtomicI64, Ordering};
+use concurrency::sync::atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use concurrency::sync::{Arc, Mutex, MutexGuard};
 use dyn_iter::{DynIter, IntoDynIterator};
 use id::Id;
 use net::buffer::PacketBufferMut;
 use net::packet::Packet;
 use ordermap::OrderMap;
 use std::any::Any;
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
 
 /// A type that represents an Id for a stage or NF
 pub type StageId<Buf> = Id<Box<dyn DynNetworkFunction<Buf>>>;
@@ -22,6 +26,17 @@ pub type StageId<Buf> = Id<Box<dyn DynNetworkFunction<Buf>>>;
 pub struct PipelineData {
     /// Current generation Id
     pub genid: AtomicI64,
+    /// The generation of the tables of the stages, odd while the tables of a config are being
+    /// published. Batches of packets are only processed under even generations, so that no batch
+    /// is processed with tables of different configs.
+    tables: AtomicU64,
+    /// The generation each [`TablesEpoch`] processes its batch under, if processing one. Locked
+    /// while the tables of a config are published.
+    epochs: Mutex<Vec<Arc<AtomicU64>>>,
+    /// Notified when a stage has packets of its own to inject, which it does upon the next batch
+    batch_request: Notify,
+    /// The number of stages holding packets, to be released even if no more packets come
+    holding: AtomicUsize,
 }
 impl PipelineData {
     #[must_use]
@@ -29,6 +44,10 @@ impl PipelineData {
     pub fn new(genid: i64) -> Self {
         Self {
             genid: AtomicI64::new(genid),
+            tables: AtomicU64::new(0),
+            epochs: Mutex::new(Vec::new()),
+            batch_request: Notify::new(),
+            holding: AtomicUsize::new(0),
         }
     }
     /// Read the generation id
@@ -39,6 +58,134 @@ impl PipelineData {
     pub fn set_genid(&self, genid: i64) {
         self.genid.store(genid, Ordering::Relaxed);
     }
+    /// Register a reader of batches of packets, which processes each of them with the tables of
+    /// a single config
+    #[must_use]
+    pub fn tables_epoch(self: &Arc<Self>) -> TablesEpoch {
+        let epoch = Arc::new(AtomicU64::new(TablesEpoch::IDLE));
+        self.epochs.lock().push(epoch.clone());
+        TablesEpoch {
+            data: self.clone(),
+            epoch,
+        }
+    }
+    /// Wait for the batches of packets being processed, and hold off new ones while publishing
+    /// the tables of a config. The generation of the tables advances once the guard is dropped.
+    pub fn commit_guard(&self) -> CommitGuard<'_> {
+        let mut epochs = self.epochs.lock();
+        epochs.retain(|epoch| Arc::strong_count(epoch) > 1);
+        let publishing = self.tables.fetch_add(1, Ordering::SeqCst) + 1;
+        // the batches begun under the previous generation complete with its tables
+        for epoch in epochs.iter() {
+            while epoch.load(Ordering::SeqCst) < publishing {
+                std::thread::yield_now();
+            }
+        }
+        CommitGuard {
+            data: self,
+            _epochs: epochs,
+        }
+    }
+    /// The generation of the tables, which advances with each config published
+    pub fn tables_generation(&self) -> u64 {
+        self.tables.load(Ordering::Acquire) / 2
+    }
+    /// Have the drivers waiting for packets process a batch anyway, so that the stages with
+    /// packets of their own get to inject them
+    pub fn request_batch(&self) {
+        self.batch_request.notify_waiters();
+    }
+    /// Wait for a stage to request a batch
+    pub async fn batch_requested(&self) {
+        self.batch_request.notified().await;
+    }
+    /// Tell that a stage started (`true`) or stopped (`false`) holding packets
+    pub fn set_holding(&self, holding: bool) {
+        if holding {
+            self.holding.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.holding.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+    /// Tell if any stage holds packets
+    pub fn is_holding(&self) -> bool {
+        self.holding.load(Ordering::Relaxed) > 0
+    }
+    /// Request a batch every `period` while stages hold packets, in a thread of its own, so
+    /// that the packets held get released even with no traffic. The thread ends within a
+    /// `period` of `cancel` being cancelled.
+    pub fn start_drain(
+        data: &Arc<Self>,
+        period: Duration,
+        cancel: CancellationToken,
+    ) -> std::io::Result<JoinHandle<()>> {
+        let data = data.clone();
+        std::thread::Builder::new()
+            .name("pipeline-drain".to_string())
+            .spawn(move || {
+                loop {
+                    std::thread::sleep(period);
+                    if cancel.is_cancelled() {
+                        break;
+                    }
+                    if data.is_holding() {
+                        data.request_batch();
+                    }
+                }
+            })
+    }
+}
+
+/// A reader of batches of packets, such as a driver queue, registered with a [`PipelineData`].
+/// Publishing the tables of a config waits for its batch, if processing one, with no lock
+/// shared by the readers.
+#[derive(Debug)]
+pub struct TablesEpoch {
+    data: Arc<PipelineData>,
+    /// The generation of the tables the batch is processed under, or [`TablesEpoch::IDLE`]
+    epoch: Arc<AtomicU64>,
+}
+
+impl TablesEpoch {
+    const IDLE: u64 = u64::MAX;
+
+    /// Hold off the publication of tables while processing a batch of packets, waiting for the
+    /// publication of those of a config to complete if under way
+    pub fn enter(&mut self) -> TablesGuard<'_> {
+        loop {
+            let generation = self.data.tables.load(Ordering::SeqCst);
+            if generation.is_multiple_of(2) {
+                self.epoch.store(generation, Ordering::SeqCst);
+                // the publisher either sees the batch, or the batch sees the publication
+                if self.data.tables.load(Ordering::SeqCst) == generation {
+                    return TablesGuard(self);
+                }
+                self.epoch.store(Self::IDLE, Ordering::Release);
+            }
+            std::thread::yield_now();
+        }
+    }
+}
+
+/// Held while a batch of packets is processed, see [`TablesEpoch::enter`]
+pub struct TablesGuard<'a>(&'a TablesEpoch);
+
+impl Drop for TablesGuard<'_> {
+    fn drop(&mut self) {
+        self.0.epoch.store(TablesEpoch::IDLE, Ordering::Release);
+    }
+}
+
+/// Held while the tables of a config are published, see [`PipelineData::commit_guard`]
+pub struct CommitGuard<'a> {
+    data: &'a PipelineData,
+    _epochs: MutexGuard<'a, Vec<Arc<AtomicU64>>>,
+}
+
+impl Drop for CommitGuard<'_> {
+    fn drop(&mut self) {
+        self.data.tables.fetch_add(1, Ordering::SeqCst);
+    }
 }
 
 /// A dynamic pipeline that can be updated at runtime.
@@ -234,8 +381,12 @@ mod test {
     use crate::dyn_nf::DynNetworkFunctionImpl;
     use crate::sample_nfs::DecrementTtl;
     use crate::test_utils::DynStageGenerator;
-    use crate::{DynNetworkFunction, DynPipeline, NetworkFunction, StageId};
+    use crate::{DynNetworkFunction, DynPipeline, NetworkFunction, PipelineData, StageId};
+    use concurrency::sync::Arc;
+    use concurrency::sync::atomic::{AtomicBool, Ordering};
     use net::packet::test_utils::build_test_ipv4_packet;
+    use std::time::Duration;
+    use tokio_util::sync::CancellationToken;
 
     type TestStageId = StageId<TestBuffer>;
 
@@ -365,4 +516,53 @@ mod test {
             );
         assert!(stage.is_some());
     }
+
+    #[test]
+    fn commit_waits_for_batches() {
+        let data = Arc::new(PipelineData::default());
+        let mut reader = data.tables_epoch();
+        // readers gone do not hold off publications
+        drop(data.tables_epoch());
+        assert_eq!(data.tables_generation(), 0);
+        drop(data.commit_guard());
+        assert_eq!(data.tables_generation(), 1);
+
+        let batch = reader.enter();
+        let published = Arc::new(AtomicBool::new(false));
+        let publisher = std::thread::spawn({
+            let data = data.clone();
+            let published = published.clone();
+            move || {
+                let commit = data.commit_guard();
+                published.store(true, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(50));
+                drop(commit);
+            }
+        });
+        // the tables are not published while the batch is processed
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(!published.load(Ordering::SeqCst));
+        drop(batch);
+        while !published.load(Ordering::SeqCst) {
+            std::thread::yield_now();
+        }
+        // nor is a batch processed while they are
+        let batch = reader.enter();
+        assert_eq!(data.tables_generation(), 2);
+        drop(batch);
+        publisher.join().unwrap();
+    }
+
+    #[test]
+    fn drain_ends_when_cancelled() {
+        let data = Arc::new(PipelineData::default());
+        data.set_holding(true);
+        let cancel = CancellationToken::new();
+        let drain =
+            PipelineData::start_drain(&data, Duration::from_millis(1), cancel.clone()).unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(!drain.is_finished());
+        cancel.cancel();
+        drain.join().unwrap();
+    }
 }
@@ -0,0 +1,351 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Egress QoS pipeline stage
+//!
+//! [`QosScheduler`] schedules the egress traffic towards the VPCs that have a QoS configuration,
+//! in two levels:
+//!
+//! - Packets are classified by DSCP into the traffic classes of their destination VPC, each with
+//!   its own bounded queue and drop policy (drop-tail or WRED).
+//! - The queues of a VPC are served in strict priority order, as long as the token-bucket shaper
+//!   of the VPC allows it.
+//!
+//! Shapers are shared by all workers, with no lock, so the rate of a VPC applies to the aggregate
+//! of its traffic. Queues are per worker and are served when the worker processes packets: while
+//! packets are queued, the pipeline has the worker process empty batches periodically, so that
+//! they are sent even if no more traffic comes. Packets towards VPCs without QoS configuration
+//! are not affected.
+
+#![deny(clippy::all, clippy::pedantic)]
+
+mod qos_rw;
+mod queue;
+mod shaper;
+mod tables;
+
+pub use qos_rw::{QosTableReader, QosTableReaderFactory, QosTableWriter};
+pub use tables::{QosTable, VpcScheduler};
+
+use crate::queue::{ClassQueue, DropRng, QueueDrop};
+use common::generation::{Generational, TableGeneration};
+use concurrency::sync::Arc;
+use net::buffer::PacketBufferMut;
+use net::headers::{Net, TryIp};
+use net::ip::dscp::Dscp;
+use net::packet::{DoneReason, Packet, VpcDiscriminant};
+use pipeline::{NetworkFunction, PipelineData};
+use std::collections::HashMap;
+use std::hash::{BuildHasher, RandomState};
+use std::time::Instant;
+use tracing::{debug, error};
+
+use tracectl::trace_target;
+trace_target!("qos", LevelFilter::INFO, &["pipeline"]);
+
+/// The queues of a worker for the traffic classes of a VPC
+struct VpcQueues<Buf: PacketBufferMut> {
+    scheduler: Arc<VpcScheduler>,
+    queues: Vec<ClassQueue<Packet<Buf>>>,
+}
+
+impl<Buf: PacketBufferMut> VpcQueues<Buf> {
+    fn new(scheduler: Arc<VpcScheduler>) -> Self {
+        let queues = scheduler
+            .classes
+            .iter()
+            .map(|class| ClassQueue::new(class.queue_depth, class.drop_policy.clone()))
+            .collect();
+        Self { scheduler, queues }
+    }
+
+    /// Remove all the packets from the queues
+    fn drain(&mut self) -> Vec<Packet<Buf>> {
+        let mut packets = vec![];
+        for (class, queue) in self.scheduler.classes.iter().zip(self.queues.iter_mut()) {
+            #[allow(clippy::cast_precision_loss)]
+            class.metrics.depth.decrement(queue.len() as f64);
+            packets.extend(queue.drain());
+        }
+        packets
+    }
+
+    /// Dequeue packets in strict priority order, while the shaper of the VPC allows it
+    fn dequeue(&mut self, now: Instant, output: &mut Vec<Packet<Buf>>) {
+        if self.queues.iter().all(ClassQueue::is_empty) {
+            return;
+        }
+        let shaper = &self.scheduler.shaper;
+        shaper.refill(now);
+        for (class, queue) in self.scheduler.classes.iter().zip(self.queues.iter_mut()) {
+            while let Some(packet) = queue.front() {
+                if !shaper.try_consume(packet.total_len()) {
+                    return;
+                }
+                if let Some(packet) = queue.pop() {
+                    class.metrics.depth.decrement(1.0);
+                    output.push(packet);
+                }
+            }
+        }
+    }
+}
+
+/// The egress QoS pipeline stage
+pub struct QosScheduler<Buf: PacketBufferMut> {
+    name: String,
+    tablesr: QosTableReader,
+    generation: TableGeneration,
+    vpcs: HashMap<VpcDiscriminant, VpcQueues<Buf>>,
+    rng: DropRng,
+    pipeline_data: Arc<PipelineData>,
+    /// Whether the pipeline was told that this stage holds packets
+    holding: bool,
+}
+
+impl<Buf: PacketBufferMut> QosScheduler<Buf> {
+    /// Create a new [`QosScheduler`] instance.
+    #[must_use]
+    pub fn new(name: &str, tablesr: QosTableReader) -> Self {
+        Self {
+            name: name.to_string(),
+            tablesr,
+            generation: TableGeneration::INITIAL,
+            vpcs: HashMap::new(),
+            rng: DropRng::new(RandomState::new().hash_one(name)),
+            pipeline_data: Arc::from(PipelineData::default()),
+            holding: false,
+        }
+    }
+
+    /// Tell the pipeline whether this stage holds packets, if that changed
+    fn update_holding(&mut self) {
+        let holding = self
+            .vpcs
+            .values()
+            .any(|vpc| !vpc.queues.iter().all(ClassQueue::is_empty));
+        if holding != self.holding {
+            self.pipeline_data.set_holding(holding);
+            self.holding = holding;
+        }
+    }
+
+    /// The DSCP to classify a packet with: the one preserved from the inner packet, if any.
+    fn packet_dscp(packet: &Packet<Buf>) -> u8 {
+        if let Some(dscp) = packet.meta().dscp {
+            return dscp.value();
+        }
+        match packet.try_ip() {
+            Some(Net::Ipv4(ipv4)) => Dscp::from(ipv4.dscp()).value(),
+            Some(Net::Ipv6(ipv6)) => Dscp::from(ipv6.dscp()).value(),
+            None => 0,
+        }
+    }
+
+    /// Enqueue a packet if its destination VPC has QoS configured, and serve the queues of that
+    /// VPC. Packets not enqueued go straight to the output, possibly marked as dropped.
+    fn enqueue(
+        vpcs: &mut HashMap<VpcDiscriminant, VpcQueues<Buf>>,
+        rng: &mut DropRng,
+        table: &QosTable,
+        mut packet: Packet<Buf>,
+        now: Instant,
+        output: &mut Vec<Packet<Buf>>,
+    ) {
+        if packet.is_done() {
+            output.push(packet);
+            return;
+        }
+        let Some(vpcd) = packet.meta().dst_vpcd else {
+            output.push(packet);
+            return;
+        };
+        let Some(scheduler) = table.get(&vpcd) else {
+            output.push(packet);
+            return;
+        };
+        let vpc = vpcs
+            .entry(vpcd)
+            .or_insert_with(|| VpcQueues::new(scheduler.clone()));
+        let index = scheduler.classify(Self::packet_dscp(&packet));
+        let class = &scheduler.classes[index];
+        match vpc.queues[index].push(packet, rng) {
+            Ok(()) => {
+                class.metrics.enqueued.increment(1);
+                class.metrics.depth.increment(1.0);
+                vpc.dequeue(now, output);
+                return;
+            }
+            Err((dropped, QueueDrop::Tail)) => {
+                class.metrics.tail_drops.increment(1);
+                packet = dropped;
+                packet.done(DoneReason::QosTailDrop);
+            }
+            Err((dropped, QueueDrop::Wred)) => {
+                class.metrics.wred_drops.increment(1);
+                packet = dropped;
+                packet.done(DoneReason::QosWredDrop);
+            }
+        }
+        output.push(packet);
+    }
+
+    /// Process a batch of packets: enqueue them and dequeue those that the shapers allow
+    fn process_batch(&mut self, input: impl Iterator<Item = Packet<Buf>>) -> Vec<Packet<Buf>> {
+        let Some(table) = self.tablesr.enter() else {
+            error!("{}: failed to read QoS table", self.name);
+            return input
+                .map(|mut packet| {
+                    packet.done(DoneReason::InternalFailure);
+                    packet
+                })
+                .collect();
+        };
+        let now = Instant::now();
+        let mut output = vec![];
+
+        // serve the packets queued in previous batches first
+        for vpc in self.vpcs.values_mut() {
+            vpc.dequeue(now, &mut output);
+        }
+
+        // on a configuration change, queued packets are re-classified with the new configuration
+        let mut requeue = vec![];
+        if table.generation() != self.generation {
+            debug!("{}: QoS table changed to {}", self.name, table.generation());
+            self.generation = table.generation();
+            for vpc in self.vpcs.values_mut() {
+                requeue.extend(vpc.drain());
+            }
+            self.vpcs.clear();
+        }
+        for packet in requeue.into_iter().chain(input) {
+            Self::enqueue(
+                &mut self.vpcs,
+                &mut self.rng,
+                &table,
+                packet,
+                now,
+                &mut output,
+            );
+        }
+        self.update_holding();
+        output
+    }
+}
+
+impl<Buf: PacketBufferMut> Drop for QosScheduler<Buf> {
+    fn drop(&mut self) {
+        if self.holding {
+            self.pipeline_data.set_holding(false);
+        }
+    }
+}
+
+impl<Buf: PacketBufferMut> NetworkFunction<Buf> for QosScheduler<Buf> {
+    fn process<'a, Input: Iterator<Item = Packet<Buf>> + 'a>(
+        &'a mut self,
+        input: Input,
+    ) -> impl Iterator<Item = Packet<Buf>> + 'a {
+        self.process_batch(input)
+            .into_iter()
+            .filter_map(Packet::enforce)
+    }
+
+    fn set_data(&mut self, data: Arc<PipelineData>) {
+        self.pipeline_data = data;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use config::external::overlay::vpc::{Vpc, VpcTable};
+    use config::external::qos::{DecapDscp, EncapDscp, QosClass, QosConfig, TunnelDscp, VpcQos};
+    use net::buffer::TestBuffer;
+    use net::packet::test_utils::build_test_ipv4_packet;
+    use net::vxlan::Vni;
+
+    fn scheduler_for(vni: Vni, qos: VpcQos) -> (QosTableWriter, QosScheduler<TestBuffer>) {
+        let mut vpc_table = VpcTable::new();
+        vpc_table
+            .add(Vpc::new("vpc1", "AAAAA", vni.as_u32()).unwrap())
+            .unwrap();
+        let vpc_table = vpc_table.validate().unwrap();
+        let mut config = QosConfig::new();
+        config.insert("vpc1", qos);
+
+        let mut tablesw = QosTableWriter::new();
+        tablesw.update_qos_table(QosTable::build(&config, &vpc_table).unwrap());
+        let scheduler = QosScheduler::new("qos", tablesw.get_reader());
+        (tablesw, scheduler)
+    }
+
+    fn packet_to(vni: Vni) -> Packet<TestBuffer> {
+        let mut packet = build_test_ipv4_packet(64).unwrap();
+        packet.meta_mut().dst_vpcd = Some(VpcDiscriminant::from_vni(vni));
+        packet
+    }
+
+    #[test]
+    fn test_tunnel_dscp() {
+        let vni = Vni::new_checked(3000).unwrap();
+        let (mut tablesw, _) = scheduler_for(vni, VpcQos::new(1_000_000));
+        let reader = tablesw.get_reader();
+        let vpcd = VpcDiscriminant::from_vni(vni);
+        assert_eq!(reader.tunnel_dscp(&vpcd), None);
+
+        let mut vpc_table = VpcTable::new();
+        vpc_table
+            .add(Vpc::new("vpc1", "AAAAA", vni.as_u32()).unwrap())
+            .unwrap();
+        let vpc_table = vpc_table.validate().unwrap();
+        let mut config = QosConfig::new();
+        let tunnel = TunnelDscp {
+            encap: EncapDscp::Fixed(10),
+            decap: DecapDscp::Copy,
+        };
+        config.set_tunnel_dscp("vpc1", tunnel);
+        tablesw.update_qos_table(QosTable::build(&config, &vpc_table).unwrap());
+        assert_eq!(reader.tunnel_dscp(&vpcd), Some(tunnel));
+
+        config.set_tunnel_dscp("vpc2", tunnel);
+        assert!(QosTable::build(&config, &vpc_table).is_err());
+    }
+
+    #[test]
+    fn test_qos_scheduler() {
+        let vni = Vni::new_checked(3000).unwrap();
+        let mut qos = VpcQos::new(1_000_000);
+        qos.burst_bytes = 200;
+        let mut class = QosClass::new("best-effort", 0);
+        class.queue_depth = 4;
+        qos.add_class(class);
+        let (_tablesw, mut scheduler) = scheduler_for(vni, qos);
+
+        // packets to other VPCs are not affected
+        let other = Vni::new_checked(4000).unwrap();
+        let output: Vec<_> = scheduler
+            .process((0..20).map(|_| packet_to(other)))
+            .collect();
+        assert_eq!(output.len(), 20);
+
+        // the burst lets a few packets through, 4 get queued, the rest gets dropped
+        let output: Vec<_> = scheduler.process((0..20).map(|_| packet_to(vni))).collect();
+        assert!(!output.is_empty() && output.len() < 20);
+        let queued: usize = scheduler
+            .vpcs
+            .values()
+            .flat_map(|v| &v.queues)
+            .map(ClassQueue::len)
+            .sum();
+        assert_eq!(queued, 4);
+        assert!(scheduler.pipeline_data.is_holding());
+
+        // once the shaper refills, empty batches send the queued packets
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        let output: Vec<_> = scheduler.process(std::iter::empty()).collect();
+        assert_eq!(output.len(), 4);
+        assert!(!scheduler.pipeline_data.is_holding());
+    }
+}
@@ -0,0 +1,94 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Left-right integration for [`QosTable`]
+
+use crate::tables::QosTable;
+use common::generation::Generational;
+use config::external::qos::TunnelDscp;
+use left_right::{Absorb, ReadGuard, ReadHandle, ReadHandleFactory, new_from_empty};
+use net::packet::VpcDiscriminant;
+use stats::InstrumentedWriteHandle;
+use tracing::debug;
+
+#[derive(Debug, Clone)]
+pub enum QosTableChange {
+    UpdateQosTable(QosTable),
+}
+
+impl Absorb<QosTableChange> for QosTable {
+    fn absorb_first(&mut self, change: &mut QosTableChange, _: &Self) {
+        match change {
+            QosTableChange::UpdateQosTable(new) => {
+                self.replace_contents(new.clone());
+            }
+        }
+    }
+    fn drop_first(self: Box<Self>) {}
+    fn sync_with(&mut self, first: &Self) {
+        *self = first.clone();
+    }
+}
+
+#[derive(Debug)]
+pub struct QosTableReader(ReadHandle<QosTable>);
+
+impl QosTableReader {
+    pub(crate) fn enter(&self) -> Option<ReadGuard<'_, QosTable>> {
+        self.0.enter()
+    }
+
+    /// The DSCP policy of the VXLAN traffic of a VPC, if the table can be read and the VPC
+    /// has one
+    #[must_use]
+    pub fn tunnel_dscp(&self, vpcd: &VpcDiscriminant) -> Option<TunnelDscp> {
+        self.enter()?.tunnel_dscp(vpcd)
+    }
+
+    #[must_use]
+    pub fn factory(&self) -> QosTableReaderFactory {
+        QosTableReaderFactory(self.0.factory())
+    }
+
+    #[must_use]
+    pub fn inner(&self) -> ReadHandle<QosTable> {
+        self.0.clone()
+    }
+}
+
+#[derive(Debug)]
+pub struct QosTableReaderFactory(ReadHandleFactory<QosTable>);
+
+impl QosTableReaderFactory {
+    #[must_use]
+    pub fn handle(&self) -> QosTableReader {
+        QosTableReader(self.0.handle())
+    }
+}
+
+#[derive(Debug)]
+pub struct QosTableWriter(InstrumentedWriteHandle<QosTable, QosTableChange>);
+
+impl QosTableWriter {
+    #[must_use]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> QosTableWriter {
+        let (w, _r) = new_from_empty::<QosTable, QosTableChange>(QosTable::new());
+        QosTableWriter(InstrumentedWriteHandle::new(w, "qos"))
+    }
+
+    #[must_use]
+    pub fn get_reader(&self) -> QosTableReader {
+        QosTableReader(self.0.clone())
+    }
+
+    pub fn get_reader_factory(&self) -> QosTableReaderFactory {
+        self.get_reader().factory()
+    }
+
+    pub fn update_qos_table(&mut self, table: QosTable) {
+        self.0.append(QosTableChange::UpdateQosTable(table));
+        self.0.publish();
+        debug!("Updated QoS table");
+    }
+}
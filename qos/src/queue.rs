@@ -0,0 +1,156 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Queues of traffic classes, with their drop policies
+
+use config::external::qos::{DropPolicy, WredParams};
+use std::collections::VecDeque;
+
+/// Weight of the current depth in the average depth of a queue, for WRED
+const WRED_WEIGHT: f64 = 1.0 / 64.0;
+
+/// Why a packet was not admitted to a queue
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum QueueDrop {
+    /// The queue was full
+    Tail,
+    /// The packet was randomly dropped by WRED
+    Wred,
+}
+
+/// A small xorshift pseudo-random generator, good enough to draw WRED drops
+#[derive(Debug)]
+pub(crate) struct DropRng(u64);
+impl DropRng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self(seed | 1)
+    }
+    /// A pseudo-random number in [0, 1)
+    #[allow(clippy::cast_precision_loss)]
+    fn next_f64(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Tell if WRED drops a packet, updating the average depth of the queue with its current `len`
+#[allow(clippy::cast_precision_loss)]
+fn wred_drops(avg_depth: &mut f64, len: usize, wred: &WredParams, rng: &mut DropRng) -> bool {
+    *avg_depth += WRED_WEIGHT * (len as f64 - *avg_depth);
+    let min = f64::from(wred.min_threshold);
+    let max = f64::from(wred.max_threshold);
+    if *avg_depth < min {
+        false
+    } else if *avg_depth >= max {
+        true
+    } else {
+        let probability =
+            f64::from(wred.max_probability) / 100.0 * (*avg_depth - min) / (max - min);
+        rng.next_f64() < probability
+    }
+}
+
+/// A bounded FIFO queue of some traffic class
+#[derive(Debug)]
+pub(crate) struct ClassQueue<T> {
+    items: VecDeque<T>,
+    depth: usize,
+    drop_policy: DropPolicy,
+    avg_depth: f64,
+}
+
+impl<T> ClassQueue<T> {
+    pub(crate) fn new(depth: usize, drop_policy: DropPolicy) -> Self {
+        Self {
+            items: VecDeque::new(),
+            depth,
+            drop_policy,
+            avg_depth: 0.0,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub(crate) fn front(&self) -> Option<&T> {
+        self.items.front()
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<T> {
+        self.items.pop_front()
+    }
+
+    pub(crate) fn drain(&mut self) -> impl Iterator<Item = T> + '_ {
+        self.items.drain(..)
+    }
+
+    /// Enqueue an item, unless the drop policy drops it. In that case, the item is given back.
+    pub(crate) fn push(&mut self, item: T, rng: &mut DropRng) -> Result<(), (T, QueueDrop)> {
+        if let DropPolicy::Wred(wred) = &self.drop_policy
+            && wred_drops(&mut self.avg_depth, self.items.len(), wred, rng)
+        {
+            return Err((item, QueueDrop::Wred));
+        }
+        if self.items.len() >= self.depth {
+            return Err((item, QueueDrop::Tail));
+        }
+        self.items.push_back(item);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_drop_tail() {
+        let mut rng = DropRng::new(1);
+        let mut queue = ClassQueue::new(4, DropPolicy::DropTail);
+        for i in 0..4 {
+            assert!(queue.push(i, &mut rng).is_ok());
+        }
+        assert_eq!(queue.push(4, &mut rng), Err((4, QueueDrop::Tail)));
+        assert_eq!(queue.pop(), Some(0));
+        assert!(queue.push(4, &mut rng).is_ok());
+        assert_eq!(queue.drain().collect::<Vec<_>>(), vec![1, 2, 3, 4]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn test_wred() {
+        let mut rng = DropRng::new(0x5eed);
+        let wred = WredParams {
+            min_threshold: 16,
+            max_threshold: 48,
+            max_probability: 50,
+        };
+        let mut queue = ClassQueue::new(64, DropPolicy::Wred(wred));
+
+        // no drops while the average depth is below the min threshold
+        for i in 0..16 {
+            assert!(queue.push(i, &mut rng).is_ok());
+        }
+
+        // as the queue builds up, WRED drops some packets before the queue gets full
+        let mut wred_drops = 0;
+        let mut tail_drops = 0;
+        for i in 0..2000 {
+            match queue.push(i, &mut rng) {
+                Ok(()) => {}
+                Err((_, QueueDrop::Wred)) => wred_drops += 1,
+                Err((_, QueueDrop::Tail)) => tail_drops += 1,
+            }
+        }
+        assert!(wred_drops > 0);
+        assert!(queue.len() <= 64);
+        assert!(wred_drops + tail_drops + queue.len() == 2016);
+    }
+}
@@ -0,0 +1,134 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Token-bucket shaper
+
+use concurrency::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::time::Instant;
+
+/// A token bucket, with tokens in bytes. The bucket may go into debt so that packets larger
+/// than the burst can be sent: no packet is sent until the debt is paid. The bucket is shared
+/// by all workers with no lock: the worker that moves the time of the last refill forward adds
+/// the tokens accrued since then.
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+    rate_bps: u64,
+    burst: i64,
+    tokens: AtomicI64,
+    /// The time the bucket was created at, which refill times are relative to
+    origin: Instant,
+    /// Nanoseconds from `origin` to the last refill
+    last: AtomicU64,
+}
+
+impl TokenBucket {
+    /// Create a token bucket, initially full
+    pub(crate) fn new(rate_bps: u64, burst_bytes: u64) -> Self {
+        let burst = i64::try_from(burst_bytes).unwrap_or(i64::MAX);
+        Self {
+            rate_bps,
+            burst,
+            tokens: AtomicI64::new(burst),
+            origin: Instant::now(),
+            last: AtomicU64::new(0),
+        }
+    }
+
+    /// Add the tokens accrued since the last refill, up to the burst
+    pub(crate) fn refill(&self, now: Instant) {
+        let now = now.saturating_duration_since(self.origin).as_nanos();
+        let now = u64::try_from(now).unwrap_or(u64::MAX);
+        let last = self.last.load(Ordering::Acquire);
+        if now <= last {
+            return;
+        }
+        let accrued = u128::from(self.rate_bps) * u128::from(now - last) / 8 / 1_000_000_000;
+        if accrued == 0 {
+            // keep the time of the last refill, so that the fractions of tokens add up
+            return;
+        }
+        if self
+            .last
+            .compare_exchange(last, now, Ordering::AcqRel, Ordering::Relaxed)
+            .is_err()
+        {
+            // another worker refilled the bucket in the meantime
+            return;
+        }
+        let accrued = i64::try_from(accrued).unwrap_or(i64::MAX);
+        let _ = self
+            .tokens
+            .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |tokens| {
+                Some(tokens.saturating_add(accrued).min(self.burst))
+            });
+    }
+
+    /// Consume the tokens for a packet of `bytes`. Fails if the bucket has no tokens left.
+    pub(crate) fn try_consume(&self, bytes: u16) -> bool {
+        self.tokens
+            .fetch_update(Ordering::AcqRel, Ordering::Relaxed, |tokens| {
+                (tokens > 0).then(|| tokens - i64::from(bytes))
+            })
+            .is_ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_token_bucket() {
+        // 8 Mbps: 1000 bytes per msec
+        let bucket = TokenBucket::new(8_000_000, 3000);
+        let start = bucket.origin;
+        assert!(bucket.try_consume(1500));
+        assert!(bucket.try_consume(1500));
+        assert!(!bucket.try_consume(64));
+
+        // refill 1000 bytes
+        bucket.refill(start + Duration::from_millis(1));
+        assert!(bucket.try_consume(1500));
+        assert!(!bucket.try_consume(64));
+
+        // after a long time, bucket is capped to the burst
+        bucket.refill(start + Duration::from_secs(10));
+        assert!(bucket.try_consume(1500));
+        assert!(bucket.try_consume(1500));
+        assert!(!bucket.try_consume(64));
+    }
+
+    #[test]
+    fn test_token_bucket_fractions() {
+        // 8 Kbps: 1 byte per msec, so that refills every 100 usec accrue less than a token
+        let bucket = TokenBucket::new(8_000, 100);
+        let start = bucket.origin;
+        assert!(bucket.try_consume(100));
+        assert!(!bucket.try_consume(64));
+        for step in 1..=10 {
+            bucket.refill(start + Duration::from_micros(100 * step));
+        }
+        assert_eq!(bucket.tokens.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn test_token_bucket_shared() {
+        // 8 Mbps: 1000 bytes per msec, refilled by several workers at once
+        let bucket = TokenBucket::new(8_000_000, 1_000_000);
+        while bucket.try_consume(u16::MAX) {}
+        let start = bucket.origin;
+        let tokens = bucket.tokens.load(Ordering::Relaxed);
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for msec in 1..=100 {
+                        bucket.refill(start + Duration::from_millis(msec));
+                    }
+                });
+            }
+        });
+        // no refill is accounted twice
+        assert_eq!(bucket.tokens.load(Ordering::Relaxed), tokens + 100_000);
+    }
+}
@@ -0,0 +1,204 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! The QoS table: the schedulers of the VPCs with an egress QoS configuration, and the DSCP
+//! policies of the VXLAN traffic of VPCs
+
+use crate::shaper::TokenBucket;
+use common::generation::{Generational, TableGeneration};
+use concurrency::sync::Arc;
+use config::ConfigError;
+use config::external::overlay::vpc::ValidatedVpcTable;
+use config::external::qos::{DropPolicy, QosConfig, TunnelDscp, VpcQos};
+use metrics::{Counter, Gauge, Unit};
+use net::packet::VpcDiscriminant;
+use stats::{MetricSpec, Register};
+use std::collections::HashMap;
+use std::fmt::Display;
+
+/// The metrics of the queue of a traffic class. They aggregate those of all workers.
+#[derive(Debug)]
+pub(crate) struct QueueMetrics {
+    pub(crate) depth: Gauge,
+    pub(crate) enqueued: Counter,
+    pub(crate) tail_drops: Counter,
+    pub(crate) wred_drops: Counter,
+}
+
+impl QueueMetrics {
+    fn new(vpc: &str, class: &str) -> Self {
+        let labels = || {
+            vec![
+                ("vpc".to_string(), vpc.to_string()),
+                ("class".to_string(), class.to_string()),
+            ]
+        };
+        let counter =
+            |id: &str| -> Counter { MetricSpec::new(id, Unit::Count, labels()).register().metric };
+        Self {
+            depth: MetricSpec::new("qos_queue_depth_packets", Unit::Count, labels())
+                .register()
+                .metric,
+            enqueued: counter("qos_queue_enqueued_packets"),
+            tail_drops: counter("qos_queue_tail_dropped_packets"),
+            wred_drops: counter("qos_queue_wred_dropped_packets"),
+        }
+    }
+}
+
+/// A traffic class of a VPC
+#[derive(Debug)]
+pub(crate) struct ClassScheduler {
+    pub(crate) name: String,
+    pub(crate) queue_depth: usize,
+    pub(crate) drop_policy: DropPolicy,
+    pub(crate) metrics: QueueMetrics,
+}
+
+/// The scheduler of a VPC: a shaper shared by all workers, feeding from the queues of the
+/// traffic classes of the VPC, served in strict priority order.
+#[derive(Debug)]
+pub struct VpcScheduler {
+    pub(crate) name: String,
+    pub(crate) rate_bps: u64,
+    pub(crate) shaper: TokenBucket,
+    /// Traffic classes, by decreasing priority
+    pub(crate) classes: Vec<ClassScheduler>,
+    /// The index of the class of each DSCP value
+    pub(crate) dscp_class: [usize; 64],
+}
+
+impl VpcScheduler {
+    fn new(name: &str, qos: &VpcQos) -> Self {
+        let mut classes: Vec<_> = qos
+            .classes
+            .iter()
+            .map(|class| ClassScheduler {
+                name: class.name.clone(),
+                queue_depth: class.queue_depth as usize,
+                drop_policy: class.drop_policy.clone(),
+                metrics: QueueMetrics::new(name, &class.name),
+            })
+            .collect();
+        if classes.is_empty() {
+            classes.push(ClassScheduler {
+                name: "default".to_string(),
+                queue_depth: config::external::qos::QosClass::DEFAULT_QUEUE_DEPTH as usize,
+                drop_policy: DropPolicy::DropTail,
+                metrics: QueueMetrics::new(name, "default"),
+            });
+        }
+
+        // the lowest-priority class gets the DSCPs not assigned to any class
+        let mut dscp_class = [classes.len() - 1; 64];
+        for (index, class) in qos.classes.iter().enumerate() {
+            for dscp in &class.dscp {
+                dscp_class[usize::from(*dscp)] = index;
+            }
+        }
+        Self {
+            name: name.to_owned(),
+            rate_bps: qos.rate_bps,
+            shaper: TokenBucket::new(qos.rate_bps, qos.burst_bytes),
+            classes,
+            dscp_class,
+        }
+    }
+
+    /// The index of the class of the packets with some DSCP
+    pub(crate) fn classify(&self, dscp: u8) -> usize {
+        self.dscp_class[usize::from(dscp & 0x3f)]
+    }
+}
+
+/// The schedulers of the VPCs with an egress QoS configuration, and the DSCP policies of the
+/// VXLAN traffic of VPCs, by their discriminant
+#[derive(Clone, Debug, Default)]
+pub struct QosTable {
+    vpcs: HashMap<VpcDiscriminant, Arc<VpcScheduler>>,
+    tunnel: HashMap<VpcDiscriminant, TunnelDscp>,
+    generation: TableGeneration,
+}
+
+impl Generational for QosTable {
+    fn generation(&self) -> TableGeneration {
+        self.generation
+    }
+    fn set_generation(&mut self, generation: TableGeneration) {
+        self.generation = generation;
+    }
+}
+
+impl QosTable {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Build the QoS table from the QoS configuration
+    ///
+    /// # Errors
+    ///
+    /// Fails if QoS is configured for a VPC that does not exist. Validation should prevent it.
+    pub fn build(qos: &QosConfig, vpc_table: &ValidatedVpcTable) -> Result<Self, ConfigError> {
+        let vpcd = |name: &String| {
+            vpc_table
+                .get_vpc(name)
+                .map(|vpc| VpcDiscriminant::from_vni(vpc.vni()))
+                .ok_or_else(|| ConfigError::NoSuchVpc(name.clone()))
+        };
+        let mut table = Self::new();
+        for (name, vpc_qos) in qos.iter() {
+            table
+                .vpcs
+                .insert(vpcd(name)?, Arc::new(VpcScheduler::new(name, vpc_qos)));
+        }
+        for (name, tunnel) in qos.tunnel_iter() {
+            table.tunnel.insert(vpcd(name)?, *tunnel);
+        }
+        Ok(table)
+    }
+
+    #[must_use]
+    pub fn get(&self, vpcd: &VpcDiscriminant) -> Option<&Arc<VpcScheduler>> {
+        self.vpcs.get(vpcd)
+    }
+
+    /// The DSCP policy of the VXLAN traffic of a VPC
+    #[must_use]
+    pub fn tunnel_dscp(&self, vpcd: &VpcDiscriminant) -> Option<TunnelDscp> {
+        self.tunnel.get(vpcd).copied()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.vpcs.is_empty() && self.tunnel.is_empty()
+    }
+}
+
+impl Display for QosTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (vpcd, scheduler) in &self.vpcs {
+            writeln!(
+                f,
+                " {} ({vpcd}): {} bps",
+                scheduler.name, scheduler.rate_bps
+            )?;
+            for (priority, class) in scheduler.classes.iter().enumerate() {
+                writeln!(
+                    f,
+                    "   [{priority}] {:<16} depth: {:>6} drop: {}",
+                    class.name, class.queue_depth, class.drop_policy
+                )?;
+            }
+        }
+        for (vpcd, tunnel) in &self.tunnel {
+            writeln!(
+                f,
+                " {vpcd}: vxlan dscp encap {}, decap {}",
+                tunnel.encap, tunnel.decap
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -164,6 +164,21 @@ pub trait AsRequirement<Observation> {
         Self: 'a;
 }
 
+/// Bring a requirement to its canonical form before it is compared with observations.
+///
+/// External systems often fill in defaults for the values a requirement leaves unspecified, or
+/// report values in a canonical form of their own. Normalizing requirements the same way prevents
+/// spurious differences between the required and the observed state.
+///
+/// # Contract
+///
+/// Normalization must be idempotent, and must not change the intent of the requirement: it only
+/// makes explicit what the external system would make of the original requirement.
+pub trait Normalize {
+    /// Normalize the requirement in place.
+    fn normalize(&mut self);
+}
+
 /// Attempt to drive an external resource into its required condition.
 pub trait Reconcile {
     /// The data required to create the resource.
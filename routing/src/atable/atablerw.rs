@@ -4,8 +4,9 @@
 //! Adjacency table left-right
 
 use crate::atable::adjacency::{Adjacency, AdjacencyTable};
-use left_right::{Absorb, ReadGuard, ReadHandle, ReadHandleFactory, WriteHandle};
+use left_right::{Absorb, ReadGuard, ReadHandle, ReadHandleFactory};
 use net::interface::InterfaceIndex;
+use stats::InstrumentedWriteHandle;
 use std::net::IpAddr;
 
 enum AtableChange {
@@ -28,13 +29,16 @@ impl Absorb<AtableChange> for AdjacencyTable {
     }
 }
 
-pub struct AtableWriter(WriteHandle<AdjacencyTable, AtableChange>);
+pub struct AtableWriter(InstrumentedWriteHandle<AdjacencyTable, AtableChange>);
 impl AtableWriter {
     #[must_use]
     pub fn new() -> (AtableWriter, AtableReader) {
         let (w, r) =
             left_right::new_from_empty::<AdjacencyTable, AtableChange>(AdjacencyTable::new());
-        (AtableWriter(w), AtableReader(r))
+        (
+            AtableWriter(InstrumentedWriteHandle::new(w, "adjacencies")),
+            AtableReader(r),
+        )
     }
     pub fn add_adjacency(&mut self, adjacency: Adjacency, publish: bool) {
         self.0.append(AtableChange::Add(adjacency));
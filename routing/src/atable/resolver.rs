@@ -1,52 +1,114 @@
 // SPDX-License-Identifier: Apache-2.0
 // Copyright Open Network Fabric Authors
 
-//! Module to resolve ARP from the /proc. This module only supports ARP (IPv4)
+//! Module to keep the adjacency table in sync with the kernel neighbor tables (ARP and ND),
+//! which are dumped over netlink.
 
 use concurrency::sync::Arc;
 use concurrency::sync::atomic::{AtomicBool, Ordering};
+use concurrency::sync::mpsc::{Receiver, RecvTimeoutError, SyncSender, TrySendError, sync_channel};
 use concurrency::thread;
 use concurrency::thread::JoinHandle;
+use futures_util::TryStreamExt;
+use rtnetlink::packet_route::neighbour::NeighbourFlags;
 use std::net::IpAddr;
-use std::time::Duration;
-
-use netdev::Interface;
-use netdev::get_interfaces;
-use procfs::net::arp;
+use std::time::{Duration, Instant};
 
 use super::adjacency::Adjacency;
 use super::atablerw::AtableReader;
 use crate::atable::atablerw::AtableWriter;
-use net::eth::mac::Mac;
-use net::interface::{InterfaceIndex, InterfaceIndexError};
+use interface_manager::neighbor::Neighbor;
+use net::interface::InterfaceIndex;
+use tokio::runtime::Runtime;
 use tracing::{debug, error, warn};
 
-/// Util that returns the ifindex of the interface with the given name out of the slice of
-/// interfaces provided as argument.
-fn get_interface_ifindex(
-    interfaces: &[Interface],
-    name: &str,
-) -> Result<Option<InterfaceIndex>, InterfaceIndexError> {
-    interfaces
-        .iter()
-        .find_map(|interface| {
-            if interface.name == name {
-                Some(InterfaceIndex::try_new(interface.index))
-            } else {
-                None
+/// A netlink connection to the kernel neighbor tables, usable from a synchronous context
+struct KernelNeighbors {
+    runtime: Runtime,
+    handle: rtnetlink::Handle,
+}
+
+impl KernelNeighbors {
+    fn new() -> std::io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_io()
+            .build()?;
+        let handle = runtime.block_on(async {
+            let (connection, handle, _) = rtnetlink::new_connection()?;
+            tokio::spawn(connection);
+            Ok::<_, std::io::Error>(handle)
+        })?;
+        Ok(Self { runtime, handle })
+    }
+
+    /// Dump the neighbors of all interfaces, for all address families
+    fn dump(&self) -> Result<Vec<Neighbor>, rtnetlink::Error> {
+        self.runtime.block_on(async {
+            let mut neighbors = vec![];
+            let mut req = self.handle.neighbours().get().execute();
+            while let Some(message) = req.try_next().await? {
+                match Neighbor::try_from(&message) {
+                    Ok(neighbor) => neighbors.push(neighbor),
+                    Err(e) => debug!("Ignoring neighbor entry: {e}"),
+                }
             }
+            Ok(neighbors)
         })
-        .transpose()
+    }
+
+    /// Have the kernel resolve `address` over the interface with the given ifindex, as if it had
+    /// packets to send to it (`NTF_USE`). Unless the neighbor is known already, the kernel sends
+    /// an ARP request (or neighbor solicitation), and learns the neighbor if it gets a reply.
+    fn resolve(&self, address: IpAddr, ifindex: InterfaceIndex) {
+        // the state of the entry is left to the kernel when NTF_USE is set
+        let result = self.runtime.block_on(
+            self.handle
+                .neighbours()
+                .add(ifindex.to_u32(), address)
+                .flags(NeighbourFlags::Use)
+                .replace()
+                .execute(),
+        );
+        match result {
+            Ok(()) => debug!("Triggered resolution of {address} over {ifindex}"),
+            Err(e) => warn!("Failed to trigger resolution of {address} over {ifindex}: {e}"),
+        }
+    }
+}
+
+/// Max number of resolution requests that may be outstanding. Requests exceeding it are ignored.
+const MAX_RESOLUTION_REQUESTS: usize = 1024;
+
+/// Time to wait after a resolution is triggered before refreshing the adjacency table
+const REFRESH_AFTER_REQUEST: Duration = Duration::from_millis(100);
+
+/// A handle to request the resolution of an address over some interface to an [`AtResolver`].
+/// Requests never block: if too many are outstanding, they are ignored.
+#[derive(Clone, Debug)]
+pub struct AtResolveRequester(SyncSender<(IpAddr, InterfaceIndex)>);
+impl AtResolveRequester {
+    /// Request the resolution of `address` over the interface with the given ifindex.
+    /// The adjacency table will include the adjacency once resolved.
+    pub fn request(&self, address: IpAddr, ifindex: InterfaceIndex) {
+        match self.0.try_send((address, ifindex)) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => debug!("Ignoring resolution request for {address}"),
+            Err(TrySendError::Disconnected(_)) => warn!("Adjacency resolver is gone"),
+        }
+    }
 }
 
-/// An object able to resolve ARP entries and update the adjacency table. The [`AtResolver`]
+/// An object able to resolve ARP / ND entries and update the adjacency table. The [`AtResolver`]
 /// object can be started / stopped and provides read access to an adjacency table via an
-/// [`AtableReader`] object.
+/// [`AtableReader`] object. Besides polling the kernel neighbor tables periodically, the resolver
+/// triggers the resolution of the addresses requested via [`AtResolveRequester`]s.
 pub struct AtResolver {
     run: Arc<AtomicBool>,
-    handle: Option<JoinHandle<AtableWriter>>,
+    handle: Option<JoinHandle<(AtableWriter, Receiver<(IpAddr, InterfaceIndex)>)>>,
     atablew: Option<AtableWriter>,
     atabler: AtableReader,
+    requests: Option<Receiver<(IpAddr, InterfaceIndex)>>,
+    requester: AtResolveRequester,
 }
 
 impl AtResolver {
@@ -55,11 +117,14 @@ impl AtResolver {
     #[must_use]
     pub fn new(run: bool) -> (Self, AtableReader) {
         let (atablew, atabler) = AtableWriter::new();
+        let (sender, requests) = sync_channel(MAX_RESOLUTION_REQUESTS);
         let resolver = Self {
             run: Arc::new(AtomicBool::new(run)),
             handle: None,
             atablew: Some(atablew),
             atabler: atabler.clone(),
+            requests: Some(requests),
+            requester: AtResolveRequester(sender),
         };
         (resolver, atabler)
     }
@@ -67,18 +132,42 @@ impl AtResolver {
     /// Start the adjacency resolver
     pub fn start(&mut self, poll_period: u64) {
         self.run.store(true, Ordering::Relaxed);
-        let Some(mut atablew) = self.atablew.take() else {
+        let (Some(mut atablew), Some(requests)) = (self.atablew.take(), self.requests.take())
+        else {
             error!("Fatal: can't start resolver; no table accessor");
             return;
         };
 
         let run = self.run.clone();
+        let poll_period = Duration::from_secs(poll_period);
         let handle = thread::spawn(move || {
+            let neighbors = match KernelNeighbors::new() {
+                Ok(neighbors) => neighbors,
+                Err(e) => {
+                    error!("Fatal: can't start resolver; failed to open netlink connection: {e}");
+                    return (atablew, requests);
+                }
+            };
+            let mut next_refresh = Instant::now();
             while run.load(Ordering::Relaxed) {
-                AtResolver::refresh_atable_from_proc(&mut atablew);
-                thread::sleep(Duration::from_secs(poll_period));
+                if Instant::now() >= next_refresh {
+                    AtResolver::refresh_atable(&mut atablew, &neighbors);
+                    next_refresh = Instant::now() + poll_period;
+                }
+                let timeout = next_refresh.saturating_duration_since(Instant::now());
+                match requests.recv_timeout(timeout) {
+                    Ok((address, ifindex)) => {
+                        neighbors.resolve(address, ifindex);
+                        for (address, ifindex) in requests.try_iter() {
+                            neighbors.resolve(address, ifindex);
+                        }
+                        next_refresh = next_refresh.min(Instant::now() + REFRESH_AFTER_REQUEST);
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => thread::sleep(timeout),
+                }
             }
-            atablew
+            (atablew, requests)
         });
         self.handle = Some(handle);
     }
@@ -89,8 +178,9 @@ impl AtResolver {
         if let Some(handle) = handle {
             debug!("Stopping adjacency resolver...");
             self.run.store(false, Ordering::Relaxed);
-            if let Ok(w) = handle.join() {
+            if let Ok((w, requests)) = handle.join() {
                 self.atablew = Some(w);
+                self.requests = Some(requests);
             }
         }
     }
@@ -101,50 +191,32 @@ impl AtResolver {
         self.atabler.clone()
     }
 
-    /// Loads arp table from /proc and the kernel interfaces and
-    /// uses the adjacency table writer to update the adjacency table
-    /// associated with the [`AtableWriter`].
-    fn refresh_atable_from_proc(atablew: &mut AtableWriter) {
-        // load kernel interface information
-        let interfaces = get_interfaces();
-
-        // Collect arp entries by loading arp table from /proc and using the
-        // interfaces vector just retrieved to resolve interface name to ifindex.
-        // Todo: interface name resolution could alternatively be done with ifname_to_index
-        // using the libc wrappers.
-        if let Ok(arptable) = arp() {
-            let adjs = arptable.iter().filter_map(|entry| {
-                if let Some(mac) = entry.hw_address {
-                    match get_interface_ifindex(&interfaces, &entry.device) {
-                        Ok(Some(ifindex)) => {
-                            let adj = Adjacency::new(
-                                IpAddr::V4(entry.ip_address),
-                                ifindex,
-                                Mac::from(mac),
-                            );
-                            Some(adj)
-                        }
-                        Ok(None) => {
-                            warn!("Unable to find Ifindex of {0}", entry.device);
-                            None
-                        }
-                        Err(e) => {
-                            error!("error refreshing ARP/ND table: {e}");
-                            None
-                        }
-                    }
-                } else {
-                    None
-                }
-            });
-
-            /* clear the table and add the known entries */
-            atablew.clear(false);
-            adjs.into_iter()
-                .map(|adj| atablew.add_adjacency(adj, false))
-                .count();
-            atablew.publish();
-        }
+    /// Get a handle to request address resolutions to this resolver
+    #[must_use]
+    pub fn get_requester(&self) -> AtResolveRequester {
+        self.requester.clone()
+    }
+
+    /// Dumps the kernel neighbor tables and uses the adjacency table writer to replace the
+    /// adjacency table associated with the [`AtableWriter`] with the resolved neighbors.
+    /// The table is left as is if the dump fails.
+    fn refresh_atable(atablew: &mut AtableWriter, kernel: &KernelNeighbors) {
+        let neighbors = match kernel.dump() {
+            Ok(neighbors) => neighbors,
+            Err(e) => {
+                error!("error refreshing ARP/ND table: {e}");
+                return;
+            }
+        };
+        let adjs = neighbors.into_iter().filter_map(|neighbor| {
+            let mac = neighbor.mac.filter(|_| neighbor.is_resolved())?;
+            Some(Adjacency::new(neighbor.address, neighbor.ifindex, mac))
+        });
+
+        /* clear the table and add the known entries */
+        atablew.clear(false);
+        adjs.map(|adj| atablew.add_adjacency(adj, false)).count();
+        atablew.publish();
     }
 }
 
@@ -169,7 +241,7 @@ pub mod tests {
     #[test]
     #[cfg_attr(
         emulated,
-        ignore = "reads /proc/net/arp and queries kernel interfaces, neither available under miri and not reliable under qemu"
+        ignore = "dumps the kernel neighbor tables over netlink, neither available under miri nor reliable under qemu"
     )]
     fn test_adjacency_resolver() {
         let (mut resolver, atabler) = AtResolver::new(true);
@@ -4,6 +4,9 @@
 //! BMP message handlers to update internal `DataplaneStatus` model.
 
 use netgauze_bgp_pkt::BgpMessage;
+use netgauze_bgp_pkt::nlri::RouteDistinguisher;
+use netgauze_bgp_pkt::path_attribute::{MpReach, MpUnreach, PathAttributeValue};
+use netgauze_bgp_pkt::update::BgpUpdateMessage;
 use netgauze_bmp_pkt::v3::{
     BmpMessageValue, MirroredBgpMessage, PeerDownNotificationMessage, PeerUpNotificationMessage,
     RouteMirroringMessage, RouteMirroringValue, RouteMonitoringMessage, StatisticsReportMessage,
@@ -20,6 +23,10 @@ use std::time::Duration;
 use tokio::sync::RwLock;
 
 use crate::RouterCtlSender;
+use crate::policy::learned::BgpRouteUpdate;
+use crate::rib::vrf::{Vrf, VrfId};
+use config::external::community_classes::BgpCommunity;
+use lpm::prefix::Prefix;
 use netgauze_bmp_pkt::iana::PeerDownReasonCode;
 use tracing::{debug, error, info};
 
@@ -92,7 +99,15 @@ pub async fn handle_bmp_message(
                     }
                 }
             }
-            BmpMessageValue::RouteMonitoring(rm) => on_route_monitoring(&mut status_guard, rm),
+            BmpMessageValue::RouteMonitoring(rm) => {
+                let update = on_route_monitoring(&mut status_guard, rm);
+                if let Some(update) = update {
+                    drop(status_guard); // release lock before sending update
+                    if let Err(e) = rtr_ctl.send_bgp_routes(update).await {
+                        error!("Failed to send bgp routes on route monitoring: {e}");
+                    }
+                }
+            }
             BmpMessageValue::StatisticsReport(sr) => on_statistics(&mut status_guard, sr),
             BmpMessageValue::RouteMirroring(rm) => on_route_mirroring(&mut status_guard, rm),
             _ => {}
@@ -120,7 +135,7 @@ fn is_real_bgp_neighbor(pt: BmpPeerType) -> bool {
 /// Format:
 /// `<peer_type>-<bgp_id>-<peer_as>-<addr|-none>-<rd|-no-rd>`
 #[allow(clippy::map_unwrap_or)]
-fn key_from_peer_header(peer: &netgauze_bmp_pkt::PeerHeader) -> String {
+pub(crate) fn key_from_peer_header(peer: &netgauze_bmp_pkt::PeerHeader) -> String {
     peer.address()
         .map(|a| a.to_string())
         .unwrap_or_else(|| peer.bgp_id().to_string())
@@ -137,6 +152,23 @@ fn vrf_from_peer_header(_peer: &netgauze_bmp_pkt::PeerHeader) -> String {
     "default".to_string()
 }
 
+/// The id of the VRF of a peer. FRR reports the peers of the default VRF as global instance
+/// peers and those of other VRFs as RD instance peers, whose peer distinguisher is the RD of
+/// the VRF if configured and `0:<vrf-id>` otherwise. We don't configure RDs, so any other
+/// distinguisher can't be mapped to a VRF.
+pub(crate) fn vrfid_from_peer_header(peer: &netgauze_bmp_pkt::PeerHeader) -> Option<VrfId> {
+    match (peer.peer_type(), peer.rd()) {
+        (BmpPeerType::GlobalInstancePeer { .. } | BmpPeerType::LocalInstancePeer { .. }, _) => {
+            Some(Vrf::DEFAULT_VRFID)
+        }
+        (
+            BmpPeerType::RdInstancePeer { .. },
+            Some(RouteDistinguisher::As2Administrator { asn2: 0, number }),
+        ) => Some(number),
+        _ => None,
+    }
+}
+
 fn set_neighbor_session_state(n: &mut BgpNeighborStatus, st: BgpNeighborSessionState) {
     n.session_state = st;
 }
@@ -379,14 +411,74 @@ fn update_msg_counts_from_neigh(msgs: &mut BgpMessages, rm: &RouteMirroringMessa
     }
 }
 
-fn on_route_monitoring(status: &mut DataplaneStatus, rm: &RouteMonitoringMessage) {
+/// Extract the unicast routes announced and withdrawn in a BGP UPDATE of a peer of VRF `vrfid`,
+/// along with the communities of the announced ones. IPv4 routes may be carried in the UPDATE
+/// itself or in the `MP_REACH` / `MP_UNREACH` attributes, IPv6 ones only in the latter.
+pub(crate) fn route_update_from_bgp_update(
+    peer_key: String,
+    vrfid: VrfId,
+    update: &BgpUpdateMessage,
+) -> BgpRouteUpdate {
+    let mut announced: Vec<Prefix> = update
+        .nlri()
+        .iter()
+        .map(|nlri| Prefix::from(nlri.network().address()))
+        .collect();
+    let mut withdrawn: Vec<Prefix> = update
+        .withdraw_routes()
+        .iter()
+        .map(|route| Prefix::from(route.network().address()))
+        .collect();
+    let mut communities = vec![];
+    for attr in update.path_attributes() {
+        match attr.value() {
+            PathAttributeValue::Communities(attr_communities) => communities.extend(
+                attr_communities
+                    .communities()
+                    .iter()
+                    .map(|community| BgpCommunity::from_u32(community.value())),
+            ),
+            PathAttributeValue::MpReach(MpReach::Ipv4Unicast { nlri, .. }) => {
+                announced.extend(
+                    nlri.iter()
+                        .map(|nlri| Prefix::from(nlri.network().address())),
+                );
+            }
+            PathAttributeValue::MpReach(MpReach::Ipv6Unicast { nlri, .. }) => {
+                announced.extend(
+                    nlri.iter()
+                        .map(|nlri| Prefix::from(nlri.network().address())),
+                );
+            }
+            PathAttributeValue::MpUnreach(MpUnreach::Ipv4Unicast { nlri, .. }) => {
+                withdrawn.extend(
+                    nlri.iter()
+                        .map(|nlri| Prefix::from(nlri.network().address())),
+                );
+            }
+            PathAttributeValue::MpUnreach(MpUnreach::Ipv6Unicast { nlri, .. }) => {
+                withdrawn.extend(
+                    nlri.iter()
+                        .map(|nlri| Prefix::from(nlri.network().address())),
+                );
+            }
+            _ => {}
+        }
+    }
+    BgpRouteUpdate::new(peer_key, vrfid, announced, communities, withdrawn)
+}
+
+fn on_route_monitoring(
+    status: &mut DataplaneStatus,
+    rm: &RouteMonitoringMessage,
+) -> Option<BgpRouteUpdate> {
     let peer = rm.peer_header();
     if !is_real_bgp_neighbor(peer.peer_type()) {
-        return;
+        return None;
     }
     let post = post_policy_from_peer_type(peer.peer_type());
     if post {
-        return;
+        return None;
     }
 
     let vrf = vrf_from_peer_header(peer);
@@ -414,6 +506,18 @@ fn on_route_monitoring(status: &mut DataplaneStatus, rm: &RouteMonitoringMessage
         "BMP: route-monitoring vrf={} key={} post_policy={} ipv4_received={} ipv4_received_pre={}",
         vrf, key, post, pref.received, pref.received_pre_policy,
     );
+
+    // routes and communities, for the classification of prefixes in policy classes
+    let Some(vrfid) = vrfid_from_peer_header(peer) else {
+        debug!("BMP: ignoring routes of peer {key}: unknown vrf");
+        return None;
+    };
+    match rm.update_message() {
+        BgpMessage::Update(update) => {
+            Some(route_update_from_bgp_update(key, vrfid, update)).filter(|u| !u.is_empty())
+        }
+        _ => None,
+    }
 }
 
 fn on_route_mirroring(status: &mut DataplaneStatus, rm: &RouteMirroringMessage) {
@@ -4,9 +4,11 @@
 pub mod bmp_render;
 pub mod handler;
 pub mod server;
+pub mod store;
 
 use crate::RouterCtlSender;
 pub use server::{BmpServer, BmpServerConfig};
+pub use store::{BmpStore, BmpStoreConfig};
 
 use concurrency::sync::Arc;
 use config::internal::status::DataplaneStatus;
@@ -19,7 +21,8 @@ use tracectl::trace_target;
 trace_target!("bmp", LevelFilter::INFO, &[]);
 
 /// Spawn the BMP server on `handle`, tracked under `mgmt` so it drains
-/// with the rest of mgmt's tasks.
+/// with the rest of mgmt's tasks. The route monitoring messages received
+/// are kept in `store`, if any.
 #[must_use]
 pub fn spawn_bmp_server(
     mgmt: &Subsystem,
@@ -27,6 +30,7 @@ pub fn spawn_bmp_server(
     bind: std::net::SocketAddr,
     dp_status: Arc<RwLock<DataplaneStatus>>,
     rtr_ctl: RouterCtlSender,
+    store: Option<Arc<BmpStore>>,
 ) -> JoinHandle<()> {
     let cancel = mgmt.cancel_token();
     let fut = async move {
@@ -35,7 +39,10 @@ pub fn spawn_bmp_server(
             bind_addr: bind,
             ..Default::default()
         };
-        let srv = BmpServer::new(cfg, handler::StatusHandler::new(dp_status, rtr_ctl));
+        let mut srv = BmpServer::new(cfg, handler::StatusHandler::new(dp_status, rtr_ctl));
+        if let Some(store) = store {
+            srv = srv.with_store(store);
+        }
         tokio::select! {
             () = cancel.cancelled() => {
                 info!("BMP server shutdown requested");
@@ -6,6 +6,7 @@
 //! - Decodes BMP frames using `BmpCodec`
 //! - On decode error: discards one BMP frame (best-effort resync) and continues
 //!   so FRR doesn't see "connection reset by peer".
+//! - Optionally keeps the route monitoring messages decoded in a [`BmpStore`].
 
 use anyhow::{Context, Result};
 use bytes::{Buf, BytesMut};
@@ -19,6 +20,7 @@ use tokio_util::codec::Decoder;
 use tracing::{debug, info, warn};
 
 use crate::bmp::handler::BmpHandler;
+use crate::bmp::store::{BmpRecord, BmpStore, route_monitoring_frame};
 
 #[derive(Clone, Debug)]
 pub struct BmpServerConfig {
@@ -44,6 +46,7 @@ impl Default for BmpServerConfig {
 pub struct BmpServer<H: BmpHandler> {
     cfg: BmpServerConfig,
     handler: Arc<H>,
+    store: Option<Arc<BmpStore>>,
 }
 
 impl<H: BmpHandler> BmpServer<H> {
@@ -52,9 +55,17 @@ impl<H: BmpHandler> BmpServer<H> {
         Self {
             cfg,
             handler: Arc::new(handler),
+            store: None,
         }
     }
 
+    /// Keep the route monitoring messages received in `store`
+    #[must_use]
+    pub fn with_store(mut self, store: Arc<BmpStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
     pub async fn run(self) -> Result<()> {
         let listener = TcpListener::bind(self.cfg.bind_addr)
             .await
@@ -76,8 +87,9 @@ impl<H: BmpHandler> BmpServer<H> {
             active = active.saturating_add(1);
             let cfg = self.cfg.clone();
             let handler = Arc::clone(&self.handler);
+            let store = self.store.clone();
 
-            tasks.spawn(async move { handle_peer(sock, peer, cfg, handler).await });
+            tasks.spawn(async move { handle_peer(sock, peer, cfg, handler, store).await });
 
             // Reap finished connections (non-blocking)
             while let Some(joined) = tasks.try_join_next() {
@@ -133,6 +145,7 @@ async fn handle_peer<H: BmpHandler>(
     peer: SocketAddr,
     cfg: BmpServerConfig,
     handler: Arc<H>,
+    store: Option<Arc<BmpStore>>,
 ) -> Result<()> {
     if cfg.tcp_nodelay {
         if let Err(e) = sock.set_nodelay(true) {
@@ -158,9 +171,16 @@ async fn handle_peer<H: BmpHandler>(
 
         // Drain as many BMP messages as possible from current buffer
         loop {
+            // the frame is copied before being consumed by the codec, to be kept as received
+            let frame = store.as_ref().and_then(|_| route_monitoring_frame(&buf));
             match codec.decode(&mut buf) {
                 Ok(Some(msg)) => {
                     debug!("BMP: received message from {}: {:?}", peer, msg);
+                    if let (Some(store), Some(frame)) = (&store, frame)
+                        && let Some(record) = BmpRecord::new(peer, frame, &msg)
+                    {
+                        store.push(record);
+                    }
                     handler.on_message(peer, msg).await;
                 }
                 Ok(None) => {
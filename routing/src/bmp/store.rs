@@ -0,0 +1,333 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Bounded store of the recent BMP route monitoring messages, kept for the offline analysis of
+//! BGP churn. The messages are kept as received, in a ring bounded both in number of messages
+//! and in bytes, the oldest being evicted first. They are dumped from the cli either as MRT
+//! (RFC 6396), for the usual BGP analysis tools, or as the routes they announce and withdraw.
+
+use bytes::Bytes;
+use concurrency::sync::Mutex;
+use lpm::prefix::Prefix;
+use netgauze_bgp_pkt::BgpMessage;
+use netgauze_bmp_pkt::BmpMessage;
+use netgauze_bmp_pkt::v3::BmpMessageValue;
+use std::collections::VecDeque;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::bmp::bmp_render::{
+    key_from_peer_header, route_update_from_bgp_update, vrfid_from_peer_header,
+};
+use crate::rib::vrf::Vrf;
+
+/// Version of BMP whose messages are kept
+const BMP_VERSION: u8 = 3;
+/// Type of the BMP route monitoring messages
+const BMP_ROUTE_MONITORING: u8 = 0;
+/// Length of the BMP common header: version, length and type
+const BMP_HEADER_LEN: usize = 6;
+/// Length of the BMP per-peer header following the common header
+const BMP_PEER_HEADER_LEN: usize = 42;
+/// Flags of the per-peer header: the peer address is IPv6, the `AS_PATH` has 2-byte ASes
+const PEER_FLAG_V: u8 = 0x80;
+const PEER_FLAG_A: u8 = 0x20;
+
+/// MRT type and subtypes of BGP messages (RFC 6396, section 4.4)
+const MRT_BGP4MP: u16 = 16;
+const MRT_BGP4MP_MESSAGE: u16 = 1;
+const MRT_BGP4MP_MESSAGE_AS4: u16 = 4;
+/// The AS of a 4-byte AS peer in 2-byte AS fields (RFC 6793)
+const AS_TRANS: u16 = 23456;
+
+/// The bounds of a [`BmpStore`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BmpStoreConfig {
+    /// Max number of messages kept
+    pub max_messages: usize,
+    /// Max number of bytes of the messages kept
+    pub max_bytes: usize,
+}
+
+/// A BMP route monitoring message, as received, with the routes of the BGP UPDATE it carries
+#[derive(Clone, Debug)]
+pub struct BmpRecord {
+    /// When the message was received
+    pub received: SystemTime,
+    /// The BMP speaker which sent the message
+    pub speaker: SocketAddr,
+    /// The BGP neighbor whose UPDATE is monitored
+    pub peer: String,
+    pub announced: Vec<Prefix>,
+    pub withdrawn: Vec<Prefix>,
+    /// The message, BMP common header included
+    pub frame: Bytes,
+}
+
+impl BmpRecord {
+    /// Build the record of a decoded BMP message from its `frame`, if a route monitoring message
+    #[must_use]
+    pub fn new(speaker: SocketAddr, frame: Bytes, msg: &BmpMessage) -> Option<Self> {
+        let BmpMessage::V3(BmpMessageValue::RouteMonitoring(rm)) = msg else {
+            return None;
+        };
+        let peer = key_from_peer_header(rm.peer_header());
+        let vrfid = vrfid_from_peer_header(rm.peer_header()).unwrap_or(Vrf::DEFAULT_VRFID);
+        let (announced, withdrawn) = match rm.update_message() {
+            BgpMessage::Update(update) => {
+                let update = route_update_from_bgp_update(peer.clone(), vrfid, update);
+                (update.announced, update.withdrawn)
+            }
+            _ => (vec![], vec![]),
+        };
+        Some(Self {
+            received: SystemTime::now(),
+            speaker,
+            peer,
+            announced,
+            withdrawn,
+            frame,
+        })
+    }
+
+    /// Append the message as an MRT `BGP4MP` record to `out`. Returns false, and appends
+    /// nothing, if the message is too short to be a route monitoring message.
+    pub fn encode_mrt(&self, out: &mut Vec<u8>) -> bool {
+        let frame = &self.frame[..];
+        if frame.len() <= BMP_HEADER_LEN + BMP_PEER_HEADER_LEN {
+            return false;
+        }
+        let peer_header = &frame[BMP_HEADER_LEN..BMP_HEADER_LEN + BMP_PEER_HEADER_LEN];
+        let bgp_pdu = &frame[BMP_HEADER_LEN + BMP_PEER_HEADER_LEN..];
+        let flags = peer_header[1];
+        let address = &peer_header[10..26];
+        let peer_as = u32::from_be_bytes([
+            peer_header[26],
+            peer_header[27],
+            peer_header[28],
+            peer_header[29],
+        ]);
+        let seconds = u32::from_be_bytes([
+            peer_header[34],
+            peer_header[35],
+            peer_header[36],
+            peer_header[37],
+        ]);
+        // the time the route was monitored at, if the speaker tells, else when it was received
+        let seconds = if seconds == 0 {
+            let since_epoch = self.received.duration_since(UNIX_EPOCH).unwrap_or_default();
+            u32::try_from(since_epoch.as_secs()).unwrap_or(u32::MAX)
+        } else {
+            seconds
+        };
+
+        let mut body = Vec::with_capacity(44 + bgp_pdu.len());
+        let subtype = if flags & PEER_FLAG_A == 0 {
+            body.extend_from_slice(&peer_as.to_be_bytes());
+            body.extend_from_slice(&0u32.to_be_bytes()); // local AS, unknown
+            MRT_BGP4MP_MESSAGE_AS4
+        } else {
+            let peer_as = u16::try_from(peer_as).unwrap_or(AS_TRANS);
+            body.extend_from_slice(&peer_as.to_be_bytes());
+            body.extend_from_slice(&0u16.to_be_bytes()); // local AS, unknown
+            MRT_BGP4MP_MESSAGE
+        };
+        body.extend_from_slice(&0u16.to_be_bytes()); // interface index, unknown
+        if flags & PEER_FLAG_V == 0 {
+            body.extend_from_slice(&1u16.to_be_bytes()); // AFI IPv4
+            body.extend_from_slice(&address[12..]);
+            body.extend_from_slice(&Ipv4Addr::UNSPECIFIED.octets()); // local address, unknown
+        } else {
+            body.extend_from_slice(&2u16.to_be_bytes()); // AFI IPv6
+            body.extend_from_slice(address);
+            body.extend_from_slice(&Ipv6Addr::UNSPECIFIED.octets()); // local address, unknown
+        }
+        body.extend_from_slice(bgp_pdu);
+
+        let Ok(length) = u32::try_from(body.len()) else {
+            return false;
+        };
+        out.extend_from_slice(&seconds.to_be_bytes());
+        out.extend_from_slice(&MRT_BGP4MP.to_be_bytes());
+        out.extend_from_slice(&subtype.to_be_bytes());
+        out.extend_from_slice(&length.to_be_bytes());
+        out.extend_from_slice(&body);
+        true
+    }
+}
+
+/// The frame of the route monitoring message at the start of `buf`, if complete. The frames of
+/// other messages are not copied.
+#[must_use]
+pub fn route_monitoring_frame(buf: &[u8]) -> Option<Bytes> {
+    if buf.len() < BMP_HEADER_LEN
+        || buf[0] != BMP_VERSION
+        || buf[BMP_HEADER_LEN - 1] != BMP_ROUTE_MONITORING
+    {
+        return None;
+    }
+    let length = u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]);
+    let length = usize::try_from(length).ok()?;
+    buf.get(..length).map(Bytes::copy_from_slice)
+}
+
+#[derive(Default)]
+struct Ring {
+    records: VecDeque<BmpRecord>,
+    bytes: usize,
+    evicted: u64,
+}
+
+/// A ring of the recent BMP route monitoring messages
+pub struct BmpStore {
+    config: BmpStoreConfig,
+    ring: Mutex<Ring>,
+}
+
+impl BmpStore {
+    #[must_use]
+    pub fn new(config: BmpStoreConfig) -> Self {
+        Self {
+            config,
+            ring: Mutex::new(Ring::default()),
+        }
+    }
+
+    /// Keep a message, evicting the oldest ones as needed to stay within the bounds. A message
+    /// larger than the byte bound is not kept.
+    pub fn push(&self, record: BmpRecord) {
+        let size = record.frame.len();
+        let mut ring = self.ring.lock();
+        if size > self.config.max_bytes || self.config.max_messages == 0 {
+            ring.evicted += 1;
+            return;
+        }
+        while ring.records.len() >= self.config.max_messages
+            || ring.bytes.saturating_add(size) > self.config.max_bytes
+        {
+            let Some(oldest) = ring.records.pop_front() else {
+                break;
+            };
+            ring.bytes -= oldest.frame.len();
+            ring.evicted += 1;
+        }
+        ring.bytes += size;
+        ring.records.push_back(record);
+    }
+
+    /// The last `count` messages kept, or all of them, oldest first
+    #[must_use]
+    pub fn recent(&self, count: Option<usize>) -> Vec<BmpRecord> {
+        let ring = self.ring.lock();
+        let skip = count.map_or(0, |count| ring.records.len().saturating_sub(count));
+        ring.records.iter().skip(skip).cloned().collect()
+    }
+
+    /// Describe the content of the store
+    #[must_use]
+    pub fn status(&self) -> String {
+        let ring = self.ring.lock();
+        format!(
+            " {} messages, {} bytes kept (max {} messages, {} bytes), {} evicted\n",
+            ring.records.len(),
+            ring.bytes,
+            self.config.max_messages,
+            self.config.max_bytes,
+            ring.evicted
+        )
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A route monitoring message from peer 192.168.1.1 of AS 65001, with a BGP PDU of `pdu_len`
+    /// bytes
+    fn frame(pdu_len: usize) -> Bytes {
+        let length = BMP_HEADER_LEN + BMP_PEER_HEADER_LEN + pdu_len;
+        let mut frame = vec![BMP_VERSION];
+        frame.extend_from_slice(&u32::try_from(length).unwrap().to_be_bytes());
+        frame.push(BMP_ROUTE_MONITORING);
+        frame.extend_from_slice(&[0, 0]); // peer type, flags
+        frame.extend_from_slice(&[0; 8]); // distinguisher
+        frame.extend_from_slice(&[0; 12]);
+        frame.extend_from_slice(&[192, 168, 1, 1]);
+        frame.extend_from_slice(&65001u32.to_be_bytes());
+        frame.extend_from_slice(&[1, 1, 1, 1]); // BGP id
+        frame.extend_from_slice(&1_700_000_000u32.to_be_bytes());
+        frame.extend_from_slice(&[0; 4]);
+        frame.extend_from_slice(&vec![0xff; pdu_len]);
+        Bytes::from(frame)
+    }
+
+    fn record(pdu_len: usize) -> BmpRecord {
+        BmpRecord {
+            received: SystemTime::now(),
+            speaker: "127.0.0.1:40000".parse().unwrap(),
+            peer: "192.168.1.1".to_string(),
+            announced: vec![],
+            withdrawn: vec![],
+            frame: frame(pdu_len),
+        }
+    }
+
+    #[test]
+    fn test_bmp_store_bounds() {
+        let store = BmpStore::new(BmpStoreConfig {
+            max_messages: 3,
+            max_bytes: 300,
+        });
+        for _ in 0..4 {
+            store.push(record(19));
+        }
+        assert_eq!(store.recent(None).len(), 3);
+        assert_eq!(store.recent(Some(2)).len(), 2);
+
+        // 3 messages of 67 bytes and one of 148 exceed 300 bytes
+        store.push(record(100));
+        let recent = store.recent(None);
+        assert_eq!(recent.len(), 3);
+        assert_eq!(recent[2].frame.len(), 148);
+
+        // too large to be kept
+        store.push(record(400));
+        assert_eq!(store.recent(None).len(), 3);
+        assert!(store.status().contains("3 evicted"));
+    }
+
+    #[test]
+    fn test_route_monitoring_frame() {
+        let frame = frame(19);
+        let mut buf = frame.to_vec();
+        buf.extend_from_slice(&[BMP_VERSION, 0, 0]); // start of the next message
+        assert_eq!(route_monitoring_frame(&buf), Some(frame.clone()));
+        assert_eq!(route_monitoring_frame(&frame[..20]), None);
+
+        let mut other = frame.to_vec();
+        other[BMP_HEADER_LEN - 1] = 4; // initiation
+        assert_eq!(route_monitoring_frame(&other), None);
+    }
+
+    #[test]
+    fn test_bmp_record_mrt() {
+        let record = record(19);
+        let mut mrt = vec![];
+        assert!(record.encode_mrt(&mut mrt));
+        assert_eq!(&mrt[..4], &1_700_000_000u32.to_be_bytes());
+        assert_eq!(&mrt[4..8], &[0, 16, 0, 4]); // BGP4MP_MESSAGE_AS4
+        assert_eq!(&mrt[8..12], &(20u32 + 19).to_be_bytes());
+        assert_eq!(&mrt[12..16], &65001u32.to_be_bytes());
+        assert_eq!(&mrt[22..24], &[0, 1]); // AFI IPv4
+        assert_eq!(&mrt[24..28], &[192, 168, 1, 1]);
+        assert_eq!(mrt.len(), 12 + 20 + 19);
+
+        let short = BmpRecord {
+            frame: record.frame.slice(..BMP_HEADER_LEN + BMP_PEER_HEADER_LEN),
+            ..record
+        };
+        let mut mrt = vec![];
+        assert!(!short.encode_mrt(&mut mrt));
+        assert!(mrt.is_empty());
+    }
+}
@@ -0,0 +1,110 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Dump of the recent BMP route monitoring messages kept by the BMP server, from the cli: as an
+//! MRT file written by the dataplane, for the usual BGP analysis tools, or as the routes each
+//! message announces and withdraws, in any of the output formats.
+
+use super::serialize::{render, render_text};
+
+use crate::bmp::store::BmpRecord;
+use crate::router::CliSources;
+
+use chrono::{DateTime, Local};
+use cli::cliproto::{CliError, CliRequest, CliResponse};
+use common::cliprovider::Heading;
+use serde::Serialize;
+use std::fmt::Display;
+
+/// A BMP route monitoring message, as dumped
+#[derive(Serialize)]
+struct BmpMessageView {
+    received: String,
+    speaker: String,
+    peer: String,
+    announced: Vec<String>,
+    withdrawn: Vec<String>,
+}
+
+impl From<&BmpRecord> for BmpMessageView {
+    fn from(record: &BmpRecord) -> Self {
+        let received: DateTime<Local> = record.received.into();
+        Self {
+            received: received.format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+            speaker: record.speaker.to_string(),
+            peer: record.peer.clone(),
+            announced: record.announced.iter().map(ToString::to_string).collect(),
+            withdrawn: record.withdrawn.iter().map(ToString::to_string).collect(),
+        }
+    }
+}
+
+/// The messages dumped, serialized as a list
+#[derive(Serialize)]
+#[serde(transparent)]
+struct BmpDumpView {
+    messages: Vec<BmpMessageView>,
+    #[serde(skip)]
+    status: String,
+}
+
+impl Display for BmpDumpView {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", Heading("BMP route monitoring"), self.status)?;
+        for m in &self.messages {
+            writeln!(
+                f,
+                " {} {:<21} peer {:<39} +{} -{}",
+                m.received,
+                m.speaker,
+                m.peer,
+                m.announced.len(),
+                m.withdrawn.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Write `records` to the MRT file at `path`, returning the number of messages written
+fn write_mrt(path: &str, records: &[BmpRecord]) -> Result<usize, CliError> {
+    let mut mrt = vec![];
+    let written = records
+        .iter()
+        .filter(|record| record.encode_mrt(&mut mrt))
+        .count();
+    std::fs::write(path, mrt)
+        .map_err(|e| CliError::NotSupported(format!("writing MRT file {path}: {e}")))?;
+    Ok(written)
+}
+
+pub(crate) fn dump_bmp(request: CliRequest, sources: &CliSources) -> Result<CliResponse, CliError> {
+    let Some(store) = &sources.bmp_store else {
+        return Err(CliError::NotSupported(
+            "BMP messages are not kept: see --bmp-store-messages and --bmp-store-bytes".to_string(),
+        ));
+    };
+    let count = request
+        .args
+        .count
+        .map(|count| usize::try_from(count).unwrap_or(usize::MAX));
+    let records = store.recent(count);
+    let format = request.args.format;
+    let data = match &request.args.dump_to {
+        Some(path) => {
+            let written = write_mrt(path, &records)?;
+            render_text(
+                format,
+                format!("Wrote {written} BMP route monitoring messages to {path}"),
+            )?
+        }
+        None => render(
+            format,
+            &BmpDumpView {
+                messages: records.iter().map(BmpMessageView::from).collect(),
+                status: store.status(),
+            },
+        )?,
+    };
+    Ok(CliResponse::from_request_ok(request, data))
+}
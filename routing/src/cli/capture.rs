@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Packet capture at the taps of the pipeline, started and stopped from the cli. The taps are
+//! owned by the pipeline and are driven through the [`PacketCapture`] provided with the
+//! [`CliSources`].
+
+use super::session::CliSession;
+use crate::router::CliSources;
+
+use cli::cliproto::{CaptureTarget, CliAction, CliError, CliRequest, CliResponse};
+use common::cliprovider::Heading;
+use lpm::prefix::Prefix;
+use net::packet::VpcDiscriminant;
+use net::vxlan::Vni;
+
+/// A capture, as requested from the cli
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CaptureRequest {
+    /// The tap to capture at, or all of them
+    pub tap: Option<String>,
+    /// The VPC packets come from or go to
+    pub vpcd: Option<VpcDiscriminant>,
+    /// A prefix covering the source or destination address of packets
+    pub prefix: Option<Prefix>,
+    /// The source or destination transport port of packets
+    pub port: Option<u16>,
+    /// The max number of packets to capture
+    pub count: Option<u64>,
+    pub target: CaptureTarget,
+}
+
+/// A type able to capture packets at the taps of the pipeline
+pub trait PacketCapture {
+    /// Start a capture.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a capture is in progress, or if its target can't be written to.
+    fn start(&self, request: CaptureRequest) -> std::io::Result<()>;
+
+    /// Stop the capture in progress, if any, and return its summary.
+    fn stop(&self) -> Option<String>;
+
+    /// Describe the capture in progress.
+    fn status(&self) -> String;
+}
+
+fn capture_from_request(request: &CliRequest) -> Result<CaptureRequest, CliError> {
+    let args = &request.args;
+    let Some(target) = args.capture_to.clone() else {
+        return Err(CliError::NotFound("pcapng or stream argument".to_string()));
+    };
+    let vpcd = args
+        .vni
+        .map(|vni| {
+            Vni::new_checked(vni)
+                .map(VpcDiscriminant::from_vni)
+                .map_err(|_| CliError::NotFound(format!("Invalid vni value: {vni}")))
+        })
+        .transpose()?;
+    let prefix = match (args.prefix, args.address) {
+        (Some(prefix), _) => Some(Prefix::try_from(prefix).map_err(|e| {
+            CliError::NotSupported(format!("prefix {}/{}: {e}", prefix.0, prefix.1))
+        })?),
+        (None, Some(address)) => Some(Prefix::from(address)),
+        (None, None) => None,
+    };
+    Ok(CaptureRequest {
+        tap: args.tap.clone(),
+        vpcd,
+        prefix,
+        port: args.port,
+        count: args.count,
+        target,
+    })
+}
+
+pub(crate) fn handle_capture(
+    request: CliRequest,
+    sources: &CliSources,
+    session: &CliSession,
+) -> Result<CliResponse, CliError> {
+    // captures see all the traffic and write on the host: only root and administrators may
+    // start or stop them
+    if request.action != CliAction::ShowCapture && !session.is_admin() {
+        return Err(CliError::Forbidden(
+            "packet captures are reserved to administrators".to_string(),
+        ));
+    }
+    let Some(capture) = &sources.packet_capture else {
+        return Err(CliError::NotSupported(
+            "packet capture is not available".to_string(),
+        ));
+    };
+    let data = match request.action {
+        CliAction::StartCapture => {
+            capture
+                .start(capture_from_request(&request)?)
+                .map_err(|e| CliError::NotSupported(format!("starting the capture: {e}")))?;
+            "Capture started".to_string()
+        }
+        CliAction::StopCapture => capture.stop().map_or_else(
+            || "No capture in progress".to_string(),
+            |summary| format!("Stopped {}", summary.trim_start()),
+        ),
+        CliAction::ShowCapture => Heading("Packet capture").to_string() + &capture.status(),
+        _ => unreachable!(),
+    };
+    Ok(CliResponse::from_request_ok(request, data))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cli::cliproto::RequestArgs;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_capture_from_request() {
+        let args = RequestArgs {
+            vni: Some(3000),
+            address: Some("10.0.0.1".parse().unwrap()),
+            port: Some(443),
+            ..RequestArgs::default()
+        };
+        let request = CliRequest::new(CliAction::StartCapture, args.clone());
+        assert!(capture_from_request(&request).is_err());
+
+        let args = RequestArgs {
+            capture_to: Some(CaptureTarget::File("capture.pcapng".to_string())),
+            ..args
+        };
+        let request = CliRequest::new(CliAction::StartCapture, args);
+        let capture = capture_from_request(&request).unwrap();
+        assert_eq!(
+            capture.vpcd,
+            Some(VpcDiscriminant::from_vni(Vni::new_checked(3000).unwrap()))
+        );
+        assert_eq!(
+            capture.prefix,
+            Some(Prefix::from_str("10.0.0.1/32").unwrap())
+        );
+        assert_eq!(capture.port, Some(443));
+    }
+}
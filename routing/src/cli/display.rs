@@ -17,6 +17,7 @@ use crate::fib::fibgroupstore::FibRoute;
 use crate::fib::fibobjects::{EgressObject, FibEntry, FibGroup, PktInstruction};
 use crate::fib::fibtype::{Fib, FibKey};
 use crate::frr::frrmi::{FrrAppliedConfig, Frrmi, FrrmiStats};
+use crate::router::bulk::{BulkSync, BulkSyncState};
 use crate::router::cpi::{CpiStats, CpiStatus, StatsRow};
 
 use crate::rib::VrfTable;
@@ -466,6 +467,30 @@ impl Display for VrfTable {
     }
 }
 
+/// A view of the [`Vrf`]s of a [`VrfTable`] for which the filter holds
+pub struct VrfTableView<'a, F>
+where
+    F: Fn(&Vrf) -> bool,
+{
+    pub vrftable: &'a VrfTable,
+    pub filter: &'a F,
+}
+impl<F: Fn(&Vrf) -> bool> Display for VrfTableView<'_, F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let vrfs: Vec<&Vrf> = self
+            .vrftable
+            .values()
+            .filter(|vrf| (self.filter)(vrf))
+            .collect();
+        Heading(format!("VRFs ({})", vrfs.len())).fmt(f)?;
+        fmt_vrf_summary_heading(f)?;
+        for vrf in vrfs {
+            fmt_vrf_summary(f, vrf)?;
+        }
+        Ok(())
+    }
+}
+
 //========================= Interfaces ================================//
 
 impl Display for Attachment {
@@ -479,7 +504,7 @@ impl Display for Attachment {
 
 macro_rules! INTERFACE_TBL_FMT {
     () => {
-        " {:<16} {:>4} {:>6} {:9} {:9} {:<20} {}"
+        " {:<16} {:>4} {:>6} {:9} {:9} {:<6} {:<20} {}"
     };
 }
 fn fmt_interface_heading(f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
@@ -488,7 +513,7 @@ fn fmt_interface_heading(f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         "{}",
         format_args!(
             INTERFACE_TBL_FMT!(),
-            "name", "id", "mtu", "AdmStatus", "OpStatus", "attachment", "type"
+            "name", "id", "mtu", "AdmStatus", "OpStatus", "uRPF", "attachment", "type"
         )
     )
 }
@@ -551,6 +576,7 @@ impl Display for Interface {
                 mtu,
                 self.admin_state,
                 self.oper_state,
+                self.urpf.to_string(),
                 attachment,
                 self.iftype,
             )
@@ -971,6 +997,8 @@ impl Display for CpiStatus {
             CpiStatus::Connected => write!(f, "Connected"),
             CpiStatus::FrrRestarted => write!(f, "Frr-restarted"),
             CpiStatus::NeedRefresh => write!(f, "Need refresh"),
+            CpiStatus::Disconnected => write!(f, "Disconnected"),
+            CpiStatus::Resumed => write!(f, "Resumed"),
         }
     }
 }
@@ -994,6 +1022,10 @@ impl Display for CpiStats {
         writeln!(f, " STATUS: {}", self.status)?;
         writeln!(f, " last connect: {connect_t} pid: {pid} peer: {peer}")?;
         writeln!(f, " last msg rx : {last_msg_rx_t}")?;
+        if let Some(requested) = &self.refresh_requested {
+            let ago = requested.elapsed().as_secs();
+            writeln!(f, " refresh requested: {ago} seconds ago (not acked)")?;
+        }
         writeln!(f, " decode failures: {}", self.decode_failures)?;
         writeln!(f, " ctl/keepalives : {}", self.control_rx)?;
         writeln!(f)?;
@@ -1013,6 +1045,30 @@ impl Display for CpiStats {
     }
 }
 
+impl Display for BulkSync {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, " Initial route download: ")?;
+        match self.state {
+            BulkSyncState::Waiting => writeln!(f, "waiting for FRR"),
+            BulkSyncState::Syncing => writeln!(
+                f,
+                "in progress: {} routes, {} published in {} chunks, for {}s",
+                self.routes,
+                self.routes - self.staged,
+                self.chunks,
+                self.started.map_or(0, |t| t.elapsed().as_secs())
+            ),
+            BulkSyncState::Complete => writeln!(
+                f,
+                "complete: {} routes in {:.1}s ({} chunks)",
+                self.routes,
+                self.duration.unwrap_or_default().as_secs_f64(),
+                self.chunks
+            ),
+        }
+    }
+}
+
 //========================= Frrmi ================================//
 impl Display for FrrmiStats {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
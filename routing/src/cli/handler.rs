@@ -5,9 +5,15 @@
 
 #![allow(clippy::unnecessary_wraps)]
 
+use super::bmp::dump_bmp;
+use super::capture::handle_capture;
 use super::display::IfTableAddress;
+use super::display::VrfTableView;
 use super::display::{FibGroups, FibViewV4, FibViewV6};
 use super::display::{VrfV4Nexthops, VrfV6Nexthops, VrfViewV4, VrfViewV6};
+use super::serialize::{VrfSummary, render, render_all, render_text};
+use super::session::{CliSession, VpcScope};
+use super::simulate::simulate_packet;
 
 use crate::fib::fibtype::{FibRouteV4Filter, FibRouteV6Filter};
 use crate::rib::vrf::{Route, RouteOrigin, Vrf, VrfId};
@@ -20,16 +26,23 @@ use crate::router::revent::ROUTER_EVENTS;
 use crate::router::rio::Rio;
 use crate::routingdb::RoutingDb;
 
-use chrono::Local;
-use cli::cliproto::{CliAction, CliError, CliRequest, CliResponse, RequestArgs, RouteProtocol};
+use chrono::{DateTime, Local};
+use cli::cliproto::{
+    CliAction, CliError, CliFormat, CliPeer, CliRequest, CliResponse, FlagValue, RequestArgs,
+    RouteProtocol,
+};
 use concurrency::sync::Arc;
 use config::{ConfigSummary, GwConfigMeta, ValidatedGwConfig};
+use kvstore::{KvError, ValueDisplay};
+use lifecycle::crash::{CrashReportError, crash_reports};
 use lpm::prefix::{Ipv4Prefix, Ipv6Prefix};
 use net::vxlan::Vni;
-use std::os::unix::net::SocketAddr;
 
 use common::cliprovider::{CliDataProvider, Heading};
+use common::flags::{self, FlagTable};
+use common::generation::{Generational, TableGeneration};
 use strum::IntoEnumIterator;
+use tokio::sync::mpsc::error::TrySendError;
 
 #[allow(unused)]
 use tracing::{error, trace};
@@ -50,75 +63,92 @@ impl From<&RouteProtocol> for RouteOrigin {
     }
 }
 
-fn show_vrf_ipv4_routes(vrf: &Vrf, filter: &RouteV4Filter) -> String {
+fn show_vrf_ipv4_routes<'a>(
+    vrf: &'a Vrf,
+    filter: &'a RouteV4Filter,
+) -> VrfViewV4<'a, RouteV4Filter> {
     /* This builds a view of the vrf, with only IPv4 routes
       and maybe not all of them, depending on the filter.
-      If other serializations are needed, here we could either build also
-      the view and implement serde on the view.
-      Alternatively, call vrf.iter_v4() or vrf.filter_v4() to yield
-      iterators over the (prefix, Routes).
+      The view is displayed as a table or serialized, depending on the
+      format requested.
     */
+    VrfViewV4 { vrf, filter }
+}
+
+fn show_vrf_ipv6_routes<'a>(
+    vrf: &'a Vrf,
+    filter: &'a RouteV6Filter,
+) -> VrfViewV6<'a, RouteV6Filter> {
+    VrfViewV6 { vrf, filter }
+}
 
-    let view = VrfViewV4 { vrf, filter };
-    format!("{view}")
+/// The VRF with the given id, if visible to the session
+fn visible_vrf<'a>(vrftable: &'a VrfTable, vrfid: VrfId, session: &CliSession) -> Option<&'a Vrf> {
+    vrftable
+        .get_vrf(vrfid)
+        .ok()
+        .filter(|vrf| session.sees_vrf(vrf))
 }
 
-fn show_vrf_ipv6_routes(vrf: &Vrf, filter: &RouteV6Filter) -> String {
-    let view = VrfViewV6 { vrf, filter };
-    format!("{view}")
+/// The VRFs visible to the session
+fn visible_vrfs<'a>(
+    vrftable: &'a VrfTable,
+    session: &'a CliSession,
+) -> impl Iterator<Item = &'a Vrf> {
+    vrftable.values().filter(|vrf| session.sees_vrf(vrf))
 }
 
 fn show_ipv4_routes_single_vrf(
     request: CliRequest,
     vrftable: &VrfTable,
+    session: &CliSession,
     vrfid: VrfId,
     filter: &RouteV4Filter,
 ) -> Result<CliResponse, CliError> {
-    let out;
-    if let Ok(vrf) = vrftable.get_vrf(vrfid) {
-        out = show_vrf_ipv4_routes(vrf, filter);
-    } else {
+    let Some(vrf) = visible_vrf(vrftable, vrfid, session) else {
         return Err(CliError::NotFound(format!("VRF with id {vrfid}")));
-    }
+    };
+    let out = render(request.args.format, &show_vrf_ipv4_routes(vrf, filter))?;
     Ok(CliResponse::from_request_ok(request, out))
 }
 
 fn show_ipv4_routes_multi(
     request: CliRequest,
     vrftable: &VrfTable,
+    session: &CliSession,
     filter: &RouteV4Filter,
 ) -> Result<CliResponse, CliError> {
-    let mut out = String::new();
-    for vrf in vrftable.values() {
-        out += show_vrf_ipv4_routes(vrf, filter).as_str();
-    }
+    let views: Vec<_> = visible_vrfs(vrftable, session)
+        .map(|vrf| show_vrf_ipv4_routes(vrf, filter))
+        .collect();
+    let out = render_all(request.args.format, &views)?;
     Ok(CliResponse::from_request_ok(request, out))
 }
 
 fn show_ipv6_routes_single_vrf(
     request: CliRequest,
     vrftable: &VrfTable,
+    session: &CliSession,
     vrfid: VrfId,
     filter: &RouteV6Filter,
 ) -> Result<CliResponse, CliError> {
-    let out;
-    if let Ok(vrf) = vrftable.get_vrf(vrfid) {
-        out = show_vrf_ipv6_routes(vrf, filter);
-    } else {
+    let Some(vrf) = visible_vrf(vrftable, vrfid, session) else {
         return Err(CliError::NotFound(format!("VRF with id {vrfid}")));
-    }
+    };
+    let out = render(request.args.format, &show_vrf_ipv6_routes(vrf, filter))?;
     Ok(CliResponse::from_request_ok(request, out))
 }
 
 fn show_ipv6_routes_multi(
     request: CliRequest,
     vrftable: &VrfTable,
+    session: &CliSession,
     filter: &RouteV6Filter,
 ) -> Result<CliResponse, CliError> {
-    let mut out = String::new();
-    for vrf in vrftable.values() {
-        out += show_vrf_ipv6_routes(vrf, filter).as_str();
-    }
+    let views: Vec<_> = visible_vrfs(vrftable, session)
+        .map(|vrf| show_vrf_ipv6_routes(vrf, filter))
+        .collect();
+    let out = render_all(request.args.format, &views)?;
     Ok(CliResponse::from_request_ok(request, out))
 }
 
@@ -143,6 +173,7 @@ fn route_filter_v6(request: &CliRequest) -> RouteV6Filter {
 fn show_vrf_routes(
     request: CliRequest,
     db: &RoutingDb,
+    session: &CliSession,
     ipv4: bool,
 ) -> Result<CliResponse, CliError> {
     let vrftable = &db.vrftable;
@@ -150,16 +181,16 @@ fn show_vrf_routes(
     if ipv4 {
         let filter = route_filter_v4(&request);
         if let Some(vrfid) = request.args.vrfid {
-            show_ipv4_routes_single_vrf(request, vrftable, vrfid, &filter)
+            show_ipv4_routes_single_vrf(request, vrftable, session, vrfid, &filter)
         } else {
-            show_ipv4_routes_multi(request, vrftable, &filter)
+            show_ipv4_routes_multi(request, vrftable, session, &filter)
         }
     } else {
         let filter = route_filter_v6(&request);
         if let Some(vrfid) = request.args.vrfid {
-            show_ipv6_routes_single_vrf(request, vrftable, vrfid, &filter)
+            show_ipv6_routes_single_vrf(request, vrftable, session, vrfid, &filter)
         } else {
-            show_ipv6_routes_multi(request, vrftable, &filter)
+            show_ipv6_routes_multi(request, vrftable, session, &filter)
         }
     }
 }
@@ -167,11 +198,12 @@ fn show_vrf_routes(
 fn show_vrf_nexthops_single(
     request: CliRequest,
     vrftable: &VrfTable,
+    session: &CliSession,
     vrfid: VrfId,
     ipv4: bool,
 ) -> Result<CliResponse, CliError> {
     let out: String;
-    if let Ok(vrf) = vrftable.get_vrf(vrfid) {
+    if let Some(vrf) = visible_vrf(vrftable, vrfid, session) {
         if ipv4 {
             out = format!("{}", VrfV4Nexthops(vrf));
         } else {
@@ -186,10 +218,11 @@ fn show_vrf_nexthops_single(
 fn show_vrf_nexthops_multi(
     request: CliRequest,
     vrftable: &VrfTable,
+    session: &CliSession,
     ipv4: bool,
 ) -> Result<CliResponse, CliError> {
     let mut out = String::new();
-    for vrf in vrftable.values() {
+    for vrf in visible_vrfs(vrftable, session) {
         if ipv4 {
             out += format!("{}", VrfV4Nexthops(vrf)).as_ref();
         } else {
@@ -202,43 +235,60 @@ fn show_vrf_nexthops_multi(
 fn show_vrf_nexthops(
     request: CliRequest,
     db: &RoutingDb,
+    session: &CliSession,
     ipv4: bool,
 ) -> Result<CliResponse, CliError> {
     let vrftable = &db.vrftable;
 
     if let Some(vrfid) = request.args.vrfid {
-        show_vrf_nexthops_single(request, vrftable, vrfid, ipv4)
+        show_vrf_nexthops_single(request, vrftable, session, vrfid, ipv4)
     } else {
-        show_vrf_nexthops_multi(request, vrftable, ipv4)
+        show_vrf_nexthops_multi(request, vrftable, session, ipv4)
     }
 }
 
-fn show_vrfs(request: CliRequest, db: &RoutingDb) -> Result<CliResponse, CliError> {
+fn show_vrfs(
+    request: CliRequest,
+    db: &RoutingDb,
+    session: &CliSession,
+) -> Result<CliResponse, CliError> {
     let vrftable = &db.vrftable;
     if let Some(vni) = request.args.vni {
         let Ok(checked_vni) = Vni::try_from(vni) else {
             return Err(CliError::NotFound(format!("Invalid vni value: {vni}")));
         };
-        if let Ok(vrf) = vrftable.get_vrf_by_vni(checked_vni) {
-            Ok(CliResponse::from_request_ok(request, format!("\n{vrf}")))
-        } else {
-            Err(CliError::NotFound(format!("VRF with vni {checked_vni}")))
+        match vrftable.get_vrf_by_vni(checked_vni) {
+            Ok(vrf) if session.sees_vrf(vrf) => {
+                let out = render(request.args.format, &VrfSummary(vrf))?;
+                Ok(CliResponse::from_request_ok(request, out))
+            }
+            _ => Err(CliError::NotFound(format!("VRF with vni {checked_vni}"))),
         }
     } else {
-        Ok(CliResponse::from_request_ok(
-            request,
-            format!("\n{vrftable}"),
-        ))
+        let filter = |vrf: &Vrf| session.sees_vrf(vrf);
+        let view = VrfTableView {
+            vrftable,
+            filter: &filter,
+        };
+        let out = match request.args.format {
+            CliFormat::Text => format!("\n{view}"),
+            format => render(format, &view)?,
+        };
+        Ok(CliResponse::from_request_ok(request, out))
     }
 }
 
-fn show_fibgroups_ipv4(vrf: &Vrf, filter: &FibRouteV4Filter) -> String {
-    let view = FibViewV4 { vrf, filter };
-    format!("{view}")
+fn show_fibgroups_ipv4<'a>(
+    vrf: &'a Vrf,
+    filter: &'a FibRouteV4Filter,
+) -> FibViewV4<'a, FibRouteV4Filter> {
+    FibViewV4 { vrf, filter }
 }
-fn show_fibgroups_ipv6(vrf: &Vrf, filter: &FibRouteV6Filter) -> String {
-    let view = FibViewV6 { vrf, filter };
-    format!("{view}")
+fn show_fibgroups_ipv6<'a>(
+    vrf: &'a Vrf,
+    filter: &'a FibRouteV6Filter,
+) -> FibViewV6<'a, FibRouteV6Filter> {
+    FibViewV6 { vrf, filter }
 }
 
 fn fibgroup_filter_v4(_request: &CliRequest) -> FibRouteV4Filter {
@@ -255,71 +305,76 @@ fn fibgroup_filter_v6(_request: &CliRequest) -> FibRouteV6Filter {
 fn show_single_fib_v4(
     request: CliRequest,
     vrftable: &VrfTable,
+    session: &CliSession,
     vrfid: VrfId,
     filter: &FibRouteV4Filter,
 ) -> Result<CliResponse, CliError> {
-    let out;
-    if let Ok(vrf) = vrftable.get_vrf(vrfid) {
-        out = show_fibgroups_ipv4(vrf, filter);
-    } else {
+    let Some(vrf) = visible_vrf(vrftable, vrfid, session) else {
         return Err(CliError::NotFound(format!("VRF with id {vrfid}")));
-    }
+    };
+    let out = render(request.args.format, &show_fibgroups_ipv4(vrf, filter))?;
     Ok(CliResponse::from_request_ok(request, out))
 }
 
 fn show_single_fib_v6(
     request: CliRequest,
     vrftable: &VrfTable,
+    session: &CliSession,
     vrfid: VrfId,
     filter: &FibRouteV6Filter,
 ) -> Result<CliResponse, CliError> {
-    let out;
-    if let Ok(vrf) = vrftable.get_vrf(vrfid) {
-        out = show_fibgroups_ipv6(vrf, filter);
-    } else {
+    let Some(vrf) = visible_vrf(vrftable, vrfid, session) else {
         return Err(CliError::NotFound(format!("VRF with id {vrfid}")));
-    }
+    };
+    let out = render(request.args.format, &show_fibgroups_ipv6(vrf, filter))?;
     Ok(CliResponse::from_request_ok(request, out))
 }
 
 fn show_multi_fib_v4(
     request: CliRequest,
     vrftable: &VrfTable,
+    session: &CliSession,
     filter: &FibRouteV4Filter,
 ) -> Result<CliResponse, CliError> {
-    let mut out = String::new();
-    for vrf in vrftable.values() {
-        out += show_fibgroups_ipv4(vrf, filter).as_str();
-    }
+    let views: Vec<_> = visible_vrfs(vrftable, session)
+        .map(|vrf| show_fibgroups_ipv4(vrf, filter))
+        .collect();
+    let out = render_all(request.args.format, &views)?;
     Ok(CliResponse::from_request_ok(request, out))
 }
 fn show_multi_fib_v6(
     request: CliRequest,
     vrftable: &VrfTable,
+    session: &CliSession,
     filter: &FibRouteV6Filter,
 ) -> Result<CliResponse, CliError> {
-    let mut out = String::new();
-    for vrf in vrftable.values() {
-        out += show_fibgroups_ipv6(vrf, filter).as_str();
-    }
+    let views: Vec<_> = visible_vrfs(vrftable, session)
+        .map(|vrf| show_fibgroups_ipv6(vrf, filter))
+        .collect();
+    let out = render_all(request.args.format, &views)?;
     Ok(CliResponse::from_request_ok(request, out))
 }
 
-fn show_ip_fib(request: CliRequest, db: &RoutingDb, ipv4: bool) -> Result<CliResponse, CliError> {
+fn show_ip_fib(
+    request: CliRequest,
+    db: &RoutingDb,
+    session: &CliSession,
+    ipv4: bool,
+) -> Result<CliResponse, CliError> {
     let vrftable = &db.vrftable;
     if ipv4 {
         let filter = fibgroup_filter_v4(&request);
         if let Some(vrfid) = request.args.vrfid {
-            show_single_fib_v4(request, vrftable, vrfid, &filter)
+            show_single_fib_v4(request, vrftable, session, vrfid, &filter)
         } else {
-            show_multi_fib_v4(request, vrftable, &filter)
+            show_multi_fib_v4(request, vrftable, session, &filter)
         }
     } else {
         let filter = fibgroup_filter_v6(&request);
         if let Some(vrfid) = request.args.vrfid {
-            show_single_fib_v6(request, vrftable, vrfid, &filter)
+            show_single_fib_v6(request, vrftable, session, vrfid, &filter)
         } else {
-            show_multi_fib_v6(request, vrftable, &filter)
+            show_multi_fib_v6(request, vrftable, session, &filter)
         }
     }
 }
@@ -327,11 +382,12 @@ fn show_ip_fib(request: CliRequest, db: &RoutingDb, ipv4: bool) -> Result<CliRes
 fn show_ip_fib_groups_single(
     request: CliRequest,
     vrftable: &VrfTable,
+    session: &CliSession,
     vrfid: VrfId,
     ipv4: bool,
 ) -> Result<CliResponse, CliError> {
     let out: String;
-    if let Ok(vrf) = vrftable.get_vrf(vrfid) {
+    if let Some(vrf) = visible_vrf(vrftable, vrfid, session) {
         #[allow(clippy::if_same_then_else)]
         if ipv4 {
             out = format!("{}", FibGroups(vrf)); // for the time being we show all
@@ -346,10 +402,11 @@ fn show_ip_fib_groups_single(
 fn show_ip_fib_groups_multi(
     request: CliRequest,
     vrftable: &VrfTable,
+    session: &CliSession,
     ipv4: bool,
 ) -> Result<CliResponse, CliError> {
     let mut out = String::new();
-    for vrf in vrftable.values() {
+    for vrf in visible_vrfs(vrftable, session) {
         #[allow(clippy::if_same_then_else)]
         if ipv4 {
             out += format!("{}", FibGroups(vrf)).as_ref();
@@ -363,39 +420,87 @@ fn show_ip_fib_groups_multi(
 fn show_ip_fib_groups(
     request: CliRequest,
     db: &RoutingDb,
+    session: &CliSession,
     ipv4: bool,
 ) -> Result<CliResponse, CliError> {
     let vrftable = &db.vrftable;
     if let Some(vrfid) = request.args.vrfid {
-        show_ip_fib_groups_single(request, vrftable, vrfid, ipv4)
+        show_ip_fib_groups_single(request, vrftable, session, vrfid, ipv4)
     } else {
-        show_ip_fib_groups_multi(request, vrftable, ipv4)
+        show_ip_fib_groups_multi(request, vrftable, session, ipv4)
     }
 }
 
 fn show_provider(
     request: CliRequest,
     provider: Option<&(dyn CliDataProvider + Send)>,
-) -> CliResponse {
-    let data = provider.map_or_else(
-        || "no data is available".to_string(),
-        CliDataProvider::provide,
+    session: &CliSession,
+) -> Result<CliResponse, CliError> {
+    let Some(provider) = provider else {
+        return Ok(CliResponse::from_request_ok(
+            request,
+            "no data is available".to_string(),
+        ));
+    };
+    let data = match session.scope() {
+        None => provider.provide(),
+        Some(scope) => provider
+            .provide_scoped(scope)
+            .ok_or_else(|| forbidden(scope))?,
+    };
+    Ok(CliResponse::from_request_ok(request, data))
+}
+
+fn show_tables(request: CliRequest, db: &RoutingDb, sources: &CliSources) -> CliResponse {
+    let show = |generation: Option<TableGeneration>| {
+        generation.map_or_else(|| "inaccessible".to_string(), |g| g.to_string())
+    };
+    let mut data = Heading("Table generations".to_string()).to_string();
+    for (name, provider) in &sources.table_generations {
+        data += &format!(" {name:<24} {}\n", show(provider.published_generation()));
+    }
+    data += &format!(
+        " {:<24} {}\n",
+        "fib-table",
+        show(db.vrftable.fibtable_generation())
+    );
+    data += &format!(
+        " {:<24} {}\n",
+        "policy-classes",
+        show(db.policy.table_generation())
     );
+    for vrf in db.vrftable.values() {
+        let generation = vrf
+            .fibw
+            .as_ref()
+            .and_then(|fibw| fibw.enter())
+            .map(|fib| fib.generation());
+        let name = format!("fib (vrf {})", vrf.name);
+        data += &format!(" {name:<24} {}\n", show(generation));
+    }
     CliResponse::from_request_ok(request, data)
 }
 
-fn show_config(request: CliRequest, config: Option<&Arc<ValidatedGwConfig>>) -> CliResponse {
+fn show_config(
+    request: CliRequest,
+    config: Option<&Arc<ValidatedGwConfig>>,
+    session: &CliSession,
+) -> CliResponse {
     let Some(config) = config else {
         return CliResponse::from_request_ok(request, "No configuration is applied".to_string());
     };
     let vpc_table = &config.external().overlay().vpc_table();
-    let contents = match request.action {
-        CliAction::ShowVpc => vpc_table.as_summary().to_string(),
-        CliAction::ShowVpcPeerings => vpc_table.as_peerings().to_string(),
-        CliAction::ShowVpcRouting => vpc_table.as_route_tables().to_string(),
-        CliAction::ShowGatewayGroups => config.external().gwgroups().to_string(),
-        CliAction::ShowGatewayCommunities => config.external().communities().to_string(),
-        CliAction::ShowConfigInternal => {
+    let vpcs = session.scope().map(|scope| &scope.vpcs);
+    let contents = match (request.action, vpcs) {
+        (CliAction::ShowVpc, None) => vpc_table.as_summary().to_string(),
+        (CliAction::ShowVpc, Some(vpcs)) => vpc_table.as_summary_of(vpcs).to_string(),
+        (CliAction::ShowVpcPeerings, None) => vpc_table.as_peerings().to_string(),
+        (CliAction::ShowVpcPeerings, Some(vpcs)) => vpc_table.as_peerings_of(vpcs).to_string(),
+        (CliAction::ShowVpcRouting, None) => vpc_table.as_route_tables().to_string(),
+        (CliAction::ShowVpcRouting, Some(vpcs)) => vpc_table.as_route_tables_of(vpcs).to_string(),
+        (CliAction::ShowGatewayGroups, None) => config.external().gwgroups().to_string(),
+        (CliAction::ShowGatewayCommunities, None) => config.external().communities().to_string(),
+        (CliAction::ShowConfigInternal, None) => {
             let heading = Heading("Internal configuration").to_string();
             format!("{heading}{:#?}", config.internal())
         }
@@ -403,6 +508,153 @@ fn show_config(request: CliRequest, config: Option<&Arc<ValidatedGwConfig>>) ->
     };
     CliResponse::from_request_ok(request, contents)
 }
+fn set_feature_flag(request: CliRequest) -> Result<CliResponse, CliError> {
+    let Some(name) = &request.args.flag else {
+        return Err(CliError::NotFound("no feature flag given".to_string()));
+    };
+    let flag = flags::lookup(name).map_err(|e| CliError::NotFound(e.to_string()))?;
+    let value = match request.args.flag_value {
+        Some(FlagValue::On) => Some(true),
+        Some(FlagValue::Off) => Some(false),
+        Some(FlagValue::Default) | None => None,
+    };
+    flag.set_override(value)
+        .map_err(|e| CliError::NotSupported(e.to_string()))?;
+    let state = if flag.is_enabled() { "on" } else { "off" };
+    let data = format!("Feature flag {} is now {state}", flag.name());
+    Ok(CliResponse::from_request_ok(request, data))
+}
+
+fn set_tracing(request: CliRequest) -> Result<CliResponse, CliError> {
+    let Some(levels) = &request.args.tracing else {
+        return Err(CliError::NotFound("no tracing levels given".to_string()));
+    };
+    let tracectl = get_trace_ctl();
+    tracectl
+        .setup_from_string(levels)
+        .map_err(|e| CliError::NotSupported(e.to_string()))?;
+    let data = match tracectl.as_string() {
+        Ok(out) => format!("Tracing levels set\n {out}"),
+        Err(_) => "Tracing levels set".to_string(),
+    };
+    Ok(CliResponse::from_request_ok(request, data))
+}
+
+fn rollback_config(request: CliRequest, sources: &CliSources) -> Result<CliResponse, CliError> {
+    let Some(rollback) = &sources.config_rollback else {
+        return Err(CliError::NotSupported(
+            "config rollback is not available".to_string(),
+        ));
+    };
+    let Some(genid) = request.args.genid else {
+        return Err(CliError::NotFound("no generation id given".to_string()));
+    };
+    // the rollback is applied asynchronously, by the config processor
+    rollback.try_send(genid).map_err(|e| match e {
+        TrySendError::Full(_) => {
+            CliError::NotSupported("a rollback is already pending".to_string())
+        }
+        TrySendError::Closed(_) => {
+            error!("Failed to request rollback to config {genid}: config processor is gone");
+            CliError::InternalError
+        }
+    })?;
+    let data = format!(
+        "Requested to roll back to config {genid}. See 'show config summary' for the outcome"
+    );
+    Ok(CliResponse::from_request_ok(request, data))
+}
+
+fn show_state_store(request: CliRequest, sources: &CliSources) -> Result<CliResponse, CliError> {
+    let Some(store) = &sources.state_store else {
+        return Ok(CliResponse::from_request_ok(
+            request,
+            "no state store is available".to_string(),
+        ));
+    };
+    let internal = |e: KvError| {
+        error!("Failed to read the state store: {e}");
+        CliError::InternalError
+    };
+    let namespaces = match &request.args.namespace {
+        Some(namespace) => vec![namespace.clone()],
+        None => store.namespaces().map_err(internal)?,
+    };
+    let mut data = Heading("State store").to_string();
+    for namespace in &namespaces {
+        let entries = store.entries(namespace).map_err(internal)?;
+        data += &format!(" {namespace} ({} keys)\n", entries.len());
+        for (key, value) in &entries {
+            data += &format!("   {key:<40} {}\n", ValueDisplay(value));
+        }
+    }
+    Ok(CliResponse::from_request_ok(request, data))
+}
+
+fn show_crash_reports(request: CliRequest) -> Result<CliResponse, CliError> {
+    let Some(reports) = crash_reports() else {
+        return Ok(CliResponse::from_request_ok(
+            request,
+            "crash reports are not enabled".to_string(),
+        ));
+    };
+    let internal = |e: CrashReportError| {
+        error!("Failed to read crash reports: {e}");
+        CliError::InternalError
+    };
+    let listed = reports.list().map_err(internal)?;
+    let mut data = Heading(format!("Crash reports ({})", listed.len())).to_string();
+    data += &format!(
+        " {:<28} {:<20} {:<12} {:>8} {}\n",
+        "name", "time", "version", "config", "message"
+    );
+    for entry in &listed {
+        match reports.get(&entry.name) {
+            Ok(report) => {
+                let time = i64::try_from(report.time)
+                    .ok()
+                    .and_then(|secs| DateTime::from_timestamp(secs, 0))
+                    .map(|t| {
+                        t.with_timezone(&Local)
+                            .format("%Y-%m-%d %H:%M:%S")
+                            .to_string()
+                    })
+                    .unwrap_or_default();
+                let message = report.message.lines().next().unwrap_or_default();
+                data += &format!(
+                    " {:<28} {time:<20} {:<12} {:>8} {message}\n",
+                    entry.name, report.version, report.config_genid
+                );
+            }
+            Err(e) => data += &format!(" {:<28} unreadable: {e}\n", entry.name),
+        }
+    }
+    Ok(CliResponse::from_request_ok(request, data))
+}
+
+fn show_crash_report(request: CliRequest) -> Result<CliResponse, CliError> {
+    let Some(reports) = crash_reports() else {
+        return Err(CliError::NotSupported(
+            "crash reports are not enabled".to_string(),
+        ));
+    };
+    let Some(name) = &request.args.report else {
+        return Err(CliError::NotFound("no crash report given".to_string()));
+    };
+    let report = match reports.get(name) {
+        Ok(report) => report,
+        Err(CrashReportError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return Err(CliError::NotFound(format!("crash report {name}")));
+        }
+        Err(e) => return Err(CliError::NotFound(e.to_string())),
+    };
+    let data = serde_yaml_ng::to_string(&report).map_err(|e| {
+        error!("Failed to serialize crash report {name}: {e}");
+        CliError::InternalError
+    })?;
+    Ok(CliResponse::from_request_ok(request, data))
+}
+
 fn show_config_summary(request: CliRequest, summary: &[GwConfigMeta]) -> CliResponse {
     CliResponse::from_request_ok(request, ConfigSummary(summary).to_string())
 }
@@ -412,18 +664,26 @@ fn show_tech(
     db: &RoutingDb,
     rio: &mut Rio,
     sources: &CliSources,
+    session: &CliSession,
 ) -> CliResponse {
     let excluded = [
         CliAction::ShowTech,
         CliAction::CpiRequestRefresh,
         CliAction::FrrmiApplyLastConfig,
+        CliAction::SimulatePacket,
+        CliAction::SetFeatureFlag,
+        CliAction::SetTracing,
+        CliAction::RollbackConfig,
+        CliAction::StartCapture,
+        CliAction::StopCapture,
+        CliAction::DumpBmp,
     ];
     let time = Local::now();
     let mut data = format!("time: {}\n", time.format("%Y-%m-%d %H:%M:%S"));
 
     for action in CliAction::iter().filter(|a| !excluded.contains(a)) {
         let request = CliRequest::new(action, RequestArgs::default());
-        if let Ok(response) = do_handle_cli_request(request, db, rio, sources) {
+        if let Ok(response) = do_handle_cli_request(request, db, rio, sources, session) {
             if let Ok(output) = response.result {
                 data += output.as_str();
                 data += "\n";
@@ -434,23 +694,77 @@ fn show_tech(
     CliResponse::from_request_ok(request, data)
 }
 
+/// The error of requests for data not visible to a restricted session
+fn forbidden(scope: &VpcScope) -> CliError {
+    CliError::Forbidden(format!("not available in view '{}'", scope.view))
+}
+
+/// Tell if a request can be served to a session restricted to some VPCs. Such requests either
+/// show data of VPCs only, or are served by providers that restrict their data themselves.
+fn is_scopable(action: CliAction) -> bool {
+    matches!(
+        action,
+        CliAction::ShowTech
+            | CliAction::ShowVpc
+            | CliAction::ShowVpcPeerings
+            | CliAction::ShowVpcRouting
+            | CliAction::ShowRouterVrfs
+            | CliAction::ShowRouterIpv4Routes
+            | CliAction::ShowRouterIpv6Routes
+            | CliAction::ShowRouterIpv4NextHops
+            | CliAction::ShowRouterIpv6NextHops
+            | CliAction::ShowRouterIpv4FibEntries
+            | CliAction::ShowRouterIpv6FibEntries
+            | CliAction::ShowRouterIpv4FibGroups
+            | CliAction::ShowRouterIpv6FibGroups
+            | CliAction::ShowFlowTable
+            | CliAction::ShowFlowFilter
+            | CliAction::ShowPortForwarding
+            | CliAction::ShowStaticNat
+            | CliAction::ShowMasquerading
+            | CliAction::ShowNatAlg
+            | CliAction::ShowPacketStats
+    )
+}
+
+/// Tell if the response to a request is serialized from structured data in the formats other
+/// than text. The text of the responses to the other requests is wrapped in a document instead.
+fn has_structured_output(action: CliAction) -> bool {
+    matches!(
+        action,
+        CliAction::ShowRouterVrfs
+            | CliAction::ShowRouterInterfaces
+            | CliAction::ShowRouterIpv4Routes
+            | CliAction::ShowRouterIpv6Routes
+            | CliAction::ShowRouterIpv4FibEntries
+            | CliAction::ShowRouterIpv6FibEntries
+            | CliAction::DumpBmp
+    )
+}
+
 #[allow(clippy::too_many_lines)]
 fn do_handle_cli_request(
     request: CliRequest,
     db: &RoutingDb,
     rio: &mut Rio,
     sources: &CliSources,
+    session: &CliSession,
 ) -> Result<CliResponse, CliError> {
+    if let Some(scope) = session.scope()
+        && !is_scopable(request.action)
+    {
+        return Err(forbidden(scope));
+    }
     let cpi_s = &rio.cpistats;
     let frrmi = &rio.frrmi;
     let response = match request.action {
-        CliAction::ShowTech => show_tech(request, db, rio, sources),
+        CliAction::ShowTech => show_tech(request, db, rio, sources, session),
         CliAction::ShowVpc
         | CliAction::ShowVpcPeerings
         | CliAction::ShowVpcRouting
         | CliAction::ShowGatewayCommunities
         | CliAction::ShowGatewayGroups
-        | CliAction::ShowConfigInternal => show_config(request, rio.gwconfig.as_ref()),
+        | CliAction::ShowConfigInternal => show_config(request, rio.gwconfig.as_ref(), session),
         CliAction::ShowConfigSummary => show_config_summary(request, rio.cfg_history.as_ref()),
         CliAction::ShowTracingTargets => match get_trace_ctl().as_string() {
             Ok(out) => CliResponse::from_request_ok(request, format!("\n {out}")),
@@ -460,7 +774,9 @@ fn do_handle_cli_request(
             Ok(out) => CliResponse::from_request_ok(request, format!("\n {out}")),
             Err(_) => CliResponse::from_request_fail(request, CliError::InternalError),
         },
-        CliAction::ShowCpiStats => CliResponse::from_request_ok(request, format!("\n {cpi_s}")),
+        CliAction::ShowCpiStats => {
+            CliResponse::from_request_ok(request, format!("\n {cpi_s}\n{}", db.bulk))
+        }
         CliAction::ShowFrrmiStats => CliResponse::from_request_ok(request, format!("\n{frrmi}")),
         CliAction::ShowFrrmiLastConfig => match frrmi.get_applied_cfg() {
             Some(cfg) => CliResponse::from_request_ok(request, format!("\n{cfg}")),
@@ -493,14 +809,18 @@ fn do_handle_cli_request(
         }),
         CliAction::ShowRouterInterfaces => {
             let iftable = db.iftw.enter().ok_or(CliError::InternalError)?;
-            CliResponse::from_request_ok(request, format!("\n{}", *iftable))
+            let out = match request.args.format {
+                CliFormat::Text => format!("\n{}", *iftable),
+                format => render(format, &*iftable)?,
+            };
+            CliResponse::from_request_ok(request, out)
         }
         CliAction::ShowRouterInterfaceAddresses => {
             let iftable = db.iftw.enter().ok_or(CliError::InternalError)?;
             let iftable_addrs = IfTableAddress(&iftable);
             CliResponse::from_request_ok(request, format!("\n{iftable_addrs}"))
         }
-        CliAction::ShowRouterVrfs => return show_vrfs(request, db),
+        CliAction::ShowRouterVrfs => return show_vrfs(request, db, session),
         CliAction::ShowRouterEvpnRmacStore => {
             let rmac_store = &db.rmac_store;
             CliResponse::from_request_ok(request, format!("\n{rmac_store}"))
@@ -513,42 +833,87 @@ fn do_handle_cli_request(
             let atable = db.atabler.enter().ok_or(CliError::InternalError)?;
             CliResponse::from_request_ok(request, format!("\n{}", *atable))
         }
-        CliAction::ShowRouterIpv4Routes => show_vrf_routes(request, db, true)?,
-        CliAction::ShowRouterIpv6Routes => show_vrf_routes(request, db, false)?,
-        CliAction::ShowRouterIpv4NextHops => show_vrf_nexthops(request, db, true)?,
-        CliAction::ShowRouterIpv6NextHops => show_vrf_nexthops(request, db, false)?,
-        CliAction::ShowRouterIpv4FibEntries => show_ip_fib(request, db, true)?,
-        CliAction::ShowRouterIpv6FibEntries => show_ip_fib(request, db, false)?,
-        CliAction::ShowRouterIpv4FibGroups => show_ip_fib_groups(request, db, true)?,
-        CliAction::ShowRouterIpv6FibGroups => show_ip_fib_groups(request, db, false)?,
-        CliAction::ShowFlowTable => show_provider(request, sources.flow_table.as_deref()),
-        CliAction::ShowFlowFilter => show_provider(request, sources.flow_filter.as_deref()),
-        CliAction::ShowPortForwarding => show_provider(request, sources.portfw_table.as_deref()),
-        CliAction::ShowStaticNat => show_provider(request, sources.nat_tables.as_deref()),
-        CliAction::ShowMasquerading => show_provider(request, sources.masquerade_state.as_deref()),
-        CliAction::ShowPacketStats => show_provider(request, sources.pkt_stats.as_deref()),
+        CliAction::ShowRouterIpv4Routes => show_vrf_routes(request, db, session, true)?,
+        CliAction::ShowRouterIpv6Routes => show_vrf_routes(request, db, session, false)?,
+        CliAction::ShowRouterIpv4NextHops => show_vrf_nexthops(request, db, session, true)?,
+        CliAction::ShowRouterIpv6NextHops => show_vrf_nexthops(request, db, session, false)?,
+        CliAction::ShowRouterIpv4FibEntries => show_ip_fib(request, db, session, true)?,
+        CliAction::ShowRouterIpv6FibEntries => show_ip_fib(request, db, session, false)?,
+        CliAction::ShowRouterIpv4FibGroups => show_ip_fib_groups(request, db, session, true)?,
+        CliAction::ShowRouterIpv6FibGroups => show_ip_fib_groups(request, db, session, false)?,
+        CliAction::ShowFlowTable => show_provider(request, sources.flow_table.as_deref(), session)?,
+        CliAction::ShowFlowFilter => {
+            show_provider(request, sources.flow_filter.as_deref(), session)?
+        }
+        CliAction::ShowPortForwarding => {
+            show_provider(request, sources.portfw_table.as_deref(), session)?
+        }
+        CliAction::ShowStaticNat => show_provider(request, sources.nat_tables.as_deref(), session)?,
+        CliAction::ShowMasquerading => {
+            show_provider(request, sources.masquerade_state.as_deref(), session)?
+        }
+        CliAction::ShowNatAlg => show_provider(request, sources.nat_alg.as_deref(), session)?,
+        CliAction::ShowPacketStats => {
+            show_provider(request, sources.pkt_stats.as_deref(), session)?
+        }
+        CliAction::SimulatePacket => simulate_packet(request, db, sources)?,
+        CliAction::ShowTables => show_tables(request, db, sources),
+        CliAction::StartCapture | CliAction::StopCapture | CliAction::ShowCapture => {
+            handle_capture(request, sources, session)?
+        }
+        CliAction::ShowKernelInterfaces => {
+            show_provider(request, sources.interfaces.as_deref(), session)?
+        }
+        CliAction::ShowStateStore => show_state_store(request, sources)?,
+        CliAction::ShowCrashReports => show_crash_reports(request)?,
+        CliAction::ShowCrashReport => show_crash_report(request)?,
+        CliAction::ShowMicrobursts => {
+            show_provider(request, sources.microbursts.as_deref(), session)?
+        }
+        CliAction::DumpBmp => dump_bmp(request, sources)?,
+        CliAction::ShowFeatureFlags => {
+            let data = Heading("Feature flags").to_string() + &FlagTable.to_string();
+            CliResponse::from_request_ok(request, data)
+        }
+        CliAction::SetFeatureFlag => set_feature_flag(request)?,
+        CliAction::SetTracing => set_tracing(request)?,
+        CliAction::RollbackConfig => rollback_config(request, sources)?,
         _ => Err(CliError::NotSupported("Not implemented yet".to_string()))?,
     };
-    Ok(response)
+    if has_structured_output(response.request.action) {
+        return Ok(response);
+    }
+    let format = response.request.args.format;
+    let result = response.result.and_then(|text| render_text(format, text));
+    Ok(CliResponse {
+        request: response.request,
+        result,
+    })
 }
 
 #[allow(clippy::cast_possible_truncation)]
 pub(crate) fn handle_cli_request(
     rio: &mut Rio,
-    peer: &SocketAddr,
-    request: CliRequest,
+    peer: &CliPeer,
+    mut request: CliRequest,
     db: &RoutingDb,
     cli_sources: &CliSources,
 ) {
+    // the token is not echoed back in the response
+    let token = request.token.take();
     trace!("Got cli request: {request:#?} from {peer:?}");
 
-    // handle the request
-    let cliresponse = do_handle_cli_request(request.clone(), db, rio, cli_sources)
+    // scope the request to the view of its sender, or deny it, and handle it
+    let config = rio.gwconfig.clone();
+    let cliresponse = rio
+        .cli_views
+        .session(token.as_deref(), peer.uid, config.as_deref())
+        .and_then(|session| do_handle_cli_request(request.clone(), db, rio, cli_sources, &session))
         .unwrap_or_else(|e| CliResponse::from_request_fail(request, e));
 
     // serialize the response and send it. Response may be sent in multiple chunks.
     // If not all of them can be sent, they will be cached.
-    if let Err(e) = cliresponse.send(peer, &rio.clisock, &mut rio.cli_cache) {
+    if let Err(e) = cliresponse.send(&peer.addr, &rio.clisock, &mut rio.cli_cache) {
         error!("Failed to send response: {e}");
     }
 }
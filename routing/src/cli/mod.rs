@@ -3,5 +3,10 @@
 
 //! Cli
 
+pub(crate) mod bmp;
+pub(crate) mod capture;
 pub(crate) mod display;
 pub(crate) mod handler;
+pub(crate) mod serialize;
+pub(crate) mod session;
+pub(crate) mod simulate;
@@ -0,0 +1,388 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Module that implements Serialize for the views of routing objects shown by the cli, so that
+//! they can be rendered as JSON or YAML documents besides the tables of the display module.
+//! The records serialized here are built on the fly from the routing database, and only
+//! from the routing thread, like the tables.
+
+use super::display::{FibViewV4, FibViewV6, VrfTableView, VrfViewV4, VrfViewV6};
+
+use crate::fib::fibgroupstore::FibRoute;
+use crate::interfaces::iftable::IfTable;
+use crate::interfaces::interface::Interface;
+use crate::rib::nexthop::NhopKey;
+use crate::rib::vrf::{Route, RouteFlags, ShimNhop, Vrf, VrfId};
+
+use cli::cliproto::{CliError, CliFormat};
+use lpm::prefix::{Ipv4Prefix, Ipv6Prefix};
+use net::vxlan::Vni;
+use serde::ser::SerializeSeq;
+use serde::{Serialize, Serializer};
+use std::fmt::Display;
+use std::net::IpAddr;
+
+use tracing::error;
+
+/// Render a view in the given format: its table for [`CliFormat::Text`], else a document.
+pub(crate) fn render<V: Display + Serialize>(
+    format: CliFormat,
+    view: &V,
+) -> Result<String, CliError> {
+    let rendered = match format {
+        CliFormat::Text => return Ok(view.to_string()),
+        CliFormat::Json => serde_json::to_string_pretty(view).map_err(|e| e.to_string()),
+        CliFormat::Yaml => serde_yaml_ng::to_string(view).map_err(|e| e.to_string()),
+    };
+    rendered.map_err(|e| {
+        error!("Failed to serialize cli output as {}: {e}", format.as_ref());
+        CliError::InternalError
+    })
+}
+
+/// Render the views of several objects, e.g. of several VRFs: their tables one after the other,
+/// or a document with a list of them.
+pub(crate) fn render_all<V: Display + Serialize>(
+    format: CliFormat,
+    views: &[V],
+) -> Result<String, CliError> {
+    match format {
+        CliFormat::Text => Ok(views.iter().map(ToString::to_string).collect()),
+        _ => render(format, &TextList(views)),
+    }
+}
+
+/// Render text that has no structured representation, as a document with a list of its lines
+/// unless text is requested.
+pub(crate) fn render_text(format: CliFormat, text: String) -> Result<String, CliError> {
+    match format {
+        CliFormat::Text => Ok(text),
+        _ => render(format, &TextLines(&text)),
+    }
+}
+
+/// A list of views, serialized as a sequence. Its display concatenates theirs.
+struct TextList<'a, V>(&'a [V]);
+impl<V: Display> Display for TextList<'_, V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.iter().try_for_each(|view| view.fmt(f))
+    }
+}
+impl<V: Serialize> Serialize for TextList<'_, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.0.len()))?;
+        for view in self.0 {
+            seq.serialize_element(view)?;
+        }
+        seq.end()
+    }
+}
+
+/// Some text, serialized as the list of its non-empty lines
+struct TextLines<'a>(&'a str);
+impl Display for TextLines<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.0)
+    }
+}
+impl Serialize for TextLines<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Text<'a> {
+            text: Vec<&'a str>,
+        }
+        let text = self
+            .0
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .collect();
+        Text { text }.serialize(serializer)
+    }
+}
+
+//=================== VRFs, routes and next-hops ====================//
+#[derive(Serialize)]
+struct NhopRecord<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vrf: Option<VrfId>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    address: Option<IpAddr>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    interface: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ifindex: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    encap: Option<String>,
+    action: String,
+}
+impl<'a> NhopRecord<'a> {
+    fn new(key: &'a NhopKey, vrf: Option<VrfId>) -> Self {
+        Self {
+            vrf,
+            address: key.address,
+            interface: key.ifname.as_deref(),
+            ifindex: key.ifindex.map(u32::from),
+            encap: key.encap.as_ref().map(ToString::to_string),
+            action: format!("{:?}", key.fwaction).to_lowercase(),
+        }
+    }
+    fn from_shim(shim: &'a ShimNhop) -> Self {
+        Self::new(&shim.rc.key, shim.ext_vrf)
+    }
+}
+
+#[derive(Serialize)]
+struct RouteRecord<'a> {
+    prefix: String,
+    origin: String,
+    distance: u8,
+    metric: u32,
+    stale: bool,
+    age_secs: u64,
+    nexthops: Vec<NhopRecord<'a>>,
+}
+impl<'a> RouteRecord<'a> {
+    fn new(prefix: &impl Display, route: &'a Route) -> Self {
+        Self {
+            prefix: prefix.to_string(),
+            origin: route.origin.to_string(),
+            distance: route.distance,
+            metric: route.metric,
+            stale: route.flags.contains(RouteFlags::STALE),
+            age_secs: route.tstamp.elapsed().as_secs(),
+            nexthops: route.s_nhops.iter().map(NhopRecord::from_shim).collect(),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct VrfRoutes<'a, R> {
+    vrf: &'a str,
+    id: VrfId,
+    total: usize,
+    routes: Vec<R>,
+}
+
+impl<F: for<'a> Fn(&'a (Ipv4Prefix, &Route)) -> bool> Serialize for VrfViewV4<'_, F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let routes = self.vrf.iter_v4().filter(&self.filter);
+        VrfRoutes {
+            vrf: &self.vrf.name,
+            id: self.vrf.vrfid,
+            total: self.vrf.len_v4(),
+            routes: routes
+                .map(|(prefix, route)| RouteRecord::new(&prefix, route))
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<F: for<'a> Fn(&'a (Ipv6Prefix, &Route)) -> bool> Serialize for VrfViewV6<'_, F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let routes = self.vrf.iter_v6().filter(&self.filter);
+        VrfRoutes {
+            vrf: &self.vrf.name,
+            id: self.vrf.vrfid,
+            total: self.vrf.len_v6(),
+            routes: routes
+                .map(|(prefix, route)| RouteRecord::new(&prefix, route))
+                .collect(),
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Serialize)]
+struct VrfRecord<'a> {
+    name: &'a str,
+    id: VrfId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    vni: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    table_id: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+    status: String,
+    ipv4_routes: usize,
+    ipv6_routes: usize,
+}
+impl<'a> VrfRecord<'a> {
+    fn new(vrf: &'a Vrf) -> Self {
+        Self {
+            name: &vrf.name,
+            id: vrf.vrfid,
+            vni: vrf.vni.map(Vni::as_u32),
+            table_id: vrf.tableid.map(u32::from),
+            description: vrf.description.as_deref(),
+            status: vrf.status.to_string(),
+            ipv4_routes: vrf.routesv4.len(),
+            ipv6_routes: vrf.routesv6.len(),
+        }
+    }
+}
+
+/// The summary of a single [`Vrf`], as shown when looked up by VNI
+pub(crate) struct VrfSummary<'a>(pub &'a Vrf);
+impl Display for VrfSummary<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "\n{}", self.0)
+    }
+}
+impl Serialize for VrfSummary<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        VrfRecord::new(self.0).serialize(serializer)
+    }
+}
+
+impl<F: Fn(&Vrf) -> bool> Serialize for VrfTableView<'_, F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let vrfs = self.vrftable.values().filter(|vrf| (self.filter)(vrf));
+        serializer.collect_seq(vrfs.map(VrfRecord::new))
+    }
+}
+
+//========================= Interfaces ================================//
+#[derive(Serialize)]
+struct InterfaceRecord<'a> {
+    name: &'a str,
+    ifindex: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    description: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mtu: Option<u32>,
+    admin_state: String,
+    oper_state: String,
+    urpf: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    attachment: Option<String>,
+    #[serde(rename = "type")]
+    iftype: String,
+    addresses: Vec<String>,
+}
+impl<'a> InterfaceRecord<'a> {
+    fn new(iface: &'a Interface) -> Self {
+        let mut addresses: Vec<_> = iface.addresses.iter().map(ToString::to_string).collect();
+        addresses.sort();
+        Self {
+            name: &iface.name,
+            ifindex: iface.ifindex.into(),
+            description: iface.description.as_deref(),
+            mtu: iface.mtu.map(u32::from),
+            admin_state: iface.admin_state.to_string(),
+            oper_state: iface.oper_state.to_string(),
+            urpf: iface.urpf.to_string(),
+            attachment: iface.attachment.as_ref().map(ToString::to_string),
+            iftype: iface
+                .iftype
+                .to_string()
+                .split_whitespace()
+                .collect::<Vec<_>>()
+                .join(" "),
+            addresses,
+        }
+    }
+}
+
+impl Serialize for IfTable {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.values().map(InterfaceRecord::new))
+    }
+}
+
+//========================= Fib ================================//
+#[derive(Serialize)]
+struct FibRouteRecord {
+    prefix: String,
+    /// the instructions of the entries of each group of the route
+    groups: Vec<Vec<Vec<String>>>,
+}
+impl FibRouteRecord {
+    fn new(prefix: &impl Display, route: &FibRoute) -> Self {
+        let groups = route
+            .iter()
+            .map(|group| {
+                group
+                    .iter()
+                    .map(|entry| entry.iter().map(ToString::to_string).collect())
+                    .collect()
+            })
+            .collect();
+        Self {
+            prefix: prefix.to_string(),
+            groups,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct VrfFib<'a> {
+    vrf: &'a str,
+    id: VrfId,
+    /// the number of destinations, if the fib is readable
+    total: Option<usize>,
+    routes: Vec<FibRouteRecord>,
+}
+
+impl<F: for<'a> Fn(&'a (Ipv4Prefix, &FibRoute)) -> bool> Serialize for FibViewV4<'_, F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let fibr = self.vrf.fibw.as_ref().and_then(|fibw| fibw.enter());
+        let routes = fibr.as_ref().map_or_else(Vec::new, |fibr| {
+            fibr.iter_v4()
+                .filter(&self.filter)
+                .map(|(prefix, route)| FibRouteRecord::new(&prefix, route))
+                .collect()
+        });
+        VrfFib {
+            vrf: &self.vrf.name,
+            id: self.vrf.vrfid,
+            total: fibr.as_ref().map(|fibr| fibr.len_v4()),
+            routes,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<F: for<'a> Fn(&'a (Ipv6Prefix, &FibRoute)) -> bool> Serialize for FibViewV6<'_, F> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let fibr = self.vrf.fibw.as_ref().and_then(|fibw| fibw.enter());
+        let routes = fibr.as_ref().map_or_else(Vec::new, |fibr| {
+            fibr.iter_v6()
+                .filter(&self.filter)
+                .map(|(prefix, route)| FibRouteRecord::new(&prefix, route))
+                .collect()
+        });
+        VrfFib {
+            vrf: &self.vrf.name,
+            id: self.vrf.vrfid,
+            total: fibr.as_ref().map(|fibr| fibr.len_v6()),
+            routes,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render, render_text};
+    use crate::interfaces::iftable::IfTable;
+    use cli::cliproto::CliFormat;
+
+    #[test]
+    fn test_render_text_as_documents() {
+        let text = "\n header\n\n  line 1\n  line 2\n".to_string();
+        assert_eq!(render_text(CliFormat::Text, text.clone()).unwrap(), text);
+        let json = render_text(CliFormat::Json, text.clone()).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["text"][0], " header");
+        assert_eq!(value["text"][2], "  line 2");
+        let yaml = render_text(CliFormat::Yaml, text).unwrap();
+        assert!(yaml.starts_with("text:"));
+    }
+
+    #[test]
+    fn test_render_empty_interface_table() {
+        let iftable = IfTable::new();
+        assert_eq!(render(CliFormat::Json, &iftable).unwrap(), "[]");
+    }
+}
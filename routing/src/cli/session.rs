@@ -0,0 +1,314 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Scoping of cli sessions to a set of VPCs.
+//!
+//! A views file lists the user ids of the administrators of the dataplane, and the views of the
+//! dataplane offered to tenants. Each view names the VPCs it shows, and the tokens and user ids of
+//! the cli sessions it applies to:
+//!
+//! ```yaml
+//! admins: [1000]
+//! views:
+//!   - name: tenant-a
+//!     vpcs: [vpc-1, vpc-2]
+//!     tokens: [2f1b9c0e7d]
+//!     uids: [1001]
+//! ```
+//!
+//! A request presenting a token is scoped to the view of the token, and rejected if no view has
+//! it. Otherwise, requests from root or an administrator are not restricted, and other requests
+//! are scoped to the view of the user id of their sender. Requests matching no view, or whose
+//! sender is unknown, are rejected. Scoped requests only see the routes, flows and settings of
+//! the VPCs of their view, and may not use the commands whose output can't be restricted.
+//! Without administrators nor views, only root may connect to the cli socket.
+
+use crate::errors::RouterError;
+use crate::rib::vrf::Vrf;
+use cli::cliproto::CliError;
+use common::cliprovider::CliScope;
+use common::token::token_matches;
+use config::ValidatedGwConfig;
+use serde::Deserialize;
+use std::collections::BTreeSet;
+use std::path::Path;
+
+#[allow(unused)]
+use tracing::{debug, info};
+
+/// A view of the dataplane, restricted to some VPCs
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CliView {
+    name: String,
+    vpcs: BTreeSet<String>,
+    #[serde(default)]
+    tokens: Vec<String>,
+    #[serde(default)]
+    uids: Vec<u32>,
+}
+
+/// The views of the dataplane offered to cli sessions
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CliViews {
+    #[serde(default)]
+    admins: Vec<u32>,
+    #[serde(default)]
+    views: Vec<CliView>,
+}
+
+impl CliViews {
+    /// Parse and check the views of a views file
+    fn parse(contents: &str) -> Result<Self, RouterError> {
+        let views: CliViews = serde_yaml_ng::from_str(contents)
+            .map_err(|e| RouterError::InvalidCliViews(e.to_string()))?;
+        let mut names = BTreeSet::new();
+        let mut tokens = BTreeSet::new();
+        let mut uids = BTreeSet::new();
+        for view in &views.views {
+            if !names.insert(view.name.as_str()) {
+                let msg = format!("duplicate view {}", view.name);
+                return Err(RouterError::InvalidCliViews(msg));
+            }
+            if view.vpcs.is_empty() {
+                let msg = format!("view {} has no VPCs", view.name);
+                return Err(RouterError::InvalidCliViews(msg));
+            }
+            if view.tokens.iter().any(|token| token.is_empty()) {
+                let msg = format!("view {} has an empty token", view.name);
+                return Err(RouterError::InvalidCliViews(msg));
+            }
+            if !view
+                .tokens
+                .iter()
+                .all(|token| tokens.insert(token.as_str()))
+            {
+                let msg = format!("view {} shares a token with another view", view.name);
+                return Err(RouterError::InvalidCliViews(msg));
+            }
+            if let Some(uid) = view.uids.iter().find(|uid| !uids.insert(**uid)) {
+                let msg = format!("uid {uid} of view {} belongs to another view", view.name);
+                return Err(RouterError::InvalidCliViews(msg));
+            }
+            if let Some(uid) = view.uids.iter().find(|uid| views.is_admin(**uid)) {
+                let msg = format!("uid {uid} of view {} is an administrator", view.name);
+                return Err(RouterError::InvalidCliViews(msg));
+            }
+        }
+        Ok(views)
+    }
+
+    /// Load the views of a views file
+    pub(crate) fn load(path: &Path) -> Result<Self, RouterError> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            RouterError::InvalidCliViews(format!("failed to read {}: {e}", path.display()))
+        })?;
+        let views = Self::parse(&contents)?;
+        info!(
+            "Loaded {} cli views from {}",
+            views.views.len(),
+            path.display()
+        );
+        Ok(views)
+    }
+
+    /// The permissions of the cli socket. Only root may connect to it, unless other users may
+    /// use the cli.
+    pub(crate) fn sock_mode(&self) -> u32 {
+        if self.admins.is_empty() && self.views.is_empty() {
+            0o600
+        } else {
+            0o666
+        }
+    }
+
+    /// Tell if a user id is that of root or of an administrator
+    fn is_admin(&self, uid: u32) -> bool {
+        uid == 0 || self.admins.contains(&uid)
+    }
+
+    /// The view a request applies to, given its token and the user id of its sender. Requests
+    /// of root and administrators which present no token apply to no view.
+    fn view(&self, token: Option<&str>, uid: Option<u32>) -> Result<Option<&CliView>, CliError> {
+        if let Some(token) = token {
+            let view = self.views.iter().find(|view| {
+                view.tokens
+                    .iter()
+                    .any(|expected| token_matches(expected, token))
+            });
+            return view
+                .map(Some)
+                .ok_or_else(|| CliError::Forbidden("unknown token".to_string()));
+        }
+        let Some(uid) = uid else {
+            return Err(CliError::Forbidden("unknown user".to_string()));
+        };
+        if self.is_admin(uid) {
+            return Ok(None);
+        }
+        self.views
+            .iter()
+            .find(|view| view.uids.contains(&uid))
+            .map(Some)
+            .ok_or_else(|| CliError::Forbidden(format!("user {uid} has no view")))
+    }
+
+    /// Build the session of a request, given its token and the user id of its sender. The VPCs
+    /// of the view are looked up in the applied configuration, if any.
+    pub(crate) fn session(
+        &self,
+        token: Option<&str>,
+        uid: Option<u32>,
+        config: Option<&ValidatedGwConfig>,
+    ) -> Result<CliSession, CliError> {
+        let Some(view) = self.view(token, uid)? else {
+            return Ok(CliSession::default());
+        };
+        let vnis = config
+            .map(|config| {
+                let vpc_table = config.external().overlay().vpc_table();
+                view.vpcs
+                    .iter()
+                    .filter_map(|name| vpc_table.get_vpc(name))
+                    .map(|vpc| vpc.vni().as_u32())
+                    .collect()
+            })
+            .unwrap_or_default();
+        debug!("Cli request scoped to view {}", view.name);
+        Ok(CliSession {
+            scope: Some(VpcScope {
+                view: view.name.clone(),
+                vpcs: view.vpcs.clone(),
+                vnis,
+            }),
+        })
+    }
+}
+
+/// The VPCs visible to a restricted cli session
+#[derive(Debug)]
+pub(crate) struct VpcScope {
+    /// The name of the view
+    pub(crate) view: String,
+    /// The names of the visible VPCs
+    pub(crate) vpcs: BTreeSet<String>,
+    /// The VNIs of the visible VPCs which are configured
+    vnis: BTreeSet<u32>,
+}
+
+impl CliScope for VpcScope {
+    fn sees_vni(&self, vni: u32) -> bool {
+        self.vnis.contains(&vni)
+    }
+}
+
+/// The context of a cli request
+#[derive(Debug, Default)]
+pub(crate) struct CliSession {
+    /// The VPCs the request is restricted to, if any
+    scope: Option<VpcScope>,
+}
+
+impl CliSession {
+    pub(crate) fn scope(&self) -> Option<&VpcScope> {
+        self.scope.as_ref()
+    }
+
+    /// Tell if the session is unrestricted, i.e. it is that of root or an administrator
+    pub(crate) fn is_admin(&self) -> bool {
+        self.scope.is_none()
+    }
+
+    /// Tell if a [`Vrf`] is visible to the session. Only the VRFs of visible VPCs are.
+    pub(crate) fn sees_vrf(&self, vrf: &Vrf) -> bool {
+        self.scope
+            .as_ref()
+            .is_none_or(|scope| vrf.vni.is_some_and(|vni| scope.sees_vni(vni.as_u32())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const VIEWS: &str = "
+admins: [1000]
+views:
+  - name: tenant-a
+    vpcs: [vpc-1, vpc-2]
+    tokens: [secret-a]
+    uids: [1001]
+  - name: tenant-b
+    vpcs: [vpc-3]
+    tokens: [secret-b]
+";
+
+    fn view_name(session: &CliSession) -> Option<&str> {
+        session.scope().map(|scope| scope.view.as_str())
+    }
+
+    #[test]
+    fn test_cli_views_session() {
+        let views = CliViews::parse(VIEWS).unwrap();
+
+        let session = views.session(Some("secret-b"), Some(1001), None).unwrap();
+        assert_eq!(view_name(&session), Some("tenant-b"));
+        assert!(!session.scope().unwrap().sees_vni(3000));
+
+        let session = views.session(None, Some(1001), None).unwrap();
+        assert_eq!(view_name(&session), Some("tenant-a"));
+        assert!(session.scope().unwrap().vpcs.contains("vpc-2"));
+
+        // root and administrators are not restricted, unless presenting a token
+        for uid in [0, 1000] {
+            let session = views.session(None, Some(uid), None).unwrap();
+            assert!(session.is_admin());
+        }
+        let session = views.session(Some("secret-a"), Some(0), None).unwrap();
+        assert_eq!(view_name(&session), Some("tenant-a"));
+        assert!(!session.is_admin());
+
+        // anybody else is denied
+        for (token, uid) in [(Some("secret"), None), (None, None), (None, Some(1002))] {
+            let err = views.session(token, uid, None).unwrap_err();
+            assert!(matches!(err, CliError::Forbidden(_)));
+        }
+
+        // without views, only root is allowed
+        let views = CliViews::default();
+        assert!(views.session(Some("secret-a"), None, None).is_err());
+        assert!(views.session(None, Some(1001), None).is_err());
+        assert!(views.session(None, Some(0), None).unwrap().is_admin());
+        assert_eq!(views.sock_mode(), 0o600);
+    }
+
+    #[test]
+    fn test_cli_views_invalid() {
+        let shared_token = "
+views:
+  - { name: a, vpcs: [vpc-1], tokens: [t] }
+  - { name: b, vpcs: [vpc-2], tokens: [t] }
+";
+        let shared_uid = "
+views:
+  - { name: a, vpcs: [vpc-1], uids: [1001] }
+  - { name: b, vpcs: [vpc-2], uids: [1001] }
+";
+        let no_vpcs = "views: [ { name: a, vpcs: [], tokens: [t] } ]";
+        let unknown = "views: [ { name: a, vpcs: [vpc-1], users: [alice] } ]";
+        let admin_uid = "{ admins: [1001], views: [ { name: a, vpcs: [vpc-1], uids: [1001] } ] }";
+        let root_uid = "views: [ { name: a, vpcs: [vpc-1], uids: [0] } ]";
+        for contents in [
+            shared_token,
+            shared_uid,
+            no_vpcs,
+            unknown,
+            admin_uid,
+            root_uid,
+        ] {
+            assert!(CliViews::parse(contents).is_err(), "{contents}");
+        }
+        assert!(CliViews::parse("views: []").is_ok());
+    }
+}
@@ -0,0 +1,374 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! "What-if" lookups: tell the fate of a synthetic packet without transmitting it.
+//!
+//! The classification and FIB stages are evaluated here, from the routing database. The stages
+//! in between (flow filter, NAT) are owned by other crates and are evaluated by a
+//! [`FlowSimulator`] provided through the [`CliSources`].
+
+use crate::fib::fibobjects::PktInstruction;
+use crate::fib::fibtype::FibKey;
+use crate::interfaces::interface::Attachment;
+use crate::router::CliSources;
+use crate::routingdb::RoutingDb;
+
+use cli::cliproto::{CliError, CliRequest, CliResponse, TransportProtocol};
+use common::cliprovider::Heading;
+use net::packet::VpcDiscriminant;
+use net::vxlan::Vni;
+use std::fmt::Display;
+use std::net::IpAddr;
+
+/// A synthetic flow, as seen by the stages of the pipeline.
+///
+/// Stages may update the flow (e.g. to set the destination VPC or to translate addresses) so
+/// that subsequent stages see it as they would see the packet.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SimulatedFlow {
+    pub src_vpcd: Option<VpcDiscriminant>,
+    pub dst_vpcd: Option<VpcDiscriminant>,
+    pub src_addr: IpAddr,
+    pub dst_addr: IpAddr,
+    pub transport: Option<TransportProtocol>,
+    pub ports: Option<(u16, u16)>,
+}
+
+impl Display for SimulatedFlow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.ports {
+            Some((src_port, dst_port)) => write!(
+                f,
+                "{}:{src_port} -> {}:{dst_port}",
+                self.src_addr, self.dst_addr
+            )?,
+            None => write!(f, "{} -> {}", self.src_addr, self.dst_addr)?,
+        }
+        if let Some(transport) = self.transport {
+            write!(f, " ({})", transport.as_ref())?;
+        }
+        Ok(())
+    }
+}
+
+/// The decision of a pipeline stage for a [`SimulatedFlow`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StageVerdict {
+    /// The packet moves on to the next stage.
+    Pass,
+    /// The stage does not apply to the packet.
+    Skip,
+    /// The packet is dropped or delivered locally; subsequent stages are not evaluated.
+    Stop,
+}
+
+/// The outcome of a pipeline stage for a [`SimulatedFlow`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StageDecision {
+    pub stage: &'static str,
+    pub verdict: StageVerdict,
+    pub detail: String,
+}
+
+impl StageDecision {
+    #[must_use]
+    pub fn new(stage: &'static str, verdict: StageVerdict, detail: impl Into<String>) -> Self {
+        Self {
+            stage,
+            verdict,
+            detail: detail.into(),
+        }
+    }
+}
+
+impl Display for StageDecision {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let verdict = match self.verdict {
+            StageVerdict::Pass => "pass",
+            StageVerdict::Skip => "skip",
+            StageVerdict::Stop => "stop",
+        };
+        write!(f, " {:<16} {verdict:<5} {}", self.stage, self.detail)
+    }
+}
+
+/// A type able to evaluate the stages of the pipeline that sit between the ingress classification
+/// and the FIB lookup, without side effects.
+pub trait FlowSimulator {
+    /// Evaluate the flow, updating it as the stages would update the packet, and return the
+    /// decision of each stage.
+    fn simulate(&self, flow: &mut SimulatedFlow) -> Vec<StageDecision>;
+}
+
+struct SimulationReport {
+    flow: SimulatedFlow,
+    decisions: Vec<StageDecision>,
+}
+
+impl Display for SimulationReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Heading(format!("Simulation of {}", self.flow)).fmt(f)?;
+        for decision in &self.decisions {
+            writeln!(f, "{decision}")?;
+        }
+        Ok(())
+    }
+}
+
+fn flow_from_request(request: &CliRequest) -> Result<SimulatedFlow, CliError> {
+    let args = &request.args;
+    let (Some(src_addr), Some(dst_addr)) = (args.src_address, args.dst_address) else {
+        return Err(CliError::InvalidArgument(
+            "src-address and dst-address are required".to_string(),
+        ));
+    };
+    if src_addr.is_ipv4() != dst_addr.is_ipv4() {
+        return Err(CliError::InvalidArgument(
+            "mixing IPv4 and IPv6 addresses".to_string(),
+        ));
+    }
+    let ports = match args.transport {
+        Some(TransportProtocol::Tcp | TransportProtocol::Udp) => {
+            Some((args.src_port.unwrap_or(0), args.dst_port.unwrap_or(0)))
+        }
+        Some(TransportProtocol::Icmp) | None => None,
+    };
+    let src_vpcd = args
+        .vni
+        .map(|vni| {
+            Vni::new_checked(vni)
+                .map(VpcDiscriminant::VNI)
+                .map_err(|_| CliError::InvalidArgument(format!("invalid vni {vni}")))
+        })
+        .transpose()?;
+    Ok(SimulatedFlow {
+        src_vpcd,
+        dst_vpcd: None,
+        src_addr,
+        dst_addr,
+        transport: args.transport,
+        ports,
+    })
+}
+
+/// Determine the FIB to forward the flow from, the way the ingress stage would.
+fn classify(
+    request: &CliRequest,
+    db: &RoutingDb,
+    flow: &SimulatedFlow,
+) -> (StageDecision, Option<FibKey>) {
+    const STAGE: &str = "classification";
+    if let Some(VpcDiscriminant::VNI(vni)) = flow.src_vpcd {
+        return match db.vrftable.get_vrfid_by_vni(vni) {
+            Ok(vrfid) => (
+                StageDecision::new(
+                    STAGE,
+                    StageVerdict::Pass,
+                    format!("overlay packet from vni {vni} (vrf {vrfid})"),
+                ),
+                Some(FibKey::from_vrfid(vrfid)),
+            ),
+            Err(_) => (
+                StageDecision::new(STAGE, StageVerdict::Stop, format!("unknown vni {vni}")),
+                None,
+            ),
+        };
+    }
+    let Some(ifname) = &request.args.ifname else {
+        return (
+            StageDecision::new(STAGE, StageVerdict::Stop, "no ingress vni or interface"),
+            None,
+        );
+    };
+    let Some(iftable) = db.iftw.enter() else {
+        return (
+            StageDecision::new(STAGE, StageVerdict::Stop, "unable to read interfaces"),
+            None,
+        );
+    };
+    let Some(iface) = iftable.values().find(|iface| &iface.name == ifname) else {
+        return (
+            StageDecision::new(
+                STAGE,
+                StageVerdict::Stop,
+                format!("unknown interface {ifname}"),
+            ),
+            None,
+        );
+    };
+    match &iface.attachment {
+        Some(Attachment::Vrf(fibkey)) => (
+            StageDecision::new(
+                STAGE,
+                StageVerdict::Pass,
+                format!("underlay packet from {ifname} (fib {fibkey})"),
+            ),
+            Some(*fibkey),
+        ),
+        _ => (
+            StageDecision::new(
+                STAGE,
+                StageVerdict::Stop,
+                format!("interface {ifname} is not attached to a vrf"),
+            ),
+            None,
+        ),
+    }
+}
+
+/// Look up the (possibly translated) destination in the FIB, the way the ip forwarding stage would.
+/// Fails if the ingress interface given is attached to no FIB.
+fn fib_lookup(
+    db: &RoutingDb,
+    flow: &SimulatedFlow,
+    ingress: FibKey,
+) -> Result<StageDecision, CliError> {
+    const STAGE: &str = "fib";
+    let vrf = match flow.dst_vpcd {
+        Some(VpcDiscriminant::VNI(vni)) => db.vrftable.get_vrf_by_vni(vni),
+        None => match ingress {
+            FibKey::Id(vrfid) => db.vrftable.get_vrf(vrfid),
+            FibKey::Vni(vni) => db.vrftable.get_vrf_by_vni(vni),
+            FibKey::Unset => {
+                return Err(CliError::InvalidArgument(
+                    "the ingress interface is attached to no fib".to_string(),
+                ));
+            }
+        },
+    };
+    let Ok(vrf) = vrf else {
+        return Ok(StageDecision::new(
+            STAGE,
+            StageVerdict::Stop,
+            "no vrf for destination",
+        ));
+    };
+    let Some(fib) = vrf.fibw.as_ref().and_then(|fibw| fibw.enter()) else {
+        return Ok(StageDecision::new(
+            STAGE,
+            StageVerdict::Stop,
+            format!("unable to read fib of vrf {}", vrf.name),
+        ));
+    };
+    let (prefix, route) = fib.lpm_with_prefix(&flow.dst_addr);
+    if !route.has_entries() {
+        return Ok(StageDecision::new(
+            STAGE,
+            StageVerdict::Stop,
+            format!("vrf {}: hit {prefix} with no entries", vrf.name),
+        ));
+    }
+    // without a packet, we can't hash: report the first entry
+    let entry = route.get_fibentry(0);
+    let instructions: Vec<_> = entry.iter().map(ToString::to_string).collect();
+    let verdict = if entry
+        .iter()
+        .any(|inst| matches!(inst, PktInstruction::Drop | PktInstruction::Local(_)))
+    {
+        StageVerdict::Stop
+    } else {
+        StageVerdict::Pass
+    };
+    Ok(StageDecision::new(
+        STAGE,
+        verdict,
+        format!(
+            "vrf {}: hit {prefix} ({} entries): {}",
+            vrf.name,
+            route.len(),
+            instructions.join(", ")
+        ),
+    ))
+}
+
+/// Handle a [`CliAction::SimulatePacket`] request.
+///
+/// [`CliAction::SimulatePacket`]: cli::cliproto::CliAction::SimulatePacket
+pub(crate) fn simulate_packet(
+    request: CliRequest,
+    db: &RoutingDb,
+    sources: &CliSources,
+) -> Result<CliResponse, CliError> {
+    let mut flow = flow_from_request(&request)?;
+    let mut decisions = vec![];
+
+    let (decision, ingress) = classify(&request, db, &flow);
+    decisions.push(decision);
+
+    if let Some(ingress) = ingress {
+        let mut stopped = false;
+        if flow.src_vpcd.is_some() {
+            if let Some(simulator) = &sources.flow_simulator {
+                let stages = simulator.simulate(&mut flow);
+                stopped = stages.iter().any(|d| d.verdict == StageVerdict::Stop);
+                decisions.extend(stages);
+            }
+        }
+        if !stopped {
+            decisions.push(fib_lookup(db, &flow, ingress)?);
+        }
+    }
+
+    let report = SimulationReport { flow, decisions };
+    Ok(CliResponse::from_request_ok(request, report.to_string()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::atable::resolver::AtResolver;
+    use crate::fib::fibtable::FibTableWriter;
+    use crate::interfaces::iftablerw::IfTableWriter;
+    use crate::policy::classtable::PolicyClassTableWriter;
+    use cli::cliproto::{CliAction, RequestArgs};
+
+    fn request(args: RequestArgs) -> CliRequest {
+        CliRequest::new(CliAction::SimulatePacket, args)
+    }
+
+    #[test]
+    fn test_simulate_invalid_arguments() {
+        let args = RequestArgs {
+            src_address: Some("10.0.0.1".parse().unwrap()),
+            dst_address: Some("10.0.0.2".parse().unwrap()),
+            ..RequestArgs::default()
+        };
+        assert!(flow_from_request(&request(args.clone())).is_ok());
+
+        let invalid = [
+            RequestArgs {
+                dst_address: None,
+                ..args.clone()
+            },
+            RequestArgs {
+                dst_address: Some("2001:db8::1".parse().unwrap()),
+                ..args.clone()
+            },
+            RequestArgs {
+                vni: Some(0),
+                ..args
+            },
+        ];
+        for args in invalid {
+            let err = flow_from_request(&request(args)).unwrap_err();
+            assert!(matches!(err, CliError::InvalidArgument(_)), "{err}");
+        }
+    }
+
+    #[test]
+    fn test_simulate_unset_fib() {
+        let (iftw, _iftr) = IfTableWriter::new();
+        let (fibtw, _fibtr) = FibTableWriter::new();
+        let (_resolver, atabler) = AtResolver::new(false);
+        let (policyw, _policyr) = PolicyClassTableWriter::new();
+        let db = RoutingDb::new(fibtw, iftw, atabler, policyw);
+        let args = RequestArgs {
+            src_address: Some("10.0.0.1".parse().unwrap()),
+            dst_address: Some("10.0.0.2".parse().unwrap()),
+            ..RequestArgs::default()
+        };
+        let flow = flow_from_request(&request(args)).unwrap();
+        let err = fib_lookup(&db, &flow, FibKey::Unset).unwrap_err();
+        assert!(matches!(err, CliError::InvalidArgument(_)), "{err}");
+    }
+}
@@ -5,6 +5,7 @@
 
 use crate::RouterError;
 use crate::config::RouterConfig;
+use crate::fib::fibobjects::EgressEvent;
 use crate::interfaces::iftable::IfTable;
 use net::interface::InterfaceIndex;
 #[allow(unused)]
@@ -104,6 +105,19 @@ impl ReconfigInterfacePlan {
         Ok(())
     }
 
+    ///////////////////////////////////////////////////////////////////////////////////
+    /// The [`EgressEvent`]s resulting from applying this plan
+    ///////////////////////////////////////////////////////////////////////////////////
+    pub(crate) fn egress_events(&self) -> Vec<EgressEvent> {
+        let changed = self.to_modify.iter().chain(&self.to_add);
+        self.to_delete
+            .iter()
+            .copied()
+            .chain(changed.map(|ifconfig| ifconfig.ifindex))
+            .map(EgressEvent::Interface)
+            .collect()
+    }
+
     pub(crate) fn apply(
         &self,
         iftw: &mut IfTableWriter,
@@ -131,6 +145,7 @@ impl Interface {
             iftype: self.iftype.clone(),
             admin_state: self.admin_state,
             mtu: self.mtu,
+            urpf: self.urpf,
             attach_cfg: self.attachment.as_ref().map(Attachment::as_config),
         }
     }
@@ -156,6 +156,8 @@ impl RouterConfig {
         let reconfig_ifaces = ReconfigInterfacePlan::generate(self, &iftabler);
         drop(iftabler);
         reconfig_ifaces.apply(&mut db.iftw, &db.vrftable)?;
+        let events = reconfig_ifaces.egress_events();
+        db.vrftable.refresh_fibs_by_egress(&events, &db.rmac_store);
         if let Some(vtep) = &self.vtep {
             vtep.apply(db);
         }
@@ -247,6 +249,7 @@ mod tests {
     use crate::interfaces::iftablerw::IfTableWriter;
     use crate::fib::fibtable::FibTableWriter;
     use crate::atable::resolver::AtResolver;
+    use crate::policy::classtable::PolicyClassTableWriter;
 
 
     fn mk_vni(vni: u32) -> Vni {
@@ -324,7 +327,8 @@ mod tests {
         let (iftw, _iftr) = IfTableWriter::new();
         let (fibtw, _fibtr) = FibTableWriter::new();
         let (_resolver, atabler) = AtResolver::new(false);
-        RoutingDb::new(fibtw, iftw, atabler)
+        let (policyw, _policyr) = PolicyClassTableWriter::new();
+        RoutingDb::new(fibtw, iftw, atabler, policyw)
     }
     fn test_apply_config(config: &RouterConfig, db: &mut RoutingDb) -> Result<(), RouterError> {
         config.apply(db)?;
@@ -46,6 +46,9 @@ pub enum RouterError {
     #[error("Invalid configuration: {0}")]
     InvalidConfig(&'static str),
 
+    #[error("Invalid cli views: {0}")]
+    InvalidCliViews(String),
+
     #[error("Fibtable is not accessible")]
     FibTableError,
 
@@ -238,6 +238,13 @@ impl FibRoute {
         unsafe { self.0.iter().map(|group| &*group.get()) }
     }
 
+    ////////////////////////////////////////////////////////////////////////////////////////////////
+    /// Provide iterator over all of the `FibEntry`s of a `FibRoute`, across its `FibGroup`s
+    ////////////////////////////////////////////////////////////////////////////////////////////////
+    pub fn entries(&self) -> impl Iterator<Item = &FibEntry> {
+        self.iter().flat_map(FibGroup::iter)
+    }
+
     ///////////////////////////////////////////////////////////////////////////////////
     /// Creates a `FibRoute` with the `FibGroups` corresponding to a set of `NhopKey`s.
     /// Fails if it can't find a `FibGroup` for any of the `NhopKey`s.
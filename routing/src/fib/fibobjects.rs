@@ -44,6 +44,13 @@ impl EgressObject {
     pub fn ifname(&self) -> &Option<String> {
         &self.ifname
     }
+    /// Tell if an [`EgressEvent`] may invalidate this [`EgressObject`]
+    #[must_use]
+    pub fn is_affected_by(&self, event: &EgressEvent) -> bool {
+        match event {
+            EgressEvent::Interface(ifindex) => self.ifindex == Some(*ifindex),
+        }
+    }
     /// merge two egress objects appearing in a next-hop or a Fib entry. This is used as part
     /// of the resolution to ensure correctness
     pub fn merge(&mut self, other: &Self) {
@@ -59,6 +66,16 @@ impl EgressObject {
     }
 }
 
+/// An event that may invalidate the [`EgressObject`]s of a fib, requiring the rebuild of the
+/// [`FibGroup`]s containing them. Neighbors are not a source of such events: the layer-2
+/// addresses of next-hops are looked up in the adjacency table when packets are forwarded,
+/// and are not part of [`EgressObject`]s.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EgressEvent {
+    /// An interface was added, removed or reconfigured, or had its addresses changed
+    Interface(InterfaceIndex),
+}
+
 /// A `FibGroup` is a set of [`FibEntry`]s that may be used to forward an IP packet.
 /// A single entry may be used for each packet. In spite of this being a set, we implement it with a
 /// vector for the following reasons:
@@ -121,6 +138,14 @@ impl FibGroup {
         self.entries.is_empty()
     }
 
+    /// Tell if some [`EgressEvent`] may invalidate any of the [`FibEntry`]ies of a [`FibGroup`]
+    #[must_use]
+    pub fn is_affected_by(&self, events: &[EgressEvent]) -> bool {
+        self.entries
+            .iter()
+            .any(|entry| events.iter().any(|event| entry.is_affected_by(event)))
+    }
+
     /// Provide a reference to the vector of [`FibEntry`]ies in a [`FibGroup`]
     #[must_use]
     pub fn entries(&self) -> &Vec<FibEntry> {
@@ -191,6 +216,15 @@ impl FibEntry {
         }
         self.instructions = out;
     }
+    /// Tell if an [`EgressEvent`] may invalidate the [`EgressObject`]s of a [`FibEntry`]
+    #[must_use]
+    pub fn is_affected_by(&self, event: &EgressEvent) -> bool {
+        self.instructions.iter().any(|inst| match inst {
+            PktInstruction::Egress(egress) => egress.is_affected_by(event),
+            PktInstruction::Local(ifindex) => *event == EgressEvent::Interface(*ifindex),
+            _ => false,
+        })
+    }
     #[must_use]
     pub fn is_iplocal(&self) -> bool {
         self.instructions.len() == 1 && matches!(self.instructions[0], PktInstruction::Local(_))
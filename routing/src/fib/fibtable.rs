@@ -7,9 +7,11 @@ use crate::RouterError;
 use crate::fib::fibtype::{FibKey, FibReader, FibReaderFactory, FibWriter};
 use crate::rib::vrf::VrfId;
 
+use common::generation::{Generational, TableGeneration};
 use concurrency::sync::Arc;
-use left_right::{Absorb, ReadGuard, ReadHandle, ReadHandleFactory, WriteHandle};
+use left_right::{Absorb, ReadGuard, ReadHandle, ReadHandleFactory};
 use net::vxlan::Vni;
+use stats::InstrumentedWriteHandle;
 use std::collections::BTreeMap;
 use std::rc::Rc;
 #[allow(unused)]
@@ -28,7 +30,7 @@ impl FibTableEntry {
 
 #[derive(Default, Debug)]
 pub struct FibTable {
-    version: u64,
+    generation: TableGeneration,
     entries: BTreeMap<FibKey, Arc<FibTableEntry>>,
 }
 
@@ -83,6 +85,15 @@ impl FibTable {
     }
 }
 
+impl Generational for FibTable {
+    fn generation(&self) -> TableGeneration {
+        self.generation
+    }
+    fn set_generation(&mut self, generation: TableGeneration) {
+        self.generation = generation;
+    }
+}
+
 enum FibTableChange {
     Add((FibKey, Arc<FibTableEntry>)),
     Del(FibKey),
@@ -92,7 +103,7 @@ enum FibTableChange {
 
 impl Absorb<FibTableChange> for FibTable {
     fn absorb_first(&mut self, change: &mut FibTableChange, _: &Self) {
-        self.version = self.version.wrapping_add(1);
+        self.advance_generation();
         match change {
             FibTableChange::Add((id, entry)) => self.add_fib(*id, entry.clone()),
             FibTableChange::Del(id) => self.del_fib(*id),
@@ -103,16 +114,18 @@ impl Absorb<FibTableChange> for FibTable {
     fn sync_with(&mut self, _first: &Self) {}
 }
 
-pub struct FibTableWriter(WriteHandle<FibTable, FibTableChange>);
+pub struct FibTableWriter(InstrumentedWriteHandle<FibTable, FibTableChange>);
 impl FibTableWriter {
     #[must_use]
     pub fn new() -> (FibTableWriter, FibTableReader) {
         let (mut write, read) = left_right::new::<FibTable, FibTableChange>();
         write.publish(); /* avoid needing to impl sync_with() so that no need to impl Clone */
-        (FibTableWriter(write), FibTableReader(read))
+        (
+            FibTableWriter(InstrumentedWriteHandle::new(write, "fib-table")),
+            FibTableReader(read),
+        )
     }
     #[must_use]
-    #[allow(unused)]
     pub fn enter(&self) -> Option<ReadGuard<'_, FibTable>> {
         self.0.enter()
     }
@@ -198,10 +211,10 @@ impl ReadHandleProvider for FibTable {
     ) -> Option<(&ReadHandleFactory<Self::Data>, Self::Key, u64)> {
         let entry = self.get_entry(*key)?.as_ref();
         let factory = entry.factory.as_ref();
-        Some((factory, entry.id, self.version))
+        Some((factory, entry.id, self.generation.as_u64()))
     }
     fn get_version(&self) -> u64 {
-        self.version
+        self.generation.as_u64()
     }
     fn get_identity(&self, key: &Self::Key) -> Option<Self::Key> {
         self.get_entry(*key).map(|entry| entry.id)
@@ -216,7 +229,7 @@ impl ReadHandleProvider for FibTable {
             .entries
             .iter()
             .map(|(key, entry)| (*key, entry.factory.as_ref(), entry.as_ref().id));
-        (self.version, iter)
+        (self.generation.as_u64(), iter)
     }
 }
 
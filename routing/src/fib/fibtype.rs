@@ -3,8 +3,10 @@
 
 //! Fib implementation for IP packet lookups
 
-use left_right::{Absorb, ReadGuard, ReadHandle, ReadHandleFactory, WriteHandle};
+use common::generation::{Generational, TableGeneration};
+use left_right::{Absorb, ReadGuard, ReadHandle, ReadHandleFactory};
 use left_right_tlcache::Identity;
+use stats::InstrumentedWriteHandle;
 use std::hash::Hash;
 use std::net::IpAddr;
 use std::rc::Rc;
@@ -58,6 +60,7 @@ pub struct Fib {
     groupstore: FibGroupStore,
     vtep: Vtep,
     valid: bool,
+    generation: TableGeneration,
 }
 impl Hash for Fib {
     // We implement explicitly `std::hash::Hash` for `Fib` instead of deriving it because:
@@ -73,6 +76,14 @@ impl Identity<FibKey> for Fib {
         self.get_id()
     }
 }
+impl Generational for Fib {
+    fn generation(&self) -> TableGeneration {
+        self.generation
+    }
+    fn set_generation(&mut self, generation: TableGeneration) {
+        self.generation = generation;
+    }
+}
 impl Default for Fib {
     fn default() -> Self {
         let mut fib = Self {
@@ -82,6 +93,7 @@ impl Default for Fib {
             groupstore: FibGroupStore::new(),
             vtep: Vtep::new(),
             valid: true,
+            generation: TableGeneration::INITIAL,
         };
         // default route
         let route = FibRoute::with_fibgroup(fib.groupstore.get_drop_fibgroup_ref());
@@ -312,6 +324,7 @@ impl Absorb<FibChange> for Fib {
             FibChange::SetVtep(vtep) => self.set_vtep(vtep),
             FibChange::Invalidate => self.valid = false,
         }
+        self.advance_generation();
     }
     fn sync_with(&mut self, first: &Self) {
         assert_ne!(self.id, FibKey::Unset);
@@ -320,7 +333,7 @@ impl Absorb<FibChange> for Fib {
     }
 }
 
-pub struct FibWriter(WriteHandle<Fib, FibChange>);
+pub struct FibWriter(InstrumentedWriteHandle<Fib, FibChange>);
 impl FibWriter {
     /// create a fib, providing a writer and a reader
     #[must_use]
@@ -337,7 +350,10 @@ impl FibWriter {
             w.publish();
         }
         info!("Created Fib with id {id}");
-        (FibWriter(w), FibReader(r))
+        (
+            FibWriter(InstrumentedWriteHandle::new(w, "fib")),
+            FibReader(r),
+        )
     }
     pub fn enter(&self) -> Option<ReadGuard<'_, Fib>> {
         self.0.enter()
@@ -386,7 +402,7 @@ impl FibWriter {
     pub fn destroy(mut self) {
         self.0.append(FibChange::Invalidate);
         self.0.publish();
-        let taken_fib = self.0.take();
+        let taken_fib = self.0.into_inner().take();
         assert!(!taken_fib.valid);
     }
 }
@@ -645,4 +645,21 @@ mod concurrency_tests {
             h.join().unwrap();
         }
     }
+
+    #[test]
+    fn test_fibgroup_egress_events() {
+        use crate::fib::fibobjects::EgressEvent;
+
+        let e1 = build_fib_entry_egress(1, "10.0.1.1", "eth1");
+        let e2 = build_fib_entry_egress(2, "10.0.2.1", "eth2");
+        let local = FibEntry::with_inst(PktInstruction::Local(InterfaceIndex::try_new(3).unwrap()));
+        let fibgroup = build_fibgroup(&[e1, e2, local]);
+
+        let event = |ifindex| EgressEvent::Interface(InterfaceIndex::try_new(ifindex).unwrap());
+        assert!(fibgroup.is_affected_by(&[event(2)]));
+        assert!(fibgroup.is_affected_by(&[event(4), event(3)]));
+        assert!(!fibgroup.is_affected_by(&[event(4)]));
+        assert!(!fibgroup.is_affected_by(&[]));
+        assert!(!FibGroup::drop_fibgroup().is_affected_by(&[event(1)]));
+    }
 }
@@ -13,7 +13,9 @@ use thiserror::Error;
 use crate::config::FrrConfig;
 use crate::router::revent::{ROUTER_EVENTS, RouterEvent, revent};
 use config::GenId;
+use config::internal::readiness::{self, Component};
 
+use tracing::Span;
 #[allow(unused)]
 use tracing::{debug, error, info, warn};
 
@@ -85,6 +87,7 @@ pub(crate) struct FrrmiRequest {
     genid: GenId,    /* gen id this frr-config corresponds to */
     cfg: FrrConfig,  /* confif to frr-agent is a string */
     max_retries: u8, /* max number of times to retry configuration on failure */
+    span: Span,      /* span of the config request that caused this one, if any */
 }
 
 const CLEAN_CONFIG: &str = "! Empty config";
@@ -95,8 +98,15 @@ impl FrrmiRequest {
             genid,
             cfg,
             max_retries,
+            span: Span::none(),
         }
     }
+    /// Log the handling of this request within the provided span
+    #[must_use]
+    pub(crate) fn in_span(mut self, span: Span) -> Self {
+        self.span = span;
+        self
+    }
     pub(crate) fn blank() -> Self {
         FrrmiRequest::new(0, CLEAN_CONFIG.to_string(), 0)
     }
@@ -134,6 +144,7 @@ impl Frrmi {
             self.stats.last_conn_time = Some(Local::now());
             info!("Successfully connected to frr-agent at {}", self.remote);
             revent!(RouterEvent::FrrmiConnectSucceeded);
+            readiness::set_ready(Component::Frr, true);
         }
     }
     pub(crate) fn disconnect(&mut self) {
@@ -152,6 +163,7 @@ impl Frrmi {
         debug!("Frrmi is now disconnected");
         self.stats.last_disconn_time = Some(Local::now());
         revent!(RouterEvent::FrrmiDisconnected);
+        readiness::set_ready(Component::Frr, false);
     }
     pub(crate) fn timeout(&mut self) {
         if self.timeout.take_if(|t| *t < Instant::now()).is_some() {
@@ -209,6 +221,8 @@ impl Frrmi {
         }
         self.sock.as_mut().ok_or(FrrErr::NotConnected)?;
         if let Some(req) = self.requests.pop_front() {
+            let span = req.span.clone();
+            let _span = span.enter();
             let genid = req.genid;
             debug!("Initiating new FRR reconfiguration (gen: {genid})");
             self.send_msg(req)?;
@@ -362,6 +376,8 @@ impl Frrmi {
             self.timeout.take();
             return;
         };
+        let span = request.span.clone();
+        let _span = span.enter();
         let reqgen = request.genid;
         let respgen = response.genid;
         if respgen != reqgen {
@@ -95,6 +95,9 @@ impl IfTable {
         if iface.mtu != config.mtu {
             iface.mtu = config.mtu;
         }
+        if iface.urpf != config.urpf {
+            iface.urpf = config.urpf;
+        }
         debug!("Modified interface with ifindex {ifindex}");
         Ok(())
     }
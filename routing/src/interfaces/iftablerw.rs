@@ -10,9 +10,10 @@ use crate::interfaces::interface::{IfState, RouterInterfaceConfig};
 use crate::rib::vrf::VrfId;
 use crate::rib::vrftable::VrfTable;
 use left_right::ReadHandleFactory;
-use left_right::{Absorb, ReadGuard, ReadHandle, WriteHandle};
+use left_right::{Absorb, ReadGuard, ReadHandle};
 use net::interface::InterfaceIndex;
 use net::interface::address::IfAddr;
+use stats::InstrumentedWriteHandle;
 
 use tracing::{debug, warn};
 
@@ -67,17 +68,23 @@ impl Absorb<IfTableChange> for IfTable {
     }
 }
 
-pub struct IfTableWriter(WriteHandle<IfTable, IfTableChange>);
+pub struct IfTableWriter(InstrumentedWriteHandle<IfTable, IfTableChange>);
 impl IfTableWriter {
     #[must_use]
     pub fn new() -> (IfTableWriter, IfTableReader) {
         let (w, r) = left_right::new_from_empty::<IfTable, IfTableChange>(IfTable::new());
-        (IfTableWriter(w), IfTableReader(r))
+        (
+            IfTableWriter(InstrumentedWriteHandle::new(w, "interfaces")),
+            IfTableReader(r),
+        )
     }
     #[cfg(test)]
     pub fn new_with_data(iftable: IfTable) -> (IfTableWriter, IfTableReader) {
         let (w, r) = left_right::new_from_empty::<IfTable, IfTableChange>(iftable);
-        (IfTableWriter(w), IfTableReader(r))
+        (
+            IfTableWriter(InstrumentedWriteHandle::new(w, "interfaces")),
+            IfTableReader(r),
+        )
     }
     #[must_use]
     pub fn as_reader(&self) -> IfTableReader {
@@ -6,6 +6,8 @@
 use crate::fib::fibtype::FibKey;
 use crate::rib::vrf::VrfId;
 
+use config::internal::interfaces::interface::UrpfMode;
+
 use net::eth::mac::SourceMac;
 use net::interface::address::IfAddr;
 use net::interface::{InterfaceIndex, Mtu};
@@ -88,6 +90,7 @@ pub struct RouterInterfaceConfig {
     pub admin_state: IfState,        /* admin state */
     pub attach_cfg: Option<AttachConfig>, /* attach config */
     pub mtu: Option<Mtu>,
+    pub urpf: UrpfMode, /* source address validation */
 }
 impl RouterInterfaceConfig {
     #[must_use]
@@ -100,6 +103,7 @@ impl RouterInterfaceConfig {
             admin_state: IfState::Up,
             attach_cfg: None,
             mtu: None,
+            urpf: UrpfMode::Off,
         }
     }
     pub fn set_name(&mut self, name: &str) {
@@ -120,6 +124,9 @@ impl RouterInterfaceConfig {
     pub fn set_mtu(&mut self, mtu: Option<Mtu>) {
         self.mtu = mtu;
     }
+    pub fn set_urpf(&mut self, urpf: UrpfMode) {
+        self.urpf = urpf;
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -131,6 +138,7 @@ pub struct Interface {
     pub iftype: IfType,
     pub admin_state: IfState,
     pub mtu: Option<Mtu>,
+    pub urpf: UrpfMode,
     /* -- state -- */
     pub oper_state: IfState,
     pub addresses: HashSet<IfAddr>,
@@ -150,6 +158,7 @@ impl Interface {
             iftype: config.iftype.clone(),
             admin_state: config.admin_state,
             mtu: config.mtu,
+            urpf: config.urpf,
             oper_state: IfState::Unknown,
             addresses: HashSet::new(),
             attachment: None,
@@ -22,28 +22,40 @@ mod evpn;
 mod fib;
 mod frr;
 mod interfaces;
+mod policy;
 mod rib;
 mod router;
 mod routingdb;
 
 // re-exports
 pub use atable::atablerw::AtableReader;
+pub use atable::resolver::AtResolveRequester;
+pub use cli::capture::{CaptureRequest, PacketCapture};
+pub use cli::simulate::{FlowSimulator, SimulatedFlow, StageDecision, StageVerdict};
 pub use config::RouterConfig;
 pub use errors::RouterError;
 pub use evpn::Vtep;
 pub use fib::fibobjects::{EgressObject, FibEntry, PktInstruction};
-pub use fib::fibtable::FibTableReader;
+pub use fib::fibtable::{FibTableReader, FibTableReaderFactory};
 pub use fib::fibtype::FibKey;
 pub use frr::frrmi::FrrAppliedConfig;
 pub use frr::renderer::builder::Render;
 pub use interfaces::iftable::IfTable;
-pub use interfaces::iftablerw::IfTableReader;
+pub use interfaces::iftablerw::{IfTableReader, IfTableReaderFactory};
 pub use interfaces::interface::{AttachConfig, Attachment, RouterInterfaceConfig};
 pub use interfaces::interface::{IfDataEthernet, IfState, IfType, Interface};
+pub use policy::classtable::{
+    PolicyClassTable, PolicyClassTableReader, PolicyClassTableReaderFactory,
+};
 pub use rib::encapsulation::{Encapsulation, VxlanEncapsulation};
+pub use rib::snapshot::{
+    EgressSnapshot, FibRouteSnapshot, NhopAction, NhopSnapshot, RibNhopSnapshot, RibRouteSnapshot,
+    RouteSnapshotRequest, VrfSnapshot, VxlanSnapshot,
+};
+pub use rib::vrf::RouteOrigin;
 pub use rib::vrf::{RouterVrfConfig, VrfId};
 
-pub use bmp::spawn_bmp_server;
+pub use bmp::{BmpStore, BmpStoreConfig, spawn_bmp_server};
 pub use router::ctl::RouterCtlSender;
 pub use router::{BmpServerParams, CliSources, Router, RouterParams, RouterParamsBuilder};
 
@@ -0,0 +1,157 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Table of policy classes of destination prefixes, per VRF, for concurrent accesses
+//! from the router and the datapath
+
+use crate::rib::vrf::VrfId;
+use common::generation::{Generational, TableGeneration};
+use left_right::{Absorb, ReadGuard, ReadHandle, ReadHandleFactory};
+use lpm::prefix::Prefix;
+use lpm::trie::IpPrefixTrie;
+use net::packet::PolicyClass;
+use stats::InstrumentedWriteHandle;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::net::IpAddr;
+
+/// The policy classes of the destination prefixes. Prefixes overlap across VRFs,
+/// so every VRF has its own trie.
+#[derive(Clone, Debug, Default)]
+pub struct PolicyClassTable {
+    classes: HashMap<VrfId, IpPrefixTrie<PolicyClass>>,
+    generation: TableGeneration,
+}
+
+impl PolicyClassTable {
+    /// Get the policy class of the longest prefix containing `addr` in VRF `vrfid`, if any.
+    #[must_use]
+    pub fn lookup(&self, vrfid: VrfId, addr: IpAddr) -> Option<(Prefix, PolicyClass)> {
+        self.classes
+            .get(&vrfid)?
+            .lookup(addr)
+            .map(|(prefix, class)| (prefix, *class))
+    }
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.classes.values().map(IpPrefixTrie::len).sum()
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.classes.values().all(IpPrefixTrie::is_empty)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (VrfId, Prefix, &PolicyClass)> {
+        self.classes
+            .iter()
+            .flat_map(|(vrfid, trie)| trie.iter().map(|(prefix, class)| (*vrfid, prefix, class)))
+    }
+    fn set(&mut self, vrfid: VrfId, prefix: Prefix, class: PolicyClass) {
+        self.classes.entry(vrfid).or_default().insert(prefix, class);
+    }
+    fn unset(&mut self, vrfid: VrfId, prefix: &Prefix) {
+        if let Some(trie) = self.classes.get_mut(&vrfid) {
+            trie.remove(prefix);
+            if trie.is_empty() {
+                self.classes.remove(&vrfid);
+            }
+        }
+    }
+}
+
+impl Generational for PolicyClassTable {
+    fn generation(&self) -> TableGeneration {
+        self.generation
+    }
+    fn set_generation(&mut self, generation: TableGeneration) {
+        self.generation = generation;
+    }
+}
+
+impl Display for PolicyClassTable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (vrfid, prefix, class) in self.iter() {
+            writeln!(f, " {vrfid:<8} {prefix:<44} {class}")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub(crate) enum PolicyClassChange {
+    Set(VrfId, Prefix, PolicyClass),
+    Unset(VrfId, Prefix),
+}
+
+impl Absorb<PolicyClassChange> for PolicyClassTable {
+    fn absorb_first(&mut self, change: &mut PolicyClassChange, _other: &Self) {
+        match change {
+            PolicyClassChange::Set(vrfid, prefix, class) => {
+                self.set(*vrfid, *prefix, *class);
+            }
+            PolicyClassChange::Unset(vrfid, prefix) => {
+                self.unset(*vrfid, prefix);
+            }
+        }
+        self.advance_generation();
+    }
+    fn sync_with(&mut self, first: &Self) {
+        *self = first.clone();
+    }
+}
+
+pub struct PolicyClassTableWriter(InstrumentedWriteHandle<PolicyClassTable, PolicyClassChange>);
+#[derive(Clone, Debug)]
+pub struct PolicyClassTableReader(ReadHandle<PolicyClassTable>);
+
+impl PolicyClassTableWriter {
+    #[must_use]
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> (PolicyClassTableWriter, PolicyClassTableReader) {
+        let (w, r) = left_right::new_from_empty::<PolicyClassTable, PolicyClassChange>(
+            PolicyClassTable::default(),
+        );
+        (
+            PolicyClassTableWriter(InstrumentedWriteHandle::new(w, "policy-class")),
+            PolicyClassTableReader(r),
+        )
+    }
+    #[must_use]
+    pub fn enter(&self) -> Option<ReadGuard<'_, PolicyClassTable>> {
+        self.0.enter()
+    }
+    /// Set the class of a prefix in a VRF. Changes are not visible to readers until published.
+    pub(crate) fn set(&mut self, vrfid: VrfId, prefix: Prefix, class: PolicyClass) {
+        self.0.append(PolicyClassChange::Set(vrfid, prefix, class));
+    }
+    /// Remove the class of a prefix in a VRF. Changes are not visible to readers until published.
+    pub(crate) fn unset(&mut self, vrfid: VrfId, prefix: Prefix) {
+        self.0.append(PolicyClassChange::Unset(vrfid, prefix));
+    }
+    pub(crate) fn publish(&mut self) {
+        self.0.publish();
+    }
+}
+
+#[derive(Debug)]
+pub struct PolicyClassTableReaderFactory(ReadHandleFactory<PolicyClassTable>);
+impl PolicyClassTableReaderFactory {
+    #[must_use]
+    pub fn handle(&self) -> PolicyClassTableReader {
+        PolicyClassTableReader(self.0.handle())
+    }
+}
+
+impl PolicyClassTableReader {
+    #[must_use]
+    pub fn enter(&self) -> Option<ReadGuard<'_, PolicyClassTable>> {
+        self.0.enter()
+    }
+    #[must_use]
+    pub fn factory(&self) -> PolicyClassTableReaderFactory {
+        PolicyClassTableReaderFactory(self.0.factory())
+    }
+    #[must_use]
+    pub fn inner(&self) -> ReadHandle<PolicyClassTable> {
+        self.0.clone()
+    }
+}
@@ -0,0 +1,333 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Communities of the routes learnt from BGP neighbors, and their classification
+
+use crate::policy::classtable::PolicyClassTableWriter;
+use crate::rib::vrf::VrfId;
+use common::generation::{Generational, TableGeneration};
+use config::external::community_classes::{BgpCommunity, CommunityClassTable};
+use lpm::prefix::Prefix;
+use net::packet::PolicyClass;
+use std::collections::{BTreeMap, BTreeSet};
+use tracing::debug;
+
+/// Routes announced and withdrawn by a BGP neighbor in a single UPDATE, as monitored over BMP.
+/// All the announced prefixes share the same communities and belong to the same VRF.
+#[derive(Debug)]
+pub struct BgpRouteUpdate {
+    pub(crate) peer_key: String,
+    pub(crate) vrfid: VrfId,
+    pub(crate) announced: Vec<Prefix>,
+    pub(crate) communities: Vec<BgpCommunity>,
+    pub(crate) withdrawn: Vec<Prefix>,
+}
+impl BgpRouteUpdate {
+    #[must_use]
+    pub fn new(
+        peer_key: String,
+        vrfid: VrfId,
+        announced: Vec<Prefix>,
+        communities: Vec<BgpCommunity>,
+        withdrawn: Vec<Prefix>,
+    ) -> Self {
+        Self {
+            peer_key,
+            vrfid,
+            announced,
+            communities,
+            withdrawn,
+        }
+    }
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.announced.is_empty() && self.withdrawn.is_empty()
+    }
+}
+
+/// Keeps the communities of the routes learnt from each neighbor and publishes the policy
+/// class of their prefixes, according to the community-to-class table in the configuration.
+pub(crate) struct CommunityPolicy {
+    /// communities of each prefix of each VRF, by the key of the neighbor it was learnt from
+    routes: BTreeMap<(VrfId, Prefix), BTreeMap<String, Vec<BgpCommunity>>>,
+    classes: CommunityClassTable,
+    /// the classes published in the table, to only publish changes
+    classified: BTreeMap<(VrfId, Prefix), PolicyClass>,
+    tablew: PolicyClassTableWriter,
+}
+
+impl CommunityPolicy {
+    pub(crate) fn new(tablew: PolicyClassTableWriter) -> Self {
+        Self {
+            routes: BTreeMap::new(),
+            classes: CommunityClassTable::new(),
+            classified: BTreeMap::new(),
+            tablew,
+        }
+    }
+
+    /// The generation of the published policy class table
+    pub(crate) fn table_generation(&self) -> Option<TableGeneration> {
+        self.tablew.enter().map(|table| table.generation())
+    }
+
+    #[cfg(test)]
+    pub(crate) fn tablew(&self) -> &PolicyClassTableWriter {
+        &self.tablew
+    }
+
+    fn classify(&self, key: &(VrfId, Prefix)) -> Option<PolicyClass> {
+        let routes = self.routes.get(key)?;
+        self.classes.classify(routes.values().flatten())
+    }
+
+    /// Re-evaluate the class of the given prefixes and publish the changes, if any
+    fn reclassify(&mut self, prefixes: BTreeSet<(VrfId, Prefix)>) {
+        let mut changed = false;
+        for key in prefixes {
+            let class = self.classify(&key);
+            if self.classified.get(&key) == class.as_ref() {
+                continue;
+            }
+            let (vrfid, prefix) = key;
+            match class {
+                Some(class) => {
+                    debug!("Prefix {prefix} of vrf {vrfid} is now of class {class}");
+                    self.classified.insert(key, class);
+                    self.tablew.set(vrfid, prefix, class);
+                }
+                None => {
+                    debug!("Prefix {prefix} of vrf {vrfid} is no longer classified");
+                    self.classified.remove(&key);
+                    self.tablew.unset(vrfid, prefix);
+                }
+            }
+            changed = true;
+        }
+        if changed {
+            self.tablew.publish();
+        }
+    }
+
+    /// Update the routes learnt from a neighbor
+    pub(crate) fn update_routes(&mut self, update: BgpRouteUpdate) {
+        let mut touched = BTreeSet::new();
+        for prefix in update.withdrawn {
+            let key = (update.vrfid, prefix);
+            if let Some(routes) = self.routes.get_mut(&key) {
+                routes.remove(&update.peer_key);
+                if routes.is_empty() {
+                    self.routes.remove(&key);
+                }
+                touched.insert(key);
+            }
+        }
+        for prefix in update.announced {
+            let key = (update.vrfid, prefix);
+            self.routes
+                .entry(key)
+                .or_default()
+                .insert(update.peer_key.clone(), update.communities.clone());
+            touched.insert(key);
+        }
+        self.reclassify(touched);
+    }
+
+    /// Forget the routes learnt from a neighbor, e.g. because the session went down
+    pub(crate) fn purge_neighbor(&mut self, peer_key: &str) {
+        let mut touched = BTreeSet::new();
+        self.routes.retain(|key, routes| {
+            if routes.remove(peer_key).is_some() {
+                touched.insert(*key);
+            }
+            !routes.is_empty()
+        });
+        self.reclassify(touched);
+    }
+
+    /// Set the community-to-class table, re-classifying all prefixes if it changed
+    pub(crate) fn set_classes(&mut self, classes: &CommunityClassTable) {
+        if &self.classes == classes {
+            return;
+        }
+        self.classes = classes.clone();
+        let prefixes = self
+            .routes
+            .keys()
+            .chain(self.classified.keys())
+            .copied()
+            .collect();
+        self.reclassify(prefixes);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::IpAddr;
+    use std::str::FromStr;
+
+    const VRF: VrfId = 1;
+
+    fn community(s: &str) -> BgpCommunity {
+        BgpCommunity::from_str(s).unwrap()
+    }
+    fn prefix(s: &str) -> Prefix {
+        Prefix::from_str(s).unwrap()
+    }
+    fn lookup(policy: &CommunityPolicy, vrfid: VrfId, addr: &str) -> Option<PolicyClass> {
+        let addr = IpAddr::from_str(addr).unwrap();
+        policy
+            .tablew()
+            .enter()
+            .unwrap()
+            .lookup(vrfid, addr)
+            .map(|(_, class)| class)
+    }
+
+    #[test]
+    fn test_community_policy() {
+        let (tablew, _tabler) = PolicyClassTableWriter::new();
+        let mut policy = CommunityPolicy::new(tablew);
+        let mut classes = CommunityClassTable::new();
+        classes
+            .insert(community("65000:1"), PolicyClass::Internet)
+            .unwrap();
+        classes
+            .insert(community("blackhole"), PolicyClass::Blackhole)
+            .unwrap();
+        policy.set_classes(&classes);
+
+        // routes with and without known communities
+        policy.update_routes(BgpRouteUpdate::new(
+            "peer-1".to_string(),
+            VRF,
+            vec![prefix("10.0.0.0/8"), prefix("2001:db8::/32")],
+            vec![community("65000:1")],
+            vec![],
+        ));
+        policy.update_routes(BgpRouteUpdate::new(
+            "peer-1".to_string(),
+            VRF,
+            vec![prefix("192.168.0.0/16")],
+            vec![community("65000:99")],
+            vec![],
+        ));
+        assert_eq!(
+            lookup(&policy, VRF, "10.1.2.3"),
+            Some(PolicyClass::Internet)
+        );
+        assert_eq!(
+            lookup(&policy, VRF, "2001:db8::1"),
+            Some(PolicyClass::Internet)
+        );
+        assert_eq!(lookup(&policy, VRF, "192.168.1.1"), None);
+
+        // a more specific blackhole route from another peer
+        policy.update_routes(BgpRouteUpdate::new(
+            "peer-2".to_string(),
+            VRF,
+            vec![prefix("10.1.0.0/16")],
+            vec![community("blackhole")],
+            vec![],
+        ));
+        assert_eq!(
+            lookup(&policy, VRF, "10.1.2.3"),
+            Some(PolicyClass::Blackhole)
+        );
+        assert_eq!(
+            lookup(&policy, VRF, "10.2.0.1"),
+            Some(PolicyClass::Internet)
+        );
+
+        // the same prefix from two peers: most restrictive class wins
+        policy.update_routes(BgpRouteUpdate::new(
+            "peer-2".to_string(),
+            VRF,
+            vec![prefix("10.0.0.0/8")],
+            vec![community("blackhole")],
+            vec![],
+        ));
+        assert_eq!(
+            lookup(&policy, VRF, "10.2.0.1"),
+            Some(PolicyClass::Blackhole)
+        );
+
+        // withdrawal and session loss
+        policy.update_routes(BgpRouteUpdate::new(
+            "peer-2".to_string(),
+            VRF,
+            vec![],
+            vec![],
+            vec![prefix("10.0.0.0/8")],
+        ));
+        assert_eq!(
+            lookup(&policy, VRF, "10.2.0.1"),
+            Some(PolicyClass::Internet)
+        );
+        policy.purge_neighbor("peer-2");
+        assert_eq!(
+            lookup(&policy, VRF, "10.1.2.3"),
+            Some(PolicyClass::Internet)
+        );
+
+        // configuration change
+        let mut classes = CommunityClassTable::new();
+        classes
+            .insert(community("65000:99"), PolicyClass::Private)
+            .unwrap();
+        policy.set_classes(&classes);
+        assert_eq!(lookup(&policy, VRF, "10.1.2.3"), None);
+        assert_eq!(
+            lookup(&policy, VRF, "192.168.1.1"),
+            Some(PolicyClass::Private)
+        );
+    }
+
+    #[test]
+    fn test_community_policy_per_vrf() {
+        let (tablew, _tabler) = PolicyClassTableWriter::new();
+        let mut policy = CommunityPolicy::new(tablew);
+        let mut classes = CommunityClassTable::new();
+        classes
+            .insert(community("blackhole"), PolicyClass::Blackhole)
+            .unwrap();
+        policy.set_classes(&classes);
+
+        // a blackhole route in one vrf does not affect overlapping prefixes of others
+        policy.update_routes(BgpRouteUpdate::new(
+            "peer-1".to_string(),
+            VRF,
+            vec![prefix("10.0.0.0/8"), prefix("2001:db8::/32")],
+            vec![community("blackhole")],
+            vec![],
+        ));
+        assert_eq!(
+            lookup(&policy, VRF, "10.1.2.3"),
+            Some(PolicyClass::Blackhole)
+        );
+        assert_eq!(
+            lookup(&policy, VRF, "2001:db8::1"),
+            Some(PolicyClass::Blackhole)
+        );
+        assert_eq!(lookup(&policy, VRF + 1, "10.1.2.3"), None);
+        assert_eq!(lookup(&policy, VRF + 1, "2001:db8::1"), None);
+
+        // a withdrawal in another vrf leaves the route alone
+        policy.update_routes(BgpRouteUpdate::new(
+            "peer-1".to_string(),
+            VRF + 1,
+            vec![],
+            vec![],
+            vec![prefix("10.0.0.0/8")],
+        ));
+        assert_eq!(
+            lookup(&policy, VRF, "10.1.2.3"),
+            Some(PolicyClass::Blackhole)
+        );
+
+        policy.purge_neighbor("peer-1");
+        assert_eq!(lookup(&policy, VRF, "10.1.2.3"), None);
+        assert!(policy.tablew().enter().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,8 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Classification of destination prefixes into policy classes, from the BGP communities of the
+//! routes monitored over BMP and the community-to-class table in the gateway configuration.
+
+pub mod classtable;
+pub mod learned;
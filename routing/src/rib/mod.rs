@@ -6,6 +6,7 @@
 pub mod encapsulation;
 pub mod nexthop;
 pub mod rib2fib;
+pub mod snapshot;
 pub mod vrf;
 pub mod vrftable;
 
@@ -0,0 +1,306 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Snapshots of the routes of the router, for external tooling to diff the forwarding state of
+//! the dataplane against the view of FRR.
+//!
+//! A snapshot has, for each VRF, the routes of its FIB, as resolved for the packet workers, and
+//! optionally those of its RIB, as pushed by FRR, with the next-hops they resolve to. Unlike the
+//! routing objects, snapshots own all of their data and can be sent to other threads.
+
+use crate::evpn::Vtep;
+use crate::fib::fibobjects::{FibEntry, PktInstruction};
+use crate::interfaces::iftable::IfTable;
+use crate::interfaces::interface::IfState;
+use crate::rib::encapsulation::Encapsulation;
+use crate::rib::nexthop::{FwAction, Nhop, NhopKey};
+use crate::rib::vrf::{Route, RouteOrigin, Vrf, VrfId};
+use crate::rib::vrftable::VrfTable;
+
+use lpm::prefix::Prefix;
+use net::eth::mac::Mac;
+use net::interface::InterfaceIndex;
+use net::vxlan::Vni;
+use std::net::IpAddr;
+
+/// The routes a snapshot is requested for
+#[derive(Clone, Debug, Default)]
+pub struct RouteSnapshotRequest {
+    /// Name of the only VRF to snapshot. All of them if None.
+    pub vrf: Option<String>,
+    /// Include the routes of the RIBs, not only those of the FIBs
+    pub rib: bool,
+}
+
+/// What is done with the packets sent to a next-hop
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NhopAction {
+    #[default]
+    Forward,
+    Drop,
+    /// The packets are for the gateway itself
+    Local,
+}
+
+/// The interface packets are sent over, and its state
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct EgressSnapshot {
+    pub ifindex: Option<InterfaceIndex>,
+    pub ifname: Option<String>,
+    /// Operational state of the interface, if known to the router
+    pub oper_state: Option<IfState>,
+}
+
+/// The VXLAN encapsulation of the packets sent to a next-hop
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VxlanSnapshot {
+    pub vni: Vni,
+    pub remote: IpAddr,
+    /// The address of the local VTEP, if set up
+    pub source: Option<IpAddr>,
+    pub dmac: Option<Mac>,
+}
+
+/// A next-hop, as resolved in a FIB or as pushed by FRR
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct NhopSnapshot {
+    pub action: NhopAction,
+    pub address: Option<IpAddr>,
+    pub egress: Option<EgressSnapshot>,
+    pub vxlan: Option<VxlanSnapshot>,
+}
+
+/// A route of a FIB, with the entries it may forward packets with
+#[derive(Clone, Debug)]
+pub struct FibRouteSnapshot {
+    pub prefix: Prefix,
+    pub entries: Vec<NhopSnapshot>,
+}
+
+/// A next-hop of a route of a RIB, with the next-hops it resolves to
+#[derive(Clone, Debug)]
+pub struct RibNhopSnapshot {
+    pub nhop: NhopSnapshot,
+    /// The VRF the next-hop is in, if not that of the route
+    pub vrfid: Option<VrfId>,
+    /// The next-hop could not be resolved: packets sent to it are dropped
+    pub invalid: bool,
+    pub resolved: Vec<NhopSnapshot>,
+}
+
+/// A route of a RIB
+#[derive(Clone, Debug)]
+pub struct RibRouteSnapshot {
+    pub prefix: Prefix,
+    pub origin: RouteOrigin,
+    pub distance: u8,
+    pub metric: u32,
+    pub stale: bool,
+    pub nhops: Vec<RibNhopSnapshot>,
+}
+
+/// The routes of a VRF
+#[derive(Clone, Debug)]
+pub struct VrfSnapshot {
+    pub name: String,
+    pub vrfid: VrfId,
+    pub vni: Option<Vni>,
+    pub fib: Vec<FibRouteSnapshot>,
+    /// Empty unless the routes of the RIBs were requested
+    pub rib: Vec<RibRouteSnapshot>,
+}
+
+/// What is needed to complete the next-hops of a snapshot
+struct SnapshotContext<'a> {
+    source: Option<IpAddr>,
+    iftable: Option<&'a IfTable>,
+}
+
+impl SnapshotContext<'_> {
+    fn egress(&self, ifindex: Option<InterfaceIndex>, ifname: Option<&str>) -> EgressSnapshot {
+        let iface = ifindex.and_then(|ifindex| self.iftable?.get_interface(ifindex));
+        EgressSnapshot {
+            ifindex,
+            ifname: ifname
+                .map(ToString::to_string)
+                .or_else(|| iface.map(|i| i.name.clone())),
+            oper_state: iface.map(|i| i.oper_state),
+        }
+    }
+
+    fn vxlan(&self, encap: &Encapsulation) -> Option<VxlanSnapshot> {
+        match encap {
+            Encapsulation::Vxlan(vxlan) => Some(VxlanSnapshot {
+                vni: vxlan.vni,
+                remote: vxlan.remote,
+                source: self.source,
+                dmac: vxlan.dmac,
+            }),
+            Encapsulation::Mpls(_) => None,
+        }
+    }
+
+    fn fibentry(&self, entry: &FibEntry) -> NhopSnapshot {
+        let mut nhop = NhopSnapshot::default();
+        for inst in entry.iter() {
+            match inst {
+                PktInstruction::Drop => nhop.action = NhopAction::Drop,
+                PktInstruction::Local(ifindex) => {
+                    nhop.action = NhopAction::Local;
+                    nhop.egress = Some(self.egress(Some(*ifindex), None));
+                }
+                PktInstruction::Encap(encap) => nhop.vxlan = self.vxlan(encap),
+                PktInstruction::Egress(egress) => {
+                    nhop.address = egress.address;
+                    nhop.egress = Some(self.egress(egress.ifindex, egress.ifname.as_deref()));
+                }
+            }
+        }
+        nhop
+    }
+
+    fn nhop_key(&self, key: &NhopKey) -> NhopSnapshot {
+        let has_egress = key.ifindex.is_some() || key.ifname.is_some();
+        NhopSnapshot {
+            action: match key.fwaction {
+                FwAction::Forward => NhopAction::Forward,
+                FwAction::Drop => NhopAction::Drop,
+            },
+            address: key.address,
+            egress: has_egress.then(|| self.egress(key.ifindex, key.ifname.as_deref())),
+            vxlan: key.encap.as_ref().and_then(|encap| self.vxlan(encap)),
+        }
+    }
+
+    fn rib_nhop(&self, nhop: &Nhop, vrfid: Option<VrfId>) -> RibNhopSnapshot {
+        let resolved = nhop
+            .fibgroup
+            .try_borrow()
+            .map(|group| group.iter().map(|entry| self.fibentry(entry)).collect())
+            .unwrap_or_default();
+        RibNhopSnapshot {
+            nhop: self.nhop_key(&nhop.key),
+            vrfid,
+            invalid: nhop.invalid.get(),
+            resolved,
+        }
+    }
+
+    fn rib_route(&self, prefix: Prefix, route: &Route) -> RibRouteSnapshot {
+        RibRouteSnapshot {
+            prefix,
+            origin: route.origin,
+            distance: route.distance,
+            metric: route.metric,
+            stale: route.is_stale(),
+            nhops: route
+                .s_nhops
+                .iter()
+                .map(|shim| self.rib_nhop(&shim.rc, shim.ext_vrf))
+                .collect(),
+        }
+    }
+
+    fn vrf(&self, vrf: &Vrf, rib: bool) -> VrfSnapshot {
+        let mut fib = vec![];
+        if let Some(guard) = vrf.fibw.as_ref().and_then(|fibw| fibw.enter()) {
+            let v4 = guard.iter_v4().map(|(p, route)| (Prefix::from(p), route));
+            let v6 = guard.iter_v6().map(|(p, route)| (Prefix::from(p), route));
+            fib.extend(v4.chain(v6).map(|(prefix, route)| FibRouteSnapshot {
+                prefix,
+                entries: route.entries().map(|entry| self.fibentry(entry)).collect(),
+            }));
+        }
+        let mut routes = vec![];
+        if rib {
+            let v4 = vrf.iter_v4().map(|(p, route)| (Prefix::from(p), route));
+            let v6 = vrf.iter_v6().map(|(p, route)| (Prefix::from(p), route));
+            routes.extend(
+                v4.chain(v6)
+                    .map(|(prefix, route)| self.rib_route(prefix, route)),
+            );
+        }
+        VrfSnapshot {
+            name: vrf.name.clone(),
+            vrfid: vrf.vrfid,
+            vni: vrf.vni,
+            fib,
+            rib: routes,
+        }
+    }
+}
+
+/// Take a snapshot of the routes of the VRFs in `vrftable`
+pub(crate) fn snapshot_routes(
+    vrftable: &VrfTable,
+    vtep: &Vtep,
+    iftable: Option<&IfTable>,
+    request: &RouteSnapshotRequest,
+) -> Vec<VrfSnapshot> {
+    let context = SnapshotContext {
+        source: vtep.get_ip(),
+        iftable,
+    };
+    let mut vrfs: Vec<VrfSnapshot> = vrftable
+        .values()
+        .filter(|vrf| request.vrf.as_ref().is_none_or(|name| *name == vrf.name))
+        .map(|vrf| context.vrf(vrf, request.rib))
+        .collect();
+    vrfs.sort_by_key(|vrf| vrf.vrfid);
+    vrfs
+}
+
+#[cfg(test)]
+mod test {
+    use super::{NhopAction, SnapshotContext};
+    use crate::fib::fibobjects::{EgressObject, FibEntry, PktInstruction};
+    use crate::rib::encapsulation::{Encapsulation, VxlanEncapsulation};
+    use crate::rib::nexthop::NhopKey;
+    use net::interface::InterfaceIndex;
+    use net::vxlan::Vni;
+    use std::net::IpAddr;
+
+    #[test]
+    fn snapshot_nhops() {
+        let source: IpAddr = "10.0.0.1".parse().unwrap();
+        let remote: IpAddr = "10.0.0.2".parse().unwrap();
+        let nexthop: IpAddr = "192.168.1.1".parse().unwrap();
+        let ifindex = InterfaceIndex::try_new(5).unwrap();
+        let vni = Vni::new_checked(3000).unwrap();
+        let context = SnapshotContext {
+            source: Some(source),
+            iftable: None,
+        };
+
+        let mut entry = FibEntry::new();
+        let encap = Encapsulation::Vxlan(VxlanEncapsulation::new(vni, remote));
+        entry.add(PktInstruction::Encap(encap));
+        entry.add(PktInstruction::Egress(EgressObject::new(
+            Some(ifindex),
+            Some(nexthop),
+            Some("eth0".to_string()),
+        )));
+        let nhop = context.fibentry(&entry);
+        assert_eq!(nhop.action, NhopAction::Forward);
+        assert_eq!(nhop.address, Some(nexthop));
+        let egress = nhop.egress.unwrap();
+        assert_eq!(egress.ifindex, Some(ifindex));
+        assert_eq!(egress.ifname.as_deref(), Some("eth0"));
+        assert_eq!(egress.oper_state, None);
+        let vxlan = nhop.vxlan.unwrap();
+        assert_eq!(vxlan.vni, vni);
+        assert_eq!(vxlan.remote, remote);
+        assert_eq!(vxlan.source, Some(source));
+
+        let nhop = context.fibentry(&FibEntry::drop_fibentry());
+        assert_eq!(nhop.action, NhopAction::Drop);
+        assert!(nhop.egress.is_none());
+
+        let nhop = context.nhop_key(&NhopKey::with_drop());
+        assert_eq!(nhop.action, NhopAction::Drop);
+        let nhop = context.nhop_key(&NhopKey::with_address(&nexthop));
+        assert_eq!(nhop.action, NhopAction::Forward);
+        assert_eq!(nhop.address, Some(nexthop));
+        assert!(nhop.egress.is_none());
+    }
+}
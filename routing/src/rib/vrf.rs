@@ -14,6 +14,7 @@ use common::cliprovider::Frame;
 
 use super::nexthop::{FwAction, Nhop, NhopKey, NhopStore};
 use crate::evpn::{RmacStore, Vtep};
+use crate::fib::fibobjects::EgressEvent;
 use crate::fib::fibtype::FibWriter;
 use lpm::prefix::{Ipv4Prefix, Ipv6Prefix, Prefix};
 use lpm::trie::{PrefixMapTrie, TrieMap, TrieMapFactory};
@@ -378,9 +379,10 @@ impl Vrf {
     }
 
     /////////////////////////////////////////////////////////////////////////
-    /// De-register a shared next-hop for the route
+    /// De-register a shared next-hop for the route. The fibgroups removed
+    /// are published only if `publish`.
     /////////////////////////////////////////////////////////////////////////
-    fn deregister_shared_nexthops(&mut self, route: &mut Route) {
+    fn deregister_shared_nexthops(&mut self, route: &mut Route, publish: bool) {
         let mut count = 0;
         while let Some(shim) = route.s_nhops.pop() {
             let key = shim.rc.key.clone();
@@ -391,7 +393,7 @@ impl Vrf {
                 }
             }
         }
-        if count > 0 {
+        if count > 0 && publish {
             if let Some(fibw) = &mut self.fibw {
                 fibw.publish();
             }
@@ -452,6 +454,20 @@ impl Vrf {
         }
     }
 
+    ////////////////////////////////////////////////////////////////////////////////////////////////
+    /// Tell if some [`EgressEvent`] may invalidate the fibgroup of any of the next-hops of a `Vrf`
+    ////////////////////////////////////////////////////////////////////////////////////////////////
+    #[must_use]
+    pub fn is_affected_by(&self, events: &[EgressEvent]) -> bool {
+        self.nhstore
+            .iter()
+            .any(|nhop| nhop.fibgroup.borrow().is_affected_by(events))
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////////////////////
+    /// Add a route to a `Vrf` and to its `Fib`. If not `publish`, the route is only staged in the
+    /// `Fib` and its next-hops are not re-resolved: [`Vrf::publish_fib`] exposes the routes staged.
+    ////////////////////////////////////////////////////////////////////////////////////////////////
     pub fn add_route_complete(
         &mut self,
         prefix: &Prefix,
@@ -459,6 +475,7 @@ impl Vrf {
         nhops: &[RouteNhop],
         vrf0: Option<&Vrf>,
         rstore: &RmacStore,
+        publish: bool,
     ) {
         // register next-hops. This mutates the route adding references to the stored next-hops
         self.register_shared_nhops(&mut route, nhops);
@@ -483,7 +500,7 @@ impl Vrf {
                 }
                 nhkeys.push(shim.rc.key.clone());
             }
-            fibw.add_fibroute(*prefix, nhkeys, true);
+            fibw.add_fibroute(*prefix, nhkeys, publish);
         }
 
         // store the route in this vrf
@@ -494,11 +511,23 @@ impl Vrf {
 
         // if we happen to replace a route, unregister its next-hops
         if let Some(mut prior) = prior {
-            self.deregister_shared_nexthops(&mut prior);
+            self.deregister_shared_nexthops(&mut prior, publish);
         }
 
         // refresh this FIB
-        self.refresh_fib(rstore, vrf0);
+        if publish {
+            self.refresh_fib(rstore, vrf0);
+        }
+    }
+
+    ////////////////////////////////////////////////////////////////////////////////////////////////
+    /// Refresh the `Fib` of a `Vrf` and publish it, exposing the routes staged
+    ////////////////////////////////////////////////////////////////////////////////////////////////
+    pub fn publish_fib(&mut self, rstore: &RmacStore, resvrf: Option<&Vrf>) {
+        self.refresh_fib(rstore, resvrf);
+        if let Some(fibw) = &mut self.fibw {
+            fibw.publish();
+        }
     }
 
     /////////////////////////////////////////////////////////////////////////
@@ -509,7 +538,7 @@ impl Vrf {
     fn del_route_v4(&mut self, prefix: Ipv4Prefix) {
         if prefix == Ipv4Prefix::default() {
             if let Some(mut prior) = self.routesv4.insert(prefix, Route::default()) {
-                self.deregister_shared_nexthops(&mut prior);
+                self.deregister_shared_nexthops(&mut prior, true);
             }
             self.add_route(
                 &Prefix::from(prefix),
@@ -518,14 +547,14 @@ impl Vrf {
                 None,
             );
         } else if let Some(found) = &mut self.routesv4.remove(prefix) {
-            self.deregister_shared_nexthops(found);
+            self.deregister_shared_nexthops(found, true);
         }
     }
     #[inline]
     fn del_route_v6(&mut self, prefix: Ipv6Prefix) {
         if prefix == Ipv6Prefix::default() {
             if let Some(mut prior) = self.routesv6.insert(prefix, Route::default()) {
-                self.deregister_shared_nexthops(&mut prior);
+                self.deregister_shared_nexthops(&mut prior, true);
             }
             self.add_route(
                 &Prefix::from(prefix),
@@ -534,7 +563,7 @@ impl Vrf {
                 None,
             );
         } else if let Some(found) = &mut self.routesv6.remove(prefix) {
-            self.deregister_shared_nexthops(found);
+            self.deregister_shared_nexthops(found, true);
         }
     }
     pub fn del_route(&mut self, prefix: Prefix, vrf0: Option<&Vrf>, rstore: &RmacStore) {
@@ -604,6 +633,11 @@ impl Vrf {
     pub fn len_v6(&self) -> usize {
         self.routesv6.len()
     }
+    pub fn len_stale(&self) -> usize {
+        let v4 = self.routesv4.iter().filter(|(_, r)| r.is_stale()).count();
+        let v6 = self.routesv6.iter().filter(|(_, r)| r.is_stale()).count();
+        v4 + v6
+    }
     /////////////////////////////////////////////////////////////////////////
     // LPM, single call
     /////////////////////////////////////////////////////////////////////////
@@ -958,6 +992,36 @@ pub mod tests {
 
     }
 
+    #[test]
+    fn test_stale_routes() {
+        let rstore = RmacStore::new();
+        let vrf_cfg = RouterVrfConfig::new(0, "default");
+        let mut vrf = Vrf::new(&vrf_cfg);
+        for i in 1..=3 {
+            let nh = build_test_nhop(Some("10.0.0.1"), Some(1), 0, None);
+            let route = build_test_route(RouteOrigin::Bgp, 20, 100);
+            let prefix = Prefix::expect_from((format!("7.0.0.{i}").as_str(), 32));
+            vrf.add_route(&prefix, route, &[nh], None);
+        }
+
+        /* the default drop routes are never marked stale */
+        vrf.set_stale(true);
+        assert_eq!(vrf.len_stale(), 3);
+
+        /* routes pushed again are no longer stale */
+        let nh = build_test_nhop(Some("10.0.0.1"), Some(1), 0, None);
+        let route = build_test_route(RouteOrigin::Bgp, 20, 100);
+        let refreshed = Prefix::expect_from(("7.0.0.1", 32));
+        vrf.add_route(&refreshed, route, &[nh], None);
+        assert_eq!(vrf.len_stale(), 2);
+
+        /* only the routes not pushed again get removed */
+        vrf.remove_stale_routes(None, &rstore);
+        assert_eq!(vrf.len_stale(), 0);
+        assert_eq!(vrf.len_v4(), 2, "There is the refreshed route + drop");
+        assert!(vrf.get_route(refreshed).is_some());
+    }
+
     fn add_vxlan_route(vrf: &mut Vrf, dst: (&str, u8), vni: u32) {
         let route: Route = build_test_route(RouteOrigin::Bgp, 0, 1);
         let nhop = build_test_nhop(
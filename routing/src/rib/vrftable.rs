@@ -6,12 +6,14 @@
 
 use crate::RouterError;
 use crate::evpn::RmacStore;
+use crate::fib::fibobjects::EgressEvent;
 use crate::fib::fibtable::FibTableWriter;
 use crate::fib::fibtype::FibKey;
 use crate::interfaces::iftablerw::IfTableWriter;
 use crate::rib::vrf::{RouterVrfConfig, Vrf, VrfId};
 
 use ahash::RandomState;
+use common::generation::{Generational, TableGeneration};
 use net::vxlan::Vni;
 use std::collections::HashMap;
 
@@ -304,6 +306,14 @@ impl VrfTable {
         }
     }
 
+    //////////////////////////////////////////////////////////////////
+    /// Get the generation of the published fib table
+    //////////////////////////////////////////////////////////////////
+    #[must_use]
+    pub fn fibtable_generation(&self) -> Option<TableGeneration> {
+        self.fibtablew.enter().map(|fibtable| fibtable.generation())
+    }
+
     //////////////////////////////////////////////////////////////////
     /// Iterate over all VRFs
     //////////////////////////////////////////////////////////////////
@@ -390,6 +400,19 @@ impl VrfTable {
         }
     }
 
+    //////////////////////////////////////////////////////////////////
+    /// Refresh and publish the fibs of all vrfs, exposing the routes
+    /// staged in them. The default vrf goes first, since the others
+    /// may resolve their next-hops in it.
+    //////////////////////////////////////////////////////////////////
+    pub fn publish_fibs(&mut self, rstore: &RmacStore) {
+        let (vrfs, vrf0) = self.values_mut_except_default();
+        vrf0.publish_fib(rstore, None);
+        for vrf in vrfs {
+            vrf.publish_fib(rstore, Some(vrf0));
+        }
+    }
+
     //////////////////////////////////////////////////////////////////
     /// Refresh the fib groups for all vrfs that have a vni in the
     /// provided set
@@ -401,6 +424,25 @@ impl VrfTable {
         }
     }
 
+    //////////////////////////////////////////////////////////////////
+    /// Refresh the fib groups of the vrfs whose fibs may be invalidated
+    /// by some [`EgressEvent`]s. The fibs of the other vrfs are left as is.
+    //////////////////////////////////////////////////////////////////
+    pub fn refresh_fibs_by_egress(&mut self, events: &[EgressEvent], rstore: &RmacStore) {
+        if events.is_empty() {
+            return;
+        }
+        let (vrfs, vrf0) = self.values_mut_except_default();
+        if vrf0.is_affected_by(events) {
+            debug!("Refreshing fib of vrf {} on {events:?}", vrf0.name);
+            vrf0.refresh_fib(rstore, None);
+        }
+        for vrf in vrfs.filter(|vrf| vrf.is_affected_by(events)) {
+            debug!("Refreshing fib of vrf {} on {events:?}", vrf.name);
+            vrf.refresh_fib(rstore, Some(vrf0));
+        }
+    }
+
     /////////////////////////////////////////////////////////////////////////
     // Set/unset stale flag for all routes in all vrfs
     /////////////////////////////////////////////////////////////////////////
@@ -409,6 +451,13 @@ impl VrfTable {
         self.by_id.values_mut().for_each(|vrf| vrf.set_stale(value));
     }
     /////////////////////////////////////////////////////////////////////////
+    // Count the stale routes across all vrfs
+    /////////////////////////////////////////////////////////////////////////
+    #[must_use]
+    pub fn len_stale(&self) -> usize {
+        self.by_id.values().map(Vrf::len_stale).sum()
+    }
+    /////////////////////////////////////////////////////////////////////////
     // Remove stale routes across all vrfs
     /////////////////////////////////////////////////////////////////////////
     pub fn remove_stale_routes(&mut self, rstore: &RmacStore) {
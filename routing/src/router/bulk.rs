@@ -0,0 +1,218 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Bulk download of the routes of FRR at startup.
+//!
+//! Once FRR connects over the CPI for the first time, it pushes its whole table, one route per
+//! message. Applying each route on its own, re-resolving the next-hops of its VRF and publishing
+//! its FIB, does not scale to full tables. Instead, while the initial download is in progress, the
+//! routes are only staged in the FIBs, which the packet workers don't see until published. The
+//! next-hops are resolved and the FIBs published once per chunk of [`BULK_SYNC_CHUNK`] routes,
+//! and once the download completes.
+//!
+//! The download is deemed complete when nothing was received from FRR for [`BULK_SYNC_SETTLE`],
+//! once a configuration is applied, or after [`BULK_SYNC_MAX`] if FRR never settles. The routes
+//! are not ready for the dataplane to forward until then. Later downloads, e.g. when FRR
+//! restarts, are applied route by route.
+//!
+//! The following metric is exported:
+//!
+//! - `routing_bulk_sync_routes`: the number of routes of the initial download received so far.
+
+use crate::router::revent::{ROUTER_EVENTS, RouterEvent, revent};
+
+use config::internal::readiness::{self, Component};
+use metrics::{Gauge, Unit};
+use stats::{MetricSpec, Register};
+use std::time::{Duration, Instant};
+
+#[allow(unused)]
+use tracing::{debug, error, info, trace, warn};
+
+/// Number of routes staged before the FIBs are published during the initial download
+pub(crate) const BULK_SYNC_CHUNK: u64 = 10_000;
+/// Time without messages from FRR after which the initial download is deemed complete
+pub(crate) const BULK_SYNC_SETTLE: Duration = Duration::from_secs(3);
+/// Max duration of the initial download
+pub(crate) const BULK_SYNC_MAX: Duration = Duration::from_secs(120);
+
+/// The stage of the initial download of the routes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum BulkSyncState {
+    /// FRR has not connected yet
+    #[default]
+    Waiting,
+    /// Routes are being downloaded and staged
+    Syncing,
+    /// The download is complete: routes are applied as they come
+    Complete,
+}
+
+/// The progress of the initial download of the routes of FRR
+pub(crate) struct BulkSync {
+    pub(crate) state: BulkSyncState,
+    pub(crate) started: Option<Instant>,
+    last_activity: Option<Instant>,
+    pub(crate) duration: Option<Duration>,
+    /// Routes received during the download
+    pub(crate) routes: u64,
+    /// Routes staged since the FIBs were last published
+    pub(crate) staged: u64,
+    /// Number of times the FIBs were published during the download
+    pub(crate) chunks: u64,
+    metric: Gauge,
+}
+
+impl BulkSync {
+    pub(crate) fn new() -> Self {
+        readiness::set_ready(Component::Routes, false);
+        Self {
+            state: BulkSyncState::Waiting,
+            started: None,
+            last_activity: None,
+            duration: None,
+            routes: 0,
+            staged: 0,
+            chunks: 0,
+            metric: MetricSpec::new("routing_bulk_sync_routes", Unit::Count, vec![])
+                .register()
+                .metric,
+        }
+    }
+
+    /// Tell if routes are to be staged rather than published
+    pub(crate) fn is_syncing(&self) -> bool {
+        self.state == BulkSyncState::Syncing
+    }
+
+    /// Start the download, when FRR connects for the first time
+    pub(crate) fn start(&mut self) {
+        if self.state == BulkSyncState::Waiting {
+            info!("Starting bulk download of routes from FRR...");
+            let now = Instant::now();
+            self.state = BulkSyncState::Syncing;
+            self.started = Some(now);
+            self.last_activity = Some(now);
+            revent!(RouterEvent::BulkSyncStarted);
+        }
+    }
+
+    /// Note activity which may be followed by more routes: a message from FRR or a new config
+    pub(crate) fn touch(&mut self) {
+        if self.is_syncing() {
+            self.last_activity = Some(Instant::now());
+        }
+    }
+
+    /// Account for a route staged. Returns true if a chunk is complete and the FIBs are to be
+    /// published.
+    pub(crate) fn stage_route(&mut self) -> bool {
+        self.routes += 1;
+        self.staged += 1;
+        #[allow(clippy::cast_precision_loss)]
+        self.metric.set(self.routes as f64);
+        self.staged >= BULK_SYNC_CHUNK
+    }
+
+    /// Account for the FIBs being published
+    pub(crate) fn published(&mut self) {
+        if self.staged > 0 {
+            debug!(
+                "Published {} staged routes ({} so far)",
+                self.staged, self.routes
+            );
+            self.staged = 0;
+            self.chunks += 1;
+        }
+    }
+
+    /// Tell if the download is complete: either FRR settled, after a configuration was applied,
+    /// or the download took too long.
+    pub(crate) fn is_done(&self, have_config: bool) -> bool {
+        if !self.is_syncing() {
+            return false;
+        }
+        let now = Instant::now();
+        let settled = self
+            .last_activity
+            .is_some_and(|last| now.duration_since(last) > BULK_SYNC_SETTLE);
+        let expired = self
+            .started
+            .is_some_and(|started| now.duration_since(started) > BULK_SYNC_MAX);
+        (settled && have_config) || expired
+    }
+
+    /// Complete the download. The staged routes must have been published.
+    pub(crate) fn complete(&mut self) {
+        let duration = self.started.map(|started| started.elapsed());
+        let secs = duration.unwrap_or_default().as_secs_f64();
+        info!(
+            "Bulk download of routes from FRR complete: {} routes in {secs:.1} seconds",
+            self.routes
+        );
+        self.state = BulkSyncState::Complete;
+        self.duration = duration;
+        revent!(RouterEvent::BulkSyncComplete(self.routes));
+        readiness::set_ready(Component::Routes, true);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{BULK_SYNC_CHUNK, BULK_SYNC_MAX, BULK_SYNC_SETTLE, BulkSync, BulkSyncState};
+    use std::time::Instant;
+
+    #[test]
+    fn test_bulk_sync_chunks() {
+        let mut bulk = BulkSync::new();
+        assert_eq!(bulk.state, BulkSyncState::Waiting);
+        assert!(!bulk.is_syncing());
+        assert!(!bulk.is_done(true));
+
+        bulk.start();
+        assert!(bulk.is_syncing());
+        for _ in 1..BULK_SYNC_CHUNK {
+            assert!(!bulk.stage_route());
+        }
+        assert!(bulk.stage_route());
+        bulk.published();
+        assert_eq!(bulk.staged, 0);
+        assert_eq!(bulk.chunks, 1);
+
+        // publishing with nothing staged is not a chunk
+        bulk.published();
+        assert_eq!(bulk.chunks, 1);
+        assert!(!bulk.stage_route());
+        bulk.published();
+        assert_eq!((bulk.routes, bulk.chunks), (BULK_SYNC_CHUNK + 1, 2));
+
+        bulk.complete();
+        assert_eq!(bulk.state, BulkSyncState::Complete);
+        assert!(bulk.duration.is_some());
+        assert!(!bulk.is_syncing());
+
+        // later downloads are applied route by route
+        bulk.start();
+        assert_eq!(bulk.state, BulkSyncState::Complete);
+    }
+
+    #[test]
+    fn test_bulk_sync_done() {
+        let mut bulk = BulkSync::new();
+        bulk.start();
+        assert!(!bulk.is_done(true));
+
+        // settled, but not done until a config is applied
+        bulk.last_activity = Instant::now().checked_sub(2 * BULK_SYNC_SETTLE);
+        assert!(!bulk.is_done(false));
+        assert!(bulk.is_done(true));
+
+        // activity delays the completion
+        bulk.touch();
+        assert!(!bulk.is_done(true));
+
+        // FRR never settling, the download completes anyway
+        bulk.started = Instant::now().checked_sub(2 * BULK_SYNC_MAX);
+        assert!(bulk.is_done(false));
+    }
+}
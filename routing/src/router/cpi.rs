@@ -4,6 +4,7 @@
 //! Main processing functions of the Control-plane interface (CPI)
 
 use crate::evpn::RmacEntry;
+use crate::fib::fibobjects::EgressEvent;
 use crate::rib::Vrf;
 use crate::routingdb::RoutingDb;
 
@@ -26,7 +27,7 @@ use net::interface::InterfaceIndex;
 use net::interface::address::IfAddr;
 use std::os::unix::net::SocketAddr;
 use std::process;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 #[allow(unused)]
 use tracing::{debug, error, info, trace, warn};
@@ -34,6 +35,12 @@ use tracing::{debug, error, info, trace, warn};
 use tracectl::trace_target;
 trace_target!("cpi", LevelFilter::DEBUG, &["routing-full"]);
 
+// FRR keeps sending keepalives while connected: if nothing is received for this long, it is gone
+pub(crate) const CPI_HOLD_TIME: Duration = Duration::from_secs(15);
+
+// A request to FRR to push all of its state again is re-sent if not acked within this time
+pub(crate) const CPI_REFRESH_RETRY: Duration = Duration::from_secs(5);
+
 pub(crate) const CPI_STATS_SIZE: usize = RpcResultCode::RpcResultCodeMax as usize;
 #[derive(Default)]
 pub(crate) struct StatsRow(pub(crate) [u64; CPI_STATS_SIZE]);
@@ -56,6 +63,8 @@ pub(crate) enum CpiStatus {
     Connected,    /* FRR has connected normally */
     FrrRestarted, /* FRR has reconnected: it has restarted */
     NeedRefresh,  /* FRR has reconnected: we have restarted */
+    Disconnected, /* FRR went silent: it may be restarting */
+    Resumed,      /* FRR talks again after going silent, without having restarted */
 }
 impl CpiStatus {
     pub(crate) fn change(&mut self, new: CpiStatus) {
@@ -86,6 +95,10 @@ pub(crate) struct CpiStats {
     // last time a message was received
     pub(crate) last_msg_rx: Option<DateTime<Local>>,
 
+    // time a refresh was requested, until FRR acks it. Monotonic, so that changes of the
+    // wall clock neither trigger nor delay the retries
+    pub(crate) refresh_requested: Option<Instant>,
+
     // decoding failures
     pub(crate) decode_failures: u64,
 
@@ -112,6 +125,20 @@ impl CpiStats {
             ..Default::default()
         }
     }
+    /// Tell if nothing was received from the CP for longer than `hold`
+    pub(crate) fn is_silent(&self, hold: Duration) -> bool {
+        self.last_msg_rx.is_some_and(|last| {
+            Local::now()
+                .signed_duration_since(last)
+                .to_std()
+                .is_ok_and(|silence| silence > hold)
+        })
+    }
+    /// Tell if a refresh was requested more than `retry` ago and FRR did not ack it yet
+    pub(crate) fn refresh_unacked(&self, retry: Duration) -> bool {
+        self.refresh_requested
+            .is_some_and(|requested| requested.elapsed() > retry)
+    }
 }
 fn build_connect_info(synt: u64) -> ConnectInfo {
     ConnectInfo {
@@ -150,6 +177,7 @@ impl RpcOperation for ConnectInfo {
             }
         }
         if self.verinfo == VerInfo::default() {
+            let disconnected = stats.status == CpiStatus::Disconnected;
             stats.last_pid = Some(self.pid);
             stats.connect_time = Some(Local::now());
             stats.peer = Some(peer.clone());
@@ -161,6 +189,9 @@ impl RpcOperation for ConnectInfo {
             if stats.connect.get(RpcResultCode::Ok) == 0 && self.synt != 0 {
                 stats.status.change(CpiStatus::NeedRefresh);
             }
+            if disconnected && stats.status == CpiStatus::Connected {
+                stats.status.change(CpiStatus::Resumed);
+            }
             RpcResultCode::Ok
         } else {
             stats.status.change(CpiStatus::Incompatible);
@@ -199,19 +230,27 @@ impl RpcOperation for IpRoute {
         let vrftable = &mut db.vrftable;
         let iftabler = &db.iftw.as_reader();
 
+        // during the initial download, routes are staged and published in bulk
+        let publish = !db.bulk.is_syncing();
+
         if self.vrfid != Vrf::DEFAULT_VRFID && (is_evpn_route(self) || nonlocal_nhop(self)) {
             let Ok((vrf, vrf0)) = vrftable.get_with_default_mut(self.vrfid) else {
                 error!("Unable to get vrf with id {}", self.vrfid);
                 return RpcResultCode::Failure;
             };
-            vrf.add_route_rpc(self, Some(vrf0), rmac_store, iftabler);
+            vrf.add_route_rpc(self, Some(vrf0), rmac_store, iftabler, publish);
         } else {
             let Ok(vrf0) = vrftable.get_vrf_mut(self.vrfid) else {
                 error!("Unable to find VRF with id {}", self.vrfid);
                 return RpcResultCode::Failure;
             };
-            vrf0.add_route_rpc(self, None, rmac_store, iftabler);
-            vrftable.refresh_non_default_fibs(rmac_store);
+            vrf0.add_route_rpc(self, None, rmac_store, iftabler, publish);
+            if publish {
+                vrftable.refresh_non_default_fibs(rmac_store);
+            }
+        }
+        if !publish && db.bulk.stage_route() {
+            db.publish_staged_routes();
         }
         RpcResultCode::Ok
     }
@@ -276,6 +315,8 @@ impl RpcOperation for IfAddress {
             }
         };
         db.iftw.add_ip_address(ifindex, ifaddr);
+        let events = [EgressEvent::Interface(ifindex)];
+        db.vrftable.refresh_fibs_by_egress(&events, &db.rmac_store);
         RpcResultCode::Ok
     }
     fn del(&self, db: &mut Self::ObjectStore) -> RpcResultCode {
@@ -291,6 +332,8 @@ impl RpcOperation for IfAddress {
             }
         };
         db.iftw.del_ip_address(ifindex, ifaddr);
+        let events = [EgressEvent::Interface(ifindex)];
+        db.vrftable.refresh_fibs_by_egress(&events, &db.rmac_store);
         RpcResultCode::Ok
     }
 }
@@ -423,6 +466,7 @@ fn handle_request(rio: &mut Rio, peer: &SocketAddr, req: &RpcRequest, db: &mut R
             RpcOp::Connect => {
                 let res = conninfo.connect(&mut rio.cpistats, peer);
                 let synt = if res == RpcResultCode::Ok {
+                    db.bulk.start();
                     rio.cpistats.synt
                 } else {
                     0
@@ -448,10 +492,16 @@ fn handle_control(
     stats.control_rx += 1;
     if ctl.refresh != 0 {
         info!("CP acks reception of refresh request");
+        stats.refresh_requested = None;
     }
     rpc_send_control(csock, peer, false);
 }
 fn handle_rpc_msg(rio: &mut Rio, peer: &SocketAddr, msg: &RpcMsg, db: &mut RoutingDb) {
+    // FRR is back after going silent. Connects tell whether it restarted meanwhile.
+    let connect = matches!(msg, RpcMsg::Request(req) if req.get_op() == RpcOp::Connect);
+    if rio.cpistats.status == CpiStatus::Disconnected && !connect {
+        rio.cpistats.status.change(CpiStatus::Resumed);
+    }
     let csock = &mut rio.cpi_sock;
     match msg {
         RpcMsg::Control(ctl) => handle_control(csock, peer, ctl, &mut rio.cpistats),
@@ -465,6 +515,7 @@ fn handle_rpc_msg(rio: &mut Rio, peer: &SocketAddr, msg: &RpcMsg, db: &mut Routi
 pub fn process_cpi_data(rio: &mut Rio, peer: &SocketAddr, data: &mut Bytes, db: &mut RoutingDb) {
     trace!("CPI: recvd {} bytes from {}...", data.len(), peer.pretty());
     rio.cpistats.last_msg_rx = Some(Local::now());
+    db.bulk.touch();
 
     match RpcMsg::decode(data) {
         Ok(msg) => handle_rpc_msg(rio, peer, &msg, db),
@@ -476,3 +527,24 @@ pub fn process_cpi_data(rio: &mut Rio, peer: &SocketAddr, data: &mut Bytes, db:
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::CpiStats;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_refresh_unacked() {
+        let retry = Duration::from_secs(5);
+        let mut stats = CpiStats::new();
+        assert!(!stats.refresh_unacked(retry));
+
+        stats.refresh_requested = Some(Instant::now());
+        assert!(!stats.refresh_unacked(retry));
+
+        let requested = Instant::now().checked_sub(2 * retry);
+        stats.refresh_requested = requested;
+        assert!(stats.refresh_unacked(retry));
+        assert!(!stats.refresh_unacked(3 * retry));
+    }
+}
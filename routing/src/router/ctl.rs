@@ -4,6 +4,7 @@
 //! Control channel for the router
 
 use concurrency::sync::Arc;
+use config::internal::status::BgpNeighborSessionState;
 use config::{GwConfigMeta, ValidatedGwConfig};
 use interface_manager::monitor::EthEvent;
 use mio::{Interest, Waker};
@@ -12,14 +13,18 @@ use tokio::sync::mpsc::error::TryRecvError;
 use tokio::sync::oneshot;
 use tokio::sync::oneshot::Sender as AsyncSender;
 use tokio::task;
+use tracing::Span;
 #[allow(unused)]
 use tracing::{debug, error, info, warn};
 
 use crate::RouterError;
 use crate::bmp::bmp_render::BgpNeighEvent;
 use crate::config::RouterConfig;
+use crate::fib::fibobjects::EgressEvent;
 use crate::frr::frrmi::FrrAppliedConfig;
 use crate::interfaces::interface::IfState;
+use crate::policy::learned::BgpRouteUpdate;
+use crate::rib::snapshot::{RouteSnapshotRequest, VrfSnapshot, snapshot_routes};
 use crate::router::revent::{ROUTER_EVENTS, RouterEvent, revent};
 use crate::router::rio::{CPSOCK, Rio};
 use crate::routingdb::RoutingDb;
@@ -30,6 +35,7 @@ type RouterCtlReplyTx = AsyncSender<RouterCtlReply>;
 pub(crate) enum RouterCtlReply {
     Result(Result<(), RouterError>),
     FrrConfig(Option<FrrAppliedConfig>),
+    Routes(Vec<VrfSnapshot>),
 }
 
 pub struct LockGuard {
@@ -55,12 +61,15 @@ pub(crate) enum RouterCtlMsg {
     Lock(RouterCtlReplyTx),
     Unlock(RouterCtlReplyTx),
     GuardedUnlock,
-    Configure(RouterConfig, RouterCtlReplyTx),
+    Configure(RouterConfig, Span, RouterCtlReplyTx),
     GetFrrAppliedConfig(RouterCtlReplyTx),
     Config(Arc<ValidatedGwConfig>),
     ConfigHistory(Arc<Vec<GwConfigMeta>>),
     IfEvent(EthEvent),
     BgpNeighStatus(BgpNeighEvent),
+    BgpRoutes(BgpRouteUpdate),
+    MoveCliSock(String, RouterCtlReplyTx),
+    GetRoutes(RouteSnapshotRequest, RouterCtlReplyTx),
 }
 
 /// Object to send control messages to the router
@@ -129,7 +138,8 @@ impl RouterCtlSender {
         let genid = config.genid();
         debug!("Requesting router to apply config for gen {genid}...");
         let (reply_tx, reply_rx) = oneshot::channel();
-        let msg = RouterCtlMsg::Configure(config, reply_tx);
+        // the router applies the config within the span of the caller, for logs to correlate
+        let msg = RouterCtlMsg::Configure(config, Span::current(), reply_tx);
         self.send_and_wake(msg).await?;
 
         let reply = reply_rx
@@ -173,6 +183,42 @@ impl RouterCtlSender {
         let msg = RouterCtlMsg::BgpNeighStatus(ev);
         self.send_and_wake(msg).await
     }
+    pub async fn send_bgp_routes(&self, update: BgpRouteUpdate) -> Result<(), RouterError> {
+        let msg = RouterCtlMsg::BgpRoutes(update);
+        self.send_and_wake(msg).await
+    }
+    /// Move the CLI socket of the router to another path
+    pub async fn move_cli_sock(&self, path: String) -> Result<(), RouterError> {
+        debug!("Requesting router to move the CLI socket to {path}...");
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let msg = RouterCtlMsg::MoveCliSock(path, reply_tx);
+        self.send_and_wake(msg).await?;
+
+        let reply = reply_rx
+            .await
+            .map_err(|_| RouterError::Internal("Failed to receive move CLI socket reply"))?;
+        let RouterCtlReply::Result(result) = reply else {
+            unreachable!()
+        };
+        result
+    }
+    /// Get a snapshot of the routes of the router
+    pub async fn get_routes(
+        &self,
+        request: RouteSnapshotRequest,
+    ) -> Result<Vec<VrfSnapshot>, RouterError> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let msg = RouterCtlMsg::GetRoutes(request, reply_tx);
+        self.send_and_wake(msg).await?;
+
+        let reply = reply_rx
+            .await
+            .map_err(|_| RouterError::Internal("Failed to receive reply for get routes"))?;
+        let RouterCtlReply::Routes(routes) = reply else {
+            unreachable!()
+        };
+        Ok(routes)
+    }
 }
 
 /// Handle a lock request for the indicated CPI
@@ -204,9 +250,11 @@ fn handle_lock(rio: &mut Rio, lock: bool, reply_to: Option<RouterCtlReplyTx>) {
 fn handle_configure(
     rio: &mut Rio,
     config: RouterConfig,
+    span: Span,
     db: &mut RoutingDb,
     reply_to: RouterCtlReplyTx,
 ) {
+    let _span = span.enter();
     revent!(RouterEvent::GotConfigRequest(config.genid()));
 
     /* apply router config */
@@ -221,7 +269,7 @@ fn handle_configure(
 
     /* request application of frr config. This is infallible */
     if let Some(frr_config) = config.get_frr_config() {
-        rio.request_frr_config(config.genid(), frr_config.clone());
+        rio.request_frr_config(config.genid(), frr_config.clone(), span.clone());
     }
 
     /* generate event: we successfully applied the router config and FRR's is on its way  */
@@ -246,7 +294,8 @@ fn handle_get_frr_applied_config(rio: &Rio, reply_to: RouterCtlReplyTx) {
         });
 }
 
-fn handle_config(rio: &mut Rio, config: Arc<ValidatedGwConfig>) {
+fn handle_config(rio: &mut Rio, config: Arc<ValidatedGwConfig>, db: &mut RoutingDb) {
+    db.policy.set_classes(config.external().community_classes());
     rio.gwconfig = Some(config);
 }
 fn handle_config_history(rio: &mut Rio, history: Arc<Vec<GwConfigMeta>>) {
@@ -261,6 +310,7 @@ fn handle_ifevent(ev: EthEvent, db: &mut RoutingDb) {
         IfState::Down
     };
     let ifindex = ev.ifindex;
+    let mut oper_changed = false;
     if let Some(iftable) = iftw.enter() {
         let Some(iface) = iftable.get_interface(ifindex) else {
             return;
@@ -274,20 +324,50 @@ fn handle_ifevent(ev: EthEvent, db: &mut RoutingDb) {
         }
         if iface.oper_state != oper_state {
             revent!(RouterEvent::IfOperChange(ev, iface.oper_state, oper_state));
+            oper_changed = true;
         }
     }
     iftw.set_iface_admin_state(ifindex, adm_state);
     iftw.set_iface_oper_state(ifindex, oper_state);
+
+    /* rebuild the fib groups egressing over the interface if its link went up or down */
+    if oper_changed {
+        let events = [EgressEvent::Interface(ifindex)];
+        db.vrftable.refresh_fibs_by_egress(&events, &db.rmac_store);
+    }
 }
 
-fn handle_bgp_peer_status_change(bgp_ev: BgpNeighEvent) {
+fn handle_bgp_peer_status_change(bgp_ev: BgpNeighEvent, db: &mut RoutingDb) {
     info!(
         "BGP session with {} (id:{} ASN:{}) changed {} -> {}",
         bgp_ev.peer_key, bgp_ev.peer_router_id, bgp_ev.peer_asn, bgp_ev.prev, bgp_ev.new
     );
+    if bgp_ev.new != BgpNeighborSessionState::Established {
+        db.policy.purge_neighbor(&bgp_ev.peer_key);
+    }
     revent!(RouterEvent::BgpNeighStateChange(bgp_ev));
 }
 
+/// Handle a request to move the CLI socket
+fn handle_move_cli_sock(rio: &mut Rio, path: String, reply_to: RouterCtlReplyTx) {
+    let result = rio.cli_sock_move(path);
+    if let Err(e) = &result {
+        error!("Failed to move CLI socket: {e}");
+    }
+    let _ = reply_to.send(RouterCtlReply::Result(result)).map_err(|e| {
+        error!("Fatal: could not reply to move CLI socket request: {e:?}");
+    });
+}
+
+/// Handle a request for a snapshot of the routes
+fn handle_get_routes(db: &RoutingDb, request: &RouteSnapshotRequest, reply_to: RouterCtlReplyTx) {
+    let iftable = db.iftw.enter();
+    let routes = snapshot_routes(&db.vrftable, &db.vtep, iftable.as_deref(), request);
+    let _ = reply_to.send(RouterCtlReply::Routes(routes)).map_err(|e| {
+        error!("Fatal: could not reply to get routes request: {e:?}");
+    });
+}
+
 /// Handle requests from the control channel. Since the channel is integrated with the poll loop via a `Waker`
 /// and the `Waker` coalesce multiple readiness events, we drain completely on each call with a loop.
 pub(crate) fn handle_ctl_msg(rio: &mut Rio, db: &mut RoutingDb) {
@@ -296,16 +376,23 @@ pub(crate) fn handle_ctl_msg(rio: &mut Rio, db: &mut RoutingDb) {
             Ok(RouterCtlMsg::Lock(reply_to)) => handle_lock(rio, true, Some(reply_to)),
             Ok(RouterCtlMsg::Unlock(reply_to)) => handle_lock(rio, false, Some(reply_to)),
             Ok(RouterCtlMsg::GuardedUnlock) => handle_lock(rio, false, None),
-            Ok(RouterCtlMsg::Configure(config, reply_to)) => {
-                handle_configure(rio, config, db, reply_to);
+            Ok(RouterCtlMsg::Configure(config, span, reply_to)) => {
+                handle_configure(rio, config, span, db, reply_to);
             }
             Ok(RouterCtlMsg::GetFrrAppliedConfig(reply_to)) => {
                 handle_get_frr_applied_config(rio, reply_to);
             }
-            Ok(RouterCtlMsg::Config(config)) => handle_config(rio, config),
+            Ok(RouterCtlMsg::Config(config)) => handle_config(rio, config, db),
             Ok(RouterCtlMsg::ConfigHistory(history)) => handle_config_history(rio, history),
             Ok(RouterCtlMsg::IfEvent(ev)) => handle_ifevent(ev, db),
-            Ok(RouterCtlMsg::BgpNeighStatus(bgp_ev)) => handle_bgp_peer_status_change(bgp_ev),
+            Ok(RouterCtlMsg::BgpNeighStatus(bgp_ev)) => handle_bgp_peer_status_change(bgp_ev, db),
+            Ok(RouterCtlMsg::BgpRoutes(update)) => db.policy.update_routes(update),
+            Ok(RouterCtlMsg::MoveCliSock(path, reply_to)) => {
+                handle_move_cli_sock(rio, path, reply_to);
+            }
+            Ok(RouterCtlMsg::GetRoutes(request, reply_to)) => {
+                handle_get_routes(db, &request, reply_to);
+            }
             Err(TryRecvError::Empty) => break,
             Err(e) => {
                 error!("Error receiving from ctl channel {e:?}");
@@ -314,3 +401,79 @@ pub(crate) fn handle_ctl_msg(rio: &mut Rio, db: &mut RoutingDb) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::handle_ifevent;
+    use crate::atable::resolver::AtResolver;
+    use crate::fib::fibtable::FibTableWriter;
+    use crate::interfaces::interface::IfState;
+    use crate::interfaces::tests::build_test_iftable_left_right;
+    use crate::policy::classtable::PolicyClassTableWriter;
+    use crate::rib::vrf::RouteOrigin;
+    use crate::rib::vrf::tests::{build_test_nhop, build_test_route};
+    use crate::routingdb::RoutingDb;
+    use interface_manager::monitor::EthEvent;
+    use lpm::prefix::Prefix;
+    use net::interface::{InterfaceIndex, InterfaceName};
+    use tracing_test::traced_test;
+
+    fn link_event(name: &str, ifindex: u32, up: bool) -> EthEvent {
+        EthEvent {
+            name: InterfaceName::try_from(name).unwrap(),
+            ifindex: InterfaceIndex::try_new(ifindex).unwrap(),
+            ifup: true,
+            iflowerup: up,
+            ifrunning: up,
+            carrier: up,
+            carrierup: 0,
+            carrierdown: 0,
+        }
+    }
+
+    fn oper_state(db: &RoutingDb, ifindex: u32) -> IfState {
+        let iftable = db.iftw.enter().unwrap();
+        let ifindex = InterfaceIndex::try_new(ifindex).unwrap();
+        iftable.get_interface(ifindex).unwrap().oper_state
+    }
+
+    #[traced_test]
+    #[test]
+    fn test_link_state_refreshes_fibs() {
+        let (iftw, _iftr) = build_test_iftable_left_right();
+        let (fibtw, _fibtr) = FibTableWriter::new();
+        let (_resolver, atabler) = AtResolver::new(false);
+        let (policyw, _policyr) = PolicyClassTableWriter::new();
+        let mut db = RoutingDb::new(fibtw, iftw, atabler, policyw);
+
+        /* a connected route over eth0 (ifindex 2) */
+        let prefix = Prefix::expect_from(("10.0.0.0", 24));
+        let route = build_test_route(RouteOrigin::Connected, 0, 1);
+        let nhop = build_test_nhop(None, Some(2), 0, None);
+        let vrf0 = db.vrftable.get_default_vrf_mut();
+        vrf0.add_route_complete(&prefix, route, &[nhop], None, &db.rmac_store, true);
+
+        /* the link of eth1 (ifindex 3), which no route egresses over, goes up */
+        handle_ifevent(link_event("eth1", 3, true), &mut db);
+        assert_eq!(oper_state(&db, 3), IfState::Up);
+        assert!(!logs_contain("Refreshing fib of vrf"));
+
+        /* the link of eth0 goes down, then up again */
+        handle_ifevent(link_event("eth0", 2, false), &mut db);
+        assert_eq!(oper_state(&db, 2), IfState::Down);
+        assert!(logs_contain("Refreshing fib of vrf default"));
+
+        handle_ifevent(link_event("eth0", 2, true), &mut db);
+        assert_eq!(oper_state(&db, 2), IfState::Up);
+        logs_assert(|lines: &[&str]| {
+            match lines
+                .iter()
+                .filter(|line| line.contains("Refreshing fib of vrf default"))
+                .count()
+            {
+                2 => Ok(()),
+                n => Err(format!("Expected 2 refreshes of the fib, got {n}")),
+            }
+        });
+    }
+}
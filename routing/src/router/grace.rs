@@ -0,0 +1,116 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Graceful restart of FRR.
+//!
+//! When FRR restarts or stops talking over the CPI, the routes it had pushed are not flushed.
+//! Instead, they are marked stale and kept in the FIBs for a grace period, so that the dataplane
+//! keeps forwarding in the meantime. Routes that FRR pushes again stop being stale. Those that
+//! are still stale when the grace period expires are removed.
+//!
+//! When the dataplane restarts instead, FRR reconnects with the sync token of the previous
+//! instance. Whatever it pushed since it reconnected is marked stale and a refresh is requested,
+//! and re-requested until FRR acks it. FRR then pushes all of its state again, and the routes it
+//! does not push within the grace period are removed.
+//!
+//! The following metrics are exported:
+//!
+//! - `routing_grace_periods`: the number of grace periods started;
+//! - `routing_stale_routes`: the number of stale routes, during a grace period;
+//! - `routing_stale_routes_flushed`: the number of stale routes removed when grace periods expired.
+
+use crate::router::revent::{ROUTER_EVENTS, RouterEvent, revent};
+use crate::routingdb::RoutingDb;
+
+use metrics::{Counter, Gauge, Unit};
+use stats::{MetricSpec, Register};
+use std::time::{Duration, Instant};
+
+#[allow(unused)]
+use tracing::{debug, error, info, trace, warn};
+
+struct GraceMetrics {
+    periods: Counter,
+    stale: Gauge,
+    flushed: Counter,
+}
+
+impl GraceMetrics {
+    fn new() -> Self {
+        let spec = |id: &str, unit| MetricSpec::new(id, unit, vec![]);
+        Self {
+            periods: spec("routing_grace_periods", Unit::Count).register().metric,
+            stale: spec("routing_stale_routes", Unit::Count).register().metric,
+            flushed: spec("routing_stale_routes_flushed", Unit::Count)
+                .register()
+                .metric,
+        }
+    }
+}
+
+/// The state of the graceful restart of FRR
+pub(crate) struct GracefulRestart {
+    period: Duration,
+    deadline: Option<Instant>,
+    metrics: GraceMetrics,
+}
+
+impl GracefulRestart {
+    pub(crate) fn new(period: Duration) -> Self {
+        Self {
+            period,
+            deadline: None,
+            metrics: GraceMetrics::new(),
+        }
+    }
+
+    /// Tell if a grace period is running
+    pub(crate) fn is_running(&self) -> bool {
+        self.deadline.is_some()
+    }
+
+    /// Mark all routes stale and (re)start the grace period. If a grace period was already
+    /// running, it is extended: FRR gets a full period to push its routes again.
+    pub(crate) fn start(&mut self, db: &mut RoutingDb) {
+        db.vrftable.remove_deleting_vrfs(&mut db.iftw);
+        db.vrftable.set_stale(true);
+        if !self.is_running() {
+            self.metrics.periods.increment(1);
+        }
+        info!("Starting grace period of {} seconds", self.period.as_secs());
+        self.deadline = Instant::now().checked_add(self.period);
+        revent!(RouterEvent::GracePeriodStarted(self.period));
+        self.update_metrics(db);
+    }
+
+    /// Stop the grace period, keeping all routes: FRR came back without losing them.
+    pub(crate) fn cancel(&mut self, db: &mut RoutingDb) {
+        if self.deadline.take().is_some() {
+            info!("Grace period cancelled: routes are no longer stale");
+            db.vrftable.set_stale(false);
+            self.update_metrics(db);
+        }
+    }
+
+    /// Refresh the metrics of the grace period and, if it expired, remove the routes that are
+    /// still stale.
+    pub(crate) fn check(&mut self, db: &mut RoutingDb) {
+        if !self.is_running() {
+            return;
+        }
+        if self.deadline.take_if(|t| *t < Instant::now()).is_some() {
+            let stale = db.vrftable.len_stale();
+            info!("Grace period expired: removing {stale} stale routes");
+            db.vrftable.remove_stale_routes(&db.rmac_store);
+            db.vrftable.remove_deleted_vrfs(&mut db.iftw);
+            self.metrics.flushed.increment(stale as u64);
+            revent!(RouterEvent::GracePeriodExpired(stale));
+        }
+        self.update_metrics(db);
+    }
+
+    fn update_metrics(&self, db: &RoutingDb) {
+        #[allow(clippy::cast_precision_loss)]
+        self.metrics.stale.set(db.vrftable.len_stale() as f64);
+    }
+}
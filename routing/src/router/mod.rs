@@ -3,33 +3,48 @@
 
 //! Module that implements a router instance
 
+pub(crate) mod bulk;
 pub(crate) mod cpi;
 pub(crate) mod ctl;
+pub(crate) mod grace;
 #[macro_use]
 pub(crate) mod revent;
 pub(crate) mod rio;
 pub(crate) mod rpc_adapt;
 
 use common::cliprovider::CliDataProvider;
+use common::generation::GenerationProvider;
+use concurrency::sync::Arc;
+use config::GenId;
 use derive_builder::Builder;
+use kvstore::KvStore;
 use std::fmt::Display;
 use std::path::PathBuf;
+use std::time::Duration;
+use tokio::sync::mpsc;
 use tracing::{debug, error};
 
 // sockets
 use std::net::SocketAddr;
 
 use crate::atable::atablerw::{AtableReader, AtableReaderFactory};
-use crate::atable::resolver::AtResolver;
+use crate::atable::resolver::{AtResolveRequester, AtResolver};
+use crate::bmp::BmpStore;
+use crate::cli::capture::PacketCapture;
+use crate::cli::simulate::FlowSimulator;
 use crate::errors::RouterError;
 use crate::fib::fibtable::{FibTableReader, FibTableReaderFactory, FibTableWriter};
 use crate::interfaces::iftablerw::{IfTableReader, IfTableReaderFactory, IfTableWriter};
+use crate::policy::classtable::{
+    PolicyClassTableReader, PolicyClassTableReaderFactory, PolicyClassTableWriter,
+};
 use crate::router::ctl::RouterCtlSender;
 use crate::router::rio::{RioConf, RioHandle, start_rio};
 
 use args::DEFAULT_DP_UX_PATH;
 use args::DEFAULT_DP_UX_PATH_CLI;
 use args::DEFAULT_FRR_AGENT_PATH;
+use args::DEFAULT_FRR_GRACE_PERIOD;
 
 #[derive(Clone, Debug)]
 pub struct BmpServerParams {
@@ -52,6 +67,14 @@ pub struct RouterParams {
 
     #[builder(setter(into), default = DEFAULT_FRR_AGENT_PATH.to_string().into())]
     pub frr_agent_path: PathBuf,
+
+    /// File with the views of the dataplane that cli sessions may be restricted to
+    #[builder(setter(into), default)]
+    pub cli_views_path: Option<PathBuf>,
+
+    /// Time the routes learned from FRR are kept once FRR restarts or goes away
+    #[builder(default = Duration::from_secs(DEFAULT_FRR_GRACE_PERIOD))]
+    pub frr_grace_period: Duration,
 }
 
 /// Optional struct containing accessors to state outside of routing,
@@ -63,7 +86,22 @@ pub struct CliSources {
     pub portfw_table: Option<Box<dyn CliDataProvider + Send>>,
     pub nat_tables: Option<Box<dyn CliDataProvider + Send>>,
     pub masquerade_state: Option<Box<dyn CliDataProvider + Send>>,
+    pub nat_alg: Option<Box<dyn CliDataProvider + Send>>,
     pub pkt_stats: Option<Box<dyn CliDataProvider + Send>>,
+    pub flow_simulator: Option<Box<dyn FlowSimulator + Send>>,
+    pub packet_capture: Option<Box<dyn PacketCapture + Send>>,
+    pub interfaces: Option<Box<dyn CliDataProvider + Send>>,
+    /// Microbursts detected on the receive queues, which `show microbursts` lists
+    pub microbursts: Option<Box<dyn CliDataProvider + Send>>,
+    /// Store persisting the state learned at runtime, whose keys `show state-store` lists
+    pub state_store: Option<Arc<dyn KvStore>>,
+    /// Recent BMP route monitoring messages, which `bmp dump` dumps
+    pub bmp_store: Option<Arc<BmpStore>>,
+    /// Tables whose generation is shown by `show tables`, by name
+    pub table_generations: Vec<(&'static str, Box<dyn GenerationProvider + Send>)>,
+    /// Requests to roll back to a config applied before, by generation id, which
+    /// `config rollback` sends to the config processor
+    pub config_rollback: Option<mpsc::Sender<GenId>>,
 }
 
 impl Display for RouterParams {
@@ -72,7 +110,12 @@ impl Display for RouterParams {
         writeln!(f, "  name     : {}", self.name)?;
         writeln!(f, "  CPI path : {}", self.cpi_sock_path.display())?;
         writeln!(f, "  CLI path : {}", self.cli_sock_path.display())?;
-        writeln!(f, "  FRR-agent: {}", self.frr_agent_path.display())
+        writeln!(f, "  FRR-agent: {}", self.frr_agent_path.display())?;
+        writeln!(f, "  FRR grace: {}s", self.frr_grace_period.as_secs())?;
+        if let Some(path) = &self.cli_views_path {
+            writeln!(f, "  CLI views: {}", path.display())?;
+        }
+        Ok(())
     }
 }
 
@@ -84,6 +127,7 @@ pub struct Router {
     rio_handle: RioHandle,
     iftr: IfTableReader,
     fibtr: FibTableReader,
+    policyr: PolicyClassTableReader,
 }
 
 impl Router {
@@ -112,6 +156,16 @@ impl Router {
                     .ok_or(RouterError::InvalidPath("(frr-agent path)".to_string()))?
                     .to_owned(),
             ),
+            cli_views_path: params
+                .cli_views_path
+                .as_ref()
+                .map(|path| {
+                    path.to_str()
+                        .map(str::to_owned)
+                        .ok_or(RouterError::InvalidPath("(cli views path)".to_string()))
+                })
+                .transpose()?,
+            grace_period: params.frr_grace_period,
         })
     }
 
@@ -130,6 +184,9 @@ impl Router {
         debug!("{name}: Creating FIB table...");
         let (fibtw, fibtr) = FibTableWriter::new();
 
+        debug!("{name}: Creating policy class table...");
+        let (policyw, policyr) = PolicyClassTableWriter::new();
+
         debug!("{name}: Creating Adjacency resolver...");
         let (mut resolver, atabler) = AtResolver::new(true);
         resolver.start(3);
@@ -138,7 +195,7 @@ impl Router {
         let rioconf = Self::build_rio_config(&params)?;
 
         debug!("{name}: Starting router IO...");
-        let rio_handle = start_rio(router, &rioconf, fibtw, iftw, atabler, cli_sources)?;
+        let rio_handle = start_rio(router, &rioconf, fibtw, iftw, atabler, policyw, cli_sources)?;
         debug!("{name}: Successfully started router with parameters:\n{params}");
         let router = Router {
             name: name.to_owned(),
@@ -147,6 +204,7 @@ impl Router {
             rio_handle,
             iftr,
             fibtr,
+            policyr,
         };
         Ok(router)
     }
@@ -172,6 +230,11 @@ impl Router {
         self.resolver.get_reader().factory()
     }
 
+    #[must_use]
+    pub fn get_atable_requester(&self) -> AtResolveRequester {
+        self.resolver.get_requester()
+    }
+
     #[must_use]
     pub fn get_iftabler_factory(&self) -> IfTableReaderFactory {
         self.iftr.factory()
@@ -182,6 +245,11 @@ impl Router {
         self.fibtr.factory()
     }
 
+    #[must_use]
+    pub fn get_policy_classr_factory(&self) -> PolicyClassTableReaderFactory {
+        self.policyr.factory()
+    }
+
     #[must_use]
     pub fn get_ctl_tx(&self) -> RouterCtlSender {
         self.rio_handle.get_ctl_tx()
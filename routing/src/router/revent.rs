@@ -10,15 +10,21 @@ use crate::event::EventLog;
 use crate::router::cpi::CpiStatus;
 
 use config::GenId;
+use config::internal::events::DataplaneEvent;
 use config::internal::status::BgpNeighborSessionState;
 use interface_manager::monitor::EthEvent;
 use std::cell::RefCell;
 use std::fmt::Display;
+use std::time::Duration;
 
 pub enum RouterEvent {
     Started,
     CpiStatusChange(CpiStatus),
     CpiRefreshRequested,
+    GracePeriodStarted(Duration),
+    GracePeriodExpired(usize),
+    BulkSyncStarted,
+    BulkSyncComplete(u64),
 
     GotConfigRequest(GenId),
     ConfigSuceeded(GenId),
@@ -44,6 +50,16 @@ impl Display for RouterEvent {
             RouterEvent::Started => write!(f, "Started!")?,
             RouterEvent::CpiStatusChange(status) => write!(f, "CPI status changed to {status}")?,
             RouterEvent::CpiRefreshRequested => write!(f, "Requested refresh to FRR")?,
+            RouterEvent::GracePeriodStarted(period) => {
+                write!(f, "Routes marked stale for {} seconds", period.as_secs())?;
+            }
+            RouterEvent::GracePeriodExpired(stale) => {
+                write!(f, "Grace period expired: removed {stale} stale routes")?;
+            }
+            RouterEvent::BulkSyncStarted => write!(f, "Started bulk download of routes")?,
+            RouterEvent::BulkSyncComplete(routes) => {
+                write!(f, "Bulk download of routes complete: {routes} routes")?;
+            }
 
             RouterEvent::GotConfigRequest(genid) => {
                 write!(f, "Router config request received for generation {genid}")?;
@@ -108,12 +124,35 @@ impl Display for RouterEvent {
     }
 }
 
+impl RouterEvent {
+    /// The event of the dataplane to publish for this event of the router, if any
+    pub(crate) fn dataplane_event(&self) -> Option<DataplaneEvent> {
+        match self {
+            RouterEvent::FrrmiConnectSucceeded => {
+                Some(DataplaneEvent::FrrAgent { connected: true })
+            }
+            RouterEvent::FrrmiDisconnected => Some(DataplaneEvent::FrrAgent { connected: false }),
+            RouterEvent::BgpNeighStateChange(bgp_ev) => Some(DataplaneEvent::BgpSession {
+                neighbor: bgp_ev.peer_key.clone(),
+                asn: bgp_ev.peer_asn,
+                state: bgp_ev.new,
+            }),
+            _ => None,
+        }
+    }
+}
+
 make_event_log!(ROUTER_EVENTS, RouterEvent, 2000);
 
+/// Record an event of the router, and publish it if it is one of the dataplane
 macro_rules! revent {
-    ($item:expr) => {
-        ROUTER_EVENTS.with(|evlog| evlog.borrow_mut().add($item))
-    };
+    ($item:expr) => {{
+        let item = $item;
+        if let Some(event) = item.dataplane_event() {
+            config::internal::events::publish(event);
+        }
+        ROUTER_EVENTS.with(|evlog| evlog.borrow_mut().add(item))
+    }};
 }
 
 pub(crate) use revent;
@@ -5,21 +5,26 @@
 
 use crate::atable::atablerw::AtableReader;
 use crate::cli::handler::handle_cli_request;
+use crate::cli::session::CliViews;
 use crate::config::FrrConfig;
 use crate::errors::RouterError;
 use crate::fib::fibtable::FibTableWriter;
 use crate::frr::frrmi::{FrrErr, Frrmi, FrrmiRequest};
 use crate::interfaces::iftablerw::IfTableWriter;
+use crate::policy::classtable::PolicyClassTableWriter;
 
 use crate::router::CliSources;
-use crate::router::cpi::{CpiStats, CpiStatus, process_cpi_data, rpc_send_control};
+use crate::router::cpi::{
+    CPI_HOLD_TIME, CPI_REFRESH_RETRY, CpiStats, CpiStatus, process_cpi_data, rpc_send_control,
+};
 use crate::router::ctl::{RouterCtlMsg, RouterCtlSender, handle_ctl_msg};
+use crate::router::grace::GracefulRestart;
 use crate::router::revent::{ROUTER_EVENTS, RouterEvent};
 use crate::routingdb::RoutingDb;
 
 use bytes::BytesMut;
 use cli::IoCache;
-use cli::cliproto::{CLI_RX_BUFF_SIZE, CliRequest};
+use cli::cliproto::{CLI_RX_BUFF_SIZE, CliLocalError, CliRequest, CliResponse};
 use config::{GwConfigMeta, ValidatedGwConfig};
 use dplane_rpc::socks::RpcCachedSock;
 use inotify::{EventMask, Inotify, WatchMask};
@@ -32,13 +37,15 @@ use mio::{Events, Interest, Poll, Token, Waker};
 
 use concurrency::sync::Arc;
 use concurrency::thread::{self, JoinHandle};
-use nix::sys::socket::{getsockopt, setsockopt, sockopt::SndBuf};
+use nix::sys::socket::sockopt::{PassCred, SndBuf};
+use nix::sys::socket::{getsockopt, setsockopt};
 use std::fs;
 use std::os::unix::fs::PermissionsExt;
 use std::os::unix::net::UnixDatagram;
 use std::time::{Duration, Instant};
 use tokio::sync::mpsc::{Receiver, Sender, channel};
 
+use tracing::Span;
 #[allow(unused)]
 use tracing::{debug, error, info, trace, warn};
 
@@ -82,16 +89,22 @@ pub(crate) struct RioConf {
     pub cpi_sock_path: Option<String>,
     pub cli_sock_path: Option<String>,
     pub frrmi_sock_path: Option<String>,
+    pub cli_views_path: Option<String>,
+    pub grace_period: Duration,
 }
 
 fn open_unix_sock(path: &String) -> Result<UnixDatagram, RouterError> {
+    open_unix_sock_mode(path, 0o777)
+}
+
+fn open_unix_sock_mode(path: &String, mode: u32) -> Result<UnixDatagram, RouterError> {
     debug!("Opening UNIX sock; target bind point is {path}");
     let _ = std::fs::remove_file(path);
     let sock = UnixDatagram::bind(path).map_err(|_| RouterError::InvalidPath(path.to_owned()))?;
     let mut perms = fs::metadata(path)
         .map_err(|_| RouterError::Internal("Failure retrieving socket metadata"))?
         .permissions();
-    perms.set_mode(0o777);
+    perms.set_mode(mode);
     fs::set_permissions(path, perms).map_err(|_| RouterError::PermError)?;
     sock.set_nonblocking(true)
         .map_err(|_| RouterError::Internal("Failure setting non-blocking socket"))?;
@@ -99,10 +112,13 @@ fn open_unix_sock(path: &String) -> Result<UnixDatagram, RouterError> {
     Ok(sock)
 }
 
-fn open_cli_sock(path: &String) -> Result<UnixDatagram, RouterError> {
-    let sock = open_unix_sock(path)?;
+fn open_cli_sock(path: &String, views: &CliViews) -> Result<UnixDatagram, RouterError> {
+    let sock = open_unix_sock_mode(path, views.sock_mode())?;
     setsockopt(&sock, SndBuf, &CLI_RX_BUFF_SIZE)
         .map_err(|_| RouterError::Internal("Failure setting snd buffer size"))?;
+    // get the credentials of the senders of requests, to scope their sessions
+    setsockopt(&sock, PassCred, &true)
+        .map_err(|_| RouterError::Internal("Failure enabling credential passing"))?;
     if let Ok(size) = getsockopt(&sock, SndBuf) {
         debug!("Cli sock send buffer set to {size}");
     }
@@ -151,10 +167,11 @@ pub(crate) struct Rio {
     pub(crate) ctl_rx: Receiver<RouterCtlMsg>,
     pub(crate) waker: Arc<Waker>,
     pub(crate) cpistats: CpiStats,
-    stale_timeout: Option<Instant>,
+    grace: GracefulRestart,
     pub(crate) gwconfig: Option<Arc<ValidatedGwConfig>>,
     pub(crate) cfg_history: Arc<Vec<GwConfigMeta>>,
     pub(crate) cli_cache: IoCache,
+    pub(crate) cli_views: CliViews,
     pub(crate) inotify: Inotify,
 }
 impl Rio {
@@ -180,8 +197,14 @@ impl Rio {
         /* create unix sock for routing function and bind it */
         let cpsock = open_unix_sock(&cp_sock_path)?;
 
+        /* views of the dataplane which cli sessions may be restricted to */
+        let cli_views = match &conf.cli_views_path {
+            Some(path) => CliViews::load(Path::new(path))?,
+            None => CliViews::default(),
+        };
+
         /* create unix sock for cli and bind it */
-        let clisock = open_cli_sock(&cli_sock_path)?;
+        let clisock = open_cli_sock(&cli_sock_path, &cli_views)?;
 
         /* add a watcher to the cli sock directory to detect if the file is removed */
         let inotify = setup_clipath_watcher(&cli_sock_path)?;
@@ -240,10 +263,11 @@ impl Rio {
             ctl_rx,
             waker,
             cpistats: CpiStats::new(),
-            stale_timeout: None,
+            grace: GracefulRestart::new(conf.grace_period),
             gwconfig: None,
             cfg_history: Arc::from(vec![]),
             cli_cache: IoCache::new(),
+            cli_views,
             inotify,
         })
     }
@@ -255,7 +279,7 @@ impl Rio {
         let _ = self.clisock.shutdown(std::net::Shutdown::Both);
 
         // open new sock, bind it and register it
-        let Ok(new_sock) = open_cli_sock(&self.cli_sock_path) else {
+        let Ok(new_sock) = open_cli_sock(&self.cli_sock_path, &self.cli_views) else {
             error!("Failed to open CLI sock");
             return;
         };
@@ -264,6 +288,33 @@ impl Rio {
         debug!("CLI socket restored at {}", self.cli_sock_path);
     }
 
+    /// Move the CLI socket to another path. The socket at the former path is removed, and the
+    /// directory of the new one watched instead. On failure, the CLI socket is left in place.
+    pub(crate) fn cli_sock_move(&mut self, path: String) -> Result<(), RouterError> {
+        if path == self.cli_sock_path {
+            return Ok(());
+        }
+        let inotify = setup_clipath_watcher(&path)?;
+        let clisock = open_cli_sock(&path, &self.cli_views)?;
+
+        self.deregister(self.clisock.as_raw_fd());
+        self.deregister(self.inotify.as_raw_fd());
+        let _ = self.clisock.shutdown(std::net::Shutdown::Both);
+        let _ = fs::remove_file(&self.cli_sock_path);
+
+        self.clisock = clisock;
+        self.inotify = inotify;
+        self.register(CLISOCK, self.clisock.as_raw_fd(), Interest::READABLE);
+        self.register(
+            CLIPATH_WATCHER,
+            self.inotify.as_raw_fd(),
+            Interest::READABLE,
+        );
+        info!("CLI: moved from {} to {path}", self.cli_sock_path);
+        self.cli_sock_path = path;
+        Ok(())
+    }
+
     pub(crate) fn register(&self, token: Token, fd: i32, interests: Interest) {
         debug!("Registering fd {fd}...");
         let mut ev_sock = SourceFd(&fd);
@@ -338,15 +389,15 @@ impl Rio {
             }
         }
     }
-    pub(crate) fn request_frr_config(&mut self, genid: i64, cfg: FrrConfig) {
-        let req = FrrmiRequest::new(genid, cfg, 0);
+    pub(crate) fn request_frr_config(&mut self, genid: i64, cfg: FrrConfig, span: Span) {
+        let req = FrrmiRequest::new(genid, cfg, 0).in_span(span);
         self.frrmi.queue_request(req);
     }
     /// Request to reapply the last configuration
     pub(crate) fn reapply_frr_config(&mut self, db: &RoutingDb) {
         if let Some(rconfig) = &db.config {
             if let Some(frr_cfg) = rconfig.get_frr_config() {
-                self.request_frr_config(rconfig.genid(), frr_cfg.clone());
+                self.request_frr_config(rconfig.genid(), frr_cfg.clone(), Span::none());
             }
         }
     }
@@ -354,12 +405,13 @@ impl Rio {
     /// Check the status of the CPI and react accordingly
     pub(crate) fn cpi_status_check(&mut self, db: &mut RoutingDb) {
         match self.cpistats.status {
-            CpiStatus::NotConnected | CpiStatus::Connected | CpiStatus::Incompatible => {}
+            CpiStatus::NotConnected
+            | CpiStatus::Connected
+            | CpiStatus::Incompatible
+            | CpiStatus::Disconnected => {}
             CpiStatus::FrrRestarted => {
                 warn!("FRR appears to have restarted!!!...");
-                db.vrftable.remove_deleting_vrfs(&mut db.iftw);
-                db.vrftable.set_stale(true);
-                self.set_stale_timeout();
+                self.grace.start(db);
                 debug!("Will now re-apply the last config to FRR...");
                 self.frrmi.clear_applied_cfg(); /* we know Frr has no config */
                 self.reapply_frr_config(db); /* request agent to apply last config */
@@ -367,29 +419,48 @@ impl Rio {
             }
             CpiStatus::NeedRefresh => {
                 warn!("We appear to have restarted. Requesting refresh to FRR...");
-                if let Some(peer) = &self.cpistats.peer {
-                    rpc_send_control(&mut self.cpi_sock, peer, true);
-                    revent!(RouterEvent::CpiRefreshRequested);
+                if self.cpistats.peer.is_some() {
+                    // whatever FRR pushed since it reconnected may be outdated: keep it stale
+                    // until FRR pushes it again within the grace period.
+                    self.grace.start(db);
+                    self.cpi_request_refresh();
                     self.cpistats.status.change(CpiStatus::Connected);
                 }
             }
+            CpiStatus::Resumed => {
+                info!("FRR is back and did not restart. Keeping its routes...");
+                self.grace.cancel(db);
+                self.cpistats.status.change(CpiStatus::Connected);
+            }
         }
     }
-    fn set_stale_timeout(&mut self) {
-        let duration = 60;
-        debug!("Set stale timeout ({duration} seconds)");
-        let duration = Duration::from_secs(duration);
-        self.stale_timeout = Instant::now().checked_add(duration);
+    /// Request FRR to push all of its state again
+    fn cpi_request_refresh(&mut self) {
+        if let Some(peer) = &self.cpistats.peer {
+            rpc_send_control(&mut self.cpi_sock, peer, true);
+            self.cpistats.refresh_requested = Some(Instant::now());
+            revent!(RouterEvent::CpiRefreshRequested);
+        }
     }
-    fn check_stale_timeout(&mut self, db: &mut RoutingDb) {
-        if self
-            .stale_timeout
-            .take_if(|t| *t < Instant::now())
-            .is_some()
+    /// Request a refresh again if FRR did not ack the last request
+    fn cpi_refresh_check(&mut self) {
+        if self.cpistats.status == CpiStatus::Connected
+            && self.cpistats.refresh_unacked(CPI_REFRESH_RETRY)
         {
-            info!("Stale timeout expired");
-            db.vrftable.remove_stale_routes(&db.rmac_store);
-            db.vrftable.remove_deleted_vrfs(&mut db.iftw);
+            warn!("FRR did not ack the refresh request. Requesting it again...");
+            self.cpi_request_refresh();
+        }
+    }
+    /// Check that FRR is still there. If it went silent, keep its routes as stale for the grace
+    /// period, rather than flushing them.
+    fn cpi_liveness_check(&mut self, db: &mut RoutingDb) {
+        if self.cpistats.status == CpiStatus::Connected && self.cpistats.is_silent(CPI_HOLD_TIME) {
+            warn!(
+                "Nothing received from FRR for {} seconds!!!...",
+                CPI_HOLD_TIME.as_secs()
+            );
+            self.cpistats.status.change(CpiStatus::Disconnected);
+            self.grace.start(db);
         }
     }
     fn cli_wake_on_writeable(&self, writeable: bool) {
@@ -428,6 +499,7 @@ pub(crate) fn start_rio(
     fibtw: FibTableWriter,
     iftw: IfTableWriter,
     atabler: AtableReader,
+    policyw: PolicyClassTableWriter,
     cli_sources: Option<CliSources>,
 ) -> Result<RioHandle, RouterError> {
     let mut rio = Rio::new(conf)?;
@@ -452,7 +524,7 @@ pub(crate) fn start_rio(
         let mut cpi_buf = BytesMut::with_capacity(2048);
 
         /* create routing database: this is fully owned by the CPI */
-        let mut db = RoutingDb::new(fibtw, iftw, atabler);
+        let mut db = RoutingDb::new(fibtw, iftw, atabler, policyw);
 
         revent!(RouterEvent::Started);
 
@@ -507,13 +579,24 @@ pub(crate) fn start_rio(
                             }
                         }
                         while event.is_readable() {
-                            if let Ok((peer, request)) = CliRequest::recv(&rio.clisock) {
-                                handle_cli_request(&mut rio, &peer, request, &db, &cli_sources);
-                                if !rio.cli_cache.is_empty() {
-                                    rio.cli_wake_on_writeable(true);
+                            match CliRequest::recv_from_peer(&rio.clisock) {
+                                Ok((peer, request)) => {
+                                    handle_cli_request(&mut rio, &peer, request, &db, &cli_sources);
                                 }
-                            } else {
-                                break;
+                                Err(CliLocalError::UnsupportedVersion(peer, version)) => {
+                                    warn!("Got cli request of protocol version {version}");
+                                    let sock = &rio.clisock;
+                                    let cache = &mut rio.cli_cache;
+                                    if let Err(e) =
+                                        CliResponse::send_version(&peer.addr, sock, cache)
+                                    {
+                                        error!("Failed to send response: {e}");
+                                    }
+                                }
+                                Err(_) => break,
+                            }
+                            if !rio.cli_cache.is_empty() {
+                                rio.cli_wake_on_writeable(true);
                             }
                         }
                     }
@@ -565,8 +648,17 @@ pub(crate) fn start_rio(
                 }
             }
 
-            /* check stale timeout. If expired, remove stale routes */
-            rio.check_stale_timeout(&mut db);
+            /* check that FRR is alive. If it went silent, routes become stale */
+            rio.cpi_liveness_check(&mut db);
+
+            /* request a refresh again if FRR did not ack it */
+            rio.cpi_refresh_check();
+
+            /* check the grace period. If expired, remove stale routes */
+            rio.grace.check(&mut db);
+
+            /* complete the initial download of routes, if FRR settled */
+            db.bulk_sync_check();
 
             /* remove stale router mac entries (if aged). If rmacs were deleted, refresh the
             fibs for the vrfs with the corresponding vnis */
@@ -618,6 +710,8 @@ mod tests {
             cpi_sock_path: Some(cpi_bind_addr),
             cli_sock_path: Some(cli_bind_addr),
             frrmi_sock_path: Some(frra_path),
+            cli_views_path: None,
+            grace_period: Duration::from_secs(60),
         };
 
         /* create interface table */
@@ -645,6 +739,8 @@ mod tests {
             cpi_sock_path: Some("/nonexistent/hh_dataplane.sock".to_string()),
             cli_sock_path: None,
             frrmi_sock_path: None,
+            cli_views_path: None,
+            grace_period: Duration::from_secs(60),
         };
 
         /* create interface table */
@@ -187,6 +187,7 @@ impl Vrf {
         vrf0: Option<&Vrf>,
         rstore: &RmacStore,
         iftabler: &IfTableReader,
+        publish: bool,
     ) {
         let Ok(prefix) = Prefix::try_from((iproute.prefix, iproute.prefix_len)) else {
             error!(
@@ -211,7 +212,7 @@ impl Vrf {
             }
         }
         // N.B. route and next-hops are passed separately
-        self.add_route_complete(&prefix, route, &nhops, vrf0, rstore);
+        self.add_route_complete(&prefix, route, &nhops, vrf0, rstore, publish);
     }
     pub fn del_route_rpc(&mut self, iproute: &IpRoute, vrf0: Option<&Vrf>, rstore: &RmacStore) {
         let Ok(prefix) = Prefix::try_from((iproute.prefix, iproute.prefix_len)) else {
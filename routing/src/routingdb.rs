@@ -8,7 +8,10 @@ use crate::config::RouterConfig;
 use crate::evpn::{RmacStore, Vtep};
 use crate::fib::fibtable::FibTableWriter;
 use crate::interfaces::iftablerw::IfTableWriter;
+use crate::policy::classtable::PolicyClassTableWriter;
+use crate::policy::learned::CommunityPolicy;
 use crate::rib::vrftable::VrfTable;
+use crate::router::bulk::BulkSync;
 use tracing::debug;
 
 /// Routing database
@@ -18,25 +21,47 @@ pub(crate) struct RoutingDb {
     pub vtep: Vtep,
     pub atabler: AtableReader,
     pub iftw: IfTableWriter,
+    pub policy: CommunityPolicy,
     pub config: Option<RouterConfig>,
+    pub bulk: BulkSync,
 }
 
 #[allow(clippy::new_without_default)]
 impl RoutingDb {
     #[must_use]
-    pub fn new(fibtable: FibTableWriter, iftw: IfTableWriter, atabler: AtableReader) -> Self {
+    pub fn new(
+        fibtable: FibTableWriter,
+        iftw: IfTableWriter,
+        atabler: AtableReader,
+        policyw: PolicyClassTableWriter,
+    ) -> Self {
         Self {
             vrftable: VrfTable::new(fibtable),
             rmac_store: RmacStore::new(),
             vtep: Vtep::new(),
             atabler,
             iftw,
+            policy: CommunityPolicy::new(policyw),
             config: None,
+            bulk: BulkSync::new(),
         }
     }
     pub fn set_config(&mut self, config: RouterConfig) {
         debug!("Storing router config for gen {}", config.genid());
         self.config = Some(config);
+        self.bulk.touch();
+    }
+    /// Expose the routes staged during the initial download of routes
+    pub fn publish_staged_routes(&mut self) {
+        self.vrftable.publish_fibs(&self.rmac_store);
+        self.bulk.published();
+    }
+    /// Complete the initial download of routes if due, publishing the routes staged
+    pub fn bulk_sync_check(&mut self) {
+        if self.bulk.is_done(self.have_config()) {
+            self.publish_staged_routes();
+            self.bulk.complete();
+        }
     }
     #[must_use]
     pub fn have_config(&self) -> bool {
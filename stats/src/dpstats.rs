@@ -27,7 +27,7 @@ use tracing::{debug, info};
 #[allow(unused)]
 use tracing::{error, trace, warn};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub struct VpcMapName {
     disc: VpcDiscriminant,
     name: String,
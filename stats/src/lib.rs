@@ -17,5 +17,8 @@ pub use spec::*;
 pub use vpc::*;
 pub use vpc_stats::*;
 
+/// Re-exported, as the crates depending on the stats one instrument their tables with them
+pub use common::publish::{InstrumentedWriteHandle, PublishMetrics};
+
 use tracectl::trace_target;
 trace_target!("dp-stats", LevelFilter::WARN, &[]);
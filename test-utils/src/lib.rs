@@ -9,6 +9,10 @@
 //! Plain `cargo test` keeps the cargo default of unwind, and the nix
 //! test archive build forces unwind via `for-tests` in `nix/profiles.nix`.
 
+#[cfg(feature = "scale")]
+pub mod scale;
+pub mod topology;
+
 use caps::{CapSet, Capability};
 use rtnetlink::NetworkNamespace;
 use std::panic::{RefUnwindSafe, UnwindSafe, catch_unwind};
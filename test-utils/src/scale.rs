@@ -0,0 +1,275 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Generators of interface requirements at scale.
+//!
+//! A [`Scale`] describes a number of VRFs, each with some bridges, each with some VTEPs attached to
+//! it. [`Scale::generate`] produces the matching interface specs and associations, which stress
+//! tests and benchmarks feed to the reconciler to catch behaviors which are quadratic (or worse)
+//! in the number of interfaces.
+
+use interface_manager::interface::{
+    BridgePropertiesSpec, InterfaceAssociationSpec, InterfacePropertiesSpec, InterfaceSpec,
+    MultiIndexInterfaceAssociationSpecMap, MultiIndexInterfaceSpecMap,
+    MultiIndexVrfPropertiesSpecMap, MultiIndexVtepPropertiesSpecMap, VrfPropertiesSpec,
+    VtepPropertiesSpec,
+};
+use interface_manager::offload::OffloadSpec;
+use net::eth::ethtype::EthType;
+use net::interface::{
+    AdminState, BridgeProperties, Interface, InterfaceIndex, InterfaceName, InterfaceProperties,
+    MultiIndexInterfaceMap, MultiIndexVrfPropertiesMap, MultiIndexVtepPropertiesMap,
+    OperationalState, VrfProperties, VtepProperties,
+};
+use net::ipv4::UnicastIpv4Addr;
+use net::route::RouteTableId;
+use net::vxlan::{Vni, Vxlan};
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+
+/// The route table of the first generated VRF, well clear of the tables reserved by linux.
+const FIRST_ROUTE_TABLE: u32 = 1000;
+
+/// The local address of the generated VTEPs.
+const VTEP_LOCAL: Ipv4Addr = Ipv4Addr::new(192, 168, 5, 155);
+
+/// The TTL of the generated VTEPs.
+const VTEP_TTL: u8 = 64;
+
+/// The shape of a generated set of interface requirements.
+///
+/// Interfaces are named after their kind and a sequence number (`vrf0`, `br0`, `vtep0`, ...), so
+/// that names stay short whatever the scale.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Scale {
+    /// The number of VRFs.
+    pub vrfs: u32,
+    /// The number of bridges in each VRF.
+    pub bridges_per_vrf: u32,
+    /// The number of VTEPs attached to each bridge.
+    pub vteps_per_bridge: u32,
+}
+
+/// Interface requirements generated by [`Scale::generate`].
+///
+/// The fields match those of the required information base of the reconciler.
+#[derive(Clone, Debug, Default)]
+pub struct ScaledRequirements {
+    /// All the interfaces.
+    pub interfaces: MultiIndexInterfaceSpecMap,
+    /// The properties of the VRFs.
+    pub vrfs: MultiIndexVrfPropertiesSpecMap,
+    /// The properties of the VTEPs.
+    pub vteps: MultiIndexVtepPropertiesSpecMap,
+    /// The bridges in VRFs, the VTEPs in bridges, and the VRFs in nothing.
+    pub associations: MultiIndexInterfaceAssociationSpecMap,
+}
+
+/// The observation of a system in which some [`ScaledRequirements`] are met.
+///
+/// The fields match those of the observed information base of the reconciler.
+#[derive(Clone, Debug, Default)]
+pub struct ScaledObservations {
+    /// All the interfaces.
+    pub interfaces: MultiIndexInterfaceMap,
+    /// The properties of the VRFs.
+    pub vrfs: MultiIndexVrfPropertiesMap,
+    /// The properties of the VTEPs.
+    pub vteps: MultiIndexVtepPropertiesMap,
+}
+
+impl Scale {
+    /// Describe a set of requirements.
+    #[must_use]
+    pub const fn new(vrfs: u32, bridges_per_vrf: u32, vteps_per_bridge: u32) -> Self {
+        Self {
+            vrfs,
+            bridges_per_vrf,
+            vteps_per_bridge,
+        }
+    }
+
+    /// The total number of interfaces at this scale.
+    #[must_use]
+    pub fn interfaces(&self) -> usize {
+        let bridges = u64::from(self.vrfs) * u64::from(self.bridges_per_vrf);
+        let vteps = bridges * u64::from(self.vteps_per_bridge);
+        usize::try_from(u64::from(self.vrfs) + bridges + vteps).unwrap_or(usize::MAX)
+    }
+
+    /// Generate the interface requirements at this scale.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the scale exceeds the number of legal VNIs or route table ids.
+    #[must_use]
+    pub fn generate(&self) -> ScaledRequirements {
+        let local = UnicastIpv4Addr::try_from(VTEP_LOCAL).unwrap_or_else(|_| unreachable!());
+        let mut generated = ScaledRequirements::default();
+        let mut bridge_seq = 0u32;
+        let mut vtep_seq = 0u32;
+        for vrf_seq in 0..self.vrfs {
+            let route_table_id = FIRST_ROUTE_TABLE
+                .checked_add(vrf_seq)
+                .and_then(|id| RouteTableId::try_from(id).ok())
+                .unwrap_or_else(|| panic!("too many vrfs: {}", self.vrfs));
+            let vrf = generated.insert(
+                format!("vrf{vrf_seq}"),
+                InterfacePropertiesSpec::Vrf(VrfPropertiesSpec { route_table_id }),
+                None,
+            );
+            for _ in 0..self.bridges_per_vrf {
+                let bridge = generated.insert(
+                    format!("br{bridge_seq}"),
+                    InterfacePropertiesSpec::Bridge(BridgePropertiesSpec {
+                        vlan_filtering: false,
+                        vlan_protocol: EthType::VLAN,
+                    }),
+                    Some(&vrf),
+                );
+                bridge_seq += 1;
+                for _ in 0..self.vteps_per_bridge {
+                    let vni = Vni::new_checked(vtep_seq + 1)
+                        .unwrap_or_else(|e| panic!("too many vteps: {e}"));
+                    generated.insert(
+                        format!("vtep{vtep_seq}"),
+                        InterfacePropertiesSpec::Vtep(VtepPropertiesSpec {
+                            vni,
+                            local,
+                            ttl: VTEP_TTL,
+                            port: Vxlan::PORT,
+                        }),
+                        Some(&bridge),
+                    );
+                    vtep_seq += 1;
+                }
+            }
+        }
+        generated
+    }
+}
+
+impl ScaledRequirements {
+    /// Add an interface, and its association with its controller, returning its name.
+    fn insert(
+        &mut self,
+        name: String,
+        properties: InterfacePropertiesSpec,
+        controller: Option<&InterfaceName>,
+    ) -> InterfaceName {
+        let name = InterfaceName::try_from(name)
+            .unwrap_or_else(|e| unreachable!("illegal generated interface name: {e}"));
+        match &properties {
+            InterfacePropertiesSpec::Vrf(props) => {
+                self.vrfs.insert(props.clone());
+            }
+            InterfacePropertiesSpec::Vtep(props) => {
+                self.vteps.insert(props.clone());
+            }
+            _ => {}
+        }
+        self.associations.insert(InterfaceAssociationSpec {
+            name: name.clone(),
+            controller_name: controller.cloned(),
+        });
+        self.interfaces.insert(InterfaceSpec {
+            name: name.clone(),
+            mac: None,
+            mtu: None,
+            admin_state: AdminState::Up,
+            controller: None,
+            properties,
+            offloads: OffloadSpec::default(),
+            netns: None,
+        });
+        name
+    }
+
+    /// Build the observation of a system in which these requirements are met, with made-up
+    /// interface indexes. Reconciling the requirements against it requires no operation.
+    #[must_use]
+    pub fn observed(&self) -> ScaledObservations {
+        let mut indexes = HashMap::with_capacity(self.interfaces.len());
+        for (seq, (_, spec)) in (1u32..).zip(self.interfaces.iter()) {
+            let index = InterfaceIndex::try_new(seq).unwrap_or_else(|_| unreachable!());
+            indexes.insert(spec.name.clone(), index);
+        }
+        let mut observed = ScaledObservations::default();
+        for (_, spec) in self.interfaces.iter() {
+            let controller = self
+                .associations
+                .get_by_name(&spec.name)
+                .and_then(|association| association.controller_name.as_ref())
+                .and_then(|controller| indexes.get(controller).copied());
+            let properties = match &spec.properties {
+                InterfacePropertiesSpec::Bridge(props) => {
+                    InterfaceProperties::Bridge(BridgeProperties {
+                        vlan_filtering: props.vlan_filtering,
+                        vlan_protocol: props.vlan_protocol,
+                    })
+                }
+                InterfacePropertiesSpec::Vrf(props) => {
+                    let props = VrfProperties {
+                        route_table_id: props.route_table_id,
+                    };
+                    observed.vrfs.insert(props.clone());
+                    InterfaceProperties::Vrf(props)
+                }
+                InterfacePropertiesSpec::Vtep(props) => {
+                    let props = VtepProperties {
+                        vni: Some(props.vni),
+                        local: Some(props.local),
+                        ttl: Some(props.ttl),
+                    };
+                    observed.vteps.insert(props.clone());
+                    InterfaceProperties::Vtep(props)
+                }
+                InterfacePropertiesSpec::Tap => InterfaceProperties::Tap,
+                InterfacePropertiesSpec::Pci(_) => unreachable!("pci interfaces are not generated"),
+            };
+            observed.interfaces.insert(Interface {
+                index: indexes[&spec.name],
+                name: spec.name.clone(),
+                mac: spec.mac,
+                mtu: spec.mtu,
+                admin_state: spec.admin_state,
+                operational_state: OperationalState::Up,
+                carrier: Some(true),
+                speed: None,
+                controller,
+                properties,
+            });
+        }
+        observed
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_generate() {
+        let scale = Scale::new(10, 3, 2);
+        let generated = scale.generate();
+        assert_eq!(generated.interfaces.len(), scale.interfaces());
+        assert_eq!(generated.interfaces.len(), 10 + 30 + 60);
+        assert_eq!(generated.vrfs.len(), 10);
+        assert_eq!(generated.vteps.len(), 60);
+        assert_eq!(generated.associations.len(), generated.interfaces.len());
+        for (_, association) in generated.associations.iter() {
+            if let Some(controller) = &association.controller_name {
+                assert!(generated.interfaces.get_by_name(controller).is_some());
+            }
+        }
+
+        let observed = generated.observed();
+        assert_eq!(observed.interfaces.len(), generated.interfaces.len());
+        for (_, spec) in generated.interfaces.iter() {
+            let interface = observed.interfaces.get_by_name(&spec.name).unwrap();
+            let mut spec = spec.clone();
+            spec.controller = interface.controller;
+            assert!(spec == *interface, "{spec:?} != {interface:?}");
+        }
+    }
+}
@@ -0,0 +1,437 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Declarative test topologies.
+//!
+//! A [`Topology`] is a set of machines, each in a network namespace of its own, connected by veth
+//! links with addresses, and each optionally running FRR with a configuration rendered from a
+//! template. Topologies are described with a [`TopologyBuilder`] and materialized with
+//! [`TopologyBuilder::build`]. Dropping the [`Topology`] tears everything down, when the test
+//! panics as well:
+//!
+//! ```ignore
+//! let mut topology = TopologyBuilder::new("bgp")
+//!     .machine(Machine::new("r1").loopback("10.255.0.1").frr(BGP_TEMPLATE))
+//!     .machine(Machine::new("r2").loopback("10.255.0.2").frr(BGP_TEMPLATE))
+//!     .link(("r1", "eth0", "10.0.0.0/31"), ("r2", "eth0", "10.0.0.1/31"))
+//!     .build();
+//! topology.start_frr(&["bgpd"]);
+//! topology.machine("r1").exec(&["ping", "-c1", "10.0.0.1"]);
+//! ```
+//!
+//! In FRR templates, `{name}` is replaced with the name of the machine, `{router_id}` with its
+//! loopback address (or the first address of its links if it has none), and `{<interface>}` with
+//! the address of the interface of the machine, with its prefix length.
+//!
+//! Topologies are materialized with `ip` (iproute2), which creates the two ends of a link in their
+//! namespaces at once. This requires `CAP_SYS_ADMIN` and `CAP_NET_ADMIN`, as the other fixtures of
+//! this crate do, and the FRR daemons to be installed in [`FRR_DAEMONS_DIR`] to run FRR.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Output, Stdio};
+use tracing::{error, info};
+
+/// Where the FRR daemons are installed
+pub const FRR_DAEMONS_DIR: &str = "/usr/lib/frr";
+
+/// A machine of a topology
+#[derive(Clone, Debug)]
+pub struct Machine {
+    name: String,
+    loopback: Option<String>,
+    frr: Option<String>,
+}
+
+impl Machine {
+    /// A machine, without links nor FRR
+    #[must_use]
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            loopback: None,
+            frr: None,
+        }
+    }
+
+    /// Give an address to the loopback interface of the machine, which is also its router id
+    #[must_use]
+    pub fn loopback(mut self, address: &str) -> Self {
+        self.loopback = Some(address.to_string());
+        self
+    }
+
+    /// Run FRR on the machine, with the configuration rendered from `template`
+    #[must_use]
+    pub fn frr(mut self, template: &str) -> Self {
+        self.frr = Some(template.to_string());
+        self
+    }
+}
+
+/// An end of a link: an interface of a machine, with an optional address and prefix length
+#[derive(Clone, Debug)]
+pub struct Endpoint {
+    machine: String,
+    interface: String,
+    address: Option<String>,
+}
+
+impl From<(&str, &str)> for Endpoint {
+    fn from((machine, interface): (&str, &str)) -> Self {
+        Self {
+            machine: machine.to_string(),
+            interface: interface.to_string(),
+            address: None,
+        }
+    }
+}
+
+impl From<(&str, &str, &str)> for Endpoint {
+    fn from((machine, interface, address): (&str, &str, &str)) -> Self {
+        Self {
+            address: Some(address.to_string()),
+            ..Endpoint::from((machine, interface))
+        }
+    }
+}
+
+/// The description of a topology
+#[derive(Clone, Debug)]
+pub struct TopologyBuilder {
+    name: String,
+    machines: Vec<Machine>,
+    links: Vec<(Endpoint, Endpoint)>,
+}
+
+impl TopologyBuilder {
+    /// Describe a topology. The name prefixes the names of the namespaces of the machines, so
+    /// it must be unique among the topologies materialized at the same time.
+    #[must_use]
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            machines: vec![],
+            links: vec![],
+        }
+    }
+
+    /// Add a machine
+    #[must_use]
+    pub fn machine(mut self, machine: Machine) -> Self {
+        self.machines.push(machine);
+        self
+    }
+
+    /// Link two machines
+    #[must_use]
+    pub fn link(mut self, a: impl Into<Endpoint>, b: impl Into<Endpoint>) -> Self {
+        self.links.push((a.into(), b.into()));
+        self
+    }
+
+    /// Create the namespaces of the machines, link them, and render their FRR configurations.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the description is inconsistent or if any step fails. What was materialized
+    /// until then is torn down.
+    #[must_use]
+    pub fn build(self) -> Topology {
+        let dir =
+            std::env::temp_dir().join(format!("topology-{}-{}", self.name, std::process::id()));
+        let mut topology = Topology {
+            dir,
+            machines: BTreeMap::new(),
+            frr: vec![],
+        };
+        for machine in &self.machines {
+            let netns = format!("{}-{}", self.name, machine.name);
+            assert!(
+                !topology.machines.contains_key(&machine.name),
+                "duplicate machine {}",
+                machine.name
+            );
+            ip(&["netns", "add", &netns]);
+            let handle = MachineHandle {
+                name: machine.name.clone(),
+                dir: topology.dir.join(&machine.name),
+                netns,
+                loopback: machine.loopback.clone(),
+                addresses: BTreeMap::new(),
+                frr_config: None,
+            };
+            topology.machines.insert(machine.name.clone(), handle);
+            let handle = topology.machine(&machine.name);
+            ip(&["-n", &handle.netns, "link", "set", "lo", "up"]);
+            if let Some(loopback) = &machine.loopback {
+                ip(&["-n", &handle.netns, "addr", "add", loopback, "dev", "lo"]);
+            }
+        }
+        for (a, b) in &self.links {
+            let (netns_a, netns_b) = (
+                topology.machine(&a.machine).netns.clone(),
+                topology.machine(&b.machine).netns.clone(),
+            );
+            ip(&[
+                "link",
+                "add",
+                &a.interface,
+                "netns",
+                &netns_a,
+                "type",
+                "veth",
+                "peer",
+                "name",
+                &b.interface,
+                "netns",
+                &netns_b,
+            ]);
+            for (end, netns) in [(a, &netns_a), (b, &netns_b)] {
+                if let Some(address) = &end.address {
+                    ip(&["-n", netns, "addr", "add", address, "dev", &end.interface]);
+                }
+                ip(&["-n", netns, "link", "set", &end.interface, "up"]);
+                if let Some(address) = &end.address
+                    && let Some(machine) = topology.machines.get_mut(&end.machine)
+                {
+                    machine
+                        .addresses
+                        .insert(end.interface.clone(), address.clone());
+                }
+            }
+        }
+        for machine in &self.machines {
+            let Some(template) = &machine.frr else {
+                continue;
+            };
+            let Some(handle) = topology.machines.get_mut(&machine.name) else {
+                unreachable!();
+            };
+            std::fs::create_dir_all(&handle.dir)
+                .unwrap_or_else(|e| panic!("failed to create {}: {e}", handle.dir.display()));
+            let path = handle.dir.join("frr.conf");
+            std::fs::write(&path, render(template, &handle.variables()))
+                .unwrap_or_else(|e| panic!("failed to write {}: {e}", path.display()));
+            handle.frr_config = Some(path);
+        }
+        info!(
+            "Built topology {} with {} machines and {} links",
+            self.name,
+            self.machines.len(),
+            self.links.len()
+        );
+        topology
+    }
+}
+
+/// A materialized topology, torn down when dropped
+#[derive(Debug)]
+pub struct Topology {
+    dir: PathBuf,
+    machines: BTreeMap<String, MachineHandle>,
+    frr: Vec<Child>,
+}
+
+impl Topology {
+    /// Get a machine of the topology
+    ///
+    /// # Panics
+    ///
+    /// Panics if the topology has no such machine.
+    #[must_use]
+    pub fn machine(&self, name: &str) -> &MachineHandle {
+        self.machines
+            .get(name)
+            .unwrap_or_else(|| panic!("no machine {name} in topology"))
+    }
+
+    /// Start the FRR daemons of the machines running FRR: zebra, and `daemons`. They are
+    /// stopped when the topology is dropped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a daemon can't be started.
+    pub fn start_frr(&mut self, daemons: &[&str]) {
+        for machine in self.machines.values() {
+            let Some(config) = &machine.frr_config else {
+                continue;
+            };
+            for daemon in std::iter::once(&"zebra").chain(daemons) {
+                let program = Path::new(FRR_DAEMONS_DIR).join(daemon);
+                let child = machine
+                    .command(&program.to_string_lossy())
+                    .args(["-N", &machine.netns, "-u", "root", "-g", "root"])
+                    .arg("-f")
+                    .arg(config)
+                    .arg("-i")
+                    .arg(machine.dir.join(format!("{daemon}.pid")))
+                    .arg("--vty_socket")
+                    .arg(&machine.dir)
+                    .stdout(Stdio::null())
+                    .spawn()
+                    .unwrap_or_else(|e| {
+                        panic!("failed to start {daemon} on {}: {e}", machine.name)
+                    });
+                self.frr.push(child);
+            }
+        }
+    }
+}
+
+impl Drop for Topology {
+    fn drop(&mut self) {
+        for child in &mut self.frr {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+        for machine in self.machines.values() {
+            match Command::new("ip")
+                .args(["netns", "del", &machine.netns])
+                .output()
+            {
+                Ok(output) if output.status.success() => {}
+                Ok(output) => error!(
+                    "failed to remove namespace {}: {}",
+                    machine.netns,
+                    String::from_utf8_lossy(&output.stderr)
+                ),
+                Err(e) => error!("failed to remove namespace {}: {e}", machine.netns),
+            }
+        }
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+/// A machine of a materialized topology
+#[derive(Debug)]
+pub struct MachineHandle {
+    name: String,
+    netns: String,
+    dir: PathBuf,
+    loopback: Option<String>,
+    /// The addresses of the interfaces, by name
+    addresses: BTreeMap<String, String>,
+    frr_config: Option<PathBuf>,
+}
+
+impl MachineHandle {
+    /// The name of the network namespace of the machine
+    #[must_use]
+    pub fn netns(&self) -> &str {
+        &self.netns
+    }
+
+    /// The path of the network namespace of the machine, as [`crate::in_netns`] expects
+    #[must_use]
+    pub fn netns_path(&self) -> PathBuf {
+        Path::new("/run/netns").join(&self.netns)
+    }
+
+    /// The address of an interface of the machine, with its prefix length
+    #[must_use]
+    pub fn address(&self, interface: &str) -> Option<&str> {
+        self.addresses.get(interface).map(String::as_str)
+    }
+
+    /// The FRR configuration rendered for the machine, if it runs FRR
+    #[must_use]
+    pub fn frr_config(&self) -> Option<&Path> {
+        self.frr_config.as_deref()
+    }
+
+    /// A command running `program` in the namespace of the machine
+    #[must_use]
+    pub fn command(&self, program: &str) -> Command {
+        let mut command = Command::new("ip");
+        command.args(["netns", "exec", &self.netns, program]);
+        command
+    }
+
+    /// Run a command in the namespace of the machine, and get its output
+    ///
+    /// # Panics
+    ///
+    /// Panics if `args` is empty or if the command can't be run.
+    #[must_use]
+    pub fn exec(&self, args: &[&str]) -> Output {
+        let Some((program, args)) = args.split_first() else {
+            panic!("no command to run on {}", self.name);
+        };
+        self.command(program)
+            .args(args)
+            .output()
+            .unwrap_or_else(|e| panic!("failed to run {program} on {}: {e}", self.name))
+    }
+
+    /// The variables of the FRR template of the machine
+    fn variables(&self) -> BTreeMap<String, String> {
+        let mut variables = self.addresses.clone();
+        variables.insert("name".to_string(), self.name.clone());
+        let router_id =
+            self.loopback
+                .as_deref()
+                .or(self.addresses.values().next().map(String::as_str));
+        if let Some(router_id) = router_id {
+            let router_id = router_id.split('/').next().unwrap_or(router_id);
+            variables.insert("router_id".to_string(), router_id.to_string());
+        }
+        variables
+    }
+}
+
+/// Run `ip` with the given arguments
+fn ip(args: &[&str]) {
+    let output = Command::new("ip")
+        .args(args)
+        .output()
+        .unwrap_or_else(|e| panic!("failed to run ip: {e}"));
+    assert!(
+        output.status.success(),
+        "ip {} failed: {}",
+        args.join(" "),
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+/// Replace the `{variable}`s of a template with their values. Unknown variables are left as is.
+fn render(template: &str, variables: &BTreeMap<String, String>) -> String {
+    variables
+        .iter()
+        .fold(template.to_string(), |rendered, (variable, value)| {
+            rendered.replace(&format!("{{{variable}}}"), value)
+        })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let machine = MachineHandle {
+            name: "r1".to_string(),
+            netns: "bgp-r1".to_string(),
+            dir: PathBuf::new(),
+            loopback: None,
+            addresses: BTreeMap::from([
+                ("eth0".to_string(), "10.0.0.0/31".to_string()),
+                ("eth1".to_string(), "10.0.1.0/31".to_string()),
+            ]),
+            frr_config: None,
+        };
+        let template = "hostname {name}\nrouter bgp 65000\n bgp router-id {router_id}\n\
+                        interface eth1\n ip address {eth1}\n{unknown}\n";
+        assert_eq!(
+            render(template, &machine.variables()),
+            "hostname r1\nrouter bgp 65000\n bgp router-id 10.0.0.0\n\
+             interface eth1\n ip address 10.0.1.0/31\n{unknown}\n"
+        );
+
+        let machine = MachineHandle {
+            loopback: Some("10.255.0.1/32".to_string()),
+            ..machine
+        };
+        assert_eq!(render("{router_id}", &machine.variables()), "10.255.0.1");
+    }
+}
@@ -17,10 +17,11 @@ use tracing_subscriber::{
     filter::LevelFilter,
     layer::{Context, Layer},
     prelude::*,
-    registry::LookupSpan,
+    registry::{LookupSpan, Registry},
 };
 
 use crate::display::TargetCfgDbByTag;
+use crate::journal::{JournalLayer, journal};
 use crate::targets::{TRACING_TAG_ALL, TRACING_TARGETS};
 use crate::throttle::RateLimitFilter;
 use crate::trace_target;
@@ -491,6 +492,10 @@ where
 
 type BoxedTracingLayer<S> = Box<dyn Layer<S> + Send + Sync>;
 
+/// A layer exporting spans and events out of the process, e.g. to OpenTelemetry. It sits
+/// beneath the filter, so it only gets what the filter lets through.
+pub type ExportLayer = BoxedTracingLayer<Registry>;
+
 pub struct TracingControl {
     db: Arc<Mutex<TargetCfgDb>>,
     reload_filter: AtomicEnvFilter,
@@ -528,25 +533,31 @@ impl TracingControl {
         rate_limit_config.map(RateLimitFilter::new)
     }
 
-    fn init_subscriber(filter: AtomicEnvFilter, throttle: AtomicThrottle) {
+    fn init_subscriber(
+        filter: AtomicEnvFilter,
+        throttle: AtomicThrottle,
+        export: Option<ExportLayer>,
+    ) {
         if let Err(e) = tracing_subscriber::registry()
+            .with(export)
             .with(filter)
             .with(Self::fmt_gate_layer(throttle))
             .with(tracing_error::ErrorLayer::default())
+            .with(JournalLayer(journal()))
             .try_init()
         {
             eprintln!("Failed to set global tracing subscriber: {e} !!");
         }
     }
 
-    fn new(rate_limit_config: Option<TracingRateLimitConfig>) -> Self {
+    fn new(rate_limit_config: Option<TracingRateLimitConfig>, export: Option<ExportLayer>) -> Self {
         let db = TargetCfgDb::new();
         let filter = AtomicEnvFilter::new(db.env_filter());
         let reload_filter = filter.clone();
         let throttle = AtomicThrottle::new(rate_limit_config);
         let reload_throttle = throttle.clone();
 
-        Self::init_subscriber(filter, throttle);
+        Self::init_subscriber(filter, throttle, export);
 
         if let Err(e) = color_eyre::install() {
             eprintln!("Failed to initialize color_eyre:\n{e}");
@@ -584,7 +595,7 @@ impl TracingControl {
 static TRACING_CTL: OnceLock<TracingControl> = OnceLock::new();
 #[must_use]
 pub fn get_trace_ctl() -> &'static TracingControl {
-    TRACING_CTL.get_or_init(|| TracingControl::new(None))
+    TRACING_CTL.get_or_init(|| TracingControl::new(None, None))
 }
 
 // public methods for TracingControl
@@ -592,11 +603,12 @@ impl TracingControl {
     fn init_once(
         tracing_ctl: &OnceLock<TracingControl>,
         rate_limit_config: Option<TracingRateLimitConfig>,
+        export: Option<ExportLayer>,
     ) -> bool {
         let mut initialized = false;
         let _ = tracing_ctl.get_or_init(|| {
             initialized = true;
-            TracingControl::new(rate_limit_config)
+            TracingControl::new(rate_limit_config, export)
         });
         initialized
     }
@@ -606,11 +618,22 @@ impl TracingControl {
     }
     pub fn init_with_rate_limit(rate_limit_config: Option<TracingRateLimitConfig>) {
         let has_rate_limit_config = rate_limit_config.is_some();
-        let initialized = Self::init_once(&TRACING_CTL, rate_limit_config);
+        let initialized = Self::init_once(&TRACING_CTL, rate_limit_config, None);
         if has_rate_limit_config && !initialized {
             warn!("TracingControl already initialized; ignoring provided rate-limit config");
         }
     }
+    /// Like [`TracingControl::init_with_rate_limit`], additionally handing spans and events over
+    /// to `export`
+    pub fn init_with_export(
+        rate_limit_config: Option<TracingRateLimitConfig>,
+        export: ExportLayer,
+    ) {
+        let initialized = Self::init_once(&TRACING_CTL, rate_limit_config, Some(export));
+        if !initialized {
+            warn!("TracingControl already initialized; spans and events won't be exported");
+        }
+    }
     fn set_tag_level(&self, tag: &str, level: LevelFilter) -> Result<(), TraceCtlError> {
         let mut db = self.lock();
         let changed = db.set_tag_level(tag, level)?;
@@ -799,14 +822,22 @@ mod tests {
             replenish_per_second: 1,
         });
 
-        assert!(TracingControl::init_once(&tracing_ctl, rate_limit_config));
+        assert!(TracingControl::init_once(
+            &tracing_ctl,
+            rate_limit_config,
+            None
+        ));
         assert!(tracing_ctl.get().is_some());
         assert_eq!(
             tracing_ctl.get().unwrap().get_default_level().unwrap(),
             crate::control::DEFAULT_DEFAULT_LOGLEVEL
         );
 
-        assert!(!TracingControl::init_once(&tracing_ctl, rate_limit_config));
+        assert!(!TracingControl::init_once(
+            &tracing_ctl,
+            rate_limit_config,
+            None
+        ));
     }
 
     #[test]
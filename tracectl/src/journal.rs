@@ -0,0 +1,140 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! In-memory journal of the most recent log events.
+//!
+//! The journal keeps the last [`JOURNAL_CAPACITY`] events of level `INFO` or above that the
+//! env filter lets through, so that they can be attached to crash reports. Events of lower
+//! levels are skipped before being formatted: they are far too frequent to be worth keeping.
+
+use concurrency::sync::{Mutex, OnceLock};
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+
+/// Number of events kept in the journal
+pub const JOURNAL_CAPACITY: usize = 256;
+
+/// Placeholder for the events when the journal is locked
+const JOURNAL_UNAVAILABLE: &str = "journal unavailable";
+
+/// A bounded log of formatted events
+#[derive(Default)]
+pub(crate) struct Journal(Mutex<VecDeque<String>>);
+
+impl Journal {
+    fn push(&self, line: String) {
+        let mut events = self.0.lock();
+        if events.len() == JOURNAL_CAPACITY {
+            events.pop_front();
+        }
+        events.push_back(line);
+    }
+
+    /// The events of the journal. Called from the panic hook, possibly while the panicking
+    /// thread holds the lock of the journal: a placeholder is returned then, so as not to
+    /// deadlock.
+    fn events(&self) -> Vec<String> {
+        match self.0.try_lock() {
+            Some(events) => events.iter().cloned().collect(),
+            None => vec![JOURNAL_UNAVAILABLE.to_string()],
+        }
+    }
+}
+
+/// The journal fed by the subscriber installed by [`crate::TracingControl`]
+pub(crate) fn journal() -> &'static Journal {
+    static JOURNAL: OnceLock<Journal> = OnceLock::new();
+    JOURNAL.get_or_init(Journal::default)
+}
+
+/// Get the most recent log events, oldest first. If the journal is being written to, a single
+/// placeholder is returned instead.
+#[must_use]
+pub fn recent_events() -> Vec<String> {
+    journal().events()
+}
+
+/// Renders the fields of an event: the message first, then the other fields as `name=value`
+struct EventVisitor<'a>(&'a mut String);
+
+impl Visit for EventVisitor<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, " {value:?}");
+        } else {
+            let _ = write!(self.0, " {}={value:?}", field.name());
+        }
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            let _ = write!(self.0, " {value}");
+        } else {
+            let _ = write!(self.0, " {}={value}", field.name());
+        }
+    }
+}
+
+/// The layer recording events into a journal
+pub(crate) struct JournalLayer(pub(crate) &'static Journal);
+
+impl<S: Subscriber> Layer<S> for JournalLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let meta = event.metadata();
+        if *meta.level() > Level::INFO {
+            return;
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        let mut line = format!(
+            "{}.{:03} {:>5} {}:",
+            now.as_secs(),
+            now.subsec_millis(),
+            meta.level(),
+            meta.target()
+        );
+        event.record(&mut EventVisitor(&mut line));
+        self.0.push(line);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use tracing::{debug, info, warn};
+    use tracing_subscriber::prelude::*;
+
+    #[test]
+    fn test_journal() {
+        let journal: &'static Journal = Box::leak(Box::default());
+        let subscriber = tracing_subscriber::registry().with(JournalLayer(journal));
+        tracing::subscriber::with_default(subscriber, || {
+            for n in 0..JOURNAL_CAPACITY {
+                info!("filler {n}");
+            }
+            debug!("not journaled");
+            warn!(vrf = 3, "last event");
+        });
+        let events = journal.events();
+        assert_eq!(events.len(), JOURNAL_CAPACITY);
+        assert!(events.iter().all(|e| !e.contains("not journaled")));
+        assert!(!events[0].contains("filler 0"));
+        let last = events.last().unwrap();
+        assert!(last.contains(" WARN ") && last.ends_with(" last event vrf=3"));
+    }
+
+    #[test]
+    fn test_journal_locked() {
+        let journal = Journal::default();
+        journal.push("event".to_string());
+        let guard = journal.0.lock();
+        assert_eq!(journal.events(), vec![JOURNAL_UNAVAILABLE.to_string()]);
+        drop(guard);
+        assert_eq!(journal.events(), vec!["event".to_string()]);
+    }
+}
@@ -8,11 +8,13 @@
 
 pub mod control;
 pub mod display;
+pub mod journal;
 pub mod targets;
 mod throttle;
 
 // re-exports
 pub use control::DEFAULT_DEFAULT_LOGLEVEL;
 pub use control::get_trace_ctl;
-pub use control::{TraceCtlError, TracingControl, TracingRateLimitConfig};
+pub use control::{ExportLayer, TraceCtlError, TracingControl, TracingRateLimitConfig};
+pub use journal::recent_events;
 pub use tracing_subscriber::filter::LevelFilter;
@@ -0,0 +1,467 @@
+// SPDX-License-Identifier: Apache-2.0
+// Copyright Open Network Fabric Authors
+
+//! Validation of the `GatewayAgent` CRDs of all the gateways of a fabric (`--mode fabric`).
+//!
+//! The input is a stream of YAML (or JSON) documents, each being a `GatewayAgent` or a list of
+//! them, such as the output of `kubectl get gatewayagents -o yaml`. Each object is first validated
+//! on its own, as in the gateway mode. The objects are then checked together for the invariants
+//! which span gateways:
+//!   - a gateway is defined once,
+//!   - a VNI identifies the same VPC on all the gateways, and a VPC has the same VNI on all of them,
+//!   - the NAT pools of distinct peerings, exposed to the same VPC, do not overlap,
+//!   - a peering is defined identically on all the gateways which define it.
+//!
+//! The reply has the result of each object. An error spanning gateways is reported on each of the
+//! objects involved.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use config::ValidatedGwConfig;
+use config::converters::k8s::key_segment;
+use config::external::overlay::vpcpeering::ValidatedExpose;
+use k8s_intf::gateway_agent_crd::{GatewayAgent, GatewayAgentPeerings};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    ValidateError, ValidateErrorOut, ValidateReply, build_reply, read_stdin, validate_crd,
+};
+
+/// The result of the validation of one of the objects of the fabric
+#[derive(Serialize, Deserialize)]
+struct ObjectReply {
+    name: String,
+    #[serde(flatten)]
+    reply: ValidateReply,
+}
+
+/// The outcome of a validation request for a fabric
+#[derive(Serialize, Deserialize)]
+pub(crate) struct FabricReply {
+    success: bool,
+    /// errors of the input as a whole, e.g. when it can't be deserialized
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    errors: Vec<ValidateErrorOut>,
+    objects: Vec<ObjectReply>,
+}
+
+impl FabricReply {
+    fn failure(e: &ValidateError) -> Self {
+        Self {
+            success: false,
+            errors: ValidateReply::from(e).errors,
+            objects: vec![],
+        }
+    }
+}
+
+/// An object of the fabric being validated
+struct FabricObject {
+    name: String,
+    crd: GatewayAgent,
+    config: Option<ValidatedGwConfig>, // the configuration of the object, if valid on its own
+    reply: ValidateReply,
+}
+
+/// An error spanning gateways: the index of an object involved, the path of the field of that
+/// object and the error message. Ordered so that duplicates are reported once.
+type FabricError = (usize, String, String);
+
+/// The gateways the objects at `indices` are for, as a string
+fn gateways(objects: &[FabricObject], indices: &[usize]) -> String {
+    indices
+        .iter()
+        .map(|index| objects[*index].name.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Deserialize the `GatewayAgent` objects from a stream of YAML/JSON documents, each being an
+/// object or a list of them
+fn deserialize_fabric(input: &str) -> Result<Vec<GatewayAgent>, ValidateError> {
+    let deserialize_error =
+        |e: serde_yaml_ng::Error| ValidateError::DeserializeError(e.to_string());
+    let mut crds = vec![];
+    for document in serde_yaml_ng::Deserializer::from_str(input) {
+        let value = serde_yaml_ng::Value::deserialize(document).map_err(deserialize_error)?;
+        if value.is_null() {
+            continue; // empty document
+        }
+        match value.get("items") {
+            Some(items) => crds.extend(
+                serde_yaml_ng::from_value::<Vec<GatewayAgent>>(items.clone())
+                    .map_err(deserialize_error)?,
+            ),
+            None => crds.push(serde_yaml_ng::from_value(value).map_err(deserialize_error)?),
+        }
+    }
+    if crds.is_empty() {
+        return Err(ValidateError::DeserializeError(
+            "No GatewayAgent object found in the input".to_string(),
+        ));
+    }
+    Ok(crds)
+}
+
+/// Check that no two objects are for the same gateway
+fn check_names(objects: &[FabricObject], errors: &mut BTreeSet<FabricError>) {
+    let mut indices: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+    for (index, object) in objects.iter().enumerate() {
+        indices.entry(&object.name).or_default().push(index);
+    }
+    for (name, indices) in indices.into_iter().filter(|(_, i)| i.len() > 1) {
+        for index in indices {
+            errors.insert((
+                index,
+                ".metadata.name".to_string(),
+                format!("Gateway {name} is defined more than once"),
+            ));
+        }
+    }
+}
+
+/// Check that each VNI identifies the same VPC, and that each VPC has the same VNI, on all the
+/// gateways
+fn check_vnis(objects: &[FabricObject], errors: &mut BTreeSet<FabricError>) {
+    let mut vpcs: BTreeMap<u32, BTreeMap<&str, Vec<usize>>> = BTreeMap::new();
+    let mut vnis: BTreeMap<&str, BTreeMap<u32, Vec<usize>>> = BTreeMap::new();
+    for (index, object) in objects.iter().enumerate() {
+        let Some(config) = &object.config else {
+            continue;
+        };
+        for vpc in config.external().overlay().vpc_table().values() {
+            let vni = vpc.vni().as_u32();
+            let vpc_indices = vpcs.entry(vni).or_default().entry(vpc.name()).or_default();
+            vpc_indices.push(index);
+            vnis.entry(vpc.name())
+                .or_default()
+                .entry(vni)
+                .or_default()
+                .push(index);
+        }
+    }
+
+    for (vni, vpcs) in vpcs.iter().filter(|(_, vpcs)| vpcs.len() > 1) {
+        for (name, indices) in vpcs {
+            let others = vpcs
+                .iter()
+                .filter(|(other, _)| *other != name)
+                .map(|(other, at)| format!("VPC {other} on {}", gateways(objects, at)))
+                .collect::<Vec<_>>()
+                .join("; ");
+            for index in indices {
+                errors.insert((
+                    *index,
+                    format!(".spec{}.vni", key_segment("vpcs", name)),
+                    format!("VNI {vni} of VPC {name} is also the VNI of {others}"),
+                ));
+            }
+        }
+    }
+
+    for (name, vnis) in vnis.iter().filter(|(_, vnis)| vnis.len() > 1) {
+        for (vni, indices) in vnis {
+            let others = vnis
+                .iter()
+                .filter(|(other, _)| *other != vni)
+                .map(|(other, at)| format!("VNI {other} on {}", gateways(objects, at)))
+                .collect::<Vec<_>>()
+                .join("; ");
+            for index in indices {
+                errors.insert((
+                    *index,
+                    format!(".spec{}.vni", key_segment("vpcs", name)),
+                    format!("VPC {name} has VNI {vni} here, but {others}"),
+                ));
+            }
+        }
+    }
+}
+
+/// Check that the NAT pools of distinct peerings, exposed to the same VPC, do not overlap, even if
+/// the peerings are on distinct gateways
+fn check_nat_pools(objects: &[FabricObject], errors: &mut BTreeSet<FabricError>) {
+    // the NAT pools exposed to each VPC, by VNI, with the peering and object defining them
+    let mut pools: BTreeMap<u32, Vec<(usize, &str, &ValidatedExpose)>> = BTreeMap::new();
+    for (index, object) in objects.iter().enumerate() {
+        let Some(config) = &object.config else {
+            continue;
+        };
+        for vpc in config.external().overlay().vpc_table().values() {
+            for peering in vpc.peerings() {
+                let exposed = peering
+                    .local()
+                    .valexp()
+                    .iter()
+                    .filter(|e| e.nat().is_some());
+                for expose in exposed {
+                    let vni = peering.remote_vni().as_u32();
+                    pools
+                        .entry(vni)
+                        .or_default()
+                        .push((index, peering.name(), expose));
+                }
+            }
+        }
+    }
+
+    for (vni, pools) in &pools {
+        for (n, (index, peering, expose)) in pools.iter().enumerate() {
+            for (other_index, other_peering, other_expose) in &pools[n + 1..] {
+                if peering == other_peering {
+                    continue;
+                }
+                let overlap = expose
+                    .as_range_or_empty()
+                    .intersection_prefixes_and_ports(other_expose.as_range_or_empty());
+                if overlap.is_empty() {
+                    continue;
+                }
+                let overlap = overlap
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let involved = [
+                    (*index, *peering, *other_index, *other_peering),
+                    (*other_index, *other_peering, *index, *peering),
+                ];
+                for (index, peering, other_index, other_peering) in involved {
+                    errors.insert((
+                        index,
+                        format!(".spec{}", key_segment("peerings", peering)),
+                        format!(
+                            "NAT pool of peering {peering} overlaps ({overlap}) the NAT pool of peering {other_peering} on {}, both exposed to the VPC with VNI {vni}",
+                            objects[other_index].name
+                        ),
+                    ));
+                }
+            }
+        }
+    }
+}
+
+/// Check that each peering is defined identically on all the gateways which define it
+fn check_peerings(objects: &[FabricObject], errors: &mut BTreeSet<FabricError>) {
+    let mut definitions: BTreeMap<&str, Vec<(usize, &GatewayAgentPeerings)>> = BTreeMap::new();
+    for (index, object) in objects.iter().enumerate() {
+        for (name, peering) in object.crd.spec.peerings.iter().flatten() {
+            definitions.entry(name).or_default().push((index, peering));
+        }
+    }
+
+    for (name, definitions) in &definitions {
+        for (index, peering) in definitions {
+            let different: Vec<usize> = definitions
+                .iter()
+                .filter(|(_, other)| other != peering)
+                .map(|(other_index, _)| *other_index)
+                .collect();
+            if !different.is_empty() {
+                errors.insert((
+                    *index,
+                    format!(".spec{}", key_segment("peerings", name)),
+                    format!(
+                        "Peering {name} is defined differently on {}",
+                        gateways(objects, &different)
+                    ),
+                ));
+            }
+        }
+    }
+}
+
+/// Validate the objects of a fabric, on their own and together
+fn validate_fabric(crds: Vec<GatewayAgent>) -> FabricReply {
+    let mut objects: Vec<FabricObject> = crds
+        .into_iter()
+        .enumerate()
+        .map(|(index, crd)| {
+            let mut warnings = vec![];
+            let (config, result) = match validate_crd(&crd, &mut warnings) {
+                Ok(config) => (Some(config), Ok(())),
+                Err(e) => (None, Err(e)),
+            };
+            FabricObject {
+                name: crd
+                    .metadata
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("#{index}")),
+                crd,
+                config,
+                reply: build_reply(result, warnings),
+            }
+        })
+        .collect();
+
+    let mut errors = BTreeSet::new();
+    check_names(&objects, &mut errors);
+    check_vnis(&objects, &mut errors);
+    check_nat_pools(&objects, &mut errors);
+    check_peerings(&objects, &mut errors);
+    for (index, context, message) in errors {
+        let reply = &mut objects[index].reply;
+        reply.success = false;
+        reply.errors.push(ValidateErrorOut {
+            r#type: "Fabric".to_string(),
+            message,
+            context: Some(context),
+        });
+    }
+
+    let objects: Vec<ObjectReply> = objects
+        .into_iter()
+        .map(|object| ObjectReply {
+            name: object.name,
+            reply: object.reply,
+        })
+        .collect();
+    FabricReply {
+        success: objects.iter().all(|object| object.reply.success),
+        errors: vec![],
+        objects,
+    }
+}
+
+/// Read from stdin the objects of a fabric and validate them
+pub(crate) fn validate_from_stdin() -> FabricReply {
+    match read_stdin().and_then(|input| deserialize_fabric(&input)) {
+        Ok(crds) => validate_fabric(crds),
+        Err(e) => FabricReply::failure(&e),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// A `GatewayAgent` document for gateway `name`, with a peering `peering-1` of group `group`
+    fn document(name: &str, group: &str) -> String {
+        format!(
+            "apiVersion: gwint.githedgehog.com/v1alpha1
+kind: GatewayAgent
+metadata:
+  name: {name}
+spec:
+  peerings:
+    peering-1:
+      gatewayGroup: {group}
+      peering:
+        vpc-1: {{}}
+        vpc-2: {{}}
+"
+        )
+    }
+
+    fn crds(input: &str) -> Vec<GatewayAgent> {
+        let Ok(crds) = deserialize_fabric(input) else {
+            panic!("Failed to deserialize the fabric");
+        };
+        crds
+    }
+
+    fn objects(crds: Vec<GatewayAgent>) -> Vec<FabricObject> {
+        crds.into_iter()
+            .map(|crd| FabricObject {
+                name: crd.metadata.name.clone().unwrap(),
+                crd,
+                config: None,
+                reply: ValidateReply::success(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_deserialize_fabric() {
+        let list = format!(
+            "apiVersion: v1\nkind: List\nitems:\n- {}\n- {}\n",
+            document("gw-2", "default").trim_end().replace('\n', "\n  "),
+            document("gw-3", "default").trim_end().replace('\n', "\n  "),
+        );
+        let input = format!("{}---\n---\n{list}", document("gw-1", "default"));
+        let names: Vec<_> = crds(&input)
+            .into_iter()
+            .map(|crd| crd.metadata.name.unwrap())
+            .collect();
+        assert_eq!(names, ["gw-1", "gw-2", "gw-3"]);
+
+        assert!(matches!(
+            deserialize_fabric("---\n"),
+            Err(ValidateError::DeserializeError(_))
+        ));
+        assert!(matches!(
+            deserialize_fabric("metadata: [not, a, gateway]\n"),
+            Err(ValidateError::DeserializeError(_))
+        ));
+    }
+
+    #[test]
+    fn test_check_names_and_peerings() {
+        let input = [
+            document("gw-1", "default"),
+            document("gw-1", "default"),
+            document("gw-2", "other"),
+        ]
+        .join("---\n");
+        let objects = objects(crds(&input));
+
+        let mut errors = BTreeSet::new();
+        check_names(&objects, &mut errors);
+        let indices: Vec<_> = errors.iter().map(|(index, _, _)| *index).collect();
+        assert_eq!(indices, [0, 1]);
+        assert!(
+            errors
+                .iter()
+                .all(|(_, context, _)| context == ".metadata.name")
+        );
+
+        let mut errors = BTreeSet::new();
+        check_peerings(&objects, &mut errors);
+        let errors: Vec<_> = errors.into_iter().collect();
+        let context = r#".spec.peerings["peering-1"]"#.to_string();
+        assert_eq!(
+            errors,
+            [
+                (
+                    0,
+                    context.clone(),
+                    "Peering peering-1 is defined differently on gw-2".to_string()
+                ),
+                (
+                    1,
+                    context.clone(),
+                    "Peering peering-1 is defined differently on gw-2".to_string()
+                ),
+                (
+                    2,
+                    context,
+                    "Peering peering-1 is defined differently on gw-1, gw-1".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_fabric_reports_on_each_object() {
+        let input = [document("gw-1", "default"), document("gw-2", "other")].join("---\n");
+        let reply = validate_fabric(crds(&input));
+        assert!(!reply.success);
+        assert!(reply.errors.is_empty());
+        assert_eq!(reply.objects.len(), 2);
+        for (object, other) in reply.objects.iter().zip(["gw-2", "gw-1"]) {
+            assert!(!object.reply.success);
+            let fabric: Vec<_> = object
+                .reply
+                .errors
+                .iter()
+                .filter(|e| e.r#type == "Fabric")
+                .collect();
+            assert_eq!(fabric.len(), 1);
+            assert_eq!(
+                fabric[0].message,
+                format!("Peering peering-1 is defined differently on {other}")
+            );
+        }
+    }
+}
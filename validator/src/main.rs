@@ -4,20 +4,82 @@
 //! A configuration validator. This validator may perform the same validation that
 //! the dataplane process. The intent is to compile this validator as WASM / WASI.
 //! The validator expects a `GatewayAgent` CRD in JSON or YAML from stdin and produces
-//! a result as a YAML string in stdout.
+//! a result as a YAML string in stdout. Errors tell, when known, the path of the field of the CRD
+//! in error, e.g. `.spec.vpcs["vpc-1"].subnets["subnet-1"].cidr`, and the reply lists the
+//! non-fatal issues of the CRD as warnings.
+//!
+//! With `--mode fabric`, the validator expects instead the `GatewayAgent` CRDs of all the gateways
+//! of a fabric and, besides validating each of them, checks the invariants spanning gateways. See
+//! [`fabric`].
 
 #![deny(clippy::all)]
 #![allow(clippy::result_large_err)]
 #![allow(clippy::field_reassign_with_default)]
 
-use config::{ExternalConfig, converters::k8s::FromK8sConversionError};
+use config::converters::k8s::warnings::{ConversionWarning, conversion_warnings};
+use config::converters::k8s::{FromK8sConversionError, config_error_field};
+use config::{ConfigError, ExternalConfig, ValidatedGwConfig};
 use k8s_intf::gateway_agent_crd::GatewayAgent;
 use serde::{Deserialize, Serialize};
 use std::io::{self, Read};
 
+mod fabric;
+
+/// What the validator validates
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Mode {
+    /// A single `GatewayAgent` CRD
+    Gateway,
+    /// The `GatewayAgent` CRDs of all the gateways of a fabric
+    Fabric,
+}
+
+/// Parse the arguments of the validator: `[--mode gateway|fabric]`
+fn parse_mode(args: &[String]) -> Result<Mode, ValidateError> {
+    match args {
+        [] => Ok(Mode::Gateway),
+        [flag, mode] if flag == "--mode" => match mode.as_str() {
+            "gateway" => Ok(Mode::Gateway),
+            "fabric" => Ok(Mode::Fabric),
+            _ => Err(ValidateError::EnvironmentError(format!(
+                "Unknown mode {mode}: expected gateway or fabric"
+            ))),
+        },
+        _ => Err(ValidateError::EnvironmentError(format!(
+            "Invalid arguments '{}': usage is validator [--mode gateway|fabric]",
+            args.join(" ")
+        ))),
+    }
+}
+
+/// An error message, with the path of the field of the CRD it is about, if known
+#[derive(Clone)]
+struct FieldError {
+    message: String,
+    context: Option<String>,
+}
+
+impl From<&FromK8sConversionError> for FieldError {
+    fn from(e: &FromK8sConversionError) -> Self {
+        Self {
+            message: e.cause().to_string(),
+            context: e.field().map(ToOwned::to_owned),
+        }
+    }
+}
+
+impl From<&ConfigError> for FieldError {
+    fn from(e: &ConfigError) -> Self {
+        Self {
+            message: e.to_string(),
+            context: config_error_field(e),
+        }
+    }
+}
+
 #[derive(Default)]
 struct ConfigErrors {
-    errors: Vec<String>, // only one error is supported at the moment
+    errors: Vec<FieldError>, // only one error is supported at the moment
 }
 
 /// The type representing an error when validating a request
@@ -35,7 +97,7 @@ enum ValidateError {
 
     /// This type contains errors that may occur when converting the CRD to a gateway configuration.
     /// These may happen mostly due to type violations, out-of-range values, etc.
-    ConversionError(String),
+    ConversionError(FieldError),
 
     /// This type contains configuration errors. If errors of this type are produced, this means
     /// that the configuration is syntactically correct and could be parsed, but it is:
@@ -59,12 +121,16 @@ impl ValidateError {
         }
     }
 
-    /// Provide a list of messages depending on the error type
-    fn get_msg(&self) -> Vec<String> {
+    /// Provide a list of messages, with their context, depending on the error type
+    fn get_msg(&self) -> Vec<FieldError> {
+        let no_context = |v: &String| FieldError {
+            message: v.clone(),
+            context: None,
+        };
         match self {
-            ValidateError::EnvironmentError(v) => vec![v.clone()],
-            ValidateError::DeserializeError(v) => vec![v.clone()],
-            ValidateError::MetadataError(v) => vec![v.clone()],
+            ValidateError::EnvironmentError(v) => vec![no_context(v)],
+            ValidateError::DeserializeError(v) => vec![no_context(v)],
+            ValidateError::MetadataError(v) => vec![no_context(v)],
             ValidateError::ConversionError(v) => vec![v.clone()],
             ValidateError::Configuration(v) => v.errors.to_vec(),
         }
@@ -79,13 +145,14 @@ impl From<&ValidateError> for ValidateReply {
         ValidateReply {
             success: false,
             errors: msg
-                .iter()
+                .into_iter()
                 .map(|m| ValidateErrorOut {
                     r#type: r#type.to_owned(),
-                    message: m.clone(),
-                    context: None,
+                    message: m.message,
+                    context: m.context,
                 })
                 .collect(),
+            warnings: vec![],
         }
     }
 }
@@ -98,17 +165,36 @@ struct ValidateErrorOut {
     context: Option<String>,
 }
 
+/// A non-fatal issue of the CRD, which does not prevent it from being applied
+#[derive(Serialize, Deserialize)]
+struct ValidateWarningOut {
+    message: String,
+    context: String,
+}
+
+impl From<ConversionWarning> for ValidateWarningOut {
+    fn from(warning: ConversionWarning) -> Self {
+        Self {
+            message: warning.message,
+            context: warning.path,
+        }
+    }
+}
+
 /// The type representing the outcome of a validation request
 #[derive(Serialize, Deserialize)]
 struct ValidateReply {
     success: bool,
     errors: Vec<ValidateErrorOut>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    warnings: Vec<ValidateWarningOut>,
 }
 impl ValidateReply {
     fn success() -> Self {
         Self {
             success: true,
             errors: vec![],
+            warnings: vec![],
         }
     }
 }
@@ -120,45 +206,76 @@ fn deserialize(ga_input: &str) -> Result<GatewayAgent, ValidateError> {
     Ok(crd)
 }
 
-/// Main validation function
-fn validate(gwagent_json: &str) -> Result<(), ValidateError> {
-    let crd = deserialize(gwagent_json)?;
-    let external = ExternalConfig::try_from(&crd).map_err(|e| match e {
+/// Validate a `GatewayAgent` CRD, adding its non-fatal issues to `warnings`, and return the
+/// resulting configuration
+fn validate_crd(
+    crd: &GatewayAgent,
+    warnings: &mut Vec<ConversionWarning>,
+) -> Result<ValidatedGwConfig, ValidateError> {
+    warnings.extend(conversion_warnings(&crd.spec));
+    let external = ExternalConfig::try_from(crd).map_err(|e| match e.cause() {
         FromK8sConversionError::K8sInfra(e) => ValidateError::MetadataError(e.to_string()),
-        _ => ValidateError::ConversionError(e.to_string()),
+        _ => ValidateError::ConversionError(FieldError::from(&e)),
     })?;
 
-    let _ = external.validate().map_err(|e| {
+    external.validate().map_err(|e| {
         let mut config = ConfigErrors::default();
-        config.errors.push(e.to_string());
+        config.errors.push(FieldError::from(&e));
         ValidateError::Configuration(config)
-    })?;
+    })
+}
 
+/// Main validation function. The non-fatal issues of the CRD, if it could be deserialized, are
+/// added to `warnings`.
+fn validate(
+    gwagent_json: &str,
+    warnings: &mut Vec<ConversionWarning>,
+) -> Result<(), ValidateError> {
+    let crd = deserialize(gwagent_json)?;
+    let _ = validate_crd(&crd, warnings)?;
     Ok(())
 }
 
-/// Read from stdin, deserialize as JSON and validate
-fn validate_from_stdin() -> Result<(), ValidateError> {
+/// Read the whole of stdin
+fn read_stdin() -> Result<String, ValidateError> {
     let mut input = String::new();
     io::stdin()
         .read_to_string(&mut input)
         .map_err(|e| ValidateError::EnvironmentError(format!("Failed to read from stdin: {e}")))?;
+    Ok(input)
+}
 
-    validate(&input)
+/// Read from stdin, deserialize as JSON and validate
+fn validate_from_stdin(warnings: &mut Vec<ConversionWarning>) -> Result<(), ValidateError> {
+    let input = read_stdin()?;
+    validate(&input, warnings)
 }
 
 /// Build a validation reply to be output as JSON
-fn build_reply(result: Result<(), ValidateError>) -> ValidateReply {
-    match result {
+fn build_reply(
+    result: Result<(), ValidateError>,
+    warnings: Vec<ConversionWarning>,
+) -> ValidateReply {
+    let mut reply = match result {
         Ok(()) => ValidateReply::success(),
         Err(e) => ValidateReply::from(&e),
-    }
+    };
+    reply.warnings = warnings.into_iter().map(ValidateWarningOut::from).collect();
+    reply
 }
 
 fn main() {
-    let result = validate_from_stdin();
-    let reply = build_reply(result);
-    match serde_yaml_ng::to_string(&reply) {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let out = match parse_mode(&args) {
+        Ok(Mode::Gateway) => {
+            let mut warnings = vec![];
+            let result = validate_from_stdin(&mut warnings);
+            serde_yaml_ng::to_string(&build_reply(result, warnings))
+        }
+        Ok(Mode::Fabric) => serde_yaml_ng::to_string(&fabric::validate_from_stdin()),
+        Err(e) => serde_yaml_ng::to_string(&build_reply(Err(e), vec![])),
+    };
+    match out {
         Ok(out) => println!("{out}"),
         Err(e) => eprintln!("Failure serializing validation response: {e}"),
     }
@@ -13,18 +13,27 @@
 
 use crate::{VpcDiscriminant, VpcMapError, VpcMapResult};
 use ahash::RandomState;
+use common::changelog::{ChangeFeed, Delta, EntryDelta, SharedChangeLog, diff_entries};
+use common::generation::{Generational, TableGeneration};
+use common::publish::InstrumentedWriteHandle;
 use left_right::new_from_empty;
-use left_right::{Absorb, ReadGuard, ReadHandle, WriteHandle};
+use left_right::{Absorb, ReadGuard, ReadHandle};
 use std::clone::Clone;
 use std::collections::HashMap;
 
 #[derive(Clone, Default)]
-pub struct VpcMap<T: Clone>(pub HashMap<VpcDiscriminant, T, RandomState>);
+pub struct VpcMap<T: Clone>(
+    pub HashMap<VpcDiscriminant, T, RandomState>,
+    TableGeneration,
+);
 
 impl<T: Clone> VpcMap<T> {
     #[must_use]
     pub fn new() -> Self {
-        Self(HashMap::with_hasher(RandomState::with_seed(0)))
+        Self(
+            HashMap::with_hasher(RandomState::with_seed(0)),
+            TableGeneration::INITIAL,
+        )
     }
     /// Add the given entry to the map. N.B. this method adds elements directly to the table object
     /// and is only public so that users can build their non-wrapped table and call `VpcMapWriter::set_map`.
@@ -50,24 +59,27 @@ impl<T: Clone> VpcMap<T> {
     }
 }
 
-enum VpcMapChange<T: Clone> {
-    Add(VpcDiscriminant, T),
-    Del(VpcDiscriminant),
-    SetMap(VpcMap<T>),
+impl<T: Clone> Generational for VpcMap<T> {
+    fn generation(&self) -> TableGeneration {
+        self.1
+    }
+    fn set_generation(&mut self, generation: TableGeneration) {
+        self.1 = generation;
+    }
+}
+
+/// A change of a [`VpcMap`]: the entry of a discriminant was set or removed
+pub type VpcMapChange<T> = EntryDelta<VpcDiscriminant, T>;
+
+impl<T: Clone> Delta<VpcMap<T>> for VpcMapChange<T> {
+    fn replay(&self, map: &mut VpcMap<T>) {
+        self.apply(&mut map.0);
+        map.advance_generation();
+    }
 }
 impl<T: Clone> Absorb<VpcMapChange<T>> for VpcMap<T> {
     fn absorb_first(&mut self, change: &mut VpcMapChange<T>, _: &Self) {
-        match change {
-            VpcMapChange::Add(disc, entry) => {
-                self.add_checked(*disc, entry.clone());
-            }
-            VpcMapChange::Del(disc) => {
-                self.del(*disc);
-            }
-            VpcMapChange::SetMap(new_map) => {
-                *self = new_map.clone();
-            }
-        }
+        change.replay(self);
     }
     fn drop_first(self: Box<Self>) {}
     fn sync_with(&mut self, first: &Self) {
@@ -75,7 +87,14 @@ impl<T: Clone> Absorb<VpcMapChange<T>> for VpcMap<T> {
     }
 }
 
-pub struct VpcMapWriter<T: Clone>(WriteHandle<VpcMap<T>, VpcMapChange<T>>);
+/// The writer of a [`VpcMap`]. The changes it publishes are recorded in a change log, so that a
+/// [`ChangeFeed`] can replay them onto a copy of the map.
+pub struct VpcMapWriter<T: Clone> {
+    handle: InstrumentedWriteHandle<VpcMap<T>, VpcMapChange<T>>,
+    /// The changes appended since the last publication
+    pending: Vec<VpcMapChange<T>>,
+    log: SharedChangeLog<VpcMapChange<T>>,
+}
 #[derive(Clone, Debug)]
 pub struct VpcMapReader<T: Clone>(ReadHandle<VpcMap<T>>);
 
@@ -84,42 +103,83 @@ impl<T: Clone> VpcMapWriter<T> {
     #[allow(clippy::new_without_default)]
     pub fn new() -> VpcMapWriter<T> {
         let (w, _) = new_from_empty::<VpcMap<T>, VpcMapChange<T>>(VpcMap::new());
-        VpcMapWriter(w)
+        VpcMapWriter {
+            handle: InstrumentedWriteHandle::new(w, "vpc-map"),
+            pending: vec![],
+            log: SharedChangeLog::default(),
+        }
     }
     #[must_use]
     pub fn get_reader(&self) -> VpcMapReader<T> {
-        VpcMapReader(self.0.clone())
+        VpcMapReader(self.handle.clone())
     }
-    /// Completely replaces the inner `VpcMap` with the provided one. This is useful when the
-    /// map is built for configuration purposes (E.g. some NAT tables).
-    pub fn set_map(&mut self, map: VpcMap<T>) {
-        self.0.append(VpcMapChange::SetMap(map));
-        self.0.publish();
+    /// Get the feed of the changes published for the map
+    #[must_use]
+    pub fn change_feed(&self) -> ChangeFeed<VpcMap<T>, VpcMapChange<T>> {
+        self.log.feed(self.handle.clone())
+    }
+    fn append(&mut self, change: VpcMapChange<T>) {
+        self.handle.append(change.clone());
+        self.pending.push(change);
+    }
+    /// Replaces the inner `VpcMap` with the provided one, publishing the changes of the entries
+    /// which differ. This is useful when the map is built for configuration purposes (E.g. some
+    /// NAT tables).
+    pub fn set_map(&mut self, map: VpcMap<T>)
+    where
+        T: PartialEq,
+    {
+        let changes = {
+            let published = self.handle.enter();
+            // the entries of the map once the pending changes are published
+            let mut current: HashMap<VpcDiscriminant, Option<&T>> = published
+                .iter()
+                .flat_map(|published| published.0.iter())
+                .map(|(disc, entry)| (*disc, Some(entry)))
+                .collect();
+            for change in &self.pending {
+                match change {
+                    EntryDelta::Set(disc, entry) => current.insert(*disc, Some(entry)),
+                    EntryDelta::Remove(disc) => current.insert(*disc, None),
+                };
+            }
+            let current = current
+                .iter()
+                .filter_map(|(disc, entry)| entry.map(|entry| (disc, entry)));
+            diff_entries(current, &map.0)
+        };
+        for change in changes {
+            self.append(change);
+        }
+        self.publish();
     }
     /// Add an entry to the `VpcMap`
     pub fn add(&mut self, disc: VpcDiscriminant, entry: T, publish: bool) -> VpcMapResult<()> {
-        let inner = self.0.raw_write_handle();
+        let inner = self.handle.raw_write_handle();
         unsafe {
             let inner = inner.as_ref();
             if inner.0.contains_key(&disc) {
                 return Err(VpcMapError::EntryExists(disc));
             }
         }
-        self.0.append(VpcMapChange::Add(disc, entry));
+        self.append(EntryDelta::Set(disc, entry));
         if publish {
-            self.0.publish();
+            self.publish();
         }
         Ok(())
     }
     /// Remove the entry with the given `VpcDiscriminant`
     pub fn del(&mut self, disc: VpcDiscriminant, publish: bool) {
-        self.0.append(VpcMapChange::Del(disc));
+        self.append(EntryDelta::Remove(disc));
         if publish {
-            self.0.publish();
+            self.publish();
         }
     }
     pub fn publish(&mut self) {
-        self.0.publish();
+        let changes = std::mem::take(&mut self.pending);
+        self.log.record(changes, || {
+            self.handle.publish();
+        });
     }
 }
 
@@ -127,5 +187,9 @@ impl<T: Clone> VpcMapReader<T> {
     pub fn enter(&self) -> Option<ReadGuard<'_, VpcMap<T>>> {
         self.0.enter()
     }
+    #[must_use]
+    pub fn inner(&self) -> ReadHandle<VpcMap<T>> {
+        self.0.clone()
+    }
     // TODO provide an easy api to read
 }
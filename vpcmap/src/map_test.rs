@@ -7,7 +7,7 @@ use crate::*;
 use net::vxlan::Vni;
 
 /// Sample mapping that maps a discriminant to a string (e.g. Vpc name)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct VpcName {
     #[allow(unused)]
     disc: VpcDiscriminant,
@@ -49,3 +49,84 @@ fn test_vpcmap_vpcname() {
     map.del(disc);
     assert!(map.get(disc).is_none());
 }
+
+#[test]
+fn test_vpcmap_generation() {
+    use common::generation::{DerivedCache, Generational};
+
+    let mut writer: VpcMapWriter<VpcName> = VpcMapWriter::new();
+    let reader = writer.get_reader();
+    let disc1 = VpcDiscriminant::from_vni(Vni::new_checked(3000).unwrap());
+    let disc2 = VpcDiscriminant::from_vni(Vni::new_checked(3001).unwrap());
+    let mut cache = DerivedCache::new();
+    let count = |map: &VpcMap<VpcName>| map.0.len();
+
+    writer
+        .add(disc1, VpcName::new(disc1, "VPC-1"), true)
+        .unwrap();
+    let generation = reader.enter().unwrap().generation();
+    assert_eq!(*cache.get_or_derive(&*reader.enter().unwrap(), count), 1);
+
+    // unpublished changes don't change the generation seen by readers
+    writer
+        .add(disc2, VpcName::new(disc2, "VPC-2"), false)
+        .unwrap();
+    assert_eq!(reader.enter().unwrap().generation(), generation);
+    assert!(cache.is_current(&*reader.enter().unwrap()));
+
+    writer.publish();
+    assert_ne!(reader.enter().unwrap().generation(), generation);
+    assert!(!cache.is_current(&*reader.enter().unwrap()));
+    assert_eq!(*cache.get_or_derive(&*reader.enter().unwrap(), count), 2);
+
+    // replacing the map never brings the generation back
+    let generation = reader.enter().unwrap().generation();
+    writer.set_map(VpcMap::new());
+    assert!(reader.enter().unwrap().generation() > generation);
+}
+
+#[test]
+fn test_vpcmap_change_feed() {
+    use common::changelog::{EntryDelta, Seqno};
+
+    let mut writer: VpcMapWriter<VpcName> = VpcMapWriter::new();
+    let feed = writer.change_feed();
+    let disc = |vni| VpcDiscriminant::from_vni(Vni::new_checked(vni).unwrap());
+    let (disc1, disc2, disc3) = (disc(3000), disc(3001), disc(3002));
+
+    writer
+        .add(disc1, VpcName::new(disc1, "VPC-1"), true)
+        .unwrap();
+    let mut checkpoint = feed.checkpoint().unwrap();
+    assert_eq!(checkpoint.seqno, Seqno::new(1));
+    assert_eq!(checkpoint.table.get(disc1).unwrap().name, "VPC-1");
+
+    // unpublished changes are not replayed
+    writer
+        .add(disc2, VpcName::new(disc2, "VPC-2"), false)
+        .unwrap();
+    assert_eq!(feed.catch_up(&mut checkpoint), Ok(0));
+
+    // replacing the map only publishes the entries which differ, including the pending ones
+    let mut map = VpcMap::new();
+    map.add(disc1, VpcName::new(disc1, "VPC-1")).unwrap();
+    map.add(disc3, VpcName::new(disc3, "VPC-3")).unwrap();
+    writer.set_map(map);
+    let publications = feed.changes_since(checkpoint.seqno).unwrap();
+    assert_eq!(publications.len(), 1);
+    let (seqno, changes) = &publications[0];
+    assert_eq!(*seqno, Seqno::new(2));
+    assert_eq!(changes.len(), 3);
+    assert_eq!(
+        changes[0],
+        EntryDelta::Set(disc2, VpcName::new(disc2, "VPC-2"))
+    );
+    assert!(changes.contains(&EntryDelta::Remove(disc2)));
+    assert!(changes.contains(&EntryDelta::Set(disc3, VpcName::new(disc3, "VPC-3"))));
+
+    assert_eq!(feed.catch_up(&mut checkpoint), Ok(1));
+    assert_eq!(checkpoint.seqno, Seqno::new(2));
+    assert_eq!(checkpoint.table.0.len(), 2);
+    assert!(checkpoint.table.get(disc2).is_none());
+    assert_eq!(checkpoint.table.get(disc3).unwrap().name, "VPC-3");
+}
@@ -48,6 +48,7 @@
 
 use super::{VpcDiscriminant, VpcMapError, VpcMapResult};
 use ahash::RandomState;
+use common::generation::{Generational, TableGeneration};
 use left_right::new_from_empty;
 use left_right::{Absorb, ReadGuard, ReadHandle, WriteHandle};
 use std::collections::HashMap;
@@ -64,10 +65,14 @@ pub trait VpcPair {
 #[derive(Clone, Default)]
 pub struct VpcPairMap<P: VpcPair + Clone>(
     HashMap<(VpcDiscriminant, VpcDiscriminant), Rc<P>, RandomState>,
+    TableGeneration,
 );
 impl<P: VpcPair + Clone> VpcPairMap<P> {
     pub fn new() -> Self {
-        Self(HashMap::with_hasher(RandomState::with_seed(0)))
+        Self(
+            HashMap::with_hasher(RandomState::with_seed(0)),
+            TableGeneration::INITIAL,
+        )
     }
     pub fn add(&mut self, entry: P) {
         let east = entry.get_east_disc();
@@ -113,6 +118,15 @@ impl<P: VpcPair + Clone> VpcPairMap<P> {
     }
 }
 
+impl<P: VpcPair + Clone> Generational for VpcPairMap<P> {
+    fn generation(&self) -> TableGeneration {
+        self.1
+    }
+    fn set_generation(&mut self, generation: TableGeneration) {
+        self.1 = generation;
+    }
+}
+
 enum VpcPairMapChange<P: Clone + VpcPair> {
     Add(P),
     Del(VpcDiscriminant, VpcDiscriminant),
@@ -122,9 +136,15 @@ enum VpcPairMapChange<P: Clone + VpcPair> {
 impl<T: VpcPair + Clone> Absorb<VpcPairMapChange<T>> for VpcPairMap<T> {
     fn absorb_first(&mut self, change: &mut VpcPairMapChange<T>, _: &Self) {
         match change {
-            VpcPairMapChange::Add(entry) => self.add(entry.clone()),
-            VpcPairMapChange::Del(east, west) => self.del(*east, *west),
-            VpcPairMapChange::SetMap(new_map) => *self = new_map.clone(),
+            VpcPairMapChange::Add(entry) => {
+                self.add(entry.clone());
+                self.advance_generation();
+            }
+            VpcPairMapChange::Del(east, west) => {
+                self.del(*east, *west);
+                self.advance_generation();
+            }
+            VpcPairMapChange::SetMap(new_map) => self.replace_contents(new_map.clone()),
         }
     }
     fn sync_with(&mut self, first: &Self) {